@@ -0,0 +1,178 @@
+//! Values in plain-text (non-`--json`) output: sizes in binary units, rates, durations and
+//! timestamps with a relative hint. `--raw` keeps them as stored (byte counts, seconds, RFC3339 /
+//! unix ms) for scripts that parse the `key=value` lines.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use chrono::{DateTime, Local, TimeZone, Utc};
+
+pub use televy_backup_core::units::human_bytes;
+
+static RAW: AtomicBool = AtomicBool::new(false);
+
+pub fn set_raw(raw: bool) {
+    RAW.store(raw, Ordering::Relaxed);
+}
+
+pub fn raw() -> bool {
+    RAW.load(Ordering::Relaxed)
+}
+
+pub fn bytes(n: u64) -> String {
+    if raw() { n.to_string() } else { human_bytes(n) }
+}
+
+/// [`bytes`] for the `i64` sums SQLite returns.
+pub fn bytes_i64(n: i64) -> String {
+    if raw() {
+        n.to_string()
+    } else {
+        human_bytes(n.max(0) as u64)
+    }
+}
+
+pub fn rate(bytes_per_second: u64) -> String {
+    if raw() {
+        bytes_per_second.to_string()
+    } else {
+        format!("{}/s", human_bytes(bytes_per_second))
+    }
+}
+
+pub fn duration(seconds: f64) -> String {
+    if raw() {
+        seconds.to_string()
+    } else {
+        human_duration(seconds)
+    }
+}
+
+/// An RFC3339 timestamp; unparsable values are printed as they are.
+pub fn timestamp(rfc3339: &str) -> String {
+    match DateTime::parse_from_rfc3339(rfc3339) {
+        Ok(at) if !raw() => human_timestamp(&at.with_timezone(&Utc), Utc::now(), &Local),
+        _ => rfc3339.to_string(),
+    }
+}
+
+pub fn timestamp_ms(unix_ms: u64) -> String {
+    match DateTime::<Utc>::from_timestamp_millis(unix_ms as i64) {
+        Some(at) if !raw() => human_timestamp(&at, Utc::now(), &Local),
+        _ => unix_ms.to_string(),
+    }
+}
+
+/// `350ms`, `4.2s`, `42s`, `12m 5s`, `1h 12m`, `3d 4h`.
+pub fn human_duration(seconds: f64) -> String {
+    let seconds = if seconds.is_finite() {
+        seconds.max(0.0)
+    } else {
+        0.0
+    };
+    // Rounded before picking the unit, so 0.9996s prints as "1.0s" rather than "1000ms".
+    let millis = (seconds * 1000.0).round();
+    if millis < 1000.0 {
+        return format!("{}ms", millis as u64);
+    }
+    let tenths = (seconds * 10.0).round_ties_even();
+    if tenths < 100.0 {
+        return format!("{:.1}s", tenths / 10.0);
+    }
+    let total = seconds.round() as u64;
+    let (days, hours, minutes, secs) = (
+        total / 86_400,
+        total % 86_400 / 3600,
+        total % 3600 / 60,
+        total % 60,
+    );
+    if total < 60 {
+        format!("{secs}s")
+    } else if total < 3600 {
+        format!("{minutes}m {secs}s")
+    } else if total < 86_400 {
+        format!("{hours}h {minutes}m")
+    } else {
+        format!("{days}d {hours}h")
+    }
+}
+
+/// `2024-06-01 02:00 (14 hours ago)`, the wall-clock part in `tz`.
+pub fn human_timestamp<Tz: TimeZone>(at: &DateTime<Utc>, now: DateTime<Utc>, tz: &Tz) -> String
+where
+    Tz::Offset: std::fmt::Display,
+{
+    format!(
+        "{} ({})",
+        at.with_timezone(tz).format("%Y-%m-%d %H:%M"),
+        relative(*at, now)
+    )
+}
+
+fn relative(at: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let delta = now.signed_duration_since(at).num_seconds();
+    let abs = delta.unsigned_abs();
+    let (n, unit) = if abs < 60 {
+        return "just now".to_string();
+    } else if abs < 3600 {
+        (abs / 60, "minute")
+    } else if abs < 86_400 {
+        (abs / 3600, "hour")
+    } else {
+        (abs / 86_400, "day")
+    };
+    let plural = if n == 1 { "" } else { "s" };
+    if delta >= 0 {
+        format!("{n} {unit}{plural} ago")
+    } else {
+        format!("in {n} {unit}{plural}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_scale_from_milliseconds_to_days() {
+        assert_eq!(human_duration(0.0), "0ms");
+        assert_eq!(human_duration(0.35), "350ms");
+        assert_eq!(human_duration(0.9994), "999ms");
+        assert_eq!(human_duration(0.9996), "1.0s");
+        assert_eq!(human_duration(9.96), "10s");
+        assert_eq!(human_duration(4.25), "4.2s");
+        assert_eq!(human_duration(42.4), "42s");
+        assert_eq!(human_duration(725.0), "12m 5s");
+        assert_eq!(human_duration(4320.0), "1h 12m");
+        assert_eq!(
+            human_duration(3.0 * 86_400.0 + 4.0 * 3600.0 + 59.0),
+            "3d 4h"
+        );
+        assert_eq!(human_duration(-1.0), "0ms");
+        assert_eq!(human_duration(f64::NAN), "0ms");
+    }
+
+    #[test]
+    fn timestamps_show_absolute_and_relative_time() {
+        let at = DateTime::parse_from_rfc3339("2024-06-01T02:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let hours = |h: i64| at + chrono::Duration::hours(h);
+        assert_eq!(
+            human_timestamp(&at, hours(14), &Utc),
+            "2024-06-01 02:00 (14 hours ago)"
+        );
+        assert_eq!(
+            human_timestamp(&at, at + chrono::Duration::seconds(30), &Utc),
+            "2024-06-01 02:00 (just now)"
+        );
+        assert_eq!(relative(at, hours(1)), "1 hour ago");
+        assert_eq!(
+            relative(at, at + chrono::Duration::minutes(5)),
+            "5 minutes ago"
+        );
+        assert_eq!(relative(at, hours(49)), "2 days ago");
+        assert_eq!(relative(hours(3), at), "in 3 hours");
+        let berlin = chrono::FixedOffset::east_opt(2 * 3600).unwrap();
+        assert!(human_timestamp(&at, hours(14), &berlin).starts_with("2024-06-01 04:00 "));
+    }
+}
@@ -1,30 +1,50 @@
 use std::collections::HashMap;
-use std::io::{Read, Write};
+use std::io::{IsTerminal, Read, Write};
 use std::path::{Path, PathBuf};
-use std::sync::{Mutex, OnceLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
 use std::time::{Duration, Instant};
 
 use base64::Engine;
-use clap::{Parser, Subcommand};
+use clap::{Args, CommandFactory, Parser, Subcommand};
 use serde::Serialize;
 use sqlx::Row;
+use televy_backup_core::chat_remap::{ChatRemap, RemappedStorage};
+use televy_backup_core::history_import::ImportSnapshot;
+use televy_backup_core::ownership::{OwnerMapping, OwnershipOptions};
+use televy_backup_core::run_log::redact_secret;
+use televy_backup_core::schedule_tz::ScheduleTz;
+use televy_backup_core::sqlite_consistent::SqliteConsistentOptions;
+use televy_backup_core::usage::UsageRun;
+use televy_backup_core::version::{self, Component, ComponentVersion, DaemonVersionStamp};
 use televy_backup_core::{
-    APP_NAME, BackupConfig, BackupOptions, ChunkingConfig, ProgressSink, RestoreConfig,
-    RestoreOptions, Storage, TelegramMtProtoStorage, TelegramMtProtoStorageConfig, VerifyConfig,
-    VerifyOptions, restore_snapshot_with, run_backup_with, verify_snapshot_with,
+    APP_NAME, BackupConfig, BackupOptions, ChunkingConfig, DataKey, ErrorCode, KeyDerivation,
+    LocalDirStorage, Phase, ProgressRecorder, ProgressSink, RestoreConfig, RestoreFsync,
+    RestoreOptions, RunStatus, Storage, TelegramMtProtoStorage, TelegramMtProtoStorageConfig,
+    VerifyConfig, VerifyOptions, restore_snapshot_with, run_backup_with, verify_snapshot_with,
 };
-use televy_backup_core::{bootstrap, config_bundle};
+use televy_backup_core::{bootstrap, config_bundle, data_dir, quiet_hours, repo_export};
 use televy_backup_core::{config as settings_config, gold_key};
 use tokio::io::AsyncBufReadExt;
 #[cfg(unix)]
 use tokio::net::UnixStream;
 use tokio_util::sync::CancellationToken;
 
+mod format;
+
 #[derive(Parser)]
 #[command(name = "televybackup")]
 #[command(about = "TelevyBackup CLI (native macOS app backend)", long_about = None)]
+#[command(after_help = "Exit status:
+  0  success
+  1  failure (generic or internal)
+  2  invalid configuration or usage (config.invalid, cli.invalid, a missing confirmation or passphrase)
+  3  retryable infrastructure failure (telegram.unavailable, control.timeout, status.unavailable, or any error with \"retryable\": true); 0 with --exit-zero-on-retryable
+  4  integrity failure (integrity, chunk.missing, index.chain_broken, audit.chain_broken, restore.partial, ...)
+  5  cancelled (Ctrl-C or SIGTERM); a second signal exits with 130 right away
+Failures print the error JSON (`code`, `message`, `details`, `retryable`) to stderr. A cancelled backup/restore/verify reports `task.cancelled` with `details.partial` (what it got done); with --events its final task.state is `cancelled`.")]
 struct Cli {
-    #[arg(long)]
+    #[arg(long, global = true)]
     json: bool,
 
     #[arg(long)]
@@ -36,8 +56,22 @@ struct Cli {
     #[arg(long)]
     data_dir: Option<PathBuf>,
 
+    /// Plain-text output: print sizes, durations and timestamps as stored (bytes, seconds,
+    /// RFC3339) instead of `45.0 GiB`, `1h 12m`, `2024-06-01 02:00 (14 hours ago)`.
+    #[arg(long)]
+    raw: bool,
+
+    /// Exit with 0 instead of 3 when the command failed with a retryable error (for cron jobs
+    /// that treat transient failures as non-fatal); the error is still printed.
+    #[arg(long, global = true)]
+    exit_zero_on_retryable: bool,
+
+    /// Print every registered error code with its detail keys and English template, then exit.
+    #[arg(long, hide = true, exclusive = true)]
+    error_catalog: bool,
+
     #[command(subcommand)]
-    cmd: Command,
+    cmd: Option<Command>,
 }
 
 #[derive(Subcommand)]
@@ -57,6 +91,14 @@ enum Command {
         #[command(subcommand)]
         cmd: StatusCmd,
     },
+    /// Secrets (bot tokens, API hash, MTProto sessions, master key).
+    ///
+    /// Each secret is read from the first of: the env var `TELEVYBACKUP_SECRET_<KEY>`, the file
+    /// `<config_dir>/secrets.d/<key>` (mode 0600), then `secrets.enc`. `<KEY>` is the key
+    /// upper-cased with every character other than A-Z and 0-9 (dots included) replaced by `_`,
+    /// so `telegram.bot_token.ep1` is `TELEVYBACKUP_SECRET_TELEGRAM_BOT_TOKEN_EP1`. Writes go to
+    /// `secrets.d/` when it exists and to `secrets.enc` otherwise; writing a key that an env var
+    /// provides fails with `secrets.read_only_provider`.
     Secrets {
         #[command(subcommand)]
         cmd: SecretsCmd,
@@ -77,6 +119,11 @@ enum Command {
         #[command(subcommand)]
         cmd: BackupCmd,
     },
+    /// Bring over history from another backup tool.
+    Import {
+        #[command(subcommand)]
+        cmd: ImportCmd,
+    },
     Restore {
         #[command(subcommand)]
         cmd: RestoreCmd,
@@ -85,6 +132,75 @@ enum Command {
         #[command(subcommand)]
         cmd: VerifyCmd,
     },
+    /// Run logs written by backup/restore/verify runs.
+    Logs {
+        #[command(subcommand)]
+        cmd: LogsCmd,
+    },
+    /// This machine's identity, recorded on every snapshot it creates.
+    Device {
+        #[command(subcommand)]
+        cmd: DeviceCmd,
+    },
+    /// Hash-chained log of security-relevant operations (secrets, key export, bundle import).
+    Audit {
+        #[command(subcommand)]
+        cmd: AuditCmd,
+    },
+    /// Local index DBs under `<data_dir>/index`.
+    Index {
+        #[command(subcommand)]
+        cmd: IndexCmd,
+    },
+    /// Where the data dir is (`--data-dir`, then `TELEVYBACKUP_DATA_DIR`, then the pointer file
+    /// `<config_dir>/data-dir.txt`, then the default) and moving it to another volume.
+    DataDir {
+        #[command(subcommand)]
+        cmd: DataDirCmd,
+    },
+    /// Pause or resume a target's scheduled backups (`targets[].enabled`).
+    Targets {
+        #[command(subcommand)]
+        cmd: TargetsCmd,
+    },
+    /// Chunk objects in the chat that no snapshot references any more.
+    Gc {
+        #[command(subcommand)]
+        cmd: GcCmd,
+    },
+    /// Offline copies of an endpoint's repository for restores without Telegram.
+    Repo {
+        #[command(subcommand)]
+        cmd: RepoCmd,
+    },
+    /// Pinned bootstrap catalog that points each target at its latest snapshot.
+    Bootstrap {
+        #[command(subcommand)]
+        cmd: BootstrapCmd,
+    },
+    /// Restore passphrase (`security.restore_requires_passphrase`).
+    Security {
+        #[command(subcommand)]
+        cmd: SecurityCmd,
+    },
+    /// Check what backups send to the chat for file names.
+    Privacy {
+        #[command(subcommand)]
+        cmd: PrivacyCmd,
+    },
+    /// Check settings and the daemon for problems that keep backups from running.
+    Doctor,
+    /// Print the CLI version and the settings schema it writes.
+    Version {
+        /// Also ask the daemon and the MTProto helper, and report any version skew.
+        #[arg(long)]
+        all: bool,
+    },
+    /// Print a shell completion script to stdout.
+    #[command(hide = true)]
+    Completions {
+        shell: clap_complete::Shell,
+    },
 }
 
 #[derive(Subcommand)]
@@ -92,8 +208,38 @@ enum SettingsCmd {
     Get {
         #[arg(long)]
         with_secrets: bool,
+        /// Print what each target's backups leave out instead of the settings: the
+        /// `scan.default_excludes` patterns, the rules in the source's root `.televyignore` and
+        /// `[[targets.filters]]`.
+        #[arg(long, conflicts_with = "with_secrets")]
+        effective: bool,
+        /// With `--effective`, only this target.
+        #[arg(long, requires = "effective")]
+        target_id: Option<String>,
+    },
+    /// Replace `config.toml` with the TOML document read from stdin or `--input-file`.
+    Set {
+        #[arg(long)]
+        input_file: Option<PathBuf>,
+    },
+    /// Check `config.toml` (or the TOML document in `--input-file`) without saving it, and print
+    /// the warnings for valid but suspicious settings such as overlapping target sources.
+    Validate {
+        #[arg(long)]
+        input_file: Option<PathBuf>,
+    },
+    /// Every settings field with its type, default and constraints.
+    Schema,
+    /// A commented `config.toml` holding all defaults.
+    Defaults,
+    /// Earlier `config.toml` generations, newest first.
+    History,
+    /// Restore an earlier `config.toml` generation (the newest unless `--to` names one).
+    Rollback {
+        /// Id of the generation (`<unix_ms>` or `<unix_ms>.<seq>`), as listed by `settings history`.
+        #[arg(long, value_parser = parse_settings_backup_id)]
+        to: Option<(i64, u32)>,
     },
-    Set,
     ExportBundle {
         #[arg(long)]
         hint: Option<String>,
@@ -105,6 +251,13 @@ enum SettingsCmd {
         apply: bool,
         #[arg(long)]
         compare_folder: bool,
+        /// With --apply: continue an interrupted apply from its partial-apply report, skipping
+        /// endpoints whose index was already rebuilt. Needs the same request as the first run.
+        #[arg(long, requires = "apply")]
+        resume: bool,
+        /// Read the bundle key or request JSON from this file instead of stdin.
+        #[arg(long)]
+        input_file: Option<PathBuf>,
     },
 }
 
@@ -118,8 +271,13 @@ enum SecretsCmd {
     SetTelegramBotToken {
         #[arg(long)]
         endpoint_id: Option<String>,
+        #[arg(long)]
+        input_file: Option<PathBuf>,
+    },
+    SetTelegramApiHash {
+        #[arg(long)]
+        input_file: Option<PathBuf>,
     },
-    SetTelegramApiHash,
     ClearTelegramMtprotoSession,
     MigrateKeychain,
     InitMasterKey,
@@ -127,9 +285,42 @@ enum SecretsCmd {
         #[arg(long)]
         i_understand: bool,
     },
+    /// Print a restore-only key for one target (`targets[].target_key` must be on); it decrypts
+    /// that target's target-keyed snapshots and nothing else.
+    ExportTargetKey {
+        #[arg(long)]
+        target_id: String,
+        #[arg(long)]
+        i_understand: bool,
+    },
     ImportMasterKey {
         #[arg(long)]
         force: bool,
+        #[arg(long)]
+        input_file: Option<PathBuf>,
+    },
+    /// List stored secret keys with their value length and a fingerprint (never the value);
+    /// keys the current settings don't reference are flagged as orphaned.
+    List,
+    /// Delete one secret key, or every orphaned one, after confirmation.
+    Delete {
+        #[arg(
+            long,
+            required_unless_present = "orphaned",
+            conflicts_with = "orphaned"
+        )]
+        key: Option<String>,
+        #[arg(long)]
+        orphaned: bool,
+        /// Skip the confirmation prompt.
+        #[arg(long)]
+        yes: bool,
+        /// Required to delete the master key; backups can't be restored without it.
+        #[arg(long)]
+        i_understand_data_loss: bool,
+        /// Don't print the gold key before deleting the master key.
+        #[arg(long)]
+        no_print: bool,
     },
 }
 
@@ -154,6 +345,40 @@ enum TelegramCmd {
         timeout_secs: u32,
         #[arg(long)]
         include_users: bool,
+        /// How long each poll of the helper waits for a message; Ctrl-C/SIGTERM is noticed
+        /// between polls.
+        #[arg(long, default_value_t = 1000)]
+        poll_interval_ms: u64,
+    },
+    /// Check the documents in the endpoint's chat against their captions (size, and the payload
+    /// hash after a re-download) and against the local index DB.
+    AuditChat {
+        #[arg(long)]
+        endpoint_id: Option<String>,
+        #[arg(long, default_value_t = 1)]
+        from_msg_id: i32,
+        /// Last message id to scan; by default scanning stops after 2000 ids without a message.
+        #[arg(long)]
+        max_msg_id: Option<i32>,
+        /// Re-download only this share of the captioned documents.
+        #[arg(long)]
+        sample_percent: Option<f64>,
+        /// Skip re-downloads; only check sizes and the index cross-reference.
+        #[arg(long)]
+        no_download: bool,
+    },
+    /// Write the `index remap-chat` mapping file for objects forwarded from `--old-chat` to the
+    /// endpoint's current chat (by forward header, else by document id).
+    ExportMapping {
+        #[arg(long)]
+        endpoint_id: Option<String>,
+        #[arg(long, allow_hyphen_values = true)]
+        old_chat: String,
+        #[arg(long)]
+        output: PathBuf,
+        /// Last message id to scan; by default scanning stops after 2000 ids without a message.
+        #[arg(long)]
+        max_msg_id: Option<i32>,
     },
 }
 
@@ -162,22 +387,123 @@ enum SnapshotsCmd {
     List {
         #[arg(long, default_value_t = 20)]
         limit: u32,
+        #[arg(long)]
+        source_path: Option<PathBuf>,
+        #[arg(long)]
+        target_id: Option<String>,
+        /// Inclusive lower bound (YYYY-MM-DD or RFC3339).
+        #[arg(long)]
+        since: Option<String>,
+        /// Exclusive upper bound (YYYY-MM-DD or RFC3339).
+        #[arg(long)]
+        until: Option<String>,
+        /// Oldest first (the limit then keeps the oldest matches).
+        #[arg(long)]
+        asc: bool,
+        /// Print one page of `--limit` snapshots followed by its `nextCursor` (absent on the last
+        /// page); pass that to `--cursor` for the next one.
+        #[arg(long)]
+        paginate: bool,
+        /// Continue after the page that returned this `nextCursor` (implies `--paginate`).
+        #[arg(long)]
+        cursor: Option<String>,
+        #[command(flatten)]
+        remote: RemoteArgs,
+    },
+    /// Keep a snapshot regardless of `keep_last_snapshots`; pinned snapshots don't count towards
+    /// the limit.
+    Pin {
+        #[arg(long)]
+        snapshot_id: String,
+    },
+    Unpin {
+        #[arg(long)]
+        snapshot_id: String,
     },
+    /// Entries in a snapshot's local file map, in path order; directories end in `/`.
+    Files {
+        #[arg(long)]
+        snapshot_id: String,
+    },
+    /// Remove a snapshot from the local index (remote objects are kept). Pinned snapshots need
+    /// `--force`.
+    Delete {
+        #[arg(long)]
+        snapshot_id: String,
+        #[arg(long)]
+        force: bool,
+    },
+    /// A snapshot and its chain of base snapshots, newest first, with the bytes each adds over
+    /// its base.
+    Chain {
+        #[arg(long)]
+        snapshot_id: String,
+    },
+    /// Point a snapshot's base at another, older snapshot of the same source (`none` makes it
+    /// self-contained), e.g. after deleting the one it was based on.
+    Rebase {
+        #[arg(long)]
+        snapshot_id: String,
+        #[arg(long)]
+        onto: String,
+    },
+}
+
+/// Ask a daemon's read-only remote listener (`[remote]` settings) instead of this machine.
+#[derive(Args, Debug, Clone, Default)]
+struct RemoteArgs {
+    /// Daemon remote listener address (`host:port`).
+    #[arg(long, value_name = "HOST:PORT")]
+    remote: Option<String>,
+    /// Bearer token (`remote.token` on the daemon); defaults to `$TELEVYBACKUP_REMOTE_TOKEN`.
+    #[arg(long, requires = "remote")]
+    remote_token: Option<String>,
+    /// SHA-256 fingerprint of the daemon's TLS certificate (logged at `remote.listening`);
+    /// connects over TLS and accepts only that certificate.
+    #[arg(long, requires = "remote")]
+    remote_fingerprint: Option<String>,
 }
 
 #[derive(Subcommand)]
 enum StatsCmd {
-    Get,
+    Get {
+        #[command(flatten)]
+        remote: RemoteArgs,
+    },
     Last {
         #[arg(long)]
         source: Option<PathBuf>,
     },
+    /// Runs, failures, bytes and average duration per calendar month from `usage.sqlite`
+    /// (recorded when `logs.usage_stats` or `notifications.digest_enabled` is on).
+    Monthly {
+        #[arg(long)]
+        target_id: Option<String>,
+    },
+    /// Delete usage rows older than an age like `90d`, `6m` or `2y`.
+    Prune {
+        #[arg(long)]
+        older_than: String,
+    },
+    /// Files of a snapshot with the most unique bytes: chunks no other file in any snapshot
+    /// references. Needs the snapshot's local file map.
+    TopFiles {
+        #[arg(long)]
+        snapshot_id: String,
+        #[arg(long, default_value_t = 50)]
+        limit: u32,
+    },
 }
 
 #[derive(Subcommand)]
 enum StatusCmd {
-    Get,
+    Get {
+        #[command(flatten)]
+        remote: RemoteArgs,
+    },
     Stream,
+    /// Refreshing plain-text view of all targets (`q` or Ctrl-C to exit).
+    Watch,
 }
 
 #[derive(Subcommand)]
@@ -187,8 +513,50 @@ enum BackupCmd {
         target_id: Option<String>,
         #[arg(long)]
         source: Option<PathBuf>,
-        #[arg(long, default_value = "manual")]
-        label: String,
+        /// Snapshot label (default: the target's `label_template`, else `manual`).
+        #[arg(long)]
+        label: Option<String>,
+        #[arg(long)]
+        no_remote_index_sync: bool,
+        /// Start a large first backup without asking (see `scan.warn_initial_backup_bytes`).
+        #[arg(long)]
+        yes: bool,
+        /// Fail on the first unreadable source file instead of skipping it (`scan.strict`).
+        #[arg(long)]
+        strict: bool,
+        /// Also back up directories on other file systems mounted inside the source (overrides
+        /// `scan.one_file_system`).
+        #[arg(long)]
+        cross_filesystems: bool,
+        /// Wait for `quiet_hours` to end before starting, and throttle uploads to
+        /// `quiet_hours.max_upload_bytes_per_sec` while they are in effect.
+        #[arg(long)]
+        respect_quiet_hours: bool,
+        /// Back up the source even if it no longer looks like the one backed up before (another
+        /// volume, a missing `.televybackup-anchor`, far fewer files), and make it the new
+        /// baseline.
+        #[arg(long)]
+        accept_source_change: bool,
+        /// Only walk the source and report what would be backed up, applying `.televyignore`
+        /// and the target's `filters` like a real run.
+        #[arg(long)]
+        dry_run: bool,
+        /// Merge the run's outcome into this Prometheus textfile-collector file (overrides
+        /// `metrics.textfile_path`).
+        #[arg(long, value_name = "PATH")]
+        metrics_textfile: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ImportCmd {
+    /// Back up each tree extracted by `restic restore` / `borg extract`, listed in
+    /// `<input>/manifest.toml`, as an `imported:` snapshot that keeps its original timestamp.
+    ResticDump {
+        #[arg(long)]
+        input: PathBuf,
+        #[arg(long)]
+        target_id: String,
         #[arg(long)]
         no_remote_index_sync: bool,
     },
@@ -201,11 +569,67 @@ enum RestoreCmd {
         snapshot_id: String,
         #[arg(long)]
         target: PathBuf,
+        /// Skip files with unrecoverable chunks and restore the rest.
+        #[arg(long)]
+        keep_going: bool,
+        /// Restore over a non-empty target and remove whatever there is not in the snapshot.
+        #[arg(long)]
+        delete_extraneous: bool,
+        /// With --delete-extraneous: only list what would be removed.
+        #[arg(long, requires = "delete_extraneous")]
+        dry_run: bool,
+        /// Write the file of a single-file snapshot exactly at --target instead of inside it.
+        #[arg(long, conflicts_with = "delete_extraneous")]
+        as_file: bool,
+        /// Give restored files their recorded mtimes and, on macOS, creation times.
+        #[arg(long)]
+        preserve_times: bool,
+        /// On a case-insensitive target, restore paths that differ only in case from an earlier
+        /// one as `name (case N).ext` instead of failing with `restore.case_collision`.
+        #[arg(long)]
+        rename_collisions: bool,
+        /// Restore paths too long for the target's file system under
+        /// `.televybackup-long-paths/<hash>/` (mapped in its `index.json`) instead of failing with
+        /// `restore.path_too_long` before anything is downloaded.
+        #[arg(long)]
+        shorten_long_paths: bool,
+        /// Leave restored files owned by the restoring user (the default).
+        #[arg(long, conflicts_with_all = ["owner_mapping", "preserve_owners"])]
+        chown_to_current: bool,
+        /// Give entries recorded with these uids to other users: `olduid:newuid,...`.
+        #[arg(long, value_parser = parse_owner_mapping)]
+        owner_mapping: Option<OwnerMapping>,
+        /// Give entries their recorded uid/gid (needs root for other users' files).
+        #[arg(long)]
+        preserve_owners: bool,
+        /// Ask for the restore passphrase like a control-socket restore would.
+        #[arg(long)]
+        require_passphrase: bool,
+        /// Restore with the key from `secrets export-target-key` in this file instead of the
+        /// master key; only that target's target-keyed snapshots can be restored.
+        #[arg(long)]
+        target_key: Option<PathBuf>,
+        /// File map manifest of the snapshot, for `--target-key` restores of snapshots this
+        /// machine has no index of.
+        #[arg(long, requires = "target_key")]
+        manifest_object_id: Option<String>,
+        /// Read the snapshot from a `repo export` directory instead of Telegram.
+        #[arg(long, value_name = "DIR")]
+        from_local_repo: Option<PathBuf>,
     },
     ListLatest {
         #[arg(long)]
         endpoint_id: Option<String>,
     },
+    /// Files, bytes and chunk objects a restore would write and download, from the index alone
+    /// (the snapshot's file map is downloaded when this machine has none).
+    Estimate {
+        #[arg(long)]
+        snapshot_id: String,
+        /// Only count entries at or under this snapshot-relative path.
+        #[arg(long)]
+        path: Option<String>,
+    },
     Latest {
         #[arg(long)]
         target_id: Option<String>,
@@ -213,6 +637,42 @@ enum RestoreCmd {
         source_path: Option<PathBuf>,
         #[arg(long)]
         target: PathBuf,
+        /// Skip files with unrecoverable chunks and restore the rest.
+        #[arg(long)]
+        keep_going: bool,
+        /// Restore over a non-empty target and remove whatever there is not in the snapshot.
+        #[arg(long)]
+        delete_extraneous: bool,
+        /// With --delete-extraneous: only list what would be removed.
+        #[arg(long, requires = "delete_extraneous")]
+        dry_run: bool,
+        /// Write the file of a single-file snapshot exactly at --target instead of inside it.
+        #[arg(long, conflicts_with = "delete_extraneous")]
+        as_file: bool,
+        /// Give restored files their recorded mtimes and, on macOS, creation times.
+        #[arg(long)]
+        preserve_times: bool,
+        /// On a case-insensitive target, restore paths that differ only in case from an earlier
+        /// one as `name (case N).ext` instead of failing with `restore.case_collision`.
+        #[arg(long)]
+        rename_collisions: bool,
+        /// Restore paths too long for the target's file system under
+        /// `.televybackup-long-paths/<hash>/` (mapped in its `index.json`) instead of failing with
+        /// `restore.path_too_long` before anything is downloaded.
+        #[arg(long)]
+        shorten_long_paths: bool,
+        /// Leave restored files owned by the restoring user (the default).
+        #[arg(long, conflicts_with_all = ["owner_mapping", "preserve_owners"])]
+        chown_to_current: bool,
+        /// Give entries recorded with these uids to other users: `olduid:newuid,...`.
+        #[arg(long, value_parser = parse_owner_mapping)]
+        owner_mapping: Option<OwnerMapping>,
+        /// Give entries their recorded uid/gid (needs root for other users' files).
+        #[arg(long)]
+        preserve_owners: bool,
+        /// Ask for the restore passphrase like a control-socket restore would.
+        #[arg(long)]
+        require_passphrase: bool,
     },
 }
 
@@ -221,72 +681,364 @@ enum VerifyCmd {
     Run {
         #[arg(long)]
         snapshot_id: String,
+        /// Check only this share of the chunks; the subset rotates weekly.
+        #[arg(long)]
+        sample_percent: Option<f64>,
+        /// Cap the bytes checked by a sample (e.g. `2G`, `500M`).
+        #[arg(long, value_parser = parse_byte_size)]
+        sample_max_bytes: Option<u64>,
+        /// Chunk objects to download and check at once (capped by the endpoint's
+        /// `rate_limit.max_concurrent_uploads`).
+        #[arg(long, default_value_t = 4)]
+        concurrency: usize,
+        /// Check the snapshot in a `repo export` directory instead of in Telegram.
+        #[arg(long, value_name = "DIR")]
+        from_local_repo: Option<PathBuf>,
+        /// Merge the run's outcome into this Prometheus textfile-collector file (overrides
+        /// `metrics.textfile_path`).
+        #[arg(long, value_name = "PATH")]
+        metrics_textfile: Option<PathBuf>,
     },
     Latest {
         #[arg(long)]
         target_id: Option<String>,
         #[arg(long)]
         source_path: Option<PathBuf>,
+        /// Check only this share of the chunks; the subset rotates weekly.
+        #[arg(long)]
+        sample_percent: Option<f64>,
+        /// Cap the bytes checked by a sample (e.g. `2G`, `500M`).
+        #[arg(long, value_parser = parse_byte_size)]
+        sample_max_bytes: Option<u64>,
+        /// Chunk objects to download and check at once (capped by the endpoint's
+        /// `rate_limit.max_concurrent_uploads`).
+        #[arg(long, default_value_t = 4)]
+        concurrency: usize,
+        /// Merge the run's outcome into this Prometheus textfile-collector file (overrides
+        /// `metrics.textfile_path`).
+        #[arg(long, value_name = "PATH")]
+        metrics_textfile: Option<PathBuf>,
     },
 }
 
-type Settings = settings_config::SettingsV2;
-
-#[derive(Debug, Serialize)]
-struct CliError {
-    code: &'static str,
-    message: String,
-    details: serde_json::Value,
-    retryable: bool,
+#[derive(Subcommand)]
+enum LogsCmd {
+    /// Newest first.
+    List {
+        #[arg(long)]
+        target_id: Option<String>,
+        /// backup, restore or verify.
+        #[arg(long)]
+        kind: Option<String>,
+        #[arg(long, default_value_t = 20)]
+        limit: u32,
+    },
+    Show {
+        #[arg(long)]
+        run_id: String,
+        /// Keep printing new lines until the run finishes.
+        #[arg(long)]
+        follow: bool,
+    },
+    /// Print the daemon's running task's log as it is written, starting with its last 100 lines,
+    /// until the run finishes.
+    Stream {
+        #[arg(long, required_unless_present = "task_id")]
+        target_id: Option<String>,
+        #[arg(long)]
+        task_id: Option<String>,
+    },
 }
 
-impl CliError {
-    fn new(code: &'static str, message: impl Into<String>) -> Self {
-        Self {
-            code,
-            message: message.into(),
-            details: serde_json::json!({}),
-            retryable: false,
-        }
-    }
-
-    fn retryable(code: &'static str, message: impl Into<String>) -> Self {
-        Self {
-            code,
-            message: message.into(),
-            details: serde_json::json!({}),
-            retryable: true,
-        }
-    }
-
-    fn with_details(mut self, details: serde_json::Value) -> Self {
-        self.details = details;
-        self
-    }
+#[derive(Subcommand)]
+enum DeviceCmd {
+    Show,
+    /// Change the name recorded on future snapshots (the device id is kept).
+    Rename {
+        name: String,
+    },
 }
 
-struct NdjsonProgressSink {
-    task_id: String,
-    throttle: Mutex<ProgressThrottle>,
-    daemon_status_report: Option<DaemonStatusReport>,
+#[derive(Subcommand)]
+enum AuditCmd {
+    /// Most recent entries, oldest first.
+    List {
+        #[arg(long, default_value_t = 50)]
+        limit: u32,
+    },
+    /// Walk the hash chain and report the first broken link.
+    Verify,
 }
 
-static VAULT_KEY_CACHE: OnceLock<[u8; 32]> = OnceLock::new();
+#[derive(Subcommand)]
+enum DataDirCmd {
+    /// Print the data dir, which rule picked it, and the size of what it holds.
+    Show,
+    /// Copy the data dir to `--to` and write the pointer file so every later command, the daemon
+    /// and the app use it. Refuses while the daemon or a run is active; stop the daemon first.
+    Migrate {
+        /// Absolute path of the new data dir; it must not hold data dir entries yet.
+        #[arg(long)]
+        to: PathBuf,
+        /// Delete the old copy once the new one checks out.
+        #[arg(long = "move", conflicts_with = "copy")]
+        move_entries: bool,
+        /// Keep the old copy (the default).
+        #[arg(long)]
+        copy: bool,
+    },
+}
 
-struct DaemonStatusReport {
-    data_dir: PathBuf,
-    kind: String,
-    target_id: String,
+#[derive(Subcommand)]
+enum IndexCmd {
+    /// Rewrite legacy provider strings and chunk object IDs (Bot API era, pre-endpoint MTProto)
+    /// to the current form. Opening a DB does this once automatically; this re-runs it.
+    MigrateProviders,
+    /// Point object ids at a new chat after its messages were forwarded there, using a mapping
+    /// file of `old_msg_id,new_msg_id` lines (see `telegram export-mapping`).
+    RemapChat {
+        #[arg(long)]
+        endpoint_id: String,
+        #[arg(long, allow_hyphen_values = true)]
+        old_chat: String,
+        #[arg(long, allow_hyphen_values = true)]
+        new_chat: String,
+        #[arg(long)]
+        mapping_file: PathBuf,
+        /// Only report how many object ids would change.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Upload a snapshot's file map from the local cache as a full index again, e.g. after
+    /// `index.chain_broken`. Later backups build their delta indexes on it.
+    Republish {
+        #[arg(long)]
+        endpoint_id: Option<String>,
+        #[arg(long)]
+        snapshot_id: String,
+    },
+    /// Write a snapshot's decoded file/chunk listing (see `index schema`) from the local file map,
+    /// downloading the index when there is none. The listing reveals every path, so it is only
+    /// written with `--i-understand-plaintext`.
+    Export {
+        #[arg(long)]
+        endpoint_id: Option<String>,
+        #[arg(long)]
+        snapshot_id: String,
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Also save the encrypted manifest object exactly as stored in the chat.
+        #[arg(long)]
+        raw_encrypted: Option<PathBuf>,
+        #[arg(long)]
+        i_understand_plaintext: bool,
+    },
+    /// Add a snapshot from an `index export` listing to the local index DB, e.g. one shared out of
+    /// band. The snapshot must be unknown locally.
+    Import {
+        #[arg(long)]
+        endpoint_id: Option<String>,
+        #[arg(long)]
+        snapshot_id: String,
+        #[arg(long)]
+        input: PathBuf,
+    },
+    /// Fields of the `index export` listing.
+    Schema,
+    /// Recount the chunk references of every snapshot and compare them with the stored counts
+    /// that `gc run` and `stats get` rely on; fails with `index.ref_count_drift` on a mismatch.
+    Check {
+        #[arg(long)]
+        endpoint_id: Option<String>,
+        /// Rebuild the counts from the file maps instead of failing. Run it while no backup is.
+        #[arg(long)]
+        repair: bool,
+    },
+    /// Run the remote-first index sync of `backup run` on its own: download the endpoint index
+    /// and the target's latest file map from the chat unless the local copies already match.
+    Sync {
+        #[arg(long)]
+        target_id: String,
+        /// Download even when the local index already matches the catalog.
+        #[arg(long)]
+        force: bool,
+        /// Sync this snapshot's file map instead of the target's latest.
+        #[arg(long)]
+        snapshot_id: Option<String>,
+    },
 }
 
-#[derive(Debug)]
-struct ProgressThrottle {
-    interval: Duration,
-    last_emit_at: Option<Instant>,
-    last_phase: Option<String>,
+#[derive(Subcommand)]
+enum TargetsCmd {
+    List,
+    /// Stop scheduled backups of a target; `backup run` still runs it.
+    Disable {
+        #[arg(long)]
+        target_id: String,
+        /// Resume scheduled backups at this instant (YYYY-MM-DD, midnight UTC, or RFC3339).
+        #[arg(long)]
+        until: Option<String>,
+    },
+    Enable {
+        #[arg(long)]
+        target_id: String,
+    },
 }
 
-impl ProgressThrottle {
+#[derive(Subcommand)]
+enum GcCmd {
+    /// Queue the chunk objects no snapshot in the endpoint's index references for deletion after
+    /// `retention.deletion_grace_days` (or delete them when it is 0). Syncs a stale local index
+    /// from the bootstrap catalog first.
+    Run {
+        #[arg(long)]
+        endpoint_id: Option<String>,
+        /// Only report what would be deleted.
+        #[arg(long)]
+        dry_run: bool,
+        /// Keep objects holding a chunk recorded within this many days, e.g. by a backup still
+        /// running on another machine.
+        #[arg(long, default_value_t = televy_backup_core::GC_DEFAULT_MIN_AGE_DAYS)]
+        min_age_days: u32,
+    },
+    /// Delete the queued objects whose grace period is over and that are still unreferenced.
+    Flush {
+        #[arg(long)]
+        endpoint_id: Option<String>,
+        /// Only report what would be deleted.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Bring back a snapshot removed by retention or `snapshots delete` within its grace period,
+    /// and take its objects off the deletion queue.
+    Undo {
+        #[arg(long)]
+        endpoint_id: Option<String>,
+        #[arg(long)]
+        snapshot_id: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum RepoCmd {
+    /// Download every object the endpoint's index references, plus a copy of the index DB, into
+    /// a directory that `restore run` / `verify run --from-local-repo` read. Re-running it only
+    /// fetches objects that are missing or whose size changed.
+    Export {
+        #[arg(long)]
+        endpoint_id: Option<String>,
+        #[arg(long)]
+        output: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum BootstrapCmd {
+    /// Print the decrypted catalog with its revision (the pinned object id).
+    Show {
+        #[arg(long)]
+        endpoint_id: Option<String>,
+    },
+    /// Drop a target from the catalog.
+    RemoveTarget {
+        #[arg(long)]
+        endpoint_id: Option<String>,
+        #[arg(long)]
+        target_id: String,
+        /// Write the catalog without asking.
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Point a target at a snapshot recorded in the local index DB.
+    SetLatest {
+        #[arg(long)]
+        endpoint_id: Option<String>,
+        #[arg(long)]
+        target_id: String,
+        #[arg(long)]
+        snapshot_id: String,
+        /// Write the catalog without asking.
+        #[arg(long)]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum PrivacyCmd {
+    /// Back a snapshot's source up again into a local recorder (nothing is uploaded) and report
+    /// every object name, caption or payload that contains one of the snapshot's paths or path
+    /// components. Exits with `privacy.leak` when there is any.
+    Audit {
+        #[arg(long)]
+        endpoint_id: Option<String>,
+        #[arg(long)]
+        snapshot_id: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum SecurityCmd {
+    /// Read a new restore passphrase twice from stdin and store its argon2 hash in settings.
+    SetRestorePassphrase,
+}
+
+type Settings = settings_config::SettingsV2;
+
+#[derive(Debug, Serialize)]
+struct CliError {
+    code: ErrorCode,
+    message: String,
+    details: serde_json::Value,
+    retryable: bool,
+}
+
+impl CliError {
+    fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            details: serde_json::json!({}),
+            retryable: false,
+        }
+    }
+
+    fn retryable(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            details: serde_json::json!({}),
+            retryable: true,
+        }
+    }
+
+    fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = details;
+        self
+    }
+}
+
+struct NdjsonProgressSink {
+    task_id: String,
+    throttle: Mutex<ProgressThrottle>,
+    daemon_status_report: Option<DaemonStatusReport>,
+}
+
+static VAULT_KEY_CACHE: OnceLock<[u8; 32]> = OnceLock::new();
+
+struct DaemonStatusReport {
+    data_dir: PathBuf,
+    kind: String,
+    target_id: String,
+}
+
+#[derive(Debug)]
+struct ProgressThrottle {
+    interval: Duration,
+    last_emit_at: Option<Instant>,
+    last_phase: Option<Phase>,
+}
+
+impl ProgressThrottle {
     fn new(interval: Duration) -> Self {
         Self {
             interval,
@@ -295,13 +1047,13 @@ impl ProgressThrottle {
         }
     }
 
-    fn should_emit(&mut self, phase: &str) -> bool {
+    fn should_emit(&mut self, phase: &Phase) -> bool {
         let now = Instant::now();
 
         // Always emit the first event, and whenever the phase changes (UI wants immediate
         // "phase flipped" feedback even if the progress cadence is throttled).
-        if self.last_phase.as_deref() != Some(phase) {
-            self.last_phase = Some(phase.to_string());
+        if self.last_phase.as_ref() != Some(phase) {
+            self.last_phase = Some(phase.clone());
             self.last_emit_at = Some(now);
             return true;
         }
@@ -343,18 +1095,7 @@ impl ProgressSink for NdjsonProgressSink {
             );
         }
 
-        // Many phases don't have a stable "total" upfront. In those cases, the core currently
-        // reports `*_total == *_done` as a "so far" counter which makes UI progress bars look
-        // stuck at 100%. Only surface totals when they look meaningful.
-        let chunks_total = match (p.phase.as_str(), p.chunks_total, p.chunks_done) {
-            (
-                "scan" | "scan_upload" | "upload" | "index" | "index_sync",
-                Some(total),
-                Some(done),
-            ) if total > 0 && total == done => None,
-            (_phase, other, _done) => other,
-        };
-
+        let chunks_total = progress_chunks_total(&p);
         let line = serde_json::json!({
             "type": "task.progress",
             "taskId": self.task_id,
@@ -372,12 +1113,36 @@ impl ProgressSink for NdjsonProgressSink {
             "bytesUploadedSource": p.bytes_uploaded_source,
             "bytesUploaded": p.bytes_uploaded,
             "bytesDownloaded": p.bytes_downloaded,
+            "bytesWritten": p.bytes_written,
             "bytesDeduped": p.bytes_deduped,
+            "bytesTotalEstimated": p.bytes_total_estimated,
+            "bytesTotal": p.bytes_total,
         });
         emit_event_stdout(line);
     }
 }
 
+/// `chunksTotal` as surfaced to the GUI.
+///
+/// Many backup phases don't have a stable "total" upfront. In those cases, the core currently
+/// reports `*_total == *_done` as a "so far" counter which makes UI progress bars look stuck at
+/// 100%. Only surface totals when they look meaningful. Runs that report `bytes_total` (restore,
+/// verify) know their totals from the index up front, so a tiny run finishing a phase quickly
+/// still keeps them.
+fn progress_chunks_total(p: &televy_backup_core::TaskProgress) -> Option<u64> {
+    if p.bytes_total.is_some() {
+        return p.chunks_total;
+    }
+    match (&p.phase, p.chunks_total, p.chunks_done) {
+        (
+            Phase::Scan | Phase::ScanUpload | Phase::Upload | Phase::Index | Phase::IndexSync,
+            Some(total),
+            Some(done),
+        ) if total > 0 && total == done => None,
+        (_phase, other, _done) => other,
+    }
+}
+
 fn emit_event_stdout(line: serde_json::Value) {
     // In `--events` mode, stdout is typically piped to the macOS GUI. Force line delivery so the
     // UI does not get "task.state" only when the process exits (block-buffered stdout).
@@ -434,7 +1199,7 @@ fn emit_task_progress_preflight(events: bool, task_id: &str) {
     emit_event_stdout(serde_json::json!({
         "type": "task.progress",
         "taskId": task_id,
-        "phase": "preflight",
+        "phase": Phase::Preflight,
         "filesTotal": serde_json::Value::Null,
         "filesDone": serde_json::Value::Null,
         "sourceFilesTotal": serde_json::Value::Null,
@@ -449,10 +1214,14 @@ fn emit_task_progress_preflight(events: bool, task_id: &str) {
         "bytesUploaded": 0,
         "bytesDownloaded": 0,
         "bytesDeduped": 0,
+        "bytesTotalEstimated": serde_json::Value::Null,
+        "bytesTotal": serde_json::Value::Null,
     }));
 }
 
-fn emit_task_state_failed(
+/// Terminal `task.state` event of a run that ended with `e`: `cancelled` for `task.cancelled`
+/// (with `partial` when the error carries it), `failed` otherwise.
+fn emit_task_state_error(
     events: bool,
     task_id: &str,
     kind: &str,
@@ -463,6 +1232,7 @@ fn emit_task_state_failed(
     if !events {
         return;
     }
+    let status = RunStatus::for_error_code(e.code.as_str());
     let mut obj = serde_json::Map::new();
     obj.insert(
         "type".to_string(),
@@ -478,7 +1248,7 @@ fn emit_task_state_failed(
     );
     obj.insert(
         "state".to_string(),
-        serde_json::Value::String("failed".to_string()),
+        serde_json::Value::String(status.as_str().to_string()),
     );
     if let Some(t) = target_id {
         obj.insert(
@@ -496,9 +1266,64 @@ fn emit_task_state_failed(
         "error".to_string(),
         serde_json::json!({ "code": e.code, "message": e.message.clone() }),
     );
+    if let Some(partial) = e.details.get("partial") {
+        obj.insert("partial".to_string(), partial.clone());
+    }
     emit_event_stdout(serde_json::Value::Object(obj));
 }
 
+/// Logs `run.finish` for a run that ended with `e`. A cancelled run is logged at warn level with
+/// `status = "cancelled"` and what it got done (`partial`).
+fn log_run_finish_error(
+    task_id: &str,
+    kind: &str,
+    ctx: RunCtx<'_>,
+    duration_seconds: f64,
+    e: &CliError,
+) -> RunStatus {
+    let status = RunStatus::for_error_code(e.code.as_str());
+    if status == RunStatus::Cancelled {
+        let partial = e
+            .details
+            .get("partial")
+            .map(|p| p.to_string())
+            .unwrap_or_default();
+        tracing::warn!(
+            event = "run.finish",
+            kind,
+            run_id = %task_id,
+            task_id = %task_id,
+            target_id = ctx.target_id.unwrap_or(""),
+            endpoint_id = ctx.endpoint_id.unwrap_or(""),
+            source_path = ctx.source_path.unwrap_or(""),
+            snapshot_id = ctx.snapshot_id.unwrap_or(""),
+            status = status.as_str(),
+            duration_seconds,
+            error_code = e.code.as_str(),
+            partial = %partial,
+            "run.finish"
+        );
+    } else {
+        tracing::error!(
+            event = "run.finish",
+            kind,
+            run_id = %task_id,
+            task_id = %task_id,
+            target_id = ctx.target_id.unwrap_or(""),
+            endpoint_id = ctx.endpoint_id.unwrap_or(""),
+            source_path = ctx.source_path.unwrap_or(""),
+            snapshot_id = ctx.snapshot_id.unwrap_or(""),
+            status = status.as_str(),
+            duration_seconds,
+            error_code = e.code.as_str(),
+            error_message = %e.message,
+            retryable = e.retryable,
+            "run.finish"
+        );
+    }
+    status
+}
+
 #[derive(Clone, Copy)]
 struct RunCtx<'a> {
     target_id: Option<&'a str>,
@@ -530,41 +1355,153 @@ fn emit_preflight_failed(
     );
 
     let duration_seconds = started.elapsed().as_secs_f64();
-    tracing::error!(
-        event = "run.finish",
-        kind,
-        run_id = %task_id,
-        task_id = %task_id,
-        target_id = ctx.target_id.unwrap_or(""),
-        endpoint_id = ctx.endpoint_id.unwrap_or(""),
-        source_path = ctx.source_path.unwrap_or(""),
-        snapshot_id = ctx.snapshot_id.unwrap_or(""),
-        status = "failed",
-        duration_seconds,
-        error_code = e.code,
-        error_message = %e.message,
-        retryable = e.retryable,
-        "run.finish"
-    );
-
-    emit_task_state_failed(events, task_id, kind, ctx.target_id, ctx.snapshot_id, &e);
+    log_run_finish_error(task_id, kind, ctx, duration_seconds, &e);
+    emit_task_state_error(events, task_id, kind, ctx.target_id, ctx.snapshot_id, &e);
     Err(e)
 }
 
 #[tokio::main]
 async fn main() {
     let cli = Cli::parse();
-    let code = match run(cli).await {
+    let exit_zero_on_retryable = cli.exit_zero_on_retryable;
+    let result = run(cli).await;
+    if let Err(e) = &result {
+        emit_error(e);
+    }
+    std::process::exit(exit_code(&result, exit_zero_on_retryable));
+}
+
+/// Exit status classes of a failed command, as listed in the `--help` footer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExitClass {
+    Failure = 1,
+    Usage = 2,
+    Retryable = 3,
+    Integrity = 4,
+    Cancelled = 5,
+}
+
+impl ExitClass {
+    fn of(e: &CliError) -> Self {
+        match e.code {
+            ErrorCode::TaskCancelled => Self::Cancelled,
+            ErrorCode::CliInvalid
+            | ErrorCode::ConfigInvalid
+            | ErrorCode::ConfigTooLarge
+            | ErrorCode::BackupConfirmationRequired
+            | ErrorCode::BootstrapConfirmationRequired
+            | ErrorCode::ConfigBundleConfirmRequired
+            | ErrorCode::ConfigBundlePassphraseRequired
+            | ErrorCode::SecretsConfirmationRequired
+            | ErrorCode::SecurityPassphraseNotSet
+            | ErrorCode::SecurityPassphraseRequired
+            | ErrorCode::TelegramMtprotoMissingApiHash => Self::Usage,
+            ErrorCode::Integrity
+            | ErrorCode::IntegrityBaseSnapshotMissingRemoteIndex
+            | ErrorCode::IntegrityManifestMismatch
+            | ErrorCode::ChunkMissing
+            | ErrorCode::IndexChainBroken
+            | ErrorCode::IndexPartMissing
+            | ErrorCode::IndexRefCountDrift
+            | ErrorCode::AuditChainBroken
+            | ErrorCode::PrivacyLeak
+            | ErrorCode::RestorePartial
+            | ErrorCode::TelegramUploadCorrupted => Self::Integrity,
+            ErrorCode::TelegramUnavailable
+            | ErrorCode::TelegramTimeout
+            | ErrorCode::ControlTimeout
+            | ErrorCode::ControlUnavailable
+            | ErrorCode::DaemonUnavailable
+            | ErrorCode::DataDirInUse
+            | ErrorCode::RemoteRateLimited
+            | ErrorCode::RemoteUnavailable
+            | ErrorCode::StatusUnavailable => Self::Retryable,
+            _ if e.retryable => Self::Retryable,
+            _ => Self::Failure,
+        }
+    }
+}
+
+/// The process exit status for `result`; the only place errors are turned into one.
+fn exit_code(result: &Result<(), CliError>, exit_zero_on_retryable: bool) -> i32 {
+    match result {
         Ok(()) => 0,
-        Err(e) => {
-            emit_error(&e);
-            1
+        Err(e) => match ExitClass::of(e) {
+            ExitClass::Retryable if exit_zero_on_retryable => 0,
+            class => class as i32,
+        },
+    }
+}
+
+/// Exit status when a second stop signal ends the process right away (128 + SIGINT, as shells
+/// report Ctrl-C).
+const EXIT_CODE_CANCELLED: i32 = 130;
+
+static STOP_SIGNAL_CANCEL: OnceLock<CancellationToken> = OnceLock::new();
+
+/// Cancelled on the first Ctrl-C or SIGTERM (how the app stops a task), so a run stops at its
+/// next checkpoint and reports `cancelled`; a second signal exits right away.
+fn cancel_on_stop_signal() -> CancellationToken {
+    STOP_SIGNAL_CANCEL
+        .get_or_init(|| {
+            let cancel = CancellationToken::new();
+            let for_task = cancel.clone();
+            tokio::spawn(async move {
+                wait_for_stop_signal().await;
+                tracing::warn!(event = "run.cancel_requested", "run.cancel_requested");
+                for_task.cancel();
+                wait_for_stop_signal().await;
+                std::process::exit(EXIT_CODE_CANCELLED);
+            });
+            cancel
+        })
+        .clone()
+}
+
+#[cfg(unix)]
+async fn wait_for_stop_signal() {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    match signal(SignalKind::terminate()) {
+        Ok(mut term) => {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = term.recv() => {}
+            }
         }
-    };
-    std::process::exit(code);
+        Err(_) => {
+            let _ = tokio::signal::ctrl_c().await;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_stop_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+fn run_status<T>(result: &Result<T, CliError>) -> RunStatus {
+    match result {
+        Ok(_) => RunStatus::Succeeded,
+        Err(e) => RunStatus::for_error_code(e.code.as_str()),
+    }
+}
+
+/// Adds what the run got done (`details.partial`) to a `task.cancelled` error.
+fn with_partial_result(mut e: CliError, recorder: &ProgressRecorder<'_>) -> CliError {
+    if e.code == ErrorCode::TaskCancelled
+        && let Some(details) = e.details.as_object_mut()
+    {
+        details.insert(
+            "partial".to_string(),
+            serde_json::to_value(recorder.partial_result()).unwrap_or_default(),
+        );
+    }
+    e
 }
 
 async fn run(cli: Cli) -> Result<(), CliError> {
+    format::set_raw(cli.raw);
     let config_dir = cli
         .config_dir
         .or_else(|| {
@@ -573,16 +1510,24 @@ async fn run(cli: Cli) -> Result<(), CliError> {
                 .map(PathBuf::from)
         })
         .unwrap_or_else(default_config_dir);
-    let data_dir = cli
-        .data_dir
-        .or_else(|| {
-            std::env::var("TELEVYBACKUP_DATA_DIR")
-                .ok()
-                .map(PathBuf::from)
-        })
-        .unwrap_or_else(default_data_dir);
+    let (data_dir, data_dir_source) =
+        data_dir::resolve_data_dir(cli.data_dir, &config_dir, default_data_dir)
+            .map_err(map_core_err)?;
+
+    if cli.error_catalog {
+        println!("{}", ErrorCode::catalog());
+        return Ok(());
+    }
+    let Some(cmd) = cli.cmd else {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::MissingSubcommand,
+                "a subcommand is required",
+            )
+            .exit();
+    };
 
-    match cli.cmd {
+    match cmd {
         Command::Ping { value } => {
             if cli.json {
                 println!(
@@ -595,10 +1540,30 @@ async fn run(cli: Cli) -> Result<(), CliError> {
             Ok(())
         }
         Command::Settings { cmd } => match cmd {
-            SettingsCmd::Get { with_secrets } => {
+            SettingsCmd::Get {
+                effective: true,
+                target_id,
+                ..
+            } => settings_get_effective(&config_dir, target_id.as_deref(), cli.json),
+            SettingsCmd::Get { with_secrets, .. } => {
                 settings_get(&config_dir, &data_dir, cli.json, with_secrets).await
             }
-            SettingsCmd::Set => settings_set(&config_dir, cli.json).await,
+            SettingsCmd::Set { input_file } => {
+                settings_set(&config_dir, input_file.as_deref(), cli.json).await
+            }
+            SettingsCmd::Validate { input_file } => {
+                settings_validate(&config_dir, input_file.as_deref(), cli.json)
+            }
+            SettingsCmd::Schema => {
+                settings_schema(cli.json);
+                Ok(())
+            }
+            SettingsCmd::Defaults => {
+                settings_defaults(cli.json);
+                Ok(())
+            }
+            SettingsCmd::History => settings_history(&config_dir, cli.json),
+            SettingsCmd::Rollback { to } => settings_rollback(&config_dir, to, cli.json),
             SettingsCmd::ExportBundle { hint } => {
                 settings_export_bundle(&config_dir, &data_dir, cli.json, hint).await
             }
@@ -606,6 +1571,8 @@ async fn run(cli: Cli) -> Result<(), CliError> {
                 dry_run,
                 apply,
                 compare_folder,
+                resume,
+                input_file,
             } => {
                 let chosen = [dry_run, apply, compare_folder]
                     .into_iter()
@@ -613,16 +1580,32 @@ async fn run(cli: Cli) -> Result<(), CliError> {
                     .count();
                 if chosen != 1 {
                     return Err(CliError::new(
-                        "config.invalid",
+                        ErrorCode::ConfigInvalid,
                         "must pass exactly one of: --dry-run, --apply, --compare-folder",
                     ));
                 }
+                let input_file = input_file.as_deref();
                 if dry_run {
-                    settings_import_bundle_dry_run(&config_dir, &data_dir, cli.json).await
+                    settings_import_bundle_dry_run(&config_dir, &data_dir, input_file, cli.json)
+                        .await
                 } else if apply {
-                    settings_import_bundle_apply(&config_dir, &data_dir, cli.json).await
+                    settings_import_bundle_apply(
+                        &config_dir,
+                        &data_dir,
+                        input_file,
+                        cli.json,
+                        cli.events,
+                        resume,
+                    )
+                    .await
                 } else {
-                    settings_import_bundle_compare_folder(&config_dir, &data_dir, cli.json).await
+                    settings_import_bundle_compare_folder(
+                        &config_dir,
+                        &data_dir,
+                        input_file,
+                        cli.json,
+                    )
+                    .await
                 }
             }
         },
@@ -630,11 +1613,27 @@ async fn run(cli: Cli) -> Result<(), CliError> {
             VaultCmd::Ensure => vault_ensure(&config_dir, &data_dir, cli.json).await,
         },
         Command::Secrets { cmd } => match cmd {
-            SecretsCmd::SetTelegramBotToken { endpoint_id } => {
-                secrets_set_telegram_bot_token(&config_dir, &data_dir, endpoint_id, cli.json).await
+            SecretsCmd::SetTelegramBotToken {
+                endpoint_id,
+                input_file,
+            } => {
+                secrets_set_telegram_bot_token(
+                    &config_dir,
+                    &data_dir,
+                    endpoint_id,
+                    input_file.as_deref(),
+                    cli.json,
+                )
+                .await
             }
-            SecretsCmd::SetTelegramApiHash => {
-                secrets_set_telegram_api_hash(&config_dir, &data_dir, cli.json).await
+            SecretsCmd::SetTelegramApiHash { input_file } => {
+                secrets_set_telegram_api_hash(
+                    &config_dir,
+                    &data_dir,
+                    input_file.as_deref(),
+                    cli.json,
+                )
+                .await
             }
             SecretsCmd::ClearTelegramMtprotoSession => {
                 secrets_clear_telegram_mtproto_session(&config_dir, &data_dir, cli.json).await
@@ -648,8 +1647,49 @@ async fn run(cli: Cli) -> Result<(), CliError> {
             SecretsCmd::ExportMasterKey { i_understand } => {
                 secrets_export_master_key(&config_dir, &data_dir, i_understand, cli.json).await
             }
-            SecretsCmd::ImportMasterKey { force } => {
-                secrets_import_master_key(&config_dir, &data_dir, force, cli.json).await
+            SecretsCmd::ExportTargetKey {
+                target_id,
+                i_understand,
+            } => {
+                secrets_export_target_key(
+                    &config_dir,
+                    &data_dir,
+                    &target_id,
+                    i_understand,
+                    cli.json,
+                )
+                .await
+            }
+            SecretsCmd::ImportMasterKey { force, input_file } => {
+                secrets_import_master_key(
+                    &config_dir,
+                    &data_dir,
+                    force,
+                    input_file.as_deref(),
+                    cli.json,
+                )
+                .await
+            }
+            SecretsCmd::List => secrets_list(&config_dir, &data_dir, cli.json).await,
+            SecretsCmd::Delete {
+                key,
+                orphaned: _,
+                yes,
+                i_understand_data_loss,
+                no_print,
+            } => {
+                secrets_delete(
+                    &config_dir,
+                    &data_dir,
+                    key,
+                    SecretsDeleteFlags {
+                        yes,
+                        i_understand_data_loss,
+                        no_print,
+                    },
+                    cli.json,
+                )
+                .await
             }
         },
         Command::Telegram { cmd } => match cmd {
@@ -675,6 +1715,7 @@ async fn run(cli: Cli) -> Result<(), CliError> {
                 endpoint_id,
                 timeout_secs,
                 include_users,
+                poll_interval_ms,
             } => {
                 telegram_wait_chat(
                     &config_dir,
@@ -682,52 +1723,254 @@ async fn run(cli: Cli) -> Result<(), CliError> {
                     endpoint_id,
                     timeout_secs,
                     include_users,
+                    poll_interval_ms,
                     cli.json,
+                    cli.events,
                 )
                 .await
             }
-        },
-        Command::Snapshots { cmd } => match cmd {
-            SnapshotsCmd::List { limit } => snapshots_list(&data_dir, limit, cli.json).await,
-        },
-        Command::Stats { cmd } => match cmd {
-            StatsCmd::Get => stats_get(&data_dir, cli.json).await,
-            StatsCmd::Last { source } => stats_last(&data_dir, source, cli.json).await,
-        },
-        Command::Status { cmd } => match cmd {
-            StatusCmd::Get => status_get(&config_dir, &data_dir, cli.json).await,
-            StatusCmd::Stream => status_stream(&config_dir, &data_dir, cli.json).await,
-        },
-        Command::Backup { cmd } => match cmd {
-            BackupCmd::Run {
-                target_id,
-                source,
-                label,
-                no_remote_index_sync,
+            TelegramCmd::AuditChat {
+                endpoint_id,
+                from_msg_id,
+                max_msg_id,
+                sample_percent,
+                no_download,
             } => {
-                backup_run(
+                telegram_audit_chat(
                     &config_dir,
                     &data_dir,
-                    target_id,
-                    source,
-                    label,
-                    no_remote_index_sync,
+                    endpoint_id,
+                    from_msg_id,
+                    max_msg_id,
+                    sample_percent,
+                    no_download,
                     cli.json,
-                    cli.events,
                 )
                 .await
             }
-        },
-        Command::Restore { cmd } => match cmd {
-            RestoreCmd::Run {
-                snapshot_id,
-                target,
+            TelegramCmd::ExportMapping {
+                endpoint_id,
+                old_chat,
+                output,
+                max_msg_id,
             } => {
-                restore_run(
+                telegram_export_mapping(
                     &config_dir,
                     &data_dir,
-                    snapshot_id,
-                    target,
+                    endpoint_id,
+                    &old_chat,
+                    &output,
+                    max_msg_id,
+                    cli.json,
+                )
+                .await
+            }
+        },
+        Command::Snapshots { cmd } => match cmd {
+            SnapshotsCmd::List {
+                limit,
+                source_path,
+                target_id,
+                since,
+                until,
+                asc,
+                paginate,
+                cursor,
+                remote,
+            } if remote.remote.is_some() => {
+                let params = televy_backup_core::remote::RemoteSnapshotsListParams {
+                    limit: Some(limit),
+                    target_id,
+                    source_path: source_path
+                        .map(|p| {
+                            p.into_os_string().into_string().map_err(|_| {
+                                CliError::new(
+                                    ErrorCode::ConfigInvalid,
+                                    "source path is not valid utf-8",
+                                )
+                            })
+                        })
+                        .transpose()?,
+                    since,
+                    until,
+                    asc,
+                    paginate,
+                    cursor,
+                };
+                snapshots_list_remote(&remote, params, cli.json)
+            }
+            SnapshotsCmd::List {
+                limit,
+                source_path,
+                target_id,
+                since,
+                until,
+                asc,
+                paginate,
+                cursor,
+                remote: _,
+            } => {
+                let filter = SnapshotsListFilter {
+                    source_path,
+                    target_id,
+                    since,
+                    until,
+                    asc,
+                    paginate: paginate || cursor.is_some(),
+                    cursor,
+                };
+                snapshots_list(&config_dir, &data_dir, limit, filter, cli.json).await
+            }
+            SnapshotsCmd::Pin { snapshot_id } => {
+                snapshots_set_pinned(&data_dir, &snapshot_id, true, cli.json).await
+            }
+            SnapshotsCmd::Unpin { snapshot_id } => {
+                snapshots_set_pinned(&data_dir, &snapshot_id, false, cli.json).await
+            }
+            SnapshotsCmd::Files { snapshot_id } => {
+                snapshots_files(&data_dir, &snapshot_id, cli.json).await
+            }
+            SnapshotsCmd::Delete { snapshot_id, force } => {
+                snapshots_delete(&data_dir, &snapshot_id, force, cli.json).await
+            }
+            SnapshotsCmd::Chain { snapshot_id } => {
+                snapshots_chain(&data_dir, &snapshot_id, cli.json).await
+            }
+            SnapshotsCmd::Rebase { snapshot_id, onto } => {
+                let onto = (onto != "none").then_some(onto);
+                snapshots_rebase(&data_dir, &snapshot_id, onto.as_deref(), cli.json).await
+            }
+        },
+        Command::Stats { cmd } => match cmd {
+            StatsCmd::Get { remote } if remote.remote.is_some() => {
+                stats_get_remote(&remote, cli.json)
+            }
+            StatsCmd::Get { remote: _ } => stats_get(&data_dir, cli.json).await,
+            StatsCmd::Last { source } => stats_last(&data_dir, source, cli.json).await,
+            StatsCmd::Monthly { target_id } => {
+                stats_monthly(&data_dir, target_id.as_deref(), cli.json).await
+            }
+            StatsCmd::Prune { older_than } => stats_prune(&data_dir, &older_than, cli.json).await,
+            StatsCmd::TopFiles { snapshot_id, limit } => {
+                stats_top_files(&data_dir, &snapshot_id, limit, cli.json).await
+            }
+        },
+        Command::Status { cmd } => match cmd {
+            StatusCmd::Get { remote } if remote.remote.is_some() => {
+                status_get_remote(&remote, cli.json)
+            }
+            StatusCmd::Get { remote: _ } => status_get(&config_dir, &data_dir, cli.json).await,
+            StatusCmd::Stream => status_stream(&config_dir, &data_dir, cli.json).await,
+            StatusCmd::Watch => status_watch(&config_dir, &data_dir, cli.json).await,
+        },
+        Command::Backup { cmd } => match cmd {
+            BackupCmd::Run {
+                target_id,
+                source,
+                cross_filesystems,
+                dry_run: true,
+                ..
+            } => backup_dry_run(&config_dir, target_id, source, cross_filesystems, cli.json).await,
+            BackupCmd::Run {
+                target_id,
+                source,
+                label,
+                no_remote_index_sync,
+                yes,
+                strict,
+                cross_filesystems,
+                respect_quiet_hours,
+                accept_source_change,
+                dry_run: false,
+                metrics_textfile,
+            } => {
+                backup_run(
+                    &config_dir,
+                    &data_dir,
+                    target_id,
+                    source,
+                    label,
+                    no_remote_index_sync,
+                    yes,
+                    strict,
+                    cross_filesystems,
+                    respect_quiet_hours,
+                    accept_source_change,
+                    cli.json,
+                    cli.events,
+                    None,
+                    metrics_textfile.as_deref(),
+                )
+                .await
+            }
+        },
+        Command::Import { cmd } => match cmd {
+            ImportCmd::ResticDump {
+                input,
+                target_id,
+                no_remote_index_sync,
+            } => {
+                import_restic_dump(
+                    &config_dir,
+                    &data_dir,
+                    &input,
+                    &target_id,
+                    no_remote_index_sync,
+                    cli.json,
+                )
+                .await
+            }
+        },
+        Command::Restore { cmd } => match cmd {
+            RestoreCmd::Run {
+                snapshot_id,
+                target,
+                keep_going,
+                delete_extraneous,
+                dry_run,
+                as_file,
+                preserve_times,
+                rename_collisions,
+                shorten_long_paths,
+                chown_to_current: _,
+                owner_mapping,
+                preserve_owners,
+                require_passphrase,
+                target_key,
+                manifest_object_id,
+                from_local_repo,
+            } => {
+                if require_passphrase {
+                    confirm_restore_passphrase(&config_dir, &data_dir)?;
+                }
+                let target_key = match target_key {
+                    Some(path) => {
+                        let input = read_command_input(Some(&path))?;
+                        Some(gold_key::decode_target_key(&input).map_err(map_core_err)?)
+                    }
+                    None => None,
+                };
+                restore_run(
+                    &config_dir,
+                    &data_dir,
+                    snapshot_id,
+                    target,
+                    target_key,
+                    manifest_object_id,
+                    from_local_repo,
+                    RestoreFlags {
+                        keep_going,
+                        delete_extraneous,
+                        dry_run,
+                        as_file,
+                        preserve_times,
+                        rename_collisions,
+                        shorten_long_paths,
+                        ownership: OwnershipOptions {
+                            preserve_owners,
+                            uid_map: owner_mapping.unwrap_or_default(),
+                        },
+                    },
                     cli.json,
                     cli.events,
                 )
@@ -736,38 +1979,313 @@ async fn run(cli: Cli) -> Result<(), CliError> {
             RestoreCmd::ListLatest { endpoint_id } => {
                 restore_list_latest(&config_dir, &data_dir, endpoint_id, cli.json).await
             }
+            RestoreCmd::Estimate { snapshot_id, path } => {
+                restore_estimate(
+                    &config_dir,
+                    &data_dir,
+                    &snapshot_id,
+                    path.as_deref(),
+                    cli.json,
+                )
+                .await
+            }
             RestoreCmd::Latest {
                 target_id,
                 source_path,
                 target,
+                keep_going,
+                delete_extraneous,
+                dry_run,
+                as_file,
+                preserve_times,
+                rename_collisions,
+                shorten_long_paths,
+                chown_to_current: _,
+                owner_mapping,
+                preserve_owners,
+                require_passphrase,
             } => {
+                if require_passphrase {
+                    confirm_restore_passphrase(&config_dir, &data_dir)?;
+                }
                 restore_latest(
                     &config_dir,
                     &data_dir,
                     target_id,
                     source_path,
                     target,
+                    RestoreFlags {
+                        keep_going,
+                        delete_extraneous,
+                        dry_run,
+                        as_file,
+                        preserve_times,
+                        rename_collisions,
+                        shorten_long_paths,
+                        ownership: OwnershipOptions {
+                            preserve_owners,
+                            uid_map: owner_mapping.unwrap_or_default(),
+                        },
+                    },
                     cli.json,
                     cli.events,
                 )
                 .await
             }
         },
+        Command::Logs { cmd } => match cmd {
+            LogsCmd::List {
+                target_id,
+                kind,
+                limit,
+            } => logs_list(&data_dir, target_id, kind, limit, cli.json),
+            LogsCmd::Show { run_id, follow } => logs_show(&data_dir, &run_id, follow).await,
+            LogsCmd::Stream { target_id, task_id } => logs_stream(&data_dir, task_id, target_id),
+        },
+        Command::Device { cmd } => match cmd {
+            DeviceCmd::Show => device_show(&data_dir, cli.json),
+            DeviceCmd::Rename { name } => device_rename(&data_dir, &name, cli.json),
+        },
+        Command::Audit { cmd } => match cmd {
+            AuditCmd::List { limit } => audit_list(&data_dir, limit, cli.json),
+            AuditCmd::Verify => audit_verify(&data_dir, cli.json),
+        },
+        Command::Index { cmd } => match cmd {
+            IndexCmd::MigrateProviders => index_migrate_providers(&data_dir, cli.json).await,
+            IndexCmd::RemapChat {
+                endpoint_id,
+                old_chat,
+                new_chat,
+                mapping_file,
+                dry_run,
+            } => {
+                index_remap_chat(
+                    &data_dir,
+                    &endpoint_id,
+                    &old_chat,
+                    &new_chat,
+                    &mapping_file,
+                    dry_run,
+                    cli.json,
+                )
+                .await
+            }
+            IndexCmd::Republish {
+                endpoint_id,
+                snapshot_id,
+            } => index_republish(&config_dir, &data_dir, endpoint_id, &snapshot_id, cli.json).await,
+            IndexCmd::Export {
+                endpoint_id,
+                snapshot_id,
+                output,
+                raw_encrypted,
+                i_understand_plaintext,
+            } => {
+                index_export(
+                    &config_dir,
+                    &data_dir,
+                    endpoint_id,
+                    &snapshot_id,
+                    output.as_deref(),
+                    raw_encrypted.as_deref(),
+                    i_understand_plaintext,
+                    cli.json,
+                )
+                .await
+            }
+            IndexCmd::Import {
+                endpoint_id,
+                snapshot_id,
+                input,
+            } => {
+                index_import(
+                    &config_dir,
+                    &data_dir,
+                    endpoint_id,
+                    &snapshot_id,
+                    &input,
+                    cli.json,
+                )
+                .await
+            }
+            IndexCmd::Schema => {
+                index_schema(cli.json);
+                Ok(())
+            }
+            IndexCmd::Check {
+                endpoint_id,
+                repair,
+            } => index_check(&config_dir, &data_dir, endpoint_id, repair, cli.json).await,
+            IndexCmd::Sync {
+                target_id,
+                force,
+                snapshot_id,
+            } => {
+                index_sync(
+                    &config_dir,
+                    &data_dir,
+                    target_id,
+                    force,
+                    snapshot_id,
+                    cli.json,
+                )
+                .await
+            }
+        },
+        Command::Privacy { cmd } => match cmd {
+            PrivacyCmd::Audit {
+                endpoint_id,
+                snapshot_id,
+            } => privacy_audit(&config_dir, &data_dir, endpoint_id, &snapshot_id, cli.json).await,
+        },
+        Command::DataDir { cmd } => match cmd {
+            DataDirCmd::Show => data_dir_show(&config_dir, &data_dir, data_dir_source, cli.json),
+            DataDirCmd::Migrate {
+                to, move_entries, ..
+            } => {
+                let mode = if move_entries {
+                    data_dir::DataDirMigrateMode::Move
+                } else {
+                    data_dir::DataDirMigrateMode::Copy
+                };
+                data_dir_migrate(&config_dir, &data_dir, data_dir_source, &to, mode, cli.json).await
+            }
+        },
+        Command::Doctor => doctor(&config_dir, &data_dir, cli.json).await,
+        Command::Version { all } => version(&data_dir, all, cli.json).await,
+        Command::Targets { cmd } => match cmd {
+            TargetsCmd::List => targets_list(&config_dir, cli.json),
+            TargetsCmd::Disable { target_id, until } => {
+                targets_set_enabled(&config_dir, &target_id, false, until.as_deref(), cli.json)
+            }
+            TargetsCmd::Enable { target_id } => {
+                targets_set_enabled(&config_dir, &target_id, true, None, cli.json)
+            }
+        },
+        Command::Gc { cmd } => match cmd {
+            GcCmd::Run {
+                endpoint_id,
+                dry_run,
+                min_age_days,
+            } => {
+                gc_run(
+                    &config_dir,
+                    &data_dir,
+                    endpoint_id,
+                    dry_run,
+                    Some(min_age_days),
+                    cli.json,
+                )
+                .await
+            }
+            GcCmd::Flush {
+                endpoint_id,
+                dry_run,
+            } => gc_run(&config_dir, &data_dir, endpoint_id, dry_run, None, cli.json).await,
+            GcCmd::Undo {
+                endpoint_id,
+                snapshot_id,
+            } => gc_undo(&config_dir, &data_dir, endpoint_id, &snapshot_id, cli.json).await,
+        },
+        Command::Repo { cmd } => match cmd {
+            RepoCmd::Export {
+                endpoint_id,
+                output,
+            } => repo_export(&config_dir, &data_dir, endpoint_id, &output, cli.json).await,
+        },
+        Command::Bootstrap { cmd } => match cmd {
+            BootstrapCmd::Show { endpoint_id } => {
+                bootstrap_show(&config_dir, &data_dir, endpoint_id, cli.json).await
+            }
+            BootstrapCmd::RemoveTarget {
+                endpoint_id,
+                target_id,
+                yes,
+            } => {
+                bootstrap_remove_target(
+                    &config_dir,
+                    &data_dir,
+                    endpoint_id,
+                    &target_id,
+                    yes,
+                    cli.json,
+                )
+                .await
+            }
+            BootstrapCmd::SetLatest {
+                endpoint_id,
+                target_id,
+                snapshot_id,
+                yes,
+            } => {
+                bootstrap_set_latest(
+                    &config_dir,
+                    &data_dir,
+                    endpoint_id,
+                    &target_id,
+                    &snapshot_id,
+                    yes,
+                    cli.json,
+                )
+                .await
+            }
+        },
+        Command::Security { cmd } => match cmd {
+            SecurityCmd::SetRestorePassphrase => {
+                security_set_restore_passphrase(&config_dir, &data_dir, cli.json)
+            }
+        },
+        Command::Completions { shell } => {
+            clap_complete::generate(
+                shell,
+                &mut Cli::command(),
+                "televybackup",
+                &mut std::io::stdout(),
+            );
+            Ok(())
+        }
         Command::Verify { cmd } => match cmd {
-            VerifyCmd::Run { snapshot_id } => {
-                verify_run(&config_dir, &data_dir, snapshot_id, cli.json, cli.events).await
+            VerifyCmd::Run {
+                snapshot_id,
+                sample_percent,
+                sample_max_bytes,
+                concurrency,
+                from_local_repo,
+                metrics_textfile,
+            } => {
+                let sample = verify_sample_from_args(sample_percent, sample_max_bytes)?;
+                verify_run(
+                    &config_dir,
+                    &data_dir,
+                    snapshot_id,
+                    sample,
+                    concurrency,
+                    from_local_repo,
+                    cli.json,
+                    cli.events,
+                    metrics_textfile.as_deref(),
+                )
+                .await
             }
             VerifyCmd::Latest {
                 target_id,
                 source_path,
+                sample_percent,
+                sample_max_bytes,
+                concurrency,
+                metrics_textfile,
             } => {
+                let sample = verify_sample_from_args(sample_percent, sample_max_bytes)?;
                 verify_latest(
                     &config_dir,
                     &data_dir,
                     target_id,
                     source_path,
+                    sample,
+                    concurrency,
                     cli.json,
                     cli.events,
+                    metrics_textfile.as_deref(),
                 )
                 .await
             }
@@ -794,55 +2312,181 @@ async fn vault_ensure(config_dir: &Path, data_dir: &Path, json: bool) -> Result<
 async fn status_get(config_dir: &Path, data_dir: &Path, json: bool) -> Result<(), CliError> {
     let snap = match read_status_snapshot_from_ipc(data_dir).await {
         Ok(s) => s,
-        Err(e) if e.code == "status.unavailable" => {
-            read_status_snapshot_from_file(config_dir, data_dir)?
+        Err(e) if e.code == ErrorCode::StatusUnavailable => {
+            let mut snap = read_status_snapshot_from_file(config_dir, data_dir)?;
+            attach_failed_run_log_excerpts(config_dir, data_dir, &mut snap);
+            snap
         }
         Err(e) => return Err(e),
     };
+    print_status_snapshot(&snap, json)
+}
 
+fn status_get_remote(remote: &RemoteArgs, json: bool) -> Result<(), CliError> {
+    let result = remote_call(remote, "status.get", serde_json::json!({}))?;
+    let snap = serde_json::from_value(result)
+        .map_err(|e| CliError::new(ErrorCode::StatusInvalid, e.to_string()))?;
+    print_status_snapshot(&snap, json)
+}
+
+fn print_status_snapshot(
+    snap: &televy_backup_core::status::StatusSnapshot,
+    json: bool,
+) -> Result<(), CliError> {
     if json {
         println!(
             "{}",
-            serde_json::to_string(&snap)
-                .map_err(|e| CliError::new("status.invalid", e.to_string()))?
+            serde_json::to_string(snap)
+                .map_err(|e| CliError::new(ErrorCode::StatusInvalid, e.to_string()))?
         );
     } else {
         println!(
             "status: schemaVersion={} generatedAt={} targets={}",
             snap.schema_version,
-            snap.generated_at,
+            format::timestamp_ms(snap.generated_at),
             snap.targets.len()
         );
+        if let Some(bytes) = snap.pending_deletion_bytes().filter(|b| *b > 0) {
+            println!("pendingDeletion={}", format::bytes(bytes));
+        }
+        for t in &snap.targets {
+            let mut line = format!("target={} state={}", t.target_id, t.state);
+            if let Some(healthy) = t.healthy() {
+                line.push_str(&format!(" healthy={healthy}"));
+            }
+            if let Some(bps) = t.up.bytes_per_second {
+                line.push_str(&format!(" up={}", format::rate(bps)));
+            }
+            if let Some(run) = &t.last_run {
+                if let Some(status) = &run.status {
+                    line.push_str(&format!(" lastRun={status}"));
+                }
+                if let Some(at) = &run.finished_at {
+                    line.push_str(&format!(" lastRunAt={}", format::timestamp(at)));
+                }
+                if let Some(s) = run.duration_seconds {
+                    line.push_str(&format!(" lastRunDuration={}", format::duration(s)));
+                }
+                if let Some(b) = run.bytes_uploaded {
+                    line.push_str(&format!(" lastRunUploaded={}", format::bytes(b)));
+                }
+            }
+            println!("{line}");
+        }
     }
 
     Ok(())
 }
 
-async fn status_stream(config_dir: &Path, data_dir: &Path, json: bool) -> Result<(), CliError> {
-    if !json {
-        return Err(CliError::new(
-            "cli.invalid",
-            "--json is required for status stream",
-        ));
+/// Fills `lastRun.logExcerpt` for failed targets from the newest run log on disk.
+///
+/// The daemon populates excerpts itself; this covers snapshots written without them (older
+/// daemons, CLI-run tasks). Secrets are best-effort: the daemon is usually down on this path.
+fn attach_failed_run_log_excerpts(
+    config_dir: &Path,
+    data_dir: &Path,
+    snap: &mut televy_backup_core::status::StatusSnapshot,
+) {
+    let needs_excerpt = |t: &televy_backup_core::status::TargetState| {
+        t.state == "failed" && t.last_run.as_ref().is_none_or(|r| r.log_excerpt.is_empty())
+    };
+    if !snap.targets.iter().any(needs_excerpt) {
+        return;
     }
 
-    #[cfg(unix)]
-    {
-        if let Ok(stream) = connect_status_ipc(data_dir).await {
-            return status_stream_ipc(stream).await;
+    let mut secrets = Vec::<String>::new();
+    if let Ok(settings) = load_settings(config_dir) {
+        let mut keys = vec![settings.telegram.mtproto.api_hash_key.clone()];
+        for e in &settings.telegram_endpoints {
+            keys.push(e.bot_token_key.clone());
+            keys.extend(e.mtproto.api_hash_key.clone());
+        }
+        for key in keys {
+            match get_secret(config_dir, data_dir, &key) {
+                Ok(Some(v)) if !v.is_empty() => secrets.push(v),
+                Ok(_) => {}
+                Err(_) => break,
+            }
         }
     }
+    let secrets = secrets.iter().map(|s| s.as_str()).collect::<Vec<_>>();
 
-    status_stream_file(config_dir, data_dir).await
+    for t in snap.targets.iter_mut().filter(|t| needs_excerpt(t)) {
+        let Ok(Some(path)) = televy_backup_core::run_log::find_latest_run_log_for_target(
+            data_dir,
+            "backup",
+            &t.target_id,
+        ) else {
+            continue;
+        };
+        let Ok(excerpt) = televy_backup_core::run_log::read_run_log_excerpt(&path, &secrets) else {
+            continue;
+        };
+        let last_run =
+            t.last_run
+                .get_or_insert_with(|| televy_backup_core::status::TargetRunSummary {
+                    finished_at: None,
+                    duration_seconds: None,
+                    status: Some("failed".to_string()),
+                    error_code: None,
+                    error_message: None,
+                    files_indexed: None,
+                    bytes_uploaded: None,
+                    bytes_deduped: None,
+                    upload_duration_seconds: None,
+                    log_excerpt: Vec::new(),
+                    warnings: Vec::new(),
+                });
+        last_run.log_excerpt = excerpt;
+    }
 }
 
-#[derive(Default)]
-struct StatusStreamEnricher {
-    started: Option<Instant>,
-    totals_by_target: HashMap<String, u64>,
-    totals_down_by_target: HashMap<String, u64>,
-    smoothed_rate_by_target: HashMap<String, f64>,
-    smoothed_down_rate_by_target: HashMap<String, f64>,
+async fn status_stream(config_dir: &Path, data_dir: &Path, json: bool) -> Result<(), CliError> {
+    if !json {
+        return Err(CliError::new(
+            ErrorCode::CliInvalid,
+            "--json is required for status stream",
+        ));
+    }
+
+    // Throttle the emitted snapshot cadence to 2Hz. The daemon may generate status snapshots at a
+    // much higher cadence (e.g. 10Hz when running), which makes the UI appear to "flicker" and
+    // violates the desired refresh semantics for "last 1s" transfer rates.
+    status_stream_enriched(config_dir, data_dir, Duration::from_millis(500), |snap| {
+        let out = serde_json::to_string(snap)
+            .map_err(|e| CliError::new(ErrorCode::StatusInvalid, e.to_string()))?;
+        println!("{out}");
+        let _ = std::io::stdout().flush();
+        Ok(())
+    })
+    .await
+}
+
+/// Follows the daemon status (IPC, falling back to `status.json`) and hands each enriched
+/// snapshot to `emit`, at most once per `output_interval`.
+async fn status_stream_enriched(
+    config_dir: &Path,
+    data_dir: &Path,
+    output_interval: Duration,
+    mut emit: impl FnMut(&televy_backup_core::status::StatusSnapshot) -> Result<(), CliError>,
+) -> Result<(), CliError> {
+    #[cfg(unix)]
+    {
+        if let Ok(stream) = connect_status_ipc(data_dir).await {
+            return status_stream_ipc(stream, output_interval, &mut emit).await;
+        }
+    }
+
+    status_stream_file(config_dir, data_dir, output_interval, &mut emit).await
+}
+
+#[derive(Default)]
+struct StatusStreamEnricher {
+    started: Option<Instant>,
+    totals_by_target: HashMap<String, u64>,
+    totals_down_by_target: HashMap<String, u64>,
+    smoothed_rate_by_target: HashMap<String, f64>,
+    smoothed_down_rate_by_target: HashMap<String, f64>,
     prev_sample_at: Option<Instant>,
     prev_uploaded_by_target: HashMap<String, u64>,
     prev_downloaded_by_target: HashMap<String, u64>,
@@ -1023,40 +2667,38 @@ impl StatusStreamEnricher {
     }
 }
 
-async fn status_stream_ipc(stream: impl tokio::io::AsyncRead + Unpin) -> Result<(), CliError> {
+async fn status_stream_ipc(
+    stream: impl tokio::io::AsyncRead + Unpin,
+    output_interval: Duration,
+    emit: &mut impl FnMut(&televy_backup_core::status::StatusSnapshot) -> Result<(), CliError>,
+) -> Result<(), CliError> {
     let mut enricher = StatusStreamEnricher::default();
     let mut lines = tokio::io::BufReader::new(stream).lines();
 
     let first = tokio::time::timeout(Duration::from_millis(500), lines.next_line())
         .await
-        .map_err(|_| CliError::retryable("status.unavailable", "ipc status stream timed out"))?
-        .map_err(|e| CliError::retryable("status.unavailable", e.to_string()))?
-        .ok_or_else(|| CliError::retryable("status.unavailable", "ipc status stream ended"))?;
-
-    // Throttle the emitted snapshot cadence to 2Hz. The daemon may generate status snapshots at a
-    // much higher cadence (e.g. 10Hz when running), which makes the UI appear to "flicker" and
-    // violates the desired refresh semantics for "last 1s" transfer rates.
-    const OUTPUT_INTERVAL: Duration = Duration::from_millis(500);
+        .map_err(|_| {
+            CliError::retryable(ErrorCode::StatusUnavailable, "ipc status stream timed out")
+        })?
+        .map_err(|e| CliError::retryable(ErrorCode::StatusUnavailable, e.to_string()))?
+        .ok_or_else(|| {
+            CliError::retryable(ErrorCode::StatusUnavailable, "ipc status stream ended")
+        })?;
 
-    let mut latest: televy_backup_core::status::StatusSnapshot =
-        serde_json::from_str(&first).map_err(|e| CliError::new("status.invalid", e.to_string()))?;
+    let mut latest: televy_backup_core::status::StatusSnapshot = serde_json::from_str(&first)
+        .map_err(|e| CliError::new(ErrorCode::StatusInvalid, e.to_string()))?;
     let mut last_emitted_generated_at = latest.generated_at;
 
-    // Emit the first snapshot immediately, then align periodic output to 2Hz.
+    // Emit the first snapshot immediately, then align periodic output to the interval.
     {
         let mut snap = latest.clone();
         enricher.enrich(&mut snap);
-        println!(
-            "{}",
-            serde_json::to_string(&snap)
-                .map_err(|e| { CliError::new("status.invalid", e.to_string()) })?
-        );
-        let _ = std::io::stdout().flush();
+        emit(&snap)?;
     }
 
     let mut interval = tokio::time::interval_at(
-        tokio::time::Instant::now() + OUTPUT_INTERVAL,
-        OUTPUT_INTERVAL,
+        tokio::time::Instant::now() + output_interval,
+        output_interval,
     );
     interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
 
@@ -1064,14 +2706,14 @@ async fn status_stream_ipc(stream: impl tokio::io::AsyncRead + Unpin) -> Result<
         tokio::select! {
             maybe_line = lines.next_line() => {
                 let line = maybe_line
-                    .map_err(|e| CliError::retryable("status.unavailable", e.to_string()))?
-                    .ok_or_else(|| CliError::retryable("status.unavailable", "ipc status stream ended"))?;
+                    .map_err(|e| CliError::retryable(ErrorCode::StatusUnavailable, e.to_string()))?
+                    .ok_or_else(|| CliError::retryable(ErrorCode::StatusUnavailable, "ipc status stream ended"))?;
                 if line.trim().is_empty() {
                     continue;
                 }
 
                 latest = serde_json::from_str(&line)
-                    .map_err(|e| CliError::new("status.invalid", e.to_string()))?;
+                    .map_err(|e| CliError::new(ErrorCode::StatusInvalid, e.to_string()))?;
             }
             _ = interval.tick() => {
                 // Avoid spamming identical snapshots (idle cadence is 1Hz on the daemon side).
@@ -1081,24 +2723,25 @@ async fn status_stream_ipc(stream: impl tokio::io::AsyncRead + Unpin) -> Result<
 
                 let mut snap = latest.clone();
                 enricher.enrich(&mut snap);
-
-                let out = serde_json::to_string(&snap)
-                    .map_err(|e| CliError::new("status.invalid", e.to_string()))?;
-                println!("{out}");
-                let _ = std::io::stdout().flush();
+                emit(&snap)?;
                 last_emitted_generated_at = snap.generated_at;
             }
         }
     }
 }
 
-async fn status_stream_file(_config_dir: &Path, data_dir: &Path) -> Result<(), CliError> {
+async fn status_stream_file(
+    _config_dir: &Path,
+    data_dir: &Path,
+    output_interval: Duration,
+    emit: &mut impl FnMut(&televy_backup_core::status::StatusSnapshot) -> Result<(), CliError>,
+) -> Result<(), CliError> {
     let path = televy_backup_core::status::status_json_path(data_dir);
     let mut enricher = StatusStreamEnricher::default();
 
     // If status.json is missing or invalid, treat it as unavailable (no synthetic snapshots).
     let mut first = televy_backup_core::status::read_status_snapshot_json(&path).map_err(|e| {
-        CliError::retryable("status.unavailable", "status source unavailable").with_details(
+        CliError::retryable(ErrorCode::StatusUnavailable, "status source unavailable").with_details(
             serde_json::json!({
                 "statusJsonPath": path.display().to_string(),
                 "error": e.to_string(),
@@ -1113,31 +2756,332 @@ async fn status_stream_file(_config_dir: &Path, data_dir: &Path) -> Result<(), C
         let any_running = first.targets.iter().any(|t| t.state == "running");
 
         enricher.enrich(&mut first);
-
-        let line = serde_json::to_string(&first)
-            .map_err(|e| CliError::new("status.invalid", e.to_string()))?;
-        println!("{line}");
-        let _ = std::io::stdout().flush();
+        emit(&first)?;
 
         // Throttle file-based streaming to 2Hz when running so the UI cadence matches the IPC path.
         let sleep = if is_daemon && any_running && stale_age_ms <= 2_000 {
             std::time::Duration::from_millis(500)
         } else {
             std::time::Duration::from_secs(1)
-        };
+        }
+        .max(output_interval);
         tokio::time::sleep(sleep).await;
 
         first = televy_backup_core::status::read_status_snapshot_json(&path).map_err(|e| {
-            CliError::retryable("status.unavailable", "status source unavailable").with_details(
-                serde_json::json!({
+            CliError::retryable(ErrorCode::StatusUnavailable, "status source unavailable")
+                .with_details(serde_json::json!({
                     "statusJsonPath": path.display().to_string(),
                     "error": e.to_string(),
-                }),
-            )
+                }))
         })?;
     }
 }
 
+/// Redraw cadence for `status watch`.
+const STATUS_WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+async fn status_watch(config_dir: &Path, data_dir: &Path, json: bool) -> Result<(), CliError> {
+    if json {
+        return Err(CliError::new(
+            ErrorCode::CliInvalid,
+            "status watch renders plain text; use status stream --json instead",
+        ));
+    }
+
+    let mut view = StatusWatchView {
+        tty: std::io::stdout().is_terminal(),
+        drawn_lines: 0,
+    };
+    let _key_mode = StdinKeyMode::enable();
+    let mut quit = spawn_status_watch_quit_listener();
+
+    tokio::select! {
+        res = status_stream_enriched(config_dir, data_dir, STATUS_WATCH_INTERVAL, |snap| view.draw(snap)) => res,
+        _ = tokio::signal::ctrl_c() => Ok(()),
+        // A closed channel means stdin hit EOF; keep watching until Ctrl-C then.
+        Ok(()) = &mut quit => Ok(()),
+    }
+}
+
+/// Resolves once `q` is read from stdin.
+fn spawn_status_watch_quit_listener() -> tokio::sync::oneshot::Receiver<()> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    std::thread::spawn(move || {
+        for byte in std::io::stdin().lock().bytes() {
+            match byte {
+                Ok(b'q' | b'Q') => {
+                    let _ = tx.send(());
+                    return;
+                }
+                Ok(_) => {}
+                Err(_) => return,
+            }
+        }
+    });
+    rx
+}
+
+/// Puts a terminal stdin into non-canonical, no-echo mode so single key presses (`q`) are seen
+/// without Enter. Signals stay enabled so Ctrl-C still arrives as SIGINT. Restored on drop.
+#[cfg(unix)]
+struct StdinKeyMode {
+    saved: libc::termios,
+}
+
+#[cfg(unix)]
+impl StdinKeyMode {
+    fn enable() -> Option<Self> {
+        if !std::io::stdin().is_terminal() {
+            return None;
+        }
+        // SAFETY: `termios` is plain data and both calls only touch the struct we pass in.
+        unsafe {
+            let mut t: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut t) != 0 {
+                return None;
+            }
+            let saved = t;
+            t.c_lflag &= !(libc::ICANON | libc::ECHO);
+            t.c_cc[libc::VMIN] = 1;
+            t.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &t) != 0 {
+                return None;
+            }
+            Some(Self { saved })
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for StdinKeyMode {
+    fn drop(&mut self) {
+        // SAFETY: restores the attributes captured in `enable`.
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.saved);
+        }
+    }
+}
+
+/// Turns off terminal echo while a passphrase line is read. Restored on drop.
+#[cfg(unix)]
+struct StdinEchoOff {
+    saved: libc::termios,
+}
+
+#[cfg(unix)]
+impl StdinEchoOff {
+    fn enable() -> Option<Self> {
+        if !std::io::stdin().is_terminal() {
+            return None;
+        }
+        // SAFETY: `termios` is plain data and both calls only touch the struct we pass in.
+        unsafe {
+            let mut t: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut t) != 0 {
+                return None;
+            }
+            let saved = t;
+            t.c_lflag &= !libc::ECHO;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &t) != 0 {
+                return None;
+            }
+            Some(Self { saved })
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for StdinEchoOff {
+    fn drop(&mut self) {
+        // SAFETY: restores the attributes captured in `enable`.
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.saved);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+struct StdinEchoOff;
+
+#[cfg(not(unix))]
+impl StdinEchoOff {
+    fn enable() -> Option<Self> {
+        None
+    }
+}
+
+#[cfg(not(unix))]
+struct StdinKeyMode;
+
+#[cfg(not(unix))]
+impl StdinKeyMode {
+    fn enable() -> Option<Self> {
+        None
+    }
+}
+
+fn terminal_width() -> usize {
+    #[cfg(unix)]
+    {
+        // SAFETY: TIOCGWINSZ only writes into the `winsize` we pass in.
+        let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+        if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut ws) } == 0
+            && ws.ws_col > 0
+        {
+            return ws.ws_col as usize;
+        }
+    }
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|w| *w > 0)
+        .unwrap_or(80)
+}
+
+/// Plain-text renderer for `status watch`: redraws in place on a TTY, appends otherwise.
+struct StatusWatchView {
+    tty: bool,
+    drawn_lines: usize,
+}
+
+impl StatusWatchView {
+    fn draw(&mut self, snap: &televy_backup_core::status::StatusSnapshot) -> Result<(), CliError> {
+        let lines = status_watch_lines(snap, terminal_width(), self.tty);
+        let mut out = std::io::stdout().lock();
+        let res = (|| {
+            if self.tty {
+                if self.drawn_lines > 0 {
+                    // Back to the first line of the previous frame.
+                    write!(out, "\x1b[{}F", self.drawn_lines)?;
+                }
+                for line in &lines {
+                    writeln!(out, "\x1b[2K{line}")?;
+                }
+                // Clear leftovers when the target list shrank.
+                write!(out, "\x1b[J")?;
+            } else {
+                for line in &lines {
+                    writeln!(out, "{line}")?;
+                }
+            }
+            out.flush()
+        })();
+        // A closed stdout (e.g. `| head`) ends the watch like any other stream consumer.
+        res.map_err(|e| CliError::new(ErrorCode::CliIo, e.to_string()))?;
+        self.drawn_lines = lines.len();
+        Ok(())
+    }
+}
+
+/// One header line plus one line per target, each cut to `width` columns so in-place redraws
+/// never wrap.
+fn status_watch_lines(
+    snap: &televy_backup_core::status::StatusSnapshot,
+    width: usize,
+    interactive: bool,
+) -> Vec<String> {
+    let at = chrono::DateTime::from_timestamp_millis(snap.generated_at as i64)
+        .map(|t| {
+            t.with_timezone(&chrono::Local)
+                .format("%H:%M:%S")
+                .to_string()
+        })
+        .unwrap_or_else(|| "-".to_string());
+    let mut header = format!(
+        "{at}  source={}{}  targets={}  up={}",
+        snap.source.kind,
+        if snap.source.degraded {
+            " (last known)"
+        } else {
+            ""
+        },
+        snap.targets.len(),
+        format_rate(snap.global.up.bytes_per_second)
+    );
+    if interactive {
+        header.push_str("  (q to quit)");
+    }
+
+    let name_width = snap
+        .targets
+        .iter()
+        .map(|t| t.label.as_deref().unwrap_or(&t.target_id).chars().count())
+        .max()
+        .unwrap_or(0)
+        .min(24);
+
+    let mut lines = vec![truncate_end(&header, width)];
+    for t in &snap.targets {
+        let name = truncate_end(t.label.as_deref().unwrap_or(&t.target_id), name_width);
+        let (phase, files) = match t.progress.as_ref() {
+            Some(p) => (
+                p.phase.as_str(),
+                match (p.files_done, p.files_total) {
+                    (None, None) => "-".to_string(),
+                    (done, total) => format!(
+                        "{}/{}",
+                        done.unwrap_or(0),
+                        total
+                            .map(|v| v.to_string())
+                            .unwrap_or_else(|| "?".to_string())
+                    ),
+                },
+            ),
+            None => ("-", "-".to_string()),
+        };
+        let last = match t.last_run.as_ref() {
+            Some(r) => match (r.status.as_deref(), r.error_code.as_deref()) {
+                (Some(status), Some(code)) => format!("{status}({code})"),
+                (Some(status), None) => status.to_string(),
+                (None, _) => "-".to_string(),
+            },
+            None => "-".to_string(),
+        };
+        let fixed = format!(
+            "{name:<name_width$}  {:<7}  {phase:<8}  files={files}  up={}  last={last}  ",
+            t.state,
+            format_rate(t.up.bytes_per_second),
+        );
+        let room = width.saturating_sub(fixed.chars().count());
+        let line = format!("{fixed}{}", truncate_path_start(&t.source_path, room));
+        lines.push(truncate_end(line.trim_end(), width));
+    }
+    lines
+}
+
+fn format_rate(bytes_per_second: Option<u64>) -> String {
+    match bytes_per_second {
+        Some(bps) => format!("{}/s", format::human_bytes(bps)),
+        None => "-".to_string(),
+    }
+}
+
+fn truncate_end(s: &str, max: usize) -> String {
+    if s.chars().count() <= max {
+        return s.to_string();
+    }
+    if max == 0 {
+        return String::new();
+    }
+    let mut out = s.chars().take(max - 1).collect::<String>();
+    out.push('…');
+    out
+}
+
+/// Keeps the tail of a path (the most specific part) when it does not fit.
+fn truncate_path_start(path: &str, max: usize) -> String {
+    let len = path.chars().count();
+    if len <= max {
+        return path.to_string();
+    }
+    if max == 0 {
+        return String::new();
+    }
+    let mut out = String::from("…");
+    out.extend(path.chars().skip(len - (max - 1)));
+    out
+}
+
 #[cfg(unix)]
 async fn connect_status_ipc(data_dir: &Path) -> std::io::Result<UnixStream> {
     let socket_path = televy_backup_core::status::status_ipc_socket_path(data_dir);
@@ -1156,7 +3100,7 @@ async fn read_status_snapshot_from_ipc(
     _data_dir: &Path,
 ) -> Result<televy_backup_core::status::StatusSnapshot, CliError> {
     Err(CliError::retryable(
-        "status.unavailable",
+        ErrorCode::StatusUnavailable,
         "status IPC is only supported on unix",
     ))
 }
@@ -1166,7 +3110,7 @@ async fn read_status_snapshot_from_ipc(
     data_dir: &Path,
 ) -> Result<televy_backup_core::status::StatusSnapshot, CliError> {
     let stream = connect_status_ipc(data_dir).await.map_err(|e| {
-        CliError::retryable("status.unavailable", "status ipc unavailable").with_details(
+        CliError::retryable(ErrorCode::StatusUnavailable, "status ipc unavailable").with_details(
             serde_json::json!({
                 "socketPath": televy_backup_core::status::status_ipc_socket_path(data_dir).display().to_string(),
                 "error": e.to_string(),
@@ -1177,11 +3121,11 @@ async fn read_status_snapshot_from_ipc(
     let mut lines = tokio::io::BufReader::new(stream).lines();
     let line = tokio::time::timeout(Duration::from_millis(500), lines.next_line())
         .await
-        .map_err(|_| CliError::retryable("status.unavailable", "ipc status get timed out"))?
-        .map_err(|e| CliError::retryable("status.unavailable", e.to_string()))?
-        .ok_or_else(|| CliError::retryable("status.unavailable", "ipc status get ended"))?;
+        .map_err(|_| CliError::retryable(ErrorCode::StatusUnavailable, "ipc status get timed out"))?
+        .map_err(|e| CliError::retryable(ErrorCode::StatusUnavailable, e.to_string()))?
+        .ok_or_else(|| CliError::retryable(ErrorCode::StatusUnavailable, "ipc status get ended"))?;
 
-    serde_json::from_str(&line).map_err(|e| CliError::new("status.invalid", e.to_string()))
+    serde_json::from_str(&line).map_err(|e| CliError::new(ErrorCode::StatusInvalid, e.to_string()))
 }
 
 fn read_status_snapshot_from_file(
@@ -1191,7 +3135,7 @@ fn read_status_snapshot_from_file(
     let _ = config_dir; // reserved for future compatibility checks / synthetic snapshots
     let path = televy_backup_core::status::status_json_path(data_dir);
     televy_backup_core::status::read_status_snapshot_json(&path).map_err(|e| {
-        CliError::retryable("status.unavailable", "status source unavailable").with_details(
+        CliError::retryable(ErrorCode::StatusUnavailable, "status source unavailable").with_details(
             serde_json::json!({
                 "statusJsonPath": path.display().to_string(),
                 "error": e.to_string(),
@@ -1213,7 +3157,8 @@ async fn settings_get(
             // The macOS UI calls `settings get --with-secrets` unconditionally.
             // Keep settings readable even when control IPC isn't available.
             match daemon_control_secrets_presence(data_dir, None) {
-                Ok(secrets) => {
+                Ok(mut secrets) => {
+                    overlay_injected_secrets_presence(config_dir, &settings, &mut secrets);
                     println!(
                         "{}",
                         serde_json::json!({ "settings": settings, "secrets": secrets })
@@ -1235,14 +3180,15 @@ async fn settings_get(
         }
     } else {
         let text = toml::to_string(&settings)
-            .map_err(|e| CliError::new("config.invalid", e.to_string()))?;
+            .map_err(|e| CliError::new(ErrorCode::ConfigInvalid, e.to_string()))?;
         print!("{text}");
         if !text.ends_with('\n') {
             println!();
         }
         if with_secrets {
             match daemon_control_secrets_presence(data_dir, None) {
-                Ok(secrets) => {
+                Ok(mut secrets) => {
+                    overlay_injected_secrets_presence(config_dir, &settings, &mut secrets);
                     let master_present = secrets
                         .get("masterKeyPresent")
                         .and_then(|v| v.as_bool())
@@ -1273,6 +3219,17 @@ async fn settings_get(
                             "telegramMtprotoSessionPresent[{id}]={mtproto_session_present}",
                             id = ep.id
                         );
+                        if ep.mtproto.api_hash_key.is_some() {
+                            let api_hash_present = secrets
+                                .get("telegramMtprotoApiHashPresentByEndpoint")
+                                .and_then(|m| m.get(&ep.id))
+                                .and_then(|v| v.as_bool())
+                                .unwrap_or(false);
+                            println!(
+                                "telegramMtprotoApiHashPresent[{id}]={api_hash_present}",
+                                id = ep.id
+                            );
+                        }
                     }
                 }
                 Err(e) => {
@@ -1287,17 +3244,211 @@ async fn settings_get(
     Ok(())
 }
 
-async fn settings_set(config_dir: &Path, json: bool) -> Result<(), CliError> {
-    let mut input = String::new();
-    std::io::stdin()
-        .read_to_string(&mut input)
-        .map_err(|e| CliError::new("config.read_failed", e.to_string()))?;
+fn settings_schema(json: bool) {
+    if json {
+        println!("{}", settings_config::settings_schema_json());
+        return;
+    }
+    for f in settings_config::SETTINGS_FIELDS {
+        let default = settings_config::settings_field_default(f.path)
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| "-".to_string());
+        let ty = serde_json::to_value(f.ty)
+            .ok()
+            .and_then(|v| v.as_str().map(str::to_string))
+            .unwrap_or_default();
+        let required = if f.required { " required" } else { "" };
+        println!("{} ({ty}{required}) default={default}", f.path);
+        println!("    {}", f.description);
+        if let Some(c) = f.constraints {
+            println!("    constraints: {c}");
+        }
+    }
+}
+
+/// `settings get --effective`: the exclusions a backup of each target applies, in the order the
+/// scan applies them. Rules of `.televyignore` files below the source root are not listed.
+fn settings_get_effective(
+    config_dir: &Path,
+    target_id: Option<&str>,
+    json: bool,
+) -> Result<(), CliError> {
+    let settings = load_settings(config_dir)?;
+    let targets = match target_id {
+        Some(_) => vec![select_target(&settings, target_id, None)?],
+        None => settings.targets.iter().collect(),
+    };
+
+    let mut out = Vec::with_capacity(targets.len());
+    for t in targets {
+        let mut excludes = Vec::new();
+        if settings.scan.default_excludes {
+            for pattern in televy_backup_core::default_excludes::PATTERNS {
+                excludes.push(serde_json::json!({ "origin": "default", "pattern": pattern }));
+            }
+        }
+        let ignore_file = Path::new(&t.source_path).join(".televyignore");
+        match std::fs::read_to_string(&ignore_file) {
+            Ok(text) => {
+                let origin = ignore_file.display().to_string();
+                for rule in text
+                    .lines()
+                    .map(str::trim)
+                    .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                {
+                    excludes.push(serde_json::json!({ "origin": origin, "pattern": rule }));
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(CliError::new(
+                    ErrorCode::Io,
+                    format!("read {}: {e}", ignore_file.display()),
+                ));
+            }
+        }
+        out.push(serde_json::json!({
+            "targetId": t.id,
+            "sourcePath": t.source_path,
+            "defaultExcludes": settings.scan.default_excludes,
+            "excludes": excludes,
+            "filters": t.filters,
+        }));
+    }
+
+    if json {
+        println!("{}", serde_json::json!({ "targets": out }));
+    } else {
+        for t in &out {
+            println!(
+                "targetId={} sourcePath={} defaultExcludes={}",
+                t["targetId"].as_str().unwrap_or_default(),
+                t["sourcePath"].as_str().unwrap_or_default(),
+                t["defaultExcludes"]
+            );
+            for e in t["excludes"].as_array().into_iter().flatten() {
+                println!(
+                    "  exclude {} ({})",
+                    e["pattern"].as_str().unwrap_or_default(),
+                    e["origin"].as_str().unwrap_or_default()
+                );
+            }
+            for (i, f) in t["filters"].as_array().into_iter().flatten().enumerate() {
+                println!("  filter[{i}] {f}");
+            }
+        }
+    }
+    Ok(())
+}
+
+fn settings_defaults(json: bool) {
+    let text = settings_config::settings_defaults_toml();
+    if json {
+        println!("{}", serde_json::json!({ "toml": text }));
+    } else {
+        print!("{text}");
+    }
+}
+
+async fn settings_set(
+    config_dir: &Path,
+    input_file: Option<&Path>,
+    json: bool,
+) -> Result<(), CliError> {
+    let input = read_command_input(input_file)?;
     let settings: Settings = settings_config::parse_settings_v2(&input)
-        .map_err(|e| CliError::new("config.invalid", e.to_string()))?;
+        .map_err(|e| CliError::new(ErrorCode::ConfigInvalid, e.to_string()))?;
     settings_config::save_settings_v2(config_dir, &settings).map_err(map_core_err)?;
+    let warnings = settings_config::settings_warnings(&settings);
+
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({ "settings": settings, "warnings": warnings })
+        );
+    } else {
+        print_settings_warnings(&warnings);
+    }
+    Ok(())
+}
+
+fn settings_validate(
+    config_dir: &Path,
+    input_file: Option<&Path>,
+    json: bool,
+) -> Result<(), CliError> {
+    let settings = match input_file {
+        Some(path) => settings_config::parse_settings_v2(&read_command_input(Some(path))?)
+            .map_err(|e| CliError::new(ErrorCode::ConfigInvalid, e.to_string()))?,
+        None => settings_config::load_settings_v2(config_dir).map_err(map_core_err)?,
+    };
+    let warnings = settings_config::validate_settings_schema_v2(&settings).map_err(map_core_err)?;
+
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({ "ok": true, "warnings": warnings })
+        );
+    } else {
+        print_settings_warnings(&warnings);
+        println!("settings ok ({} warnings)", warnings.len());
+    }
+    Ok(())
+}
+
+fn print_settings_warnings(warnings: &[settings_config::SettingsWarning]) {
+    for w in warnings {
+        eprintln!("warning: {}", w.message);
+    }
+}
+
+fn settings_backup_json(backup: &settings_config::SettingsBackup) -> serde_json::Value {
+    serde_json::json!({
+        "id": backup.id(),
+        "timestamp": backup.timestamp,
+        "path": backup.path.display().to_string(),
+        "bytes": backup.bytes,
+    })
+}
+
+fn settings_history(config_dir: &Path, json: bool) -> Result<(), CliError> {
+    let backups = settings_config::list_settings_backups(config_dir).map_err(map_core_err)?;
+    if json {
+        let backups = backups.iter().map(settings_backup_json).collect::<Vec<_>>();
+        println!("{}", serde_json::json!({ "backups": backups }));
+        return Ok(());
+    }
+    if backups.is_empty() {
+        println!("no settings backups");
+    }
+    for b in &backups {
+        println!(
+            "{}  {}  {}",
+            b.id(),
+            format::timestamp_ms(b.timestamp.max(0) as u64),
+            format::bytes(b.bytes)
+        );
+    }
+    Ok(())
+}
 
+fn settings_rollback(
+    config_dir: &Path,
+    to: Option<(i64, u32)>,
+    json: bool,
+) -> Result<(), CliError> {
+    let restored = settings_config::rollback_settings_v2(config_dir, to).map_err(map_core_err)?;
     if json {
-        println!("{}", serde_json::json!({ "settings": settings }));
+        println!(
+            "{}",
+            serde_json::json!({ "restored": settings_backup_json(&restored) })
+        );
+    } else {
+        println!(
+            "restored settings generation {} from {}",
+            restored.id(),
+            format::timestamp_ms(restored.timestamp.max(0) as u64)
+        );
     }
     Ok(())
 }
@@ -1360,7 +3511,7 @@ fn load_config_bundle_passphrase() -> Result<String, CliError> {
     let passphrase = std::env::var(CONFIG_BUNDLE_PASSPHRASE_ENV).unwrap_or_default();
     if passphrase.trim().is_empty() {
         return Err(CliError::new(
-            "config_bundle.passphrase_required",
+            ErrorCode::ConfigBundlePassphraseRequired,
             format!("missing {CONFIG_BUNDLE_PASSPHRASE_ENV}"),
         ));
     }
@@ -1487,7 +3638,7 @@ struct SettingsImportBundleApplyConfirm {
     phrase: String,
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
 #[serde(rename_all = "snake_case", tag = "mode")]
 enum SettingsImportBundleApplyResolution {
     OverwriteLocal,
@@ -1500,11 +3651,34 @@ enum SettingsImportBundleApplyResolution {
 #[serde(rename_all = "camelCase")]
 struct SettingsImportBundleApplyResponse {
     ok: bool,
+    /// Not every endpoint was rebuilt or the settings/secrets were not all written; see
+    /// `reportPath` and `--resume`.
+    partial: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    report_path: Option<String>,
     local_index: SettingsImportBundleApplyLocalIndexJson,
+    endpoints: Vec<SettingsImportBundleApplyEndpointJson>,
     applied: SettingsImportBundleApplyAppliedJson,
     actions: SettingsImportBundleApplyActionsJson,
 }
 
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SettingsImportBundleApplyEndpointJson {
+    endpoint_id: String,
+    status: config_bundle::ApplyEndpointStatus,
+    /// Rebuilt by an earlier, interrupted run and skipped by this `--resume`.
+    resumed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rebuilt_from: Option<SettingsImportBundleApplyRebuiltFromJson>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rebuilt_db_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous_db_backup_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_code: Option<String>,
+}
+
 #[derive(Debug, serde::Serialize)]
 #[serde(rename_all = "camelCase")]
 struct SettingsImportBundleApplyLocalIndexJson {
@@ -1629,13 +3803,18 @@ async fn settings_export_bundle(
     required.push(settings.telegram.mtproto.api_hash_key.clone());
     for ep in &settings.telegram_endpoints {
         required.push(ep.bot_token_key.clone());
+        required.extend(ep.mtproto.api_hash_key.clone());
     }
     required.sort();
     required.dedup();
 
+    let provider = televy_backup_core::secrets::SecretsProvider::new(config_dir);
     for key in required {
-        if let Some(value) = store.get(&key) {
-            bundle_secrets.entries.insert(key, value.to_string());
+        if let Some((value, _)) = provider
+            .get(&key, Some(&store))
+            .map_err(map_secrets_store_err)?
+        {
+            bundle_secrets.entries.insert(key, value);
         } else {
             bundle_secrets.missing.push(key);
         }
@@ -1661,7 +3840,7 @@ async fn settings_export_bundle(
                 bundle_key: bundle_key.clone(),
                 format: config_bundle::CONFIG_BUNDLE_FORMAT_V2.to_string(),
             })
-            .map_err(|e| CliError::new("config.invalid", e.to_string()))?
+            .map_err(|e| CliError::new(ErrorCode::ConfigInvalid, e.to_string()))?
         );
     } else {
         println!("{bundle_key}");
@@ -1669,18 +3848,51 @@ async fn settings_export_bundle(
     Ok(())
 }
 
-fn read_stdin_one_line() -> Result<String, CliError> {
-    let mut input = String::new();
-    std::io::stdin()
-        .read_to_string(&mut input)
-        .map_err(|e| CliError::new("config.read_failed", e.to_string()))?;
+/// Largest document accepted on stdin or through `--input-file`.
+const MAX_INPUT_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Reads a command's whole input (`--input-file`, or stdin until EOF) before anything is written to
+/// stdout, so a caller still writing a large document never waits on our output.
+fn read_command_input(input_file: Option<&Path>) -> Result<String, CliError> {
+    let (reader, source): (Box<dyn Read>, String) = match input_file {
+        Some(path) => {
+            let file = std::fs::File::open(path).map_err(|e| {
+                CliError::new(ErrorCode::ConfigReadFailed, e.to_string())
+                    .with_details(serde_json::json!({ "path": path.display().to_string() }))
+            })?;
+            (Box::new(file), path.display().to_string())
+        }
+        None => (Box::new(std::io::stdin()), "stdin".to_string()),
+    };
+    let mut input = Vec::new();
+    reader
+        .take(MAX_INPUT_BYTES + 1)
+        .read_to_end(&mut input)
+        .map_err(|e| CliError::new(ErrorCode::ConfigReadFailed, e.to_string()))?;
+    if input.len() as u64 > MAX_INPUT_BYTES {
+        return Err(CliError::new(
+            ErrorCode::ConfigTooLarge,
+            format!("{source} is larger than {MAX_INPUT_BYTES} bytes"),
+        )
+        .with_details(serde_json::json!({ "maxBytes": MAX_INPUT_BYTES })));
+    }
+    String::from_utf8(input).map_err(|_| {
+        CliError::new(
+            ErrorCode::ConfigInvalid,
+            format!("{source} is not valid UTF-8"),
+        )
+    })
+}
+
+fn read_input_one_line(input_file: Option<&Path>) -> Result<String, CliError> {
+    let input = read_command_input(input_file)?;
     let line = input
         .lines()
         .find(|l| !l.trim().is_empty())
         .unwrap_or("")
         .trim();
     if line.is_empty() {
-        return Err(CliError::new("config.invalid", "stdin is empty"));
+        return Err(CliError::new(ErrorCode::ConfigInvalid, "input is empty"));
     }
     Ok(line.to_string())
 }
@@ -1694,10 +3906,10 @@ fn load_optional_master_key(
     };
     let bytes = base64::engine::general_purpose::STANDARD
         .decode(b64.as_bytes())
-        .map_err(|e| CliError::new("config.invalid", e.to_string()))?;
+        .map_err(|e| CliError::new(ErrorCode::ConfigInvalid, e.to_string()))?;
     let arr: [u8; 32] = bytes
         .try_into()
-        .map_err(|_| CliError::new("config.invalid", "invalid master key length"))?;
+        .map_err(|_| CliError::new(ErrorCode::ConfigInvalid, "invalid master key length"))?;
     Ok(Some(arr))
 }
 
@@ -1734,7 +3946,7 @@ async fn init_empty_index_db(path: &Path) -> Result<(), CliError> {
     // (SQLite can create the DB file, but not missing directories.)
     if let Some(parent) = path.parent() {
         std::fs::create_dir_all(parent)
-            .map_err(|e| CliError::new("config.write_failed", e.to_string()))?;
+            .map_err(|e| CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()))?;
     }
     let _ = televy_backup_core::index_db::open_index_db(path)
         .await
@@ -1745,16 +3957,17 @@ async fn init_empty_index_db(path: &Path) -> Result<(), CliError> {
 async fn settings_import_bundle_dry_run(
     config_dir: &Path,
     data_dir: &Path,
+    input_file: Option<&Path>,
     json: bool,
 ) -> Result<(), CliError> {
+    let bundle_key = read_input_one_line(input_file)?;
     if !json {
         return Err(CliError::new(
-            "config.invalid",
+            ErrorCode::ConfigInvalid,
             "import-bundle requires --json",
         ));
     }
 
-    let bundle_key = read_stdin_one_line()?;
     let passphrase = load_config_bundle_passphrase()?;
     let decoded = config_bundle::decode_config_bundle_key_v2(&bundle_key, &passphrase)
         .map_err(map_core_err)?;
@@ -1812,12 +4025,6 @@ async fn settings_import_bundle_dry_run(
         missing_keys,
     };
 
-    let api_id = bundle_settings.telegram.mtproto.api_id;
-    let api_hash = bundle_secrets
-        .entries
-        .get(&bundle_settings.telegram.mtproto.api_hash_key)
-        .cloned();
-
     let mut endpoint_catalogs: HashMap<String, Option<bootstrap::BootstrapCatalogV1>> =
         HashMap::new();
     let mut endpoint_bootstrap: HashMap<String, SettingsImportBundleDryRunBootstrapJson> =
@@ -1826,8 +4033,13 @@ async fn settings_import_bundle_dry_run(
     for ep in &bundle_settings.telegram_endpoints {
         let bot_token = bundle_secrets.entries.get(&ep.bot_token_key).cloned();
         let provider = settings_config::endpoint_provider(&ep.id);
+        let api_id = ep.mtproto_api_id(&bundle_settings.telegram.mtproto);
+        let api_hash = bundle_secrets
+            .entries
+            .get(ep.mtproto_api_hash_key(&bundle_settings.telegram.mtproto))
+            .cloned();
 
-        let Some(api_hash) = api_hash.clone() else {
+        let Some(api_hash) = api_hash else {
             endpoint_catalogs.insert(ep.id.clone(), None);
             endpoint_bootstrap.insert(
                 ep.id.clone(),
@@ -1865,7 +4077,7 @@ async fn settings_import_bundle_dry_run(
 
         let cache_dir = data_dir.join("cache").join("mtproto");
         std::fs::create_dir_all(&cache_dir)
-            .map_err(|e| CliError::new("config.write_failed", e.to_string()))?;
+            .map_err(|e| CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()))?;
 
         let storage = TelegramMtProtoStorage::connect(TelegramMtProtoStorageConfig {
             provider: provider.clone(),
@@ -1873,11 +4085,14 @@ async fn settings_import_bundle_dry_run(
             api_hash: api_hash.clone(),
             bot_token: bot_token.clone(),
             chat_id: ep.chat_id.clone(),
+            migrated_from_chat_ids: ep.migrated_from_chat_ids.clone(),
             session: None,
             cache_dir,
             min_delay_ms: Some(ep.rate_limit.min_delay_ms as u64),
             max_concurrent_uploads: Some(ep.rate_limit.max_concurrent_uploads as usize),
             helper_path: None,
+            bootstrap_pin_mode: ep.bootstrap.pin_mode,
+            object_prefix: endpoint_object_prefix(data_dir, ep),
         })
         .await
         .map_err(|e| map_mtproto_validate_err(e, &bot_token, &api_hash))?;
@@ -1894,11 +4109,16 @@ async fn settings_import_bundle_dry_run(
                 Some(cat)
             }
             Ok(None) => {
+                let details = if ep.bootstrap.pin_mode == bootstrap::BootstrapPinMode::Disabled {
+                    serde_json::json!({ "reason": "pin_mode_disabled" })
+                } else {
+                    serde_json::json!({})
+                };
                 endpoint_bootstrap.insert(
                     ep.id.clone(),
                     SettingsImportBundleDryRunBootstrapJson {
                         state: ConfigBundleBootstrapState::Missing,
-                        details: serde_json::json!({}),
+                        details,
                     },
                 );
                 None
@@ -2046,7 +4266,8 @@ async fn settings_import_bundle_dry_run(
 
     println!(
         "{}",
-        serde_json::to_string(&out).map_err(|e| CliError::new("config.invalid", e.to_string()))?
+        serde_json::to_string(&out)
+            .map_err(|e| CliError::new(ErrorCode::ConfigInvalid, e.to_string()))?
     );
     Ok(())
 }
@@ -2054,30 +4275,37 @@ async fn settings_import_bundle_dry_run(
 async fn settings_import_bundle_compare_folder(
     _config_dir: &Path,
     data_dir: &Path,
+    input_file: Option<&Path>,
     json: bool,
 ) -> Result<(), CliError> {
+    let input = read_command_input(input_file)?;
     if !json {
         return Err(CliError::new(
-            "config.invalid",
+            ErrorCode::ConfigInvalid,
             "import-bundle --compare-folder requires --json",
         ));
     }
 
-    let mut input = String::new();
-    std::io::stdin()
-        .read_to_string(&mut input)
-        .map_err(|e| CliError::new("config.read_failed", e.to_string()))?;
-    let req: SettingsImportBundleCompareFolderRequest =
-        serde_json::from_str(&input).map_err(|e| CliError::new("config.invalid", e.to_string()))?;
+    let req: SettingsImportBundleCompareFolderRequest = serde_json::from_str(&input)
+        .map_err(|e| CliError::new(ErrorCode::ConfigInvalid, e.to_string()))?;
 
     if req.bundle_key.trim().is_empty() {
-        return Err(CliError::new("config.invalid", "bundleKey is required"));
+        return Err(CliError::new(
+            ErrorCode::ConfigInvalid,
+            "bundleKey is required",
+        ));
     }
     if req.target_id.trim().is_empty() {
-        return Err(CliError::new("config.invalid", "targetId is required"));
+        return Err(CliError::new(
+            ErrorCode::ConfigInvalid,
+            "targetId is required",
+        ));
     }
     if req.source_path.trim().is_empty() {
-        return Err(CliError::new("config.invalid", "sourcePath is required"));
+        return Err(CliError::new(
+            ErrorCode::ConfigInvalid,
+            "sourcePath is required",
+        ));
     }
 
     let passphrase = load_config_bundle_passphrase()?;
@@ -2093,7 +4321,7 @@ async fn settings_import_bundle_compare_folder(
         .find(|t| t.id == req.target_id)
     else {
         return Err(CliError::new(
-            "config.invalid",
+            ErrorCode::ConfigInvalid,
             format!("unknown targetId: {}", req.target_id),
         ));
     };
@@ -2104,7 +4332,7 @@ async fn settings_import_bundle_compare_folder(
         .find(|e| e.id == target.endpoint_id)
     else {
         return Err(CliError::new(
-            "config.invalid",
+            ErrorCode::ConfigInvalid,
             format!(
                 "missing endpoint in bundle settings: {}",
                 target.endpoint_id
@@ -2112,33 +4340,33 @@ async fn settings_import_bundle_compare_folder(
         ));
     };
 
-    let api_id = bundle_settings.telegram.mtproto.api_id;
+    let api_id = endpoint.mtproto_api_id(&bundle_settings.telegram.mtproto);
     let Some(api_hash) = bundle_secrets
         .entries
-        .get(&bundle_settings.telegram.mtproto.api_hash_key)
+        .get(endpoint.mtproto_api_hash_key(&bundle_settings.telegram.mtproto))
         .cloned()
     else {
         return Err(CliError::new(
-            "config_bundle.conflict",
+            ErrorCode::ConfigBundleConflict,
             "missing telegram.mtproto api_hash; cannot compare remote latest",
         ));
     };
     let Some(bot_token) = bundle_secrets.entries.get(&endpoint.bot_token_key).cloned() else {
         return Err(CliError::new(
-            "config_bundle.conflict",
+            ErrorCode::ConfigBundleConflict,
             "missing endpoint bot token; cannot compare remote latest",
         ));
     };
     if api_id <= 0 {
         return Err(CliError::new(
-            "config_bundle.conflict",
+            ErrorCode::ConfigBundleConflict,
             "invalid telegram.mtproto api_id; cannot compare remote latest",
         ));
     }
 
     let cache_dir = data_dir.join("cache").join("mtproto");
     std::fs::create_dir_all(&cache_dir)
-        .map_err(|e| CliError::new("config.write_failed", e.to_string()))?;
+        .map_err(|e| CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()))?;
 
     let provider = settings_config::endpoint_provider(&endpoint.id);
     let storage = TelegramMtProtoStorage::connect(TelegramMtProtoStorageConfig {
@@ -2147,11 +4375,14 @@ async fn settings_import_bundle_compare_folder(
         api_hash: api_hash.clone(),
         bot_token: bot_token.clone(),
         chat_id: endpoint.chat_id.clone(),
+        migrated_from_chat_ids: endpoint.migrated_from_chat_ids.clone(),
         session: None,
         cache_dir,
         min_delay_ms: Some(endpoint.rate_limit.min_delay_ms as u64),
         max_concurrent_uploads: Some(endpoint.rate_limit.max_concurrent_uploads as usize),
         helper_path: None,
+        bootstrap_pin_mode: endpoint.bootstrap.pin_mode,
+        object_prefix: endpoint_object_prefix(data_dir, endpoint),
     })
     .await
     .map_err(|e| map_mtproto_validate_err(e, &bot_token, &api_hash))?;
@@ -2171,7 +4402,7 @@ async fn settings_import_bundle_compare_folder(
             println!(
                 "{}",
                 serde_json::to_string(&resp)
-                    .map_err(|e| CliError::new("config.invalid", e.to_string()))?
+                    .map_err(|e| CliError::new(ErrorCode::ConfigInvalid, e.to_string()))?
             );
             return Ok(());
         }
@@ -2182,7 +4413,7 @@ async fn settings_import_bundle_compare_folder(
         .targets
         .iter()
         .find(|x| x.target_id == target.id)
-        .and_then(|x| x.latest.clone());
+        .and_then(|x| Some((x.target_id.clone(), x.latest.clone()?)));
     if latest.is_none() {
         let matches = cat
             .targets
@@ -2190,11 +4421,14 @@ async fn settings_import_bundle_compare_folder(
             .filter(|x| x.source_path == target.source_path)
             .collect::<Vec<_>>();
         if matches.len() == 1 {
-            latest = matches[0].latest.clone();
+            latest = matches[0]
+                .latest
+                .clone()
+                .map(|l| (matches[0].target_id.clone(), l));
         }
     }
 
-    let Some(latest) = latest else {
+    let Some((latest_target_id, latest)) = latest else {
         let resp = SettingsImportBundleCompareFolderResponse {
             ok: true,
             state: ConfigBundleFolderCompareState::RemoteMissing,
@@ -2207,7 +4441,7 @@ async fn settings_import_bundle_compare_folder(
         println!(
             "{}",
             serde_json::to_string(&resp)
-                .map_err(|e| CliError::new("config.invalid", e.to_string()))?
+                .map_err(|e| CliError::new(ErrorCode::ConfigInvalid, e.to_string()))?
         );
         return Ok(());
     };
@@ -2215,7 +4449,7 @@ async fn settings_import_bundle_compare_folder(
     // Download remote index DB and compare the local folder contents against the snapshot index.
     let compare_dir = data_dir.join("tmp").join("import_bundle_compare");
     std::fs::create_dir_all(&compare_dir)
-        .map_err(|e| CliError::new("config.write_failed", e.to_string()))?;
+        .map_err(|e| CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()))?;
 
     fn sanitize_for_filename(s: &str) -> String {
         s.chars()
@@ -2237,11 +4471,15 @@ async fn settings_import_bundle_compare_folder(
         "remote-index-{safe_snapshot}-{safe_manifest}.sqlite"
     ));
 
+    let data_key = latest
+        .data_key(&bundle_master_key, &latest_target_id)
+        .map_err(map_core_err)?;
     televy_backup_core::remote_index_db::download_and_write_index_db_atomic(
         &storage,
         &latest.snapshot_id,
         &latest.manifest_object_id,
-        &bundle_master_key,
+        latest.manifest_sha256.as_deref(),
+        data_key.key(),
         &db_path,
         None,
         Some(&provider),
@@ -2290,7 +4528,8 @@ async fn settings_import_bundle_compare_folder(
 
     println!(
         "{}",
-        serde_json::to_string(&resp).map_err(|e| CliError::new("config.invalid", e.to_string()))?
+        serde_json::to_string(&resp)
+            .map_err(|e| CliError::new(ErrorCode::ConfigInvalid, e.to_string()))?
     );
     Ok(())
 }
@@ -2298,35 +4537,174 @@ async fn settings_import_bundle_compare_folder(
 async fn settings_import_bundle_apply(
     config_dir: &Path,
     data_dir: &Path,
+    input_file: Option<&Path>,
     json: bool,
+    events: bool,
+    resume: bool,
 ) -> Result<(), CliError> {
+    let input = read_command_input(input_file)?;
     if !json {
         return Err(CliError::new(
-            "config.invalid",
+            ErrorCode::ConfigInvalid,
             "import-bundle --apply requires --json",
         ));
     }
 
-    let mut input = String::new();
-    std::io::stdin()
-        .read_to_string(&mut input)
-        .map_err(|e| CliError::new("config.read_failed", e.to_string()))?;
-    let req: SettingsImportBundleApplyRequest =
-        serde_json::from_str(&input).map_err(|e| CliError::new("config.invalid", e.to_string()))?;
+    let task_id = format!("tsk_{}", uuid::Uuid::new_v4());
+    let cancel = cancel_on_stop_signal();
+    emit_task_state_running(events, &task_id, "import_bundle", None, None);
+    let run = ImportApplyRun {
+        events,
+        task_id: &task_id,
+        cancel: &cancel,
+    };
+    let resp = match import_bundle_apply(config_dir, data_dir, &input, resume, run).await {
+        Ok(resp) => resp,
+        Err(e) => {
+            emit_task_state_error(events, &task_id, "import_bundle", None, None, &e);
+            return Err(e);
+        }
+    };
+
+    if events {
+        emit_event_stdout(serde_json::json!({
+            "type": "task.state",
+            "taskId": task_id,
+            "kind": "import_bundle",
+            "state": "succeeded",
+            "result": resp,
+        }));
+        return Ok(());
+    }
+
+    println!(
+        "{}",
+        serde_json::to_string(&resp)
+            .map_err(|e| CliError::new(ErrorCode::ConfigInvalid, e.to_string()))?
+    );
+
+    Ok(())
+}
+
+/// Event and cancellation context of one `settings import-bundle --apply` run.
+#[derive(Clone, Copy)]
+struct ImportApplyRun<'a> {
+    events: bool,
+    task_id: &'a str,
+    cancel: &'a CancellationToken,
+}
+
+impl ImportApplyRun<'_> {
+    fn phase(&self, phase: Phase, endpoint_id: Option<&str>) {
+        if !self.events {
+            return;
+        }
+        let mut line = serde_json::json!({
+            "type": "task.progress",
+            "taskId": self.task_id,
+            "phase": phase,
+        });
+        if let Some(endpoint_id) = endpoint_id {
+            line["endpointId"] = endpoint_id.into();
+        }
+        emit_event_stdout(line);
+    }
+
+    fn check_cancelled(&self) -> Result<(), CliError> {
+        if self.cancel.is_cancelled() {
+            return Err(CliError::new(
+                ErrorCode::TaskCancelled,
+                "import-bundle apply cancelled",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Reports an import's index download as `index_rebuild` progress of one endpoint.
+struct ImportIndexRebuildProgressSink<'a> {
+    task_id: &'a str,
+    endpoint_id: &'a str,
+    throttle: Mutex<ProgressThrottle>,
+}
+
+impl ProgressSink for ImportIndexRebuildProgressSink<'_> {
+    fn on_progress(&self, p: televy_backup_core::TaskProgress) {
+        let should_emit = self
+            .throttle
+            .lock()
+            .expect("progress throttle mutex poisoned")
+            .should_emit(&p.phase);
+        if !should_emit {
+            return;
+        }
+        emit_event_stdout(serde_json::json!({
+            "type": "task.progress",
+            "taskId": self.task_id,
+            "phase": Phase::IndexRebuild,
+            "endpointId": self.endpoint_id,
+            "bytesDownloaded": p.bytes_downloaded,
+            "bytesWritten": p.bytes_written,
+        }));
+    }
+}
+
+async fn import_bundle_apply(
+    config_dir: &Path,
+    data_dir: &Path,
+    input: &str,
+    resume: bool,
+    run: ImportApplyRun<'_>,
+) -> Result<SettingsImportBundleApplyResponse, CliError> {
+    run.phase(Phase::Decode, None);
+    let req: SettingsImportBundleApplyRequest = serde_json::from_str(input)
+        .map_err(|e| CliError::new(ErrorCode::ConfigInvalid, e.to_string()))?;
 
     if req.selected_target_ids.is_empty() {
         return Err(CliError::new(
-            "config.invalid",
+            ErrorCode::ConfigInvalid,
             "selectedTargetIds must not be empty",
         ));
     }
     if req.confirm.phrase != "IMPORT" {
         return Err(CliError::new(
-            "config_bundle.confirm_required",
+            ErrorCode::ConfigBundleConfirmRequired,
             "apply requires confirm.phrase=\"IMPORT\"",
         ));
     }
 
+    let resolutions_json = serde_json::to_string(
+        &req.resolutions
+            .iter()
+            .collect::<std::collections::BTreeMap<_, _>>(),
+    )
+    .map_err(|e| CliError::new(ErrorCode::ConfigInvalid, e.to_string()))?;
+    let request_sha256 = config_bundle::apply_request_sha256(
+        &req.bundle_key,
+        &req.selected_target_ids,
+        &resolutions_json,
+    );
+    let resumed_report = if resume {
+        let report_path = config_bundle::apply_report_path(data_dir);
+        let Some(report) = config_bundle::load_apply_report(data_dir).map_err(map_core_err)? else {
+            return Err(CliError::new(
+                ErrorCode::ConfigInvalid,
+                "no interrupted import-bundle apply to resume",
+            )
+            .with_details(serde_json::json!({ "reportPath": report_path.display().to_string() })));
+        };
+        if report.request_sha256 != request_sha256 {
+            return Err(CliError::new(
+                ErrorCode::ConfigInvalid,
+                "the interrupted import-bundle apply was started with a different request; send the same request to resume it",
+            )
+            .with_details(serde_json::json!({ "reportPath": report_path.display().to_string() })));
+        }
+        Some(report)
+    } else {
+        None
+    };
+
     let passphrase = load_config_bundle_passphrase()?;
     let decoded = config_bundle::decode_config_bundle_key_v2(&req.bundle_key, &passphrase)
         .map_err(map_core_err)?;
@@ -2344,7 +4722,7 @@ async fn settings_import_bundle_apply(
     };
     if matches!(local_master_key_state, LocalMasterKeyState::Mismatch) && local_has_targets {
         return Err(CliError::new(
-            "config_bundle.rotation_required",
+            ErrorCode::ConfigBundleRotationRequired,
             "local master key mismatch and local targets exist; start master key rotation flow",
         ));
     }
@@ -2359,7 +4737,7 @@ async fn settings_import_bundle_apply(
             SettingsImportBundleApplyResolution::OverwriteRemote
         ) {
             return Err(CliError::new(
-                "config_bundle.conflict",
+                ErrorCode::ConfigBundleConflict,
                 format!(
                     "target {} overwrite_remote is not supported during import; run a backup to update remote latest",
                     target_id
@@ -2379,18 +4757,12 @@ async fn settings_import_bundle_apply(
         .collect::<Vec<_>>();
     if selected_targets.len() != selected_ids.len() {
         return Err(CliError::new(
-            "config.invalid",
+            ErrorCode::ConfigInvalid,
             "selectedTargetIds contains unknown ids",
         ));
     }
 
     // Preflight only on the selected targets.
-    let api_id = bundle_settings.telegram.mtproto.api_id;
-    let api_hash = bundle_secrets
-        .entries
-        .get(&bundle_settings.telegram.mtproto.api_hash_key)
-        .cloned();
-
     let mut endpoint_storage: HashMap<String, TelegramMtProtoStorage> = HashMap::new();
     let mut endpoint_catalogs: HashMap<String, Option<bootstrap::BootstrapCatalogV1>> =
         HashMap::new();
@@ -2402,6 +4774,8 @@ async fn settings_import_bundle_apply(
     }
 
     for ep_id in endpoints_needed {
+        run.check_cancelled()?;
+        run.phase(Phase::EndpointConnect, Some(&ep_id));
         let Some(ep) = bundle_settings
             .telegram_endpoints
             .iter()
@@ -2409,12 +4783,17 @@ async fn settings_import_bundle_apply(
             .cloned()
         else {
             return Err(CliError::new(
-                "config.invalid",
+                ErrorCode::ConfigInvalid,
                 format!("missing endpoint in bundle settings: {ep_id}"),
             ));
         };
 
-        let Some(api_hash) = api_hash.clone() else {
+        let api_id = ep.mtproto_api_id(&bundle_settings.telegram.mtproto);
+        let Some(api_hash) = bundle_secrets
+            .entries
+            .get(ep.mtproto_api_hash_key(&bundle_settings.telegram.mtproto))
+            .cloned()
+        else {
             endpoint_catalogs.insert(ep.id.clone(), None);
             endpoint_bootstrap_state.insert(ep.id.clone(), ConfigBundleBootstrapState::Missing);
             continue;
@@ -2432,7 +4811,7 @@ async fn settings_import_bundle_apply(
 
         let cache_dir = data_dir.join("cache").join("mtproto");
         std::fs::create_dir_all(&cache_dir)
-            .map_err(|e| CliError::new("config.write_failed", e.to_string()))?;
+            .map_err(|e| CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()))?;
 
         let provider = settings_config::endpoint_provider(&ep.id);
         let storage = TelegramMtProtoStorage::connect(TelegramMtProtoStorageConfig {
@@ -2441,11 +4820,14 @@ async fn settings_import_bundle_apply(
             api_hash: api_hash.clone(),
             bot_token: bot_token.clone(),
             chat_id: ep.chat_id.clone(),
+            migrated_from_chat_ids: ep.migrated_from_chat_ids.clone(),
             session: None,
             cache_dir,
             min_delay_ms: Some(ep.rate_limit.min_delay_ms as u64),
             max_concurrent_uploads: Some(ep.rate_limit.max_concurrent_uploads as usize),
             helper_path: None,
+            bootstrap_pin_mode: ep.bootstrap.pin_mode,
+            object_prefix: endpoint_object_prefix(data_dir, &ep),
         })
         .await
         .map_err(|e| map_mtproto_validate_err(e, &bot_token, &api_hash))?;
@@ -2470,6 +4852,7 @@ async fn settings_import_bundle_apply(
         endpoint_catalogs.insert(ep.id.clone(), cat);
     }
 
+    run.phase(Phase::Preflight, None);
     // Detect conflicts (missing_path / bootstrap_invalid) and enforce
     // that apply provides explicit resolutions for any target needing resolution.
     for t in &selected_targets {
@@ -2493,7 +4876,7 @@ async fn settings_import_bundle_apply(
 
         let res = req.resolutions.get(&t.id).ok_or_else(|| {
             CliError::new(
-                "config_bundle.conflict",
+                ErrorCode::ConfigBundleConflict,
                 format!(
                     "missing resolution for target {} ({})",
                     t.id,
@@ -2508,7 +4891,7 @@ async fn settings_import_bundle_apply(
                 | SettingsImportBundleApplyResolution::Skip => {}
                 _ => {
                     return Err(CliError::new(
-                        "config_bundle.conflict",
+                        ErrorCode::ConfigBundleConflict,
                         format!("target {} missing_path; must choose rebind or skip", t.id),
                     ));
                 }
@@ -2519,14 +4902,12 @@ async fn settings_import_bundle_apply(
             && !matches!(res, SettingsImportBundleApplyResolution::Skip)
         {
             return Err(CliError::new(
-                "config_bundle.conflict",
+                ErrorCode::ConfigBundleConflict,
                 format!("target {} bootstrap invalid; must skip", t.id),
             ));
         }
     }
 
-    let updated_pins = Vec::new();
-
     // Filter targets by resolutions (skip/rebind) and validate constraints.
     selected_targets = selected_targets
         .into_iter()
@@ -2545,7 +4926,7 @@ async fn settings_import_bundle_apply(
 
     if selected_targets.is_empty() {
         return Err(CliError::new(
-            "config.invalid",
+            ErrorCode::ConfigInvalid,
             "all selected targets were skipped",
         ));
     }
@@ -2553,7 +4934,7 @@ async fn settings_import_bundle_apply(
     for t in &selected_targets {
         if !Path::new(&t.source_path).exists() {
             return Err(CliError::new(
-                "config_bundle.conflict",
+                ErrorCode::ConfigBundleConflict,
                 format!(
                     "target {} source_path does not exist after resolution; choose rebind to an existing path or skip",
                     t.id
@@ -2567,243 +4948,193 @@ async fn settings_import_bundle_apply(
             .unwrap_or(ConfigBundleBootstrapState::Missing);
         if bootstrap_state == ConfigBundleBootstrapState::Invalid {
             return Err(CliError::new(
-                "config_bundle.conflict",
+                ErrorCode::ConfigBundleConflict,
                 format!("target {} bootstrap invalid; must skip", t.id),
             ));
         }
     }
 
     // Index rebuild: per endpoint, backup existing DB then rebuild from remote latest (or empty).
-    let mut rebuilt_db_path = String::new();
-    let mut previous_backup_path: Option<String> = None;
-    let mut rebuilt_from = SettingsImportBundleApplyRebuiltFromJson {
-        mode: "empty".to_string(),
-        snapshot_id: None,
-        manifest_object_id: None,
-    };
-    let mut local_index_synced = Vec::new();
-
+    // Each rebuilt endpoint is checkpointed in the partial-apply report, so `--resume` after an
+    // interruption only rebuilds the rest.
     let mut endpoints_to_rebuild = std::collections::BTreeSet::<String>::new();
     for t in &selected_targets {
         endpoints_to_rebuild.insert(t.endpoint_id.clone());
     }
 
-    for ep_id in endpoints_to_rebuild.iter() {
-        let db_path = endpoint_index_db_path(data_dir, ep_id);
-        let legacy_global = legacy_global_index_db_path(data_dir);
-        let _ = legacy_global; // must not read/write legacy global db here (migration compat)
-
-        // Build a replacement DB first, then swap it into place, so failures don't leave us without
-        // `index.{endpoint_id}.sqlite`.
-        let ts = config_bundle::utc_now_compact_timestamp();
+    let mut report = resumed_report.unwrap_or_else(|| {
+        config_bundle::ApplyPartialReport::new(
+            request_sha256.clone(),
+            endpoints_to_rebuild.iter().cloned(),
+        )
+    });
+    let resumed_endpoints = report
+        .endpoints
+        .iter()
+        .filter(|e| e.status == config_bundle::ApplyEndpointStatus::Rebuilt)
+        .map(|e| e.endpoint_id.clone())
+        .collect::<std::collections::BTreeSet<_>>();
+    let report_path =
+        config_bundle::write_apply_report(data_dir, &mut report).map_err(map_core_err)?;
 
-        let backup_path = if db_path.exists() {
-            let mut backup = db_path.clone();
-            backup.set_extension(format!("sqlite.bak.{ts}"));
-            if backup.exists() {
-                backup.set_extension(format!("sqlite.bak.{ts}.1"));
+    let mut endpoints_written = std::collections::BTreeSet::<String>::new();
+    let mut secrets_written = Vec::new();
+    let applied: Result<(), CliError> = async {
+        for ep_id in endpoints_to_rebuild.iter() {
+            if resumed_endpoints.contains(ep_id) {
+                continue;
             }
-            Some(backup)
-        } else {
-            None
-        };
-
-        let mut tmp_path = db_path.clone();
-        tmp_path.set_extension(format!("sqlite.tmp.{ts}"));
-        if tmp_path.exists() {
-            tmp_path.set_extension(format!("sqlite.tmp.{ts}.1"));
-        }
-
-        // Choose one target under this endpoint with remote latest available.
-        let mut chosen_remote: Option<(String, String, String)> = None;
-        if let Some(cat) = endpoint_catalogs.get(ep_id).and_then(|c| c.as_ref()) {
-            for t in &selected_targets {
-                if &t.endpoint_id != ep_id {
-                    continue;
-                }
-
-                let mut latest = cat
-                    .targets
-                    .iter()
-                    .find(|x| x.target_id == t.id)
-                    .and_then(|x| x.latest.clone());
-                if latest.is_none() {
-                    let matches = cat
-                        .targets
-                        .iter()
-                        .filter(|x| x.source_path == t.source_path)
-                        .collect::<Vec<_>>();
-                    if matches.len() == 1 {
-                        latest = matches[0].latest.clone();
+            run.check_cancelled()?;
+            run.phase(Phase::IndexRebuild, Some(ep_id));
+            let sink = ImportIndexRebuildProgressSink {
+                task_id: run.task_id,
+                endpoint_id: ep_id,
+                throttle: Mutex::new(ProgressThrottle::new(Duration::from_millis(200))),
+            };
+            let rebuilt = rebuild_import_endpoint_index(
+                data_dir,
+                ep_id,
+                &selected_targets,
+                endpoint_catalogs.get(ep_id).and_then(|c| c.as_ref()),
+                endpoint_storage.get(ep_id),
+                &bundle_master_key,
+                run.cancel,
+                run.events.then_some(&sink as &dyn ProgressSink),
+            )
+            .await;
+            match rebuilt {
+                Ok(entry) => report.set_endpoint(entry),
+                Err(e) => {
+                    if e.code != ErrorCode::TaskCancelled
+                        && let Some(entry) = report
+                            .endpoints
+                            .iter_mut()
+                            .find(|x| &x.endpoint_id == ep_id)
+                    {
+                        entry.status = config_bundle::ApplyEndpointStatus::Failed;
+                        entry.error_code = Some(e.code.as_str().to_string());
                     }
-                }
-
-                if let Some(latest) = latest {
-                    chosen_remote =
-                        Some((t.id.clone(), latest.snapshot_id, latest.manifest_object_id));
-                    break;
+                    return Err(e);
                 }
             }
+            config_bundle::write_apply_report(data_dir, &mut report).map_err(map_core_err)?;
         }
 
-        if let Some((target_id, snapshot_id, manifest_object_id)) = chosen_remote {
-            let provider = settings_config::endpoint_provider(ep_id);
-            let storage = endpoint_storage.get(ep_id).ok_or_else(|| {
-                CliError::retryable("telegram.unavailable", "telegram storage unavailable")
-            })?;
-
-            televy_backup_core::remote_index_db::download_and_write_index_db_atomic(
-                storage,
-                &snapshot_id,
-                &manifest_object_id,
-                &bundle_master_key,
-                &tmp_path,
-                None,
-                Some(&provider),
-                None,
-            )
-            .await
-            .map_err(map_core_err)?;
-
-            rebuilt_from = SettingsImportBundleApplyRebuiltFromJson {
-                mode: "remote_latest".to_string(),
-                snapshot_id: Some(snapshot_id),
-                manifest_object_id: Some(manifest_object_id),
-            };
-            local_index_synced.push(SettingsImportBundleApplyLocalIndexSyncedJson {
-                target_id,
-                from: "remoteLatest".to_string(),
-                to: "local".to_string(),
-            });
-        } else {
-            // No bootstrap/latest: initialize an empty DB so future backups can build it up.
-            init_empty_index_db(&tmp_path).await?;
+        // Auto-clean legacy global DB if all in-use per-endpoint DBs are present and usable.
+        let legacy_db_path = legacy_global_index_db_path(data_dir);
+        if legacy_db_path.exists() {
+            let mut in_use_endpoints = std::collections::BTreeSet::<String>::new();
 
-            rebuilt_from = SettingsImportBundleApplyRebuiltFromJson {
-                mode: "empty".to_string(),
-                snapshot_id: None,
-                manifest_object_id: None,
-            };
-        }
+            for t in &local_settings.targets {
+                if t.enabled {
+                    in_use_endpoints.insert(t.endpoint_id.clone());
+                }
+            }
+            for t in &selected_targets {
+                in_use_endpoints.insert(t.endpoint_id.clone());
+            }
 
-        // Swap: move old DB to a backup path (if any), then move the new DB into place.
-        previous_backup_path = None;
-        if let Some(backup) = &backup_path {
-            if let Err(e) = std::fs::rename(&db_path, backup) {
-                let _ = std::fs::remove_file(&tmp_path);
-                return Err(CliError::new("config.write_failed", e.to_string()));
+            let mut all_ok = true;
+            for ep_id in in_use_endpoints {
+                let p = endpoint_index_db_path(data_dir, &ep_id);
+                if !p.exists() {
+                    all_ok = false;
+                    break;
+                }
+                if televy_backup_core::index_db::open_index_db(&p)
+                    .await
+                    .is_err()
+                {
+                    all_ok = false;
+                    break;
+                }
             }
-            previous_backup_path = Some(backup.display().to_string());
-        }
 
-        if let Err(e) = std::fs::rename(&tmp_path, &db_path) {
-            // Best-effort rollback: restore the previous DB if we moved it.
-            if let Some(backup) = &backup_path {
-                let _ = std::fs::rename(backup, &db_path);
+            if all_ok {
+                let _ = std::fs::remove_file(&legacy_db_path);
             }
-            let _ = std::fs::remove_file(&tmp_path);
-            return Err(CliError::new("config.write_failed", e.to_string()));
         }
 
-        rebuilt_db_path = db_path.display().to_string();
-    }
-
-    // Auto-clean legacy global DB if all in-use per-endpoint DBs are present and usable.
-    let legacy_db_path = legacy_global_index_db_path(data_dir);
-    if legacy_db_path.exists() {
-        let mut in_use_endpoints = std::collections::BTreeSet::<String>::new();
+        // Write settings (merge semantics) + secrets after index rebuild succeeds.
+        run.check_cancelled()?;
+        run.phase(Phase::SettingsWrite, None);
+        let mut next_settings = local_settings.clone();
+        next_settings.schedule = bundle_settings.schedule.clone();
+        next_settings.retention = bundle_settings.retention.clone();
+        next_settings.chunking = bundle_settings.chunking.clone();
+        next_settings.telegram = bundle_settings.telegram.clone();
 
-        for t in &local_settings.targets {
-            if t.enabled {
-                in_use_endpoints.insert(t.endpoint_id.clone());
-            }
-        }
         for t in &selected_targets {
-            in_use_endpoints.insert(t.endpoint_id.clone());
-        }
+            endpoints_written.insert(t.endpoint_id.clone());
 
-        let mut all_ok = true;
-        for ep_id in in_use_endpoints {
-            let p = endpoint_index_db_path(data_dir, &ep_id);
-            if !p.exists() {
-                all_ok = false;
-                break;
+            // Upsert target.
+            match next_settings.targets.iter_mut().find(|x| x.id == t.id) {
+                Some(existing) => *existing = t.clone(),
+                None => next_settings.targets.push(t.clone()),
             }
-            if televy_backup_core::index_db::open_index_db(&p)
-                .await
-                .is_err()
+        }
+        for ep_id in endpoints_written.iter() {
+            if let Some(ep) = bundle_settings
+                .telegram_endpoints
+                .iter()
+                .find(|e| &e.id == ep_id)
             {
-                all_ok = false;
-                break;
+                match next_settings
+                    .telegram_endpoints
+                    .iter_mut()
+                    .find(|x| x.id == ep.id)
+                {
+                    Some(existing) => *existing = ep.clone(),
+                    None => next_settings.telegram_endpoints.push(ep.clone()),
+                }
             }
         }
 
-        if all_ok {
-            let _ = std::fs::remove_file(&legacy_db_path);
-        }
-    }
+        settings_config::save_settings_v2(config_dir, &next_settings).map_err(map_core_err)?;
+        report.settings_written = true;
+        config_bundle::write_apply_report(data_dir, &mut report).map_err(map_core_err)?;
 
-    // Write settings (merge semantics) + secrets after index rebuild succeeds.
-    let mut next_settings = local_settings.clone();
-    next_settings.schedule = bundle_settings.schedule.clone();
-    next_settings.retention = bundle_settings.retention.clone();
-    next_settings.chunking = bundle_settings.chunking.clone();
-    next_settings.telegram = bundle_settings.telegram.clone();
+        run.check_cancelled()?;
+        run.phase(Phase::SecretsWrite, None);
+        let vault_key = load_or_create_vault_key(data_dir)?;
+        let secrets_path = televy_backup_core::secrets::secrets_path(config_dir);
+        let mut store = televy_backup_core::secrets::load_secrets_store(&secrets_path, &vault_key)
+            .map_err(map_secrets_store_err)?;
 
-    let mut endpoints_written = std::collections::BTreeSet::<String>::new();
-    for t in &selected_targets {
-        endpoints_written.insert(t.endpoint_id.clone());
+        let master_key_b64 = base64::engine::general_purpose::STANDARD.encode(bundle_master_key);
+        let mut writes = vec![(MASTER_KEY_KEY.to_string(), master_key_b64)];
 
-        // Upsert target.
-        match next_settings.targets.iter_mut().find(|x| x.id == t.id) {
-            Some(existing) => *existing = t.clone(),
-            None => next_settings.targets.push(t.clone()),
+        for (k, v) in bundle_secrets.entries.iter() {
+            // Defense in depth: the core bundle decoder should reject this already.
+            if k == MASTER_KEY_KEY {
+                return Err(CliError::new(
+                    ErrorCode::ConfigInvalid,
+                    "config bundle secrets must not contain televybackup.master_key",
+                ));
+            }
+            writes.push((k.to_string(), v.to_string()));
         }
-    }
 
-    for ep_id in endpoints_written.iter() {
-        if let Some(ep) = bundle_settings
-            .telegram_endpoints
-            .iter()
-            .find(|e| &e.id == ep_id)
-        {
-            match next_settings
-                .telegram_endpoints
-                .iter_mut()
-                .find(|x| x.id == ep.id)
-            {
-                Some(existing) => *existing = ep.clone(),
-                None => next_settings.telegram_endpoints.push(ep.clone()),
+        // Route every secret before writing any, so an env-provided key fails the import up front.
+        let provider = televy_backup_core::secrets::SecretsProvider::new(config_dir);
+        let mut file_writes = Vec::new();
+        for (k, v) in writes {
+            match provider.write_target(&k).map_err(map_secrets_store_err)? {
+                televy_backup_core::secrets::SecretSource::File => file_writes.push((k.clone(), v)),
+                _ => store.set(k.as_str(), v.as_str()),
             }
+            secrets_written.push(k);
         }
-    }
-
-    settings_config::save_settings_v2(config_dir, &next_settings).map_err(map_core_err)?;
-
-    let vault_key = load_or_create_vault_key(data_dir)?;
-    let secrets_path = televy_backup_core::secrets::secrets_path(config_dir);
-    let mut store = televy_backup_core::secrets::load_secrets_store(&secrets_path, &vault_key)
-        .map_err(map_secrets_store_err)?;
-
-    let mut secrets_written = Vec::new();
-    let master_key_b64 = base64::engine::general_purpose::STANDARD.encode(bundle_master_key);
-    store.set(MASTER_KEY_KEY, master_key_b64);
-    secrets_written.push(MASTER_KEY_KEY.to_string());
 
-    for (k, v) in bundle_secrets.entries.iter() {
-        // Defense in depth: the core bundle decoder should reject this already.
-        if k == MASTER_KEY_KEY {
-            return Err(CliError::new(
-                "config.invalid",
-                "config bundle secrets must not contain televybackup.master_key",
-            ));
+        televy_backup_core::secrets::save_secrets_store(&secrets_path, &vault_key, &store)
+            .map_err(map_secrets_store_err)?;
+        for (k, v) in &file_writes {
+            provider.write_file(k, v).map_err(map_secrets_store_err)?;
         }
-        store.set(k.as_str(), v.as_str());
-        secrets_written.push(k.to_string());
+        report.secrets_written = true;
+        Ok(())
     }
-
-    televy_backup_core::secrets::save_secrets_store(&secrets_path, &vault_key, &store)
-        .map_err(map_secrets_store_err)?;
+    .await;
 
     secrets_written.sort();
     secrets_written.dedup();
@@ -2814,42 +5145,308 @@ async fn settings_import_bundle_apply(
         .collect::<Vec<_>>();
     applied_targets.sort();
 
-    let mut applied_endpoints = endpoints_written.into_iter().collect::<Vec<_>>();
-    applied_endpoints.sort();
+    let applied_endpoints = endpoints_written.into_iter().collect::<Vec<_>>();
+
+    if let Err(e) = applied {
+        let report_path =
+            config_bundle::write_apply_report(data_dir, &mut report).unwrap_or(report_path);
+        let resp = import_bundle_apply_response(
+            &report,
+            &resumed_endpoints,
+            Some(report_path.display().to_string()),
+            SettingsImportBundleApplyAppliedJson {
+                targets: if report.settings_written {
+                    applied_targets
+                } else {
+                    Vec::new()
+                },
+                endpoints: if report.settings_written {
+                    applied_endpoints
+                } else {
+                    Vec::new()
+                },
+                secrets_written: Vec::new(),
+            },
+        );
+        let mut e = e;
+        if let Some(details) = e.details.as_object_mut() {
+            details.insert(
+                "partial".to_string(),
+                serde_json::to_value(&resp).unwrap_or_default(),
+            );
+            details.insert(
+                "reportPath".to_string(),
+                report_path.display().to_string().into(),
+            );
+        }
+        return Err(e);
+    }
+
+    let updated_pins: Vec<SettingsImportBundleApplyPinnedUpdateJson> = Vec::new();
+    record_audit(
+        data_dir,
+        televy_backup_core::audit::AUDIT_OP_BUNDLE_APPLY,
+        serde_json::json!({
+            "targets": applied_targets,
+            "endpoints": applied_endpoints,
+            "secretsWritten": secrets_written,
+            "updatedPinnedCatalog": updated_pins,
+            "resumed": resume,
+        }),
+    );
 
-    let resp = SettingsImportBundleApplyResponse {
-        ok: true,
-        local_index: SettingsImportBundleApplyLocalIndexJson {
-            previous_db_backup_path: previous_backup_path,
-            rebuilt_db_path,
-            rebuilt_from,
-        },
-        applied: SettingsImportBundleApplyAppliedJson {
+    if let Err(e) = config_bundle::remove_apply_report(data_dir) {
+        tracing::warn!(
+            event = "config_bundle.apply_report_remove_failed",
+            error = %e,
+            "failed to remove the partial-apply report"
+        );
+    }
+
+    Ok(import_bundle_apply_response(
+        &report,
+        &resumed_endpoints,
+        None,
+        SettingsImportBundleApplyAppliedJson {
             targets: applied_targets,
             endpoints: applied_endpoints,
             secrets_written,
         },
-        actions: SettingsImportBundleApplyActionsJson {
-            updated_pinned_catalog: updated_pins,
-            local_index_synced,
-        },
-    };
-
-    println!(
-        "{}",
-        serde_json::to_string(&resp).map_err(|e| CliError::new("config.invalid", e.to_string()))?
-    );
-
-    Ok(())
+    ))
 }
 
-fn select_endpoint<'a>(
-    settings: &'a Settings,
-    endpoint_id: Option<&str>,
+/// Builds the apply response from the report's per-endpoint state. `localIndex` describes the
+/// last rebuilt endpoint, as it did before the per-endpoint `endpoints` list existed; the
+/// response is `partial` unless every endpoint was rebuilt and the secrets were written.
+fn import_bundle_apply_response(
+    report: &config_bundle::ApplyPartialReport,
+    resumed_endpoints: &std::collections::BTreeSet<String>,
+    report_path: Option<String>,
+    applied: SettingsImportBundleApplyAppliedJson,
+) -> SettingsImportBundleApplyResponse {
+    let rebuilt_from_json = |e: &config_bundle::ApplyEndpointReport| {
+        e.rebuilt_from
+            .as_ref()
+            .map(|mode| SettingsImportBundleApplyRebuiltFromJson {
+                mode: mode.clone(),
+                snapshot_id: e.snapshot_id.clone(),
+                manifest_object_id: e.manifest_object_id.clone(),
+            })
+    };
+
+    let last_rebuilt = report
+        .endpoints
+        .iter()
+        .rfind(|e| e.status == config_bundle::ApplyEndpointStatus::Rebuilt);
+    let local_index = SettingsImportBundleApplyLocalIndexJson {
+        previous_db_backup_path: last_rebuilt.and_then(|e| e.previous_db_backup_path.clone()),
+        rebuilt_db_path: last_rebuilt
+            .and_then(|e| e.rebuilt_db_path.clone())
+            .unwrap_or_default(),
+        rebuilt_from: last_rebuilt.and_then(rebuilt_from_json).unwrap_or(
+            SettingsImportBundleApplyRebuiltFromJson {
+                mode: "empty".to_string(),
+                snapshot_id: None,
+                manifest_object_id: None,
+            },
+        ),
+    };
+
+    let endpoints = report
+        .endpoints
+        .iter()
+        .map(|e| SettingsImportBundleApplyEndpointJson {
+            endpoint_id: e.endpoint_id.clone(),
+            status: e.status,
+            resumed: resumed_endpoints.contains(&e.endpoint_id),
+            rebuilt_from: rebuilt_from_json(e),
+            rebuilt_db_path: e.rebuilt_db_path.clone(),
+            previous_db_backup_path: e.previous_db_backup_path.clone(),
+            error_code: e.error_code.clone(),
+        })
+        .collect::<Vec<_>>();
+    let local_index_synced = report
+        .endpoints
+        .iter()
+        .filter_map(|e| e.synced_target_id.clone())
+        .map(|target_id| SettingsImportBundleApplyLocalIndexSyncedJson {
+            target_id,
+            from: "remoteLatest".to_string(),
+            to: "local".to_string(),
+        })
+        .collect();
+    let partial = !report.secrets_written
+        || report
+            .endpoints
+            .iter()
+            .any(|e| e.status != config_bundle::ApplyEndpointStatus::Rebuilt);
+
+    SettingsImportBundleApplyResponse {
+        ok: !partial,
+        partial,
+        report_path,
+        local_index,
+        endpoints,
+        applied,
+        actions: SettingsImportBundleApplyActionsJson {
+            updated_pinned_catalog: Vec::new(),
+            local_index_synced,
+        },
+    }
+}
+
+/// Rebuilds one endpoint's local index for an import: downloads the remote latest index of the
+/// first selected target on it that has one (or starts an empty DB), then swaps it into place,
+/// keeping the previous DB as a `.bak` next to it.
+#[allow(clippy::too_many_arguments)]
+async fn rebuild_import_endpoint_index(
+    data_dir: &Path,
+    ep_id: &str,
+    selected_targets: &[settings_config::Target],
+    catalog: Option<&bootstrap::BootstrapCatalogV1>,
+    storage: Option<&TelegramMtProtoStorage>,
+    master_key: &[u8; 32],
+    cancel: &CancellationToken,
+    progress: Option<&dyn ProgressSink>,
+) -> Result<config_bundle::ApplyEndpointReport, CliError> {
+    let db_path = endpoint_index_db_path(data_dir, ep_id);
+    let legacy_global = legacy_global_index_db_path(data_dir);
+    let _ = legacy_global; // must not read/write legacy global db here (migration compat)
+
+    // Build a replacement DB first, then swap it into place, so failures don't leave us without
+    // `index.{endpoint_id}.sqlite`.
+    let ts = config_bundle::utc_now_compact_timestamp();
+
+    let backup_path = if db_path.exists() {
+        let mut backup = db_path.clone();
+        backup.set_extension(format!("sqlite.bak.{ts}"));
+        if backup.exists() {
+            backup.set_extension(format!("sqlite.bak.{ts}.1"));
+        }
+        Some(backup)
+    } else {
+        None
+    };
+
+    let mut tmp_path = db_path.clone();
+    tmp_path.set_extension(format!("sqlite.tmp.{ts}"));
+    if tmp_path.exists() {
+        tmp_path.set_extension(format!("sqlite.tmp.{ts}.1"));
+    }
+
+    // Choose one target under this endpoint with remote latest available.
+    let mut chosen_remote: Option<(String, televy_backup_core::bootstrap::BootstrapLatest)> = None;
+    if let Some(cat) = catalog {
+        for t in selected_targets {
+            if t.endpoint_id != ep_id {
+                continue;
+            }
+
+            let mut latest = cat
+                .targets
+                .iter()
+                .find(|x| x.target_id == t.id)
+                .and_then(|x| x.latest.clone());
+            if latest.is_none() {
+                let matches = cat
+                    .targets
+                    .iter()
+                    .filter(|x| x.source_path == t.source_path)
+                    .collect::<Vec<_>>();
+                if matches.len() == 1 {
+                    latest = matches[0].latest.clone();
+                }
+            }
+
+            if let Some(latest) = latest {
+                chosen_remote = Some((t.id.clone(), latest));
+                break;
+            }
+        }
+    }
+
+    let mut entry = config_bundle::ApplyEndpointReport {
+        endpoint_id: ep_id.to_string(),
+        status: config_bundle::ApplyEndpointStatus::Rebuilt,
+        rebuilt_from: Some("empty".to_string()),
+        snapshot_id: None,
+        manifest_object_id: None,
+        rebuilt_db_path: None,
+        previous_db_backup_path: None,
+        synced_target_id: None,
+        error_code: None,
+    };
+
+    if let Some((target_id, latest)) = chosen_remote {
+        let data_key = latest
+            .data_key(master_key, &target_id)
+            .map_err(map_core_err)?;
+        let televy_backup_core::bootstrap::BootstrapLatest {
+            snapshot_id,
+            manifest_object_id,
+            manifest_sha256,
+            ..
+        } = latest;
+        let provider = settings_config::endpoint_provider(ep_id);
+        let storage = storage.ok_or_else(|| {
+            CliError::retryable(
+                ErrorCode::TelegramUnavailable,
+                "telegram storage unavailable",
+            )
+        })?;
+
+        televy_backup_core::remote_index_db::download_and_write_index_db_atomic(
+            storage,
+            &snapshot_id,
+            &manifest_object_id,
+            manifest_sha256.as_deref(),
+            data_key.key(),
+            &tmp_path,
+            Some(cancel),
+            Some(&provider),
+            progress,
+        )
+        .await
+        .map_err(map_core_err)?;
+
+        entry.rebuilt_from = Some("remote_latest".to_string());
+        entry.snapshot_id = Some(snapshot_id);
+        entry.manifest_object_id = Some(manifest_object_id);
+        entry.synced_target_id = Some(target_id);
+    } else {
+        // No bootstrap/latest: initialize an empty DB so future backups can build it up.
+        init_empty_index_db(&tmp_path).await?;
+    }
+
+    // Swap: move old DB to a backup path (if any), then move the new DB into place.
+    if let Some(backup) = &backup_path {
+        if let Err(e) = std::fs::rename(&db_path, backup) {
+            let _ = std::fs::remove_file(&tmp_path);
+            return Err(CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()));
+        }
+        entry.previous_db_backup_path = Some(backup.display().to_string());
+    }
+
+    if let Err(e) = std::fs::rename(&tmp_path, &db_path) {
+        // Best-effort rollback: restore the previous DB if we moved it.
+        if let Some(backup) = &backup_path {
+            let _ = std::fs::rename(backup, &db_path);
+        }
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()));
+    }
+
+    entry.rebuilt_db_path = Some(db_path.display().to_string());
+    Ok(entry)
+}
+
+fn select_endpoint<'a>(
+    settings: &'a Settings,
+    endpoint_id: Option<&str>,
 ) -> Result<&'a settings_config::TelegramEndpoint, CliError> {
     if settings.telegram_endpoints.is_empty() {
         return Err(CliError::new(
-            "config.invalid",
+            ErrorCode::ConfigInvalid,
             "no telegram endpoints configured",
         ));
     }
@@ -2859,7 +5456,12 @@ fn select_endpoint<'a>(
             .telegram_endpoints
             .iter()
             .find(|e| e.id == id)
-            .ok_or_else(|| CliError::new("config.invalid", format!("unknown endpoint_id: {id}")));
+            .ok_or_else(|| {
+                CliError::new(
+                    ErrorCode::ConfigInvalid,
+                    format!("unknown endpoint_id: {id}"),
+                )
+            });
     }
 
     if settings.telegram_endpoints.len() == 1 {
@@ -2874,7 +5476,7 @@ fn select_endpoint<'a>(
     }
 
     Err(CliError::new(
-        "config.invalid",
+        ErrorCode::ConfigInvalid,
         "multiple endpoints configured; pass --endpoint-id",
     ))
 }
@@ -2886,29 +5488,27 @@ fn select_target<'a>(
 ) -> Result<&'a settings_config::Target, CliError> {
     if settings.targets.is_empty() {
         return Err(CliError::new(
-            "config.invalid",
+            ErrorCode::ConfigInvalid,
             "no backup targets configured",
         ));
     }
 
     if let Some(id) = target_id {
-        return settings
-            .targets
-            .iter()
-            .find(|t| t.id == id)
-            .ok_or_else(|| CliError::new("config.invalid", format!("unknown target_id: {id}")));
+        return settings.targets.iter().find(|t| t.id == id).ok_or_else(|| {
+            CliError::new(ErrorCode::ConfigInvalid, format!("unknown target_id: {id}"))
+        });
     }
 
     let Some(source) = source else {
         return Err(CliError::new(
-            "config.invalid",
+            ErrorCode::ConfigInvalid,
             "either --target-id or --source must be provided",
         ));
     };
 
     let source_str = source
         .to_str()
-        .ok_or_else(|| CliError::new("config.invalid", "source path is not valid utf-8"))?;
+        .ok_or_else(|| CliError::new(ErrorCode::ConfigInvalid, "source path is not valid utf-8"))?;
 
     let mut matches = settings
         .targets
@@ -2918,13 +5518,13 @@ fn select_target<'a>(
 
     if matches.is_empty() {
         return Err(CliError::new(
-            "config.invalid",
+            ErrorCode::ConfigInvalid,
             format!("no target configured for source_path: {source_str}"),
         ));
     }
     if matches.len() > 1 {
         return Err(CliError::new(
-            "config.invalid",
+            ErrorCode::ConfigInvalid,
             format!("multiple targets match source_path={source_str}; use --target-id"),
         ));
     }
@@ -2935,18 +5535,16 @@ async fn secrets_set_telegram_bot_token(
     config_dir: &Path,
     data_dir: &Path,
     endpoint_id: Option<String>,
+    input_file: Option<&Path>,
     json: bool,
 ) -> Result<(), CliError> {
+    let token = read_command_input(input_file)?;
     let settings = load_settings(config_dir)?;
     let ep = select_endpoint(&settings, endpoint_id.as_deref())?;
 
-    let mut token = String::new();
-    std::io::stdin()
-        .read_to_string(&mut token)
-        .map_err(|e| CliError::new("config.read_failed", e.to_string()))?;
     let token = token.trim().to_string();
     if token.is_empty() {
-        return Err(CliError::new("config.invalid", "token is empty"));
+        return Err(CliError::new(ErrorCode::ConfigInvalid, "token is empty"));
     }
     daemon_control_secrets_set_telegram_bot_token(data_dir, &ep.id, &token)?;
 
@@ -2961,16 +5559,14 @@ async fn secrets_set_telegram_bot_token(
 async fn secrets_set_telegram_api_hash(
     config_dir: &Path,
     data_dir: &Path,
+    input_file: Option<&Path>,
     json: bool,
 ) -> Result<(), CliError> {
+    let api_hash = read_command_input(input_file)?;
     let settings = load_settings(config_dir)?;
-    let mut api_hash = String::new();
-    std::io::stdin()
-        .read_to_string(&mut api_hash)
-        .map_err(|e| CliError::new("config.read_failed", e.to_string()))?;
     let api_hash = api_hash.trim().to_string();
     if api_hash.is_empty() {
-        return Err(CliError::new("config.invalid", "api_hash is empty"));
+        return Err(CliError::new(ErrorCode::ConfigInvalid, "api_hash is empty"));
     }
     let _ = settings;
     daemon_control_secrets_set_telegram_api_hash(data_dir, &api_hash)?;
@@ -3001,6 +5597,173 @@ async fn secrets_clear_telegram_mtproto_session(
     Ok(())
 }
 
+/// The daemon answers when it's running; otherwise the store is read directly.
+fn secrets_list_keys(
+    config_dir: &Path,
+    data_dir: &Path,
+) -> Result<Vec<televy_backup_core::secrets::SecretKeyInfo>, CliError> {
+    match daemon_control_secrets_list(data_dir) {
+        Err(e) if control_ipc_unreachable(&e) => {}
+        other => return other,
+    }
+
+    let settings = load_settings(config_dir)?;
+    let provider = televy_backup_core::secrets::SecretsProvider::new(config_dir);
+    let vault_key = load_or_create_vault_key(data_dir)?;
+    let path = televy_backup_core::secrets::secrets_path(config_dir);
+    let store = televy_backup_core::secrets::load_secrets_store(&path, &vault_key)
+        .map_err(map_secrets_store_err)?;
+    televy_backup_core::secrets::list_secret_keys(&provider, &store, &settings)
+        .map_err(map_secrets_store_err)
+}
+
+/// True when the daemon isn't running or predates the method, so the CLI should do the work.
+fn control_ipc_unreachable(e: &CliError) -> bool {
+    matches!(
+        e.code,
+        ErrorCode::ControlUnavailable | ErrorCode::ControlMethodNotFound
+    )
+}
+
+async fn secrets_list(config_dir: &Path, data_dir: &Path, json: bool) -> Result<(), CliError> {
+    let keys = secrets_list_keys(config_dir, data_dir)?;
+    if json {
+        println!("{}", serde_json::json!({ "keys": keys }));
+    } else {
+        for k in &keys {
+            println!(
+                "key={} source={} len={} fingerprint={} orphaned={}",
+                k.key, k.source, k.value_len, k.fingerprint, k.orphaned
+            );
+        }
+    }
+    Ok(())
+}
+
+/// `secrets delete` switches besides `--key`/`--orphaned` (no `--key` means `--orphaned`).
+#[derive(Debug, Clone, Copy)]
+struct SecretsDeleteFlags {
+    yes: bool,
+    i_understand_data_loss: bool,
+    no_print: bool,
+}
+
+async fn secrets_delete(
+    config_dir: &Path,
+    data_dir: &Path,
+    key: Option<String>,
+    flags: SecretsDeleteFlags,
+    json: bool,
+) -> Result<(), CliError> {
+    let keys = match key {
+        Some(key) => vec![key],
+        None => secrets_list_keys(config_dir, data_dir)?
+            .into_iter()
+            .filter(|k| k.orphaned)
+            .map(|k| k.key)
+            .collect(),
+    };
+    if keys.is_empty() {
+        print_secrets_deleted(&[], json);
+        return Ok(());
+    }
+
+    let master = keys.iter().any(|k| k == MASTER_KEY_KEY);
+    if master && !flags.i_understand_data_loss {
+        return Err(CliError::new(
+            ErrorCode::ConfigInvalid,
+            "refusing to delete the master key without --i-understand-data-loss; backups can't be restored without it",
+        ));
+    }
+    if master && !flags.no_print && get_secret(config_dir, data_dir, MASTER_KEY_KEY)?.is_some() {
+        let gold = gold_key::encode_gold_key(&load_master_key(config_dir, data_dir)?);
+        record_audit(
+            data_dir,
+            televy_backup_core::audit::AUDIT_OP_MASTER_KEY_EXPORT,
+            serde_json::json!({ "format": gold_key::GOLD_KEY_FORMAT }),
+        );
+        eprintln!("master key (keep this to restore existing backups): {gold}");
+    }
+
+    if !flags.yes {
+        confirm_secrets_delete(&keys).await?;
+    }
+
+    let deleted = match daemon_control_secrets_delete(data_dir, &keys, flags.i_understand_data_loss)
+    {
+        Err(e) if control_ipc_unreachable(&e) => {
+            let provider = televy_backup_core::secrets::SecretsProvider::new(config_dir);
+            let vault_key = load_or_create_vault_key(data_dir)?;
+            let path = televy_backup_core::secrets::secrets_path(config_dir);
+            let mut store = televy_backup_core::secrets::load_secrets_store(&path, &vault_key)
+                .map_err(map_secrets_store_err)?;
+            let deleted =
+                televy_backup_core::secrets::delete_secret_keys(&provider, &mut store, &keys)
+                    .map_err(map_secrets_store_err)?;
+            if !deleted.is_empty() {
+                televy_backup_core::secrets::save_secrets_store(&path, &vault_key, &store)
+                    .map_err(map_secrets_store_err)?;
+            }
+            for key in &deleted {
+                record_audit(
+                    data_dir,
+                    televy_backup_core::audit::AUDIT_OP_SECRET_DELETE,
+                    serde_json::json!({ "key": key }),
+                );
+            }
+            deleted
+        }
+        other => other?,
+    };
+    print_secrets_deleted(&deleted, json);
+    Ok(())
+}
+
+async fn confirm_secrets_delete(keys: &[String]) -> Result<(), CliError> {
+    if !std::io::stdin().is_terminal() {
+        return Err(CliError::new(
+            ErrorCode::SecretsConfirmationRequired,
+            "deleting secrets needs confirmation; re-run with --yes",
+        )
+        .with_details(serde_json::json!({ "keys": keys })));
+    }
+    for key in keys {
+        eprintln!("  {key}");
+    }
+    eprint!("Delete {} secret(s)? [y/N] ", keys.len());
+    let _ = std::io::stderr().flush();
+
+    let answer = tokio::task::spawn_blocking(|| {
+        let mut line = String::new();
+        std::io::stdin().read_line(&mut line).map(|_| line)
+    })
+    .await
+    .map_err(|e| CliError::new(ErrorCode::TaskCancelled, format!("prompt aborted: {e}")))?
+    .map_err(|e| CliError::new(ErrorCode::Io, e.to_string()))?;
+
+    if prompt_answer_confirms(&answer) {
+        Ok(())
+    } else {
+        Err(CliError::new(
+            ErrorCode::TaskCancelled,
+            "secrets delete cancelled at the confirmation",
+        ))
+    }
+}
+
+fn print_secrets_deleted(deleted: &[String], json: bool) {
+    if json {
+        println!("{}", serde_json::json!({ "ok": true, "deleted": deleted }));
+    } else {
+        for key in deleted {
+            println!("deleted={key}");
+        }
+        if deleted.is_empty() {
+            println!("deleted=none");
+        }
+    }
+}
+
 async fn secrets_migrate_keychain(
     config_dir: &Path,
     data_dir: &Path,
@@ -3054,7 +5817,7 @@ async fn secrets_migrate_keychain(
                     }
                 } else {
                     return Err(CliError::new(
-                        "secrets.migrate_conflict",
+                        ErrorCode::SecretsMigrateConflict,
                         "master key differs between secrets store and Keychain; refusing to delete Keychain item. Fix: decide which master key to keep, then re-run migration.",
                     ));
                 }
@@ -3089,21 +5852,25 @@ async fn secrets_init_master_key(
 ) -> Result<(), CliError> {
     if get_secret(config_dir, data_dir, MASTER_KEY_KEY)?.is_some() {
         return Err(CliError::new(
-            "secrets.store_failed",
+            ErrorCode::SecretsStoreFailed,
             "master key already exists",
         ));
     }
 
     if daemon_keychain_get_secret(data_dir, MASTER_KEY_KEY)?.is_some() {
         return Err(CliError::new(
-            "secrets.store_failed",
+            ErrorCode::SecretsStoreFailed,
             "master key exists in Keychain (old scheme). Fix: run `televybackup secrets migrate-keychain` instead of generating a new one.",
         ));
     }
 
     let mut bytes = [0u8; 32];
-    getrandom::getrandom(&mut bytes)
-        .map_err(|e| CliError::new("secrets.store_failed", format!("getrandom failed: {e}")))?;
+    getrandom::getrandom(&mut bytes).map_err(|e| {
+        CliError::new(
+            ErrorCode::SecretsStoreFailed,
+            format!("getrandom failed: {e}"),
+        )
+    })?;
     let b64 = base64::engine::general_purpose::STANDARD.encode(bytes);
     set_secret(config_dir, data_dir, MASTER_KEY_KEY, &b64)?;
     if json {
@@ -3122,13 +5889,18 @@ async fn secrets_export_master_key(
 ) -> Result<(), CliError> {
     if !i_understand {
         return Err(CliError::new(
-            "config.invalid",
+            ErrorCode::ConfigInvalid,
             "refusing to export master key without --i-understand",
         ));
     }
 
     let master_key = load_master_key(config_dir, data_dir)?;
     let gold = gold_key::encode_gold_key(&master_key);
+    record_audit(
+        data_dir,
+        televy_backup_core::audit::AUDIT_OP_MASTER_KEY_EXPORT,
+        serde_json::json!({ "format": gold_key::GOLD_KEY_FORMAT }),
+    );
 
     if json {
         println!(
@@ -3141,26 +5913,113 @@ async fn secrets_export_master_key(
     Ok(())
 }
 
+async fn secrets_export_target_key(
+    config_dir: &Path,
+    data_dir: &Path,
+    target_id: &str,
+    i_understand: bool,
+    json: bool,
+) -> Result<(), CliError> {
+    if !i_understand {
+        return Err(CliError::new(
+            ErrorCode::ConfigInvalid,
+            "refusing to export target key without --i-understand",
+        ));
+    }
+
+    let settings = load_settings(config_dir)?;
+    let target = select_target(&settings, Some(target_id), None)?;
+    if !target.target_key {
+        return Err(CliError::new(
+            ErrorCode::ConfigInvalid,
+            format!(
+                "target {target_id} does not use a target key (set targets[].target_key = true)"
+            ),
+        ));
+    }
+
+    let master_key = load_master_key(config_dir, data_dir)?;
+    let provider = settings_config::endpoint_provider(&target.endpoint_id);
+    let encoded = gold_key::encode_target_key(&gold_key::TargetKey::derive(
+        &master_key,
+        &target.id,
+        &provider,
+    ));
+    record_audit(
+        data_dir,
+        televy_backup_core::audit::AUDIT_OP_TARGET_KEY_EXPORT,
+        serde_json::json!({ "format": gold_key::TARGET_KEY_FORMAT, "targetId": target.id }),
+    );
+
+    if json {
+        // The newest target-keyed snapshot, so the recipient knows what to pass to
+        // `restore run --target-key`.
+        let latest = latest_target_keyed_snapshot(data_dir, target).await?;
+        println!(
+            "{}",
+            serde_json::json!({
+                "targetKey": encoded,
+                "format": gold_key::TARGET_KEY_FORMAT,
+                "targetId": target.id,
+                "latestSnapshotId": latest.as_ref().map(|(snapshot_id, _)| snapshot_id),
+                "manifestObjectId": latest.as_ref().map(|(_, manifest_object_id)| manifest_object_id),
+            })
+        );
+    } else {
+        println!("{encoded}");
+    }
+    Ok(())
+}
+
+/// Newest snapshot of `target` written under its target key, with its file map manifest id.
+async fn latest_target_keyed_snapshot(
+    data_dir: &Path,
+    target: &settings_config::Target,
+) -> Result<Option<(String, String)>, CliError> {
+    let db_path = endpoint_index_db_path(data_dir, &target.endpoint_id);
+    if !db_path.exists() {
+        return Ok(None);
+    }
+    let pool = televy_backup_core::index_db::open_index_db(&db_path)
+        .await
+        .map_err(map_core_err)?;
+    let row = sqlx::query(
+        r#"
+        SELECT s.snapshot_id AS snapshot_id, ri.manifest_object_id AS manifest_object_id
+        FROM snapshots s
+        JOIN remote_indexes ri ON ri.snapshot_id = s.snapshot_id
+        WHERE s.key_derivation = ? AND s.key_target_id = ?
+        ORDER BY s.created_at DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(KeyDerivation::TargetV1.version())
+    .bind(&target.id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|e| CliError::new(ErrorCode::DbFailed, e.to_string()));
+    pool.close().await;
+    Ok(row?.map(|r| (r.get("snapshot_id"), r.get("manifest_object_id"))))
+}
+
 async fn secrets_import_master_key(
     config_dir: &Path,
     data_dir: &Path,
     force: bool,
+    input_file: Option<&Path>,
     json: bool,
 ) -> Result<(), CliError> {
+    let input = read_command_input(input_file)?;
     if get_secret(config_dir, data_dir, MASTER_KEY_KEY)?.is_some() && !force {
         return Err(CliError::new(
-            "secrets.store_failed",
+            ErrorCode::SecretsStoreFailed,
             "master key already exists (pass --force to overwrite)",
         ));
     }
 
-    let mut input = String::new();
-    std::io::stdin()
-        .read_to_string(&mut input)
-        .map_err(|e| CliError::new("config.read_failed", e.to_string()))?;
     let input = input.trim();
     if input.is_empty() {
-        return Err(CliError::new("config.invalid", "gold key is empty"));
+        return Err(CliError::new(ErrorCode::ConfigInvalid, "gold key is empty"));
     }
 
     let master_key = gold_key::decode_gold_key(input).map_err(map_core_err)?;
@@ -3184,35 +6043,47 @@ async fn telegram_validate(
     let settings = load_settings(config_dir)?;
     let ep = select_endpoint(&settings, endpoint_id.as_deref())?;
 
-    if settings.telegram.mtproto.api_id <= 0 {
+    if ep.mtproto_api_id(&settings.telegram.mtproto) <= 0 {
         return Err(CliError::new(
-            "config.invalid",
+            ErrorCode::ConfigInvalid,
             "telegram.mtproto.api_id must be > 0",
         ));
     }
-    if settings.telegram.mtproto.api_hash_key.is_empty() {
+    if ep
+        .mtproto_api_hash_key(&settings.telegram.mtproto)
+        .is_empty()
+    {
         return Err(CliError::new(
-            "config.invalid",
+            ErrorCode::ConfigInvalid,
             "telegram.mtproto.api_hash_key must not be empty",
         ));
     }
     if ep.chat_id.trim().is_empty() {
         return Err(CliError::new(
-            "config.invalid",
+            ErrorCode::ConfigInvalid,
             format!("telegram_endpoints[{id}].chat_id is empty", id = ep.id),
         ));
     }
+    let likely_private_chat = settings_config::is_likely_private_chat_id(&ep.chat_id);
+    if likely_private_chat {
+        tracing::warn!(
+            event = "telegram.chat_id_private",
+            endpoint_id = %ep.id,
+            chat_id = %ep.chat_id,
+            "chat_id looks like a private user chat; bootstrap pinning needs a group/channel (e.g. -100...) or @username chat id"
+        );
+    }
 
     let bot_token = get_secret(config_dir, data_dir, &ep.bot_token_key)?
-        .ok_or_else(|| CliError::new("telegram.unauthorized", "bot token missing"))?;
+        .ok_or_else(|| CliError::new(ErrorCode::TelegramUnauthorized, "bot token missing"))?;
     let api_hash = get_secret(
         config_dir,
         data_dir,
-        &settings.telegram.mtproto.api_hash_key,
+        ep.mtproto_api_hash_key(&settings.telegram.mtproto),
     )?
     .ok_or_else(|| {
         CliError::new(
-            "telegram.mtproto.missing_api_hash",
+            ErrorCode::TelegramMtprotoMissingApiHash,
             "mtproto api_hash missing",
         )
     })?;
@@ -3221,34 +6092,38 @@ async fn telegram_validate(
         config_dir,
         data_dir,
         &ep.mtproto.session_key,
-        "telegram.mtproto.session_invalid",
+        ErrorCode::TelegramMtprotoSessionInvalid,
         "invalid mtproto session (try: `televybackup secrets clear-telegram-mtproto-session`)",
     )?;
 
     let cache_dir = data_dir.join("cache").join("mtproto");
     std::fs::create_dir_all(&cache_dir)
-        .map_err(|e| CliError::new("config.write_failed", e.to_string()))?;
+        .map_err(|e| CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()))?;
 
     let provider = settings_config::endpoint_provider(&ep.id);
 
     let storage = TelegramMtProtoStorage::connect(TelegramMtProtoStorageConfig {
         provider,
-        api_id: settings.telegram.mtproto.api_id,
+        api_id: ep.mtproto_api_id(&settings.telegram.mtproto),
         api_hash: api_hash.clone(),
         bot_token: bot_token.clone(),
         chat_id: ep.chat_id.clone(),
+        migrated_from_chat_ids: ep.migrated_from_chat_ids.clone(),
         session,
         cache_dir,
         min_delay_ms: Some(ep.rate_limit.min_delay_ms as u64),
         max_concurrent_uploads: Some(ep.rate_limit.max_concurrent_uploads as usize),
         helper_path: None,
+        bootstrap_pin_mode: ep.bootstrap.pin_mode,
+        object_prefix: endpoint_object_prefix(data_dir, ep),
     })
     .await
     .map_err(|e| map_mtproto_validate_err(e, &bot_token, &api_hash))?;
+    save_mtproto_chat_migration(config_dir, &ep.id, &storage)?;
 
     let mut sample = vec![0u8; 1024];
     getrandom::getrandom(&mut sample)
-        .map_err(|e| CliError::new("config.invalid", format!("getrandom failed: {e}")))?;
+        .map_err(|e| CliError::new(ErrorCode::ConfigInvalid, format!("getrandom failed: {e}")))?;
 
     let sample_name = "televybackup-validate.bin";
     let object_id = storage
@@ -3261,7 +6136,7 @@ async fn telegram_validate(
         .map_err(|e| map_mtproto_validate_err(e, &bot_token, &api_hash))?;
     if downloaded != sample {
         return Err(CliError::new(
-            "telegram.roundtrip_failed",
+            ErrorCode::TelegramRoundtripFailed,
             format!(
                 "roundtrip mismatch: uploaded_len={} downloaded_len={}",
                 sample.len(),
@@ -3283,6 +6158,7 @@ async fn telegram_validate(
                 "mode": "mtproto",
                 "endpointId": ep.id,
                 "chatId": ep.chat_id,
+                "likelyPrivateChat": likely_private_chat,
                 "roundTripOk": true,
                 "sampleObjectId": object_id,
             })
@@ -3308,29 +6184,32 @@ async fn telegram_dialogs(
     let settings = load_settings(config_dir)?;
     let ep = select_endpoint(&settings, endpoint_id.as_deref())?;
 
-    if settings.telegram.mtproto.api_id <= 0 {
+    if ep.mtproto_api_id(&settings.telegram.mtproto) <= 0 {
         return Err(CliError::new(
-            "config.invalid",
+            ErrorCode::ConfigInvalid,
             "telegram.mtproto.api_id must be > 0",
         ));
     }
-    if settings.telegram.mtproto.api_hash_key.is_empty() {
+    if ep
+        .mtproto_api_hash_key(&settings.telegram.mtproto)
+        .is_empty()
+    {
         return Err(CliError::new(
-            "config.invalid",
+            ErrorCode::ConfigInvalid,
             "telegram.mtproto.api_hash_key must not be empty",
         ));
     }
 
     let bot_token = get_secret(config_dir, data_dir, &ep.bot_token_key)?
-        .ok_or_else(|| CliError::new("telegram.unauthorized", "bot token missing"))?;
+        .ok_or_else(|| CliError::new(ErrorCode::TelegramUnauthorized, "bot token missing"))?;
     let api_hash = get_secret(
         config_dir,
         data_dir,
-        &settings.telegram.mtproto.api_hash_key,
+        ep.mtproto_api_hash_key(&settings.telegram.mtproto),
     )?
     .ok_or_else(|| {
         CliError::new(
-            "telegram.mtproto.missing_api_hash",
+            ErrorCode::TelegramMtprotoMissingApiHash,
             "mtproto api_hash missing",
         )
     })?;
@@ -3338,28 +6217,31 @@ async fn telegram_dialogs(
         config_dir,
         data_dir,
         &ep.mtproto.session_key,
-        "telegram.mtproto.session_invalid",
+        ErrorCode::TelegramMtprotoSessionInvalid,
         "invalid mtproto session (try: `televybackup secrets clear-telegram-mtproto-session`)",
     )?;
 
     let cache_dir = data_dir.join("cache").join("mtproto");
     std::fs::create_dir_all(&cache_dir)
-        .map_err(|e| CliError::new("config.write_failed", e.to_string()))?;
+        .map_err(|e| CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()))?;
 
     let provider = settings_config::endpoint_provider(&ep.id);
     let storage = TelegramMtProtoStorage::connect(TelegramMtProtoStorageConfig {
         provider,
-        api_id: settings.telegram.mtproto.api_id,
+        api_id: ep.mtproto_api_id(&settings.telegram.mtproto),
         api_hash: api_hash.clone(),
         bot_token: bot_token.clone(),
         // Dialog listing does not require a selected chat. Intentionally skip resolve_chat so users
         // can discover a valid group/channel even when chat_id is empty/invalid.
         chat_id: String::new(),
+        migrated_from_chat_ids: Vec::new(),
         session,
         cache_dir,
         min_delay_ms: Some(ep.rate_limit.min_delay_ms as u64),
         max_concurrent_uploads: Some(ep.rate_limit.max_concurrent_uploads as usize),
         helper_path: None,
+        bootstrap_pin_mode: ep.bootstrap.pin_mode,
+        object_prefix: endpoint_object_prefix(data_dir, ep),
     })
     .await
     .map_err(map_core_err)?;
@@ -3367,12 +6249,12 @@ async fn telegram_dialogs(
     let limit = limit.clamp(1, 5_000) as usize;
     let dialogs = match storage.list_dialogs(limit, include_users) {
         Ok(v) => v,
-        Err(televy_backup_core::Error::Telegram { message })
+        Err(televy_backup_core::Error::Telegram { message, .. })
             if message.contains("BOT_METHOD_INVALID")
                 || message.contains("messages.getDialogs") =>
         {
             return Err(CliError::new(
-                "telegram.dialogs_unsupported",
+                ErrorCode::TelegramDialogsUnsupported,
                 "bots cannot list dialogs via MTProto (messages.getDialogs rejected); use `televybackup telegram wait-chat` and send a message in the target group/channel to discover its chat_id",
             ));
         }
@@ -3384,7 +6266,7 @@ async fn telegram_dialogs(
         if let Err(e) = set_secret(config_dir, data_dir, &ep.mtproto.session_key, &b64) {
             tracing::warn!(
                 event = "secrets.session_persist_failed",
-                error_code = e.code,
+                error_code = e.code.as_str(),
                 error_message = %e.message,
                 "failed to persist mtproto session"
             );
@@ -3423,40 +6305,50 @@ async fn telegram_dialogs(
     Ok(())
 }
 
-async fn telegram_wait_chat(
+/// Message ids per `list_documents` call (the helper's cap).
+const CHAT_SCAN_WINDOW: usize = 100;
+/// Without `--max-msg-id`, stop after this many windows in a row had no messages.
+const CHAT_SCAN_EMPTY_WINDOWS_STOP: u32 = 20;
+
+/// Connects to the endpoint's chat for commands that only read it.
+async fn connect_endpoint_storage(
     config_dir: &Path,
     data_dir: &Path,
-    endpoint_id: Option<String>,
-    timeout_secs: u32,
-    include_users: bool,
-    json: bool,
-) -> Result<(), CliError> {
-    let settings = load_settings(config_dir)?;
-    let ep = select_endpoint(&settings, endpoint_id.as_deref())?;
-
-    if settings.telegram.mtproto.api_id <= 0 {
+    settings: &Settings,
+    ep: &settings_config::TelegramEndpoint,
+) -> Result<TelegramMtProtoStorage, CliError> {
+    if ep.chat_id.trim().is_empty() {
+        return Err(CliError::new(
+            ErrorCode::ConfigInvalid,
+            format!("telegram_endpoints[{id}].chat_id is empty", id = ep.id),
+        ));
+    }
+    if ep.mtproto_api_id(&settings.telegram.mtproto) <= 0 {
         return Err(CliError::new(
-            "config.invalid",
+            ErrorCode::ConfigInvalid,
             "telegram.mtproto.api_id must be > 0",
         ));
     }
-    if settings.telegram.mtproto.api_hash_key.is_empty() {
+    if ep
+        .mtproto_api_hash_key(&settings.telegram.mtproto)
+        .is_empty()
+    {
         return Err(CliError::new(
-            "config.invalid",
+            ErrorCode::ConfigInvalid,
             "telegram.mtproto.api_hash_key must not be empty",
         ));
     }
 
     let bot_token = get_secret(config_dir, data_dir, &ep.bot_token_key)?
-        .ok_or_else(|| CliError::new("telegram.unauthorized", "bot token missing"))?;
+        .ok_or_else(|| CliError::new(ErrorCode::TelegramUnauthorized, "bot token missing"))?;
     let api_hash = get_secret(
         config_dir,
         data_dir,
-        &settings.telegram.mtproto.api_hash_key,
+        ep.mtproto_api_hash_key(&settings.telegram.mtproto),
     )?
     .ok_or_else(|| {
         CliError::new(
-            "telegram.mtproto.missing_api_hash",
+            ErrorCode::TelegramMtprotoMissingApiHash,
             "mtproto api_hash missing",
         )
     })?;
@@ -3464,53 +6356,456 @@ async fn telegram_wait_chat(
         config_dir,
         data_dir,
         &ep.mtproto.session_key,
-        "telegram.mtproto.session_invalid",
+        ErrorCode::TelegramMtprotoSessionInvalid,
         "invalid mtproto session (try: `televybackup secrets clear-telegram-mtproto-session`)",
     )?;
 
     let cache_dir = data_dir.join("cache").join("mtproto");
     std::fs::create_dir_all(&cache_dir)
-        .map_err(|e| CliError::new("config.write_failed", e.to_string()))?;
+        .map_err(|e| CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()))?;
 
-    let provider = settings_config::endpoint_provider(&ep.id);
-    let storage = TelegramMtProtoStorage::connect(TelegramMtProtoStorageConfig {
-        provider,
-        api_id: settings.telegram.mtproto.api_id,
+    TelegramMtProtoStorage::connect(TelegramMtProtoStorageConfig {
+        provider: settings_config::endpoint_provider(&ep.id),
+        api_id: ep.mtproto_api_id(&settings.telegram.mtproto),
         api_hash: api_hash.clone(),
         bot_token: bot_token.clone(),
-        // WaitChat does not require a selected chat.
-        chat_id: String::new(),
+        chat_id: ep.chat_id.clone(),
+        migrated_from_chat_ids: ep.migrated_from_chat_ids.clone(),
         session,
         cache_dir,
         min_delay_ms: Some(ep.rate_limit.min_delay_ms as u64),
         max_concurrent_uploads: Some(ep.rate_limit.max_concurrent_uploads as usize),
         helper_path: None,
+        bootstrap_pin_mode: ep.bootstrap.pin_mode,
+        object_prefix: endpoint_object_prefix(data_dir, ep),
     })
     .await
-    .map_err(map_core_err)?;
+    .map_err(|e| map_mtproto_validate_err(e, &bot_token, &api_hash))
+}
 
-    let timeout_secs = (timeout_secs as u64).clamp(1, 10 * 60);
-    let chat = match storage.wait_for_chat(timeout_secs, include_users) {
-        Ok(v) => v,
-        Err(televy_backup_core::Error::Telegram { message })
-            if message.contains("wait_for_chat timed out") =>
-        {
-            return Err(CliError::retryable("telegram.timeout", message));
+/// Documents in message ids `from_msg_id..` (through `max_msg_id`, else until the chat seems to
+/// end) and the last message id scanned. Bots can't read history, so this walks message ids
+/// window by window.
+fn scan_chat_documents(
+    storage: &TelegramMtProtoStorage,
+    from_msg_id: i32,
+    max_msg_id: Option<i32>,
+) -> Result<(Vec<televy_backup_core::TelegramDocumentInfo>, i32), CliError> {
+    let mut documents = Vec::new();
+    let mut from_msg_id = from_msg_id.max(1);
+    let mut empty_windows = 0u32;
+    loop {
+        let count = match max_msg_id {
+            Some(max) if from_msg_id > max => break,
+            Some(max) => usize::try_from(max - from_msg_id + 1)
+                .unwrap_or(CHAT_SCAN_WINDOW)
+                .min(CHAT_SCAN_WINDOW),
+            None => CHAT_SCAN_WINDOW,
+        };
+        let batch = storage
+            .list_documents(from_msg_id, count)
+            .map_err(map_core_err)?;
+        if batch.messages == 0 {
+            empty_windows += 1;
+            if max_msg_id.is_none() && empty_windows >= CHAT_SCAN_EMPTY_WINDOWS_STOP {
+                break;
+            }
+        } else {
+            empty_windows = 0;
         }
-        Err(e) => return Err(map_core_err(e)),
-    };
-
+        documents.extend(batch.documents);
+        let Some(next) = i32::try_from(count)
+            .ok()
+            .and_then(|count| from_msg_id.checked_add(count))
+        else {
+            break;
+        };
+        from_msg_id = next;
+    }
+    Ok((documents, from_msg_id - 1))
+}
+
+async fn telegram_export_mapping(
+    config_dir: &Path,
+    data_dir: &Path,
+    endpoint_id: Option<String>,
+    old_chat: &str,
+    output: &Path,
+    max_msg_id: Option<i32>,
+    json: bool,
+) -> Result<(), CliError> {
+    let settings = load_settings(config_dir)?;
+    let ep = select_endpoint(&settings, endpoint_id.as_deref())?;
+
+    let old_chat = settings_config::normalize_chat_id(old_chat).map_err(map_core_err)?;
+    if old_chat.is_empty() {
+        return Err(CliError::new(
+            ErrorCode::ConfigInvalid,
+            "--old-chat must not be empty",
+        ));
+    }
+    if old_chat == ep.chat_id {
+        return Err(CliError::new(
+            ErrorCode::ConfigInvalid,
+            format!(
+                "--old-chat is the endpoint's current chat; set telegram_endpoints[{id}].chat_id to the new chat first",
+                id = ep.id
+            ),
+        ));
+    }
+
+    let db_path = endpoint_index_db_path(data_dir, &ep.id);
+    if !db_path.exists() {
+        return Err(CliError::new(
+            ErrorCode::ConfigInvalid,
+            format!("local index db not found: {}", db_path.display()),
+        ));
+    }
+    let old_documents =
+        televy_backup_core::chat_remap::index_db_documents_in_chat(&db_path, &old_chat)
+            .await
+            .map_err(map_core_err)?;
+
+    let storage = connect_endpoint_storage(config_dir, data_dir, &settings, ep).await?;
+    let scanned = scan_chat_documents(&storage, 1, max_msg_id);
+    persist_mtproto_session(config_dir, data_dir, ep, &storage);
+    let (forwarded, _) = scanned?;
+
+    let pairs = televy_backup_core::chat_remap::match_forwarded_documents(
+        &old_chat,
+        &old_documents,
+        &forwarded,
+    );
+    std::fs::write(output, televy_backup_core::chat_remap::mapping_csv(&pairs))
+        .map_err(|e| CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()))?;
+
+    let matched: std::collections::HashSet<i32> = pairs.iter().map(|(old, _)| *old).collect();
+    let unmatched = old_documents
+        .values()
+        .filter(|msg_id| !matched.contains(msg_id))
+        .count();
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({
+                "endpointId": ep.id,
+                "oldChatId": old_chat,
+                "newChatId": ep.chat_id,
+                "output": output.display().to_string(),
+                "documentsScanned": forwarded.len(),
+                "matched": pairs.len(),
+                "unmatched": unmatched,
+            })
+        );
+    } else {
+        println!("output={}", output.display());
+        println!("documentsScanned={}", forwarded.len());
+        println!("matched={}", pairs.len());
+        println!("unmatched={unmatched}");
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn telegram_audit_chat(
+    config_dir: &Path,
+    data_dir: &Path,
+    endpoint_id: Option<String>,
+    from_msg_id: i32,
+    max_msg_id: Option<i32>,
+    sample_percent: Option<f64>,
+    no_download: bool,
+    json: bool,
+) -> Result<(), CliError> {
+    use televy_backup_core::chat_audit::{
+        ChatAuditIssueKind, audit_chat_documents, in_audit_sample,
+    };
+
+    let percent = sample_percent.unwrap_or(100.0);
+    if !(percent > 0.0 && percent <= 100.0) {
+        return Err(CliError::new(
+            ErrorCode::ConfigInvalid,
+            format!("--sample-percent must be in (0, 100]: got {percent}"),
+        ));
+    }
+
+    let settings = load_settings(config_dir)?;
+    let ep = select_endpoint(&settings, endpoint_id.as_deref())?;
+    let db_path = endpoint_index_db_path(data_dir, &ep.id);
+    let indexed = if db_path.exists() {
+        televy_backup_core::chat_remap::index_db_objects_in_chat(&db_path, &ep.chat_id)
+            .await
+            .map_err(map_core_err)?
+    } else {
+        Vec::new()
+    };
+
+    let storage = connect_endpoint_storage(config_dir, data_dir, &settings, ep).await?;
+    let scanned = scan_chat_documents(&storage, from_msg_id, max_msg_id);
+    let (documents, last_msg_id) = match scanned {
+        Ok(v) => v,
+        Err(e) => {
+            persist_mtproto_session(config_dir, data_dir, ep, &storage);
+            return Err(e);
+        }
+    };
+    let mut report = audit_chat_documents(&documents, &indexed, from_msg_id.max(1)..=last_msg_id);
+
+    if !no_download {
+        let mut seed = [0u8; 8];
+        getrandom::getrandom(&mut seed).map_err(|e| {
+            CliError::new(ErrorCode::ConfigInvalid, format!("getrandom failed: {e}"))
+        })?;
+        let seed = u64::from_le_bytes(seed);
+        for doc in &documents {
+            if doc.caption.is_none() || !in_audit_sample(doc.doc_id, percent, seed) {
+                continue;
+            }
+            let object_id = televy_backup_core::encode_tgmtproto_object_id_v1(
+                &ep.chat_id,
+                doc.msg_id,
+                doc.doc_id,
+                doc.access_hash,
+            )
+            .map_err(map_core_err)?;
+            let downloaded = storage.download_document(&object_id).await;
+            report.record_download(doc, downloaded);
+        }
+    }
+    persist_mtproto_session(config_dir, data_dir, ep, &storage);
+
+    for issue in &report.issues {
+        tracing::warn!(
+            event = "telegram.audit_chat.issue",
+            endpoint_id = %ep.id,
+            kind = issue.kind.as_str(),
+            msg_id = issue.msg_id,
+            object_kind = issue.object_kind.map(|k| k.as_str()),
+            message = %issue.message,
+            "telegram.audit_chat.issue"
+        );
+    }
+
+    if json {
+        let issues: Vec<serde_json::Value> = report
+            .issues
+            .iter()
+            .map(|i| {
+                serde_json::json!({
+                    "kind": i.kind.as_str(),
+                    "msgId": i.msg_id,
+                    "objectKind": i.object_kind.map(|k| k.as_str()),
+                    "message": i.message,
+                })
+            })
+            .collect();
+        println!(
+            "{}",
+            serde_json::json!({
+                "endpointId": ep.id,
+                "chatId": ep.chat_id,
+                "fromMsgId": from_msg_id.max(1),
+                "lastMsgId": last_msg_id,
+                "documentsScanned": report.documents_scanned,
+                "captioned": report.captioned,
+                "indexed": report.indexed,
+                "unrelated": report.unrelated,
+                "hashChecked": report.hash_checked,
+                "captionedByPrefix": report.captioned_by_prefix,
+                "issues": issues,
+            })
+        );
+    } else {
+        println!("documentsScanned={}", report.documents_scanned);
+        println!("captioned={}", report.captioned);
+        println!("indexed={}", report.indexed);
+        println!("unrelated={}", report.unrelated);
+        println!("hashChecked={}", report.hash_checked);
+        for (prefix, count) in &report.captioned_by_prefix {
+            println!("captionedByPrefix prefix={prefix} count={count}");
+        }
+        for kind in [
+            ChatAuditIssueKind::SizeMismatch,
+            ChatAuditIssueKind::HashMismatch,
+            ChatAuditIssueKind::DownloadFailed,
+            ChatAuditIssueKind::Extra,
+            ChatAuditIssueKind::Missing,
+        ] {
+            println!("{}={}", kind.as_str(), report.count(kind));
+        }
+        for issue in &report.issues {
+            println!(
+                "issue kind={} msgId={} {}",
+                issue.kind.as_str(),
+                issue.msg_id,
+                issue.message
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Interval of the `waiting_chat` heartbeats `telegram wait-chat --events` emits.
+const WAIT_CHAT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(2);
+
+#[allow(clippy::too_many_arguments)]
+async fn telegram_wait_chat(
+    config_dir: &Path,
+    data_dir: &Path,
+    endpoint_id: Option<String>,
+    timeout_secs: u32,
+    include_users: bool,
+    poll_interval_ms: u64,
+    json: bool,
+    events: bool,
+) -> Result<(), CliError> {
+    let settings = load_settings(config_dir)?;
+    let ep = select_endpoint(&settings, endpoint_id.as_deref())?;
+
+    if ep.mtproto_api_id(&settings.telegram.mtproto) <= 0 {
+        return Err(CliError::new(
+            ErrorCode::ConfigInvalid,
+            "telegram.mtproto.api_id must be > 0",
+        ));
+    }
+    if ep
+        .mtproto_api_hash_key(&settings.telegram.mtproto)
+        .is_empty()
+    {
+        return Err(CliError::new(
+            ErrorCode::ConfigInvalid,
+            "telegram.mtproto.api_hash_key must not be empty",
+        ));
+    }
+
+    let bot_token = get_secret(config_dir, data_dir, &ep.bot_token_key)?
+        .ok_or_else(|| CliError::new(ErrorCode::TelegramUnauthorized, "bot token missing"))?;
+    let api_hash = get_secret(
+        config_dir,
+        data_dir,
+        ep.mtproto_api_hash_key(&settings.telegram.mtproto),
+    )?
+    .ok_or_else(|| {
+        CliError::new(
+            ErrorCode::TelegramMtprotoMissingApiHash,
+            "mtproto api_hash missing",
+        )
+    })?;
+    let session = load_optional_base64_secret_bytes(
+        config_dir,
+        data_dir,
+        &ep.mtproto.session_key,
+        ErrorCode::TelegramMtprotoSessionInvalid,
+        "invalid mtproto session (try: `televybackup secrets clear-telegram-mtproto-session`)",
+    )?;
+
+    let cache_dir = data_dir.join("cache").join("mtproto");
+    std::fs::create_dir_all(&cache_dir)
+        .map_err(|e| CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()))?;
+
+    let provider = settings_config::endpoint_provider(&ep.id);
+    let storage = TelegramMtProtoStorage::connect(TelegramMtProtoStorageConfig {
+        provider,
+        api_id: ep.mtproto_api_id(&settings.telegram.mtproto),
+        api_hash: api_hash.clone(),
+        bot_token: bot_token.clone(),
+        // WaitChat does not require a selected chat.
+        chat_id: String::new(),
+        migrated_from_chat_ids: Vec::new(),
+        session,
+        cache_dir,
+        min_delay_ms: Some(ep.rate_limit.min_delay_ms as u64),
+        max_concurrent_uploads: Some(ep.rate_limit.max_concurrent_uploads as usize),
+        helper_path: None,
+        bootstrap_pin_mode: ep.bootstrap.pin_mode,
+        object_prefix: endpoint_object_prefix(data_dir, ep),
+    })
+    .await
+    .map_err(map_core_err)?;
+
+    let timeout = Duration::from_secs((timeout_secs as u64).clamp(1, 10 * 60));
+    let poll_interval = Duration::from_millis(poll_interval_ms.clamp(100, 10_000));
+    let task_id = format!("tsk_{}", uuid::Uuid::new_v4());
+    let cancel = cancel_on_stop_signal();
+    emit_task_state_running(events, &task_id, "wait_chat", None, None);
+
+    let started = std::time::Instant::now();
+    let mut last_heartbeat: Option<std::time::Instant> = None;
+    let mut first_poll = true;
+    let result = loop {
+        if cancel.is_cancelled() {
+            break Err(CliError::new(
+                ErrorCode::TaskCancelled,
+                "wait-chat cancelled",
+            ));
+        }
+        let elapsed = started.elapsed();
+        if elapsed >= timeout {
+            break Err(CliError::retryable(
+                ErrorCode::TelegramTimeout,
+                format!("wait_for_chat timed out after {}s", timeout.as_secs()),
+            ));
+        }
+        if events && last_heartbeat.is_none_or(|t| t.elapsed() >= WAIT_CHAT_HEARTBEAT_INTERVAL) {
+            emit_event_stdout(serde_json::json!({
+                "type": "task.progress",
+                "taskId": task_id,
+                "phase": Phase::WaitingChat,
+                "elapsedSeconds": elapsed.as_secs(),
+            }));
+            last_heartbeat = Some(std::time::Instant::now());
+        }
+        let wait = poll_interval
+            .min(timeout - elapsed)
+            .min(WAIT_CHAT_HEARTBEAT_INTERVAL);
+        match storage.check_for_new_chat(wait, include_users, first_poll) {
+            Ok(Some(chat)) => break Ok(chat),
+            Ok(None) => first_poll = false,
+            Err(e) => break Err(map_core_err(e)),
+        }
+    };
+
     if let Some(bytes) = storage.session_bytes() {
         let b64 = base64::engine::general_purpose::STANDARD.encode(bytes);
         if let Err(e) = set_secret(config_dir, data_dir, &ep.mtproto.session_key, &b64) {
             tracing::warn!(
                 event = "secrets.session_persist_failed",
-                error_code = e.code,
+                error_code = e.code.as_str(),
                 error_message = %e.message,
                 "failed to persist mtproto session"
             );
         }
     }
+    // Stops the helper before the terminal event, so a cancelled wait leaves nothing running.
+    drop(storage);
+
+    let chat = match result {
+        Ok(chat) => chat,
+        Err(e) => {
+            emit_task_state_error(events, &task_id, "wait_chat", None, None, &e);
+            return Err(e);
+        }
+    };
+
+    if events {
+        emit_event_stdout(serde_json::json!({
+            "type": "task.state",
+            "taskId": task_id,
+            "kind": "wait_chat",
+            "state": "succeeded",
+            "result": {
+                "chat": {
+                    "kind": chat.kind,
+                    "title": chat.title,
+                    "username": chat.username,
+                    "peerId": chat.peer_id,
+                    "configChatId": chat.config_chat_id,
+                    "bootstrapHint": chat.bootstrap_hint,
+                }
+            }
+        }));
+        return Ok(());
+    }
 
     if json {
         println!(
@@ -3545,7 +6840,7 @@ fn load_optional_base64_secret_bytes(
     config_dir: &Path,
     data_dir: &Path,
     key: &str,
-    error_code: &'static str,
+    error_code: ErrorCode,
     error_message: &str,
 ) -> Result<Option<Vec<u8>>, CliError> {
     let Some(b64) = get_secret(config_dir, data_dir, key)? else {
@@ -3562,25 +6857,29 @@ fn map_mtproto_validate_err(
     bot_token: &str,
     api_hash: &str,
 ) -> CliError {
+    if let televy_backup_core::Error::ChatMigrated { .. } = e {
+        return map_core_err(e);
+    }
+
     let msg = redact_secret(redact_secret(e.to_string(), bot_token), api_hash);
     let msg_lc = msg.to_ascii_lowercase();
 
     if msg_lc.contains("invalid session base64") || msg_lc.contains("session load failed") {
-        return CliError::new("telegram.mtproto.session_invalid", msg);
+        return CliError::new(ErrorCode::TelegramMtprotoSessionInvalid, msg);
     }
     if msg_lc.contains("bot_sign_in failed") {
-        return CliError::new("telegram.unauthorized", msg);
+        return CliError::new(ErrorCode::TelegramUnauthorized, msg);
     }
     if msg_lc.contains("chat not found") || msg_lc.contains("resolve chat failed") {
-        return CliError::new("telegram.chat_not_found", msg);
+        return CliError::new(ErrorCode::TelegramChatNotFound, msg);
     }
     if msg_lc.contains("message not found") || msg_lc.contains("document mismatch") {
-        return CliError::new("telegram.roundtrip_failed", msg);
+        return CliError::new(ErrorCode::TelegramRoundtripFailed, msg);
     }
 
     match e {
         televy_backup_core::Error::Telegram { .. } => {
-            CliError::retryable("telegram.unavailable", msg)
+            CliError::retryable(ErrorCode::TelegramUnavailable, msg)
         }
         televy_backup_core::Error::InvalidConfig { .. } => {
             if msg_lc.contains("failed to start mtproto helper")
@@ -3588,197 +6887,255 @@ fn map_mtproto_validate_err(
                 || msg_lc.contains("mtproto helper missing stdout")
                 || msg_lc.contains("cache dir create failed")
             {
-                CliError::new("config.invalid", msg)
+                CliError::new(ErrorCode::ConfigInvalid, msg)
             } else {
-                CliError::retryable("telegram.unavailable", msg)
+                CliError::retryable(ErrorCode::TelegramUnavailable, msg)
             }
         }
         televy_backup_core::Error::Integrity { .. } => {
-            CliError::new("telegram.roundtrip_failed", msg)
+            CliError::new(ErrorCode::TelegramRoundtripFailed, msg)
         }
-        _ => CliError::new("telegram.roundtrip_failed", msg),
+        _ => CliError::new(ErrorCode::TelegramRoundtripFailed, msg),
     }
 }
 
+/// Points the endpoint at the supergroup `connect` followed after a group upgrade. When the
+/// settings cannot be written, fails with `telegram.chat_migrated` carrying the new id.
+fn save_mtproto_chat_migration(
+    config_dir: &Path,
+    endpoint_id: &str,
+    storage: &TelegramMtProtoStorage,
+) -> Result<(), CliError> {
+    let Some(old_chat_id) = storage.migrated_from_chat_id() else {
+        return Ok(());
+    };
+    let new_chat_id = storage.chat_id();
+    settings_config::record_chat_migration(config_dir, endpoint_id, old_chat_id, new_chat_id)
+        .map_err(|e| {
+            CliError::new(
+                ErrorCode::TelegramChatMigrated,
+                format!(
+                    "telegram group was upgraded to a supergroup but the new chat_id could not be saved: {e}"
+                ),
+            )
+            .with_details(serde_json::json!({
+                "endpointId": endpoint_id,
+                "oldChatId": old_chat_id,
+                "newChatId": new_chat_id,
+            }))
+        })?;
+    tracing::info!(
+        event = "config.chat_id_updated",
+        endpoint_id,
+        old_chat_id,
+        new_chat_id,
+        "config.chat_id_updated"
+    );
+    Ok(())
+}
+
 fn list_index_db_paths_for_read(data_dir: &Path) -> Result<Vec<PathBuf>, CliError> {
-    let index_dir = data_dir.join("index");
-    let mut dbs = Vec::<PathBuf>::new();
+    televy_backup_core::index_db::list_index_db_paths(data_dir)
+        .map_err(|e| CliError::new(ErrorCode::DbFailed, e.to_string()))
+}
 
-    match std::fs::read_dir(&index_dir) {
-        Ok(entries) => {
-            for entry in entries.flatten() {
-                let path = entry.path();
-                if !path.is_file() {
-                    continue;
-                }
+struct SnapshotsListFilter {
+    source_path: Option<PathBuf>,
+    target_id: Option<String>,
+    since: Option<String>,
+    until: Option<String>,
+    asc: bool,
+    paginate: bool,
+    cursor: Option<String>,
+}
 
-                let Some(name) = path.file_name().and_then(|s| s.to_str()) else {
-                    continue;
-                };
+/// Normalizes a `--since/--until` bound to the `snapshots.created_at` format (UTC, millis) so the
+/// SQL comparison stays a plain string comparison.
+fn snapshot_time_bound(flag: &str, raw: &str) -> Result<String, CliError> {
+    televy_backup_core::index_db::snapshot_created_at_bound(raw).ok_or_else(|| {
+        CliError::new(
+            ErrorCode::ConfigInvalid,
+            format!(
+                "{flag} must be YYYY-MM-DD or RFC3339 (got {:?})",
+                raw.trim()
+            ),
+        )
+    })
+}
 
-                // Legacy global DB is a fallback only; prefer per-endpoint DBs when present.
-                if name == "index.sqlite" {
-                    continue;
-                }
-                if !name.starts_with("index.") || !name.ends_with(".sqlite") {
-                    continue;
-                }
+async fn snapshots_list(
+    config_dir: &Path,
+    data_dir: &Path,
+    limit: u32,
+    filter: SnapshotsListFilter,
+    json: bool,
+) -> Result<(), CliError> {
+    let cursor = filter
+        .cursor
+        .as_deref()
+        .map(televy_backup_core::index_db::SnapshotCursor::decode)
+        .transpose()
+        .map_err(|e| CliError::new(ErrorCode::CliInvalid, e.to_string()))?;
+    let since = filter
+        .since
+        .as_deref()
+        .map(|s| snapshot_time_bound("--since", s))
+        .transpose()?;
+    let until = filter
+        .until
+        .as_deref()
+        .map(|s| snapshot_time_bound("--until", s))
+        .transpose()?;
 
-                dbs.push(path);
-            }
-        }
-        Err(e) => {
-            if e.kind() != std::io::ErrorKind::NotFound {
-                return Err(CliError::new("db.failed", e.to_string()));
+    let mut source_path = filter
+        .source_path
+        .as_deref()
+        .map(|p| {
+            p.to_str().map(|s| s.to_string()).ok_or_else(|| {
+                CliError::new(ErrorCode::ConfigInvalid, "source path is not valid utf-8")
+            })
+        })
+        .transpose()?;
+
+    // Snapshots don't record a target id; a target is its endpoint DB + source path.
+    let db_paths = match filter.target_id.as_deref() {
+        Some(target_id) => {
+            let settings = load_settings(config_dir)?;
+            let target = select_target(&settings, Some(target_id), None)?;
+            if source_path
+                .as_deref()
+                .is_some_and(|p| p != target.source_path)
+            {
+                print_snapshot_summaries(&[], filter.paginate.then_some(None), json);
+                return Ok(());
             }
+            source_path = Some(target.source_path.clone());
+            let db = endpoint_index_db_path(data_dir, &target.endpoint_id);
+            if db.exists() { vec![db] } else { Vec::new() }
         }
+        None => list_index_db_paths_for_read(data_dir)?,
+    };
+    if db_paths.is_empty() {
+        print_snapshot_summaries(&[], filter.paginate.then_some(None), json);
+        return Ok(());
     }
 
-    dbs.sort();
-    if dbs.is_empty() {
-        let legacy = legacy_global_index_db_path(data_dir);
-        if legacy.exists() {
-            dbs.push(legacy);
-        }
+    let query = televy_backup_core::index_db::SnapshotQuery {
+        source_path,
+        since,
+        until,
+        asc: filter.asc,
+        limit,
+    };
+    if filter.paginate {
+        let page =
+            televy_backup_core::index_db::list_snapshot_page(&db_paths, &query, cursor.as_ref())
+                .await
+                .map_err(map_core_err)?;
+        print_snapshot_summaries(&page.snapshots, Some(page.next_cursor), json);
+        return Ok(());
     }
-
-    Ok(dbs)
+    let items = televy_backup_core::index_db::list_snapshots(&db_paths, &query)
+        .await
+        .map_err(map_core_err)?;
+    print_snapshot_summaries(&items, None, json);
+    Ok(())
 }
 
-async fn snapshots_list(data_dir: &Path, limit: u32, json: bool) -> Result<(), CliError> {
-    let db_paths = list_index_db_paths_for_read(data_dir)?;
-    if db_paths.is_empty() {
-        if json {
-            println!("{}", serde_json::json!({ "snapshots": [] }));
-        }
-        return Ok(());
-    }
-
-    #[derive(Debug)]
-    struct SnapshotListItem {
-        snapshot_id: String,
-        created_at: String,
-        source_path: String,
-        label: String,
-        base_snapshot_id: Option<String>,
-    }
-
-    // Query each DB for its newest snapshots, then merge and keep the global top N.
-    // This matches the legacy "single global DB" behavior while the index is now per-endpoint.
-    let mut items: Vec<SnapshotListItem> = Vec::new();
-    for db_path in db_paths {
-        let pool = televy_backup_core::index_db::open_existing_index_db(&db_path)
-            .await
-            .map_err(map_core_err)?;
-
-        let rows = sqlx::query(
-            r#"
-            SELECT snapshot_id, created_at, source_path, label, base_snapshot_id
-            FROM snapshots
-            ORDER BY created_at DESC
-            LIMIT ?
-            "#,
-        )
-        .bind(limit as i64)
-        .fetch_all(&pool)
-        .await
-        .map_err(|e| CliError::new("db.failed", e.to_string()))?;
-
-        for row in rows {
-            items.push(SnapshotListItem {
-                snapshot_id: row.get::<String, _>("snapshot_id"),
-                created_at: row.get::<String, _>("created_at"),
-                source_path: row.get::<String, _>("source_path"),
-                label: row.get::<String, _>("label"),
-                base_snapshot_id: row.get::<Option<String>, _>("base_snapshot_id"),
-            });
-        }
-    }
-
-    items.sort_by(|a, b| b.created_at.cmp(&a.created_at));
-    items.truncate(limit as usize);
-
-    let out = items
-        .into_iter()
-        .map(|i| {
-            serde_json::json!({
-                "snapshotId": i.snapshot_id,
-                "createdAt": i.created_at,
-                "sourcePath": i.source_path,
-                "label": i.label,
-                "baseSnapshotId": i.base_snapshot_id,
-            })
-        })
-        .collect::<Vec<_>>();
+fn snapshots_list_remote(
+    remote: &RemoteArgs,
+    params: televy_backup_core::remote::RemoteSnapshotsListParams,
+    json: bool,
+) -> Result<(), CliError> {
+    let params = serde_json::to_value(params)
+        .map_err(|e| CliError::new(ErrorCode::CliInvalid, e.to_string()))?;
+    let paged = params["paginate"] == true || !params["cursor"].is_null();
+    let mut result = remote_call(remote, "snapshots.list", params)?;
+    let items: Vec<televy_backup_core::index_db::SnapshotSummary> =
+        serde_json::from_value(result["snapshots"].take()).map_err(|e| {
+            CliError::new(ErrorCode::ControlFailed, format!("invalid response: {e}"))
+        })?;
+    let next_cursor = paged.then(|| result["nextCursor"].as_str().map(str::to_string));
+    print_snapshot_summaries(&items, next_cursor, json);
+    Ok(())
+}
 
+/// `next_cursor` is `Some` for a page (`--paginate`), holding the cursor of the next one if any.
+fn print_snapshot_summaries(
+    items: &[televy_backup_core::index_db::SnapshotSummary],
+    next_cursor: Option<Option<String>>,
+    json: bool,
+) {
     if json {
-        println!("{}", serde_json::json!({ "snapshots": out }));
+        match next_cursor {
+            Some(next) => println!(
+                "{}",
+                serde_json::json!({ "snapshots": items, "nextCursor": next })
+            ),
+            None => println!("{}", serde_json::json!({ "snapshots": items })),
+        }
+    } else if format::raw() {
+        for s in items {
+            println!("{}", serde_json::json!(s));
+        }
+        if let Some(Some(next)) = next_cursor {
+            println!("nextCursor={next}");
+        }
     } else {
-        for s in out {
-            println!("{}", s);
+        for s in items {
+            let mut line = format!(
+                "snapshotId={} createdAt={} source={}",
+                s.snapshot_id,
+                format::timestamp(&s.created_at),
+                s.source_path
+            );
+            if let Some(name) = &s.device_name {
+                line.push_str(&format!(" device={name}"));
+            }
+            if s.pinned {
+                line.push_str(" pinned");
+            }
+            println!("{line}");
+        }
+        if let Some(Some(next)) = next_cursor {
+            println!("nextCursor={next}");
         }
     }
-    Ok(())
 }
 
 async fn stats_get(data_dir: &Path, json: bool) -> Result<(), CliError> {
     let db_paths = list_index_db_paths_for_read(data_dir)?;
-    if db_paths.is_empty() {
-        if json {
-            println!(
-                "{}",
-                serde_json::json!({ "snapshotsTotal": 0, "chunksTotal": 0, "chunksBytesTotal": 0 })
-            );
-        }
-        return Ok(());
-    }
-
-    let mut snapshots_total: i64 = 0;
-    let mut chunks_total: i64 = 0;
-    let mut chunks_bytes_total: i64 = 0;
-    for db_path in db_paths {
-        let pool = televy_backup_core::index_db::open_existing_index_db(&db_path)
-            .await
-            .map_err(map_core_err)?;
+    let stats = televy_backup_core::index_db::index_stats(&db_paths)
+        .await
+        .map_err(map_core_err)?;
+    print_index_stats(&stats, json);
+    Ok(())
+}
 
-        snapshots_total = snapshots_total.saturating_add(
-            sqlx::query("SELECT COUNT(1) as c FROM snapshots")
-                .fetch_one(&pool)
-                .await
-                .map_err(|e| CliError::new("db.failed", e.to_string()))?
-                .get::<i64, _>("c"),
-        );
-        chunks_total = chunks_total.saturating_add(
-            sqlx::query("SELECT COUNT(1) as c FROM chunks")
-                .fetch_one(&pool)
-                .await
-                .map_err(|e| CliError::new("db.failed", e.to_string()))?
-                .get::<i64, _>("c"),
-        );
-        chunks_bytes_total = chunks_bytes_total.saturating_add(
-            sqlx::query("SELECT COALESCE(SUM(size), 0) as s FROM chunks")
-                .fetch_one(&pool)
-                .await
-                .map_err(|e| CliError::new("db.failed", e.to_string()))?
-                .get::<i64, _>("s"),
-        );
-    }
+fn stats_get_remote(remote: &RemoteArgs, json: bool) -> Result<(), CliError> {
+    let result = remote_call(remote, "stats.get", serde_json::json!({}))?;
+    let stats = serde_json::from_value(result)
+        .map_err(|e| CliError::new(ErrorCode::ControlFailed, format!("invalid response: {e}")))?;
+    print_index_stats(&stats, json);
+    Ok(())
+}
 
+fn print_index_stats(stats: &televy_backup_core::index_db::IndexStats, json: bool) {
     if json {
+        println!("{}", serde_json::json!(stats));
+    } else {
+        println!("snapshotsTotal={}", stats.snapshots_total);
+        println!("chunksTotal={}", stats.chunks_total);
         println!(
-            "{}",
-            serde_json::json!({
-                "snapshotsTotal": snapshots_total,
-                "chunksTotal": chunks_total,
-                "chunksBytesTotal": chunks_bytes_total
-            })
+            "chunksBytesTotal={}",
+            format::bytes_i64(stats.chunks_bytes_total)
         );
-    } else {
-        println!("snapshotsTotal={snapshots_total}");
-        println!("chunksTotal={chunks_total}");
-        println!("chunksBytesTotal={chunks_bytes_total}");
+        if let (Some(n), Some(bytes)) = (stats.chunks_unreferenced, stats.chunks_unreferenced_bytes)
+        {
+            println!("chunksUnreferenced={n}");
+            println!("chunksUnreferencedBytes={}", format::bytes_i64(bytes));
+        }
     }
-    Ok(())
 }
 
 async fn stats_last(data_dir: &Path, source: Option<PathBuf>, json: bool) -> Result<(), CliError> {
@@ -3797,7 +7154,9 @@ async fn stats_last(data_dir: &Path, source: Option<PathBuf>, json: bool) -> Res
         .as_ref()
         .map(|p| {
             p.to_str()
-                .ok_or_else(|| CliError::new("config.invalid", "source path is not valid utf-8"))
+                .ok_or_else(|| {
+                    CliError::new(ErrorCode::ConfigInvalid, "source path is not valid utf-8")
+                })
                 .map(|s| s.to_string())
         })
         .transpose()?;
@@ -3808,6 +7167,8 @@ async fn stats_last(data_dir: &Path, source: Option<PathBuf>, json: bool) -> Res
         snapshot_id: String,
         created_at: String,
         base_snapshot_id: Option<String>,
+        device_id: Option<String>,
+        device_name: Option<String>,
     }
 
     let mut best: Option<LastSnapshot> = None;
@@ -3816,32 +7177,33 @@ async fn stats_last(data_dir: &Path, source: Option<PathBuf>, json: bool) -> Res
             .await
             .map_err(map_core_err)?;
 
+        let device_columns = snapshot_device_columns_sql(&pool).await?;
         let snapshot_row: Option<sqlx::sqlite::SqliteRow> = if let Some(source) = &source_str {
-            sqlx::query(
+            sqlx::query(&format!(
                 r#"
-                SELECT snapshot_id, created_at, base_snapshot_id
+                SELECT snapshot_id, created_at, base_snapshot_id, {device_columns}
                 FROM snapshots
                 WHERE source_path = ?
                 ORDER BY created_at DESC
                 LIMIT 1
                 "#,
-            )
+            ))
             .bind(source)
             .fetch_optional(&pool)
             .await
-            .map_err(|e| CliError::new("db.failed", e.to_string()))?
+            .map_err(|e| CliError::new(ErrorCode::DbFailed, e.to_string()))?
         } else {
-            sqlx::query(
+            sqlx::query(&format!(
                 r#"
-                SELECT snapshot_id, created_at, base_snapshot_id
+                SELECT snapshot_id, created_at, base_snapshot_id, {device_columns}
                 FROM snapshots
                 ORDER BY created_at DESC
                 LIMIT 1
                 "#,
-            )
+            ))
             .fetch_optional(&pool)
             .await
-            .map_err(|e| CliError::new("db.failed", e.to_string()))?
+            .map_err(|e| CliError::new(ErrorCode::DbFailed, e.to_string()))?
         };
 
         let Some(row) = snapshot_row else {
@@ -3850,19 +7212,27 @@ async fn stats_last(data_dir: &Path, source: Option<PathBuf>, json: bool) -> Res
 
         let snapshot_id: String = row
             .try_get("snapshot_id")
-            .map_err(|e| CliError::new("db.failed", e.to_string()))?;
+            .map_err(|e| CliError::new(ErrorCode::DbFailed, e.to_string()))?;
         let created_at: String = row
             .try_get("created_at")
-            .map_err(|e| CliError::new("db.failed", e.to_string()))?;
+            .map_err(|e| CliError::new(ErrorCode::DbFailed, e.to_string()))?;
         let base_snapshot_id: Option<String> = row
             .try_get("base_snapshot_id")
-            .map_err(|e| CliError::new("db.failed", e.to_string()))?;
+            .map_err(|e| CliError::new(ErrorCode::DbFailed, e.to_string()))?;
+        let device_id: Option<String> = row
+            .try_get("device_id")
+            .map_err(|e| CliError::new(ErrorCode::DbFailed, e.to_string()))?;
+        let device_name: Option<String> = row
+            .try_get("device_name")
+            .map_err(|e| CliError::new(ErrorCode::DbFailed, e.to_string()))?;
 
         let candidate = LastSnapshot {
             db_path: db_path.clone(),
             snapshot_id,
             created_at,
             base_snapshot_id,
+            device_id,
+            device_name,
         };
 
         let replace = match best.as_ref() {
@@ -3887,6 +7257,8 @@ async fn stats_last(data_dir: &Path, source: Option<PathBuf>, json: bool) -> Res
     let snapshot_id = best.snapshot_id;
     let created_at = best.created_at;
     let base_snapshot_id = best.base_snapshot_id;
+    let device_id = best.device_id;
+    let device_name = best.device_name;
 
     let pool = televy_backup_core::index_db::open_existing_index_db(&best.db_path)
         .await
@@ -3907,7 +7279,7 @@ async fn stats_last(data_dir: &Path, source: Option<PathBuf>, json: bool) -> Res
     .bind(&snapshot_id)
     .fetch_one(&pool)
     .await
-    .map_err(|e| CliError::new("db.failed", e.to_string()))?
+    .map_err(|e| CliError::new(ErrorCode::DbFailed, e.to_string()))?
     .get("s");
 
     let bytes_new: i64 = if let Some(base_id) = &base_snapshot_id {
@@ -3940,7 +7312,7 @@ async fn stats_last(data_dir: &Path, source: Option<PathBuf>, json: bool) -> Res
         .bind(base_id)
         .fetch_one(&pool)
         .await
-        .map_err(|e| CliError::new("db.failed", e.to_string()))?
+        .map_err(|e| CliError::new(ErrorCode::DbFailed, e.to_string()))?
         .get("s")
     } else {
         cur_bytes_unique
@@ -3960,7 +7332,7 @@ async fn stats_last(data_dir: &Path, source: Option<PathBuf>, json: bool) -> Res
     .bind(&snapshot_id)
     .fetch_optional(&pool)
     .await
-    .map_err(|e| CliError::new("db.failed", e.to_string()))?
+    .map_err(|e| CliError::new(ErrorCode::DbFailed, e.to_string()))?
     .map(|r| r.get::<f64, _>("seconds"));
 
     if json {
@@ -3971,6 +7343,8 @@ async fn stats_last(data_dir: &Path, source: Option<PathBuf>, json: bool) -> Res
                     "snapshotId": snapshot_id,
                     "createdAt": created_at,
                     "baseSnapshotId": base_snapshot_id,
+                    "deviceId": device_id,
+                    "deviceName": device_name,
                     "bytesUploaded": bytes_new,
                     "bytesDeduped": bytes_reused,
                     "durationSeconds": duration_seconds,
@@ -3980,164 +7354,2577 @@ async fn stats_last(data_dir: &Path, source: Option<PathBuf>, json: bool) -> Res
         );
     } else {
         println!("snapshotId={snapshot_id}");
-        println!("createdAt={created_at}");
-        println!("bytesUploaded={bytes_new}");
-        println!("bytesDeduped={bytes_reused}");
+        println!("createdAt={}", format::timestamp(&created_at));
+        if let Some(name) = &device_name {
+            println!("deviceName={name}");
+        }
+        println!("bytesUploaded={}", format::bytes_i64(bytes_new));
+        println!("bytesDeduped={}", format::bytes_i64(bytes_reused));
         if let Some(s) = duration_seconds {
-            println!("durationSeconds={s}");
+            println!("durationSeconds={}", format::duration(s));
         }
     }
 
     Ok(())
 }
 
-#[allow(clippy::too_many_arguments)]
-async fn backup_run(
+/// Records a finished run in `usage.sqlite` when settings ask for it; never fails the run.
+async fn record_usage(config_dir: &Path, data_dir: &Path, run: UsageRun) {
+    let enabled = load_settings(config_dir).is_ok_and(|s| s.records_usage());
+    if enabled {
+        televy_backup_core::usage::record_usage_run_best_effort(data_dir, &run).await;
+    }
+}
+
+/// Merges a finished backup or verify run into the Prometheus textfile from `--metrics-textfile`
+/// or `metrics.textfile_path`; never fails the run. A run without a target (`verify run`) is
+/// attributed to the target whose source `snapshot_id` belongs to, if any.
+async fn write_metrics_textfile(
     config_dir: &Path,
     data_dir: &Path,
-    target_id: Option<String>,
-    source: Option<PathBuf>,
-    label: String,
-    no_remote_index_sync: bool,
-    json: bool,
-    events: bool,
-) -> Result<(), CliError> {
-    let task_id = format!("tsk_{}", uuid::Uuid::new_v4());
-    let run_log = televy_backup_core::run_log::start_run_log("backup", &task_id, data_dir)
-        .map_err(|e| CliError::new("log.init_failed", e.to_string()))?;
-
-    let started = std::time::Instant::now();
-
-    let settings = match load_settings(config_dir) {
-        Ok(s) => s,
-        Err(e) => {
-            return emit_preflight_failed(
-                events,
-                &task_id,
-                "backup",
-                run_log.path(),
-                started,
-                RunCtx {
-                    target_id: None,
-                    endpoint_id: None,
-                    source_path: None,
-                    snapshot_id: None,
-                },
-                e,
-            );
-        }
+    flag: Option<&Path>,
+    run: &UsageRun,
+    snapshot_id: Option<&str>,
+) {
+    let settings = load_settings(config_dir).ok();
+    let Some(path) = flag.map(Path::to_path_buf).or_else(|| {
+        settings
+            .as_ref()
+            .and_then(|s| s.metrics.textfile_path.as_deref())
+            .map(PathBuf::from)
+    }) else {
+        return;
     };
 
-    let target = match select_target(&settings, target_id.as_deref(), source.as_deref()) {
-        Ok(t) => t,
-        Err(e) => {
-            return emit_preflight_failed(
-                events,
-                &task_id,
-                "backup",
-                run_log.path(),
-                started,
-                RunCtx {
-                    target_id: None,
-                    endpoint_id: None,
-                    source_path: None,
-                    snapshot_id: None,
-                },
-                e,
-            );
-        }
+    let target = match (&settings, run.target_id.as_deref(), snapshot_id) {
+        (Some(s), Some(id), _) => s.targets.iter().find(|t| t.id == id),
+        (Some(s), None, Some(snapshot_id)) => snapshot_target(s, data_dir, snapshot_id).await,
+        _ => None,
     };
+    let snapshots_total = match target {
+        Some(t) => target_snapshot_count(data_dir, t).await,
+        None => None,
+    };
+    let metrics = televy_backup_core::metrics_textfile::RunMetrics {
+        kind: run.kind.clone(),
+        target_id: target
+            .map(|t| t.id.clone())
+            .or_else(|| run.target_id.clone())
+            .unwrap_or_default(),
+        finished_at_unix: chrono::DateTime::parse_from_rfc3339(&run.finished_at)
+            .map_or_else(|_| chrono::Utc::now().timestamp(), |t| t.timestamp()),
+        success: run.status == RunStatus::Succeeded.as_str(),
+        bytes_uploaded: if run.kind == "backup" { run.bytes } else { 0 },
+        duration_seconds: run.duration_seconds,
+        snapshots_total,
+    };
+    if let Err(e) = televy_backup_core::metrics_textfile::write_run_metrics(&path, &metrics) {
+        tracing::warn!(
+            event = "metrics.textfile_write_failed",
+            path = %path.display(),
+            error = %e,
+            "metrics.textfile_write_failed"
+        );
+    }
+}
 
-    let ep = match settings
-        .telegram_endpoints
-        .iter()
-        .find(|e| e.id == target.endpoint_id)
-    {
-        Some(ep) => ep,
-        None => {
-            let e = CliError::new(
-                "config.invalid",
-                format!(
-                    "target references unknown endpoint_id: target_id={} endpoint_id={}",
-                    target.id, target.endpoint_id
-                ),
-            );
-            return emit_preflight_failed(
-                events,
-                &task_id,
-                "backup",
-                run_log.path(),
-                started,
-                RunCtx {
-                    target_id: Some(target.id.as_str()),
-                    endpoint_id: None,
-                    source_path: Some(target.source_path.as_str()),
-                    snapshot_id: None,
-                },
-                e,
-            );
+/// The configured target whose source `snapshot_id` was taken of, per the local endpoint indexes.
+async fn snapshot_target<'a>(
+    settings: &'a settings_config::SettingsV2,
+    data_dir: &Path,
+    snapshot_id: &str,
+) -> Option<&'a settings_config::Target> {
+    for t in &settings.targets {
+        let endpoint_db = endpoint_index_db_path(data_dir, &t.endpoint_id);
+        if !endpoint_db.exists() {
+            continue;
         }
-    };
+        let Ok(pool) = televy_backup_core::index_db::open_existing_index_db(&endpoint_db).await
+        else {
+            continue;
+        };
+        let found =
+            sqlx::query("SELECT 1 FROM snapshots WHERE snapshot_id = ? AND source_path = ?")
+                .bind(snapshot_id)
+                .bind(&t.source_path)
+                .fetch_optional(&pool)
+                .await;
+        pool.close().await;
+        if matches!(found, Ok(Some(_))) {
+            return Some(t);
+        }
+    }
+    None
+}
 
-    if settings.telegram.mtproto.api_id <= 0 {
-        let e = CliError::new("config.invalid", "telegram.mtproto.api_id must be > 0");
-        return emit_preflight_failed(
-            events,
-            &task_id,
-            "backup",
-            run_log.path(),
-            started,
-            RunCtx {
-                target_id: Some(target.id.as_str()),
-                endpoint_id: Some(ep.id.as_str()),
-                source_path: Some(target.source_path.as_str()),
-                snapshot_id: None,
-            },
-            e,
-        );
+/// Snapshots of `target`'s source in its endpoint's local index; `None` when unreadable.
+async fn target_snapshot_count(data_dir: &Path, target: &settings_config::Target) -> Option<u64> {
+    let endpoint_db = endpoint_index_db_path(data_dir, &target.endpoint_id);
+    if !endpoint_db.exists() {
+        return Some(0);
     }
-    if settings.telegram.mtproto.api_hash_key.is_empty() {
-        let e = CliError::new(
-            "config.invalid",
-            "telegram.mtproto.api_hash_key must not be empty",
-        );
-        return emit_preflight_failed(
-            events,
-            &task_id,
-            "backup",
-            run_log.path(),
-            started,
-            RunCtx {
-                target_id: Some(target.id.as_str()),
-                endpoint_id: Some(ep.id.as_str()),
-                source_path: Some(target.source_path.as_str()),
-                snapshot_id: None,
-            },
-            e,
-        );
+    let pool = televy_backup_core::index_db::open_existing_index_db(&endpoint_db)
+        .await
+        .ok()?;
+    let count: Option<i64> =
+        sqlx::query_scalar("SELECT COUNT(1) FROM snapshots WHERE source_path = ?")
+            .bind(&target.source_path)
+            .fetch_one(&pool)
+            .await
+            .ok();
+    pool.close().await;
+    count.map(|n| n.max(0) as u64)
+}
+
+/// Cutoff for `stats prune --older-than` (`<n>d`, `<n>w`, `<n>m` or `<n>y`).
+fn usage_prune_cutoff(
+    older_than: &str,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Result<chrono::DateTime<chrono::Utc>, CliError> {
+    let raw = older_than.trim();
+    let invalid = || {
+        CliError::new(
+            ErrorCode::ConfigInvalid,
+            format!("--older-than must look like 90d, 8w, 6m or 2y (got {raw:?})"),
+        )
+    };
+    let split = raw
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let (digits, unit) = raw.split_at(split);
+    let n: u32 = digits.parse().map_err(|_| invalid())?;
+    let cutoff = match unit {
+        "d" => now.checked_sub_signed(chrono::Duration::days(n.into())),
+        "w" => now.checked_sub_signed(chrono::Duration::weeks(n.into())),
+        "m" => now.checked_sub_months(chrono::Months::new(n)),
+        "y" => n
+            .checked_mul(12)
+            .and_then(|months| now.checked_sub_months(chrono::Months::new(months))),
+        _ => return Err(invalid()),
+    };
+    cutoff.ok_or_else(invalid)
+}
+
+async fn stats_monthly(
+    data_dir: &Path,
+    target_id: Option<&str>,
+    json: bool,
+) -> Result<(), CliError> {
+    let months = televy_backup_core::usage::usage_monthly(data_dir, target_id)
+        .await
+        .map_err(map_core_err)?;
+    if json {
+        println!("{}", serde_json::json!({ "months": months }));
+        return Ok(());
     }
-    if ep.chat_id.is_empty() {
-        let e = CliError::new(
-            "config.invalid",
-            format!("telegram_endpoints[{id}].chat_id is empty", id = ep.id),
-        );
-        return emit_preflight_failed(
-            events,
-            &task_id,
-            "backup",
-            run_log.path(),
-            started,
-            RunCtx {
-                target_id: Some(target.id.as_str()),
-                endpoint_id: Some(ep.id.as_str()),
-                source_path: Some(target.source_path.as_str()),
-                snapshot_id: None,
-            },
-            e,
+    for m in months {
+        println!(
+            "month={} kind={} runs={} runsFailed={} runsCancelled={} bytes={} durationSecondsAvg={:.1}",
+            m.month,
+            m.kind,
+            m.runs,
+            m.runs_failed,
+            m.runs_cancelled,
+            m.bytes,
+            m.duration_seconds_avg
         );
     }
+    Ok(())
+}
 
-    let ctx_target_id = target.id.clone();
+async fn stats_prune(data_dir: &Path, older_than: &str, json: bool) -> Result<(), CliError> {
+    let cutoff = usage_prune_cutoff(older_than, chrono::Utc::now())?
+        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    let removed = televy_backup_core::usage::prune_usage_runs(data_dir, &cutoff)
+        .await
+        .map_err(map_core_err)?;
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({ "removed": removed, "before": cutoff })
+        );
+    } else {
+        println!("removed={removed} before={cutoff}");
+    }
+    Ok(())
+}
+
+async fn stats_top_files(
+    data_dir: &Path,
+    snapshot_id: &str,
+    limit: u32,
+    json: bool,
+) -> Result<(), CliError> {
+    let db_path = find_snapshot_index_db(data_dir, snapshot_id).await?;
+    let filemap_dir = index_db_filemap_dir(data_dir, &db_path);
+    let filemap_db_path = filemap_dir.join(format!("{snapshot_id}.sqlite"));
+    if !filemap_db_path.exists() {
+        return Err(snapshot_not_found(
+            snapshot_id,
+            format!(
+                "no local file map for snapshot {snapshot_id} (`televybackup index sync --target-id <target> --snapshot-id {snapshot_id}` downloads it): {}",
+                filemap_db_path.display()
+            ),
+        ));
+    }
+    let top =
+        televy_backup_core::dedup_stats::top_files(&db_path, &filemap_dir, snapshot_id, limit)
+            .await
+            .map_err(map_core_err)?;
+    if json {
+        println!("{}", serde_json::to_value(&top).unwrap_or_default());
+        return Ok(());
+    }
+    println!("{:>10}  {:>10}  {:>10}  PATH", "UNIQUE", "SHARED", "SIZE");
+    for f in &top.files {
+        println!(
+            "{:>10}  {:>10}  {:>10}  {}",
+            format::bytes(f.unique_bytes),
+            format::bytes(f.shared_bytes),
+            format::bytes(f.size),
+            f.path
+        );
+    }
+    if top.snapshots_unscanned > 0 {
+        eprintln!(
+            "warning: {} snapshot(s) have no local file map; chunks they share may show as unique",
+            top.snapshots_unscanned
+        );
+    }
+    Ok(())
+}
+
+/// `device_id, device_name` select list; NULLs for index DBs that predate the columns.
+/// Index DB holding `snapshot_id` (snapshot IDs are unique across endpoints).
+async fn find_snapshot_index_db(data_dir: &Path, snapshot_id: &str) -> Result<PathBuf, CliError> {
+    for db_path in list_index_db_paths_for_read(data_dir)? {
+        let pool = televy_backup_core::index_db::open_existing_index_db(&db_path)
+            .await
+            .map_err(map_core_err)?;
+        let found: Option<i64> =
+            sqlx::query_scalar("SELECT 1 FROM snapshots WHERE snapshot_id = ? LIMIT 1")
+                .bind(snapshot_id)
+                .fetch_optional(&pool)
+                .await
+                .map_err(|e| CliError::new(ErrorCode::DbFailed, e.to_string()))?;
+        pool.close().await;
+        if found.is_some() {
+            return Ok(db_path);
+        }
+    }
+    Err(snapshot_not_found(
+        snapshot_id,
+        format!("snapshot not found: {snapshot_id}"),
+    ))
+}
+
+/// Key snapshot `snapshot_id` was written with, as recorded in the local index DBs; the master
+/// key when no local DB holds it.
+async fn local_snapshot_data_key(
+    data_dir: &Path,
+    master_key: &[u8; 32],
+    snapshot_id: &str,
+) -> Result<DataKey, CliError> {
+    for db_path in list_index_db_paths_for_read(data_dir)? {
+        if let Some(data_key) =
+            televy_backup_core::index_db::snapshot_data_key_at(&db_path, master_key, snapshot_id)
+                .await
+                .map_err(map_core_err)?
+        {
+            return Ok(data_key);
+        }
+    }
+    Ok(DataKey::master(master_key))
+}
+
+/// Per-snapshot file map DBs that belong to the endpoint index DB at `db_path`.
+fn index_db_filemap_dir(data_dir: &Path, db_path: &Path) -> PathBuf {
+    match televy_backup_core::index_db::endpoint_id_from_index_db_path(db_path) {
+        Some(endpoint_id) => endpoint_filemap_dir(data_dir, &endpoint_id),
+        None => data_dir.join("index").join("filemaps"),
+    }
+}
+
+async fn snapshots_set_pinned(
+    data_dir: &Path,
+    snapshot_id: &str,
+    pinned: bool,
+    json: bool,
+) -> Result<(), CliError> {
+    let db_path = find_snapshot_index_db(data_dir, snapshot_id).await?;
+    let found = televy_backup_core::set_snapshot_pinned(&db_path, snapshot_id, pinned)
+        .await
+        .map_err(map_core_err)?;
+    if !found {
+        return Err(snapshot_not_found(
+            snapshot_id,
+            format!("snapshot not found: {snapshot_id}"),
+        ));
+    }
+
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({ "snapshotId": snapshot_id, "pinned": pinned })
+        );
+    } else {
+        println!("snapshotId={snapshot_id}");
+        println!("pinned={pinned}");
+        eprintln!("note: other machines see the change after this endpoint's next backup");
+    }
+    Ok(())
+}
+
+async fn snapshots_files(data_dir: &Path, snapshot_id: &str, json: bool) -> Result<(), CliError> {
+    let db_path = find_snapshot_index_db(data_dir, snapshot_id).await?;
+    let filemap_dir = index_db_filemap_dir(data_dir, &db_path);
+    let filemap_db_path = filemap_dir.join(format!("{snapshot_id}.sqlite"));
+    if !filemap_db_path.exists() {
+        return Err(snapshot_not_found(
+            snapshot_id,
+            format!(
+                "no local file map for snapshot {snapshot_id} (only the machine that took it, or one that restored it, has one): {}",
+                filemap_db_path.display()
+            ),
+        ));
+    }
+
+    let pool = televy_backup_core::index_db::open_existing_index_db(&filemap_db_path)
+        .await
+        .map_err(map_core_err)?;
+    let rows = sqlx::query(
+        "SELECT path, kind, size, mtime_ms FROM files WHERE snapshot_id = ? ORDER BY path",
+    )
+    .bind(snapshot_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| CliError::new(ErrorCode::DbFailed, e.to_string()))?;
+    pool.close().await;
+
+    let entries = rows.iter().map(|row| {
+        let path: String = row.get("path");
+        let kind: String = row.get("kind");
+        let path = if kind == "dir" {
+            format!("{path}/")
+        } else {
+            path
+        };
+        (
+            path,
+            kind,
+            row.get::<i64, _>("size"),
+            row.get::<i64, _>("mtime_ms"),
+        )
+    });
+    if json {
+        let entries = entries
+            .map(|(path, kind, size, mtime_ms)| {
+                serde_json::json!({ "path": path, "kind": kind, "size": size, "mtimeMs": mtime_ms })
+            })
+            .collect::<Vec<_>>();
+        println!(
+            "{}",
+            serde_json::json!({ "snapshotId": snapshot_id, "entries": entries })
+        );
+    } else {
+        for (path, kind, size, _) in entries {
+            println!("kind={kind} size={size} path={path}");
+        }
+    }
+    Ok(())
+}
+
+async fn snapshots_delete(
+    data_dir: &Path,
+    snapshot_id: &str,
+    force: bool,
+    json: bool,
+) -> Result<(), CliError> {
+    let db_path = find_snapshot_index_db(data_dir, snapshot_id).await?;
+    let filemap_dir = index_db_filemap_dir(data_dir, &db_path);
+    let deleted = televy_backup_core::delete_snapshot(&db_path, &filemap_dir, snapshot_id, force)
+        .await
+        .map_err(map_core_err)?;
+    if !deleted {
+        return Err(snapshot_not_found(
+            snapshot_id,
+            format!("snapshot not found: {snapshot_id}"),
+        ));
+    }
+
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({ "snapshotId": snapshot_id, "deleted": true })
+        );
+    } else {
+        println!("snapshotId={snapshot_id}");
+        println!("deleted=true");
+    }
+    Ok(())
+}
+
+async fn snapshots_chain(data_dir: &Path, snapshot_id: &str, json: bool) -> Result<(), CliError> {
+    let db_path = find_snapshot_index_db(data_dir, snapshot_id).await?;
+    let filemap_dir = index_db_filemap_dir(data_dir, &db_path);
+    let Some(chain) = televy_backup_core::snapshot_chain(&db_path, &filemap_dir, snapshot_id)
+        .await
+        .map_err(map_core_err)?
+    else {
+        return Err(snapshot_not_found(
+            snapshot_id,
+            format!("snapshot not found: {snapshot_id}"),
+        ));
+    };
+
+    if json {
+        let links = chain
+            .links
+            .iter()
+            .map(|l| {
+                serde_json::json!({
+                    "snapshotId": l.snapshot_id,
+                    "createdAt": l.created_at,
+                    "baseSnapshotId": l.base_snapshot_id,
+                    "bytesNew": l.bytes_new,
+                })
+            })
+            .collect::<Vec<_>>();
+        println!(
+            "{}",
+            serde_json::json!({
+                "snapshotId": snapshot_id,
+                "links": links,
+                "missingBase": chain.missing_base,
+            })
+        );
+    } else {
+        for l in &chain.links {
+            println!(
+                "snapshotId={} createdAt={} base={} bytesNew={}",
+                l.snapshot_id,
+                format::timestamp(&l.created_at),
+                l.base_snapshot_id.as_deref().unwrap_or("none"),
+                l.bytes_new.map_or_else(|| "-".to_string(), format::bytes),
+            );
+        }
+        if let Some(missing) = &chain.missing_base {
+            eprintln!(
+                "warning: base snapshot {missing} is not in the index; `snapshots rebase` can point past it"
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn snapshots_rebase(
+    data_dir: &Path,
+    snapshot_id: &str,
+    onto: Option<&str>,
+    json: bool,
+) -> Result<(), CliError> {
+    let db_path = find_snapshot_index_db(data_dir, snapshot_id).await?;
+    let filemap_dir = index_db_filemap_dir(data_dir, &db_path);
+    let found = televy_backup_core::rebase_snapshot(&db_path, &filemap_dir, snapshot_id, onto)
+        .await
+        .map_err(map_core_err)?;
+    if !found {
+        return Err(snapshot_not_found(
+            snapshot_id,
+            format!("snapshot not found: {snapshot_id}"),
+        ));
+    }
+
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({ "snapshotId": snapshot_id, "baseSnapshotId": onto })
+        );
+    } else {
+        println!("snapshotId={snapshot_id}");
+        println!("baseSnapshotId={}", onto.unwrap_or("none"));
+        eprintln!("note: other machines see the change after this endpoint's next backup");
+    }
+    Ok(())
+}
+
+async fn snapshot_device_columns_sql(pool: &sqlx::SqlitePool) -> Result<&'static str, CliError> {
+    let present = televy_backup_core::index_db::snapshots_have_device_columns(pool)
+        .await
+        .map_err(map_core_err)?;
+    Ok(if present {
+        "device_id, device_name"
+    } else {
+        "NULL AS device_id, NULL AS device_name"
+    })
+}
+
+const LOGS_FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+fn prune_run_logs_best_effort(data_dir: &Path, settings: &Settings) {
+    match televy_backup_core::run_log::prune_run_logs(data_dir, &settings.logs) {
+        Ok(stats) if stats.deleted > 0 || stats.skipped_active > 0 => tracing::info!(
+            event = "run_log.pruned",
+            deleted = stats.deleted as u64,
+            kept = stats.kept as u64,
+            skipped_active = stats.skipped_active as u64,
+            "run_log.pruned"
+        ),
+        Ok(_) => {}
+        Err(e) => tracing::warn!(
+            event = "run_log.prune_failed",
+            error = %e,
+            "run_log.prune_failed"
+        ),
+    }
+}
+
+fn logs_list(
+    data_dir: &Path,
+    target_id: Option<String>,
+    kind: Option<String>,
+    limit: u32,
+    json: bool,
+) -> Result<(), CliError> {
+    let logs = televy_backup_core::run_log::summarize_run_logs(
+        data_dir,
+        target_id.as_deref(),
+        kind.as_deref(),
+        limit as usize,
+    )
+    .map_err(|e| CliError::new(ErrorCode::LogReadFailed, e.to_string()))?;
+
+    if json {
+        println!("{}", serde_json::json!({ "logs": logs }));
+    } else {
+        for l in logs {
+            println!(
+                "runId={} kind={} startedAt={} targetId={} active={} path={}",
+                l.run_id,
+                l.kind,
+                l.started_at,
+                l.target_id.as_deref().unwrap_or("-"),
+                l.active,
+                l.path,
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn logs_show(data_dir: &Path, run_id: &str, follow: bool) -> Result<(), CliError> {
+    let log = televy_backup_core::run_log::list_run_logs(data_dir)
+        .map_err(|e| CliError::new(ErrorCode::LogReadFailed, e.to_string()))?
+        .into_iter()
+        .find(|l| l.run_id == run_id)
+        .ok_or_else(|| {
+            CliError::new(
+                ErrorCode::LogNotFound,
+                format!("run log not found: run_id={run_id}"),
+            )
+        })?;
+
+    let read_err = |e: std::io::Error| CliError::new(ErrorCode::LogReadFailed, e.to_string());
+    let mut file = std::fs::File::open(&log.path).map_err(read_err)?;
+    let mut stdout = std::io::stdout().lock();
+    loop {
+        // Check before draining so the last lines written before the lock is released are shown.
+        let active = follow
+            && televy_backup_core::run_log::run_log_is_active(&log.path).map_err(read_err)?;
+        std::io::copy(&mut file, &mut stdout).map_err(read_err)?;
+        stdout.flush().map_err(read_err)?;
+        if !active {
+            return Ok(());
+        }
+        tokio::time::sleep(LOGS_FOLLOW_POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(unix)]
+fn logs_stream(
+    data_dir: &Path,
+    task_id: Option<String>,
+    target_id: Option<String>,
+) -> Result<(), CliError> {
+    let params =
+        serde_json::to_value(televy_backup_core::control::LogsStreamParams { task_id, target_id })
+            .map_err(|e| CliError::new(ErrorCode::ControlInvalidRequest, e.to_string()))?;
+    let (resp, mut reader) = control_ipc_open(
+        data_dir,
+        "logs.stream",
+        params,
+        Duration::from_secs(30),
+        Duration::from_secs(5),
+    )?;
+    if let Some(err) = resp
+        .error
+        .as_ref()
+        .filter(|e| e.code == ErrorCode::ControlNotFound.as_str())
+    {
+        return Err(CliError::new(ErrorCode::LogNotFound, err.message.clone())
+            .with_details(err.details.clone()));
+    }
+    control_response_result(resp)?;
+
+    // A run can go quiet for a long time (one large upload); the daemon closes the stream at the
+    // end of the run.
+    let _ = reader.get_ref().set_read_timeout(None);
+    let mut stdout = std::io::stdout().lock();
+    std::io::copy(&mut reader, &mut stdout)
+        .map_err(|e| CliError::new(ErrorCode::LogReadFailed, e.to_string()))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn logs_stream(
+    _data_dir: &Path,
+    _task_id: Option<String>,
+    _target_id: Option<String>,
+) -> Result<(), CliError> {
+    Err(CliError::new(
+        ErrorCode::DaemonUnavailable,
+        "control IPC is only supported on unix",
+    ))
+}
+
+/// The name prefix of documents this machine uploads to `ep`; none when the device identity
+/// cannot be read, which only changes document names.
+fn endpoint_object_prefix(
+    data_dir: &Path,
+    ep: &settings_config::TelegramEndpoint,
+) -> Option<String> {
+    if ep.object_prefix.is_some() {
+        return ep.object_prefix.clone();
+    }
+    televy_backup_core::device::load_or_create_device_identity(data_dir)
+        .ok()
+        .map(|d| ep.object_prefix_for(&d.device_name))
+}
+
+fn print_device_identity(identity: &televy_backup_core::device::DeviceIdentity, json: bool) {
+    if json {
+        println!("{}", serde_json::json!({ "device": identity }));
+    } else {
+        println!("deviceId={}", identity.device_id);
+        println!("deviceName={}", identity.device_name);
+    }
+}
+
+fn device_show(data_dir: &Path, json: bool) -> Result<(), CliError> {
+    let identity = televy_backup_core::device::load_or_create_device_identity(data_dir)
+        .map_err(map_core_err)?;
+    print_device_identity(&identity, json);
+    Ok(())
+}
+
+fn device_rename(data_dir: &Path, name: &str, json: bool) -> Result<(), CliError> {
+    let identity =
+        televy_backup_core::device::rename_device(data_dir, name).map_err(map_core_err)?;
+    print_device_identity(&identity, json);
+    Ok(())
+}
+
+/// Appends to the audit log; a failure is logged but never fails the audited operation.
+fn record_audit(data_dir: &Path, op: &str, details: serde_json::Value) {
+    let actor = televy_backup_core::audit::AuditActor::cli_from_env();
+    if let Err(e) = televy_backup_core::audit::append_audit_entry(data_dir, actor, op, details) {
+        tracing::warn!(event = "audit.append_failed", op, error = %e, "audit.append_failed");
+    }
+}
+
+async fn index_migrate_providers(data_dir: &Path, json: bool) -> Result<(), CliError> {
+    let index_dir = data_dir.join("index");
+    let mut db_paths = Vec::new();
+    if index_dir.exists() {
+        let entries = std::fs::read_dir(&index_dir)
+            .map_err(|e| CliError::new(ErrorCode::DbFailed, e.to_string()))?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(name) = path.file_name().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            if path.is_file() && name.starts_with("index.") && name.ends_with(".sqlite") {
+                db_paths.push(path);
+            }
+        }
+    }
+    db_paths.sort();
+
+    let mut out = Vec::new();
+    for path in db_paths {
+        let report = televy_backup_core::index_db::migrate_index_db_providers(&path)
+            .await
+            .map_err(map_core_err)?;
+        tracing::info!(
+            event = "index_db.providers_migrated",
+            db_path = %path.display(),
+            endpoint_id = report.endpoint_id.as_deref(),
+            providers_updated = report.providers_updated,
+            object_ids_updated = report.object_ids_updated,
+            "index_db.providers_migrated"
+        );
+        if !json {
+            println!(
+                "{}\tendpoint={}\tproviders_updated={}\tobject_ids_updated={}",
+                path.display(),
+                report.endpoint_id.as_deref().unwrap_or("-"),
+                report.providers_updated,
+                report.object_ids_updated
+            );
+        }
+        out.push(serde_json::json!({
+            "path": path.display().to_string(),
+            "endpointId": report.endpoint_id,
+            "providersUpdated": report.providers_updated,
+            "objectIdsUpdated": report.object_ids_updated,
+        }));
+    }
+
+    if json {
+        println!("{}", serde_json::json!({ "databases": out }));
+    } else if out.is_empty() {
+        println!("no index databases");
+    }
+    Ok(())
+}
+
+async fn index_sync(
+    config_dir: &Path,
+    data_dir: &Path,
+    target_id: String,
+    force: bool,
+    snapshot_id: Option<String>,
+    json: bool,
+) -> Result<(), CliError> {
+    let settings = load_settings(config_dir)?;
+    let target = select_target(&settings, Some(&target_id), None)?;
+    let ep = select_endpoint(&settings, Some(&target.endpoint_id))?;
+    let (storage, master_key) =
+        connect_pinned_endpoint(config_dir, data_dir, &settings, ep).await?;
+    let pin_mode = bootstrap::PinnedStorage::bootstrap_pin_mode(&storage);
+    if pin_mode == bootstrap::BootstrapPinMode::Disabled {
+        return Err(bootstrap_missing_err(pin_mode));
+    }
+    let res = televy_backup_core::index_sync::sync_index_from_remote(
+        &storage,
+        &master_key,
+        &endpoint_index_db_path(data_dir, &ep.id),
+        &endpoint_filemap_dir(data_dir, &ep.id),
+        &televy_backup_core::index_sync::IndexSyncOptions {
+            target_id,
+            snapshot_id,
+            force,
+        },
+    )
+    .await;
+    persist_mtproto_session(config_dir, data_dir, ep, &storage);
+    let report = res.map_err(map_core_err)?;
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string(&report)
+                .map_err(|e| CliError::new(ErrorCode::ConfigInvalid, e.to_string()))?
+        );
+    } else {
+        println!(
+            "snapshotId={}",
+            report.snapshot_id.as_deref().unwrap_or("-")
+        );
+        println!("endpointDb={}", report.endpoint_db.as_str());
+        println!("filemap={}", report.filemap.as_str());
+        println!("bytesDownloaded={}", report.bytes_downloaded);
+    }
+    Ok(())
+}
+
+async fn index_check(
+    config_dir: &Path,
+    data_dir: &Path,
+    endpoint_id: Option<String>,
+    repair: bool,
+    json: bool,
+) -> Result<(), CliError> {
+    let settings = load_settings(config_dir)?;
+    let ep = select_endpoint(&settings, endpoint_id.as_deref())?;
+    let db_path = endpoint_index_db_path(data_dir, &ep.id);
+    if !db_path.exists() {
+        return Err(CliError::new(
+            ErrorCode::SnapshotNotFound,
+            format!("local index db not found: {}", db_path.display()),
+        )
+        .with_details(serde_json::json!({ "snapshotId": null, "endpointId": ep.id })));
+    }
+    let (storage, master_key) =
+        connect_pinned_endpoint(config_dir, data_dir, &settings, ep).await?;
+    let res = televy_backup_core::check_chunk_ref_counts(
+        &storage,
+        &db_path,
+        &endpoint_filemap_dir(data_dir, &ep.id),
+        &master_key,
+        repair,
+        None,
+    )
+    .await;
+    persist_mtproto_session(config_dir, data_dir, ep, &storage);
+    let check = res.map_err(map_core_err)?;
+
+    let drift = check
+        .drift_examples
+        .iter()
+        .map(|d| serde_json::json!({ "chunkHash": d.chunk_hash, "stored": d.stored, "actual": d.actual }))
+        .collect::<Vec<_>>();
+    if !check.is_consistent() && !check.repaired {
+        return Err(CliError::new(
+            ErrorCode::IndexRefCountDrift,
+            match &check.stale_reason {
+                Some(reason) => format!(
+                    "chunk reference counts are stale ({reason}); {} chunks differ from the file maps",
+                    check.chunks_drifted
+                ),
+                None => format!(
+                    "{} chunk reference counts differ from the file maps",
+                    check.chunks_drifted
+                ),
+            },
+        )
+        .with_details(serde_json::json!({
+            "endpointId": ep.id,
+            "chunksDrifted": check.chunks_drifted,
+            "staleReason": check.stale_reason,
+            "drift": drift,
+        })));
+    }
+
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({
+                "endpointId": ep.id,
+                "snapshots": check.snapshots,
+                "snapshotsUncounted": check.snapshots_uncounted,
+                "staleReason": check.stale_reason,
+                "chunksChecked": check.chunks_checked,
+                "chunksDrifted": check.chunks_drifted,
+                "drift": drift,
+                "repaired": check.repaired,
+            })
+        );
+    } else {
+        println!("snapshots={}", check.snapshots);
+        println!("snapshotsUncounted={}", check.snapshots_uncounted);
+        println!("chunksChecked={}", check.chunks_checked);
+        println!("chunksDrifted={}", check.chunks_drifted);
+        println!("repaired={}", check.repaired);
+    }
+    Ok(())
+}
+
+async fn index_republish(
+    config_dir: &Path,
+    data_dir: &Path,
+    endpoint_id: Option<String>,
+    snapshot_id: &str,
+    json: bool,
+) -> Result<(), CliError> {
+    let settings = load_settings(config_dir)?;
+    let ep = select_endpoint(&settings, endpoint_id.as_deref())?;
+    let db_path = endpoint_index_db_path(data_dir, &ep.id);
+    if !db_path.exists() {
+        return Err(snapshot_not_found(
+            snapshot_id,
+            format!("local index db not found: {}", db_path.display()),
+        ));
+    }
+    let filemap_db_path =
+        endpoint_filemap_dir(data_dir, &ep.id).join(format!("{snapshot_id}.sqlite"));
+    if !filemap_db_path.exists() {
+        return Err(snapshot_not_found(
+            snapshot_id,
+            format!(
+                "no local file map for snapshot {snapshot_id} (only the machine that took it, or one that restored it, has one): {}",
+                filemap_db_path.display()
+            ),
+        ));
+    }
+    let device = televy_backup_core::device::load_or_create_device_identity(data_dir)
+        .map_err(map_core_err)?;
+
+    let (storage, master_key) =
+        connect_pinned_endpoint(config_dir, data_dir, &settings, ep).await?;
+    let res = televy_backup_core::republish_snapshot_index(
+        &storage,
+        &db_path,
+        &filemap_db_path,
+        snapshot_id,
+        &master_key,
+        Some(&device),
+    )
+    .await;
+    persist_mtproto_session(config_dir, data_dir, ep, &storage);
+    let published = res.map_err(map_core_err)?;
+
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({
+                "endpointId": ep.id,
+                "snapshotId": published.snapshot_id,
+                "manifestObjectId": published.manifest_object_id,
+                "manifestSha256": published.manifest_sha256,
+                "indexParts": published.index_parts,
+                "replacedManifestObjectId": published.replaced_manifest_object_id,
+            })
+        );
+    } else {
+        println!("snapshotId={}", published.snapshot_id);
+        println!("manifestObjectId={}", published.manifest_object_id);
+        println!("indexParts={}", published.index_parts);
+        eprintln!(
+            "note: if this is a target's latest snapshot, also run `televybackup bootstrap set-latest --target-id <target> --snapshot-id {snapshot_id}` so other machines restore from the new index"
+        );
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn index_export(
+    config_dir: &Path,
+    data_dir: &Path,
+    endpoint_id: Option<String>,
+    snapshot_id: &str,
+    output: Option<&Path>,
+    raw_encrypted: Option<&Path>,
+    i_understand_plaintext: bool,
+    json: bool,
+) -> Result<(), CliError> {
+    if output.is_none() && raw_encrypted.is_none() {
+        return Err(CliError::new(
+            ErrorCode::CliInvalid,
+            "pass --output, --raw-encrypted or both",
+        ));
+    }
+    if output.is_some() && !i_understand_plaintext {
+        return Err(CliError::new(
+            ErrorCode::ConfigInvalid,
+            "refusing to write a plaintext snapshot listing (it reveals every file path) without --i-understand-plaintext",
+        ));
+    }
+
+    let settings = load_settings(config_dir)?;
+    let ep = select_endpoint(&settings, endpoint_id.as_deref())?;
+    let db_path = endpoint_index_db_path(data_dir, &ep.id);
+    let filemap_db_path =
+        endpoint_filemap_dir(data_dir, &ep.id).join(format!("{snapshot_id}.sqlite"));
+    let download_filemap = output.is_some() && !filemap_db_path.exists();
+
+    if download_filemap || raw_encrypted.is_some() {
+        if !db_path.exists() {
+            return Err(snapshot_not_found(
+                snapshot_id,
+                format!("local index db not found: {}", db_path.display()),
+            ));
+        }
+        let (manifest_object_id, _provider, manifest_sha256) =
+            lookup_manifest_meta(&db_path, snapshot_id).await?;
+        if manifest_sha256.is_none() {
+            televy_backup_core::remote_index_db::warn_manifest_unverified(
+                snapshot_id,
+                &manifest_object_id,
+            );
+        }
+        let (storage, master_key) =
+            connect_pinned_endpoint(config_dir, data_dir, &settings, ep).await?;
+        let res = async {
+            if let Some(path) = raw_encrypted {
+                let manifest_enc =
+                    televy_backup_core::remote_index_db::download_encrypted_manifest(
+                        &storage,
+                        snapshot_id,
+                        &manifest_object_id,
+                        manifest_sha256.as_deref(),
+                    )
+                    .await
+                    .map_err(map_core_err)?;
+                std::fs::write(path, manifest_enc)
+                    .map_err(|e| CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()))?;
+            }
+            if download_filemap {
+                if let Some(parent) = filemap_db_path.parent() {
+                    std::fs::create_dir_all(parent)
+                        .map_err(|e| CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()))?;
+                }
+                let data_key = local_snapshot_data_key(data_dir, &master_key, snapshot_id).await?;
+                televy_backup_core::remote_index_db::download_and_write_index_db_atomic(
+                    &storage,
+                    snapshot_id,
+                    &manifest_object_id,
+                    manifest_sha256.as_deref(),
+                    data_key.key(),
+                    &filemap_db_path,
+                    None,
+                    Some(storage.provider()),
+                    None,
+                )
+                .await
+                .map_err(map_core_err)?;
+            }
+            Ok(())
+        }
+        .await;
+        persist_mtproto_session(config_dir, data_dir, ep, &storage);
+        res?;
+    }
+
+    let mut counts = None;
+    if let Some(path) = output {
+        let listing = televy_backup_core::snapshot_listing::read_snapshot_listing(
+            &filemap_db_path,
+            Some(&db_path),
+            snapshot_id,
+        )
+        .await
+        .map_err(map_core_err)?;
+        let body = serde_json::to_vec_pretty(&listing)
+            .map_err(|e| CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()))?;
+        write_private_file(path, &body)
+            .map_err(|e| CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()))?;
+        counts = Some((listing.files.len(), listing.chunks.len()));
+    }
+
+    let source = if download_filemap { "remote" } else { "local" };
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({
+                "endpointId": ep.id,
+                "snapshotId": snapshot_id,
+                "source": output.map(|_| source),
+                "output": output.map(|p| p.display().to_string()),
+                "rawEncrypted": raw_encrypted.map(|p| p.display().to_string()),
+                "files": counts.map(|(files, _)| files),
+                "chunks": counts.map(|(_, chunks)| chunks),
+            })
+        );
+    } else {
+        println!("snapshotId={snapshot_id}");
+        if let (Some(path), Some((files, chunks))) = (output, counts) {
+            println!("output={} source={source}", path.display());
+            println!("files={files} chunks={chunks}");
+        }
+        if let Some(path) = raw_encrypted {
+            println!("rawEncrypted={}", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Creates `path` readable by the owner only before writing to it.
+fn write_private_file(path: &Path, body: &[u8]) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(body)
+}
+
+async fn index_import(
+    config_dir: &Path,
+    data_dir: &Path,
+    endpoint_id: Option<String>,
+    snapshot_id: &str,
+    input: &Path,
+    json: bool,
+) -> Result<(), CliError> {
+    let text = std::fs::read_to_string(input).map_err(|e| {
+        CliError::new(ErrorCode::ConfigReadFailed, e.to_string())
+            .with_details(serde_json::json!({ "path": input.display().to_string() }))
+    })?;
+    let listing: televy_backup_core::snapshot_listing::SnapshotListing =
+        serde_json::from_str(&text).map_err(|e| {
+            CliError::new(
+                ErrorCode::ConfigInvalid,
+                format!("invalid snapshot listing: {e}"),
+            )
+        })?;
+    if listing.snapshot_id != snapshot_id {
+        return Err(CliError::new(
+            ErrorCode::ConfigInvalid,
+            format!(
+                "listing is for snapshot {}, not {snapshot_id}",
+                listing.snapshot_id
+            ),
+        ));
+    }
+
+    let settings = load_settings(config_dir)?;
+    let ep = select_endpoint(&settings, endpoint_id.as_deref())?;
+    let db_path = endpoint_index_db_path(data_dir, &ep.id);
+    let filemap_db_path =
+        endpoint_filemap_dir(data_dir, &ep.id).join(format!("{snapshot_id}.sqlite"));
+    televy_backup_core::snapshot_listing::import_snapshot_listing(
+        &db_path,
+        &filemap_db_path,
+        &listing,
+    )
+    .await
+    .map_err(map_core_err)?;
+
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({
+                "endpointId": ep.id,
+                "snapshotId": snapshot_id,
+                "files": listing.files.len(),
+                "chunks": listing.chunks.len(),
+            })
+        );
+    } else {
+        println!("snapshotId={snapshot_id}");
+        println!(
+            "files={} chunks={}",
+            listing.files.len(),
+            listing.chunks.len()
+        );
+    }
+    Ok(())
+}
+
+fn index_schema(json: bool) {
+    use televy_backup_core::snapshot_listing::{
+        SNAPSHOT_LISTING_FIELDS, snapshot_listing_schema_json,
+    };
+    if json {
+        println!("{}", snapshot_listing_schema_json());
+        return;
+    }
+    for f in SNAPSHOT_LISTING_FIELDS {
+        let ty = serde_json::to_value(f.ty)
+            .ok()
+            .and_then(|v| v.as_str().map(str::to_string))
+            .unwrap_or_default();
+        let nullable = if f.required { "" } else { " nullable" };
+        println!("{} ({ty}{nullable})", f.path);
+        println!("    {}", f.description);
+    }
+}
+
+/// `gc run`, or `gc flush` when `min_age_days` is `None`.
+async fn gc_run(
+    config_dir: &Path,
+    data_dir: &Path,
+    endpoint_id: Option<String>,
+    dry_run: bool,
+    min_age_days: Option<u32>,
+    json: bool,
+) -> Result<(), CliError> {
+    let settings = load_settings(config_dir)?;
+    let ep = select_endpoint(&settings, endpoint_id.as_deref())?;
+    let (storage, master_key) =
+        connect_pinned_endpoint(config_dir, data_dir, &settings, ep).await?;
+    let res = gc_endpoint(
+        &storage,
+        &master_key,
+        &settings,
+        data_dir,
+        &ep.id,
+        dry_run,
+        min_age_days,
+    )
+    .await;
+    persist_mtproto_session(config_dir, data_dir, ep, &storage);
+    let (res, dedupe_catalog_object_id) = res?;
+    let grace_days = settings.retention.deletion_grace_days;
+
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({
+                "endpointId": ep.id,
+                "flush": min_age_days.is_none(),
+                "dryRun": res.dry_run,
+                "minAgeDays": min_age_days,
+                "graceDays": grace_days,
+                "snapshotsLive": res.snapshots_live,
+                "chunksLive": res.chunks_live,
+                "objectsDeleted": res.objects_deleted,
+                "chunksDeleted": res.chunks_deleted,
+                "bytesReclaimed": res.bytes_reclaimed,
+                "objectsTooRecent": res.objects_too_recent,
+                "objectsFailed": res.objects_failed,
+                "objectsQueued": res.objects_queued,
+                "bytesQueued": res.bytes_queued,
+                "objectsUnqueued": res.objects_unqueued,
+                "objectsNotDue": res.objects_not_due,
+                "pendingObjects": res.pending_objects,
+                "pendingBytes": res.pending_bytes,
+                "tombstonesPurged": res.tombstones_purged,
+                "dedupeCatalogObjectId": dedupe_catalog_object_id,
+            })
+        );
+    } else {
+        println!("dryRun={}", res.dry_run);
+        println!("snapshotsLive={}", res.snapshots_live);
+        println!("chunksLive={}", res.chunks_live);
+        println!("objectsDeleted={}", res.objects_deleted);
+        println!("chunksDeleted={}", res.chunks_deleted);
+        println!("bytesReclaimed={}", res.bytes_reclaimed);
+        if min_age_days.is_some() {
+            println!("objectsTooRecent={}", res.objects_too_recent);
+            println!("objectsQueued={}", res.objects_queued);
+            println!("bytesQueued={}", res.bytes_queued);
+        } else {
+            println!("objectsNotDue={}", res.objects_not_due);
+        }
+        println!("objectsUnqueued={}", res.objects_unqueued);
+        println!("pendingObjects={}", res.pending_objects);
+        println!("pendingBytes={}", res.pending_bytes);
+        println!("tombstonesPurged={}", res.tombstones_purged);
+        if res.objects_queued > 0 {
+            eprintln!(
+                "note: queued objects are deleted by `gc flush` after {grace_days} days; `gc undo --snapshot-id <id>` brings back a deleted snapshot until then"
+            );
+        }
+        if res.objects_failed > 0 {
+            eprintln!(
+                "warning: {} objects could not be deleted and stay in the chat unreferenced",
+                res.objects_failed
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn gc_undo(
+    config_dir: &Path,
+    data_dir: &Path,
+    endpoint_id: Option<String>,
+    snapshot_id: &str,
+    json: bool,
+) -> Result<(), CliError> {
+    let settings = load_settings(config_dir)?;
+    let ep = select_endpoint(&settings, endpoint_id.as_deref())?;
+    let (storage, master_key) =
+        connect_pinned_endpoint(config_dir, data_dir, &settings, ep).await?;
+    let res = async {
+        let (config, _) = gc_prepare(
+            &storage,
+            &master_key,
+            &settings,
+            data_dir,
+            &ep.id,
+            televy_backup_core::GC_DEFAULT_MIN_AGE_DAYS,
+        )
+        .await?;
+        televy_backup_core::undo_snapshot_delete(&storage, &config, snapshot_id)
+            .await
+            .map_err(map_core_err)
+    }
+    .await;
+    persist_mtproto_session(config_dir, data_dir, ep, &storage);
+    let Some(res) = res? else {
+        return Err(CliError::new(
+            ErrorCode::SnapshotNotFound,
+            format!(
+                "no deleted snapshot {snapshot_id} to bring back (never deleted, or its grace period ended at a flush)"
+            ),
+        )
+        .with_details(serde_json::json!({ "snapshotId": snapshot_id, "endpointId": ep.id })));
+    };
+
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({
+                "endpointId": ep.id,
+                "snapshotId": res.snapshot_id,
+                "sourcePath": res.source_path,
+                "deletedAt": res.deleted_at,
+                "objectsUnqueued": res.objects_unqueued,
+                "bytesUnqueued": res.bytes_unqueued,
+            })
+        );
+    } else {
+        println!("snapshotId={}", res.snapshot_id);
+        println!("sourcePath={}", res.source_path);
+        println!("deletedAt={}", res.deleted_at);
+        println!("objectsUnqueued={}", res.objects_unqueued);
+        println!("bytesUnqueued={}", res.bytes_unqueued);
+        eprintln!(
+            "note: the snapshot is back in the local index; the next backup of the endpoint publishes it"
+        );
+    }
+    Ok(())
+}
+
+/// Brings the local endpoint and dedupe DBs up to the bootstrap catalog, since a stale local
+/// index would miss snapshots taken elsewhere and collect their chunks. Returns the GC config
+/// for the endpoint and the catalog's dedupe pointer.
+async fn gc_prepare(
+    storage: &TelegramMtProtoStorage,
+    master_key: &[u8; 32],
+    settings: &Settings,
+    data_dir: &Path,
+    endpoint_id: &str,
+    min_age_days: u32,
+) -> Result<
+    (
+        televy_backup_core::GcConfig,
+        Option<bootstrap::BootstrapEndpointDedupeLatest>,
+    ),
+    CliError,
+> {
+    let db_path = endpoint_index_db_path(data_dir, endpoint_id);
+    let dedupe_db_path = endpoint_dedupe_db_path(data_dir, endpoint_id);
+    let dedupe_pending_db_path = endpoint_dedupe_pending_db_path(data_dir, endpoint_id);
+
+    let catalog = if bootstrap::PinnedStorage::bootstrap_pin_mode(storage)
+        == bootstrap::BootstrapPinMode::Disabled
+    {
+        None
+    } else {
+        bootstrap::load_remote_catalog(storage, master_key)
+            .await
+            .map_err(map_core_err)?
+    };
+    let endpoint_latest = catalog.as_ref().and_then(|c| c.endpoint_latest.clone());
+    let dedupe_latest = catalog
+        .as_ref()
+        .and_then(|c| c.endpoint_dedupe_latest.clone());
+    if let Some(endpoint_latest) = &endpoint_latest {
+        if dedupe_latest.is_none() {
+            return Err(CliError::new(
+                ErrorCode::GcRemoteDedupeMissing,
+                "the bootstrap catalog points at an endpoint index but no remote dedupe index; run a backup first",
+            ));
+        }
+        sync_local_endpoint_db(storage, master_key, endpoint_latest, &db_path, None).await?;
+    }
+    if let Some(dedupe_latest) = &dedupe_latest {
+        sync_local_dedupe_db(storage, master_key, dedupe_latest, &dedupe_db_path, None).await?;
+    }
+    if !db_path.exists() {
+        return Err(CliError::new(
+            ErrorCode::SnapshotNotFound,
+            format!("local index db not found: {}", db_path.display()),
+        )
+        .with_details(serde_json::json!({ "snapshotId": null, "endpointId": endpoint_id })));
+    }
+
+    let config = televy_backup_core::GcConfig {
+        endpoint_db_path: db_path,
+        filemap_dir: endpoint_filemap_dir(data_dir, endpoint_id),
+        dedupe_db_path: Some(dedupe_db_path),
+        dedupe_pending_db_path: Some(dedupe_pending_db_path),
+        master_key: *master_key,
+        min_age_days,
+        grace_days: settings.retention.deletion_grace_days,
+    };
+    Ok((config, dedupe_latest))
+}
+
+/// Runs `gc run` (or `gc flush` when `min_age_days` is `None`) against an endpoint: syncs the
+/// local DBs (see [`gc_prepare`]), collects, then republishes the dedupe base so no machine
+/// dedupes against a deleted object. Returns the new dedupe catalog object id when one was
+/// published.
+async fn gc_endpoint(
+    storage: &TelegramMtProtoStorage,
+    master_key: &[u8; 32],
+    settings: &Settings,
+    data_dir: &Path,
+    endpoint_id: &str,
+    dry_run: bool,
+    min_age_days: Option<u32>,
+) -> Result<(televy_backup_core::GcResult, Option<String>), CliError> {
+    // Queued objects passed the age check when `gc run` queued them.
+    let (config, dedupe_latest) = gc_prepare(
+        storage,
+        master_key,
+        settings,
+        data_dir,
+        endpoint_id,
+        min_age_days.unwrap_or(0),
+    )
+    .await?;
+    let options = televy_backup_core::GcOptions {
+        cancel: None,
+        dry_run,
+        retry: settings.retry.clone(),
+    };
+    let res = match min_age_days {
+        Some(_) => televy_backup_core::collect_garbage(storage, &config, options).await,
+        None => televy_backup_core::flush_pending_deletions(storage, &config, options).await,
+    }
+    .map_err(map_core_err)?;
+
+    let Some(dedupe_latest) = dedupe_latest else {
+        return Ok((res, None));
+    };
+    if res.dry_run || res.objects_deleted + res.objects_failed == 0 {
+        return Ok((res, None));
+    }
+    let dedupe_db_path = endpoint_dedupe_db_path(data_dir, endpoint_id);
+    let dedupe_pending_db_path = endpoint_dedupe_pending_db_path(data_dir, endpoint_id);
+    let device = televy_backup_core::device::load_or_create_device_identity(data_dir)
+        .map_err(map_core_err)?;
+    let catalog_object_id = televy_backup_core::republish_dedupe_base(
+        storage,
+        &dedupe_db_path,
+        &dedupe_pending_db_path,
+        &dedupe_latest.endpoint_dedupe_id,
+        master_key,
+        Some(&device),
+    )
+    .await
+    .map_err(map_core_err)?;
+    let mut cat = bootstrap::load_remote_catalog(storage, master_key)
+        .await
+        .map_err(map_core_err)?
+        .unwrap_or_default();
+    cat.touch();
+    cat.endpoint_dedupe_latest = Some(bootstrap::BootstrapEndpointDedupeLatest {
+        endpoint_dedupe_id: dedupe_latest.endpoint_dedupe_id,
+        catalog_object_id: catalog_object_id.clone(),
+    });
+    bootstrap::save_remote_catalog(storage, master_key, &cat)
+        .await
+        .map_err(map_core_err)?;
+    Ok((res, Some(catalog_object_id)))
+}
+
+async fn repo_export(
+    config_dir: &Path,
+    data_dir: &Path,
+    endpoint_id: Option<String>,
+    output: &Path,
+    json: bool,
+) -> Result<(), CliError> {
+    let settings = load_settings(config_dir)?;
+    let ep = select_endpoint(&settings, endpoint_id.as_deref())?;
+    let master_key = load_master_key(config_dir, data_dir)?;
+    let storage = connect_endpoint_storage(config_dir, data_dir, &settings, ep).await?;
+    let bootstrap_catalog_object_id = if settings_config::is_likely_private_chat_id(&ep.chat_id) {
+        None
+    } else {
+        bootstrap::remote_catalog_object_id(&storage).map_err(map_core_err)?
+    };
+
+    let cancel = cancel_on_stop_signal();
+    let config = repo_export::RepoExportConfig {
+        endpoint_id: ep.id.clone(),
+        endpoint_db_path: endpoint_index_db_path(data_dir, &ep.id),
+        bootstrap_catalog_object_id,
+        master_key,
+        output_dir: output.to_path_buf(),
+    };
+    let remapped = RemappedStorage::new(&storage, endpoint_chat_remaps(data_dir, &ep.id).await?);
+    let res = repo_export::export_repo(
+        &remapped,
+        &config,
+        repo_export::RepoExportOptions {
+            cancel: Some(&cancel),
+            progress: None,
+            retry: settings.retry.clone(),
+        },
+    )
+    .await;
+    persist_mtproto_session(config_dir, data_dir, ep, &storage);
+    let res = res.map_err(map_core_err)?;
+    tracing::info!(
+        event = "repo.exported",
+        endpoint_id = %ep.id,
+        output = %output.display(),
+        objects_total = res.objects_total,
+        objects_downloaded = res.objects_downloaded,
+        objects_skipped = res.objects_skipped,
+        bytes_downloaded = res.bytes_downloaded,
+        "repo.exported"
+    );
+
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({
+                "endpointId": ep.id,
+                "output": output.display().to_string(),
+                "objectsTotal": res.objects_total,
+                "objectsDownloaded": res.objects_downloaded,
+                "objectsSkipped": res.objects_skipped,
+                "bytesDownloaded": res.bytes_downloaded,
+                "bytesTotal": res.bytes_total,
+                "retries": res.retry.retries,
+            })
+        );
+    } else {
+        println!("objectsTotal={}", res.objects_total);
+        println!("objectsDownloaded={}", res.objects_downloaded);
+        println!("objectsSkipped={}", res.objects_skipped);
+        println!("bytesDownloaded={}", format::bytes(res.bytes_downloaded));
+        println!("bytesTotal={}", format::bytes(res.bytes_total));
+    }
+    Ok(())
+}
+
+async fn index_remap_chat(
+    data_dir: &Path,
+    endpoint_id: &str,
+    old_chat: &str,
+    new_chat: &str,
+    mapping_file: &Path,
+    dry_run: bool,
+    json: bool,
+) -> Result<(), CliError> {
+    let text = std::fs::read_to_string(mapping_file).map_err(|e| {
+        CliError::new(
+            ErrorCode::ConfigInvalid,
+            format!("mapping file read failed: {}: {e}", mapping_file.display()),
+        )
+    })?;
+    let remap = ChatRemap::parse_mapping_csv(old_chat, new_chat, &text).map_err(map_core_err)?;
+    let db_path = endpoint_index_db_path(data_dir, endpoint_id);
+    let report = televy_backup_core::chat_remap::remap_index_db_chat(&db_path, &remap, dry_run)
+        .await
+        .map_err(map_core_err)?;
+    tracing::info!(
+        event = "index_db.chat_remapped",
+        db_path = %db_path.display(),
+        endpoint_id,
+        old_chat_id = remap.old_chat_id(),
+        new_chat_id = remap.new_chat_id(),
+        dry_run,
+        remapped = report.remapped(),
+        unmapped = report.unmapped,
+        "index_db.chat_remapped"
+    );
+
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({
+                "endpointId": endpoint_id,
+                "oldChatId": remap.old_chat_id(),
+                "newChatId": remap.new_chat_id(),
+                "dryRun": dry_run,
+                "mappingEntries": remap.len(),
+                "remapped": report.remapped(),
+                "chunkObjectsRemapped": report.chunk_objects_remapped,
+                "remoteIndexesRemapped": report.remote_indexes_remapped,
+                "remoteIndexPartsRemapped": report.remote_index_parts_remapped,
+                "endpointStateRemapped": report.endpoint_state_remapped,
+                "unmapped": report.unmapped,
+            })
+        );
+    } else {
+        println!("dryRun={dry_run}");
+        println!("remapped={}", report.remapped());
+        println!("chunkObjectsRemapped={}", report.chunk_objects_remapped);
+        println!("remoteIndexesRemapped={}", report.remote_indexes_remapped);
+        println!(
+            "remoteIndexPartsRemapped={}",
+            report.remote_index_parts_remapped
+        );
+        println!("endpointStateRemapped={}", report.endpoint_state_remapped);
+        println!("unmapped={}", report.unmapped);
+    }
+    Ok(())
+}
+
+/// Chats moved with `index remap-chat`; restore and verify read their object ids through
+/// [`RemappedStorage`].
+async fn endpoint_chat_remaps(
+    data_dir: &Path,
+    endpoint_id: &str,
+) -> Result<Vec<ChatRemap>, CliError> {
+    televy_backup_core::chat_remap::load_chat_remaps(&endpoint_index_db_path(data_dir, endpoint_id))
+        .await
+        .map_err(map_core_err)
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DoctorCheck {
+    check: &'static str,
+    ok: bool,
+    message: String,
+}
+
+impl DoctorCheck {
+    fn new(check: &'static str, problem: Result<String, String>) -> Self {
+        let (ok, message) = match problem {
+            Ok(message) => (true, message),
+            Err(message) => (false, message),
+        };
+        Self { check, ok, message }
+    }
+}
+
+/// `doctor`: reports every check, problems included; the exit status stays 0.
+async fn privacy_audit(
+    config_dir: &Path,
+    data_dir: &Path,
+    endpoint_id: Option<String>,
+    snapshot_id: &str,
+    json: bool,
+) -> Result<(), CliError> {
+    use televy_backup_core::privacy_audit::{
+        PrivacyAuditConfig, SANCTIONED_LOCATIONS, audit_backup_privacy, privacy_needles,
+    };
+
+    let settings = load_settings(config_dir)?;
+    let ep = select_endpoint(&settings, endpoint_id.as_deref())?;
+    let db_path = endpoint_index_db_path(data_dir, &ep.id);
+    let filemap_db_path =
+        endpoint_filemap_dir(data_dir, &ep.id).join(format!("{snapshot_id}.sqlite"));
+    if !filemap_db_path.exists() {
+        return Err(snapshot_not_found(
+            snapshot_id,
+            format!(
+                "no local file map for snapshot: {} (run `televybackup index export` first)",
+                filemap_db_path.display()
+            ),
+        ));
+    }
+    let listing = televy_backup_core::snapshot_listing::read_snapshot_listing(
+        &filemap_db_path,
+        db_path.exists().then_some(db_path.as_path()),
+        snapshot_id,
+    )
+    .await
+    .map_err(map_core_err)?;
+    let target_id = settings
+        .targets
+        .iter()
+        .find(|t| t.endpoint_id == ep.id && t.source_path == listing.source_path)
+        .map(|t| t.id.clone())
+        .unwrap_or_else(|| "privacy-audit".to_string());
+
+    let report = audit_backup_privacy(PrivacyAuditConfig {
+        source_path: PathBuf::from(&listing.source_path),
+        target_id,
+        label: listing.label.clone(),
+        chunking: ChunkingConfig {
+            min_bytes: settings.chunking.min_bytes,
+            avg_bytes: settings.chunking.avg_bytes,
+            max_bytes: settings.chunking.max_bytes,
+        },
+        needles: privacy_needles(
+            &listing.source_path,
+            listing.files.iter().map(|f| f.path.as_str()),
+        ),
+    })
+    .await
+    .map_err(map_core_err)?;
+
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({
+                "snapshotId": snapshot_id,
+                "sourcePath": listing.source_path,
+                "objectsChecked": report.objects_checked,
+                "bytesChecked": report.bytes_checked,
+                "needles": report.needles,
+                "sanctioned": SANCTIONED_LOCATIONS,
+                "findings": report.findings.iter().map(|f| serde_json::json!({
+                    "location": f.location.as_str(),
+                    "objectName": f.object_name,
+                    "kind": f.kind.map(|k| k.as_str()),
+                    "needle": f.needle,
+                })).collect::<Vec<_>>(),
+            })
+        );
+    } else {
+        println!("snapshotId={snapshot_id}");
+        println!(
+            "objectsChecked={} bytesChecked={} needles={}",
+            report.objects_checked, report.bytes_checked, report.needles
+        );
+        for f in &report.findings {
+            println!(
+                "finding location={} object={} kind={} needle={:?}",
+                f.location.as_str(),
+                f.object_name,
+                f.kind.map(|k| k.as_str()).unwrap_or("-"),
+                f.needle
+            );
+        }
+        for place in SANCTIONED_LOCATIONS {
+            println!("sanctioned={place}");
+        }
+        println!("findings={}", report.findings.len());
+    }
+    if !report.findings.is_empty() {
+        return Err(CliError::new(
+            ErrorCode::PrivacyLeak,
+            format!(
+                "{} place(s) reveal a file name outside an encrypted payload",
+                report.findings.len()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// `version --all` reports skew instead of refusing, so it still works against a stale daemon.
+async fn version(data_dir: &Path, all: bool, json: bool) -> Result<(), CliError> {
+    let cli = cli_component_version();
+    let mut out = serde_json::json!({
+        "cli": {
+            "version": cli.version,
+            "settingsVersion": cli.schema,
+            "helperProtocolVersion": version::MTPROTO_HELPER_PROTOCOL_VERSION,
+        },
+    });
+    if !all {
+        if json {
+            println!("{out}");
+        } else {
+            println!("version={}", env!("CARGO_PKG_VERSION"));
+            println!(
+                "settingsVersion={}",
+                settings_config::SETTINGS_SCHEMA_VERSION
+            );
+        }
+        return Ok(());
+    }
+
+    let mut skews = Vec::new();
+    let mut helper = None::<(televy_backup_core::MtProtoHelperVersion, &str)>;
+    let mut helper_error = None::<String>;
+    match control_ipc_exchange(
+        data_dir,
+        "daemon.version",
+        serde_json::json!({}),
+        Duration::from_secs(15),
+        Duration::from_secs(5),
+    ) {
+        Ok(resp) => {
+            let daemon = resp.versions.component_version();
+            out["daemon"] = serde_json::json!({
+                "version": daemon.version,
+                "settingsVersion": daemon.schema,
+            });
+            skews.extend(version::check_skew(&cli, &daemon));
+            // Daemons predating `daemon.version` answer `control.method_not_found`.
+            if let Some(result) = resp.result.filter(|_| resp.ok).and_then(|v| {
+                serde_json::from_value::<televy_backup_core::control::DaemonVersionResult>(v).ok()
+            }) {
+                helper = result.helper.map(|h| (h, "daemon"));
+                helper_error = result.helper_error;
+            }
+        }
+        Err(e) => {
+            out["daemon"] = serde_json::json!({
+                "error": { "code": e.code, "message": e.message },
+            });
+        }
+    }
+    if helper.is_none() && helper_error.is_none() {
+        match tokio::task::spawn_blocking(|| televy_backup_core::probe_mtproto_helper(None)).await {
+            Ok(Ok(h)) => helper = Some((h, "cli")),
+            Ok(Err(e)) => helper_error = Some(e.to_string()),
+            Err(e) => helper_error = Some(e.to_string()),
+        }
+    }
+    out["helper"] = match &helper {
+        Some((h, probed_by)) => {
+            skews.extend(version::check_skew(&cli, &h.component_version()));
+            serde_json::json!({
+                "path": h.path.display().to_string(),
+                "version": h.version,
+                "protocolVersion": h.protocol_version,
+                "probedBy": probed_by,
+            })
+        }
+        None => serde_json::json!({
+            "error": { "message": helper_error.unwrap_or_default() },
+        }),
+    };
+    out["skew"] = skews
+        .iter()
+        .map(|skew| {
+            serde_json::json!({
+                "component": skew.stale().component,
+                "breaking": skew.breaking,
+                "message": skew.message(),
+            })
+        })
+        .collect();
+
+    if json {
+        println!("{out}");
+        return Ok(());
+    }
+    let field = |v: &serde_json::Value, key: &str| match &v[key] {
+        serde_json::Value::Null => "unknown".to_string(),
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    println!("cli={}", env!("CARGO_PKG_VERSION"));
+    println!(
+        "cliSettingsVersion={}",
+        settings_config::SETTINGS_SCHEMA_VERSION
+    );
+    match out["daemon"].get("error") {
+        Some(err) => println!("daemon=unavailable ({})", field(err, "message")),
+        None => {
+            println!("daemon={}", field(&out["daemon"], "version"));
+            println!(
+                "daemonSettingsVersion={}",
+                field(&out["daemon"], "settingsVersion")
+            );
+        }
+    }
+    match out["helper"].get("error") {
+        Some(err) => println!("helper=unavailable ({})", field(err, "message")),
+        None => {
+            println!("helper={}", field(&out["helper"], "version"));
+            println!(
+                "helperProtocolVersion={}",
+                field(&out["helper"], "protocolVersion")
+            );
+            println!("helperPath={}", field(&out["helper"], "path"));
+        }
+    }
+    for skew in &skews {
+        eprintln!("warning: {}", skew.message());
+    }
+    Ok(())
+}
+
+fn data_dir_show(
+    config_dir: &Path,
+    data_dir: &Path,
+    source: data_dir::DataDirSource,
+    json: bool,
+) -> Result<(), CliError> {
+    let pointer_file = data_dir::data_dir_pointer_path(config_dir);
+    let pointer = data_dir::read_data_dir_pointer(config_dir).map_err(map_core_err)?;
+    let entries = data_dir::probe_data_dir(data_dir).map_err(map_core_err)?;
+
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({
+                "dataDir": data_dir,
+                "source": source,
+                "pointerFile": pointer_file,
+                "pointer": pointer,
+                "entries": entries,
+                "bytesTotal": entries.iter().map(|e| e.bytes).sum::<u64>(),
+            })
+        );
+    } else {
+        println!("dataDir={}", data_dir.display());
+        println!("source={}", source.as_str());
+        match &pointer {
+            Some(p) => println!("pointer={} ({})", p.display(), pointer_file.display()),
+            None => println!("pointer=none"),
+        }
+        for e in &entries {
+            println!(
+                "entry={} files={} bytes={}",
+                e.name,
+                e.files,
+                format::bytes(e.bytes)
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn data_dir_migrate(
+    config_dir: &Path,
+    data_dir: &Path,
+    source: data_dir::DataDirSource,
+    to: &Path,
+    mode: data_dir::DataDirMigrateMode,
+    json: bool,
+) -> Result<(), CliError> {
+    let report = data_dir::migrate_data_dir(data_dir, to, config_dir, mode)
+        .await
+        .map_err(map_core_err)?;
+    let launch_agents = launch_agents_overriding_data_dir();
+    let rollback = match mode {
+        data_dir::DataDirMigrateMode::Copy => {
+            format!("rm '{}'", report.pointer_file.display())
+        }
+        data_dir::DataDirMigrateMode::Move => format!(
+            "move {} from '{}' back to '{}', then rm '{}'",
+            report.entries.join(", "),
+            report.to.display(),
+            report.from.display(),
+            report.pointer_file.display()
+        ),
+    };
+
+    if json {
+        let mut out = serde_json::to_value(&report).unwrap_or_default();
+        out["source"] = serde_json::json!(source);
+        out["launchAgentsWithOverride"] = serde_json::json!(launch_agents);
+        out["rollback"] = serde_json::json!(rollback);
+        println!("{out}");
+        return Ok(());
+    }
+
+    println!("from={}", report.from.display());
+    println!("to={}", report.to.display());
+    println!("entries={}", report.entries.join(","));
+    println!("files={}", report.files);
+    println!("bytes={}", format::bytes(report.bytes));
+    println!("databasesChecked={}", report.databases_checked);
+    println!("pointerFile={}", report.pointer_file.display());
+    if report.skipped > 0 {
+        eprintln!(
+            "note: skipped {} sockets or special files; the daemon recreates them",
+            report.skipped
+        );
+    }
+    if matches!(
+        source,
+        data_dir::DataDirSource::Flag | data_dir::DataDirSource::Env
+    ) {
+        eprintln!(
+            "note: this command's data dir came from --data-dir or {}; both take precedence over the pointer file, so drop them (or point them at {}) for later commands",
+            data_dir::DATA_DIR_ENV,
+            report.to.display()
+        );
+    }
+    for plist in &launch_agents {
+        eprintln!(
+            "launchd: {} sets {}, which takes precedence over the pointer file; change it to {} (or remove it), then reload with `launchctl bootout gui/$(id -u) '{}' && launchctl bootstrap gui/$(id -u) '{}'`",
+            plist.display(),
+            data_dir::DATA_DIR_ENV,
+            report.to.display(),
+            plist.display(),
+            plist.display()
+        );
+    }
+    eprintln!("rollback: {rollback}");
+    Ok(())
+}
+
+/// User LaunchAgents whose plist sets the data dir env var, which would shadow the pointer file.
+fn launch_agents_overriding_data_dir() -> Vec<PathBuf> {
+    let Ok(home) = std::env::var("HOME") else {
+        return Vec::new();
+    };
+    let Ok(entries) = std::fs::read_dir(Path::new(&home).join("Library").join("LaunchAgents"))
+    else {
+        return Vec::new();
+    };
+    let mut plists = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "plist"))
+        .filter(|p| {
+            std::fs::read(p).is_ok_and(|bytes| {
+                bytes
+                    .windows(data_dir::DATA_DIR_ENV.len())
+                    .any(|w| w == data_dir::DATA_DIR_ENV.as_bytes())
+            })
+        })
+        .collect::<Vec<_>>();
+    plists.sort();
+    plists
+}
+
+async fn doctor(config_dir: &Path, data_dir: &Path, json: bool) -> Result<(), CliError> {
+    // Loaded without validation so one bad field does not hide the others.
+    let settings = settings_config::load_settings_v2(config_dir);
+    let timezone = match &settings {
+        Ok(settings) => {
+            televy_backup_core::schedule_tz::ScheduleTz::parse(&settings.schedule.timezone)
+                .map(|tz| tz.resolved_name())
+        }
+        Err(e) => Err(format!("settings unreadable: {e}")),
+    };
+
+    let snap = match read_status_snapshot_from_ipc(data_dir).await {
+        Ok(snap) => Ok(snap),
+        Err(_) => read_status_snapshot_from_file(config_dir, data_dir),
+    };
+    let scheduler = match &snap {
+        Ok(snap) => match snap.schedule_status() {
+            Some(schedule) => {
+                let running = snap.targets.iter().any(|t| t.state == "running");
+                scheduler_tick_check(
+                    &schedule,
+                    running,
+                    televy_backup_core::status::now_unix_ms(),
+                )
+            }
+            None => Err("the daemon has not reported a scheduler tick yet".to_string()),
+        },
+        Err(_) => Err("no daemon status (is televybackupd running?)".to_string()),
+    };
+
+    let target_overlaps = match &settings {
+        Ok(settings) => {
+            let warnings = settings_config::settings_warnings(settings);
+            if warnings.is_empty() {
+                Ok("no target source paths overlap".to_string())
+            } else {
+                Err(warnings
+                    .into_iter()
+                    .map(|w| w.message)
+                    .collect::<Vec<_>>()
+                    .join("; "))
+            }
+        }
+        Err(e) => Err(format!("settings unreadable: {e}")),
+    };
+
+    let checks = [
+        DoctorCheck::new("schedule.timezone", timezone),
+        DoctorCheck::new("targets.overlap", target_overlaps),
+        DoctorCheck::new("daemon.scheduler", scheduler),
+        DoctorCheck::new("macos.full_disk_access", full_disk_access_check()),
+    ];
+    if json {
+        let ok = checks.iter().all(|c| c.ok);
+        println!("{}", serde_json::json!({ "ok": ok, "checks": checks }));
+        return Ok(());
+    }
+    for c in &checks {
+        println!(
+            "check={} status={} {}",
+            c.check,
+            if c.ok { "ok" } else { "problem" },
+            c.message
+        );
+    }
+    Ok(())
+}
+
+/// Lists the Full Disk Access canary as this process; the daemon's own grant may differ.
+fn full_disk_access_check() -> Result<String, String> {
+    use televy_backup_core::full_disk_access::{self, Access};
+
+    if !cfg!(target_os = "macos") {
+        return Ok("not macOS".to_string());
+    }
+    let Ok(home) = std::env::var("HOME") else {
+        return Ok("HOME is not set; nothing to probe".to_string());
+    };
+    let canary = full_disk_access::canary(Path::new(&home));
+    match full_disk_access::probe(Path::new(&home)) {
+        Access::Granted => Ok(format!("{} is readable", canary.display())),
+        Access::Missing => Err(format!(
+            "{} is not readable: backups will miss Mail, Messages, Safari and other protected folders; grant TelevyBackup and televybackupd Full Disk Access in {}",
+            canary.display(),
+            full_disk_access::SETTINGS_PANE
+        )),
+        Access::Unknown => Ok(format!(
+            "{} could not be probed; nothing to check",
+            canary.display()
+        )),
+    }
+}
+
+/// The scheduler ticks every `tickIntervalSeconds` except while a backup runs; a last tick older
+/// than twice that means the daemon is stuck or was stopped.
+fn scheduler_tick_check(
+    schedule: &televy_backup_core::status::ScheduleStatus,
+    running: bool,
+    now_ms: u64,
+) -> Result<String, String> {
+    let summary = format!(
+        "timezone={} catch_up={} missed_slots_caught_up={}",
+        schedule.timezone, schedule.catch_up, schedule.missed_slots_caught_up
+    );
+    let Some(last_tick_at) = schedule.last_tick_at else {
+        return Err(format!("no scheduler tick yet; {summary}"));
+    };
+    let age_ms = now_ms.saturating_sub(last_tick_at);
+    if !running && age_ms > 2 * schedule.tick_interval_seconds.max(1) * 1000 {
+        return Err(format!(
+            "last scheduler tick {}s ago (ticks every {}s); {summary}",
+            age_ms / 1000,
+            schedule.tick_interval_seconds
+        ));
+    }
+    Ok(summary)
+}
+
+fn targets_list(config_dir: &Path, json: bool) -> Result<(), CliError> {
+    let settings = load_settings(config_dir)?;
+    let now = chrono::Utc::now();
+    if json {
+        let targets = settings
+            .targets
+            .iter()
+            .map(|t| {
+                serde_json::json!({
+                    "targetId": t.id,
+                    "label": t.label,
+                    "sourcePath": t.source_path,
+                    "endpointId": t.endpoint_id,
+                    "enabled": t.enabled,
+                    "disabledUntil": t.disabled_until,
+                    "active": t.enabled_at(&now),
+                })
+            })
+            .collect::<Vec<_>>();
+        println!("{}", serde_json::json!({ "targets": targets }));
+        return Ok(());
+    }
+    for t in &settings.targets {
+        println!(
+            "target_id={} enabled={} disabled_until={} active={} endpoint_id={} source_path={}",
+            t.id,
+            t.enabled,
+            t.disabled_until.as_deref().unwrap_or("-"),
+            t.enabled_at(&now),
+            t.endpoint_id,
+            t.source_path,
+        );
+    }
+    Ok(())
+}
+
+/// `targets enable/disable`: `enabled` and `disabled_until` go through the normal settings save;
+/// a running daemon picks them up on its next config reload.
+fn targets_set_enabled(
+    config_dir: &Path,
+    target_id: &str,
+    enabled: bool,
+    until: Option<&str>,
+    json: bool,
+) -> Result<(), CliError> {
+    let disabled_until = until
+        .map(|raw| snapshot_time_bound("--until", raw))
+        .transpose()?;
+    let mut settings = load_settings(config_dir)?;
+    let target = settings
+        .targets
+        .iter_mut()
+        .find(|t| t.id == target_id)
+        .ok_or_else(|| {
+            CliError::new(
+                ErrorCode::ConfigInvalid,
+                format!("unknown target_id: {target_id}"),
+            )
+        })?;
+    target.enabled = enabled;
+    target.disabled_until = disabled_until;
+    let disabled_until = target.disabled_until.clone();
+    settings_config::save_settings_v2(config_dir, &settings).map_err(map_core_err)?;
+
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({
+                "targetId": target_id,
+                "enabled": enabled,
+                "disabledUntil": disabled_until,
+            })
+        );
+    } else {
+        println!(
+            "target_id={target_id} enabled={enabled} disabled_until={}",
+            disabled_until.as_deref().unwrap_or("-")
+        );
+    }
+    Ok(())
+}
+
+fn security_set_restore_passphrase(
+    config_dir: &Path,
+    data_dir: &Path,
+    json: bool,
+) -> Result<(), CliError> {
+    let mut settings = load_settings(config_dir)?;
+    let passphrase = read_passphrase_line("Restore passphrase")?;
+    if passphrase.is_empty() {
+        return Err(CliError::new(
+            ErrorCode::ConfigInvalid,
+            "passphrase is empty",
+        ));
+    }
+    if read_passphrase_line("Repeat restore passphrase")? != passphrase {
+        return Err(CliError::new(
+            ErrorCode::ConfigInvalid,
+            "passphrases do not match",
+        ));
+    }
+
+    let hash =
+        televy_backup_core::security::hash_restore_passphrase(&passphrase).map_err(map_core_err)?;
+    settings.security.restore_passphrase_hash = Some(hash);
+    settings_config::save_settings_v2(config_dir, &settings).map_err(map_core_err)?;
+    record_audit(
+        data_dir,
+        televy_backup_core::audit::AUDIT_OP_RESTORE_PASSPHRASE_SET,
+        serde_json::json!({
+            "restoreRequiresPassphrase": settings.security.restore_requires_passphrase,
+        }),
+    );
+
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({
+                "ok": true,
+                "restoreRequiresPassphrase": settings.security.restore_requires_passphrase,
+            })
+        );
+    } else {
+        println!("ok");
+    }
+    Ok(())
+}
+
+/// `restore --require-passphrase`: checks the restore passphrase before a local run. A running
+/// daemon checks it (`security.authorizeRestore`), so wrong guesses count against the same lockout
+/// as every other caller's; without one it is checked here. The gate is advisory: anyone who can
+/// read the secrets store can restore without it.
+fn confirm_restore_passphrase(config_dir: &Path, data_dir: &Path) -> Result<(), CliError> {
+    let settings = load_settings(config_dir)?;
+    let Some(hash) = settings.security.restore_passphrase_hash.as_deref() else {
+        return Err(CliError::new(
+            ErrorCode::SecurityPassphraseNotSet,
+            "no restore passphrase is set (televybackup security set-restore-passphrase)",
+        ));
+    };
+    let passphrase = read_passphrase_line("Restore passphrase")?;
+    match daemon_authorize_restore(data_dir, &passphrase) {
+        Ok(true) => return Ok(()),
+        // The daemon doesn't require the passphrase, so it didn't check it.
+        Ok(false) => {}
+        Err(e) if control_ipc_unreachable(&e) => {}
+        Err(e) => return Err(e),
+    }
+    if !televy_backup_core::security::verify_restore_passphrase(hash, &passphrase)
+        .map_err(map_core_err)?
+    {
+        return Err(CliError::new(
+            ErrorCode::SecurityPassphraseInvalid,
+            "invalid restore passphrase",
+        ));
+    }
+    Ok(())
+}
+
+/// Asks the daemon to check a restore passphrase; `Ok(false)` when it does not require one.
+/// Its `security.*` errors keep their codes.
+fn daemon_authorize_restore(data_dir: &Path, passphrase: &str) -> Result<bool, CliError> {
+    let params = televy_backup_core::control::SecurityAuthorizeRestoreParams {
+        passphrase: Some(passphrase.to_string()),
+    };
+    let params = serde_json::to_value(params).unwrap_or_else(|_| serde_json::json!({}));
+    let resp = control_ipc_call(data_dir, "security.authorizeRestore", params).map_err(|e| {
+        let daemon_code = e
+            .details
+            .get("daemonCode")
+            .and_then(|v| v.as_str())
+            .and_then(ErrorCode::from_code);
+        match daemon_code {
+            Some(
+                code @ (ErrorCode::SecurityPassphraseInvalid
+                | ErrorCode::SecurityPassphraseRequired
+                | ErrorCode::SecurityPassphraseNotSet),
+            ) => CliError {
+                code,
+                details: e.details["daemonDetails"].clone(),
+                ..e
+            },
+            _ => e,
+        }
+    })?;
+    let result: televy_backup_core::control::SecurityAuthorizeRestoreResult = resp
+        .result
+        .and_then(|v| serde_json::from_value(v).ok())
+        .ok_or_else(|| CliError::new(ErrorCode::ControlFailed, "missing result"))?;
+    Ok(result.passphrase_required)
+}
+
+/// Reads one line from stdin; on a terminal the prompt goes to stderr and input is not echoed.
+fn read_passphrase_line(prompt: &str) -> Result<String, CliError> {
+    let tty = std::io::stdin().is_terminal();
+    if tty {
+        eprint!("{prompt}: ");
+        let _ = std::io::stderr().flush();
+    }
+    let mut line = String::new();
+    let res = {
+        let _echo_off = StdinEchoOff::enable();
+        std::io::stdin().read_line(&mut line)
+    };
+    if tty {
+        eprintln!();
+    }
+    res.map_err(|e| CliError::new(ErrorCode::ConfigReadFailed, e.to_string()))?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn audit_list(data_dir: &Path, limit: u32, json: bool) -> Result<(), CliError> {
+    let entries = televy_backup_core::audit::read_audit_entries(data_dir)
+        .map_err(|e| CliError::new(ErrorCode::AuditReadFailed, e.to_string()))?;
+    let skip = entries.len().saturating_sub(limit as usize);
+    let entries = &entries[skip..];
+
+    if json {
+        println!("{}", serde_json::json!({ "entries": entries }));
+    } else {
+        for e in entries {
+            println!(
+                "seq={} ts={} actor={} op={} details={}",
+                e.seq,
+                e.ts,
+                serde_json::to_value(e.actor)
+                    .ok()
+                    .and_then(|v| v.as_str().map(str::to_string))
+                    .unwrap_or_default(),
+                e.op,
+                e.details,
+            );
+        }
+    }
+    Ok(())
+}
+
+fn audit_verify(data_dir: &Path, json: bool) -> Result<(), CliError> {
+    let report = televy_backup_core::audit::verify_audit_log(data_dir)
+        .map_err(|e| CliError::new(ErrorCode::AuditReadFailed, e.to_string()))?;
+
+    if let Some(broken) = &report.first_broken {
+        return Err(CliError::new(
+            ErrorCode::AuditChainBroken,
+            format!(
+                "audit log chain broken at line {}: {}",
+                broken.line, broken.reason
+            ),
+        )
+        .with_details(serde_json::json!({
+            "entriesOk": report.entries_ok,
+            "firstBroken": broken,
+        })));
+    }
+
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({ "ok": true, "entries": report.entries_ok, "lastHash": report.last_hash })
+        );
+    } else {
+        println!(
+            "ok entries={} lastHash={}",
+            report.entries_ok,
+            report.last_hash.as_deref().unwrap_or("-")
+        );
+    }
+    Ok(())
+}
+
+/// `backup run --respect-quiet-hours`: waits while `quiet_hours` are in effect. `config.toml` is
+/// re-read every minute, so shortening or removing the quiet hours ends the wait early.
+async fn wait_out_quiet_hours(config_dir: &Path) -> Result<(), CliError> {
+    let cancel = cancel_on_stop_signal();
+    let mut announced = false;
+    loop {
+        let settings = load_settings(config_dir)?;
+        let tz = ScheduleTz::parse(&settings.schedule.timezone).unwrap_or(ScheduleTz::Local);
+        let now = chrono::Utc::now();
+        let Some(until) = quiet_hours::quiet_until(&settings.quiet_hours, &tz, now) else {
+            return Ok(());
+        };
+        if !announced {
+            announced = true;
+            tracing::warn!(
+                event = "backup.waiting_quiet_hours",
+                until = %until.to_rfc3339(),
+                "backup.waiting_quiet_hours"
+            );
+            eprintln!(
+                "waiting for quiet hours to end at {}",
+                until.with_timezone(&tz).to_rfc3339()
+            );
+        }
+        let wait = (until - now)
+            .to_std()
+            .unwrap_or_default()
+            .min(Duration::from_secs(60));
+        tokio::select! {
+            _ = cancel.cancelled() => {
+                return Err(CliError::new(
+                    ErrorCode::TaskCancelled,
+                    "cancelled while waiting for quiet hours to end",
+                ));
+            }
+            _ = tokio::time::sleep(wait) => {}
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn backup_run(
+    config_dir: &Path,
+    data_dir: &Path,
+    target_id: Option<String>,
+    source: Option<PathBuf>,
+    label: Option<String>,
+    no_remote_index_sync: bool,
+    yes: bool,
+    strict: bool,
+    cross_filesystems: bool,
+    respect_quiet_hours: bool,
+    accept_source_change: bool,
+    json: bool,
+    events: bool,
+    import: Option<&ImportSnapshot>,
+    metrics_textfile: Option<&Path>,
+) -> Result<(), CliError> {
+    let task_id = format!("tsk_{}", uuid::Uuid::new_v4());
+    let run_log = televy_backup_core::run_log::start_run_log("backup", &task_id, data_dir)
+        .map_err(|e| CliError::new(ErrorCode::LogInitFailed, e.to_string()))?;
+
+    let started = std::time::Instant::now();
+
+    let settings = match load_settings(config_dir) {
+        Ok(s) => s,
+        Err(e) => {
+            return emit_preflight_failed(
+                events,
+                &task_id,
+                "backup",
+                run_log.path(),
+                started,
+                RunCtx {
+                    target_id: None,
+                    endpoint_id: None,
+                    source_path: None,
+                    snapshot_id: None,
+                },
+                e,
+            );
+        }
+    };
+    prune_run_logs_best_effort(data_dir, &settings);
+    let one_file_system = settings.scan.one_file_system && !cross_filesystems;
+    // Imported trees are kept as they were extracted.
+    let default_excludes = settings.scan.default_excludes && import.is_none();
+
+    let target = match select_target(&settings, target_id.as_deref(), source.as_deref()) {
+        Ok(t) => t,
+        Err(e) => {
+            return emit_preflight_failed(
+                events,
+                &task_id,
+                "backup",
+                run_log.path(),
+                started,
+                RunCtx {
+                    target_id: None,
+                    endpoint_id: None,
+                    source_path: None,
+                    snapshot_id: None,
+                },
+                e,
+            );
+        }
+    };
+
+    let ep = match settings
+        .telegram_endpoints
+        .iter()
+        .find(|e| e.id == target.endpoint_id)
+    {
+        Some(ep) => ep,
+        None => {
+            let e = CliError::new(
+                ErrorCode::ConfigInvalid,
+                format!(
+                    "target references unknown endpoint_id: target_id={} endpoint_id={}",
+                    target.id, target.endpoint_id
+                ),
+            );
+            return emit_preflight_failed(
+                events,
+                &task_id,
+                "backup",
+                run_log.path(),
+                started,
+                RunCtx {
+                    target_id: Some(target.id.as_str()),
+                    endpoint_id: None,
+                    source_path: Some(target.source_path.as_str()),
+                    snapshot_id: None,
+                },
+                e,
+            );
+        }
+    };
+
+    if ep.mtproto_api_id(&settings.telegram.mtproto) <= 0 {
+        let e = CliError::new(
+            ErrorCode::ConfigInvalid,
+            "telegram.mtproto.api_id must be > 0",
+        );
+        return emit_preflight_failed(
+            events,
+            &task_id,
+            "backup",
+            run_log.path(),
+            started,
+            RunCtx {
+                target_id: Some(target.id.as_str()),
+                endpoint_id: Some(ep.id.as_str()),
+                source_path: Some(target.source_path.as_str()),
+                snapshot_id: None,
+            },
+            e,
+        );
+    }
+    if ep
+        .mtproto_api_hash_key(&settings.telegram.mtproto)
+        .is_empty()
+    {
+        let e = CliError::new(
+            ErrorCode::ConfigInvalid,
+            "telegram.mtproto.api_hash_key must not be empty",
+        );
+        return emit_preflight_failed(
+            events,
+            &task_id,
+            "backup",
+            run_log.path(),
+            started,
+            RunCtx {
+                target_id: Some(target.id.as_str()),
+                endpoint_id: Some(ep.id.as_str()),
+                source_path: Some(target.source_path.as_str()),
+                snapshot_id: None,
+            },
+            e,
+        );
+    }
+    if ep.chat_id.is_empty() {
+        let e = CliError::new(
+            ErrorCode::ConfigInvalid,
+            format!("telegram_endpoints[{id}].chat_id is empty", id = ep.id),
+        );
+        return emit_preflight_failed(
+            events,
+            &task_id,
+            "backup",
+            run_log.path(),
+            started,
+            RunCtx {
+                target_id: Some(target.id.as_str()),
+                endpoint_id: Some(ep.id.as_str()),
+                source_path: Some(target.source_path.as_str()),
+                snapshot_id: None,
+            },
+            e,
+        );
+    }
+
+    let ctx_target_id = target.id.clone();
     let ctx_endpoint_id = ep.id.clone();
     let ctx_source_path = target.source_path.clone();
 
@@ -4155,6 +9942,25 @@ async fn backup_run(
         "run.start"
     );
 
+    // Disabling only pauses scheduled runs; an explicit run goes ahead.
+    if !target.enabled_at(&chrono::Utc::now()) {
+        tracing::warn!(
+            event = "backup.target_disabled",
+            target_id = %target.id,
+            disabled_until = target.disabled_until.as_deref().unwrap_or("-"),
+            "backup.target_disabled"
+        );
+        eprintln!(
+            "warning: target {} is disabled{}; running anyway",
+            target.id,
+            target
+                .disabled_until
+                .as_deref()
+                .map(|t| format!(" until {t}"))
+                .unwrap_or_default(),
+        );
+    }
+
     emit_task_state_running(
         events,
         &task_id,
@@ -4168,14 +9974,32 @@ async fn backup_run(
     }
 
     let result: Result<televy_backup_core::BackupResult, CliError> = async {
+        // Manual runs ignore quiet hours unless asked to respect them; the throttle then follows
+        // `quiet_hours` (edits included) until the run ends.
+        let upload_throttle = if respect_quiet_hours {
+            wait_out_quiet_hours(config_dir).await?;
+            Some(Arc::new(quiet_hours::UploadThrottle::default()))
+        } else {
+            None
+        };
+        let throttle_follower = CancellationToken::new();
+        if let Some(throttle) = &upload_throttle {
+            tokio::spawn(quiet_hours::follow_settings(
+                config_dir.to_path_buf(),
+                Arc::clone(throttle),
+                throttle_follower.clone(),
+            ));
+        }
+        let _stop_throttle_follower = throttle_follower.drop_guard();
+
         let bot_token = get_secret(config_dir, data_dir, &ep.bot_token_key)?
-            .ok_or_else(|| CliError::new("telegram.unauthorized", "bot token missing"))?;
+            .ok_or_else(|| CliError::new(ErrorCode::TelegramUnauthorized, "bot token missing"))?;
         let master_key = load_master_key(config_dir, data_dir)?;
 
         let db_path = endpoint_index_db_path(data_dir, &ep.id);
         if let Some(parent) = db_path.parent() {
             std::fs::create_dir_all(parent)
-                .map_err(|e| CliError::new("config.write_failed", e.to_string()))?;
+                .map_err(|e| CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()))?;
         }
 
         let sink = NdjsonProgressSink {
@@ -4193,35 +10017,39 @@ async fn backup_run(
         let dedupe_db_path = endpoint_dedupe_db_path(data_dir, &ep.id);
         let dedupe_pending_db_path = endpoint_dedupe_pending_db_path(data_dir, &ep.id);
 
-        let api_hash = get_secret(config_dir, data_dir, &settings.telegram.mtproto.api_hash_key)?
-            .ok_or_else(|| CliError::new("telegram.mtproto.missing_api_hash", "mtproto api_hash missing"))?;
+        let api_hash = get_secret(config_dir, data_dir, ep.mtproto_api_hash_key(&settings.telegram.mtproto))?
+            .ok_or_else(|| CliError::new(ErrorCode::TelegramMtprotoMissingApiHash, "mtproto api_hash missing"))?;
         let session = load_optional_base64_secret_bytes(
             config_dir,
             data_dir,
             &ep.mtproto.session_key,
-            "telegram.mtproto.session_invalid",
+            ErrorCode::TelegramMtprotoSessionInvalid,
             "invalid mtproto session (try: `televybackup secrets clear-telegram-mtproto-session`)",
         )?;
 
         let cache_dir = data_dir.join("cache").join("mtproto");
         std::fs::create_dir_all(&cache_dir)
-            .map_err(|e| CliError::new("config.write_failed", e.to_string()))?;
+            .map_err(|e| CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()))?;
 
         let provider = settings_config::endpoint_provider(&ep.id);
         let storage = TelegramMtProtoStorage::connect(TelegramMtProtoStorageConfig {
             provider,
-            api_id: settings.telegram.mtproto.api_id,
+            api_id: ep.mtproto_api_id(&settings.telegram.mtproto),
             api_hash: api_hash.clone(),
             bot_token: bot_token.clone(),
             chat_id: ep.chat_id.clone(),
+            migrated_from_chat_ids: ep.migrated_from_chat_ids.clone(),
             session,
             cache_dir,
             min_delay_ms: Some(ep.rate_limit.min_delay_ms as u64),
             max_concurrent_uploads: Some(ep.rate_limit.max_concurrent_uploads as usize),
             helper_path: None,
+            bootstrap_pin_mode: ep.bootstrap.pin_mode,
+            object_prefix: endpoint_object_prefix(data_dir, ep),
         })
         .await
         .map_err(map_core_err)?;
+    save_mtproto_chat_migration(config_dir, &ep.id, &storage)?;
 
         let quick_stats_cancel = CancellationToken::new();
         let quick_stats_cancel_for_task = quick_stats_cancel.clone();
@@ -4235,12 +10063,14 @@ async fn backup_run(
                 &filemap_dir,
                 &dedupe_db_path,
                 no_remote_index_sync,
-                is_likely_private_chat_id(&ep.chat_id),
+                settings_config::is_likely_private_chat_id(&ep.chat_id),
                 progress_sink,
             ),
             async {
                 match preflight_local_quick_stats(
-                    Path::new(&target.source_path),
+                    import.map_or(Path::new(&target.source_path), |i| i.dir.as_path()),
+                    one_file_system,
+                    default_excludes,
                     progress_sink,
                     Some(quick_stats_cancel_for_task),
                 )
@@ -4252,7 +10082,7 @@ async fn backup_run(
                             event = "prepare.local_quick_stats_failed",
                             target_id = %target.id,
                             source_path = %target.source_path,
-                            error_code = e.code,
+                            error_code = e.code.as_str(),
                             error_message = %e.message,
                             "prepare.local_quick_stats_failed"
                         );
@@ -4270,6 +10100,70 @@ async fn backup_run(
             }
         };
 
+        let warn_bytes = settings.scan.warn_initial_backup_bytes;
+        if let Some(stats) = quick_stats.as_ref()
+            && warn_bytes > 0
+            && stats.bytes_total > warn_bytes
+            && !has_base_snapshot(&db_path, &target.source_path, storage.provider()).await?
+        {
+            tracing::warn!(
+                event = "backup.initial_size_warning",
+                target_id = %target.id,
+                bytes_total_estimated = stats.bytes_total,
+                warn_initial_backup_bytes = warn_bytes,
+                "backup.initial_size_warning"
+            );
+            eprintln!(
+                "warning: first backup of {} will upload about {} ({} files; scan.warn_initial_backup_bytes = {})",
+                target.source_path,
+                format::human_bytes(stats.bytes_total),
+                stats.files_total,
+                format::human_bytes(warn_bytes),
+            );
+            if !yes {
+                confirm_initial_backup(events, &task_id, &target.id, stats, warn_bytes).await?;
+            }
+        }
+
+        // Imports back up an extracted tree, not the target's source.
+        let source_identity = match import {
+            Some(_) => None,
+            None => Some(
+                televy_backup_core::source_guard::guard_source(
+                    data_dir,
+                    &target.id,
+                    Path::new(&target.source_path),
+                    quick_stats.map(|s| s.files_total),
+                    televy_backup_core::source_guard::SourceGuardLimits {
+                        min_expected_files: target.min_expected_files,
+                        max_file_drop_percent: settings.scan.max_file_drop_percent,
+                    },
+                    accept_source_change,
+                )
+                .map_err(map_core_err)?,
+            ),
+        };
+
+        let device = televy_backup_core::device::load_or_create_device_identity(data_dir)
+            .map_err(map_core_err)?;
+        let label = match (import, label, target.label_template.as_deref()) {
+            (Some(import), ..) => import.label.clone(),
+            (None, Some(label), _) => label,
+            (None, None, Some(template)) => {
+                let hostname = televy_backup_core::device::host_name().unwrap_or_default();
+                let vars = televy_backup_core::label_template::LabelVars {
+                    schedule: "manual",
+                    local_time: chrono::Local::now().naive_local(),
+                    hostname: &hostname,
+                    target_id: &target.id,
+                    device_name: &device.device_name,
+                };
+                televy_backup_core::label_template::expand_label_template(template, &vars)
+                    .map_err(map_core_err)?
+            }
+            (None, None, None) => "manual".to_string(),
+        };
+
         let cfg = BackupConfig {
             endpoint_db_path: db_path.clone(),
             filemap_dir: filemap_dir.clone(),
@@ -4284,21 +10178,65 @@ async fn backup_run(
             },
             rate_limit: ep.rate_limit.clone(),
             master_key,
+            data_key: target.data_key(&master_key, settings.chunking.hash_alg()),
             snapshot_id: None,
             keep_last_snapshots: settings.retention.keep_last_snapshots,
             remote_dedupe: remote_dedupe.clone(),
+            hint_changed_paths: None,
+            device: Some(device),
+            index_full_every: settings.index.full_every,
+            created_at: import.map(|i| i.created_at.clone()),
         };
         let label_for_bootstrap = cfg.label.clone();
+        let device_for_bootstrap = cfg.device.clone();
 
+        // Dropping the guard unmounts and deletes the snapshot, also when the run fails.
+        let apfs_snapshot = if import.is_none() && target.use_apfs_snapshot(&settings.scan) {
+            televy_backup_core::apfs_snapshot::prepare_apfs_snapshot(
+                &target.id,
+                Path::new(&target.source_path),
+            )
+            .await
+        } else {
+            None
+        };
+        let cancel = cancel_on_stop_signal();
+        let recorder = ProgressRecorder::new(progress_sink);
         let opts = BackupOptions {
-            cancel: None,
-            progress: progress_sink,
+            cancel: Some(&cancel),
+            progress: Some(&recorder),
             source_quick_stats: quick_stats,
+            strict: strict || settings.scan.strict,
+            one_file_system,
+            default_excludes,
+            scan_root: import
+                .map(|i| i.dir.as_path())
+                .or_else(|| apfs_snapshot.as_ref().map(|s| s.scan_root())),
+            retry: settings.retry.clone(),
+            worker_threads: settings.performance.worker_threads as usize,
+            verify_after_upload: settings.upload.verify_after_upload,
+            // Imported trees are kept as they were extracted.
+            filters: if import.is_none() {
+                &target.filters
+            } else {
+                &[]
+            },
+            warn_path_bytes: settings.scan.warn_path_bytes,
+            max_unreadable_percent: settings.scan.max_unreadable_percent,
+            upload_throttle: upload_throttle.as_deref(),
+            // An APFS snapshot is crash-consistent already, and its read-only volume leaves
+            // SQLite no place for a WAL database's shared-memory file.
+            sqlite_consistent: if import.is_none() && apfs_snapshot.is_none() {
+                SqliteConsistentOptions::for_target(target, &settings.scan)
+            } else {
+                SqliteConsistentOptions::default()
+            },
         };
 
-        let res = run_backup_with(&storage, cfg, opts)
+        let mut res = run_backup_with(&storage, cfg, opts)
             .await
-            .map_err(map_core_err)?;
+            .map_err(|e| with_partial_result(map_core_err(e), &recorder))?;
+        drop(apfs_snapshot);
 
         // Update remote bootstrap/catalog for cross-device restore. This uses Telegram pinned
         // messages; if chat_id points at a private user dialog, MTProto bots can't rely on pinning.
@@ -4308,7 +10246,7 @@ async fn backup_run(
                 reason = "flag_no_remote_index_sync",
                 "skipping bootstrap catalog update (--no-remote-index-sync)"
             );
-        } else if is_likely_private_chat_id(&ep.chat_id) {
+        } else if settings_config::is_likely_private_chat_id(&ep.chat_id) {
             tracing::warn!(
                 event = "bootstrap.skipped",
                 reason = "unsupported_private_chat",
@@ -4316,11 +10254,11 @@ async fn backup_run(
                 "bootstrap catalog requires pinning; use a group/channel (e.g. -100...) or @username chat id"
             );
         } else {
-            let (manifest_object_id, snapshot_provider) =
+            let (manifest_object_id, snapshot_provider, manifest_sha256) =
                 lookup_manifest_meta(&db_path, &res.snapshot_id).await?;
             if snapshot_provider != storage.provider() {
                 return Err(CliError::new(
-                    "snapshot.unsupported_provider",
+                    ErrorCode::SnapshotUnsupportedProvider,
                     format!(
                         "unexpected snapshot provider in local db: snapshot_id={} expected_provider={} got_provider={}",
                         res.snapshot_id,
@@ -4349,7 +10287,7 @@ async fn backup_run(
             .map_err(map_core_err)?
             .ok_or_else(|| {
                 CliError::new(
-                    "index.endpoint_manifest_missing",
+                    ErrorCode::IndexEndpointManifestMissing,
                     "missing endpoint_state.endpoint_manifest_object_id after backup".to_string(),
                 )
             })?;
@@ -4366,7 +10304,7 @@ async fn backup_run(
                     .map_err(map_core_err)?
                     .ok_or_else(|| {
                         CliError::new(
-                            "index.dedupe_catalog_missing",
+                            ErrorCode::IndexDedupeCatalogMissing,
                             "missing endpoint_state.dedupe_catalog_object_id after backup"
                                 .to_string(),
                         )
@@ -4378,22 +10316,65 @@ async fn backup_run(
                 }
             };
 
-            televy_backup_core::bootstrap::update_remote_latest(
-                &storage,
-                &master_key,
-                Some(televy_backup_core::bootstrap::BootstrapEndpointLatest {
-                    endpoint_index_id,
-                    manifest_object_id: endpoint_manifest_object_id,
-                }),
-                endpoint_dedupe_latest,
-                &target.id,
-                &target.source_path,
-                &label_for_bootstrap,
-                &res.snapshot_id,
-                &manifest_object_id,
-            )
-            .await
-            .map_err(map_core_err)?;
+            let endpoint_latest = televy_backup_core::bootstrap::BootstrapEndpointLatest {
+                endpoint_index_id,
+                manifest_object_id: endpoint_manifest_object_id,
+            };
+            let retry = televy_backup_core::retry::RetryBudget::resume(
+                &settings.retry,
+                None,
+                res.retry,
+            );
+            // Imported history must not move the target's latest pointer back in time.
+            let keep_target_latest = match import {
+                Some(import) => {
+                    has_newer_snapshot(&db_path, &target.source_path, &import.created_at).await?
+                }
+                None => false,
+            };
+            let replaced = retry
+                .run("bootstrap_catalog", || async {
+                    if keep_target_latest {
+                        televy_backup_core::bootstrap::update_remote_endpoint_latest(
+                            &storage,
+                            &master_key,
+                            Some(endpoint_latest.clone()),
+                            endpoint_dedupe_latest.clone(),
+                        )
+                        .await?;
+                        return Ok(None);
+                    }
+                    televy_backup_core::bootstrap::update_remote_latest(
+                        &storage,
+                        &master_key,
+                        Some(endpoint_latest.clone()),
+                        endpoint_dedupe_latest.clone(),
+                        &target.id,
+                        &target.source_path,
+                        &label_for_bootstrap,
+                        &res.snapshot_id,
+                        &manifest_object_id,
+                        manifest_sha256.as_deref(),
+                        target.key_derivation(),
+                        device_for_bootstrap.as_ref(),
+                    )
+                    .await
+                })
+                .await;
+            res.retry = retry.stats();
+            let replaced = replaced.map_err(map_core_err)?;
+            if let Some(previous) = replaced {
+                record_audit(
+                    data_dir,
+                    televy_backup_core::audit::AUDIT_OP_BOOTSTRAP_OVERWRITE,
+                    televy_backup_core::audit::bootstrap_overwrite_details(
+                        &target.id,
+                        &previous,
+                        &res.snapshot_id,
+                        device_for_bootstrap.as_ref(),
+                    ),
+                );
+            }
         }
 
         if let Some(bytes) = storage.session_bytes() {
@@ -4401,18 +10382,39 @@ async fn backup_run(
             if let Err(e) = set_secret(config_dir, data_dir, &ep.mtproto.session_key, &b64) {
                 tracing::warn!(
                     event = "secrets.session_persist_failed",
-                    error_code = e.code,
+                    error_code = e.code.as_str(),
                     error_message = %e.message,
                     "failed to persist mtproto session"
                 );
             }
         }
 
+        if let Some(identity) = &source_identity
+            && let Err(e) =
+                televy_backup_core::source_guard::record_baseline(data_dir, &target.id, identity)
+        {
+            tracing::warn!(
+                event = "backup.source_baseline_failed",
+                target_id = %target.id,
+                error = %e,
+                "backup.source_baseline_failed"
+            );
+        }
+
         Ok(res)
     }
     .await;
 
     let duration_seconds = started.elapsed().as_secs_f64();
+    let usage = UsageRun::finished_now(
+        "backup",
+        Some(ctx_target_id.as_str()),
+        result.as_ref().map_or(0, |res| res.bytes_uploaded),
+        duration_seconds,
+        run_status(&result),
+    );
+    write_metrics_textfile(config_dir, data_dir, metrics_textfile, &usage, None).await;
+    record_usage(config_dir, data_dir, usage).await;
     match result {
         Ok(res) => {
             tracing::warn!(
@@ -4427,6 +10429,7 @@ async fn backup_run(
                 duration_seconds,
                 snapshot_id = %res.snapshot_id,
                 files_indexed = res.files_indexed,
+                dirs_indexed = res.dirs_indexed,
                 chunks_uploaded = res.chunks_uploaded,
                 data_objects_uploaded = res.data_objects_uploaded,
                 data_objects_estimated_without_pack = res.data_objects_estimated_without_pack,
@@ -4435,6 +10438,12 @@ async fn backup_run(
                 index_parts = res.index_parts,
                 ignore_rule_files = res.ignore_rule_files,
                 ignore_invalid_rules = res.ignore_invalid_rules,
+                files_skipped_errors = res.files_skipped_errors,
+                retries = res.retry.retries,
+                retry_wait_ms = res.retry.retry_wait_ms,
+                upload_checks = res.upload_checks,
+                upload_reuploads = res.upload_reuploads,
+                phase_timings_ms = %res.phase_timings,
                 "run.finish"
             );
 
@@ -4446,8 +10455,10 @@ async fn backup_run(
                     "state": "succeeded",
                     "snapshotId": res.snapshot_id,
                     "targetId": ctx_target_id.clone(),
+                    "warnings": res.files_skipped_errors,
                     "result": {
                         "filesIndexed": res.files_indexed,
+                        "dirsIndexed": res.dirs_indexed,
                         "chunksUploaded": res.chunks_uploaded,
                         "dataObjectsUploaded": res.data_objects_uploaded,
                         "dataObjectsEstimatedWithoutPack": res.data_objects_estimated_without_pack,
@@ -4456,7 +10467,22 @@ async fn backup_run(
                         "indexParts": res.index_parts,
                         "ignoreRuleFiles": res.ignore_rule_files,
                         "ignoreInvalidRules": res.ignore_invalid_rules,
+                        "filesSkippedErrors": res.files_skipped_errors,
+                        "skippedFiles": res.skipped_files,
+                        "caseCollisions": res.case_collisions,
+                        "longPaths": res.long_paths,
+                        "mountPointsSkipped": res.mount_points_skipped,
+                        "filesSkippedDefaultExcludes": res.files_skipped_default_excludes,
+                        "fileFilters": res.file_filters,
+                        "retries": res.retry.retries,
+                        "retryWaitMs": res.retry.retry_wait_ms,
+                        "uploadChecks": res.upload_checks,
+                        "uploadReuploads": res.upload_reuploads,
+                        "sqliteConsistentFiles": res.sqlite_consistent_files,
+                        "sqliteConsistentFallbacks": res.sqlite_consistent_fallbacks,
                         "durationSeconds": duration_seconds,
+                        "phaseTimings": res.phase_timings,
+                        "warnings": res.warnings,
                     }
                 }));
                 daemon_control_status_task_finish(
@@ -4473,55 +10499,93 @@ async fn backup_run(
                 println!(
                     "{}",
                     serde_json::to_string(&res)
-                        .map_err(|e| CliError::new("config.invalid", e.to_string()))?
+                        .map_err(|e| CliError::new(ErrorCode::ConfigInvalid, e.to_string()))?
                 );
             } else {
                 println!("snapshotId={}", res.snapshot_id);
                 println!(
-                    "filesIndexed={} chunksUploaded={} dataObjectsUploaded={} dataObjectsEstimatedWithoutPack={} bytesUploaded={} bytesDeduped={} ignoreRuleFiles={} ignoreInvalidRules={}",
+                    "filesIndexed={} dirsIndexed={} chunksUploaded={} dataObjectsUploaded={} dataObjectsEstimatedWithoutPack={} bytesUploaded={} bytesDeduped={} ignoreRuleFiles={} ignoreInvalidRules={} filesSkippedErrors={} durationSeconds={}",
                     res.files_indexed,
+                    res.dirs_indexed,
                     res.chunks_uploaded,
                     res.data_objects_uploaded,
                     res.data_objects_estimated_without_pack,
-                    res.bytes_uploaded,
-                    res.bytes_deduped,
+                    format::bytes(res.bytes_uploaded),
+                    format::bytes(res.bytes_deduped),
                     res.ignore_rule_files,
-                    res.ignore_invalid_rules
+                    res.ignore_invalid_rules,
+                    res.files_skipped_errors,
+                    format::duration(duration_seconds)
                 );
+                for skipped in &res.skipped_files {
+                    println!("skipped {} ({})", skipped.path, skipped.reason.as_str());
+                }
+                print_file_filter_stats(&res.file_filters);
+                if res.case_collisions > 0 {
+                    eprintln!(
+                        "warning: {} entries differ from another only in letter case; a case-insensitive volume (the macOS default) needs `restore run --rename-collisions` to restore them all",
+                        res.case_collisions
+                    );
+                }
+                if res.long_paths > 0 {
+                    eprintln!(
+                        "warning: {} paths are longer than scan.warn_path_bytes = {}; restoring them onto another file system may need `restore run --shorten-long-paths`",
+                        res.long_paths, settings.scan.warn_path_bytes
+                    );
+                }
+                if res.mount_points_skipped > 0 {
+                    eprintln!(
+                        "warning: skipped {} directories on other file systems (scan.one_file_system); pass --cross-filesystems to back them up",
+                        res.mount_points_skipped
+                    );
+                }
+                if res.sqlite_consistent_fallbacks > 0 {
+                    eprintln!(
+                        "warning: {} SQLite databases matching targets[].sqlite_consistent were read live instead of from a consistent copy; see the scan.sqlite_consistent_fallback log events",
+                        res.sqlite_consistent_fallbacks
+                    );
+                }
+                if res
+                    .warnings
+                    .iter()
+                    .any(|w| w == televy_backup_core::full_disk_access::WARNING)
+                {
+                    eprintln!(
+                        "warning: macOS denied access to protected folders, so parts of the source are missing from this snapshot; grant TelevyBackup and televybackupd Full Disk Access in {}",
+                        televy_backup_core::full_disk_access::SETTINGS_PANE
+                    );
+                }
             }
             Ok(())
         }
         Err(e) => {
-            tracing::error!(
-                event = "run.finish",
-                kind = "backup",
-                run_id = %task_id,
-                task_id = %task_id,
-                target_id = %ctx_target_id,
-                endpoint_id = %ctx_endpoint_id,
-                source_path = %ctx_source_path,
-                status = "failed",
+            let status = log_run_finish_error(
+                &task_id,
+                "backup",
+                RunCtx {
+                    target_id: Some(&ctx_target_id),
+                    endpoint_id: Some(&ctx_endpoint_id),
+                    source_path: Some(&ctx_source_path),
+                    snapshot_id: None,
+                },
                 duration_seconds,
-                error_code = e.code,
-                error_message = %e.message,
-                retryable = e.retryable,
-                "run.finish"
+                &e,
             );
             if events {
-                emit_event_stdout(serde_json::json!({
-                    "type": "task.state",
-                    "taskId": task_id,
-                    "kind": "backup",
-                    "state": "failed",
-                    "targetId": ctx_target_id.clone(),
-                    "error": { "code": e.code, "message": e.message.clone() },
-                }));
+                emit_task_state_error(
+                    events,
+                    &task_id,
+                    "backup",
+                    Some(ctx_target_id.as_str()),
+                    None,
+                    &e,
+                );
                 daemon_control_status_task_finish(
                     data_dir,
                     &task_id,
                     "backup",
                     ctx_target_id.as_str(),
-                    "failed",
+                    status.as_str(),
                 );
             }
             Err(e)
@@ -4529,6 +10593,181 @@ async fn backup_run(
     }
 }
 
+/// Runs one backup per `<input>/manifest.toml` entry, oldest first. Entries already in the local
+/// index (same `created_at` and label) are skipped, so an interrupted import can be re-run.
+async fn import_restic_dump(
+    config_dir: &Path,
+    data_dir: &Path,
+    input: &Path,
+    target_id: &str,
+    no_remote_index_sync: bool,
+    json: bool,
+) -> Result<(), CliError> {
+    let snapshots =
+        televy_backup_core::history_import::load_import_manifest(input).map_err(map_core_err)?;
+    let settings = load_settings(config_dir)?;
+    let target = select_target(&settings, Some(target_id), None)?;
+    let db_path = endpoint_index_db_path(data_dir, &target.endpoint_id);
+
+    let mut imported = 0usize;
+    let mut skipped = 0usize;
+    for snapshot in &snapshots {
+        if has_imported_snapshot(&db_path, &target.source_path, snapshot).await? {
+            skipped += 1;
+            if !json {
+                eprintln!(
+                    "skipping {} ({}): already imported",
+                    snapshot.label, snapshot.created_at
+                );
+            }
+            continue;
+        }
+        if !json {
+            eprintln!("importing {} ({})", snapshot.label, snapshot.created_at);
+        }
+        backup_run(
+            config_dir,
+            data_dir,
+            Some(target_id.to_string()),
+            None,
+            None,
+            no_remote_index_sync,
+            true,
+            false,
+            false,
+            false,
+            false,
+            json,
+            false,
+            Some(snapshot),
+            None,
+        )
+        .await?;
+        imported += 1;
+    }
+
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({ "imported": imported, "skipped": skipped })
+        );
+    } else {
+        println!("imported={imported} skipped={skipped}");
+    }
+    Ok(())
+}
+
+async fn has_imported_snapshot(
+    endpoint_db: &Path,
+    source_path: &str,
+    snapshot: &ImportSnapshot,
+) -> Result<bool, CliError> {
+    if !endpoint_db.exists() {
+        return Ok(false);
+    }
+    let pool = televy_backup_core::index_db::open_index_db(endpoint_db)
+        .await
+        .map_err(map_core_err)?;
+    let found = sqlx::query(
+        "SELECT 1 FROM snapshots WHERE source_path = ? AND created_at = ? AND label = ? LIMIT 1",
+    )
+    .bind(source_path)
+    .bind(&snapshot.created_at)
+    .bind(&snapshot.label)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|e| CliError::new(ErrorCode::DbFailed, e.to_string()));
+    pool.close().await;
+    Ok(found?.is_some())
+}
+
+/// Downloads the endpoint DB `endpoint_latest` points at unless the local one already is it;
+/// `None` when nothing was downloaded.
+async fn sync_local_endpoint_db(
+    storage: &TelegramMtProtoStorage,
+    master_key: &[u8; 32],
+    endpoint_latest: &bootstrap::BootstrapEndpointLatest,
+    local_endpoint_db: &Path,
+    sink: Option<&dyn ProgressSink>,
+) -> Result<Option<televy_backup_core::remote_index_db::DownloadedIndexDbStats>, CliError> {
+    let already_synced = televy_backup_core::index_sync::local_endpoint_db_matches_remote_latest(
+        local_endpoint_db,
+        &endpoint_latest.manifest_object_id,
+    )
+    .await
+    .map_err(map_core_err)?;
+    if already_synced {
+        return Ok(None);
+    }
+
+    let provider = storage.provider();
+    let stats = televy_backup_core::remote_index_db::download_and_write_index_db_atomic(
+        storage,
+        &endpoint_latest.endpoint_index_id,
+        &endpoint_latest.manifest_object_id,
+        None,
+        master_key,
+        local_endpoint_db,
+        None,
+        Some(provider),
+        sink,
+    )
+    .await
+    .map_err(map_core_err)?;
+
+    // The remote endpoint DB may have been uploaded before it knew its own manifest id.
+    // Record the pointer locally so future runs can skip redundant downloads.
+    televy_backup_core::index_sync::endpoint_state_set(
+        local_endpoint_db,
+        televy_backup_core::index_sync::ENDPOINT_STATE_ENDPOINT_INDEX_ID_KEY,
+        &endpoint_latest.endpoint_index_id,
+    )
+    .await
+    .map_err(map_core_err)?;
+    televy_backup_core::index_sync::endpoint_state_set(
+        local_endpoint_db,
+        televy_backup_core::index_sync::ENDPOINT_STATE_ENDPOINT_MANIFEST_OBJECT_ID_KEY,
+        &endpoint_latest.manifest_object_id,
+    )
+    .await
+    .map_err(map_core_err)?;
+    Ok(Some(stats))
+}
+
+/// Rebuilds the local dedupe DB from the catalog `dedupe_latest` points at unless it already is
+/// built from it; `None` when nothing was downloaded.
+async fn sync_local_dedupe_db(
+    storage: &TelegramMtProtoStorage,
+    master_key: &[u8; 32],
+    dedupe_latest: &bootstrap::BootstrapEndpointDedupeLatest,
+    local_dedupe_db: &Path,
+    sink: Option<&dyn ProgressSink>,
+) -> Result<Option<televy_backup_core::dedupe_sync::DedupeMaterializeStats>, CliError> {
+    let already_synced = televy_backup_core::dedupe_sync::local_dedupe_db_matches_remote_latest(
+        local_dedupe_db,
+        &dedupe_latest.catalog_object_id,
+    )
+    .await
+    .map_err(map_core_err)?;
+    if already_synced {
+        return Ok(None);
+    }
+
+    let provider = storage.provider();
+    let stats = televy_backup_core::dedupe_sync::materialize_remote_dedupe_db(
+        storage,
+        master_key,
+        &dedupe_latest.endpoint_dedupe_id,
+        &dedupe_latest.catalog_object_id,
+        local_dedupe_db,
+        Some(provider),
+        sink,
+    )
+    .await
+    .map_err(map_core_err)?;
+    Ok(Some(stats))
+}
+
 #[allow(clippy::too_many_arguments)]
 async fn preflight_remote_first_index_sync(
     storage: &TelegramMtProtoStorage,
@@ -4550,7 +10789,7 @@ async fn preflight_remote_first_index_sync(
     tracing::debug!(event = "phase.start", phase = "index_sync", "phase.start");
     if let Some(sink) = sink {
         sink.on_progress(televy_backup_core::TaskProgress {
-            phase: "index_sync".to_string(),
+            phase: Phase::IndexSync,
             ..Default::default()
         });
     }
@@ -4572,8 +10811,28 @@ async fn preflight_remote_first_index_sync(
         return Ok(televy_backup_core::RemoteDedupeMode::Disabled);
     }
 
+    let pin_mode = bootstrap::PinnedStorage::bootstrap_pin_mode(storage);
+    if pin_mode == bootstrap::BootstrapPinMode::Disabled {
+        // Remote index and dedupe sync both hang off the catalog.
+        tracing::debug!(
+            event = "index_sync.skipped",
+            reason = "bootstrap_disabled",
+            target_id,
+            "bootstrap.pin_mode is disabled; continue with the local index only"
+        );
+        tracing::debug!(
+            event = "phase.finish",
+            phase = "index_sync",
+            duration_ms = started.elapsed().as_millis() as u64,
+            index_source = "skipped",
+            reason = "bootstrap_disabled",
+            "phase.finish"
+        );
+        return Ok(televy_backup_core::RemoteDedupeMode::Disabled);
+    }
+
     std::fs::create_dir_all(filemap_dir)
-        .map_err(|e| CliError::new("config.write_failed", e.to_string()))?;
+        .map_err(|e| CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()))?;
 
     // Strict remote gating: Telegram errors in bootstrap/index fetch are fatal; only "bootstrap is
     // missing" is allowed (first initialization / user pinned something else).
@@ -4583,46 +10842,15 @@ async fn preflight_remote_first_index_sync(
 
     // 1) Sync the endpoint DB from bootstrap.endpointLatest (if present).
     if let Some(endpoint_latest) = catalog.as_ref().and_then(|c| c.endpoint_latest.clone()) {
-        let already_synced =
-            televy_backup_core::index_sync::local_endpoint_db_matches_remote_latest(
-                local_endpoint_db,
-                &endpoint_latest.manifest_object_id,
-            )
-            .await
-            .map_err(map_core_err)?;
-
-        if !already_synced {
-            let provider = storage.provider();
-            let stats = televy_backup_core::remote_index_db::download_and_write_index_db_atomic(
-                storage,
-                &endpoint_latest.endpoint_index_id,
-                &endpoint_latest.manifest_object_id,
-                master_key,
-                local_endpoint_db,
-                None,
-                Some(provider),
-                sink,
-            )
-            .await
-            .map_err(map_core_err)?;
-
-            // The remote endpoint DB may have been uploaded before it knew its own manifest id.
-            // Record the pointer locally so future runs can skip redundant downloads.
-            televy_backup_core::index_sync::endpoint_state_set(
-                local_endpoint_db,
-                televy_backup_core::index_sync::ENDPOINT_STATE_ENDPOINT_INDEX_ID_KEY,
-                &endpoint_latest.endpoint_index_id,
-            )
-            .await
-            .map_err(map_core_err)?;
-            televy_backup_core::index_sync::endpoint_state_set(
-                local_endpoint_db,
-                televy_backup_core::index_sync::ENDPOINT_STATE_ENDPOINT_MANIFEST_OBJECT_ID_KEY,
-                &endpoint_latest.manifest_object_id,
-            )
-            .await
-            .map_err(map_core_err)?;
-
+        if let Some(stats) = sync_local_endpoint_db(
+            storage,
+            master_key,
+            &endpoint_latest,
+            local_endpoint_db,
+            sink,
+        )
+        .await?
+        {
             tracing::debug!(
                 event = "index_sync.endpoint.downloaded",
                 target_id,
@@ -4636,7 +10864,8 @@ async fn preflight_remote_first_index_sync(
             event = "index_sync.skipped",
             reason = "bootstrap_missing",
             target_id,
-            "no pinned bootstrap catalog (first init); continue without remote endpoint sync"
+            pin_mode = pin_mode.as_str(),
+            "no bootstrap catalog (first init); continue without remote endpoint sync"
         );
     }
 
@@ -4645,26 +10874,9 @@ async fn preflight_remote_first_index_sync(
         .as_ref()
         .and_then(|c| c.endpoint_dedupe_latest.clone())
     {
-        let already_synced =
-            televy_backup_core::dedupe_sync::local_dedupe_db_matches_remote_latest(
-                local_dedupe_db,
-                &dedupe_latest.catalog_object_id,
-            )
-            .await
-            .map_err(map_core_err)?;
-        if !already_synced {
-            let provider = storage.provider();
-            let stats = televy_backup_core::dedupe_sync::materialize_remote_dedupe_db(
-                storage,
-                master_key,
-                &dedupe_latest.endpoint_dedupe_id,
-                &dedupe_latest.catalog_object_id,
-                local_dedupe_db,
-                Some(provider),
-                sink,
-            )
-            .await
-            .map_err(map_core_err)?;
+        if let Some(stats) =
+            sync_local_dedupe_db(storage, master_key, &dedupe_latest, local_dedupe_db, sink).await?
+        {
             tracing::debug!(
                 event = "index_sync.dedupe.downloaded",
                 target_id,
@@ -4694,58 +10906,66 @@ async fn preflight_remote_first_index_sync(
             .map_err(map_core_err)?;
 
         let provider = storage.provider();
-        let kind = provider.split(['/', ':']).next().unwrap_or(provider).trim();
-        let like = format!("{kind}%");
-        let base_row = sqlx::query(
-            r#"
-            SELECT s.snapshot_id as snapshot_id
-            FROM snapshots s
-            JOIN remote_indexes ri ON ri.snapshot_id = s.snapshot_id
-            WHERE s.source_path = ?
-              AND (ri.provider = ? OR ri.provider LIKE ?)
-            ORDER BY s.created_at DESC
-            LIMIT 1
-            "#,
-        )
-        .bind(source_path)
-        .bind(provider)
-        .bind(&like)
-        .fetch_optional(&pool)
-        .await
-        .map_err(|e| CliError::new("db.failed", e.to_string()))?;
-
-        if let Some(base_row) = base_row {
-            let base_snapshot_id: String = base_row.get("snapshot_id");
+        if let Some(base_snapshot_id) =
+            latest_base_snapshot_id(&pool, source_path, provider).await?
+        {
             let cached_path = filemap_dir.join(format!("{base_snapshot_id}.sqlite"));
             if !cached_path.exists() {
                 let row = sqlx::query(
-                    "SELECT provider, manifest_object_id FROM remote_indexes WHERE snapshot_id = ? LIMIT 1",
+                    "SELECT provider, manifest_object_id, manifest_sha256 FROM remote_indexes WHERE snapshot_id = ? LIMIT 1",
                 )
                 .bind(&base_snapshot_id)
                 .fetch_optional(&pool)
                 .await
-                .map_err(|e| CliError::new("db.failed", e.to_string()))?;
+                .map_err(|e| CliError::new(ErrorCode::DbFailed, e.to_string()))?;
 
                 let row = row.ok_or_else(|| {
                     CliError::new(
-                        "integrity.base_snapshot_missing_remote_index",
+                        ErrorCode::IntegrityBaseSnapshotMissingRemoteIndex,
                         format!("base snapshot missing remote index pointer: {base_snapshot_id}"),
                     )
                 })?;
 
                 let row_provider: String = row.get("provider");
                 let manifest_object_id: String = row.get("manifest_object_id");
-                let row_kind = row_provider
-                    .split(['/', ':'])
-                    .next()
-                    .unwrap_or(&row_provider)
-                    .trim();
-                if row_provider == provider || row_kind == kind {
+                // Prefer the hash pinned in the catalog for this target; the synced endpoint DB
+                // records it too for snapshots uploaded by current versions.
+                let manifest_sha256 = catalog
+                    .as_ref()
+                    .and_then(|c| c.targets.iter().find(|t| t.target_id == target_id))
+                    .and_then(|t| t.latest.as_ref())
+                    .filter(|l| {
+                        l.snapshot_id == base_snapshot_id
+                            && l.manifest_object_id == manifest_object_id
+                    })
+                    .and_then(|l| l.manifest_sha256.clone())
+                    .or_else(|| row.get::<Option<String>, _>("manifest_sha256"));
+                if row_provider == provider {
+                    if manifest_sha256.is_none() {
+                        televy_backup_core::remote_index_db::warn_manifest_unverified(
+                            &base_snapshot_id,
+                            &manifest_object_id,
+                        );
+                    }
+                    let mut conn = pool
+                        .acquire()
+                        .await
+                        .map_err(|e| CliError::new(ErrorCode::DbFailed, e.to_string()))?;
+                    let data_key = televy_backup_core::index_db::snapshot_data_key(
+                        &mut conn,
+                        master_key,
+                        &base_snapshot_id,
+                    )
+                    .await
+                    .map_err(map_core_err)?
+                    .unwrap_or_else(|| DataKey::master(master_key));
+                    drop(conn);
                     televy_backup_core::remote_index_db::download_and_write_index_db_atomic(
                         storage,
                         &base_snapshot_id,
                         &manifest_object_id,
-                        master_key,
+                        manifest_sha256.as_deref(),
+                        data_key.key(),
                         &cached_path,
                         None,
                         Some(provider),
@@ -4758,25 +10978,205 @@ async fn preflight_remote_first_index_sync(
         }
     }
 
-    tracing::debug!(
-        event = "phase.finish",
-        phase = "index_sync",
-        duration_ms = started.elapsed().as_millis() as u64,
-        target_id,
-        "phase.finish"
-    );
+    tracing::debug!(
+        event = "phase.finish",
+        phase = "index_sync",
+        duration_ms = started.elapsed().as_millis() as u64,
+        target_id,
+        "phase.finish"
+    );
+
+    Ok(remote_dedupe)
+}
+
+/// Latest snapshot of `source_path` with a remote index on `provider` (the next run's base).
+async fn latest_base_snapshot_id(
+    pool: &sqlx::SqlitePool,
+    source_path: &str,
+    provider: &str,
+) -> Result<Option<String>, CliError> {
+    let row = sqlx::query(
+        r#"
+        SELECT s.snapshot_id as snapshot_id
+        FROM snapshots s
+        JOIN remote_indexes ri ON ri.snapshot_id = s.snapshot_id
+        WHERE s.source_path = ?
+          AND ri.provider = ?
+        ORDER BY s.created_at DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(source_path)
+    .bind(provider)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| CliError::new(ErrorCode::DbFailed, e.to_string()))?;
+    Ok(row.map(|r| r.get("snapshot_id")))
+}
+
+async fn has_base_snapshot(
+    endpoint_db: &Path,
+    source_path: &str,
+    provider: &str,
+) -> Result<bool, CliError> {
+    if !endpoint_db.exists() {
+        return Ok(false);
+    }
+    let pool = televy_backup_core::index_db::open_index_db(endpoint_db)
+        .await
+        .map_err(map_core_err)?;
+    let base = latest_base_snapshot_id(&pool, source_path, provider).await;
+    pool.close().await;
+    Ok(base?.is_some())
+}
+
+/// Whether `source_path` has a snapshot newer than `created_at` in the endpoint index.
+async fn has_newer_snapshot(
+    endpoint_db: &Path,
+    source_path: &str,
+    created_at: &str,
+) -> Result<bool, CliError> {
+    let pool = televy_backup_core::index_db::open_index_db(endpoint_db)
+        .await
+        .map_err(map_core_err)?;
+    let newer =
+        sqlx::query("SELECT 1 FROM snapshots WHERE source_path = ? AND created_at > ? LIMIT 1")
+            .bind(source_path)
+            .bind(created_at)
+            .fetch_optional(&pool)
+            .await
+            .map_err(|e| CliError::new(ErrorCode::DbFailed, e.to_string()));
+    pool.close().await;
+    Ok(newer?.is_some())
+}
+
+const INITIAL_BACKUP_PROMPT_ID: &str = "backup.initial_size";
+
+/// Asks whether a first backup larger than `scan.warn_initial_backup_bytes` should start.
+///
+/// In `--events` mode a `task.prompt` event is emitted and the answer (`continue` or `cancel`) is
+/// read as one line from stdin, so the GUI can answer it; EOF counts as `cancel`. Otherwise the
+/// question is asked on the terminal, and a non-interactive run fails asking for `--yes`.
+async fn confirm_initial_backup(
+    events: bool,
+    task_id: &str,
+    target_id: &str,
+    stats: &televy_backup_core::SourceQuickStats,
+    warn_bytes: u64,
+) -> Result<(), CliError> {
+    let message = format!(
+        "This is the first backup of this target and it will upload about {} ({} files). Continue?",
+        format::human_bytes(stats.bytes_total),
+        stats.files_total
+    );
+    if events {
+        emit_event_stdout(serde_json::json!({
+            "type": "task.prompt",
+            "taskId": task_id,
+            "promptId": INITIAL_BACKUP_PROMPT_ID,
+            "targetId": target_id,
+            "message": message,
+            "bytesTotalEstimated": stats.bytes_total,
+            "filesTotalEstimated": stats.files_total,
+            "warnInitialBackupBytes": warn_bytes,
+            "choices": ["continue", "cancel"],
+        }));
+    } else if std::io::stdin().is_terminal() {
+        eprint!("{message} [y/N] ");
+        let _ = std::io::stderr().flush();
+    } else {
+        return Err(CliError::new(
+            ErrorCode::BackupConfirmationRequired,
+            "first backup exceeds scan.warn_initial_backup_bytes; re-run with --yes to start it",
+        )
+        .with_details(serde_json::json!({
+            "bytesTotalEstimated": stats.bytes_total,
+            "warnInitialBackupBytes": warn_bytes,
+        })));
+    }
+
+    let answer = tokio::task::spawn_blocking(|| {
+        let mut line = String::new();
+        std::io::stdin().read_line(&mut line).map(|_| line)
+    })
+    .await
+    .map_err(|e| CliError::new(ErrorCode::TaskCancelled, format!("prompt aborted: {e}")))?
+    .map_err(|e| CliError::new(ErrorCode::Io, e.to_string()))?;
+
+    if prompt_answer_confirms(&answer) {
+        Ok(())
+    } else {
+        Err(CliError::new(
+            ErrorCode::TaskCancelled,
+            "first backup cancelled at the size confirmation",
+        ))
+    }
+}
+
+fn prompt_answer_confirms(answer: &str) -> bool {
+    matches!(
+        answer.trim().to_ascii_lowercase().as_str(),
+        "continue" | "y" | "yes"
+    )
+}
 
-    Ok(remote_dedupe)
+async fn backup_dry_run(
+    config_dir: &Path,
+    target_id: Option<String>,
+    source: Option<PathBuf>,
+    cross_filesystems: bool,
+    json: bool,
+) -> Result<(), CliError> {
+    let settings = load_settings(config_dir)?;
+    let target = select_target(&settings, target_id.as_deref(), source.as_deref())?;
+    let one_file_system = settings.scan.one_file_system && !cross_filesystems;
+    let cancel = cancel_on_stop_signal();
+    let preview = televy_backup_core::preview_source(
+        Path::new(&target.source_path),
+        &target.filters,
+        Some(&cancel),
+        one_file_system,
+        settings.scan.default_excludes,
+    )
+    .await
+    .map_err(map_core_err)?;
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string(&preview)
+                .map_err(|e| CliError::new(ErrorCode::ConfigInvalid, e.to_string()))?
+        );
+    } else {
+        println!("targetId={}", target.id);
+        println!("files={}", preview.files_total);
+        println!("bytes={}", preview.bytes_total);
+        println!("filesExcluded={}", preview.files_excluded);
+        println!("bytesExcluded={}", preview.bytes_excluded);
+        print_file_filter_stats(&preview.file_filters);
+    }
+    Ok(())
+}
+
+fn print_file_filter_stats(stats: &[televy_backup_core::file_filter::FileFilterStats]) {
+    for (i, f) in stats.iter().enumerate() {
+        println!(
+            "filter[{i}] kind={} filesExcluded={} timeouts={} budgetExhausted={}",
+            f.kind, f.files_excluded, f.timeouts, f.budget_exhausted
+        );
+    }
 }
 
 async fn preflight_local_quick_stats(
     source_path: &Path,
+    one_file_system: bool,
+    default_excludes: bool,
     sink: Option<&dyn ProgressSink>,
     cancel: Option<CancellationToken>,
 ) -> Result<televy_backup_core::SourceQuickStats, CliError> {
     if let Some(sink) = sink {
         sink.on_progress(televy_backup_core::TaskProgress {
-            phase: "prepare".to_string(),
+            phase: Phase::Prepare,
             ..Default::default()
         });
     }
@@ -4784,42 +11184,123 @@ async fn preflight_local_quick_stats(
     let source_path = source_path.to_path_buf();
     let cancel_for_task = cancel;
     let stats = tokio::task::spawn_blocking(move || {
-        televy_backup_core::compute_source_quick_stats(&source_path, cancel_for_task.as_ref())
+        televy_backup_core::compute_source_quick_stats(
+            &source_path,
+            cancel_for_task.as_ref(),
+            one_file_system,
+            default_excludes,
+        )
     })
     .await
-    .map_err(|e| CliError::new("task.cancelled", format!("prepare aborted: {e}")))?
+    .map_err(|e| CliError::new(ErrorCode::TaskCancelled, format!("prepare aborted: {e}")))?
     .map_err(map_core_err)?;
 
     if let Some(sink) = sink {
         sink.on_progress(televy_backup_core::TaskProgress {
-            phase: "prepare".to_string(),
+            phase: Phase::Prepare,
             source_files_total: Some(stats.files_total),
             source_bytes_total: Some(stats.bytes_total),
+            bytes_total_estimated: Some(stats.bytes_total),
+            bytes_total: None,
             ..Default::default()
         });
     }
     Ok(stats)
 }
 
-fn is_likely_private_chat_id(chat_id: &str) -> bool {
-    let s = chat_id.trim();
-    let Ok(id) = s.parse::<i64>() else {
-        return false;
-    };
-    id > 0
+async fn restore_estimate(
+    config_dir: &Path,
+    data_dir: &Path,
+    snapshot_id: &str,
+    path: Option<&str>,
+    json: bool,
+) -> Result<(), CliError> {
+    let db_path = find_snapshot_index_db(data_dir, snapshot_id).await?;
+    let endpoint_id = televy_backup_core::index_db::endpoint_id_from_index_db_path(&db_path);
+    let filemap_db_path =
+        index_db_filemap_dir(data_dir, &db_path).join(format!("{snapshot_id}.sqlite"));
+    let (manifest_object_id, snapshot_provider, manifest_sha256) =
+        lookup_manifest_meta_any(data_dir, snapshot_id).await?;
+
+    if !filemap_db_path.exists() {
+        let settings = load_settings(config_dir)?;
+        let ep = select_endpoint(&settings, endpoint_id.as_deref())?;
+        if let Some(parent) = filemap_db_path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()))?;
+        }
+        let (storage, master_key) =
+            connect_pinned_endpoint(config_dir, data_dir, &settings, ep).await?;
+        let data_key = local_snapshot_data_key(data_dir, &master_key, snapshot_id).await?;
+        let res = televy_backup_core::remote_index_db::download_and_write_index_db_atomic(
+            &storage,
+            snapshot_id,
+            &manifest_object_id,
+            manifest_sha256.as_deref(),
+            data_key.key(),
+            &filemap_db_path,
+            None,
+            Some(storage.provider()),
+            None,
+        )
+        .await;
+        persist_mtproto_session(config_dir, data_dir, ep, &storage);
+        res.map_err(map_core_err)?;
+    }
+
+    let provider = endpoint_id
+        .as_deref()
+        .map(settings_config::endpoint_provider)
+        .unwrap_or(snapshot_provider);
+    let est = televy_backup_core::estimate_restore(
+        &filemap_db_path,
+        &db_path,
+        snapshot_id,
+        &provider,
+        path,
+    )
+    .await
+    .map_err(map_core_err)?;
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string(&est)
+                .map_err(|e| CliError::new(ErrorCode::ConfigInvalid, e.to_string()))?
+        );
+    } else {
+        println!("files={}", est.files);
+        println!("dirs={}", est.dirs);
+        println!("bytesToWrite={}", est.bytes_to_write);
+        println!("chunks={}", est.chunks);
+        println!("objects={}", est.objects);
+        println!("bytesToDownload={}", est.bytes_to_download);
+        println!("chunksMissing={}", est.chunks_missing);
+        eprintln!(
+            "This will download {} and write {}.",
+            format::human_bytes(est.bytes_to_download),
+            format::human_bytes(est.bytes_to_write)
+        );
+    }
+    Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn restore_run(
     config_dir: &Path,
     data_dir: &Path,
     snapshot_id: String,
     target: PathBuf,
+    target_key: Option<gold_key::TargetKey>,
+    manifest_object_id_override: Option<String>,
+    from_local_repo: Option<PathBuf>,
+    flags: RestoreFlags,
     json: bool,
     events: bool,
 ) -> Result<(), CliError> {
     let task_id = format!("tsk_{}", uuid::Uuid::new_v4());
     let run_log = televy_backup_core::run_log::start_run_log("restore", &task_id, data_dir)
-        .map_err(|e| CliError::new("log.init_failed", e.to_string()))?;
+        .map_err(|e| CliError::new(ErrorCode::LogInitFailed, e.to_string()))?;
 
     tracing::warn!(
         event = "run.start",
@@ -4834,169 +11315,258 @@ async fn restore_run(
     let started = std::time::Instant::now();
     let result: Result<televy_backup_core::RestoreResult, CliError> = async {
         let settings = load_settings(config_dir)?;
-
-        let (manifest_object_id, snapshot_provider) =
-            lookup_manifest_meta_any(data_dir, &snapshot_id).await?;
-
-        let endpoint_id = if snapshot_provider == "telegram.mtproto" {
-            None
-        } else if let Some(rest) = snapshot_provider.strip_prefix("telegram.mtproto/") {
-            Some(rest)
-        } else {
-            return Err(CliError::new(
-                "snapshot.unsupported_provider",
-                format!(
-                    "unsupported snapshot provider: snapshot_id={snapshot_id} provider={snapshot_provider}. TelevyBackup is MTProto-only now. Fix: run a new backup with MTProto."
-                ),
-            ));
-        };
-
-        let ep = select_endpoint(&settings, endpoint_id)?;
-
-        if settings.telegram.mtproto.api_id <= 0 {
-            return Err(CliError::new(
-                "config.invalid",
-                "telegram.mtproto.api_id must be > 0",
-            ));
-        }
-        if settings.telegram.mtproto.api_hash_key.is_empty() {
-            return Err(CliError::new(
-                "config.invalid",
-                "telegram.mtproto.api_hash_key must not be empty",
-            ));
-        }
-        if ep.chat_id.is_empty() {
-            return Err(CliError::new(
-                "config.invalid",
-                format!("telegram_endpoints[{id}].chat_id is empty", id = ep.id),
-            ));
-        }
-
-        let bot_token = get_secret(config_dir, data_dir, &ep.bot_token_key)?
-            .ok_or_else(|| CliError::new("telegram.unauthorized", "bot token missing"))?;
-        let master_key = load_master_key(config_dir, data_dir)?;
-
-        let filemap_db_path = endpoint_filemap_dir(data_dir, &ep.id)
-            .join(format!("{snapshot_id}.sqlite"));
-        if let Some(parent) = filemap_db_path.parent() {
-            std::fs::create_dir_all(parent)
-                .map_err(|e| CliError::new("config.write_failed", e.to_string()))?;
-        }
-
-        if events {
-            emit_event_stdout(serde_json::json!({
-                "type": "task.state",
-                "taskId": task_id,
-                "kind": "restore",
-                "state": "running",
-                "snapshotId": snapshot_id,
-            }));
-        }
+        prune_run_logs_best_effort(data_dir, &settings);
 
         let sink = NdjsonProgressSink {
             task_id: task_id.clone(),
             throttle: Mutex::new(ProgressThrottle::new(Duration::from_millis(200))),
             daemon_status_report: None,
         };
+        let cancel = cancel_on_stop_signal();
+        let recorder = ProgressRecorder::new(events.then_some(&sink as &dyn ProgressSink));
         let opts = RestoreOptions {
-            cancel: None,
-            progress: if events { Some(&sink) } else { None },
+            cancel: Some(&cancel),
+            progress: Some(&recorder),
+            keep_going: flags.keep_going,
+            retry: settings.retry.clone(),
+            delete_extraneous: flags.delete_extraneous,
+            dry_run: flags.dry_run,
+            as_file: flags.as_file,
+            preserve_times: flags.preserve_times,
+            rename_collisions: flags.rename_collisions,
+            shorten_long_paths: flags.shorten_long_paths,
+            path_limits: None,
+            ownership: flags.ownership.clone(),
+            write_concurrency: settings.restore.write_concurrency as usize,
+            fsync: RestoreFsync::parse(&settings.restore.fsync).unwrap_or_default(),
         };
 
-        let api_hash = get_secret(config_dir, data_dir, &settings.telegram.mtproto.api_hash_key)?.ok_or_else(
-            || CliError::new("telegram.mtproto.missing_api_hash", "mtproto api_hash missing"),
-        )?;
-        let session = load_optional_base64_secret_bytes(
-            config_dir,
-            data_dir,
-            &ep.mtproto.session_key,
-            "telegram.mtproto.session_invalid",
-            "invalid mtproto session (try: `televybackup secrets clear-telegram-mtproto-session`)",
-        )?;
+        let emit_running = || {
+            if events {
+                emit_event_stdout(serde_json::json!({
+                    "type": "task.state",
+                    "taskId": task_id,
+                    "kind": "restore",
+                    "state": "running",
+                    "snapshotId": snapshot_id,
+                }));
+            }
+        };
 
-        let cache_dir = data_dir.join("cache").join("mtproto");
-        std::fs::create_dir_all(&cache_dir)
-            .map_err(|e| CliError::new("config.write_failed", e.to_string()))?;
+        let res = match from_local_repo.as_deref() {
+            Some(repo_dir) => {
+                // The export holds the objects and the index, so nothing talks to Telegram.
+                let master_key = match target_key.as_ref() {
+                    Some(_) => [0u8; 32],
+                    None => load_master_key(config_dir, data_dir)?,
+                };
+                let repo = open_local_repo_run(
+                    data_dir,
+                    repo_dir,
+                    &snapshot_id,
+                    &master_key,
+                    target_key.as_ref(),
+                    manifest_object_id_override,
+                )
+                .await?;
+                emit_running();
+                let cfg = RestoreConfig {
+                    snapshot_id: snapshot_id.clone(),
+                    filemap_manifest_object_id: repo.manifest_object_id.clone(),
+                    filemap_manifest_sha256: repo.manifest_sha256.clone(),
+                    endpoint_manifest_object_id: repo.endpoint_manifest_object_id.clone(),
+                    dedupe_catalog_object_id: repo.dedupe_catalog_object_id.clone(),
+                    endpoint_dedupe_id: repo.endpoint_dedupe_id.clone(),
+                    endpoint_index_id: repo.endpoint_index_id.clone(),
+                    master_key,
+                    data_key: Some(repo.data_key.clone()),
+                    filemap_db_path: repo.filemap_db_path(&snapshot_id),
+                    endpoint_db_path: Some(repo.scratch_dir.join("endpoint.sqlite")),
+                    dedupe_db_path: Some(repo.scratch_dir.join("dedupe.sqlite")),
+                    target_path: target,
+                };
+                restore_snapshot_with(&repo.storage, cfg, opts)
+                    .await
+                    .map_err(|e| with_partial_result(map_core_err(e), &recorder))?
+            }
+            None => {
+                let (manifest_object_id, snapshot_provider, manifest_sha256) =
+                    match (manifest_object_id_override, target_key.as_ref()) {
+                        (Some(manifest_object_id), Some(target_key)) => {
+                            (manifest_object_id, target_key.provider.clone(), None)
+                        }
+                        _ => lookup_manifest_meta_any(data_dir, &snapshot_id).await?,
+                    };
 
-        let storage = TelegramMtProtoStorage::connect(TelegramMtProtoStorageConfig {
-            provider: snapshot_provider.clone(),
-            api_id: settings.telegram.mtproto.api_id,
-            api_hash: api_hash.clone(),
-            bot_token: bot_token.clone(),
-            chat_id: ep.chat_id.clone(),
-            session,
-            cache_dir,
-            min_delay_ms: Some(ep.rate_limit.min_delay_ms as u64),
-            max_concurrent_uploads: Some(ep.rate_limit.max_concurrent_uploads as usize),
-            helper_path: None,
-        })
-        .await
-        .map_err(map_core_err)?;
+                let endpoint_id = if snapshot_provider == "telegram.mtproto" {
+                    None
+                } else if let Some(rest) = snapshot_provider.strip_prefix("telegram.mtproto/") {
+                    Some(rest)
+                } else {
+                    return Err(CliError::new(
+                        ErrorCode::SnapshotUnsupportedProvider,
+                        format!(
+                            "unsupported snapshot provider: snapshot_id={snapshot_id} provider={snapshot_provider}. TelevyBackup is MTProto-only now. Fix: run a new backup with MTProto."
+                        ),
+                    ));
+                };
 
-        let local_endpoint_db_path = endpoint_index_db_path(data_dir, &ep.id);
-        let (endpoint_latest, endpoint_dedupe_latest) = if is_likely_private_chat_id(&ep.chat_id) {
-            (None, None)
-        } else {
-            match televy_backup_core::bootstrap::load_remote_catalog(&storage, &master_key)
+                let ep = select_endpoint(&settings, endpoint_id)?;
+
+                if ep.mtproto_api_id(&settings.telegram.mtproto) <= 0 {
+                    return Err(CliError::new(
+                        ErrorCode::ConfigInvalid,
+                        "telegram.mtproto.api_id must be > 0",
+                    ));
+                }
+                if ep.mtproto_api_hash_key(&settings.telegram.mtproto).is_empty() {
+                    return Err(CliError::new(
+                        ErrorCode::ConfigInvalid,
+                        "telegram.mtproto.api_hash_key must not be empty",
+                    ));
+                }
+                if ep.chat_id.is_empty() {
+                    return Err(CliError::new(
+                        ErrorCode::ConfigInvalid,
+                        format!("telegram_endpoints[{id}].chat_id is empty", id = ep.id),
+                    ));
+                }
+
+                let bot_token = get_secret(config_dir, data_dir, &ep.bot_token_key)?
+                    .ok_or_else(|| CliError::new(ErrorCode::TelegramUnauthorized, "bot token missing"))?;
+                // A target key restores from the file map alone: the bootstrap catalog, endpoint DB and
+                // dedupe catalog are encrypted under the master key.
+                let master_key = match target_key.as_ref() {
+                    Some(_) => [0u8; 32],
+                    None => load_master_key(config_dir, data_dir)?,
+                };
+
+                let filemap_db_path = endpoint_filemap_dir(data_dir, &ep.id)
+                    .join(format!("{snapshot_id}.sqlite"));
+                if let Some(parent) = filemap_db_path.parent() {
+                    std::fs::create_dir_all(parent)
+                        .map_err(|e| CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()))?;
+                }
+
+                emit_running();
+
+                let api_hash = get_secret(config_dir, data_dir, ep.mtproto_api_hash_key(&settings.telegram.mtproto))?.ok_or_else(
+                    || CliError::new(ErrorCode::TelegramMtprotoMissingApiHash, "mtproto api_hash missing"),
+                )?;
+                let session = load_optional_base64_secret_bytes(
+                    config_dir,
+                    data_dir,
+                    &ep.mtproto.session_key,
+                    ErrorCode::TelegramMtprotoSessionInvalid,
+                    "invalid mtproto session (try: `televybackup secrets clear-telegram-mtproto-session`)",
+                )?;
+
+                let cache_dir = data_dir.join("cache").join("mtproto");
+                std::fs::create_dir_all(&cache_dir)
+                    .map_err(|e| CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()))?;
+
+                let storage = TelegramMtProtoStorage::connect(TelegramMtProtoStorageConfig {
+                    provider: snapshot_provider.clone(),
+                    api_id: ep.mtproto_api_id(&settings.telegram.mtproto),
+                    api_hash: api_hash.clone(),
+                    bot_token: bot_token.clone(),
+                    chat_id: ep.chat_id.clone(),
+                    migrated_from_chat_ids: ep.migrated_from_chat_ids.clone(),
+                    session,
+                    cache_dir,
+                    min_delay_ms: Some(ep.rate_limit.min_delay_ms as u64),
+                    max_concurrent_uploads: Some(ep.rate_limit.max_concurrent_uploads as usize),
+                    helper_path: None,
+                    bootstrap_pin_mode: ep.bootstrap.pin_mode,
+                    object_prefix: endpoint_object_prefix(data_dir, ep),
+                })
                 .await
-                .map_err(map_core_err)?
-            {
-                Some(cat) => (cat.endpoint_latest, cat.endpoint_dedupe_latest),
-                None => (None, None),
-            }
-        };
-        let endpoint_manifest_object_id = match endpoint_latest.as_ref() {
-            Some(v) => Some(v.manifest_object_id.clone()),
-            None => televy_backup_core::index_sync::endpoint_state_get(
-                &local_endpoint_db_path,
-                televy_backup_core::index_sync::ENDPOINT_STATE_ENDPOINT_MANIFEST_OBJECT_ID_KEY,
-            )
-            .await
-            .map_err(map_core_err)?,
-        };
-        let endpoint_index_id = match endpoint_latest.as_ref() {
-            Some(v) => Some(v.endpoint_index_id.clone()),
-            None => televy_backup_core::index_sync::endpoint_state_get(
-                &local_endpoint_db_path,
-                televy_backup_core::index_sync::ENDPOINT_STATE_ENDPOINT_INDEX_ID_KEY,
-            )
-            .await
-            .map_err(map_core_err)?,
-        };
+                .map_err(map_core_err)?;
+                save_mtproto_chat_migration(config_dir, &ep.id, &storage)?;
 
-        let local_dedupe_db_path = endpoint_dedupe_db_path(data_dir, &ep.id);
-        let dedupe_catalog_object_id =
-            endpoint_dedupe_latest.as_ref().map(|v| v.catalog_object_id.clone());
-        let endpoint_dedupe_id =
-            endpoint_dedupe_latest.as_ref().map(|v| v.endpoint_dedupe_id.clone());
+                let local_endpoint_db_path = endpoint_index_db_path(data_dir, &ep.id);
+                let (endpoint_latest, endpoint_dedupe_latest) = if target_key.is_some()
+                    || settings_config::is_likely_private_chat_id(&ep.chat_id)
+                {
+                    (None, None)
+                } else {
+                    match televy_backup_core::bootstrap::load_remote_catalog(&storage, &master_key)
+                        .await
+                        .map_err(map_core_err)?
+                    {
+                        Some(cat) => (cat.endpoint_latest, cat.endpoint_dedupe_latest),
+                        None => (None, None),
+                    }
+                };
+                let endpoint_manifest_object_id = match endpoint_latest.as_ref() {
+                    Some(v) => Some(v.manifest_object_id.clone()),
+                    None if target_key.is_some() => None,
+                    None => televy_backup_core::index_sync::endpoint_state_get(
+                        &local_endpoint_db_path,
+                        televy_backup_core::index_sync::ENDPOINT_STATE_ENDPOINT_MANIFEST_OBJECT_ID_KEY,
+                    )
+                    .await
+                    .map_err(map_core_err)?,
+                };
+                let endpoint_index_id = match endpoint_latest.as_ref() {
+                    Some(v) => Some(v.endpoint_index_id.clone()),
+                    None => televy_backup_core::index_sync::endpoint_state_get(
+                        &local_endpoint_db_path,
+                        televy_backup_core::index_sync::ENDPOINT_STATE_ENDPOINT_INDEX_ID_KEY,
+                    )
+                    .await
+                    .map_err(map_core_err)?,
+                };
 
-        let cfg = RestoreConfig {
-            snapshot_id: snapshot_id.clone(),
-            filemap_manifest_object_id: manifest_object_id,
-            endpoint_manifest_object_id: endpoint_manifest_object_id.clone(),
-            dedupe_catalog_object_id: dedupe_catalog_object_id.clone(),
-            endpoint_dedupe_id,
-            endpoint_index_id,
-            master_key,
-            filemap_db_path: filemap_db_path.clone(),
-            endpoint_db_path: (dedupe_catalog_object_id.is_none() && endpoint_manifest_object_id.is_some())
-                .then_some(local_endpoint_db_path),
-            dedupe_db_path: dedupe_catalog_object_id.is_some().then_some(local_dedupe_db_path),
-            target_path: target,
-        };
+                let local_dedupe_db_path = endpoint_dedupe_db_path(data_dir, &ep.id);
+                let dedupe_catalog_object_id =
+                    endpoint_dedupe_latest.as_ref().map(|v| v.catalog_object_id.clone());
+                let endpoint_dedupe_id =
+                    endpoint_dedupe_latest.as_ref().map(|v| v.endpoint_dedupe_id.clone());
+
+                let cfg = RestoreConfig {
+                    snapshot_id: snapshot_id.clone(),
+                    filemap_manifest_object_id: manifest_object_id,
+                    filemap_manifest_sha256: manifest_sha256,
+                    endpoint_manifest_object_id: endpoint_manifest_object_id.clone(),
+                    dedupe_catalog_object_id: dedupe_catalog_object_id.clone(),
+                    endpoint_dedupe_id,
+                    endpoint_index_id,
+                    master_key,
+                    data_key: Some(match target_key.as_ref() {
+                        Some(target_key) => target_key.data_key(),
+                        None => local_snapshot_data_key(data_dir, &master_key, &snapshot_id).await?,
+                    }),
+                    filemap_db_path: filemap_db_path.clone(),
+                    endpoint_db_path: (dedupe_catalog_object_id.is_none() && endpoint_manifest_object_id.is_some())
+                        .then_some(local_endpoint_db_path),
+                    dedupe_db_path: dedupe_catalog_object_id.is_some().then_some(local_dedupe_db_path),
+                    target_path: target,
+                };
 
-        let res = restore_snapshot_with(&storage, cfg, opts).await.map_err(map_core_err)?;
+                let remapped = RemappedStorage::new(&storage, endpoint_chat_remaps(data_dir, &ep.id).await?);
+                let res = restore_snapshot_with(&remapped, cfg, opts)
+                    .await
+                    .map_err(|e| with_partial_result(map_core_err(e), &recorder))?;
 
-        if let Some(bytes) = storage.session_bytes() {
-            let b64 = base64::engine::general_purpose::STANDARD.encode(bytes);
-            if let Err(e) = set_secret(config_dir, data_dir, &ep.mtproto.session_key, &b64) {
-                tracing::warn!(
-                    event = "secrets.session_persist_failed",
-                    error_code = e.code,
-                    error_message = %e.message,
-                    "failed to persist mtproto session"
-                );
+                if let Some(bytes) = storage.session_bytes() {
+                    let b64 = base64::engine::general_purpose::STANDARD.encode(bytes);
+                    if let Err(e) = set_secret(config_dir, data_dir, &ep.mtproto.session_key, &b64) {
+                        tracing::warn!(
+                            event = "secrets.session_persist_failed",
+                            error_code = e.code.as_str(),
+                            error_message = %e.message,
+                            "failed to persist mtproto session"
+                        );
+                    }
+                }
+
+                res
             }
+        };
+
+        if res.files_failed > 0 {
+            return Err(restore_partial_error(&res));
         }
 
         Ok(res)
@@ -5004,6 +11574,18 @@ async fn restore_run(
     .await;
 
     let duration_seconds = started.elapsed().as_secs_f64();
+    record_usage(
+        config_dir,
+        data_dir,
+        UsageRun::finished_now(
+            "restore",
+            None,
+            result.as_ref().map_or(0, |res| res.bytes_written),
+            duration_seconds,
+            run_status(&result),
+        ),
+    )
+    .await;
     match result {
         Ok(res) => {
             tracing::warn!(
@@ -5015,8 +11597,14 @@ async fn restore_run(
                 status = "succeeded",
                 duration_seconds,
                 files_restored = res.files_restored,
+                dirs_restored = res.dirs_restored,
                 chunks_downloaded = res.chunks_downloaded,
+                objects_downloaded = res.objects_downloaded,
                 bytes_written = res.bytes_written,
+                files_deleted = res.files_deleted,
+                retries = res.retry.retries,
+                retry_wait_ms = res.retry.retry_wait_ms,
+                phase_timings_ms = %res.phase_timings,
                 "run.finish"
             );
 
@@ -5029,48 +11617,185 @@ async fn restore_run(
                     "snapshotId": snapshot_id,
                     "result": {
                         "filesRestored": res.files_restored,
+                        "dirsRestored": res.dirs_restored,
                         "chunksDownloaded": res.chunks_downloaded,
+                        "objectsDownloaded": res.objects_downloaded,
                         "bytesWritten": res.bytes_written,
+                        "filesDeleted": res.files_deleted,
+                        "pathsRenamed": res.renamed_paths.len(),
+                        "pathsShortened": res.shortened_paths.len(),
+                        "retries": res.retry.retries,
+                        "retryWaitMs": res.retry.retry_wait_ms,
+                        "downloadMs": res.download_ms,
+                        "writeMs": res.write_ms,
                         "durationSeconds": duration_seconds,
+                        "phaseTimings": res.phase_timings,
+                    }
+                }));
+                return Ok(());
+            }
+
+            if json {
+                let mut out = serde_json::json!({ "ok": true });
+                add_restore_changes_json(&mut out, &res, &flags);
+                println!("{out}");
+            } else {
+                println!("ok");
+                print_restore_summary(&res, duration_seconds);
+                print_restore_changes(&res, &flags);
+            }
+            Ok(())
+        }
+        Err(e) => {
+            let ctx = RunCtx {
+                target_id: None,
+                endpoint_id: None,
+                source_path: None,
+                snapshot_id: Some(&snapshot_id),
+            };
+            log_run_finish_error(&task_id, "restore", ctx, duration_seconds, &e);
+            emit_task_state_error(events, &task_id, "restore", None, Some(&snapshot_id), &e);
+            Err(e)
+        }
+    }
+}
+
+/// A `repo export` directory opened for `restore run` / `verify run --from-local-repo`, with the
+/// snapshot's manifest and the endpoint pointers resolved the way they are against the chat.
+struct LocalRepoRun {
+    storage: LocalDirStorage,
+    manifest_object_id: String,
+    manifest_sha256: Option<String>,
+    endpoint_manifest_object_id: Option<String>,
+    endpoint_index_id: Option<String>,
+    dedupe_catalog_object_id: Option<String>,
+    endpoint_dedupe_id: Option<String>,
+    data_key: DataKey,
+    /// Where the file map and the endpoint and dedupe DBs are rebuilt; the export is only read.
+    scratch_dir: PathBuf,
+}
+
+impl LocalRepoRun {
+    fn filemap_db_path(&self, snapshot_id: &str) -> PathBuf {
+        self.scratch_dir
+            .join("filemaps")
+            .join(format!("{snapshot_id}.sqlite"))
+    }
+}
+
+/// Looks `snapshot_id` up in the export's index DB copy, else among the latest snapshots of its
+/// bootstrap catalog (snapshots other machines made). With a target key only the file map is
+/// read, as for a `--target-key` restore from the chat.
+async fn open_local_repo_run(
+    data_dir: &Path,
+    repo_dir: &Path,
+    snapshot_id: &str,
+    master_key: &[u8; 32],
+    target_key: Option<&gold_key::TargetKey>,
+    manifest_object_id_override: Option<String>,
+) -> Result<LocalRepoRun, CliError> {
+    let storage = LocalDirStorage::open(repo_dir).map_err(map_core_err)?;
+    let index_db_path = storage.index_db_path();
+    let catalog = match target_key {
+        Some(_) => None,
+        None => bootstrap::load_remote_catalog(&storage, master_key)
+            .await
+            .map_err(map_core_err)?,
+    };
+    let catalog_latest = catalog.as_ref().and_then(|cat| {
+        cat.targets.iter().find_map(|t| {
+            t.latest
+                .as_ref()
+                .filter(|latest| latest.snapshot_id == snapshot_id)
+                .map(|latest| (t.target_id.clone(), latest.clone()))
+        })
+    });
+
+    let (manifest_object_id, manifest_sha256) = match manifest_object_id_override {
+        Some(manifest_object_id) => (manifest_object_id, None),
+        None => match lookup_manifest_meta(&index_db_path, snapshot_id).await {
+            Ok((manifest_object_id, _, manifest_sha256)) => (manifest_object_id, manifest_sha256),
+            Err(e) if e.code == ErrorCode::SnapshotNotFound || !index_db_path.exists() => {
+                match &catalog_latest {
+                    Some((_, latest)) => (
+                        latest.manifest_object_id.clone(),
+                        latest.manifest_sha256.clone(),
+                    ),
+                    None => {
+                        return Err(snapshot_not_found(
+                            snapshot_id,
+                            format!("snapshot not found in repo export: {}", repo_dir.display()),
+                        ));
                     }
-                }));
-                return Ok(());
+                }
             }
+            Err(e) => return Err(e),
+        },
+    };
 
-            if json {
-                println!("{}", serde_json::json!({ "ok": true }));
+    let data_key = match target_key {
+        Some(target_key) => target_key.data_key(),
+        None => {
+            let recorded = if index_db_path.exists() {
+                televy_backup_core::index_db::snapshot_data_key_at(
+                    &index_db_path,
+                    master_key,
+                    snapshot_id,
+                )
+                .await
+                .map_err(map_core_err)?
             } else {
-                println!("ok");
-            }
-            Ok(())
-        }
-        Err(e) => {
-            tracing::error!(
-                event = "run.finish",
-                kind = "restore",
-                run_id = %task_id,
-                task_id = %task_id,
-                snapshot_id = %snapshot_id,
-                status = "failed",
-                duration_seconds,
-                error_code = e.code,
-                error_message = %e.message,
-                retryable = e.retryable,
-                "run.finish"
-            );
-            if events {
-                emit_event_stdout(serde_json::json!({
-                    "type": "task.state",
-                    "taskId": task_id,
-                    "kind": "restore",
-                    "state": "failed",
-                    "snapshotId": snapshot_id,
-                    "error": { "code": e.code, "message": e.message.clone() },
-                }));
+                None
+            };
+            match (recorded, &catalog_latest) {
+                (Some(data_key), _) => data_key,
+                (None, Some((target_id, latest))) => latest
+                    .data_key(master_key, target_id)
+                    .map_err(map_core_err)?,
+                (None, None) => DataKey::master(master_key),
             }
-            Err(e)
         }
-    }
+    };
+
+    let endpoint_latest = catalog.as_ref().and_then(|c| c.endpoint_latest.clone());
+    let (endpoint_manifest_object_id, endpoint_index_id) = match endpoint_latest {
+        Some(v) => (Some(v.manifest_object_id), Some(v.endpoint_index_id)),
+        None if target_key.is_some() => (None, None),
+        None => (
+            televy_backup_core::index_sync::endpoint_state_get(
+                &index_db_path,
+                televy_backup_core::index_sync::ENDPOINT_STATE_ENDPOINT_MANIFEST_OBJECT_ID_KEY,
+            )
+            .await
+            .map_err(map_core_err)?,
+            televy_backup_core::index_sync::endpoint_state_get(
+                &index_db_path,
+                televy_backup_core::index_sync::ENDPOINT_STATE_ENDPOINT_INDEX_ID_KEY,
+            )
+            .await
+            .map_err(map_core_err)?,
+        ),
+    };
+    let dedupe_latest = catalog.and_then(|c| c.endpoint_dedupe_latest);
+
+    let scratch_dir = data_dir
+        .join("cache")
+        .join("local-repo")
+        .join(&storage.manifest().endpoint_id);
+    std::fs::create_dir_all(scratch_dir.join("filemaps"))
+        .map_err(|e| CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()))?;
+
+    Ok(LocalRepoRun {
+        manifest_object_id,
+        manifest_sha256,
+        endpoint_manifest_object_id,
+        endpoint_index_id,
+        dedupe_catalog_object_id: dedupe_latest.as_ref().map(|v| v.catalog_object_id.clone()),
+        endpoint_dedupe_id: dedupe_latest.map(|v| v.endpoint_dedupe_id),
+        data_key,
+        scratch_dir,
+        storage,
+    })
 }
 
 async fn restore_list_latest(
@@ -5081,43 +11806,95 @@ async fn restore_list_latest(
 ) -> Result<(), CliError> {
     let settings = load_settings(config_dir)?;
     let ep = select_endpoint(&settings, endpoint_id.as_deref())?;
+    if ep.bootstrap.pin_mode == bootstrap::BootstrapPinMode::Disabled {
+        return Err(bootstrap_missing_err(ep.bootstrap.pin_mode));
+    }
+
+    let (storage, master_key) =
+        connect_pinned_endpoint(config_dir, data_dir, &settings, ep).await?;
+
+    let cat = televy_backup_core::bootstrap::load_remote_catalog(&storage, &master_key)
+        .await
+        .map_err(map_core_err)?;
+    let Some(cat) = cat else {
+        return Err(bootstrap_missing_err(ep.bootstrap.pin_mode));
+    };
+
+    persist_mtproto_session(config_dir, data_dir, ep, &storage);
+
+    if json {
+        println!("{}", serde_json::json!({ "catalog": cat }));
+        return Ok(());
+    }
+
+    println!("updatedAt={}", cat.updated_at);
+    for t in cat.targets {
+        if let Some(latest) = t.latest {
+            println!(
+                "targetId={} sourcePath={} snapshotId={} manifestObjectId={} deviceName={} objectPrefix={}",
+                t.target_id,
+                t.source_path,
+                latest.snapshot_id,
+                latest.manifest_object_id,
+                latest.device_name.as_deref().unwrap_or("unknown"),
+                latest.object_prefix.as_deref().unwrap_or("none")
+            );
+        } else {
+            println!(
+                "targetId={} sourcePath={} latest=none",
+                t.target_id, t.source_path
+            );
+        }
+    }
+    Ok(())
+}
 
-    if settings.telegram.mtproto.api_id <= 0 {
+/// Connects to an endpoint whose chat supports the pinned bootstrap catalog.
+async fn connect_pinned_endpoint(
+    config_dir: &Path,
+    data_dir: &Path,
+    settings: &Settings,
+    ep: &settings_config::TelegramEndpoint,
+) -> Result<(TelegramMtProtoStorage, [u8; 32]), CliError> {
+    if ep.mtproto_api_id(&settings.telegram.mtproto) <= 0 {
         return Err(CliError::new(
-            "config.invalid",
+            ErrorCode::ConfigInvalid,
             "telegram.mtproto.api_id must be > 0",
         ));
     }
-    if settings.telegram.mtproto.api_hash_key.is_empty() {
+    if ep
+        .mtproto_api_hash_key(&settings.telegram.mtproto)
+        .is_empty()
+    {
         return Err(CliError::new(
-            "config.invalid",
+            ErrorCode::ConfigInvalid,
             "telegram.mtproto.api_hash_key must not be empty",
         ));
     }
     if ep.chat_id.is_empty() {
         return Err(CliError::new(
-            "config.invalid",
+            ErrorCode::ConfigInvalid,
             format!("telegram_endpoints[{id}].chat_id is empty", id = ep.id),
         ));
     }
-    if is_likely_private_chat_id(&ep.chat_id) {
+    if settings_config::is_likely_private_chat_id(&ep.chat_id) {
         return Err(CliError::new(
-            "bootstrap.unsupported_chat",
+            ErrorCode::BootstrapUnsupportedChat,
             "bootstrap catalog requires message pinning; private chats are not supported. Use a group/channel (e.g. -100...) or an @username chat id.".to_string(),
         ));
     }
 
     let bot_token = get_secret(config_dir, data_dir, &ep.bot_token_key)?
-        .ok_or_else(|| CliError::new("telegram.unauthorized", "bot token missing"))?;
+        .ok_or_else(|| CliError::new(ErrorCode::TelegramUnauthorized, "bot token missing"))?;
     let master_key = load_master_key(config_dir, data_dir)?;
     let api_hash = get_secret(
         config_dir,
         data_dir,
-        &settings.telegram.mtproto.api_hash_key,
+        ep.mtproto_api_hash_key(&settings.telegram.mtproto),
     )?
     .ok_or_else(|| {
         CliError::new(
-            "telegram.mtproto.missing_api_hash",
+            ErrorCode::TelegramMtprotoMissingApiHash,
             "mtproto api_hash missing",
         )
     })?;
@@ -5125,86 +11902,427 @@ async fn restore_list_latest(
         config_dir,
         data_dir,
         &ep.mtproto.session_key,
-        "telegram.mtproto.session_invalid",
+        ErrorCode::TelegramMtprotoSessionInvalid,
         "invalid mtproto session (try: `televybackup secrets clear-telegram-mtproto-session`)",
     )?;
 
     let cache_dir = data_dir.join("cache").join("mtproto");
     std::fs::create_dir_all(&cache_dir)
-        .map_err(|e| CliError::new("config.write_failed", e.to_string()))?;
+        .map_err(|e| CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()))?;
 
     let provider = settings_config::endpoint_provider(&ep.id);
     let storage = TelegramMtProtoStorage::connect(TelegramMtProtoStorageConfig {
         provider,
-        api_id: settings.telegram.mtproto.api_id,
+        api_id: ep.mtproto_api_id(&settings.telegram.mtproto),
         api_hash: api_hash.clone(),
         bot_token: bot_token.clone(),
         chat_id: ep.chat_id.clone(),
+        migrated_from_chat_ids: ep.migrated_from_chat_ids.clone(),
         session,
         cache_dir,
         min_delay_ms: Some(ep.rate_limit.min_delay_ms as u64),
         max_concurrent_uploads: Some(ep.rate_limit.max_concurrent_uploads as usize),
         helper_path: None,
+        bootstrap_pin_mode: ep.bootstrap.pin_mode,
+        object_prefix: endpoint_object_prefix(data_dir, ep),
     })
     .await
     .map_err(map_core_err)?;
+    save_mtproto_chat_migration(config_dir, &ep.id, &storage)?;
 
-    let cat = televy_backup_core::bootstrap::load_remote_catalog(&storage, &master_key)
-        .await
-        .map_err(map_core_err)?;
-    let Some(cat) = cat else {
-        return Err(CliError::new(
-            "bootstrap.missing",
-            "bootstrap missing (no pinned catalog)",
-        ));
-    };
+    Ok((storage, master_key))
+}
 
+fn persist_mtproto_session(
+    config_dir: &Path,
+    data_dir: &Path,
+    ep: &settings_config::TelegramEndpoint,
+    storage: &TelegramMtProtoStorage,
+) {
     if let Some(bytes) = storage.session_bytes() {
         let b64 = base64::engine::general_purpose::STANDARD.encode(bytes);
         if let Err(e) = set_secret(config_dir, data_dir, &ep.mtproto.session_key, &b64) {
             tracing::warn!(
                 event = "secrets.session_persist_failed",
-                error_code = e.code,
+                error_code = e.code.as_str(),
                 error_message = %e.message,
                 "failed to persist mtproto session"
             );
         }
     }
+}
+
+async fn load_bootstrap_catalog(
+    storage: &TelegramMtProtoStorage,
+    master_key: &[u8; 32],
+) -> Result<(String, bootstrap::BootstrapCatalogV1), CliError> {
+    let revision = bootstrap::remote_catalog_object_id(storage).map_err(map_core_err)?;
+    let cat = bootstrap::load_remote_catalog(storage, master_key)
+        .await
+        .map_err(map_core_err)?;
+    match (revision, cat) {
+        (Some(revision), Some(cat)) => Ok((revision, cat)),
+        _ => Err(bootstrap_missing_err(
+            bootstrap::PinnedStorage::bootstrap_pin_mode(storage),
+        )),
+    }
+}
+
+/// The error for an endpoint without a remote catalog, explaining its `bootstrap.pin_mode`.
+fn bootstrap_missing_err(mode: bootstrap::BootstrapPinMode) -> CliError {
+    if mode == bootstrap::BootstrapPinMode::Disabled {
+        return CliError::new(
+            ErrorCode::BootstrapDisabled,
+            "this endpoint keeps no bootstrap catalog (bootstrap.pin_mode = \"disabled\"), so latest snapshots can't be resolved from the chat; list them with `televybackup snapshots list` and restore one with `televybackup restore run --snapshot-id <id>` on a machine with the local index",
+        );
+    }
+    CliError::new(
+        ErrorCode::BootstrapMissing,
+        format!("bootstrap missing ({})", mode.missing_message()),
+    )
+}
+
+fn bootstrap_target_line(target: Option<&bootstrap::BootstrapTarget>) -> String {
+    let Some(t) = target else {
+        return "none".to_string();
+    };
+    match &t.latest {
+        Some(latest) => format!(
+            "targetId={} sourcePath={} label={} snapshotId={} manifestObjectId={} deviceName={}",
+            t.target_id,
+            t.source_path,
+            t.label,
+            latest.snapshot_id,
+            latest.manifest_object_id,
+            latest.device_name.as_deref().unwrap_or("unknown")
+        ),
+        None => format!(
+            "targetId={} sourcePath={} label={} latest=none",
+            t.target_id, t.source_path, t.label
+        ),
+    }
+}
+
+async fn bootstrap_show(
+    config_dir: &Path,
+    data_dir: &Path,
+    endpoint_id: Option<String>,
+    json: bool,
+) -> Result<(), CliError> {
+    let settings = load_settings(config_dir)?;
+    let ep = select_endpoint(&settings, endpoint_id.as_deref())?;
+    let (storage, master_key) =
+        connect_pinned_endpoint(config_dir, data_dir, &settings, ep).await?;
+    let (revision, cat) = load_bootstrap_catalog(&storage, &master_key).await?;
+    persist_mtproto_session(config_dir, data_dir, ep, &storage);
+
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({
+                "endpointId": ep.id,
+                "revision": revision,
+                "updatedAt": cat.updated_at,
+                "catalog": cat,
+            })
+        );
+        return Ok(());
+    }
+
+    println!("endpointId={}", ep.id);
+    println!("revision={revision}");
+    println!("updatedAt={}", cat.updated_at);
+    if let Some(latest) = &cat.endpoint_latest {
+        println!(
+            "endpointIndexId={} endpointManifestObjectId={}",
+            latest.endpoint_index_id, latest.manifest_object_id
+        );
+    }
+    if let Some(latest) = &cat.endpoint_dedupe_latest {
+        println!(
+            "endpointDedupeId={} dedupeCatalogObjectId={}",
+            latest.endpoint_dedupe_id, latest.catalog_object_id
+        );
+    }
+    for t in &cat.targets {
+        println!("{}", bootstrap_target_line(Some(t)));
+    }
+    Ok(())
+}
+
+async fn bootstrap_remove_target(
+    config_dir: &Path,
+    data_dir: &Path,
+    endpoint_id: Option<String>,
+    target_id: &str,
+    yes: bool,
+    json: bool,
+) -> Result<(), CliError> {
+    let settings = load_settings(config_dir)?;
+    let ep = select_endpoint(&settings, endpoint_id.as_deref())?;
+    let (storage, master_key) =
+        connect_pinned_endpoint(config_dir, data_dir, &settings, ep).await?;
+    let (revision, mut cat) = load_bootstrap_catalog(&storage, &master_key).await?;
+
+    let before = cat.remove_target(target_id).ok_or_else(|| {
+        CliError::new(
+            ErrorCode::BootstrapTargetMissing,
+            format!("bootstrap missing target_id: {target_id}"),
+        )
+    })?;
+    let edit = BootstrapEdit {
+        action: "remove_target",
+        target_id,
+        revision,
+        before: Some(before),
+        after: None,
+    };
+    save_bootstrap_edit(
+        config_dir,
+        data_dir,
+        ep,
+        &storage,
+        &master_key,
+        cat,
+        edit,
+        yes,
+        json,
+    )
+    .await
+}
+
+async fn bootstrap_set_latest(
+    config_dir: &Path,
+    data_dir: &Path,
+    endpoint_id: Option<String>,
+    target_id: &str,
+    snapshot_id: &str,
+    yes: bool,
+    json: bool,
+) -> Result<(), CliError> {
+    let settings = load_settings(config_dir)?;
+    let ep = select_endpoint(&settings, endpoint_id.as_deref())?;
+
+    // Validate against the local index DB before touching the remote catalog.
+    let db_path = endpoint_index_db_path(data_dir, &ep.id);
+    if !db_path.exists() {
+        return Err(snapshot_not_found(
+            snapshot_id,
+            format!("local index db not found: {}", db_path.display()),
+        ));
+    }
+    let pool = televy_backup_core::index_db::open_existing_index_db(&db_path)
+        .await
+        .map_err(map_core_err)?;
+    let row = sqlx::query(
+        r#"
+        SELECT s.source_path, s.label, s.device_id, s.device_name,
+               r.manifest_object_id, r.manifest_sha256, r.object_prefix
+        FROM snapshots s
+        LEFT JOIN remote_indexes r ON r.snapshot_id = s.snapshot_id AND r.provider = ?
+        WHERE s.snapshot_id = ?
+        "#,
+    )
+    .bind(settings_config::endpoint_provider(&ep.id))
+    .bind(snapshot_id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|e| CliError::new(ErrorCode::DbFailed, e.to_string()))?
+    .ok_or_else(|| {
+        snapshot_not_found(
+            snapshot_id,
+            format!("snapshot not found in local db: {snapshot_id}"),
+        )
+    })?;
+    let key_record = {
+        let mut conn = pool
+            .acquire()
+            .await
+            .map_err(|e| CliError::new(ErrorCode::DbFailed, e.to_string()))?;
+        televy_backup_core::index_db::snapshot_key_derivation(&mut conn, snapshot_id)
+            .await
+            .map_err(map_core_err)?
+    };
+    pool.close().await;
+    let (key_derivation, key_target_id) = key_record.unwrap_or_default();
+    // The catalog derives a target key from the target id it files the pointer under.
+    if key_derivation != KeyDerivation::Master && key_target_id.as_deref() != Some(target_id) {
+        return Err(CliError::new(
+            ErrorCode::ConfigInvalid,
+            format!(
+                "snapshot {snapshot_id} is encrypted with the target key of {}, not {target_id}",
+                key_target_id.as_deref().unwrap_or("?")
+            ),
+        ));
+    }
+
+    let source_path: String = row.get("source_path");
+    let label: String = row.get("label");
+    let Some(manifest_object_id) = row.get::<Option<String>, _>("manifest_object_id") else {
+        return Err(snapshot_not_found(
+            snapshot_id,
+            format!(
+                "manifest not found in local db: snapshot_id={snapshot_id} endpoint_id={}",
+                ep.id
+            ),
+        ));
+    };
+    if let Some(target) = settings.targets.iter().find(|t| t.id == target_id)
+        && target.source_path != source_path
+    {
+        return Err(CliError::new(
+            ErrorCode::ConfigInvalid,
+            format!(
+                "snapshot {snapshot_id} was taken from {source_path}, not target {target_id} ({})",
+                target.source_path
+            ),
+        ));
+    }
+    let latest = bootstrap::BootstrapLatest {
+        snapshot_id: snapshot_id.to_string(),
+        manifest_object_id,
+        manifest_sha256: row.get("manifest_sha256"),
+        device_id: row.get("device_id"),
+        device_name: row.get("device_name"),
+        key_derivation: key_derivation.version(),
+        object_prefix: row.get("object_prefix"),
+    };
+
+    let (storage, master_key) =
+        connect_pinned_endpoint(config_dir, data_dir, &settings, ep).await?;
+    let (revision, mut cat) = load_bootstrap_catalog(&storage, &master_key).await?;
+    let before = cat.target(target_id).cloned();
+    cat.set_latest(target_id, &source_path, &label, latest);
+    let after = cat.target(target_id).cloned();
+    let edit = BootstrapEdit {
+        action: "set_latest",
+        target_id,
+        revision,
+        before,
+        after,
+    };
+    save_bootstrap_edit(
+        config_dir,
+        data_dir,
+        ep,
+        &storage,
+        &master_key,
+        cat,
+        edit,
+        yes,
+        json,
+    )
+    .await
+}
+
+struct BootstrapEdit<'a> {
+    action: &'static str,
+    target_id: &'a str,
+    revision: String,
+    before: Option<bootstrap::BootstrapTarget>,
+    after: Option<bootstrap::BootstrapTarget>,
+}
+
+/// Prints the before/after entries, confirms, then re-pins the edited catalog.
+#[allow(clippy::too_many_arguments)]
+async fn save_bootstrap_edit(
+    config_dir: &Path,
+    data_dir: &Path,
+    ep: &settings_config::TelegramEndpoint,
+    storage: &TelegramMtProtoStorage,
+    master_key: &[u8; 32],
+    mut cat: bootstrap::BootstrapCatalogV1,
+    edit: BootstrapEdit<'_>,
+    yes: bool,
+    json: bool,
+) -> Result<(), CliError> {
+    let before_line = format!("before: {}", bootstrap_target_line(edit.before.as_ref()));
+    let after_line = format!("after: {}", bootstrap_target_line(edit.after.as_ref()));
+    // Keep stdout a single JSON document in --json mode.
+    if json {
+        eprintln!("{before_line}\n{after_line}");
+    } else {
+        println!("{before_line}\n{after_line}");
+    }
+
+    if !yes {
+        if !std::io::stdin().is_terminal() {
+            return Err(CliError::new(
+                ErrorCode::BootstrapConfirmationRequired,
+                "re-run with --yes to write the bootstrap catalog",
+            ));
+        }
+        eprint!(
+            "Write the bootstrap catalog of endpoint {} ({})? [y/N] ",
+            ep.id, edit.action
+        );
+        let _ = std::io::stderr().flush();
+        let answer = tokio::task::spawn_blocking(|| {
+            let mut line = String::new();
+            std::io::stdin().read_line(&mut line).map(|_| line)
+        })
+        .await
+        .map_err(|e| CliError::new(ErrorCode::TaskCancelled, format!("prompt aborted: {e}")))?
+        .map_err(|e| CliError::new(ErrorCode::Io, e.to_string()))?;
+        if !prompt_answer_confirms(&answer) {
+            return Err(CliError::new(
+                ErrorCode::TaskCancelled,
+                "bootstrap catalog left unchanged",
+            ));
+        }
+    }
+
+    cat.touch();
+    let revision = bootstrap::save_remote_catalog(storage, master_key, &cat)
+        .await
+        .map_err(map_core_err)?;
+    record_audit(
+        data_dir,
+        televy_backup_core::audit::AUDIT_OP_BOOTSTRAP_EDIT,
+        televy_backup_core::audit::bootstrap_edit_details(
+            edit.action,
+            &ep.id,
+            edit.target_id,
+            edit.before.as_ref(),
+            edit.after.as_ref(),
+        ),
+    );
+    persist_mtproto_session(config_dir, data_dir, ep, storage);
 
     if json {
-        println!("{}", serde_json::json!({ "catalog": cat }));
-        return Ok(());
-    }
-
-    println!("updatedAt={}", cat.updated_at);
-    for t in cat.targets {
-        if let Some(latest) = t.latest {
-            println!(
-                "targetId={} sourcePath={} snapshotId={} manifestObjectId={}",
-                t.target_id, t.source_path, latest.snapshot_id, latest.manifest_object_id
-            );
-        } else {
-            println!(
-                "targetId={} sourcePath={} latest=none",
-                t.target_id, t.source_path
-            );
-        }
+        println!(
+            "{}",
+            serde_json::json!({
+                "endpointId": ep.id,
+                "action": edit.action,
+                "targetId": edit.target_id,
+                "previousRevision": edit.revision,
+                "revision": revision,
+                "updatedAt": cat.updated_at,
+                "before": edit.before,
+                "after": edit.after,
+            })
+        );
+    } else {
+        println!("revision={revision} previousRevision={}", edit.revision);
     }
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn restore_latest(
     config_dir: &Path,
     data_dir: &Path,
     target_id: Option<String>,
     source_path: Option<PathBuf>,
     target: PathBuf,
+    flags: RestoreFlags,
     json: bool,
     events: bool,
 ) -> Result<(), CliError> {
     let task_id = format!("tsk_{}", uuid::Uuid::new_v4());
     let run_log = televy_backup_core::run_log::start_run_log("restore", &task_id, data_dir)
-        .map_err(|e| CliError::new("log.init_failed", e.to_string()))?;
+        .map_err(|e| CliError::new(ErrorCode::LogInitFailed, e.to_string()))?;
     let started = std::time::Instant::now();
 
     let settings = match load_settings(config_dir) {
@@ -5226,6 +12344,7 @@ async fn restore_latest(
             );
         }
     };
+    prune_run_logs_best_effort(data_dir, &settings);
 
     let t = match select_target(&settings, target_id.as_deref(), source_path.as_deref()) {
         Ok(t) => t,
@@ -5255,7 +12374,7 @@ async fn restore_latest(
         Some(ep) => ep,
         None => {
             let e = CliError::new(
-                "config.invalid",
+                ErrorCode::ConfigInvalid,
                 format!(
                     "target references unknown endpoint_id: target_id={} endpoint_id={}",
                     t.id, t.endpoint_id
@@ -5278,8 +12397,11 @@ async fn restore_latest(
         }
     };
 
-    if settings.telegram.mtproto.api_id <= 0 {
-        let e = CliError::new("config.invalid", "telegram.mtproto.api_id must be > 0");
+    if ep.mtproto_api_id(&settings.telegram.mtproto) <= 0 {
+        let e = CliError::new(
+            ErrorCode::ConfigInvalid,
+            "telegram.mtproto.api_id must be > 0",
+        );
         return emit_preflight_failed(
             events,
             &task_id,
@@ -5295,9 +12417,12 @@ async fn restore_latest(
             e,
         );
     }
-    if settings.telegram.mtproto.api_hash_key.is_empty() {
+    if ep
+        .mtproto_api_hash_key(&settings.telegram.mtproto)
+        .is_empty()
+    {
         let e = CliError::new(
-            "config.invalid",
+            ErrorCode::ConfigInvalid,
             "telegram.mtproto.api_hash_key must not be empty",
         );
         return emit_preflight_failed(
@@ -5317,7 +12442,7 @@ async fn restore_latest(
     }
     if ep.chat_id.is_empty() {
         let e = CliError::new(
-            "config.invalid",
+            ErrorCode::ConfigInvalid,
             format!("telegram_endpoints[{id}].chat_id is empty", id = ep.id),
         );
         return emit_preflight_failed(
@@ -5335,9 +12460,26 @@ async fn restore_latest(
             e,
         );
     }
-    if is_likely_private_chat_id(&ep.chat_id) {
+    if ep.bootstrap.pin_mode == bootstrap::BootstrapPinMode::Disabled {
+        let e = bootstrap_missing_err(ep.bootstrap.pin_mode);
+        return emit_preflight_failed(
+            events,
+            &task_id,
+            "restore",
+            run_log.path(),
+            started,
+            RunCtx {
+                target_id: Some(t.id.as_str()),
+                endpoint_id: Some(ep.id.as_str()),
+                source_path: Some(t.source_path.as_str()),
+                snapshot_id: Some("latest"),
+            },
+            e,
+        );
+    }
+    if settings_config::is_likely_private_chat_id(&ep.chat_id) {
         let e = CliError::new(
-            "bootstrap.unsupported_chat",
+            ErrorCode::BootstrapUnsupportedChat,
             "restore latest requires the pinned bootstrap catalog; private chats are not supported. Use a group/channel (e.g. -100...) or an @username chat id.".to_string(),
         );
         return emit_preflight_failed(
@@ -5383,16 +12525,16 @@ async fn restore_latest(
 
     let result: Result<(String, televy_backup_core::RestoreResult), CliError> = async {
         let bot_token = get_secret(config_dir, data_dir, &ep.bot_token_key)?
-            .ok_or_else(|| CliError::new("telegram.unauthorized", "bot token missing"))?;
+            .ok_or_else(|| CliError::new(ErrorCode::TelegramUnauthorized, "bot token missing"))?;
         let master_key = load_master_key(config_dir, data_dir)?;
         let api_hash = get_secret(
             config_dir,
             data_dir,
-            &settings.telegram.mtproto.api_hash_key,
+            ep.mtproto_api_hash_key(&settings.telegram.mtproto),
         )?
         .ok_or_else(|| {
             CliError::new(
-                "telegram.mtproto.missing_api_hash",
+                ErrorCode::TelegramMtprotoMissingApiHash,
                 "mtproto api_hash missing",
             )
         })?;
@@ -5400,34 +12542,38 @@ async fn restore_latest(
             config_dir,
             data_dir,
             &ep.mtproto.session_key,
-            "telegram.mtproto.session_invalid",
+            ErrorCode::TelegramMtprotoSessionInvalid,
             "invalid mtproto session (try: `televybackup secrets clear-telegram-mtproto-session`)",
         )?;
 
         let cache_dir = data_dir.join("cache").join("mtproto");
         std::fs::create_dir_all(&cache_dir)
-            .map_err(|e| CliError::new("config.write_failed", e.to_string()))?;
+            .map_err(|e| CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()))?;
 
         let provider = settings_config::endpoint_provider(&ep.id);
         let storage = TelegramMtProtoStorage::connect(TelegramMtProtoStorageConfig {
             provider: provider.clone(),
-            api_id: settings.telegram.mtproto.api_id,
+            api_id: ep.mtproto_api_id(&settings.telegram.mtproto),
             api_hash: api_hash.clone(),
             bot_token: bot_token.clone(),
             chat_id: ep.chat_id.clone(),
+            migrated_from_chat_ids: ep.migrated_from_chat_ids.clone(),
             session,
             cache_dir,
             min_delay_ms: Some(ep.rate_limit.min_delay_ms as u64),
             max_concurrent_uploads: Some(ep.rate_limit.max_concurrent_uploads as usize),
             helper_path: None,
+            bootstrap_pin_mode: ep.bootstrap.pin_mode,
+            object_prefix: endpoint_object_prefix(data_dir, ep),
         })
         .await
         .map_err(map_core_err)?;
+        save_mtproto_chat_migration(config_dir, &ep.id, &storage)?;
 
         let cat = televy_backup_core::bootstrap::load_remote_catalog(&storage, &master_key)
             .await
             .map_err(map_core_err)?
-            .ok_or_else(|| CliError::new("bootstrap.missing", "no pinned bootstrap catalog"))?;
+            .ok_or_else(|| bootstrap_missing_err(ep.bootstrap.pin_mode))?;
 
         let latest = cat
             .targets
@@ -5436,10 +12582,16 @@ async fn restore_latest(
             .and_then(|it| it.latest.clone())
             .ok_or_else(|| {
                 CliError::new(
-                    "bootstrap.latest_missing",
+                    ErrorCode::BootstrapLatestMissing,
                     format!("bootstrap missing latest for target_id: {}", t.id),
                 )
             })?;
+        if latest.manifest_sha256.is_none() {
+            televy_backup_core::remote_index_db::warn_manifest_unverified(
+                &latest.snapshot_id,
+                &latest.manifest_object_id,
+            );
+        }
         let endpoint_latest = cat.endpoint_latest.clone();
         let endpoint_dedupe_latest = cat.endpoint_dedupe_latest.clone();
 
@@ -5455,7 +12607,7 @@ async fn restore_latest(
             endpoint_filemap_dir(data_dir, &ep.id).join(format!("{}.sqlite", latest.snapshot_id));
         if let Some(parent) = filemap_db_path.parent() {
             std::fs::create_dir_all(parent)
-                .map_err(|e| CliError::new("config.write_failed", e.to_string()))?;
+                .map_err(|e| CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()))?;
         }
 
         let sink = NdjsonProgressSink {
@@ -5467,9 +12619,23 @@ async fn restore_latest(
                 target_id: t.id.clone(),
             }),
         };
+        let cancel = cancel_on_stop_signal();
+        let recorder = ProgressRecorder::new(events.then_some(&sink as &dyn ProgressSink));
         let opts = RestoreOptions {
-            cancel: None,
-            progress: if events { Some(&sink) } else { None },
+            cancel: Some(&cancel),
+            progress: Some(&recorder),
+            keep_going: flags.keep_going,
+            retry: settings.retry.clone(),
+            delete_extraneous: flags.delete_extraneous,
+            dry_run: flags.dry_run,
+            as_file: flags.as_file,
+            preserve_times: flags.preserve_times,
+            rename_collisions: flags.rename_collisions,
+            shorten_long_paths: flags.shorten_long_paths,
+            path_limits: None,
+            ownership: flags.ownership.clone(),
+            write_concurrency: settings.restore.write_concurrency as usize,
+            fsync: RestoreFsync::parse(&settings.restore.fsync).unwrap_or_default(),
         };
 
         let local_endpoint_db_path = endpoint_index_db_path(data_dir, &ep.id);
@@ -5483,9 +12649,11 @@ async fn restore_latest(
         let endpoint_dedupe_id = endpoint_dedupe_latest
             .as_ref()
             .map(|v| v.endpoint_dedupe_id.clone());
+        let data_key = latest.data_key(&master_key, &t.id).map_err(map_core_err)?;
         let cfg = RestoreConfig {
             snapshot_id: latest.snapshot_id.clone(),
             filemap_manifest_object_id: latest.manifest_object_id,
+            filemap_manifest_sha256: latest.manifest_sha256,
             endpoint_manifest_object_id: endpoint_manifest_object_id.clone(),
             dedupe_catalog_object_id: dedupe_catalog_object_id.clone(),
             endpoint_dedupe_id,
@@ -5493,6 +12661,7 @@ async fn restore_latest(
                 .as_ref()
                 .map(|v| v.endpoint_index_id.clone()),
             master_key,
+            data_key: Some(data_key),
             filemap_db_path: filemap_db_path.clone(),
             endpoint_db_path: (dedupe_catalog_object_id.is_none()
                 && endpoint_manifest_object_id.is_some())
@@ -5503,27 +12672,45 @@ async fn restore_latest(
             target_path: target,
         };
 
-        let res = restore_snapshot_with(&storage, cfg, opts)
+        let remapped =
+            RemappedStorage::new(&storage, endpoint_chat_remaps(data_dir, &ep.id).await?);
+        let res = restore_snapshot_with(&remapped, cfg, opts)
             .await
-            .map_err(map_core_err)?;
+            .map_err(|e| with_partial_result(map_core_err(e), &recorder))?;
 
         if let Some(bytes) = storage.session_bytes() {
             let b64 = base64::engine::general_purpose::STANDARD.encode(bytes);
             if let Err(e) = set_secret(config_dir, data_dir, &ep.mtproto.session_key, &b64) {
                 tracing::warn!(
                     event = "secrets.session_persist_failed",
-                    error_code = e.code,
+                    error_code = e.code.as_str(),
                     error_message = %e.message,
                     "failed to persist mtproto session"
                 );
             }
         }
 
+        if res.files_failed > 0 {
+            return Err(restore_partial_error(&res));
+        }
+
         Ok((latest.snapshot_id, res))
     }
     .await;
 
     let duration_seconds = started.elapsed().as_secs_f64();
+    record_usage(
+        config_dir,
+        data_dir,
+        UsageRun::finished_now(
+            "restore",
+            Some(t.id.as_str()),
+            result.as_ref().map_or(0, |(_, res)| res.bytes_written),
+            duration_seconds,
+            run_status(&result),
+        ),
+    )
+    .await;
     match result {
         Ok((snapshot_id, res)) => {
             tracing::warn!(
@@ -5538,8 +12725,14 @@ async fn restore_latest(
                 status = "succeeded",
                 duration_seconds,
                 files_restored = res.files_restored,
+                dirs_restored = res.dirs_restored,
                 chunks_downloaded = res.chunks_downloaded,
+                objects_downloaded = res.objects_downloaded,
                 bytes_written = res.bytes_written,
+                files_deleted = res.files_deleted,
+                retries = res.retry.retries,
+                retry_wait_ms = res.retry.retry_wait_ms,
+                phase_timings_ms = %res.phase_timings,
                 "run.finish"
             );
 
@@ -5553,9 +12746,19 @@ async fn restore_latest(
                     "targetId": t.id.clone(),
                     "result": {
                         "filesRestored": res.files_restored,
+                        "dirsRestored": res.dirs_restored,
                         "chunksDownloaded": res.chunks_downloaded,
+                        "objectsDownloaded": res.objects_downloaded,
                         "bytesWritten": res.bytes_written,
+                        "filesDeleted": res.files_deleted,
+                        "pathsRenamed": res.renamed_paths.len(),
+                        "pathsShortened": res.shortened_paths.len(),
+                        "retries": res.retry.retries,
+                        "retryWaitMs": res.retry.retry_wait_ms,
+                        "downloadMs": res.download_ms,
+                        "writeMs": res.write_ms,
                         "durationSeconds": duration_seconds,
+                        "phaseTimings": res.phase_timings,
                     }
                 }));
                 daemon_control_status_task_finish(
@@ -5569,48 +12772,33 @@ async fn restore_latest(
             }
 
             if json {
-                println!(
-                    "{}",
-                    serde_json::json!({ "ok": true, "snapshotId": snapshot_id })
-                );
+                let mut out = serde_json::json!({ "ok": true, "snapshotId": snapshot_id });
+                add_restore_changes_json(&mut out, &res, &flags);
+                println!("{out}");
             } else {
                 println!("ok");
                 println!("snapshotId={snapshot_id}");
+                print_restore_summary(&res, duration_seconds);
+                print_restore_changes(&res, &flags);
             }
             Ok(())
         }
         Err(e) => {
-            tracing::error!(
-                event = "run.finish",
-                kind = "restore",
-                run_id = %task_id,
-                task_id = %task_id,
-                target_id = %t.id,
-                endpoint_id = %ep.id,
-                source_path = %t.source_path,
-                snapshot_id = "latest",
-                status = "failed",
-                duration_seconds,
-                error_code = e.code,
-                error_message = %e.message,
-                retryable = e.retryable,
-                "run.finish"
-            );
+            let ctx = RunCtx {
+                target_id: Some(&t.id),
+                endpoint_id: Some(&ep.id),
+                source_path: Some(&t.source_path),
+                snapshot_id: Some("latest"),
+            };
+            let status = log_run_finish_error(&task_id, "restore", ctx, duration_seconds, &e);
             if events {
-                emit_event_stdout(serde_json::json!({
-                    "type": "task.state",
-                    "taskId": task_id,
-                    "kind": "restore",
-                    "state": "failed",
-                    "targetId": t.id.clone(),
-                    "error": { "code": e.code, "message": e.message.clone() },
-                }));
+                emit_task_state_error(events, &task_id, "restore", Some(t.id.as_str()), None, &e);
                 daemon_control_status_task_finish(
                     data_dir,
                     &task_id,
                     "restore",
                     t.id.as_str(),
-                    "failed",
+                    status.as_str(),
                 );
             }
             Err(e)
@@ -5618,17 +12806,72 @@ async fn restore_latest(
     }
 }
 
+/// Parses sizes like `2G`, `500MiB` or `1048576` (binary units).
+fn parse_owner_mapping(s: &str) -> Result<OwnerMapping, String> {
+    OwnerMapping::parse(s).map_err(|e| e.to_string())
+}
+
+fn parse_settings_backup_id(s: &str) -> Result<(i64, u32), String> {
+    settings_config::parse_settings_backup_id(s.trim())
+        .ok_or_else(|| format!("invalid settings generation: {s:?}"))
+}
+
+fn parse_byte_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (digits, unit) = s.split_at(split);
+    let n: u64 = digits.parse().map_err(|_| format!("invalid size: {s:?}"))?;
+    let shift = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 0,
+        "k" | "kb" | "kib" => 10,
+        "m" | "mb" | "mib" => 20,
+        "g" | "gb" | "gib" => 30,
+        "t" | "tb" | "tib" => 40,
+        _ => return Err(format!("invalid size unit: {s:?}")),
+    };
+    n.checked_mul(1u64 << shift)
+        .ok_or_else(|| format!("size too large: {s:?}"))
+}
+
+fn verify_sample_from_args(
+    percent: Option<f64>,
+    max_bytes: Option<u64>,
+) -> Result<Option<televy_backup_core::VerifySample>, CliError> {
+    if percent.is_none() && max_bytes.is_none() {
+        return Ok(None);
+    }
+    let percent = percent.unwrap_or(100.0);
+    if !(percent > 0.0 && percent <= 100.0) {
+        return Err(CliError::new(
+            ErrorCode::ConfigInvalid,
+            format!("--sample-percent must be in (0, 100]: got {percent}"),
+        ));
+    }
+    Ok(Some(televy_backup_core::VerifySample::weekly(
+        percent, max_bytes,
+    )))
+}
+
+/// Clamps `--concurrency` to the endpoint's MTProto helper pool, which bounds parallel downloads.
+fn verify_concurrency(requested: usize, ep: &settings_config::TelegramEndpoint) -> usize {
+    requested.clamp(1, (ep.rate_limit.max_concurrent_uploads as usize).max(1))
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn verify_latest(
     config_dir: &Path,
     data_dir: &Path,
     target_id: Option<String>,
     source_path: Option<PathBuf>,
+    sample: Option<televy_backup_core::VerifySample>,
+    concurrency: usize,
     json: bool,
     events: bool,
+    metrics_textfile: Option<&Path>,
 ) -> Result<(), CliError> {
     let task_id = format!("tsk_{}", uuid::Uuid::new_v4());
     let run_log = televy_backup_core::run_log::start_run_log("verify", &task_id, data_dir)
-        .map_err(|e| CliError::new("log.init_failed", e.to_string()))?;
+        .map_err(|e| CliError::new(ErrorCode::LogInitFailed, e.to_string()))?;
     let started = std::time::Instant::now();
 
     let settings = match load_settings(config_dir) {
@@ -5650,6 +12893,7 @@ async fn verify_latest(
             );
         }
     };
+    prune_run_logs_best_effort(data_dir, &settings);
 
     let t = match select_target(&settings, target_id.as_deref(), source_path.as_deref()) {
         Ok(t) => t,
@@ -5679,7 +12923,7 @@ async fn verify_latest(
         Some(ep) => ep,
         None => {
             let e = CliError::new(
-                "config.invalid",
+                ErrorCode::ConfigInvalid,
                 format!(
                     "target references unknown endpoint_id: target_id={} endpoint_id={}",
                     t.id, t.endpoint_id
@@ -5702,8 +12946,11 @@ async fn verify_latest(
         }
     };
 
-    if settings.telegram.mtproto.api_id <= 0 {
-        let e = CliError::new("config.invalid", "telegram.mtproto.api_id must be > 0");
+    if ep.mtproto_api_id(&settings.telegram.mtproto) <= 0 {
+        let e = CliError::new(
+            ErrorCode::ConfigInvalid,
+            "telegram.mtproto.api_id must be > 0",
+        );
         return emit_preflight_failed(
             events,
             &task_id,
@@ -5719,9 +12966,12 @@ async fn verify_latest(
             e,
         );
     }
-    if settings.telegram.mtproto.api_hash_key.is_empty() {
+    if ep
+        .mtproto_api_hash_key(&settings.telegram.mtproto)
+        .is_empty()
+    {
         let e = CliError::new(
-            "config.invalid",
+            ErrorCode::ConfigInvalid,
             "telegram.mtproto.api_hash_key must not be empty",
         );
         return emit_preflight_failed(
@@ -5741,7 +12991,7 @@ async fn verify_latest(
     }
     if ep.chat_id.is_empty() {
         let e = CliError::new(
-            "config.invalid",
+            ErrorCode::ConfigInvalid,
             format!("telegram_endpoints[{id}].chat_id is empty", id = ep.id),
         );
         return emit_preflight_failed(
@@ -5759,9 +13009,26 @@ async fn verify_latest(
             e,
         );
     }
-    if is_likely_private_chat_id(&ep.chat_id) {
+    if ep.bootstrap.pin_mode == bootstrap::BootstrapPinMode::Disabled {
+        let e = bootstrap_missing_err(ep.bootstrap.pin_mode);
+        return emit_preflight_failed(
+            events,
+            &task_id,
+            "verify",
+            run_log.path(),
+            started,
+            RunCtx {
+                target_id: Some(t.id.as_str()),
+                endpoint_id: Some(ep.id.as_str()),
+                source_path: Some(t.source_path.as_str()),
+                snapshot_id: Some("latest"),
+            },
+            e,
+        );
+    }
+    if settings_config::is_likely_private_chat_id(&ep.chat_id) {
         let e = CliError::new(
-            "bootstrap.unsupported_chat",
+            ErrorCode::BootstrapUnsupportedChat,
             "verify latest requires the pinned bootstrap catalog; private chats are not supported. Use a group/channel (e.g. -100...) or an @username chat id.".to_string(),
         );
         return emit_preflight_failed(
@@ -5807,16 +13074,16 @@ async fn verify_latest(
 
     let result: Result<(String, televy_backup_core::VerifyResult), CliError> = async {
         let bot_token = get_secret(config_dir, data_dir, &ep.bot_token_key)?
-            .ok_or_else(|| CliError::new("telegram.unauthorized", "bot token missing"))?;
+            .ok_or_else(|| CliError::new(ErrorCode::TelegramUnauthorized, "bot token missing"))?;
         let master_key = load_master_key(config_dir, data_dir)?;
         let api_hash = get_secret(
             config_dir,
             data_dir,
-            &settings.telegram.mtproto.api_hash_key,
+            ep.mtproto_api_hash_key(&settings.telegram.mtproto),
         )?
         .ok_or_else(|| {
             CliError::new(
-                "telegram.mtproto.missing_api_hash",
+                ErrorCode::TelegramMtprotoMissingApiHash,
                 "mtproto api_hash missing",
             )
         })?;
@@ -5824,34 +13091,38 @@ async fn verify_latest(
             config_dir,
             data_dir,
             &ep.mtproto.session_key,
-            "telegram.mtproto.session_invalid",
+            ErrorCode::TelegramMtprotoSessionInvalid,
             "invalid mtproto session (try: `televybackup secrets clear-telegram-mtproto-session`)",
         )?;
 
         let cache_dir = data_dir.join("cache").join("mtproto");
         std::fs::create_dir_all(&cache_dir)
-            .map_err(|e| CliError::new("config.write_failed", e.to_string()))?;
+            .map_err(|e| CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()))?;
 
         let provider = settings_config::endpoint_provider(&ep.id);
         let storage = TelegramMtProtoStorage::connect(TelegramMtProtoStorageConfig {
             provider: provider.clone(),
-            api_id: settings.telegram.mtproto.api_id,
+            api_id: ep.mtproto_api_id(&settings.telegram.mtproto),
             api_hash: api_hash.clone(),
             bot_token: bot_token.clone(),
             chat_id: ep.chat_id.clone(),
+            migrated_from_chat_ids: ep.migrated_from_chat_ids.clone(),
             session,
             cache_dir,
             min_delay_ms: Some(ep.rate_limit.min_delay_ms as u64),
             max_concurrent_uploads: Some(ep.rate_limit.max_concurrent_uploads as usize),
             helper_path: None,
+            bootstrap_pin_mode: ep.bootstrap.pin_mode,
+            object_prefix: endpoint_object_prefix(data_dir, ep),
         })
         .await
         .map_err(map_core_err)?;
+        save_mtproto_chat_migration(config_dir, &ep.id, &storage)?;
 
         let cat = televy_backup_core::bootstrap::load_remote_catalog(&storage, &master_key)
             .await
             .map_err(map_core_err)?
-            .ok_or_else(|| CliError::new("bootstrap.missing", "no pinned bootstrap catalog"))?;
+            .ok_or_else(|| bootstrap_missing_err(ep.bootstrap.pin_mode))?;
 
         let latest = cat
             .targets
@@ -5860,10 +13131,16 @@ async fn verify_latest(
             .and_then(|it| it.latest.clone())
             .ok_or_else(|| {
                 CliError::new(
-                    "bootstrap.latest_missing",
+                    ErrorCode::BootstrapLatestMissing,
                     format!("bootstrap missing latest for target_id: {}", t.id),
                 )
             })?;
+        if latest.manifest_sha256.is_none() {
+            televy_backup_core::remote_index_db::warn_manifest_unverified(
+                &latest.snapshot_id,
+                &latest.manifest_object_id,
+            );
+        }
         let endpoint_latest = cat.endpoint_latest.clone();
         let endpoint_dedupe_latest = cat.endpoint_dedupe_latest.clone();
 
@@ -5879,7 +13156,7 @@ async fn verify_latest(
             endpoint_filemap_dir(data_dir, &ep.id).join(format!("{}.sqlite", latest.snapshot_id));
         if let Some(parent) = filemap_db_path.parent() {
             std::fs::create_dir_all(parent)
-                .map_err(|e| CliError::new("config.write_failed", e.to_string()))?;
+                .map_err(|e| CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()))?;
         }
 
         let sink = NdjsonProgressSink {
@@ -5891,9 +13168,12 @@ async fn verify_latest(
                 target_id: t.id.clone(),
             }),
         };
+        let cancel = cancel_on_stop_signal();
+        let recorder = ProgressRecorder::new(events.then_some(&sink as &dyn ProgressSink));
         let opts = VerifyOptions {
-            cancel: None,
-            progress: if events { Some(&sink) } else { None },
+            cancel: Some(&cancel),
+            progress: Some(&recorder),
+            concurrency: verify_concurrency(concurrency, ep),
         };
 
         let local_endpoint_db_path = endpoint_index_db_path(data_dir, &ep.id);
@@ -5907,9 +13187,11 @@ async fn verify_latest(
         let endpoint_dedupe_id = endpoint_dedupe_latest
             .as_ref()
             .map(|v| v.endpoint_dedupe_id.clone());
+        let data_key = latest.data_key(&master_key, &t.id).map_err(map_core_err)?;
         let cfg = VerifyConfig {
             snapshot_id: latest.snapshot_id.clone(),
             filemap_manifest_object_id: latest.manifest_object_id,
+            filemap_manifest_sha256: latest.manifest_sha256,
             endpoint_manifest_object_id: endpoint_manifest_object_id.clone(),
             dedupe_catalog_object_id: dedupe_catalog_object_id.clone(),
             endpoint_dedupe_id,
@@ -5917,6 +13199,7 @@ async fn verify_latest(
                 .as_ref()
                 .map(|v| v.endpoint_index_id.clone()),
             master_key,
+            data_key: Some(data_key),
             filemap_db_path: filemap_db_path.clone(),
             endpoint_db_path: (dedupe_catalog_object_id.is_none()
                 && endpoint_manifest_object_id.is_some())
@@ -5924,18 +13207,38 @@ async fn verify_latest(
             dedupe_db_path: dedupe_catalog_object_id
                 .is_some()
                 .then_some(local_dedupe_db_path),
+            sample,
         };
 
-        let res = verify_snapshot_with(&storage, cfg, opts)
+        let remapped =
+            RemappedStorage::new(&storage, endpoint_chat_remaps(data_dir, &ep.id).await?);
+        let res = verify_snapshot_with(&remapped, cfg, opts)
             .await
-            .map_err(map_core_err)?;
+            .map_err(|e| with_partial_result(map_core_err(e), &recorder))?;
+        // Pushes the daemon's next scheduled verify (`verify_schedule`) out by a full period.
+        let verified_at = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+        if let Err(e) = televy_backup_core::record_snapshot_verified(
+            &endpoint_index_db_path(data_dir, &ep.id),
+            &latest.snapshot_id,
+            &verified_at,
+        )
+        .await
+        {
+            tracing::warn!(
+                event = "verify.record_failed",
+                snapshot_id = %latest.snapshot_id,
+                error_code = e.code(),
+                error_message = %e,
+                "verify.record_failed"
+            );
+        }
 
         if let Some(bytes) = storage.session_bytes() {
             let b64 = base64::engine::general_purpose::STANDARD.encode(bytes);
             if let Err(e) = set_secret(config_dir, data_dir, &ep.mtproto.session_key, &b64) {
                 tracing::warn!(
                     event = "secrets.session_persist_failed",
-                    error_code = e.code,
+                    error_code = e.code.as_str(),
                     error_message = %e.message,
                     "failed to persist mtproto session"
                 );
@@ -5947,6 +13250,15 @@ async fn verify_latest(
     .await;
 
     let duration_seconds = started.elapsed().as_secs_f64();
+    let usage = UsageRun::finished_now(
+        "verify",
+        Some(t.id.as_str()),
+        result.as_ref().map_or(0, |(_, res)| res.bytes_checked),
+        duration_seconds,
+        run_status(&result),
+    );
+    write_metrics_textfile(config_dir, data_dir, metrics_textfile, &usage, None).await;
+    record_usage(config_dir, data_dir, usage).await;
     match result {
         Ok((snapshot_id, res)) => {
             tracing::warn!(
@@ -5962,6 +13274,9 @@ async fn verify_latest(
                 duration_seconds,
                 chunks_checked = res.chunks_checked,
                 bytes_checked = res.bytes_checked,
+                chunks_skipped = res.chunks_skipped,
+                coverage_percent = res.coverage_percent,
+                phase_timings_ms = %res.phase_timings,
                 "run.finish"
             );
 
@@ -5976,7 +13291,10 @@ async fn verify_latest(
                     "result": {
                         "chunksChecked": res.chunks_checked,
                         "bytesChecked": res.bytes_checked,
+                        "chunksSkipped": res.chunks_skipped,
+                        "coveragePercent": res.coverage_percent,
                         "durationSeconds": duration_seconds,
+                        "phaseTimings": res.phase_timings,
                     }
                 }));
                 daemon_control_status_task_finish(
@@ -5992,46 +13310,37 @@ async fn verify_latest(
             if json {
                 println!(
                     "{}",
-                    serde_json::json!({ "ok": true, "snapshotId": snapshot_id })
+                    serde_json::json!({
+                        "ok": true,
+                        "snapshotId": snapshot_id,
+                        "chunksChecked": res.chunks_checked,
+                        "chunksSkipped": res.chunks_skipped,
+                        "coveragePercent": res.coverage_percent,
+                    })
                 );
             } else {
                 println!("ok");
                 println!("snapshotId={snapshot_id}");
+                print_verify_coverage(&res, duration_seconds);
             }
             Ok(())
         }
         Err(e) => {
-            tracing::error!(
-                event = "run.finish",
-                kind = "verify",
-                run_id = %task_id,
-                task_id = %task_id,
-                target_id = %t.id,
-                endpoint_id = %ep.id,
-                source_path = %t.source_path,
-                snapshot_id = "latest",
-                status = "failed",
-                duration_seconds,
-                error_code = e.code,
-                error_message = %e.message,
-                retryable = e.retryable,
-                "run.finish"
-            );
+            let ctx = RunCtx {
+                target_id: Some(&t.id),
+                endpoint_id: Some(&ep.id),
+                source_path: Some(&t.source_path),
+                snapshot_id: Some("latest"),
+            };
+            let status = log_run_finish_error(&task_id, "verify", ctx, duration_seconds, &e);
             if events {
-                emit_event_stdout(serde_json::json!({
-                    "type": "task.state",
-                    "taskId": task_id,
-                    "kind": "verify",
-                    "state": "failed",
-                    "targetId": t.id.clone(),
-                    "error": { "code": e.code, "message": e.message.clone() },
-                }));
+                emit_task_state_error(events, &task_id, "verify", Some(t.id.as_str()), None, &e);
                 daemon_control_status_task_finish(
                     data_dir,
                     &task_id,
                     "verify",
                     t.id.as_str(),
-                    "failed",
+                    status.as_str(),
                 );
             }
             Err(e)
@@ -6039,16 +13348,21 @@ async fn verify_latest(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn verify_run(
     config_dir: &Path,
     data_dir: &Path,
     snapshot_id: String,
+    sample: Option<televy_backup_core::VerifySample>,
+    concurrency: usize,
+    from_local_repo: Option<PathBuf>,
     json: bool,
     events: bool,
+    metrics_textfile: Option<&Path>,
 ) -> Result<(), CliError> {
     let task_id = format!("tsk_{}", uuid::Uuid::new_v4());
     let run_log = televy_backup_core::run_log::start_run_log("verify", &task_id, data_dir)
-        .map_err(|e| CliError::new("log.init_failed", e.to_string()))?;
+        .map_err(|e| CliError::new(ErrorCode::LogInitFailed, e.to_string()))?;
 
     tracing::warn!(
         event = "run.start",
@@ -6063,175 +13377,245 @@ async fn verify_run(
     let started = std::time::Instant::now();
     let result: Result<televy_backup_core::VerifyResult, CliError> = async {
         let settings = load_settings(config_dir)?;
-
-        let (manifest_object_id, snapshot_provider) =
-            lookup_manifest_meta_any(data_dir, &snapshot_id).await?;
-
-        let endpoint_id = if snapshot_provider == "telegram.mtproto" {
-            None
-        } else if let Some(rest) = snapshot_provider.strip_prefix("telegram.mtproto/") {
-            Some(rest)
-        } else {
-            return Err(CliError::new(
-                "snapshot.unsupported_provider",
-                format!(
-                    "unsupported snapshot provider: snapshot_id={snapshot_id} provider={snapshot_provider}. TelevyBackup is MTProto-only now. Fix: run a new backup with MTProto."
-                ),
-            ));
-        };
-
-        let ep = select_endpoint(&settings, endpoint_id)?;
-
-        if settings.telegram.mtproto.api_id <= 0 {
-            return Err(CliError::new(
-                "config.invalid",
-                "telegram.mtproto.api_id must be > 0",
-            ));
-        }
-        if settings.telegram.mtproto.api_hash_key.is_empty() {
-            return Err(CliError::new(
-                "config.invalid",
-                "telegram.mtproto.api_hash_key must not be empty",
-            ));
-        }
-        if ep.chat_id.is_empty() {
-            return Err(CliError::new(
-                "config.invalid",
-                format!("telegram_endpoints[{id}].chat_id is empty", id = ep.id),
-            ));
-        }
-
-        let bot_token = get_secret(config_dir, data_dir, &ep.bot_token_key)?
-            .ok_or_else(|| CliError::new("telegram.unauthorized", "bot token missing"))?;
-        let master_key = load_master_key(config_dir, data_dir)?;
-
-        let filemap_db_path = endpoint_filemap_dir(data_dir, &ep.id)
-            .join(format!("{snapshot_id}.sqlite"));
-        if let Some(parent) = filemap_db_path.parent() {
-            std::fs::create_dir_all(parent)
-                .map_err(|e| CliError::new("config.write_failed", e.to_string()))?;
-        }
-
-        if events {
-            emit_event_stdout(serde_json::json!({
-                "type": "task.state",
-                "taskId": task_id,
-                "kind": "verify",
-                "state": "running",
-                "snapshotId": snapshot_id,
-            }));
-        }
+        prune_run_logs_best_effort(data_dir, &settings);
 
         let sink = NdjsonProgressSink {
             task_id: task_id.clone(),
             throttle: Mutex::new(ProgressThrottle::new(Duration::from_millis(200))),
             daemon_status_report: None,
         };
-        let opts = VerifyOptions {
-            cancel: None,
-            progress: if events { Some(&sink) } else { None },
+        let cancel = cancel_on_stop_signal();
+        let recorder = ProgressRecorder::new(events.then_some(&sink as &dyn ProgressSink));
+        let emit_running = || {
+            if events {
+                emit_event_stdout(serde_json::json!({
+                    "type": "task.state",
+                    "taskId": task_id,
+                    "kind": "verify",
+                    "state": "running",
+                    "snapshotId": snapshot_id,
+                }));
+            }
         };
 
-        let api_hash = get_secret(config_dir, data_dir, &settings.telegram.mtproto.api_hash_key)?.ok_or_else(
-            || CliError::new("telegram.mtproto.missing_api_hash", "mtproto api_hash missing"),
-        )?;
-        let session = load_optional_base64_secret_bytes(
-            config_dir,
-            data_dir,
-            &ep.mtproto.session_key,
-            "telegram.mtproto.session_invalid",
-            "invalid mtproto session (try: `televybackup secrets clear-telegram-mtproto-session`)",
-        )?;
+        let res = match from_local_repo.as_deref() {
+            Some(repo_dir) => {
+                let master_key = load_master_key(config_dir, data_dir)?;
+                let repo =
+                    open_local_repo_run(data_dir, repo_dir, &snapshot_id, &master_key, None, None)
+                        .await?;
+                emit_running();
+                let cfg = VerifyConfig {
+                    snapshot_id: snapshot_id.clone(),
+                    filemap_manifest_object_id: repo.manifest_object_id.clone(),
+                    filemap_manifest_sha256: repo.manifest_sha256.clone(),
+                    endpoint_manifest_object_id: repo.endpoint_manifest_object_id.clone(),
+                    dedupe_catalog_object_id: repo.dedupe_catalog_object_id.clone(),
+                    endpoint_dedupe_id: repo.endpoint_dedupe_id.clone(),
+                    endpoint_index_id: repo.endpoint_index_id.clone(),
+                    master_key,
+                    data_key: Some(repo.data_key.clone()),
+                    filemap_db_path: repo.filemap_db_path(&snapshot_id),
+                    endpoint_db_path: Some(repo.scratch_dir.join("endpoint.sqlite")),
+                    dedupe_db_path: Some(repo.scratch_dir.join("dedupe.sqlite")),
+                    sample,
+                };
+                let opts = VerifyOptions {
+                    cancel: Some(&cancel),
+                    progress: Some(&recorder),
+                    concurrency: concurrency.max(1),
+                };
+                verify_snapshot_with(&repo.storage, cfg, opts)
+                    .await
+                    .map_err(|e| with_partial_result(map_core_err(e), &recorder))?
+            }
+            None => {
+                let (manifest_object_id, snapshot_provider, manifest_sha256) =
+                    lookup_manifest_meta_any(data_dir, &snapshot_id).await?;
 
-        let cache_dir = data_dir.join("cache").join("mtproto");
-        std::fs::create_dir_all(&cache_dir)
-            .map_err(|e| CliError::new("config.write_failed", e.to_string()))?;
+                let endpoint_id = if snapshot_provider == "telegram.mtproto" {
+                    None
+                } else if let Some(rest) = snapshot_provider.strip_prefix("telegram.mtproto/") {
+                    Some(rest)
+                } else {
+                    return Err(CliError::new(
+                        ErrorCode::SnapshotUnsupportedProvider,
+                        format!(
+                            "unsupported snapshot provider: snapshot_id={snapshot_id} provider={snapshot_provider}. TelevyBackup is MTProto-only now. Fix: run a new backup with MTProto."
+                        ),
+                    ));
+                };
 
-        let storage = TelegramMtProtoStorage::connect(TelegramMtProtoStorageConfig {
-            provider: snapshot_provider.clone(),
-            api_id: settings.telegram.mtproto.api_id,
-            api_hash: api_hash.clone(),
-            bot_token: bot_token.clone(),
-            chat_id: ep.chat_id.clone(),
-            session,
-            cache_dir,
-            min_delay_ms: Some(ep.rate_limit.min_delay_ms as u64),
-            max_concurrent_uploads: Some(ep.rate_limit.max_concurrent_uploads as usize),
-            helper_path: None,
-        })
-        .await
-        .map_err(map_core_err)?;
+                let ep = select_endpoint(&settings, endpoint_id)?;
 
-        let local_endpoint_db_path = endpoint_index_db_path(data_dir, &ep.id);
-        let (endpoint_latest, endpoint_dedupe_latest) = if is_likely_private_chat_id(&ep.chat_id) {
-            (None, None)
-        } else {
-            match televy_backup_core::bootstrap::load_remote_catalog(&storage, &master_key)
+                if ep.mtproto_api_id(&settings.telegram.mtproto) <= 0 {
+                    return Err(CliError::new(
+                        ErrorCode::ConfigInvalid,
+                        "telegram.mtproto.api_id must be > 0",
+                    ));
+                }
+                if ep.mtproto_api_hash_key(&settings.telegram.mtproto).is_empty() {
+                    return Err(CliError::new(
+                        ErrorCode::ConfigInvalid,
+                        "telegram.mtproto.api_hash_key must not be empty",
+                    ));
+                }
+                if ep.chat_id.is_empty() {
+                    return Err(CliError::new(
+                        ErrorCode::ConfigInvalid,
+                        format!("telegram_endpoints[{id}].chat_id is empty", id = ep.id),
+                    ));
+                }
+
+                let bot_token = get_secret(config_dir, data_dir, &ep.bot_token_key)?
+                    .ok_or_else(|| CliError::new(ErrorCode::TelegramUnauthorized, "bot token missing"))?;
+                let master_key = load_master_key(config_dir, data_dir)?;
+
+                let filemap_db_path = endpoint_filemap_dir(data_dir, &ep.id)
+                    .join(format!("{snapshot_id}.sqlite"));
+                if let Some(parent) = filemap_db_path.parent() {
+                    std::fs::create_dir_all(parent)
+                        .map_err(|e| CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()))?;
+                }
+
+                emit_running();
+
+                let opts = VerifyOptions {
+                    cancel: Some(&cancel),
+                    progress: Some(&recorder),
+                    concurrency: verify_concurrency(concurrency, ep),
+                };
+
+                let api_hash = get_secret(config_dir, data_dir, ep.mtproto_api_hash_key(&settings.telegram.mtproto))?.ok_or_else(
+                    || CliError::new(ErrorCode::TelegramMtprotoMissingApiHash, "mtproto api_hash missing"),
+                )?;
+                let session = load_optional_base64_secret_bytes(
+                    config_dir,
+                    data_dir,
+                    &ep.mtproto.session_key,
+                    ErrorCode::TelegramMtprotoSessionInvalid,
+                    "invalid mtproto session (try: `televybackup secrets clear-telegram-mtproto-session`)",
+                )?;
+
+                let cache_dir = data_dir.join("cache").join("mtproto");
+                std::fs::create_dir_all(&cache_dir)
+                    .map_err(|e| CliError::new(ErrorCode::ConfigWriteFailed, e.to_string()))?;
+
+                let storage = TelegramMtProtoStorage::connect(TelegramMtProtoStorageConfig {
+                    provider: snapshot_provider.clone(),
+                    api_id: ep.mtproto_api_id(&settings.telegram.mtproto),
+                    api_hash: api_hash.clone(),
+                    bot_token: bot_token.clone(),
+                    chat_id: ep.chat_id.clone(),
+                    migrated_from_chat_ids: ep.migrated_from_chat_ids.clone(),
+                    session,
+                    cache_dir,
+                    min_delay_ms: Some(ep.rate_limit.min_delay_ms as u64),
+                    max_concurrent_uploads: Some(ep.rate_limit.max_concurrent_uploads as usize),
+                    helper_path: None,
+                    bootstrap_pin_mode: ep.bootstrap.pin_mode,
+                    object_prefix: endpoint_object_prefix(data_dir, ep),
+                })
                 .await
-                .map_err(map_core_err)?
-            {
-                Some(cat) => (cat.endpoint_latest, cat.endpoint_dedupe_latest),
-                None => (None, None),
-            }
-        };
-        let endpoint_manifest_object_id = match endpoint_latest.as_ref() {
-            Some(v) => Some(v.manifest_object_id.clone()),
-            None => televy_backup_core::index_sync::endpoint_state_get(
-                &local_endpoint_db_path,
-                televy_backup_core::index_sync::ENDPOINT_STATE_ENDPOINT_MANIFEST_OBJECT_ID_KEY,
-            )
-            .await
-            .map_err(map_core_err)?,
-        };
-        let endpoint_index_id = match endpoint_latest.as_ref() {
-            Some(v) => Some(v.endpoint_index_id.clone()),
-            None => televy_backup_core::index_sync::endpoint_state_get(
-                &local_endpoint_db_path,
-                televy_backup_core::index_sync::ENDPOINT_STATE_ENDPOINT_INDEX_ID_KEY,
-            )
-            .await
-            .map_err(map_core_err)?,
-        };
+                .map_err(map_core_err)?;
+                save_mtproto_chat_migration(config_dir, &ep.id, &storage)?;
 
-        let local_dedupe_db_path = endpoint_dedupe_db_path(data_dir, &ep.id);
-        let dedupe_catalog_object_id =
-            endpoint_dedupe_latest.as_ref().map(|v| v.catalog_object_id.clone());
-        let endpoint_dedupe_id =
-            endpoint_dedupe_latest.as_ref().map(|v| v.endpoint_dedupe_id.clone());
+                let local_endpoint_db_path = endpoint_index_db_path(data_dir, &ep.id);
+                let (endpoint_latest, endpoint_dedupe_latest) = if settings_config::is_likely_private_chat_id(&ep.chat_id) {
+                    (None, None)
+                } else {
+                    match televy_backup_core::bootstrap::load_remote_catalog(&storage, &master_key)
+                        .await
+                        .map_err(map_core_err)?
+                    {
+                        Some(cat) => (cat.endpoint_latest, cat.endpoint_dedupe_latest),
+                        None => (None, None),
+                    }
+                };
+                let endpoint_manifest_object_id = match endpoint_latest.as_ref() {
+                    Some(v) => Some(v.manifest_object_id.clone()),
+                    None => televy_backup_core::index_sync::endpoint_state_get(
+                        &local_endpoint_db_path,
+                        televy_backup_core::index_sync::ENDPOINT_STATE_ENDPOINT_MANIFEST_OBJECT_ID_KEY,
+                    )
+                    .await
+                    .map_err(map_core_err)?,
+                };
+                let endpoint_index_id = match endpoint_latest.as_ref() {
+                    Some(v) => Some(v.endpoint_index_id.clone()),
+                    None => televy_backup_core::index_sync::endpoint_state_get(
+                        &local_endpoint_db_path,
+                        televy_backup_core::index_sync::ENDPOINT_STATE_ENDPOINT_INDEX_ID_KEY,
+                    )
+                    .await
+                    .map_err(map_core_err)?,
+                };
 
-        let cfg = VerifyConfig {
-            snapshot_id: snapshot_id.clone(),
-            filemap_manifest_object_id: manifest_object_id,
-            endpoint_manifest_object_id: endpoint_manifest_object_id.clone(),
-            dedupe_catalog_object_id: dedupe_catalog_object_id.clone(),
-            endpoint_dedupe_id,
-            endpoint_index_id,
-            master_key,
-            filemap_db_path: filemap_db_path.clone(),
-            endpoint_db_path: (dedupe_catalog_object_id.is_none() && endpoint_manifest_object_id.is_some())
-                .then_some(local_endpoint_db_path),
-            dedupe_db_path: dedupe_catalog_object_id.is_some().then_some(local_dedupe_db_path),
-        };
+                let local_dedupe_db_path = endpoint_dedupe_db_path(data_dir, &ep.id);
+                let dedupe_catalog_object_id =
+                    endpoint_dedupe_latest.as_ref().map(|v| v.catalog_object_id.clone());
+                let endpoint_dedupe_id =
+                    endpoint_dedupe_latest.as_ref().map(|v| v.endpoint_dedupe_id.clone());
+
+                let cfg = VerifyConfig {
+                    snapshot_id: snapshot_id.clone(),
+                    filemap_manifest_object_id: manifest_object_id,
+                    filemap_manifest_sha256: manifest_sha256,
+                    endpoint_manifest_object_id: endpoint_manifest_object_id.clone(),
+                    dedupe_catalog_object_id: dedupe_catalog_object_id.clone(),
+                    endpoint_dedupe_id,
+                    endpoint_index_id,
+                    master_key,
+                    data_key: Some(local_snapshot_data_key(data_dir, &master_key, &snapshot_id).await?),
+                    filemap_db_path: filemap_db_path.clone(),
+                    endpoint_db_path: (dedupe_catalog_object_id.is_none() && endpoint_manifest_object_id.is_some())
+                        .then_some(local_endpoint_db_path),
+                    dedupe_db_path: dedupe_catalog_object_id.is_some().then_some(local_dedupe_db_path),
+                    sample,
+                };
 
-        let res = verify_snapshot_with(&storage, cfg, opts).await.map_err(map_core_err)?;
+                let remapped = RemappedStorage::new(&storage, endpoint_chat_remaps(data_dir, &ep.id).await?);
+                let res = verify_snapshot_with(&remapped, cfg, opts)
+                    .await
+                    .map_err(|e| with_partial_result(map_core_err(e), &recorder))?;
 
-        if let Some(bytes) = storage.session_bytes() {
-            let b64 = base64::engine::general_purpose::STANDARD.encode(bytes);
-            if let Err(e) = set_secret(config_dir, data_dir, &ep.mtproto.session_key, &b64) {
-                tracing::warn!(
-                    event = "secrets.session_persist_failed",
-                    error_code = e.code,
-                    error_message = %e.message,
-                    "failed to persist mtproto session"
-                );
+                if let Some(bytes) = storage.session_bytes() {
+                    let b64 = base64::engine::general_purpose::STANDARD.encode(bytes);
+                    if let Err(e) = set_secret(config_dir, data_dir, &ep.mtproto.session_key, &b64) {
+                        tracing::warn!(
+                            event = "secrets.session_persist_failed",
+                            error_code = e.code.as_str(),
+                            error_message = %e.message,
+                            "failed to persist mtproto session"
+                        );
+                    }
+                }
+
+                res
             }
-        }
+        };
 
         Ok(res)
     }
     .await;
 
     let duration_seconds = started.elapsed().as_secs_f64();
+    let usage = UsageRun::finished_now(
+        "verify",
+        None,
+        result.as_ref().map_or(0, |res| res.bytes_checked),
+        duration_seconds,
+        run_status(&result),
+    );
+    write_metrics_textfile(
+        config_dir,
+        data_dir,
+        metrics_textfile,
+        &usage,
+        Some(&snapshot_id),
+    )
+    .await;
+    record_usage(config_dir, data_dir, usage).await;
     match result {
         Ok(res) => {
             tracing::warn!(
@@ -6244,6 +13628,9 @@ async fn verify_run(
                 duration_seconds,
                 chunks_checked = res.chunks_checked,
                 bytes_checked = res.bytes_checked,
+                chunks_skipped = res.chunks_skipped,
+                coverage_percent = res.coverage_percent,
+                phase_timings_ms = %res.phase_timings,
                 "run.finish"
             );
 
@@ -6257,71 +13644,83 @@ async fn verify_run(
                     "result": {
                         "chunksChecked": res.chunks_checked,
                         "bytesChecked": res.bytes_checked,
+                        "chunksSkipped": res.chunks_skipped,
+                        "coveragePercent": res.coverage_percent,
                         "durationSeconds": duration_seconds,
+                        "phaseTimings": res.phase_timings,
                     }
                 }));
                 return Ok(());
             }
 
             if json {
-                println!("{}", serde_json::json!({ "ok": true }));
+                println!(
+                    "{}",
+                    serde_json::json!({
+                        "ok": true,
+                        "chunksChecked": res.chunks_checked,
+                        "chunksSkipped": res.chunks_skipped,
+                        "coveragePercent": res.coverage_percent,
+                    })
+                );
             } else {
                 println!("ok");
+                print_verify_coverage(&res, duration_seconds);
             }
             Ok(())
         }
         Err(e) => {
-            tracing::error!(
-                event = "run.finish",
-                kind = "verify",
-                run_id = %task_id,
-                task_id = %task_id,
-                snapshot_id = %snapshot_id,
-                status = "failed",
-                duration_seconds,
-                error_code = e.code,
-                error_message = %e.message,
-                retryable = e.retryable,
-                "run.finish"
-            );
-            if events {
-                emit_event_stdout(serde_json::json!({
-                    "type": "task.state",
-                    "taskId": task_id,
-                    "kind": "verify",
-                    "state": "failed",
-                    "snapshotId": snapshot_id,
-                    "error": { "code": e.code, "message": e.message.clone() },
-                }));
-            }
+            let ctx = RunCtx {
+                target_id: None,
+                endpoint_id: None,
+                source_path: None,
+                snapshot_id: Some(&snapshot_id),
+            };
+            log_run_finish_error(&task_id, "verify", ctx, duration_seconds, &e);
+            emit_task_state_error(events, &task_id, "verify", None, Some(&snapshot_id), &e);
             Err(e)
         }
     }
 }
 
+fn print_verify_coverage(res: &televy_backup_core::VerifyResult, duration_seconds: f64) {
+    println!(
+        "bytesChecked={} durationSeconds={}",
+        format::bytes(res.bytes_checked),
+        format::duration(duration_seconds)
+    );
+    if res.chunks_skipped > 0 {
+        println!(
+            "chunksChecked={} chunksSkipped={} coveragePercent={:.1}",
+            res.chunks_checked, res.chunks_skipped, res.coverage_percent
+        );
+    }
+}
+
 async fn lookup_manifest_meta(
     db_path: &Path,
     snapshot_id: &str,
-) -> Result<(String, String), CliError> {
+) -> Result<(String, String, Option<String>), CliError> {
     let pool = televy_backup_core::index_db::open_existing_index_db(db_path)
         .await
         .map_err(map_core_err)?;
 
     let row = sqlx::query(
-        "SELECT manifest_object_id, provider FROM remote_indexes WHERE snapshot_id = ? LIMIT 1",
+        "SELECT manifest_object_id, provider, manifest_sha256 FROM remote_indexes WHERE snapshot_id = ? LIMIT 1",
     )
     .bind(snapshot_id)
     .fetch_optional(&pool)
     .await
-    .map_err(|e| CliError::new("db.failed", e.to_string()))?;
+    .map_err(|e| CliError::new(ErrorCode::DbFailed, e.to_string()))?;
 
     match row {
         Some(r) => Ok((
             r.get::<String, _>("manifest_object_id"),
             r.get::<String, _>("provider"),
+            r.get::<Option<String>, _>("manifest_sha256"),
         )),
-        None => Err(CliError::new(
-            "snapshot.not_found",
+        None => Err(snapshot_not_found(
+            snapshot_id,
             "manifest not found in local db",
         )),
     }
@@ -6330,7 +13729,7 @@ async fn lookup_manifest_meta(
 async fn lookup_manifest_meta_any(
     data_dir: &Path,
     snapshot_id: &str,
-) -> Result<(String, String), CliError> {
+) -> Result<(String, String, Option<String>), CliError> {
     let global = legacy_global_index_db_path(data_dir);
     if global.exists()
         && let Ok(found) = lookup_manifest_meta(&global, snapshot_id).await
@@ -6340,7 +13739,7 @@ async fn lookup_manifest_meta_any(
 
     let index_dir = data_dir.join("index");
     let entries = std::fs::read_dir(&index_dir)
-        .map_err(|e| CliError::new("snapshot.not_found", e.to_string()))?;
+        .map_err(|e| snapshot_not_found(snapshot_id, e.to_string()))?;
 
     for entry in entries.flatten() {
         let path = entry.path();
@@ -6360,13 +13759,13 @@ async fn lookup_manifest_meta_any(
 
         match lookup_manifest_meta(&path, snapshot_id).await {
             Ok(found) => return Ok(found),
-            Err(e) if e.code == "snapshot.not_found" => continue,
+            Err(e) if e.code == ErrorCode::SnapshotNotFound => continue,
             Err(_) => continue,
         }
     }
 
-    Err(CliError::new(
-        "snapshot.not_found",
+    Err(snapshot_not_found(
+        snapshot_id,
         "manifest not found in local db",
     ))
 }
@@ -6391,15 +13790,6 @@ fn load_settings(config_dir: &Path) -> Result<Settings, CliError> {
     Ok(settings)
 }
 
-fn redact_secret(s: impl Into<String>, secret: &str) -> String {
-    let s = s.into();
-    if secret.is_empty() {
-        s
-    } else {
-        s.replace(secret, "[redacted]")
-    }
-}
-
 #[derive(Debug, serde::Serialize)]
 #[serde(tag = "type")]
 enum VaultIpcRequest {
@@ -6421,6 +13811,8 @@ struct VaultIpcResponse {
     value: Option<String>,
     deleted: Option<bool>,
     error: Option<String>,
+    #[serde(flatten)]
+    versions: DaemonVersionStamp,
 }
 
 #[cfg(unix)]
@@ -6431,7 +13823,7 @@ fn vault_ipc_call(data_dir: &Path, req: &VaultIpcRequest) -> Result<VaultIpcResp
 
     let socket_path = televy_backup_core::secrets::vault_ipc_socket_path(data_dir);
     let mut stream = UnixStream::connect(&socket_path).map_err(|e| {
-        CliError::retryable("daemon.unavailable", "vault IPC unavailable").with_details(
+        CliError::retryable(ErrorCode::DaemonUnavailable, "vault IPC unavailable").with_details(
             serde_json::json!({
                 "socketPath": socket_path.display().to_string(),
                 "error": e.to_string(),
@@ -6445,24 +13837,23 @@ fn vault_ipc_call(data_dir: &Path, req: &VaultIpcRequest) -> Result<VaultIpcResp
     let _ = stream.set_write_timeout(Some(Duration::from_secs(120)));
 
     let line = serde_json::to_string(req)
-        .map_err(|e| CliError::new("daemon.unavailable", e.to_string()))?;
+        .map_err(|e| CliError::new(ErrorCode::DaemonUnavailable, e.to_string()))?;
     stream
         .write_all(line.as_bytes())
         .and_then(|_| stream.write_all(b"\n"))
         .map_err(|e| {
-            CliError::retryable("daemon.unavailable", "vault IPC write failed").with_details(
-                serde_json::json!({
+            CliError::retryable(ErrorCode::DaemonUnavailable, "vault IPC write failed")
+                .with_details(serde_json::json!({
                     "socketPath": socket_path.display().to_string(),
                     "error": e.to_string(),
-                }),
-            )
+                }))
         })?;
     let _ = stream.flush();
 
     let mut reader = BufReader::new(stream);
     let mut resp_line = String::new();
     reader.read_line(&mut resp_line).map_err(|e| {
-        CliError::retryable("daemon.unavailable", "vault IPC read failed").with_details(
+        CliError::retryable(ErrorCode::DaemonUnavailable, "vault IPC read failed").with_details(
             serde_json::json!({
                 "socketPath": socket_path.display().to_string(),
                 "error": e.to_string(),
@@ -6470,14 +13861,19 @@ fn vault_ipc_call(data_dir: &Path, req: &VaultIpcRequest) -> Result<VaultIpcResp
         )
     })?;
 
-    let resp: VaultIpcResponse = serde_json::from_str(resp_line.trim_end())
-        .map_err(|e| CliError::new("daemon.unavailable", format!("invalid IPC response: {e}")))?;
+    let resp: VaultIpcResponse = serde_json::from_str(resp_line.trim_end()).map_err(|e| {
+        CliError::new(
+            ErrorCode::DaemonUnavailable,
+            format!("invalid IPC response: {e}"),
+        )
+    })?;
+    check_daemon_skew(&resp.versions)?;
 
     if resp.ok {
         Ok(resp)
     } else {
         Err(CliError::new(
-            "daemon.failed",
+            ErrorCode::DaemonFailed,
             resp.error
                 .unwrap_or_else(|| "daemon request failed".to_string()),
         ))
@@ -6487,7 +13883,7 @@ fn vault_ipc_call(data_dir: &Path, req: &VaultIpcRequest) -> Result<VaultIpcResp
 #[cfg(not(unix))]
 fn vault_ipc_call(_data_dir: &Path, _req: &VaultIpcRequest) -> Result<VaultIpcResponse, CliError> {
     Err(CliError::new(
-        "daemon.unavailable",
+        ErrorCode::DaemonUnavailable,
         "vault IPC is only supported on unix",
     ))
 }
@@ -6495,7 +13891,33 @@ fn vault_ipc_call(_data_dir: &Path, _req: &VaultIpcRequest) -> Result<VaultIpcRe
 fn daemon_vault_get_or_create_b64(data_dir: &Path) -> Result<String, CliError> {
     let resp = vault_ipc_call(data_dir, &VaultIpcRequest::VaultGetOrCreate)?;
     resp.vault_key_b64
-        .ok_or_else(|| CliError::new("daemon.failed", "vault IPC missing vault_key_b64"))
+        .ok_or_else(|| CliError::new(ErrorCode::DaemonFailed, "vault IPC missing vault_key_b64"))
+}
+
+/// Refuses to talk to a daemon from an incompatible release and warns (once per process) about
+/// other skew, so a daemon left running across an upgrade gets noticed.
+fn check_daemon_skew(stamp: &DaemonVersionStamp) -> Result<(), CliError> {
+    static WARNED: AtomicBool = AtomicBool::new(false);
+
+    let Some(skew) = version::check_skew(&cli_component_version(), &stamp.component_version())
+    else {
+        return Ok(());
+    };
+    if skew.breaking {
+        return Err(map_core_err(skew.into()));
+    }
+    if !WARNED.swap(true, Ordering::Relaxed) {
+        eprintln!("warning: {}", skew.message());
+    }
+    Ok(())
+}
+
+fn cli_component_version() -> ComponentVersion {
+    ComponentVersion::local(
+        Component::Cli,
+        env!("CARGO_PKG_VERSION"),
+        u64::from(settings_config::SETTINGS_SCHEMA_VERSION),
+    )
 }
 
 #[cfg(unix)]
@@ -6506,6 +13928,83 @@ fn control_ipc_call_with_timeouts(
     read_timeout: Duration,
     write_timeout: Duration,
 ) -> Result<televy_backup_core::control::ControlResponse, CliError> {
+    let resp = control_ipc_exchange(data_dir, method, params, read_timeout, write_timeout)?;
+    control_response_result(resp)
+}
+
+/// Checks the daemon's version stamp and turns an error response into a [`CliError`].
+#[cfg(unix)]
+fn control_response_result(
+    resp: televy_backup_core::control::ControlResponse,
+) -> Result<televy_backup_core::control::ControlResponse, CliError> {
+    check_daemon_skew(&resp.versions)?;
+
+    if resp.ok {
+        Ok(resp)
+    } else {
+        let err = resp.error.unwrap_or_else(|| {
+            televy_backup_core::control::ControlError::new(
+                ErrorCode::ControlFailed,
+                "daemon request failed",
+                false,
+                serde_json::json!({}),
+            )
+        });
+
+        let (code, details) = match ErrorCode::from_code(&err.code) {
+            Some(
+                code @ (ErrorCode::ControlUnavailable
+                | ErrorCode::ControlTimeout
+                | ErrorCode::ControlInvalidRequest
+                | ErrorCode::ControlMethodNotFound),
+            ) => (code, err.details),
+            _ => (
+                ErrorCode::ControlFailed,
+                serde_json::json!({
+                    "daemonCode": err.code,
+                    "daemonDetails": err.details,
+                }),
+            ),
+        };
+
+        let out = if err.retryable {
+            CliError::retryable(code, err.message)
+        } else {
+            CliError::new(code, err.message)
+        };
+        Err(out.with_details(details))
+    }
+}
+
+/// One request/response round trip on the control socket; the response is returned as is, error
+/// or not, and without the version check.
+#[cfg(unix)]
+fn control_ipc_exchange(
+    data_dir: &Path,
+    method: &str,
+    params: serde_json::Value,
+    read_timeout: Duration,
+    write_timeout: Duration,
+) -> Result<televy_backup_core::control::ControlResponse, CliError> {
+    control_ipc_open(data_dir, method, params, read_timeout, write_timeout).map(|(resp, _)| resp)
+}
+
+/// Like [`control_ipc_exchange`], but hands back the connection for methods that keep writing
+/// after their response (`logs.stream`).
+#[cfg(unix)]
+fn control_ipc_open(
+    data_dir: &Path,
+    method: &str,
+    params: serde_json::Value,
+    read_timeout: Duration,
+    write_timeout: Duration,
+) -> Result<
+    (
+        televy_backup_core::control::ControlResponse,
+        std::io::BufReader<std::os::unix::net::UnixStream>,
+    ),
+    CliError,
+> {
     use std::io::{BufRead, BufReader, Write};
     use std::os::unix::net::UnixStream;
 
@@ -6519,7 +14018,7 @@ fn control_ipc_call_with_timeouts(
     let socket_path = televy_backup_core::control::control_ipc_socket_path(data_dir);
     let mut stream = UnixStream::connect(&socket_path).map_err(|e| {
         CliError::retryable(
-            "control.unavailable",
+            ErrorCode::ControlUnavailable,
             "control IPC unavailable (is daemon running?)",
         )
         .with_details(serde_json::json!({
@@ -6537,25 +14036,24 @@ fn control_ipc_call_with_timeouts(
         params,
     );
     let line = serde_json::to_string(&req)
-        .map_err(|e| CliError::new("control.unavailable", e.to_string()))?;
+        .map_err(|e| CliError::new(ErrorCode::ControlUnavailable, e.to_string()))?;
     stream
         .write_all(line.as_bytes())
         .and_then(|_| stream.write_all(b"\n"))
         .map_err(|e| {
             if is_timeout_io_error(&e) {
-                CliError::retryable("control.timeout", "control IPC timeout").with_details(
+                CliError::retryable(ErrorCode::ControlTimeout, "control IPC timeout").with_details(
                     serde_json::json!({
                         "socketPath": socket_path.display().to_string(),
                         "error": e.to_string(),
                     }),
                 )
             } else {
-                CliError::retryable("control.unavailable", "control IPC write failed").with_details(
-                    serde_json::json!({
+                CliError::retryable(ErrorCode::ControlUnavailable, "control IPC write failed")
+                    .with_details(serde_json::json!({
                         "socketPath": socket_path.display().to_string(),
                         "error": e.to_string(),
-                    }),
-                )
+                    }))
             }
         })?;
     let _ = stream.flush();
@@ -6564,7 +14062,7 @@ fn control_ipc_call_with_timeouts(
     let mut resp_line = String::new();
     match reader.read_line(&mut resp_line) {
         Ok(0) => Err(CliError::retryable(
-            "control.unavailable",
+            ErrorCode::ControlUnavailable,
             "control IPC closed before response",
         )
         .with_details(serde_json::json!({
@@ -6572,65 +14070,33 @@ fn control_ipc_call_with_timeouts(
         }))),
         Ok(_) => Ok(()),
         Err(e) => Err(if is_timeout_io_error(&e) {
-            CliError::retryable("control.timeout", "control IPC timeout").with_details(
+            CliError::retryable(ErrorCode::ControlTimeout, "control IPC timeout").with_details(
                 serde_json::json!({
                     "socketPath": socket_path.display().to_string(),
                     "error": e.to_string(),
                 }),
             )
         } else {
-            CliError::retryable("control.unavailable", "control IPC read failed").with_details(
-                serde_json::json!({
+            CliError::retryable(ErrorCode::ControlUnavailable, "control IPC read failed")
+                .with_details(serde_json::json!({
                     "socketPath": socket_path.display().to_string(),
                     "error": e.to_string(),
-                }),
-            )
+                }))
         }),
     }?;
 
     let resp: televy_backup_core::control::ControlResponse =
         serde_json::from_str(resp_line.trim_end()).map_err(|e| {
-            CliError::new("control.unavailable", format!("invalid IPC response: {e}")).with_details(
-                serde_json::json!({
-                    "socketPath": socket_path.display().to_string(),
-                    "responseLine": resp_line.clone(),
-                }),
+            CliError::new(
+                ErrorCode::ControlUnavailable,
+                format!("invalid IPC response: {e}"),
             )
+            .with_details(serde_json::json!({
+                "socketPath": socket_path.display().to_string(),
+                "responseLine": resp_line.clone(),
+            }))
         })?;
-
-    if resp.ok {
-        Ok(resp)
-    } else {
-        let err = resp
-            .error
-            .unwrap_or(televy_backup_core::control::ControlError {
-                code: "control.failed".to_string(),
-                message: "daemon request failed".to_string(),
-                retryable: false,
-                details: serde_json::json!({}),
-            });
-
-        let (code, details) = match err.code.as_str() {
-            "control.unavailable" => ("control.unavailable", err.details),
-            "control.timeout" => ("control.timeout", err.details),
-            "control.invalid_request" => ("control.invalid_request", err.details),
-            "control.method_not_found" => ("control.method_not_found", err.details),
-            _ => (
-                "control.failed",
-                serde_json::json!({
-                    "daemonCode": err.code,
-                    "daemonDetails": err.details,
-                }),
-            ),
-        };
-
-        let out = if err.retryable {
-            CliError::retryable(code, err.message)
-        } else {
-            CliError::new(code, err.message)
-        };
-        Err(out.with_details(details))
-    }
+    Ok((resp, reader))
 }
 
 #[cfg(unix)]
@@ -6655,11 +14121,86 @@ fn control_ipc_call(
     _params: serde_json::Value,
 ) -> Result<televy_backup_core::control::ControlResponse, CliError> {
     Err(CliError::new(
-        "daemon.unavailable",
+        ErrorCode::DaemonUnavailable,
+        "control IPC is only supported on unix",
+    ))
+}
+
+#[cfg(not(unix))]
+fn control_ipc_exchange(
+    _data_dir: &Path,
+    _method: &str,
+    _params: serde_json::Value,
+    _read_timeout: Duration,
+    _write_timeout: Duration,
+) -> Result<televy_backup_core::control::ControlResponse, CliError> {
+    Err(CliError::new(
+        ErrorCode::DaemonUnavailable,
         "control IPC is only supported on unix",
     ))
 }
 
+const REMOTE_TOKEN_ENV: &str = "TELEVYBACKUP_REMOTE_TOKEN";
+
+/// Calls a read-only method on a daemon's remote listener; daemon errors keep their codes.
+fn remote_call(
+    remote: &RemoteArgs,
+    method: &str,
+    params: serde_json::Value,
+) -> Result<serde_json::Value, CliError> {
+    let addr = remote.remote.as_deref().unwrap_or_default();
+    let token = match remote.remote_token.clone() {
+        Some(t) => t,
+        None => std::env::var(REMOTE_TOKEN_ENV).map_err(|_| {
+            CliError::new(
+                ErrorCode::ConfigInvalid,
+                format!("--remote needs --remote-token or ${REMOTE_TOKEN_ENV}"),
+            )
+        })?,
+    };
+    let req = televy_backup_core::control::ControlRequest::new(
+        uuid::Uuid::new_v4().to_string(),
+        method,
+        params,
+    );
+    let resp = televy_backup_core::remote::remote_call(
+        addr,
+        &token,
+        remote.remote_fingerprint.as_deref(),
+        req,
+        Duration::from_secs(30),
+    )
+    .map_err(|e| {
+        CliError::retryable(ErrorCode::RemoteUnavailable, format!("remote {addr}: {e}"))
+            .with_details(serde_json::json!({ "remote": addr, "error": e.to_string() }))
+    })?;
+
+    if resp.ok {
+        return Ok(resp.result.unwrap_or(serde_json::Value::Null));
+    }
+    let err = resp.error.unwrap_or_else(|| {
+        televy_backup_core::control::ControlError::new(
+            ErrorCode::ControlFailed,
+            "remote request failed",
+            false,
+            serde_json::json!({}),
+        )
+    });
+    let (code, details) = match ErrorCode::from_code(&err.code) {
+        Some(code) => (code, err.details),
+        None => (
+            ErrorCode::ControlFailed,
+            serde_json::json!({ "daemonCode": err.code, "daemonDetails": err.details }),
+        ),
+    };
+    let out = if err.retryable {
+        CliError::retryable(code, err.message)
+    } else {
+        CliError::new(code, err.message)
+    };
+    Err(out.with_details(details))
+}
+
 #[cfg(all(test, unix))]
 mod control_ipc_tests {
     use std::io::{BufRead, BufReader, Write};
@@ -6691,7 +14232,7 @@ mod control_ipc_tests {
         )
         .unwrap_err();
 
-        assert_eq!(err.code, "control.unavailable");
+        assert_eq!(err.code, ErrorCode::ControlUnavailable);
         assert!(err.retryable);
     }
 
@@ -6717,21 +14258,70 @@ mod control_ipc_tests {
         wait_for_socket(&socket_path);
         let err = control_ipc_call_with_timeouts(
             dir.path(),
-            "secrets.presence",
+            "secrets.presence",
+            serde_json::json!({}),
+            Duration::from_millis(20),
+            Duration::from_millis(20),
+        )
+        .unwrap_err();
+
+        assert_eq!(err.code, ErrorCode::ControlTimeout);
+        assert!(err.retryable);
+
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn control_ipc_method_not_found_maps_code() {
+        let dir = tempfile::tempdir().unwrap();
+        let ipc_dir = dir.path().join("ipc");
+        std::fs::create_dir_all(&ipc_dir).unwrap();
+        let socket_path = ipc_dir.join("control.sock");
+
+        let server = thread::spawn({
+            let socket_path = socket_path.clone();
+            move || {
+                let listener = UnixListener::bind(socket_path).unwrap();
+                let (mut stream, _addr) = listener.accept().unwrap();
+
+                let mut line = String::new();
+                BufReader::new(stream.try_clone().unwrap())
+                    .read_line(&mut line)
+                    .unwrap();
+
+                let req: televy_backup_core::control::ControlRequest =
+                    serde_json::from_str(line.trim_end()).unwrap();
+                let resp = televy_backup_core::control::ControlResponse::err(
+                    req.id,
+                    televy_backup_core::control::ControlError::method_not_found(
+                        "unknown method",
+                        serde_json::json!({}),
+                    ),
+                );
+                let resp_line = serde_json::to_string(&resp).unwrap() + "\n";
+                stream.write_all(resp_line.as_bytes()).unwrap();
+                let _ = stream.flush();
+            }
+        });
+
+        wait_for_socket(&socket_path);
+        let err = control_ipc_call_with_timeouts(
+            dir.path(),
+            "unknown.method",
             serde_json::json!({}),
-            Duration::from_millis(20),
-            Duration::from_millis(20),
+            Duration::from_millis(200),
+            Duration::from_millis(200),
         )
         .unwrap_err();
 
-        assert_eq!(err.code, "control.timeout");
-        assert!(err.retryable);
+        assert_eq!(err.code, ErrorCode::ControlMethodNotFound);
+        assert!(!err.retryable);
 
         server.join().unwrap();
     }
 
     #[test]
-    fn control_ipc_method_not_found_maps_code() {
+    fn control_ipc_refuses_a_daemon_from_an_incompatible_release() {
         let dir = tempfile::tempdir().unwrap();
         let ipc_dir = dir.path().join("ipc");
         std::fs::create_dir_all(&ipc_dir).unwrap();
@@ -6750,13 +14340,9 @@ mod control_ipc_tests {
 
                 let req: televy_backup_core::control::ControlRequest =
                     serde_json::from_str(line.trim_end()).unwrap();
-                let resp = televy_backup_core::control::ControlResponse::err(
-                    req.id,
-                    televy_backup_core::control::ControlError::method_not_found(
-                        "unknown method",
-                        serde_json::json!({}),
-                    ),
-                );
+                let mut resp =
+                    televy_backup_core::control::ControlResponse::ok(req.id, serde_json::json!({}));
+                resp.versions = DaemonVersionStamp::new("99.0.0");
                 let resp_line = serde_json::to_string(&resp).unwrap() + "\n";
                 stream.write_all(resp_line.as_bytes()).unwrap();
                 let _ = stream.flush();
@@ -6766,19 +14352,58 @@ mod control_ipc_tests {
         wait_for_socket(&socket_path);
         let err = control_ipc_call_with_timeouts(
             dir.path(),
-            "unknown.method",
+            "vault.status",
             serde_json::json!({}),
             Duration::from_millis(200),
             Duration::from_millis(200),
         )
         .unwrap_err();
 
-        assert_eq!(err.code, "control.method_not_found");
-        assert!(!err.retryable);
+        assert_eq!(err.code, ErrorCode::VersionSkew);
+        assert_eq!(err.details["component"], "cli");
+        assert_eq!(err.details["remoteVersion"], "99.0.0");
+        assert!(err.message.contains("is older than the daemon"));
 
         server.join().unwrap();
     }
 
+    #[test]
+    fn remote_call_keeps_daemon_error_codes_and_maps_connect_failures() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (mut stream, _addr) = listener.accept().unwrap();
+            let req: televy_backup_core::remote::RemoteRequest =
+                serde_json::from_str(&televy_backup_core::remote::read_line(&mut stream).unwrap())
+                    .unwrap();
+            let resp = televy_backup_core::control::ControlResponse::err(
+                req.request.id,
+                televy_backup_core::control::ControlError::new(
+                    ErrorCode::RemoteUnauthorized,
+                    "invalid remote token",
+                    false,
+                    serde_json::json!({}),
+                ),
+            );
+            televy_backup_core::remote::write_line(&mut stream, &resp).unwrap();
+            req.token
+        });
+
+        let remote = RemoteArgs {
+            remote: Some(addr.clone()),
+            remote_token: Some("wrong".to_string()),
+            remote_fingerprint: None,
+        };
+        let err = remote_call(&remote, "stats.get", serde_json::json!({})).unwrap_err();
+        assert_eq!(err.code, ErrorCode::RemoteUnauthorized);
+        assert_eq!(server.join().unwrap(), "wrong");
+
+        // Nothing listens there any more.
+        let err = remote_call(&remote, "stats.get", serde_json::json!({})).unwrap_err();
+        assert_eq!(err.code, ErrorCode::RemoteUnavailable);
+        assert!(err.retryable);
+    }
+
     #[test]
     fn control_ipc_preserves_daemon_error_code_in_details() {
         let dir = tempfile::tempdir().unwrap();
@@ -6824,7 +14449,7 @@ mod control_ipc_tests {
         )
         .unwrap_err();
 
-        assert_eq!(err.code, "control.failed");
+        assert_eq!(err.code, ErrorCode::ControlFailed);
         assert_eq!(
             err.details.get("daemonCode").and_then(|v| v.as_str()),
             Some("secrets.vault_unavailable")
@@ -6834,6 +14459,34 @@ mod control_ipc_tests {
     }
 }
 
+/// The daemon reports presence from its own environment; secrets injected into this process's
+/// environment (or `secrets.d/`) count as present too.
+fn overlay_injected_secrets_presence(
+    config_dir: &Path,
+    settings: &Settings,
+    secrets: &mut serde_json::Value,
+) {
+    let provider = televy_backup_core::secrets::SecretsProvider::new(config_dir);
+    let injected = |key: &str| provider.injected(key).ok().flatten().is_some();
+    if injected(MASTER_KEY_KEY) {
+        secrets["masterKeyPresent"] = serde_json::json!(true);
+    }
+    if injected(&settings.telegram.mtproto.api_hash_key) {
+        secrets["telegramMtprotoApiHashPresent"] = serde_json::json!(true);
+    }
+    for ep in &settings.telegram_endpoints {
+        if injected(&ep.bot_token_key) {
+            secrets["telegramBotTokenPresentByEndpoint"][&ep.id] = serde_json::json!(true);
+        }
+        if injected(&ep.mtproto.session_key) {
+            secrets["telegramMtprotoSessionPresentByEndpoint"][&ep.id] = serde_json::json!(true);
+        }
+        if ep.mtproto.api_hash_key.as_deref().is_some_and(injected) {
+            secrets["telegramMtprotoApiHashPresentByEndpoint"][&ep.id] = serde_json::json!(true);
+        }
+    }
+}
+
 fn daemon_control_secrets_presence(
     data_dir: &Path,
     endpoint_id: Option<&str>,
@@ -6841,7 +14494,7 @@ fn daemon_control_secrets_presence(
     let params = serde_json::json!({ "endpointId": endpoint_id });
     let resp = control_ipc_call(data_dir, "secrets.presence", params)?;
     resp.result
-        .ok_or_else(|| CliError::new("control.failed", "missing result"))
+        .ok_or_else(|| CliError::new(ErrorCode::ControlFailed, "missing result"))
 }
 
 fn daemon_control_secrets_set_telegram_bot_token(
@@ -6872,6 +14525,35 @@ fn daemon_control_secrets_clear_telegram_mtproto_session(
     Ok(())
 }
 
+fn daemon_control_secrets_list(
+    data_dir: &Path,
+) -> Result<Vec<televy_backup_core::secrets::SecretKeyInfo>, CliError> {
+    let resp = control_ipc_call(data_dir, "secrets.list", serde_json::json!({}))?;
+    let result: televy_backup_core::control::SecretsListResult = resp
+        .result
+        .and_then(|v| serde_json::from_value(v).ok())
+        .ok_or_else(|| CliError::new(ErrorCode::ControlFailed, "missing result"))?;
+    Ok(result.keys)
+}
+
+fn daemon_control_secrets_delete(
+    data_dir: &Path,
+    keys: &[String],
+    i_understand_data_loss: bool,
+) -> Result<Vec<String>, CliError> {
+    let params = televy_backup_core::control::SecretsDeleteParams {
+        keys: keys.to_vec(),
+        i_understand_data_loss,
+    };
+    let params = serde_json::to_value(params).unwrap_or_else(|_| serde_json::json!({}));
+    let resp = control_ipc_call(data_dir, "secrets.delete", params)?;
+    let result: televy_backup_core::control::SecretsDeleteResult = resp
+        .result
+        .and_then(|v| serde_json::from_value(v).ok())
+        .ok_or_else(|| CliError::new(ErrorCode::ControlFailed, "missing result"))?;
+    Ok(result.deleted)
+}
+
 #[cfg(unix)]
 fn daemon_control_status_task_start(data_dir: &Path, task_id: &str, kind: &str, target_id: &str) {
     let params = televy_backup_core::control::StatusTaskStartParams {
@@ -6922,6 +14604,7 @@ fn daemon_control_status_task_progress(
         bytes_uploaded: p.bytes_uploaded,
         bytes_downloaded: p.bytes_downloaded,
         bytes_deduped: p.bytes_deduped,
+        bytes_total: p.bytes_total,
     };
     let params = televy_backup_core::control::StatusTaskProgressParams {
         task_id: task_id.to_string(),
@@ -7010,12 +14693,16 @@ fn load_or_create_vault_key(data_dir: &Path) -> Result<[u8; 32], CliError> {
 
     let b64 = daemon_vault_get_or_create_b64(data_dir)?;
     let key = televy_backup_core::secrets::vault_key_from_base64(&b64)
-        .map_err(|e| CliError::new("secrets.vault_unavailable", e.to_string()))?;
+        .map_err(|e| CliError::new(ErrorCode::SecretsVaultUnavailable, e.to_string()))?;
     let _ = VAULT_KEY_CACHE.set(key);
     Ok(key)
 }
 
 fn get_secret(config_dir: &Path, data_dir: &Path, key: &str) -> Result<Option<String>, CliError> {
+    let provider = televy_backup_core::secrets::SecretsProvider::new(config_dir);
+    if let Some((value, _)) = provider.injected(key).map_err(map_secrets_store_err)? {
+        return Ok(Some(value));
+    }
     let vault_key = load_or_create_vault_key(data_dir)?;
     let path = televy_backup_core::secrets::secrets_path(config_dir);
     let store = televy_backup_core::secrets::load_secrets_store(&path, &vault_key)
@@ -7024,64 +14711,292 @@ fn get_secret(config_dir: &Path, data_dir: &Path, key: &str) -> Result<Option<St
 }
 
 fn set_secret(config_dir: &Path, data_dir: &Path, key: &str, value: &str) -> Result<(), CliError> {
-    let vault_key = load_or_create_vault_key(data_dir)?;
-    let path = televy_backup_core::secrets::secrets_path(config_dir);
-    let mut store = televy_backup_core::secrets::load_secrets_store(&path, &vault_key)
-        .map_err(map_secrets_store_err)?;
-    store.set(key, value);
-    televy_backup_core::secrets::save_secrets_store(&path, &vault_key, &store)
-        .map_err(map_secrets_store_err)?;
+    let provider = televy_backup_core::secrets::SecretsProvider::new(config_dir);
+    // `secrets.d/` writes need neither the vault key nor the store.
+    let changed = match provider
+        .write_if_file_target(key, value)
+        .map_err(map_secrets_store_err)?
+    {
+        Some(changed) => changed,
+        None => {
+            let vault_key = load_or_create_vault_key(data_dir)?;
+            let path = televy_backup_core::secrets::secrets_path(config_dir);
+            let mut store = televy_backup_core::secrets::load_secrets_store(&path, &vault_key)
+                .map_err(map_secrets_store_err)?;
+            provider
+                .write(key, value, &mut store, &path, &vault_key)
+                .map_err(map_secrets_store_err)?
+        }
+    };
+    if changed {
+        record_audit(
+            data_dir,
+            televy_backup_core::audit::AUDIT_OP_SECRET_SET,
+            serde_json::json!({ "key": key }),
+        );
+    }
     Ok(())
 }
 
 fn map_secrets_store_err(e: televy_backup_core::secrets::SecretsStoreError) -> CliError {
-    CliError::new("secrets.store_failed", e.to_string())
+    CliError::new(e.error_code(), e.to_string())
 }
 
 fn load_master_key(config_dir: &Path, data_dir: &Path) -> Result<[u8; 32], CliError> {
     let b64 = get_secret(config_dir, data_dir, MASTER_KEY_KEY)?
-        .ok_or_else(|| CliError::new("config.invalid", "master key missing"))?;
+        .ok_or_else(|| CliError::new(ErrorCode::ConfigInvalid, "master key missing"))?;
     let bytes = base64::engine::general_purpose::STANDARD
         .decode(b64.as_bytes())
-        .map_err(|e| CliError::new("config.invalid", e.to_string()))?;
+        .map_err(|e| CliError::new(ErrorCode::ConfigInvalid, e.to_string()))?;
     bytes
         .try_into()
-        .map_err(|_| CliError::new("config.invalid", "invalid master key length"))
+        .map_err(|_| CliError::new(ErrorCode::ConfigInvalid, "invalid master key length"))
+}
+
+/// `--keep-going` restores run to the end but still fail the command when files were left out.
+/// `restore run`/`restore latest` switches passed on to `RestoreOptions`.
+#[derive(Debug, Clone)]
+struct RestoreFlags {
+    keep_going: bool,
+    delete_extraneous: bool,
+    dry_run: bool,
+    as_file: bool,
+    preserve_times: bool,
+    rename_collisions: bool,
+    shorten_long_paths: bool,
+    ownership: OwnershipOptions,
+}
+
+fn add_restore_changes_json(
+    out: &mut serde_json::Value,
+    res: &televy_backup_core::RestoreResult,
+    flags: &RestoreFlags,
+) {
+    if flags.delete_extraneous {
+        out["dryRun"] = serde_json::json!(flags.dry_run);
+        out["filesDeleted"] = serde_json::json!(res.files_deleted);
+        out["deletedPaths"] = serde_json::json!(res.deleted_paths);
+    }
+    if !res.renamed_paths.is_empty() {
+        out["renamedPaths"] = res
+            .renamed_paths
+            .iter()
+            .map(|(path, restored_as)| serde_json::json!({ "path": path, "restoredAs": restored_as }))
+            .collect();
+    }
+    if !res.shortened_paths.is_empty() {
+        out["shortenedPaths"] = res
+            .shortened_paths
+            .iter()
+            .map(|(path, restored_as)| serde_json::json!({ "path": path, "restoredAs": restored_as }))
+            .collect();
+    }
+    if res.ownership_warnings > 0 {
+        out["ownershipWarnings"] = serde_json::json!(res.ownership_warnings);
+    }
+}
+
+fn print_restore_summary(res: &televy_backup_core::RestoreResult, duration_seconds: f64) {
+    println!(
+        "filesRestored={} bytesWritten={} durationSeconds={}",
+        res.files_restored,
+        format::bytes(res.bytes_written),
+        format::duration(duration_seconds)
+    );
+}
+
+fn print_restore_changes(res: &televy_backup_core::RestoreResult, flags: &RestoreFlags) {
+    for (path, restored_as) in &res.renamed_paths {
+        println!("renamed={path} -> {restored_as}");
+    }
+    for (path, restored_as) in &res.shortened_paths {
+        println!("shortened={path} -> {restored_as}");
+    }
+    if !res.shortened_paths.is_empty() {
+        eprintln!(
+            "note: {} entries were too long for the target and were restored under {}; {}/{} maps them to their snapshot paths",
+            res.shortened_paths.len(),
+            televy_backup_core::LONG_PATHS_DIR,
+            televy_backup_core::LONG_PATHS_DIR,
+            televy_backup_core::LONG_PATHS_INDEX_FILE
+        );
+    }
+    if res.ownership_warnings > 0 {
+        eprintln!(
+            "warning: could not change the owner of {} restored entries; they stay owned by the restoring user (see the run log)",
+            res.ownership_warnings
+        );
+    }
+    if !flags.delete_extraneous {
+        return;
+    }
+    let key = if flags.dry_run {
+        "wouldDelete"
+    } else {
+        "deleted"
+    };
+    for path in &res.deleted_paths {
+        println!("{key}={path}");
+    }
+    println!("filesDeleted={}", res.files_deleted);
+}
+
+fn snapshot_not_found(snapshot_id: &str, message: impl Into<String>) -> CliError {
+    CliError::new(ErrorCode::SnapshotNotFound, message)
+        .with_details(serde_json::json!({ "snapshotId": snapshot_id }))
+}
+
+fn restore_partial_error(res: &televy_backup_core::RestoreResult) -> CliError {
+    CliError::new(
+        ErrorCode::RestorePartial,
+        format!(
+            "{} file(s) could not be restored ({} restored)",
+            res.files_failed, res.files_restored
+        ),
+    )
+    .with_details(serde_json::json!({
+        "filesRestored": res.files_restored,
+        "filesFailed": res.files_failed,
+        "failures": res
+            .failures
+            .iter()
+            .map(|f| serde_json::json!({
+                "path": f.path,
+                "chunkHash": f.chunk_hash,
+                "objectId": f.object_id,
+                "error": f.error,
+            }))
+            .collect::<Vec<_>>(),
+    }))
 }
 
 fn map_core_err(e: televy_backup_core::Error) -> CliError {
-    match e {
+    // Structured fields come from the core error; arms only add context such as `cause`.
+    let details = e.details();
+    let with_cause = |mut details: serde_json::Value, cause: String| {
+        details["cause"] = serde_json::Value::String(cause);
+        details
+    };
+    let err = match e {
         televy_backup_core::Error::InvalidConfig { message } => {
-            CliError::new("config.invalid", message)
+            CliError::new(ErrorCode::ConfigInvalid, message)
         }
         televy_backup_core::Error::BootstrapMissing { message } => {
-            CliError::new("bootstrap.missing", message)
+            CliError::new(ErrorCode::BootstrapMissing, message)
         }
-        televy_backup_core::Error::BootstrapDecryptFailed { message } => CliError::new(
-            "bootstrap.decrypt_failed",
-            "pinned bootstrap catalog exists but cannot be decrypted; import the correct master key (TBK1)".to_string(),
-        )
-        .with_details(serde_json::json!({ "cause": message })),
-        televy_backup_core::Error::Crypto { message } => {
-            CliError::new("crypto", format!("crypto error: {message}"))
+        televy_backup_core::Error::BootstrapDecryptFailed { message } => {
+            return CliError::new(
+                ErrorCode::BootstrapDecryptFailed,
+                "pinned bootstrap catalog exists but cannot be decrypted; import the correct master key (TBK1)".to_string(),
+            )
+            .with_details(with_cause(details, message));
         }
-        televy_backup_core::Error::Telegram { message } => {
-            CliError::retryable("telegram.unavailable", message)
+        televy_backup_core::Error::Crypto { message } => {
+            CliError::new(ErrorCode::Crypto, format!("crypto error: {message}"))
         }
-        televy_backup_core::Error::MissingChunkObject { chunk_hash } => {
-            CliError::new("chunk.missing", format!("missing chunk: {chunk_hash}"))
+        televy_backup_core::Error::Telegram { message, .. } => {
+            CliError::retryable(ErrorCode::TelegramUnavailable, message)
         }
+        televy_backup_core::Error::ChatMigrated {
+            old_chat_id,
+            new_chat_id,
+        } => CliError::new(
+            ErrorCode::TelegramChatMigrated,
+            format!(
+                "telegram group was upgraded to a supergroup; update chat_id from {old_chat_id} to {new_chat_id}"
+            ),
+        ),
+        televy_backup_core::Error::MissingChunkObject { chunk_hash } => CliError::new(
+            ErrorCode::ChunkMissing,
+            format!("missing chunk: {chunk_hash}"),
+        ),
         televy_backup_core::Error::MissingIndexPart {
             snapshot_id,
             part_no,
         } => CliError::new(
-            "index.part_missing",
+            ErrorCode::IndexPartMissing,
             format!("missing index part: snapshot_id={snapshot_id} part_no={part_no}"),
         ),
-        televy_backup_core::Error::Integrity { message } => CliError::new("integrity", message),
-        televy_backup_core::Error::Cancelled => CliError::new("task.cancelled", "cancelled"),
-        other => CliError::new("unknown", other.to_string()),
-    }
+        televy_backup_core::Error::IndexChainBroken {
+            snapshot_id,
+            missing_snapshot_id,
+            message,
+        } => {
+            return CliError::new(
+                ErrorCode::IndexChainBroken,
+                format!(
+                    "delta index of snapshot {snapshot_id} cannot be rebuilt: the index of {missing_snapshot_id} is missing; run `televybackup index republish --snapshot-id {snapshot_id}` on a machine that still has its file map"
+                ),
+            )
+            .with_details(with_cause(details, message));
+        }
+        televy_backup_core::Error::SnapshotPinned { snapshot_id } => CliError::new(
+            ErrorCode::SnapshotPinned,
+            format!(
+                "snapshot {snapshot_id} is pinned; run `televybackup snapshots unpin --snapshot-id {snapshot_id}` or pass --force"
+            ),
+        ),
+        televy_backup_core::Error::Integrity { message } => {
+            CliError::new(ErrorCode::Integrity, message)
+        }
+        televy_backup_core::Error::ManifestMismatch {
+            snapshot_id,
+            object_id,
+            ..
+        } => CliError::new(
+            ErrorCode::IntegrityManifestMismatch,
+            format!(
+                "index manifest does not match the recorded hash: snapshot_id={snapshot_id} object_id={object_id}"
+            ),
+        ),
+        televy_backup_core::Error::UploadCorrupted {
+            object_kind,
+            object_id,
+            message,
+        } => {
+            return CliError::new(
+                ErrorCode::TelegramUploadCorrupted,
+                format!(
+                    "uploaded {object_kind} did not match what was sent, also after uploading it again: object_id={object_id}"
+                ),
+            )
+            .with_details(with_cause(details, message));
+        }
+        televy_backup_core::Error::Cancelled => {
+            CliError::new(ErrorCode::TaskCancelled, "cancelled")
+        }
+        televy_backup_core::Error::CaseCollision { paths } => CliError::new(
+            ErrorCode::RestoreCaseCollision,
+            format!(
+                "the target is case-insensitive and these snapshot paths differ only in case: {}; pass --rename-collisions to restore the later ones with a suffix",
+                paths.join(", ")
+            ),
+        ),
+        televy_backup_core::Error::VersionSkew { message, .. } => {
+            CliError::new(ErrorCode::VersionSkew, message)
+        }
+        televy_backup_core::Error::PathTooLong {
+            paths,
+            name_max,
+            path_max,
+        } => CliError::new(
+            ErrorCode::RestorePathTooLong,
+            format!(
+                "{} paths are too long for the target (NAME_MAX {name_max}, PATH_MAX {path_max}); nothing was restored. Pick a shorter target path or pass --shorten-long-paths: {}",
+                paths.len(),
+                paths.join(", ")
+            ),
+        ),
+        televy_backup_core::Error::DataDirInUse { path, reason } => CliError::new(
+            ErrorCode::DataDirInUse,
+            format!(
+                "data dir {} is in use: {reason}; stop the daemon (`brew services stop televybackupd` or quit the app) and wait for running tasks to finish",
+                path.display()
+            ),
+        ),
+        other => CliError::new(ErrorCode::Unknown, other.to_string()),
+    };
+    err.with_details(details)
 }
 
 fn emit_error(e: &CliError) {
@@ -7093,21 +15008,66 @@ fn emit_error(e: &CliError) {
 mod tests {
     use super::*;
 
+    #[test]
+    fn snapshot_time_bound_normalizes_to_created_at_format() {
+        assert_eq!(
+            snapshot_time_bound("--since", "2024-01-01").unwrap(),
+            "2024-01-01T00:00:00.000Z"
+        );
+        assert_eq!(
+            snapshot_time_bound("--until", "2024-02-01T08:30:00+08:00").unwrap(),
+            "2024-02-01T00:30:00.000Z"
+        );
+        let err = snapshot_time_bound("--since", "yesterday").unwrap_err();
+        assert_eq!(err.code, ErrorCode::ConfigInvalid);
+    }
+
+    #[test]
+    fn map_core_err_carries_structured_details() {
+        let err = map_core_err(televy_backup_core::Error::MissingChunkObject {
+            chunk_hash: "abc123".to_string(),
+        });
+        assert_eq!(err.code, ErrorCode::ChunkMissing);
+        assert_eq!(err.details["chunkHash"], "abc123");
+
+        let err = map_core_err(televy_backup_core::Error::telegram(
+            "rpc error 420: FLOOD_WAIT caused by upload.saveFilePart (value: 30)",
+        ));
+        assert!(err.retryable);
+        assert_eq!(err.details["kind"], "flood_wait");
+        assert_eq!(err.details["waitSeconds"], 30);
+
+        let err = map_core_err(televy_backup_core::Error::IndexChainBroken {
+            snapshot_id: "snp_2".to_string(),
+            missing_snapshot_id: "snp_1".to_string(),
+            message: "gone".to_string(),
+        });
+        assert_eq!(err.details["snapshotId"], "snp_2");
+        assert_eq!(err.details["missingSnapshotId"], "snp_1");
+        assert_eq!(err.details["cause"], "gone");
+    }
+
     #[test]
     fn progress_throttle_emits_first_event_phase_changes_and_rate_limits() {
         let mut t = ProgressThrottle::new(Duration::from_millis(50));
-        assert!(t.should_emit("scan"));
-        assert!(!t.should_emit("scan"), "should throttle within interval");
+        assert!(t.should_emit(&Phase::Scan));
+        assert!(
+            !t.should_emit(&Phase::Scan),
+            "should throttle within interval"
+        );
 
         // Phase change should bypass the cadence throttle.
-        assert!(t.should_emit("upload"));
+        assert!(t.should_emit(&Phase::Upload));
         assert!(
-            !t.should_emit("upload"),
+            !t.should_emit(&Phase::Upload),
             "should throttle again after phase emit"
         );
 
         std::thread::sleep(Duration::from_millis(60));
-        assert!(t.should_emit("upload"), "should emit again after interval");
+        assert!(
+            t.should_emit(&Phase::Upload),
+            "should emit again after interval"
+        );
     }
 
     fn status_snapshot_one_target(
@@ -7122,6 +15082,7 @@ mod tests {
             source: televy_backup_core::status::StatusSource {
                 kind: "daemon".to_string(),
                 detail: Some("test".to_string()),
+                degraded: false,
             },
             global: televy_backup_core::status::GlobalStatus {
                 up: televy_backup_core::status::Rate {
@@ -7147,7 +15108,7 @@ mod tests {
                 },
                 up_total: televy_backup_core::status::Counter { bytes: None },
                 progress: Some(televy_backup_core::status::Progress {
-                    phase: "upload".to_string(),
+                    phase: Phase::Upload,
                     source_files_total: None,
                     source_bytes_total: None,
                     source_bytes_need_upload_total: None,
@@ -7161,7 +15122,9 @@ mod tests {
                     bytes_uploaded_source: None,
                     bytes_uploaded: Some(123),
                     bytes_downloaded: None,
+                    bytes_written: None,
                     bytes_deduped: None,
+                    bytes_total: None,
                 }),
                 last_run: None,
                 extra: Default::default(),
@@ -7170,6 +15133,29 @@ mod tests {
         }
     }
 
+    #[test]
+    fn status_watch_lines_fit_width_and_keep_path_tail() {
+        let mut snap = status_snapshot_one_target(0, "running", Some(3 * 1024 * 1024 / 2));
+        snap.targets[0].source_path =
+            "/Users/someone/Documents/projects/very/long/path".to_string();
+        if let Some(p) = snap.targets[0].progress.as_mut() {
+            p.files_done = Some(7);
+            p.files_total = Some(10);
+        }
+
+        let lines = status_watch_lines(&snap, 200, false);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].starts_with("t1  running  upload    files=7/10  up=1.5 MiB/s  last=-"));
+        assert!(lines[1].ends_with("/very/long/path"));
+
+        let lines = status_watch_lines(&snap, 70, true);
+        assert!(lines.iter().all(|l| l.chars().count() <= 70));
+        assert!(lines[1].ends_with("  …ry/long/path"), "{}", lines[1]);
+
+        assert_eq!(format_rate(Some(512)), "512 B/s");
+        assert_eq!(format_rate(None), "-");
+    }
+
     #[test]
     fn status_stream_enricher_preserves_daemon_rate_when_fresh_and_running() {
         let now = televy_backup_core::status::now_unix_ms();
@@ -7205,6 +15191,7 @@ mod tests {
             source: televy_backup_core::status::StatusSource {
                 kind: "daemon".to_string(),
                 detail: Some("test".to_string()),
+                degraded: false,
             },
             global: televy_backup_core::status::GlobalStatus {
                 up: televy_backup_core::status::Rate {
@@ -7231,7 +15218,7 @@ mod tests {
                     },
                     up_total: televy_backup_core::status::Counter { bytes: None },
                     progress: Some(televy_backup_core::status::Progress {
-                        phase: "upload".to_string(),
+                        phase: Phase::Upload,
                         source_files_total: None,
                         source_bytes_total: None,
                         source_bytes_need_upload_total: None,
@@ -7245,7 +15232,9 @@ mod tests {
                         bytes_uploaded_source: None,
                         bytes_uploaded: Some(10),
                         bytes_downloaded: None,
+                        bytes_written: None,
                         bytes_deduped: None,
+                        bytes_total: None,
                     }),
                     last_run: None,
                     extra: Default::default(),
@@ -7263,7 +15252,7 @@ mod tests {
                     },
                     up_total: televy_backup_core::status::Counter { bytes: None },
                     progress: Some(televy_backup_core::status::Progress {
-                        phase: "upload".to_string(),
+                        phase: Phase::Upload,
                         source_files_total: None,
                         source_bytes_total: None,
                         source_bytes_need_upload_total: None,
@@ -7277,7 +15266,9 @@ mod tests {
                         bytes_uploaded_source: None,
                         bytes_uploaded: Some(20),
                         bytes_downloaded: None,
+                        bytes_written: None,
                         bytes_deduped: None,
+                        bytes_total: None,
                     }),
                     last_run: None,
                     extra: Default::default(),
@@ -7297,9 +15288,12 @@ mod tests {
             id: id.to_string(),
             mode: "mtproto".to_string(),
             chat_id: "-1001".to_string(),
+            migrated_from_chat_ids: Vec::new(),
             bot_token_key: format!("telegram.bot_token.{id}"),
             mtproto: settings_config::TelegramEndpointMtproto::default(),
             rate_limit: settings_config::TelegramRateLimit::default(),
+            bootstrap: settings_config::TelegramEndpointBootstrap::default(),
+            object_prefix: None,
         }
     }
 
@@ -7330,6 +15324,66 @@ mod tests {
         assert!(path.exists());
     }
 
+    #[test]
+    fn import_bundle_apply_response_reports_per_endpoint_status() {
+        let mut report = config_bundle::ApplyPartialReport::new(
+            "sha".to_string(),
+            ["ep_a".to_string(), "ep_b".to_string()],
+        );
+        report.set_endpoint(config_bundle::ApplyEndpointReport {
+            endpoint_id: "ep_a".to_string(),
+            status: config_bundle::ApplyEndpointStatus::Rebuilt,
+            rebuilt_from: Some("remote_latest".to_string()),
+            snapshot_id: Some("snp_1".to_string()),
+            manifest_object_id: Some("obj".to_string()),
+            rebuilt_db_path: Some("/d/index.ep_a.sqlite".to_string()),
+            previous_db_backup_path: None,
+            synced_target_id: Some("t1".to_string()),
+            error_code: None,
+        });
+        let applied = || SettingsImportBundleApplyAppliedJson {
+            targets: Vec::new(),
+            endpoints: Vec::new(),
+            secrets_written: Vec::new(),
+        };
+        let resumed = std::collections::BTreeSet::from(["ep_a".to_string()]);
+
+        let resp = import_bundle_apply_response(
+            &report,
+            &resumed,
+            Some("/d/report.json".to_string()),
+            applied(),
+        );
+        let v = serde_json::to_value(&resp).unwrap();
+        assert_eq!(v["ok"], false);
+        assert_eq!(v["partial"], true);
+        assert_eq!(v["reportPath"], "/d/report.json");
+        assert_eq!(v["localIndex"]["rebuiltDbPath"], "/d/index.ep_a.sqlite");
+        assert_eq!(v["endpoints"][0]["status"], "rebuilt");
+        assert_eq!(v["endpoints"][0]["resumed"], true);
+        assert_eq!(v["endpoints"][0]["rebuiltFrom"]["snapshotId"], "snp_1");
+        assert_eq!(v["endpoints"][1]["status"], "pending");
+        assert_eq!(v["actions"]["localIndexSynced"][0]["targetId"], "t1");
+
+        let mut done = report.endpoint("ep_b").unwrap().clone();
+        done.status = config_bundle::ApplyEndpointStatus::Rebuilt;
+        done.rebuilt_from = Some("empty".to_string());
+        report.set_endpoint(done);
+        report.settings_written = true;
+        report.secrets_written = true;
+        let v = serde_json::to_value(import_bundle_apply_response(
+            &report,
+            &resumed,
+            None,
+            applied(),
+        ))
+        .unwrap();
+        assert_eq!(v["ok"], true);
+        assert_eq!(v["partial"], false);
+        assert!(v.get("reportPath").is_none());
+        assert_eq!(v["endpoints"][1]["resumed"], false);
+    }
+
     #[test]
     fn load_settings_rejects_duplicate_endpoint_ids() {
         let dir = temp_config_dir("dup-endpoint");
@@ -7353,7 +15407,7 @@ bot_token_key = "telegram.bot_token.ep1b"
         );
 
         let err = load_settings(&dir).unwrap_err();
-        assert_eq!(err.code, "config.invalid");
+        assert_eq!(err.code, ErrorCode::ConfigInvalid);
         assert!(err.message.contains("duplicate telegram_endpoints id"));
     }
 
@@ -7379,10 +15433,68 @@ endpoint_id = "missing"
         );
 
         let err = load_settings(&dir).unwrap_err();
-        assert_eq!(err.code, "config.invalid");
+        assert_eq!(err.code, ErrorCode::ConfigInvalid);
         assert!(err.message.contains("references unknown endpoint_id"));
     }
 
+    #[test]
+    fn targets_disable_and_enable_round_trip_through_settings() {
+        let dir = temp_config_dir("targets-enable");
+        write_config(
+            &dir,
+            r#"
+version = 2
+
+[[telegram_endpoints]]
+id = "ep1"
+mode = "mtproto"
+chat_id = "-1001"
+bot_token_key = "telegram.bot_token.ep1"
+
+[telegram_endpoints.mtproto]
+session_key = "telegram.mtproto.session.ep1"
+
+[[targets]]
+id = "t1"
+source_path = "/tmp"
+endpoint_id = "ep1"
+"#,
+        );
+
+        targets_set_enabled(&dir, "t1", false, Some("2024-07-01"), true).unwrap();
+        let t = &load_settings(&dir).unwrap().targets[0];
+        assert!(!t.enabled);
+        assert_eq!(
+            t.disabled_until.as_deref(),
+            Some("2024-07-01T00:00:00.000Z")
+        );
+
+        let err = targets_set_enabled(&dir, "t1", false, Some("soon"), true).unwrap_err();
+        assert_eq!(err.code, ErrorCode::ConfigInvalid);
+        let err = targets_set_enabled(&dir, "t2", true, None, true).unwrap_err();
+        assert_eq!(err.code, ErrorCode::ConfigInvalid);
+
+        targets_set_enabled(&dir, "t1", true, None, true).unwrap();
+        let t = &load_settings(&dir).unwrap().targets[0];
+        assert!(t.enabled);
+        assert_eq!(t.disabled_until, None);
+    }
+
+    #[test]
+    fn secrets_round_trip_through_secrets_d_without_the_vault() {
+        let dir = temp_config_dir("secrets-d");
+        let data_dir = dir.join("data");
+        std::fs::create_dir_all(televy_backup_core::secrets::secrets_dir_path(&dir)).unwrap();
+
+        // With `secrets.d/` present neither call needs the vault key.
+        set_secret(&dir, &data_dir, "telegram.bot_token.ep1", "123:abc").unwrap();
+        assert!(!televy_backup_core::secrets::secrets_path(&dir).exists());
+        assert_eq!(
+            get_secret(&dir, &data_dir, "telegram.bot_token.ep1").unwrap(),
+            Some("123:abc".to_string())
+        );
+    }
+
     #[test]
     fn select_endpoint_defaults_to_only_endpoint() {
         let settings = Settings {
@@ -7410,7 +15522,192 @@ endpoint_id = "missing"
             ..Default::default()
         };
         let err = select_endpoint(&settings, None).unwrap_err();
-        assert_eq!(err.code, "config.invalid");
+        assert_eq!(err.code, ErrorCode::ConfigInvalid);
         assert!(err.message.contains("multiple endpoints configured"));
     }
+
+    #[test]
+    fn progress_chunks_total_keeps_known_totals() {
+        let so_far = televy_backup_core::TaskProgress {
+            phase: Phase::Index,
+            chunks_total: Some(3),
+            chunks_done: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(progress_chunks_total(&so_far), None);
+
+        let restore = televy_backup_core::TaskProgress {
+            bytes_total: Some(10),
+            ..so_far.clone()
+        };
+        assert_eq!(progress_chunks_total(&restore), Some(3));
+
+        let running = televy_backup_core::TaskProgress {
+            chunks_done: Some(1),
+            ..so_far
+        };
+        assert_eq!(progress_chunks_total(&running), Some(3));
+    }
+
+    #[test]
+    fn usage_prune_cutoff_accepts_day_week_month_year_ages() {
+        let now = chrono::DateTime::parse_from_rfc3339("2026-03-31T12:00:00Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        let cutoff = |s: &str| {
+            usage_prune_cutoff(s, now)
+                .map(|c| c.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+                .map_err(|e| e.code)
+        };
+        assert_eq!(cutoff("2y").unwrap(), "2024-03-31T12:00:00Z");
+        assert_eq!(cutoff("1m").unwrap(), "2026-02-28T12:00:00Z");
+        assert_eq!(cutoff("2w").unwrap(), "2026-03-17T12:00:00Z");
+        assert_eq!(cutoff(" 30d ").unwrap(), "2026-03-01T12:00:00Z");
+        assert_eq!(cutoff("2"), Err(ErrorCode::ConfigInvalid));
+        assert_eq!(cutoff("y"), Err(ErrorCode::ConfigInvalid));
+        assert_eq!(cutoff("3h"), Err(ErrorCode::ConfigInvalid));
+    }
+
+    #[test]
+    fn prompt_answer_confirms_only_explicit_yes() {
+        assert!(prompt_answer_confirms("continue\n"));
+        assert!(prompt_answer_confirms(" Y "));
+        assert!(prompt_answer_confirms("yes"));
+        assert!(!prompt_answer_confirms(""));
+        assert!(!prompt_answer_confirms("cancel\n"));
+        assert!(!prompt_answer_confirms("n"));
+    }
+
+    #[test]
+    fn version_all_takes_json_after_the_subcommand() {
+        let cli = Cli::try_parse_from(["televybackup", "version", "--all", "--json"]).unwrap();
+        assert!(cli.json);
+        assert!(matches!(cli.cmd, Some(Command::Version { all: true })));
+
+        let cli = Cli::try_parse_from(["televybackup", "version"]).unwrap();
+        assert!(!cli.json);
+        assert!(matches!(cli.cmd, Some(Command::Version { all: false })));
+    }
+
+    #[tokio::test]
+    async fn exit_codes_follow_the_documented_classes() {
+        let config_dir = temp_config_dir("exit-codes");
+        let data_dir = config_dir.join("data");
+        std::fs::create_dir_all(&data_dir).unwrap();
+        let run_args = |args: &[&str]| {
+            let mut argv = vec![
+                "televybackup",
+                "--config-dir",
+                config_dir.to_str().unwrap(),
+                "--data-dir",
+                data_dir.to_str().unwrap(),
+            ];
+            argv.extend_from_slice(args);
+            run(Cli::try_parse_from(argv).unwrap())
+        };
+
+        let ok = run_args(&["ping", "x"]).await;
+        assert_eq!(exit_code(&ok, false), 0);
+
+        let failure = run_args(&["logs", "show", "--run-id", "run_missing"]).await;
+        assert_eq!(exit_code(&failure, false), 1);
+
+        let usage = run_args(&[
+            "verify",
+            "latest",
+            "--target-id",
+            "t1",
+            "--sample-percent",
+            "0",
+        ])
+        .await;
+        assert_eq!(usage.as_ref().unwrap_err().code, ErrorCode::ConfigInvalid);
+        assert_eq!(exit_code(&usage, false), 2);
+
+        // No daemon socket and no status.json.
+        let retryable = run_args(&["status", "get"]).await;
+        assert_eq!(
+            retryable.as_ref().unwrap_err().code,
+            ErrorCode::StatusUnavailable
+        );
+        assert_eq!(exit_code(&retryable, false), 3);
+        assert_eq!(exit_code(&retryable, true), 0);
+
+        std::fs::write(
+            televy_backup_core::audit::audit_log_path(&data_dir),
+            "not an audit entry\n",
+        )
+        .unwrap();
+        let integrity = run_args(&["audit", "verify"]).await;
+        assert_eq!(
+            integrity.as_ref().unwrap_err().code,
+            ErrorCode::AuditChainBroken
+        );
+        assert_eq!(exit_code(&integrity, false), 4);
+        assert_eq!(exit_code(&integrity, true), 4);
+
+        let cancelled = Err(CliError::new(ErrorCode::TaskCancelled, "task cancelled"));
+        assert_eq!(exit_code(&cancelled, false), 5);
+        assert_eq!(exit_code(&cancelled, true), 5);
+
+        let retryable_flag = Err(CliError::retryable(
+            ErrorCode::DbFailed,
+            "database is locked",
+        ));
+        assert_eq!(exit_code(&retryable_flag, false), 3);
+
+        let cli =
+            Cli::try_parse_from(["televybackup", "status", "get", "--exit-zero-on-retryable"])
+                .unwrap();
+        assert!(cli.exit_zero_on_retryable);
+
+        let _ = std::fs::remove_dir_all(&config_dir);
+    }
+
+    #[test]
+    fn error_catalog_flag_stands_alone() {
+        let cli = Cli::try_parse_from(["televybackup", "--error-catalog"]).unwrap();
+        assert!(cli.error_catalog);
+        assert!(cli.cmd.is_none());
+        assert!(Cli::try_parse_from(["televybackup", "--json", "--error-catalog"]).is_err());
+
+        let catalog = ErrorCode::catalog();
+        let entry = catalog["codes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|c| c["code"] == "snapshot.not_found")
+            .unwrap();
+        assert_eq!(entry["params"], serde_json::json!(["snapshotId"]));
+        let err = snapshot_not_found("snp_1", "snapshot not found: snp_1");
+        assert_eq!(err.details["snapshotId"], "snp_1");
+    }
+
+    #[test]
+    fn parse_byte_size_accepts_binary_units() {
+        assert_eq!(parse_byte_size("1024"), Ok(1024));
+        assert_eq!(parse_byte_size("2G"), Ok(2 << 30));
+        assert_eq!(parse_byte_size("500MiB"), Ok(500 << 20));
+        assert_eq!(parse_byte_size("8kb"), Ok(8 << 10));
+        assert!(parse_byte_size("2X").is_err());
+        assert!(parse_byte_size("G").is_err());
+        assert!(parse_byte_size("99999999999T").is_err());
+    }
+
+    #[test]
+    fn doctor_flags_a_scheduler_that_stopped_ticking() {
+        let schedule = televy_backup_core::status::ScheduleStatus {
+            timezone: "Europe/Berlin".to_string(),
+            catch_up: true,
+            tick_interval_seconds: 1,
+            last_tick_at: Some(10_000),
+            missed_slots_caught_up: 2,
+        };
+        let ok = scheduler_tick_check(&schedule, false, 11_500).unwrap();
+        assert!(ok.contains("missed_slots_caught_up=2"), "{ok}");
+        let err = scheduler_tick_check(&schedule, false, 15_000).unwrap_err();
+        assert!(err.starts_with("last scheduler tick 5s ago"), "{err}");
+        // A running backup holds up the ticks.
+        assert!(scheduler_tick_check(&schedule, true, 15_000).is_ok());
+    }
 }
@@ -0,0 +1,121 @@
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use std::thread;
+
+use televy_backup_core::config as settings_config;
+use televy_backup_core::config_bundle::{ConfigBundleSecretsV2, encode_config_bundle_key_v2};
+
+const PASSPHRASE: &str = "hunter2";
+
+/// A bundle whose bot token alone is a few megabytes, so the request overflows any pipe buffer.
+fn large_bundle_key() -> String {
+    let settings = settings_config::parse_settings_v2(
+        r#"
+version = 2
+
+[[telegram_endpoints]]
+id = "ep1"
+mode = "mtproto"
+chat_id = "-1001"
+bot_token_key = "telegram.bot_token.ep1"
+
+[[targets]]
+id = "t1"
+source_path = "/tmp"
+endpoint_id = "ep1"
+"#,
+    )
+    .unwrap();
+    let mut secrets = ConfigBundleSecretsV2::default();
+    secrets
+        .entries
+        .insert("telegram.bot_token.ep1".to_string(), "x".repeat(3 << 20));
+    encode_config_bundle_key_v2(&[7u8; 32], &settings, secrets, PASSPHRASE, "").unwrap()
+}
+
+fn apply_request(bundle_key: &str) -> Vec<u8> {
+    serde_json::to_vec(&serde_json::json!({
+        "bundleKey": bundle_key,
+        "selectedTargetIds": ["t1"],
+        "confirm": { "phrase": "IMPORT" },
+    }))
+    .unwrap()
+}
+
+fn televybackup(root: &Path, args: &[&str], stdin: Option<Vec<u8>>) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_televybackup"))
+        .args(args)
+        .env("TELEVYBACKUP_CONFIG_DIR", root.join("config"))
+        .env("TELEVYBACKUP_DATA_DIR", root.join("data"))
+        .env("TELEVYBACKUP_CONFIG_BUNDLE_PASSPHRASE", PASSPHRASE)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut pipe = child.stdin.take().unwrap();
+    // The CLI may stop reading early (oversized input), so a broken pipe here is expected.
+    let writer = thread::spawn(move || {
+        if let Some(input) = stdin {
+            let _ = pipe.write_all(&input);
+        }
+    });
+    let out = child.wait_with_output().unwrap();
+    writer.join().unwrap();
+    out
+}
+
+fn error_code(out: &Output) -> String {
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    let line = stderr
+        .lines()
+        .rev()
+        .find(|l| l.starts_with('{'))
+        .unwrap_or_else(|| panic!("no error json on stderr: {stderr}"));
+    let v: serde_json::Value = serde_json::from_str(line).unwrap();
+    v["code"].as_str().unwrap().to_string()
+}
+
+#[test]
+fn import_bundle_apply_reads_a_multi_megabyte_request_from_stdin_and_input_file() {
+    let root = tempfile::tempdir().unwrap();
+    let request = apply_request(&large_bundle_key());
+    assert!(request.len() > 4 << 20);
+
+    // With no daemon behind the temp data dir, apply stops at the local master key lookup, which
+    // only happens once the whole request was read and the bundle decrypted.
+    let args = ["--json", "settings", "import-bundle", "--apply"];
+    let out = televybackup(root.path(), &args, Some(request.clone()));
+    assert!(!out.status.success());
+    assert_eq!(error_code(&out), "daemon.unavailable");
+
+    let input = root.path().join("request.json");
+    std::fs::write(&input, &request).unwrap();
+    let input = input.to_str().unwrap();
+    let out = televybackup(
+        root.path(),
+        &[&args[..], &["--input-file", input]].concat(),
+        None,
+    );
+    assert!(!out.status.success());
+    assert_eq!(error_code(&out), "daemon.unavailable");
+}
+
+#[test]
+fn oversized_input_fails_with_config_too_large() {
+    let root = tempfile::tempdir().unwrap();
+    let oversized = vec![b' '; (16 << 20) + 1];
+
+    let out = televybackup(root.path(), &["settings", "set"], Some(oversized.clone()));
+    assert_eq!(error_code(&out), "config.too_large");
+
+    let input = root.path().join("settings.toml");
+    std::fs::write(&input, &oversized).unwrap();
+    let out = televybackup(
+        root.path(),
+        &["settings", "set", "--input-file", input.to_str().unwrap()],
+        None,
+    );
+    assert_eq!(error_code(&out), "config.too_large");
+}
@@ -0,0 +1,4 @@
+-- Which machine created the snapshot (see `device.json` in the data dir). NULL for snapshots
+-- written before device identities existed.
+ALTER TABLE snapshots ADD COLUMN device_id TEXT NULL;
+ALTER TABLE snapshots ADD COLUMN device_name TEXT NULL;
@@ -0,0 +1,3 @@
+-- SHA-256 (hex) of the encrypted manifest bytes as uploaded. Downloads compare against it before
+-- trusting the manifest. NULL for indexes uploaded before the hash was recorded.
+ALTER TABLE remote_indexes ADD COLUMN manifest_sha256 TEXT NULL;
@@ -0,0 +1,11 @@
+-- Message ids of objects forwarded from an old backup chat to the endpoint's current one
+-- (`televybackup index remap-chat`). Downloads of object ids still pointing at the old chat, e.g.
+-- inside remote index DBs uploaded before the move, are translated through this table.
+CREATE TABLE IF NOT EXISTS chat_remap_messages (
+  old_peer TEXT NOT NULL,
+  old_msg_id INTEGER NOT NULL,
+  new_peer TEXT NOT NULL,
+  new_msg_id INTEGER NOT NULL,
+  created_at TEXT NOT NULL,
+  PRIMARY KEY (old_peer, old_msg_id)
+);
@@ -0,0 +1,5 @@
+-- Differential filemap indexes (`index.full_every`): a `delta` manifest only carries the file
+-- entries that changed since the snapshot whose manifest is `parent_manifest_object_id`; a `full`
+-- manifest is self-contained. Rows written before deltas existed are all `full`.
+ALTER TABLE remote_indexes ADD COLUMN manifest_kind TEXT NOT NULL DEFAULT 'full';
+ALTER TABLE remote_indexes ADD COLUMN parent_manifest_object_id TEXT NULL;
@@ -0,0 +1,3 @@
+-- Pinned snapshots (`televybackup snapshots pin`) are never removed by retention or
+-- `snapshots delete` without `--force`.
+ALTER TABLE snapshots ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,3 @@
+-- File creation (birth) time in Unix ms, where the platform exposes it. NULL for directories,
+-- symlinks, platforms without birth times, and files indexed before this column existed.
+ALTER TABLE files ADD COLUMN btime_ms INTEGER NULL;
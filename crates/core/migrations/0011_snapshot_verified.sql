@@ -0,0 +1,3 @@
+-- When a `verify` of the snapshot last succeeded (RFC3339); the daemon's `verify_schedule`
+-- counts its cadence from it. NULL = never verified.
+ALTER TABLE snapshots ADD COLUMN verified_at TEXT NULL;
@@ -0,0 +1,7 @@
+-- Owner of files and directories at backup time: numeric uid/gid plus the user and group names
+-- they resolved to on the backed-up machine (NULL when unresolvable). All NULL for symlinks,
+-- platforms without POSIX owners, and entries indexed before these columns existed.
+ALTER TABLE files ADD COLUMN uid INTEGER NULL;
+ALTER TABLE files ADD COLUMN gid INTEGER NULL;
+ALTER TABLE files ADD COLUMN owner_name TEXT NULL;
+ALTER TABLE files ADD COLUMN group_name TEXT NULL;
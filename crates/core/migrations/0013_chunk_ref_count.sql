@@ -0,0 +1,17 @@
+-- How many `file_chunks` rows of the snapshots this DB records point at each chunk, so orphaned
+-- chunks are `ref_count = 0` instead of a scan of every file map. Only kept up to date in endpoint
+-- DBs: a snapshot's rows count from the transaction that commits its index pointer (or, for file
+-- maps held in this DB, from here on) until retention removes it, and `chunk_refs_counted` marks
+-- the snapshots whose rows are in. The rest are counted by `gc run` or `index check --repair`.
+ALTER TABLE chunks ADD COLUMN ref_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE snapshots ADD COLUMN chunk_refs_counted INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX IF NOT EXISTS idx_chunks_unreferenced
+  ON chunks(size) WHERE ref_count = 0;
+
+UPDATE chunks SET ref_count = r.n
+FROM (SELECT chunk_hash, COUNT(*) AS n FROM file_chunks GROUP BY chunk_hash) AS r
+WHERE r.chunk_hash = chunks.chunk_hash;
+
+UPDATE snapshots SET chunk_refs_counted = 1
+WHERE snapshot_id IN (SELECT DISTINCT snapshot_id FROM files);
@@ -0,0 +1,4 @@
+-- Which key encrypts the snapshot's chunks, packs and file map index: 0 = the master key,
+-- 1 = the target key derived from it for `key_target_id` (see `crypto::KeyDerivation`).
+ALTER TABLE snapshots ADD COLUMN key_derivation INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE snapshots ADD COLUMN key_target_id TEXT NULL;
@@ -0,0 +1,5 @@
+-- Name prefix (`telegram_endpoints[].object_prefix`) of the machine that uploaded an object, for
+-- telling machines that share a chat apart. Informational only: objects are always found by id,
+-- and rows written before this column existed leave it NULL.
+ALTER TABLE chunk_objects ADD COLUMN object_prefix TEXT;
+ALTER TABLE remote_indexes ADD COLUMN object_prefix TEXT;
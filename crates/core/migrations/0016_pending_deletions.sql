@@ -0,0 +1,31 @@
+-- Deletion queue (`retention.deletion_grace_days`): `gc run` records unreferenced storage objects
+-- here instead of deleting them, and `gc flush` deletes those past `delete_after` that are still
+-- unreferenced. Their chunk rows stay until then, so a snapshot brought back by `gc undo` keeps
+-- its objects.
+CREATE TABLE IF NOT EXISTS pending_deletions (
+  provider TEXT NOT NULL,
+  object_id TEXT NOT NULL,
+  bytes INTEGER NOT NULL,
+  queued_at TEXT NOT NULL,
+  delete_after TEXT NOT NULL,
+  PRIMARY KEY (provider, object_id)
+);
+
+-- Rows of snapshots removed by retention or `snapshots delete`, kept for `gc undo` until a flush
+-- drops them after the grace period. The `deleted_*` tables copy the live tables' columns in
+-- order, so a migration adding a column to one of those must add it to its copy as well.
+CREATE TABLE IF NOT EXISTS snapshot_tombstones (
+  snapshot_id TEXT PRIMARY KEY,
+  deleted_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS deleted_snapshots AS SELECT * FROM snapshots WHERE 0;
+CREATE TABLE IF NOT EXISTS deleted_remote_indexes AS SELECT * FROM remote_indexes WHERE 0;
+CREATE TABLE IF NOT EXISTS deleted_remote_index_parts AS SELECT * FROM remote_index_parts WHERE 0;
+
+CREATE INDEX IF NOT EXISTS idx_deleted_snapshots_snapshot_id
+  ON deleted_snapshots(snapshot_id);
+CREATE INDEX IF NOT EXISTS idx_deleted_remote_indexes_snapshot_id
+  ON deleted_remote_indexes(snapshot_id);
+CREATE INDEX IF NOT EXISTS idx_deleted_remote_index_parts_snapshot_id
+  ON deleted_remote_index_parts(snapshot_id);
@@ -0,0 +1,17 @@
+-- How the snapshot's chunk ids were computed (see `crypto::ChunkHashAlg`). Snapshots under a
+-- target key always used keyed blake3; `chunks.hash_alg` said "blake3" for those chunks as well,
+-- which is corrected where the snapshot's file rows are in this DB.
+ALTER TABLE snapshots ADD COLUMN hash_alg TEXT NOT NULL DEFAULT 'blake3';
+ALTER TABLE deleted_snapshots ADD COLUMN hash_alg TEXT NOT NULL DEFAULT 'blake3';
+
+UPDATE snapshots SET hash_alg = 'blake3-keyed' WHERE key_derivation = 1;
+UPDATE deleted_snapshots SET hash_alg = 'blake3-keyed' WHERE key_derivation = 1;
+
+UPDATE chunks SET hash_alg = 'blake3-keyed'
+WHERE chunk_hash IN (
+  SELECT fc.chunk_hash
+  FROM file_chunks fc
+  JOIN files f ON f.file_id = fc.file_id
+  JOIN snapshots s ON s.snapshot_id = f.snapshot_id
+  WHERE s.key_derivation = 1
+);
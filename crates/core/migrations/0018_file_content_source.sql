@@ -0,0 +1,4 @@
+-- Where a file's chunks were read from when not from the file itself: "sqlite_backup" for a
+-- consistent copy of a SQLite database (see `sqlite_consistent`). NULL for everything else and
+-- for files indexed before this column existed.
+ALTER TABLE files ADD COLUMN content_source TEXT NULL;
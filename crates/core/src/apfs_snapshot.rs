@@ -0,0 +1,266 @@
+//! Point-in-time backups on macOS: a local APFS snapshot of the source volume, mounted read-only.
+//!
+//! The backup walks the mount ([`ApfsSnapshot::scan_root`]) while file paths stay relative to the
+//! logical source path, so manifests, snapshot records and `.televyignore` rules never see the
+//! mount point. Dropping the guard unmounts and deletes the snapshot, including on errors and
+//! cancelled runs.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use tracing::{info, warn};
+
+use crate::{Error, Result};
+
+/// Where the writable half of the boot volume group is mounted; firmlinks (`/Users`,
+/// `/Applications`, ...) on the sealed system volume point here.
+const MACOS_DATA_VOLUME: &str = "/System/Volumes/Data";
+
+const SNAPSHOT_MOUNT_PREFIX: &str = "televybackup-apfs-";
+
+pub struct ApfsSnapshot {
+    /// `tmutil` snapshot date (`YYYY-MM-DD-HHMMSS`), which names the snapshot on every volume.
+    date: String,
+    volume: PathBuf,
+    /// `None` once the mount point is left behind because unmounting failed.
+    mount_dir: Option<tempfile::TempDir>,
+    scan_root: PathBuf,
+}
+
+impl ApfsSnapshot {
+    /// Takes a local snapshot of the APFS volume holding `source_path` and mounts it read-only.
+    ///
+    /// Blocking: shells out to `tmutil` and `mount_apfs`.
+    pub fn create(source_path: &Path) -> Result<Self> {
+        if !cfg!(target_os = "macos") {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "APFS snapshots are only available on macOS",
+            )));
+        }
+
+        let source_path = std::fs::canonicalize(source_path)?;
+        let mounts = parse_mount_table(&run("mount", &[])?);
+        let (mut volume, rel_path) = volume_for_path(&mounts, &source_path).ok_or_else(|| {
+            snapshot_error(format!(
+                "source is not on an APFS volume: {}",
+                source_path.display()
+            ))
+        })?;
+        // A firmlinked path keeps its relative path on the data volume.
+        if volume == Path::new("/")
+            && mounts
+                .iter()
+                .any(|(m, fs)| m == Path::new(MACOS_DATA_VOLUME) && fs == "apfs")
+            && Path::new(MACOS_DATA_VOLUME).join(&rel_path).exists()
+        {
+            volume = PathBuf::from(MACOS_DATA_VOLUME);
+        }
+
+        let date =
+            parse_localsnapshot_date(&run("tmutil", &["localsnapshot"])?).ok_or_else(|| {
+                snapshot_error("tmutil localsnapshot did not report a snapshot date".to_string())
+            })?;
+        let mut snapshot = Self {
+            date,
+            volume,
+            mount_dir: None,
+            scan_root: PathBuf::new(),
+        };
+
+        let mount_dir = tempfile::Builder::new()
+            .prefix(SNAPSHOT_MOUNT_PREFIX)
+            .tempdir()?;
+        let volume_str = snapshot.volume.to_string_lossy().into_owned();
+        let mount_str = mount_dir.path().to_string_lossy().into_owned();
+        run(
+            "mount_apfs",
+            &[
+                "-o",
+                "ro,nobrowse",
+                "-s",
+                &snapshot.name(),
+                &volume_str,
+                &mount_str,
+            ],
+        )?;
+        snapshot.scan_root = mount_dir.path().join(rel_path);
+        snapshot.mount_dir = Some(mount_dir);
+        Ok(snapshot)
+    }
+
+    /// The source directory inside the snapshot mount; walk this instead of the source path.
+    pub fn scan_root(&self) -> &Path {
+        &self.scan_root
+    }
+
+    pub fn name(&self) -> String {
+        format!("com.apple.TimeMachine.{}.local", self.date)
+    }
+}
+
+impl Drop for ApfsSnapshot {
+    fn drop(&mut self) {
+        if let Some(mount_dir) = self.mount_dir.take() {
+            let mount_str = mount_dir.path().to_string_lossy().into_owned();
+            let unmounted =
+                run("umount", &[&mount_str]).or_else(|_| run("umount", &["-f", &mount_str]));
+            if let Err(e) = unmounted {
+                // Never let `TempDir` recurse into a still-mounted volume.
+                let left_behind = mount_dir.keep();
+                warn!(
+                    event = "scan.apfs_snapshot.cleanup_failed",
+                    step = "unmount",
+                    mount_dir = %left_behind.display(),
+                    error = %e,
+                    "scan.apfs_snapshot.cleanup_failed"
+                );
+            }
+        }
+        if let Err(e) = run("tmutil", &["deletelocalsnapshots", &self.date]) {
+            warn!(
+                event = "scan.apfs_snapshot.cleanup_failed",
+                step = "delete",
+                snapshot = %self.name(),
+                volume = %self.volume.display(),
+                error = %e,
+                "scan.apfs_snapshot.cleanup_failed"
+            );
+        }
+    }
+}
+
+/// Snapshot for a target with `scan.use_apfs_snapshot`; logs and returns `None` (back up the live
+/// files) when the volume isn't APFS or the snapshot can't be taken or mounted.
+pub async fn prepare_apfs_snapshot(target_id: &str, source_path: &Path) -> Option<ApfsSnapshot> {
+    let path = source_path.to_path_buf();
+    let res = tokio::task::spawn_blocking(move || ApfsSnapshot::create(&path))
+        .await
+        .unwrap_or_else(|e| Err(snapshot_error(format!("snapshot task failed: {e}"))));
+    match res {
+        Ok(snapshot) => {
+            info!(
+                event = "scan.apfs_snapshot.mounted",
+                target_id,
+                snapshot = %snapshot.name(),
+                volume = %snapshot.volume.display(),
+                scan_root = %snapshot.scan_root().display(),
+                "scan.apfs_snapshot.mounted"
+            );
+            Some(snapshot)
+        }
+        Err(e) => {
+            warn!(
+                event = "scan.apfs_snapshot.unavailable",
+                target_id,
+                source_path = %source_path.display(),
+                error = %e,
+                "scan.apfs_snapshot.unavailable; backing up live files"
+            );
+            None
+        }
+    }
+}
+
+fn snapshot_error(message: String) -> Error {
+    Error::Io(std::io::Error::other(message))
+}
+
+fn run(program: &str, args: &[&str]) -> Result<String> {
+    let out = Command::new(program).args(args).output()?;
+    if !out.status.success() {
+        return Err(snapshot_error(format!(
+            "{program} failed ({}): {}",
+            out.status,
+            String::from_utf8_lossy(&out.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+/// `(mount point, file system type)` per line of macOS `mount` output, e.g.
+/// `/dev/disk3s5 on /System/Volumes/Data (apfs, local, journaled, nobrowse)`.
+fn parse_mount_table(text: &str) -> Vec<(PathBuf, String)> {
+    text.lines()
+        .filter_map(|line| {
+            let (_, rest) = line.split_once(" on ")?;
+            let (mount_point, opts) = rest.rsplit_once(" (")?;
+            let fs_type = opts.split([',', ')']).next()?.trim();
+            Some((PathBuf::from(mount_point), fs_type.to_string()))
+        })
+        .collect()
+}
+
+/// Mount point of the APFS volume holding `path` (the deepest enclosing mount) and `path`
+/// relative to it; `None` when that volume isn't APFS.
+fn volume_for_path(mounts: &[(PathBuf, String)], path: &Path) -> Option<(PathBuf, PathBuf)> {
+    let (mount_point, fs_type) = mounts
+        .iter()
+        .filter(|(m, _)| path.starts_with(m))
+        .max_by_key(|(m, _)| m.components().count())?;
+    if fs_type != "apfs" {
+        return None;
+    }
+    let rel_path = path.strip_prefix(mount_point).ok()?.to_path_buf();
+    Some((mount_point.clone(), rel_path))
+}
+
+/// `Created local snapshot with date: 2024-01-05-101112` -> `2024-01-05-101112`.
+fn parse_localsnapshot_date(text: &str) -> Option<String> {
+    text.lines()
+        .find_map(|line| line.split_once("date:"))
+        .map(|(_, date)| date.trim().to_string())
+        .filter(|date| !date.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOUNT_OUTPUT: &str = "\
+/dev/disk3s1s1 on / (apfs, sealed, local, read-only, journaled)
+devfs on /dev (devfs, local, nobrowse)
+/dev/disk3s5 on /System/Volumes/Data (apfs, local, journaled, nobrowse, protect)
+/dev/disk5s1 on /Volumes/USB Stick (msdos, local, nodev, nosuid, noowners)
+/dev/disk6s1 on /Volumes/Work (apfs, local, nodev, nosuid, journaled, noowners)
+";
+
+    #[test]
+    fn source_paths_resolve_to_their_apfs_volume() {
+        let mounts = parse_mount_table(MOUNT_OUTPUT);
+        assert_eq!(mounts.len(), 5);
+        assert_eq!(
+            mounts[3],
+            (PathBuf::from("/Volumes/USB Stick"), "msdos".to_string())
+        );
+
+        assert_eq!(
+            volume_for_path(&mounts, Path::new("/Volumes/Work/projects/a")),
+            Some((PathBuf::from("/Volumes/Work"), PathBuf::from("projects/a")))
+        );
+        assert_eq!(
+            volume_for_path(&mounts, Path::new("/System/Volumes/Data/Users/me")),
+            Some((
+                PathBuf::from("/System/Volumes/Data"),
+                PathBuf::from("Users/me")
+            ))
+        );
+        assert_eq!(
+            volume_for_path(&mounts, Path::new("/Users/me")),
+            Some((PathBuf::from("/"), PathBuf::from("Users/me")))
+        );
+        assert_eq!(
+            volume_for_path(&mounts, Path::new("/Volumes/USB Stick/photos")),
+            None
+        );
+    }
+
+    #[test]
+    fn localsnapshot_date_is_parsed() {
+        assert_eq!(
+            parse_localsnapshot_date("Created local snapshot with date: 2024-01-05-101112\n"),
+            Some("2024-01-05-101112".to_string())
+        );
+        assert_eq!(parse_localsnapshot_date("NOTE: nothing\n"), None);
+    }
+}
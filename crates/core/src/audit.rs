@@ -0,0 +1,330 @@
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::bootstrap::{BootstrapLatest, BootstrapTarget};
+use crate::device::DeviceIdentity;
+use crate::{Error, Result};
+
+pub const AUDIT_OP_SECRET_SET: &str = "secret.set";
+pub const AUDIT_OP_SECRET_DELETE: &str = "secret.delete";
+pub const AUDIT_OP_MASTER_KEY_EXPORT: &str = "master_key.export";
+pub const AUDIT_OP_TARGET_KEY_EXPORT: &str = "target_key.export";
+pub const AUDIT_OP_BUNDLE_APPLY: &str = "bundle.apply";
+pub const AUDIT_OP_BOOTSTRAP_OVERWRITE: &str = "bootstrap.overwrite";
+pub const AUDIT_OP_BOOTSTRAP_EDIT: &str = "bootstrap.edit";
+pub const AUDIT_OP_RESTORE_PASSPHRASE_SET: &str = "security.restore_passphrase_set";
+
+/// `prevHash` of the first entry.
+pub const AUDIT_GENESIS_HASH: &str =
+    "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Env var the macOS app sets when it runs the CLI, so its operations are attributed to `gui`.
+pub const AUDIT_ACTOR_ENV: &str = "TELEVYBACKUP_AUDIT_ACTOR";
+
+const AUDIT_TAIL_READ_BYTES: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditActor {
+    Cli,
+    Daemon,
+    Gui,
+}
+
+impl AuditActor {
+    /// Actor for CLI processes: `gui` when launched by the app, else `cli`.
+    pub fn cli_from_env() -> Self {
+        match std::env::var(AUDIT_ACTOR_ENV).as_deref() {
+            Ok("gui") => Self::Gui,
+            _ => Self::Cli,
+        }
+    }
+}
+
+/// One line of `audit.ndjson`.
+///
+/// `hash` covers every other field including `prevHash`, so editing, removing or reordering
+/// entries breaks the chain at that point. Dropping entries from the end is only detectable
+/// against an earlier copy of the last hash.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    pub seq: u64,
+    pub ts: String,
+    pub op: String,
+    pub actor: AuditActor,
+    #[serde(default)]
+    pub details: serde_json::Value,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AuditEntryBody<'a> {
+    seq: u64,
+    ts: &'a str,
+    op: &'a str,
+    actor: AuditActor,
+    details: &'a serde_json::Value,
+    prev_hash: &'a str,
+}
+
+impl AuditEntry {
+    fn compute_hash(&self) -> String {
+        let body = AuditEntryBody {
+            seq: self.seq,
+            ts: &self.ts,
+            op: &self.op,
+            actor: self.actor,
+            details: &self.details,
+            prev_hash: &self.prev_hash,
+        };
+        let bytes = serde_json::to_vec(&body).expect("audit entry body serializes");
+        blake3::hash(&bytes).to_hex().to_string()
+    }
+}
+
+/// `details` for [`AUDIT_OP_BOOTSTRAP_OVERWRITE`].
+pub fn bootstrap_overwrite_details(
+    target_id: &str,
+    previous: &BootstrapLatest,
+    snapshot_id: &str,
+    device: Option<&DeviceIdentity>,
+) -> serde_json::Value {
+    serde_json::json!({
+        "targetId": target_id,
+        "previousSnapshotId": previous.snapshot_id,
+        "previousDeviceId": previous.device_id,
+        "snapshotId": snapshot_id,
+        "deviceId": device.map(|d| d.device_id.as_str()),
+    })
+}
+
+/// `details` for [`AUDIT_OP_BOOTSTRAP_EDIT`]; `before`/`after` are the target entries.
+pub fn bootstrap_edit_details(
+    action: &str,
+    endpoint_id: &str,
+    target_id: &str,
+    before: Option<&BootstrapTarget>,
+    after: Option<&BootstrapTarget>,
+) -> serde_json::Value {
+    serde_json::json!({
+        "action": action,
+        "endpointId": endpoint_id,
+        "targetId": target_id,
+        "before": before,
+        "after": after,
+    })
+}
+
+pub fn audit_log_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("audit.ndjson")
+}
+
+/// Appends an entry chained to the current last one.
+///
+/// Writers serialize on an exclusive lock of the log file, so the CLI and the daemon can append
+/// concurrently. Secret values must never be passed in `details`.
+pub fn append_audit_entry(
+    data_dir: &Path,
+    actor: AuditActor,
+    op: &str,
+    details: serde_json::Value,
+) -> Result<AuditEntry> {
+    std::fs::create_dir_all(data_dir)?;
+    let path = audit_log_path(data_dir);
+    let mut file = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(&path)?;
+    file.lock()?;
+
+    let (seq, prev_hash) = match read_last_entry(&mut file)? {
+        Some(last) => (last.seq + 1, last.hash),
+        None => (1, AUDIT_GENESIS_HASH.to_string()),
+    };
+    let mut entry = AuditEntry {
+        seq,
+        ts: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        op: op.to_string(),
+        actor,
+        details,
+        prev_hash,
+        hash: String::new(),
+    };
+    entry.hash = entry.compute_hash();
+
+    let mut line = serde_json::to_vec(&entry).map_err(|e| Error::InvalidConfig {
+        message: format!("audit entry encode failed: {e}"),
+    })?;
+    line.push(b'\n');
+    file.write_all(&line)?;
+    file.sync_all()?;
+    Ok(entry)
+}
+
+fn read_last_entry(file: &mut std::fs::File) -> Result<Option<AuditEntry>> {
+    let len = file.metadata()?.len();
+    let start = len.saturating_sub(AUDIT_TAIL_READ_BYTES);
+    file.seek(SeekFrom::Start(start))?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+
+    let Some(line) = buf
+        .split(|b| *b == b'\n')
+        .rev()
+        .find(|l| !l.iter().all(u8::is_ascii_whitespace))
+    else {
+        return Ok(None);
+    };
+    // A torn/corrupt tail is reported by `verify_audit_log`; refuse to extend a chain we can't
+    // read rather than silently starting a new one.
+    let entry = serde_json::from_slice(line).map_err(|e| Error::Integrity {
+        message: format!("audit log tail is unreadable: {e}"),
+    })?;
+    Ok(Some(entry))
+}
+
+/// Reads all entries in file order. Unparseable lines are an error.
+pub fn read_audit_entries(data_dir: &Path) -> Result<Vec<AuditEntry>> {
+    let file = match std::fs::File::open(audit_log_path(data_dir)) {
+        Ok(f) => f,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut out = Vec::new();
+    for (idx, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(&line).map_err(|e| Error::Integrity {
+            message: format!("audit log line {} is unreadable: {e}", idx + 1),
+        })?;
+        out.push(entry);
+    }
+    Ok(out)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditBrokenLink {
+    /// 1-based line number in `audit.ndjson`.
+    pub line: u64,
+    pub seq: Option<u64>,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditVerifyReport {
+    /// Entries verified before the first broken link (all entries when intact).
+    pub entries_ok: u64,
+    pub last_hash: Option<String>,
+    pub first_broken: Option<AuditBrokenLink>,
+}
+
+/// Walks the hash chain and reports the first entry that does not link up.
+pub fn verify_audit_log(data_dir: &Path) -> Result<AuditVerifyReport> {
+    let mut report = AuditVerifyReport {
+        entries_ok: 0,
+        last_hash: None,
+        first_broken: None,
+    };
+    let file = match std::fs::File::open(audit_log_path(data_dir)) {
+        Ok(f) => f,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(report),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut expected_seq = 1u64;
+    let mut expected_prev = AUDIT_GENESIS_HASH.to_string();
+    for (idx, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let broken = |seq: Option<u64>, reason: String| AuditBrokenLink {
+            line: idx as u64 + 1,
+            seq,
+            reason,
+        };
+        let entry = match serde_json::from_str::<AuditEntry>(&line) {
+            Ok(entry) => entry,
+            Err(e) => {
+                report.first_broken = Some(broken(None, format!("unreadable entry: {e}")));
+                return Ok(report);
+            }
+        };
+        let reason = if entry.seq != expected_seq {
+            Some(format!("seq {} (expected {expected_seq})", entry.seq))
+        } else if entry.prev_hash != expected_prev {
+            Some("prevHash does not match the previous entry".to_string())
+        } else if entry.hash != entry.compute_hash() {
+            Some("hash does not match the entry contents".to_string())
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            report.first_broken = Some(broken(Some(entry.seq), reason));
+            return Ok(report);
+        }
+        report.entries_ok += 1;
+        expected_seq += 1;
+        expected_prev = entry.hash;
+        report.last_hash = Some(expected_prev.clone());
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chain_verifies_and_detects_edits_and_removals() {
+        let dir = tempfile::tempdir().unwrap();
+        let report = verify_audit_log(dir.path()).unwrap();
+        assert_eq!(report.entries_ok, 0);
+        assert_eq!(report.first_broken, None);
+
+        for i in 0..3 {
+            append_audit_entry(
+                dir.path(),
+                AuditActor::Cli,
+                AUDIT_OP_SECRET_SET,
+                serde_json::json!({ "key": format!("k{i}") }),
+            )
+            .unwrap();
+        }
+        let entries = read_audit_entries(dir.path()).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].prev_hash, AUDIT_GENESIS_HASH);
+        assert_eq!(entries[2].prev_hash, entries[1].hash);
+
+        let report = verify_audit_log(dir.path()).unwrap();
+        assert_eq!(report.entries_ok, 3);
+        assert_eq!(report.last_hash.as_deref(), Some(entries[2].hash.as_str()));
+        assert_eq!(report.first_broken, None);
+
+        let path = audit_log_path(dir.path());
+        let original = std::fs::read_to_string(&path).unwrap();
+        let lines = original.lines().collect::<Vec<_>>();
+
+        // Edited details.
+        std::fs::write(&path, original.replacen("\"k1\"", "\"kX\"", 1)).unwrap();
+        let broken = verify_audit_log(dir.path()).unwrap().first_broken.unwrap();
+        assert_eq!((broken.line, broken.seq), (2, Some(2)));
+
+        // Removed middle entry.
+        std::fs::write(&path, format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+        let report = verify_audit_log(dir.path()).unwrap();
+        assert_eq!(report.entries_ok, 1);
+        assert_eq!(report.first_broken.unwrap().seq, Some(3));
+    }
+}
@@ -1,10 +1,10 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::ffi::OsStr;
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
 use std::time::{Duration, Instant};
 
 use fastcdc::ronomon::FastCDC;
@@ -14,25 +14,46 @@ use ignore::{Error as IgnoreError, Walk, WalkBuilder};
 use serde::{Deserialize, Serialize};
 use sqlx::pool::PoolConnection;
 use sqlx::{Connection, QueryBuilder, Row, Sqlite};
+use tempfile::TempPath;
 use tracing::{debug, error, info, warn};
 
-use crate::config::TelegramRateLimit;
+use crate::case_fold::case_collisions;
+use crate::chunk_refs;
+use crate::config::{Retry, TargetFilter, TelegramRateLimit};
 use crate::crypto::FRAMING_OVERHEAD_BYTES;
-use crate::crypto::encrypt_framed;
+use crate::crypto::{
+    ChunkHashAlg, DataKey, FramedEncryptReader, KeyDerivation, decrypt_framed, encrypt_framed,
+};
 use crate::dedupe_catalog::{
     DEDUPE_CATALOG_VERSION, DedupeCatalogBase, DedupeCatalogDelta, DedupeCatalogV1,
     dedupe_base_id_for_storage, dedupe_delta_id_from_scope, load_remote_dedupe_catalog,
     save_remote_dedupe_catalog,
 };
-use crate::index_db::{open_existing_index_db, open_index_db};
-use crate::index_manifest::{IndexManifest, IndexManifestPart, index_part_aad};
+use crate::default_excludes::DefaultExcludes;
+use crate::device::DeviceIdentity;
+use crate::error::TelegramErrorKind;
+use crate::file_filter::{FileFilterChain, FileFilterStats};
+use crate::full_disk_access;
+use crate::index_db::{files_optional_columns, open_existing_index_db, open_index_db};
+use crate::index_delta::write_filemap_delta_db;
+use crate::index_manifest::{
+    INDEX_MANIFEST_VERSION_DELTA, INDEX_MANIFEST_VERSION_FULL, IndexManifest, IndexManifestKind,
+    IndexManifestParent, IndexManifestPart, index_part_aad,
+};
+use crate::mounts::MountBoundary;
+use crate::ownership::{FileOwner, OwnerNames, file_owner_from_row};
 use crate::pack::{
     PACK_MAX_BYTES, PACK_MAX_ENTRIES_PER_PACK, PACK_TARGET_BYTES, PACK_TARGET_JITTER_BYTES,
     PackBlob, PackBuilder,
 };
-use crate::progress::{ProgressSink, TaskProgress};
+use crate::progress::{Phase, PhaseTimings, ProgressSink, TaskProgress};
+use crate::quiet_hours::UploadThrottle;
+use crate::retry::{RetryBudget, RetryStats};
+use crate::sqlite_consistent::{self, SqliteConsistent, SqliteConsistentOptions};
 use crate::storage::MTPROTO_ENGINEERED_UPLOAD_MAX_BYTES;
-use crate::storage::{Storage, encode_tgfile_object_id, encode_tgpack_object_id};
+use crate::storage::{
+    ObjectKind, Storage, UploadMetadata, encode_tgfile_object_id, encode_tgpack_object_id,
+};
 use crate::{Error, Result};
 use tokio::sync::{Mutex, Notify, OwnedSemaphorePermit, Semaphore, mpsc};
 use tokio::time::sleep;
@@ -44,6 +65,13 @@ const SINGLE_BLOB_PACK_OVERHEAD_BUDGET_BYTES: usize = 4096;
 const RONOMON_READ_CHUNK_BYTES: usize = 1024 * 1024;
 const PACK_MAX_STAGING_AGE_SECS: u64 = 3;
 const BASE_FILE_CHUNK_COPY_BATCH_SIZE: usize = 128;
+/// Chunks a scan worker may read ahead of the scan loop, per file.
+const SCAN_FILE_CHUNK_QUEUE: usize = 2;
+/// Default `performance.worker_threads` never exceeds this many threads.
+const SCAN_AUTO_MAX_WORKER_THREADS: usize = 16;
+/// New chunks recorded in the chunk index per transaction (or fewer, see the byte cap).
+const SCAN_NEW_CHUNK_BATCH: usize = 64;
+const SCAN_NEW_CHUNK_BATCH_BYTES: usize = 32 * 1024 * 1024;
 const ADAPTIVE_MIN_CONCURRENCY: usize = 1;
 const ADAPTIVE_MAX_CONCURRENCY: usize = 8;
 const ADAPTIVE_MAX_DELAY_MS: u64 = 500;
@@ -57,10 +85,10 @@ const ADAPTIVE_UPSHIFT_DELAY_STEP_MS: i64 = -50;
 const ADAPTIVE_DOWNSHIFT_DELAY_STEP_MS: i64 = 50;
 const DEDUPE_MAX_DELTAS_BEFORE_COMPACT: usize = 128;
 const SQLITE_BUSY_RETRY_DELAYS_MS: [u64; 5] = [100, 250, 500, 1000, 2000];
-const UPLOAD_OBJECT_MAX_ATTEMPTS: usize = 3;
-const UPLOAD_OBJECT_RETRY_BASE_MS: u64 = 1_000;
-const UPLOAD_OBJECT_RETRY_MAX_MS: u64 = 15_000;
 const CHUNK_OBJECT_CHECKPOINT_BATCH_SIZE: usize = 256;
+/// Uploaded chunks are also checkpointed this often, so a run stopped partway through a large
+/// file (cancel, sleep, kill) leaves the chunks it already sent for the next run to dedupe.
+const CHUNK_OBJECT_CHECKPOINT_INTERVAL: Duration = Duration::from_secs(5);
 const INDEX_COMPACT_MIN_PAGE_COUNT: i64 = 131_072; // ~= 512 MiB @ 4 KiB pages
 const INDEX_COMPACT_MIN_FREE_PAGES: i64 = 16_384; // ~= 64 MiB @ 4 KiB pages
 const INDEX_COMPACT_MIN_FREE_RATIO: f64 = 0.20;
@@ -183,6 +211,102 @@ fn file_chunker(
     }
 }
 
+/// `performance.worker_threads`, with 0 meaning one thread per physical core (capped).
+fn scan_worker_threads(configured: usize) -> usize {
+    if configured > 0 {
+        return configured;
+    }
+    num_cpus::get_physical().clamp(1, SCAN_AUTO_MAX_WORKER_THREADS)
+}
+
+/// What a scan worker reports for its source file, in file order.
+enum ScanChunk {
+    Chunk {
+        hash: String,
+        data: ChunkData,
+    },
+    /// The file could not be opened; nothing follows.
+    OpenError(std::io::Error),
+    /// Reading the file failed part-way; nothing follows.
+    ReadError(std::io::Error),
+    /// The chunker failed for a reason other than I/O; nothing follows.
+    ChunkingFailed,
+}
+
+/// A source file handed to a scan worker.
+///
+/// The scan loop keeps up to `worker_threads` of these in flight and consumes them in walk order,
+/// so file rows and uploads do not depend on the worker count. Each file's chunk queue is bounded;
+/// dropping the file (error, cancellation) makes its worker stop at the next chunk. A file backed
+/// up from a consistent copy is read from `copy`, which its worker removes when done.
+struct ScanFile {
+    file_id: String,
+    path: PathBuf,
+    chunks: mpsc::Receiver<ScanChunk>,
+    worker: tokio::task::JoinHandle<()>,
+}
+
+impl ScanFile {
+    fn spawn(
+        file_id: String,
+        path: PathBuf,
+        copy: Option<TempPath>,
+        chunking: &ChunkingConfig,
+        data_key: &DataKey,
+        cancel: Option<&CancellationToken>,
+    ) -> Self {
+        let (tx, chunks) = mpsc::channel(SCAN_FILE_CHUNK_QUEUE);
+        let chunking = chunking.clone();
+        let data_key = data_key.clone();
+        let cancel = cancel.cloned();
+        let worker_path = path.clone();
+        let worker = tokio::task::spawn_blocking(move || {
+            let file = match File::open(copy.as_deref().unwrap_or(&worker_path)) {
+                Ok(f) => f,
+                Err(e) => {
+                    let _ = tx.blocking_send(ScanChunk::OpenError(e));
+                    return;
+                }
+            };
+            for chunk in file_chunker(file, &chunking) {
+                if cancel.as_ref().is_some_and(CancellationToken::is_cancelled) {
+                    return;
+                }
+                let (msg, last) = match chunk {
+                    Ok(data) => {
+                        let hash = data_key.chunk_hash(&data.data);
+                        (ScanChunk::Chunk { hash, data }, false)
+                    }
+                    Err(CdcError::IoError(e)) => (ScanChunk::ReadError(e), true),
+                    Err(_) => (ScanChunk::ChunkingFailed, true),
+                };
+                if tx.blocking_send(msg).is_err() || last {
+                    return;
+                }
+            }
+        });
+        Self {
+            file_id,
+            path,
+            chunks,
+            worker,
+        }
+    }
+
+    /// The next chunk; `None` once the worker is done with the file.
+    async fn next(&mut self) -> Result<Option<ScanChunk>> {
+        if let Some(chunk) = self.chunks.recv().await {
+            return Ok(Some(chunk));
+        }
+        (&mut self.worker).await.map_err(scan_worker_failed)?;
+        Ok(None)
+    }
+}
+
+fn scan_worker_failed(e: tokio::task::JoinError) -> Error {
+    Error::Io(std::io::Error::other(format!("scan worker failed: {e}")))
+}
+
 struct RonomonStreamCDC<R: Read> {
     source: R,
     buffer: Vec<u8>,
@@ -330,9 +454,30 @@ pub struct BackupConfig {
     pub chunking: ChunkingConfig,
     pub rate_limit: TelegramRateLimit,
     pub master_key: [u8; 32],
+    /// Key for the snapshot's chunks, packs and file map index; `None` uses the master key.
+    ///
+    /// A derived key (`DataKey::for_target`) gives the snapshot its own chunk ids, so it shares
+    /// no chunks and no base snapshot with snapshots under another key, and its file map is
+    /// always uploaded in full with its `chunk_objects` rows, so the target key alone restores it.
+    pub data_key: Option<DataKey>,
     pub snapshot_id: Option<String>,
     pub keep_last_snapshots: u32,
     pub remote_dedupe: RemoteDedupeMode,
+    /// Paths changed since the previous snapshot of `source_path` (e.g. from a file system
+    /// watcher); relative entries are resolved against `source_path`.
+    ///
+    /// When set and a base snapshot exists, files outside these paths reuse the base snapshot's
+    /// metadata instead of being stat'ed. The tree is still walked, so deletions and
+    /// `.televyignore` rules resolve exactly as in a full scan. `None` means a full scan.
+    pub hint_changed_paths: Option<Vec<PathBuf>>,
+    /// Machine creating the snapshot; recorded on the snapshot row and the index manifest.
+    pub device: Option<DeviceIdentity>,
+    /// Upload a full filemap index every this many snapshots of `source_path`, and in between
+    /// only its delta against the base snapshot's index. 0 or 1 uploads a full index every time.
+    pub index_full_every: u32,
+    /// Record this as the snapshot's `created_at` (index format, UTC) instead of now; used when
+    /// importing history from another tool.
+    pub created_at: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -360,6 +505,9 @@ pub struct BackupResult {
     pub snapshot_id: String,
     pub files_total: u64,
     pub files_indexed: u64,
+    /// Directory rows among `files_indexed`.
+    #[serde(default)]
+    pub dirs_indexed: u64,
     pub chunks_total: u64,
     pub chunks_uploaded: u64,
     pub data_objects_uploaded: u64,
@@ -370,6 +518,100 @@ pub struct BackupResult {
     pub index_parts: u64,
     pub ignore_rule_files: u64,
     pub ignore_invalid_rules: u64,
+    /// Source files left out of the snapshot because they could not be read.
+    #[serde(default)]
+    pub files_skipped_errors: u64,
+    /// The first [`SKIPPED_FILE_EXAMPLES_MAX`] of those files.
+    #[serde(default)]
+    pub skipped_files: Vec<SkippedFile>,
+    /// Entries whose path equals another's up to letter case; a case-insensitive volume (the
+    /// macOS default) can't restore them side by side.
+    #[serde(default)]
+    pub case_collisions: u64,
+    /// Entries whose snapshot path is longer than `scan.warn_path_bytes`; restoring them may need
+    /// `RestoreOptions::shorten_long_paths`.
+    #[serde(default)]
+    pub long_paths: u64,
+    /// Directories not entered because another file system is mounted on them
+    /// (`scan.one_file_system`).
+    #[serde(default)]
+    pub mount_points_skipped: u64,
+    /// Entries left out by `scan.default_excludes`; a directory counts once, without its contents.
+    #[serde(default)]
+    pub files_skipped_default_excludes: u64,
+    /// One entry per `targets[].filters` entry, in order.
+    #[serde(default)]
+    pub file_filters: Vec<FileFilterStats>,
+    #[serde(default)]
+    pub phase_timings: PhaseTimings,
+    #[serde(default)]
+    pub retry: RetryStats,
+    /// Uploaded objects compared with what was sent before being indexed.
+    #[serde(default)]
+    pub upload_checks: u64,
+    /// Objects uploaded a second time because the first copy did not match.
+    #[serde(default)]
+    pub upload_reuploads: u64,
+    /// SQLite databases chunked from a consistent copy (`targets[].sqlite_consistent`).
+    #[serde(default)]
+    pub sqlite_consistent_files: u64,
+    /// Matching databases read live instead because the copy was not possible; each adds
+    /// [`sqlite_consistent::WARNING`] to `warnings`.
+    #[serde(default)]
+    pub sqlite_consistent_fallbacks: u64,
+    /// Problems with the run as a whole, e.g. [`crate::full_disk_access::WARNING`].
+    #[serde(default)]
+    pub warnings: Vec<String>,
+}
+
+/// Number of skipped files kept in [`BackupResult::skipped_files`] and logged individually.
+pub const SKIPPED_FILE_EXAMPLES_MAX: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    PermissionDenied,
+    /// Deleted between the directory walk and reading it.
+    Vanished,
+    IoError,
+}
+
+impl SkipReason {
+    fn from_io(err: &std::io::Error) -> Self {
+        match err.kind() {
+            std::io::ErrorKind::PermissionDenied => Self::PermissionDenied,
+            std::io::ErrorKind::NotFound => Self::Vanished,
+            _ => Self::IoError,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::PermissionDenied => "permission_denied",
+            Self::Vanished => "vanished",
+            Self::IoError => "io_error",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkippedFile {
+    /// Relative to the source path.
+    pub path: String,
+    pub reason: SkipReason,
+}
+
+impl BackupResult {
+    /// Wall time during which uploads were in flight: the `scan_upload` overlap plus the
+    /// `upload` drain after the scan.
+    pub fn upload_duration(&self) -> Option<std::time::Duration> {
+        let overlap = self.phase_timings.get(&Phase::ScanUpload);
+        let drain = self.phase_timings.get(&Phase::Upload);
+        match (overlap, drain) {
+            (None, None) => None,
+            (a, b) => Some(a.unwrap_or_default() + b.unwrap_or_default()),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
@@ -387,6 +629,37 @@ pub struct BackupOptions<'a> {
     pub cancel: Option<&'a CancellationToken>,
     pub progress: Option<&'a dyn ProgressSink>,
     pub source_quick_stats: Option<SourceQuickStats>,
+    /// Fail on the first unreadable source file instead of skipping it (`scan.strict`).
+    pub strict: bool,
+    /// Walk this directory instead of `source_path` (e.g. an APFS snapshot mount); file paths,
+    /// ignore rules and the snapshot record stay relative to the logical `source_path`.
+    pub scan_root: Option<&'a Path>,
+    /// Retries of transient upload failures (`retry.*`).
+    pub retry: Retry,
+    /// Threads reading, chunking and hashing source files (`performance.worker_threads`); 0 picks
+    /// one per physical core.
+    pub worker_threads: usize,
+    /// Don't descend into directories on another file system than the source root
+    /// (`scan.one_file_system`).
+    pub one_file_system: bool,
+    /// Leave out [`crate::default_excludes::PATTERNS`] (`scan.default_excludes`).
+    pub default_excludes: bool,
+    /// Read every uploaded object back before indexing it (`upload.verify_after_upload`). Without
+    /// it only the stored size is compared, where the provider reports one.
+    pub verify_after_upload: bool,
+    /// Per-file filters applied to regular files during the scan (`targets[].filters`).
+    pub filters: &'a [TargetFilter],
+    /// Count and warn about snapshot paths longer than this many bytes (`scan.warn_path_bytes`);
+    /// 0 disables the check.
+    pub warn_path_bytes: u64,
+    /// Fail instead of keeping a snapshot when more than this percentage of the scanned entries
+    /// could not be read (`scan.max_unreadable_percent`); 0 disables the check.
+    pub max_unreadable_percent: u32,
+    /// Paces chunk and pack uploads; its limit may change while the run goes (quiet hours).
+    pub upload_throttle: Option<&'a UploadThrottle>,
+    /// SQLite databases to copy consistently before chunking them (`targets[].sqlite_consistent`,
+    /// `scan.sqlite_consistent_*`).
+    pub sqlite_consistent: SqliteConsistentOptions<'a>,
 }
 
 #[derive(Debug, Clone)]
@@ -424,7 +697,13 @@ fn compute_upload_limits(rate_limit: &TelegramRateLimit) -> Result<UploadLimits>
     })
 }
 
-fn build_source_walk(source_path: &Path) -> Walk {
+/// With a `boundary`, directories on another file system are skipped and recorded there; with
+/// `excludes`, so are `scan.default_excludes` matches.
+fn build_source_walk(
+    source_path: &Path,
+    boundary: Option<&Arc<MountBoundary>>,
+    excludes: Option<&Arc<DefaultExcludes>>,
+) -> Walk {
     let mut builder = WalkBuilder::new(source_path);
     builder
         .follow_links(false)
@@ -434,10 +713,66 @@ fn build_source_walk(source_path: &Path) -> Walk {
         .git_ignore(false)
         .git_global(false)
         .git_exclude(false)
-        .add_custom_ignore_filename(TELEVYIGNORE_FILE_NAME);
+        .add_custom_ignore_filename(TELEVYIGNORE_FILE_NAME)
+        // Sorted so file rows (and the uploads they trigger) follow a stable path order.
+        .sort_by_file_name(|a, b| a.cmp(b));
+    if boundary.is_some() || excludes.is_some() {
+        let boundary = boundary.cloned();
+        let excludes = excludes.cloned();
+        builder.filter_entry(move |entry| {
+            excludes.as_ref().is_none_or(|e| e.allows(entry))
+                && boundary.as_ref().is_none_or(|b| b.allows(entry))
+        });
+    }
     builder.build()
 }
 
+/// `(kind, size, mtime_ms, mode, btime_ms)` as stored in `files`; `None` for unsupported entry
+/// types. Directories keep their mtime and mode (restored after their contents); symlinks store
+/// zeros. Only regular files record a birth time, and only where the platform reports one.
+fn scan_entry_stat(
+    metadata: &std::fs::Metadata,
+) -> Option<(&'static str, i64, i64, i64, Option<i64>)> {
+    let kind = if metadata.is_dir() {
+        "dir"
+    } else if metadata.is_file() {
+        "file"
+    } else if metadata.is_symlink() {
+        "symlink"
+    } else {
+        return None;
+    };
+
+    if kind == "symlink" {
+        return Some((kind, 0, 0, 0, None));
+    }
+    let size = if kind == "file" {
+        metadata.len() as i64
+    } else {
+        0
+    };
+    let mtime_ms = metadata.modified().ok().and_then(unix_ms).unwrap_or(0);
+    let btime_ms = if kind == "file" {
+        metadata.created().ok().and_then(unix_ms)
+    } else {
+        None
+    };
+    #[cfg(unix)]
+    let mode = {
+        use std::os::unix::fs::MetadataExt;
+        metadata.mode() as i64
+    };
+    #[cfg(not(unix))]
+    let mode = 0i64;
+    Some((kind, size, mtime_ms, mode, btime_ms))
+}
+
+fn unix_ms(t: std::time::SystemTime) -> Option<i64> {
+    t.duration_since(std::time::UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_millis() as i64)
+}
+
 fn ignore_error_is_rule_parse_only(err: &IgnoreError) -> bool {
     match err {
         IgnoreError::Partial(errs) => {
@@ -521,74 +856,200 @@ fn map_ignore_error(err: IgnoreError, source_path: &Path) -> Error {
     }
 }
 
-pub fn compute_source_quick_stats(
+/// `logical_root` is what `source_path` stands for (they differ when scanning an APFS snapshot).
+fn record_skipped_file(
+    result: &mut BackupResult,
     source_path: &Path,
-    cancel: Option<&CancellationToken>,
-) -> Result<SourceQuickStats> {
-    let mut files_total = 0u64;
-    let mut bytes_total = 0u64;
-    let mut warned_ignore_errors = HashSet::<String>::new();
+    logical_root: &Path,
+    path: &Path,
+    reason: SkipReason,
+    error: &dyn std::fmt::Display,
+) {
+    let rel_path = path.strip_prefix(source_path).unwrap_or(path);
+    if reason == SkipReason::PermissionDenied
+        && !result
+            .warnings
+            .iter()
+            .any(|w| w == full_disk_access::WARNING)
+        && full_disk_access::is_protected_here(&logical_root.join(rel_path))
+    {
+        warn!(
+            event = "scan.full_disk_access_missing",
+            path = %rel_path.display(),
+            settings_pane = full_disk_access::SETTINGS_PANE,
+            "scan.full_disk_access_missing"
+        );
+        result.warnings.push(full_disk_access::WARNING.to_string());
+    }
+    let rel_path = rel_path.to_string_lossy().into_owned();
+    result.files_skipped_errors += 1;
+    if result.skipped_files.len() < SKIPPED_FILE_EXAMPLES_MAX {
+        warn!(
+            event = "scan.file_skipped",
+            path = %rel_path,
+            reason = reason.as_str(),
+            error = %error,
+            "scan.file_skipped"
+        );
+        result.skipped_files.push(SkippedFile {
+            path: rel_path,
+            reason,
+        });
+    }
+}
 
-    for entry in build_source_walk(source_path) {
-        if let Some(cancel) = cancel
-            && cancel.is_cancelled()
-        {
-            return Err(Error::Cancelled);
+/// Fails the run when more than `max_percent` of the entries the scan met were skipped as
+/// unreadable: a snapshot missing most of the source is worse than an error.
+fn check_unreadable_share(result: &BackupResult, max_percent: u32) -> Result<()> {
+    let unreadable = result.files_skipped_errors;
+    let seen = result.files_indexed.saturating_add(unreadable);
+    if max_percent == 0 || unreadable == 0 {
+        return Ok(());
+    }
+    let percent = unreadable.saturating_mul(100) / seen;
+    if percent <= u64::from(max_percent) {
+        return Ok(());
+    }
+    let full_disk_access_missing = result
+        .warnings
+        .iter()
+        .any(|w| w == full_disk_access::WARNING);
+    let mut message = format!(
+        "{percent}% of the source could not be read ({unreadable} of {seen} entries; scan.max_unreadable_percent = {max_percent})"
+    );
+    if full_disk_access_missing {
+        message.push_str(&format!(
+            "; macOS blocked access to protected folders: grant TelevyBackup and televybackupd Full Disk Access in {}",
+            full_disk_access::SETTINGS_PANE
+        ));
+    }
+    Err(Error::SourceUnreadable {
+        entries_unreadable: unreadable,
+        percent_unreadable: percent,
+        full_disk_access_missing,
+        message,
+    })
+}
+
+/// Unreadable entries below the source root that a non-strict scan skips.
+fn skippable_walk_error<'a>(
+    err: &'a IgnoreError,
+    source_path: &Path,
+) -> Option<(&'a Path, &'a std::io::Error)> {
+    let io = err.io_error()?;
+    let path = ignore_error_path(err)?;
+    (err.depth() != Some(0) && path != source_path).then_some((path, io))
+}
+
+/// Regular files below a source root, walked with the backup's ignore rules. Invalid ignore
+/// rules are warned about once; entries that vanish mid-walk are passed over.
+struct SourceFiles<'a> {
+    walk: Walk,
+    source_path: &'a Path,
+    cancel: Option<&'a CancellationToken>,
+    warned_ignore_errors: HashSet<String>,
+}
+
+impl<'a> SourceFiles<'a> {
+    fn new(
+        source_path: &'a Path,
+        cancel: Option<&'a CancellationToken>,
+        one_file_system: bool,
+        default_excludes: bool,
+    ) -> Self {
+        let boundary = one_file_system
+            .then(|| MountBoundary::for_root(source_path))
+            .flatten()
+            .map(Arc::new);
+        let excludes = default_excludes.then(|| Arc::new(DefaultExcludes::for_root(source_path)));
+        Self {
+            walk: build_source_walk(source_path, boundary.as_ref(), excludes.as_ref()),
+            source_path,
+            cancel,
+            warned_ignore_errors: HashSet::new(),
         }
+    }
 
-        let entry = match entry {
-            Ok(v) => v,
-            Err(e) => {
-                if ignore_error_is_rule_parse_only(&e) {
+    fn next_file(&mut self) -> Result<Option<(PathBuf, std::fs::Metadata)>> {
+        let source_path = self.source_path;
+        for entry in self.walk.by_ref() {
+            if let Some(cancel) = self.cancel
+                && cancel.is_cancelled()
+            {
+                return Err(Error::Cancelled);
+            }
+
+            let entry = match entry {
+                Ok(v) => v,
+                Err(e) => {
+                    if ignore_error_is_rule_parse_only(&e) {
+                        warn_invalid_televyignore_rule_once(
+                            &mut self.warned_ignore_errors,
+                            &e,
+                            source_path,
+                            "prepare",
+                        );
+                        continue;
+                    }
+                    if ignore_error_is_non_root_not_found(&e, source_path) {
+                        continue;
+                    }
+                    return Err(map_ignore_error(e, source_path));
+                }
+            };
+
+            if let Some(err) = entry.error() {
+                if ignore_error_is_rule_parse_only(err) {
                     warn_invalid_televyignore_rule_once(
-                        &mut warned_ignore_errors,
-                        &e,
+                        &mut self.warned_ignore_errors,
+                        err,
                         source_path,
                         "prepare",
                     );
+                } else if ignore_error_is_not_found(err) && entry.path() != source_path {
                     continue;
+                } else {
+                    return Err(map_ignore_error(err.clone(), source_path));
                 }
-                if ignore_error_is_non_root_not_found(&e, source_path) {
-                    continue;
-                }
-                return Err(map_ignore_error(e, source_path));
             }
-        };
 
-        if let Some(err) = entry.error() {
-            if ignore_error_is_rule_parse_only(err) {
-                warn_invalid_televyignore_rule_once(
-                    &mut warned_ignore_errors,
-                    err,
-                    source_path,
-                    "prepare",
-                );
-            } else if ignore_error_is_not_found(err) && entry.path() != source_path {
+            let path = entry.path();
+            // A directory root is not an entry itself; a single-file source is the only one.
+            if path == source_path && !entry.file_type().is_some_and(|t| t.is_file()) {
                 continue;
-            } else {
-                return Err(map_ignore_error(err.clone(), source_path));
             }
-        }
-
-        let path = entry.path();
-        if path == source_path {
-            continue;
-        }
 
-        let metadata = match entry.metadata() {
-            Ok(v) => v,
-            Err(e) => {
-                if ignore_error_is_not_found(&e) {
-                    continue;
+            let metadata = match entry.metadata() {
+                Ok(v) => v,
+                Err(e) => {
+                    if ignore_error_is_not_found(&e) {
+                        continue;
+                    }
+                    return Err(map_ignore_error(e, source_path));
                 }
-                return Err(map_ignore_error(e, source_path));
-            }
-        };
+            };
 
-        if !metadata.is_file() {
-            continue;
+            if metadata.is_file() {
+                return Ok(Some((entry.into_path(), metadata)));
+            }
         }
+        Ok(None)
+    }
+}
 
+/// `one_file_system` and `default_excludes` leave out directories on other file systems and
+/// system-generated noise, like the backup itself (`scan.one_file_system`,
+/// `scan.default_excludes`).
+pub fn compute_source_quick_stats(
+    source_path: &Path,
+    cancel: Option<&CancellationToken>,
+    one_file_system: bool,
+    default_excludes: bool,
+) -> Result<SourceQuickStats> {
+    let mut files_total = 0u64;
+    let mut bytes_total = 0u64;
+    let mut files = SourceFiles::new(source_path, cancel, one_file_system, default_excludes);
+    while let Some((_, metadata)) = files.next_file()? {
         files_total = files_total.saturating_add(1);
         bytes_total = bytes_total.saturating_add(metadata.len());
     }
@@ -599,6 +1060,45 @@ pub fn compute_source_quick_stats(
     })
 }
 
+/// What a backup of a source would pick up, without reading file contents beyond what the
+/// filters need (`backup run --dry-run`).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SourcePreview {
+    /// Regular files the backup would include.
+    pub files_total: u64,
+    pub bytes_total: u64,
+    pub files_excluded: u64,
+    pub bytes_excluded: u64,
+    /// Same shape as [`BackupResult::file_filters`].
+    pub file_filters: Vec<FileFilterStats>,
+}
+
+/// Walks `source_path` like [`run_backup_with`] does, applying `filters` the same way, so the
+/// counts match what a real run would include.
+pub async fn preview_source(
+    source_path: &Path,
+    filters: &[TargetFilter],
+    cancel: Option<&CancellationToken>,
+    one_file_system: bool,
+    default_excludes: bool,
+) -> Result<SourcePreview> {
+    let mut chain = FileFilterChain::new(filters)?;
+    let mut preview = SourcePreview::default();
+    let mut files = SourceFiles::new(source_path, cancel, one_file_system, default_excludes);
+    while let Some((path, metadata)) = files.next_file()? {
+        let size = metadata.len();
+        if chain.excludes(&path, size).await? {
+            preview.files_excluded = preview.files_excluded.saturating_add(1);
+            preview.bytes_excluded = preview.bytes_excluded.saturating_add(size);
+        } else {
+            preview.files_total = preview.files_total.saturating_add(1);
+            preview.bytes_total = preview.bytes_total.saturating_add(size);
+        }
+    }
+    preview.file_filters = chain.into_stats();
+    Ok(preview)
+}
+
 #[derive(Debug)]
 struct UploadRateLimiter {
     min_delay_floor_ms: u64,
@@ -846,33 +1346,13 @@ impl AdaptiveUploadController {
 }
 
 fn error_has_flood_wait(error: &Error) -> bool {
-    match error {
-        Error::Telegram { message } => {
-            let msg = message.to_ascii_uppercase();
-            msg.contains("FLOOD_WAIT")
-                || msg.contains("FLOOD WAIT")
-                || msg.contains("FLOOD_PREMIUM_WAIT")
-                || msg.contains("FLOOD PREMIUM WAIT")
+    matches!(
+        error,
+        Error::Telegram {
+            kind: Some(TelegramErrorKind::FloodWait),
+            ..
         }
-        _ => false,
-    }
-}
-
-fn is_retryable_upload_error(error: &Error) -> bool {
-    if error_has_flood_wait(error) {
-        return true;
-    }
-    match error {
-        Error::Telegram { message } => crate::error::is_transient_telegram_message(message),
-        _ => false,
-    }
-}
-
-fn upload_object_retry_backoff(attempt: usize) -> Duration {
-    let shift = attempt.saturating_sub(1).min(16) as u32;
-    let mul = 1u64.checked_shl(shift).unwrap_or(u64::MAX);
-    let ms = UPLOAD_OBJECT_RETRY_BASE_MS.saturating_mul(mul);
-    Duration::from_millis(ms.min(UPLOAD_OBJECT_RETRY_MAX_MS))
+    )
 }
 
 fn saturating_sub_usize(atom: &AtomicUsize, delta: usize) {
@@ -942,9 +1422,11 @@ struct PackEntryRef {
 
 #[derive(Debug)]
 enum UploadJob {
+    /// A chunk too large for a pack. Kept as plaintext and encrypted frame by frame while it is
+    /// streamed to storage, so only one copy of the chunk is ever held.
     Direct {
         chunk_hash: String,
-        blob: Vec<u8>,
+        plain: Vec<u8>,
         source_bytes: u64,
         _bytes_permit: OwnedSemaphorePermit,
     },
@@ -959,7 +1441,7 @@ enum UploadJob {
 impl UploadJob {
     fn payload_len(&self) -> usize {
         match self {
-            UploadJob::Direct { blob, .. } => blob.len(),
+            UploadJob::Direct { plain, .. } => framed_len(plain.len()),
             UploadJob::Pack { pack_bytes, .. } => pack_bytes.len(),
         }
     }
@@ -996,19 +1478,67 @@ struct FileChunkRow {
     len: i64,
 }
 
+/// A new chunk awaiting upload (plaintext; encrypted when packed or streamed).
 #[derive(Debug, Clone)]
 struct SourceBlob {
     chunk_hash: String,
-    blob: Vec<u8>,
+    plain: Vec<u8>,
+    /// The framed ciphertext once sealed by [`seal_blobs`] (`plain` is emptied then).
+    sealed: Option<Vec<u8>>,
     source_bytes: u64,
 }
 
+impl SourceBlob {
+    fn framed_len(&self) -> usize {
+        self.sealed
+            .as_ref()
+            .map_or_else(|| framed_len(self.plain.len()), Vec::len)
+    }
+}
+
+fn framed_len(plain_len: usize) -> usize {
+    plain_len.saturating_add(FRAMING_OVERHEAD_BYTES)
+}
+
+/// Changed-path hint for the scan phase (see `BackupConfig::hint_changed_paths`).
+#[derive(Debug, Clone)]
+struct ChangedPathHint {
+    roots: Vec<PathBuf>,
+}
+
+impl ChangedPathHint {
+    /// `paths` are absolute under `source_path` or relative to it; roots are re-based onto
+    /// `scan_root`, the directory the scan actually walks.
+    fn new(source_path: &Path, scan_root: &Path, paths: &[PathBuf]) -> Self {
+        let roots = paths
+            .iter()
+            .map(|p| match p.strip_prefix(source_path) {
+                Ok(rel) => scan_root.join(rel),
+                Err(_) if p.is_absolute() => p.clone(),
+                Err(_) => scan_root.join(p),
+            })
+            .collect();
+        Self { roots }
+    }
+
+    /// A changed directory covers its whole subtree (e.g. a renamed or newly created dir).
+    fn covers(&self, path: &Path) -> bool {
+        self.roots.iter().any(|root| path.starts_with(root))
+    }
+}
+
 #[derive(Debug, Clone)]
 struct BaseFileSnapshotRow {
     file_id: String,
     size: i64,
     mtime_ms: i64,
     mode: i64,
+    /// `None` also when the base file map predates `files.btime_ms`.
+    btime_ms: Option<i64>,
+    /// `None` also when the base file map predates `files.uid`.
+    owner: Option<FileOwner>,
+    /// `None` also when the base file map predates `files.content_source`.
+    content_source: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -1027,6 +1557,8 @@ struct UploadQueue {
     pending_bytes: Arc<AtomicU64>,
     planned_upload_bytes: Arc<AtomicU64>,
     phase_started: Arc<AtomicBool>,
+    /// When the first upload was queued, i.e. the start of the `scan_upload` phase.
+    scan_upload_started: Arc<OnceLock<Instant>>,
     cancel: CancellationToken,
 }
 
@@ -1034,10 +1566,10 @@ impl UploadQueue {
     async fn enqueue_direct(
         &self,
         chunk_hash: String,
-        blob: Vec<u8>,
+        plain: Vec<u8>,
         source_bytes: u64,
     ) -> Result<()> {
-        let bytes = blob.len();
+        let bytes = framed_len(plain.len());
         let permit = acquire_bytes(&self.bytes_sem, self.bytes_budget, bytes, &self.cancel).await?;
         self.pending_jobs.fetch_add(1, Ordering::Relaxed);
         self.pending_bytes
@@ -1046,7 +1578,7 @@ impl UploadQueue {
             .fetch_add(bytes as u64, Ordering::Relaxed);
         let job = UploadJob::Direct {
             chunk_hash,
-            blob,
+            plain,
             source_bytes,
             _bytes_permit: permit,
         };
@@ -1054,15 +1586,14 @@ impl UploadQueue {
             saturating_sub_usize(self.pending_jobs.as_ref(), 1);
             saturating_sub_u64(self.pending_bytes.as_ref(), bytes as u64);
             saturating_sub_u64(self.planned_upload_bytes.as_ref(), bytes as u64);
-            return Err(Error::Telegram {
-                message: "upload queue closed".to_string(),
-            });
+            return Err(Error::telegram("upload queue closed".to_string()));
         }
         if self
             .phase_started
             .compare_exchange(false, true, Ordering::Relaxed, Ordering::Relaxed)
             .is_ok()
         {
+            let _ = self.scan_upload_started.set(Instant::now());
             debug!(event = "phase.start", phase = "scan_upload", "phase.start");
         }
         Ok(())
@@ -1089,15 +1620,14 @@ impl UploadQueue {
             saturating_sub_usize(self.pending_jobs.as_ref(), 1);
             saturating_sub_u64(self.pending_bytes.as_ref(), bytes as u64);
             saturating_sub_u64(self.planned_upload_bytes.as_ref(), bytes as u64);
-            return Err(Error::Telegram {
-                message: "upload queue closed".to_string(),
-            });
+            return Err(Error::telegram("upload queue closed".to_string()));
         }
         if self
             .phase_started
             .compare_exchange(false, true, Ordering::Relaxed, Ordering::Relaxed)
             .is_ok()
         {
+            let _ = self.scan_upload_started.set(Instant::now());
             debug!(event = "phase.start", phase = "scan_upload", "phase.start");
         }
         Ok(())
@@ -1125,42 +1655,160 @@ async fn acquire_bytes(
     })?;
     tokio::select! {
         permit = bytes_sem.clone().acquire_many_owned(bytes_u32) => {
-            permit.map_err(|_| Error::Telegram {
-                message: "upload queue closed".to_string(),
-            })
+            permit.map_err(|_| Error::telegram("upload queue closed".to_string()))
         }
         _ = cancel.cancelled() => Err(Error::Cancelled),
     }
 }
 
+/// Confirms each uploaded object before it is indexed: its size where the provider reports one
+/// ([`Storage::object_size`]), or its whole content read back with `upload.verify_after_upload`.
+/// An object that does not match is uploaded once more; a second mismatch fails the run with
+/// [`Error::UploadCorrupted`].
+#[derive(Debug, Default)]
+struct UploadChecker {
+    verify_after_upload: bool,
+    checks: AtomicU64,
+    reuploads: AtomicU64,
+}
+
+impl UploadChecker {
+    fn new(verify_after_upload: bool) -> Self {
+        Self {
+            verify_after_upload,
+            ..Self::default()
+        }
+    }
+
+    /// Why the object at `object_id` differs from the `len` bytes just uploaded, if it does.
+    /// `matches` judges content read back in paranoid mode.
+    async fn mismatch<S: Storage>(
+        &self,
+        storage: &S,
+        retry: &RetryBudget,
+        object_id: &str,
+        len: u64,
+        matches: impl FnOnce(&[u8]) -> bool,
+    ) -> Result<Option<String>> {
+        if self.verify_after_upload {
+            let bytes = match retry
+                .run("upload_check", || storage.download_document(object_id))
+                .await
+            {
+                Ok(bytes) => bytes,
+                Err(Error::Integrity { message }) => {
+                    self.checks.fetch_add(1, Ordering::Relaxed);
+                    return Ok(Some(message));
+                }
+                Err(e) => return Err(e),
+            };
+            self.checks.fetch_add(1, Ordering::Relaxed);
+            if bytes.len() as u64 != len {
+                return Ok(Some(format!(
+                    "read back {} bytes, expected {len}",
+                    bytes.len()
+                )));
+            }
+            return Ok((!matches(&bytes)).then(|| "read back different content".to_string()));
+        }
+
+        match retry
+            .run("upload_check", || storage.object_size(object_id))
+            .await
+        {
+            Ok(None) => Ok(None),
+            Ok(Some(size)) => {
+                self.checks.fetch_add(1, Ordering::Relaxed);
+                Ok((size != len).then(|| format!("stored {size} bytes, expected {len}")))
+            }
+            Err(Error::Integrity { message }) => {
+                self.checks.fetch_add(1, Ordering::Relaxed);
+                Ok(Some(message))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Lets the caller upload a mismatched object once more; the second mismatch is the error.
+    fn reupload_or_fail(
+        &self,
+        provider: &str,
+        kind: ObjectKind,
+        object_id: &str,
+        reason: String,
+        reuploaded: &mut bool,
+    ) -> Result<()> {
+        if *reuploaded {
+            error!(
+                event = "io.telegram.upload_corrupted",
+                provider,
+                kind = kind.as_str(),
+                object_id,
+                reason = %reason,
+                "io.telegram.upload_corrupted"
+            );
+            return Err(Error::UploadCorrupted {
+                object_kind: kind.as_str().to_string(),
+                object_id: object_id.to_string(),
+                message: reason,
+            });
+        }
+        *reuploaded = true;
+        self.reuploads.fetch_add(1, Ordering::Relaxed);
+        warn!(
+            event = "io.telegram.upload_reupload",
+            provider,
+            kind = kind.as_str(),
+            object_id,
+            reason = %reason,
+            "io.telegram.upload_reupload"
+        );
+        Ok(())
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn process_upload_job<S: Storage>(
     storage: &S,
+    master_key: &[u8; 32],
     provider: &str,
     limiter: &UploadRateLimiter,
     uploaded_bytes: &AtomicU64,
     uploaded_net_bytes: &AtomicU64,
     have_uploaded_net_bytes: &AtomicBool,
+    retry: &RetryBudget,
+    checker: &UploadChecker,
     job: UploadJob,
 ) -> Result<UploadOutcome> {
     match job {
         UploadJob::Direct {
             chunk_hash,
-            blob,
+            plain,
             source_bytes,
             _bytes_permit,
         } => {
-            let bytes_len = blob.len() as u64;
-            for attempt in 1..=UPLOAD_OBJECT_MAX_ATTEMPTS {
+            let bytes_len = framed_len(plain.len()) as u64;
+            let mut reuploaded = false;
+            let mut attempt = 0;
+            loop {
+                attempt += 1;
                 limiter.wait_turn().await;
                 let filename = telegram_camouflaged_filename();
                 let last_reported = Arc::new(AtomicU64::new(0));
                 let last_reported_net = Arc::new(AtomicU64::new(0));
                 let last_for_cb = Arc::clone(&last_reported);
                 let last_net_for_cb = Arc::clone(&last_reported_net);
+                let body = FramedEncryptReader::new(
+                    master_key,
+                    chunk_hash.as_bytes(),
+                    plain.as_slice(),
+                    plain.len() as u64,
+                )?;
                 let upload_res = storage
-                    .upload_document_with_progress(
+                    .upload_document_stream_with_metadata(
                         &filename,
-                        blob.clone(),
+                        Box::new(body),
+                        bytes_len,
                         Some(Box::new(move |p| {
                             let n = p.bytes;
                             let prev = last_for_cb.swap(n, Ordering::Relaxed);
@@ -1176,6 +1824,9 @@ async fn process_upload_job<S: Storage>(
                                 }
                             }
                         })),
+                        Some(UploadMetadata {
+                            kind: ObjectKind::Chunk,
+                        }),
                     )
                     .await;
 
@@ -1185,6 +1836,26 @@ async fn process_upload_job<S: Storage>(
                         if reported < bytes_len {
                             uploaded_bytes.fetch_add(bytes_len - reported, Ordering::Relaxed);
                         }
+                        let matches = |bytes: &[u8]| {
+                            decrypt_framed(master_key, chunk_hash.as_bytes(), bytes)
+                                .is_ok_and(|read| read == plain)
+                        };
+                        if let Some(reason) = checker
+                            .mismatch(storage, retry, &object_id, bytes_len, matches)
+                            .await?
+                        {
+                            checker.reupload_or_fail(
+                                provider,
+                                ObjectKind::Chunk,
+                                &object_id,
+                                reason,
+                                &mut reuploaded,
+                            )?;
+                            saturating_sub_u64(uploaded_bytes, bytes_len);
+                            // The new upload gets the full retry allowance again.
+                            attempt = 0;
+                            continue;
+                        }
                         return Ok(UploadOutcome::Direct {
                             chunk_hash,
                             object_id,
@@ -1202,8 +1873,7 @@ async fn process_upload_job<S: Storage>(
                             saturating_sub_u64(uploaded_net_bytes, reported_net);
                         }
 
-                        if attempt < UPLOAD_OBJECT_MAX_ATTEMPTS && is_retryable_upload_error(&e) {
-                            let backoff = upload_object_retry_backoff(attempt);
+                        if let Some(backoff) = retry.next_backoff(attempt, &e) {
                             warn!(
                                 event = "io.telegram.upload_retry",
                                 provider,
@@ -1211,12 +1881,12 @@ async fn process_upload_job<S: Storage>(
                                 chunk_hash,
                                 blob_bytes = bytes_len,
                                 attempt,
-                                max_attempts = UPLOAD_OBJECT_MAX_ATTEMPTS,
+                                max_attempts = retry.max_attempts(),
                                 backoff_ms = backoff.as_millis() as u64,
                                 error = %e,
                                 "io.telegram.upload_retry"
                             );
-                            sleep(backoff).await;
+                            retry.wait(backoff).await?;
                             continue;
                         }
 
@@ -1229,20 +1899,12 @@ async fn process_upload_job<S: Storage>(
                             error = %e,
                             "io.telegram.upload_failed"
                         );
-                        return Err(Error::Telegram {
-                            message: format!(
-                                "upload failed: kind=direct chunk_hash={chunk_hash} bytes={bytes_len}; {e}"
-                            ),
-                        });
+                        return Err(Error::telegram(format!(
+                            "upload failed: kind=direct chunk_hash={chunk_hash} bytes={bytes_len}; {e}"
+                        )));
                     }
                 }
             }
-
-            Err(Error::Telegram {
-                message: format!(
-                    "upload failed: kind=direct chunk_hash={chunk_hash} bytes={bytes_len}; retry loop exhausted"
-                ),
-            })
         }
         UploadJob::Pack {
             entries,
@@ -1251,7 +1913,10 @@ async fn process_upload_job<S: Storage>(
             _bytes_permit,
         } => {
             let bytes_len = pack_bytes.len() as u64;
-            for attempt in 1..=UPLOAD_OBJECT_MAX_ATTEMPTS {
+            let mut reuploaded = false;
+            let mut attempt = 0;
+            loop {
+                attempt += 1;
                 limiter.wait_turn().await;
                 let filename = telegram_camouflaged_filename();
                 let last_reported = Arc::new(AtomicU64::new(0));
@@ -1259,9 +1924,10 @@ async fn process_upload_job<S: Storage>(
                 let last_for_cb = Arc::clone(&last_reported);
                 let last_net_for_cb = Arc::clone(&last_reported_net);
                 let upload_res = storage
-                    .upload_document_with_progress(
+                    .upload_document_stream_with_metadata(
                         &filename,
-                        pack_bytes.clone(),
+                        Box::new(pack_bytes.as_slice()),
+                        bytes_len,
                         Some(Box::new(move |p| {
                             let n = p.bytes;
                             let prev = last_for_cb.swap(n, Ordering::Relaxed);
@@ -1277,6 +1943,9 @@ async fn process_upload_job<S: Storage>(
                                 }
                             }
                         })),
+                        Some(UploadMetadata {
+                            kind: ObjectKind::Pack,
+                        }),
                     )
                     .await;
 
@@ -1286,6 +1955,22 @@ async fn process_upload_job<S: Storage>(
                         if reported < bytes_len {
                             uploaded_bytes.fetch_add(bytes_len - reported, Ordering::Relaxed);
                         }
+                        let matches = |bytes: &[u8]| bytes == pack_bytes.as_slice();
+                        if let Some(reason) = checker
+                            .mismatch(storage, retry, &pack_object_id, bytes_len, matches)
+                            .await?
+                        {
+                            checker.reupload_or_fail(
+                                provider,
+                                ObjectKind::Pack,
+                                &pack_object_id,
+                                reason,
+                                &mut reuploaded,
+                            )?;
+                            saturating_sub_u64(uploaded_bytes, bytes_len);
+                            attempt = 0;
+                            continue;
+                        }
                         return Ok(UploadOutcome::Pack {
                             entries,
                             pack_object_id,
@@ -1303,20 +1988,19 @@ async fn process_upload_job<S: Storage>(
                             saturating_sub_u64(uploaded_net_bytes, reported_net);
                         }
 
-                        if attempt < UPLOAD_OBJECT_MAX_ATTEMPTS && is_retryable_upload_error(&e) {
-                            let backoff = upload_object_retry_backoff(attempt);
+                        if let Some(backoff) = retry.next_backoff(attempt, &e) {
                             warn!(
                                 event = "io.telegram.upload_retry",
                                 provider,
                                 kind = "pack",
                                 blob_bytes = bytes_len,
                                 attempt,
-                                max_attempts = UPLOAD_OBJECT_MAX_ATTEMPTS,
+                                max_attempts = retry.max_attempts(),
                                 backoff_ms = backoff.as_millis() as u64,
                                 error = %e,
                                 "io.telegram.upload_retry"
                             );
-                            sleep(backoff).await;
+                            retry.wait(backoff).await?;
                             continue;
                         }
 
@@ -1328,18 +2012,12 @@ async fn process_upload_job<S: Storage>(
                             error = %e,
                             "io.telegram.upload_failed"
                         );
-                        return Err(Error::Telegram {
-                            message: format!("upload failed: kind=pack bytes={bytes_len}; {e}"),
-                        });
+                        return Err(Error::telegram(format!(
+                            "upload failed: kind=pack bytes={bytes_len}; {e}"
+                        )));
                     }
                 }
             }
-
-            Err(Error::Telegram {
-                message: format!(
-                    "upload failed: kind=pack bytes={bytes_len}; retry loop exhausted"
-                ),
-            })
         }
     }
 }
@@ -1367,9 +2045,9 @@ pub async fn run_backup_with<S: Storage>(
             message: "keep_last_snapshots must be >= 1".to_string(),
         });
     }
-    if !config.source_path.is_dir() {
+    if !config.source_path.is_dir() && !config.source_path.is_file() {
         return Err(Error::InvalidConfig {
-            message: "source_path must be an existing directory".to_string(),
+            message: "source_path must be an existing directory or regular file".to_string(),
         });
     }
 
@@ -1378,6 +2056,7 @@ pub async fn run_backup_with<S: Storage>(
     let source_bytes_total = source_quick_stats.map(|s| s.bytes_total);
 
     let provider_owned = provider.to_string();
+    let object_prefix_owned = storage.object_prefix().map(str::to_string);
     let limits = compute_upload_limits(&config.rate_limit)?;
     let configured_concurrency = config.rate_limit.max_concurrent_uploads as usize;
     // Treat `rate_limit.max_concurrent_uploads` as a hard cap. Adaptive mode may downshift on
@@ -1420,26 +2099,54 @@ pub async fn run_backup_with<S: Storage>(
     let have_uploaded_net_bytes = Arc::new(AtomicBool::new(false));
     let scan_done = Arc::new(AtomicBool::new(false));
     let upload_phase_started = Arc::new(AtomicBool::new(false));
+    let scan_upload_started = Arc::new(OnceLock::new());
     let active_uploads = Arc::new(AtomicUsize::new(0));
     let pending_jobs = Arc::new(AtomicUsize::new(0));
     let pending_bytes = Arc::new(AtomicU64::new(0));
 
-    let scan_source_path = config.source_path.clone();
+    let logical_source_path = config.source_path.clone();
+    let scan_source_path = options
+        .scan_root
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| config.source_path.clone());
+    // A single-file source is recorded by its basename, i.e. relative to its parent directory.
+    let scan_root_is_file = scan_source_path.is_file();
+    let scan_rel_root = match scan_source_path.parent() {
+        Some(parent) if scan_root_is_file => parent.to_path_buf(),
+        _ => scan_source_path.clone(),
+    };
+    let logical_rel_root = match logical_source_path.parent() {
+        Some(parent) if scan_root_is_file => parent.to_path_buf(),
+        _ => logical_source_path.clone(),
+    };
     let snapshot_id = config
         .snapshot_id
         .clone()
         .unwrap_or_else(|| format!("snp_{}", uuid::Uuid::new_v4()));
     let filemap_db_path = config.filemap_dir.join(format!("{snapshot_id}.sqlite"));
     let scan_label = config.label.clone();
+    let scan_created_at = config.created_at.clone();
+    let scan_device = config.device.clone();
     let scan_chunking = config.chunking.clone();
-    let scan_master_key = config.master_key;
+    let data_key = config
+        .data_key
+        .clone()
+        .unwrap_or_else(|| DataKey::master(&config.master_key));
+    let scan_data_key = data_key.clone();
+    let scan_key = *data_key.key();
     let scan_endpoint_db_path = config.endpoint_db_path.clone();
     let scan_filemap_dir = config.filemap_dir.clone();
     let scan_filemap_db_path = filemap_db_path.clone();
+    let scan_hint = config
+        .hint_changed_paths
+        .as_deref()
+        .map(|paths| ChangedPathHint::new(&config.source_path, &scan_source_path, paths));
 
     std::fs::create_dir_all(&config.filemap_dir)?;
 
     let bytes_budget = u32::try_from(limits.max_pending_bytes).unwrap_or(u32::MAX) as usize;
+    let retry_budget = Arc::new(RetryBudget::new(&options.retry, options.cancel));
+    let upload_checker = Arc::new(UploadChecker::new(options.verify_after_upload));
     let upload_cancel = options
         .cancel
         .map(CancellationToken::child_token)
@@ -1455,6 +2162,7 @@ pub async fn run_backup_with<S: Storage>(
         pending_bytes: Arc::clone(&pending_bytes),
         planned_upload_bytes: Arc::clone(&upload_workload_total),
         phase_started: Arc::clone(&upload_phase_started),
+        scan_upload_started: Arc::clone(&scan_upload_started),
         cancel: upload_cancel.clone(),
     };
 
@@ -1473,6 +2181,9 @@ pub async fn run_backup_with<S: Storage>(
         let active_uploads = Arc::clone(&active_uploads);
         let pending_jobs = Arc::clone(&pending_jobs);
         let pending_bytes = Arc::clone(&pending_bytes);
+        let retry = Arc::clone(&retry_budget);
+        let checker = Arc::clone(&upload_checker);
+        let throttle = options.upload_throttle;
         workers.push(async move {
             struct ActiveUploadToken<'a>(&'a AtomicUsize);
             impl Drop for ActiveUploadToken<'_> {
@@ -1510,16 +2221,25 @@ pub async fn run_backup_with<S: Storage>(
                 if cancel.is_cancelled() {
                     break;
                 }
+                if let Some(throttle) = throttle {
+                    tokio::select! {
+                        _ = cancel.cancelled() => break,
+                        _ = throttle.acquire(job.payload_len() as u64) => {}
+                    }
+                }
                 active_uploads.fetch_add(1, Ordering::Relaxed);
                 let _token = ActiveUploadToken(active_uploads.as_ref());
                 adaptive.on_attempt();
                 let outcome = process_upload_job(
                     storage,
+                    &scan_key,
                     &provider,
                     &limiter,
                     uploaded_bytes.as_ref(),
                     uploaded_net_bytes.as_ref(),
                     have_uploaded_net_bytes.as_ref(),
+                    retry.as_ref(),
+                    checker.as_ref(),
                     job,
                 )
                 .await;
@@ -1578,19 +2298,25 @@ pub async fn run_backup_with<S: Storage>(
     // maintenance cost before any scanning/upload begins, which can look like a "stuck" backup.
     // Restrict retention to the source being backed up; other sources will be cleaned up when
     // they run, or via an explicit maintenance task.
-    let pruned_preflight =
-        match apply_retention(&mut conn, &config.source_path, config.keep_last_snapshots).await {
-            Ok(v) => v,
-            Err(e) => {
-                warn!(
-                    event = "snapshots.retention.preflight_failed",
-                    source_path = %config.source_path.display(),
-                    error = %e,
-                    "snapshots.retention.preflight_failed"
-                );
-                Vec::new()
-            }
-        };
+    let pruned_preflight = match apply_retention(
+        &mut conn,
+        &config.source_path,
+        &config.filemap_dir,
+        config.keep_last_snapshots,
+    )
+    .await
+    {
+        Ok(v) => v,
+        Err(e) => {
+            warn!(
+                event = "snapshots.retention.preflight_failed",
+                source_path = %config.source_path.display(),
+                error = %e,
+                "snapshots.retention.preflight_failed"
+            );
+            Vec::new()
+        }
+    };
     cleanup_filemap_cache_best_effort(&config.filemap_dir, &pruned_preflight);
     compact_index_db_if_needed(&mut conn, &config.endpoint_db_path).await;
 
@@ -1615,26 +2341,38 @@ pub async fn run_backup_with<S: Storage>(
         let have_uploaded_net_bytes = Arc::clone(&have_uploaded_net_bytes);
         let scan_done = Arc::clone(&scan_done);
         let upload_phase_started = Arc::clone(&upload_phase_started);
+        let scan_upload_started = Arc::clone(&scan_upload_started);
         let active_uploads = Arc::clone(&active_uploads);
         async move {
             let res = async {
-                let base_snapshot_id =
-                    latest_snapshot_for_source(conn, &scan_source_path, provider).await?;
+                let base_snapshot_id = latest_snapshot_for_source(
+                    conn,
+                    &logical_source_path,
+                    provider,
+                    &scan_data_key,
+                )
+                .await?;
                 let snapshot_id = snapshot_id.clone();
-                let source_path_utf8 = path_to_utf8(&scan_source_path)?;
+                let source_path_utf8 = path_to_utf8(&logical_source_path)?;
 
                 execute_sqlite_with_busy_retry!(
                     "snapshots.insert",
                     sqlx::query(
                         r#"
-                        INSERT INTO snapshots (snapshot_id, created_at, source_path, label, base_snapshot_id)
-                        VALUES (?, strftime('%Y-%m-%dT%H:%M:%fZ','now'), ?, ?, ?)
+                        INSERT INTO snapshots (snapshot_id, created_at, source_path, label, base_snapshot_id, device_id, device_name, key_derivation, key_target_id, hash_alg)
+                        VALUES (?, COALESCE(?, strftime('%Y-%m-%dT%H:%M:%fZ','now')), ?, ?, ?, ?, ?, ?, ?, ?)
                         "#,
                     )
                     .bind(&snapshot_id)
+                    .bind(&scan_created_at)
                     .bind(&source_path_utf8)
                     .bind(&scan_label)
                     .bind(&base_snapshot_id)
+                    .bind(scan_device.as_ref().map(|d| d.device_id.as_str()))
+                    .bind(scan_device.as_ref().map(|d| d.device_name.as_str()))
+                    .bind(scan_data_key.derivation().version())
+                    .bind(scan_data_key.target_id())
+                    .bind(scan_data_key.chunk_hash_alg().as_str())
                     .execute(&mut **conn)
                 )?;
 
@@ -1648,19 +2386,26 @@ pub async fn run_backup_with<S: Storage>(
                     "snapshots.insert.filemap",
                     sqlx::query(
                         r#"
-                        INSERT INTO snapshots (snapshot_id, created_at, source_path, label, base_snapshot_id)
-                        VALUES (?, strftime('%Y-%m-%dT%H:%M:%fZ','now'), ?, ?, ?)
+                        INSERT INTO snapshots (snapshot_id, created_at, source_path, label, base_snapshot_id, device_id, device_name, key_derivation, key_target_id, hash_alg)
+                        VALUES (?, COALESCE(?, strftime('%Y-%m-%dT%H:%M:%fZ','now')), ?, ?, ?, ?, ?, ?, ?, ?)
                         "#,
                     )
                     .bind(&snapshot_id)
+                    .bind(&scan_created_at)
                     .bind(&source_path_utf8)
                     .bind(&scan_label)
                     .bind(&base_snapshot_id)
+                    .bind(scan_device.as_ref().map(|d| d.device_id.as_str()))
+                    .bind(scan_device.as_ref().map(|d| d.device_name.as_str()))
+                    .bind(scan_data_key.derivation().version())
+                    .bind(scan_data_key.target_id())
+                    .bind(scan_data_key.chunk_hash_alg().as_str())
                     .execute(&mut *filemap_conn)
                 )?;
 
                 // If we have a base snapshot, attach its filemap DB as `base` so base-chunk-copy
                 // can copy `file_chunks` without re-chunking file contents.
+                let mut base_columns = "";
                 if let Some(base_snapshot_id) = base_snapshot_id.as_deref() {
                     let cached_path = scan_filemap_dir.join(format!("{base_snapshot_id}.sqlite"));
                     let base_db_path = if cached_path.exists() {
@@ -1670,23 +2415,27 @@ pub async fn run_backup_with<S: Storage>(
                         // filemap rows.
                         scan_endpoint_db_path.clone()
                     } else {
-                        let manifest_object_id = lookup_remote_index_manifest_object_id(
-                            conn,
-                            base_snapshot_id,
-                            provider,
-                        )
-                        .await?
-                        .ok_or_else(|| Error::Integrity {
-                            message: format!(
-                                "base snapshot missing remote index pointer: base_snapshot_id={base_snapshot_id}"
-                            ),
-                        })?;
+                        let (manifest_object_id, manifest_sha256) =
+                            lookup_remote_index_manifest(conn, base_snapshot_id, provider)
+                                .await?
+                                .ok_or_else(|| Error::Integrity {
+                                    message: format!(
+                                        "base snapshot missing remote index pointer: base_snapshot_id={base_snapshot_id}"
+                                    ),
+                                })?;
+                        if manifest_sha256.is_none() {
+                            crate::remote_index_db::warn_manifest_unverified(
+                                base_snapshot_id,
+                                &manifest_object_id,
+                            );
+                        }
 
                         crate::remote_index_db::download_and_write_index_db_atomic(
                             storage,
                             base_snapshot_id,
                             &manifest_object_id,
-                            &scan_master_key,
+                            manifest_sha256.as_deref(),
+                            &scan_key,
                             &cached_path,
                             options.cancel,
                             Some(provider),
@@ -1697,12 +2446,15 @@ pub async fn run_backup_with<S: Storage>(
                     };
 
                     attach_db(&mut filemap_conn, "base", &base_db_path).await?;
+                    base_columns =
+                        files_optional_columns(&mut *filemap_conn, "base").await?;
                 }
 
                 let mut result = BackupResult {
                     snapshot_id: snapshot_id.clone(),
                     ..BackupResult::default()
                 };
+                let mut owner_names = OwnerNames::default();
 
                 let global_conn: &mut DbConn = if dedupe_enabled {
                     dedupe_conn
@@ -1715,19 +2467,34 @@ pub async fn run_backup_with<S: Storage>(
                     conn
                 };
                 let mut known_chunk_hashes =
-                    load_chunk_hashes_for_storage(global_conn, storage, provider).await?;
-                let mut pack_enabled = false;
-                let mut pending_bytes: usize = 0;
-                let mut pending_uploads: Vec<SourceBlob> = Vec::new();
-                let mut pack_state = PackState::new(provider, &snapshot_id);
+                    load_chunk_hashes_for_storage(global_conn, storage, provider, &scan_data_key)
+                        .await?;
+                let worker_threads = scan_worker_threads(options.worker_threads);
+                debug!(event = "scan.workers", worker_threads, "scan.workers");
+                let mut new_chunks =
+                    NewChunkBatch::new(worker_threads, scan_data_key.chunk_hash_alg());
+                let mut staging = ChunkStaging::new(provider, &snapshot_id);
                 let mut pending_base_chunk_copies: Vec<BaseFileChunkCopyRow> = Vec::new();
                 let mut warned_ignore_errors = HashSet::<String>::new();
                 let mut seen_ignore_files = HashSet::<PathBuf>::new();
                 let mut ignore_rule_files = 0u64;
+                let mut file_filters = FileFilterChain::new(options.filters)?;
+                let sqlite_consistent = SqliteConsistent::new(options.sqlite_consistent)?;
+                let mut consistent_dbs = HashSet::<PathBuf>::new();
+                // Without a base snapshot there is nothing to reuse, so the hint is moot.
+                let scan_hint = scan_hint.as_ref().filter(|_| base_snapshot_id.is_some());
+                let mut hint_files_reused = 0u64;
+                if let Some(hint) = scan_hint {
+                    debug!(
+                        event = "scan.hint",
+                        changed_paths = hint.roots.len() as u64,
+                        "scan.hint"
+                    );
+                }
 
                 if let Some(sink) = options.progress {
                     sink.on_progress(TaskProgress {
-                        phase: "scan".to_string(),
+                        phase: Phase::Scan,
                         files_total: None,
                         files_done: Some(0),
                         source_files_total,
@@ -1747,17 +2514,151 @@ pub async fn run_backup_with<S: Storage>(
                             .then_some(uploaded_net_bytes.load(Ordering::Relaxed)),
                         bytes_downloaded: None,
                         net_bytes_downloaded: None,
+                        bytes_written: None,
                         bytes_deduped: Some(0),
+                        bytes_total_estimated: None,
+                        bytes_total: None,
                     });
                 }
 
-                for entry in build_source_walk(&scan_source_path) {
+                let boundary = options
+                    .one_file_system
+                    .then(|| MountBoundary::for_root(&scan_source_path))
+                    .flatten()
+                    .map(Arc::new);
+                let excludes = options
+                    .default_excludes
+                    .then(|| Arc::new(DefaultExcludes::for_root(&scan_source_path)));
+                let mut walk =
+                    build_source_walk(&scan_source_path, boundary.as_ref(), excludes.as_ref());
+                let mut walk_done = false;
+                let mut in_flight = VecDeque::<ScanFile>::with_capacity(worker_threads);
+                loop {
                     if let Some(cancel) = options.cancel
                         && cancel.is_cancelled()
                     {
                         return Err(Error::Cancelled);
                     }
 
+                    if walk_done || in_flight.len() >= worker_threads {
+                        let Some(mut file) = in_flight.pop_front() else {
+                            break;
+                        };
+                        let mut read_error = None;
+                        let mut file_chunk_rows: Vec<FileChunkRow> = Vec::new();
+                        loop {
+                            let next = file.next().await?;
+                            if let Some(cancel) = options.cancel
+                                && cancel.is_cancelled()
+                            {
+                                return Err(Error::Cancelled);
+                            }
+
+                            let (chunk_hash, mut chunk) = match next {
+                                None => break,
+                                Some(ScanChunk::Chunk { hash, data }) => (hash, data),
+                                Some(ScanChunk::OpenError(e)) => {
+                                    if e.kind() == std::io::ErrorKind::NotFound {
+                                        debug!(
+                                            event = "scan.file_not_found",
+                                            path = %file.path.display(),
+                                            error = %e,
+                                            "scan.file_not_found"
+                                        );
+                                    } else if options.strict {
+                                        return Err(e.into());
+                                    }
+                                    read_error = Some(e);
+                                    break;
+                                }
+                                Some(ScanChunk::ReadError(e)) if !options.strict => {
+                                    read_error = Some(e);
+                                    break;
+                                }
+                                Some(ScanChunk::ReadError(_) | ScanChunk::ChunkingFailed) => {
+                                    return Err(Error::InvalidConfig {
+                                        message: "chunking failed".to_string(),
+                                    });
+                                }
+                            };
+                            result.chunks_total += 1;
+                            scan_chunks_total.store(result.chunks_total, Ordering::Relaxed);
+                            result.bytes_read += chunk.data.len() as u64;
+                            scan_bytes_read.store(result.bytes_read, Ordering::Relaxed);
+
+                            let exists = known_chunk_hashes.contains(&chunk_hash);
+                            if exists {
+                                result.bytes_deduped += chunk.data.len() as u64;
+                                scan_bytes_deduped.store(result.bytes_deduped, Ordering::Relaxed);
+                            } else {
+                                known_chunk_hashes.insert(chunk_hash.clone());
+                                scan_source_bytes_need_upload
+                                    .fetch_add(chunk.data.len() as u64, Ordering::Relaxed);
+
+                                let source_bytes = chunk.data.len() as u64;
+                                let full = new_chunks.push(SourceBlob {
+                                    chunk_hash: chunk_hash.clone(),
+                                    plain: std::mem::take(&mut chunk.data),
+                                    sealed: None,
+                                    source_bytes,
+                                });
+                                if full {
+                                    new_chunks
+                                        .flush(
+                                            global_conn,
+                                            &mut staging,
+                                            &uploader,
+                                            &scan_key,
+                                            &active_uploads,
+                                        )
+                                        .await?;
+                                }
+                            }
+
+                            file_chunk_rows.push(FileChunkRow {
+                                seq: file_chunk_rows.len() as i64,
+                                chunk_hash,
+                                offset: chunk.offset as i64,
+                                len: chunk.length as i64,
+                            });
+                        }
+
+                        // An unreadable file is dropped from the snapshot (restores would otherwise
+                        // create an empty placeholder). Chunks already uploaded stay as dedupe hits.
+                        if let Some(e) = read_error {
+                            execute_sqlite_with_busy_retry!(
+                                "files.delete_skipped",
+                                sqlx::query("DELETE FROM files WHERE file_id = ?")
+                                    .bind(&file.file_id)
+                                    .execute(&mut *filemap_conn)
+                            )?;
+                            result.files_indexed -= 1;
+                            scan_files_indexed.store(result.files_indexed, Ordering::Relaxed);
+                            record_skipped_file(
+                                &mut result,
+                                &scan_rel_root,
+                                &logical_rel_root,
+                                &file.path,
+                                SkipReason::from_io(&e),
+                                &e,
+                            );
+                            continue;
+                        }
+
+                        insert_file_chunks_batch(
+                            &mut filemap_conn,
+                            &file.file_id,
+                            &file_chunk_rows,
+                            scan_data_key.chunk_hash_alg(),
+                        )
+                        .await?;
+                        continue;
+                    }
+
+                    let Some(entry) = walk.next() else {
+                        walk_done = true;
+                        continue;
+                    };
                     let entry = match entry {
                         Ok(v) => v,
                         Err(e) => {
@@ -1778,6 +2679,20 @@ pub async fn run_backup_with<S: Storage>(
                                 );
                                 continue;
                             }
+                            if !options.strict
+                                && let Some((path, io)) =
+                                    skippable_walk_error(&e, &scan_source_path)
+                            {
+                                record_skipped_file(
+                                    &mut result,
+                                    &scan_rel_root,
+                                    &logical_rel_root,
+                                    path,
+                                    SkipReason::from_io(io),
+                                    &e,
+                                );
+                                continue;
+                            }
                             return Err(map_ignore_error(e, &scan_source_path));
                         }
                     };
@@ -1799,80 +2714,178 @@ pub async fn run_backup_with<S: Storage>(
                                 "scan.walkdir.not_found"
                             );
                             continue;
+                        } else if !options.strict
+                            && let Some((path, io)) =
+                                skippable_walk_error(err, &scan_source_path)
+                        {
+                            record_skipped_file(
+                                &mut result,
+                                &scan_rel_root,
+                                &logical_rel_root,
+                                path,
+                                SkipReason::from_io(io),
+                                err,
+                            );
+                            continue;
                         } else {
                             return Err(map_ignore_error(err.clone(), &scan_source_path));
                         }
                     }
 
                     let path = entry.path();
-                    let metadata = match entry.metadata() {
-                        Ok(v) => v,
-                        Err(e) => {
-                            if ignore_error_is_not_found(&e) {
-                                debug!(
-                                    event = "scan.entry_not_found",
-                                    path = %path.display(),
-                                    error = %e,
-                                    "scan.entry_not_found"
-                                );
-                                continue;
+
+                    // Files outside the changed-path hint take their metadata from the base
+                    // snapshot; the walk itself (readdir) still runs so deletions are detected.
+                    let hinted_base_row = match (scan_hint, base_snapshot_id.as_deref()) {
+                        (Some(hint), Some(base_snapshot_id))
+                            if entry.file_type().is_some_and(|t| t.is_file())
+                                && !hint.covers(path) =>
+                        {
+                            let rel_path = path.strip_prefix(&scan_rel_root).map_err(|_| {
+                                Error::InvalidConfig {
+                                    message: "path strip_prefix failed".to_string(),
+                                }
+                            })?;
+                            lookup_base_file_snapshot_row(
+                                &mut filemap_conn,
+                                base_snapshot_id,
+                                base_columns,
+                                &path_to_utf8(rel_path)?,
+                            )
+                            .await?
+                        }
+                        _ => None,
+                    };
+
+                    let metadata = if hinted_base_row.is_some() {
+                        hint_files_reused += 1;
+                        None
+                    } else {
+                        match entry.metadata() {
+                            Ok(v) => Some(v),
+                            Err(e) => {
+                                if ignore_error_is_not_found(&e) {
+                                    debug!(
+                                        event = "scan.entry_not_found",
+                                        path = %path.display(),
+                                        error = %e,
+                                        "scan.entry_not_found"
+                                    );
+                                    continue;
+                                }
+                                if !options.strict
+                                    && let Some(io) = e.io_error()
+                                    && path != scan_source_path
+                                {
+                                    record_skipped_file(
+                                        &mut result,
+                                        &scan_rel_root,
+                                        &logical_rel_root,
+                                        path,
+                                        SkipReason::from_io(io),
+                                        &e,
+                                    );
+                                    continue;
+                                }
+                                return Err(map_ignore_error(e, &scan_source_path));
                             }
-                            return Err(map_ignore_error(e, &scan_source_path));
                         }
                     };
+                    let is_file = hinted_base_row.is_some()
+                        || metadata.as_ref().is_some_and(|m| m.is_file());
 
-                    if metadata.is_dir() {
+                    if metadata.as_ref().is_some_and(|m| m.is_dir()) {
                         ignore_rule_files = ignore_rule_files.saturating_add(
                             count_ignore_file_for_dir(&mut seen_ignore_files, path),
                         );
                     }
 
-                    if metadata.is_file()
+                    if is_file
                         && path.file_name() == Some(OsStr::new(TELEVYIGNORE_FILE_NAME))
                         && seen_ignore_files.insert(path.to_path_buf())
                     {
                         ignore_rule_files = ignore_rule_files.saturating_add(1);
                     }
 
-                    if path == scan_source_path {
+                    if path == scan_source_path && !scan_root_is_file {
                         continue;
                     }
 
                     let rel_path =
-                        path.strip_prefix(&scan_source_path)
+                        path.strip_prefix(&scan_rel_root)
                             .map_err(|_| Error::InvalidConfig {
                                 message: "path strip_prefix failed".to_string(),
                             })?;
                     let rel_path_str = path_to_utf8(rel_path)?;
 
-                    let kind = if metadata.is_dir() {
-                        "dir"
-                    } else if metadata.is_file() {
-                        "file"
-                    } else if metadata.is_symlink() {
-                        "symlink"
-                    } else {
-                        continue;
+                    let (kind, size, mtime_ms, mode, btime_ms) = match (&metadata, &hinted_base_row) {
+                        (Some(metadata), _) => match scan_entry_stat(metadata) {
+                            Some(v) => v,
+                            None => continue,
+                        },
+                        (None, Some(row)) => {
+                            ("file", row.size, row.mtime_ms, row.mode, row.btime_ms)
+                        }
+                        (None, None) => continue,
                     };
 
-                    let (size, mtime_ms, mode) = if kind == "file" {
-                        let size = metadata.len() as i64;
-                        let mtime_ms = metadata
-                            .modified()
-                            .ok()
-                            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-                            .map(|d| d.as_millis() as i64)
-                            .unwrap_or(0);
-                        #[cfg(unix)]
-                        let mode = {
-                            use std::os::unix::fs::MetadataExt;
-                            metadata.mode() as i64
-                        };
-                        #[cfg(not(unix))]
-                        let mode = 0i64;
-                        (size, mtime_ms, mode)
-                    } else {
-                        (0i64, 0i64, 0i64)
+                    if kind == "file"
+                        && !file_filters.is_empty()
+                        && file_filters.excludes(path, size.max(0) as u64).await?
+                    {
+                        continue;
+                    }
+                    // The walk is sorted, so a database comes before its `-wal`/`-shm`/`-journal`
+                    // files; once it was copied consistently they hold nothing of its own.
+                    if kind == "file"
+                        && !consistent_dbs.is_empty()
+                        && sqlite_consistent::sidecar_database(path)
+                            .is_some_and(|db| consistent_dbs.contains(&db))
+                    {
+                        continue;
+                    }
+                    let mut content_source = hinted_base_row
+                        .as_ref()
+                        .and_then(|row| row.content_source.clone());
+                    let mut consistent_copy = None;
+                    if kind == "file"
+                        && hinted_base_row.is_none()
+                        && sqlite_consistent.applies_to(rel_path, path)
+                    {
+                        match sqlite_consistent
+                            .copy(path, &scan_filemap_dir, options.cancel)
+                            .await?
+                        {
+                            Ok(copy) => {
+                                result.sqlite_consistent_files += 1;
+                                content_source = Some(sqlite_consistent::CONTENT_SOURCE.to_string());
+                                consistent_copy = Some(copy);
+                            }
+                            Err(fallback) => {
+                                warn!(
+                                    event = "scan.sqlite_consistent_fallback",
+                                    path = %rel_path_str,
+                                    reason = fallback.reason(),
+                                    error = %fallback,
+                                    "scan.sqlite_consistent_fallback"
+                                );
+                                result.sqlite_consistent_fallbacks += 1;
+                                if !result.warnings.iter().any(|w| w == sqlite_consistent::WARNING) {
+                                    result.warnings.push(sqlite_consistent::WARNING.to_string());
+                                }
+                            }
+                        }
+                    }
+                    if content_source.as_deref() == Some(sqlite_consistent::CONTENT_SOURCE) {
+                        consistent_dbs.insert(path.to_path_buf());
+                    }
+                    // The copy is what gets chunked, so the row records its size.
+                    let size = consistent_copy.as_ref().map_or(size, |c| c.size as i64);
+                    // Symlinks store zeros above and no owner here.
+                    let owner = match (&metadata, &hinted_base_row) {
+                        (Some(metadata), _) if kind != "symlink" => owner_names.owner(metadata),
+                        (None, Some(row)) => row.owner.clone(),
+                        _ => None,
                     };
 
                     result.files_total += 1;
@@ -1882,8 +2895,8 @@ pub async fn run_backup_with<S: Storage>(
                         "files.insert",
                         sqlx::query(
                             r#"
-                            INSERT INTO files (file_id, snapshot_id, path, size, mtime_ms, mode, kind)
-                            VALUES (?, ?, ?, ?, ?, ?, ?)
+                            INSERT INTO files (file_id, snapshot_id, path, size, mtime_ms, mode, kind, btime_ms, uid, gid, owner_name, group_name, content_source)
+                            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
                             "#,
                         )
                         .bind(&file_id)
@@ -1893,11 +2906,20 @@ pub async fn run_backup_with<S: Storage>(
                         .bind(mtime_ms)
                         .bind(mode)
                         .bind(kind)
+                        .bind(btime_ms)
+                        .bind(owner.as_ref().map(|o| i64::from(o.uid)))
+                        .bind(owner.as_ref().map(|o| i64::from(o.gid)))
+                        .bind(owner.as_ref().and_then(|o| o.user.as_deref()))
+                        .bind(owner.as_ref().and_then(|o| o.group.as_deref()))
+                        .bind(content_source.as_deref())
                         .execute(&mut *filemap_conn)
                     )?;
 
                     result.files_indexed += 1;
                     scan_files_indexed.store(result.files_indexed, Ordering::Relaxed);
+                    if kind == "dir" {
+                        result.dirs_indexed += 1;
+                    }
 
                     if kind == "file" {
                         scan_source_files_done.fetch_add(1, Ordering::Relaxed);
@@ -1907,10 +2929,22 @@ pub async fn run_backup_with<S: Storage>(
                         continue;
                     }
 
-                    if let Some(base_snapshot_id) = base_snapshot_id.as_deref()
-                        && let Some(base_row) =
-                            lookup_base_file_snapshot_row(&mut filemap_conn, base_snapshot_id, &rel_path_str)
+                    // A fresh copy's size and the live file's mtime say nothing about the base's copy.
+                    let base_row = match (hinted_base_row, base_snapshot_id.as_deref()) {
+                        (Some(row), _) => Some(row),
+                        (None, _) if consistent_copy.is_some() => None,
+                        (None, Some(base_snapshot_id)) => {
+                            lookup_base_file_snapshot_row(
+                                &mut filemap_conn,
+                                base_snapshot_id,
+                                base_columns,
+                                &rel_path_str,
+                            )
                                 .await?
+                        }
+                        (None, None) => None,
+                    };
+                    if let Some(base_row) = base_row
                         && base_row.size == size
                         && base_row.mtime_ms == mtime_ms
                         && base_row.mode == mode
@@ -1938,133 +2972,14 @@ pub async fn run_backup_with<S: Storage>(
                         continue;
                     }
 
-                    let file = match File::open(path) {
-                        Ok(f) => f,
-                        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
-                            debug!(
-                                event = "scan.file_not_found",
-                                path = %path.display(),
-                                error = %e,
-                                "scan.file_not_found"
-                            );
-                            continue;
-                        }
-                        Err(e) => return Err(e.into()),
-                    };
-                    let chunker = file_chunker(file, &scan_chunking);
-                    let mut file_chunk_rows: Vec<FileChunkRow> = Vec::new();
-
-                    for (seq, chunk) in chunker.enumerate() {
-                        if let Some(cancel) = options.cancel
-                            && cancel.is_cancelled()
-                        {
-                            return Err(Error::Cancelled);
-                        }
-
-                        let chunk = chunk.map_err(|_| Error::InvalidConfig {
-                            message: "chunking failed".to_string(),
-                        })?;
-                        result.chunks_total += 1;
-                        scan_chunks_total.store(result.chunks_total, Ordering::Relaxed);
-                        result.bytes_read += chunk.data.len() as u64;
-                        scan_bytes_read.store(result.bytes_read, Ordering::Relaxed);
-
-                        let chunk_hash = blake3::hash(&chunk.data).to_hex().to_string();
-
-                        // `file_chunks` has a FK to `chunks`, so ensure the chunk row exists in
-                        // the per-snapshot filemap DB regardless of whether the chunk is deduped.
-                        execute_sqlite_with_busy_retry!(
-                            "chunks.insert.filemap",
-                            sqlx::query(
-                                r#"
-                                INSERT OR IGNORE INTO chunks (chunk_hash, size, hash_alg, enc_alg, created_at)
-                                VALUES (?, ?, 'blake3', 'xchacha20poly1305', strftime('%Y-%m-%dT%H:%M:%fZ','now'))
-                                "#,
-                            )
-                            .bind(&chunk_hash)
-                            .bind(chunk.data.len() as i64)
-                            .execute(&mut *filemap_conn)
-                        )?;
-
-                        let exists = known_chunk_hashes.contains(&chunk_hash);
-                        if exists {
-                            result.bytes_deduped += chunk.data.len() as u64;
-                            scan_bytes_deduped.store(result.bytes_deduped, Ordering::Relaxed);
-                        } else {
-                            known_chunk_hashes.insert(chunk_hash.clone());
-                            scan_source_bytes_need_upload
-                                .fetch_add(chunk.data.len() as u64, Ordering::Relaxed);
-
-                            execute_sqlite_with_busy_retry!(
-                                "chunks.insert",
-                                sqlx::query(
-                                    r#"
-                                    INSERT OR IGNORE INTO chunks (chunk_hash, size, hash_alg, enc_alg, created_at)
-                                    VALUES (?, ?, 'blake3', 'xchacha20poly1305', strftime('%Y-%m-%dT%H:%M:%fZ','now'))
-                                    "#,
-                                )
-                                .bind(&chunk_hash)
-                                .bind(chunk.data.len() as i64)
-                                .execute(&mut **global_conn)
-                            )?;
-
-                            let encrypted =
-                                encrypt_framed(&scan_master_key, chunk_hash.as_bytes(), &chunk.data)?;
-                            let blob = SourceBlob {
-                                chunk_hash: chunk_hash.clone(),
-                                blob: encrypted,
-                                source_bytes: chunk.data.len() as u64,
-                            };
-                            if !pack_enabled {
-                                pending_bytes = pending_bytes.saturating_add(blob.blob.len());
-                                pending_uploads.push(blob);
-                                if pending_uploads.len() > PACK_ENABLE_MIN_OBJECTS
-                                    || pending_bytes > PACK_TARGET_BYTES
-                                {
-                                    pack_enabled = true;
-                                    for b in pending_uploads.drain(..) {
-                                        schedule_pack_or_direct_upload(
-                                            &uploader,
-                                            &scan_master_key,
-                                            &mut pack_state,
-                                            b,
-                                        )
-                                        .await?;
-                                    }
-                                    pending_bytes = 0;
-                                }
-                            } else {
-                                schedule_pack_or_direct_upload(
-                                    &uploader,
-                                    &scan_master_key,
-                                    &mut pack_state,
-                                    blob,
-                                )
-                                .await?;
-                            }
-
-                            if pack_enabled {
-                                let should_flush_for_progress =
-                                    active_uploads.load(Ordering::Relaxed) == 0
-                                        && pack_state.packer.entries_len() >= PACK_ENABLE_MIN_OBJECTS;
-                                if should_flush_for_progress || pack_state.should_flush_due_to_age() {
-                                    flush_packer(&uploader, &scan_master_key, &mut pack_state)
-                                        .await?;
-                                }
-                            }
-                        }
-
-                        file_chunk_rows.push(FileChunkRow {
-                            seq: seq as i64,
-                            chunk_hash,
-                            offset: chunk.offset as i64,
-                            len: chunk.length as i64,
-                        });
-
-                    }
-
-                    insert_file_chunks_batch(&mut filemap_conn, &file_id, &file_chunk_rows)
-                        .await?;
+                    in_flight.push_back(ScanFile::spawn(
+                        file_id,
+                        path.to_path_buf(),
+                        consistent_copy.map(|c| c.path),
+                        &scan_chunking,
+                        &scan_data_key,
+                        options.cancel,
+                    ));
                 }
 
                 if !pending_base_chunk_copies.is_empty() {
@@ -2081,23 +2996,112 @@ pub async fn run_backup_with<S: Storage>(
                     }
                 }
 
-                if pack_enabled {
-                    flush_packer(&uploader, &scan_master_key, &mut pack_state).await?;
-                } else {
-                    for blob in pending_uploads {
-                        uploader
-                            .enqueue_direct(blob.chunk_hash, blob.blob, blob.source_bytes)
-                            .await?;
-                    }
-                }
+                new_chunks
+                    .flush(
+                        global_conn,
+                        &mut staging,
+                        &uploader,
+                        &scan_key,
+                        &active_uploads,
+                    )
+                    .await?;
+                staging.finish(&uploader, &scan_key).await?;
 
                 result.ignore_rule_files = ignore_rule_files;
+                result.file_filters = file_filters.into_stats();
+                let files_filtered = result
+                    .file_filters
+                    .iter()
+                    .map(|f| f.files_excluded)
+                    .sum::<u64>();
+                if files_filtered > 0 {
+                    info!(
+                        event = "scan.filters.summary",
+                        phase = "scan",
+                        source_path = %logical_source_path.display(),
+                        files_filtered,
+                        filters = ?result.file_filters,
+                        "scan.filters.summary"
+                    );
+                }
+                if let Some(excludes) = &excludes {
+                    result.files_skipped_default_excludes = excludes.skipped();
+                    if result.files_skipped_default_excludes > 0 {
+                        info!(
+                            event = "scan.default_excludes.summary",
+                            phase = "scan",
+                            source_path = %logical_source_path.display(),
+                            files_skipped_default_excludes = result.files_skipped_default_excludes,
+                            "scan.default_excludes.summary"
+                        );
+                    }
+                }
+                if let Some(boundary) = &boundary {
+                    let skipped = boundary.skipped();
+                    result.mount_points_skipped = skipped.len() as u64;
+                    if !skipped.is_empty() {
+                        warn!(
+                            event = "scan.mounts_skipped.summary",
+                            phase = "scan",
+                            source_path = %logical_source_path.display(),
+                            mount_points_skipped = result.mount_points_skipped,
+                            paths = ?skipped.iter().map(|m| m.path.display().to_string()).collect::<Vec<_>>(),
+                            "scan.mounts_skipped.summary"
+                        );
+                    }
+                }
+                let paths: Vec<String> =
+                    sqlx::query_scalar("SELECT path FROM files WHERE snapshot_id = ?")
+                        .bind(&snapshot_id)
+                        .fetch_all(&mut *filemap_conn)
+                        .await?;
+                if options.warn_path_bytes > 0 {
+                    let long_paths = paths
+                        .iter()
+                        .filter(|p| p.len() as u64 > options.warn_path_bytes)
+                        .collect::<Vec<_>>();
+                    result.long_paths = long_paths.len() as u64;
+                    if !long_paths.is_empty() {
+                        warn!(
+                            event = "scan.long_paths",
+                            phase = "scan",
+                            source_path = %logical_source_path.display(),
+                            long_paths = result.long_paths,
+                            warn_path_bytes = options.warn_path_bytes,
+                            longest_bytes = long_paths.iter().map(|p| p.len()).max().unwrap_or(0) as u64,
+                            examples = ?long_paths.iter().take(5).collect::<Vec<_>>(),
+                            "scan.long_paths"
+                        );
+                    }
+                }
+                let collisions = case_collisions(paths);
+                result.case_collisions = collisions.iter().map(|g| g.len() as u64).sum();
+                if !collisions.is_empty() {
+                    warn!(
+                        event = "scan.case_collisions",
+                        phase = "scan",
+                        source_path = %logical_source_path.display(),
+                        case_collisions = result.case_collisions,
+                        examples = ?collisions.iter().take(5).collect::<Vec<_>>(),
+                        "scan.case_collisions"
+                    );
+                }
+                if result.files_skipped_errors > 0 {
+                    warn!(
+                        event = "scan.skipped.summary",
+                        phase = "scan",
+                        source_path = %logical_source_path.display(),
+                        files_skipped_errors = result.files_skipped_errors,
+                        "scan.skipped.summary"
+                    );
+                }
+                check_unreadable_share(&result, options.max_unreadable_percent)?;
                 result.ignore_invalid_rules = warned_ignore_errors.len() as u64;
                 if result.ignore_invalid_rules > 0 {
                     warn!(
                         event = "source.ignore.summary",
                         phase = "scan",
-                        source_path = %scan_source_path.display(),
+                        source_path = %logical_source_path.display(),
                         ignore_file = TELEVYIGNORE_FILE_NAME,
                         ignore_rule_files = result.ignore_rule_files,
                         ignore_invalid_rules = result.ignore_invalid_rules,
@@ -2107,7 +3111,7 @@ pub async fn run_backup_with<S: Storage>(
                     info!(
                         event = "source.ignore.summary",
                         phase = "scan",
-                        source_path = %scan_source_path.display(),
+                        source_path = %logical_source_path.display(),
                         ignore_file = TELEVYIGNORE_FILE_NAME,
                         ignore_rule_files = result.ignore_rule_files,
                         ignore_invalid_rules = result.ignore_invalid_rules,
@@ -2123,8 +3127,17 @@ pub async fn run_backup_with<S: Storage>(
                     files_indexed = result.files_indexed,
                     chunks_total = result.chunks_total,
                     bytes_read = result.bytes_read,
+                    hint_files_reused,
                     "phase.finish"
                 );
+                result
+                    .phase_timings
+                    .record(Phase::Scan, scan_started.elapsed());
+                if let Some(started) = scan_upload_started.get() {
+                    result
+                        .phase_timings
+                        .record(Phase::ScanUpload, started.elapsed());
+                }
 
                 let upload_started = Instant::now();
                 if !upload_phase_started.load(Ordering::Relaxed) {
@@ -2132,7 +3145,7 @@ pub async fn run_backup_with<S: Storage>(
                     upload_phase_started.store(true, Ordering::Relaxed);
                     if let Some(sink) = options.progress {
                         sink.on_progress(TaskProgress {
-                            phase: "upload".to_string(),
+                            phase: Phase::Upload,
                             files_total: None,
                             files_done: Some(if source_files_total.is_some() {
                                 scan_source_files_done.load(Ordering::Relaxed)
@@ -2160,7 +3173,10 @@ pub async fn run_backup_with<S: Storage>(
                                 .load(Ordering::Relaxed)
                                 .then_some(uploaded_net_bytes.load(Ordering::Relaxed)),
                             net_bytes_downloaded: None,
+                            bytes_written: None,
                             bytes_deduped: Some(scan_bytes_deduped.load(Ordering::Relaxed)),
+                            bytes_total_estimated: None,
+                            bytes_total: None,
                         });
                     }
                 }
@@ -2196,6 +3212,8 @@ pub async fn run_backup_with<S: Storage>(
         let dedupe_db_path_for_checkpoint = config.dedupe_db_path.clone();
         let pending_dedupe_db_path_for_checkpoint = config.dedupe_pending_db_path.clone();
         let provider_for_checkpoint = provider_owned.clone();
+        let object_prefix_for_checkpoint = object_prefix_owned.clone();
+        let hash_alg_for_checkpoint = data_key.chunk_hash_alg();
         let remote_dedupe_for_checkpoint = config.remote_dedupe.clone();
         let scan_files_indexed = Arc::clone(&scan_files_indexed);
         let scan_source_files_done = Arc::clone(&scan_source_files_done);
@@ -2217,6 +3235,7 @@ pub async fn run_backup_with<S: Storage>(
             let mut pending_pool: Option<sqlx::SqlitePool> = None;
             let mut pending_conn: Option<DbConn> = None;
             let mut checkpoint_disabled = false;
+            let mut last_checkpoint = Instant::now();
             let mut rx = result_rx;
             while let Some(outcome) = rx.recv().await {
                 match outcome {
@@ -2268,8 +3287,11 @@ pub async fn run_backup_with<S: Storage>(
                 }
 
                 if !checkpoint_disabled
-                    && stats.chunk_objects.len() >= CHUNK_OBJECT_CHECKPOINT_BATCH_SIZE
+                    && (stats.chunk_objects.len() >= CHUNK_OBJECT_CHECKPOINT_BATCH_SIZE
+                        || (!stats.chunk_objects.is_empty()
+                            && last_checkpoint.elapsed() >= CHUNK_OBJECT_CHECKPOINT_INTERVAL))
                 {
+                    last_checkpoint = Instant::now();
                     if checkpoint_conn.is_none() {
                         let path = if remote_dedupe_for_checkpoint.enabled() {
                             &dedupe_db_path_for_checkpoint
@@ -2338,6 +3360,8 @@ pub async fn run_backup_with<S: Storage>(
                                 dedupe,
                                 pending,
                                 &provider_for_checkpoint,
+                                object_prefix_for_checkpoint.as_deref(),
+                                hash_alg_for_checkpoint,
                                 &stats.chunk_objects,
                             )
                             .await
@@ -2359,6 +3383,7 @@ pub async fn run_backup_with<S: Storage>(
                         && let Err(e) = record_chunk_objects_batch(
                             conn,
                             &provider_for_checkpoint,
+                            object_prefix_for_checkpoint.as_deref(),
                             &stats.chunk_objects,
                         )
                         .await
@@ -2378,12 +3403,12 @@ pub async fn run_backup_with<S: Storage>(
 
                 if let Some(sink) = options.progress {
                     let phase = if scan_done.load(Ordering::Relaxed) {
-                        "upload"
+                        Phase::Upload
                     } else {
-                        "scan_upload"
+                        Phase::ScanUpload
                     };
                     sink.on_progress(TaskProgress {
-                        phase: phase.to_string(),
+                        phase,
                         files_total: None,
                         files_done: Some(if source_files_total.is_some() {
                             scan_source_files_done.load(Ordering::Relaxed)
@@ -2409,7 +3434,10 @@ pub async fn run_backup_with<S: Storage>(
                             .then_some(uploaded_net_bytes.load(Ordering::Relaxed)),
                         bytes_downloaded: None,
                         net_bytes_downloaded: None,
+                        bytes_written: None,
                         bytes_deduped: Some(scan_bytes_deduped.load(Ordering::Relaxed)),
+                        bytes_total_estimated: None,
+                        bytes_total: None,
                     });
                 }
             }
@@ -2477,6 +3505,8 @@ pub async fn run_backup_with<S: Storage>(
                             dedupe,
                             pending,
                             &provider_for_checkpoint,
+                            object_prefix_for_checkpoint.as_deref(),
+                            hash_alg_for_checkpoint,
                             &stats.chunk_objects,
                         )
                         .await
@@ -2497,6 +3527,7 @@ pub async fn run_backup_with<S: Storage>(
                     && let Err(e) = record_chunk_objects_batch(
                         conn,
                         &provider_for_checkpoint,
+                        object_prefix_for_checkpoint.as_deref(),
                         &stats.chunk_objects,
                     )
                     .await
@@ -2566,14 +3597,14 @@ pub async fn run_backup_with<S: Storage>(
                     last_net = net;
                     last_emit = Instant::now();
                     let phase = if scan_done.load(Ordering::Relaxed) {
-                        "upload"
+                        Phase::Upload
                     } else if upload_phase_started.load(Ordering::Relaxed) {
-                        "scan_upload"
+                        Phase::ScanUpload
                     } else {
-                        "scan"
+                        Phase::Scan
                     };
                     sink.on_progress(TaskProgress {
-                        phase: phase.to_string(),
+                        phase,
                         files_total: None,
                         files_done: Some(if source_files_total.is_some() {
                             scan_source_files_done.load(Ordering::Relaxed)
@@ -2597,7 +3628,10 @@ pub async fn run_backup_with<S: Storage>(
                         net_bytes_uploaded: net,
                         bytes_downloaded: None,
                         net_bytes_downloaded: None,
+                        bytes_written: None,
                         bytes_deduped: Some(scan_bytes_deduped.load(Ordering::Relaxed)),
+                        bytes_total_estimated: None,
+                        bytes_total: None,
                     });
                 }
 
@@ -2749,11 +3783,19 @@ pub async fn run_backup_with<S: Storage>(
             dedupe_conn,
             &mut pending_conn,
             &provider_owned,
+            object_prefix_owned.as_deref(),
+            data_key.chunk_hash_alg(),
             &chunk_objects,
         )
         .await
     } else {
-        record_chunk_objects_batch(&mut conn, &provider_owned, &chunk_objects).await
+        record_chunk_objects_batch(
+            &mut conn,
+            &provider_owned,
+            object_prefix_owned.as_deref(),
+            &chunk_objects,
+        )
+        .await
     };
 
     if let Err(tail_err) = tail_res {
@@ -2778,6 +3820,9 @@ pub async fn run_backup_with<S: Storage>(
     result.bytes_uploaded = bytes_uploaded;
 
     result.data_objects_estimated_without_pack = result.chunks_uploaded;
+    result
+        .phase_timings
+        .record(Phase::Upload, upload_started.elapsed());
     debug!(
         event = "phase.finish",
         phase = "upload",
@@ -2791,7 +3836,7 @@ pub async fn run_backup_with<S: Storage>(
 
     if let Some(sink) = options.progress {
         sink.on_progress(TaskProgress {
-            phase: "index".to_string(),
+            phase: Phase::Index,
             files_total: None,
             files_done: Some(if source_files_total.is_some() {
                 scan_source_files_done.load(Ordering::Relaxed)
@@ -2815,7 +3860,10 @@ pub async fn run_backup_with<S: Storage>(
                 .then_some(uploaded_net_bytes.load(Ordering::Relaxed)),
             bytes_downloaded: None,
             net_bytes_downloaded: None,
+            bytes_written: None,
             bytes_deduped: Some(result.bytes_deduped),
+            bytes_total_estimated: None,
+            bytes_total: None,
         });
     }
 
@@ -2836,14 +3884,48 @@ pub async fn run_backup_with<S: Storage>(
         .map(std::path::Path::to_path_buf)
         .unwrap_or_else(std::env::temp_dir);
 
-    // 1) Upload per-snapshot filemap DB, then persist its manifest pointer in the endpoint DB.
+    // 1) Upload per-snapshot filemap DB (or its delta against the base snapshot's), then persist
+    // its manifest pointer in the endpoint DB.
+    if data_key.derivation() != KeyDerivation::Master {
+        let chunk_objects_db_path = if config.remote_dedupe.enabled() {
+            &config.dedupe_db_path
+        } else {
+            &config.endpoint_db_path
+        };
+        copy_filemap_chunk_objects(&filemap_db_path, chunk_objects_db_path, provider).await?;
+    }
+    let delta_parent = filemap_delta_parent(&mut conn, provider, &config, &snapshot_id).await?;
+    let filemap_delta_db = match &delta_parent {
+        Some((parent, parent_db_path)) => {
+            let delta_db = tempfile::Builder::new()
+                .prefix("televy-index-delta-")
+                .suffix(".sqlite")
+                .tempfile_in(&filemap_temp_parent)?;
+            write_filemap_delta_db(
+                &filemap_db_path,
+                parent_db_path,
+                &parent.snapshot_id,
+                &snapshot_id,
+                delta_db.path(),
+            )
+            .await?;
+            Some(delta_db)
+        }
+        None => None,
+    };
     let uploaded_filemap = upload_index_sqlite_db(
         storage,
-        &config,
+        data_key.key(),
+        config.device.as_ref(),
         &snapshot_id,
-        &filemap_db_path,
+        delta_parent.map(|(parent, _)| parent),
+        filemap_delta_db
+            .as_ref()
+            .map_or(filemap_db_path.as_path(), |f| f.path()),
         &filemap_temp_parent,
         &rate_limiter,
+        retry_budget.as_ref(),
+        upload_checker.as_ref(),
         uploaded_bytes.as_ref(),
         uploaded_net_bytes.as_ref(),
         have_uploaded_net_bytes.as_ref(),
@@ -2860,23 +3942,36 @@ pub async fn run_backup_with<S: Storage>(
         scan_bytes_deduped.load(Ordering::Relaxed),
     )
     .await?;
-    persist_snapshot_remote_index_meta(&mut conn, provider, &snapshot_id, &uploaded_filemap)
-        .await?;
+    drop(filemap_delta_db);
+    persist_snapshot_remote_index_meta(
+        &mut conn,
+        provider,
+        &snapshot_id,
+        &uploaded_filemap,
+        Some(&filemap_db_path),
+    )
+    .await?;
 
     // Apply retention now so the exported endpoint DB reflects the configured window.
-    let pruned_final =
-        match apply_retention(&mut conn, &config.source_path, config.keep_last_snapshots).await {
-            Ok(v) => v,
-            Err(e) => {
-                warn!(
-                    event = "snapshots.retention.final_failed",
-                    source_path = %config.source_path.display(),
-                    error = %e,
-                    "snapshots.retention.final_failed"
-                );
-                Vec::new()
-            }
-        };
+    let pruned_final = match apply_retention(
+        &mut conn,
+        &config.source_path,
+        &config.filemap_dir,
+        config.keep_last_snapshots,
+    )
+    .await
+    {
+        Ok(v) => v,
+        Err(e) => {
+            warn!(
+                event = "snapshots.retention.final_failed",
+                source_path = %config.source_path.display(),
+                error = %e,
+                "snapshots.retention.final_failed"
+            );
+            Vec::new()
+        }
+    };
     cleanup_filemap_cache_best_effort(&config.filemap_dir, &pruned_final);
 
     // 2) Export+upload small endpoint DB (global/dedupe state, no file maps).
@@ -2897,11 +3992,15 @@ pub async fn run_backup_with<S: Storage>(
     );
     let uploaded_endpoint = upload_index_sqlite_db(
         storage,
-        &config,
+        &config.master_key,
+        config.device.as_ref(),
         &endpoint_index_id,
+        None,
         exported_endpoint_db.path(),
         &endpoint_temp_parent,
         &rate_limiter,
+        retry_budget.as_ref(),
+        upload_checker.as_ref(),
         uploaded_bytes.as_ref(),
         uploaded_net_bytes.as_ref(),
         have_uploaded_net_bytes.as_ref(),
@@ -2945,6 +4044,8 @@ pub async fn run_backup_with<S: Storage>(
             &config,
             dedupe_conn,
             &rate_limiter,
+            retry_budget.as_ref(),
+            upload_checker.as_ref(),
             uploaded_bytes.as_ref(),
             uploaded_net_bytes.as_ref(),
             have_uploaded_net_bytes.as_ref(),
@@ -2966,6 +4067,12 @@ pub async fn run_backup_with<S: Storage>(
 
     result.index_parts = index_parts_total;
     result.bytes_uploaded = uploaded_bytes.load(Ordering::Relaxed);
+    result.retry = retry_budget.stats();
+    result.upload_checks = upload_checker.checks.load(Ordering::Relaxed);
+    result.upload_reuploads = upload_checker.reuploads.load(Ordering::Relaxed);
+    result
+        .phase_timings
+        .record(Phase::Index, index_started.elapsed());
 
     debug!(
         event = "phase.finish",
@@ -2995,36 +4102,203 @@ pub async fn run_backup_with<S: Storage>(
     Ok(result)
 }
 
-async fn schedule_pack_or_direct_upload(
-    uploader: &UploadQueue,
-    master_key: &[u8; 32],
-    pack_state: &mut PackState,
-    blob: SourceBlob,
-) -> Result<()> {
-    let SourceBlob {
-        chunk_hash,
-        blob,
-        source_bytes,
-    } = blob;
+/// New chunks found by the scan, recorded in the chunk index in one transaction per batch and
+/// only then handed to the uploader (upload checkpoints reference these `chunks` rows).
+struct NewChunkBatch {
+    blobs: Vec<SourceBlob>,
+    bytes: usize,
+    /// Threads sealing pack-bound chunks of a batch.
+    workers: usize,
+    hash_alg: ChunkHashAlg,
+}
 
-    if blob.len() + SINGLE_BLOB_PACK_OVERHEAD_BUDGET_BYTES > PACK_MAX_BYTES {
-        flush_packer(uploader, master_key, pack_state).await?;
-        uploader
-            .enqueue_direct(chunk_hash, blob, source_bytes)
-            .await?;
-        return Ok(());
+impl NewChunkBatch {
+    fn new(workers: usize, hash_alg: ChunkHashAlg) -> Self {
+        Self {
+            blobs: Vec::new(),
+            bytes: 0,
+            workers,
+            hash_alg,
+        }
     }
 
-    if !pack_state.packer.is_empty() && pack_state.packer.blob_len() + blob.len() > PACK_MAX_BYTES {
-        flush_packer(uploader, master_key, pack_state).await?;
+    /// Adds `blob`; `true` once the batch should be flushed.
+    fn push(&mut self, blob: SourceBlob) -> bool {
+        self.bytes = self.bytes.saturating_add(blob.plain.len());
+        self.blobs.push(blob);
+        self.blobs.len() >= SCAN_NEW_CHUNK_BATCH || self.bytes >= SCAN_NEW_CHUNK_BATCH_BYTES
     }
 
-    pack_state
-        .staged_source_bytes
-        .insert(chunk_hash.clone(), source_bytes);
-    pack_state.packer.push_blob(PackBlob { chunk_hash, blob })?;
-    pack_state.mark_staged();
-    if pack_state.packer.entries_len() >= PACK_MAX_ENTRIES_PER_PACK
+    async fn flush(
+        &mut self,
+        conn: &mut DbConn,
+        staging: &mut ChunkStaging,
+        uploader: &UploadQueue,
+        master_key: &[u8; 32],
+        active_uploads: &AtomicUsize,
+    ) -> Result<()> {
+        if self.blobs.is_empty() {
+            return Ok(());
+        }
+        insert_chunks_batch(conn, &self.blobs, self.hash_alg).await?;
+        self.bytes = 0;
+        let mut blobs = std::mem::take(&mut self.blobs);
+        if staging.pack_enabled {
+            blobs = seal_blobs(master_key, blobs, self.workers).await?;
+        }
+        for blob in blobs {
+            staging
+                .stage(uploader, master_key, active_uploads, blob)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Encrypts the pack-bound `blobs` on up to `workers` blocking threads, keeping their order.
+async fn seal_blobs(
+    master_key: &[u8; 32],
+    blobs: Vec<SourceBlob>,
+    workers: usize,
+) -> Result<Vec<SourceBlob>> {
+    let total = blobs.len();
+    let per_worker = total.div_ceil(workers.max(1)).max(1);
+    let mut blobs = blobs.into_iter().peekable();
+    let mut tasks = Vec::new();
+    while blobs.peek().is_some() {
+        let group = blobs.by_ref().take(per_worker).collect::<Vec<_>>();
+        let master_key = *master_key;
+        tasks.push(tokio::task::spawn_blocking(move || {
+            group
+                .into_iter()
+                .map(|mut blob| {
+                    if blob.sealed.is_none()
+                        && blob.framed_len() + SINGLE_BLOB_PACK_OVERHEAD_BUDGET_BYTES
+                            <= PACK_MAX_BYTES
+                    {
+                        let sealed =
+                            encrypt_framed(&master_key, blob.chunk_hash.as_bytes(), &blob.plain)?;
+                        blob.sealed = Some(sealed);
+                        blob.plain = Vec::new();
+                    }
+                    Ok(blob)
+                })
+                .collect::<Result<Vec<_>>>()
+        }));
+    }
+    let mut sealed = Vec::with_capacity(total);
+    for task in tasks {
+        sealed.extend(task.await.map_err(scan_worker_failed)??);
+    }
+    Ok(sealed)
+}
+
+/// Routes new chunks to the uploader: they are held back until enough show up to make packing
+/// worthwhile, then packed; a small backup uploads its few chunks directly at the end.
+struct ChunkStaging {
+    pack_enabled: bool,
+    pending_bytes: usize,
+    pending_uploads: Vec<SourceBlob>,
+    pack_state: PackState,
+}
+
+impl ChunkStaging {
+    fn new(provider: &str, snapshot_id: &str) -> Self {
+        Self {
+            pack_enabled: false,
+            pending_bytes: 0,
+            pending_uploads: Vec::new(),
+            pack_state: PackState::new(provider, snapshot_id),
+        }
+    }
+
+    async fn stage(
+        &mut self,
+        uploader: &UploadQueue,
+        master_key: &[u8; 32],
+        active_uploads: &AtomicUsize,
+        blob: SourceBlob,
+    ) -> Result<()> {
+        if !self.pack_enabled {
+            self.pending_bytes = self.pending_bytes.saturating_add(blob.framed_len());
+            self.pending_uploads.push(blob);
+            if self.pending_uploads.len() > PACK_ENABLE_MIN_OBJECTS
+                || self.pending_bytes > PACK_TARGET_BYTES
+            {
+                self.pack_enabled = true;
+                for b in std::mem::take(&mut self.pending_uploads) {
+                    schedule_pack_or_direct_upload(uploader, master_key, &mut self.pack_state, b)
+                        .await?;
+                }
+                self.pending_bytes = 0;
+            }
+        } else {
+            schedule_pack_or_direct_upload(uploader, master_key, &mut self.pack_state, blob)
+                .await?;
+        }
+
+        if self.pack_enabled {
+            let should_flush_for_progress = active_uploads.load(Ordering::Relaxed) == 0
+                && self.pack_state.packer.entries_len() >= PACK_ENABLE_MIN_OBJECTS;
+            if should_flush_for_progress || self.pack_state.should_flush_due_to_age() {
+                flush_packer(uploader, master_key, &mut self.pack_state).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn finish(mut self, uploader: &UploadQueue, master_key: &[u8; 32]) -> Result<()> {
+        if self.pack_enabled {
+            flush_packer(uploader, master_key, &mut self.pack_state).await?;
+        } else {
+            for blob in self.pending_uploads {
+                uploader
+                    .enqueue_direct(blob.chunk_hash, blob.plain, blob.source_bytes)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn schedule_pack_or_direct_upload(
+    uploader: &UploadQueue,
+    master_key: &[u8; 32],
+    pack_state: &mut PackState,
+    blob: SourceBlob,
+) -> Result<()> {
+    let blob_len = blob.framed_len();
+    let SourceBlob {
+        chunk_hash,
+        plain,
+        sealed,
+        source_bytes,
+    } = blob;
+
+    if blob_len + SINGLE_BLOB_PACK_OVERHEAD_BUDGET_BYTES > PACK_MAX_BYTES {
+        flush_packer(uploader, master_key, pack_state).await?;
+        uploader
+            .enqueue_direct(chunk_hash, plain, source_bytes)
+            .await?;
+        return Ok(());
+    }
+
+    if !pack_state.packer.is_empty() && pack_state.packer.blob_len() + blob_len > PACK_MAX_BYTES {
+        flush_packer(uploader, master_key, pack_state).await?;
+    }
+
+    let blob = match sealed {
+        Some(blob) => blob,
+        None => encrypt_framed(master_key, chunk_hash.as_bytes(), &plain)?,
+    };
+    drop(plain);
+
+    pack_state
+        .staged_source_bytes
+        .insert(chunk_hash.clone(), source_bytes);
+    pack_state.packer.push_blob(PackBlob { chunk_hash, blob })?;
+    pack_state.mark_staged();
+    if pack_state.packer.entries_len() >= PACK_MAX_ENTRIES_PER_PACK
         || pack_state.packer.blob_len() >= pack_state.flush_target_bytes
     {
         flush_packer(uploader, master_key, pack_state).await?;
@@ -3124,10 +4398,71 @@ impl PackState {
     }
 }
 
+async fn insert_chunks_batch(
+    conn: &mut DbConn,
+    blobs: &[SourceBlob],
+    hash_alg: ChunkHashAlg,
+) -> Result<()> {
+    let mut retry_idx = 0usize;
+    'retry: loop {
+        let mut tx = conn.begin().await.map_err(Error::from)?;
+
+        for blob in blobs {
+            if let Err(e) = sqlx::query(
+                r#"
+                INSERT OR IGNORE INTO chunks (chunk_hash, size, hash_alg, enc_alg, created_at)
+                VALUES (?, ?, ?, 'xchacha20poly1305', strftime('%Y-%m-%dT%H:%M:%fZ','now'))
+                "#,
+            )
+            .bind(&blob.chunk_hash)
+            .bind(blob.source_bytes as i64)
+            .bind(hash_alg.as_str())
+            .execute(&mut *tx)
+            .await
+            {
+                let _ = tx.rollback().await;
+                if is_sqlite_busy_or_locked(&e) && retry_idx < SQLITE_BUSY_RETRY_DELAYS_MS.len() {
+                    let wait_ms = SQLITE_BUSY_RETRY_DELAYS_MS[retry_idx];
+                    retry_idx += 1;
+                    debug!(
+                        event = "sqlite.busy_retry",
+                        op = "chunks.insert.batch",
+                        retry = retry_idx,
+                        wait_ms,
+                        "sqlite.busy_retry"
+                    );
+                    sleep(Duration::from_millis(wait_ms)).await;
+                    continue 'retry;
+                }
+                return Err(Error::from(e));
+            }
+        }
+
+        if let Err(e) = tx.commit().await {
+            if is_sqlite_busy_or_locked(&e) && retry_idx < SQLITE_BUSY_RETRY_DELAYS_MS.len() {
+                let wait_ms = SQLITE_BUSY_RETRY_DELAYS_MS[retry_idx];
+                retry_idx += 1;
+                debug!(
+                    event = "sqlite.busy_retry",
+                    op = "chunks.insert.batch",
+                    retry = retry_idx,
+                    wait_ms,
+                    "sqlite.busy_retry"
+                );
+                sleep(Duration::from_millis(wait_ms)).await;
+                continue 'retry;
+            }
+            return Err(Error::from(e));
+        }
+        return Ok(());
+    }
+}
+
 async fn insert_file_chunks_batch(
     conn: &mut DbConn,
     file_id: &str,
     rows: &[FileChunkRow],
+    hash_alg: ChunkHashAlg,
 ) -> Result<()> {
     if rows.is_empty() {
         return Ok(());
@@ -3138,20 +4473,36 @@ async fn insert_file_chunks_batch(
         let mut tx = conn.begin().await.map_err(Error::from)?;
 
         for row in rows {
-            if let Err(e) = sqlx::query(
-                r#"
-                INSERT INTO file_chunks (file_id, seq, chunk_hash, offset, len)
-                VALUES (?, ?, ?, ?, ?)
-                "#,
-            )
-            .bind(file_id)
-            .bind(row.seq)
-            .bind(&row.chunk_hash)
-            .bind(row.offset)
-            .bind(row.len)
-            .execute(&mut *tx)
-            .await
-            {
+            // `file_chunks` has a FK to `chunks`, so ensure the chunk row exists in the
+            // per-snapshot filemap DB regardless of whether the chunk is deduped.
+            let inserted = async {
+                sqlx::query(
+                    r#"
+                    INSERT OR IGNORE INTO chunks (chunk_hash, size, hash_alg, enc_alg, created_at)
+                    VALUES (?, ?, ?, 'xchacha20poly1305', strftime('%Y-%m-%dT%H:%M:%fZ','now'))
+                    "#,
+                )
+                .bind(&row.chunk_hash)
+                .bind(row.len)
+                .bind(hash_alg.as_str())
+                .execute(&mut *tx)
+                .await?;
+                sqlx::query(
+                    r#"
+                    INSERT INTO file_chunks (file_id, seq, chunk_hash, offset, len)
+                    VALUES (?, ?, ?, ?, ?)
+                    "#,
+                )
+                .bind(file_id)
+                .bind(row.seq)
+                .bind(&row.chunk_hash)
+                .bind(row.offset)
+                .bind(row.len)
+                .execute(&mut *tx)
+                .await
+            }
+            .await;
+            if let Err(e) = inserted {
                 let _ = tx.rollback().await;
                 if is_sqlite_busy_or_locked(&e) && retry_idx < SQLITE_BUSY_RETRY_DELAYS_MS.len() {
                     let wait_ms = SQLITE_BUSY_RETRY_DELAYS_MS[retry_idx];
@@ -3193,6 +4544,7 @@ async fn insert_file_chunks_batch(
 async fn record_chunk_objects_batch(
     conn: &mut DbConn,
     provider: &str,
+    object_prefix: Option<&str>,
     chunk_objects: &[ChunkObjectMapping],
 ) -> Result<()> {
     if chunk_objects.is_empty() {
@@ -3227,16 +4579,18 @@ async fn record_chunk_objects_batch(
         for m in chunk_objects {
             if let Err(e) = sqlx::query(
                 r#"
-                INSERT INTO chunk_objects (chunk_hash, provider, object_id, created_at)
-                VALUES (?, ?, ?, strftime('%Y-%m-%dT%H:%M:%fZ','now'))
+                INSERT INTO chunk_objects (chunk_hash, provider, object_id, created_at, object_prefix)
+                VALUES (?, ?, ?, strftime('%Y-%m-%dT%H:%M:%fZ','now'), ?)
                 ON CONFLICT(provider, chunk_hash) DO UPDATE SET
                   object_id = excluded.object_id,
-                  created_at = excluded.created_at
+                  created_at = excluded.created_at,
+                  object_prefix = excluded.object_prefix
                 "#,
             )
             .bind(&m.chunk_hash)
             .bind(provider)
             .bind(&m.object_id)
+            .bind(object_prefix)
             .execute(&mut *tx)
             .await
             {
@@ -3329,16 +4683,34 @@ async fn record_dedupe_chunk_objects_batch(
     dedupe_conn: &mut DbConn,
     pending_conn: &mut DbConn,
     provider: &str,
+    object_prefix: Option<&str>,
+    hash_alg: ChunkHashAlg,
     chunk_objects: &[ChunkObjectMapping],
 ) -> Result<()> {
-    record_dedupe_chunk_objects_batch_inner(dedupe_conn, provider, chunk_objects).await?;
-    record_dedupe_chunk_objects_batch_inner(pending_conn, provider, chunk_objects).await?;
+    record_dedupe_chunk_objects_batch_inner(
+        dedupe_conn,
+        provider,
+        object_prefix,
+        hash_alg,
+        chunk_objects,
+    )
+    .await?;
+    record_dedupe_chunk_objects_batch_inner(
+        pending_conn,
+        provider,
+        object_prefix,
+        hash_alg,
+        chunk_objects,
+    )
+    .await?;
     Ok(())
 }
 
 async fn record_dedupe_chunk_objects_batch_inner(
     conn: &mut DbConn,
     provider: &str,
+    object_prefix: Option<&str>,
+    hash_alg: ChunkHashAlg,
     chunk_objects: &[ChunkObjectMapping],
 ) -> Result<()> {
     if chunk_objects.is_empty() {
@@ -3374,11 +4746,12 @@ async fn record_dedupe_chunk_objects_batch_inner(
             if let Err(e) = sqlx::query(
                 r#"
                 INSERT OR IGNORE INTO chunks (chunk_hash, size, hash_alg, enc_alg, created_at)
-                VALUES (?, ?, 'blake3', 'xchacha20poly1305', strftime('%Y-%m-%dT%H:%M:%fZ','now'))
+                VALUES (?, ?, ?, 'xchacha20poly1305', strftime('%Y-%m-%dT%H:%M:%fZ','now'))
                 "#,
             )
             .bind(&m.chunk_hash)
             .bind(m.source_bytes as i64)
+            .bind(hash_alg.as_str())
             .execute(&mut *tx)
             .await
             {
@@ -3388,16 +4761,18 @@ async fn record_dedupe_chunk_objects_batch_inner(
 
             if let Err(e) = sqlx::query(
                 r#"
-                INSERT INTO chunk_objects (chunk_hash, provider, object_id, created_at)
-                VALUES (?, ?, ?, strftime('%Y-%m-%dT%H:%M:%fZ','now'))
+                INSERT INTO chunk_objects (chunk_hash, provider, object_id, created_at, object_prefix)
+                VALUES (?, ?, ?, strftime('%Y-%m-%dT%H:%M:%fZ','now'), ?)
                 ON CONFLICT(provider, chunk_hash) DO UPDATE SET
                   object_id = excluded.object_id,
-                  created_at = excluded.created_at
+                  created_at = excluded.created_at,
+                  object_prefix = excluded.object_prefix
                 "#,
             )
             .bind(&m.chunk_hash)
             .bind(provider)
             .bind(&m.object_id)
+            .bind(object_prefix)
             .execute(&mut *tx)
             .await
             {
@@ -3434,9 +4809,12 @@ async fn record_dedupe_chunk_objects_batch_inner(
     }
 }
 
+/// Drops all but the newest `keep_last_snapshots` unpinned snapshots of `source_path`; pinned
+/// snapshots are always kept and do not count towards the limit.
 async fn apply_retention(
     conn: &mut DbConn,
     source_path: &Path,
+    filemap_dir: &Path,
     keep_last_snapshots: u32,
 ) -> Result<Vec<String>> {
     let source = path_to_utf8(source_path)?;
@@ -3444,7 +4822,7 @@ async fn apply_retention(
         r#"
         SELECT snapshot_id
         FROM snapshots
-        WHERE source_path = ?
+        WHERE source_path = ? AND pinned = 0
         ORDER BY created_at DESC
         LIMIT -1 OFFSET ?
         "#,
@@ -3470,7 +4848,15 @@ async fn apply_retention(
     {
         let batch_no = batch_idx + 1;
         let batch_ids = batch.to_vec();
-        apply_retention_snapshot_batch(conn, &source, &batch_ids, batch_no, total_batches).await?;
+        apply_retention_snapshot_batch(
+            conn,
+            &source,
+            filemap_dir,
+            &batch_ids,
+            batch_no,
+            total_batches,
+        )
+        .await?;
     }
 
     Ok(snapshot_ids)
@@ -3502,10 +4888,12 @@ fn cleanup_filemap_cache_best_effort(filemap_dir: &Path, snapshot_ids: &[String]
 async fn apply_retention_snapshot_batch(
     conn: &mut DbConn,
     source_path: &str,
+    filemap_dir: &Path,
     snapshot_ids: &[String],
     batch_no: usize,
     total_batches: usize,
 ) -> Result<()> {
+    release_snapshot_chunk_refs(conn, filemap_dir, snapshot_ids).await?;
     let mut retry_idx = 0usize;
     loop {
         let started = Instant::now();
@@ -3531,18 +4919,21 @@ async fn apply_retention_snapshot_batch(
             Err(e) => return Err(Error::Sqlite(e)),
         };
 
-        let mut retry_err: Option<sqlx::Error> = None;
+        // Kept for `gc undo` until the deletion grace period is over.
+        let mut retry_err = tombstone_snapshots(&mut tx, snapshot_ids).await.err();
         let mut deleted_file_rows = 0u64;
         let mut deleted_chunk_rows = 0u64;
         let mut file_batches = 0usize;
 
-        match delete_files_and_chunks_for_snapshots(&mut tx, snapshot_ids).await {
-            Ok(stats) => {
-                deleted_file_rows = stats.deleted_files;
-                deleted_chunk_rows = stats.deleted_chunks;
-                file_batches = stats.file_batches;
+        if retry_err.is_none() {
+            match delete_files_and_chunks_for_snapshots(&mut tx, snapshot_ids).await {
+                Ok(stats) => {
+                    deleted_file_rows = stats.deleted_files;
+                    deleted_chunk_rows = stats.deleted_chunks;
+                    file_batches = stats.file_batches;
+                }
+                Err(e) => retry_err = Some(e),
             }
-            Err(e) => retry_err = Some(e),
         }
 
         if retry_err.is_none()
@@ -3638,6 +5029,71 @@ async fn apply_retention_snapshot_batch(
     }
 }
 
+/// Takes the chunk references of counted snapshots about to be removed out of
+/// `chunks.ref_count`, one snapshot per transaction; a crash in between leaves the snapshot
+/// uncounted, which readers handle. A snapshot whose file map is neither in the endpoint DB nor
+/// cached cannot be subtracted, so the counts are flagged stale instead.
+async fn release_snapshot_chunk_refs(
+    conn: &mut DbConn,
+    filemap_dir: &Path,
+    snapshot_ids: &[String],
+) -> Result<()> {
+    if !chunk_refs::have_ref_count_column(conn, "main").await? {
+        return Ok(());
+    }
+    for snapshot_id in snapshot_ids {
+        let counted: Option<bool> =
+            sqlx::query_scalar("SELECT chunk_refs_counted FROM snapshots WHERE snapshot_id = ?")
+                .bind(snapshot_id)
+                .fetch_optional(&mut **conn)
+                .await?;
+        if counted != Some(true) {
+            continue;
+        }
+        let schema = if endpoint_db_has_snapshot_filemap(conn, snapshot_id).await? {
+            "main"
+        } else {
+            let path = filemap_dir.join(format!("{snapshot_id}.sqlite"));
+            if !path.exists() {
+                warn!(
+                    event = "chunks.ref_count.stale",
+                    snapshot_id,
+                    reason = "file map not cached",
+                    "chunks.ref_count.stale"
+                );
+                execute_sqlite_with_busy_retry!(
+                    "chunks.ref_count.mark_stale",
+                    chunk_refs::mark_ref_counts_stale(
+                        conn,
+                        &format!("removed snapshot {snapshot_id} without its file map"),
+                    )
+                )?;
+                continue;
+            }
+            attach_db(conn, "refs_fm", &path).await?;
+            "refs_fm"
+        };
+        let res = async {
+            let mut tx =
+                execute_sqlite_with_busy_retry!("chunks.ref_count.begin_tx", conn.begin())?;
+            execute_sqlite_with_busy_retry!(
+                "chunks.ref_count.release",
+                chunk_refs::apply_snapshot_refs(&mut tx, schema, snapshot_id, -1)
+            )?;
+            tx.commit().await?;
+            Ok::<_, Error>(())
+        }
+        .await;
+        if schema != "main" {
+            sqlx::query("DETACH DATABASE refs_fm")
+                .execute(&mut **conn)
+                .await?;
+        }
+        res?;
+    }
+    Ok(())
+}
+
 #[derive(Default)]
 struct RetentionDeleteStats {
     deleted_files: u64,
@@ -3668,6 +5124,35 @@ async fn delete_files_and_chunks_for_snapshots(
     Ok(stats)
 }
 
+/// Copies the snapshots' `snapshots`, `remote_indexes` and `remote_index_parts` rows into the
+/// `deleted_*` tables (`migrations/0016_pending_deletions.sql`), replacing earlier copies.
+async fn tombstone_snapshots(
+    tx: &mut sqlx::Transaction<'_, Sqlite>,
+    snapshot_ids: &[String],
+) -> std::result::Result<(), sqlx::Error> {
+    for sql_prefix in [
+        "DELETE FROM deleted_remote_index_parts WHERE snapshot_id IN (",
+        "DELETE FROM deleted_remote_indexes WHERE snapshot_id IN (",
+        "DELETE FROM deleted_snapshots WHERE snapshot_id IN (",
+        "INSERT INTO deleted_snapshots SELECT * FROM snapshots WHERE snapshot_id IN (",
+        "INSERT INTO deleted_remote_indexes SELECT * FROM remote_indexes WHERE snapshot_id IN (",
+        "INSERT INTO deleted_remote_index_parts SELECT * FROM remote_index_parts WHERE snapshot_id IN (",
+    ] {
+        delete_rows_for_snapshot_ids(tx, sql_prefix, snapshot_ids).await?;
+    }
+    let deleted_at = gc_timestamp(chrono::Utc::now());
+    for snapshot_id in snapshot_ids {
+        sqlx::query(
+            "INSERT OR REPLACE INTO snapshot_tombstones (snapshot_id, deleted_at) VALUES (?, ?)",
+        )
+        .bind(snapshot_id)
+        .bind(&deleted_at)
+        .execute(&mut **tx)
+        .await?;
+    }
+    Ok(())
+}
+
 async fn select_file_ids_for_snapshots(
     tx: &mut sqlx::Transaction<'_, Sqlite>,
     snapshot_ids: &[String],
@@ -3804,10 +5289,14 @@ async fn compact_index_db_if_needed(conn: &mut DbConn, db_path: &Path) {
     }
 }
 
+/// The newest indexed snapshot of `source_path` written under the same key and chunk hash as
+/// `data_key`; only those can be the base of a new snapshot, since their chunk ids are the new
+/// one's.
 async fn latest_snapshot_for_source(
     conn: &mut DbConn,
     source_path: &Path,
     provider: &str,
+    data_key: &DataKey,
 ) -> Result<Option<String>> {
     let source = path_to_utf8(source_path)?;
     let kind = provider_kind(provider);
@@ -3819,6 +5308,9 @@ async fn latest_snapshot_for_source(
         JOIN remote_indexes ri ON ri.snapshot_id = s.snapshot_id
         WHERE s.source_path = ?
           AND (ri.provider = ? OR ri.provider LIKE ?)
+          AND s.key_derivation = ?
+          AND s.key_target_id IS ?
+          AND s.hash_alg = ?
         ORDER BY s.created_at DESC
         LIMIT 1
         "#,
@@ -3826,6 +5318,9 @@ async fn latest_snapshot_for_source(
     .bind(source)
     .bind(provider)
     .bind(like)
+    .bind(data_key.derivation().version())
+    .bind(data_key.target_id())
+    .bind(data_key.chunk_hash_alg().as_str())
     .fetch_optional(&mut **conn)
     .await?;
 
@@ -3852,13 +5347,14 @@ async fn endpoint_db_has_snapshot_filemap(conn: &mut DbConn, snapshot_id: &str)
     Ok(row.is_some())
 }
 
-async fn lookup_remote_index_manifest_object_id(
+/// Manifest object id and (when recorded) manifest hash of a snapshot's remote index.
+async fn lookup_remote_index_manifest(
     conn: &mut DbConn,
     snapshot_id: &str,
     provider: &str,
-) -> Result<Option<String>> {
+) -> Result<Option<(String, Option<String>)>> {
     let row = sqlx::query(
-        "SELECT provider, manifest_object_id FROM remote_indexes WHERE snapshot_id = ? LIMIT 1",
+        "SELECT provider, manifest_object_id, manifest_sha256 FROM remote_indexes WHERE snapshot_id = ? LIMIT 1",
     )
     .bind(snapshot_id)
     .fetch_optional(&mut **conn)
@@ -3870,32 +5366,166 @@ async fn lookup_remote_index_manifest_object_id(
 
     let row_provider: String = row.get("provider");
     let manifest_object_id: String = row.get("manifest_object_id");
+    let manifest_sha256: Option<String> = row.get("manifest_sha256");
 
     if row_provider == provider || provider_kind(&row_provider) == provider_kind(provider) {
-        Ok(Some(manifest_object_id))
+        Ok(Some((manifest_object_id, manifest_sha256)))
     } else {
         Ok(None)
     }
 }
 
+/// Copies the `chunk_objects` rows of the chunks a snapshot's file map references from the DB
+/// that records them into the file map, so the file map alone locates every chunk (used for
+/// snapshots under a target key, whose holder cannot read the endpoint DB).
+async fn copy_filemap_chunk_objects(
+    filemap_db_path: &Path,
+    chunk_objects_db_path: &Path,
+    provider: &str,
+) -> Result<()> {
+    let pool = open_existing_index_db(filemap_db_path).await?;
+    let mut conn: DbConn = pool.acquire().await?;
+    drop(pool);
+    attach_db(&mut conn, "src", chunk_objects_db_path).await?;
+    let copied = sqlx::query(
+        r#"
+        INSERT OR IGNORE INTO chunk_objects (
+          chunk_hash, provider, object_id, created_at, object_prefix
+        )
+        SELECT co.chunk_hash, co.provider, co.object_id, co.created_at, co.object_prefix
+        FROM src.chunk_objects co
+        WHERE co.provider = ?
+          AND co.chunk_hash IN (SELECT chunk_hash FROM chunks)
+        "#,
+    )
+    .bind(provider)
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+    sqlx::query("DETACH DATABASE src")
+        .execute(&mut *conn)
+        .await?;
+    debug!(
+        event = "index.filemap_chunk_objects_copied",
+        rows = copied,
+        "index.filemap_chunk_objects_copied"
+    );
+    Ok(())
+}
+
+/// The index a new snapshot's filemap can be uploaded as a delta against, with the local path of
+/// that index's file map; `None` means upload a full index.
+///
+/// Deltas are only built against the base snapshot's cached file map, and only while the chain
+/// back to the last full index (as recorded in `remote_indexes`) stays below
+/// `index_full_every`. Snapshots under a derived key always upload a full index.
+async fn filemap_delta_parent(
+    conn: &mut DbConn,
+    provider: &str,
+    config: &BackupConfig,
+    snapshot_id: &str,
+) -> Result<Option<(IndexManifestParent, PathBuf)>> {
+    if config.index_full_every <= 1
+        || config
+            .data_key
+            .as_ref()
+            .is_some_and(|k| k.derivation() != KeyDerivation::Master)
+    {
+        return Ok(None);
+    }
+    let base_snapshot_id: Option<String> =
+        sqlx::query("SELECT base_snapshot_id FROM snapshots WHERE snapshot_id = ?")
+            .bind(snapshot_id)
+            .fetch_optional(&mut **conn)
+            .await?
+            .and_then(|row| row.get("base_snapshot_id"));
+    let Some(base_snapshot_id) = base_snapshot_id else {
+        return Ok(None);
+    };
+    let parent_db_path = config
+        .filemap_dir
+        .join(format!("{base_snapshot_id}.sqlite"));
+    if !parent_db_path.exists() {
+        return Ok(None);
+    }
+
+    let mut parent: Option<IndexManifestParent> = None;
+    let mut deltas_in_chain = 0u32;
+    let mut row = sqlx::query(
+        r#"
+        SELECT snapshot_id, provider, manifest_object_id, manifest_sha256, manifest_kind, parent_manifest_object_id
+        FROM remote_indexes
+        WHERE snapshot_id = ?
+        "#,
+    )
+    .bind(&base_snapshot_id)
+    .fetch_optional(&mut **conn)
+    .await?;
+    loop {
+        // Rows pruned by retention or written for another provider leave the chain length
+        // unknown: start a new chain.
+        let Some(r) = row else {
+            return Ok(None);
+        };
+        let row_provider: String = r.get("provider");
+        if row_provider != provider && provider_kind(&row_provider) != provider_kind(provider) {
+            return Ok(None);
+        }
+        if parent.is_none() {
+            let Some(manifest_sha256) = r.get::<Option<String>, _>("manifest_sha256") else {
+                return Ok(None);
+            };
+            parent = Some(IndexManifestParent {
+                snapshot_id: r.get("snapshot_id"),
+                manifest_object_id: r.get("manifest_object_id"),
+                manifest_sha256,
+            });
+        }
+        let kind: String = r.get("manifest_kind");
+        if kind != IndexManifestKind::Delta.as_str() {
+            break;
+        }
+        deltas_in_chain += 1;
+        if deltas_in_chain + 1 >= config.index_full_every {
+            return Ok(None);
+        }
+        let Some(next) = r.get::<Option<String>, _>("parent_manifest_object_id") else {
+            return Ok(None);
+        };
+        row = sqlx::query(
+            r#"
+            SELECT snapshot_id, provider, manifest_object_id, manifest_sha256, manifest_kind, parent_manifest_object_id
+            FROM remote_indexes
+            WHERE manifest_object_id = ?
+            "#,
+        )
+        .bind(next)
+        .fetch_optional(&mut **conn)
+        .await?;
+    }
+    Ok(parent.map(|p| (p, parent_db_path)))
+}
+
 async fn lookup_base_file_snapshot_row(
     conn: &mut DbConn,
     base_snapshot_id: &str,
+    base_columns: &str,
     rel_path: &str,
 ) -> Result<Option<BaseFileSnapshotRow>> {
-    let row = execute_sqlite_with_busy_retry!(
-        "files.lookup_base_snapshot_row",
-        sqlx::query(
-            r#"
-            SELECT file_id, size, mtime_ms, mode
+    let sql = format!(
+        r#"
+            SELECT file_id, size, mtime_ms, mode, {base_columns}
             FROM base.files
             WHERE snapshot_id = ? AND path = ? AND kind = 'file'
             LIMIT 1
-            "#,
-        )
-        .bind(base_snapshot_id)
-        .bind(rel_path)
-        .fetch_optional(&mut **conn)
+            "#
+    );
+    let row = execute_sqlite_with_busy_retry!(
+        "files.lookup_base_snapshot_row",
+        sqlx::query(&sql)
+            .bind(base_snapshot_id)
+            .bind(rel_path)
+            .fetch_optional(&mut **conn)
     )?;
 
     Ok(row.map(|r| BaseFileSnapshotRow {
@@ -3903,6 +5533,9 @@ async fn lookup_base_file_snapshot_row(
         size: r.get::<i64, _>("size"),
         mtime_ms: r.get::<i64, _>("mtime_ms"),
         mode: r.get::<i64, _>("mode"),
+        btime_ms: r.get::<Option<i64>, _>("btime_ms"),
+        owner: file_owner_from_row(&r),
+        content_source: r.get::<Option<String>, _>("content_source"),
     }))
 }
 
@@ -4044,22 +5677,35 @@ fn tgmtproto_peer_from_object_id(object_id: &str) -> Option<String> {
         .map(|v| v.peer)
 }
 
+/// Chunks with an object in `storage`'s chat, from the endpoint-wide `chunk_objects` table: a
+/// chunk uploaded by any target on the endpoint dedupes, if its id was computed the way
+/// `data_key` computes them (dedupe is keyed on `(hash_alg, chunk_hash)`).
 async fn load_chunk_hashes_for_storage<S: Storage>(
     conn: &mut DbConn,
     storage: &S,
     provider: &str,
+    data_key: &DataKey,
 ) -> Result<HashSet<String>> {
     let kind = provider_kind(provider);
     let like = format!("{kind}%");
+    // Chunks of target key snapshots were labelled "blake3" before the label followed the
+    // algorithm, and the migration can only relabel those whose file map is in the same DB. A
+    // keyed id never equals a plain one, so target keys also accept the old label.
+    let legacy_label = data_key.derivation() != KeyDerivation::Master;
     let rows: Vec<sqlx::sqlite::SqliteRow> = sqlx::query(
         r#"
-        SELECT chunk_hash, object_id
-        FROM chunk_objects
-        WHERE provider = ? OR provider LIKE ?
+        SELECT co.chunk_hash AS chunk_hash, co.object_id AS object_id
+        FROM chunk_objects co
+        LEFT JOIN chunks c ON c.chunk_hash = co.chunk_hash
+        WHERE (co.provider = ?1 OR co.provider LIKE ?2)
+          AND (COALESCE(c.hash_alg, 'blake3') = ?3
+               OR (?4 AND COALESCE(c.hash_alg, 'blake3') = 'blake3'))
         "#,
     )
     .bind(provider)
     .bind(&like)
+    .bind(data_key.chunk_hash_alg().as_str())
+    .bind(legacy_label)
     .fetch_all(&mut **conn)
     .await?;
 
@@ -4075,7 +5721,7 @@ async fn load_chunk_hashes_for_storage<S: Storage>(
             let Some(peer) = tgmtproto_peer_from_object_id(&object_id) else {
                 continue;
             };
-            if peer != expected_scope {
+            if peer != expected_scope && !storage.legacy_object_id_scopes().contains(&peer) {
                 continue;
             }
         }
@@ -4115,21 +5761,25 @@ async fn export_endpoint_index_db_for_upload(
 
     // Copy in one transaction to keep export fast.
     let mut tx = conn.begin().await?;
+    // Reference counts only travel with the chunk rows; without them every snapshot arrives
+    // uncounted.
     sqlx::query(
         r#"
-        INSERT INTO snapshots (snapshot_id, created_at, source_path, label, base_snapshot_id)
-        SELECT snapshot_id, created_at, source_path, label, base_snapshot_id
+        INSERT INTO snapshots (snapshot_id, created_at, source_path, label, base_snapshot_id, device_id, device_name, pinned, verified_at, chunk_refs_counted, key_derivation, key_target_id, hash_alg)
+        SELECT snapshot_id, created_at, source_path, label, base_snapshot_id, device_id, device_name, pinned, verified_at,
+               CASE WHEN ? THEN chunk_refs_counted ELSE 0 END, key_derivation, key_target_id, hash_alg
         FROM src.snapshots
         "#,
     )
+    .bind(include_dedupe)
     .execute(&mut *tx)
     .await?;
 
     if include_dedupe {
         sqlx::query(
             r#"
-            INSERT INTO chunks (chunk_hash, size, hash_alg, enc_alg, created_at)
-            SELECT chunk_hash, size, hash_alg, enc_alg, created_at
+            INSERT INTO chunks (chunk_hash, size, hash_alg, enc_alg, created_at, ref_count)
+            SELECT chunk_hash, size, hash_alg, enc_alg, created_at, ref_count
             FROM src.chunks
             "#,
         )
@@ -4138,8 +5788,8 @@ async fn export_endpoint_index_db_for_upload(
 
         sqlx::query(
             r#"
-            INSERT INTO chunk_objects (chunk_hash, provider, object_id, created_at)
-            SELECT chunk_hash, provider, object_id, created_at
+            INSERT INTO chunk_objects (chunk_hash, provider, object_id, created_at, object_prefix)
+            SELECT chunk_hash, provider, object_id, created_at, object_prefix
             FROM src.chunk_objects
             "#,
         )
@@ -4149,8 +5799,12 @@ async fn export_endpoint_index_db_for_upload(
 
     sqlx::query(
         r#"
-        INSERT INTO remote_indexes (snapshot_id, provider, manifest_object_id, created_at)
-        SELECT snapshot_id, provider, manifest_object_id, created_at
+        INSERT INTO remote_indexes (
+          snapshot_id, provider, manifest_object_id, created_at, manifest_sha256,
+          manifest_kind, parent_manifest_object_id, object_prefix
+        )
+        SELECT snapshot_id, provider, manifest_object_id, created_at, manifest_sha256,
+               manifest_kind, parent_manifest_object_id, object_prefix
         FROM src.remote_indexes
         "#,
     )
@@ -4172,8 +5826,11 @@ async fn export_endpoint_index_db_for_upload(
         INSERT INTO endpoint_state (key, value)
         SELECT key, value
         FROM src.endpoint_state
+        WHERE ? OR key != ?
         "#,
     )
+    .bind(include_dedupe)
+    .bind(chunk_refs::CHUNK_REF_COUNTS_STALE_KEY)
     .execute(&mut *tx)
     .await?;
 
@@ -4218,6 +5875,8 @@ async fn publish_remote_dedupe_if_needed<S: Storage>(
     config: &BackupConfig,
     dedupe_conn: &mut DbConn,
     rate_limiter: &UploadRateLimiter,
+    retry: &RetryBudget,
+    checker: &UploadChecker,
     uploaded_bytes: &AtomicU64,
     uploaded_net_bytes: &AtomicU64,
     have_uploaded_net_bytes: &AtomicBool,
@@ -4247,11 +5906,15 @@ async fn publish_remote_dedupe_if_needed<S: Storage>(
                 export_dedupe_db_for_upload(&config.dedupe_db_path, &dedupe_temp_parent).await?;
             let uploaded_base = upload_index_sqlite_db(
                 storage,
-                config,
+                &config.master_key,
+                config.device.as_ref(),
                 &base_id,
+                None,
                 exported_base.path(),
                 &dedupe_temp_parent,
                 rate_limiter,
+                retry,
+                checker,
                 uploaded_bytes,
                 uploaded_net_bytes,
                 have_uploaded_net_bytes,
@@ -4282,8 +5945,11 @@ async fn publish_remote_dedupe_if_needed<S: Storage>(
             };
 
             rate_limiter.wait_turn().await;
-            let catalog_object_id =
-                save_remote_dedupe_catalog(storage, &config.master_key, &cat).await?;
+            let catalog_object_id = retry
+                .run("dedupe_catalog", || {
+                    save_remote_dedupe_catalog(storage, &config.master_key, &cat)
+                })
+                .await?;
 
             // Only clear pending once base+catalog is fully published.
             reset_dedupe_pending_spool_db(&config.dedupe_pending_db_path).await?;
@@ -4363,11 +6029,15 @@ async fn publish_remote_dedupe_if_needed<S: Storage>(
                         .await?;
                 let uploaded_base = upload_index_sqlite_db(
                     storage,
-                    config,
+                    &config.master_key,
+                    config.device.as_ref(),
                     &base_id,
+                    None,
                     exported_base.path(),
                     &dedupe_temp_parent,
                     rate_limiter,
+                    retry,
+                    checker,
                     uploaded_bytes,
                     uploaded_net_bytes,
                     have_uploaded_net_bytes,
@@ -4399,8 +6069,11 @@ async fn publish_remote_dedupe_if_needed<S: Storage>(
                 };
 
                 rate_limiter.wait_turn().await;
-                let new_id =
-                    save_remote_dedupe_catalog(storage, &config.master_key, &new_cat).await?;
+                let new_id = retry
+                    .run("dedupe_catalog", || {
+                        save_remote_dedupe_catalog(storage, &config.master_key, &new_cat)
+                    })
+                    .await?;
 
                 reset_dedupe_pending_spool_db(&config.dedupe_pending_db_path).await?;
 
@@ -4434,11 +6107,15 @@ async fn publish_remote_dedupe_if_needed<S: Storage>(
                     .unwrap_or(0);
                 let uploaded_delta = upload_index_sqlite_db(
                     storage,
-                    config,
+                    &config.master_key,
+                    config.device.as_ref(),
                     &delta_id,
+                    None,
                     exported_delta.path(),
                     &dedupe_temp_parent,
                     rate_limiter,
+                    retry,
+                    checker,
                     uploaded_bytes,
                     uploaded_net_bytes,
                     have_uploaded_net_bytes,
@@ -4467,7 +6144,11 @@ async fn publish_remote_dedupe_if_needed<S: Storage>(
                 });
 
                 rate_limiter.wait_turn().await;
-                let new_id = save_remote_dedupe_catalog(storage, &config.master_key, &cat).await?;
+                let new_id = retry
+                    .run("dedupe_catalog", || {
+                        save_remote_dedupe_catalog(storage, &config.master_key, &cat)
+                    })
+                    .await?;
 
                 reset_dedupe_pending_spool_db(&config.dedupe_pending_db_path).await?;
 
@@ -4563,8 +6244,8 @@ async fn export_dedupe_tables_only_db(
 
         sqlx::query(
             r#"
-            INSERT INTO chunk_objects (chunk_hash, provider, object_id, created_at)
-            SELECT chunk_hash, provider, object_id, created_at
+            INSERT INTO chunk_objects (chunk_hash, provider, object_id, created_at, object_prefix)
+            SELECT chunk_hash, provider, object_id, created_at, object_prefix
             FROM src.chunk_objects
             "#,
         )
@@ -4667,16 +6348,22 @@ fn replace_atomic(tmp: &Path, path: &Path) -> std::io::Result<()> {
 struct UploadedIndex {
     manifest: IndexManifest,
     manifest_object_id: String,
+    manifest_sha256: String,
+    object_prefix: Option<String>,
 }
 
 #[allow(clippy::too_many_arguments)]
 async fn upload_index_sqlite_db<S: Storage>(
     storage: &S,
-    config: &BackupConfig,
+    master_key: &[u8; 32],
+    device: Option<&DeviceIdentity>,
     index_id: &str,
+    parent: Option<IndexManifestParent>,
     sqlite_db_path: &Path,
     temp_parent: &Path,
     rate_limiter: &UploadRateLimiter,
+    retry: &RetryBudget,
+    checker: &UploadChecker,
     uploaded_bytes: &AtomicU64,
     uploaded_net_bytes: &AtomicU64,
     have_uploaded_net_bytes: &AtomicBool,
@@ -4741,20 +6428,23 @@ async fn upload_index_sqlite_db<S: Storage>(
         }
         let part_plain = &part_buf[..filled];
         let aad = index_part_aad(index_id, part_no);
-        let part_enc = encrypt_framed(&config.master_key, aad.as_bytes(), part_plain)?;
+        let part_enc = encrypt_framed(master_key, aad.as_bytes(), part_plain)?;
         let part_hash = blake3::hash(&part_enc).to_hex().to_string();
         let part_len = part_enc.len();
         let part_len_u64 = part_len as u64;
-        let mut object_id: Option<String> = None;
-        for attempt in 1..=UPLOAD_OBJECT_MAX_ATTEMPTS {
+        let mut reuploaded = false;
+        let mut attempt = 0;
+        let object_id = loop {
+            attempt += 1;
             rate_limiter.wait_turn().await;
             let filename = telegram_camouflaged_filename();
             let last_reported = AtomicU64::new(0);
             let last_reported_net = AtomicU64::new(0);
             let upload_res = storage
-                .upload_document_with_progress(
+                .upload_document_stream_with_metadata(
                     &filename,
-                    part_enc.clone(),
+                    Box::new(part_enc.as_slice()),
+                    part_len_u64,
                     Some(Box::new(|p| {
                         let mut progressed = false;
 
@@ -4776,7 +6466,7 @@ async fn upload_index_sqlite_db<S: Storage>(
 
                         if progressed && let Some(sink) = progress {
                             sink.on_progress(TaskProgress {
-                                phase: "index".to_string(),
+                                phase: Phase::Index,
                                 files_total: None,
                                 files_done: Some(files_indexed),
                                 source_files_total,
@@ -4800,10 +6490,16 @@ async fn upload_index_sqlite_db<S: Storage>(
                                     .then_some(uploaded_net_bytes.load(Ordering::Relaxed)),
                                 bytes_downloaded: None,
                                 net_bytes_downloaded: None,
+                                bytes_written: None,
                                 bytes_deduped: Some(bytes_deduped),
+                                bytes_total_estimated: None,
+                                bytes_total: None,
                             });
                         }
                     })),
+                    Some(UploadMetadata {
+                        kind: ObjectKind::IndexPart,
+                    }),
                 )
                 .await;
 
@@ -4813,8 +6509,23 @@ async fn upload_index_sqlite_db<S: Storage>(
                     if reported < part_len_u64 {
                         uploaded_bytes.fetch_add(part_len_u64 - reported, Ordering::Relaxed);
                     }
-                    object_id = Some(uploaded_object_id);
-                    break;
+                    let matches = |bytes: &[u8]| bytes == part_enc.as_slice();
+                    if let Some(reason) = checker
+                        .mismatch(storage, retry, &uploaded_object_id, part_len_u64, matches)
+                        .await?
+                    {
+                        checker.reupload_or_fail(
+                            provider,
+                            ObjectKind::IndexPart,
+                            &uploaded_object_id,
+                            reason,
+                            &mut reuploaded,
+                        )?;
+                        saturating_sub_u64(uploaded_bytes, part_len_u64);
+                        attempt = 0;
+                        continue;
+                    }
+                    break uploaded_object_id;
                 }
                 Err(e) => {
                     let reported = last_reported.load(Ordering::Relaxed).min(part_len_u64);
@@ -4826,8 +6537,7 @@ async fn upload_index_sqlite_db<S: Storage>(
                         saturating_sub_u64(uploaded_net_bytes, reported_net);
                     }
 
-                    if attempt < UPLOAD_OBJECT_MAX_ATTEMPTS && is_retryable_upload_error(&e) {
-                        let backoff = upload_object_retry_backoff(attempt);
+                    if let Some(backoff) = retry.next_backoff(attempt, &e) {
                         warn!(
                             event = "io.telegram.upload_retry",
                             provider,
@@ -4836,12 +6546,12 @@ async fn upload_index_sqlite_db<S: Storage>(
                             part_no,
                             blob_bytes = part_len_u64,
                             attempt,
-                            max_attempts = UPLOAD_OBJECT_MAX_ATTEMPTS,
+                            max_attempts = retry.max_attempts(),
                             backoff_ms = backoff.as_millis() as u64,
                             error = %e,
                             "io.telegram.upload_retry"
                         );
-                        sleep(backoff).await;
+                        retry.wait(backoff).await?;
                         continue;
                     }
 
@@ -4855,23 +6565,16 @@ async fn upload_index_sqlite_db<S: Storage>(
                         error = %e,
                         "io.telegram.upload_failed"
                     );
-                    return Err(Error::Telegram {
-                        message: format!(
-                            "upload failed: kind=index_part snapshot_id={index_id} part_no={part_no} bytes={part_len_u64}; {e}"
-                        ),
-                    });
+                    return Err(Error::telegram(format!(
+                        "upload failed: kind=index_part snapshot_id={index_id} part_no={part_no} bytes={part_len_u64}; {e}"
+                    )));
                 }
             }
-        }
-        let object_id = object_id.ok_or_else(|| Error::Telegram {
-            message: format!(
-                "upload failed: kind=index_part snapshot_id={index_id} part_no={part_no} bytes={part_len_u64}; retry loop exhausted"
-            ),
-        })?;
+        };
         upload_confirmed_bytes.fetch_add(part_len_u64, Ordering::Relaxed);
         if let Some(sink) = progress {
             sink.on_progress(TaskProgress {
-                phase: "index".to_string(),
+                phase: Phase::Index,
                 files_total: None,
                 files_done: Some(files_indexed),
                 source_files_total,
@@ -4889,7 +6592,10 @@ async fn upload_index_sqlite_db<S: Storage>(
                     .then_some(uploaded_net_bytes.load(Ordering::Relaxed)),
                 bytes_downloaded: None,
                 net_bytes_downloaded: None,
+                bytes_written: None,
                 bytes_deduped: Some(bytes_deduped),
+                bytes_total_estimated: None,
+                bytes_total: None,
             });
         }
 
@@ -4903,30 +6609,41 @@ async fn upload_index_sqlite_db<S: Storage>(
     }
 
     let manifest = IndexManifest {
-        version: 1,
+        version: if parent.is_some() {
+            INDEX_MANIFEST_VERSION_DELTA
+        } else {
+            INDEX_MANIFEST_VERSION_FULL
+        },
         snapshot_id: index_id.to_string(),
         hash_alg: "blake3".to_string(),
         enc_alg: "xchacha20poly1305".to_string(),
         compression: "zstd".to_string(),
+        device_id: device.map(|d| d.device_id.clone()),
+        device_name: device.map(|d| d.device_name.clone()),
+        parent,
         parts,
     };
     let manifest_json = serde_json::to_vec(&manifest).map_err(|_| Error::InvalidConfig {
         message: "serialize index manifest failed".to_string(),
     })?;
 
-    let manifest_enc = encrypt_framed(&config.master_key, index_id.as_bytes(), &manifest_json)?;
+    let manifest_enc = encrypt_framed(master_key, index_id.as_bytes(), &manifest_json)?;
+    let manifest_sha256 = crate::remote_index_db::manifest_sha256(&manifest_enc);
     let manifest_bytes = manifest_enc.len() as u64;
     upload_workload_total.fetch_add(manifest_bytes, Ordering::Relaxed);
-    let mut manifest_object_id: Option<String> = None;
-    for attempt in 1..=UPLOAD_OBJECT_MAX_ATTEMPTS {
+    let mut reuploaded = false;
+    let mut attempt = 0;
+    let manifest_object_id = loop {
+        attempt += 1;
         rate_limiter.wait_turn().await;
         let manifest_filename = telegram_camouflaged_filename();
         let last_reported = AtomicU64::new(0);
         let last_reported_net = AtomicU64::new(0);
         let upload_res = storage
-            .upload_document_with_progress(
+            .upload_document_stream_with_metadata(
                 &manifest_filename,
-                manifest_enc.clone(),
+                Box::new(manifest_enc.as_slice()),
+                manifest_bytes,
                 Some(Box::new(|p| {
                     let mut progressed = false;
 
@@ -4948,7 +6665,7 @@ async fn upload_index_sqlite_db<S: Storage>(
 
                     if progressed && let Some(sink) = progress {
                         sink.on_progress(TaskProgress {
-                            phase: "index".to_string(),
+                            phase: Phase::Index,
                             files_total: None,
                             files_done: Some(files_indexed),
                             source_files_total,
@@ -4968,10 +6685,16 @@ async fn upload_index_sqlite_db<S: Storage>(
                                 .then_some(uploaded_net_bytes.load(Ordering::Relaxed)),
                             bytes_downloaded: None,
                             net_bytes_downloaded: None,
+                            bytes_written: None,
                             bytes_deduped: Some(bytes_deduped),
+                            bytes_total_estimated: None,
+                            bytes_total: None,
                         });
                     }
                 })),
+                Some(UploadMetadata {
+                    kind: ObjectKind::IndexManifest,
+                }),
             )
             .await;
 
@@ -4981,8 +6704,29 @@ async fn upload_index_sqlite_db<S: Storage>(
                 if reported < manifest_bytes {
                     uploaded_bytes.fetch_add(manifest_bytes - reported, Ordering::Relaxed);
                 }
-                manifest_object_id = Some(uploaded_manifest_object_id);
-                break;
+                let matches = |bytes: &[u8]| bytes == manifest_enc.as_slice();
+                if let Some(reason) = checker
+                    .mismatch(
+                        storage,
+                        retry,
+                        &uploaded_manifest_object_id,
+                        manifest_bytes,
+                        matches,
+                    )
+                    .await?
+                {
+                    checker.reupload_or_fail(
+                        provider,
+                        ObjectKind::IndexManifest,
+                        &uploaded_manifest_object_id,
+                        reason,
+                        &mut reuploaded,
+                    )?;
+                    saturating_sub_u64(uploaded_bytes, manifest_bytes);
+                    attempt = 0;
+                    continue;
+                }
+                break uploaded_manifest_object_id;
             }
             Err(e) => {
                 let reported = last_reported.load(Ordering::Relaxed).min(manifest_bytes);
@@ -4994,8 +6738,7 @@ async fn upload_index_sqlite_db<S: Storage>(
                     saturating_sub_u64(uploaded_net_bytes, reported_net);
                 }
 
-                if attempt < UPLOAD_OBJECT_MAX_ATTEMPTS && is_retryable_upload_error(&e) {
-                    let backoff = upload_object_retry_backoff(attempt);
+                if let Some(backoff) = retry.next_backoff(attempt, &e) {
                     warn!(
                         event = "io.telegram.upload_retry",
                         provider,
@@ -5003,12 +6746,12 @@ async fn upload_index_sqlite_db<S: Storage>(
                         snapshot_id = index_id,
                         blob_bytes = manifest_bytes,
                         attempt,
-                        max_attempts = UPLOAD_OBJECT_MAX_ATTEMPTS,
+                        max_attempts = retry.max_attempts(),
                         backoff_ms = backoff.as_millis() as u64,
                         error = %e,
                         "io.telegram.upload_retry"
                     );
-                    sleep(backoff).await;
+                    retry.wait(backoff).await?;
                     continue;
                 }
 
@@ -5022,24 +6765,17 @@ async fn upload_index_sqlite_db<S: Storage>(
                     error = %e,
                     "io.telegram.upload_failed"
                 );
-                return Err(Error::Telegram {
-                    message: format!(
-                        "upload failed: kind=index_manifest snapshot_id={index_id} bytes={manifest_bytes}; {e}"
-                    ),
-                });
+                return Err(Error::telegram(format!(
+                    "upload failed: kind=index_manifest snapshot_id={index_id} bytes={manifest_bytes}; {e}"
+                )));
             }
         }
-    }
-    let manifest_object_id = manifest_object_id.ok_or_else(|| Error::Telegram {
-        message: format!(
-            "upload failed: kind=index_manifest snapshot_id={index_id} bytes={manifest_bytes}; retry loop exhausted"
-        ),
-    })?;
+    };
 
     upload_confirmed_bytes.fetch_add(manifest_bytes, Ordering::Relaxed);
     if let Some(sink) = progress {
         sink.on_progress(TaskProgress {
-            phase: "index".to_string(),
+            phase: Phase::Index,
             files_total: None,
             files_done: Some(files_indexed),
             source_files_total,
@@ -5057,22 +6793,65 @@ async fn upload_index_sqlite_db<S: Storage>(
                 .then_some(uploaded_net_bytes.load(Ordering::Relaxed)),
             bytes_downloaded: None,
             net_bytes_downloaded: None,
+            bytes_written: None,
             bytes_deduped: Some(bytes_deduped),
+            bytes_total_estimated: None,
+            bytes_total: None,
         });
     }
 
     Ok(UploadedIndex {
         manifest,
         manifest_object_id,
+        manifest_sha256,
+        object_prefix: storage.object_prefix().map(str::to_string),
     })
 }
 
+/// Records a snapshot's uploaded index in the endpoint DB. With `counted_filemap` (a backup
+/// committing its snapshot) the file map's chunk references go into `chunks.ref_count` in the
+/// same transaction, so the snapshot ends up either committed and counted or neither.
 async fn persist_snapshot_remote_index_meta(
     conn: &mut DbConn,
     provider: &str,
     snapshot_id: &str,
     uploaded: &UploadedIndex,
+    counted_filemap: Option<&Path>,
+) -> Result<()> {
+    if let Some(path) = counted_filemap {
+        attach_db(conn, "refs_fm", path).await?;
+    }
+    let res = persist_snapshot_remote_index_meta_tx(
+        conn,
+        provider,
+        snapshot_id,
+        uploaded,
+        counted_filemap.is_some(),
+    )
+    .await;
+    if counted_filemap.is_some() {
+        sqlx::query("DETACH DATABASE refs_fm")
+            .execute(&mut **conn)
+            .await?;
+    }
+    res
+}
+
+async fn persist_snapshot_remote_index_meta_tx(
+    conn: &mut DbConn,
+    provider: &str,
+    snapshot_id: &str,
+    uploaded: &UploadedIndex,
+    count_refs: bool,
 ) -> Result<()> {
+    let mut tx = execute_sqlite_with_busy_retry!("remote_indexes.begin_tx", conn.begin())?;
+    // A republished index may have fewer parts than the one it replaces.
+    execute_sqlite_with_busy_retry!(
+        "remote_index_parts.delete",
+        sqlx::query("DELETE FROM remote_index_parts WHERE snapshot_id = ?")
+            .bind(snapshot_id)
+            .execute(&mut *tx)
+    )?;
     for part in &uploaded.manifest.parts {
         execute_sqlite_with_busy_retry!(
             "remote_index_parts.upsert",
@@ -5088,7 +6867,7 @@ async fn persist_snapshot_remote_index_meta(
             .bind(&part.object_id)
             .bind(part.size as i64)
             .bind(&part.hash)
-            .execute(&mut **conn)
+            .execute(&mut *tx)
         )?;
     }
 
@@ -5096,154 +6875,1854 @@ async fn persist_snapshot_remote_index_meta(
         "remote_indexes.upsert",
         sqlx::query(
             r#"
-            INSERT OR REPLACE INTO remote_indexes (snapshot_id, provider, manifest_object_id, created_at)
-            VALUES (?, ?, ?, strftime('%Y-%m-%dT%H:%M:%fZ','now'))
+            INSERT OR REPLACE INTO remote_indexes (
+              snapshot_id, provider, manifest_object_id, created_at, manifest_sha256,
+              manifest_kind, parent_manifest_object_id, object_prefix
+            )
+            VALUES (?, ?, ?, strftime('%Y-%m-%dT%H:%M:%fZ','now'), ?, ?, ?, ?)
             "#,
         )
         .bind(snapshot_id)
         .bind(provider)
         .bind(&uploaded.manifest_object_id)
-        .execute(&mut **conn)
+        .bind(&uploaded.manifest_sha256)
+        .bind(uploaded.manifest.kind().as_str())
+        .bind(
+            uploaded
+                .manifest
+                .parent
+                .as_ref()
+                .map(|p| p.manifest_object_id.as_str()),
+        )
+        .bind(uploaded.object_prefix.as_deref())
+        .execute(&mut *tx)
     )?;
 
+    if count_refs {
+        execute_sqlite_with_busy_retry!(
+            "chunks.ref_count.add",
+            chunk_refs::apply_snapshot_refs(&mut tx, "refs_fm", snapshot_id, 1)
+        )?;
+    }
+    tx.commit().await?;
     Ok(())
 }
 
-fn path_to_utf8(path: &Path) -> Result<String> {
-    path.to_str()
-        .map(|s| s.to_string())
-        .ok_or_else(|| Error::NonUtf8Path {
-            path: path.to_path_buf(),
-        })
+#[derive(Debug, Clone)]
+pub struct RepublishedIndex {
+    pub snapshot_id: String,
+    pub manifest_object_id: String,
+    pub manifest_sha256: String,
+    pub index_parts: u64,
+    /// Manifest the endpoint DB pointed at before.
+    pub replaced_manifest_object_id: Option<String>,
 }
 
-#[cfg(test)]
-mod tests {
-    use std::io;
-
-    use ignore::Error as IgnoreError;
-    use sqlx::Row;
-
-    use super::{
-        error_has_flood_wait, export_endpoint_index_db_for_upload,
-        ignore_error_is_non_root_not_found,
+/// Uploads a snapshot's cached file map (`<filemap_dir>/<snapshot_id>.sqlite`) as a full index
+/// and points the endpoint DB at it (`televybackup index republish`), e.g. after a delta index it
+/// builds on went missing. Later backups build their deltas on the new index.
+pub async fn republish_snapshot_index<S: Storage>(
+    storage: &S,
+    endpoint_db_path: &Path,
+    filemap_db_path: &Path,
+    snapshot_id: &str,
+    master_key: &[u8; 32],
+    device: Option<&DeviceIdentity>,
+) -> Result<RepublishedIndex> {
+    if !filemap_db_path.exists() {
+        return Err(Error::InvalidConfig {
+            message: format!(
+                "no local file map for snapshot {snapshot_id}: {}",
+                filemap_db_path.display()
+            ),
+        });
+    }
+    let filemap_pool = open_existing_index_db(filemap_db_path).await?;
+    let data_key = {
+        let mut filemap_conn = filemap_pool.acquire().await?;
+        crate::index_db::snapshot_data_key(&mut filemap_conn, master_key, snapshot_id).await?
+    };
+    filemap_pool.close().await;
+    let Some(data_key) = data_key else {
+        return Err(Error::Integrity {
+            message: format!(
+                "local file map does not hold snapshot {snapshot_id}: {}",
+                filemap_db_path.display()
+            ),
+        });
     };
-    use crate::Error;
 
-    #[test]
-    fn flood_wait_detection_matches_regular_and_premium() {
-        assert!(error_has_flood_wait(&Error::Telegram {
-            message: "rpc error: FLOOD_WAIT_12".to_string(),
-        }));
-        assert!(error_has_flood_wait(&Error::Telegram {
-            message: "rpc error: FLOOD_PREMIUM_WAIT_34".to_string(),
-        }));
+    let provider = storage.provider();
+    let pool = open_existing_index_db(endpoint_db_path).await?;
+    let mut conn: DbConn = pool.acquire().await?;
+    drop(pool);
+    let replaced = lookup_remote_index_manifest(&mut conn, snapshot_id, provider).await?;
 
-        // Some errors include "flood wait" in a human-readable form.
-        assert!(error_has_flood_wait(&Error::Telegram {
-            message: "rpc error 420: flood wait (value: 5)".to_string(),
-        }));
-        assert!(error_has_flood_wait(&Error::Telegram {
-            message: "rpc error 420: flood premium wait (value: 5)".to_string(),
-        }));
+    let temp_parent = filemap_db_path
+        .parent()
+        .map(std::path::Path::to_path_buf)
+        .unwrap_or_else(std::env::temp_dir);
+    let rate_limiter = UploadRateLimiter::new(0, 0, ADAPTIVE_MAX_DELAY_MS);
+    let uploaded = upload_index_sqlite_db(
+        storage,
+        data_key.key(),
+        device,
+        snapshot_id,
+        None,
+        filemap_db_path,
+        &temp_parent,
+        &rate_limiter,
+        &RetryBudget::new(&Retry::default(), None),
+        &UploadChecker::default(),
+        &AtomicU64::new(0),
+        &AtomicU64::new(0),
+        &AtomicBool::new(false),
+        &AtomicU64::new(0),
+        &AtomicU64::new(0),
+        None,
+        None,
+        None,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+    )
+    .await?;
+    persist_snapshot_remote_index_meta(&mut conn, provider, snapshot_id, &uploaded, None).await?;
+    info!(
+        event = "index.republished",
+        snapshot_id,
+        manifest_object_id = %uploaded.manifest_object_id,
+        index_parts = uploaded.manifest.parts.len(),
+        "index.republished"
+    );
 
-        assert!(!error_has_flood_wait(&Error::Telegram {
-            message: "AUTH_KEY_UNREGISTERED".to_string(),
-        }));
+    Ok(RepublishedIndex {
+        snapshot_id: snapshot_id.to_string(),
+        index_parts: uploaded.manifest.parts.len() as u64,
+        manifest_object_id: uploaded.manifest_object_id,
+        manifest_sha256: uploaded.manifest_sha256,
+        replaced_manifest_object_id: replaced.map(|(object_id, _)| object_id),
+    })
+}
+
+/// Pins or unpins a snapshot in the index DB at `db_path` (`televybackup snapshots pin`).
+/// Retention never removes pinned snapshots. Returns `false` when the DB has no such snapshot.
+pub async fn set_snapshot_pinned(db_path: &Path, snapshot_id: &str, pinned: bool) -> Result<bool> {
+    let pool = open_index_db(db_path).await?;
+    let updated = sqlx::query("UPDATE snapshots SET pinned = ? WHERE snapshot_id = ?")
+        .bind(pinned)
+        .bind(snapshot_id)
+        .execute(&pool)
+        .await?
+        .rows_affected();
+    pool.close().await;
+    if updated > 0 {
+        info!(
+            event = "snapshots.pin_changed",
+            snapshot_id, pinned, "snapshots.pin_changed"
+        );
     }
+    Ok(updated > 0)
+}
 
-    #[test]
-    fn non_root_not_found_without_path_is_skipped_only_when_root_exists() {
-        let temp = tempfile::tempdir().unwrap();
-        let root_exists = temp.path().join("source");
-        std::fs::create_dir_all(&root_exists).unwrap();
-        let root_missing = temp.path().join("missing");
+/// Records a successful `verify` of a snapshot in the index DB at `db_path` (`verified_at`, an
+/// RFC3339 instant). Returns `false` when the DB has no such snapshot.
+pub async fn record_snapshot_verified(
+    db_path: &Path,
+    snapshot_id: &str,
+    verified_at: &str,
+) -> Result<bool> {
+    let pool = open_index_db(db_path).await?;
+    let updated = sqlx::query("UPDATE snapshots SET verified_at = ? WHERE snapshot_id = ?")
+        .bind(verified_at)
+        .bind(snapshot_id)
+        .execute(&pool)
+        .await?
+        .rows_affected();
+    pool.close().await;
+    Ok(updated > 0)
+}
 
-        let non_root_not_found = IgnoreError::WithDepth {
-            depth: 1,
-            err: Box::new(IgnoreError::Io(io::Error::from(io::ErrorKind::NotFound))),
-        };
-        let root_not_found = IgnoreError::WithDepth {
-            depth: 0,
-            err: Box::new(IgnoreError::Io(io::Error::from(io::ErrorKind::NotFound))),
-        };
+/// The latest snapshot of `source_path` in the index DB at `db_path` and when any of its snapshots
+/// last verified successfully; `None` when the DB has no snapshot of it.
+pub async fn snapshot_verify_state(
+    db_path: &Path,
+    source_path: &str,
+) -> Result<Option<SnapshotVerifyState>> {
+    let pool = open_index_db(db_path).await?;
+    let row = sqlx::query(
+        r#"
+        SELECT
+          (SELECT snapshot_id FROM snapshots WHERE source_path = ?1
+             ORDER BY created_at DESC, snapshot_id DESC LIMIT 1) AS latest_snapshot_id,
+          (SELECT MAX(verified_at) FROM snapshots WHERE source_path = ?1) AS last_verified_at
+        "#,
+    )
+    .bind(source_path)
+    .fetch_one(&pool)
+    .await?;
+    pool.close().await;
+    let Some(latest_snapshot_id) = row.get::<Option<String>, _>("latest_snapshot_id") else {
+        return Ok(None);
+    };
+    Ok(Some(SnapshotVerifyState {
+        latest_snapshot_id,
+        last_verified_at: row.get("last_verified_at"),
+    }))
+}
 
-        assert!(ignore_error_is_non_root_not_found(
-            &non_root_not_found,
-            &root_exists,
-        ));
-        assert!(!ignore_error_is_non_root_not_found(
-            &non_root_not_found,
-            &root_missing,
-        ));
-        assert!(!ignore_error_is_non_root_not_found(
-            &root_not_found,
-            &root_exists,
-        ));
-    }
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotVerifyState {
+    pub latest_snapshot_id: String,
+    /// RFC3339; see [`record_snapshot_verified`].
+    pub last_verified_at: Option<String>,
+}
 
-    #[tokio::test]
-    async fn endpoint_index_export_excludes_file_maps() {
-        let dir = tempfile::tempdir().unwrap();
-        let source_db = dir.path().join("source.sqlite");
+/// Removes a snapshot from the index DB at `db_path` along with its cached file map under
+/// `filemap_dir` (`televybackup snapshots delete`); its objects stay in the chat. A pinned
+/// snapshot is refused with [`Error::SnapshotPinned`] unless `force` is set. Returns `false` when
+/// the DB has no such snapshot.
+pub async fn delete_snapshot(
+    db_path: &Path,
+    filemap_dir: &Path,
+    snapshot_id: &str,
+    force: bool,
+) -> Result<bool> {
+    let pool = open_index_db(db_path).await?;
+    let mut conn: DbConn = pool.acquire().await?;
+    drop(pool);
+    let Some(row) = sqlx::query("SELECT source_path, pinned FROM snapshots WHERE snapshot_id = ?")
+        .bind(snapshot_id)
+        .fetch_optional(&mut *conn)
+        .await?
+    else {
+        return Ok(false);
+    };
+    let pinned: bool = row.get("pinned");
+    if pinned && !force {
+        return Err(Error::SnapshotPinned {
+            snapshot_id: snapshot_id.to_string(),
+        });
+    }
+    let source_path: String = row.get("source_path");
+    let snapshot_ids = [snapshot_id.to_string()];
+    apply_retention_snapshot_batch(&mut conn, &source_path, filemap_dir, &snapshot_ids, 1, 1)
+        .await?;
+    cleanup_filemap_cache_best_effort(filemap_dir, &snapshot_ids);
+    info!(
+        event = "snapshots.deleted",
+        snapshot_id, source_path, pinned, "snapshots.deleted"
+    );
+    Ok(true)
+}
 
-        let pool = crate::index_db::open_index_db(&source_db).await.unwrap();
+/// One snapshot in a [`SnapshotChain`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotChainLink {
+    pub snapshot_id: String,
+    pub created_at: String,
+    pub base_snapshot_id: Option<String>,
+    /// Bytes of the distinct chunks this snapshot references and its base doesn't (all of them
+    /// without a base); `None` when a file map involved isn't available locally.
+    pub bytes_new: Option<u64>,
+}
 
-        // Two sources, two snapshots each.
-        for (snapshot_id, created_at, source_path) in [
-            ("snp_a1", "2026-01-01T00:00:00Z", "/a"),
-            ("snp_a2", "2026-01-02T00:00:00Z", "/a"),
-            ("snp_b1", "2026-01-01T00:00:00Z", "/b"),
-            ("snp_b2", "2026-01-03T00:00:00Z", "/b"),
-        ] {
-            sqlx::query(
-                "INSERT INTO snapshots (snapshot_id, created_at, source_path, label, base_snapshot_id) VALUES (?, ?, ?, 'test', NULL)",
-            )
-            .bind(snapshot_id)
-            .bind(created_at)
-            .bind(source_path)
-            .execute(&pool)
-            .await
-            .unwrap();
+/// A snapshot followed by its bases, newest first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotChain {
+    pub links: Vec<SnapshotChainLink>,
+    /// Base named by the last link that the index no longer has.
+    pub missing_base: Option<String>,
+}
 
-            sqlx::query(
-                "INSERT INTO remote_indexes (snapshot_id, provider, manifest_object_id, created_at) VALUES (?, 'telegram.mtproto/test', ?, ?)",
-            )
-            .bind(snapshot_id)
-            .bind(format!("man_{snapshot_id}"))
-            .bind(created_at)
-            .execute(&pool)
-            .await
-            .unwrap();
+/// Follows `base_snapshot_id` from `snapshot_id` in the index DB at `db_path`
+/// (`televybackup snapshots chain`). Returns `None` when the DB has no such snapshot.
+pub async fn snapshot_chain(
+    db_path: &Path,
+    filemap_dir: &Path,
+    snapshot_id: &str,
+) -> Result<Option<SnapshotChain>> {
+    let pool = open_index_db(db_path).await?;
+    let mut conn: DbConn = pool.acquire().await?;
+    drop(pool);
 
-            sqlx::query(
-                "INSERT INTO remote_index_parts (snapshot_id, part_no, provider, object_id, size, hash) VALUES (?, 0, 'telegram.mtproto/test', ?, 1, 'h')",
-            )
-            .bind(snapshot_id)
-            .bind(format!("part_{snapshot_id}"))
-            .execute(&pool)
-            .await
-            .unwrap();
+    let mut links = Vec::new();
+    let mut missing_base = None;
+    let mut next = Some(snapshot_id.to_string());
+    let mut seen = HashSet::new();
+    while let Some(id) = next.take() {
+        if !seen.insert(id.clone()) {
+            return Err(Error::Integrity {
+                message: format!("base snapshot chain of {snapshot_id} loops at {id}"),
+            });
         }
+        let Some(row) =
+            sqlx::query("SELECT created_at, base_snapshot_id FROM snapshots WHERE snapshot_id = ?")
+                .bind(&id)
+                .fetch_optional(&mut *conn)
+                .await?
+        else {
+            if links.is_empty() {
+                return Ok(None);
+            }
+            missing_base = Some(id);
+            break;
+        };
+        let base_snapshot_id: Option<String> = row.get("base_snapshot_id");
+        next = base_snapshot_id.clone();
+        links.push(SnapshotChainLink {
+            snapshot_id: id,
+            created_at: row.get("created_at"),
+            base_snapshot_id,
+            bytes_new: None,
+        });
+    }
 
-        // One file per snapshot; only the latest per source should remain in the export.
-        for (snapshot_id, file_id, chunk_hash) in [
-            ("snp_a1", "f_a1", "chk_a1"),
-            ("snp_a2", "f_a2", "chk_a2"),
-            ("snp_b1", "f_b1", "chk_b1"),
-            ("snp_b2", "f_b2", "chk_b2"),
-        ] {
-            sqlx::query(
-                "INSERT INTO files (file_id, snapshot_id, path, size, mtime_ms, mode, kind) VALUES (?, ?, 'x.txt', 1, 0, 0, 'file')",
-            )
-            .bind(file_id)
+    let mut chunks = Vec::with_capacity(links.len());
+    for link in &links {
+        chunks.push(local_snapshot_chunk_sizes(&mut conn, filemap_dir, &link.snapshot_id).await?);
+    }
+    // The last link's base, when it has one, is the missing one.
+    let last = links.len() - 1;
+    for (i, link) in links.iter_mut().enumerate() {
+        let Some(own) = &chunks[i] else {
+            continue;
+        };
+        link.bytes_new = if link.base_snapshot_id.is_none() {
+            Some(own.values().sum())
+        } else if i < last {
+            chunks[i + 1].as_ref().map(|base| {
+                own.iter()
+                    .filter(|(hash, _)| !base.contains_key(*hash))
+                    .map(|(_, len)| *len)
+                    .sum()
+            })
+        } else {
+            None
+        };
+    }
+    Ok(Some(SnapshotChain {
+        links,
+        missing_base,
+    }))
+}
+
+/// Distinct chunks of a snapshot with their sizes, from its cached file map or (for indexes
+/// written before per-snapshot file maps) the endpoint DB; `None` when neither has it.
+async fn local_snapshot_chunk_sizes(
+    conn: &mut DbConn,
+    filemap_dir: &Path,
+    snapshot_id: &str,
+) -> Result<Option<HashMap<String, u64>>> {
+    const SQL: &str = r#"
+        SELECT fc.chunk_hash AS chunk_hash, MAX(fc.len) AS len
+        FROM file_chunks fc
+        JOIN files f ON f.file_id = fc.file_id
+        WHERE f.snapshot_id = ?
+        GROUP BY fc.chunk_hash
+        "#;
+    let filemap_path = filemap_dir.join(format!("{snapshot_id}.sqlite"));
+    let rows = if filemap_path.exists() {
+        let pool = open_existing_index_db(&filemap_path).await?;
+        let rows = sqlx::query(SQL).bind(snapshot_id).fetch_all(&pool).await?;
+        pool.close().await;
+        rows
+    } else if endpoint_db_has_snapshot_filemap(conn, snapshot_id).await? {
+        sqlx::query(SQL)
             .bind(snapshot_id)
-            .execute(&pool)
-            .await
-            .unwrap();
+            .fetch_all(&mut **conn)
+            .await?
+    } else {
+        return Ok(None);
+    };
+    Ok(Some(
+        rows.into_iter()
+            .map(|r| {
+                (
+                    r.get::<String, _>("chunk_hash"),
+                    r.get::<i64, _>("len").max(0) as u64,
+                )
+            })
+            .collect(),
+    ))
+}
 
-            sqlx::query(
-                "INSERT INTO chunks (chunk_hash, size, hash_alg, enc_alg, created_at) VALUES (?, 1, 'blake3', 'xchacha20poly1305', '2026-01-01T00:00:00Z')",
+/// Points a snapshot's `base_snapshot_id` at `onto` (`None` makes it self-contained) in the
+/// index DB at `db_path` (`televybackup snapshots rebase`), e.g. after the base was deleted.
+///
+/// Restores never go through the base: chunks are content-addressed, and a delta index records
+/// its parent manifest separately. Rebasing only changes what the snapshot's new bytes are
+/// counted against. A new base must be an older snapshot of the same source, and every chunk the
+/// snapshot references must still have an object, checked against its local file map. Returns
+/// `false` when the DB has no such snapshot.
+pub async fn rebase_snapshot(
+    db_path: &Path,
+    filemap_dir: &Path,
+    snapshot_id: &str,
+    onto: Option<&str>,
+) -> Result<bool> {
+    let pool = open_index_db(db_path).await?;
+    let mut conn: DbConn = pool.acquire().await?;
+    drop(pool);
+
+    let Some(row) =
+        sqlx::query("SELECT source_path, created_at FROM snapshots WHERE snapshot_id = ?")
+            .bind(snapshot_id)
+            .fetch_optional(&mut *conn)
+            .await?
+    else {
+        return Ok(false);
+    };
+    let source_path: String = row.get("source_path");
+    let created_at: String = row.get("created_at");
+
+    if let Some(onto) = onto {
+        let Some(base) =
+            sqlx::query("SELECT source_path, created_at FROM snapshots WHERE snapshot_id = ?")
+                .bind(onto)
+                .fetch_optional(&mut *conn)
+                .await?
+        else {
+            return Err(Error::InvalidConfig {
+                message: format!("rebase target not found in this index: {onto}"),
+            });
+        };
+        let base_source: String = base.get("source_path");
+        let base_created_at: String = base.get("created_at");
+        if base_source != source_path {
+            return Err(Error::InvalidConfig {
+                message: format!(
+                    "rebase target {onto} is a snapshot of {base_source}, not {source_path}"
+                ),
+            });
+        }
+        // Bases are always older, which also rules out cycles.
+        if base_created_at >= created_at {
+            return Err(Error::InvalidConfig {
+                message: format!("rebase target {onto} is not older than {snapshot_id}"),
+            });
+        }
+        if let Some(chunk_hash) =
+            first_unreachable_chunk(&mut conn, filemap_dir, snapshot_id).await?
+        {
+            return Err(Error::MissingChunkObject { chunk_hash });
+        }
+    }
+
+    sqlx::query("UPDATE snapshots SET base_snapshot_id = ? WHERE snapshot_id = ?")
+        .bind(onto)
+        .bind(snapshot_id)
+        .execute(&mut *conn)
+        .await?;
+    info!(
+        event = "snapshots.rebased",
+        snapshot_id,
+        onto = onto.unwrap_or("none"),
+        "snapshots.rebased"
+    );
+    Ok(true)
+}
+
+/// A chunk of the snapshot with no object in the endpoint DB or the file map; errors when the
+/// snapshot's file map isn't available locally.
+async fn first_unreachable_chunk(
+    conn: &mut DbConn,
+    filemap_dir: &Path,
+    snapshot_id: &str,
+) -> Result<Option<String>> {
+    let filemap_path = filemap_dir.join(format!("{snapshot_id}.sqlite"));
+    if filemap_path.exists() {
+        attach_db(conn, "rebase_fm", &filemap_path).await?;
+        let res = sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT fc.chunk_hash
+            FROM rebase_fm.file_chunks fc
+            JOIN rebase_fm.files f ON f.file_id = fc.file_id
+            WHERE f.snapshot_id = ?
+              AND NOT EXISTS (SELECT 1 FROM main.chunk_objects co WHERE co.chunk_hash = fc.chunk_hash)
+              AND NOT EXISTS (SELECT 1 FROM rebase_fm.chunk_objects co WHERE co.chunk_hash = fc.chunk_hash)
+            LIMIT 1
+            "#,
+        )
+        .bind(snapshot_id)
+        .fetch_optional(&mut **conn)
+        .await;
+        sqlx::query("DETACH DATABASE rebase_fm")
+            .execute(&mut **conn)
+            .await?;
+        return Ok(res?);
+    }
+    if endpoint_db_has_snapshot_filemap(conn, snapshot_id).await? {
+        return Ok(sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT fc.chunk_hash
+            FROM file_chunks fc
+            JOIN files f ON f.file_id = fc.file_id
+            WHERE f.snapshot_id = ?
+              AND NOT EXISTS (SELECT 1 FROM chunk_objects co WHERE co.chunk_hash = fc.chunk_hash)
+            LIMIT 1
+            "#,
+        )
+        .bind(snapshot_id)
+        .fetch_optional(&mut **conn)
+        .await?);
+    }
+    Err(Error::InvalidConfig {
+        message: format!(
+            "no local file map for snapshot {snapshot_id}; its chunks can't be checked (rebase onto none needs no check)"
+        ),
+    })
+}
+
+/// Uploads the local dedupe DB as a new remote dedupe base with an empty catalog and records the
+/// catalog in the dedupe DB's `endpoint_state` (`televybackup gc run`), so other machines stop
+/// deduplicating against collected objects. The pending spool is cleared once published. Returns
+/// the new catalog's object id for the bootstrap catalog.
+pub async fn republish_dedupe_base<S: Storage>(
+    storage: &S,
+    dedupe_db_path: &Path,
+    dedupe_pending_db_path: &Path,
+    endpoint_dedupe_id: &str,
+    master_key: &[u8; 32],
+    device: Option<&DeviceIdentity>,
+) -> Result<String> {
+    let temp_parent = dedupe_db_path
+        .parent()
+        .map(std::path::Path::to_path_buf)
+        .unwrap_or_else(std::env::temp_dir);
+    let base_id = dedupe_base_id_for_storage(storage);
+    let exported_base = export_dedupe_db_for_upload(dedupe_db_path, &temp_parent).await?;
+    let rate_limiter = UploadRateLimiter::new(0, 0, ADAPTIVE_MAX_DELAY_MS);
+    let retry = RetryBudget::new(&Retry::default(), None);
+    let uploaded_base = upload_index_sqlite_db(
+        storage,
+        master_key,
+        device,
+        &base_id,
+        None,
+        exported_base.path(),
+        &temp_parent,
+        &rate_limiter,
+        &retry,
+        &UploadChecker::default(),
+        &AtomicU64::new(0),
+        &AtomicU64::new(0),
+        &AtomicBool::new(false),
+        &AtomicU64::new(0),
+        &AtomicU64::new(0),
+        None,
+        None,
+        None,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+    )
+    .await?;
+
+    let cat = DedupeCatalogV1 {
+        version: DEDUPE_CATALOG_VERSION,
+        updated_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        endpoint_dedupe_id: endpoint_dedupe_id.to_string(),
+        base: DedupeCatalogBase {
+            base_id,
+            manifest_object_id: uploaded_base.manifest_object_id.clone(),
+        },
+        deltas: Vec::new(),
+    };
+    let catalog_object_id = retry
+        .run("dedupe_catalog", || {
+            save_remote_dedupe_catalog(storage, master_key, &cat)
+        })
+        .await?;
+
+    reset_dedupe_pending_spool_db(dedupe_pending_db_path).await?;
+    crate::index_sync::endpoint_state_set(
+        dedupe_db_path,
+        crate::index_sync::ENDPOINT_STATE_ENDPOINT_DEDUPE_ID_KEY,
+        endpoint_dedupe_id,
+    )
+    .await?;
+    crate::index_sync::endpoint_state_set(
+        dedupe_db_path,
+        crate::index_sync::ENDPOINT_STATE_DEDUPE_CATALOG_OBJECT_ID_KEY,
+        &catalog_object_id,
+    )
+    .await?;
+    info!(
+        event = "dedupe.base_republished",
+        endpoint_dedupe_id,
+        catalog_object_id,
+        base_manifest_object_id = %uploaded_base.manifest_object_id,
+        base_parts = uploaded_base.manifest.parts.len() as u64,
+        "dedupe.base_republished"
+    );
+    Ok(catalog_object_id)
+}
+
+/// Local path of a snapshot's file map for a scan over every snapshot: `None` when the endpoint
+/// DB holds it, otherwise the cached `<filemap_dir>/<snapshot_id>.sqlite`, downloaded from the
+/// snapshot's remote index when missing. A snapshot with neither is an integrity error, since
+/// skipping it would lose its chunks.
+async fn snapshot_filemap_for_scan<S: Storage>(
+    storage: &S,
+    conn: &mut DbConn,
+    filemap_dir: &Path,
+    master_key: &[u8; 32],
+    snapshot_id: &str,
+    cancel: Option<&CancellationToken>,
+) -> Result<Option<PathBuf>> {
+    let provider = storage.provider();
+    let filemap_path = filemap_dir.join(format!("{snapshot_id}.sqlite"));
+    if filemap_path.exists() {
+        return Ok(Some(filemap_path));
+    }
+    if endpoint_db_has_snapshot_filemap(conn, snapshot_id).await? {
+        return Ok(None);
+    }
+    let (manifest_object_id, manifest_sha256) =
+        lookup_remote_index_manifest(conn, snapshot_id, provider)
+            .await?
+            .ok_or_else(|| Error::Integrity {
+                message: format!(
+                    "no file map for snapshot {snapshot_id} (not cached and no remote index)"
+                ),
+            })?;
+    if manifest_sha256.is_none() {
+        crate::remote_index_db::warn_manifest_unverified(snapshot_id, &manifest_object_id);
+    }
+    let data_key = crate::index_db::snapshot_data_key(conn, master_key, snapshot_id)
+        .await?
+        .unwrap_or_else(|| DataKey::master(master_key));
+    crate::remote_index_db::download_and_write_index_db_atomic(
+        storage,
+        snapshot_id,
+        &manifest_object_id,
+        manifest_sha256.as_deref(),
+        data_key.key(),
+        &filemap_path,
+        cancel,
+        Some(provider),
+        None,
+    )
+    .await?;
+    Ok(Some(filemap_path))
+}
+
+/// Default `gc run --min-age-days`.
+pub const GC_DEFAULT_MIN_AGE_DAYS: u32 = 7;
+/// Storage objects whose chunk rows are dropped per transaction before they are deleted.
+const GC_DELETE_BATCH_OBJECTS: usize = 256;
+
+#[derive(Debug, Clone)]
+pub struct GcConfig {
+    pub endpoint_db_path: PathBuf,
+    /// Per-snapshot filemap DBs (`<filemap_dir>/<snapshot_id>.sqlite`); missing ones are fetched
+    /// from the snapshot's remote index.
+    pub filemap_dir: PathBuf,
+    /// Local materialized dedupe DB, which holds the chunk mappings when remote dedupe is on.
+    pub dedupe_db_path: Option<PathBuf>,
+    /// Local pending dedupe spool DB.
+    pub dedupe_pending_db_path: Option<PathBuf>,
+    pub master_key: [u8; 32],
+    /// Objects holding a chunk recorded less than this many days ago are never collected, so a
+    /// backup running elsewhere can still dedupe against them.
+    pub min_age_days: u32,
+    /// Days a queued object waits before a flush deletes it, and a deleted snapshot's tombstone
+    /// is kept for `gc undo` (`retention.deletion_grace_days`); 0 deletes right away.
+    pub grace_days: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GcOptions<'a> {
+    pub cancel: Option<&'a CancellationToken>,
+    /// Report what would be collected without changing anything.
+    pub dry_run: bool,
+    /// Retries of transient delete failures (`retry.*`).
+    pub retry: Retry,
+}
+
+/// Outcome of [`collect_garbage`] and [`flush_pending_deletions`]. A dry run reports exactly
+/// what a real run would queue or delete.
+#[derive(Debug, Clone, Default)]
+pub struct GcResult {
+    pub dry_run: bool,
+    pub snapshots_live: u64,
+    /// Distinct chunks referenced by the live snapshots.
+    pub chunks_live: u64,
+    pub objects_deleted: u64,
+    /// Distinct chunks whose mappings went with the deleted objects.
+    pub chunks_deleted: u64,
+    /// Encrypted chunk bytes of the deleted objects (pack headers not counted).
+    pub bytes_reclaimed: u64,
+    /// Unreferenced objects kept because of `min_age_days`.
+    pub objects_too_recent: u64,
+    /// Objects whose rows were dropped but whose delete failed; they stay in storage unreferenced.
+    pub objects_failed: u64,
+    /// Unreferenced objects added to the deletion queue by this run.
+    pub objects_queued: u64,
+    pub bytes_queued: u64,
+    /// Queued objects that left the queue because a snapshot references them again.
+    pub objects_unqueued: u64,
+    /// Queued unreferenced objects a flush kept because their grace period is not over.
+    pub objects_not_due: u64,
+    /// Deletion queue of the endpoint after the run.
+    pub pending_objects: u64,
+    pub pending_bytes: u64,
+    /// Snapshot tombstones dropped because they are older than the grace period.
+    pub tombstones_purged: u64,
+    pub retry: RetryStats,
+}
+
+#[derive(Debug, Default)]
+struct GcObject {
+    /// `(db alias, provider, chunk_hash, object_id)` of every mapping into this object.
+    rows: Vec<(usize, String, String, String)>,
+    chunk_bytes: HashMap<String, u64>,
+    live: bool,
+    newest_created_at: String,
+}
+
+/// Queues the storage objects no live snapshot of the endpoint references for deletion once
+/// `grace_days` are over (`televybackup gc run`), or deletes them right away when `grace_days` is
+/// 0. Live chunks come from every snapshot's file map; a snapshot whose file map is neither
+/// cached nor in a remote index fails the run rather than losing its chunks. A pack is kept while
+/// any of its slices is live.
+pub async fn collect_garbage<S: Storage>(
+    storage: &S,
+    config: &GcConfig,
+    options: GcOptions<'_>,
+) -> Result<GcResult> {
+    run_gc(storage, config, options, GcMode::Queue).await
+}
+
+/// Deletes the queued objects whose grace period is over and that no live snapshot references
+/// (`televybackup gc flush`); queued objects referenced again leave the queue. Tombstones of
+/// snapshots deleted more than `grace_days` ago are dropped, which ends their `gc undo` window.
+pub async fn flush_pending_deletions<S: Storage>(
+    storage: &S,
+    config: &GcConfig,
+    options: GcOptions<'_>,
+) -> Result<GcResult> {
+    run_gc(storage, config, options, GcMode::Flush).await
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GcMode {
+    Queue,
+    Flush,
+}
+
+/// `created_at`-style UTC timestamp that the deletion queue compares as text.
+fn gc_timestamp(at: chrono::DateTime<chrono::Utc>) -> String {
+    at.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
+
+async fn run_gc<S: Storage>(
+    storage: &S,
+    config: &GcConfig,
+    options: GcOptions<'_>,
+    mode: GcMode,
+) -> Result<GcResult> {
+    let provider = storage.provider();
+    let cancelled = || options.cancel.is_some_and(|c| c.is_cancelled());
+    let pool = open_existing_index_db(&config.endpoint_db_path).await?;
+    // A DB synced from an older machine may predate the deletion queue.
+    sqlx::migrate!().run(&pool).await?;
+    let mut conn: DbConn = pool.acquire().await?;
+    drop(pool);
+
+    sqlx::query("CREATE TEMP TABLE gc_live_chunks (chunk_hash TEXT PRIMARY KEY) WITHOUT ROWID")
+        .execute(&mut *conn)
+        .await?;
+    let snapshots_live: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM snapshots")
+        .fetch_one(&mut *conn)
+        .await?;
+    // Counted snapshots are live through `chunks.ref_count`; only the others' file maps are read.
+    let use_ref_counts = chunk_refs::ref_counts_usable(&mut conn).await?;
+    let snapshot_ids: Vec<String> = if use_ref_counts {
+        sqlx::query(
+            "INSERT OR IGNORE INTO temp.gc_live_chunks (chunk_hash) SELECT chunk_hash FROM main.chunks WHERE ref_count > 0",
+        )
+        .execute(&mut *conn)
+        .await?;
+        sqlx::query_scalar(
+            "SELECT snapshot_id FROM snapshots WHERE chunk_refs_counted = 0 ORDER BY created_at",
+        )
+        .fetch_all(&mut *conn)
+        .await?
+    } else {
+        // Older endpoint DBs kept file maps in their own `files`/`file_chunks`.
+        sqlx::query("INSERT OR IGNORE INTO temp.gc_live_chunks (chunk_hash) SELECT chunk_hash FROM main.file_chunks")
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query_scalar("SELECT snapshot_id FROM snapshots ORDER BY created_at")
+            .fetch_all(&mut *conn)
+            .await?
+    };
+    std::fs::create_dir_all(&config.filemap_dir)?;
+    for snapshot_id in &snapshot_ids {
+        if cancelled() {
+            return Err(Error::Cancelled);
+        }
+        let filemap = snapshot_filemap_for_scan(
+            storage,
+            &mut conn,
+            &config.filemap_dir,
+            &config.master_key,
+            snapshot_id,
+            options.cancel,
+        )
+        .await?;
+        let schema = match &filemap {
+            Some(path) => {
+                attach_db(&mut conn, "gc_fm", path).await?;
+                sqlx::query(
+                    "INSERT OR IGNORE INTO temp.gc_live_chunks (chunk_hash) SELECT chunk_hash FROM gc_fm.file_chunks",
+                )
+                .execute(&mut *conn)
+                .await?;
+                "gc_fm"
+            }
+            None if use_ref_counts => {
+                sqlx::query(
+                    r#"
+                    INSERT OR IGNORE INTO temp.gc_live_chunks (chunk_hash)
+                    SELECT fc.chunk_hash FROM main.file_chunks fc
+                    JOIN main.files f ON f.file_id = fc.file_id
+                    WHERE f.snapshot_id = ?
+                    "#,
+                )
+                .bind(snapshot_id)
+                .execute(&mut *conn)
+                .await?;
+                "main"
+            }
+            None => continue,
+        };
+        // Count the snapshot now so later runs skip its file map.
+        if use_ref_counts && !options.dry_run {
+            let mut tx = conn.begin().await?;
+            chunk_refs::apply_snapshot_refs(&mut tx, schema, snapshot_id, 1).await?;
+            tx.commit().await?;
+        }
+        if filemap.is_some() {
+            sqlx::query("DETACH DATABASE gc_fm")
+                .execute(&mut *conn)
+                .await?;
+        }
+    }
+    let chunks_live: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM temp.gc_live_chunks")
+        .fetch_one(&mut *conn)
+        .await?;
+
+    let mut aliases = vec!["main"];
+    for (alias, path) in [
+        ("gc_dedupe", config.dedupe_db_path.as_deref()),
+        ("gc_pending", config.dedupe_pending_db_path.as_deref()),
+    ] {
+        if let Some(path) = path.filter(|p| p.exists()) {
+            attach_db(&mut conn, alias, path).await?;
+            aliases.push(alias);
+        }
+    }
+
+    let like = format!("{}%", provider_kind(provider));
+    let expected_scope = storage.object_id_scope();
+    let mut objects: std::collections::BTreeMap<String, GcObject> = Default::default();
+    for (alias_idx, alias) in aliases.iter().enumerate() {
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT co.provider, co.chunk_hash, co.object_id, co.created_at, c.size,
+              EXISTS (SELECT 1 FROM temp.gc_live_chunks l WHERE l.chunk_hash = co.chunk_hash) AS live
+            FROM {alias}.chunk_objects co
+            JOIN {alias}.chunks c ON c.chunk_hash = co.chunk_hash
+            WHERE co.provider = ? OR co.provider LIKE ?
+            "#
+        ))
+        .bind(provider)
+        .bind(&like)
+        .fetch_all(&mut *conn)
+        .await?;
+        for row in rows {
+            let object_id: String = row.get("object_id");
+            let Ok(object_ref) = crate::storage::parse_chunk_object_ref(&object_id) else {
+                continue;
+            };
+            let (storage_object_id, bytes) = match object_ref {
+                crate::storage::ChunkObjectRef::Direct { object_id } => {
+                    let size: i64 = row.get("size");
+                    (object_id, framed_len(size.max(0) as usize) as u64)
+                }
+                crate::storage::ChunkObjectRef::PackSlice {
+                    pack_object_id,
+                    len,
+                    ..
+                } => (pack_object_id, len),
+            };
+            // Only objects in the current chat can be deleted; earlier chats are left alone.
+            if let Some(expected_scope) = expected_scope
+                && tgmtproto_peer_from_object_id(&storage_object_id).as_deref()
+                    != Some(expected_scope)
+            {
+                continue;
+            }
+            let chunk_hash: String = row.get("chunk_hash");
+            let created_at: String = row.get("created_at");
+            let object = objects.entry(storage_object_id).or_default();
+            object.live |= row.get::<bool, _>("live");
+            if created_at > object.newest_created_at {
+                object.newest_created_at = created_at;
+            }
+            object.chunk_bytes.insert(chunk_hash.clone(), bytes);
+            object
+                .rows
+                .push((alias_idx, row.get("provider"), chunk_hash, object_id));
+        }
+    }
+
+    let now = chrono::Utc::now();
+    let now_ts = gc_timestamp(now);
+    let cutoff = gc_timestamp(now - chrono::Duration::days(i64::from(config.min_age_days)));
+    let grace_cutoff = gc_timestamp(now - chrono::Duration::days(i64::from(config.grace_days)));
+    let mut queued: HashMap<String, String> = sqlx::query_as(
+        "SELECT object_id, delete_after FROM main.pending_deletions WHERE provider = ?",
+    )
+    .bind(provider)
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .collect();
+    let mut result = GcResult {
+        dry_run: options.dry_run,
+        snapshots_live: snapshots_live as u64,
+        chunks_live: chunks_live as u64,
+        ..GcResult::default()
+    };
+    let mut garbage = Vec::new();
+    let mut to_queue = Vec::new();
+    let mut unqueue = Vec::new();
+    for (object_id, object) in objects {
+        let delete_after = queued.remove(&object_id);
+        if object.live {
+            // Referenced again, e.g. by a snapshot `gc undo` brought back.
+            if delete_after.is_some() {
+                unqueue.push(object_id);
+            }
+            continue;
+        }
+        if object.newest_created_at > cutoff {
+            result.objects_too_recent += 1;
+            continue;
+        }
+        match (mode, delete_after) {
+            (GcMode::Queue, _) if config.grace_days == 0 => garbage.push((object_id, object)),
+            (GcMode::Queue, Some(_)) => {}
+            (GcMode::Queue, None) => to_queue.push((object_id, object)),
+            (GcMode::Flush, Some(delete_after)) if delete_after <= now_ts => {
+                garbage.push((object_id, object))
+            }
+            (GcMode::Flush, Some(_)) => result.objects_not_due += 1,
+            // Not queued yet: the next `gc run` queues it.
+            (GcMode::Flush, None) => {}
+        }
+    }
+    // Queued objects no chunk row maps into any more have nothing left to delete.
+    unqueue.extend(queued.into_keys());
+    result.objects_unqueued = unqueue.len() as u64;
+    for (_, object) in &to_queue {
+        result.objects_queued += 1;
+        result.bytes_queued += object.chunk_bytes.values().sum::<u64>();
+    }
+    result.tombstones_purged = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM main.snapshot_tombstones WHERE deleted_at < ?",
+    )
+    .bind(&grace_cutoff)
+    .fetch_one(&mut *conn)
+    .await? as u64;
+
+    if options.dry_run {
+        for (_, object) in &garbage {
+            result.objects_deleted += 1;
+            result.chunks_deleted += object.chunk_bytes.len() as u64;
+            result.bytes_reclaimed += object.chunk_bytes.values().sum::<u64>();
+        }
+    } else {
+        let delete_after = gc_timestamp(now + chrono::Duration::days(i64::from(config.grace_days)));
+        let mut tx = conn.begin().await?;
+        for object_id in &unqueue {
+            sqlx::query("DELETE FROM main.pending_deletions WHERE provider = ? AND object_id = ?")
+                .bind(provider)
+                .bind(object_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        for (object_id, object) in &to_queue {
+            sqlx::query(
+                "INSERT OR IGNORE INTO main.pending_deletions (provider, object_id, bytes, queued_at, delete_after) VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(provider)
+            .bind(object_id)
+            .bind(object.chunk_bytes.values().sum::<u64>() as i64)
+            .bind(&now_ts)
+            .bind(&delete_after)
+            .execute(&mut *tx)
+            .await?;
+        }
+        for table in [
+            "deleted_remote_index_parts",
+            "deleted_remote_indexes",
+            "deleted_snapshots",
+        ] {
+            sqlx::query(&format!(
+                "DELETE FROM main.{table} WHERE snapshot_id IN (SELECT snapshot_id FROM main.snapshot_tombstones WHERE deleted_at < ?)"
+            ))
+            .bind(&grace_cutoff)
+            .execute(&mut *tx)
+            .await?;
+        }
+        sqlx::query("DELETE FROM main.snapshot_tombstones WHERE deleted_at < ?")
+            .bind(&grace_cutoff)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        let retry = RetryBudget::new(&options.retry, options.cancel);
+        for batch in garbage.chunks(GC_DELETE_BATCH_OBJECTS) {
+            if cancelled() {
+                return Err(Error::Cancelled);
+            }
+            // Rows go first: a crash before the deletes below leaks objects, whereas objects
+            // deleted under surviving rows would let later backups dedupe against missing data.
+            let mut tx = conn.begin().await?;
+            for (storage_object_id, object) in batch {
+                sqlx::query(
+                    "DELETE FROM main.pending_deletions WHERE provider = ? AND object_id = ?",
+                )
+                .bind(provider)
+                .bind(storage_object_id)
+                .execute(&mut *tx)
+                .await?;
+                for (alias_idx, row_provider, chunk_hash, object_id) in &object.rows {
+                    let alias = aliases[*alias_idx];
+                    sqlx::query(&format!(
+                        "DELETE FROM {alias}.chunk_objects WHERE provider = ? AND chunk_hash = ? AND object_id = ?"
+                    ))
+                    .bind(row_provider)
+                    .bind(chunk_hash)
+                    .bind(object_id)
+                    .execute(&mut *tx)
+                    .await?;
+                    sqlx::query(&format!(
+                        "DELETE FROM {alias}.chunks WHERE chunk_hash = ? AND NOT EXISTS (SELECT 1 FROM {alias}.chunk_objects co WHERE co.chunk_hash = ?)"
+                    ))
+                    .bind(chunk_hash)
+                    .bind(chunk_hash)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+            }
+            tx.commit().await?;
+
+            for (object_id, object) in batch {
+                match retry
+                    .run("gc_delete", || storage.delete_document(object_id))
+                    .await
+                {
+                    Ok(()) => {
+                        result.objects_deleted += 1;
+                        result.chunks_deleted += object.chunk_bytes.len() as u64;
+                        result.bytes_reclaimed += object.chunk_bytes.values().sum::<u64>();
+                    }
+                    Err(Error::Cancelled) => return Err(Error::Cancelled),
+                    Err(e) => {
+                        warn!(
+                            event = "gc.delete_failed",
+                            object_id,
+                            error = %e,
+                            "gc.delete_failed"
+                        );
+                        result.objects_failed += 1;
+                    }
+                }
+            }
+        }
+        result.retry = retry.stats();
+    }
+    let (pending_objects, pending_bytes): (i64, i64) = sqlx::query_as(
+        "SELECT COUNT(*), COALESCE(SUM(bytes), 0) FROM main.pending_deletions WHERE provider = ?",
+    )
+    .bind(provider)
+    .fetch_one(&mut *conn)
+    .await?;
+    result.pending_objects = pending_objects as u64;
+    result.pending_bytes = pending_bytes as u64;
+
+    info!(
+        event = "gc.finish",
+        dry_run = result.dry_run,
+        snapshots_live = result.snapshots_live,
+        chunks_live = result.chunks_live,
+        objects_deleted = result.objects_deleted,
+        chunks_deleted = result.chunks_deleted,
+        bytes_reclaimed = result.bytes_reclaimed,
+        objects_too_recent = result.objects_too_recent,
+        objects_failed = result.objects_failed,
+        flush = mode == GcMode::Flush,
+        objects_queued = result.objects_queued,
+        objects_unqueued = result.objects_unqueued,
+        objects_not_due = result.objects_not_due,
+        pending_bytes = result.pending_bytes,
+        tombstones_purged = result.tombstones_purged,
+        "gc.finish"
+    );
+    Ok(result)
+}
+
+/// Deletion queue of an endpoint index DB, across providers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PendingDeletions {
+    pub objects: u64,
+    pub bytes: u64,
+    /// Objects whose grace period is over; the next flush deletes those still unreferenced.
+    pub due_objects: u64,
+}
+
+/// Reads the deletion queue of the index DB at `db_path` without changing the DB; empty for DBs
+/// that predate the queue.
+pub async fn pending_deletions(db_path: &Path) -> Result<PendingDeletions> {
+    let pool = open_existing_index_db(db_path).await?;
+    let has_queue: bool = sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'pending_deletions')",
+    )
+    .fetch_one(&pool)
+    .await?;
+    if !has_queue {
+        pool.close().await;
+        return Ok(PendingDeletions::default());
+    }
+    let (objects, bytes, due_objects): (i64, i64, i64) = sqlx::query_as(
+        "SELECT COUNT(*), COALESCE(SUM(bytes), 0), COUNT(*) FILTER (WHERE delete_after <= ?) FROM pending_deletions",
+    )
+    .bind(gc_timestamp(chrono::Utc::now()))
+    .fetch_one(&pool)
+    .await?;
+    pool.close().await;
+    Ok(PendingDeletions {
+        objects: objects as u64,
+        bytes: bytes as u64,
+        due_objects: due_objects as u64,
+    })
+}
+
+/// Outcome of [`undo_snapshot_delete`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GcUndoResult {
+    pub snapshot_id: String,
+    pub source_path: String,
+    /// When retention or `snapshots delete` removed the snapshot (RFC3339).
+    pub deleted_at: String,
+    /// Queued objects holding the snapshot's chunks, taken off the deletion queue.
+    pub objects_unqueued: u64,
+    pub bytes_unqueued: u64,
+}
+
+/// Brings back a snapshot removed by retention or `snapshots delete` from its tombstone (`gc
+/// undo`) and takes the objects holding its chunks off the deletion queue. Returns `None` when
+/// the snapshot has no tombstone (never deleted, or its grace period ended at a flush). Fails
+/// with [`Error::MissingChunkObject`], leaving the snapshot deleted, when a flush already deleted
+/// one of its objects.
+pub async fn undo_snapshot_delete<S: Storage>(
+    storage: &S,
+    config: &GcConfig,
+    snapshot_id: &str,
+) -> Result<Option<GcUndoResult>> {
+    let provider = storage.provider();
+    let pool = open_index_db(&config.endpoint_db_path).await?;
+    let mut conn: DbConn = pool.acquire().await?;
+    drop(pool);
+    let Some(deleted_at) = sqlx::query_scalar::<_, String>(
+        "SELECT deleted_at FROM snapshot_tombstones WHERE snapshot_id = ?",
+    )
+    .bind(snapshot_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let Some(source_path) = sqlx::query_scalar::<_, String>(
+        "SELECT source_path FROM deleted_snapshots WHERE snapshot_id = ?",
+    )
+    .bind(snapshot_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let present: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM snapshots WHERE snapshot_id = ?")
+        .bind(snapshot_id)
+        .fetch_one(&mut *conn)
+        .await?;
+    if present > 0 {
+        return Err(Error::InvalidConfig {
+            message: format!("snapshot {snapshot_id} is not deleted"),
+        });
+    }
+
+    // Its chunk references were released on delete; the next `gc run` counts them again.
+    let mut tx = conn.begin().await?;
+    for sql in [
+        "INSERT INTO snapshots SELECT * FROM deleted_snapshots WHERE snapshot_id = ?",
+        "INSERT INTO remote_indexes SELECT * FROM deleted_remote_indexes WHERE snapshot_id = ?",
+        "INSERT INTO remote_index_parts SELECT * FROM deleted_remote_index_parts WHERE snapshot_id = ?",
+        "UPDATE snapshots SET chunk_refs_counted = 0 WHERE snapshot_id = ?",
+        "DELETE FROM deleted_remote_index_parts WHERE snapshot_id = ?",
+        "DELETE FROM deleted_remote_indexes WHERE snapshot_id = ?",
+        "DELETE FROM deleted_snapshots WHERE snapshot_id = ?",
+        "DELETE FROM snapshot_tombstones WHERE snapshot_id = ?",
+    ] {
+        sqlx::query(sql).bind(snapshot_id).execute(&mut *tx).await?;
+    }
+    tx.commit().await?;
+
+    let unqueued = unqueue_snapshot_objects(storage, &mut conn, config, snapshot_id).await;
+    let (objects_unqueued, bytes_unqueued) = match unqueued {
+        Ok(unqueued) => unqueued,
+        Err(e) => {
+            // Back to deleted, tombstone and all, as if the undo never ran.
+            let snapshot_ids = [snapshot_id.to_string()];
+            apply_retention_snapshot_batch(
+                &mut conn,
+                &source_path,
+                &config.filemap_dir,
+                &snapshot_ids,
+                1,
+                1,
+            )
+            .await?;
+            sqlx::query("UPDATE snapshot_tombstones SET deleted_at = ? WHERE snapshot_id = ?")
+                .bind(&deleted_at)
+                .bind(snapshot_id)
+                .execute(&mut *conn)
+                .await?;
+            return Err(e);
+        }
+    };
+    info!(
+        event = "gc.undo",
+        snapshot_id, source_path, provider, objects_unqueued, bytes_unqueued, "gc.undo"
+    );
+    Ok(Some(GcUndoResult {
+        snapshot_id: snapshot_id.to_string(),
+        source_path,
+        deleted_at,
+        objects_unqueued,
+        bytes_unqueued,
+    }))
+}
+
+/// Takes the objects holding `snapshot_id`'s chunks off the deletion queue; fails with
+/// [`Error::MissingChunkObject`] when one of its chunks has no object left.
+async fn unqueue_snapshot_objects<S: Storage>(
+    storage: &S,
+    conn: &mut DbConn,
+    config: &GcConfig,
+    snapshot_id: &str,
+) -> Result<(u64, u64)> {
+    std::fs::create_dir_all(&config.filemap_dir)?;
+    // Retention removed any file map rows the endpoint DB held, so it comes from the cache or
+    // the remote index.
+    let filemap = snapshot_filemap_for_scan(
+        storage,
+        conn,
+        &config.filemap_dir,
+        &config.master_key,
+        snapshot_id,
+        None,
+    )
+    .await?
+    .ok_or_else(|| Error::Integrity {
+        message: format!("no file map for snapshot {snapshot_id}"),
+    })?;
+    let mut aliases = vec!["main"];
+    attach_db(conn, "undo_fm", &filemap).await?;
+    for (alias, path) in [
+        ("undo_dedupe", config.dedupe_db_path.as_deref()),
+        ("undo_pending", config.dedupe_pending_db_path.as_deref()),
+    ] {
+        if let Some(path) = path.filter(|p| p.exists()) {
+            attach_db(conn, alias, path).await?;
+            aliases.push(alias);
+        }
+    }
+    let res = async {
+        let mapped = aliases
+            .iter()
+            .map(|alias| {
+                format!("SELECT 1 FROM {alias}.chunk_objects co WHERE co.chunk_hash = fc.chunk_hash")
+            })
+            .collect::<Vec<_>>()
+            .join(" UNION ALL ");
+        let missing: Option<String> = sqlx::query_scalar(&format!(
+            "SELECT fc.chunk_hash FROM undo_fm.file_chunks fc WHERE NOT EXISTS ({mapped}) LIMIT 1"
+        ))
+        .fetch_optional(&mut **conn)
+        .await?;
+        if let Some(chunk_hash) = missing {
+            return Err(Error::MissingChunkObject { chunk_hash });
+        }
+
+        let mut storage_object_ids = std::collections::BTreeSet::new();
+        for alias in &aliases {
+            let object_ids: Vec<String> = sqlx::query_scalar(&format!(
+                "SELECT DISTINCT object_id FROM {alias}.chunk_objects WHERE chunk_hash IN (SELECT chunk_hash FROM undo_fm.file_chunks)"
+            ))
+            .fetch_all(&mut **conn)
+            .await?;
+            storage_object_ids.extend(object_ids.iter().filter_map(|object_id| {
+                match crate::storage::parse_chunk_object_ref(object_id).ok()? {
+                    crate::storage::ChunkObjectRef::Direct { object_id } => Some(object_id),
+                    crate::storage::ChunkObjectRef::PackSlice { pack_object_id, .. } => {
+                        Some(pack_object_id)
+                    }
+                }
+            }));
+        }
+        let (mut objects, mut bytes) = (0u64, 0u64);
+        let mut tx = conn.begin().await?;
+        for object_id in &storage_object_ids {
+            let queued: Option<i64> = sqlx::query_scalar(
+                "DELETE FROM main.pending_deletions WHERE provider = ? AND object_id = ? RETURNING bytes",
+            )
+            .bind(storage.provider())
+            .bind(object_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+            if let Some(queued) = queued {
+                objects += 1;
+                bytes += queued.max(0) as u64;
+            }
+        }
+        tx.commit().await?;
+        Ok((objects, bytes))
+    }
+    .await;
+    for alias in aliases.iter().skip(1).chain(&["undo_fm"]) {
+        sqlx::query(&format!("DETACH DATABASE {alias}"))
+            .execute(&mut **conn)
+            .await?;
+    }
+    res
+}
+
+/// Drifted chunks [`check_chunk_ref_counts`] lists at most.
+const CHUNK_REF_DRIFT_EXAMPLES_MAX: i64 = 20;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkRefDrift {
+    pub chunk_hash: String,
+    /// `chunks.ref_count`.
+    pub stored: i64,
+    /// References in the file maps of the counted snapshots.
+    pub actual: i64,
+}
+
+/// Outcome of [`check_chunk_ref_counts`].
+#[derive(Debug, Clone, Default)]
+pub struct ChunkRefCheck {
+    pub snapshots: u64,
+    /// Snapshots whose references are not in the counts yet; `gc run` counts them as it reads
+    /// their file maps.
+    pub snapshots_uncounted: u64,
+    /// Why the counts are flagged stale, when they are.
+    pub stale_reason: Option<String>,
+    pub chunks_checked: u64,
+    /// Chunks whose stored count differs from a recount over the counted snapshots.
+    pub chunks_drifted: u64,
+    /// The first drifted chunks by hash.
+    pub drift_examples: Vec<ChunkRefDrift>,
+    /// The counts were rebuilt from every snapshot's file map.
+    pub repaired: bool,
+}
+
+impl ChunkRefCheck {
+    pub fn is_consistent(&self) -> bool {
+        self.chunks_drifted == 0 && self.stale_reason.is_none()
+    }
+}
+
+/// Recounts the chunk references of every counted snapshot of the endpoint and compares them with
+/// `chunks.ref_count` (`televybackup index check`). File maps are read like [`collect_garbage`]
+/// does, downloading the ones not cached. With `repair` the counts are rebuilt from all
+/// snapshots, uncounted ones included, and the stale flag is cleared; a backup committing a
+/// snapshot meanwhile would be lost from the counts, so run it while none is running.
+pub async fn check_chunk_ref_counts<S: Storage>(
+    storage: &S,
+    endpoint_db_path: &Path,
+    filemap_dir: &Path,
+    master_key: &[u8; 32],
+    repair: bool,
+    cancel: Option<&CancellationToken>,
+) -> Result<ChunkRefCheck> {
+    let pool = open_index_db(endpoint_db_path).await?;
+    let mut conn: DbConn = pool.acquire().await?;
+    drop(pool);
+
+    sqlx::query(
+        "CREATE TEMP TABLE chunk_ref_recount (chunk_hash TEXT PRIMARY KEY, counted INTEGER NOT NULL, total INTEGER NOT NULL) WITHOUT ROWID",
+    )
+    .execute(&mut *conn)
+    .await?;
+    let snapshots: Vec<(String, bool)> =
+        sqlx::query_as("SELECT snapshot_id, chunk_refs_counted FROM snapshots ORDER BY created_at")
+            .fetch_all(&mut *conn)
+            .await?;
+    std::fs::create_dir_all(filemap_dir)?;
+    for (snapshot_id, counted) in &snapshots {
+        if cancel.is_some_and(|c| c.is_cancelled()) {
+            return Err(Error::Cancelled);
+        }
+        let filemap = snapshot_filemap_for_scan(
+            storage,
+            &mut conn,
+            filemap_dir,
+            master_key,
+            snapshot_id,
+            cancel,
+        )
+        .await?;
+        let schema = match &filemap {
+            Some(path) => {
+                attach_db(&mut conn, "check_fm", path).await?;
+                "check_fm"
+            }
+            None => "main",
+        };
+        if repair && filemap.is_some() {
+            sqlx::query(
+                r#"
+                INSERT OR IGNORE INTO main.chunks (chunk_hash, size, hash_alg, enc_alg, created_at)
+                SELECT chunk_hash, size, hash_alg, enc_alg, created_at
+                FROM check_fm.chunks
+                "#,
+            )
+            .execute(&mut *conn)
+            .await?;
+        }
+        sqlx::query(&format!(
+            r#"
+            INSERT INTO temp.chunk_ref_recount (chunk_hash, counted, total)
+            SELECT fc.chunk_hash, ? * COUNT(*), COUNT(*)
+            FROM {schema}.file_chunks fc
+            JOIN {schema}.files f ON f.file_id = fc.file_id
+            WHERE f.snapshot_id = ?
+            GROUP BY fc.chunk_hash
+            ON CONFLICT (chunk_hash) DO UPDATE SET
+              counted = counted + excluded.counted,
+              total = total + excluded.total
+            "#
+        ))
+        .bind(i64::from(*counted))
+        .bind(snapshot_id)
+        .execute(&mut *conn)
+        .await?;
+        if filemap.is_some() {
+            sqlx::query("DETACH DATABASE check_fm")
+                .execute(&mut *conn)
+                .await?;
+        }
+    }
+
+    const DRIFT: &str = r#"
+        SELECT c.chunk_hash AS chunk_hash, c.ref_count AS stored, COALESCE(r.counted, 0) AS actual
+        FROM main.chunks c
+        LEFT JOIN temp.chunk_ref_recount r ON r.chunk_hash = c.chunk_hash
+        WHERE c.ref_count != COALESCE(r.counted, 0)
+        UNION ALL
+        SELECT r.chunk_hash, 0, r.counted
+        FROM temp.chunk_ref_recount r
+        WHERE r.counted != 0
+          AND NOT EXISTS (SELECT 1 FROM main.chunks c WHERE c.chunk_hash = r.chunk_hash)
+    "#;
+    let chunks_drifted: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM ({DRIFT})"))
+        .fetch_one(&mut *conn)
+        .await?;
+    let drift_examples = sqlx::query_as::<_, (String, i64, i64)>(&format!(
+        "SELECT chunk_hash, stored, actual FROM ({DRIFT}) ORDER BY chunk_hash LIMIT ?"
+    ))
+    .bind(CHUNK_REF_DRIFT_EXAMPLES_MAX)
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|(chunk_hash, stored, actual)| ChunkRefDrift {
+        chunk_hash,
+        stored,
+        actual,
+    })
+    .collect();
+    let chunks_checked: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM main.chunks")
+        .fetch_one(&mut *conn)
+        .await?;
+    let stale_reason: Option<String> =
+        sqlx::query_scalar("SELECT value FROM endpoint_state WHERE key = ?")
+            .bind(chunk_refs::CHUNK_REF_COUNTS_STALE_KEY)
+            .fetch_optional(&mut *conn)
+            .await?;
+    let mut check = ChunkRefCheck {
+        snapshots: snapshots.len() as u64,
+        snapshots_uncounted: snapshots.iter().filter(|(_, counted)| !counted).count() as u64,
+        stale_reason,
+        chunks_checked: chunks_checked as u64,
+        chunks_drifted: chunks_drifted as u64,
+        drift_examples,
+        repaired: false,
+    };
+
+    if repair && (!check.is_consistent() || check.snapshots_uncounted > 0) {
+        let mut tx = conn.begin().await?;
+        sqlx::query("UPDATE main.chunks SET ref_count = 0 WHERE ref_count != 0")
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            r#"
+            UPDATE main.chunks SET ref_count = r.total
+            FROM temp.chunk_ref_recount AS r
+            WHERE r.chunk_hash = chunks.chunk_hash
+            "#,
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("UPDATE main.snapshots SET chunk_refs_counted = 1")
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM endpoint_state WHERE key = ?")
+            .bind(chunk_refs::CHUNK_REF_COUNTS_STALE_KEY)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        check.repaired = true;
+    }
+
+    info!(
+        event = "index.chunk_refs_checked",
+        snapshots = check.snapshots,
+        snapshots_uncounted = check.snapshots_uncounted,
+        stale = check.stale_reason.is_some(),
+        chunks_checked = check.chunks_checked,
+        chunks_drifted = check.chunks_drifted,
+        repaired = check.repaired,
+        "index.chunk_refs_checked"
+    );
+    Ok(check)
+}
+
+fn path_to_utf8(path: &Path) -> Result<String> {
+    path.to_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| Error::NonUtf8Path {
+            path: path.to_path_buf(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::path::Path;
+
+    use ignore::Error as IgnoreError;
+    use sqlx::Row;
+
+    use super::{
+        BackupResult, SKIPPED_FILE_EXAMPLES_MAX, SkipReason, UploadChecker, UploadJob,
+        UploadOutcome, UploadRateLimiter, check_unreadable_share, error_has_flood_wait,
+        export_endpoint_index_db_for_upload, ignore_error_is_non_root_not_found,
+        process_upload_job, record_skipped_file,
+    };
+    use crate::Error;
+    use crate::alloc_tracking;
+    use crate::config::Retry;
+    use crate::full_disk_access;
+    use crate::retry::RetryBudget;
+
+    /// Consumes upload bodies through a small buffer without keeping them.
+    #[derive(Default)]
+    struct DrainingStorage {
+        received: std::sync::atomic::AtomicU64,
+    }
+
+    impl crate::Storage for DrainingStorage {
+        fn provider(&self) -> &str {
+            "test.drain"
+        }
+
+        fn upload_document<'a>(
+            &'a self,
+            _filename: &'a str,
+            _bytes: Vec<u8>,
+        ) -> std::pin::Pin<Box<dyn Future<Output = crate::Result<String>> + Send + 'a>> {
+            Box::pin(async { panic!("whole-object upload used for a streamed chunk") })
+        }
+
+        fn upload_document_stream<'a>(
+            &'a self,
+            _filename: &'a str,
+            mut body: crate::UploadBody<'a>,
+            len: u64,
+            _progress: Option<Box<dyn FnMut(crate::StorageProgress) + Send + 'a>>,
+        ) -> std::pin::Pin<Box<dyn Future<Output = crate::Result<String>> + Send + 'a>> {
+            Box::pin(async move {
+                let mut buf = [0u8; 16 * 1024];
+                let mut total = 0u64;
+                loop {
+                    let n = io::Read::read(&mut body, &mut buf)?;
+                    if n == 0 {
+                        break;
+                    }
+                    total += n as u64;
+                }
+                assert_eq!(total, len);
+                self.received
+                    .fetch_add(total, std::sync::atomic::Ordering::Relaxed);
+                Ok("drain:1".to_string())
+            })
+        }
+
+        fn download_document<'a>(
+            &'a self,
+            _object_id: &'a str,
+        ) -> std::pin::Pin<Box<dyn Future<Output = crate::Result<Vec<u8>>> + Send + 'a>> {
+            Box::pin(async { unreachable!() })
+        }
+    }
+
+    #[test]
+    fn skipped_files_are_counted_past_the_example_cap() {
+        let source = Path::new("/src");
+        let mut result = BackupResult::default();
+        let denied = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        let gone = std::io::Error::from(std::io::ErrorKind::NotFound);
+        let other = std::io::Error::other("bad sector");
+        assert_eq!(SkipReason::from_io(&denied), SkipReason::PermissionDenied);
+        assert_eq!(SkipReason::from_io(&gone), SkipReason::Vanished);
+        assert_eq!(SkipReason::from_io(&other), SkipReason::IoError);
+
+        for i in 0..SKIPPED_FILE_EXAMPLES_MAX + 5 {
+            let path = source.join(format!("dir/f{i}"));
+            record_skipped_file(
+                &mut result,
+                source,
+                source,
+                &path,
+                SkipReason::from_io(&denied),
+                &denied,
+            );
+        }
+        assert_eq!(
+            result.files_skipped_errors,
+            SKIPPED_FILE_EXAMPLES_MAX as u64 + 5
+        );
+        assert_eq!(result.skipped_files.len(), SKIPPED_FILE_EXAMPLES_MAX);
+        assert_eq!(result.skipped_files[0].path, "dir/f0");
+    }
+
+    #[test]
+    fn mostly_unreadable_sources_fail_and_name_full_disk_access() {
+        let mut result = BackupResult {
+            files_indexed: 5,
+            files_skipped_errors: 95,
+            ..BackupResult::default()
+        };
+        assert!(check_unreadable_share(&result, 0).is_ok());
+        assert!(check_unreadable_share(&result, 95).is_ok());
+        let err = check_unreadable_share(&result, 50).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::SourceUnreadable {
+                percent_unreadable: 95,
+                full_disk_access_missing: false,
+                ..
+            }
+        ));
+
+        result.warnings.push(full_disk_access::WARNING.to_string());
+        let err = check_unreadable_share(&result, 50).unwrap_err();
+        assert_eq!(err.code(), "scan.unreadable");
+        assert!(
+            err.to_string().contains(full_disk_access::SETTINGS_PANE),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn flood_wait_detection_matches_regular_and_premium() {
+        assert!(error_has_flood_wait(&Error::telegram(
+            "rpc error: FLOOD_WAIT_12".to_string()
+        )));
+        assert!(error_has_flood_wait(&Error::telegram(
+            "rpc error: FLOOD_PREMIUM_WAIT_34".to_string()
+        )));
+
+        // Some errors include "flood wait" in a human-readable form.
+        assert!(error_has_flood_wait(&Error::telegram(
+            "rpc error 420: flood wait (value: 5)".to_string()
+        )));
+        assert!(error_has_flood_wait(&Error::telegram(
+            "rpc error 420: flood premium wait (value: 5)".to_string()
+        )));
+
+        assert!(!error_has_flood_wait(&Error::telegram(
+            "AUTH_KEY_UNREGISTERED".to_string()
+        )));
+    }
+
+    #[test]
+    fn non_root_not_found_without_path_is_skipped_only_when_root_exists() {
+        let temp = tempfile::tempdir().unwrap();
+        let root_exists = temp.path().join("source");
+        std::fs::create_dir_all(&root_exists).unwrap();
+        let root_missing = temp.path().join("missing");
+
+        let non_root_not_found = IgnoreError::WithDepth {
+            depth: 1,
+            err: Box::new(IgnoreError::Io(io::Error::from(io::ErrorKind::NotFound))),
+        };
+        let root_not_found = IgnoreError::WithDepth {
+            depth: 0,
+            err: Box::new(IgnoreError::Io(io::Error::from(io::ErrorKind::NotFound))),
+        };
+
+        assert!(ignore_error_is_non_root_not_found(
+            &non_root_not_found,
+            &root_exists,
+        ));
+        assert!(!ignore_error_is_non_root_not_found(
+            &non_root_not_found,
+            &root_missing,
+        ));
+        assert!(!ignore_error_is_non_root_not_found(
+            &root_not_found,
+            &root_exists,
+        ));
+    }
+
+    #[tokio::test]
+    async fn direct_upload_streams_large_chunk_with_bounded_memory() {
+        const CHUNK_BYTES: usize = 100 * 1024 * 1024;
+        let plain = vec![0x5a_u8; CHUNK_BYTES];
+        let permit = std::sync::Arc::new(tokio::sync::Semaphore::new(1))
+            .acquire_owned()
+            .await
+            .unwrap();
+        let job = UploadJob::Direct {
+            chunk_hash: "chunk".to_string(),
+            plain,
+            source_bytes: CHUNK_BYTES as u64,
+            _bytes_permit: permit,
+        };
+        let storage = DrainingStorage::default();
+        let limiter = UploadRateLimiter::new(0, 0, 0);
+        let uploaded = std::sync::atomic::AtomicU64::new(0);
+        let uploaded_net = std::sync::atomic::AtomicU64::new(0);
+        let have_net = std::sync::atomic::AtomicBool::new(false);
+
+        alloc_tracking::start();
+        let outcome = process_upload_job(
+            &storage,
+            &[3u8; 32],
+            "test.drain",
+            &limiter,
+            &uploaded,
+            &uploaded_net,
+            &have_net,
+            &RetryBudget::new(&Retry::default(), None),
+            &UploadChecker::default(),
+            job,
+        )
+        .await;
+        let peak = alloc_tracking::stop();
+
+        let framed_len = (CHUNK_BYTES + crate::crypto::FRAMING_OVERHEAD_BYTES) as u64;
+        match outcome.unwrap() {
+            UploadOutcome::Direct { bytes, .. } => assert_eq!(bytes, framed_len),
+            other => panic!("unexpected outcome: {other:?}"),
+        }
+        assert_eq!(
+            storage.received.load(std::sync::atomic::Ordering::Relaxed),
+            framed_len
+        );
+        assert!(
+            peak < 4 * 1024 * 1024,
+            "peak allocation {peak} bytes while uploading a {CHUNK_BYTES} byte chunk"
+        );
+    }
+
+    #[tokio::test]
+    async fn endpoint_index_export_excludes_file_maps() {
+        let dir = tempfile::tempdir().unwrap();
+        let source_db = dir.path().join("source.sqlite");
+
+        let pool = crate::index_db::open_index_db(&source_db).await.unwrap();
+
+        // Two sources, two snapshots each.
+        for (snapshot_id, created_at, source_path) in [
+            ("snp_a1", "2026-01-01T00:00:00Z", "/a"),
+            ("snp_a2", "2026-01-02T00:00:00Z", "/a"),
+            ("snp_b1", "2026-01-01T00:00:00Z", "/b"),
+            ("snp_b2", "2026-01-03T00:00:00Z", "/b"),
+        ] {
+            sqlx::query(
+                "INSERT INTO snapshots (snapshot_id, created_at, source_path, label, base_snapshot_id, device_id, device_name) VALUES (?, ?, ?, 'test', NULL, 'dev_1', 'Laptop')",
+            )
+            .bind(snapshot_id)
+            .bind(created_at)
+            .bind(source_path)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+            sqlx::query(
+                "INSERT INTO remote_indexes (snapshot_id, provider, manifest_object_id, created_at) VALUES (?, 'telegram.mtproto/test', ?, ?)",
+            )
+            .bind(snapshot_id)
+            .bind(format!("man_{snapshot_id}"))
+            .bind(created_at)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+            sqlx::query(
+                "INSERT INTO remote_index_parts (snapshot_id, part_no, provider, object_id, size, hash) VALUES (?, 0, 'telegram.mtproto/test', ?, 1, 'h')",
+            )
+            .bind(snapshot_id)
+            .bind(format!("part_{snapshot_id}"))
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        // One file per snapshot; only the latest per source should remain in the export.
+        for (snapshot_id, file_id, chunk_hash) in [
+            ("snp_a1", "f_a1", "chk_a1"),
+            ("snp_a2", "f_a2", "chk_a2"),
+            ("snp_b1", "f_b1", "chk_b1"),
+            ("snp_b2", "f_b2", "chk_b2"),
+        ] {
+            sqlx::query(
+                "INSERT INTO files (file_id, snapshot_id, path, size, mtime_ms, mode, kind) VALUES (?, ?, 'x.txt', 1, 0, 0, 'file')",
+            )
+            .bind(file_id)
+            .bind(snapshot_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+            sqlx::query(
+                "INSERT INTO chunks (chunk_hash, size, hash_alg, enc_alg, created_at) VALUES (?, 1, 'blake3', 'xchacha20poly1305', '2026-01-01T00:00:00Z')",
             )
             .bind(chunk_hash)
             .execute(&pool)
@@ -5295,6 +8774,16 @@ mod tests {
             .get("n");
         assert_eq!(snapshots, 4);
 
+        // Device identity travels with the snapshot rows to other machines.
+        let with_device: i64 = sqlx::query(
+            "SELECT COUNT(*) AS n FROM snapshots WHERE device_id = 'dev_1' AND device_name = 'Laptop'",
+        )
+        .fetch_one(&export_pool)
+        .await
+        .unwrap()
+        .get("n");
+        assert_eq!(with_device, 4);
+
         // Endpoint DB export must not include file maps (`files` / `file_chunks`).
         let files: i64 = sqlx::query("SELECT COUNT(*) AS n FROM files")
             .fetch_one(&export_pool)
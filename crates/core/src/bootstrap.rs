@@ -1,7 +1,8 @@
 use serde::{Deserialize, Serialize};
 
-use crate::crypto::{decrypt_framed, encrypt_framed};
-use crate::storage::Storage;
+use crate::crypto::{DataKey, KeyDerivation, decrypt_framed, encrypt_framed};
+use crate::device::DeviceIdentity;
+use crate::storage::{ObjectKind, Storage, UploadMetadata};
 use crate::{Error, Result};
 
 pub const BOOTSTRAP_CATALOG_VERSION: u32 = 1;
@@ -21,9 +22,49 @@ pub fn endpoint_index_id_for_storage<S: Storage>(storage: &S) -> Result<String>
     Ok(endpoint_index_id_from_scope(scope))
 }
 
+/// How an endpoint keeps its bootstrap catalog in the chat (`bootstrap.pin_mode`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BootstrapPinMode {
+    /// The catalog is the chat's pinned message.
+    #[default]
+    Pin,
+    /// The catalog is an unpinned message captioned as one, found among the chat's recent
+    /// messages; the chat's pin is left to humans.
+    TaggedMessage,
+    /// No remote catalog: backups don't maintain one and restoring "latest" needs the local index.
+    Disabled,
+}
+
+impl BootstrapPinMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Pin => "pin",
+            Self::TaggedMessage => "tagged_message",
+            Self::Disabled => "disabled",
+        }
+    }
+
+    /// Why no catalog was found, for [`Error::BootstrapMissing`].
+    pub fn missing_message(self) -> &'static str {
+        match self {
+            Self::Pin => "no pinned bootstrap catalog",
+            Self::TaggedMessage => "no tagged bootstrap catalog among the chat's recent messages",
+            Self::Disabled => "bootstrap catalog disabled (bootstrap.pin_mode = \"disabled\")",
+        }
+    }
+}
+
 pub trait PinnedStorage: Storage {
     fn get_pinned_object_id(&self) -> Result<Option<String>>;
     fn set_pinned_object_id(&self, object_id: &str) -> Result<()>;
+
+    /// Object id of the newest of the chat's recent documents captioned as `kind`.
+    fn find_recent_object_id(&self, kind: ObjectKind) -> Result<Option<String>>;
+
+    fn bootstrap_pin_mode(&self) -> BootstrapPinMode {
+        BootstrapPinMode::Pin
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -53,7 +94,7 @@ pub struct BootstrapEndpointDedupeLatest {
     pub catalog_object_id: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct BootstrapTarget {
     pub target_id: String,
     pub source_path: String,
@@ -62,10 +103,39 @@ pub struct BootstrapTarget {
     pub latest: Option<BootstrapLatest>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct BootstrapLatest {
     pub snapshot_id: String,
     pub manifest_object_id: String,
+    /// SHA-256 of the encrypted manifest object, so a replaced manifest is detected (absent in
+    /// catalogs written by older versions).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manifest_sha256: Option<String>,
+    /// Machine that produced the snapshot (absent in catalogs written by older versions).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_name: Option<String>,
+    /// [`KeyDerivation::version`] of the snapshot's data key; absent (0, the master key) in
+    /// catalogs written by older versions.
+    #[serde(default, skip_serializing_if = "is_master_key_derivation")]
+    pub key_derivation: i64,
+    /// `object_prefix` in the names of the snapshot's documents (absent in catalogs written by
+    /// older versions, or when uploaded without one).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub object_prefix: Option<String>,
+}
+
+impl BootstrapLatest {
+    /// Key the snapshot was written with, for the pointer filed under `target_id`.
+    pub fn data_key(&self, master_key: &[u8; 32], target_id: &str) -> Result<DataKey> {
+        let derivation = KeyDerivation::from_version(self.key_derivation)?;
+        DataKey::for_snapshot(master_key, derivation, Some(target_id))
+    }
+}
+
+fn is_master_key_derivation(version: &i64) -> bool {
+    *version == KeyDerivation::Master.version()
 }
 
 impl Default for BootstrapCatalogV1 {
@@ -80,6 +150,51 @@ impl Default for BootstrapCatalogV1 {
     }
 }
 
+impl BootstrapCatalogV1 {
+    pub fn target(&self, target_id: &str) -> Option<&BootstrapTarget> {
+        self.targets.iter().find(|t| t.target_id == target_id)
+    }
+
+    /// Points `target_id` at `latest`, adding the target when it is missing.
+    ///
+    /// Returns the previous pointer when it referenced a different snapshot.
+    pub fn set_latest(
+        &mut self,
+        target_id: &str,
+        source_path: &str,
+        label: &str,
+        latest: BootstrapLatest,
+    ) -> Option<BootstrapLatest> {
+        if let Some(t) = self.targets.iter_mut().find(|t| t.target_id == target_id) {
+            t.source_path = source_path.to_string();
+            t.label = label.to_string();
+            let snapshot_id = latest.snapshot_id.clone();
+            return t
+                .latest
+                .replace(latest)
+                .filter(|prev| prev.snapshot_id != snapshot_id);
+        }
+
+        self.targets.push(BootstrapTarget {
+            target_id: target_id.to_string(),
+            source_path: source_path.to_string(),
+            label: label.to_string(),
+            latest: Some(latest),
+        });
+        None
+    }
+
+    /// Drops `target_id` from the catalog, returning the removed entry.
+    pub fn remove_target(&mut self, target_id: &str) -> Option<BootstrapTarget> {
+        let pos = self.targets.iter().position(|t| t.target_id == target_id)?;
+        Some(self.targets.remove(pos))
+    }
+
+    pub fn touch(&mut self) {
+        self.updated_at = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    }
+}
+
 pub fn encrypt_catalog(master_key: &[u8; 32], catalog: &BootstrapCatalogV1) -> Result<Vec<u8>> {
     if catalog.version != BOOTSTRAP_CATALOG_VERSION {
         return Err(Error::InvalidConfig {
@@ -105,11 +220,23 @@ pub fn decrypt_catalog(master_key: &[u8; 32], framed: &[u8]) -> Result<Bootstrap
     Ok(cat)
 }
 
+/// Object id of the endpoint's current catalog message, per its [`BootstrapPinMode`]; it changes
+/// whenever the catalog is saved.
+pub fn remote_catalog_object_id<S: PinnedStorage>(storage: &S) -> Result<Option<String>> {
+    match storage.bootstrap_pin_mode() {
+        BootstrapPinMode::Pin => storage.get_pinned_object_id(),
+        BootstrapPinMode::TaggedMessage => {
+            storage.find_recent_object_id(ObjectKind::BootstrapCatalog)
+        }
+        BootstrapPinMode::Disabled => Ok(None),
+    }
+}
+
 pub async fn load_remote_catalog<S: PinnedStorage>(
     storage: &S,
     master_key: &[u8; 32],
 ) -> Result<Option<BootstrapCatalogV1>> {
-    let Some(object_id) = storage.get_pinned_object_id()? else {
+    let Some(object_id) = remote_catalog_object_id(storage)? else {
         return Ok(None);
     };
     let bytes = storage.download_document(&object_id).await?;
@@ -122,13 +249,13 @@ pub async fn load_remote_catalog<S: PinnedStorage>(
                 event = "bootstrap.catalog.not_catalog",
                 object_id = %object_id,
                 error = %message,
-                "ignoring pinned document: not a TelevyBackup bootstrap catalog"
+                "ignoring bootstrap document: not a TelevyBackup bootstrap catalog"
             );
             Ok(None)
         }
         Err(e) => Err(Error::BootstrapDecryptFailed {
             message: format!(
-                "pinned bootstrap catalog decrypt failed: object_id={object_id}; {e} (check TBK1 master key)"
+                "bootstrap catalog decrypt failed: object_id={object_id}; {e} (check TBK1 master key)"
             ),
         }),
     }
@@ -139,7 +266,28 @@ pub async fn save_remote_catalog<S: PinnedStorage>(
     master_key: &[u8; 32],
     catalog: &BootstrapCatalogV1,
 ) -> Result<String> {
+    let mode = storage.bootstrap_pin_mode();
+    if mode == BootstrapPinMode::Disabled {
+        return Err(Error::InvalidConfig {
+            message: mode.missing_message().to_string(),
+        });
+    }
     let bytes = encrypt_catalog(master_key, catalog)?;
+    if mode == BootstrapPinMode::TaggedMessage {
+        // The caption is what `remote_catalog_object_id` looks for.
+        let len = bytes.len() as u64;
+        return storage
+            .upload_document_stream_with_metadata(
+                "televybackup-bootstrap.catalog",
+                Box::new(std::io::Cursor::new(bytes)),
+                len,
+                None,
+                Some(UploadMetadata {
+                    kind: ObjectKind::BootstrapCatalog,
+                }),
+            )
+            .await;
+    }
     let object_id = storage
         .upload_document("televybackup-bootstrap.catalog", bytes)
         .await?;
@@ -147,6 +295,11 @@ pub async fn save_remote_catalog<S: PinnedStorage>(
     Ok(object_id)
 }
 
+/// Points `target_id` at `snapshot_id` in the endpoint's catalog; a no-op with
+/// [`BootstrapPinMode::Disabled`].
+///
+/// Returns the previous pointer when it referenced a different snapshot, so callers can record
+/// the overwrite.
 #[allow(clippy::too_many_arguments)]
 pub async fn update_remote_latest<S: PinnedStorage>(
     storage: &S,
@@ -158,11 +311,17 @@ pub async fn update_remote_latest<S: PinnedStorage>(
     label: &str,
     snapshot_id: &str,
     manifest_object_id: &str,
-) -> Result<()> {
+    manifest_sha256: Option<&str>,
+    key_derivation: KeyDerivation,
+    device: Option<&DeviceIdentity>,
+) -> Result<Option<BootstrapLatest>> {
+    if storage.bootstrap_pin_mode() == BootstrapPinMode::Disabled {
+        return Ok(None);
+    }
     let mut cat = load_remote_catalog(storage, master_key)
         .await?
         .unwrap_or_default();
-    cat.updated_at = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    cat.touch();
     if endpoint_latest.is_some() {
         cat.endpoint_latest = endpoint_latest;
     }
@@ -170,32 +329,42 @@ pub async fn update_remote_latest<S: PinnedStorage>(
         cat.endpoint_dedupe_latest = endpoint_dedupe_latest;
     }
 
-    let mut found = false;
-    for t in &mut cat.targets {
-        if t.target_id == target_id {
-            t.source_path = source_path.to_string();
-            t.label = label.to_string();
-            t.latest = Some(BootstrapLatest {
-                snapshot_id: snapshot_id.to_string(),
-                manifest_object_id: manifest_object_id.to_string(),
-            });
-            found = true;
-            break;
-        }
-    }
+    let latest = BootstrapLatest {
+        snapshot_id: snapshot_id.to_string(),
+        manifest_object_id: manifest_object_id.to_string(),
+        manifest_sha256: manifest_sha256.map(str::to_string),
+        device_id: device.map(|d| d.device_id.clone()),
+        device_name: device.map(|d| d.device_name.clone()),
+        key_derivation: key_derivation.version(),
+        object_prefix: storage.object_prefix().map(str::to_string),
+    };
+    let replaced = cat.set_latest(target_id, source_path, label, latest);
 
-    if !found {
-        cat.targets.push(BootstrapTarget {
-            target_id: target_id.to_string(),
-            source_path: source_path.to_string(),
-            label: label.to_string(),
-            latest: Some(BootstrapLatest {
-                snapshot_id: snapshot_id.to_string(),
-                manifest_object_id: manifest_object_id.to_string(),
-            }),
-        });
-    }
+    let _ = save_remote_catalog(storage, master_key, &cat).await?;
+    Ok(replaced)
+}
 
+/// Like [`update_remote_latest`], but leaves every target's latest pointer alone; used when the
+/// new snapshot is older than the one the target already points at (imported history).
+pub async fn update_remote_endpoint_latest<S: PinnedStorage>(
+    storage: &S,
+    master_key: &[u8; 32],
+    endpoint_latest: Option<BootstrapEndpointLatest>,
+    endpoint_dedupe_latest: Option<BootstrapEndpointDedupeLatest>,
+) -> Result<()> {
+    if storage.bootstrap_pin_mode() == BootstrapPinMode::Disabled {
+        return Ok(());
+    }
+    let mut cat = load_remote_catalog(storage, master_key)
+        .await?
+        .unwrap_or_default();
+    cat.touch();
+    if endpoint_latest.is_some() {
+        cat.endpoint_latest = endpoint_latest;
+    }
+    if endpoint_dedupe_latest.is_some() {
+        cat.endpoint_dedupe_latest = endpoint_dedupe_latest;
+    }
     let _ = save_remote_catalog(storage, master_key, &cat).await?;
     Ok(())
 }
@@ -208,7 +377,7 @@ pub async fn resolve_remote_endpoint_latest<S: PinnedStorage>(
     let cat = load_remote_catalog(storage, master_key)
         .await?
         .ok_or_else(|| Error::BootstrapMissing {
-            message: "no pinned bootstrap catalog".to_string(),
+            message: storage.bootstrap_pin_mode().missing_message().to_string(),
         })?;
 
     cat.endpoint_latest.ok_or_else(|| Error::InvalidConfig {
@@ -225,7 +394,7 @@ pub async fn resolve_remote_latest<S: PinnedStorage>(
     let cat = load_remote_catalog(storage, master_key)
         .await?
         .ok_or_else(|| Error::BootstrapMissing {
-            message: "no pinned bootstrap catalog".to_string(),
+            message: storage.bootstrap_pin_mode().missing_message().to_string(),
         })?;
 
     if let Some(id) = target_id {
@@ -272,22 +441,39 @@ pub async fn resolve_remote_latest<S: PinnedStorage>(
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use std::sync::Mutex;
 
     use super::*;
     use crate::storage::InMemoryStorage;
 
-    struct MemPinned {
+    /// In-memory storage with a pin slot; other modules' tests use it too.
+    pub(crate) struct MemPinned {
         inner: InMemoryStorage,
         pinned: Mutex<Option<String>>,
+        /// Uploads captioned as a bootstrap catalog, oldest first.
+        tagged: Mutex<Vec<String>>,
+        mode: BootstrapPinMode,
     }
 
     impl MemPinned {
-        fn new() -> Self {
+        pub(crate) fn new() -> Self {
+            Self::with_mode(BootstrapPinMode::Pin)
+        }
+
+        fn with_mode(mode: BootstrapPinMode) -> Self {
             Self {
                 inner: InMemoryStorage::new(),
                 pinned: Mutex::new(None),
+                tagged: Mutex::new(Vec::new()),
+                mode,
+            }
+        }
+
+        fn with_object_prefix(prefix: &str) -> Self {
+            Self {
+                inner: InMemoryStorage::builder().object_prefix(prefix).build(),
+                ..Self::new()
             }
         }
     }
@@ -306,6 +492,27 @@ mod tests {
             self.inner.upload_document(filename, bytes)
         }
 
+        fn upload_document_stream_with_metadata<'a>(
+            &'a self,
+            filename: &'a str,
+            body: crate::storage::UploadBody<'a>,
+            len: u64,
+            progress: Option<Box<dyn FnMut(crate::StorageProgress) + Send + 'a>>,
+            metadata: Option<UploadMetadata>,
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<String>> + Send + 'a>>
+        {
+            Box::pin(async move {
+                let object_id = self
+                    .inner
+                    .upload_document_stream(filename, body, len, progress)
+                    .await?;
+                if metadata.is_some_and(|m| m.kind == ObjectKind::BootstrapCatalog) {
+                    self.tagged.lock().unwrap().push(object_id.clone());
+                }
+                Ok(object_id)
+            })
+        }
+
         fn download_document<'a>(
             &'a self,
             object_id: &'a str,
@@ -313,6 +520,10 @@ mod tests {
         {
             self.inner.download_document(object_id)
         }
+
+        fn object_prefix(&self) -> Option<&str> {
+            self.inner.object_prefix()
+        }
     }
 
     impl PinnedStorage for MemPinned {
@@ -326,15 +537,108 @@ mod tests {
             })? = Some(object_id.to_string());
             Ok(())
         }
+
+        fn find_recent_object_id(&self, kind: ObjectKind) -> Result<Option<String>> {
+            assert_eq!(kind, ObjectKind::BootstrapCatalog);
+            Ok(self.tagged.lock().unwrap().last().cloned())
+        }
+
+        fn bootstrap_pin_mode(&self) -> BootstrapPinMode {
+            self.mode
+        }
     }
 
     #[tokio::test]
     async fn catalog_round_trip_via_remote() {
+        let store = MemPinned::with_object_prefix("work-macbook");
+        let key = [3u8; 32];
+
+        let replaced = update_remote_latest(
+            &store,
+            &key,
+            None,
+            None,
+            "t1",
+            "/A",
+            "manual",
+            "snp_1",
+            "obj_1",
+            None,
+            KeyDerivation::Master,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(replaced, None);
+
+        let latest = resolve_remote_latest(&store, &key, Some("t1"), None)
+            .await
+            .unwrap();
+        assert_eq!(latest.snapshot_id, "snp_1");
+        assert_eq!(latest.manifest_object_id, "obj_1");
+        assert_eq!(latest.manifest_sha256, None);
+        assert_eq!(latest.device_id, None);
+        assert_eq!(latest.object_prefix.as_deref(), Some("work-macbook"));
+
+        let device = DeviceIdentity {
+            device_id: "dev_1".to_string(),
+            device_name: "Work MacBook".to_string(),
+        };
+        let replaced = update_remote_latest(
+            &store,
+            &key,
+            None,
+            None,
+            "t1",
+            "/A",
+            "manual",
+            "snp_2",
+            "obj_2",
+            Some("abc123"),
+            KeyDerivation::Master,
+            Some(&device),
+        )
+        .await
+        .unwrap();
+        assert_eq!(replaced.map(|l| l.snapshot_id).as_deref(), Some("snp_1"));
+        let latest = resolve_remote_latest(&store, &key, None, Some("/A"))
+            .await
+            .unwrap();
+        assert_eq!(latest.snapshot_id, "snp_2");
+        assert_eq!(latest.manifest_sha256.as_deref(), Some("abc123"));
+        assert_eq!(latest.device_id.as_deref(), Some("dev_1"));
+        assert_eq!(latest.device_name.as_deref(), Some("Work MacBook"));
+    }
+
+    #[tokio::test]
+    async fn endpoint_only_update_keeps_target_latest() {
         let store = MemPinned::new();
         let key = [3u8; 32];
 
         update_remote_latest(
-            &store, &key, None, None, "t1", "/A", "manual", "snp_1", "obj_1",
+            &store,
+            &key,
+            None,
+            None,
+            "t1",
+            "/A",
+            "manual",
+            "snp_1",
+            "obj_1",
+            None,
+            KeyDerivation::Master,
+            None,
+        )
+        .await
+        .unwrap();
+        update_remote_endpoint_latest(
+            &store,
+            &key,
+            Some(BootstrapEndpointLatest {
+                endpoint_index_id: "ep_idx".to_string(),
+                manifest_object_id: "ep_obj_2".to_string(),
+            }),
+            None,
         )
         .await
         .unwrap();
@@ -343,7 +647,8 @@ mod tests {
             .await
             .unwrap();
         assert_eq!(latest.snapshot_id, "snp_1");
-        assert_eq!(latest.manifest_object_id, "obj_1");
+        let endpoint = resolve_remote_endpoint_latest(&store, &key).await.unwrap();
+        assert_eq!(endpoint.manifest_object_id, "ep_obj_2");
     }
 
     #[tokio::test]
@@ -358,7 +663,18 @@ mod tests {
         store.set_pinned_object_id(&pinned_before).unwrap();
 
         update_remote_latest(
-            &store, &key, None, None, "t1", "/A", "manual", "snp_1", "obj_1",
+            &store,
+            &key,
+            None,
+            None,
+            "t1",
+            "/A",
+            "manual",
+            "snp_1",
+            "obj_1",
+            None,
+            KeyDerivation::Master,
+            None,
         )
         .await
         .unwrap();
@@ -380,7 +696,18 @@ mod tests {
         let key_bad = [4u8; 32];
 
         update_remote_latest(
-            &store, &key_ok, None, None, "t1", "/A", "manual", "snp_1", "obj_1",
+            &store,
+            &key_ok,
+            None,
+            None,
+            "t1",
+            "/A",
+            "manual",
+            "snp_1",
+            "obj_1",
+            None,
+            KeyDerivation::Master,
+            None,
         )
         .await
         .unwrap();
@@ -393,4 +720,139 @@ mod tests {
             other => panic!("unexpected error: {other:?}"),
         }
     }
+
+    #[tokio::test]
+    async fn edited_catalog_round_trips_with_new_pin() {
+        let store = MemPinned::new();
+        let key = [3u8; 32];
+
+        for (target_id, snapshot_id) in [("t1", "snp_1"), ("t_old", "snp_old")] {
+            update_remote_latest(
+                &store,
+                &key,
+                None,
+                None,
+                target_id,
+                "/A",
+                "manual",
+                snapshot_id,
+                "obj",
+                None,
+                KeyDerivation::Master,
+                None,
+            )
+            .await
+            .unwrap();
+        }
+        let pinned_before = store.get_pinned_object_id().unwrap().unwrap();
+
+        let mut cat = load_remote_catalog(&store, &key).await.unwrap().unwrap();
+        let removed = cat.remove_target("t_old").unwrap();
+        assert_eq!(removed.latest.unwrap().snapshot_id, "snp_old");
+        assert_eq!(cat.remove_target("t_missing"), None);
+
+        let latest = BootstrapLatest {
+            snapshot_id: "snp_0".to_string(),
+            manifest_object_id: "obj_0".to_string(),
+            manifest_sha256: Some("sha_0".to_string()),
+            device_id: None,
+            device_name: None,
+            key_derivation: 0,
+            object_prefix: None,
+        };
+        let replaced = cat.set_latest("t1", "/A", "manual", latest.clone());
+        assert_eq!(replaced.map(|l| l.snapshot_id).as_deref(), Some("snp_1"));
+        assert_eq!(cat.set_latest("t1", "/A", "manual", latest), None);
+        save_remote_catalog(&store, &key, &cat).await.unwrap();
+
+        assert_ne!(
+            store.get_pinned_object_id().unwrap().unwrap(),
+            pinned_before
+        );
+        let cat = load_remote_catalog(&store, &key).await.unwrap().unwrap();
+        assert_eq!(cat.targets.len(), 1);
+        assert_eq!(cat.target("t_old"), None);
+        let latest = resolve_remote_latest(&store, &key, Some("t1"), None)
+            .await
+            .unwrap();
+        assert_eq!(latest.snapshot_id, "snp_0");
+        assert_eq!(latest.manifest_sha256.as_deref(), Some("sha_0"));
+    }
+
+    #[tokio::test]
+    async fn tagged_message_mode_leaves_the_pin_alone() {
+        let store = MemPinned::with_mode(BootstrapPinMode::TaggedMessage);
+        let key = [3u8; 32];
+        let announcement = store
+            .upload_document("announcement", b"hello humans".to_vec())
+            .await
+            .unwrap();
+        store.set_pinned_object_id(&announcement).unwrap();
+
+        for snapshot_id in ["snp_1", "snp_2"] {
+            update_remote_latest(
+                &store,
+                &key,
+                None,
+                None,
+                "t1",
+                "/A",
+                "manual",
+                snapshot_id,
+                "obj",
+                None,
+                KeyDerivation::Master,
+                None,
+            )
+            .await
+            .unwrap();
+        }
+
+        assert_eq!(store.get_pinned_object_id().unwrap(), Some(announcement));
+        assert_eq!(store.tagged.lock().unwrap().len(), 2);
+        assert_eq!(
+            remote_catalog_object_id(&store).unwrap(),
+            store.tagged.lock().unwrap().last().cloned()
+        );
+        let latest = resolve_remote_latest(&store, &key, Some("t1"), None)
+            .await
+            .unwrap();
+        assert_eq!(latest.snapshot_id, "snp_2");
+    }
+
+    #[tokio::test]
+    async fn disabled_mode_skips_the_catalog() {
+        let store = MemPinned::with_mode(BootstrapPinMode::Disabled);
+        let key = [3u8; 32];
+
+        let replaced = update_remote_latest(
+            &store,
+            &key,
+            None,
+            None,
+            "t1",
+            "/A",
+            "manual",
+            "snp_1",
+            "obj_1",
+            None,
+            KeyDerivation::Master,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(replaced, None);
+        assert_eq!(store.inner.object_count().await, 0);
+        assert!(load_remote_catalog(&store, &key).await.unwrap().is_none());
+
+        let err = resolve_remote_latest(&store, &key, Some("t1"), None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), "bootstrap.missing");
+        assert!(err.to_string().contains("bootstrap.pin_mode"), "{err}");
+        let err = save_remote_catalog(&store, &key, &BootstrapCatalogV1::default())
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), "config.invalid");
+    }
 }
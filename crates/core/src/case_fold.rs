@@ -0,0 +1,193 @@
+//! Snapshot paths that differ only in letter case (`README.md` / `readme.md`), which a
+//! case-insensitive volume (the macOS default) can hold only one of.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+fn fold(path: &str) -> String {
+    path.to_lowercase()
+}
+
+/// Groups of two or more `paths` that are equal ignoring case, each group and the list sorted.
+pub(crate) fn case_collisions<I, S>(paths: I) -> Vec<Vec<String>>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    let mut by_folded: HashMap<String, Vec<String>> = HashMap::new();
+    for path in paths {
+        let path = path.into();
+        by_folded.entry(fold(&path)).or_default().push(path);
+    }
+    let mut groups = by_folded
+        .into_values()
+        .filter(|g| g.len() > 1)
+        .map(|mut g| {
+            g.sort();
+            g
+        })
+        .collect::<Vec<_>>();
+    groups.sort();
+    groups
+}
+
+/// New names for the entries that would land on an earlier one on a case-insensitive volume.
+///
+/// Paths are taken in byte order and the first of each colliding set keeps its name; the others
+/// get ` (case N)` before their extension. Children of a renamed directory follow it.
+#[derive(Debug, Default)]
+pub(crate) struct CaseRenames {
+    /// Snapshot path -> new file name of its last component.
+    names: HashMap<String, String>,
+}
+
+impl CaseRenames {
+    pub(crate) fn plan(paths: &[String]) -> Self {
+        let mut sorted = paths.iter().map(String::as_str).collect::<Vec<_>>();
+        sorted.sort_unstable();
+        let original = sorted.iter().map(|p| fold(p)).collect::<HashSet<_>>();
+
+        let mut renames = Self::default();
+        let mut taken = HashSet::with_capacity(sorted.len());
+        for path in sorted {
+            let (parent, name) = match path.rsplit_once('/') {
+                Some((parent, name)) => (Some(renames.apply(parent)), name),
+                None => (None, path),
+            };
+            let join = |name: &str| match &parent {
+                Some(parent) => format!("{parent}/{name}"),
+                None => name.to_string(),
+            };
+            if taken.insert(fold(&join(name))) {
+                continue;
+            }
+            let (stem, ext) = match name.rfind('.') {
+                Some(i) if i > 0 => name.split_at(i),
+                _ => (name, ""),
+            };
+            let (renamed, key) = (2u32..)
+                .map(|n| format!("{stem} (case {n}){ext}"))
+                .map(|renamed| {
+                    let key = fold(&join(&renamed));
+                    (renamed, key)
+                })
+                .find(|(_, key)| !taken.contains(key) && !original.contains(key))
+                .expect("unbounded suffixes");
+            taken.insert(key);
+            renames.names.insert(path.to_string(), renamed);
+        }
+        renames
+    }
+
+    /// `(snapshot path, restored path)` of every renamed entry, sorted.
+    pub(crate) fn renamed(&self) -> Vec<(String, String)> {
+        let mut out = self
+            .names
+            .keys()
+            .map(|path| (path.clone(), self.apply(path)))
+            .collect::<Vec<_>>();
+        out.sort();
+        out
+    }
+
+    /// Where the snapshot path `rel` is restored, relative to the target.
+    pub(crate) fn apply(&self, rel: &str) -> String {
+        if self.names.is_empty() {
+            return rel.to_string();
+        }
+        let mut out = String::with_capacity(rel.len());
+        for (i, name) in rel.split('/').enumerate() {
+            let original_prefix = match rel.match_indices('/').nth(i) {
+                Some((end, _)) => &rel[..end],
+                None => rel,
+            };
+            if i > 0 {
+                out.push('/');
+            }
+            out.push_str(
+                self.names
+                    .get(original_prefix)
+                    .map(String::as_str)
+                    .unwrap_or(name),
+            );
+        }
+        out
+    }
+}
+
+/// Whether `dir` treats names that differ only in case as the same entry, found by creating a
+/// probe file in it and looking it up with its name upper-cased.
+pub(crate) fn dir_is_case_insensitive(dir: &Path) -> std::io::Result<bool> {
+    let name = format!(".televybackup-case-probe-{}", uuid::Uuid::new_v4().simple());
+    let probe = dir.join(&name);
+    fs::File::create_new(&probe)?;
+    let insensitive = dir.join(name.to_uppercase()).symlink_metadata().is_ok();
+    fs::remove_file(&probe)?;
+    Ok(insensitive)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(paths: &[&str]) -> Vec<String> {
+        paths.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn collisions_are_grouped_by_folded_path() {
+        let groups = case_collisions(["README.md", "a/x", "readme.md", "A/x", "b", "Ä", "ä"]);
+        assert_eq!(
+            groups,
+            vec![
+                strings(&["A/x", "a/x"]),
+                strings(&["README.md", "readme.md"]),
+                strings(&["Ä", "ä"]),
+            ]
+        );
+        assert!(case_collisions(["a", "b", "a/b"]).is_empty());
+    }
+
+    #[test]
+    fn later_entries_get_a_suffix_and_children_follow_their_dir() {
+        let paths = strings(&[
+            "readme.md",
+            "README.md",
+            "Docs",
+            "Docs/a",
+            "docs",
+            "docs/a",
+            "docs/A",
+            "Makefile",
+            "makefile",
+            "makefile (case 2)",
+        ]);
+        let renames = CaseRenames::plan(&paths);
+        assert_eq!(
+            renames.renamed(),
+            vec![
+                ("docs".to_string(), "docs (case 2)".to_string()),
+                ("docs/a".to_string(), "docs (case 2)/a (case 2)".to_string()),
+                ("makefile".to_string(), "makefile (case 3)".to_string()),
+                ("readme.md".to_string(), "readme (case 2).md".to_string()),
+            ]
+        );
+        assert_eq!(renames.apply("docs/A"), "docs (case 2)/A");
+        assert_eq!(renames.apply("Docs/a"), "Docs/a");
+        assert_eq!(renames.apply("README.md"), "README.md");
+
+        let restored = paths.iter().map(|p| renames.apply(p)).collect::<Vec<_>>();
+        assert!(case_collisions(restored).is_empty());
+    }
+
+    #[test]
+    fn probe_leaves_the_dir_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let insensitive = dir_is_case_insensitive(dir.path()).unwrap();
+        if cfg!(target_os = "linux") {
+            assert!(!insensitive);
+        }
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+}
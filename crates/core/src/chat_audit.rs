@@ -0,0 +1,346 @@
+//! Auditing an endpoint's chat without decrypting anything (`televybackup telegram audit-chat`).
+//!
+//! Uploads caption each document with an [`ObjectCaption`] (kind, length and SHA-256 of the
+//! encrypted payload). [`audit_chat_documents`] checks document sizes against those captions and
+//! cross-references the chat with the local index DB; [`ChatAuditReport::record_download`] adds
+//! the result of re-downloading a document and hashing it.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ops::RangeInclusive;
+
+use crate::Result;
+use crate::storage::{
+    ObjectCaption, ObjectKind, TelegramDocumentInfo, TgMtProtoObjectIdV1, split_object_prefix,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatAuditIssueKind {
+    /// The document's size differs from its caption's `len`.
+    SizeMismatch,
+    /// The re-downloaded payload doesn't match its caption.
+    HashMismatch,
+    DownloadFailed,
+    /// A captioned document the index DB doesn't refer to.
+    Extra,
+    /// The index DB refers to a message in the scanned range that isn't this document.
+    Missing,
+}
+
+impl ChatAuditIssueKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::SizeMismatch => "size_mismatch",
+            Self::HashMismatch => "hash_mismatch",
+            Self::DownloadFailed => "download_failed",
+            Self::Extra => "extra",
+            Self::Missing => "missing",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatAuditIssue {
+    pub kind: ChatAuditIssueKind,
+    pub msg_id: i32,
+    pub object_kind: Option<ObjectKind>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChatAuditReport {
+    pub documents_scanned: u64,
+    pub captioned: u64,
+    /// Documents the index DB refers to.
+    pub indexed: u64,
+    /// Documents without a caption that the index DB doesn't refer to either (e.g. files posted by
+    /// people in the chat).
+    pub unrelated: u64,
+    pub hash_checked: u64,
+    /// Captioned documents by the `object_prefix` in their name, so objects of several machines
+    /// sharing the chat can be told apart; `""` for documents uploaded without one.
+    pub captioned_by_prefix: BTreeMap<String, u64>,
+    pub issues: Vec<ChatAuditIssue>,
+}
+
+impl ChatAuditReport {
+    pub fn count(&self, kind: ChatAuditIssueKind) -> usize {
+        self.issues.iter().filter(|i| i.kind == kind).count()
+    }
+
+    /// Records a re-download of `doc`; documents without a caption have nothing to check against.
+    pub fn record_download(&mut self, doc: &TelegramDocumentInfo, downloaded: Result<Vec<u8>>) {
+        let Some(caption) = doc.caption.as_deref().and_then(ObjectCaption::parse) else {
+            return;
+        };
+        self.hash_checked += 1;
+        let issue = match downloaded {
+            Ok(bytes) if caption.matches(&bytes) => return,
+            Ok(bytes) => ChatAuditIssue {
+                kind: ChatAuditIssueKind::HashMismatch,
+                msg_id: doc.msg_id,
+                object_kind: Some(caption.kind),
+                message: format!(
+                    "downloaded payload does not match caption: caption_len={} got_len={}",
+                    caption.len,
+                    bytes.len()
+                ),
+            },
+            Err(e) => ChatAuditIssue {
+                kind: ChatAuditIssueKind::DownloadFailed,
+                msg_id: doc.msg_id,
+                object_kind: Some(caption.kind),
+                message: e.to_string(),
+            },
+        };
+        self.issues.push(issue);
+    }
+}
+
+/// Checks the documents of one scan of the chat (message ids `scanned`) against their captions
+/// and against `indexed`, the chat's objects in the local index DB.
+pub fn audit_chat_documents(
+    documents: &[TelegramDocumentInfo],
+    indexed: &[TgMtProtoObjectIdV1],
+    scanned: RangeInclusive<i32>,
+) -> ChatAuditReport {
+    let indexed_by_msg: HashMap<i32, i64> = indexed.iter().map(|o| (o.msg_id, o.doc_id)).collect();
+    let docs_by_msg: HashMap<i32, &TelegramDocumentInfo> =
+        documents.iter().map(|d| (d.msg_id, d)).collect();
+
+    let mut report = ChatAuditReport::default();
+    for doc in documents {
+        report.documents_scanned += 1;
+        let caption = doc.caption.as_deref().and_then(ObjectCaption::parse);
+        let is_indexed = indexed_by_msg.get(&doc.msg_id) == Some(&doc.doc_id);
+        if is_indexed {
+            report.indexed += 1;
+        }
+        let Some(caption) = caption else {
+            if !is_indexed {
+                report.unrelated += 1;
+            }
+            continue;
+        };
+        report.captioned += 1;
+        let object_prefix = doc
+            .file_name
+            .as_deref()
+            .and_then(|name| split_object_prefix(name).0);
+        *report
+            .captioned_by_prefix
+            .entry(object_prefix.unwrap_or_default().to_string())
+            .or_default() += 1;
+        if caption.len != doc.size {
+            report.issues.push(ChatAuditIssue {
+                kind: ChatAuditIssueKind::SizeMismatch,
+                msg_id: doc.msg_id,
+                object_kind: Some(caption.kind),
+                message: format!("caption_len={} document_size={}", caption.len, doc.size),
+            });
+        }
+        // Bootstrap catalogs are found by their caption, not through the index DB.
+        if !is_indexed && caption.kind != ObjectKind::BootstrapCatalog {
+            report.issues.push(ChatAuditIssue {
+                kind: ChatAuditIssueKind::Extra,
+                msg_id: doc.msg_id,
+                object_kind: Some(caption.kind),
+                message: match object_prefix {
+                    Some(prefix) => {
+                        format!("not referenced by the local index db: object_prefix={prefix}")
+                    }
+                    None => "not referenced by the local index db".to_string(),
+                },
+            });
+        }
+    }
+
+    for object in indexed {
+        if !scanned.contains(&object.msg_id) {
+            continue;
+        }
+        let message = match docs_by_msg.get(&object.msg_id) {
+            Some(doc) if doc.doc_id == object.doc_id => continue,
+            Some(doc) => format!(
+                "message holds another document: expected_doc_id={} got_doc_id={}",
+                object.doc_id, doc.doc_id
+            ),
+            None => "no document at this message".to_string(),
+        };
+        report.issues.push(ChatAuditIssue {
+            kind: ChatAuditIssueKind::Missing,
+            msg_id: object.msg_id,
+            object_kind: None,
+            message,
+        });
+    }
+    report.issues.sort_by_key(|i| (i.msg_id, i.kind.as_str()));
+    report
+}
+
+/// Whether `doc_id` is in a `percent` sample; `seed` picks which share.
+pub fn in_audit_sample(doc_id: i64, percent: f64, seed: u64) -> bool {
+    if percent >= 100.0 {
+        return true;
+    }
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&seed.to_le_bytes());
+    hasher.update(&doc_id.to_le_bytes());
+    let pos = u64::from_le_bytes(
+        hasher.finalize().as_bytes()[..8]
+            .try_into()
+            .expect("8 bytes"),
+    );
+    (pos as f64) < percent / 100.0 * 2f64.powi(64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    fn doc(
+        msg_id: i32,
+        doc_id: i64,
+        payload: &[u8],
+        kind: Option<ObjectKind>,
+    ) -> TelegramDocumentInfo {
+        TelegramDocumentInfo {
+            msg_id,
+            doc_id,
+            access_hash: 5,
+            size: payload.len() as u64,
+            caption: kind.map(|k| ObjectCaption::for_payload(k, payload).encode()),
+            forwarded_from: None,
+            file_name: None,
+        }
+    }
+
+    fn indexed(msg_id: i32, doc_id: i64) -> TgMtProtoObjectIdV1 {
+        TgMtProtoObjectIdV1 {
+            peer: "-100111".to_string(),
+            msg_id,
+            doc_id,
+            access_hash: 5,
+        }
+    }
+
+    #[test]
+    fn captions_round_trip_and_reject_other_text() {
+        let caption = ObjectCaption::for_payload(ObjectKind::Pack, b"payload");
+        let encoded = caption.encode();
+        assert!(encoded.starts_with("televybackup:v1 kind=pack len=7 sha256="));
+        assert_eq!(ObjectCaption::parse(&encoded), Some(caption.clone()));
+        assert!(caption.matches(b"payload"));
+        assert!(!caption.matches(b"payloaD"));
+
+        assert_eq!(ObjectCaption::parse(""), None);
+        assert_eq!(ObjectCaption::parse("holiday photos"), None);
+        assert_eq!(
+            ObjectCaption::parse("televybackup:v1 kind=pack len=7 sha256=xyz"),
+            None
+        );
+    }
+
+    #[test]
+    fn audit_reports_mismatches_extra_and_missing_objects() {
+        let mut resized = doc(3, 30, b"chunk-three", Some(ObjectKind::Chunk));
+        resized.size += 1;
+        let documents = vec![
+            doc(1, 10, b"chunk-one", Some(ObjectKind::Chunk)),
+            // Uploaded before captions existed.
+            doc(2, 20, b"old", None),
+            resized,
+            doc(4, 40, b"stray", Some(ObjectKind::Pack)),
+            doc(5, 50, b"someone's photo", None),
+            doc(7, 71, b"replaced", None),
+            doc(8, 80, b"catalog", Some(ObjectKind::BootstrapCatalog)),
+        ];
+        let index = vec![
+            indexed(1, 10),
+            indexed(2, 20),
+            indexed(3, 30),
+            indexed(6, 60),
+            indexed(7, 70),
+            // Past the scanned range: not reported.
+            indexed(20, 200),
+        ];
+
+        let mut report = audit_chat_documents(&documents, &index, 1..=10);
+        assert_eq!(
+            (
+                report.documents_scanned,
+                report.captioned,
+                report.indexed,
+                report.unrelated
+            ),
+            (7, 4, 3, 2)
+        );
+        assert_eq!(
+            report
+                .issues
+                .iter()
+                .map(|i| (i.msg_id, i.kind))
+                .collect::<Vec<_>>(),
+            vec![
+                (3, ChatAuditIssueKind::SizeMismatch),
+                (4, ChatAuditIssueKind::Extra),
+                (6, ChatAuditIssueKind::Missing),
+                (7, ChatAuditIssueKind::Missing),
+            ]
+        );
+
+        report.record_download(&documents[0], Ok(b"chunk-one".to_vec()));
+        report.record_download(&documents[0], Ok(b"chunk-0ne".to_vec()));
+        report.record_download(
+            &documents[3],
+            Err(Error::telegram("message not found".to_string())),
+        );
+        report.record_download(&documents[1], Ok(Vec::new()));
+        assert_eq!(report.hash_checked, 3);
+        assert_eq!(report.count(ChatAuditIssueKind::HashMismatch), 1);
+        assert_eq!(report.count(ChatAuditIssueKind::DownloadFailed), 1);
+    }
+
+    #[test]
+    fn audit_attributes_captioned_documents_to_object_prefixes() {
+        let named = |msg_id, name: &str| TelegramDocumentInfo {
+            file_name: Some(name.to_string()),
+            ..doc(msg_id, i64::from(msg_id), b"x", Some(ObjectKind::Chunk))
+        };
+        let documents = vec![
+            named(1, "mac-mini-file_0123456789ab.dat"),
+            named(2, "nas-file_0123456789ab.dat"),
+            named(3, "file_0123456789ab.dat"),
+            doc(4, 4, b"x", Some(ObjectKind::Chunk)),
+            named(5, "mac-mini-televybackup-bootstrap.catalog"),
+        ];
+        let report = audit_chat_documents(&documents, &[indexed(1, 1)], 1..=5);
+        assert_eq!(
+            report.captioned_by_prefix,
+            BTreeMap::from([
+                (String::new(), 2),
+                ("mac-mini".to_string(), 2),
+                ("nas".to_string(), 1),
+            ])
+        );
+        let extra = report
+            .issues
+            .iter()
+            .find(|i| i.msg_id == 2)
+            .expect("nas object is extra");
+        assert!(
+            extra.message.ends_with("object_prefix=nas"),
+            "{}",
+            extra.message
+        );
+    }
+
+    #[test]
+    fn audit_sample_keeps_roughly_the_requested_share() {
+        let picked = (0..10_000)
+            .filter(|id| in_audit_sample(*id, 10.0, 42))
+            .count();
+        assert!((800..1200).contains(&picked), "{picked}");
+        assert!((0..100).all(|id| in_audit_sample(id, 100.0, 7)));
+    }
+}
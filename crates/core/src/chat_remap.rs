@@ -0,0 +1,616 @@
+//! Moving an endpoint to another chat by forwarding its messages (`televybackup index
+//! remap-chat`).
+//!
+//! MTProto object ids address documents by `peer + msgId`. Forwarded documents keep their
+//! document id but get new message ids in the new chat, so a [`ChatRemap`] pairs old message ids
+//! with new ones. [`remap_index_db_chat`] rewrites the local index DB and records the pairs;
+//! [`RemappedStorage`] translates the object ids that only exist in remote copies (filemap
+//! manifests, endpoint DBs uploaded before the move) when they are downloaded.
+
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+
+use futures::TryStreamExt;
+use sqlx::{Row, Sqlite, Transaction};
+
+use crate::index_sync::{
+    ENDPOINT_STATE_DEDUPE_CATALOG_OBJECT_ID_KEY, ENDPOINT_STATE_ENDPOINT_MANIFEST_OBJECT_ID_KEY,
+};
+use crate::storage::{
+    ChunkObjectRef, DownloadBatch, Storage, StorageProgress, TelegramDocumentInfo,
+    TgMtProtoObjectIdV1, UploadBody, UploadMetadata, encode_tgfile_object_id,
+    encode_tgmtproto_object_id_v1, encode_tgpack_object_id, parse_chunk_object_ref,
+    parse_tgmtproto_object_id_v1,
+};
+use crate::{Error, Result};
+
+/// `(table, column, filter)` of every object id the index DB stores.
+const OBJECT_ID_COLUMNS: &[(&str, &str, &str)] = &[
+    ("chunk_objects", "object_id", ""),
+    ("remote_indexes", "manifest_object_id", ""),
+    ("remote_index_parts", "object_id", ""),
+    ("endpoint_state", "value", "key IN (?, ?)"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatRemap {
+    old_chat_id: String,
+    new_chat_id: String,
+    /// Old message id -> new message id.
+    msg_ids: HashMap<i32, i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ObjectIdRemap {
+    /// Not an object in the old chat.
+    Unchanged,
+    Remapped(String),
+    /// In the old chat, but the mapping has no entry for its message.
+    Unmapped,
+}
+
+impl ChatRemap {
+    pub fn new(old_chat_id: &str, new_chat_id: &str, msg_ids: HashMap<i32, i32>) -> Result<Self> {
+        let old_chat_id = crate::config::normalize_chat_id(old_chat_id)?;
+        let new_chat_id = crate::config::normalize_chat_id(new_chat_id)?;
+        if old_chat_id.is_empty() || new_chat_id.is_empty() {
+            return Err(Error::InvalidConfig {
+                message: "old and new chat ids must not be empty".to_string(),
+            });
+        }
+        if old_chat_id == new_chat_id {
+            return Err(Error::InvalidConfig {
+                message: format!("old and new chat are the same: {old_chat_id}"),
+            });
+        }
+        Ok(Self {
+            old_chat_id,
+            new_chat_id,
+            msg_ids,
+        })
+    }
+
+    /// Parses a mapping file: one `old_msg_id,new_msg_id` pair per line. Blank lines, `#`
+    /// comments and a header line are skipped.
+    pub fn parse_mapping_csv(old_chat_id: &str, new_chat_id: &str, text: &str) -> Result<Self> {
+        let mut msg_ids = HashMap::new();
+        let mut seen_row = false;
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let first_row = !seen_row;
+            seen_row = true;
+            let pair = line
+                .split_once(',')
+                .map(|(old, new)| (old.trim().parse::<i32>(), new.trim().parse::<i32>()));
+            match pair {
+                Some((Ok(old), Ok(new))) if old > 0 && new > 0 => {
+                    if msg_ids.insert(old, new).is_some_and(|prev| prev != new) {
+                        return Err(Error::InvalidConfig {
+                            message: format!(
+                                "mapping line {}: message {old} is mapped twice",
+                                idx + 1
+                            ),
+                        });
+                    }
+                }
+                _ if first_row => {}
+                _ => {
+                    return Err(Error::InvalidConfig {
+                        message: format!(
+                            "mapping line {}: expected old_msg_id,new_msg_id (got {line:?})",
+                            idx + 1
+                        ),
+                    });
+                }
+            }
+        }
+        Self::new(old_chat_id, new_chat_id, msg_ids)
+    }
+
+    pub fn old_chat_id(&self) -> &str {
+        &self.old_chat_id
+    }
+
+    pub fn new_chat_id(&self) -> &str {
+        &self.new_chat_id
+    }
+
+    pub fn len(&self) -> usize {
+        self.msg_ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.msg_ids.is_empty()
+    }
+
+    /// Remaps a storage object id (`tgmtproto:v1:...`).
+    pub fn remap_storage_object_id(&self, object_id: &str) -> ObjectIdRemap {
+        let Ok(parsed) = parse_tgmtproto_object_id_v1(object_id) else {
+            return ObjectIdRemap::Unchanged;
+        };
+        if parsed.peer != self.old_chat_id {
+            return ObjectIdRemap::Unchanged;
+        }
+        let Some(&new_msg_id) = self.msg_ids.get(&parsed.msg_id) else {
+            return ObjectIdRemap::Unmapped;
+        };
+        encode_tgmtproto_object_id_v1(
+            &self.new_chat_id,
+            new_msg_id,
+            parsed.doc_id,
+            parsed.access_hash,
+        )
+        .map_or(ObjectIdRemap::Unmapped, ObjectIdRemap::Remapped)
+    }
+
+    /// Remaps an index DB object id: a storage object id, a `tgfile:` id or a `tgpack:` slice.
+    pub fn remap_chunk_object_id(&self, encoded: &str) -> ObjectIdRemap {
+        if let Some(object_id) = encoded.strip_prefix("tgfile:") {
+            return match self.remap_storage_object_id(object_id) {
+                ObjectIdRemap::Remapped(id) => {
+                    ObjectIdRemap::Remapped(encode_tgfile_object_id(&id))
+                }
+                other => other,
+            };
+        }
+        if let Ok(ChunkObjectRef::PackSlice {
+            pack_object_id,
+            offset,
+            len,
+        }) = parse_chunk_object_ref(encoded)
+        {
+            return match self.remap_storage_object_id(&pack_object_id) {
+                ObjectIdRemap::Remapped(id) => {
+                    ObjectIdRemap::Remapped(encode_tgpack_object_id(&id, offset, len))
+                }
+                other => other,
+            };
+        }
+        self.remap_storage_object_id(encoded)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChatRemapReport {
+    pub dry_run: bool,
+    pub chunk_objects_remapped: u64,
+    pub remote_indexes_remapped: u64,
+    pub remote_index_parts_remapped: u64,
+    pub endpoint_state_remapped: u64,
+    /// Object ids in the old chat without a mapping entry; they are left unchanged.
+    pub unmapped: u64,
+}
+
+impl ChatRemapReport {
+    pub fn remapped(&self) -> u64 {
+        self.chunk_objects_remapped
+            + self.remote_indexes_remapped
+            + self.remote_index_parts_remapped
+            + self.endpoint_state_remapped
+    }
+}
+
+/// Rewrites every object id of `remap`'s old chat in the index DB at `db_path` and records the
+/// message pairs for [`load_chat_remaps`], in one transaction. `dry_run` only counts.
+pub async fn remap_index_db_chat(
+    db_path: &Path,
+    remap: &ChatRemap,
+    dry_run: bool,
+) -> Result<ChatRemapReport> {
+    if !db_path.exists() {
+        return Err(Error::InvalidConfig {
+            message: format!("index db not found: {}", db_path.display()),
+        });
+    }
+    let pool = crate::index_db::open_index_db(db_path).await?;
+    let mut tx = pool.begin().await?;
+
+    let mut report = ChatRemapReport {
+        dry_run,
+        ..Default::default()
+    };
+    for &(table, column, filter) in OBJECT_ID_COLUMNS {
+        let rows = select_object_ids(&mut tx, table, column, filter).await?;
+        let mut remapped = 0u64;
+        for (rowid, object_id) in rows {
+            match remap.remap_chunk_object_id(&object_id) {
+                ObjectIdRemap::Remapped(new_object_id) => {
+                    remapped += 1;
+                    if !dry_run {
+                        sqlx::query(&format!("UPDATE {table} SET {column} = ? WHERE rowid = ?"))
+                            .bind(&new_object_id)
+                            .bind(rowid)
+                            .execute(&mut *tx)
+                            .await?;
+                    }
+                }
+                ObjectIdRemap::Unmapped => report.unmapped += 1,
+                ObjectIdRemap::Unchanged => {}
+            }
+        }
+        match table {
+            "chunk_objects" => report.chunk_objects_remapped = remapped,
+            "remote_indexes" => report.remote_indexes_remapped = remapped,
+            "remote_index_parts" => report.remote_index_parts_remapped = remapped,
+            _ => report.endpoint_state_remapped = remapped,
+        }
+    }
+
+    if dry_run {
+        tx.rollback().await?;
+    } else {
+        // All pairs, not just the ones used here: remote index DBs may reference messages the
+        // local DB no longer does.
+        for (old_msg_id, new_msg_id) in &remap.msg_ids {
+            sqlx::query(
+                "INSERT OR REPLACE INTO chat_remap_messages (old_peer, old_msg_id, new_peer, new_msg_id, created_at) VALUES (?, ?, ?, ?, strftime('%Y-%m-%dT%H:%M:%fZ','now'))",
+            )
+            .bind(&remap.old_chat_id)
+            .bind(old_msg_id)
+            .bind(&remap.new_chat_id)
+            .bind(new_msg_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+    }
+    pool.close().await;
+    Ok(report)
+}
+
+/// Remaps recorded by [`remap_index_db_chat`]; empty when the DB doesn't exist.
+pub async fn load_chat_remaps(db_path: &Path) -> Result<Vec<ChatRemap>> {
+    if !db_path.exists() {
+        return Ok(Vec::new());
+    }
+    let pool = crate::index_db::open_index_db(db_path).await?;
+    let rows =
+        sqlx::query("SELECT old_peer, old_msg_id, new_peer, new_msg_id FROM chat_remap_messages")
+            .fetch_all(&pool)
+            .await?;
+    pool.close().await;
+
+    let mut by_chat = BTreeMap::<(String, String), HashMap<i32, i32>>::new();
+    for row in rows {
+        by_chat
+            .entry((row.get("old_peer"), row.get("new_peer")))
+            .or_default()
+            .insert(row.get("old_msg_id"), row.get("new_msg_id"));
+    }
+    Ok(by_chat
+        .into_iter()
+        .map(|((old_chat_id, new_chat_id), msg_ids)| ChatRemap {
+            old_chat_id,
+            new_chat_id,
+            msg_ids,
+        })
+        .collect())
+}
+
+/// Every MTProto object in `chat_id` the index DB at `db_path` refers to (pack slices as their
+/// pack), deduplicated.
+pub async fn index_db_objects_in_chat(
+    db_path: &Path,
+    chat_id: &str,
+) -> Result<Vec<TgMtProtoObjectIdV1>> {
+    let pool = crate::index_db::open_existing_index_db(db_path).await?;
+    let mut conn = pool.begin().await?;
+    let mut out = BTreeMap::new();
+    for &(table, column, filter) in OBJECT_ID_COLUMNS {
+        for (_, encoded) in select_object_ids(&mut conn, table, column, filter).await? {
+            let object_id = match parse_chunk_object_ref(&encoded) {
+                Ok(ChunkObjectRef::Direct { object_id }) => object_id,
+                Ok(ChunkObjectRef::PackSlice { pack_object_id, .. }) => pack_object_id,
+                Err(_) => continue,
+            };
+            if let Ok(parsed) = parse_tgmtproto_object_id_v1(&object_id)
+                && parsed.peer == chat_id
+            {
+                out.insert((parsed.msg_id, parsed.doc_id), parsed);
+            }
+        }
+    }
+    conn.rollback().await?;
+    pool.close().await;
+    Ok(out.into_values().collect())
+}
+
+/// Document id -> message id of every object the index DB at `db_path` stores in `chat_id`.
+pub async fn index_db_documents_in_chat(
+    db_path: &Path,
+    chat_id: &str,
+) -> Result<HashMap<i64, i32>> {
+    Ok(index_db_objects_in_chat(db_path, chat_id)
+        .await?
+        .into_iter()
+        .map(|o| (o.doc_id, o.msg_id))
+        .collect())
+}
+
+/// `(old_msg_id, new_msg_id)` for the messages of `old_chat_id` found again in `forwarded`, sorted
+/// by old message id.
+///
+/// A post forwarded from a channel names its original message; other forwards are matched to
+/// `old_documents` (document id -> old message id, see [`index_db_documents_in_chat`]) because
+/// forwarding keeps the document id.
+pub fn match_forwarded_documents(
+    old_chat_id: &str,
+    old_documents: &HashMap<i64, i32>,
+    forwarded: &[TelegramDocumentInfo],
+) -> Vec<(i32, i32)> {
+    let mut pairs: Vec<(i32, i32)> = forwarded
+        .iter()
+        .filter_map(|doc| {
+            let by_header = doc
+                .forwarded_from
+                .as_ref()
+                .filter(|(chat_id, _)| chat_id == old_chat_id)
+                .map(|&(_, old)| old);
+            by_header
+                .or_else(|| old_documents.get(&doc.doc_id).copied())
+                .map(|old| (old, doc.msg_id))
+        })
+        .collect();
+    pairs.sort_unstable();
+    pairs.dedup_by_key(|(old, _)| *old);
+    pairs
+}
+
+/// Mapping file contents for [`ChatRemap::parse_mapping_csv`].
+pub fn mapping_csv(pairs: &[(i32, i32)]) -> String {
+    let mut out = String::from("old_msg_id,new_msg_id\n");
+    for (old, new) in pairs {
+        out.push_str(&format!("{old},{new}\n"));
+    }
+    out
+}
+
+async fn select_object_ids(
+    tx: &mut Transaction<'_, Sqlite>,
+    table: &str,
+    column: &str,
+    filter: &str,
+) -> Result<Vec<(i64, String)>> {
+    let sql = if filter.is_empty() {
+        format!("SELECT rowid AS rid, {column} AS object_id FROM {table}")
+    } else {
+        format!("SELECT rowid AS rid, {column} AS object_id FROM {table} WHERE {filter}")
+    };
+    let mut query = sqlx::query(&sql);
+    if !filter.is_empty() {
+        query = query
+            .bind(ENDPOINT_STATE_ENDPOINT_MANIFEST_OBJECT_ID_KEY)
+            .bind(ENDPOINT_STATE_DEDUPE_CATALOG_OBJECT_ID_KEY);
+    }
+    let mut out = Vec::new();
+    let mut rows = query.fetch(&mut **tx);
+    while let Some(row) = rows.try_next().await? {
+        out.push((row.get("rid"), row.get("object_id")));
+    }
+    Ok(out)
+}
+
+/// Storage that downloads objects of a moved chat from their forwarded copies; everything else
+/// goes straight to `inner`.
+pub struct RemappedStorage<'a, S> {
+    inner: &'a S,
+    remaps: Vec<ChatRemap>,
+}
+
+impl<'a, S: Storage> RemappedStorage<'a, S> {
+    pub fn new(inner: &'a S, remaps: Vec<ChatRemap>) -> Self {
+        Self { inner, remaps }
+    }
+
+    /// Where `object_id` lives now, following chained moves (A -> B -> C).
+    pub fn resolve(&self, object_id: &str) -> String {
+        let mut current = object_id.to_string();
+        // Bounded so a cyclic mapping can't spin forever.
+        for _ in 0..self.remaps.len() {
+            let next = self
+                .remaps
+                .iter()
+                .find_map(|r| match r.remap_storage_object_id(&current) {
+                    ObjectIdRemap::Remapped(id) => Some(id),
+                    _ => None,
+                });
+            match next {
+                Some(id) => current = id,
+                None => break,
+            }
+        }
+        current
+    }
+}
+
+impl<S: Storage + Sync> Storage for RemappedStorage<'_, S> {
+    fn provider(&self) -> &str {
+        self.inner.provider()
+    }
+
+    fn object_id_scope(&self) -> Option<&str> {
+        self.inner.object_id_scope()
+    }
+
+    fn legacy_object_id_scopes(&self) -> &[String] {
+        self.inner.legacy_object_id_scopes()
+    }
+
+    fn object_prefix(&self) -> Option<&str> {
+        self.inner.object_prefix()
+    }
+
+    fn upload_document<'a>(
+        &'a self,
+        filename: &'a str,
+        bytes: Vec<u8>,
+    ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>> {
+        self.inner.upload_document(filename, bytes)
+    }
+
+    fn upload_document_with_progress<'a>(
+        &'a self,
+        filename: &'a str,
+        bytes: Vec<u8>,
+        progress: Option<Box<dyn FnMut(StorageProgress) + Send + 'a>>,
+    ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>> {
+        self.inner
+            .upload_document_with_progress(filename, bytes, progress)
+    }
+
+    fn upload_document_stream<'a>(
+        &'a self,
+        filename: &'a str,
+        body: UploadBody<'a>,
+        len: u64,
+        progress: Option<Box<dyn FnMut(StorageProgress) + Send + 'a>>,
+    ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>> {
+        self.inner
+            .upload_document_stream(filename, body, len, progress)
+    }
+
+    fn upload_document_stream_with_metadata<'a>(
+        &'a self,
+        filename: &'a str,
+        body: UploadBody<'a>,
+        len: u64,
+        progress: Option<Box<dyn FnMut(StorageProgress) + Send + 'a>>,
+        metadata: Option<UploadMetadata>,
+    ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>> {
+        self.inner
+            .upload_document_stream_with_metadata(filename, body, len, progress, metadata)
+    }
+
+    fn download_document<'a>(
+        &'a self,
+        object_id: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>>> + Send + 'a>> {
+        let object_id = self.resolve(object_id);
+        Box::pin(async move { self.inner.download_document(&object_id).await })
+    }
+
+    fn download_document_with_progress<'a>(
+        &'a self,
+        object_id: &'a str,
+        progress: Option<Box<dyn FnMut(StorageProgress) + Send + 'a>>,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>>> + Send + 'a>> {
+        let object_id = self.resolve(object_id);
+        Box::pin(async move {
+            self.inner
+                .download_document_with_progress(&object_id, progress)
+                .await
+        })
+    }
+
+    fn object_size<'a>(
+        &'a self,
+        object_id: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<u64>>> + Send + 'a>> {
+        let object_id = self.resolve(object_id);
+        Box::pin(async move { self.inner.object_size(&object_id).await })
+    }
+
+    fn download_documents<'a>(
+        &'a self,
+        object_ids: &'a [String],
+    ) -> Pin<Box<dyn Future<Output = Result<DownloadBatch>> + Send + 'a>> {
+        let object_ids = object_ids
+            .iter()
+            .map(|id| self.resolve(id))
+            .collect::<Vec<_>>();
+        Box::pin(async move { self.inner.download_documents(&object_ids).await })
+    }
+
+    fn upload_documents<'a>(
+        &'a self,
+        items: Vec<(&'a str, Vec<u8>)>,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<String>>> + Send + 'a>> {
+        self.inner.upload_documents(items)
+    }
+
+    fn delete_document<'a>(
+        &'a self,
+        object_id: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+        let object_id = self.resolve(object_id);
+        Box::pin(async move { self.inner.delete_document(&object_id).await })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mapping_csv_roundtrips_and_remaps_every_object_id_form() {
+        let text = mapping_csv(&[(7, 107), (8, 108)]);
+        let remap = ChatRemap::parse_mapping_csv("-100111", "https://t.me/c/222/1", &text).unwrap();
+        assert_eq!(remap.new_chat_id(), "-100222");
+        assert_eq!(remap.len(), 2);
+
+        let old = encode_tgmtproto_object_id_v1("-100111", 7, 42, 9).unwrap();
+        let new = encode_tgmtproto_object_id_v1("-100222", 107, 42, 9).unwrap();
+        assert_eq!(
+            remap.remap_chunk_object_id(&old),
+            ObjectIdRemap::Remapped(new.clone())
+        );
+        assert_eq!(
+            remap.remap_chunk_object_id(&encode_tgfile_object_id(&old)),
+            ObjectIdRemap::Remapped(encode_tgfile_object_id(&new))
+        );
+        assert_eq!(
+            remap.remap_chunk_object_id(&encode_tgpack_object_id(&old, 10, 20)),
+            ObjectIdRemap::Remapped(encode_tgpack_object_id(&new, 10, 20))
+        );
+
+        let unmapped = encode_tgmtproto_object_id_v1("-100111", 9, 43, 9).unwrap();
+        assert_eq!(
+            remap.remap_chunk_object_id(&unmapped),
+            ObjectIdRemap::Unmapped
+        );
+        let elsewhere = encode_tgmtproto_object_id_v1("-100333", 7, 42, 9).unwrap();
+        assert_eq!(
+            remap.remap_chunk_object_id(&elsewhere),
+            ObjectIdRemap::Unchanged
+        );
+        assert_eq!(
+            remap.remap_chunk_object_id("mem:abc"),
+            ObjectIdRemap::Unchanged
+        );
+
+        assert!(ChatRemap::parse_mapping_csv("-100111", "-100222", "1,2\nx,3\n").is_err());
+        assert!(ChatRemap::parse_mapping_csv("-100111", "-100222", "1,2\n1,3\n").is_err());
+        assert!(ChatRemap::parse_mapping_csv("-100111", "-100111", "1,2\n").is_err());
+    }
+
+    #[test]
+    fn forwarded_documents_match_by_forward_header_or_document_id() {
+        let old = HashMap::from([(42i64, 7i32), (43, 8), (44, 9)]);
+        let doc = |msg_id, doc_id, forwarded_from: Option<(&str, i32)>| TelegramDocumentInfo {
+            msg_id,
+            doc_id,
+            access_hash: 9,
+            size: 1,
+            caption: None,
+            forwarded_from: forwarded_from.map(|(chat, msg)| (chat.to_string(), msg)),
+            file_name: None,
+        };
+        let forwarded = [
+            doc(108, 43, None),
+            doc(107, 42, Some(("-100111", 7))),
+            // Only the forward header knows where this one came from.
+            doc(106, 41, Some(("-100111", 6))),
+            doc(110, 99, Some(("-100333", 9))),
+            doc(111, 98, None),
+        ];
+        assert_eq!(
+            match_forwarded_documents("-100111", &old, &forwarded),
+            vec![(6, 106), (7, 107), (8, 108)]
+        );
+    }
+}
@@ -0,0 +1,97 @@
+//! Upkeep of `chunks.ref_count` in endpoint index DBs (`migrations/0013_chunk_ref_count.sql`).
+//!
+//! A snapshot's `file_chunks` rows go into the counts once, in the transaction that sets its
+//! `chunk_refs_counted`, and come out again before retention removes it. When they cannot come
+//! out (the file map is neither in the endpoint DB nor cached) the counts are flagged stale, and
+//! readers fall back to scanning every file map until `index check --repair` recounts them.
+
+use sqlx::SqliteConnection;
+
+/// `endpoint_state` key present while the counts cannot be trusted; its value says why.
+pub(crate) const CHUNK_REF_COUNTS_STALE_KEY: &str = "chunk_ref_counts_stale";
+
+/// Whether `<schema>.chunks` has `ref_count`. DBs opened without migrating may not.
+pub(crate) async fn have_ref_count_column(
+    conn: &mut SqliteConnection,
+    schema: &str,
+) -> sqlx::Result<bool> {
+    let n: i64 = sqlx::query_scalar(&format!(
+        "SELECT COUNT(1) FROM pragma_table_info('chunks', '{schema}') WHERE name = 'ref_count'"
+    ))
+    .fetch_one(&mut *conn)
+    .await?;
+    Ok(n == 1)
+}
+
+/// Whether `main.chunks.ref_count` can stand in for a scan of the counted snapshots' file maps.
+pub(crate) async fn ref_counts_usable(conn: &mut SqliteConnection) -> sqlx::Result<bool> {
+    if !have_ref_count_column(conn, "main").await? {
+        return Ok(false);
+    }
+    let stale: Option<String> =
+        sqlx::query_scalar("SELECT value FROM main.endpoint_state WHERE key = ?")
+            .bind(CHUNK_REF_COUNTS_STALE_KEY)
+            .fetch_optional(&mut *conn)
+            .await?;
+    Ok(stale.is_none())
+}
+
+pub(crate) async fn mark_ref_counts_stale(
+    conn: &mut SqliteConnection,
+    reason: &str,
+) -> sqlx::Result<()> {
+    sqlx::query("INSERT OR REPLACE INTO main.endpoint_state (key, value) VALUES (?, ?)")
+        .bind(CHUNK_REF_COUNTS_STALE_KEY)
+        .bind(reason)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// Adds (`sign = 1`) or subtracts (`sign = -1`) the `file_chunks` rows of `snapshot_id` in the
+/// file map at `schema` to `main.chunks.ref_count`, and sets the snapshot's `chunk_refs_counted`
+/// to match. Run it inside the caller's transaction.
+///
+/// Adding from an attached file map first copies the chunk rows the endpoint DB lacks (chunks
+/// deduplicated against another machine's uploads), so no reference is dropped.
+pub(crate) async fn apply_snapshot_refs(
+    conn: &mut SqliteConnection,
+    schema: &str,
+    snapshot_id: &str,
+    sign: i64,
+) -> sqlx::Result<()> {
+    if sign > 0 && schema != "main" {
+        sqlx::query(&format!(
+            r#"
+            INSERT OR IGNORE INTO main.chunks (chunk_hash, size, hash_alg, enc_alg, created_at)
+            SELECT chunk_hash, size, hash_alg, enc_alg, created_at
+            FROM {schema}.chunks
+            "#
+        ))
+        .execute(&mut *conn)
+        .await?;
+    }
+    sqlx::query(&format!(
+        r#"
+        UPDATE main.chunks SET ref_count = ref_count + ? * r.n
+        FROM (
+          SELECT fc.chunk_hash AS chunk_hash, COUNT(*) AS n
+          FROM {schema}.file_chunks fc
+          JOIN {schema}.files f ON f.file_id = fc.file_id
+          WHERE f.snapshot_id = ?
+          GROUP BY fc.chunk_hash
+        ) AS r
+        WHERE r.chunk_hash = chunks.chunk_hash
+        "#
+    ))
+    .bind(sign)
+    .bind(snapshot_id)
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query("UPDATE main.snapshots SET chunk_refs_counted = ? WHERE snapshot_id = ?")
+        .bind(sign > 0)
+        .bind(snapshot_id)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
@@ -1,26 +1,74 @@
+use std::io::Write as _;
 use std::path::{Path, PathBuf};
 
 use serde::de::Error as _;
 use serde::{Deserialize, Serialize};
 
+use crate::bootstrap::BootstrapPinMode;
 use crate::crypto::FRAMING_OVERHEAD_BYTES;
+use crate::fs_sync::sync_dir;
 use crate::storage::MTPROTO_ENGINEERED_UPLOAD_MAX_BYTES;
 use crate::{Error, Result};
 
+mod schema;
+pub use schema::{
+    SETTINGS_FIELDS, SettingsField, SettingsFieldType, settings_defaults_toml,
+    settings_field_default, settings_schema_json,
+};
+
 pub const SETTINGS_SCHEMA_VERSION: u32 = 2;
 
+/// Upper bound for `performance.worker_threads`.
+pub const MAX_WORKER_THREADS: u32 = 64;
+
+/// Bounds of `performance.status_write_interval_ms`. The daemon rewrites an unchanged status every
+/// second anyway, so a longer interval would only delay progress behind those rewrites.
+pub const MIN_STATUS_WRITE_INTERVAL_MS: u32 = 50;
+pub const MAX_STATUS_WRITE_INTERVAL_MS: u32 = 1000;
+
+/// Upper bound for `restore.write_concurrency`.
+pub const MAX_RESTORE_WRITE_CONCURRENCY: u32 = 64;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SettingsV2 {
     pub version: u32,
     #[serde(default)]
     pub schedule: Schedule,
     #[serde(default)]
+    pub verify_schedule: VerifySchedule,
+    #[serde(default)]
+    pub quiet_hours: QuietHours,
+    #[serde(default)]
     pub retention: Retention,
     #[serde(default)]
     pub chunking: Chunking,
     #[serde(default)]
+    pub scan: Scan,
+    #[serde(default)]
+    pub logs: Logs,
+    #[serde(default)]
+    pub index: Index,
+    #[serde(default)]
+    pub retry: Retry,
+    #[serde(default)]
+    pub performance: Performance,
+    #[serde(default)]
+    pub upload: Upload,
+    #[serde(default)]
+    pub restore: Restore,
+    #[serde(default)]
     pub telegram: TelegramGlobal,
     #[serde(default)]
+    pub security: Security,
+    #[serde(default)]
+    pub remote: Remote,
+    #[serde(default)]
+    pub notifications: Notifications,
+    #[serde(default)]
+    pub settings_history: SettingsHistory,
+    #[serde(default)]
+    pub metrics: Metrics,
+    #[serde(default)]
     pub telegram_endpoints: Vec<TelegramEndpoint>,
     #[serde(default)]
     pub targets: Vec<Target>,
@@ -32,12 +80,60 @@ pub struct Schedule {
     pub kind: String,
     pub hourly_minute: u8,
     pub daily_at: String,
+    /// `local` or a tz database name; see [`crate::schedule_tz::ScheduleTz`].
     pub timezone: String,
+    /// Daemon only: run a slot that passed while the daemon was not ticking (asleep, busy with
+    /// another run) as soon as it ticks again, instead of skipping it.
+    #[serde(default = "default_true")]
+    pub catch_up: bool,
+    /// Daemon only: backups running at once (daemon and CLI runs alike); further run requests
+    /// wait in the daemon's run queue.
+    #[serde(default = "default_schedule_max_concurrent_runs")]
+    pub max_concurrent_runs: u32,
+}
+
+/// Daemon only: periodic `verify latest` of every enabled target that has a snapshot.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerifySchedule {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Days between a target's verifies, counted from its last successful (or last failed) one.
+    #[serde(default = "default_verify_every_days")]
+    pub every_days: u32,
+    /// Below 100, check a rotating sample of this share of the chunks (`verify --sample-percent`).
+    #[serde(default = "default_verify_sample_percent")]
+    pub sample_percent: u32,
+}
+
+/// Times the daemon starts no scheduled backups or verifies (see [`crate::quiet_hours`]).
+/// Manual runs ignore them unless started with `backup run --respect-quiet-hours`.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct QuietHours {
+    /// Upload limit for runs still going during quiet hours; 0 leaves them unthrottled.
+    #[serde(default)]
+    pub max_upload_bytes_per_sec: u64,
+    #[serde(default)]
+    pub ranges: Vec<QuietHoursRange>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuietHoursRange {
+    /// `mon`..`sun`; empty means every day. A range past midnight belongs to the day it starts.
+    #[serde(default)]
+    pub days: Vec<String>,
+    /// `HH:MM` in `schedule.timezone`.
+    pub start: String,
+    /// `HH:MM`; at or before `start` it ends the next day (`00:00`-`00:00` is the whole day).
+    pub end: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Retention {
     pub keep_last_snapshots: u32,
+    /// Days `gc run` queues unreferenced objects before `gc flush` deletes them, and a removed
+    /// snapshot stays restorable with `gc undo`; 0 deletes right away.
+    #[serde(default = "default_retention_deletion_grace_days")]
+    pub deletion_grace_days: u32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,6 +141,215 @@ pub struct Chunking {
     pub min_bytes: u32,
     pub avg_bytes: u32,
     pub max_bytes: u32,
+    /// How chunk ids of master key snapshots are computed: `blake3`, or `blake3-keyed` so they
+    /// can't be matched against the hashes of known files. Target keys always hash keyed.
+    /// Switching starts a new dedupe generation: the next snapshot uploads every chunk again.
+    #[serde(default = "default_chunking_hash")]
+    pub hash: String,
+}
+
+impl Chunking {
+    /// `hash`, parsed; validation rejects unknown names.
+    pub fn hash_alg(&self) -> crate::ChunkHashAlg {
+        crate::ChunkHashAlg::parse(self.hash.trim()).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Scan {
+    /// Daemon only: watch enabled targets for file system changes between runs so scheduled
+    /// backups can skip re-stat'ing unchanged files.
+    #[serde(default)]
+    pub watch: bool,
+    /// `backup run` asks for confirmation before a target's first backup when the estimated
+    /// source size exceeds this many bytes; 0 disables the check.
+    #[serde(default = "default_scan_warn_initial_backup_bytes")]
+    pub warn_initial_backup_bytes: u64,
+    /// Fail a backup on the first unreadable source file instead of skipping it with a warning.
+    #[serde(default)]
+    pub strict: bool,
+    /// macOS only: back up from a read-only local APFS snapshot of the source volume instead of
+    /// the live files; falls back to the live files when no snapshot can be taken.
+    #[serde(default)]
+    pub use_apfs_snapshot: bool,
+    /// Don't descend into directories on another file system than the target's source path (a
+    /// mounted network share, a FUSE or USB volume); they are skipped and logged.
+    #[serde(default = "default_true")]
+    pub one_file_system: bool,
+    /// Leave out caches, trash and other system-generated noise
+    /// ([`crate::default_excludes::PATTERNS`]). On in new configs; a config without the key keeps
+    /// backing everything up.
+    #[serde(default)]
+    pub default_excludes: bool,
+    /// Warn about (and count) snapshot paths longer than this many bytes, which a restore onto
+    /// another file system may not be able to create; 0 disables the check.
+    #[serde(default = "default_scan_warn_path_bytes")]
+    pub warn_path_bytes: u64,
+    /// Fail a backup instead of keeping the snapshot when more than this percentage of the
+    /// scanned entries could not be read; 0 disables the check.
+    #[serde(default = "default_scan_max_unreadable_percent")]
+    pub max_unreadable_percent: u32,
+    /// Fail a backup before uploading anything when the source has this many percent fewer
+    /// files than at the target's last successful backup (see [`crate::source_guard`]); 0
+    /// disables the check.
+    #[serde(default = "default_scan_max_file_drop_percent")]
+    pub max_file_drop_percent: u32,
+    /// `targets[].sqlite_consistent` databases larger than this (with their `-wal` file) are read
+    /// live instead of copied; 0 disables the limit.
+    #[serde(default = "default_scan_sqlite_consistent_max_bytes")]
+    pub sqlite_consistent_max_bytes: u64,
+    /// Time limit of one database's consistent copy before the live file is read instead.
+    #[serde(default = "default_scan_sqlite_consistent_timeout_secs")]
+    pub sqlite_consistent_timeout_secs: u64,
+}
+
+/// Run log retention (`sync-*.ndjson` under the log dir); 0 disables a limit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Logs {
+    #[serde(default = "default_logs_keep_days")]
+    pub keep_days: u32,
+    #[serde(default = "default_logs_keep_max_files")]
+    pub keep_max_files: u32,
+    /// Record every run in `<data_dir>/usage.sqlite` for `televybackup stats monthly`; unaffected
+    /// by the run log limits above.
+    #[serde(default)]
+    pub usage_stats: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Index {
+    /// Upload a target's full file map index every this many backups and only the changes since
+    /// the previous backup in between; 0 or 1 uploads the full index every time.
+    #[serde(default = "default_index_full_every")]
+    pub full_every: u32,
+}
+
+/// Retries of transient Telegram failures within one run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Retry {
+    /// Attempts per upload/download before the run fails; 1 disables retries.
+    #[serde(default = "default_retry_max_attempts")]
+    pub max_attempts: u32,
+    /// Backoff waited across all retries of a run before further failures end it.
+    #[serde(default = "default_retry_max_total_secs")]
+    pub max_total_secs: u64,
+}
+
+/// Local CPU and disk use of backup runs and the daemon.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Performance {
+    /// Threads reading, chunking and hashing source files during the scan; 0 picks the number of
+    /// physical cores (capped).
+    #[serde(default)]
+    pub worker_threads: u32,
+    /// Daemon only: progress is written to `status.json` at most this often; run starts and
+    /// finishes are written right away.
+    #[serde(default = "default_performance_status_write_interval_ms")]
+    pub status_write_interval_ms: u32,
+}
+
+impl Default for Performance {
+    fn default() -> Self {
+        Self {
+            worker_threads: 0,
+            status_write_interval_ms: default_performance_status_write_interval_ms(),
+        }
+    }
+}
+
+/// How backups confirm their uploads. Every uploaded object's stored size is compared with what
+/// was sent before it is indexed, where the provider reports sizes.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Upload {
+    /// Paranoid mode: download every uploaded object back and compare its content too.
+    #[serde(default)]
+    pub verify_after_upload: bool,
+}
+
+/// How restores write the files they download.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Restore {
+    /// Files written at the same time; each file's chunks are still written in order.
+    #[serde(default = "default_restore_write_concurrency")]
+    pub write_concurrency: u32,
+    /// When restored files are flushed to disk: `per_file`, `at_end` or `none`.
+    #[serde(default = "default_restore_fsync")]
+    pub fsync: String,
+}
+
+impl Default for Restore {
+    fn default() -> Self {
+        Self {
+            write_concurrency: default_restore_write_concurrency(),
+            fsync: default_restore_fsync(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Security {
+    /// Clients restoring through the daemon control socket must present the restore passphrase
+    /// (`security.authorizeRestore`); CLI restores only check it with `--require-passphrase`.
+    /// Advisory: it does not stop anyone who can read the secrets store.
+    #[serde(default)]
+    pub restore_requires_passphrase: bool,
+    /// Argon2 PHC string written by `televybackup security set-restore-passphrase`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restore_passphrase_hash: Option<String>,
+}
+
+/// Daemon only: read-only monitoring over TCP (`status.get`, `snapshots.list`, `stats.get`,
+/// `runs.list`). Off unless `listen` is set; read at daemon start.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Remote {
+    /// `host:port` to listen on, e.g. `"0.0.0.0:9479"`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub listen: Option<String>,
+    /// Bearer token every request must carry; required when `listen` is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// Serve TLS with a self-signed certificate generated on first start (clients pin its
+    /// SHA-256 fingerprint).
+    #[serde(default)]
+    pub tls: bool,
+}
+
+/// Daemon only: a daily digest of every target's last 24 hours (see [`crate::notifications`]).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notifications {
+    #[serde(default)]
+    pub digest_enabled: bool,
+    /// `HH:MM` in `schedule.timezone`; a digest missed while the daemon was not running is sent
+    /// once it runs again.
+    #[serde(default = "default_notifications_digest_at")]
+    pub digest_at: String,
+    /// Receives the digest as a JSON POST.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook_url: Option<String>,
+    /// ntfy topic URL (e.g. `https://ntfy.sh/<topic>`) the digest is published to as text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ntfy_url: Option<String>,
+    /// Include target source paths; the digest otherwise names targets by id only.
+    #[serde(default)]
+    pub include_paths: bool,
+}
+
+/// Earlier `config.toml` generations kept by [`save_settings_v2`] as
+/// `config.toml.bak.<unix_ms>[.<seq>]`, for `televybackup settings rollback`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettingsHistory {
+    /// Generations kept; 0 keeps none.
+    #[serde(default = "default_settings_history_keep")]
+    pub keep: u32,
+}
+
+/// CLI only: Prometheus textfile-collector output (see [`crate::metrics_textfile`]).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Metrics {
+    /// `backup run` and `verify run`/`verify latest` merge their outcome into this file unless
+    /// `--metrics-textfile` names another.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub textfile_path: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -65,16 +370,64 @@ pub struct TelegramEndpoint {
     pub id: String,
     pub mode: String,
     pub chat_id: String,
+    /// Earlier chat ids of this endpoint (a group later upgraded to a supergroup); objects
+    /// uploaded there remain readable.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub migrated_from_chat_ids: Vec<String>,
     pub bot_token_key: String,
     #[serde(default)]
     pub mtproto: TelegramEndpointMtproto,
     #[serde(default)]
     pub rate_limit: TelegramRateLimit,
+    #[serde(default)]
+    pub bootstrap: TelegramEndpointBootstrap,
+    /// Start of the names of the documents this machine uploads, so machines sharing a chat can
+    /// be told apart; derived from the device name when unset. Objects are found by id, so
+    /// changing it orphans nothing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub object_prefix: Option<String>,
+}
+
+impl TelegramEndpoint {
+    /// `telegram_endpoints[].object_prefix`, else one derived from `device_name`.
+    pub fn object_prefix_for(&self, device_name: &str) -> String {
+        self.object_prefix
+            .clone()
+            .unwrap_or_else(|| crate::device::object_prefix_from_device_name(device_name))
+    }
+
+    /// `telegram_endpoints[].mtproto.api_id`, else `telegram.mtproto.api_id`.
+    pub fn mtproto_api_id(&self, global: &TelegramMtprotoGlobal) -> i32 {
+        self.mtproto.api_id.unwrap_or(global.api_id)
+    }
+
+    /// `telegram_endpoints[].mtproto.api_hash_key`, else `telegram.mtproto.api_hash_key`.
+    pub fn mtproto_api_hash_key<'a>(&'a self, global: &'a TelegramMtprotoGlobal) -> &'a str {
+        self.mtproto
+            .api_hash_key
+            .as_deref()
+            .unwrap_or(&global.api_hash_key)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct TelegramEndpointMtproto {
     pub session_key: String,
+    /// Overrides `telegram.mtproto.api_id` for a bot registered under another Telegram API
+    /// application.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_id: Option<i32>,
+    /// Overrides `telegram.mtproto.api_hash_key`; usually set together with `api_id`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_hash_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct TelegramEndpointBootstrap {
+    /// How the bootstrap catalog is kept in the chat; `tagged_message` leaves the pinned message
+    /// alone for chats shared with people.
+    #[serde(default)]
+    pub pin_mode: BootstrapPinMode,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -89,11 +442,90 @@ pub struct Target {
     pub source_path: String,
     #[serde(default)]
     pub label: String,
+    /// Label of backups run without an explicit one, e.g. `"{schedule}-{date}-{hostname}"`
+    /// (see [`crate::label_template`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label_template: Option<String>,
     pub endpoint_id: String,
     #[serde(default = "default_true")]
     pub enabled: bool,
+    /// RFC3339 instant a disabled target resumes at (`targets disable --until`); unset keeps it
+    /// disabled until enabled again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disabled_until: Option<String>,
+    /// Run order among targets due in the same schedule slot on the same endpoint (higher first).
+    #[serde(default)]
+    pub priority: i32,
     #[serde(default)]
     pub schedule: Option<TargetScheduleOverride>,
+    #[serde(default)]
+    pub scan: Option<TargetScanOverride>,
+    /// Encrypt new snapshots with a key derived from the master key for this target id instead
+    /// of the master key itself, so `secrets export-target-key` can share just this target.
+    #[serde(default)]
+    pub target_key: bool,
+    /// Back up a source equal to or nested in another target's on purpose: silences the
+    /// overlap warning (see [`target_overlaps`]) for every pair this target is in.
+    #[serde(default)]
+    pub allow_overlap: bool,
+    /// Fail a backup before uploading anything when the source has fewer files than this, e.g.
+    /// because its drive isn't mounted (see [`crate::source_guard`]); 0 disables the check.
+    #[serde(default)]
+    pub min_expected_files: u64,
+    /// Per-file decisions made during the scan, in order; the first filter that leaves a file
+    /// out wins (see [`crate::file_filter`]).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub filters: Vec<TargetFilter>,
+    /// Gitignore-style patterns of SQLite databases backed up from a consistent copy instead of
+    /// the live file (see [`crate::sqlite_consistent`]).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sqlite_consistent: Vec<String>,
+}
+
+impl Target {
+    /// Whether the target runs at `now`: `enabled`, or disabled with a `disabled_until` that has
+    /// passed.
+    pub fn enabled_at<Tz: chrono::TimeZone>(&self, now: &chrono::DateTime<Tz>) -> bool {
+        self.enabled || self.disabled_until_at().is_some_and(|until| *now >= until)
+    }
+
+    /// `disabled_until`, parsed.
+    pub fn disabled_until_at(&self) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+        self.disabled_until
+            .as_deref()
+            .and_then(|s| chrono::DateTime::parse_from_rfc3339(s.trim()).ok())
+    }
+
+    /// Key for the target's new snapshots, hashing chunk ids with `chunk_hash` (`chunking.hash`)
+    /// unless it is a target key; `None` means the master key with plain blake3.
+    pub fn data_key(
+        &self,
+        master_key: &[u8; 32],
+        chunk_hash: crate::ChunkHashAlg,
+    ) -> Option<crate::DataKey> {
+        if self.target_key {
+            return Some(crate::DataKey::for_target(master_key, &self.id));
+        }
+        (chunk_hash != crate::ChunkHashAlg::Blake3)
+            .then(|| crate::DataKey::master(master_key).with_chunk_hash(chunk_hash))
+    }
+
+    /// How [`Target::data_key`] is derived.
+    pub fn key_derivation(&self) -> crate::KeyDerivation {
+        if self.target_key {
+            crate::KeyDerivation::TargetV1
+        } else {
+            crate::KeyDerivation::Master
+        }
+    }
+
+    /// `targets[].scan.use_apfs_snapshot`, else `scan.use_apfs_snapshot`.
+    pub fn use_apfs_snapshot(&self, scan: &Scan) -> bool {
+        self.scan
+            .as_ref()
+            .and_then(|o| o.use_apfs_snapshot)
+            .unwrap_or(scan.use_apfs_snapshot)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -104,10 +536,133 @@ pub struct TargetScheduleOverride {
     pub daily_at: Option<String>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct TargetScanOverride {
+    pub use_apfs_snapshot: Option<bool>,
+}
+
+/// One `[[targets.filters]]` entry. `kind` picks which of the other keys apply.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TargetFilter {
+    /// `max_file_bytes`, `mime_exclude` or `command`.
+    pub kind: String,
+    /// `max_file_bytes`: files larger than this are left out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_bytes: Option<u64>,
+    /// `mime_exclude`: sniffed content types to leave out, e.g. `video/*` or
+    /// `application/x-iso9660-image`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mime_types: Vec<String>,
+    /// `command`: program and arguments, run with the file's path appended. Exit status 0 keeps
+    /// the file; any other status leaves it out.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub command: Vec<String>,
+    /// `command`: a run taking longer is killed and the file kept.
+    #[serde(default = "default_filter_timeout_ms")]
+    pub timeout_ms: u64,
+    /// `command`: total time the command may take per backup; once spent, remaining files are
+    /// kept without asking it.
+    #[serde(default = "default_filter_budget_secs")]
+    pub budget_secs: u64,
+}
+
 fn default_true() -> bool {
     true
 }
 
+fn default_filter_timeout_ms() -> u64 {
+    5_000
+}
+
+fn default_filter_budget_secs() -> u64 {
+    600
+}
+
+fn default_schedule_max_concurrent_runs() -> u32 {
+    1
+}
+
+fn default_chunking_hash() -> String {
+    crate::ChunkHashAlg::Blake3.as_str().to_string()
+}
+
+fn default_retention_deletion_grace_days() -> u32 {
+    7
+}
+
+fn default_verify_every_days() -> u32 {
+    7
+}
+
+fn default_verify_sample_percent() -> u32 {
+    100
+}
+
+fn default_scan_warn_initial_backup_bytes() -> u64 {
+    50 * 1024 * 1024 * 1024
+}
+
+/// `PATH_MAX` on macOS.
+fn default_scan_warn_path_bytes() -> u64 {
+    1024
+}
+
+fn default_scan_max_unreadable_percent() -> u32 {
+    50
+}
+
+fn default_scan_max_file_drop_percent() -> u32 {
+    90
+}
+
+fn default_scan_sqlite_consistent_max_bytes() -> u64 {
+    crate::sqlite_consistent::DEFAULT_MAX_BYTES
+}
+
+fn default_scan_sqlite_consistent_timeout_secs() -> u64 {
+    crate::sqlite_consistent::DEFAULT_TIMEOUT.as_secs()
+}
+
+fn default_performance_status_write_interval_ms() -> u32 {
+    250
+}
+
+fn default_restore_write_concurrency() -> u32 {
+    crate::restore::DEFAULT_RESTORE_WRITE_CONCURRENCY as u32
+}
+
+fn default_restore_fsync() -> String {
+    "at_end".to_string()
+}
+
+fn default_logs_keep_days() -> u32 {
+    30
+}
+
+fn default_logs_keep_max_files() -> u32 {
+    1000
+}
+
+fn default_notifications_digest_at() -> String {
+    "08:00".to_string()
+}
+
+fn default_settings_history_keep() -> u32 {
+    5
+}
+
+fn default_index_full_every() -> u32 {
+    10
+}
+
+fn default_retry_max_attempts() -> u32 {
+    3
+}
+
+fn default_retry_max_total_secs() -> u64 {
+    300
+}
+
 impl Default for Schedule {
     fn default() -> Self {
         Self {
@@ -116,6 +671,18 @@ impl Default for Schedule {
             hourly_minute: 0,
             daily_at: "02:00".to_string(),
             timezone: "local".to_string(),
+            catch_up: true,
+            max_concurrent_runs: default_schedule_max_concurrent_runs(),
+        }
+    }
+}
+
+impl Default for VerifySchedule {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            every_days: default_verify_every_days(),
+            sample_percent: default_verify_sample_percent(),
         }
     }
 }
@@ -124,6 +691,7 @@ impl Default for Retention {
     fn default() -> Self {
         Self {
             keep_last_snapshots: 7,
+            deletion_grace_days: default_retention_deletion_grace_days(),
         }
     }
 }
@@ -134,6 +702,72 @@ impl Default for Chunking {
             min_bytes: 1024 * 1024,
             avg_bytes: 4 * 1024 * 1024,
             max_bytes: 10 * 1024 * 1024,
+            hash: default_chunking_hash(),
+        }
+    }
+}
+
+impl Default for Scan {
+    fn default() -> Self {
+        Self {
+            watch: false,
+            warn_initial_backup_bytes: default_scan_warn_initial_backup_bytes(),
+            strict: false,
+            use_apfs_snapshot: false,
+            one_file_system: true,
+            default_excludes: false,
+            warn_path_bytes: default_scan_warn_path_bytes(),
+            max_unreadable_percent: default_scan_max_unreadable_percent(),
+            max_file_drop_percent: default_scan_max_file_drop_percent(),
+            sqlite_consistent_max_bytes: default_scan_sqlite_consistent_max_bytes(),
+            sqlite_consistent_timeout_secs: default_scan_sqlite_consistent_timeout_secs(),
+        }
+    }
+}
+
+impl Default for Notifications {
+    fn default() -> Self {
+        Self {
+            digest_enabled: false,
+            digest_at: default_notifications_digest_at(),
+            webhook_url: None,
+            ntfy_url: None,
+            include_paths: false,
+        }
+    }
+}
+
+impl Default for SettingsHistory {
+    fn default() -> Self {
+        Self {
+            keep: default_settings_history_keep(),
+        }
+    }
+}
+
+impl Default for Logs {
+    fn default() -> Self {
+        Self {
+            keep_days: default_logs_keep_days(),
+            keep_max_files: default_logs_keep_max_files(),
+            usage_stats: false,
+        }
+    }
+}
+
+impl Default for Index {
+    fn default() -> Self {
+        Self {
+            full_every: default_index_full_every(),
+        }
+    }
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_retry_max_attempts(),
+            max_total_secs: default_retry_max_total_secs(),
         }
     }
 }
@@ -170,15 +804,41 @@ impl Default for SettingsV2 {
         Self {
             version: SETTINGS_SCHEMA_VERSION,
             schedule: Schedule::default(),
+            verify_schedule: VerifySchedule::default(),
+            quiet_hours: QuietHours::default(),
             retention: Retention::default(),
             chunking: Chunking::default(),
+            // Only new configs opt in; `Scan::default()` (an absent key) keeps old ones unchanged.
+            scan: Scan {
+                default_excludes: true,
+                ..Scan::default()
+            },
+            logs: Logs::default(),
+            index: Index::default(),
+            retry: Retry::default(),
+            performance: Performance::default(),
+            upload: Upload::default(),
+            restore: Restore::default(),
             telegram: TelegramGlobal::default(),
+            security: Security::default(),
+            remote: Remote::default(),
+            notifications: Notifications::default(),
+            settings_history: SettingsHistory::default(),
+            metrics: Metrics::default(),
             telegram_endpoints: Vec::new(),
             targets: Vec::new(),
         }
     }
 }
 
+impl SettingsV2 {
+    /// Whether finished runs are recorded in `usage.sqlite`: for `stats monthly`, and as the
+    /// source of the daily digest.
+    pub fn records_usage(&self) -> bool {
+        self.logs.usage_stats || self.notifications.digest_enabled
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 struct SettingsV1 {
     #[serde(default)]
@@ -274,6 +934,10 @@ fn normalize_settings_v2(settings: &mut SettingsV2) {
         if key.is_empty() || (multi_endpoints && key == "telegram.mtproto.session") {
             ep.mtproto.session_key = endpoint_session_key_default(&ep.id);
         }
+        // Invalid ids are left as written for validation to report.
+        if let Ok(chat_id) = normalize_chat_id(&ep.chat_id) {
+            ep.chat_id = chat_id;
+        }
     }
 }
 
@@ -284,7 +948,17 @@ pub fn to_toml_v2(settings: &SettingsV2) -> Result<String> {
     })
 }
 
+/// Validates and writes `config.toml` atomically (temp file, fsync, rename). The replaced file is
+/// kept as a [`SettingsBackup`] first, up to `settings_history.keep` generations.
 pub fn save_settings_v2(config_dir: &Path, settings: &SettingsV2) -> Result<()> {
+    save_settings_v2_with(config_dir, settings, |f, bytes| f.write_all(bytes))
+}
+
+fn save_settings_v2_with(
+    config_dir: &Path,
+    settings: &SettingsV2,
+    write: impl FnOnce(&mut std::fs::File, &[u8]) -> std::io::Result<()>,
+) -> Result<()> {
     validate_settings_schema_v2(settings)?;
 
     let path = config_path(config_dir);
@@ -297,14 +971,212 @@ pub fn save_settings_v2(config_dir: &Path, settings: &SettingsV2) -> Result<()>
     let text = toml::to_string(settings).map_err(|e| Error::InvalidConfig {
         message: format!("config encode failed: {e}"),
     })?;
-
-    atomic_write(&path, text.as_bytes()).map_err(|e| Error::InvalidConfig {
+    let write_failed = |e: std::io::Error| Error::InvalidConfig {
         message: format!("config write failed: {e}"),
+    };
+
+    let tmp = path.with_extension("toml.tmp");
+    write_synced(&tmp, text.as_bytes(), write).map_err(|e| {
+        let _ = std::fs::remove_file(&tmp);
+        write_failed(e)
     })?;
+
+    // Empty or unchanged files are not worth a generation.
+    let keep = settings.settings_history.keep as usize;
+    match std::fs::read(&path) {
+        Ok(current) if keep > 0 && !current.is_empty() && current != text.as_bytes() => {
+            let backup =
+                free_settings_backup_path(config_dir, chrono::Utc::now().timestamp_millis());
+            write_synced(&backup, &current, |f, bytes| f.write_all(bytes)).map_err(|e| {
+                let _ = std::fs::remove_file(&backup);
+                write_failed(e)
+            })?;
+        }
+        _ => {}
+    }
+
+    std::fs::rename(&tmp, &path).map_err(write_failed)?;
+    if let Some(parent) = path.parent() {
+        sync_dir(parent);
+    }
+
+    prune_settings_backups(config_dir, keep)
+}
+
+/// A `config.toml.bak.<unix_ms>` generation kept by [`save_settings_v2`]. Later saves within the
+/// same millisecond append a counter: `config.toml.bak.<unix_ms>.<seq>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SettingsBackup {
+    /// When it was replaced, in Unix milliseconds; also its name suffix.
+    pub timestamp: i64,
+    /// Orders generations saved within the same millisecond; 0 for the first.
+    pub seq: u32,
+    pub path: PathBuf,
+    pub bytes: u64,
+}
+
+impl SettingsBackup {
+    /// `<unix_ms>` or `<unix_ms>.<seq>`, as `settings history` lists it.
+    pub fn id(&self) -> String {
+        match self.seq {
+            0 => self.timestamp.to_string(),
+            seq => format!("{}.{seq}", self.timestamp),
+        }
+    }
+}
+
+const SETTINGS_BACKUP_PREFIX: &str = "config.toml.bak.";
+
+fn settings_backup_path(config_dir: &Path, timestamp: i64, seq: u32) -> PathBuf {
+    match seq {
+        0 => config_dir.join(format!("{SETTINGS_BACKUP_PREFIX}{timestamp}")),
+        seq => config_dir.join(format!("{SETTINGS_BACKUP_PREFIX}{timestamp}.{seq}")),
+    }
+}
+
+/// The first name at `timestamp` no generation uses yet.
+fn free_settings_backup_path(config_dir: &Path, timestamp: i64) -> PathBuf {
+    (0..)
+        .map(|seq| settings_backup_path(config_dir, timestamp, seq))
+        .find(|path| std::fs::symlink_metadata(path).is_err())
+        .expect("a free settings backup name")
+}
+
+/// `(timestamp, seq)` from a [`SettingsBackup::id`], the part of its name after the prefix.
+pub fn parse_settings_backup_id(suffix: &str) -> Option<(i64, u32)> {
+    match suffix.split_once('.') {
+        Some((ts, seq)) => Some((ts.parse().ok()?, seq.parse().ok().filter(|&s| s > 0)?)),
+        None => Some((suffix.parse().ok()?, 0)),
+    }
+}
+
+/// Kept generations, newest first.
+pub fn list_settings_backups(config_dir: &Path) -> Result<Vec<SettingsBackup>> {
+    let entries = match std::fs::read_dir(config_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(Error::InvalidConfig {
+                message: format!("config dir read failed: {e}"),
+            });
+        }
+    };
+    let mut out = Vec::new();
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let Some((timestamp, seq)) = name
+            .to_str()
+            .and_then(|n| n.strip_prefix(SETTINGS_BACKUP_PREFIX))
+            .and_then(parse_settings_backup_id)
+        else {
+            continue;
+        };
+        let bytes = entry.metadata().map(|m| m.len()).unwrap_or(0);
+        out.push(SettingsBackup {
+            timestamp,
+            seq,
+            path: entry.path(),
+            bytes,
+        });
+    }
+    out.sort_by_key(|b| std::cmp::Reverse((b.timestamp, b.seq)));
+    Ok(out)
+}
+
+fn prune_settings_backups(config_dir: &Path, keep: usize) -> Result<()> {
+    for old in list_settings_backups(config_dir)?.into_iter().skip(keep) {
+        let _ = std::fs::remove_file(&old.path);
+    }
     Ok(())
 }
 
-pub fn validate_settings_schema_v2(settings: &SettingsV2) -> Result<()> {
+/// Restores the generation `to` (`(timestamp, seq)`, the newest without one) through
+/// [`save_settings_v2`], so it is validated again and the replaced file becomes a generation in
+/// turn. The daemon picks the rewritten file up like any other change.
+pub fn rollback_settings_v2(config_dir: &Path, to: Option<(i64, u32)>) -> Result<SettingsBackup> {
+    let backups = list_settings_backups(config_dir)?;
+    let backup = match to {
+        Some(to) => backups.into_iter().find(|b| (b.timestamp, b.seq) == to),
+        None => backups.into_iter().next(),
+    }
+    .ok_or_else(|| Error::InvalidConfig {
+        message: match to {
+            Some((ts, 0)) => format!("no settings backup at {ts} (see `settings history`)"),
+            Some((ts, seq)) => format!("no settings backup at {ts}.{seq} (see `settings history`)"),
+            None => "no settings backups".to_string(),
+        },
+    })?;
+
+    let text = std::fs::read_to_string(&backup.path).map_err(|e| Error::InvalidConfig {
+        message: format!("settings backup read failed: {e}"),
+    })?;
+    let settings = parse_settings_v2(&text).map_err(|e| Error::InvalidConfig {
+        message: format!("settings backup {} invalid: {e}", backup.id()),
+    })?;
+    save_settings_v2(config_dir, &settings)?;
+    Ok(backup)
+}
+
+/// Points `endpoint_id` at `new_chat_id` after its group was upgraded to a supergroup, keeping
+/// `old_chat_id` in `migrated_from_chat_ids`. Returns `false` if the endpoint is missing or
+/// already moved on from `old_chat_id`.
+pub fn record_chat_migration(
+    config_dir: &Path,
+    endpoint_id: &str,
+    old_chat_id: &str,
+    new_chat_id: &str,
+) -> Result<bool> {
+    let mut settings = load_settings_v2(config_dir)?;
+    let Some(ep) = settings
+        .telegram_endpoints
+        .iter_mut()
+        .find(|ep| ep.id == endpoint_id && ep.chat_id.trim() == old_chat_id)
+    else {
+        return Ok(false);
+    };
+    ep.chat_id = new_chat_id.to_string();
+    if !ep.migrated_from_chat_ids.iter().any(|id| id == old_chat_id) {
+        ep.migrated_from_chat_ids.push(old_chat_id.to_string());
+    }
+    save_settings_v2(config_dir, &settings)?;
+    Ok(true)
+}
+
+/// Something valid settings allow but that is most likely a mistake; `settings validate`,
+/// `settings set` and `doctor` print these.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettingsWarning {
+    /// Stable identifier, e.g. `"target_overlap"`.
+    pub code: &'static str,
+    pub message: String,
+    pub target_ids: Vec<String>,
+}
+
+/// Pairs of targets whose source paths are equal or nested in each other (compared by path
+/// components, without touching the filesystem), leaving out pairs where either target sets
+/// `allow_overlap`. Each pair is listed once, in `settings.targets` order.
+pub fn target_overlaps(settings: &SettingsV2) -> Vec<(&Target, &Target)> {
+    let mut out = Vec::new();
+    for (i, a) in settings.targets.iter().enumerate() {
+        for b in &settings.targets[i + 1..] {
+            if a.allow_overlap || b.allow_overlap {
+                continue;
+            }
+            let (pa, pb) = (
+                Path::new(a.source_path.trim()),
+                Path::new(b.source_path.trim()),
+            );
+            if pa.starts_with(pb) || pb.starts_with(pa) {
+                out.push((a, b));
+            }
+        }
+    }
+    out
+}
+
+/// Checks `settings`, returning the warnings for settings that are valid but suspicious.
+pub fn validate_settings_schema_v2(settings: &SettingsV2) -> Result<Vec<SettingsWarning>> {
     if settings.version != SETTINGS_SCHEMA_VERSION {
         return Err(Error::InvalidConfig {
             message: format!(
@@ -314,9 +1186,136 @@ pub fn validate_settings_schema_v2(settings: &SettingsV2) -> Result<()> {
         });
     }
 
-    if settings.telegram.mode.trim() != "mtproto" {
+    if settings.telegram.mode.trim() != "mtproto" {
+        return Err(Error::InvalidConfig {
+            message: "telegram.mode must be \"mtproto\"".to_string(),
+        });
+    }
+
+    if settings.retry.max_attempts < 1 {
+        return Err(Error::InvalidConfig {
+            message: "retry.max_attempts must be >= 1".to_string(),
+        });
+    }
+
+    if settings.scan.max_unreadable_percent > 100 {
+        return Err(Error::InvalidConfig {
+            message: "scan.max_unreadable_percent must be <= 100".to_string(),
+        });
+    }
+
+    if settings.scan.max_file_drop_percent > 100 {
+        return Err(Error::InvalidConfig {
+            message: "scan.max_file_drop_percent must be <= 100".to_string(),
+        });
+    }
+
+    if settings.scan.sqlite_consistent_timeout_secs == 0 {
+        return Err(Error::InvalidConfig {
+            message: "scan.sqlite_consistent_timeout_secs must be >= 1".to_string(),
+        });
+    }
+
+    if settings
+        .metrics
+        .textfile_path
+        .as_deref()
+        .is_some_and(|p| p.trim().is_empty())
+    {
+        return Err(Error::InvalidConfig {
+            message: "metrics.textfile_path must not be empty".to_string(),
+        });
+    }
+
+    if settings.performance.worker_threads > MAX_WORKER_THREADS {
+        return Err(Error::InvalidConfig {
+            message: format!("performance.worker_threads must be <= {MAX_WORKER_THREADS}"),
+        });
+    }
+
+    if !(MIN_STATUS_WRITE_INTERVAL_MS..=MAX_STATUS_WRITE_INTERVAL_MS)
+        .contains(&settings.performance.status_write_interval_ms)
+    {
+        return Err(Error::InvalidConfig {
+            message: format!(
+                "performance.status_write_interval_ms must be between {MIN_STATUS_WRITE_INTERVAL_MS} and {MAX_STATUS_WRITE_INTERVAL_MS}"
+            ),
+        });
+    }
+
+    if !(1..=MAX_RESTORE_WRITE_CONCURRENCY).contains(&settings.restore.write_concurrency) {
+        return Err(Error::InvalidConfig {
+            message: format!(
+                "restore.write_concurrency must be between 1 and {MAX_RESTORE_WRITE_CONCURRENCY}"
+            ),
+        });
+    }
+    if crate::restore::RestoreFsync::parse(&settings.restore.fsync).is_none() {
+        return Err(Error::InvalidConfig {
+            message: format!(
+                "restore.fsync must be one of {}",
+                crate::restore::RestoreFsync::NAMES.join(", ")
+            ),
+        });
+    }
+
+    if let Some(listen) = settings.remote.listen.as_deref() {
+        if listen.parse::<std::net::SocketAddr>().is_err() {
+            return Err(Error::InvalidConfig {
+                message: format!(
+                    "remote.listen must be host:port with an IP host (got {listen:?})"
+                ),
+            });
+        }
+        if settings
+            .remote
+            .token
+            .as_deref()
+            .is_none_or(|t| t.trim().is_empty())
+        {
+            return Err(Error::InvalidConfig {
+                message: "remote.token is required when remote.listen is set".to_string(),
+            });
+        }
+    }
+
+    for (i, range) in settings.quiet_hours.ranges.iter().enumerate() {
+        validate_hhmm(&format!("quiet_hours.ranges[{i}].start"), &range.start)?;
+        validate_hhmm(&format!("quiet_hours.ranges[{i}].end"), &range.end)?;
+        if let Some(day) = range
+            .days
+            .iter()
+            .find(|d| crate::quiet_hours::parse_day(d).is_none())
+        {
+            return Err(Error::InvalidConfig {
+                message: format!(
+                    "quiet_hours.ranges[{i}].days must be weekday names like \"mon\" (got {day:?})"
+                ),
+            });
+        }
+    }
+
+    let notifications = &settings.notifications;
+    validate_hhmm("notifications.digest_at", &notifications.digest_at)?;
+    for (key, url) in [
+        ("notifications.webhook_url", &notifications.webhook_url),
+        ("notifications.ntfy_url", &notifications.ntfy_url),
+    ] {
+        if let Some(url) = url.as_deref()
+            && let Err(e) = crate::notifications::parse_http_url(url)
+        {
+            return Err(Error::InvalidConfig {
+                message: format!("{key} {e} (got {url:?})"),
+            });
+        }
+    }
+    if notifications.digest_enabled
+        && notifications.webhook_url.is_none()
+        && notifications.ntfy_url.is_none()
+    {
         return Err(Error::InvalidConfig {
-            message: "telegram.mode must be \"mtproto\"".to_string(),
+            message: "notifications.digest_enabled needs notifications.webhook_url or notifications.ntfy_url"
+                .to_string(),
         });
     }
 
@@ -326,6 +1325,15 @@ pub fn validate_settings_schema_v2(settings: &SettingsV2) -> Result<()> {
         });
     }
 
+    if crate::ChunkHashAlg::parse(settings.chunking.hash.trim()).is_err() {
+        return Err(Error::InvalidConfig {
+            message: format!(
+                "chunking.hash must be \"blake3\" or \"blake3-keyed\" (got {:?})",
+                settings.chunking.hash
+            ),
+        });
+    }
+
     if settings.chunking.min_bytes == 0
         || settings.chunking.avg_bytes == 0
         || settings.chunking.max_bytes == 0
@@ -407,6 +1415,29 @@ pub fn validate_settings_schema_v2(settings: &SettingsV2) -> Result<()> {
         Some(settings.schedule.hourly_minute),
         Some(&settings.schedule.daily_at),
     )?;
+    if let Err(message) = crate::schedule_tz::ScheduleTz::parse(&settings.schedule.timezone) {
+        return Err(Error::InvalidConfig {
+            message: format!("schedule.timezone: {message}"),
+        });
+    }
+    if settings.schedule.max_concurrent_runs < 1 {
+        return Err(Error::InvalidConfig {
+            message: "schedule.max_concurrent_runs must be >= 1".to_string(),
+        });
+    }
+    if settings.verify_schedule.every_days < 1 {
+        return Err(Error::InvalidConfig {
+            message: "verify_schedule.every_days must be >= 1".to_string(),
+        });
+    }
+    if !(1..=100).contains(&settings.verify_schedule.sample_percent) {
+        return Err(Error::InvalidConfig {
+            message: format!(
+                "verify_schedule.sample_percent must be in 1..=100 (got {})",
+                settings.verify_schedule.sample_percent
+            ),
+        });
+    }
 
     // Endpoints: unique ids + minimal invariants.
     let mut endpoint_ids = std::collections::HashSet::<String>::new();
@@ -450,6 +1481,17 @@ pub fn validate_settings_schema_v2(settings: &SettingsV2) -> Result<()> {
                 ),
             });
         }
+        if let Some(prefix) = ep.object_prefix.as_deref()
+            && let Err(Error::InvalidConfig { message }) =
+                crate::device::validate_object_prefix(prefix)
+        {
+            return Err(Error::InvalidConfig {
+                message: format!(
+                    "telegram_endpoints[].object_prefix: {message} (endpoint_id={})",
+                    ep.id
+                ),
+            });
+        }
         if ep.bot_token_key.trim().is_empty() {
             return Err(Error::InvalidConfig {
                 message: format!(
@@ -458,8 +1500,16 @@ pub fn validate_settings_schema_v2(settings: &SettingsV2) -> Result<()> {
                 ),
             });
         }
-        let chat_id = ep.chat_id.trim();
-        if !chat_id.is_empty() && !chat_ids.insert(chat_id.to_string()) {
+        let chat_id = normalize_chat_id(&ep.chat_id).map_err(|e| match e {
+            Error::InvalidConfig { message } => Error::InvalidConfig {
+                message: format!(
+                    "telegram_endpoints[].chat_id: {message} (endpoint_id={})",
+                    ep.id
+                ),
+            },
+            other => other,
+        })?;
+        if !chat_id.is_empty() && !chat_ids.insert(chat_id.clone()) {
             return Err(Error::InvalidConfig {
                 message: format!("duplicate telegram_endpoints chat_id: {chat_id}"),
             });
@@ -472,6 +1522,27 @@ pub fn validate_settings_schema_v2(settings: &SettingsV2) -> Result<()> {
                 ),
             });
         }
+        if ep.mtproto.api_id.is_some_and(|id| id <= 0) {
+            return Err(Error::InvalidConfig {
+                message: format!(
+                    "telegram_endpoints[].mtproto.api_id must be > 0 (endpoint_id={})",
+                    ep.id
+                ),
+            });
+        }
+        if ep
+            .mtproto
+            .api_hash_key
+            .as_deref()
+            .is_some_and(|k| k.trim().is_empty())
+        {
+            return Err(Error::InvalidConfig {
+                message: format!(
+                    "telegram_endpoints[].mtproto.api_hash_key must not be empty (endpoint_id={})",
+                    ep.id
+                ),
+            });
+        }
     }
 
     // Targets: unique ids + endpoint references.
@@ -512,6 +1583,25 @@ pub fn validate_settings_schema_v2(settings: &SettingsV2) -> Result<()> {
             });
         }
 
+        if let Some(until) = &t.disabled_until
+            && chrono::DateTime::parse_from_rfc3339(until.trim()).is_err()
+        {
+            return Err(Error::InvalidConfig {
+                message: format!(
+                    "targets[].disabled_until must be an RFC3339 timestamp (target_id={}, got {until:?})",
+                    t.id
+                ),
+            });
+        }
+
+        if let Some(template) = &t.label_template
+            && let Err(message) = crate::label_template::validate_label_template(template)
+        {
+            return Err(Error::InvalidConfig {
+                message: format!("targets[].label_template: {message} (target_id={})", t.id),
+            });
+        }
+
         if let Some(o) = &t.schedule {
             validate_schedule_fields(
                 &format!("targets[].schedule (target_id={})", t.id),
@@ -520,9 +1610,50 @@ pub fn validate_settings_schema_v2(settings: &SettingsV2) -> Result<()> {
                 o.daily_at.as_ref(),
             )?;
         }
+
+        for f in &t.filters {
+            if let Err(message) = crate::file_filter::validate_filter(f) {
+                return Err(Error::InvalidConfig {
+                    message: format!("targets[].filters: {message} (target_id={})", t.id),
+                });
+            }
+        }
+
+        if let Err(message) = crate::sqlite_consistent::validate_patterns(&t.sqlite_consistent) {
+            return Err(Error::InvalidConfig {
+                message: format!(
+                    "targets[].sqlite_consistent: {message} (target_id={})",
+                    t.id
+                ),
+            });
+        }
     }
 
-    Ok(())
+    Ok(settings_warnings(settings))
+}
+
+/// The warnings [`validate_settings_schema_v2`] returns, without the checks that fail.
+pub fn settings_warnings(settings: &SettingsV2) -> Vec<SettingsWarning> {
+    target_overlaps(settings)
+        .into_iter()
+        .map(|(a, b)| {
+            let relation = if Path::new(a.source_path.trim()) == Path::new(b.source_path.trim()) {
+                "the same source path"
+            } else {
+                "nested source paths"
+            };
+            SettingsWarning {
+                code: "target_overlap",
+                message: format!(
+                    "targets {} ({}, endpoint_id={}) and {} ({}, endpoint_id={}) back up {relation}, \
+                     so files are scanned and uploaded twice; set allow_overlap = true on either \
+                     target if this is intended",
+                    a.id, a.source_path, a.endpoint_id, b.id, b.source_path, b.endpoint_id
+                ),
+                target_ids: vec![a.id.clone(), b.id.clone()],
+            }
+        })
+        .collect()
 }
 
 fn validate_schedule_fields(
@@ -549,26 +1680,25 @@ fn validate_schedule_fields(
     }
 
     if let Some(daily_at) = daily_at {
-        let s = daily_at.trim();
-        let (hh, mm) = s.split_once(':').ok_or_else(|| Error::InvalidConfig {
-            message: format!("{ctx}.daily_at must be HH:MM (got {s:?})"),
-        })?;
-        let hh: u8 = hh.parse().map_err(|_| Error::InvalidConfig {
-            message: format!("{ctx}.daily_at must be HH:MM (got {s:?})"),
-        })?;
-        let mm: u8 = mm.parse().map_err(|_| Error::InvalidConfig {
-            message: format!("{ctx}.daily_at must be HH:MM (got {s:?})"),
-        })?;
-        if hh >= 24 || mm >= 60 {
-            return Err(Error::InvalidConfig {
-                message: format!("{ctx}.daily_at must be HH:MM (got {s:?})"),
-            });
-        }
+        validate_hhmm(&format!("{ctx}.daily_at"), daily_at)?;
     }
 
     Ok(())
 }
 
+fn validate_hhmm(key: &str, value: &str) -> Result<()> {
+    let s = value.trim();
+    let valid = s.split_once(':').is_some_and(|(hh, mm)| {
+        matches!((hh.parse::<u8>(), mm.parse::<u8>()), (Ok(hh), Ok(mm)) if hh < 24 && mm < 60)
+    });
+    if !valid {
+        return Err(Error::InvalidConfig {
+            message: format!("{key} must be HH:MM (got {s:?})"),
+        });
+    }
+    Ok(())
+}
+
 pub fn effective_schedule(
     global: &Schedule,
     override_: Option<&TargetScheduleOverride>,
@@ -605,6 +1735,91 @@ pub fn endpoint_session_key_default(endpoint_id: &str) -> String {
     format!("telegram.mtproto.session.{endpoint_id}")
 }
 
+/// Canonical `chat_id` for user input: `-100…` (supergroup/channel), `-…` (basic group), a
+/// positive user id or `@username`. `t.me` links become `-100<id>` (`t.me/c/<id>/…`) or
+/// `@username`; a bare username gets its `@`. Empty input stays empty.
+///
+/// Positive ids are kept as user ids: a channel's bare peer id looks the same, so the `-100`
+/// prefix is only added when a `t.me/c/` link says it is a channel.
+pub fn normalize_chat_id(raw: &str) -> Result<String> {
+    let s = raw.trim();
+    if s.is_empty() {
+        return Ok(String::new());
+    }
+    let invalid = |why: &str| Error::InvalidConfig {
+        message: format!(
+            "invalid telegram chat_id {raw:?} ({why}); expected -100<id> (supergroup/channel), \
+             -<id> (group), a positive user id, @username or a t.me link"
+        ),
+    };
+
+    if let Some(path) = strip_telegram_link_host(s) {
+        let path = path.split(['?', '#']).next().unwrap_or_default();
+        let mut segs = path.split('/').filter(|seg| !seg.is_empty());
+        return match segs.next() {
+            Some("c") => match segs.next().map(str::parse::<i64>) {
+                Some(Ok(id)) if id > 0 => Ok(format!("-100{id}")),
+                _ => Err(invalid("t.me/c/ link without a channel id")),
+            },
+            Some("joinchat") => Err(invalid("invite links can't be resolved by a bot")),
+            Some(seg) if seg.starts_with('+') => {
+                Err(invalid("invite links can't be resolved by a bot"))
+            }
+            Some("s") => match segs.next() {
+                Some(name) if is_telegram_username(name) => Ok(format!("@{name}")),
+                _ => Err(invalid("not a public username link")),
+            },
+            Some(name) if is_telegram_username(name) => Ok(format!("@{name}")),
+            _ => Err(invalid("not a public username or t.me/c/ link")),
+        };
+    }
+
+    if let Some(name) = s.strip_prefix('@') {
+        return if is_telegram_username(name) {
+            Ok(s.to_string())
+        } else {
+            Err(invalid("not a valid username"))
+        };
+    }
+
+    let digits = s.strip_prefix(['-', '+']).unwrap_or(s);
+    if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+        return match s.parse::<i64>() {
+            Ok(0) => Err(invalid("0 is not a chat")),
+            Ok(id) => Ok(id.to_string()),
+            Err(_) => Err(invalid("number out of range")),
+        };
+    }
+
+    if is_telegram_username(s) {
+        return Ok(format!("@{s}"));
+    }
+    Err(invalid("unrecognized format"))
+}
+
+/// A positive id is a private user chat, where bots can't pin the bootstrap catalog.
+pub fn is_likely_private_chat_id(chat_id: &str) -> bool {
+    chat_id.trim().parse::<i64>().is_ok_and(|id| id > 0)
+}
+
+fn strip_telegram_link_host(s: &str) -> Option<&str> {
+    let rest = s
+        .strip_prefix("https://")
+        .or_else(|| s.strip_prefix("http://"))
+        .unwrap_or(s);
+    let rest = rest.strip_prefix("www.").unwrap_or(rest);
+    ["t.me/", "telegram.me/", "telegram.dog/"]
+        .iter()
+        .find_map(|host| rest.strip_prefix(host))
+}
+
+/// 4-32 of `[A-Za-z0-9_]`, starting with a letter (4-character names are auctioned ones).
+fn is_telegram_username(name: &str) -> bool {
+    (4..=32).contains(&name.len())
+        && name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
 pub fn target_id_from_source_path(source_path: &str) -> String {
     let hash = blake3::hash(source_path.as_bytes()).to_hex();
     format!("src_{}", &hash[..8])
@@ -617,11 +1832,15 @@ fn migrate_v1_to_v2(v1: SettingsV1) -> SettingsV2 {
         id: endpoint_id.clone(),
         mode: "mtproto".to_string(),
         chat_id: v1.telegram.chat_id,
+        migrated_from_chat_ids: Vec::new(),
         bot_token_key: v1.telegram.bot_token_key,
         mtproto: TelegramEndpointMtproto {
             session_key: v1.telegram.mtproto.session_key,
+            ..Default::default()
         },
         rate_limit: v1.telegram.rate_limit,
+        bootstrap: TelegramEndpointBootstrap::default(),
+        object_prefix: None,
     }];
 
     let targets = v1
@@ -631,17 +1850,35 @@ fn migrate_v1_to_v2(v1: SettingsV1) -> SettingsV2 {
             id: target_id_from_source_path(&source_path),
             source_path,
             label: "manual".to_string(),
+            label_template: None,
             endpoint_id: endpoint_id.clone(),
             enabled: true,
+            disabled_until: None,
+            priority: 0,
             schedule: None,
+            scan: None,
+            target_key: false,
+            allow_overlap: false,
+            min_expected_files: 0,
+            filters: Vec::new(),
+            sqlite_consistent: Vec::new(),
         })
         .collect::<Vec<_>>();
 
     SettingsV2 {
         version: SETTINGS_SCHEMA_VERSION,
         schedule: v1.schedule,
+        verify_schedule: VerifySchedule::default(),
+        quiet_hours: QuietHours::default(),
         retention: v1.retention,
         chunking: v1.chunking,
+        scan: Scan::default(),
+        logs: Logs::default(),
+        index: Index::default(),
+        retry: Retry::default(),
+        performance: Performance::default(),
+        upload: Upload::default(),
+        restore: Restore::default(),
         telegram: TelegramGlobal {
             mode: v1.telegram.mode,
             mtproto: TelegramMtprotoGlobal {
@@ -649,16 +1886,24 @@ fn migrate_v1_to_v2(v1: SettingsV1) -> SettingsV2 {
                 api_hash_key: v1.telegram.mtproto.api_hash_key,
             },
         },
+        security: Security::default(),
+        remote: Remote::default(),
+        notifications: Notifications::default(),
+        settings_history: SettingsHistory::default(),
+        metrics: Metrics::default(),
         telegram_endpoints: endpoints,
         targets,
     }
 }
 
-fn atomic_write(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
-    let tmp = path.with_extension("tmp");
-    std::fs::write(&tmp, bytes)?;
-    std::fs::rename(tmp, path)?;
-    Ok(())
+fn write_synced(
+    path: &Path,
+    bytes: &[u8],
+    write: impl FnOnce(&mut std::fs::File, &[u8]) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let mut f = std::fs::File::create(path)?;
+    write(&mut f, bytes)?;
+    f.sync_all()
 }
 
 #[cfg(test)]
@@ -730,6 +1975,21 @@ min_delay_ms = 250
         assert_eq!(s.targets[0].endpoint_id, "default");
     }
 
+    #[test]
+    fn default_excludes_are_on_only_for_new_configs() {
+        assert!(SettingsV2::default().scan.default_excludes);
+        assert!(!base_settings_v2().scan.default_excludes);
+        assert!(
+            !parse_settings_v2("version = 2\n[scan]\nstrict = true\n")
+                .unwrap()
+                .scan
+                .default_excludes
+        );
+
+        let text = toml::to_string(&SettingsV2::default()).unwrap();
+        assert!(parse_settings_v2(&text).unwrap().scan.default_excludes);
+    }
+
     #[test]
     fn v2_schedule_kind_is_validated() {
         let mut s = base_settings_v2();
@@ -759,6 +2019,81 @@ min_delay_ms = 250
         assert!(err.to_string().contains("daily_at"));
     }
 
+    #[test]
+    fn v2_schedule_timezone_must_be_in_the_tz_database() {
+        let mut s = base_settings_v2();
+        s.schedule.timezone = "Europe/Berlin".to_string();
+        validate_settings_schema_v2(&s).unwrap();
+
+        s.schedule.timezone = "CEST+2".to_string();
+        let err = validate_settings_schema_v2(&s).unwrap_err();
+        assert!(err.to_string().contains("schedule.timezone"));
+    }
+
+    #[test]
+    fn v2_remote_listen_requires_an_address_and_a_token() {
+        let mut s = base_settings_v2();
+        validate_settings_schema_v2(&s).unwrap();
+
+        s.remote.listen = Some("0.0.0.0:9479".to_string());
+        let err = validate_settings_schema_v2(&s).unwrap_err();
+        assert!(err.to_string().contains("remote.token"));
+
+        s.remote.token = Some("secret".to_string());
+        validate_settings_schema_v2(&s).unwrap();
+
+        s.remote.listen = Some("somewhere".to_string());
+        let err = validate_settings_schema_v2(&s).unwrap_err();
+        assert!(err.to_string().contains("remote.listen"));
+    }
+
+    #[test]
+    fn v2_digest_needs_a_valid_time_and_a_destination() {
+        let mut s = base_settings_v2();
+        s.notifications.digest_enabled = true;
+        let err = validate_settings_schema_v2(&s).unwrap_err();
+        assert!(err.to_string().contains("notifications.webhook_url"));
+
+        s.notifications.ntfy_url = Some("https://ntfy.sh/backups".to_string());
+        validate_settings_schema_v2(&s).unwrap();
+        assert!(s.records_usage());
+
+        s.notifications.webhook_url = Some("ftp://example.com/hook".to_string());
+        let err = validate_settings_schema_v2(&s).unwrap_err();
+        assert!(err.to_string().contains("notifications.webhook_url"));
+
+        s.notifications.webhook_url = None;
+        s.notifications.digest_at = "25:00".to_string();
+        let err = validate_settings_schema_v2(&s).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("notifications.digest_at must be HH:MM")
+        );
+    }
+
+    #[test]
+    fn v2_quiet_hours_ranges_are_validated() {
+        let mut s = base_settings_v2();
+        s.quiet_hours.ranges.push(QuietHoursRange {
+            days: vec!["Sat".to_string(), "sun".to_string()],
+            start: "22:00".to_string(),
+            end: "07:00".to_string(),
+        });
+        validate_settings_schema_v2(&s).unwrap();
+
+        s.quiet_hours.ranges[0].days.push("someday".to_string());
+        let err = validate_settings_schema_v2(&s).unwrap_err();
+        assert!(err.to_string().contains("quiet_hours.ranges[0].days"));
+
+        s.quiet_hours.ranges[0].days.clear();
+        s.quiet_hours.ranges[0].end = "7".to_string();
+        let err = validate_settings_schema_v2(&s).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("quiet_hours.ranges[0].end must be HH:MM")
+        );
+    }
+
     #[test]
     fn v2_target_schedule_override_is_validated() {
         let mut s = base_settings_v2();
@@ -772,6 +2107,37 @@ min_delay_ms = 250
         assert!(err.to_string().contains("targets[].schedule"));
     }
 
+    #[test]
+    fn v2_target_label_template_is_validated() {
+        let mut s = base_settings_v2();
+        s.targets[0].label_template = Some("{schedule}-{date}-{hostname}".to_string());
+        validate_settings_schema_v2(&s).unwrap();
+
+        s.targets[0].label_template = Some("{schedule}-{weekday}".to_string());
+        let err = validate_settings_schema_v2(&s).unwrap_err();
+        assert_eq!(err.error_code(), crate::ErrorCode::ConfigInvalid);
+        assert!(err.to_string().contains("targets[].label_template"));
+        assert!(err.to_string().contains("{weekday}"));
+    }
+
+    #[test]
+    fn v2_target_disabled_until_is_validated_and_resumes_the_target() {
+        let mut s = base_settings_v2();
+        s.targets[0].enabled = false;
+        s.targets[0].disabled_until = Some("next week".to_string());
+        let err = validate_settings_schema_v2(&s).unwrap_err();
+        assert!(err.to_string().contains("targets[].disabled_until"));
+
+        s.targets[0].disabled_until = Some("2024-07-01T00:00:00+02:00".to_string());
+        validate_settings_schema_v2(&s).unwrap();
+        let at = |ts: &str| chrono::DateTime::parse_from_rfc3339(ts).unwrap();
+        assert!(!s.targets[0].enabled_at(&at("2024-06-30T23:59:59+02:00")));
+        assert!(s.targets[0].enabled_at(&at("2024-06-30T22:00:00Z")));
+
+        s.targets[0].disabled_until = None;
+        assert!(!s.targets[0].enabled_at(&at("2030-01-01T00:00:00Z")));
+    }
+
     #[test]
     fn v2_chat_id_must_be_unique() {
         let mut s = base_settings_v2();
@@ -779,11 +2145,15 @@ min_delay_ms = 250
             id: "e2".to_string(),
             mode: "mtproto".to_string(),
             chat_id: s.telegram_endpoints[0].chat_id.clone(),
+            migrated_from_chat_ids: Vec::new(),
             bot_token_key: "telegram.bot_token.e2".to_string(),
             mtproto: TelegramEndpointMtproto {
                 session_key: "telegram.mtproto.session.e2".to_string(),
+                ..Default::default()
             },
             rate_limit: TelegramRateLimit::default(),
+            bootstrap: TelegramEndpointBootstrap::default(),
+            object_prefix: None,
         });
 
         let err = validate_settings_schema_v2(&s).unwrap_err();
@@ -801,11 +2171,15 @@ min_delay_ms = 250
             id: "e2".to_string(),
             mode: "mtproto".to_string(),
             chat_id: "".to_string(),
+            migrated_from_chat_ids: Vec::new(),
             bot_token_key: "telegram.bot_token.e2".to_string(),
             mtproto: TelegramEndpointMtproto {
                 session_key: "telegram.mtproto.session.e2".to_string(),
+                ..Default::default()
             },
             rate_limit: TelegramRateLimit::default(),
+            bootstrap: TelegramEndpointBootstrap::default(),
+            object_prefix: None,
         });
 
         validate_settings_schema_v2(&s).unwrap();
@@ -819,11 +2193,15 @@ min_delay_ms = 250
             id: "e2".to_string(),
             mode: "mtproto".to_string(),
             chat_id: s.telegram_endpoints[0].chat_id.trim().to_string(),
+            migrated_from_chat_ids: Vec::new(),
             bot_token_key: "telegram.bot_token.e2".to_string(),
             mtproto: TelegramEndpointMtproto {
                 session_key: "telegram.mtproto.session.e2".to_string(),
+                ..Default::default()
             },
             rate_limit: TelegramRateLimit::default(),
+            bootstrap: TelegramEndpointBootstrap::default(),
+            object_prefix: None,
         });
 
         let err = validate_settings_schema_v2(&s).unwrap_err();
@@ -845,6 +2223,22 @@ min_delay_ms = 250
         );
     }
 
+    #[test]
+    fn v2_endpoint_object_prefix_must_be_short_ascii() {
+        let mut s = base_settings_v2();
+        s.telegram_endpoints[0].object_prefix = Some("mac-mini".to_string());
+        validate_settings_schema_v2(&s).unwrap();
+
+        s.telegram_endpoints[0].object_prefix = Some("Мой Mac".to_string());
+        let err = validate_settings_schema_v2(&s).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("telegram_endpoints[].object_prefix")
+        );
+        s.telegram_endpoints[0].object_prefix = Some("m".repeat(33));
+        assert!(validate_settings_schema_v2(&s).is_err());
+    }
+
     #[test]
     fn v2_endpoint_id_must_not_have_whitespace() {
         let mut s = base_settings_v2();
@@ -889,6 +2283,50 @@ endpoint_id = "e1"
         validate_settings_schema_v2(&s).unwrap();
     }
 
+    #[test]
+    fn v2_endpoint_mtproto_api_overrides_fall_back_to_global() {
+        let s = base_settings_v2();
+        let ep = &s.telegram_endpoints[0];
+        assert_eq!(ep.mtproto.api_id, None);
+        assert_eq!(
+            ep.mtproto_api_id(&s.telegram.mtproto),
+            s.telegram.mtproto.api_id
+        );
+        assert_eq!(
+            ep.mtproto_api_hash_key(&s.telegram.mtproto),
+            "telegram.mtproto.api_hash"
+        );
+        // Unset overrides are not written back.
+        let text = toml::to_string(&s).unwrap();
+        assert!(!text.contains("api_hash_key = \"telegram.mtproto.api_hash.e1\""));
+
+        let text = text.replace(
+            "session_key = \"telegram.mtproto.session.e1\"",
+            "session_key = \"telegram.mtproto.session.e1\"\napi_id = 42\napi_hash_key = \"telegram.mtproto.api_hash.e1\"",
+        );
+        let s = parse_settings_v2(&text).unwrap();
+        validate_settings_schema_v2(&s).unwrap();
+        let ep = &s.telegram_endpoints[0];
+        assert_eq!(ep.mtproto_api_id(&s.telegram.mtproto), 42);
+        assert_eq!(
+            ep.mtproto_api_hash_key(&s.telegram.mtproto),
+            "telegram.mtproto.api_hash.e1"
+        );
+
+        let mut bad = s.clone();
+        bad.telegram_endpoints[0].mtproto.api_id = Some(0);
+        let err = validate_settings_schema_v2(&bad).unwrap_err().to_string();
+        assert!(err.contains("mtproto.api_id must be > 0"), "{err}");
+
+        let mut bad = s;
+        bad.telegram_endpoints[0].mtproto.api_hash_key = Some(" ".to_string());
+        let err = validate_settings_schema_v2(&bad).unwrap_err().to_string();
+        assert!(
+            err.contains("mtproto.api_hash_key must not be empty"),
+            "{err}"
+        );
+    }
+
     #[test]
     fn v2_chunking_max_bytes_cap_is_validated() {
         let mut s = base_settings_v2();
@@ -928,4 +2366,240 @@ endpoint_id = "e1"
         let err = validate_settings_schema_v2(&s).unwrap_err();
         assert!(err.to_string().contains("min <= avg <= max"));
     }
+
+    #[test]
+    fn chat_id_input_forms_normalize() {
+        let cases: &[(&str, Option<&str>)] = &[
+            ("-1001234567890", Some("-1001234567890")),
+            ("  -1001234567890\n", Some("-1001234567890")),
+            ("-123456", Some("-123456")),
+            ("123456789", Some("123456789")),
+            ("+123456789", Some("123456789")),
+            ("@my_backups", Some("@my_backups")),
+            ("my_backups", Some("@my_backups")),
+            ("https://t.me/c/1234567890/56", Some("-1001234567890")),
+            ("t.me/c/1234567890", Some("-1001234567890")),
+            ("https://t.me/my_backups/12?single", Some("@my_backups")),
+            ("http://telegram.me/s/my_backups", Some("@my_backups")),
+            ("https://www.t.me/my_backups", Some("@my_backups")),
+            ("", Some("")),
+            ("0", None),
+            ("-", None),
+            ("12a4", None),
+            ("99999999999999999999", None),
+            ("@ab", None),
+            ("@1backups", None),
+            ("my backups", None),
+            ("https://t.me/+AbCdEf123", None),
+            ("https://t.me/joinchat/AbCdEf123", None),
+            ("https://t.me/c/notanid/5", None),
+            ("https://example.com/my_backups", None),
+        ];
+        for (input, want) in cases {
+            let got = normalize_chat_id(input);
+            match want {
+                Some(want) => assert_eq!(got.unwrap(), *want, "input {input:?}"),
+                None => {
+                    let err = got.unwrap_err().to_string();
+                    assert!(err.contains("expected -100<id>"), "input {input:?}: {err}");
+                }
+            }
+        }
+
+        assert!(is_likely_private_chat_id("123456789"));
+        assert!(!is_likely_private_chat_id("-1001234567890"));
+        assert!(!is_likely_private_chat_id("@my_backups"));
+    }
+
+    #[test]
+    fn settings_normalize_chat_ids_and_reject_invalid_ones() {
+        let text = toml::to_string(&base_settings_v2())
+            .unwrap()
+            .replace("\"-100123\"", "\"https://t.me/c/123/4\"");
+        let s = parse_settings_v2(&text).unwrap();
+        assert_eq!(s.telegram_endpoints[0].chat_id, "-100123");
+        validate_settings_schema_v2(&s).unwrap();
+
+        let mut s = s;
+        s.telegram_endpoints[0].chat_id = "t.me/+invite".to_string();
+        let err = validate_settings_schema_v2(&s).unwrap_err().to_string();
+        assert!(err.contains("endpoint_id=e1"), "{err}");
+    }
+
+    /// Saves `s` with `retention.keep_last_snapshots = n`, so every save differs.
+    fn save_generation(dir: &Path, s: &mut SettingsV2, n: u32) {
+        s.retention.keep_last_snapshots = n;
+        save_settings_v2(dir, s).unwrap();
+    }
+
+    #[test]
+    fn failed_settings_write_leaves_the_original_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut s = base_settings_v2();
+        save_settings_v2(dir.path(), &s).unwrap();
+        let original = std::fs::read(config_path(dir.path())).unwrap();
+
+        s.retention.keep_last_snapshots = 99;
+        let err = save_settings_v2_with(dir.path(), &s, |f, bytes| {
+            f.write_all(&bytes[..bytes.len() / 2])?;
+            Err(std::io::Error::other("disk full"))
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("disk full"), "{err}");
+
+        assert_eq!(std::fs::read(config_path(dir.path())).unwrap(), original);
+        assert!(list_settings_backups(dir.path()).unwrap().is_empty());
+        let names = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["config.toml"]);
+    }
+
+    #[test]
+    fn settings_saves_keep_and_prune_generations() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut s = base_settings_v2();
+        s.settings_history.keep = 3;
+        for n in 1..=6 {
+            save_generation(dir.path(), &mut s, n);
+        }
+        // An unchanged save adds nothing.
+        save_settings_v2(dir.path(), &s).unwrap();
+
+        let backups = list_settings_backups(dir.path()).unwrap();
+        let kept = backups
+            .iter()
+            .map(|b| {
+                let text = std::fs::read_to_string(&b.path).unwrap();
+                parse_settings_v2(&text)
+                    .unwrap()
+                    .retention
+                    .keep_last_snapshots
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(kept, [5, 4, 3]);
+
+        s.settings_history.keep = 0;
+        save_generation(dir.path(), &mut s, 7);
+        assert!(list_settings_backups(dir.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn generations_saved_in_the_same_millisecond_get_a_counter() {
+        let dir = tempfile::tempdir().unwrap();
+        for seq in 0..2 {
+            std::fs::write(settings_backup_path(dir.path(), 5, seq), "version = 2\n").unwrap();
+        }
+        assert_eq!(
+            free_settings_backup_path(dir.path(), 5),
+            dir.path().join("config.toml.bak.5.2")
+        );
+        let ids = list_settings_backups(dir.path())
+            .unwrap()
+            .iter()
+            .map(SettingsBackup::id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["5.1", "5"]);
+        assert_eq!(parse_settings_backup_id("5.1"), Some((5, 1)));
+        assert_eq!(parse_settings_backup_id("5.0"), None);
+        assert_eq!(parse_settings_backup_id("5.x"), None);
+    }
+
+    #[test]
+    fn rollback_restores_a_generation_after_validating_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut s = base_settings_v2();
+        for n in 1..=3 {
+            save_generation(dir.path(), &mut s, n);
+        }
+        let current = || {
+            load_settings_v2(dir.path())
+                .unwrap()
+                .retention
+                .keep_last_snapshots
+        };
+
+        let restored = rollback_settings_v2(dir.path(), None).unwrap();
+        assert_eq!(current(), 2);
+        // The rolled back file became a generation itself.
+        let newest = &list_settings_backups(dir.path()).unwrap()[0];
+        assert_ne!(newest.path, restored.path);
+
+        let oldest = list_settings_backups(dir.path()).unwrap().pop().unwrap();
+        rollback_settings_v2(dir.path(), parse_settings_backup_id(&oldest.id())).unwrap();
+        assert_eq!(current(), 1);
+
+        let err = rollback_settings_v2(dir.path(), Some((1, 0))).unwrap_err();
+        assert!(err.to_string().contains("no settings backup at 1"), "{err}");
+
+        std::fs::write(
+            settings_backup_path(dir.path(), 2, 0),
+            "version = 2\n[retry]\nmax_attempts = 0\n",
+        )
+        .unwrap();
+        let before = std::fs::read(config_path(dir.path())).unwrap();
+        let err = rollback_settings_v2(dir.path(), Some((2, 0))).unwrap_err();
+        assert!(err.to_string().contains("retry.max_attempts"), "{err}");
+        assert_eq!(std::fs::read(config_path(dir.path())).unwrap(), before);
+    }
+
+    #[test]
+    fn record_chat_migration_updates_endpoint_and_keeps_old_chat_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut s = base_settings_v2();
+        s.telegram_endpoints[0].chat_id = "-123".to_string();
+        save_settings_v2(dir.path(), &s).unwrap();
+
+        assert!(record_chat_migration(dir.path(), "e1", "-123", "-100123").unwrap());
+        let saved = load_settings_v2(dir.path()).unwrap();
+        assert_eq!(saved.telegram_endpoints[0].chat_id, "-100123");
+        assert_eq!(saved.telegram_endpoints[0].migrated_from_chat_ids, ["-123"]);
+
+        // Already migrated (or another endpoint): nothing to do.
+        assert!(!record_chat_migration(dir.path(), "e1", "-123", "-100123").unwrap());
+        assert!(!record_chat_migration(dir.path(), "e2", "-100123", "-100456").unwrap());
+    }
+
+    #[test]
+    fn overlapping_target_sources_warn_unless_allowed() {
+        let mut s = base_settings_v2();
+        assert!(validate_settings_schema_v2(&s).unwrap().is_empty());
+
+        let target = |id: &str, source_path: &str| Target {
+            id: id.to_string(),
+            source_path: source_path.to_string(),
+            ..s.targets[0].clone()
+        };
+        s.targets = vec![
+            target("docs", "/Users/me/Documents"),
+            target("old", "/Users/me/Documents/"),
+            target("work", "/Users/me/Documents/work"),
+            target("docs2", "/Users/me/Documents2"),
+        ];
+        let warnings = validate_settings_schema_v2(&s).unwrap();
+        let pairs = warnings
+            .iter()
+            .map(|w| w.target_ids.join("+"))
+            .collect::<Vec<_>>();
+        assert_eq!(pairs, ["docs+old", "docs+work", "old+work"]);
+        assert_eq!(warnings[0].code, "target_overlap");
+        assert!(
+            warnings[0].message.contains("the same source path"),
+            "{}",
+            warnings[0].message
+        );
+        assert!(
+            warnings[1].message.contains("nested source paths"),
+            "{}",
+            warnings[1].message
+        );
+
+        s.targets[2].allow_overlap = true;
+        let pairs = settings_warnings(&s)
+            .iter()
+            .map(|w| w.target_ids.join("+"))
+            .collect::<Vec<_>>();
+        assert_eq!(pairs, ["docs+old"]);
+    }
 }
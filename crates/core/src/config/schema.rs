@@ -0,0 +1,1031 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use super::{SETTINGS_SCHEMA_VERSION, SettingsV2, Target, TelegramEndpoint};
+use SettingsFieldType::{Bool, Integer, String as Str, StringList};
+
+/// Value kind of a settings field, as rendered by `settings schema`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SettingsFieldType {
+    Bool,
+    Integer,
+    String,
+    StringList,
+}
+
+/// One leaf field of [`SettingsV2`].
+///
+/// `path` uses dotted keys, with `[]` marking an array of tables (`targets[].endpoint_id`).
+/// `required` means the key must be present whenever its table is written; whole sections may
+/// still be omitted and fall back to their defaults.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SettingsField {
+    pub path: &'static str,
+    #[serde(rename = "type")]
+    pub ty: SettingsFieldType,
+    pub required: bool,
+    pub description: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub constraints: Option<&'static str>,
+}
+
+const fn field(
+    path: &'static str,
+    ty: SettingsFieldType,
+    required: bool,
+    description: &'static str,
+    constraints: Option<&'static str>,
+) -> SettingsField {
+    SettingsField {
+        path,
+        ty,
+        required,
+        description,
+        constraints,
+    }
+}
+
+/// Every field of [`SettingsV2`], grouped by table in file order.
+///
+/// Keep in sync with the structs in `config.rs`; the unit tests below fail when a field is added,
+/// removed, retyped or changes between required and defaulted.
+pub const SETTINGS_FIELDS: &[SettingsField] = &[
+    field(
+        "version",
+        Integer,
+        true,
+        "Settings file format version.",
+        Some("must be 2"),
+    ),
+    field(
+        "schedule.enabled",
+        Bool,
+        true,
+        "Run scheduled backups from the daemon.",
+        None,
+    ),
+    field(
+        "schedule.kind",
+        Str,
+        true,
+        "Default schedule for all targets.",
+        Some("\"hourly\" or \"daily\""),
+    ),
+    field(
+        "schedule.hourly_minute",
+        Integer,
+        true,
+        "Minute past the hour for hourly runs.",
+        Some("0..=59"),
+    ),
+    field(
+        "schedule.daily_at",
+        Str,
+        true,
+        "Time of day for daily runs.",
+        Some("HH:MM, 24-hour clock"),
+    ),
+    field(
+        "schedule.timezone",
+        Str,
+        true,
+        "Time zone of schedule times.",
+        Some("\"local\" or a tz database name, e.g. \"Europe/Berlin\""),
+    ),
+    field(
+        "schedule.catch_up",
+        Bool,
+        false,
+        "Daemon only: run a slot missed while asleep or busy once the daemon ticks again.",
+        None,
+    ),
+    field(
+        "schedule.max_concurrent_runs",
+        Integer,
+        false,
+        "Daemon only: backups that may run at once; later requests are queued.",
+        Some(">= 1"),
+    ),
+    field(
+        "verify_schedule.enabled",
+        Bool,
+        false,
+        "Daemon only: periodically verify the latest snapshot of every enabled target.",
+        None,
+    ),
+    field(
+        "verify_schedule.every_days",
+        Integer,
+        false,
+        "Days between a target's scheduled verifies.",
+        Some(">= 1"),
+    ),
+    field(
+        "verify_schedule.sample_percent",
+        Integer,
+        false,
+        "Share of chunks each scheduled verify downloads; 100 checks them all.",
+        Some("1..=100"),
+    ),
+    field(
+        "quiet_hours.max_upload_bytes_per_sec",
+        Integer,
+        false,
+        "Upload limit for runs still going during quiet hours.",
+        Some("0 leaves them unthrottled"),
+    ),
+    field(
+        "quiet_hours.ranges[].days",
+        StringList,
+        false,
+        "Weekdays the range starts on; empty means every day.",
+        Some("\"mon\"..\"sun\""),
+    ),
+    field(
+        "quiet_hours.ranges[].start",
+        Str,
+        true,
+        "Start of the range, in schedule.timezone.",
+        Some("HH:MM"),
+    ),
+    field(
+        "quiet_hours.ranges[].end",
+        Str,
+        true,
+        "End of the range; at or before start it ends the next day.",
+        Some("HH:MM"),
+    ),
+    field(
+        "retention.keep_last_snapshots",
+        Integer,
+        true,
+        "Snapshots kept per target.",
+        Some(">= 1"),
+    ),
+    field(
+        "retention.deletion_grace_days",
+        Integer,
+        false,
+        "Days unreferenced objects wait in the deletion queue before `gc flush` deletes them; 0 deletes right away.",
+        None,
+    ),
+    field(
+        "chunking.min_bytes",
+        Integer,
+        true,
+        "Minimum chunk size in bytes.",
+        Some("> 0, <= chunking.avg_bytes, within FastCDC bounds"),
+    ),
+    field(
+        "chunking.avg_bytes",
+        Integer,
+        true,
+        "Target average chunk size in bytes.",
+        Some("between chunking.min_bytes and chunking.max_bytes, within FastCDC bounds"),
+    ),
+    field(
+        "chunking.max_bytes",
+        Integer,
+        true,
+        "Maximum chunk size in bytes.",
+        Some(">= chunking.avg_bytes; at most 128 MiB minus encryption framing overhead"),
+    ),
+    field(
+        "chunking.hash",
+        Str,
+        false,
+        "How chunk ids of master key snapshots are computed; keyed ids can't be matched against known files.",
+        Some("\"blake3\" or \"blake3-keyed\""),
+    ),
+    field(
+        "scan.watch",
+        Bool,
+        false,
+        "Daemon only: watch enabled targets for changes between runs.",
+        None,
+    ),
+    field(
+        "scan.warn_initial_backup_bytes",
+        Integer,
+        false,
+        "Confirm a target's first backup above this estimated source size in bytes.",
+        Some("0 disables the check"),
+    ),
+    field(
+        "scan.strict",
+        Bool,
+        false,
+        "Fail a backup on the first unreadable source file instead of skipping it.",
+        None,
+    ),
+    field(
+        "scan.use_apfs_snapshot",
+        Bool,
+        false,
+        "macOS only: back up from a local APFS snapshot of the source volume.",
+        None,
+    ),
+    field(
+        "scan.one_file_system",
+        Bool,
+        false,
+        "Skip directories on another file system than the source path (network shares, FUSE mounts).",
+        None,
+    ),
+    field(
+        "scan.default_excludes",
+        Bool,
+        false,
+        "Leave out caches, trash and other system-generated noise (Caches/, .Trash/, node_modules/.cache/, *.nobackup, .DS_Store, ...).",
+        Some("true in new configs; an absent key means false"),
+    ),
+    field(
+        "scan.warn_path_bytes",
+        Integer,
+        false,
+        "Warn about snapshot paths longer than this many bytes, which another file system may not restore.",
+        Some("0 disables the check"),
+    ),
+    field(
+        "scan.max_unreadable_percent",
+        Integer,
+        false,
+        "Fail a backup when more than this percentage of the scanned entries could not be read.",
+        Some("0..=100; 0 disables the check"),
+    ),
+    field(
+        "scan.max_file_drop_percent",
+        Integer,
+        false,
+        "Fail a backup before uploading anything when the source has this many percent fewer files than at the last successful backup.",
+        Some("0..=100; 0 disables the check"),
+    ),
+    field(
+        "scan.sqlite_consistent_max_bytes",
+        Integer,
+        false,
+        "Databases matching targets[].sqlite_consistent that are larger than this are read live, with a warning.",
+        Some("0 disables the limit"),
+    ),
+    field(
+        "scan.sqlite_consistent_timeout_secs",
+        Integer,
+        false,
+        "Time limit of one database's consistent copy; a copy taking longer falls back to the live file.",
+        Some(">= 1"),
+    ),
+    field(
+        "logs.keep_days",
+        Integer,
+        false,
+        "Days run logs are kept.",
+        Some("0 disables the limit"),
+    ),
+    field(
+        "logs.keep_max_files",
+        Integer,
+        false,
+        "Run log files kept.",
+        Some("0 disables the limit"),
+    ),
+    field(
+        "logs.usage_stats",
+        Bool,
+        false,
+        "Record every run in usage.sqlite for `stats monthly`.",
+        None,
+    ),
+    field(
+        "index.full_every",
+        Integer,
+        false,
+        "Upload a target's full file map index every this many backups, deltas in between.",
+        Some("0 or 1 uploads the full index every backup"),
+    ),
+    field(
+        "retry.max_attempts",
+        Integer,
+        false,
+        "Attempts per upload/download of a run before it fails.",
+        Some(">= 1; 1 disables retries"),
+    ),
+    field(
+        "retry.max_total_secs",
+        Integer,
+        false,
+        "Backoff waited across all retries of a run.",
+        None,
+    ),
+    field(
+        "performance.worker_threads",
+        Integer,
+        false,
+        "Threads reading, chunking and hashing source files during a backup scan.",
+        Some("0 = number of physical cores (capped at 16); <= 64"),
+    ),
+    field(
+        "performance.status_write_interval_ms",
+        Integer,
+        false,
+        "Daemon: minimum time between status.json rewrites while progress changes.",
+        Some("50..=1000"),
+    ),
+    field(
+        "upload.verify_after_upload",
+        Bool,
+        false,
+        "Download every uploaded object back before indexing it instead of only checking its size.",
+        None,
+    ),
+    field(
+        "restore.write_concurrency",
+        Integer,
+        false,
+        "Files a restore writes at the same time.",
+        Some("1..=64"),
+    ),
+    field(
+        "restore.fsync",
+        Str,
+        false,
+        "When restored files are flushed to disk: per_file, at_end or none.",
+        None,
+    ),
+    field(
+        "telegram.mode",
+        Str,
+        true,
+        "Telegram client mode.",
+        Some("must be \"mtproto\""),
+    ),
+    field(
+        "telegram.mtproto.api_id",
+        Integer,
+        true,
+        "Telegram API id (my.telegram.org).",
+        None,
+    ),
+    field(
+        "telegram.mtproto.api_hash_key",
+        Str,
+        true,
+        "Secrets key holding the Telegram API hash.",
+        None,
+    ),
+    field(
+        "security.restore_requires_passphrase",
+        Bool,
+        false,
+        "Require the restore passphrase for restores requested over the control socket.",
+        None,
+    ),
+    field(
+        "security.restore_passphrase_hash",
+        Str,
+        false,
+        "Argon2 hash of the restore passphrase.",
+        Some("written by `televybackup security set-restore-passphrase`"),
+    ),
+    field(
+        "remote.listen",
+        Str,
+        false,
+        "Daemon address serving read-only remote monitoring; unset = off.",
+        Some("IP:port, e.g. \"0.0.0.0:9479\""),
+    ),
+    field(
+        "remote.token",
+        Str,
+        false,
+        "Bearer token remote monitoring requests must carry.",
+        Some("required when remote.listen is set"),
+    ),
+    field(
+        "remote.tls",
+        Bool,
+        false,
+        "Serve remote monitoring over TLS with a self-signed certificate.",
+        None,
+    ),
+    field(
+        "notifications.digest_enabled",
+        Bool,
+        false,
+        "Send a daily digest of every target's last 24 hours (daemon).",
+        Some("needs notifications.webhook_url or notifications.ntfy_url"),
+    ),
+    field(
+        "notifications.digest_at",
+        Str,
+        false,
+        "Time of day the digest is sent, in schedule.timezone.",
+        Some("HH:MM"),
+    ),
+    field(
+        "notifications.webhook_url",
+        Str,
+        false,
+        "URL the digest is POSTed to as JSON.",
+        Some("http:// or https:// URL"),
+    ),
+    field(
+        "notifications.ntfy_url",
+        Str,
+        false,
+        "ntfy topic URL the digest is published to as text.",
+        Some("http:// or https:// URL, e.g. \"https://ntfy.sh/<topic>\""),
+    ),
+    field(
+        "notifications.include_paths",
+        Bool,
+        false,
+        "Include target source paths in the digest.",
+        None,
+    ),
+    field(
+        "settings_history.keep",
+        Integer,
+        false,
+        "Earlier config.toml generations kept for `settings rollback`.",
+        Some("0 keeps none"),
+    ),
+    field(
+        "metrics.textfile_path",
+        Str,
+        false,
+        "CLI backup and verify runs merge their outcome into this Prometheus textfile-collector file.",
+        Some("path of a .prom file; --metrics-textfile overrides it"),
+    ),
+    field(
+        "telegram_endpoints[].id",
+        Str,
+        true,
+        "Endpoint id referenced by targets.",
+        Some("unique, [A-Za-z0-9_-]+"),
+    ),
+    field(
+        "telegram_endpoints[].mode",
+        Str,
+        true,
+        "Telegram client mode of this endpoint.",
+        Some("must be \"mtproto\""),
+    ),
+    field(
+        "telegram_endpoints[].chat_id",
+        Str,
+        true,
+        "Chat that stores this endpoint's objects.",
+        Some("unique across endpoints when set"),
+    ),
+    field(
+        "telegram_endpoints[].migrated_from_chat_ids",
+        StringList,
+        false,
+        "Earlier chat ids whose objects stay readable (group upgraded to supergroup).",
+        None,
+    ),
+    field(
+        "telegram_endpoints[].bot_token_key",
+        Str,
+        true,
+        "Secrets key holding the bot token.",
+        Some("not empty"),
+    ),
+    field(
+        "telegram_endpoints[].mtproto.session_key",
+        Str,
+        true,
+        "Secrets key holding the MTProto session.",
+        Some("not empty"),
+    ),
+    field(
+        "telegram_endpoints[].mtproto.api_id",
+        Integer,
+        false,
+        "Telegram API id for this endpoint's bot; unset = telegram.mtproto.api_id.",
+        Some("> 0"),
+    ),
+    field(
+        "telegram_endpoints[].mtproto.api_hash_key",
+        Str,
+        false,
+        "Secrets key holding this endpoint's API hash; unset = telegram.mtproto.api_hash_key.",
+        Some("not empty"),
+    ),
+    field(
+        "telegram_endpoints[].rate_limit.max_concurrent_uploads",
+        Integer,
+        true,
+        "Parallel uploads (and verify downloads) for this endpoint.",
+        Some("effective range 1..=8"),
+    ),
+    field(
+        "telegram_endpoints[].rate_limit.min_delay_ms",
+        Integer,
+        true,
+        "Minimum delay between uploads in milliseconds.",
+        None,
+    ),
+    field(
+        "telegram_endpoints[].bootstrap.pin_mode",
+        Str,
+        false,
+        "Where the bootstrap catalog lives: the pinned message, an unpinned tagged message, or nowhere.",
+        Some("\"pin\", \"tagged_message\" or \"disabled\""),
+    ),
+    field(
+        "telegram_endpoints[].object_prefix",
+        Str,
+        false,
+        "Start of uploaded document names; unset = derived from the device name.",
+        Some("1..=32 of A-Z a-z 0-9 . _ -"),
+    ),
+    field(
+        "targets[].id",
+        Str,
+        true,
+        "Target id.",
+        Some("unique, not empty"),
+    ),
+    field(
+        "targets[].source_path",
+        Str,
+        true,
+        "Directory backed up by this target.",
+        Some("not empty"),
+    ),
+    field("targets[].label", Str, false, "Snapshot label.", None),
+    field(
+        "targets[].label_template",
+        Str,
+        false,
+        "Label of backups run without an explicit one.",
+        Some(
+            "variables {schedule} {date} {time} {hostname} {target_id} {device_name}; {{ and }} are literal braces",
+        ),
+    ),
+    field(
+        "targets[].endpoint_id",
+        Str,
+        true,
+        "Endpoint this target uploads to.",
+        Some("must name a telegram_endpoints id"),
+    ),
+    field(
+        "targets[].enabled",
+        Bool,
+        false,
+        "Include this target in scheduled backups.",
+        None,
+    ),
+    field(
+        "targets[].disabled_until",
+        Str,
+        false,
+        "When a disabled target resumes scheduled backups.",
+        Some("RFC3339 timestamp"),
+    ),
+    field(
+        "targets[].priority",
+        Integer,
+        false,
+        "Run order among targets due in the same slot on one endpoint (higher first).",
+        None,
+    ),
+    field(
+        "targets[].schedule.enabled",
+        Bool,
+        false,
+        "Overrides schedule.enabled for this target.",
+        None,
+    ),
+    field(
+        "targets[].schedule.kind",
+        Str,
+        false,
+        "Overrides schedule.kind for this target.",
+        Some("\"hourly\" or \"daily\""),
+    ),
+    field(
+        "targets[].schedule.hourly_minute",
+        Integer,
+        false,
+        "Overrides schedule.hourly_minute for this target.",
+        Some("0..=59"),
+    ),
+    field(
+        "targets[].schedule.daily_at",
+        Str,
+        false,
+        "Overrides schedule.daily_at for this target.",
+        Some("HH:MM, 24-hour clock"),
+    ),
+    field(
+        "targets[].scan.use_apfs_snapshot",
+        Bool,
+        false,
+        "Overrides scan.use_apfs_snapshot for this target.",
+        None,
+    ),
+    field(
+        "targets[].target_key",
+        Bool,
+        false,
+        "Encrypt new snapshots with a key derived for this target instead of the master key.",
+        None,
+    ),
+    field(
+        "targets[].allow_overlap",
+        Bool,
+        false,
+        "Silence the warning for a source path equal to or nested in another target's.",
+        None,
+    ),
+    field(
+        "targets[].min_expected_files",
+        Integer,
+        false,
+        "Fail a backup before uploading anything when the source has fewer files than this.",
+        Some("0 disables the check"),
+    ),
+    field(
+        "targets[].sqlite_consistent",
+        StringList,
+        false,
+        "Back up SQLite databases matching these patterns from a consistent copy instead of the live file.",
+        Some("gitignore-style patterns, e.g. \"*.sqlite\""),
+    ),
+    field(
+        "targets[].filters[].kind",
+        Str,
+        true,
+        "Per-file scan filter, evaluated in order; the first that excludes a file wins.",
+        Some("\"max_file_bytes\", \"mime_exclude\" or \"command\""),
+    ),
+    field(
+        "targets[].filters[].max_bytes",
+        Integer,
+        false,
+        "max_file_bytes: files larger than this are left out.",
+        Some(">= 1"),
+    ),
+    field(
+        "targets[].filters[].mime_types",
+        StringList,
+        false,
+        "mime_exclude: sniffed content types to leave out.",
+        Some("\"type/subtype\" or \"type/*\""),
+    ),
+    field(
+        "targets[].filters[].command",
+        StringList,
+        false,
+        "command: program and arguments, run with the file's path appended; a non-zero exit leaves the file out.",
+        None,
+    ),
+    field(
+        "targets[].filters[].timeout_ms",
+        Integer,
+        false,
+        "command: per-file time limit; a run taking longer is killed and the file kept.",
+        Some(">= 1"),
+    ),
+    field(
+        "targets[].filters[].budget_secs",
+        Integer,
+        false,
+        "command: total time per backup; once spent, remaining files are kept without running it.",
+        Some(">= 1"),
+    ),
+];
+
+/// Default value of a settings field; `None` when it has no default (required array-table keys
+/// and unset optional overrides).
+pub fn settings_field_default(path: &str) -> Option<Value> {
+    lookup(&defaults_document(), path)
+        .filter(|v| !v.is_null())
+        .cloned()
+}
+
+/// `settings schema --json` output: every field with its type, default and constraints.
+pub fn settings_schema_json() -> Value {
+    let defaults = defaults_document();
+    let fields = SETTINGS_FIELDS
+        .iter()
+        .map(|f| {
+            let mut v = serde_json::to_value(f).expect("settings field serializes");
+            v["default"] = lookup(&defaults, f.path).cloned().unwrap_or(Value::Null);
+            v
+        })
+        .collect::<Vec<_>>();
+    serde_json::json!({ "version": SETTINGS_SCHEMA_VERSION, "fields": fields })
+}
+
+/// A complete `config.toml` with every default, each field preceded by its description.
+///
+/// Arrays of tables have no defaults and are written as a commented-out template.
+pub fn settings_defaults_toml() -> String {
+    let defaults = defaults_document();
+    let mut out = String::new();
+    let mut table: Option<&str> = None;
+    for f in SETTINGS_FIELDS {
+        let (parent, key) = match f.path.rsplit_once('.') {
+            Some((parent, key)) => (parent, key),
+            None => ("", f.path),
+        };
+        let in_array = parent.contains("[]");
+        let prefix = if in_array { "# " } else { "" };
+
+        if table != Some(parent) {
+            table = Some(parent);
+            if !parent.is_empty() {
+                if !out.is_empty() {
+                    out.push('\n');
+                }
+                match parent.strip_suffix("[]") {
+                    Some(array) => out.push_str(&format!("# [[{}]]\n", array.replace("[]", ""))),
+                    None => out.push_str(&format!("{prefix}[{}]\n", parent.replace("[]", ""))),
+                }
+            }
+        }
+
+        out.push_str(&format!("# {}", f.description));
+        if let Some(c) = f.constraints {
+            out.push_str(&format!(" ({c})"));
+        }
+        if f.required {
+            out.push_str(" Required.");
+        }
+        out.push('\n');
+
+        // Keys without a default stay commented out so the file parses back to the defaults.
+        match lookup(&defaults, f.path).filter(|v| !v.is_null()) {
+            Some(v) => out.push_str(&format!("{prefix}{key} = {}\n", toml_value(v))),
+            None => out.push_str(&format!("# {key} = {}\n", placeholder(f.ty))),
+        }
+    }
+    out
+}
+
+fn toml_value(v: &Value) -> String {
+    toml::Value::try_from(v)
+        .map(|t| t.to_string())
+        .unwrap_or_else(|_| v.to_string())
+}
+
+fn placeholder(ty: SettingsFieldType) -> &'static str {
+    match ty {
+        Bool => "false",
+        Integer => "0",
+        Str => "\"\"",
+        StringList => "[]",
+    }
+}
+
+/// Default settings as JSON, with one default element in each array of tables.
+///
+/// Array elements have no `Default`; they are parsed from their required keys alone (filled with
+/// placeholders that are then dropped), so every other key shows the serde default.
+fn defaults_document() -> Value {
+    let mut doc = serde_json::to_value(SettingsV2::default()).expect("settings serialize");
+    for array in ["telegram_endpoints", "targets"] {
+        let prefix = format!("{array}[].");
+        let required = SETTINGS_FIELDS
+            .iter()
+            .filter(|f| f.required)
+            .filter_map(|f| f.path.strip_prefix(prefix.as_str()).map(|key| (key, f.ty)))
+            .filter(|(key, _)| !key.contains('.'))
+            .collect::<Vec<_>>();
+        let minimal = required
+            .iter()
+            .map(|(key, ty)| {
+                let v = match ty {
+                    Bool => Value::Bool(false),
+                    Integer => Value::from(0),
+                    Str => Value::from(""),
+                    StringList => Value::Array(Vec::new()),
+                };
+                (key.to_string(), v)
+            })
+            .collect::<Map<_, _>>();
+        let minimal = Value::Object(minimal);
+        let item = match array {
+            "telegram_endpoints" => serde_json::from_value::<TelegramEndpoint>(minimal)
+                .ok()
+                .and_then(|v| serde_json::to_value(v).ok()),
+            _ => serde_json::from_value::<Target>(minimal)
+                .ok()
+                .and_then(|v| serde_json::to_value(v).ok()),
+        };
+        let mut item = item.unwrap_or_else(|| Value::Object(Map::new()));
+        if let Some(obj) = item.as_object_mut() {
+            for (key, _) in &required {
+                obj.remove(*key);
+            }
+        }
+        doc[array] = Value::Array(vec![item]);
+    }
+    doc
+}
+
+fn lookup<'a>(doc: &'a Value, path: &str) -> Option<&'a Value> {
+    let mut cur = doc;
+    for seg in path.split('.') {
+        cur = match seg.strip_suffix("[]") {
+            Some(array) => cur.get(array)?.get(0)?,
+            None => cur.get(seg)?,
+        };
+    }
+    Some(cur)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::bootstrap::BootstrapPinMode;
+    use crate::config::{
+        Metrics, Notifications, QuietHours, QuietHoursRange, Remote, Security, TargetFilter,
+        TargetScanOverride, TargetScheduleOverride, TelegramEndpointBootstrap,
+        TelegramEndpointMtproto,
+    };
+
+    /// Settings with every optional field set, so serialization shows the full shape.
+    fn populated() -> Value {
+        let mut settings = SettingsV2 {
+            security: Security {
+                restore_requires_passphrase: true,
+                restore_passphrase_hash: Some("$argon2id$x".to_string()),
+            },
+            metrics: Metrics {
+                textfile_path: Some(
+                    "/var/lib/node_exporter/textfile/televybackup.prom".to_string(),
+                ),
+            },
+            remote: Remote {
+                listen: Some("127.0.0.1:9479".to_string()),
+                token: Some("t".to_string()),
+                tls: true,
+            },
+            quiet_hours: QuietHours {
+                max_upload_bytes_per_sec: 1024,
+                ranges: vec![QuietHoursRange {
+                    days: vec!["mon".to_string()],
+                    start: "09:00".to_string(),
+                    end: "17:00".to_string(),
+                }],
+            },
+            notifications: Notifications {
+                webhook_url: Some("https://example.com/hook".to_string()),
+                ntfy_url: Some("https://ntfy.sh/t".to_string()),
+                ..Notifications::default()
+            },
+            ..SettingsV2::default()
+        };
+        settings.telegram_endpoints.push(TelegramEndpoint {
+            id: "ep1".to_string(),
+            mode: "mtproto".to_string(),
+            chat_id: "-100".to_string(),
+            migrated_from_chat_ids: vec!["-1".to_string()],
+            bot_token_key: "telegram.bot_token.ep1".to_string(),
+            mtproto: TelegramEndpointMtproto {
+                session_key: "telegram.mtproto.session.ep1".to_string(),
+                api_id: Some(2),
+                api_hash_key: Some("telegram.mtproto.api_hash.ep1".to_string()),
+            },
+            rate_limit: Default::default(),
+            bootstrap: TelegramEndpointBootstrap {
+                pin_mode: BootstrapPinMode::TaggedMessage,
+            },
+            object_prefix: Some("mac-mini".to_string()),
+        });
+        settings.targets.push(Target {
+            id: "t1".to_string(),
+            source_path: "/src".to_string(),
+            label: "manual".to_string(),
+            label_template: Some("{schedule}-{date}".to_string()),
+            endpoint_id: "ep1".to_string(),
+            enabled: true,
+            disabled_until: Some("2024-07-01T00:00:00Z".to_string()),
+            priority: 1,
+            schedule: Some(TargetScheduleOverride {
+                enabled: Some(true),
+                kind: Some("daily".to_string()),
+                hourly_minute: Some(5),
+                daily_at: Some("03:00".to_string()),
+            }),
+            scan: Some(TargetScanOverride {
+                use_apfs_snapshot: Some(true),
+            }),
+            target_key: false,
+            allow_overlap: false,
+            min_expected_files: 1000,
+            filters: vec![TargetFilter {
+                kind: "command".to_string(),
+                max_bytes: Some(1024),
+                mime_types: vec!["video/*".to_string()],
+                command: vec!["/usr/local/bin/scan".to_string()],
+                timeout_ms: 1000,
+                budget_secs: 60,
+            }],
+            sqlite_consistent: vec!["*.db".to_string()],
+        });
+        serde_json::to_value(settings).unwrap()
+    }
+
+    fn leaf_paths(v: &Value, prefix: &str, out: &mut Vec<(String, SettingsFieldType)>) {
+        let join = |key: &str| {
+            if prefix.is_empty() {
+                key.to_string()
+            } else {
+                format!("{prefix}.{key}")
+            }
+        };
+        let Value::Object(map) = v else {
+            return;
+        };
+        for (key, child) in map {
+            match child {
+                Value::Object(_) => leaf_paths(child, &join(key), out),
+                Value::Array(items) if items.first().is_some_and(Value::is_object) => {
+                    leaf_paths(&items[0], &join(&format!("{key}[]")), out)
+                }
+                Value::Array(_) => out.push((join(key), StringList)),
+                Value::Bool(_) => out.push((join(key), Bool)),
+                Value::Number(_) => out.push((join(key), Integer)),
+                Value::String(_) => out.push((join(key), Str)),
+                Value::Null => panic!("populated settings left {} unset", join(key)),
+            }
+        }
+    }
+
+    fn remove(doc: &mut Value, path: &str) {
+        let (parent, key) = path.rsplit_once('.').unwrap_or(("", path));
+        let mut cur = doc;
+        if !parent.is_empty() {
+            for seg in parent.split('.') {
+                cur = match seg.strip_suffix("[]") {
+                    Some(array) => &mut cur[array][0],
+                    None => &mut cur[seg],
+                };
+            }
+        }
+        cur.as_object_mut().unwrap().remove(key).unwrap();
+    }
+
+    #[test]
+    fn descriptor_table_matches_settings_structs() {
+        let mut actual = Vec::new();
+        leaf_paths(&populated(), "", &mut actual);
+        let actual = actual.into_iter().collect::<BTreeSet<_>>();
+        let described = SETTINGS_FIELDS
+            .iter()
+            .map(|f| (f.path.to_string(), f.ty))
+            .collect::<BTreeSet<_>>();
+        assert_eq!(described.len(), SETTINGS_FIELDS.len(), "duplicate paths");
+        assert_eq!(actual, described);
+    }
+
+    #[test]
+    fn descriptor_required_flags_match_serde_defaults() {
+        let full = populated();
+        serde_json::from_value::<SettingsV2>(full.clone()).unwrap();
+        for f in SETTINGS_FIELDS {
+            let mut doc = full.clone();
+            remove(&mut doc, f.path);
+            let parsed = serde_json::from_value::<SettingsV2>(doc);
+            assert_eq!(parsed.is_err(), f.required, "{}", f.path);
+        }
+    }
+
+    #[test]
+    fn defaults_toml_parses_back_to_default_settings() {
+        let text = settings_defaults_toml();
+        let parsed = crate::config::parse_settings_v2(&text).unwrap();
+        assert_eq!(
+            serde_json::to_value(parsed).unwrap(),
+            serde_json::to_value(SettingsV2::default()).unwrap()
+        );
+        assert!(text.contains("# [[telegram_endpoints]]\n"));
+        assert!(text.contains("# [targets.schedule]\n"));
+        assert!(text.contains("# [[targets.filters]]\n"));
+        assert!(text.contains("# [[quiet_hours.ranges]]\n"));
+
+        assert_eq!(
+            settings_field_default("telegram_endpoints[].rate_limit.max_concurrent_uploads"),
+            Some(Value::from(2))
+        );
+        assert_eq!(
+            settings_field_default("targets[].enabled"),
+            Some(Value::Bool(true))
+        );
+        assert_eq!(settings_field_default("targets[].id"), None);
+        assert_eq!(settings_field_default("targets[].schedule.kind"), None);
+        assert_eq!(
+            settings_field_default("chunking.avg_bytes"),
+            Some(Value::from(4 * 1024 * 1024))
+        );
+    }
+}
@@ -1,9 +1,10 @@
 use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
 
 use base64::Engine;
 use pbkdf2::pbkdf2_hmac;
 use serde::{Deserialize, Serialize};
-use sha2::Sha256;
+use sha2::{Digest, Sha256};
 
 use crate::config::{SETTINGS_SCHEMA_VERSION, SettingsV2};
 use crate::crypto::{decrypt_framed, encrypt_framed};
@@ -354,10 +355,177 @@ pub fn utc_now_compact_timestamp() -> String {
     chrono::Utc::now().format("%Y%m%d-%H%M%S").to_string()
 }
 
+pub const APPLY_REPORT_VERSION: u32 = 1;
+
+/// How far `settings import-bundle --apply` got with one endpoint's local index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApplyEndpointStatus {
+    Rebuilt,
+    Pending,
+    Failed,
+}
+
+/// One endpoint of an [`ApplyPartialReport`]. A rebuilt endpoint keeps where its index came from,
+/// so a resumed apply can report it without rebuilding it again.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApplyEndpointReport {
+    pub endpoint_id: String,
+    pub status: ApplyEndpointStatus,
+    /// `remote_latest` or `empty`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rebuilt_from: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manifest_object_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rebuilt_db_path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_db_backup_path: Option<String>,
+    /// Target whose remote latest the index was downloaded for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub synced_target_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+}
+
+/// Progress of a `settings import-bundle --apply` run, kept at [`apply_report_path`] from the
+/// start of the index rebuild until the apply succeeds. `--resume` continues from it when the
+/// request (`request_sha256`, see [`apply_request_sha256`]) is the same.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApplyPartialReport {
+    pub version: u32,
+    pub request_sha256: String,
+    pub updated_at: String,
+    pub endpoints: Vec<ApplyEndpointReport>,
+    pub settings_written: bool,
+    pub secrets_written: bool,
+}
+
+impl ApplyPartialReport {
+    pub fn new(request_sha256: String, endpoint_ids: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            version: APPLY_REPORT_VERSION,
+            request_sha256,
+            updated_at: String::new(),
+            endpoints: endpoint_ids
+                .into_iter()
+                .map(|endpoint_id| ApplyEndpointReport {
+                    endpoint_id,
+                    status: ApplyEndpointStatus::Pending,
+                    rebuilt_from: None,
+                    snapshot_id: None,
+                    manifest_object_id: None,
+                    rebuilt_db_path: None,
+                    previous_db_backup_path: None,
+                    synced_target_id: None,
+                    error_code: None,
+                })
+                .collect(),
+            settings_written: false,
+            secrets_written: false,
+        }
+    }
+
+    pub fn endpoint(&self, endpoint_id: &str) -> Option<&ApplyEndpointReport> {
+        self.endpoints.iter().find(|e| e.endpoint_id == endpoint_id)
+    }
+
+    /// Replaces the entry of `report.endpoint_id` (or appends one).
+    pub fn set_endpoint(&mut self, report: ApplyEndpointReport) {
+        match self
+            .endpoints
+            .iter_mut()
+            .find(|e| e.endpoint_id == report.endpoint_id)
+        {
+            Some(existing) => *existing = report,
+            None => self.endpoints.push(report),
+        }
+    }
+}
+
+pub fn apply_report_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("import-bundle").join("apply-partial.json")
+}
+
+/// Fingerprint of an apply request: the bundle key, the selected target ids (in any order) and
+/// the resolutions, given as their JSON encoding with sorted keys.
+pub fn apply_request_sha256(
+    bundle_key: &str,
+    selected_target_ids: &[String],
+    resolutions_json: &str,
+) -> String {
+    let mut ids = selected_target_ids.to_vec();
+    ids.sort();
+    ids.dedup();
+    let mut h = Sha256::new();
+    h.update(bundle_key.as_bytes());
+    for id in &ids {
+        h.update(b"\0");
+        h.update(id.as_bytes());
+    }
+    h.update(b"\0\0");
+    h.update(resolutions_json.as_bytes());
+    hex::encode(h.finalize())
+}
+
+pub fn load_apply_report(data_dir: &Path) -> Result<Option<ApplyPartialReport>> {
+    let path = apply_report_path(data_dir);
+    let bytes = match std::fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let report: ApplyPartialReport =
+        serde_json::from_slice(&bytes).map_err(|e| Error::InvalidConfig {
+            message: format!(
+                "partial apply report decode failed: path={}; {e}",
+                path.display()
+            ),
+        })?;
+    if report.version != APPLY_REPORT_VERSION {
+        return Err(Error::InvalidConfig {
+            message: format!(
+                "unsupported partial apply report version {}: path={}",
+                report.version,
+                path.display()
+            ),
+        });
+    }
+    Ok(Some(report))
+}
+
+/// Writes `report` (stamping `updated_at`) and returns its path.
+pub fn write_apply_report(data_dir: &Path, report: &mut ApplyPartialReport) -> Result<PathBuf> {
+    report.updated_at = chrono::Utc::now().to_rfc3339();
+    let path = apply_report_path(data_dir);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let bytes = serde_json::to_vec_pretty(report).map_err(std::io::Error::other)?;
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, bytes)?;
+    std::fs::rename(&tmp, &path)?;
+    Ok(path)
+}
+
+pub fn remove_apply_report(data_dir: &Path) -> Result<()> {
+    match std::fs::remove_file(apply_report_path(data_dir)) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::{TelegramEndpoint, TelegramEndpointMtproto, TelegramRateLimit};
+    use crate::config::{
+        TelegramEndpoint, TelegramEndpointBootstrap, TelegramEndpointMtproto, TelegramRateLimit,
+    };
 
     fn outer_from_bundle_key(key: &str) -> ConfigBundleOuterV2 {
         let rest = key
@@ -401,26 +569,53 @@ mod tests {
         SettingsV2 {
             version: SETTINGS_SCHEMA_VERSION,
             schedule: crate::config::Schedule::default(),
+            verify_schedule: crate::config::VerifySchedule::default(),
+            quiet_hours: crate::config::QuietHours::default(),
             retention: crate::config::Retention::default(),
             chunking: crate::config::Chunking::default(),
+            scan: crate::config::Scan::default(),
+            logs: crate::config::Logs::default(),
+            index: crate::config::Index::default(),
+            retry: crate::config::Retry::default(),
+            performance: crate::config::Performance::default(),
+            upload: crate::config::Upload::default(),
+            restore: crate::config::Restore::default(),
             telegram: crate::config::TelegramGlobal::default(),
+            security: crate::config::Security::default(),
+            remote: crate::config::Remote::default(),
+            notifications: crate::config::Notifications::default(),
+            settings_history: crate::config::SettingsHistory::default(),
+            metrics: crate::config::Metrics::default(),
             telegram_endpoints: vec![TelegramEndpoint {
                 id: "ep1".to_string(),
                 mode: "mtproto".to_string(),
                 chat_id: "-1001".to_string(),
+                migrated_from_chat_ids: Vec::new(),
                 bot_token_key: "telegram.bot_token.ep1".to_string(),
                 mtproto: TelegramEndpointMtproto {
                     session_key: "telegram.mtproto.session.ep1".to_string(),
+                    ..Default::default()
                 },
                 rate_limit: TelegramRateLimit::default(),
+                bootstrap: TelegramEndpointBootstrap::default(),
+                object_prefix: None,
             }],
             targets: vec![crate::config::Target {
                 id: "t1".to_string(),
                 source_path: "/tmp".to_string(),
                 label: "manual".to_string(),
+                label_template: None,
                 endpoint_id: "ep1".to_string(),
                 enabled: true,
+                disabled_until: None,
+                priority: 0,
                 schedule: None,
+                scan: None,
+                target_key: false,
+                allow_overlap: false,
+                min_expected_files: 0,
+                filters: Vec::new(),
+                sqlite_consistent: Vec::new(),
             }],
         }
     }
@@ -586,4 +781,43 @@ mod tests {
                 .contains("may not reference reserved secret key")
         );
     }
+
+    #[test]
+    fn apply_report_round_trips_and_is_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(load_apply_report(dir.path()).unwrap(), None);
+
+        let sha = apply_request_sha256("TBC2:x", &["t2".to_string(), "t1".to_string()], "{}");
+        assert_eq!(
+            sha,
+            apply_request_sha256("TBC2:x", &["t1".to_string(), "t2".to_string()], "{}")
+        );
+        assert_ne!(
+            sha,
+            apply_request_sha256("TBC2:x", &["t1".to_string()], "{}")
+        );
+
+        let mut report = ApplyPartialReport::new(sha, ["ep_a".to_string(), "ep_b".to_string()]);
+        let mut rebuilt = report.endpoint("ep_a").unwrap().clone();
+        rebuilt.status = ApplyEndpointStatus::Rebuilt;
+        rebuilt.rebuilt_from = Some("empty".to_string());
+        report.set_endpoint(rebuilt);
+        let path = write_apply_report(dir.path(), &mut report).unwrap();
+        assert_eq!(path, apply_report_path(dir.path()));
+
+        let loaded = load_apply_report(dir.path()).unwrap().unwrap();
+        assert_eq!(loaded, report);
+        assert_eq!(
+            loaded.endpoint("ep_a").unwrap().status,
+            ApplyEndpointStatus::Rebuilt
+        );
+        assert_eq!(
+            loaded.endpoint("ep_b").unwrap().status,
+            ApplyEndpointStatus::Pending
+        );
+
+        remove_apply_report(dir.path()).unwrap();
+        remove_apply_report(dir.path()).unwrap();
+        assert_eq!(load_apply_report(dir.path()).unwrap(), None);
+    }
 }
@@ -2,6 +2,11 @@ use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
 
+use crate::error_code::ErrorCode;
+use crate::progress::Phase;
+use crate::storage::MtProtoHelperVersion;
+use crate::version::DaemonVersionStamp;
+
 pub fn control_ipc_socket_path(data_dir: &Path) -> PathBuf {
     data_dir.join("ipc").join("control.sock")
 }
@@ -17,40 +22,50 @@ pub struct ControlError {
 }
 
 impl ControlError {
-    pub fn unavailable(message: impl Into<String>, details: serde_json::Value) -> Self {
+    /// The only way daemon code builds an error, so every code it answers is registered.
+    pub fn new(
+        code: ErrorCode,
+        message: impl Into<String>,
+        retryable: bool,
+        details: serde_json::Value,
+    ) -> Self {
         Self {
-            code: "control.unavailable".to_string(),
+            code: code.as_str().to_string(),
             message: message.into(),
-            retryable: true,
+            retryable,
             details,
         }
     }
 
+    pub fn unavailable(message: impl Into<String>, details: serde_json::Value) -> Self {
+        Self::new(ErrorCode::ControlUnavailable, message, true, details)
+    }
+
     pub fn timeout(message: impl Into<String>, details: serde_json::Value) -> Self {
-        Self {
-            code: "control.timeout".to_string(),
-            message: message.into(),
-            retryable: true,
-            details,
-        }
+        Self::new(ErrorCode::ControlTimeout, message, true, details)
     }
 
     pub fn invalid_request(message: impl Into<String>, details: serde_json::Value) -> Self {
-        Self {
-            code: "control.invalid_request".to_string(),
-            message: message.into(),
-            retryable: false,
-            details,
-        }
+        Self::new(ErrorCode::ControlInvalidRequest, message, false, details)
     }
 
     pub fn method_not_found(message: impl Into<String>, details: serde_json::Value) -> Self {
-        Self {
-            code: "control.method_not_found".to_string(),
-            message: message.into(),
-            retryable: false,
-            details,
-        }
+        Self::new(ErrorCode::ControlMethodNotFound, message, false, details)
+    }
+
+    pub fn not_found(message: impl Into<String>, details: serde_json::Value) -> Self {
+        Self::new(ErrorCode::ControlNotFound, message, false, details)
+    }
+}
+
+impl From<&crate::Error> for ControlError {
+    fn from(e: &crate::Error) -> Self {
+        Self::new(
+            e.error_code(),
+            e.to_string(),
+            crate::retry::is_transient(e),
+            e.details(),
+        )
     }
 }
 
@@ -89,6 +104,9 @@ pub struct ControlResponse {
     pub result: Option<serde_json::Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<ControlError>,
+    /// Stamped by the daemon as it writes the response.
+    #[serde(flatten)]
+    pub versions: DaemonVersionStamp,
 }
 
 impl ControlResponse {
@@ -99,6 +117,7 @@ impl ControlResponse {
             ok: true,
             result: Some(result),
             error: None,
+            versions: DaemonVersionStamp::default(),
         }
     }
 
@@ -109,10 +128,22 @@ impl ControlResponse {
             ok: false,
             result: None,
             error: Some(error),
+            versions: DaemonVersionStamp::default(),
         }
     }
 }
 
+/// `daemon.version`: the daemon's build and the helper it would spawn.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DaemonVersionResult {
+    pub daemon_version: String,
+    pub settings_version: u32,
+    pub helper: Option<MtProtoHelperVersion>,
+    /// Why the helper could not be asked (missing binary, crash).
+    pub helper_error: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct VaultStatusResult {
@@ -156,6 +187,210 @@ pub struct SecretsClearTelegramMtprotoSessionParams {
     pub endpoint_id: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SecretsListResult {
+    pub keys: Vec<crate::secrets::SecretKeyInfo>,
+}
+
+/// Params for `secrets.delete`. The caller names every key (e.g. the orphaned ones from
+/// `secrets.list` the user confirmed); the master key also needs `iUnderstandDataLoss`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SecretsDeleteParams {
+    pub keys: Vec<String>,
+    #[serde(default)]
+    pub i_understand_data_loss: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SecretsDeleteResult {
+    /// Keys that were found and removed; absent keys are skipped.
+    pub deleted: Vec<String>,
+}
+
+/// Params for `security.authorizeRestore`: a client about to restore (`restore --require-passphrase`)
+/// checks the restore passphrase with it. Advisory: nothing stops a client that skips the call.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SecurityAuthorizeRestoreParams {
+    #[serde(default)]
+    pub passphrase: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SecurityAuthorizeRestoreResult {
+    /// False when the passphrase requirement is disabled (the passphrase was not checked).
+    pub passphrase_required: bool,
+}
+
+// Daemon run queue: backups requested while `schedule.max_concurrent_runs` runs are active wait
+// here in FIFO order. The queue lives in daemon memory only.
+
+/// Params for `backup.runNow`; a target that is already queued keeps its place.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupRunNowParams {
+    pub target_id: String,
+    /// Let the run back up a source that no longer looks like the last one backed up (see
+    /// [`crate::source_guard`]).
+    #[serde(default)]
+    pub accept_source_change: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupRunNowResult {
+    pub task_id: String,
+    /// 1-based place in the queue.
+    pub position: u32,
+    /// True when the target was already queued and that entry is returned instead.
+    pub already_queued: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueEntry {
+    pub task_id: String,
+    pub target_id: String,
+    /// `schedule` | `manual` (control file) | `ipc` (`backup.runNow`).
+    pub trigger: String,
+    /// 1-based; 1 runs next.
+    pub position: u32,
+    pub enqueued_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueListResult {
+    pub max_concurrent_runs: u32,
+    pub entries: Vec<QueueEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueRemoveParams {
+    pub task_id: String,
+}
+
+/// Params for `targets.setEnabled`: pauses or resumes a target's scheduled backups through the
+/// settings file (`targets[].enabled`, `targets[].disabled_until`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TargetsSetEnabledParams {
+    pub target_id: String,
+    pub enabled: bool,
+    /// RFC3339; only with `enabled: false`, the target resumes at this instant.
+    #[serde(default)]
+    pub disabled_until: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TargetsSetEnabledResult {
+    pub target_id: String,
+    pub enabled: bool,
+    pub disabled_until: Option<String>,
+}
+
+/// Params for `verify.acknowledge`: clears the warning a failed scheduled verify left on a target
+/// (`lastVerify.warning` in the status snapshot).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyAcknowledgeParams {
+    pub target_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyAcknowledgeResult {
+    pub target_id: String,
+    /// False when the target had no warning to clear.
+    pub cleared: bool,
+}
+
+/// Params for `restore.estimate`; the result is a [`crate::RestoreEstimate`]. Only local index DBs
+/// are read: the snapshot's file map must be on this machine (its backup or a restore wrote it).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreEstimateParams {
+    pub snapshot_id: String,
+    /// Search only this endpoint's index DBs.
+    #[serde(default)]
+    pub endpoint_id: Option<String>,
+    /// Snapshot-relative path; only entries at or under it count.
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+/// Params for `stats.topFiles`; the result is a [`crate::dedup_stats::TopFiles`]. Like
+/// `restore.estimate`, it needs the snapshot's file map on this machine.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatsTopFilesParams {
+    pub snapshot_id: String,
+    /// Search only this endpoint's index DBs.
+    #[serde(default)]
+    pub endpoint_id: Option<String>,
+    /// At most this many files (default 50).
+    #[serde(default)]
+    pub limit: Option<u32>,
+}
+
+/// Params for `index.sync`; the result is a [`crate::index_sync::IndexSyncReport`]. Same as
+/// `televybackup index sync`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexSyncParams {
+    pub target_id: String,
+    /// Sync this snapshot's file map instead of the target's latest.
+    #[serde(default)]
+    pub snapshot_id: Option<String>,
+    /// Download even when the local index already matches the catalog.
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// Params for `logs.stream`: follow the daemon's running task, picked by task id or target id.
+/// The response is followed by the run's NDJSON log lines (the same lines as its run log file),
+/// starting with up to [`crate::run_log::LIVE_RUN_LOG_BACKLOG_LINES`] earlier ones; the daemon
+/// closes the connection when the run finishes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogsStreamParams {
+    #[serde(default)]
+    pub task_id: Option<String>,
+    #[serde(default)]
+    pub target_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogsStreamResult {
+    pub task_id: String,
+    pub target_id: Option<String>,
+}
+
+/// Written in place of lines a slow `logs.stream` client fell too far behind to receive.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogsStreamLagged {
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub skipped: u64,
+}
+
+impl LogsStreamLagged {
+    pub fn new(skipped: u64) -> Self {
+        Self {
+            type_: "logs.lagged".to_string(),
+            skipped,
+        }
+    }
+}
+
 // Best-effort status reporting from CLI -> daemon for UI status surfaces.
 // These calls must not be required for correctness; they only improve observability.
 
@@ -170,7 +405,7 @@ pub struct StatusTaskStartParams {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct StatusTaskProgress {
-    pub phase: String,
+    pub phase: Phase,
     pub files_total: Option<u64>,
     pub files_done: Option<u64>,
     pub source_files_total: Option<u64>,
@@ -185,6 +420,7 @@ pub struct StatusTaskProgress {
     pub bytes_uploaded: Option<u64>,
     pub bytes_downloaded: Option<u64>,
     pub bytes_deduped: Option<u64>,
+    pub bytes_total: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -202,5 +438,5 @@ pub struct StatusTaskFinishParams {
     pub task_id: String,
     pub kind: String, // "backup" | "restore" | "verify"
     pub target_id: String,
-    pub state: String, // "succeeded" | "failed"
+    pub state: String, // "succeeded" | "failed" | "cancelled"
 }
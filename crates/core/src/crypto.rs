@@ -1,7 +1,13 @@
+use std::io::Read;
+
+use chacha20::XChaCha20;
+use chacha20::cipher::{KeyIvInit, StreamCipher, StreamCipherSeek};
 use chacha20poly1305::{
     XChaCha20Poly1305, XNonce,
     aead::{AeadCore, AeadInPlace, KeyInit, OsRng},
 };
+use poly1305::Poly1305;
+use poly1305::universal_hash::UniversalHash;
 
 use crate::{Error, Result};
 
@@ -10,6 +16,11 @@ pub const NONCE_LEN: usize = 24;
 pub(crate) const AEAD_TAG_LEN: usize = 16;
 pub(crate) const FRAMING_OVERHEAD_BYTES: usize = 1 + NONCE_LEN + AEAD_TAG_LEN;
 
+/// Plaintext bytes encrypted per step by [`FramedEncryptReader`] (a multiple of the 64-byte
+/// ChaCha20 block so Poly1305 padding only ever applies to the final frame).
+pub(crate) const STREAM_FRAME_BYTES: usize = 1024 * 1024;
+const CHACHA20_BLOCK_BYTES: u64 = 64;
+
 pub fn encrypt_framed(master_key: &[u8; 32], aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
     let cipher = XChaCha20Poly1305::new(master_key.into());
     let nonce: XNonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
@@ -30,6 +41,16 @@ pub fn encrypt_framed(master_key: &[u8; 32], aad: &[u8], plaintext: &[u8]) -> Re
 
 #[allow(dead_code)]
 pub fn decrypt_framed(master_key: &[u8; 32], aad: &[u8], framed: &[u8]) -> Result<Vec<u8>> {
+    decrypt_framed_vec(master_key, aad, framed.to_vec())
+}
+
+/// [`decrypt_framed`] on an owned buffer, decrypted in place so no second buffer of its size is
+/// allocated.
+pub(crate) fn decrypt_framed_vec(
+    master_key: &[u8; 32],
+    aad: &[u8],
+    mut framed: Vec<u8>,
+) -> Result<Vec<u8>> {
     if framed.len() < 1 + NONCE_LEN {
         return Err(Error::Crypto {
             message: "invalid framing (too small)".to_string(),
@@ -42,15 +63,297 @@ pub fn decrypt_framed(master_key: &[u8; 32], aad: &[u8], framed: &[u8]) -> Resul
     }
 
     let cipher = XChaCha20Poly1305::new(master_key.into());
-    let nonce = XNonce::from_slice(&framed[1..1 + NONCE_LEN]);
-
-    let mut buffer = framed[1 + NONCE_LEN..].to_vec();
+    let nonce = XNonce::clone_from_slice(&framed[1..1 + NONCE_LEN]);
+    framed.drain(..1 + NONCE_LEN);
     cipher
-        .decrypt_in_place(nonce, aad, &mut buffer)
+        .decrypt_in_place(&nonce, aad, &mut framed)
         .map_err(|_| Error::Crypto {
             message: "decrypt failed".to_string(),
         })?;
-    Ok(buffer)
+    Ok(framed)
+}
+
+const TARGET_KEY_INFO_V1: &[u8] = b"televybackup/target-key/v1:";
+/// blake3 `derive_key` context for the chunk id key of a derived data key.
+const CHUNK_ID_KEY_CONTEXT: &str = "televybackup 2026 chunk id v1";
+
+/// How a snapshot's data key (chunks, packs and file map index) was obtained; recorded per
+/// snapshot as [`KeyDerivation::version`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeyDerivation {
+    /// The master key itself (every snapshot written before per-target keys).
+    #[default]
+    Master,
+    /// HKDF-SHA256 of the master key and the target id ([`derive_target_key`]).
+    TargetV1,
+}
+
+impl KeyDerivation {
+    pub const fn version(self) -> i64 {
+        match self {
+            Self::Master => 0,
+            Self::TargetV1 => 1,
+        }
+    }
+
+    pub fn from_version(version: i64) -> Result<Self> {
+        match version {
+            0 => Ok(Self::Master),
+            1 => Ok(Self::TargetV1),
+            other => Err(Error::Crypto {
+                message: format!("unsupported key derivation version: {other}"),
+            }),
+        }
+    }
+}
+
+/// How chunk ids are computed from chunk contents; recorded per chunk (`chunks.hash_alg`) and per
+/// snapshot (`snapshots.hash_alg`) as [`ChunkHashAlg::as_str`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChunkHashAlg {
+    /// Plain blake3 of the chunk: anyone holding a file can compute its chunk ids.
+    #[default]
+    Blake3,
+    /// blake3 keyed with a key derived from the data key, so chunk ids reveal nothing about
+    /// contents to someone without it.
+    Blake3Keyed,
+}
+
+impl ChunkHashAlg {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Blake3 => "blake3",
+            Self::Blake3Keyed => "blake3-keyed",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "blake3" => Ok(Self::Blake3),
+            "blake3-keyed" => Ok(Self::Blake3Keyed),
+            other => Err(Error::Crypto {
+                message: format!("unsupported chunk hash algorithm: {other}"),
+            }),
+        }
+    }
+}
+
+/// The data key of target `target_id`: HKDF-SHA256 over the master key, with the target id in
+/// the info string. Holding it decrypts that target's snapshots and nothing else.
+pub fn derive_target_key(master_key: &[u8; 32], target_id: &str) -> [u8; 32] {
+    let hk = hkdf::Hkdf::<sha2::Sha256>::new(None, master_key);
+    let mut info = Vec::with_capacity(TARGET_KEY_INFO_V1.len() + target_id.len());
+    info.extend_from_slice(TARGET_KEY_INFO_V1);
+    info.extend_from_slice(target_id.as_bytes());
+    let mut out = [0u8; 32];
+    hk.expand(&info, &mut out)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    out
+}
+
+/// Key for a snapshot's chunks, packs and file map index.
+///
+/// Chunks under a derived key are identified by a keyed blake3 hash, so their ids (and with them
+/// dedupe) never match chunks of another target or of the master key. The master key hashes plain
+/// blake3 unless switched to [`ChunkHashAlg::Blake3Keyed`] (`chunking.hash`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataKey {
+    key: [u8; 32],
+    derivation: KeyDerivation,
+    target_id: Option<String>,
+    chunk_id_key: Option<[u8; 32]>,
+}
+
+impl DataKey {
+    pub fn master(master_key: &[u8; 32]) -> Self {
+        Self {
+            key: *master_key,
+            derivation: KeyDerivation::Master,
+            target_id: None,
+            chunk_id_key: None,
+        }
+    }
+
+    /// Data key of `target_id`, derived from the master key.
+    pub fn for_target(master_key: &[u8; 32], target_id: &str) -> Self {
+        Self::from_target_key(target_id, derive_target_key(master_key, target_id))
+    }
+
+    /// A target key exported on its own (`secrets export-target-key`).
+    pub fn from_target_key(target_id: &str, target_key: [u8; 32]) -> Self {
+        Self {
+            chunk_id_key: Some(blake3::derive_key(CHUNK_ID_KEY_CONTEXT, &target_key)),
+            key: target_key,
+            derivation: KeyDerivation::TargetV1,
+            target_id: Some(target_id.to_string()),
+        }
+    }
+
+    /// The key a snapshot recorded with `derivation` (and, for derived keys, `target_id`) uses.
+    pub fn for_snapshot(
+        master_key: &[u8; 32],
+        derivation: KeyDerivation,
+        target_id: Option<&str>,
+    ) -> Result<Self> {
+        match (derivation, target_id) {
+            (KeyDerivation::Master, _) => Ok(Self::master(master_key)),
+            (KeyDerivation::TargetV1, Some(target_id)) => {
+                Ok(Self::for_target(master_key, target_id))
+            }
+            (KeyDerivation::TargetV1, None) => Err(Error::Crypto {
+                message: "snapshot uses a target key but records no target id".to_string(),
+            }),
+        }
+    }
+
+    /// This key hashing chunk ids with `alg`. Keys derived for a target are always keyed, so
+    /// `Blake3` leaves them as they are.
+    pub fn with_chunk_hash(mut self, alg: ChunkHashAlg) -> Self {
+        if self.derivation == KeyDerivation::Master {
+            self.chunk_id_key = match alg {
+                ChunkHashAlg::Blake3 => None,
+                ChunkHashAlg::Blake3Keyed => {
+                    Some(blake3::derive_key(CHUNK_ID_KEY_CONTEXT, &self.key))
+                }
+            };
+        }
+        self
+    }
+
+    pub fn key(&self) -> &[u8; 32] {
+        &self.key
+    }
+
+    pub fn chunk_hash_alg(&self) -> ChunkHashAlg {
+        if self.chunk_id_key.is_some() {
+            ChunkHashAlg::Blake3Keyed
+        } else {
+            ChunkHashAlg::Blake3
+        }
+    }
+
+    pub fn derivation(&self) -> KeyDerivation {
+        self.derivation
+    }
+
+    /// The target a derived key belongs to; `None` for the master key.
+    pub fn target_id(&self) -> Option<&str> {
+        self.target_id.as_deref()
+    }
+
+    /// Hex chunk id of `plain` under this key.
+    pub fn chunk_hash(&self, plain: &[u8]) -> String {
+        match &self.chunk_id_key {
+            Some(k) => blake3::keyed_hash(k, plain).to_hex().to_string(),
+            None => blake3::hash(plain).to_hex().to_string(),
+        }
+    }
+}
+
+/// Streaming form of [`encrypt_framed`].
+///
+/// Yields byte-for-byte the same framed layout (`version | nonce | ciphertext | tag`, i.e.
+/// `plain_len + FRAMING_OVERHEAD_BYTES` bytes) that `encrypt_framed` produces, but encrypts
+/// `source` in [`STREAM_FRAME_BYTES`] frames so only one frame of ciphertext is held at a time.
+/// Output decrypts with [`decrypt_framed`].
+pub(crate) struct FramedEncryptReader<R> {
+    source: R,
+    aad_len: u64,
+    plain_len: u64,
+    remaining: u64,
+    cipher: XChaCha20,
+    mac: Option<Poly1305>,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> FramedEncryptReader<R> {
+    /// `source` must yield at least `plain_len` bytes; only the first `plain_len` are consumed.
+    pub(crate) fn new(
+        master_key: &[u8; 32],
+        aad: &[u8],
+        source: R,
+        plain_len: u64,
+    ) -> Result<Self> {
+        let nonce: XNonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        Self::with_nonce(master_key, aad, source, plain_len, nonce)
+    }
+
+    fn with_nonce(
+        master_key: &[u8; 32],
+        aad: &[u8],
+        source: R,
+        plain_len: u64,
+        nonce: XNonce,
+    ) -> Result<Self> {
+        if plain_len / CHACHA20_BLOCK_BYTES >= u64::from(u32::MAX) {
+            return Err(Error::Crypto {
+                message: "encrypt failed (plaintext too large)".to_string(),
+            });
+        }
+
+        // Same construction as `chacha20poly1305`: the Poly1305 key is the first 32 bytes of the
+        // keystream and the payload starts at block counter 1.
+        let mut cipher = XChaCha20::new(master_key.into(), &nonce);
+        let mut mac_key = poly1305::Key::default();
+        cipher.apply_keystream(&mut mac_key);
+        let mut mac = Poly1305::new(&mac_key);
+        mac_key.fill(0);
+        cipher.seek(CHACHA20_BLOCK_BYTES);
+        mac.update_padded(aad);
+
+        let mut buf = Vec::with_capacity(1 + NONCE_LEN);
+        buf.push(FRAMING_VERSION);
+        buf.extend_from_slice(&nonce);
+
+        Ok(Self {
+            source,
+            aad_len: aad.len() as u64,
+            plain_len,
+            remaining: plain_len,
+            cipher,
+            mac: Some(mac),
+            buf,
+            pos: 0,
+        })
+    }
+
+    fn refill(&mut self) -> std::io::Result<()> {
+        self.buf.clear();
+        self.pos = 0;
+        if self.remaining > 0 {
+            let n = self.remaining.min(STREAM_FRAME_BYTES as u64) as usize;
+            self.buf.resize(n, 0);
+            self.source.read_exact(&mut self.buf)?;
+            self.remaining -= n as u64;
+            self.cipher.apply_keystream(&mut self.buf);
+            if let Some(mac) = self.mac.as_mut() {
+                mac.update_padded(&self.buf);
+            }
+        } else if let Some(mut mac) = self.mac.take() {
+            let mut block = poly1305::Block::default();
+            block[..8].copy_from_slice(&self.aad_len.to_le_bytes());
+            block[8..].copy_from_slice(&self.plain_len.to_le_bytes());
+            mac.update(&[block]);
+            self.buf.extend_from_slice(&mac.finalize());
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for FramedEncryptReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+        if out.is_empty() {
+            return Ok(0);
+        }
+        if self.pos >= self.buf.len() {
+            self.refill()?;
+        }
+        let n = (self.buf.len() - self.pos).min(out.len());
+        out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
 }
 
 #[cfg(test)]
@@ -69,4 +372,100 @@ mod tests {
 
         assert!(decrypt_framed(&key, b"wrong", &enc).is_err());
     }
+
+    #[test]
+    fn framed_encrypt_reader_matches_encrypt_framed() {
+        let key = [7u8; 32];
+        let aad = b"chunk-hash";
+        for len in [
+            0usize,
+            1,
+            63,
+            STREAM_FRAME_BYTES,
+            2 * STREAM_FRAME_BYTES + 17,
+        ] {
+            let msg = (0..len).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+
+            let mut reader =
+                FramedEncryptReader::new(&key, aad, msg.as_slice(), len as u64).unwrap();
+            let mut streamed = Vec::new();
+            reader.read_to_end(&mut streamed).unwrap();
+            assert_eq!(streamed.len(), len + FRAMING_OVERHEAD_BYTES);
+            assert_eq!(decrypt_framed(&key, aad, &streamed).unwrap(), msg);
+
+            // Same nonce => identical bytes to the one-shot AEAD.
+            let nonce = XNonce::clone_from_slice(&streamed[1..1 + NONCE_LEN]);
+            let cipher = XChaCha20Poly1305::new((&key).into());
+            let mut one_shot = msg.clone();
+            cipher.encrypt_in_place(&nonce, aad, &mut one_shot).unwrap();
+            assert_eq!(&streamed[1 + NONCE_LEN..], one_shot.as_slice());
+        }
+    }
+
+    #[test]
+    fn target_keys_are_separate_per_target() {
+        let master = [3u8; 32];
+        let a = DataKey::for_target(&master, "t1");
+        let b = DataKey::for_target(&master, "t2");
+        assert_ne!(a.key(), b.key());
+        assert_ne!(a.key(), &master);
+        assert_eq!(
+            a,
+            DataKey::from_target_key("t1", derive_target_key(&master, "t1"))
+        );
+
+        let plain = b"same bytes";
+        let master_hash = DataKey::master(&master).chunk_hash(plain);
+        assert_eq!(master_hash, blake3::hash(plain).to_hex().to_string());
+        assert_ne!(a.chunk_hash(plain), master_hash);
+        assert_ne!(a.chunk_hash(plain), b.chunk_hash(plain));
+
+        let enc = encrypt_framed(a.key(), b"aad", plain).unwrap();
+        assert!(decrypt_framed(b.key(), b"aad", &enc).is_err());
+        assert!(decrypt_framed(&master, b"aad", &enc).is_err());
+    }
+
+    #[test]
+    fn keyed_master_chunk_ids_differ_from_plain_ones() {
+        let master = [5u8; 32];
+        let plain = b"known file";
+        let keyed = DataKey::master(&master).with_chunk_hash(ChunkHashAlg::Blake3Keyed);
+        assert_eq!(keyed.chunk_hash_alg(), ChunkHashAlg::Blake3Keyed);
+        assert_eq!(keyed.key(), &master);
+        assert_ne!(
+            keyed.chunk_hash(plain),
+            DataKey::master(&master).chunk_hash(plain)
+        );
+        assert_eq!(
+            keyed.clone().with_chunk_hash(ChunkHashAlg::Blake3),
+            DataKey::master(&master)
+        );
+
+        let target = DataKey::for_target(&master, "t1");
+        assert_eq!(target.clone().with_chunk_hash(ChunkHashAlg::Blake3), target);
+        assert_eq!(target.chunk_hash_alg(), ChunkHashAlg::Blake3Keyed);
+
+        for alg in [ChunkHashAlg::Blake3, ChunkHashAlg::Blake3Keyed] {
+            assert_eq!(ChunkHashAlg::parse(alg.as_str()).unwrap(), alg);
+        }
+        assert!(ChunkHashAlg::parse("sha256").is_err());
+    }
+
+    #[test]
+    fn key_derivation_versions_round_trip() {
+        for d in [KeyDerivation::Master, KeyDerivation::TargetV1] {
+            assert_eq!(KeyDerivation::from_version(d.version()).unwrap(), d);
+        }
+        assert!(KeyDerivation::from_version(9).is_err());
+        assert!(DataKey::for_snapshot(&[1u8; 32], KeyDerivation::TargetV1, None).is_err());
+    }
+
+    #[test]
+    fn framed_encrypt_reader_fails_on_short_source() {
+        let key = [7u8; 32];
+        let mut reader = FramedEncryptReader::new(&key, b"aad", &b"abc"[..], 10).unwrap();
+        let mut out = Vec::new();
+        let err = reader.read_to_end(&mut out).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
 }
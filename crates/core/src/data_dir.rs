@@ -0,0 +1,505 @@
+//! Where the data dir (index DBs, caches, logs, IPC sockets, status) lives, and moving it.
+//!
+//! The data dir is resolved as `--data-dir`, then `TELEVYBACKUP_DATA_DIR`, then the pointer file
+//! `<config_dir>/data-dir.txt`, then the default. The pointer lets the data dir live on another
+//! volume without every launcher (GUI, launchd plist, shell) having to set the env var; removing
+//! it goes back to the default.
+
+use std::path::{Component, Path, PathBuf};
+
+use serde::Serialize;
+use sqlx::Row;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+
+use crate::{Error, Result};
+
+pub const DATA_DIR_ENV: &str = "TELEVYBACKUP_DATA_DIR";
+pub const DATA_DIR_POINTER_FILE_NAME: &str = "data-dir.txt";
+
+/// Everything a data dir holds. Only these are moved, so a data dir shared with the config dir
+/// (the default) keeps `config.toml` and secrets where they are.
+pub const DATA_DIR_ENTRIES: &[&str] = &[
+    "index",
+    "cache",
+    "logs",
+    "ipc",
+    "status",
+    "control",
+    "tmp",
+    "device.json",
+    "audit.ndjson",
+    "usage.sqlite",
+    "usage.sqlite-wal",
+    "usage.sqlite-shm",
+    "verify-state.json",
+    "fs-watch.json",
+];
+
+/// Which rule picked the data dir.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DataDirSource {
+    Flag,
+    Env,
+    Pointer,
+    Default,
+}
+
+impl DataDirSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Flag => "flag",
+            Self::Env => "env",
+            Self::Pointer => "pointer",
+            Self::Default => "default",
+        }
+    }
+}
+
+pub fn data_dir_pointer_path(config_dir: &Path) -> PathBuf {
+    config_dir.join(DATA_DIR_POINTER_FILE_NAME)
+}
+
+/// The data dir named by the pointer file, `None` when there is no pointer file.
+pub fn read_data_dir_pointer(config_dir: &Path) -> Result<Option<PathBuf>> {
+    let path = data_dir_pointer_path(config_dir);
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let value = text.trim();
+    if value.is_empty() || !Path::new(value).is_absolute() {
+        return Err(Error::InvalidConfig {
+            message: format!(
+                "{} must hold an absolute path; remove it to use the default data dir",
+                path.display()
+            ),
+        });
+    }
+    Ok(Some(PathBuf::from(value)))
+}
+
+pub fn write_data_dir_pointer(config_dir: &Path, data_dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(config_dir)?;
+    let path = data_dir_pointer_path(config_dir);
+    let tmp = path.with_extension("txt.tmp");
+    std::fs::write(&tmp, format!("{}\n", data_dir.display()))?;
+    std::fs::rename(&tmp, &path)?;
+    Ok(())
+}
+
+/// Resolves the data dir: `flag`, then [`DATA_DIR_ENV`], then the pointer file, then `default`.
+///
+/// A pointer to a missing directory is an error rather than a silent fallback: an unmounted
+/// external volume must not make the daemon start over with an empty data dir.
+pub fn resolve_data_dir(
+    flag: Option<PathBuf>,
+    config_dir: &Path,
+    default: impl FnOnce() -> PathBuf,
+) -> Result<(PathBuf, DataDirSource)> {
+    if let Some(dir) = flag {
+        return Ok((dir, DataDirSource::Flag));
+    }
+    if let Some(dir) = std::env::var_os(DATA_DIR_ENV).filter(|v| !v.is_empty()) {
+        return Ok((PathBuf::from(dir), DataDirSource::Env));
+    }
+    if let Some(dir) = read_data_dir_pointer(config_dir)? {
+        if !dir.is_dir() {
+            return Err(Error::InvalidConfig {
+                message: format!(
+                    "data dir {} named by {} does not exist (is the volume mounted?); remove the pointer file to use the default data dir",
+                    dir.display(),
+                    data_dir_pointer_path(config_dir).display()
+                ),
+            });
+        }
+        return Ok((dir, DataDirSource::Pointer));
+    }
+    Ok((default(), DataDirSource::Default))
+}
+
+/// One of [`DATA_DIR_ENTRIES`] present in a data dir.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DataDirEntry {
+    pub name: String,
+    pub files: u64,
+    pub bytes: u64,
+}
+
+/// What a data dir holds, in [`DATA_DIR_ENTRIES`] order; missing entries are left out.
+pub fn probe_data_dir(data_dir: &Path) -> Result<Vec<DataDirEntry>> {
+    let mut out = Vec::new();
+    for name in DATA_DIR_ENTRIES {
+        let path = data_dir.join(name);
+        if std::fs::symlink_metadata(&path).is_err() {
+            continue;
+        }
+        let mut entry = DataDirEntry {
+            name: name.to_string(),
+            files: 0,
+            bytes: 0,
+        };
+        for item in walkdir::WalkDir::new(&path) {
+            let meta = item?.metadata()?;
+            if meta.is_file() {
+                entry.files += 1;
+                entry.bytes += meta.len();
+            }
+        }
+        out.push(entry);
+    }
+    Ok(out)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DataDirMigrateMode {
+    Copy,
+    Move,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DataDirMigration {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub mode: DataDirMigrateMode,
+    /// Top-level [`DATA_DIR_ENTRIES`] that existed and were copied.
+    pub entries: Vec<String>,
+    pub files: u64,
+    pub bytes: u64,
+    /// Sockets and other special files, which only make sense to the process that made them.
+    pub skipped: u64,
+    /// SQLite DBs that passed `PRAGMA integrity_check` at the destination.
+    pub databases_checked: u64,
+    pub pointer_file: PathBuf,
+}
+
+/// Copies (or moves) the data dir `from` to `to` and points `config_dir` at it.
+///
+/// Refuses while the daemon holds `ipc/daemon.lock` or a run log is still being written, and
+/// holds the daemon lock itself until done so the daemon can't start halfway. Every SQLite DB is
+/// integrity-checked at the destination before the pointer file is written; on any failure the
+/// copied entries are removed again and the pointer and the source stay as they were.
+pub async fn migrate_data_dir(
+    from: &Path,
+    to: &Path,
+    config_dir: &Path,
+    mode: DataDirMigrateMode,
+) -> Result<DataDirMigration> {
+    if !to.is_absolute() {
+        return Err(Error::InvalidConfig {
+            message: format!("destination must be an absolute path: {}", to.display()),
+        });
+    }
+    let from_abs = std::fs::canonicalize(from).map_err(|e| Error::InvalidConfig {
+        message: format!("data dir {} cannot be read: {e}", from.display()),
+    })?;
+    let to_abs = normalize(to);
+    if to_abs.starts_with(&from_abs) || from_abs.starts_with(&to_abs) {
+        return Err(Error::InvalidConfig {
+            message: format!(
+                "destination {} must not be the data dir {}, inside it, or contain it",
+                to_abs.display(),
+                from_abs.display()
+            ),
+        });
+    }
+
+    let _daemon_lock = lock_data_dir(&from_abs)?;
+
+    let entries = DATA_DIR_ENTRIES
+        .iter()
+        .filter(|name| std::fs::symlink_metadata(from_abs.join(name)).is_ok())
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>();
+    std::fs::create_dir_all(&to_abs)?;
+    if let Some(name) = entries
+        .iter()
+        .find(|name| std::fs::symlink_metadata(to_abs.join(name)).is_ok())
+    {
+        return Err(Error::InvalidConfig {
+            message: format!(
+                "destination already has {}; pick an empty directory",
+                to_abs.join(name).display()
+            ),
+        });
+    }
+
+    let mut report = DataDirMigration {
+        from: from_abs.clone(),
+        to: to_abs.clone(),
+        mode,
+        entries,
+        files: 0,
+        bytes: 0,
+        skipped: 0,
+        databases_checked: 0,
+        pointer_file: data_dir_pointer_path(config_dir),
+    };
+    if let Err(e) = copy_and_check(&mut report).await {
+        for name in &report.entries {
+            let _ = remove_entry(&to_abs.join(name));
+        }
+        return Err(e);
+    }
+
+    write_data_dir_pointer(config_dir, &to_abs)?;
+    if mode == DataDirMigrateMode::Move {
+        for name in &report.entries {
+            remove_entry(&from_abs.join(name))?;
+        }
+    }
+    Ok(report)
+}
+
+async fn copy_and_check(report: &mut DataDirMigration) -> Result<()> {
+    let mut databases = Vec::new();
+    for name in report.entries.clone() {
+        copy_entry(
+            &report.from.join(&name),
+            &report.to.join(&name),
+            report,
+            &mut databases,
+        )?;
+    }
+    for db in databases {
+        check_sqlite_integrity(&db).await?;
+        report.databases_checked += 1;
+    }
+    Ok(())
+}
+
+/// Takes the daemon instance lock of `data_dir`, failing when the daemon or a run is active.
+fn lock_data_dir(data_dir: &Path) -> Result<Option<std::fs::File>> {
+    let in_use = |reason: String| Error::DataDirInUse {
+        path: data_dir.to_path_buf(),
+        reason,
+    };
+    let lock_path = data_dir.join("ipc").join("daemon.lock");
+    let lock = match std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&lock_path)
+    {
+        Ok(file) => match file.try_lock() {
+            Ok(()) => Some(file),
+            Err(std::fs::TryLockError::WouldBlock) => {
+                return Err(in_use("the daemon is running".to_string()));
+            }
+            Err(std::fs::TryLockError::Error(e)) => return Err(e.into()),
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+    for log in crate::run_log::list_run_logs(data_dir)? {
+        if crate::run_log::run_log_is_active(&log.path).unwrap_or(false) {
+            return Err(in_use(format!("run {} is still running", log.run_id)));
+        }
+    }
+    Ok(lock)
+}
+
+fn copy_entry(
+    src: &Path,
+    dst: &Path,
+    report: &mut DataDirMigration,
+    databases: &mut Vec<PathBuf>,
+) -> Result<()> {
+    let meta = std::fs::symlink_metadata(src)?;
+    if meta.is_dir() {
+        std::fs::create_dir_all(dst)?;
+        std::fs::set_permissions(dst, meta.permissions())?;
+        for entry in std::fs::read_dir(src)? {
+            let entry = entry?;
+            copy_entry(
+                &entry.path(),
+                &dst.join(entry.file_name()),
+                report,
+                databases,
+            )?;
+        }
+    } else if meta.is_file() {
+        report.bytes += std::fs::copy(src, dst)?;
+        report.files += 1;
+        if dst.extension().is_some_and(|ext| ext == "sqlite") {
+            databases.push(dst.to_path_buf());
+        }
+    } else {
+        report.skipped += 1;
+    }
+    Ok(())
+}
+
+async fn check_sqlite_integrity(path: &Path) -> Result<()> {
+    let options = SqliteConnectOptions::new()
+        .filename(path)
+        .create_if_missing(false);
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(options)
+        .await?;
+    let result = sqlx::query("PRAGMA integrity_check")
+        .fetch_one(&pool)
+        .await
+        .map(|row| row.get::<String, _>(0));
+    pool.close().await;
+    match result? {
+        ok if ok == "ok" => Ok(()),
+        problem => Err(Error::Integrity {
+            message: format!("{} failed its integrity check: {problem}", path.display()),
+        }),
+    }
+}
+
+fn remove_entry(path: &Path) -> std::io::Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => std::fs::remove_dir_all(path),
+        Ok(_) => std::fs::remove_file(path),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Lexically resolves `.` and `..`, plus symlinks in the longest existing prefix, so a
+/// destination that does not exist yet can still be compared with the canonical source.
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                out.pop();
+            }
+            Component::CurDir => {}
+            other => out.push(other),
+        }
+    }
+    let mut existing = out.as_path();
+    let mut rest = Vec::new();
+    while !existing.exists() {
+        let (Some(parent), Some(name)) = (existing.parent(), existing.file_name()) else {
+            return out;
+        };
+        rest.push(name.to_os_string());
+        existing = parent;
+    }
+    let mut resolved = std::fs::canonicalize(existing).unwrap_or_else(|_| existing.to_path_buf());
+    resolved.extend(rest.iter().rev());
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn seed_data_dir(dir: &Path) {
+        std::fs::create_dir_all(dir.join("index")).unwrap();
+        std::fs::create_dir_all(dir.join("status")).unwrap();
+        std::fs::write(dir.join("status").join("status.json"), b"{}").unwrap();
+        std::fs::write(dir.join("device.json"), b"{}").unwrap();
+        std::fs::write(dir.join("config.toml"), b"version = 2\n").unwrap();
+        let db = crate::index_db::open_index_db(&dir.join("index").join("index.ep1.sqlite"))
+            .await
+            .unwrap();
+        db.close().await;
+    }
+
+    #[test]
+    fn pointer_is_used_only_without_flag_or_env() {
+        let config = tempfile::tempdir().unwrap();
+        let target = tempfile::tempdir().unwrap();
+        let default = || PathBuf::from("/default/data");
+
+        let (dir, source) = resolve_data_dir(None, config.path(), default).unwrap();
+        if std::env::var_os(DATA_DIR_ENV).is_none() {
+            assert_eq!((dir, source), (default(), DataDirSource::Default));
+        }
+
+        write_data_dir_pointer(config.path(), target.path()).unwrap();
+        let (dir, source) =
+            resolve_data_dir(Some(PathBuf::from("/flag")), config.path(), default).unwrap();
+        assert_eq!((dir, source), (PathBuf::from("/flag"), DataDirSource::Flag));
+        if std::env::var_os(DATA_DIR_ENV).is_none() {
+            let (dir, source) = resolve_data_dir(None, config.path(), default).unwrap();
+            assert_eq!(
+                (dir, source),
+                (target.path().to_path_buf(), DataDirSource::Pointer)
+            );
+
+            let missing = target.path().join("unmounted");
+            write_data_dir_pointer(config.path(), &missing).unwrap();
+            let err = resolve_data_dir(None, config.path(), default).unwrap_err();
+            assert!(err.to_string().contains("does not exist"), "{err}");
+        }
+
+        std::fs::write(data_dir_pointer_path(config.path()), "relative/dir\n").unwrap();
+        assert!(read_data_dir_pointer(config.path()).is_err());
+    }
+
+    #[tokio::test]
+    async fn move_relocates_data_entries_and_keeps_config_files() {
+        let root = tempfile::tempdir().unwrap();
+        let from = root.path().join("app");
+        let to = root.path().join("external").join("TelevyBackup");
+        seed_data_dir(&from).await;
+
+        let report = migrate_data_dir(&from, &to, &from, DataDirMigrateMode::Move)
+            .await
+            .unwrap();
+        assert_eq!(report.entries, vec!["index", "status", "device.json"]);
+        assert_eq!(report.databases_checked, 1);
+        assert!(to.join("index").join("index.ep1.sqlite").exists());
+        assert!(to.join("status").join("status.json").exists());
+        assert!(!from.join("index").exists());
+        assert!(!from.join("device.json").exists());
+        assert!(from.join("config.toml").exists());
+        assert_eq!(
+            read_data_dir_pointer(&from).unwrap(),
+            Some(std::fs::canonicalize(&to).unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn copy_refuses_a_nested_or_occupied_destination_or_an_active_run() {
+        let root = tempfile::tempdir().unwrap();
+        let from = root.path().join("app");
+        let config = root.path().join("config");
+        seed_data_dir(&from).await;
+
+        let nested = migrate_data_dir(&from, &from.join("sub"), &config, DataDirMigrateMode::Copy)
+            .await
+            .unwrap_err();
+        assert!(matches!(nested, Error::InvalidConfig { .. }), "{nested}");
+
+        let occupied = root.path().join("occupied");
+        std::fs::create_dir_all(occupied.join("index")).unwrap();
+        let err = migrate_data_dir(&from, &occupied, &config, DataDirMigrateMode::Copy)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("already has"), "{err}");
+
+        std::fs::create_dir_all(from.join("ipc")).unwrap();
+        let lock_path = from.join("ipc").join("daemon.lock");
+        let daemon = std::fs::File::create(&lock_path).unwrap();
+        daemon.lock().unwrap();
+        let to = root.path().join("dest");
+        let err = migrate_data_dir(&from, &to, &config, DataDirMigrateMode::Copy)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::DataDirInUse { .. }), "{err}");
+        drop(daemon);
+
+        let report = migrate_data_dir(&from, &to, &config, DataDirMigrateMode::Copy)
+            .await
+            .unwrap();
+        assert!(report.entries.contains(&"ipc".to_string()));
+        assert!(from.join("index").join("index.ep1.sqlite").exists());
+        assert!(to.join("index").join("index.ep1.sqlite").exists());
+        assert!(read_data_dir_pointer(&config).unwrap().is_some());
+        let probed = probe_data_dir(&to).unwrap();
+        let status = probed.iter().find(|e| e.name == "status").unwrap();
+        assert_eq!((status.files, status.bytes), (1, 2));
+    }
+}
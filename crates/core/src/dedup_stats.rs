@@ -0,0 +1,170 @@
+//! Chunk-level dedup statistics per file (`televybackup stats top-files`, `stats.topFiles`).
+//!
+//! A file's bytes are *unique* when its chunks are referenced by no other file in any snapshot of
+//! the endpoint, so deleting the file (from every snapshot holding this version) frees them; the
+//! rest are *shared*. References come from `chunks.ref_count` when the counts can be trusted and
+//! from the other snapshots' cached file maps otherwise.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use sqlx::SqliteConnection;
+
+use crate::index_db::open_existing_index_db;
+use crate::{Error, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileDedupStats {
+    pub path: String,
+    pub size: u64,
+    pub unique_bytes: u64,
+    pub shared_bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TopFiles {
+    pub snapshot_id: String,
+    /// Sorted by `unique_bytes`, largest first.
+    pub files: Vec<FileDedupStats>,
+    /// Snapshots whose references were needed but have no local file map; chunks only they share
+    /// are counted as unique.
+    pub snapshots_unscanned: u64,
+}
+
+/// The `limit` files of `snapshot_id` with the most unique bytes. `endpoint_db_path` is the
+/// endpoint index DB holding the snapshot and `filemap_dir` its per-snapshot file maps, which must
+/// include `snapshot_id`'s.
+pub async fn top_files(
+    endpoint_db_path: &Path,
+    filemap_dir: &Path,
+    snapshot_id: &str,
+    limit: u32,
+) -> Result<TopFiles> {
+    let filemap_db_path = filemap_dir.join(format!("{snapshot_id}.sqlite"));
+    if !filemap_db_path.exists() {
+        return Err(Error::InvalidConfig {
+            message: format!("no local file map for snapshot {snapshot_id}"),
+        });
+    }
+    let pool = open_existing_index_db(endpoint_db_path).await?;
+    let mut conn = pool.acquire().await?;
+    attach(&mut conn, "top_fm", &filemap_db_path).await?;
+
+    // Only the snapshot's own chunks matter, so the counts stay small even on large endpoints.
+    sqlx::query(
+        "CREATE TEMP TABLE top_files_refs (chunk_hash TEXT PRIMARY KEY, refs INTEGER NOT NULL) WITHOUT ROWID",
+    )
+    .execute(&mut *conn)
+    .await?;
+    let use_ref_counts = crate::chunk_refs::ref_counts_usable(&mut conn).await?;
+    let refs_from = if use_ref_counts {
+        "COALESCE((SELECT c.ref_count FROM main.chunks c WHERE c.chunk_hash = fc.chunk_hash), 0)"
+    } else {
+        "0"
+    };
+    sqlx::query(&format!(
+        "INSERT INTO temp.top_files_refs (chunk_hash, refs) SELECT fc.chunk_hash, {refs_from} FROM (SELECT DISTINCT chunk_hash FROM top_fm.file_chunks) fc"
+    ))
+    .execute(&mut *conn)
+    .await?;
+
+    let scan: Vec<String> = if use_ref_counts {
+        sqlx::query_scalar("SELECT snapshot_id FROM snapshots WHERE chunk_refs_counted = 0")
+            .fetch_all(&mut *conn)
+            .await?
+    } else {
+        sqlx::query_scalar("SELECT snapshot_id FROM snapshots")
+            .fetch_all(&mut *conn)
+            .await?
+    };
+    let mut snapshots_unscanned = 0u64;
+    for id in &scan {
+        let schema = if id == snapshot_id {
+            "top_fm"
+        } else {
+            let path = filemap_dir.join(format!("{id}.sqlite"));
+            if !path.exists() {
+                snapshots_unscanned += 1;
+                continue;
+            }
+            attach(&mut conn, "top_other", &path).await?;
+            "top_other"
+        };
+        sqlx::query(&format!(
+            r#"
+            UPDATE temp.top_files_refs
+            SET refs = refs + s.n
+            FROM (
+              SELECT chunk_hash, COUNT(1) AS n FROM {schema}.file_chunks
+              WHERE chunk_hash IN (SELECT chunk_hash FROM temp.top_files_refs)
+              GROUP BY chunk_hash
+            ) s
+            WHERE s.chunk_hash = top_files_refs.chunk_hash
+            "#
+        ))
+        .execute(&mut *conn)
+        .await?;
+        if schema == "top_other" {
+            sqlx::query("DETACH DATABASE top_other")
+                .execute(&mut *conn)
+                .await?;
+        }
+    }
+
+    // A chunk is the file's own when every reference to it comes from the file itself.
+    let rows = sqlx::query(
+        r#"
+        SELECT f.path,
+               SUM(pf.bytes) AS size,
+               SUM(CASE WHEN r.refs <= pf.n THEN pf.bytes ELSE 0 END) AS unique_bytes
+        FROM (
+          SELECT file_id, chunk_hash, COUNT(1) AS n, SUM(len) AS bytes
+          FROM top_fm.file_chunks
+          GROUP BY file_id, chunk_hash
+        ) pf
+        JOIN top_fm.files f ON f.file_id = pf.file_id
+        JOIN temp.top_files_refs r ON r.chunk_hash = pf.chunk_hash
+        WHERE f.snapshot_id = ?1 AND f.kind = 'file'
+        GROUP BY pf.file_id
+        ORDER BY unique_bytes DESC, size DESC, f.path
+        LIMIT ?2
+        "#,
+    )
+    .bind(snapshot_id)
+    .bind(i64::from(limit))
+    .fetch_all(&mut *conn)
+    .await?;
+    drop(conn);
+    pool.close().await;
+
+    let files = rows
+        .iter()
+        .map(|row| {
+            let size = row.get::<i64, _>("size").max(0) as u64;
+            let unique_bytes = row.get::<i64, _>("unique_bytes").max(0) as u64;
+            FileDedupStats {
+                path: row.get("path"),
+                size,
+                unique_bytes,
+                shared_bytes: size.saturating_sub(unique_bytes),
+            }
+        })
+        .collect();
+    Ok(TopFiles {
+        snapshot_id: snapshot_id.to_string(),
+        files,
+        snapshots_unscanned,
+    })
+}
+
+async fn attach(conn: &mut SqliteConnection, alias: &str, path: &Path) -> Result<()> {
+    // ATTACH needs a literal string; escape single quotes defensively.
+    let path_sql = path.to_string_lossy().replace('\'', "''");
+    sqlx::query(&format!("ATTACH DATABASE '{path_sql}' AS {alias}"))
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
@@ -67,6 +67,7 @@ pub async fn materialize_remote_dedupe_db<S: Storage>(
         storage,
         &cat.base.base_id,
         &cat.base.manifest_object_id,
+        None,
         master_key,
         &base_path,
         None,
@@ -87,6 +88,7 @@ pub async fn materialize_remote_dedupe_db<S: Storage>(
             storage,
             &delta.delta_id,
             &delta.manifest_object_id,
+            None,
             master_key,
             &delta_path,
             None,
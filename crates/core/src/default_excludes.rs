@@ -0,0 +1,94 @@
+//! `scan.default_excludes`: caches, trash and other system-generated noise that no target needs
+//! backed up, left out before `.televyignore` rules and `targets[].filters` see them.
+//!
+//! Matched directories are not entered, so their contents are neither read nor counted.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+/// Gitignore-style patterns, matched against paths relative to the source root.
+pub const PATTERNS: &[&str] = &[
+    "Caches/",
+    ".Trash/",
+    ".Trashes/",
+    ".DocumentRevisions-V100/",
+    ".Spotlight-V100/",
+    ".fseventsd/",
+    ".TemporaryItems/",
+    "**/node_modules/.cache/",
+    ".node-gyp/",
+    "*.nobackup",
+    ".DS_Store",
+];
+
+/// Walk filter applying [`PATTERNS`] below one root.
+#[derive(Debug)]
+pub(crate) struct DefaultExcludes {
+    root: PathBuf,
+    matcher: Gitignore,
+    skipped: AtomicU64,
+}
+
+impl DefaultExcludes {
+    pub(crate) fn for_root(root: &Path) -> Self {
+        let mut builder = GitignoreBuilder::new("");
+        for pattern in PATTERNS {
+            builder
+                .add_line(None, pattern)
+                .expect("default exclude patterns are valid");
+        }
+        Self {
+            root: root.to_path_buf(),
+            matcher: builder.build().expect("default exclude patterns are valid"),
+            skipped: AtomicU64::new(0),
+        }
+    }
+
+    /// Whether the walk keeps `entry`; counts it as skipped if not. The root always passes.
+    pub(crate) fn allows(&self, entry: &ignore::DirEntry) -> bool {
+        if entry.depth() == 0 {
+            return true;
+        }
+        let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+        if !self.excludes(entry.path(), is_dir) {
+            return true;
+        }
+        self.skipped.fetch_add(1, Ordering::Relaxed);
+        false
+    }
+
+    fn excludes(&self, path: &Path, is_dir: bool) -> bool {
+        let rel = path.strip_prefix(&self.root).unwrap_or(path);
+        self.matcher.matched(rel, is_dir).is_ignore()
+    }
+
+    /// Entries left out so far; a directory counts once.
+    pub(crate) fn skipped(&self) -> u64 {
+        self.skipped.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noise_paths_match_anywhere_below_the_root() {
+        let excludes = DefaultExcludes::for_root(Path::new("/Users/me"));
+        let excluded = |p: &str, is_dir: bool| excludes.excludes(Path::new(p), is_dir);
+
+        assert!(excluded("/Users/me/Library/Caches", true));
+        assert!(excluded("/Users/me/.Trash", true));
+        assert!(excluded("/Users/me/src/app/node_modules/.cache", true));
+        assert!(excluded("/Users/me/Movies/render.nobackup", true));
+        assert!(excluded("/Users/me/Documents/.DS_Store", false));
+
+        // Only directories named like the cache folders, and only node_modules' own cache.
+        assert!(!excluded("/Users/me/Documents/Caches", false));
+        assert!(!excluded("/Users/me/src/app/.cache", true));
+        assert!(!excluded("/Users/me/Library/Preferences", true));
+        assert!(!excluded("/Users/me/notes.txt", false));
+    }
+}
@@ -0,0 +1,242 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, Result};
+
+pub const DEVICE_FILE_VERSION: u32 = 1;
+const DEVICE_NAME_MAX_CHARS: usize = 128;
+/// Longest `telegram_endpoints[].object_prefix`.
+pub const OBJECT_PREFIX_MAX_LEN: usize = 32;
+const DEFAULT_OBJECT_PREFIX: &str = "televybackup";
+
+/// Identity of the machine that creates snapshots.
+///
+/// Stored next to the local index DBs (`<data_dir>/device.json`) rather than in `config.toml`:
+/// settings travel between machines via config bundles, but the device id must not.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceIdentity {
+    pub device_id: String,
+    pub device_name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DeviceFileV1 {
+    version: u32,
+    #[serde(flatten)]
+    identity: DeviceIdentity,
+}
+
+pub fn device_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("device.json")
+}
+
+/// Loads the device identity, generating and persisting one on first use.
+pub fn load_or_create_device_identity(data_dir: &Path) -> Result<DeviceIdentity> {
+    if let Some(identity) = load_device_identity(data_dir)? {
+        return Ok(identity);
+    }
+    let identity = DeviceIdentity {
+        device_id: format!("dev_{}", uuid::Uuid::new_v4()),
+        device_name: default_device_name(),
+    };
+    write_device_identity(data_dir, &identity)?;
+    tracing::info!(
+        event = "device.created",
+        device_id = %identity.device_id,
+        device_name = %identity.device_name,
+        "device.created"
+    );
+    Ok(identity)
+}
+
+pub fn load_device_identity(data_dir: &Path) -> Result<Option<DeviceIdentity>> {
+    let path = device_path(data_dir);
+    let bytes = match std::fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let file: DeviceFileV1 = serde_json::from_slice(&bytes).map_err(|e| Error::InvalidConfig {
+        message: format!("device file decode failed: path={}; {e}", path.display()),
+    })?;
+    if file.version != DEVICE_FILE_VERSION || file.identity.device_id.is_empty() {
+        return Err(Error::InvalidConfig {
+            message: format!("invalid device file: path={}", path.display()),
+        });
+    }
+    Ok(Some(file.identity))
+}
+
+/// Changes the device name used for future snapshots; the device id is kept.
+pub fn rename_device(data_dir: &Path, name: &str) -> Result<DeviceIdentity> {
+    let name = normalize_device_name(name)?;
+    let mut identity = load_or_create_device_identity(data_dir)?;
+    identity.device_name = name;
+    write_device_identity(data_dir, &identity)?;
+    Ok(identity)
+}
+
+fn normalize_device_name(name: &str) -> Result<String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(Error::InvalidConfig {
+            message: "device name must not be empty".to_string(),
+        });
+    }
+    if name.chars().count() > DEVICE_NAME_MAX_CHARS {
+        return Err(Error::InvalidConfig {
+            message: format!("device name must be at most {DEVICE_NAME_MAX_CHARS} characters"),
+        });
+    }
+    if name.chars().any(char::is_control) {
+        return Err(Error::InvalidConfig {
+            message: "device name must not contain control characters".to_string(),
+        });
+    }
+    Ok(name.to_string())
+}
+
+fn is_object_prefix_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')
+}
+
+/// Checks an object name prefix: 1 to [`OBJECT_PREFIX_MAX_LEN`] ASCII letters, digits, `-`, `_`
+/// or `.`.
+pub fn validate_object_prefix(prefix: &str) -> Result<()> {
+    if prefix.is_empty() || prefix.len() > OBJECT_PREFIX_MAX_LEN {
+        return Err(Error::InvalidConfig {
+            message: format!(
+                "object prefix must be 1 to {OBJECT_PREFIX_MAX_LEN} characters (got {prefix:?})"
+            ),
+        });
+    }
+    if !prefix.chars().all(is_object_prefix_char) {
+        return Err(Error::InvalidConfig {
+            message: format!("object prefix must match [A-Za-z0-9._-]+ (got {prefix:?})"),
+        });
+    }
+    Ok(())
+}
+
+/// The object name prefix used when an endpoint sets none: the device name with each run of
+/// other characters turned into one `-`, cut to [`OBJECT_PREFIX_MAX_LEN`].
+pub fn object_prefix_from_device_name(device_name: &str) -> String {
+    let mut out = String::new();
+    for c in device_name.chars() {
+        if is_object_prefix_char(c) {
+            out.push(c);
+        } else if !out.is_empty() && !out.ends_with('-') {
+            out.push('-');
+        }
+    }
+    out.truncate(OBJECT_PREFIX_MAX_LEN);
+    let out = out.trim_end_matches('-');
+    if out.is_empty() {
+        DEFAULT_OBJECT_PREFIX.to_string()
+    } else {
+        out.to_string()
+    }
+}
+
+fn write_device_identity(data_dir: &Path, identity: &DeviceIdentity) -> Result<()> {
+    std::fs::create_dir_all(data_dir)?;
+    let path = device_path(data_dir);
+    let file = DeviceFileV1 {
+        version: DEVICE_FILE_VERSION,
+        identity: identity.clone(),
+    };
+    let bytes = serde_json::to_vec_pretty(&file).map_err(|e| Error::InvalidConfig {
+        message: format!("device file encode failed: {e}"),
+    })?;
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, bytes)?;
+    std::fs::rename(&tmp, &path)?;
+    Ok(())
+}
+
+fn command_output_name(program: &str, args: &[&str]) -> Option<String> {
+    let out = std::process::Command::new(program)
+        .args(args)
+        .stderr(std::process::Stdio::null())
+        .output()
+        .ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    let name = String::from_utf8_lossy(&out.stdout).trim().to_string();
+    normalize_device_name(&name).ok()
+}
+
+/// This machine's host name without a `.local` suffix (`$HOSTNAME`/`$COMPUTERNAME`, else
+/// `hostname`).
+pub fn host_name() -> Option<String> {
+    ["HOSTNAME", "COMPUTERNAME"]
+        .into_iter()
+        .find_map(|k| {
+            std::env::var(k)
+                .ok()
+                .and_then(|v| normalize_device_name(&v).ok())
+        })
+        .or_else(|| command_output_name("hostname", &[]))
+        .map(|name| {
+            name.strip_suffix(".local")
+                .map(str::to_string)
+                .unwrap_or(name)
+        })
+}
+
+/// Best-effort human-readable machine name (macOS computer name, else the host name).
+fn default_device_name() -> String {
+    #[cfg(target_os = "macos")]
+    if let Some(name) = command_output_name("scutil", &["--get", "ComputerName"]) {
+        return name;
+    }
+
+    host_name().unwrap_or_else(|| "unknown device".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identity_is_stable_and_rename_keeps_id() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(load_device_identity(dir.path()).unwrap(), None);
+
+        let first = load_or_create_device_identity(dir.path()).unwrap();
+        assert!(first.device_id.starts_with("dev_"));
+        assert!(!first.device_name.is_empty());
+        assert_eq!(load_or_create_device_identity(dir.path()).unwrap(), first);
+
+        let renamed = rename_device(dir.path(), "  Work MacBook ").unwrap();
+        assert_eq!(renamed.device_id, first.device_id);
+        assert_eq!(renamed.device_name, "Work MacBook");
+        assert_eq!(load_device_identity(dir.path()).unwrap(), Some(renamed));
+
+        assert!(rename_device(dir.path(), "   ").is_err());
+        assert!(rename_device(dir.path(), "a\nb").is_err());
+    }
+
+    #[test]
+    fn object_prefix_is_derived_from_the_device_name_and_validated() {
+        assert_eq!(
+            object_prefix_from_device_name("Ivan's MacBook Pro"),
+            "Ivan-s-MacBook-Pro"
+        );
+        assert_eq!(object_prefix_from_device_name("  работа  "), "televybackup");
+        let long = object_prefix_from_device_name(&"a b".repeat(20));
+        assert_eq!(long.len(), OBJECT_PREFIX_MAX_LEN - 1);
+        assert!(!long.ends_with('-'));
+        validate_object_prefix(&long).unwrap();
+
+        validate_object_prefix("mac-mini_2.home").unwrap();
+        assert!(validate_object_prefix("").is_err());
+        assert!(validate_object_prefix("mac mini").is_err());
+        assert!(validate_object_prefix("ноутбук").is_err());
+        assert!(validate_object_prefix(&"x".repeat(OBJECT_PREFIX_MAX_LEN + 1)).is_err());
+    }
+}
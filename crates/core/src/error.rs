@@ -1,5 +1,9 @@
 use std::path::PathBuf;
 
+use serde::{Deserialize, Serialize};
+
+use crate::error_code::ErrorCode;
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug, thiserror::Error)]
@@ -34,20 +38,196 @@ pub enum Error {
     #[error("cancelled")]
     Cancelled,
 
+    /// Built with [`Error::telegram`], which classifies the message once.
     #[error("telegram error: {message}")]
-    Telegram { message: String },
+    Telegram {
+        message: String,
+        kind: Option<TelegramErrorKind>,
+        /// Seconds Telegram asked to wait (`FLOOD_WAIT_<n>`).
+        wait_seconds: Option<u64>,
+    },
+
+    /// The configured group was upgraded to a supergroup and now lives under `new_chat_id`.
+    #[error("telegram chat migrated: old={old_chat_id} new={new_chat_id}")]
+    ChatMigrated {
+        old_chat_id: String,
+        new_chat_id: String,
+    },
 
     #[error("missing index part: snapshot_id={snapshot_id} part_no={part_no}")]
     MissingIndexPart { snapshot_id: String, part_no: u32 },
 
+    /// A delta index can't be rebuilt because an index it builds on is gone.
+    #[error(
+        "index chain broken: snapshot_id={snapshot_id} missing_snapshot_id={missing_snapshot_id}; {message}"
+    )]
+    IndexChainBroken {
+        snapshot_id: String,
+        missing_snapshot_id: String,
+        message: String,
+    },
+
+    /// The snapshot is pinned and may only be deleted with `force`.
+    #[error("snapshot is pinned: snapshot_id={snapshot_id}")]
+    SnapshotPinned { snapshot_id: String },
+
     #[error("missing chunk object: chunk_hash={chunk_hash}")]
     MissingChunkObject { chunk_hash: String },
 
     #[error("integrity check failed: {message}")]
     Integrity { message: String },
 
+    #[error(
+        "manifest mismatch: snapshot_id={snapshot_id} object_id={object_id} expected_sha256={expected_sha256} actual_sha256={actual_sha256}"
+    )]
+    ManifestMismatch {
+        snapshot_id: String,
+        object_id: String,
+        expected_sha256: String,
+        actual_sha256: String,
+    },
+
+    /// An uploaded object did not read back as sent, also after uploading it once more.
+    #[error("upload corrupted: kind={object_kind} object_id={object_id}; {message}")]
+    UploadCorrupted {
+        object_kind: String,
+        object_id: String,
+        message: String,
+    },
+
     #[error("unsupported path (must be UTF-8): {path:?}")]
     NonUtf8Path { path: PathBuf },
+
+    /// Snapshot paths equal up to letter case, restored onto a case-insensitive volume.
+    #[error("paths differ only in case on a case-insensitive target: {}", paths.join(", "))]
+    CaseCollision { paths: Vec<String> },
+
+    /// Components of one installation disagree on versions (see `version::check_skew`).
+    #[error("{message}")]
+    VersionSkew {
+        component: String,
+        local_version: String,
+        remote_version: Option<String>,
+        message: String,
+    },
+
+    /// Restored paths the target's file system can't hold, found before anything is written.
+    #[error(
+        "paths too long for the restore target (NAME_MAX {name_max}, PATH_MAX {path_max}): {}",
+        paths.join(", ")
+    )]
+    PathTooLong {
+        paths: Vec<String>,
+        name_max: u64,
+        path_max: u64,
+    },
+
+    /// The data dir can't be moved while the daemon or a run uses it.
+    #[error("data dir in use: {path:?}; {reason}")]
+    DataDirInUse { path: PathBuf, reason: String },
+
+    /// More of the source was unreadable than `scan.max_unreadable_percent` allows.
+    #[error("{message}")]
+    SourceUnreadable {
+        entries_unreadable: u64,
+        percent_unreadable: u64,
+        /// Permission errors hit folders macOS protects (see [`crate::full_disk_access`]).
+        full_disk_access_missing: bool,
+        message: String,
+    },
+
+    /// The source no longer looks like the one the target last backed up (see
+    /// [`crate::source_guard`]).
+    #[error("{message}")]
+    SourceSuspicious {
+        source_path: PathBuf,
+        reason: crate::source_guard::SourceCheck,
+        message: String,
+    },
+}
+
+/// What a Telegram failure was about, as far as its message tells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TelegramErrorKind {
+    FloodWait,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    Timeout,
+}
+
+impl TelegramErrorKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::FloodWait => "flood_wait",
+            Self::Unauthorized => "unauthorized",
+            Self::Forbidden => "forbidden",
+            Self::NotFound => "not_found",
+            Self::Timeout => "timeout",
+        }
+    }
+
+    pub fn classify(message: &str) -> Option<Self> {
+        let upper = message.to_ascii_uppercase();
+        let any = |tokens: &[&str]| tokens.iter().any(|t| upper.contains(t));
+        if any(&[
+            "FLOOD_WAIT",
+            "FLOOD WAIT",
+            "FLOOD_PREMIUM_WAIT",
+            "FLOOD PREMIUM WAIT",
+        ]) {
+            Some(Self::FloodWait)
+        } else if any(&[
+            "AUTH_KEY_UNREGISTERED",
+            "AUTH_KEY_INVALID",
+            "SESSION_REVOKED",
+            "SESSION_EXPIRED",
+            "USER_DEACTIVATED",
+            "ACCESS_TOKEN_INVALID",
+            "RPC ERROR 401",
+            "UNAUTHORIZED",
+        ]) {
+            Some(Self::Unauthorized)
+        } else if any(&[
+            "CHAT_WRITE_FORBIDDEN",
+            "CHAT_ADMIN_REQUIRED",
+            "CHANNEL_PRIVATE",
+            "RPC ERROR 403",
+        ]) {
+            Some(Self::Forbidden)
+        } else if any(&[
+            "NOT FOUND",
+            "CHAT_NOT_FOUND",
+            "PEER_ID_INVALID",
+            "CHAT_ID_INVALID",
+            "MESSAGE_ID_INVALID",
+        ]) {
+            Some(Self::NotFound)
+        } else if is_transient_telegram_message(message) {
+            Some(Self::Timeout)
+        } else {
+            None
+        }
+    }
+}
+
+/// `FLOOD_WAIT_12` (raw RPC name) or `FLOOD_WAIT ... (value: 12)` (grammers' rendering).
+fn flood_wait_seconds(message: &str) -> Option<u64> {
+    let upper = message.to_ascii_uppercase();
+    let leading_number = |s: &str| -> Option<u64> {
+        let digits: String = s.chars().take_while(char::is_ascii_digit).collect();
+        digits.parse().ok()
+    };
+    for token in ["FLOOD_PREMIUM_WAIT_", "FLOOD_WAIT_"] {
+        if let Some(idx) = upper.find(token)
+            && let Some(v) = leading_number(&upper[idx + token.len()..])
+        {
+            return Some(v);
+        }
+    }
+    let idx = upper.find("(VALUE: ")?;
+    leading_number(&upper[idx + "(VALUE: ".len()..])
 }
 
 pub fn is_transient_telegram_message(message: &str) -> bool {
@@ -70,30 +250,415 @@ pub fn is_transient_telegram_message(message: &str) -> bool {
 }
 
 impl Error {
+    pub fn telegram(message: impl Into<String>) -> Self {
+        let message = message.into();
+        let kind = TelegramErrorKind::classify(&message);
+        let wait_seconds = match kind {
+            Some(TelegramErrorKind::FloodWait) => flood_wait_seconds(&message),
+            _ => None,
+        };
+        Self::Telegram {
+            message,
+            kind,
+            wait_seconds,
+        }
+    }
+
+    /// Structured context for machine consumers (CLI `details`, control IPC errors), keyed in
+    /// camelCase. Free-form messages stay in `Display`; only fields worth acting on are listed.
+    pub fn details(&self) -> serde_json::Value {
+        let mut details = serde_json::Map::new();
+        let mut put = |key: &str, value: serde_json::Value| {
+            details.insert(key.to_string(), value);
+        };
+        match self {
+            Self::InvalidConfig { .. }
+            | Self::BootstrapMissing { .. }
+            | Self::BootstrapDecryptFailed { .. }
+            | Self::Walk { .. }
+            | Self::Crypto { .. }
+            | Self::Integrity { .. }
+            | Self::Cancelled => {}
+            Self::Io(e) => put("ioKind", format!("{:?}", e.kind()).into()),
+            Self::Sqlite(e) => {
+                if let Some(code) = e.as_database_error().and_then(|d| d.code()) {
+                    put("sqliteCode", code.into_owned().into());
+                }
+            }
+            Self::SqliteMigrate(e) => {
+                if let sqlx::migrate::MigrateError::VersionMissing(version)
+                | sqlx::migrate::MigrateError::VersionMismatch(version) = e
+                {
+                    put("migrationVersion", (*version).into());
+                }
+            }
+            Self::Walkdir(e) => {
+                if let Some(path) = e.path() {
+                    put("path", path.display().to_string().into());
+                }
+            }
+            Self::Telegram {
+                kind, wait_seconds, ..
+            } => {
+                if let Some(kind) = kind {
+                    put("kind", kind.as_str().into());
+                }
+                if let Some(wait_seconds) = wait_seconds {
+                    put("waitSeconds", (*wait_seconds).into());
+                }
+            }
+            Self::ChatMigrated {
+                old_chat_id,
+                new_chat_id,
+            } => {
+                put("oldChatId", old_chat_id.as_str().into());
+                put("newChatId", new_chat_id.as_str().into());
+            }
+            Self::MissingIndexPart {
+                snapshot_id,
+                part_no,
+            } => {
+                put("snapshotId", snapshot_id.as_str().into());
+                put("partNo", (*part_no).into());
+            }
+            Self::IndexChainBroken {
+                snapshot_id,
+                missing_snapshot_id,
+                ..
+            } => {
+                put("snapshotId", snapshot_id.as_str().into());
+                put("missingSnapshotId", missing_snapshot_id.as_str().into());
+            }
+            Self::SnapshotPinned { snapshot_id } => {
+                put("snapshotId", snapshot_id.as_str().into());
+            }
+            Self::MissingChunkObject { chunk_hash } => {
+                put("chunkHash", chunk_hash.as_str().into());
+            }
+            Self::ManifestMismatch {
+                snapshot_id,
+                object_id,
+                expected_sha256,
+                actual_sha256,
+            } => {
+                put("snapshotId", snapshot_id.as_str().into());
+                put("objectId", object_id.as_str().into());
+                put("expectedSha256", expected_sha256.as_str().into());
+                put("actualSha256", actual_sha256.as_str().into());
+            }
+            Self::UploadCorrupted {
+                object_kind,
+                object_id,
+                ..
+            } => {
+                put("objectKind", object_kind.as_str().into());
+                put("objectId", object_id.as_str().into());
+            }
+            Self::NonUtf8Path { path } => put("path", path.to_string_lossy().into_owned().into()),
+            Self::CaseCollision { paths } => put("paths", paths.clone().into()),
+            Self::VersionSkew {
+                component,
+                local_version,
+                remote_version,
+                ..
+            } => {
+                put("component", component.as_str().into());
+                put("localVersion", local_version.as_str().into());
+                if let Some(remote_version) = remote_version {
+                    put("remoteVersion", remote_version.as_str().into());
+                }
+            }
+            Self::PathTooLong {
+                paths,
+                name_max,
+                path_max,
+            } => {
+                put("paths", paths.clone().into());
+                put("nameMax", (*name_max).into());
+                put("pathMax", (*path_max).into());
+            }
+            Self::DataDirInUse { path, reason } => {
+                put("path", path.display().to_string().into());
+                put("reason", reason.as_str().into());
+            }
+            Self::SourceUnreadable {
+                entries_unreadable,
+                percent_unreadable,
+                full_disk_access_missing,
+                ..
+            } => {
+                put("entriesUnreadable", (*entries_unreadable).into());
+                put("percentUnreadable", (*percent_unreadable).into());
+                put("fullDiskAccessMissing", (*full_disk_access_missing).into());
+            }
+            Self::SourceSuspicious {
+                source_path,
+                reason,
+                ..
+            } => {
+                put("sourcePath", source_path.display().to_string().into());
+                put("reason", reason.as_str().into());
+            }
+        }
+        serde_json::Value::Object(details)
+    }
+
+    pub fn error_code(&self) -> ErrorCode {
+        match self {
+            Self::InvalidConfig { .. } => ErrorCode::ConfigInvalid,
+            Self::BootstrapMissing { .. } => ErrorCode::BootstrapMissing,
+            Self::BootstrapDecryptFailed { .. } => ErrorCode::BootstrapDecryptFailed,
+            Self::Io(_) => ErrorCode::Io,
+            Self::Sqlite(_) => ErrorCode::Sqlite,
+            Self::SqliteMigrate(_) => ErrorCode::SqliteMigrate,
+            Self::Walkdir(_) => ErrorCode::Walkdir,
+            Self::Walk { .. } => ErrorCode::Walkdir,
+            Self::Crypto { .. } => ErrorCode::Crypto,
+            Self::Cancelled => ErrorCode::TaskCancelled,
+            Self::Telegram { .. } => ErrorCode::TelegramUnavailable,
+            Self::ChatMigrated { .. } => ErrorCode::TelegramChatMigrated,
+            Self::MissingIndexPart { .. } => ErrorCode::IndexPartMissing,
+            Self::IndexChainBroken { .. } => ErrorCode::IndexChainBroken,
+            Self::SnapshotPinned { .. } => ErrorCode::SnapshotPinned,
+            Self::MissingChunkObject { .. } => ErrorCode::ChunkMissing,
+            Self::Integrity { .. } => ErrorCode::Integrity,
+            Self::ManifestMismatch { .. } => ErrorCode::IntegrityManifestMismatch,
+            Self::UploadCorrupted { .. } => ErrorCode::TelegramUploadCorrupted,
+            Self::NonUtf8Path { .. } => ErrorCode::PathNonUtf8,
+            Self::CaseCollision { .. } => ErrorCode::RestoreCaseCollision,
+            Self::VersionSkew { .. } => ErrorCode::VersionSkew,
+            Self::PathTooLong { .. } => ErrorCode::RestorePathTooLong,
+            Self::DataDirInUse { .. } => ErrorCode::DataDirInUse,
+            Self::SourceUnreadable { .. } => ErrorCode::ScanUnreadable,
+            Self::SourceSuspicious { .. } => ErrorCode::BackupSourceSuspicious,
+        }
+    }
+
     pub fn code(&self) -> &'static str {
+        self.error_code().as_str()
+    }
+
+    /// `BackupResult::warnings` entries that still apply to the run this error ended.
+    pub fn run_warnings(&self) -> Vec<String> {
         match self {
-            Self::InvalidConfig { .. } => "config.invalid",
-            Self::BootstrapMissing { .. } => "bootstrap.missing",
-            Self::BootstrapDecryptFailed { .. } => "bootstrap.decrypt_failed",
-            Self::Io(_) => "io",
-            Self::Sqlite(_) => "sqlite",
-            Self::SqliteMigrate(_) => "sqlite.migrate",
-            Self::Walkdir(_) => "walkdir",
-            Self::Walk { .. } => "walkdir",
-            Self::Crypto { .. } => "crypto",
-            Self::Cancelled => "task.cancelled",
-            Self::Telegram { .. } => "telegram.unavailable",
-            Self::MissingIndexPart { .. } => "index.part_missing",
-            Self::MissingChunkObject { .. } => "chunk.missing",
-            Self::Integrity { .. } => "integrity",
-            Self::NonUtf8Path { .. } => "path.non_utf8",
+            Self::SourceUnreadable {
+                full_disk_access_missing: true,
+                ..
+            } => vec![crate::full_disk_access::WARNING.to_string()],
+            Self::SourceSuspicious { reason, .. } => vec![reason.warning().to_string()],
+            _ => Vec::new(),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::is_transient_telegram_message;
+    use std::collections::HashSet;
+    use std::mem::discriminant;
+    use std::path::PathBuf;
+
+    use super::{Error, TelegramErrorKind, is_transient_telegram_message};
+
+    /// One value per variant; keep in sync with `expected_detail_keys`.
+    fn every_variant() -> Vec<Error> {
+        let walkdir_err = walkdir::WalkDir::new("/definitely/missing/televy")
+            .into_iter()
+            .next()
+            .unwrap()
+            .unwrap_err();
+        vec![
+            Error::InvalidConfig {
+                message: "bad".to_string(),
+            },
+            Error::BootstrapMissing {
+                message: "none".to_string(),
+            },
+            Error::BootstrapDecryptFailed {
+                message: "wrong key".to_string(),
+            },
+            Error::Io(std::io::Error::from(std::io::ErrorKind::PermissionDenied)),
+            Error::Sqlite(sqlx::Error::RowNotFound),
+            Error::SqliteMigrate(sqlx::migrate::MigrateError::VersionMissing(9)),
+            Error::Walkdir(walkdir_err),
+            Error::Walk {
+                message: "walk".to_string(),
+            },
+            Error::Crypto {
+                message: "tag".to_string(),
+            },
+            Error::Cancelled,
+            Error::telegram("rpc error 420: FLOOD_WAIT caused by upload.saveFilePart (value: 31)"),
+            Error::ChatMigrated {
+                old_chat_id: "-1".to_string(),
+                new_chat_id: "-1001".to_string(),
+            },
+            Error::MissingIndexPart {
+                snapshot_id: "snp_1".to_string(),
+                part_no: 3,
+            },
+            Error::IndexChainBroken {
+                snapshot_id: "snp_2".to_string(),
+                missing_snapshot_id: "snp_1".to_string(),
+                message: "gone".to_string(),
+            },
+            Error::SnapshotPinned {
+                snapshot_id: "snp_1".to_string(),
+            },
+            Error::MissingChunkObject {
+                chunk_hash: "abc123".to_string(),
+            },
+            Error::Integrity {
+                message: "hash".to_string(),
+            },
+            Error::ManifestMismatch {
+                snapshot_id: "snp_1".to_string(),
+                object_id: "tgfile:1".to_string(),
+                expected_sha256: "aa".to_string(),
+                actual_sha256: "bb".to_string(),
+            },
+            Error::UploadCorrupted {
+                object_kind: "chunk".to_string(),
+                object_id: "mem:1".to_string(),
+                message: "size 9 != 10".to_string(),
+            },
+            Error::NonUtf8Path {
+                path: PathBuf::from("/tmp/x"),
+            },
+            Error::CaseCollision {
+                paths: vec!["README.md".to_string(), "readme.md".to_string()],
+            },
+            Error::VersionSkew {
+                component: "daemon".to_string(),
+                local_version: "0.2.0".to_string(),
+                remote_version: Some("0.1.0".to_string()),
+                message: "skew".to_string(),
+            },
+            Error::PathTooLong {
+                paths: vec!["deep/name".to_string()],
+                name_max: 255,
+                path_max: 1024,
+            },
+            Error::DataDirInUse {
+                path: PathBuf::from("/tmp/data"),
+                reason: "daemon running".to_string(),
+            },
+            Error::SourceUnreadable {
+                entries_unreadable: 96,
+                percent_unreadable: 96,
+                full_disk_access_missing: true,
+                message: "unreadable".to_string(),
+            },
+            Error::SourceSuspicious {
+                source_path: PathBuf::from("/Volumes/Backup"),
+                reason: crate::source_guard::SourceCheck::VolumeChanged,
+                message: "suspicious".to_string(),
+            },
+        ]
+    }
+
+    /// Exhaustive on purpose: a new variant does not compile until its details are decided.
+    fn expected_detail_keys(e: &Error) -> &'static [&'static str] {
+        match e {
+            Error::InvalidConfig { .. }
+            | Error::BootstrapMissing { .. }
+            | Error::BootstrapDecryptFailed { .. }
+            | Error::Walk { .. }
+            | Error::Crypto { .. }
+            | Error::Integrity { .. }
+            | Error::Cancelled => &[],
+            // `RowNotFound` carries no database error code.
+            Error::Sqlite(_) => &[],
+            Error::Io(_) => &["ioKind"],
+            Error::SqliteMigrate(_) => &["migrationVersion"],
+            Error::Walkdir(_) => &["path"],
+            Error::Telegram { .. } => &["kind", "waitSeconds"],
+            Error::ChatMigrated { .. } => &["oldChatId", "newChatId"],
+            Error::MissingIndexPart { .. } => &["snapshotId", "partNo"],
+            Error::IndexChainBroken { .. } => &["snapshotId", "missingSnapshotId"],
+            Error::SnapshotPinned { .. } => &["snapshotId"],
+            Error::MissingChunkObject { .. } => &["chunkHash"],
+            Error::ManifestMismatch { .. } => {
+                &["snapshotId", "objectId", "expectedSha256", "actualSha256"]
+            }
+            Error::UploadCorrupted { .. } => &["objectKind", "objectId"],
+            Error::NonUtf8Path { .. } => &["path"],
+            Error::CaseCollision { .. } => &["paths"],
+            Error::VersionSkew { .. } => &["component", "localVersion", "remoteVersion"],
+            Error::PathTooLong { .. } => &["paths", "nameMax", "pathMax"],
+            Error::DataDirInUse { .. } => &["path", "reason"],
+            Error::SourceUnreadable { .. } => &[
+                "entriesUnreadable",
+                "percentUnreadable",
+                "fullDiskAccessMissing",
+            ],
+            Error::SourceSuspicious { .. } => &["sourcePath", "reason"],
+        }
+    }
+
+    #[test]
+    fn every_variant_reports_its_structured_details() {
+        let errors = every_variant();
+        let variants = errors.iter().map(discriminant).collect::<HashSet<_>>();
+        assert_eq!(variants.len(), errors.len(), "one sample per variant");
+        assert_eq!(variants.len(), 26, "add new variants to every_variant()");
+
+        for e in &errors {
+            let details = e.details();
+            let keys = details
+                .as_object()
+                .unwrap()
+                .keys()
+                .map(String::as_str)
+                .collect::<Vec<_>>();
+            let mut expected = expected_detail_keys(e).to_vec();
+            expected.sort_unstable();
+            let mut actual = keys.clone();
+            actual.sort_unstable();
+            assert_eq!(actual, expected, "{}: {details}", e.code());
+            assert!(details.as_object().unwrap().values().all(|v| !v.is_null()));
+        }
+
+        let flood = &errors[10];
+        assert_eq!(flood.details()["kind"], "flood_wait");
+        assert_eq!(flood.details()["waitSeconds"], 31);
+        let chunk = &errors[15];
+        assert_eq!(chunk.details()["chunkHash"], "abc123");
+    }
+
+    #[test]
+    fn telegram_messages_are_classified_once() {
+        let kind = |m: &str| match Error::telegram(m) {
+            Error::Telegram {
+                kind, wait_seconds, ..
+            } => (kind, wait_seconds),
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            kind("rpc error: FLOOD_WAIT_12"),
+            (Some(TelegramErrorKind::FloodWait), Some(12))
+        );
+        assert_eq!(
+            kind("FLOOD_PREMIUM_WAIT_7"),
+            (Some(TelegramErrorKind::FloodWait), Some(7))
+        );
+        assert_eq!(
+            kind("rpc error 401: AUTH_KEY_UNREGISTERED"),
+            (Some(TelegramErrorKind::Unauthorized), None)
+        );
+        assert_eq!(
+            kind("send failed: CHAT_WRITE_FORBIDDEN"),
+            (Some(TelegramErrorKind::Forbidden), None)
+        );
+        assert_eq!(
+            kind("resolve chat failed: chat not found: -123"),
+            (Some(TelegramErrorKind::NotFound), None)
+        );
+        assert_eq!(
+            kind("save_file_part timed out after 60s"),
+            (Some(TelegramErrorKind::Timeout), None)
+        );
+        assert_eq!(kind("mtproto upload missing objectId"), (None, None));
+    }
 
     #[test]
     fn transient_telegram_message_matches_expected_tokens() {
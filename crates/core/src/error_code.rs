@@ -0,0 +1,300 @@
+//! Registry of every error code the CLI and daemon report.
+//!
+//! Codes are the contract with the GUI, which renders localized messages from `code` plus
+//! `details`; the English `message` next to them is for logs and terminals. Each code lists the
+//! `details` keys it always carries (its params) and an English template using them as `{param}`.
+//! `televybackup --error-catalog` prints the registry.
+
+use serde::{Serialize, Serializer};
+
+macro_rules! error_codes {
+    ($($variant:ident = $code:literal, [$($param:literal),*], $template:literal;)*) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum ErrorCode {
+            $($variant,)*
+        }
+
+        impl ErrorCode {
+            pub const ALL: &'static [ErrorCode] = &[$(ErrorCode::$variant,)*];
+
+            pub const fn as_str(self) -> &'static str {
+                match self {
+                    $(ErrorCode::$variant => $code,)*
+                }
+            }
+
+            /// `details` keys always present with this code.
+            pub const fn params(self) -> &'static [&'static str] {
+                match self {
+                    $(ErrorCode::$variant => &[$($param),*],)*
+                }
+            }
+
+            /// Default English message; `{param}` stands for `details.param`.
+            pub const fn template(self) -> &'static str {
+                match self {
+                    $(ErrorCode::$variant => $template,)*
+                }
+            }
+        }
+    };
+}
+
+error_codes! {
+    AuditChainBroken = "audit.chain_broken", ["entriesOk"],
+        "The audit log is broken after {entriesOk} intact entries.";
+    AuditReadFailed = "audit.read_failed", [],
+        "The audit log could not be read.";
+    BackupConfirmationRequired = "backup.confirmation_required",
+        ["bytesTotalEstimated", "warnInitialBackupBytes"],
+        "The first backup is about {bytesTotalEstimated} bytes, above the {warnInitialBackupBytes}-byte warning threshold; confirm to start it.";
+    BackupSourceSuspicious = "backup.source_suspicious", ["sourcePath", "reason"],
+        "{sourcePath} doesn't look like the source backed up before ({reason}); check that its drive is mounted, or re-run with --accept-source-change.";
+    BootstrapConfirmationRequired = "bootstrap.confirmation_required", [],
+        "Replacing the pinned bootstrap catalog needs confirmation.";
+    BootstrapDecryptFailed = "bootstrap.decrypt_failed", [],
+        "The pinned bootstrap catalog cannot be decrypted; import the correct master key.";
+    BootstrapDisabled = "bootstrap.disabled", [],
+        "The bootstrap catalog is disabled for this endpoint.";
+    BootstrapLatestMissing = "bootstrap.latest_missing", [],
+        "The bootstrap catalog has no latest snapshot for this target.";
+    BootstrapMissing = "bootstrap.missing", [],
+        "No pinned bootstrap catalog was found.";
+    BootstrapTargetMissing = "bootstrap.target_missing", [],
+        "The bootstrap catalog does not list this target.";
+    BootstrapUnsupportedChat = "bootstrap.unsupported_chat", [],
+        "The bootstrap catalog needs a group or channel; private chats cannot pin messages.";
+    ChunkMissing = "chunk.missing", ["chunkHash"],
+        "Chunk {chunkHash} has no stored object.";
+    CliInvalid = "cli.invalid", [],
+        "Invalid command-line arguments.";
+    CliIo = "cli.io", [],
+        "Reading from or writing to the terminal failed.";
+    ConfigInvalid = "config.invalid", [],
+        "The configuration is invalid.";
+    ConfigReadFailed = "config.read_failed", [],
+        "The configuration could not be read.";
+    ConfigTooLarge = "config.too_large", ["maxBytes"],
+        "The input is larger than the {maxBytes}-byte limit.";
+    ConfigWriteFailed = "config.write_failed", [],
+        "The configuration could not be written.";
+    ConfigBundleConfirmRequired = "config_bundle.confirm_required", [],
+        "Importing the config bundle needs confirmation.";
+    ConfigBundleConflict = "config_bundle.conflict", [],
+        "The config bundle conflicts with the current configuration.";
+    ConfigBundlePassphraseRequired = "config_bundle.passphrase_required", [],
+        "The config bundle needs its passphrase.";
+    ConfigBundleRotationRequired = "config_bundle.rotation_required", [],
+        "The bundle's master key differs from this machine's; rotate the master key first.";
+    ControlFailed = "control.failed", [],
+        "The daemon request failed.";
+    ControlInvalidRequest = "control.invalid_request", [],
+        "The daemon rejected the request as invalid.";
+    ControlMethodNotFound = "control.method_not_found", [],
+        "The daemon does not support this request.";
+    ControlNotFound = "control.not_found", [],
+        "The daemon could not find the requested item.";
+    ControlTimeout = "control.timeout", [],
+        "The daemon did not answer in time.";
+    ControlUnavailable = "control.unavailable", [],
+        "The daemon's control socket is unavailable.";
+    Crypto = "crypto", [],
+        "Encryption or decryption failed.";
+    DaemonFailed = "daemon.failed", [],
+        "The daemon reported a failure.";
+    DaemonUnavailable = "daemon.unavailable", [],
+        "The daemon is not running or not reachable.";
+    DataDirInUse = "data_dir.in_use", ["path", "reason"],
+        "The data dir {path} is in use ({reason}); stop the daemon and wait for running tasks to finish.";
+    DbFailed = "db.failed", [],
+        "The local index database failed.";
+    GcRemoteDedupeMissing = "gc.remote_dedupe_missing", [],
+        "Garbage collection needs the remote dedupe index; run a backup first.";
+    IndexChainBroken = "index.chain_broken", ["snapshotId", "missingSnapshotId"],
+        "The index of snapshot {snapshotId} cannot be rebuilt: the index of {missingSnapshotId} is missing.";
+    IndexDedupeCatalogMissing = "index.dedupe_catalog_missing", [],
+        "The remote dedupe catalog is missing.";
+    IndexEndpointManifestMissing = "index.endpoint_manifest_missing", [],
+        "The endpoint index manifest is missing.";
+    IndexPartMissing = "index.part_missing", ["snapshotId", "partNo"],
+        "Part {partNo} of the index of snapshot {snapshotId} is missing.";
+    IndexRefCountDrift = "index.ref_count_drift", ["chunksDrifted"],
+        "{chunksDrifted} chunk reference counts do not match the snapshots' file maps; run `index check --repair`.";
+    Integrity = "integrity", [],
+        "An integrity check failed.";
+    IntegrityBaseSnapshotMissingRemoteIndex = "integrity.base_snapshot_missing_remote_index", [],
+        "The base snapshot has no remote index.";
+    IntegrityManifestMismatch = "integrity.manifest_mismatch",
+        ["snapshotId", "objectId", "expectedSha256", "actualSha256"],
+        "Index manifest {objectId} of snapshot {snapshotId} hashes to {actualSha256}, expected {expectedSha256}.";
+    Io = "io", [],
+        "A file system operation failed.";
+    LogInitFailed = "log.init_failed", [],
+        "Logging could not be set up.";
+    LogNotFound = "log.not_found", [],
+        "The run log was not found.";
+    LogReadFailed = "log.read_failed", [],
+        "The run log could not be read.";
+    PathNonUtf8 = "path.non_utf8", ["path"],
+        "The path {path} is not valid UTF-8.";
+    PrivacyLeak = "privacy.leak", [],
+        "A file name appears outside an encrypted payload.";
+    RemoteRateLimited = "remote.rate_limited", ["retryAfterMs"],
+        "Too many rejected remote requests; retry in {retryAfterMs} ms.";
+    RemoteUnauthorized = "remote.unauthorized", [],
+        "The remote monitoring token was rejected.";
+    RemoteUnavailable = "remote.unavailable", [],
+        "The remote daemon is not reachable.";
+    RestoreCaseCollision = "restore.case_collision", ["paths"],
+        "{paths} differ only in letter case and cannot all be restored onto this case-insensitive volume.";
+    RestorePartial = "restore.partial", ["filesRestored", "filesFailed"],
+        "{filesRestored} files were restored; {filesFailed} could not be.";
+    RestorePathTooLong = "restore.path_too_long", ["paths", "nameMax", "pathMax"],
+        "These paths exceed the restore target's limits ({nameMax}-byte names, {pathMax}-byte paths): {paths}.";
+    ScanUnreadable = "scan.unreadable", ["entriesUnreadable", "percentUnreadable"],
+        "{percentUnreadable}% of the source ({entriesUnreadable} entries) could not be read, more than scan.max_unreadable_percent allows; if macOS blocked access, grant Full Disk Access in System Settings > Privacy & Security.";
+    SecretsConfirmationRequired = "secrets.confirmation_required", [],
+        "Deleting secrets needs confirmation.";
+    SecretsInsecureFile = "secrets.insecure_file", [],
+        "A secrets file is readable by other users.";
+    SecretsMigrateConflict = "secrets.migrate_conflict", [],
+        "The master key differs between the secrets store and the Keychain.";
+    SecretsReadOnlyProvider = "secrets.read_only_provider", [],
+        "This secret comes from an environment variable and cannot be changed here.";
+    SecretsStoreFailed = "secrets.store_failed", [],
+        "The secrets store failed.";
+    SecretsVaultKeyFileIoFailed = "secrets.vault_key_file_io_failed", ["path"],
+        "The vault key file {path} could not be read or written.";
+    SecretsVaultUnavailable = "secrets.vault_unavailable", [],
+        "The vault key is unavailable.";
+    SecurityPassphraseInvalid = "security.passphrase_invalid", [],
+        "The restore passphrase is wrong.";
+    SecurityPassphraseNotSet = "security.passphrase_not_set", [],
+        "A restore passphrase is required but none is set.";
+    SecurityPassphraseRequired = "security.passphrase_required", [],
+        "This restore requires the restore passphrase.";
+    SnapshotNotFound = "snapshot.not_found", ["snapshotId"],
+        "Snapshot {snapshotId} was not found.";
+    SnapshotPinned = "snapshot.pinned", ["snapshotId"],
+        "Snapshot {snapshotId} is pinned.";
+    SnapshotUnsupportedProvider = "snapshot.unsupported_provider", [],
+        "The snapshot is stored with an unsupported provider.";
+    Sqlite = "sqlite", [],
+        "The index database failed.";
+    SqliteMigrate = "sqlite.migrate", [],
+        "The index database could not be migrated.";
+    StatusInvalid = "status.invalid", [],
+        "The status data is invalid.";
+    StatusUnavailable = "status.unavailable", [],
+        "Status is unavailable.";
+    TargetDisabled = "target.disabled", [],
+        "The target is paused.";
+    TaskCancelled = "task.cancelled", [],
+        "The task was cancelled.";
+    TelegramChatMigrated = "telegram.chat_migrated", ["oldChatId", "newChatId"],
+        "Telegram group {oldChatId} was upgraded to supergroup {newChatId}; update the chat ID.";
+    TelegramChatNotFound = "telegram.chat_not_found", [],
+        "The Telegram chat was not found.";
+    TelegramDialogsUnsupported = "telegram.dialogs_unsupported", [],
+        "Bots cannot list chats; send a message in the chat to discover its ID.";
+    TelegramMtprotoMissingApiHash = "telegram.mtproto.missing_api_hash", [],
+        "The Telegram API hash is not set.";
+    TelegramMtprotoSessionInvalid = "telegram.mtproto.session_invalid", [],
+        "The saved Telegram session is invalid.";
+    TelegramRoundtripFailed = "telegram.roundtrip_failed", [],
+        "The Telegram upload and download test failed.";
+    TelegramTimeout = "telegram.timeout", [],
+        "Telegram did not answer in time.";
+    TelegramUnauthorized = "telegram.unauthorized", [],
+        "Telegram rejected the credentials.";
+    TelegramUnavailable = "telegram.unavailable", [],
+        "Telegram is unavailable.";
+    TelegramUploadCorrupted = "telegram.upload_corrupted", ["objectKind"],
+        "An uploaded {objectKind} did not match what was sent, also after uploading it again.";
+    Unknown = "unknown", [],
+        "An unexpected error occurred.";
+    VersionSkew = "version.skew", ["component"],
+        "The {component} runs a different version than the rest of TelevyBackup; restart or reinstall it.";
+    Walkdir = "walkdir", [],
+        "The source folder could not be scanned.";
+}
+
+impl ErrorCode {
+    pub fn from_code(code: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|c| c.as_str() == code)
+    }
+
+    /// `{"codes": [{"code", "params", "template"}, ...]}` for every registered code.
+    pub fn catalog() -> serde_json::Value {
+        let codes = Self::ALL
+            .iter()
+            .map(|c| {
+                serde_json::json!({
+                    "code": c.as_str(),
+                    "params": c.params(),
+                    "template": c.template(),
+                })
+            })
+            .collect::<Vec<_>>();
+        serde_json::json!({ "codes": codes })
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for ErrorCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::ErrorCode;
+
+    #[test]
+    fn codes_are_unique_and_round_trip() {
+        let codes = ErrorCode::ALL
+            .iter()
+            .map(|c| c.as_str())
+            .collect::<BTreeSet<_>>();
+        assert_eq!(codes.len(), ErrorCode::ALL.len());
+        for c in ErrorCode::ALL {
+            assert_eq!(ErrorCode::from_code(c.as_str()), Some(*c));
+        }
+        assert_eq!(ErrorCode::from_code("nope.never"), None);
+    }
+
+    #[test]
+    fn templates_use_exactly_their_params() {
+        for c in ErrorCode::ALL {
+            let mut placeholders = BTreeSet::new();
+            let mut rest = c.template();
+            while let Some(start) = rest.find('{') {
+                let end = rest[start..].find('}').expect("unclosed placeholder") + start;
+                placeholders.insert(&rest[start + 1..end]);
+                rest = &rest[end + 1..];
+            }
+            let params = c.params().iter().copied().collect::<BTreeSet<_>>();
+            assert_eq!(placeholders, params, "{c}");
+        }
+    }
+
+    #[test]
+    fn core_errors_map_to_registered_codes() {
+        let e = crate::Error::SnapshotPinned {
+            snapshot_id: "snp_1".to_string(),
+        };
+        assert_eq!(e.error_code(), ErrorCode::SnapshotPinned);
+        let details = e.details();
+        for param in e.error_code().params() {
+            assert!(details.get(param).is_some(), "{param}");
+        }
+    }
+}
@@ -0,0 +1,436 @@
+//! `targets[].filters`: per-file decisions made while scanning a source.
+//!
+//! Filters run in configured order on every regular file that survived `.televyignore`; the
+//! first one that excludes a file stops the chain. `command` filters are bounded by a per-file
+//! timeout and a per-run budget, and fail open: a file is kept when the command times out or the
+//! budget is spent.
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tokio::time::sleep;
+use tracing::{debug, warn};
+
+use crate::config::TargetFilter;
+use crate::{Error, Result};
+
+pub const FILTER_KINDS: &[&str] = &["max_file_bytes", "mime_exclude", "command"];
+
+/// Bytes read from the start of a file for [`sniff_mime`].
+const SNIFF_HEAD_BYTES: usize = 512;
+/// Offset of the ISO 9660 primary volume descriptor's `CD001` magic.
+const ISO9660_MAGIC_OFFSET: u64 = 32769;
+
+/// Exclusions made by one `targets[].filters` entry during a run.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileFilterStats {
+    pub kind: String,
+    pub files_excluded: u64,
+    /// `command`: files kept because the command ran past `timeout_ms`.
+    #[serde(default)]
+    pub timeouts: u64,
+    /// `command`: `budget_secs` ran out and later files were kept without running it.
+    #[serde(default)]
+    pub budget_exhausted: bool,
+}
+
+/// `Err` carries a message naming the offending key.
+pub fn validate_filter(filter: &TargetFilter) -> std::result::Result<(), String> {
+    match filter.kind.as_str() {
+        "max_file_bytes" => match filter.max_bytes {
+            Some(n) if n > 0 => Ok(()),
+            _ => Err("max_file_bytes needs max_bytes >= 1".to_string()),
+        },
+        "mime_exclude" => {
+            if filter.mime_types.is_empty() {
+                return Err("mime_exclude needs at least one mime_types entry".to_string());
+            }
+            for pattern in &filter.mime_types {
+                let valid = pattern.split_once('/').is_some_and(|(ty, sub)| {
+                    !ty.is_empty() && !sub.is_empty() && !ty.contains('*')
+                });
+                if !valid {
+                    return Err(format!(
+                        "mime_types entries must look like \"type/subtype\" or \"type/*\" (got {pattern:?})"
+                    ));
+                }
+            }
+            Ok(())
+        }
+        "command" => {
+            if filter.command.first().is_none_or(|p| p.trim().is_empty()) {
+                return Err("command needs a program".to_string());
+            }
+            if filter.timeout_ms == 0 {
+                return Err("command needs timeout_ms >= 1".to_string());
+            }
+            if filter.budget_secs == 0 {
+                return Err("command needs budget_secs >= 1".to_string());
+            }
+            Ok(())
+        }
+        other => Err(format!(
+            "unknown kind {other:?} (expected one of {})",
+            FILTER_KINDS.join(", ")
+        )),
+    }
+}
+
+enum Filter {
+    MaxFileBytes(u64),
+    MimeExclude(Vec<String>),
+    Command {
+        argv: Vec<String>,
+        timeout: Duration,
+        budget_left: Duration,
+    },
+}
+
+/// A target's filters, with the counts of one run.
+pub struct FileFilterChain {
+    filters: Vec<(Filter, FileFilterStats)>,
+}
+
+impl FileFilterChain {
+    pub fn new(filters: &[TargetFilter]) -> Result<Self> {
+        let filters = filters
+            .iter()
+            .map(|f| {
+                validate_filter(f).map_err(|message| Error::InvalidConfig {
+                    message: format!("targets[].filters: {message}"),
+                })?;
+                let filter = match f.kind.as_str() {
+                    "max_file_bytes" => Filter::MaxFileBytes(f.max_bytes.unwrap_or(u64::MAX)),
+                    "mime_exclude" => Filter::MimeExclude(
+                        f.mime_types
+                            .iter()
+                            .map(|p| p.to_ascii_lowercase())
+                            .collect(),
+                    ),
+                    _ => Filter::Command {
+                        argv: f.command.clone(),
+                        timeout: Duration::from_millis(f.timeout_ms),
+                        budget_left: Duration::from_secs(f.budget_secs),
+                    },
+                };
+                let stats = FileFilterStats {
+                    kind: f.kind.clone(),
+                    ..FileFilterStats::default()
+                };
+                Ok((filter, stats))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { filters })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    /// Whether a filter leaves out the regular file at `path`; `size` comes from the walk.
+    pub async fn excludes(&mut self, path: &Path, size: u64) -> Result<bool> {
+        for (filter, stats) in &mut self.filters {
+            let excluded = match filter {
+                Filter::MaxFileBytes(max_bytes) => size > *max_bytes,
+                Filter::MimeExclude(patterns) => sniff_file_mime(path, size)
+                    .is_some_and(|mime| patterns.iter().any(|p| mime_matches(p, mime))),
+                Filter::Command {
+                    argv,
+                    timeout,
+                    budget_left,
+                } => {
+                    if budget_left.is_zero() {
+                        false
+                    } else {
+                        let started = Instant::now();
+                        let verdict =
+                            run_filter_command(argv, path, (*timeout).min(*budget_left)).await?;
+                        *budget_left = budget_left.saturating_sub(started.elapsed());
+                        if budget_left.is_zero() && !stats.budget_exhausted {
+                            stats.budget_exhausted = true;
+                            warn!(
+                                event = "scan.filter_budget_exhausted",
+                                command = %argv[0],
+                                "scan.filter_budget_exhausted"
+                            );
+                        }
+                        match verdict {
+                            Some(keep) => !keep,
+                            None => {
+                                stats.timeouts += 1;
+                                debug!(
+                                    event = "scan.filter_timeout",
+                                    command = %argv[0],
+                                    path = %path.display(),
+                                    "scan.filter_timeout"
+                                );
+                                false
+                            }
+                        }
+                    }
+                }
+            };
+            if excluded {
+                stats.files_excluded += 1;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    pub fn into_stats(self) -> Vec<FileFilterStats> {
+        self.filters.into_iter().map(|(_, stats)| stats).collect()
+    }
+}
+
+/// `Some(true)` on exit status 0, `Some(false)` on any other, `None` when killed at `timeout`.
+async fn run_filter_command(
+    argv: &[String],
+    path: &Path,
+    timeout: Duration,
+) -> Result<Option<bool>> {
+    let mut child = Command::new(&argv[0])
+        .args(&argv[1..])
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| Error::InvalidConfig {
+            message: format!(
+                "targets[].filters: failed to run command {:?}: {e}",
+                argv[0]
+            ),
+        })?;
+
+    let deadline = Instant::now() + timeout;
+    let mut poll = Duration::from_millis(1);
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status.success()));
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(None);
+        }
+        sleep(poll.min(deadline - now)).await;
+        poll = (poll * 2).min(Duration::from_millis(25));
+    }
+}
+
+/// `video/*` matches every `video/` type; anything else must match exactly.
+fn mime_matches(pattern: &str, mime: &str) -> bool {
+    match pattern.strip_suffix("/*") {
+        Some(ty) => mime
+            .strip_prefix(ty)
+            .is_some_and(|rest| rest.starts_with('/')),
+        None => pattern == mime,
+    }
+}
+
+/// `None` when the file can't be read (the scan reports that itself) or its type isn't known.
+fn sniff_file_mime(path: &Path, size: u64) -> Option<&'static str> {
+    let mut file = File::open(path).ok()?;
+    let mut head = Vec::with_capacity(SNIFF_HEAD_BYTES);
+    (&mut file)
+        .take(SNIFF_HEAD_BYTES as u64)
+        .read_to_end(&mut head)
+        .ok()?;
+    if let Some(mime) = sniff_mime(&head) {
+        return Some(mime);
+    }
+    if size >= ISO9660_MAGIC_OFFSET + 5 {
+        let mut magic = [0u8; 5];
+        file.seek(SeekFrom::Start(ISO9660_MAGIC_OFFSET)).ok()?;
+        file.read_exact(&mut magic).ok()?;
+        if &magic == b"CD001" {
+            return Some("application/x-iso9660-image");
+        }
+    }
+    None
+}
+
+/// Content type from a file's leading bytes, by magic number.
+pub fn sniff_mime(head: &[u8]) -> Option<&'static str> {
+    let at = |offset: usize, magic: &[u8]| head.get(offset..offset + magic.len()) == Some(magic);
+
+    if at(4, b"ftyp") {
+        return Some(match head.get(8..12) {
+            Some(b"qt  ") => "video/quicktime",
+            Some(b"heic" | b"heix" | b"mif1" | b"msf1") => "image/heic",
+            Some(b"M4A " | b"M4B ") => "audio/mp4",
+            _ => "video/mp4",
+        });
+    }
+    if at(0, b"RIFF") {
+        return match head.get(8..12) {
+            Some(b"WEBP") => Some("image/webp"),
+            Some(b"WAVE") => Some("audio/wav"),
+            Some(b"AVI ") => Some("video/x-msvideo"),
+            _ => None,
+        };
+    }
+
+    const MAGIC: &[(usize, &[u8], &str)] = &[
+        (0, b"\x89PNG\r\n\x1a\n", "image/png"),
+        (0, b"\xff\xd8\xff", "image/jpeg"),
+        (0, b"GIF87a", "image/gif"),
+        (0, b"GIF89a", "image/gif"),
+        (0, b"%PDF-", "application/pdf"),
+        (0, b"PK\x03\x04", "application/zip"),
+        (0, b"PK\x05\x06", "application/zip"),
+        (0, b"\x1f\x8b", "application/gzip"),
+        (0, b"BZh", "application/x-bzip2"),
+        (0, b"\xfd7zXZ\x00", "application/x-xz"),
+        (0, b"\x28\xb5\x2f\xfd", "application/zstd"),
+        (0, b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+        (0, b"Rar!\x1a\x07", "application/vnd.rar"),
+        (257, b"ustar", "application/x-tar"),
+        (0, b"SQLite format 3\x00", "application/vnd.sqlite3"),
+        (0, b"\x7fELF", "application/x-executable"),
+        (0, b"\xcf\xfa\xed\xfe", "application/x-mach-binary"),
+        (0, b"\xce\xfa\xed\xfe", "application/x-mach-binary"),
+        (0, b"\xfe\xed\xfa\xcf", "application/x-mach-binary"),
+        (0, b"\xfe\xed\xfa\xce", "application/x-mach-binary"),
+        (0, b"MZ", "application/vnd.microsoft.portable-executable"),
+        (0, b"\x1a\x45\xdf\xa3", "video/x-matroska"),
+        (0, b"ID3", "audio/mpeg"),
+        (0, b"\xff\xfb", "audio/mpeg"),
+        (0, b"\xff\xf3", "audio/mpeg"),
+        (0, b"fLaC", "audio/flac"),
+        (0, b"OggS", "audio/ogg"),
+    ];
+    MAGIC
+        .iter()
+        .find(|(offset, magic, _)| at(*offset, magic))
+        .map(|(_, _, mime)| *mime)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(kind: &str) -> TargetFilter {
+        TargetFilter {
+            kind: kind.to_string(),
+            max_bytes: None,
+            mime_types: Vec::new(),
+            command: Vec::new(),
+            timeout_ms: 5_000,
+            budget_secs: 600,
+        }
+    }
+
+    #[test]
+    fn sniffs_common_magic_numbers() {
+        assert_eq!(sniff_mime(b"\x89PNG\r\n\x1a\n...."), Some("image/png"));
+        assert_eq!(
+            sniff_mime(b"\x00\x00\x00\x18ftypisom\x00\x00"),
+            Some("video/mp4")
+        );
+        assert_eq!(
+            sniff_mime(b"\x00\x00\x00\x14ftypqt  \x00\x00"),
+            Some("video/quicktime")
+        );
+        assert_eq!(
+            sniff_mime(b"RIFF\x00\x00\x00\x00WAVEfmt "),
+            Some("audio/wav")
+        );
+        assert_eq!(sniff_mime(b"hello world"), None);
+        assert_eq!(sniff_mime(b""), None);
+    }
+
+    #[test]
+    fn mime_patterns_match_exact_types_and_families() {
+        assert!(mime_matches("video/*", "video/mp4"));
+        assert!(!mime_matches("video/*", "videos/mp4"));
+        assert!(mime_matches("application/zip", "application/zip"));
+        assert!(!mime_matches("application/zip", "application/gzip"));
+    }
+
+    #[test]
+    fn validation_names_the_missing_key() {
+        assert!(validate_filter(&filter("max_file_bytes")).is_err());
+        assert!(validate_filter(&filter("command")).is_err());
+        let mut mime = filter("mime_exclude");
+        mime.mime_types = vec!["video".to_string()];
+        assert!(validate_filter(&mime).unwrap_err().contains("mime_types"));
+        mime.mime_types = vec!["video/*".to_string()];
+        assert!(validate_filter(&mime).is_ok());
+        assert!(
+            validate_filter(&filter("clamav"))
+                .unwrap_err()
+                .contains("unknown kind")
+        );
+    }
+
+    #[tokio::test]
+    async fn chain_short_circuits_and_counts_per_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        let big = dir.path().join("big.bin");
+        std::fs::write(&big, vec![0u8; 64]).unwrap();
+        let png = dir.path().join("pic.dat");
+        std::fs::write(&png, b"\x89PNG\r\n\x1a\nrest").unwrap();
+        let text = dir.path().join("notes.txt");
+        std::fs::write(&text, b"hello").unwrap();
+
+        let mut max = filter("max_file_bytes");
+        max.max_bytes = Some(32);
+        let mut mime = filter("mime_exclude");
+        mime.mime_types = vec!["image/*".to_string()];
+        let mut chain = FileFilterChain::new(&[max, mime]).unwrap();
+
+        assert!(chain.excludes(&big, 64).await.unwrap());
+        assert!(chain.excludes(&png, 12).await.unwrap());
+        assert!(!chain.excludes(&text, 5).await.unwrap());
+
+        let stats = chain.into_stats();
+        assert_eq!(stats[0].kind, "max_file_bytes");
+        assert_eq!(stats[0].files_excluded, 1);
+        assert_eq!(stats[1].kind, "mime_exclude");
+        assert_eq!(stats[1].files_excluded, 1);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn command_exit_status_decides_and_timeouts_keep_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let keep = dir.path().join("keep.txt");
+        let drop = dir.path().join("drop.txt");
+        std::fs::write(&keep, b"a").unwrap();
+        std::fs::write(&drop, b"b").unwrap();
+
+        let mut cmd = filter("command");
+        cmd.command = vec![
+            "/bin/sh".to_string(),
+            "-c".to_string(),
+            r#"case "$0" in *drop.txt) exit 1 ;; esac"#.to_string(),
+        ];
+        let mut chain = FileFilterChain::new(&[cmd]).unwrap();
+        assert!(!chain.excludes(&keep, 1).await.unwrap());
+        assert!(chain.excludes(&drop, 1).await.unwrap());
+        assert_eq!(chain.into_stats()[0].files_excluded, 1);
+
+        let mut slow = filter("command");
+        slow.command = vec![
+            "/bin/sh".to_string(),
+            "-c".to_string(),
+            "sleep 5".to_string(),
+        ];
+        slow.timeout_ms = 50;
+        slow.budget_secs = 1;
+        let mut chain = FileFilterChain::new(&[slow]).unwrap();
+        let started = Instant::now();
+        assert!(!chain.excludes(&drop, 1).await.unwrap());
+        assert!(started.elapsed() < Duration::from_secs(2));
+        let stats = chain.into_stats();
+        assert_eq!(stats[0].timeouts, 1);
+        assert_eq!(stats[0].files_excluded, 0);
+    }
+}
@@ -0,0 +1,14 @@
+//! Durability helpers for the writers that replace files by renaming a temp file over them.
+
+use std::path::Path;
+
+/// Makes renames in `dir` durable. Best effort; only unix needs (and allows) it.
+#[cfg(unix)]
+pub(crate) fn sync_dir(dir: &Path) {
+    if let Ok(dir) = std::fs::File::open(dir) {
+        let _ = dir.sync_all();
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn sync_dir(_dir: &Path) {}
@@ -0,0 +1,98 @@
+//! macOS privacy protection (TCC) of user data. Without Full Disk Access, reads under these
+//! folders fail with `EPERM` even for their owner, so a backup of a home folder quietly loses
+//! them; a scan that hits it reports [`WARNING`].
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// `BackupResult::warnings` / `lastRun.warnings` entry for permission errors under a protected
+/// folder.
+pub const WARNING: &str = "full_disk_access_missing";
+
+/// Where the user grants it.
+pub const SETTINGS_PANE: &str = "System Settings > Privacy & Security > Full Disk Access";
+
+/// Home-relative folders macOS guards behind Full Disk Access or a per-folder consent.
+const PROTECTED_HOME_DIRS: &[&str] = &[
+    "Desktop",
+    "Documents",
+    "Downloads",
+    "Movies",
+    "Music",
+    "Pictures",
+    "Library/Application Support/AddressBook",
+    "Library/Application Support/CallHistoryDB",
+    "Library/Calendars",
+    "Library/Containers/com.apple.mail",
+    "Library/Cookies",
+    "Library/HomeKit",
+    "Library/Mail",
+    "Library/Messages",
+    "Library/Metadata/CoreSpotlight",
+    "Library/Mobile Documents",
+    "Library/Reminders",
+    "Library/Safari",
+    "Library/Suggestions",
+];
+
+/// Whether `path` lies in (or is) a folder protected for the user whose home is `home`.
+pub fn is_protected(path: &Path, home: &Path) -> bool {
+    let Ok(rel) = path.strip_prefix(home) else {
+        return false;
+    };
+    PROTECTED_HOME_DIRS.iter().any(|dir| rel.starts_with(dir))
+}
+
+/// [`is_protected`] for the current user, on macOS only.
+pub fn is_protected_here(path: &Path) -> bool {
+    cfg!(target_os = "macos")
+        && std::env::var_os("HOME").is_some_and(|home| is_protected(path, Path::new(&home)))
+}
+
+/// The folder `doctor` tries to list: it exists on every Mac and only opens with Full Disk Access.
+pub fn canary(home: &Path) -> PathBuf {
+    home.join("Library").join("Mail")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Access {
+    Granted,
+    Missing,
+    /// The canary is absent or failed for another reason.
+    Unknown,
+}
+
+/// Lists the [`canary`] under `home` as the calling process. The answer holds for that process
+/// only: macOS grants Full Disk Access per app, so the daemon and a terminal can differ.
+pub fn probe(home: &Path) -> Access {
+    match std::fs::read_dir(canary(home)) {
+        Ok(_) => Access::Granted,
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => Access::Missing,
+        Err(_) => Access::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protected_folders_are_matched_by_component_under_home() {
+        let home = Path::new("/Users/alice");
+        assert!(is_protected(&home.join("Documents"), home));
+        assert!(is_protected(&home.join("Library/Mail/V10/x.emlx"), home));
+        assert!(!is_protected(&home.join("DocumentsOld/a"), home));
+        assert!(!is_protected(&home.join("Library/Caches"), home));
+        assert!(!is_protected(Path::new("/Users/bob/Documents"), home));
+    }
+
+    #[test]
+    fn probe_tells_denied_from_absent() {
+        let home = tempfile::tempdir().unwrap();
+        assert_eq!(probe(home.path()), Access::Unknown);
+        std::fs::create_dir_all(canary(home.path())).unwrap();
+        assert_eq!(probe(home.path()), Access::Granted);
+    }
+}
@@ -1,9 +1,13 @@
 use base64::Engine;
+use serde::{Deserialize, Serialize};
 
+use crate::crypto::{DataKey, derive_target_key};
 use crate::{Error, Result};
 
 pub const GOLD_KEY_PREFIX: &str = "TBK1:";
 pub const GOLD_KEY_FORMAT: &str = "tbk1";
+pub const TARGET_KEY_PREFIX: &str = "TBT1:";
+pub const TARGET_KEY_FORMAT: &str = "tbt1";
 
 pub fn encode_gold_key(master_key: &[u8; 32]) -> String {
     let b64 = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(master_key);
@@ -29,6 +33,80 @@ pub fn decode_gold_key(s: &str) -> Result<[u8; 32]> {
     Ok(arr)
 }
 
+/// Restore-only key of one target: decrypts the snapshots written under that target's derived
+/// key and nothing else.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetKey {
+    pub target_id: String,
+    /// Provider of the target's endpoint, e.g. `telegram.mtproto/ep1`.
+    pub provider: String,
+    pub key: [u8; 32],
+}
+
+impl TargetKey {
+    pub fn derive(master_key: &[u8; 32], target_id: &str, provider: &str) -> Self {
+        Self {
+            target_id: target_id.to_string(),
+            provider: provider.to_string(),
+            key: derive_target_key(master_key, target_id),
+        }
+    }
+
+    pub fn data_key(&self) -> DataKey {
+        DataKey::from_target_key(&self.target_id, self.key)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TargetKeyWire {
+    target_id: String,
+    provider: String,
+    key: String,
+}
+
+pub fn encode_target_key(target_key: &TargetKey) -> String {
+    let engine = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    let wire = TargetKeyWire {
+        target_id: target_key.target_id.clone(),
+        provider: target_key.provider.clone(),
+        key: engine.encode(target_key.key),
+    };
+    let json = serde_json::to_vec(&wire).expect("target key serializes");
+    format!("{TARGET_KEY_PREFIX}{}", engine.encode(json))
+}
+
+pub fn decode_target_key(s: &str) -> Result<TargetKey> {
+    let engine = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    let rest = s
+        .trim()
+        .strip_prefix(TARGET_KEY_PREFIX)
+        .ok_or_else(|| Error::InvalidConfig {
+            message: "invalid target key (missing TBT1: prefix)".to_string(),
+        })?;
+
+    let json = engine
+        .decode(rest.as_bytes())
+        .map_err(|e| Error::InvalidConfig {
+            message: format!("invalid target key (bad base64url): {e}"),
+        })?;
+    let wire: TargetKeyWire = serde_json::from_slice(&json).map_err(|e| Error::InvalidConfig {
+        message: format!("invalid target key: {e}"),
+    })?;
+    let key = engine
+        .decode(wire.key.as_bytes())
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .ok_or_else(|| Error::InvalidConfig {
+            message: "invalid target key (bad key bytes)".to_string(),
+        })?;
+    Ok(TargetKey {
+        target_id: wire.target_id,
+        provider: wire.provider,
+        key,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -41,4 +119,14 @@ mod tests {
         let parsed = decode_gold_key(&s).unwrap();
         assert_eq!(parsed, key);
     }
+
+    #[test]
+    fn tbt1_round_trip() {
+        let target_key = TargetKey::derive(&[7u8; 32], "t1", "telegram.mtproto/ep1");
+        let s = encode_target_key(&target_key);
+        assert!(s.starts_with(TARGET_KEY_PREFIX));
+        assert_eq!(decode_target_key(&s).unwrap(), target_key);
+        assert!(decode_target_key(&encode_gold_key(&[7u8; 32])).is_err());
+        assert_eq!(target_key.data_key(), DataKey::for_target(&[7u8; 32], "t1"));
+    }
 }
@@ -0,0 +1,94 @@
+//! Backup health: whether a target's last successful backup keeps up with its schedule.
+//!
+//! Shared by the daemon's status (`healthy` per target) and the daily digest, so both call the
+//! same targets stale.
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::config::{Schedule, SettingsV2, Target, effective_schedule};
+
+/// Slack on top of the schedule period before a target counts as stale: a backup takes a while,
+/// and the daemon starts scheduled runs up to a tick late.
+pub fn stale_grace() -> TimeDelta {
+    TimeDelta::hours(1)
+}
+
+/// Time between two scheduled runs; `None` when the schedule is off.
+pub fn schedule_period(schedule: &Schedule) -> Option<TimeDelta> {
+    if !schedule.enabled {
+        return None;
+    }
+    match schedule.kind.trim() {
+        "hourly" => Some(TimeDelta::hours(1)),
+        "daily" => Some(TimeDelta::days(1)),
+        _ => None,
+    }
+}
+
+/// The schedule period of `target` (its override applied), whether or not it is enabled.
+pub fn target_schedule_period(settings: &SettingsV2, target: &Target) -> Option<TimeDelta> {
+    schedule_period(&effective_schedule(
+        &settings.schedule,
+        target.schedule.as_ref(),
+    ))
+}
+
+/// Whether a target that should back up every `period` has no successful backup within
+/// `period` plus [`stale_grace`]. Targets without a period (unscheduled or disabled) back up on
+/// demand and are never stale; scheduled targets that never succeeded are.
+pub fn is_stale(
+    period: Option<TimeDelta>,
+    last_success: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> bool {
+    let Some(period) = period else {
+        return false;
+    };
+    last_success.is_none_or(|at| now - at > period + stale_grace())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TargetScheduleOverride;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().to_utc()
+    }
+
+    #[test]
+    fn stale_once_a_scheduled_target_misses_its_period_plus_grace() {
+        let mut settings = SettingsV2::default();
+        settings.schedule.enabled = true;
+        settings.schedule.kind = "daily".to_string();
+        let mut target: Target = toml::from_str(
+            r#"
+            id = "t1"
+            source_path = "/src"
+            endpoint_id = "ep1"
+            label = "scheduled"
+            "#,
+        )
+        .unwrap();
+
+        let period = target_schedule_period(&settings, &target);
+        assert_eq!(period, Some(TimeDelta::days(1)));
+        let now = at("2024-06-10T12:00:00Z");
+        assert!(!is_stale(period, Some(at("2024-06-09T11:30:00Z")), now));
+        assert!(is_stale(period, Some(at("2024-06-09T10:30:00Z")), now));
+        assert!(is_stale(period, None, now));
+
+        target.schedule = Some(TargetScheduleOverride {
+            kind: Some("hourly".to_string()),
+            ..Default::default()
+        });
+        let period = target_schedule_period(&settings, &target);
+        assert!(is_stale(period, Some(at("2024-06-10T09:30:00Z")), now));
+
+        settings.schedule.enabled = false;
+        target.schedule = None;
+        let period = target_schedule_period(&settings, &target);
+        assert_eq!(period, None);
+        assert!(!is_stale(period, None, now));
+    }
+}
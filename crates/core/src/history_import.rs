@@ -0,0 +1,183 @@
+//! `televybackup import restic-dump`: turn trees extracted from another backup tool into
+//! snapshots that keep their original timestamps.
+//!
+//! The dump directory holds one extracted tree per historical snapshot (`restic restore`,
+//! `borg extract`) plus a `manifest.toml`:
+//!
+//! ```toml
+//! [[snapshots]]
+//! created_at = "2021-03-04T05:06:07Z"
+//! dir = "2021-03-04"      # relative to the dump directory
+//! label = "4f2a91c0"      # optional; defaults to the directory name
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::{Error, Result};
+
+pub const IMPORT_MANIFEST_FILE: &str = "manifest.toml";
+
+/// Every imported snapshot's label starts with this, so it never passes for a real backup.
+pub const IMPORTED_LABEL_PREFIX: &str = "imported:";
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManifestFile {
+    #[serde(default)]
+    snapshots: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManifestEntry {
+    created_at: String,
+    dir: PathBuf,
+    #[serde(default)]
+    label: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportSnapshot {
+    /// UTC, in the index's `created_at` format (`2021-03-04T05:06:07.000Z`).
+    pub created_at: String,
+    pub dir: PathBuf,
+    /// Already carries [`IMPORTED_LABEL_PREFIX`].
+    pub label: String,
+}
+
+/// Parse `<input>/manifest.toml`; snapshots come back oldest first so successive imports
+/// dedupe against each other.
+pub fn load_import_manifest(input: &Path) -> Result<Vec<ImportSnapshot>> {
+    let path = input.join(IMPORT_MANIFEST_FILE);
+    let raw = std::fs::read_to_string(&path).map_err(|e| Error::InvalidConfig {
+        message: format!("read import manifest {}: {e}", path.display()),
+    })?;
+    parse_import_manifest(&raw, input)
+}
+
+fn parse_import_manifest(raw: &str, input: &Path) -> Result<Vec<ImportSnapshot>> {
+    let invalid = |message: String| Error::InvalidConfig {
+        message: format!("{IMPORT_MANIFEST_FILE}: {message}"),
+    };
+    let file: ManifestFile = toml::from_str(raw).map_err(|e| invalid(e.to_string()))?;
+    if file.snapshots.is_empty() {
+        return Err(invalid("no [[snapshots]] entries".to_string()));
+    }
+
+    let mut out = Vec::with_capacity(file.snapshots.len());
+    for entry in file.snapshots {
+        let created_at = chrono::DateTime::parse_from_rfc3339(entry.created_at.trim())
+            .map_err(|e| {
+                invalid(format!(
+                    "snapshots[].created_at must be RFC3339: {:?}: {e}",
+                    entry.created_at
+                ))
+            })?
+            .with_timezone(&chrono::Utc);
+        if created_at > chrono::Utc::now() {
+            return Err(invalid(format!(
+                "snapshots[].created_at is in the future: {}",
+                entry.created_at
+            )));
+        }
+
+        let dir = if entry.dir.is_absolute() {
+            entry.dir.clone()
+        } else {
+            input.join(&entry.dir)
+        };
+        if !dir.is_dir() {
+            return Err(invalid(format!(
+                "snapshots[].dir is not a directory: {}",
+                dir.display()
+            )));
+        }
+
+        let name = entry
+            .label
+            .as_deref()
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+            .or_else(|| {
+                entry
+                    .dir
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+            })
+            .unwrap_or_else(|| "snapshot".to_string());
+
+        out.push(ImportSnapshot {
+            created_at: created_at.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            dir,
+            label: format!("{IMPORTED_LABEL_PREFIX}{name}"),
+        });
+    }
+
+    out.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+    if let Some(w) = out.windows(2).find(|w| w[0].created_at == w[1].created_at) {
+        return Err(invalid(format!(
+            "duplicate snapshots[].created_at: {}",
+            w[0].created_at
+        )));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_is_sorted_normalized_and_labelled() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("b")).unwrap();
+        std::fs::create_dir(dir.path().join("a")).unwrap();
+        let raw = r#"
+            [[snapshots]]
+            created_at = "2022-01-02T03:04:05+02:00"
+            dir = "b"
+            label = "4f2a91c0"
+
+            [[snapshots]]
+            created_at = "2021-03-04T05:06:07Z"
+            dir = "a"
+        "#;
+
+        let snapshots = parse_import_manifest(raw, dir.path()).unwrap();
+        assert_eq!(
+            snapshots,
+            vec![
+                ImportSnapshot {
+                    created_at: "2021-03-04T05:06:07.000Z".to_string(),
+                    dir: dir.path().join("a"),
+                    label: "imported:a".to_string(),
+                },
+                ImportSnapshot {
+                    created_at: "2022-01-02T01:04:05.000Z".to_string(),
+                    dir: dir.path().join("b"),
+                    label: "imported:4f2a91c0".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_manifests_are_config_invalid() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("a")).unwrap();
+        for raw in [
+            "",
+            "[[snapshots]]\ncreated_at = \"yesterday\"\ndir = \"a\"\n",
+            "[[snapshots]]\ncreated_at = \"2021-03-04T05:06:07Z\"\ndir = \"missing\"\n",
+            "[[snapshots]]\ncreated_at = \"9999-01-01T00:00:00Z\"\ndir = \"a\"\n",
+            "[[snapshots]]\ncreated_at = \"2021-03-04T05:06:07Z\"\ndir = \"a\"\n\
+             [[snapshots]]\ncreated_at = \"2021-03-04T05:06:07Z\"\ndir = \"a\"\n",
+        ] {
+            let err = parse_import_manifest(raw, dir.path()).unwrap_err();
+            assert_eq!(err.error_code(), crate::ErrorCode::ConfigInvalid, "{raw}");
+        }
+    }
+}
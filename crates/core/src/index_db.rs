@@ -1,11 +1,15 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
-use sqlx::SqlitePool;
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
 use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous};
-use tracing::{debug, error};
+use sqlx::{Row, SqlitePool};
+use tracing::{debug, error, info, warn};
 
 use crate::Result;
+use crate::crypto::{ChunkHashAlg, DataKey, KeyDerivation};
+use crate::storage::{ChunkObjectRef, encode_tgfile_object_id, encode_tgpack_object_id};
 
 // Large endpoint index DBs can legitimately take a long time to open (e.g. journal recovery after
 // crashes or forced termination). Keep the pool acquire timeout comfortably above the default so
@@ -75,10 +79,27 @@ pub async fn open_index_db(path: &Path) -> Result<SqlitePool> {
         );
         e
     })?;
+    migrate_legacy_providers_best_effort(&pool, path).await;
     Ok(pool)
 }
 
 pub async fn open_existing_index_db(path: &Path) -> Result<SqlitePool> {
+    let pool = connect_existing_index_db(path).await?;
+    migrate_legacy_providers_best_effort(&pool, path).await;
+    Ok(pool)
+}
+
+/// Explicit form of the migration the open functions run once (`televybackup index
+/// migrate-providers`); it runs even when the version is already recorded.
+pub async fn migrate_index_db_providers(path: &Path) -> Result<ProviderMigrationReport> {
+    let pool = connect_existing_index_db(path).await?;
+    let endpoint_id = endpoint_id_from_index_db_path(path);
+    let report = migrate_legacy_providers(&pool, endpoint_id.as_deref(), true).await;
+    pool.close().await;
+    report
+}
+
+async fn connect_existing_index_db(path: &Path) -> Result<SqlitePool> {
     debug!(
         event = "sqlite.open",
         db_path = %path.display(),
@@ -134,3 +155,777 @@ pub async fn open_existing_index_db(path: &Path) -> Result<SqlitePool> {
 
     Ok(pool)
 }
+
+/// Whether `snapshots` has the `device_id`/`device_name` columns.
+///
+/// Read-only callers open DBs without migrating them, and DBs downloaded from older versions may
+/// not have the columns yet.
+pub async fn snapshots_have_device_columns(pool: &SqlitePool) -> Result<bool> {
+    let n: i64 = sqlx::query_scalar(
+        "SELECT COUNT(1) FROM pragma_table_info('snapshots') WHERE name IN ('device_id', 'device_name')",
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(n == 2)
+}
+
+/// Whether `snapshots` has the `pinned` column (see [`snapshots_have_device_columns`]).
+pub async fn snapshots_have_pinned_column(pool: &SqlitePool) -> Result<bool> {
+    let n: i64 = sqlx::query_scalar(
+        "SELECT COUNT(1) FROM pragma_table_info('snapshots') WHERE name = 'pinned'",
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(n == 1)
+}
+
+/// The key derivation snapshot `snapshot_id` was recorded with (`key_derivation` and
+/// `key_target_id`); `None` when the DB has no row for it. DBs without the columns (see
+/// [`snapshots_have_device_columns`]) only hold master key snapshots.
+pub async fn snapshot_key_derivation(
+    conn: &mut sqlx::SqliteConnection,
+    snapshot_id: &str,
+) -> Result<Option<(KeyDerivation, Option<String>)>> {
+    let n: i64 = sqlx::query_scalar(
+        "SELECT COUNT(1) FROM pragma_table_info('snapshots') WHERE name IN ('key_derivation', 'key_target_id')",
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    let query = if n == 2 {
+        "SELECT key_derivation, key_target_id FROM snapshots WHERE snapshot_id = ?"
+    } else {
+        "SELECT 0 AS key_derivation, NULL AS key_target_id FROM snapshots WHERE snapshot_id = ?"
+    };
+    let Some(row) = sqlx::query(query)
+        .bind(snapshot_id)
+        .fetch_optional(&mut *conn)
+        .await?
+    else {
+        return Ok(None);
+    };
+    let derivation = KeyDerivation::from_version(row.get("key_derivation"))?;
+    Ok(Some((derivation, row.get("key_target_id"))))
+}
+
+/// Whether `<schema>.snapshots` has the `hash_alg` column (see [`snapshots_have_device_columns`]).
+pub async fn snapshots_have_hash_alg_column<'e, E>(executor: E, schema: &str) -> Result<bool>
+where
+    E: sqlx::SqliteExecutor<'e>,
+{
+    let n: i64 = sqlx::query_scalar(
+        "SELECT COUNT(1) FROM pragma_table_info('snapshots', ?) WHERE name = 'hash_alg'",
+    )
+    .bind(schema)
+    .fetch_one(executor)
+    .await?;
+    Ok(n == 1)
+}
+
+/// How snapshot `snapshot_id`'s chunk ids were computed; `None` when the DB has no row for it.
+/// DBs without the column only hold plain blake3 snapshots, apart from those under a target key,
+/// which [`DataKey::with_chunk_hash`] keeps keyed either way.
+pub async fn snapshot_chunk_hash_alg(
+    conn: &mut sqlx::SqliteConnection,
+    snapshot_id: &str,
+) -> Result<Option<ChunkHashAlg>> {
+    let query = if snapshots_have_hash_alg_column(&mut *conn, "main").await? {
+        "SELECT hash_alg FROM snapshots WHERE snapshot_id = ?"
+    } else {
+        "SELECT 'blake3' AS hash_alg FROM snapshots WHERE snapshot_id = ?"
+    };
+    let alg: Option<String> = sqlx::query_scalar(query)
+        .bind(snapshot_id)
+        .fetch_optional(&mut *conn)
+        .await?;
+    alg.map(|alg| ChunkHashAlg::parse(&alg)).transpose()
+}
+
+/// The data key snapshot `snapshot_id` was written with (see [`snapshot_key_derivation`] and
+/// [`snapshot_chunk_hash_alg`]).
+pub async fn snapshot_data_key(
+    conn: &mut sqlx::SqliteConnection,
+    master_key: &[u8; 32],
+    snapshot_id: &str,
+) -> Result<Option<DataKey>> {
+    let Some((derivation, target_id)) = snapshot_key_derivation(conn, snapshot_id).await? else {
+        return Ok(None);
+    };
+    let alg = snapshot_chunk_hash_alg(conn, snapshot_id)
+        .await?
+        .unwrap_or_default();
+    DataKey::for_snapshot(master_key, derivation, target_id.as_deref())
+        .map(|key| Some(key.with_chunk_hash(alg)))
+}
+
+/// [`snapshot_data_key`] of the index DB at `db_path`; `None` as well when there is no DB there.
+pub async fn snapshot_data_key_at(
+    db_path: &Path,
+    master_key: &[u8; 32],
+    snapshot_id: &str,
+) -> Result<Option<DataKey>> {
+    if !db_path.exists() {
+        return Ok(None);
+    }
+    let pool = open_existing_index_db(db_path).await?;
+    let key = {
+        let mut conn = pool.acquire().await?;
+        snapshot_data_key(&mut conn, master_key, snapshot_id).await
+    };
+    pool.close().await;
+    key
+}
+
+/// Whether `<schema>.files` has the `btime_ms` column (see [`snapshots_have_device_columns`]);
+/// `schema` is `main` or the alias of an attached DB.
+pub async fn files_have_btime_column<'e, E>(executor: E, schema: &str) -> Result<bool>
+where
+    E: sqlx::SqliteExecutor<'e>,
+{
+    let n: i64 = sqlx::query_scalar(
+        "SELECT COUNT(1) FROM pragma_table_info('files', ?) WHERE name = 'btime_ms'",
+    )
+    .bind(schema)
+    .fetch_one(executor)
+    .await?;
+    Ok(n == 1)
+}
+
+/// Whether `<schema>.files` has the owner columns (`uid`, `gid`, `owner_name`, `group_name`).
+pub async fn files_have_owner_columns<'e, E>(executor: E, schema: &str) -> Result<bool>
+where
+    E: sqlx::SqliteExecutor<'e>,
+{
+    let n: i64 =
+        sqlx::query_scalar("SELECT COUNT(1) FROM pragma_table_info('files', ?) WHERE name = 'uid'")
+            .bind(schema)
+            .fetch_one(executor)
+            .await?;
+    Ok(n == 1)
+}
+
+/// The `files` columns added after the original schema (`btime_ms`, the owner columns, then
+/// `content_source`) as a select list over `<schema>.files`, with `NULL AS` for those a file map
+/// written by an older build lacks.
+pub async fn files_optional_columns<'e, E>(executor: E, schema: &str) -> Result<&'static str>
+where
+    E: sqlx::SqliteExecutor<'e>,
+{
+    let names: Vec<String> = sqlx::query_scalar(
+        "SELECT name FROM pragma_table_info('files', ?) WHERE name IN ('btime_ms', 'uid', 'content_source')",
+    )
+    .bind(schema)
+    .fetch_all(executor)
+    .await?;
+    Ok(if names.iter().any(|n| n == "content_source") {
+        "btime_ms, uid, gid, owner_name, group_name, content_source"
+    } else if names.iter().any(|n| n == "uid") {
+        "btime_ms, uid, gid, owner_name, group_name, NULL AS content_source"
+    } else if names.iter().any(|n| n == "btime_ms") {
+        "btime_ms, NULL AS uid, NULL AS gid, NULL AS owner_name, NULL AS group_name, NULL AS content_source"
+    } else {
+        "NULL AS btime_ms, NULL AS uid, NULL AS gid, NULL AS owner_name, NULL AS group_name, NULL AS content_source"
+    })
+}
+
+/// `schema_migrations` version recorded once provider strings and chunk object IDs have been
+/// rewritten to their canonical form (see [`migrate_legacy_providers`]).
+pub const PROVIDER_MIGRATION_SCHEMA_VERSION: i64 = 7;
+
+/// Provider kinds written by older versions (Bot API era, pre-endpoint MTProto).
+const LEGACY_TELEGRAM_PROVIDER_KINDS: &[&str] = &[
+    "telegram",
+    "telegram.botapi",
+    "telegram.bot_api",
+    "telegram.mtproto",
+    "telegram_mtproto",
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProviderMigrationReport {
+    /// False when the DB was already migrated or has no index tables.
+    pub ran: bool,
+    pub endpoint_id: Option<String>,
+    pub providers_updated: u64,
+    pub object_ids_updated: u64,
+}
+
+/// Endpoint ID of a per-endpoint index DB (`index.<endpoint_id>.sqlite`).
+pub fn endpoint_id_from_index_db_path(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_str()?;
+    let endpoint_id = name.strip_prefix("index.")?.strip_suffix(".sqlite")?;
+    (!endpoint_id.is_empty()).then(|| endpoint_id.to_string())
+}
+
+/// Index DBs to read from, sorted: every `index.<endpoint_id>.sqlite` under `<data_dir>/index`,
+/// or the legacy global `index.sqlite` when there are none.
+pub fn list_index_db_paths(data_dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let index_dir = data_dir.join("index");
+    let mut dbs = Vec::<PathBuf>::new();
+    match std::fs::read_dir(&index_dir) {
+        Ok(entries) => {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.is_file() && endpoint_id_from_index_db_path(&path).is_some() {
+                    dbs.push(path);
+                }
+            }
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+
+    dbs.sort();
+    if dbs.is_empty() {
+        let legacy = index_dir.join("index.sqlite");
+        if legacy.exists() {
+            dbs.push(legacy);
+        }
+    }
+    Ok(dbs)
+}
+
+/// Normalizes a date (`YYYY-MM-DD`, midnight UTC) or RFC3339 time to the `snapshots.created_at`
+/// format (UTC, millis), so bounds compare as plain strings. `None` if `raw` is neither.
+pub fn snapshot_created_at_bound(raw: &str) -> Option<String> {
+    let raw = raw.trim();
+    let parsed = chrono::NaiveDate::parse_from_str(raw, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|dt| dt.and_utc())
+        .or_else(|| {
+            chrono::DateTime::parse_from_rfc3339(raw)
+                .ok()
+                .map(|dt| dt.with_timezone(&chrono::Utc))
+        })?;
+    Some(parsed.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string())
+}
+
+/// Filters for [`list_snapshots`]; `since`/`until` are [`snapshot_created_at_bound`] values.
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotQuery {
+    pub source_path: Option<String>,
+    /// Inclusive.
+    pub since: Option<String>,
+    /// Exclusive.
+    pub until: Option<String>,
+    pub asc: bool,
+    pub limit: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotSummary {
+    pub snapshot_id: String,
+    pub created_at: String,
+    pub source_path: String,
+    pub label: String,
+    pub base_snapshot_id: Option<String>,
+    pub device_id: Option<String>,
+    pub device_name: Option<String>,
+    pub pinned: bool,
+}
+
+/// Position after the last item of a [`list_snapshot_page`] page: snapshots sort by
+/// `(created_at, snapshot_id)`, so one position resumes every DB's keyset query at once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotCursor {
+    pub created_at: String,
+    pub snapshot_id: String,
+}
+
+impl SnapshotCursor {
+    /// Opaque to callers; they only hand it back.
+    pub fn encode(&self) -> String {
+        use base64::Engine;
+        base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .encode(format!("{}\n{}", self.created_at, self.snapshot_id))
+    }
+
+    pub fn decode(raw: &str) -> Result<Self> {
+        use base64::Engine;
+        let invalid = || crate::Error::InvalidConfig {
+            message: format!("invalid snapshots cursor: {raw:?}"),
+        };
+        let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(raw.trim())
+            .map_err(|_| invalid())?;
+        let text = String::from_utf8(bytes).map_err(|_| invalid())?;
+        let (created_at, snapshot_id) = text.split_once('\n').ok_or_else(invalid)?;
+        if created_at.is_empty() || snapshot_id.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self {
+            created_at: created_at.to_string(),
+            snapshot_id: snapshot_id.to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotPage {
+    pub snapshots: Vec<SnapshotSummary>,
+    /// Encoded [`SnapshotCursor`] of the next page; `None` on the last one.
+    pub next_cursor: Option<String>,
+}
+
+/// Newest (or oldest, with `asc`) snapshots matching `query` across `db_paths`, at most
+/// `query.limit` in total.
+pub async fn list_snapshots(
+    db_paths: &[PathBuf],
+    query: &SnapshotQuery,
+) -> Result<Vec<SnapshotSummary>> {
+    // Each DB's first N matches, merged down to the global first N.
+    let mut items = Vec::<SnapshotSummary>::new();
+    for db_path in db_paths {
+        items.extend(query_snapshots(db_path, query, None, query.limit).await?);
+    }
+
+    if query.asc {
+        items.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+    } else {
+        items.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    }
+    items.truncate(query.limit as usize);
+    Ok(items)
+}
+
+/// The page of [`list_snapshots`] after `cursor` (the first page without one). Each DB is asked
+/// for at most `query.limit + 1` rows past the cursor, so a page costs the same however deep it
+/// is.
+pub async fn list_snapshot_page(
+    db_paths: &[PathBuf],
+    query: &SnapshotQuery,
+    cursor: Option<&SnapshotCursor>,
+) -> Result<SnapshotPage> {
+    let mut items = Vec::<SnapshotSummary>::new();
+    for db_path in db_paths {
+        items.extend(
+            query_snapshots(db_path, query, Some(cursor), query.limit.saturating_add(1)).await?,
+        );
+    }
+
+    let key = |s: &SnapshotSummary| (s.created_at.clone(), s.snapshot_id.clone());
+    if query.asc {
+        items.sort_by_key(key);
+    } else {
+        items.sort_by_key(|s| std::cmp::Reverse(key(s)));
+    }
+    let more = items.len() > query.limit as usize;
+    items.truncate(query.limit as usize);
+    let next_cursor = items.last().filter(|_| more).map(|s| {
+        SnapshotCursor {
+            created_at: s.created_at.clone(),
+            snapshot_id: s.snapshot_id.clone(),
+        }
+        .encode()
+    });
+    Ok(SnapshotPage {
+        snapshots: items,
+        next_cursor,
+    })
+}
+
+/// One DB's first `limit` matches of `query`. `keyset` orders ties on `created_at` by
+/// `snapshot_id` and starts past its cursor, if any.
+async fn query_snapshots(
+    db_path: &Path,
+    query: &SnapshotQuery,
+    keyset: Option<Option<&SnapshotCursor>>,
+    limit: u32,
+) -> Result<Vec<SnapshotSummary>> {
+    let cursor = keyset.flatten();
+    let mut filters = String::new();
+    if query.source_path.is_some() {
+        filters.push_str(" AND source_path = ?");
+    }
+    if query.since.is_some() {
+        filters.push_str(" AND created_at >= ?");
+    }
+    if query.until.is_some() {
+        filters.push_str(" AND created_at < ?");
+    }
+    let (cmp, dir) = if query.asc {
+        (">", "ASC")
+    } else {
+        ("<", "DESC")
+    };
+    if cursor.is_some() {
+        filters.push_str(&format!(
+            " AND (created_at {cmp} ? OR (created_at = ? AND snapshot_id {cmp} ?))"
+        ));
+    }
+    filters.push_str(&format!(" ORDER BY created_at {dir}"));
+    if keyset.is_some() {
+        filters.push_str(&format!(", snapshot_id {dir}"));
+    }
+    filters.push_str(" LIMIT ?");
+
+    let pool = open_existing_index_db(db_path).await?;
+    let device_columns = if snapshots_have_device_columns(&pool).await? {
+        "device_id, device_name"
+    } else {
+        "NULL AS device_id, NULL AS device_name"
+    };
+    let pinned_column = if snapshots_have_pinned_column(&pool).await? {
+        "pinned"
+    } else {
+        "0 AS pinned"
+    };
+    let sql = format!(
+        "SELECT snapshot_id, created_at, source_path, label, base_snapshot_id, {device_columns}, {pinned_column} FROM snapshots WHERE 1 = 1{filters}"
+    );
+    let mut q = sqlx::query(&sql);
+    for v in [&query.source_path, &query.since, &query.until]
+        .into_iter()
+        .flatten()
+    {
+        q = q.bind(v);
+    }
+    if let Some(c) = cursor {
+        q = q
+            .bind(&c.created_at)
+            .bind(&c.created_at)
+            .bind(&c.snapshot_id);
+    }
+    let rows = q.bind(limit as i64).fetch_all(&pool).await?;
+    pool.close().await;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| SnapshotSummary {
+            snapshot_id: row.get("snapshot_id"),
+            created_at: row.get("created_at"),
+            source_path: row.get("source_path"),
+            label: row.get("label"),
+            base_snapshot_id: row.get("base_snapshot_id"),
+            device_id: row.get("device_id"),
+            device_name: row.get("device_name"),
+            pinned: row.get::<i64, _>("pinned") != 0,
+        })
+        .collect())
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexStats {
+    pub snapshots_total: i64,
+    pub chunks_total: i64,
+    pub chunks_bytes_total: i64,
+    /// Chunks no snapshot references (`chunks.ref_count = 0`); `None` unless every DB's counts
+    /// cover all of its snapshots (see `index check`).
+    #[serde(default)]
+    pub chunks_unreferenced: Option<i64>,
+    #[serde(default)]
+    pub chunks_unreferenced_bytes: Option<i64>,
+}
+
+/// Snapshot and chunk totals summed over `db_paths`.
+pub async fn index_stats(db_paths: &[PathBuf]) -> Result<IndexStats> {
+    let mut stats = IndexStats::default();
+    let mut unreferenced = Some((0i64, 0i64));
+    for db_path in db_paths {
+        let pool = open_existing_index_db(db_path).await?;
+        let snapshots: i64 = sqlx::query_scalar("SELECT COUNT(1) FROM snapshots")
+            .fetch_one(&pool)
+            .await?;
+        let (chunks, bytes): (i64, i64) =
+            sqlx::query_as("SELECT COUNT(1), COALESCE(SUM(size), 0) FROM chunks")
+                .fetch_one(&pool)
+                .await?;
+        if let Some((n, b)) = unreferenced {
+            unreferenced = unreferenced_chunks(&pool)
+                .await?
+                .map(|(dn, db)| (n.saturating_add(dn), b.saturating_add(db)));
+        }
+        pool.close().await;
+        stats.snapshots_total = stats.snapshots_total.saturating_add(snapshots);
+        stats.chunks_total = stats.chunks_total.saturating_add(chunks);
+        stats.chunks_bytes_total = stats.chunks_bytes_total.saturating_add(bytes);
+    }
+    stats.chunks_unreferenced = unreferenced.map(|(n, _)| n);
+    stats.chunks_unreferenced_bytes = unreferenced.map(|(_, b)| b);
+    Ok(stats)
+}
+
+/// Count and bytes of the chunks with `ref_count = 0`, when the counts can be trusted.
+async fn unreferenced_chunks(pool: &SqlitePool) -> Result<Option<(i64, i64)>> {
+    let mut conn = pool.acquire().await?;
+    if !crate::chunk_refs::ref_counts_usable(&mut conn).await? {
+        return Ok(None);
+    }
+    let uncounted: i64 =
+        sqlx::query_scalar("SELECT COUNT(1) FROM snapshots WHERE chunk_refs_counted = 0")
+            .fetch_one(&mut *conn)
+            .await?;
+    if uncounted > 0 {
+        return Ok(None);
+    }
+    let counts =
+        sqlx::query_as("SELECT COUNT(1), COALESCE(SUM(size), 0) FROM chunks WHERE ref_count = 0")
+            .fetch_one(&mut *conn)
+            .await?;
+    Ok(Some(counts))
+}
+
+/// Rewrites legacy provider strings to `telegram.mtproto/<endpoint_id>` and chunk object IDs to
+/// the current `tgfile:`/`tgpack:` encoding, then records [`PROVIDER_MIGRATION_SCHEMA_VERSION`].
+///
+/// `endpoint_id` owns every Telegram row in the DB when given (an endpoint index DB). Otherwise
+/// rows without an endpoint are assigned to the only endpoint already present, if there is
+/// exactly one; rows that stay ambiguous are left alone. Unless `force` is set, a DB that already
+/// records the version is not touched.
+pub async fn migrate_legacy_providers(
+    pool: &SqlitePool,
+    endpoint_id: Option<&str>,
+    force: bool,
+) -> Result<ProviderMigrationReport> {
+    let mut report = ProviderMigrationReport::default();
+
+    let tables: i64 = sqlx::query_scalar(
+        "SELECT COUNT(1) FROM sqlite_master WHERE type = 'table' AND name IN ('chunk_objects', 'remote_indexes', 'remote_index_parts')",
+    )
+    .fetch_one(pool)
+    .await?;
+    if tables != 3 {
+        return Ok(report);
+    }
+
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (version INTEGER PRIMARY KEY, applied_at TEXT NOT NULL)",
+    )
+    .execute(&mut *tx)
+    .await?;
+    let applied: Option<i64> =
+        sqlx::query_scalar("SELECT version FROM schema_migrations WHERE version = ?")
+            .bind(PROVIDER_MIGRATION_SCHEMA_VERSION)
+            .fetch_optional(&mut *tx)
+            .await?;
+    if applied.is_some() && !force {
+        return Ok(report);
+    }
+
+    let fallback_endpoint_id = match endpoint_id {
+        Some(_) => None,
+        None => {
+            let endpoints: Vec<String> = sqlx::query_scalar(
+                r#"
+                SELECT provider FROM chunk_objects WHERE provider LIKE 'telegram.mtproto/_%'
+                UNION
+                SELECT provider FROM remote_indexes WHERE provider LIKE 'telegram.mtproto/_%'
+                UNION
+                SELECT provider FROM remote_index_parts WHERE provider LIKE 'telegram.mtproto/_%'
+                "#,
+            )
+            .fetch_all(&mut *tx)
+            .await?;
+            match endpoints.as_slice() {
+                [only] => only.strip_prefix("telegram.mtproto/").map(str::to_string),
+                _ => None,
+            }
+        }
+    };
+    report.ran = true;
+    report.endpoint_id = endpoint_id
+        .map(str::to_string)
+        .or(fallback_endpoint_id.clone());
+
+    for table in ["chunk_objects", "remote_indexes", "remote_index_parts"] {
+        let providers: Vec<Option<String>> =
+            sqlx::query_scalar(&format!("SELECT DISTINCT provider FROM {table}"))
+                .fetch_all(&mut *tx)
+                .await?;
+        for old in providers {
+            let Some(new) =
+                canonical_provider(old.as_deref(), endpoint_id, fallback_endpoint_id.as_deref())
+            else {
+                continue;
+            };
+            if old.as_deref() == Some(new.as_str()) {
+                continue;
+            }
+
+            // `chunk_objects` is unique per (provider, chunk_hash): keep the row already stored
+            // under the canonical provider and drop the legacy duplicate.
+            let updated = sqlx::query(&format!(
+                "UPDATE OR IGNORE {table} SET provider = ? WHERE provider IS ?"
+            ))
+            .bind(&new)
+            .bind(&old)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+            if table == "chunk_objects" {
+                sqlx::query("DELETE FROM chunk_objects WHERE provider IS ?")
+                    .bind(&old)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            report.providers_updated += updated;
+        }
+    }
+
+    let mut rewrites: Vec<(String, String, String)> = Vec::new();
+    {
+        let mut rows = sqlx::query("SELECT provider, object_id FROM chunk_objects").fetch(&mut *tx);
+        while let Some(row) = rows.try_next().await? {
+            let object_id: String = row.get("object_id");
+            match canonical_chunk_object_id(&object_id) {
+                Some(canonical) if canonical != object_id => {
+                    rewrites.push((row.get("provider"), object_id, canonical));
+                }
+                Some(_) => {}
+                None => warn!(
+                    event = "index_db.object_id_unparseable",
+                    object_id = %object_id,
+                    "index_db.object_id_unparseable"
+                ),
+            }
+        }
+    }
+    for (provider, old, new) in rewrites {
+        report.object_ids_updated += sqlx::query(
+            "UPDATE OR IGNORE chunk_objects SET object_id = ? WHERE provider = ? AND object_id = ?",
+        )
+        .bind(&new)
+        .bind(&provider)
+        .bind(&old)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    }
+
+    sqlx::query(
+        "INSERT OR REPLACE INTO schema_migrations(version, applied_at) VALUES (?, strftime('%Y-%m-%dT%H:%M:%fZ','now'))",
+    )
+    .bind(PROVIDER_MIGRATION_SCHEMA_VERSION)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(report)
+}
+
+/// Runs [`migrate_legacy_providers`] when a DB is opened; a failure only logs, since callers can
+/// still work with the legacy rows.
+async fn migrate_legacy_providers_best_effort(pool: &SqlitePool, path: &Path) {
+    let endpoint_id = endpoint_id_from_index_db_path(path);
+    match migrate_legacy_providers(pool, endpoint_id.as_deref(), false).await {
+        Ok(report) if report.providers_updated > 0 || report.object_ids_updated > 0 => info!(
+            event = "index_db.providers_migrated",
+            db_path = %path.display(),
+            endpoint_id = report.endpoint_id.as_deref(),
+            providers_updated = report.providers_updated,
+            object_ids_updated = report.object_ids_updated,
+            "index_db.providers_migrated"
+        ),
+        Ok(_) => {}
+        Err(e) => warn!(
+            event = "index_db.providers_migrate_failed",
+            db_path = %path.display(),
+            error = %e,
+            "index_db.providers_migrate_failed"
+        ),
+    }
+}
+
+/// `None` when `provider` is not a Telegram provider or no endpoint can be derived for it.
+fn canonical_provider(
+    provider: Option<&str>,
+    owner_endpoint_id: Option<&str>,
+    fallback_endpoint_id: Option<&str>,
+) -> Option<String> {
+    let provider = provider.map(str::trim).unwrap_or("");
+    let (kind, embedded) = match provider.split_once(['/', ':']) {
+        Some((kind, rest)) => (kind, Some(rest).filter(|r| !r.is_empty())),
+        None => (provider, None),
+    };
+    if !provider.is_empty() && !LEGACY_TELEGRAM_PROVIDER_KINDS.contains(&kind) {
+        return None;
+    }
+    let endpoint_id = owner_endpoint_id.or(embedded).or(fallback_endpoint_id)?;
+    Some(crate::config::endpoint_provider(endpoint_id))
+}
+
+fn canonical_chunk_object_id(object_id: &str) -> Option<String> {
+    match crate::storage::parse_chunk_object_ref(object_id).ok()? {
+        ChunkObjectRef::Direct { object_id } => Some(encode_tgfile_object_id(&object_id)),
+        ChunkObjectRef::PackSlice {
+            pack_object_id,
+            offset,
+            len,
+        } => Some(encode_tgpack_object_id(&pack_object_id, offset, len)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn db_with_snapshots(path: &Path, snapshots: &[(&str, &str)]) {
+        let pool = open_index_db(path).await.unwrap();
+        for (snapshot_id, created_at) in snapshots {
+            sqlx::query(
+                "INSERT INTO snapshots (snapshot_id, created_at, source_path, label, base_snapshot_id) VALUES (?, ?, '/src', 'manual', NULL)",
+            )
+            .bind(snapshot_id)
+            .bind(created_at)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+        pool.close().await;
+    }
+
+    #[tokio::test]
+    async fn snapshot_pages_walk_every_db_once_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("index.a.sqlite");
+        let b = dir.path().join("index.b.sqlite");
+        let t1 = "2026-01-01T00:00:00.000Z";
+        let t2 = "2026-01-02T00:00:00.000Z";
+        let t3 = "2026-01-03T00:00:00.000Z";
+        db_with_snapshots(&a, &[("snp_a1", t1), ("snp_a2", t2), ("snp_a3", t3)]).await;
+        // Same created_at as snp_a2: the snapshot id breaks the tie across pages.
+        db_with_snapshots(&b, &[("snp_b1", t1), ("snp_b2", t2)]).await;
+        let dbs = vec![a, b];
+
+        for asc in [false, true] {
+            let query = SnapshotQuery {
+                asc,
+                limit: 2,
+                ..SnapshotQuery::default()
+            };
+            let mut seen = Vec::new();
+            let mut cursor = None;
+            loop {
+                let page = list_snapshot_page(&dbs, &query, cursor.as_ref())
+                    .await
+                    .unwrap();
+                assert!(page.snapshots.len() <= 2);
+                seen.extend(page.snapshots.into_iter().map(|s| s.snapshot_id));
+                match page.next_cursor {
+                    Some(next) => cursor = Some(SnapshotCursor::decode(&next).unwrap()),
+                    None => break,
+                }
+            }
+            let mut expected = vec!["snp_a1", "snp_b1", "snp_a2", "snp_b2", "snp_a3"];
+            if !asc {
+                expected.reverse();
+            }
+            assert_eq!(seen, expected, "asc={asc}");
+        }
+
+        let unpaged = list_snapshots(
+            &dbs,
+            &SnapshotQuery {
+                limit: 10,
+                ..SnapshotQuery::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(unpaged.len(), 5);
+        assert!(SnapshotCursor::decode("not a cursor").is_err());
+    }
+}
@@ -0,0 +1,329 @@
+//! Delta filemap indexes (`index.full_every`).
+//!
+//! A delta DB uses the regular index schema. It holds the snapshot row, the `files` and
+//! `file_chunks` rows that are new or changed since the parent snapshot and the `chunks` rows the
+//! parent lacks, plus two extra tables naming what to drop from the parent's file map. Applying it
+//! to the parent's (reconstructed) file map yields the snapshot's file map; unchanged files keep
+//! the parent's `file_id`s.
+
+use std::path::Path;
+
+use sqlx::Row;
+use sqlx::pool::PoolConnection;
+use sqlx::sqlite::Sqlite;
+use tracing::debug;
+
+use crate::index_db::{
+    files_have_owner_columns, files_optional_columns, open_index_db, snapshots_have_hash_alg_column,
+};
+use crate::{Error, Result};
+
+type DbConn = PoolConnection<Sqlite>;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct FilemapDeltaStats {
+    /// Files written to the delta (added or changed since the parent).
+    pub files_written: u64,
+    /// Parent paths the delta drops (removed or changed).
+    pub paths_dropped: u64,
+}
+
+/// Writes the delta between two snapshots' file maps to a new DB at `out_path`.
+pub(crate) async fn write_filemap_delta_db(
+    filemap_db_path: &Path,
+    parent_db_path: &Path,
+    parent_snapshot_id: &str,
+    snapshot_id: &str,
+    out_path: &Path,
+) -> Result<FilemapDeltaStats> {
+    let pool = open_index_db(out_path).await?;
+    let mut conn = pool.acquire().await?;
+    drop(pool);
+    // Changed files reference chunks that only the parent's `chunks` table holds.
+    sqlx::query("PRAGMA foreign_keys = OFF")
+        .execute(&mut *conn)
+        .await?;
+    attach(&mut conn, "cur", filemap_db_path).await?;
+    attach(&mut conn, "parent", parent_db_path).await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE filemap_delta_removed_paths (path TEXT PRIMARY KEY);
+        CREATE TABLE filemap_delta_removed_chunks (chunk_hash TEXT PRIMARY KEY);
+        "#,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    create_file_signatures(&mut conn, "cur", snapshot_id).await?;
+    create_file_signatures(&mut conn, "parent", parent_snapshot_id).await?;
+
+    sqlx::query(
+        r#"
+        CREATE TEMP TABLE delta_file_ids AS
+        SELECT c.file_id
+        FROM cur_sig c
+        LEFT JOIN parent_sig p ON p.path = c.path
+        WHERE p.sig IS NULL OR p.sig != c.sig
+        "#,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO snapshots (snapshot_id, created_at, source_path, label, base_snapshot_id, device_id, device_name, hash_alg)
+        SELECT snapshot_id, created_at, source_path, label, base_snapshot_id, device_id, device_name, hash_alg
+        FROM cur.snapshots
+        WHERE snapshot_id = ?
+        "#,
+    )
+    .bind(snapshot_id)
+    .execute(&mut *conn)
+    .await?;
+    let files_written = sqlx::query(
+        r#"
+        INSERT INTO files (file_id, snapshot_id, path, size, mtime_ms, mode, kind, btime_ms, uid, gid, owner_name, group_name, content_source)
+        SELECT file_id, snapshot_id, path, size, mtime_ms, mode, kind, btime_ms, uid, gid, owner_name, group_name, content_source
+        FROM cur.files
+        WHERE file_id IN (SELECT file_id FROM delta_file_ids)
+        "#,
+    )
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+    sqlx::query(
+        r#"
+        INSERT INTO file_chunks (file_id, seq, chunk_hash, offset, len)
+        SELECT file_id, seq, chunk_hash, offset, len
+        FROM cur.file_chunks
+        WHERE file_id IN (SELECT file_id FROM delta_file_ids)
+        "#,
+    )
+    .execute(&mut *conn)
+    .await?;
+    let paths_dropped = sqlx::query(
+        r#"
+        INSERT INTO filemap_delta_removed_paths (path)
+        SELECT p.path
+        FROM parent_sig p
+        LEFT JOIN cur_sig c ON c.path = p.path
+        WHERE c.sig IS NULL OR c.sig != p.sig
+        "#,
+    )
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+    sqlx::query(
+        r#"
+        INSERT INTO chunks (chunk_hash, size, hash_alg, enc_alg, created_at)
+        SELECT chunk_hash, size, hash_alg, enc_alg, created_at
+        FROM cur.chunks
+        WHERE chunk_hash NOT IN (SELECT chunk_hash FROM parent.chunks)
+        "#,
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query(
+        r#"
+        INSERT INTO filemap_delta_removed_chunks (chunk_hash)
+        SELECT chunk_hash
+        FROM parent.chunks
+        WHERE chunk_hash NOT IN (SELECT chunk_hash FROM cur.chunks)
+        "#,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query("DETACH DATABASE cur")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("DETACH DATABASE parent")
+        .execute(&mut *conn)
+        .await?;
+    conn.close().await?;
+
+    let stats = FilemapDeltaStats {
+        files_written,
+        paths_dropped,
+    };
+    debug!(
+        event = "index.delta.written",
+        snapshot_id,
+        parent_snapshot_id,
+        files_written = stats.files_written,
+        paths_dropped = stats.paths_dropped,
+        "index.delta.written"
+    );
+    Ok(stats)
+}
+
+/// Rewrites the parent snapshot's file map at `db_path` into the snapshot's file map described by
+/// the delta DB at `delta_db_path`.
+pub(crate) async fn apply_filemap_delta_db(
+    db_path: &Path,
+    delta_db_path: &Path,
+    parent_snapshot_id: &str,
+    snapshot_id: &str,
+) -> Result<()> {
+    let pool = open_index_db(db_path).await?;
+    let mut conn = pool.acquire().await?;
+    drop(pool);
+    attach(&mut conn, "delta", delta_db_path).await?;
+    // Deltas written before `files.btime_ms`, the owner columns or `content_source` existed
+    // leave them NULL.
+    let insert_files = format!(
+        r#"
+            INSERT INTO files (file_id, snapshot_id, path, size, mtime_ms, mode, kind, btime_ms, uid, gid, owner_name, group_name, content_source)
+            SELECT file_id, snapshot_id, path, size, mtime_ms, mode, kind, {}
+            FROM delta.files
+            "#,
+        files_optional_columns(&mut *conn, "delta").await?
+    );
+    // Deltas written before `snapshots.hash_alg` existed are all plain blake3.
+    let insert_snapshot = format!(
+        r#"
+            INSERT INTO snapshots (snapshot_id, created_at, source_path, label, base_snapshot_id, device_id, device_name, hash_alg)
+            SELECT snapshot_id, created_at, source_path, label, base_snapshot_id, device_id, device_name, {}
+            FROM delta.snapshots
+            "#,
+        if snapshots_have_hash_alg_column(&mut *conn, "delta").await? {
+            "hash_alg"
+        } else {
+            "'blake3'"
+        }
+    );
+
+    let has_parent = sqlx::query("SELECT 1 AS present FROM snapshots WHERE snapshot_id = ?")
+        .bind(parent_snapshot_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .is_some();
+    let delta_snapshot = sqlx::query("SELECT snapshot_id FROM delta.snapshots")
+        .fetch_all(&mut *conn)
+        .await?;
+    if !has_parent
+        || delta_snapshot.len() != 1
+        || delta_snapshot[0].get::<String, _>("snapshot_id") != snapshot_id
+    {
+        return Err(Error::Integrity {
+            message: format!(
+                "index delta does not fit its parent: snapshot_id={snapshot_id} parent_snapshot_id={parent_snapshot_id}"
+            ),
+        });
+    }
+
+    let parent = [parent_snapshot_id];
+    let both = [parent_snapshot_id, snapshot_id];
+    let statements: [(&str, &[&str]); 9] = [
+        (insert_snapshot.as_str(), &[]),
+        (
+            r#"
+            INSERT OR IGNORE INTO chunks (chunk_hash, size, hash_alg, enc_alg, created_at)
+            SELECT chunk_hash, size, hash_alg, enc_alg, created_at
+            FROM delta.chunks
+            "#,
+            &[],
+        ),
+        (
+            r#"
+            DELETE FROM file_chunks
+            WHERE file_id IN (
+              SELECT file_id FROM files
+              WHERE snapshot_id = ? AND path IN (SELECT path FROM delta.filemap_delta_removed_paths)
+            )
+            "#,
+            &parent,
+        ),
+        (
+            r#"
+            DELETE FROM files
+            WHERE snapshot_id = ? AND path IN (SELECT path FROM delta.filemap_delta_removed_paths)
+            "#,
+            &parent,
+        ),
+        (
+            "UPDATE files SET snapshot_id = ?2 WHERE snapshot_id = ?1",
+            &both,
+        ),
+        ("DELETE FROM snapshots WHERE snapshot_id = ?", &parent),
+        (insert_files.as_str(), &[]),
+        (
+            r#"
+            INSERT INTO file_chunks (file_id, seq, chunk_hash, offset, len)
+            SELECT file_id, seq, chunk_hash, offset, len
+            FROM delta.file_chunks
+            "#,
+            &[],
+        ),
+        (
+            r#"
+            DELETE FROM chunks
+            WHERE chunk_hash IN (SELECT chunk_hash FROM delta.filemap_delta_removed_chunks)
+            "#,
+            &[],
+        ),
+    ];
+    sqlx::query("BEGIN").execute(&mut *conn).await?;
+    for (sql, binds) in statements {
+        let mut query = sqlx::query(sql);
+        for value in binds {
+            query = query.bind(*value);
+        }
+        if let Err(e) = query.execute(&mut *conn).await {
+            let _ = sqlx::query("ROLLBACK").execute(&mut *conn).await;
+            return Err(e.into());
+        }
+    }
+    sqlx::query("COMMIT").execute(&mut *conn).await?;
+
+    sqlx::query("DETACH DATABASE delta")
+        .execute(&mut *conn)
+        .await?;
+    conn.close().await?;
+    Ok(())
+}
+
+/// Creates `temp.<schema>_sig(file_id, path, sig)`: one row per file of `snapshot_id`, where `sig`
+/// covers the file's metadata and chunk list. `btime_ms` is left out: a parent file map written
+/// before that column existed does not have it. Owners are in (a `chown` changes nothing else);
+/// a file map without the owner columns signs like one whose owners are all `NULL`.
+async fn create_file_signatures(conn: &mut DbConn, schema: &str, snapshot_id: &str) -> Result<()> {
+    let owner = if files_have_owner_columns(&mut **conn, schema).await? {
+        "COALESCE(f.uid, '') || ':' || COALESCE(f.gid, '')"
+    } else {
+        "':'"
+    };
+    let sql = format!(
+        r#"
+        CREATE TEMP TABLE {schema}_sig AS
+        SELECT f.file_id, f.path,
+          f.size || '|' || f.mtime_ms || '|' || f.mode || '|' || f.kind || '|' || {owner} || '|' || COALESCE((
+            SELECT group_concat(fc.seq || ':' || fc.chunk_hash || ':' || fc.offset || ':' || fc.len, ',' ORDER BY fc.seq)
+            FROM {schema}.file_chunks fc
+            WHERE fc.file_id = f.file_id
+          ), '') AS sig
+        FROM {schema}.files f
+        WHERE f.snapshot_id = ?
+        "#
+    );
+    sqlx::query(&sql)
+        .bind(snapshot_id)
+        .execute(&mut **conn)
+        .await?;
+    sqlx::query(&format!(
+        "CREATE UNIQUE INDEX temp.{schema}_sig_path ON {schema}_sig(path)"
+    ))
+    .execute(&mut **conn)
+    .await?;
+    Ok(())
+}
+
+async fn attach(conn: &mut DbConn, alias: &str, path: &Path) -> Result<()> {
+    // ATTACH needs a literal string; escape single quotes defensively.
+    let path_sql = path.to_string_lossy().replace('\'', "''");
+    sqlx::query(&format!("ATTACH DATABASE '{path_sql}' AS {alias}"))
+        .execute(&mut **conn)
+        .await?;
+    Ok(())
+}
@@ -1,5 +1,11 @@
 use serde::{Deserialize, Serialize};
 
+/// Manifest version of self-contained index DBs.
+pub const INDEX_MANIFEST_VERSION_FULL: u8 = 1;
+/// Manifest version of delta filemap indexes. Versions that predate deltas reject it instead of
+/// restoring from a partial file map.
+pub const INDEX_MANIFEST_VERSION_DELTA: u8 = 2;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IndexManifest {
     pub version: u8,
@@ -7,9 +13,28 @@ pub struct IndexManifest {
     pub hash_alg: String,
     pub enc_alg: String,
     pub compression: String,
+    /// Machine that uploaded this index (absent in manifests written by older versions).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_name: Option<String>,
+    /// Set on delta filemap indexes: the parts hold only the file entries that changed since this
+    /// parent snapshot's index.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<IndexManifestParent>,
     pub parts: Vec<IndexManifestPart>,
 }
 
+impl IndexManifest {
+    pub fn kind(&self) -> IndexManifestKind {
+        if self.parent.is_some() {
+            IndexManifestKind::Delta
+        } else {
+            IndexManifestKind::Full
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IndexManifestPart {
     pub no: u32,
@@ -18,6 +43,30 @@ pub struct IndexManifestPart {
     pub object_id: String,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexManifestParent {
+    pub snapshot_id: String,
+    pub manifest_object_id: String,
+    /// SHA-256 of the parent's encrypted manifest, checked like `remote_indexes.manifest_sha256`.
+    pub manifest_sha256: String,
+}
+
+/// `remote_indexes.manifest_kind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexManifestKind {
+    Full,
+    Delta,
+}
+
+impl IndexManifestKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Full => "full",
+            Self::Delta => "delta",
+        }
+    }
+}
+
 pub fn index_part_aad(snapshot_id: &str, part_no: u32) -> String {
     format!("{snapshot_id}:{part_no}")
 }
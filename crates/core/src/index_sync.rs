@@ -1,7 +1,13 @@
 use std::path::Path;
 
+use serde::{Deserialize, Serialize};
 use sqlx::Row;
 
+use crate::Error;
+use crate::bootstrap::PinnedStorage;
+use crate::crypto::{DataKey, KeyDerivation};
+use crate::index_db::snapshot_data_key_at;
+
 pub const ENDPOINT_STATE_ENDPOINT_INDEX_ID_KEY: &str = "endpoint_index_id";
 pub const ENDPOINT_STATE_ENDPOINT_MANIFEST_OBJECT_ID_KEY: &str = "endpoint_manifest_object_id";
 pub const ENDPOINT_STATE_ENDPOINT_DEDUPE_ID_KEY: &str = "endpoint_dedupe_id";
@@ -103,6 +109,219 @@ fn provider_kind(provider: &str) -> &str {
     provider.split(['/', ':']).next().unwrap_or(provider).trim()
 }
 
+/// What [`sync_index_from_remote`] syncs and whether it may skip up-to-date copies.
+#[derive(Debug, Clone, Default)]
+pub struct IndexSyncOptions {
+    pub target_id: String,
+    /// Sync this snapshot's file map instead of the one the catalog lists as the target's latest.
+    pub snapshot_id: Option<String>,
+    /// Download even when the local copies already match the catalog.
+    pub force: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexSyncOutcome {
+    /// Downloaded and written in place of the local copy, if there was one.
+    Replaced,
+    /// The local copy already matched the catalog.
+    Kept,
+    /// The catalog points at nothing to sync it from.
+    Missing,
+}
+
+impl IndexSyncOutcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Replaced => "replaced",
+            Self::Kept => "kept",
+            Self::Missing => "missing",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexSyncReport {
+    pub target_id: String,
+    /// Snapshot whose file map was synced; `None` when the target has no remote snapshot yet.
+    pub snapshot_id: Option<String>,
+    pub endpoint_db: IndexSyncOutcome,
+    pub filemap: IndexSyncOutcome,
+    pub bytes_downloaded: u64,
+}
+
+/// The remote-first index sync backups run in their preflight, on its own: brings the endpoint DB
+/// up to the catalog's `endpointLatest`, then caches the file map of the target's latest snapshot
+/// (or `options.snapshot_id`) in `filemap_dir`. Remote dedupe is left to the next backup.
+///
+/// Copies that already match the catalog are kept unless `options.force` is set. A snapshot the
+/// catalog does not list as latest is looked up in the (synced) endpoint DB.
+pub async fn sync_index_from_remote<S: PinnedStorage>(
+    storage: &S,
+    master_key: &[u8; 32],
+    endpoint_db_path: &Path,
+    filemap_dir: &Path,
+    options: &IndexSyncOptions,
+) -> crate::Result<IndexSyncReport> {
+    let catalog = crate::bootstrap::load_remote_catalog(storage, master_key)
+        .await?
+        .ok_or_else(|| Error::BootstrapMissing {
+            message: storage.bootstrap_pin_mode().missing_message().to_string(),
+        })?;
+    let provider = storage.provider();
+    let mut bytes_downloaded = 0u64;
+
+    let endpoint_db = match &catalog.endpoint_latest {
+        None => IndexSyncOutcome::Missing,
+        Some(latest)
+            if !options.force
+                && local_endpoint_db_matches_remote_latest(
+                    endpoint_db_path,
+                    &latest.manifest_object_id,
+                )
+                .await? =>
+        {
+            IndexSyncOutcome::Kept
+        }
+        Some(latest) => {
+            let stats = crate::remote_index_db::download_and_write_index_db_atomic(
+                storage,
+                &latest.endpoint_index_id,
+                &latest.manifest_object_id,
+                None,
+                master_key,
+                endpoint_db_path,
+                None,
+                Some(provider),
+                None,
+            )
+            .await?;
+            bytes_downloaded += stats.bytes_downloaded;
+            endpoint_state_set(
+                endpoint_db_path,
+                ENDPOINT_STATE_ENDPOINT_INDEX_ID_KEY,
+                &latest.endpoint_index_id,
+            )
+            .await?;
+            endpoint_state_set(
+                endpoint_db_path,
+                ENDPOINT_STATE_ENDPOINT_MANIFEST_OBJECT_ID_KEY,
+                &latest.manifest_object_id,
+            )
+            .await?;
+            IndexSyncOutcome::Replaced
+        }
+    };
+
+    let latest = catalog
+        .target(&options.target_id)
+        .and_then(|t| t.latest.clone());
+    let latest = match options.snapshot_id.as_deref() {
+        None => latest,
+        Some(id) => latest.filter(|l| l.snapshot_id == id),
+    };
+    let catalog_key_derivation = latest.as_ref().map(|l| l.key_derivation);
+    let pointer = match (latest, options.snapshot_id.as_deref()) {
+        (Some(l), _) => Some((l.snapshot_id, l.manifest_object_id, l.manifest_sha256)),
+        (None, None) => None,
+        (None, Some(id)) => Some(
+            remote_index_pointer(endpoint_db_path, id)
+                .await?
+                .ok_or_else(|| Error::InvalidConfig {
+                    message: format!(
+                        "snapshot {id} has no remote index in the catalog or the endpoint index"
+                    ),
+                })?,
+        ),
+    };
+    let Some((snapshot_id, manifest_object_id, manifest_sha256)) = pointer else {
+        return Ok(IndexSyncReport {
+            target_id: options.target_id.clone(),
+            snapshot_id: None,
+            endpoint_db,
+            filemap: IndexSyncOutcome::Missing,
+            bytes_downloaded,
+        });
+    };
+
+    let filemap_path = filemap_dir.join(format!("{snapshot_id}.sqlite"));
+    let filemap = if !options.force
+        && filemap_path.exists()
+        && local_index_matches_remote_latest(
+            endpoint_db_path,
+            provider,
+            &snapshot_id,
+            &manifest_object_id,
+        )
+        .await?
+    {
+        IndexSyncOutcome::Kept
+    } else {
+        std::fs::create_dir_all(filemap_dir)?;
+        if manifest_sha256.is_none() {
+            crate::remote_index_db::warn_manifest_unverified(&snapshot_id, &manifest_object_id);
+        }
+        // The endpoint DB records every snapshot's key; a catalog pointer carries it as well.
+        let data_key =
+            match snapshot_data_key_at(endpoint_db_path, master_key, &snapshot_id).await? {
+                Some(k) => k,
+                None => DataKey::for_snapshot(
+                    master_key,
+                    KeyDerivation::from_version(catalog_key_derivation.unwrap_or_default())?,
+                    Some(&options.target_id),
+                )?,
+            };
+        let stats = crate::remote_index_db::download_and_write_index_db_atomic(
+            storage,
+            &snapshot_id,
+            &manifest_object_id,
+            manifest_sha256.as_deref(),
+            data_key.key(),
+            &filemap_path,
+            None,
+            Some(provider),
+            None,
+        )
+        .await?;
+        bytes_downloaded += stats.bytes_downloaded;
+        IndexSyncOutcome::Replaced
+    };
+
+    Ok(IndexSyncReport {
+        target_id: options.target_id.clone(),
+        snapshot_id: Some(snapshot_id),
+        endpoint_db,
+        filemap,
+        bytes_downloaded,
+    })
+}
+
+/// `(snapshot_id, manifest_object_id, manifest_sha256)` recorded for `snapshot_id`.
+async fn remote_index_pointer(
+    db_path: &Path,
+    snapshot_id: &str,
+) -> crate::Result<Option<(String, String, Option<String>)>> {
+    if !db_path.exists() {
+        return Ok(None);
+    }
+    let pool = crate::index_db::open_existing_index_db(db_path).await?;
+    let row = sqlx::query(
+        "SELECT manifest_object_id, manifest_sha256 FROM remote_indexes WHERE snapshot_id = ? LIMIT 1",
+    )
+    .bind(snapshot_id)
+    .fetch_optional(&pool)
+    .await;
+    pool.close().await;
+    Ok(row?.map(|r| {
+        (
+            snapshot_id.to_string(),
+            r.get("manifest_object_id"),
+            r.get("manifest_sha256"),
+        )
+    }))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -162,4 +381,141 @@ mod tests {
                 .unwrap();
         assert!(!stale);
     }
+
+    #[tokio::test]
+    async fn sync_downloads_keeps_and_forces_endpoint_db_and_filemap() {
+        use crate::bootstrap::BootstrapEndpointLatest;
+        use crate::bootstrap::tests::MemPinned;
+
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("src");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::write(source.join("a.txt"), b"hello index sync").unwrap();
+
+        let storage = MemPinned::new();
+        let master_key = [5u8; 32];
+        let backup_db = dir.path().join("backup").join("index.sqlite");
+        let backup = crate::run_backup(
+            &storage,
+            crate::BackupConfig {
+                endpoint_db_path: backup_db.clone(),
+                filemap_dir: dir.path().join("backup").join("filemaps"),
+                dedupe_db_path: dir.path().join("backup").join("dedupe.sqlite"),
+                dedupe_pending_db_path: dir.path().join("backup").join("dedupe.pending.sqlite"),
+                source_path: source.clone(),
+                label: "manual".to_string(),
+                chunking: crate::ChunkingConfig {
+                    min_bytes: 64,
+                    avg_bytes: 256,
+                    max_bytes: 1024,
+                },
+                rate_limit: Default::default(),
+                master_key,
+                data_key: None,
+                snapshot_id: None,
+                keep_last_snapshots: 10,
+                remote_dedupe: crate::RemoteDedupeMode::Disabled,
+                hint_changed_paths: None,
+                device: None,
+                index_full_every: 1,
+                created_at: None,
+            },
+        )
+        .await
+        .unwrap();
+
+        let endpoint_latest = BootstrapEndpointLatest {
+            endpoint_index_id: endpoint_state_get(&backup_db, ENDPOINT_STATE_ENDPOINT_INDEX_ID_KEY)
+                .await
+                .unwrap()
+                .unwrap(),
+            manifest_object_id: endpoint_state_get(
+                &backup_db,
+                ENDPOINT_STATE_ENDPOINT_MANIFEST_OBJECT_ID_KEY,
+            )
+            .await
+            .unwrap()
+            .unwrap(),
+        };
+        let (_, manifest_object_id, manifest_sha256) =
+            remote_index_pointer(&backup_db, &backup.snapshot_id)
+                .await
+                .unwrap()
+                .unwrap();
+        crate::bootstrap::update_remote_latest(
+            &storage,
+            &master_key,
+            Some(endpoint_latest),
+            None,
+            "t1",
+            &source.display().to_string(),
+            "manual",
+            &backup.snapshot_id,
+            &manifest_object_id,
+            manifest_sha256.as_deref(),
+            KeyDerivation::Master,
+            None,
+        )
+        .await
+        .unwrap();
+
+        // A second machine with no local index.
+        let db_path = dir.path().join("other").join("index.sqlite");
+        let filemap_dir = dir.path().join("other").join("filemaps");
+        let mut options = IndexSyncOptions {
+            target_id: "t1".to_string(),
+            ..Default::default()
+        };
+
+        let report =
+            sync_index_from_remote(&storage, &master_key, &db_path, &filemap_dir, &options)
+                .await
+                .unwrap();
+        assert_eq!(
+            report.snapshot_id.as_deref(),
+            Some(backup.snapshot_id.as_str())
+        );
+        assert_eq!(report.endpoint_db, IndexSyncOutcome::Replaced);
+        assert_eq!(report.filemap, IndexSyncOutcome::Replaced);
+        assert!(report.bytes_downloaded > 0);
+        assert!(
+            filemap_dir
+                .join(format!("{}.sqlite", backup.snapshot_id))
+                .exists()
+        );
+
+        let report =
+            sync_index_from_remote(&storage, &master_key, &db_path, &filemap_dir, &options)
+                .await
+                .unwrap();
+        assert_eq!(report.endpoint_db, IndexSyncOutcome::Kept);
+        assert_eq!(report.filemap, IndexSyncOutcome::Kept);
+        assert_eq!(report.bytes_downloaded, 0);
+
+        options.force = true;
+        let report =
+            sync_index_from_remote(&storage, &master_key, &db_path, &filemap_dir, &options)
+                .await
+                .unwrap();
+        assert_eq!(report.endpoint_db, IndexSyncOutcome::Replaced);
+        assert_eq!(report.filemap, IndexSyncOutcome::Replaced);
+
+        options.force = false;
+        options.snapshot_id = Some("snp_unknown".to_string());
+        let err = sync_index_from_remote(&storage, &master_key, &db_path, &filemap_dir, &options)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("snp_unknown"), "{err}");
+
+        // Targets without a remote snapshot only get the endpoint DB.
+        options.target_id = "t2".to_string();
+        options.snapshot_id = None;
+        let report =
+            sync_index_from_remote(&storage, &master_key, &db_path, &filemap_dir, &options)
+                .await
+                .unwrap();
+        assert_eq!(report.snapshot_id, None);
+        assert_eq!(report.endpoint_db, IndexSyncOutcome::Kept);
+        assert_eq!(report.filemap, IndexSyncOutcome::Missing);
+    }
 }
@@ -0,0 +1,153 @@
+//! `targets[].label_template`: snapshot labels like `nightly-2024-06-01-macbook`.
+//!
+//! `{name}` is replaced by a variable from [`LABEL_TEMPLATE_VARS`]; `{{` and `}}` are literal
+//! braces.
+
+use crate::{Error, Result};
+
+pub const LABEL_TEMPLATE_VARS: &[&str] = &[
+    "schedule",
+    "date",
+    "time",
+    "hostname",
+    "target_id",
+    "device_name",
+];
+
+/// Values a template is expanded with, captured when the run starts.
+#[derive(Debug, Clone)]
+pub struct LabelVars<'a> {
+    /// Schedule kind of a scheduled run (`hourly`, `daily`), else `manual`.
+    pub schedule: &'a str,
+    /// Local start time: `{date}` is `YYYY-MM-DD`, `{time}` is `HHMM`.
+    pub local_time: chrono::NaiveDateTime,
+    pub hostname: &'a str,
+    pub target_id: &'a str,
+    pub device_name: &'a str,
+}
+
+enum Piece<'t> {
+    Literal(&'t str),
+    Var(&'t str),
+}
+
+fn parse(template: &str) -> std::result::Result<Vec<Piece<'_>>, String> {
+    let invalid = |message: String| format!("{message} in {template:?}");
+
+    let mut pieces = Vec::new();
+    let mut rest = template;
+    while let Some(pos) = rest.find(['{', '}']) {
+        if pos > 0 {
+            pieces.push(Piece::Literal(&rest[..pos]));
+        }
+        let brace = &rest[pos..pos + 1];
+        let after = &rest[pos + 1..];
+        if after.starts_with(brace) {
+            pieces.push(Piece::Literal(brace));
+            rest = &after[1..];
+            continue;
+        }
+        if brace == "}" {
+            return Err(invalid(
+                "unmatched `}` (write `}}` for a literal brace)".to_string(),
+            ));
+        }
+        let end = after
+            .find('}')
+            .ok_or_else(|| invalid("unclosed `{` (write `{{` for a literal brace)".to_string()))?;
+        let name = &after[..end];
+        if !LABEL_TEMPLATE_VARS.contains(&name) {
+            return Err(invalid(format!(
+                "unknown variable {{{name}}} (known: {})",
+                LABEL_TEMPLATE_VARS.join(", ")
+            )));
+        }
+        pieces.push(Piece::Var(name));
+        rest = &after[end + 1..];
+    }
+    if !rest.is_empty() {
+        pieces.push(Piece::Literal(rest));
+    }
+    Ok(pieces)
+}
+
+/// Why `template` is unusable (unknown variable, unbalanced brace), if it is.
+pub fn validate_label_template(template: &str) -> std::result::Result<(), String> {
+    parse(template).map(|_| ())
+}
+
+pub fn expand_label_template(template: &str, vars: &LabelVars<'_>) -> Result<String> {
+    let pieces = parse(template).map_err(|message| Error::InvalidConfig {
+        message: format!("label_template: {message}"),
+    })?;
+    let mut out = String::new();
+    for piece in pieces {
+        match piece {
+            Piece::Literal(s) => out.push_str(s),
+            Piece::Var("schedule") => out.push_str(vars.schedule),
+            Piece::Var("date") => out.push_str(&vars.local_time.format("%Y-%m-%d").to_string()),
+            Piece::Var("time") => out.push_str(&vars.local_time.format("%H%M").to_string()),
+            Piece::Var("hostname") => out.push_str(vars.hostname),
+            Piece::Var("target_id") => out.push_str(vars.target_id),
+            Piece::Var("device_name") => out.push_str(vars.device_name),
+            Piece::Var(other) => unreachable!("unchecked label variable {other}"),
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> LabelVars<'static> {
+        LabelVars {
+            schedule: "daily",
+            local_time: chrono::NaiveDate::from_ymd_opt(2024, 6, 1)
+                .unwrap()
+                .and_hms_opt(2, 5, 0)
+                .unwrap(),
+            hostname: "macbook",
+            target_id: "t1",
+            device_name: "Ivan's MacBook",
+        }
+    }
+
+    fn expand(template: &str) -> String {
+        expand_label_template(template, &vars()).unwrap()
+    }
+
+    #[test]
+    fn each_variable_expands() {
+        assert_eq!(expand("{schedule}"), "daily");
+        assert_eq!(expand("{date}"), "2024-06-01");
+        assert_eq!(expand("{time}"), "0205");
+        assert_eq!(expand("{hostname}"), "macbook");
+        assert_eq!(expand("{target_id}"), "t1");
+        assert_eq!(expand("{device_name}"), "Ivan's MacBook");
+        assert_eq!(
+            expand("nightly-{date}-{hostname}"),
+            "nightly-2024-06-01-macbook"
+        );
+        assert_eq!(expand("plain"), "plain");
+        assert_eq!(expand(""), "");
+    }
+
+    #[test]
+    fn doubled_braces_are_literal() {
+        assert_eq!(expand("{{date}}"), "{date}");
+        assert_eq!(expand("{{{date}}}"), "{2024-06-01}");
+        assert_eq!(expand("a}}b{{c"), "a}b{c");
+    }
+
+    #[test]
+    fn unknown_variables_and_stray_braces_are_rejected() {
+        for bad in ["{host}", "{date", "date}", "{}", "{ date }"] {
+            assert!(validate_label_template(bad).is_err(), "{bad}");
+            let err = expand_label_template(bad, &vars()).unwrap_err();
+            assert_eq!(err.error_code(), crate::ErrorCode::ConfigInvalid, "{bad}");
+        }
+        let err = validate_label_template("{host}").unwrap_err();
+        assert!(err.contains("unknown variable {host}"), "{err}");
+    }
+}
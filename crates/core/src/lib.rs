@@ -1,46 +1,102 @@
+#[cfg(test)]
+mod alloc_tracking;
+pub mod apfs_snapshot;
+pub mod audit;
 mod backup;
 pub mod bootstrap;
+mod case_fold;
+pub mod chat_audit;
+pub mod chat_remap;
+mod chunk_refs;
 pub mod config;
 pub mod config_bundle;
 pub mod control;
 mod crypto;
+pub mod data_dir;
+pub mod dedup_stats;
 pub mod dedupe_catalog;
 pub mod dedupe_sync;
+pub mod default_excludes;
+pub mod device;
 mod error;
+mod error_code;
+pub mod file_filter;
 pub mod folder_compare;
+mod fs_sync;
+pub mod full_disk_access;
 pub mod gold_key;
+pub mod health;
+pub mod history_import;
 pub mod index_db;
+mod index_delta;
 mod index_manifest;
 pub mod index_sync;
+pub mod label_template;
+mod long_paths;
+pub mod metrics_textfile;
+mod mounts;
+pub mod notifications;
+pub mod ownership;
 mod pack;
+pub mod privacy_audit;
 mod progress;
+pub mod quiet_hours;
+pub mod remote;
 pub mod remote_index_db;
+pub mod repo_export;
 mod restore;
+pub mod retry;
 pub mod run_log;
+pub mod schedule_tz;
 pub mod secrets;
+pub mod security;
+pub mod snapshot_listing;
+pub mod source_guard;
+pub mod sqlite_consistent;
 pub mod status;
 mod storage;
+pub mod units;
+pub mod usage;
+pub mod version;
 
 pub const APP_NAME: &str = "TelevyBackup";
 
 pub use backup::{
-    BackupConfig, BackupOptions, BackupResult, ChunkingConfig, RemoteDedupeMode, SourceQuickStats,
-    compute_source_quick_stats, run_backup, run_backup_with,
+    BackupConfig, BackupOptions, BackupResult, ChunkRefCheck, ChunkRefDrift, ChunkingConfig,
+    GC_DEFAULT_MIN_AGE_DAYS, GcConfig, GcOptions, GcResult, GcUndoResult, PendingDeletions,
+    RemoteDedupeMode, RepublishedIndex, SKIPPED_FILE_EXAMPLES_MAX, SkipReason, SkippedFile,
+    SnapshotChain, SnapshotChainLink, SnapshotVerifyState, SourcePreview, SourceQuickStats,
+    check_chunk_ref_counts, collect_garbage, compute_source_quick_stats, delete_snapshot,
+    flush_pending_deletions, pending_deletions, preview_source, rebase_snapshot,
+    record_snapshot_verified, republish_dedupe_base, republish_snapshot_index, run_backup,
+    run_backup_with, set_snapshot_pinned, snapshot_chain, snapshot_verify_state,
+    undo_snapshot_delete,
+};
+pub use crypto::{ChunkHashAlg, DataKey, KeyDerivation, derive_target_key};
+pub use error::{Error, Result, TelegramErrorKind, is_transient_telegram_message};
+pub use error_code::ErrorCode;
+pub use long_paths::{LONG_PATHS_DIR, LONG_PATHS_INDEX_FILE, PathLimits};
+pub use progress::{
+    PartialRunResult, Phase, PhaseTimings, ProgressRecorder, ProgressSink, TaskProgress,
 };
-pub use error::{Error, Result, is_transient_telegram_message};
-pub use progress::{ProgressSink, TaskProgress};
 pub use restore::{
-    RestoreConfig, RestoreOptions, RestoreResult, VerifyConfig, VerifyOptions, VerifyResult,
-    restore_snapshot, restore_snapshot_with, verify_snapshot, verify_snapshot_with,
+    DEFAULT_RESTORE_WRITE_CONCURRENCY, RestoreConfig, RestoreEstimate, RestoreFailure,
+    RestoreFsync, RestoreOptions, RestoreResult, VerifyConfig, VerifyOptions, VerifyResult,
+    VerifySample, estimate_restore, restore_snapshot, restore_snapshot_with, verify_snapshot,
+    verify_snapshot_with,
 };
+#[doc(hidden)]
+pub use restore::{RestoreFileSync, restore_snapshot_with_file_sync};
 pub use status::{
-    Counter, GlobalStatus, Progress, Rate, StatusSnapshot, StatusSource, TargetRunSummary,
-    TargetState, now_unix_ms, read_status_snapshot_json, status_json_path,
-    write_status_snapshot_json_atomic,
+    Counter, GlobalStatus, Progress, Rate, RunStatus, StatusFileWriter, StatusSnapshot,
+    StatusSource, TargetRunSummary, TargetState, now_unix_ms, read_status_snapshot_json,
+    status_json_path, write_status_snapshot_json_atomic,
 };
 pub use storage::{
-    ChunkObjectRef, InMemoryStorage, Storage, TelegramDialogInfo, TelegramMtProtoStorage,
-    TelegramMtProtoStorageConfig, TgMtProtoObjectIdV1, encode_tgfile_object_id,
-    encode_tgmtproto_object_id_v1, encode_tgpack_object_id, parse_chunk_object_ref,
-    parse_tgmtproto_object_id_v1,
+    ChunkObjectRef, DownloadBatch, InMemoryStorage, InMemoryStorageBuilder, LocalDirStorage,
+    MtProtoHelperVersion, ObjectCaption, ObjectKind, Storage, StorageCallCounts, StorageProgress,
+    TelegramDialogInfo, TelegramDocumentBatch, TelegramDocumentInfo, TelegramMtProtoStorage,
+    TelegramMtProtoStorageConfig, TgMtProtoObjectIdV1, UploadBody, UploadMetadata,
+    encode_tgfile_object_id, encode_tgmtproto_object_id_v1, encode_tgpack_object_id,
+    parse_chunk_object_ref, parse_tgmtproto_object_id_v1, probe_mtproto_helper,
 };
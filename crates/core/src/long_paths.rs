@@ -0,0 +1,245 @@
+//! Snapshot paths too long for the restore target's file system (`NAME_MAX` per component,
+//! `PATH_MAX` for the whole path), which would otherwise fail with `ENAMETOOLONG` halfway through
+//! a restore.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::case_fold::CaseRenames;
+
+/// Directory under the restore target that holds the entries restored under a shortened path.
+pub const LONG_PATHS_DIR: &str = ".televybackup-long-paths";
+/// `[{"path", "restoredPath"}]` of every shortened entry, inside [`LONG_PATHS_DIR`].
+pub const LONG_PATHS_INDEX_FILE: &str = "index.json";
+
+/// Length limits of a file system, in bytes. `path_max` counts the terminating NUL, like
+/// `PATH_MAX`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathLimits {
+    pub name_max: usize,
+    pub path_max: usize,
+}
+
+impl PathLimits {
+    /// The limits `pathconf` reports for `dir`; values it can't tell fall back to the macOS ones
+    /// (255 and 1024), the tightest this runs on.
+    pub fn probe(dir: &Path) -> std::io::Result<Self> {
+        let fallback = Self {
+            name_max: 255,
+            path_max: 1024,
+        };
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            let c_dir = std::ffi::CString::new(dir.as_os_str().as_bytes())?;
+            // SAFETY: `c_dir` is NUL-terminated and outlives the calls.
+            let (name_max, path_max) = unsafe {
+                (
+                    libc::pathconf(c_dir.as_ptr(), libc::_PC_NAME_MAX),
+                    libc::pathconf(c_dir.as_ptr(), libc::_PC_PATH_MAX),
+                )
+            };
+            Ok(Self {
+                name_max: usize::try_from(name_max).unwrap_or(fallback.name_max),
+                path_max: usize::try_from(path_max).unwrap_or(fallback.path_max),
+            })
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = dir;
+            Ok(fallback)
+        }
+    }
+
+    /// Whether `target/rel` can be created.
+    pub fn fits(&self, target: &Path, rel: &str) -> bool {
+        target.as_os_str().len() + 1 + rel.len() < self.path_max
+            && rel.split('/').all(|name| name.len() <= self.name_max)
+    }
+}
+
+/// Where each snapshot path lands in the target: the case renames, except for entries moved to
+/// [`LONG_PATHS_DIR`] because their path is too long.
+#[derive(Debug, Default)]
+pub(crate) struct RestorePaths {
+    pub(crate) case: CaseRenames,
+    /// Snapshot path -> target-relative path under [`LONG_PATHS_DIR`].
+    shortened: HashMap<String, String>,
+}
+
+impl RestorePaths {
+    pub(crate) fn new(case: CaseRenames) -> Self {
+        Self {
+            case,
+            shortened: HashMap::new(),
+        }
+    }
+
+    /// Where the snapshot path `rel` is restored, relative to the target.
+    pub(crate) fn apply(&self, rel: &str) -> String {
+        match self.shortened.get(rel) {
+            Some(restored) => restored.clone(),
+            None => self.case.apply(rel),
+        }
+    }
+
+    /// Snapshot paths whose restored path does not fit `limits` under `target`. With `shorten`,
+    /// each gets `LONG_PATHS_DIR/<hash of its path>/<its name, cut to fit>` instead, and only
+    /// those that don't fit even so are returned.
+    pub(crate) fn plan_long_paths(
+        &mut self,
+        paths: &[String],
+        target: &Path,
+        limits: PathLimits,
+        shorten: bool,
+    ) -> Vec<String> {
+        let mut too_long = Vec::new();
+        for path in paths {
+            if limits.fits(target, &self.case.apply(path)) {
+                continue;
+            }
+            let restored = shorten.then(|| shortened_path(path, limits.name_max));
+            match restored {
+                Some(restored) if limits.fits(target, &restored) => {
+                    self.shortened.insert(path.clone(), restored);
+                }
+                _ => too_long.push(path.clone()),
+            }
+        }
+        too_long.sort();
+        too_long
+    }
+
+    /// `(snapshot path, restored path)` of every shortened entry, sorted.
+    pub(crate) fn shortened(&self) -> Vec<(String, String)> {
+        let mut out = self
+            .shortened
+            .iter()
+            .map(|(path, restored)| (path.clone(), restored.clone()))
+            .collect::<Vec<_>>();
+        out.sort();
+        out
+    }
+
+    /// `path -> kind` of what shortening adds to the target besides the entries themselves.
+    pub(crate) fn shortened_entry_kinds(&self) -> Vec<(String, String)> {
+        if self.shortened.is_empty() {
+            return Vec::new();
+        }
+        let mut out = vec![
+            (LONG_PATHS_DIR.to_string(), "dir".to_string()),
+            (
+                format!("{LONG_PATHS_DIR}/{LONG_PATHS_INDEX_FILE}"),
+                "file".to_string(),
+            ),
+        ];
+        for restored in self.shortened.values() {
+            if let Some((parent, _)) = restored.rsplit_once('/') {
+                out.push((parent.to_string(), "dir".to_string()));
+            }
+        }
+        out
+    }
+
+    /// Writes [`LONG_PATHS_INDEX_FILE`] under `target`; nothing when no path was shortened.
+    pub(crate) fn write_index(&self, target: &Path) -> std::io::Result<()> {
+        if self.shortened.is_empty() {
+            return Ok(());
+        }
+        let entries = self
+            .shortened()
+            .into_iter()
+            .map(|(path, restored)| serde_json::json!({ "path": path, "restoredPath": restored }))
+            .collect::<Vec<_>>();
+        let dir = target.join(LONG_PATHS_DIR);
+        std::fs::create_dir_all(&dir)?;
+        let mut bytes = serde_json::to_vec_pretty(&entries)?;
+        bytes.push(b'\n');
+        std::fs::write(dir.join(LONG_PATHS_INDEX_FILE), bytes)
+    }
+}
+
+/// `LONG_PATHS_DIR/<16 hex chars of blake3(path)>/<name>`, the name cut to `name_max` bytes
+/// (keeping its extension when that is short).
+fn shortened_path(path: &str, name_max: usize) -> String {
+    let hash = blake3::hash(path.as_bytes()).to_hex();
+    let name = path.rsplit('/').next().unwrap_or(path);
+    format!(
+        "{LONG_PATHS_DIR}/{}/{}",
+        &hash.as_str()[..16],
+        truncate_name(name, name_max)
+    )
+}
+
+fn truncate_name(name: &str, max: usize) -> String {
+    if name.len() <= max {
+        return name.to_string();
+    }
+    let ext = match name.rfind('.') {
+        Some(i) if i > 0 && name.len() - i <= 16 && name.len() - i < max => &name[i..],
+        _ => "",
+    };
+    let mut end = max - ext.len();
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{ext}", &name[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(name_max: usize, path_max: usize) -> PathLimits {
+        PathLimits { name_max, path_max }
+    }
+
+    #[test]
+    fn fits_counts_the_target_separator_and_nul() {
+        let target = Path::new("/t");
+        assert!(limits(255, 8).fits(target, "abcd"));
+        assert!(!limits(255, 7).fits(target, "abcd"));
+        assert!(!limits(3, 1024).fits(target, "ab/abcd"));
+    }
+
+    #[test]
+    fn long_paths_fail_or_move_under_the_hashed_dir() {
+        let target = Path::new("/restore");
+        let deep = format!("deep/{}/file.txt", "d".repeat(60));
+        let paths = vec!["a.txt".to_string(), deep.clone()];
+        let tight = limits(255, 60);
+
+        let mut plan = RestorePaths::default();
+        assert_eq!(
+            plan.plan_long_paths(&paths, target, tight, false),
+            vec![deep.clone()]
+        );
+        assert!(plan.shortened().is_empty());
+
+        let mut plan = RestorePaths::default();
+        assert!(plan.plan_long_paths(&paths, target, tight, true).is_empty());
+        let restored = plan.apply(&deep);
+        assert!(restored.starts_with(&format!("{LONG_PATHS_DIR}/")));
+        assert!(restored.ends_with("/file.txt"));
+        assert!(tight.fits(target, &restored));
+        assert_eq!(plan.apply("a.txt"), "a.txt");
+        assert_eq!(plan.shortened(), vec![(deep, restored)]);
+
+        let mut plan = RestorePaths::default();
+        assert_eq!(
+            plan.plan_long_paths(&paths, target, limits(255, 30), true),
+            vec![paths[1].clone()]
+        );
+    }
+
+    #[test]
+    fn names_are_cut_at_char_boundaries_keeping_the_extension() {
+        assert_eq!(truncate_name("short.txt", 255), "short.txt");
+        assert_eq!(
+            truncate_name(&format!("{}.txt", "a".repeat(20)), 10),
+            "aaaaaa.txt"
+        );
+        assert_eq!(truncate_name("ééééé", 5), "éé");
+        assert_eq!(truncate_name("abcdefgh.verylongextension", 6), "abcdef");
+    }
+}
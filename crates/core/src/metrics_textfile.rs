@@ -0,0 +1,397 @@
+//! Prometheus textfile-collector output of CLI runs (`--metrics-textfile`,
+//! `metrics.textfile_path`).
+//!
+//! After a `backup run` or `verify run` the CLI merges the run's outcome into a `.prom` file that
+//! node_exporter's textfile collector picks up. Series are labelled by `target` and `kind`; a run
+//! replaces only its own series, so one file serves every target. Writers serialize on a lock of
+//! `<path>.lock` and replace the file atomically. A file that does not parse is replaced, with a
+//! warning.
+
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use tracing::warn;
+
+use crate::Result;
+
+/// The series written per run: name and `# HELP` text.
+pub const METRICS: &[(&str, &str)] = &[
+    (
+        "televybackup_last_run_timestamp",
+        "Unix time the last run finished.",
+    ),
+    (
+        "televybackup_last_run_success",
+        "1 if the last run succeeded, 0 otherwise.",
+    ),
+    (
+        "televybackup_bytes_uploaded",
+        "Bytes uploaded by the last run.",
+    ),
+    (
+        "televybackup_duration_seconds",
+        "Wall-clock duration of the last run.",
+    ),
+    (
+        "televybackup_snapshots_total",
+        "Snapshots of the target in the local index after the last run.",
+    ),
+];
+
+/// Outcome of one run, as written to the textfile.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunMetrics {
+    /// `backup` or `verify`.
+    pub kind: String,
+    /// Empty when the run is not tied to a configured target.
+    pub target_id: String,
+    pub finished_at_unix: i64,
+    pub success: bool,
+    pub bytes_uploaded: u64,
+    pub duration_seconds: f64,
+    /// `None` leaves the series out, e.g. when the local index could not be read.
+    pub snapshots_total: Option<u64>,
+}
+
+impl RunMetrics {
+    fn samples(&self) -> Vec<Sample> {
+        let labels = vec![
+            ("kind".to_string(), self.kind.clone()),
+            ("target".to_string(), self.target_id.clone()),
+        ];
+        let values = [
+            Some(self.finished_at_unix.to_string()),
+            Some(u8::from(self.success).to_string()),
+            Some(self.bytes_uploaded.to_string()),
+            Some(format!("{:.3}", self.duration_seconds)),
+            self.snapshots_total.map(|n| n.to_string()),
+        ];
+        METRICS
+            .iter()
+            .zip(values)
+            .filter_map(|((name, _), value)| {
+                Some(Sample {
+                    name: (*name).to_string(),
+                    labels: labels.clone(),
+                    value: value?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// One sample line: `name{label="value",...} value`. Labels are kept sorted by name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+    pub name: String,
+    pub labels: Vec<(String, String)>,
+    pub value: String,
+}
+
+/// Escapes a label value for the text exposition format.
+pub fn escape_label_value(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Parses the sample lines of a textfile; comments and blank lines are skipped. `Err` names the
+/// first line that is not a valid sample.
+pub fn parse_samples(text: &str) -> std::result::Result<Vec<Sample>, String> {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(i, line)| parse_sample(line).ok_or_else(|| format!("line {}: {line:?}", i + 1)))
+        .collect()
+}
+
+fn parse_sample(line: &str) -> Option<Sample> {
+    let name_end = line
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == ':'))
+        .unwrap_or(line.len());
+    let name = &line[..name_end];
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let mut rest = &line[name_end..];
+    let mut labels = Vec::new();
+    if let Some(body) = rest.strip_prefix('{') {
+        let mut chars = body.char_indices().peekable();
+        loop {
+            while chars.next_if(|(_, c)| *c == ',' || *c == ' ').is_some() {}
+            if let Some((i, '}')) = chars.peek().copied() {
+                chars.next();
+                rest = &body[i + 1..];
+                break;
+            }
+            let mut label = String::new();
+            while let Some((_, c)) = chars.next_if(|(_, c)| c.is_ascii_alphanumeric() || *c == '_')
+            {
+                label.push(c);
+            }
+            if label.is_empty() || chars.next()?.1 != '=' || chars.next()?.1 != '"' {
+                return None;
+            }
+            let mut value = String::new();
+            loop {
+                match chars.next()?.1 {
+                    '"' => break,
+                    '\\' => match chars.next()?.1 {
+                        'n' => value.push('\n'),
+                        c @ ('\\' | '"') => value.push(c),
+                        _ => return None,
+                    },
+                    c => value.push(c),
+                }
+            }
+            labels.push((label, value));
+        }
+    }
+
+    let mut fields = rest.split_whitespace();
+    let value = fields.next()?;
+    // An optional timestamp may follow; nothing else.
+    if fields.nth(1).is_some() || value.parse::<f64>().is_err() {
+        return None;
+    }
+    labels.sort();
+    Some(Sample {
+        name: name.to_string(),
+        labels,
+        value: value.to_string(),
+    })
+}
+
+/// `existing` with the series of `run`'s target and kind replaced by `run`'s.
+pub fn merge_samples(existing: Vec<Sample>, run: &RunMetrics) -> Vec<Sample> {
+    let ours = run.samples();
+    let mut merged: Vec<Sample> = existing
+        .into_iter()
+        .filter(|s| {
+            !(METRICS.iter().any(|(name, _)| *name == s.name)
+                && ours.first().is_some_and(|o| o.labels == s.labels))
+        })
+        .collect();
+    merged.extend(ours);
+    merged
+}
+
+/// The file's text: each known metric with `# HELP`/`# TYPE` and its samples sorted by labels,
+/// then any other samples as they were.
+pub fn render(samples: &[Sample]) -> String {
+    let mut by_name: BTreeMap<&str, Vec<&Sample>> = BTreeMap::new();
+    for s in samples {
+        by_name.entry(s.name.as_str()).or_default().push(s);
+    }
+    let mut out = String::new();
+    for (name, help) in METRICS {
+        let Some(samples) = by_name.remove(name) else {
+            continue;
+        };
+        out.push_str(&format!("# HELP {name} {help}\n# TYPE {name} gauge\n"));
+        write_samples(&mut out, samples);
+    }
+    for (_, samples) in by_name {
+        write_samples(&mut out, samples);
+    }
+    out
+}
+
+fn write_samples(out: &mut String, mut samples: Vec<&Sample>) {
+    samples.sort_by(|a, b| a.labels.cmp(&b.labels));
+    for s in samples {
+        out.push_str(&s.name);
+        if !s.labels.is_empty() {
+            let labels = s
+                .labels
+                .iter()
+                .map(|(k, v)| format!("{k}=\"{}\"", escape_label_value(v)))
+                .collect::<Vec<_>>();
+            out.push('{');
+            out.push_str(&labels.join(","));
+            out.push('}');
+        }
+        out.push(' ');
+        out.push_str(&s.value);
+        out.push('\n');
+    }
+}
+
+fn lock_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".lock");
+    path.with_file_name(name)
+}
+
+/// Merges `run` into the textfile at `path`, creating it (and its directory) if needed.
+pub fn write_run_metrics(path: &Path, run: &RunMetrics) -> Result<()> {
+    let dir = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    std::fs::create_dir_all(dir)?;
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(lock_path(path))?;
+    lock.lock()?;
+
+    let existing = match std::fs::read_to_string(path) {
+        Ok(text) => match parse_samples(&text) {
+            Ok(samples) => samples,
+            Err(e) => {
+                warn!(
+                    event = "metrics.textfile_replaced",
+                    path = %path.display(),
+                    error = %e,
+                    "metrics textfile does not parse; replacing it"
+                );
+                Vec::new()
+            }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let text = render(&merge_samples(existing, run));
+
+    let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
+    tmp.write_all(text.as_bytes())?;
+    tmp.as_file().sync_all()?;
+    // node_exporter usually runs as another user.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        tmp.as_file()
+            .set_permissions(std::fs::Permissions::from_mode(0o644))?;
+    }
+    tmp.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(target: &str, kind: &str, success: bool) -> RunMetrics {
+        RunMetrics {
+            kind: kind.to_string(),
+            target_id: target.to_string(),
+            finished_at_unix: 1_700_000_000,
+            success,
+            bytes_uploaded: 42,
+            duration_seconds: 1.5,
+            snapshots_total: Some(3),
+        }
+    }
+
+    #[test]
+    fn merging_replaces_only_the_runs_own_series() {
+        let first = render(&merge_samples(Vec::new(), &run("home", "backup", true)));
+        let second = render(&merge_samples(
+            parse_samples(&first).unwrap(),
+            &run("docs", "backup", true),
+        ));
+        let third = render(&merge_samples(
+            parse_samples(&second).unwrap(),
+            &run("home", "backup", false),
+        ));
+
+        let samples = parse_samples(&third).unwrap();
+        assert_eq!(samples.len(), 2 * METRICS.len());
+        let success = |target: &str| {
+            samples
+                .iter()
+                .find(|s| {
+                    s.name == "televybackup_last_run_success"
+                        && s.labels
+                            .contains(&("target".to_string(), target.to_string()))
+                })
+                .map(|s| s.value.clone())
+        };
+        assert_eq!(success("home").as_deref(), Some("0"));
+        assert_eq!(success("docs").as_deref(), Some("1"));
+        // Each metric gets its HELP and TYPE once.
+        assert_eq!(
+            third
+                .matches("# TYPE televybackup_last_run_success gauge")
+                .count(),
+            1
+        );
+
+        let verify = merge_samples(samples, &run("home", "verify", true));
+        assert_eq!(verify.len(), 3 * METRICS.len());
+    }
+
+    #[test]
+    fn rendering_and_parsing_keep_escaped_labels_and_foreign_samples() {
+        assert_eq!(escape_label_value(r#"a\b"c"#), r#"a\\b\"c"#);
+        assert_eq!(escape_label_value("line\nbreak"), r"line\nbreak");
+
+        let odd = r#"we"ird\tar
+get"#;
+        let text = render(&merge_samples(
+            parse_samples("other_metric{job=\"x\"} 7 1700000000\n").unwrap(),
+            &RunMetrics {
+                snapshots_total: None,
+                ..run(odd, "backup", true)
+            },
+        ));
+        assert!(text.contains(r#"target="we\"ird\\tar\nget""#), "{text}");
+        assert!(!text.contains("televybackup_snapshots_total"));
+        assert!(text.ends_with("other_metric{job=\"x\"} 7\n"), "{text}");
+
+        let samples = parse_samples(&text).unwrap();
+        assert!(
+            samples
+                .iter()
+                .any(|s| s.labels.contains(&("target".to_string(), odd.to_string())))
+        );
+    }
+
+    #[test]
+    fn malformed_lines_are_rejected() {
+        for line in [
+            "no_value",
+            "9starts_with_digit 1",
+            "m{target=\"unterminated} 1",
+            "m{target=unquoted} 1",
+            "m{a=\"\\x\"} 1",
+            "m 1 2 3",
+            "m not_a_number",
+        ] {
+            assert!(parse_samples(line).is_err(), "{line}");
+        }
+        assert!(parse_samples("# comment only\n\nm{} +Inf\n").is_ok());
+    }
+
+    #[test]
+    fn write_replaces_a_malformed_file_and_keeps_other_targets() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let path = temp.path().join("textfile").join("televybackup.prom");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, "garbage {{{\n").unwrap();
+
+        write_run_metrics(&path, &run("home", "backup", true)).unwrap();
+        write_run_metrics(&path, &run("docs", "verify", true)).unwrap();
+        let samples = parse_samples(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(samples.len(), 2 * METRICS.len());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o644);
+        }
+    }
+}
@@ -0,0 +1,246 @@
+//! `scan.one_file_system`: backups stay on the source root's file system and skip directories
+//! that are mount points of another one (an SMB share under the home directory, a FUSE mount).
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use tracing::warn;
+
+/// A directory the scan did not enter because another file system is mounted on it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SkippedMount {
+    pub path: PathBuf,
+    /// From the mount table, when the mount point is listed there.
+    pub fs_type: Option<String>,
+}
+
+/// Decides, directory by directory, whether the walk stays on the root's device.
+#[derive(Debug)]
+pub(crate) struct MountBoundary {
+    root_dev: u64,
+    skipped: Mutex<Vec<SkippedMount>>,
+}
+
+impl MountBoundary {
+    /// `None` where the platform reports no device ids (the walk then crosses everything).
+    pub(crate) fn for_root(root: &Path) -> Option<Self> {
+        let dev = device_id(&std::fs::metadata(root).ok()?)?;
+        Some(Self::new(dev))
+    }
+
+    pub(crate) fn new(root_dev: u64) -> Self {
+        Self {
+            root_dev,
+            skipped: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Whether to enter the directory `path` on device `dev`; records it as skipped if not.
+    pub(crate) fn enter_dir(
+        &self,
+        path: &Path,
+        dev: u64,
+        fs_type: impl FnOnce() -> Option<String>,
+    ) -> bool {
+        if dev == self.root_dev {
+            return true;
+        }
+        let fs_type = fs_type();
+        if fs_type.as_deref().is_some_and(is_network_fs_type) {
+            warn!(
+                event = "scan.network_mount_skipped",
+                path = %path.display(),
+                fs_type = fs_type.as_deref().unwrap_or(""),
+                "skipping network/FUSE mount {} ({}); set scan.one_file_system = false or pass --cross-filesystems to back it up",
+                path.display(),
+                fs_type.as_deref().unwrap_or("")
+            );
+        } else {
+            warn!(
+                event = "scan.mount_skipped",
+                path = %path.display(),
+                fs_type = fs_type.as_deref().unwrap_or(""),
+                "scan.mount_skipped"
+            );
+        }
+        self.lock().push(SkippedMount {
+            path: path.to_path_buf(),
+            fs_type,
+        });
+        false
+    }
+
+    /// [`MountBoundary::enter_dir`] for a walk entry; entries that aren't directories always pass.
+    pub(crate) fn allows(&self, entry: &ignore::DirEntry) -> bool {
+        if entry.depth() == 0 || !entry.file_type().is_some_and(|t| t.is_dir()) {
+            return true;
+        }
+        let Some(dev) = entry.metadata().ok().as_ref().and_then(device_id) else {
+            return true;
+        };
+        self.enter_dir(entry.path(), dev, || mount_fs_type(entry.path()))
+    }
+
+    pub(crate) fn skipped(&self) -> Vec<SkippedMount> {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<SkippedMount>> {
+        self.skipped.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(unix)]
+fn device_id(metadata: &std::fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.dev())
+}
+
+#[cfg(not(unix))]
+fn device_id(_metadata: &std::fs::Metadata) -> Option<u64> {
+    None
+}
+
+/// SMB/NFS/AFP/WebDAV shares and FUSE file systems (macFUSE, sshfs, ...).
+pub(crate) fn is_network_fs_type(fs_type: &str) -> bool {
+    let t = fs_type.to_ascii_lowercase();
+    matches!(
+        t.as_str(),
+        "smbfs" | "cifs" | "smb3" | "nfs" | "nfs4" | "afpfs" | "webdav" | "9p" | "ceph"
+    ) || t.contains("fuse")
+}
+
+/// One line of the mount table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct MountEntry {
+    /// What is mounted, e.g. `/dev/disk4s1` or `//me@nas/share`.
+    pub device: String,
+    pub path: String,
+    pub fs_type: String,
+}
+
+/// The file system type mounted at `mount_point`, from the mount table.
+fn mount_fs_type(mount_point: &Path) -> Option<String> {
+    let table = mount_table()?;
+    parse_mount_table(&table)
+        .into_iter()
+        .rev()
+        .find(|m| Path::new(&m.path) == mount_point)
+        .map(|m| m.fs_type)
+}
+
+/// The mount holding `path`: the table entry with the longest mount point above it (the latest
+/// one when a mount point is listed twice). `path` should be canonical.
+pub(crate) fn containing_mount(path: &Path) -> Option<MountEntry> {
+    let table = mount_table()?;
+    parse_mount_table(&table)
+        .into_iter()
+        .enumerate()
+        .filter(|(_, m)| path.starts_with(&m.path))
+        .max_by_key(|(idx, m)| (m.path.len(), *idx))
+        .map(|(_, m)| m)
+}
+
+#[cfg(target_os = "linux")]
+fn mount_table() -> Option<String> {
+    std::fs::read_to_string("/proc/self/mounts").ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn mount_table() -> Option<String> {
+    let out = std::process::Command::new("/sbin/mount").output().ok()?;
+    out.status
+        .success()
+        .then(|| String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+/// Entries from `/proc/self/mounts` lines (`dev path type opts 0 0`, spaces in paths as
+/// `\040`) or BSD `mount` lines (`dev on path (type, opts)`).
+fn parse_mount_table(table: &str) -> Vec<MountEntry> {
+    table
+        .lines()
+        .filter_map(|line| {
+            if let Some((device, rest)) = line.split_once(" on ") {
+                let (path, opts) = rest.rsplit_once(" (")?;
+                let fs_type = opts.split([',', ')']).next()?.trim();
+                return Some(MountEntry {
+                    device: device.to_string(),
+                    path: path.to_string(),
+                    fs_type: fs_type.to_string(),
+                });
+            }
+            let mut fields = line.split_whitespace();
+            let device = fields.next()?.replace("\\040", " ");
+            let path = fields.next()?.replace("\\040", " ");
+            let fs_type = fields.next()?;
+            Some(MountEntry {
+                device,
+                path,
+                fs_type: fs_type.to_string(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn walk_stays_on_the_root_device() {
+        let boundary = MountBoundary::new(7);
+        let mut looked_up = false;
+        assert!(boundary.enter_dir(Path::new("/src/docs"), 7, || {
+            looked_up = true;
+            None
+        }));
+        assert!(!looked_up);
+        assert!(!boundary.enter_dir(Path::new("/src/nas"), 9, || Some("smbfs".to_string())));
+        assert!(!boundary.enter_dir(Path::new("/src/usb"), 8, || None));
+        assert_eq!(
+            boundary.skipped(),
+            vec![
+                SkippedMount {
+                    path: PathBuf::from("/src/nas"),
+                    fs_type: Some("smbfs".to_string()),
+                },
+                SkippedMount {
+                    path: PathBuf::from("/src/usb"),
+                    fs_type: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn mount_tables_parse_on_linux_and_macos() {
+        let linux = "/dev/sda1 / ext4 rw 0 0\n//nas/share /home/me/My\\040Share cifs rw 0 0\n";
+        let pairs = |table: &str| {
+            parse_mount_table(table)
+                .into_iter()
+                .map(|m| (m.path, m.fs_type))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            pairs(linux),
+            vec![
+                ("/".to_string(), "ext4".to_string()),
+                ("/home/me/My Share".to_string(), "cifs".to_string()),
+            ]
+        );
+        let macos = "/dev/disk3s1s1 on / (apfs, sealed, local, read-only, journaled)\n//me@nas/share on /Volumes/share (smbfs, nodev, nosuid, mounted by me)\n";
+        assert_eq!(
+            pairs(macos),
+            vec![
+                ("/".to_string(), "apfs".to_string()),
+                ("/Volumes/share".to_string(), "smbfs".to_string()),
+            ]
+        );
+        assert_eq!(parse_mount_table(macos)[1].device, "//me@nas/share");
+        assert_eq!(parse_mount_table(linux)[0].device, "/dev/sda1");
+        assert!(is_network_fs_type("smbfs"));
+        assert!(is_network_fs_type("fuse.sshfs"));
+        assert!(is_network_fs_type("macfuse"));
+        assert!(!is_network_fs_type("apfs"));
+    }
+}
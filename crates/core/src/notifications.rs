@@ -0,0 +1,637 @@
+//! Daily digest notifications (`[notifications]`): one summary of every target's last 24 hours,
+//! POSTed as JSON to `webhook_url` and published as text to the ntfy topic at `ntfy_url`.
+//!
+//! The digest names targets by id; source paths only leave the machine with `include_paths`.
+//! Each send is one HTTP/1.1 request per connection; `https://` URLs are checked against the
+//! bundled web PKI roots.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use rustls::pki_types::ServerName;
+use serde::Serialize;
+
+use crate::config::{Notifications, SettingsV2};
+use crate::health;
+use crate::status::RunStatus;
+use crate::units::human_bytes;
+use crate::usage::UsageRun;
+
+/// How far back the digest counts runs.
+pub fn digest_window() -> TimeDelta {
+    TimeDelta::hours(24)
+}
+
+/// Connect, write and read timeout of one send.
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+const USER_AGENT: &str = concat!("televybackupd/", env!("CARGO_PKG_VERSION"));
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DigestTarget {
+    pub target_id: String,
+    /// Only with `notifications.include_paths`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_path: Option<String>,
+    pub enabled: bool,
+    /// Backups that finished in the window; the rest of `runs` were cancelled.
+    pub runs: u64,
+    pub runs_succeeded: u64,
+    pub runs_failed: u64,
+    pub bytes_uploaded: u64,
+    /// RFC3339.
+    pub last_success_at: Option<String>,
+    /// The last success is older than the target's schedule allows ([`health::is_stale`]).
+    pub stale: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Digest {
+    /// RFC3339.
+    pub generated_at: String,
+    pub window_hours: i64,
+    /// Targets with a successful backup in the window.
+    pub targets_succeeded: u64,
+    /// Targets whose backups in the window failed without one succeeding.
+    pub targets_failed: u64,
+    pub bytes_uploaded: u64,
+    /// The enabled target whose last successful backup is the oldest.
+    pub oldest_success_target_id: Option<String>,
+    /// RFC3339.
+    pub oldest_success_at: Option<String>,
+    pub targets: Vec<DigestTarget>,
+}
+
+/// Summarizes the backups in `runs` that finished within [`digest_window`] before `now`, with
+/// each target's last successful backup from `last_success` (targets missing there never
+/// succeeded).
+pub fn build_digest(
+    settings: &SettingsV2,
+    runs: &[UsageRun],
+    last_success: &HashMap<String, DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> Digest {
+    let since = now - digest_window();
+    let mut digest = Digest {
+        generated_at: rfc3339(now),
+        window_hours: digest_window().num_hours(),
+        targets_succeeded: 0,
+        targets_failed: 0,
+        bytes_uploaded: 0,
+        oldest_success_target_id: None,
+        oldest_success_at: None,
+        targets: Vec::new(),
+    };
+    let mut oldest: Option<(&str, DateTime<Utc>)> = None;
+
+    for target in &settings.targets {
+        let enabled = target.enabled_at(&now);
+        let last = last_success.get(&target.id).copied();
+        let period = health::target_schedule_period(settings, target).filter(|_| enabled);
+        let mut t = DigestTarget {
+            target_id: target.id.clone(),
+            source_path: settings
+                .notifications
+                .include_paths
+                .then(|| target.source_path.clone()),
+            enabled,
+            runs: 0,
+            runs_succeeded: 0,
+            runs_failed: 0,
+            bytes_uploaded: 0,
+            last_success_at: last.map(rfc3339),
+            stale: health::is_stale(period, last, now),
+        };
+        for run in runs
+            .iter()
+            .filter(|r| r.kind == "backup" && r.target_id.as_deref() == Some(target.id.as_str()))
+        {
+            let in_window = DateTime::parse_from_rfc3339(&run.finished_at)
+                .is_ok_and(|at| at >= since && at <= now);
+            if !in_window {
+                continue;
+            }
+            t.runs += 1;
+            t.bytes_uploaded += run.bytes;
+            if run.status == RunStatus::Succeeded.as_str() {
+                t.runs_succeeded += 1;
+            } else if run.status == RunStatus::Failed.as_str() {
+                t.runs_failed += 1;
+            }
+        }
+
+        if t.runs_succeeded > 0 {
+            digest.targets_succeeded += 1;
+        } else if t.runs_failed > 0 {
+            digest.targets_failed += 1;
+        }
+        digest.bytes_uploaded += t.bytes_uploaded;
+        if let Some(at) = last.filter(|_| enabled)
+            && oldest.is_none_or(|(_, o)| at < o)
+        {
+            oldest = Some((&target.id, at));
+        }
+        digest.targets.push(t);
+    }
+
+    if let Some((id, at)) = oldest {
+        digest.oldest_success_target_id = Some(id.to_string());
+        digest.oldest_success_at = Some(rfc3339(at));
+    }
+    digest
+}
+
+impl Digest {
+    pub fn stale_targets(&self) -> impl Iterator<Item = &DigestTarget> {
+        self.targets.iter().filter(|t| t.stale)
+    }
+
+    /// Failed or missed backups.
+    pub fn needs_attention(&self) -> bool {
+        self.targets_failed > 0 || self.stale_targets().next().is_some()
+    }
+
+    pub fn title(&self) -> String {
+        let stale = self.stale_targets().count();
+        if self.targets_failed == 0 && stale == 0 {
+            return "TelevyBackup: all backups on schedule".to_string();
+        }
+        format!(
+            "TelevyBackup: {} failed, {} behind schedule",
+            plural(self.targets_failed, "target"),
+            plural(stale as u64, "target"),
+        )
+    }
+
+    /// Plain-text body, as published to ntfy.
+    pub fn text(&self) -> String {
+        let now = DateTime::parse_from_rfc3339(&self.generated_at)
+            .map(|t| t.to_utc())
+            .unwrap_or_else(|_| Utc::now());
+        let ago = |at: &str| {
+            DateTime::parse_from_rfc3339(at)
+                .map(|at| human_age(now - at.to_utc()))
+                .unwrap_or_else(|_| at.to_string())
+        };
+        let name = |t: &DigestTarget| match &t.source_path {
+            Some(path) => format!("{} ({path})", t.target_id),
+            None => t.target_id.clone(),
+        };
+
+        let mut lines = vec![format!(
+            "{} backed up, {} failed, {} uploaded in the last {}h.",
+            plural(self.targets_succeeded, "target"),
+            self.targets_failed,
+            human_bytes(self.bytes_uploaded),
+            self.window_hours,
+        )];
+        if let (Some(id), Some(at)) = (&self.oldest_success_target_id, &self.oldest_success_at) {
+            lines.push(format!(
+                "Oldest successful backup is {} old ({id}).",
+                ago(at)
+            ));
+        }
+        let failed = self
+            .targets
+            .iter()
+            .filter(|t| t.runs_failed > 0 && t.runs_succeeded == 0)
+            .map(name)
+            .collect::<Vec<_>>();
+        if !failed.is_empty() {
+            lines.push(format!("Failed: {}.", failed.join(", ")));
+        }
+        let stale = self
+            .stale_targets()
+            .map(|t| match &t.last_success_at {
+                Some(at) => format!("- {}: last success {} ago", name(t), ago(at)),
+                None => format!("- {}: never backed up", name(t)),
+            })
+            .collect::<Vec<_>>();
+        if !stale.is_empty() {
+            lines.push("Behind schedule:".to_string());
+            lines.extend(stale);
+        }
+        lines.join("\n")
+    }
+}
+
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    #[serde(rename = "type")]
+    type_: &'static str,
+    title: String,
+    text: String,
+    #[serde(flatten)]
+    digest: &'a Digest,
+}
+
+/// Sends `digest` to the webhook and the ntfy topic that are configured. Both are tried; the
+/// first failure is returned. Blocking.
+pub fn send_digest(notifications: &Notifications, digest: &Digest) -> std::io::Result<()> {
+    let mut result = Ok(());
+    if let Some(url) = notifications.webhook_url.as_deref() {
+        let payload = WebhookPayload {
+            type_: "backup.digest",
+            title: digest.title(),
+            text: digest.text(),
+            digest,
+        };
+        let body = serde_json::to_vec(&payload).map_err(std::io::Error::other)?;
+        result = http_post(url, "application/json", &[], &body);
+    }
+    if let Some(url) = notifications.ntfy_url.as_deref() {
+        let (priority, tags) = if digest.needs_attention() {
+            ("high", "warning")
+        } else {
+            ("default", "white_check_mark")
+        };
+        let title = digest.title();
+        let sent = http_post(
+            url,
+            "text/plain; charset=utf-8",
+            &[("Title", &title), ("Priority", priority), ("Tags", tags)],
+            digest.text().as_bytes(),
+        );
+        result = result.and(sent);
+    }
+    result
+}
+
+/// An `http://` or `https://` URL, split for one request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpUrl {
+    pub tls: bool,
+    pub host: String,
+    pub port: u16,
+    /// Path and query, `/` when the URL has none.
+    pub path: String,
+}
+
+impl HttpUrl {
+    fn default_port(&self) -> u16 {
+        if self.tls { 443 } else { 80 }
+    }
+
+    /// `Host` header value.
+    fn authority(&self) -> String {
+        let host = if self.host.contains(':') {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        };
+        if self.port == self.default_port() {
+            host
+        } else {
+            format!("{host}:{}", self.port)
+        }
+    }
+}
+
+/// Errors read as the rest of a sentence about the URL ("must start with http:// or https://").
+pub fn parse_http_url(url: &str) -> Result<HttpUrl, String> {
+    let url = url.trim();
+    let (tls, rest) = if let Some(rest) = url.strip_prefix("https://") {
+        (true, rest)
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        (false, rest)
+    } else {
+        return Err("must start with http:// or https://".to_string());
+    };
+    let rest = rest.split('#').next().unwrap_or_default();
+    let (authority, path) = match rest.find(['/', '?']) {
+        Some(i) if rest[i..].starts_with('?') => (&rest[..i], format!("/{}", &rest[i..])),
+        Some(i) => (&rest[..i], rest[i..].to_string()),
+        None => (rest, "/".to_string()),
+    };
+    if authority.contains('@') {
+        return Err("must not carry credentials".to_string());
+    }
+    let (host, port) = match authority.strip_prefix('[') {
+        Some(bracketed) => {
+            let (host, after) = bracketed
+                .split_once(']')
+                .ok_or_else(|| "has an unterminated IPv6 address".to_string())?;
+            (host, after.strip_prefix(':'))
+        }
+        None => match authority.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        },
+    };
+    if host.is_empty() {
+        return Err("must name a host".to_string());
+    }
+    let port = match port {
+        Some(p) => p
+            .parse::<u16>()
+            .map_err(|_| format!("has an invalid port {p:?}"))?,
+        None if tls => 443,
+        None => 80,
+    };
+    if path.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err("must not contain spaces".to_string());
+    }
+    Ok(HttpUrl {
+        tls,
+        host: host.to_string(),
+        port,
+        path,
+    })
+}
+
+fn web_pki_tls_config() -> std::io::Result<Arc<rustls::ClientConfig>> {
+    let roots = rustls::RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let config = rustls::ClientConfig::builder_with_provider(crate::remote::crypto_provider())
+        .with_safe_default_protocol_versions()
+        .map_err(std::io::Error::other)?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(Arc::new(config))
+}
+
+/// POSTs `body` and fails unless the response status is 2xx.
+fn http_post(
+    url: &str,
+    content_type: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> std::io::Result<()> {
+    let url = parse_http_url(url)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("url {e}")))?;
+    let tcp = crate::remote::connect_tcp((url.host.as_str(), url.port), HTTP_TIMEOUT)?;
+
+    let mut head = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: {USER_AGENT}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        url.path,
+        url.authority(),
+        body.len()
+    );
+    for (name, value) in headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+
+    let status_line = if url.tls {
+        let name = ServerName::try_from(url.host.clone()).map_err(std::io::Error::other)?;
+        let conn = rustls::ClientConnection::new(web_pki_tls_config()?, name)
+            .map_err(std::io::Error::other)?;
+        let mut stream = rustls::StreamOwned::new(conn, tcp);
+        exchange(&mut stream, head.as_bytes(), body)?
+    } else {
+        let mut tcp = tcp;
+        exchange(&mut tcp, head.as_bytes(), body)?
+    };
+
+    let status = status_line
+        .strip_prefix("HTTP/1.")
+        .and_then(|rest| rest.split_whitespace().nth(1))
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("not an HTTP response: {:?}", status_line.trim_end()),
+            )
+        })?;
+    if !(200..300).contains(&status) {
+        return Err(std::io::Error::other(format!(
+            "{}:{} answered HTTP {status}",
+            url.host, url.port
+        )));
+    }
+    Ok(())
+}
+
+/// Writes the request and returns the response's status line.
+fn exchange<S: Read + Write>(stream: &mut S, head: &[u8], body: &[u8]) -> std::io::Result<String> {
+    stream.write_all(head)?;
+    stream.write_all(body)?;
+    stream.flush()?;
+    crate::remote::read_line(stream)
+}
+
+fn rfc3339(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+fn plural(n: u64, noun: &str) -> String {
+    if n == 1 {
+        format!("1 {noun}")
+    } else {
+        format!("{n} {noun}s")
+    }
+}
+
+/// `45m`, `5h`, `6d`.
+fn human_age(age: TimeDelta) -> String {
+    if age >= TimeDelta::days(1) {
+        format!("{}d", age.num_days())
+    } else if age >= TimeDelta::hours(1) {
+        format!("{}h", age.num_hours())
+    } else {
+        format!("{}m", age.num_minutes().max(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+
+    use super::*;
+    use crate::config::Target;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().to_utc()
+    }
+
+    fn target(id: &str, source_path: &str) -> Target {
+        toml::from_str(&format!(
+            "id = {id:?}\nsource_path = {source_path:?}\nendpoint_id = \"ep1\"\n"
+        ))
+        .unwrap()
+    }
+
+    fn run(finished_at: &str, target_id: &str, bytes: u64, status: RunStatus) -> UsageRun {
+        UsageRun {
+            finished_at: finished_at.to_string(),
+            kind: "backup".to_string(),
+            target_id: Some(target_id.to_string()),
+            bytes,
+            duration_seconds: 1.0,
+            status: status.as_str().to_string(),
+        }
+    }
+
+    fn settings() -> SettingsV2 {
+        let mut settings = SettingsV2::default();
+        settings.schedule.enabled = true;
+        settings.schedule.kind = "daily".to_string();
+        settings.targets = vec![
+            target("docs", "/Users/me/Documents"),
+            target("photos", "/Users/me/Pictures"),
+            target("music", "/Users/me/Music"),
+        ];
+        settings
+    }
+
+    fn digest(settings: &SettingsV2) -> Digest {
+        let now = at("2024-06-10T08:00:00Z");
+        let runs = [
+            run("2024-06-09T07:00:00Z", "docs", 999, RunStatus::Succeeded),
+            run("2024-06-09T09:00:00Z", "docs", 100, RunStatus::Failed),
+            run("2024-06-10T02:00:00Z", "docs", 2048, RunStatus::Succeeded),
+            run("2024-06-10T02:10:00Z", "photos", 0, RunStatus::Failed),
+            run("2024-06-10T02:20:00Z", "photos", 0, RunStatus::Failed),
+        ];
+        let last_success = HashMap::from([
+            ("docs".to_string(), at("2024-06-10T02:00:00Z")),
+            ("photos".to_string(), at("2024-06-04T02:00:00Z")),
+        ]);
+        build_digest(settings, &runs, &last_success, now)
+    }
+
+    #[test]
+    fn digest_counts_the_last_day_and_flags_targets_behind_schedule() {
+        let d = digest(&settings());
+        assert_eq!(d.targets_succeeded, 1);
+        assert_eq!(d.targets_failed, 1);
+        assert_eq!(d.bytes_uploaded, 2148);
+        assert_eq!(d.oldest_success_target_id.as_deref(), Some("photos"));
+        let docs = &d.targets[0];
+        assert_eq!(
+            (docs.runs, docs.runs_succeeded, docs.runs_failed),
+            (2, 1, 1)
+        );
+        assert!(!docs.stale);
+        assert_eq!(
+            d.stale_targets()
+                .map(|t| t.target_id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["photos", "music"]
+        );
+        assert!(d.needs_attention());
+        assert_eq!(
+            d.text(),
+            "1 target backed up, 1 failed, 2.1 KiB uploaded in the last 24h.\n\
+             Oldest successful backup is 6d old (photos).\n\
+             Failed: photos.\n\
+             Behind schedule:\n\
+             - photos: last success 6d ago\n\
+             - music: never backed up"
+        );
+        assert_eq!(
+            d.title(),
+            "TelevyBackup: 1 target failed, 2 targets behind schedule"
+        );
+        assert!(d.targets.iter().all(|t| t.source_path.is_none()));
+    }
+
+    /// Serves one request per connection and hands back its head and body.
+    fn serve(
+        status: &'static str,
+        connections: usize,
+    ) -> (String, std::thread::JoinHandle<Vec<(String, String)>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let handle = std::thread::spawn(move || {
+            let mut out = Vec::new();
+            for _ in 0..connections {
+                let (tcp, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(tcp.try_clone().unwrap());
+                let mut head = String::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    head.push_str(&line);
+                }
+                let len = head
+                    .lines()
+                    .find_map(|l| l.strip_prefix("Content-Length: "))
+                    .unwrap()
+                    .parse::<usize>()
+                    .unwrap();
+                let mut body = vec![0u8; len];
+                reader.read_exact(&mut body).unwrap();
+                let mut tcp = tcp;
+                write!(tcp, "HTTP/1.1 {status}\r\nContent-Length: 0\r\n\r\n").unwrap();
+                out.push((head, String::from_utf8(body).unwrap()));
+            }
+            out
+        });
+        (addr, handle)
+    }
+
+    #[test]
+    fn digest_goes_to_the_webhook_as_json_and_to_ntfy_as_text() {
+        let mut settings = settings();
+        let (addr, server) = serve("200 OK", 2);
+        settings.notifications.webhook_url = Some(format!("http://{addr}/hooks/backup"));
+        settings.notifications.ntfy_url = Some(format!("http://{addr}/my-topic"));
+        send_digest(&settings.notifications, &digest(&settings)).unwrap();
+
+        let requests = server.join().unwrap();
+        let (head, body) = &requests[0];
+        assert!(head.starts_with("POST /hooks/backup HTTP/1.1\r\n"));
+        assert!(head.contains("Content-Type: application/json\r\n"));
+        let json: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(json["type"], "backup.digest");
+        assert_eq!(json["targetsFailed"], 1);
+        assert_eq!(json["targets"][1]["stale"], true);
+        assert!(!body.contains("/Users/me"));
+
+        let (head, body) = &requests[1];
+        assert!(head.starts_with("POST /my-topic HTTP/1.1\r\n"));
+        assert!(head.contains("Priority: high\r\n"));
+        assert!(head.contains("Title: TelevyBackup: 1 target failed"));
+        assert!(body.contains("- photos: last success 6d ago"));
+        assert!(!body.contains("/Users/me"));
+
+        settings.notifications.include_paths = true;
+        let (addr, server) = serve("200 OK", 1);
+        settings.notifications.webhook_url = None;
+        settings.notifications.ntfy_url = Some(format!("http://{addr}/my-topic"));
+        send_digest(&settings.notifications, &digest(&settings)).unwrap();
+        let (_, body) = &server.join().unwrap()[0];
+        assert!(body.contains("- photos (/Users/me/Pictures): last success 6d ago"));
+    }
+
+    #[test]
+    fn a_rejected_send_is_an_error() {
+        let mut settings = settings();
+        let (addr, server) = serve("403 Forbidden", 1);
+        settings.notifications.ntfy_url = Some(format!("http://{addr}/my-topic"));
+        let err = send_digest(&settings.notifications, &digest(&settings)).unwrap_err();
+        assert!(err.to_string().contains("HTTP 403"));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn urls_split_into_host_port_and_path() {
+        let url = parse_http_url("https://ntfy.sh/backups").unwrap();
+        assert_eq!(
+            (url.tls, url.host.as_str(), url.port, url.path.as_str()),
+            (true, "ntfy.sh", 443, "/backups")
+        );
+        assert_eq!(url.authority(), "ntfy.sh");
+        let url = parse_http_url("http://[::1]:8080?x=1").unwrap();
+        assert_eq!(
+            (url.host.as_str(), url.port, url.path.as_str()),
+            ("::1", 8080, "/?x=1")
+        );
+        assert_eq!(url.authority(), "[::1]:8080");
+        assert!(parse_http_url("ntfy.sh/backups").is_err());
+        assert!(parse_http_url("https://user:pw@example.com/").is_err());
+        assert!(parse_http_url("https://example.com:99999/").is_err());
+        assert!(parse_http_url("https:///path").is_err());
+    }
+}
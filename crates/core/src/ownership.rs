@@ -0,0 +1,284 @@
+//! File ownership: the uid/gid (and user/group names) backups record per file and directory, and
+//! how a restore maps them onto the restoring machine (`RestoreOptions::ownership`).
+//!
+//! By default restored entries stay owned by the restoring user, as before owners were recorded.
+//! A uid mapping (`--owner-mapping 501:502`) hands the mapped entries to their new user, and
+//! `preserve_owners` puts every entry back to its recorded uid/gid, which needs root unless they
+//! are the restoring user's own.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{Error, Result};
+
+/// An entry's owner as recorded in `files.uid` / `gid` / `owner_name` / `group_name`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileOwner {
+    pub uid: u32,
+    pub gid: u32,
+    pub user: Option<String>,
+    pub group: Option<String>,
+}
+
+/// The owner of a `files` row selected with the owner columns; `None` when they are `NULL`.
+pub(crate) fn file_owner_from_row(row: &sqlx::sqlite::SqliteRow) -> Option<FileOwner> {
+    use sqlx::Row;
+    let uid = row.get::<Option<i64>, _>("uid")?;
+    let gid = row.get::<Option<i64>, _>("gid")?;
+    Some(FileOwner {
+        uid: u32::try_from(uid).ok()?,
+        gid: u32::try_from(gid).ok()?,
+        user: row.get("owner_name"),
+        group: row.get("group_name"),
+    })
+}
+
+/// Resolves owners of scanned entries, looking each uid/gid's name up once per backup.
+#[derive(Debug, Default)]
+pub struct OwnerNames {
+    users: HashMap<u32, Option<String>>,
+    groups: HashMap<u32, Option<String>>,
+}
+
+impl OwnerNames {
+    #[cfg(unix)]
+    pub fn owner(&mut self, metadata: &std::fs::Metadata) -> Option<FileOwner> {
+        use std::os::unix::fs::MetadataExt;
+        let (uid, gid) = (metadata.uid(), metadata.gid());
+        Some(FileOwner {
+            uid,
+            gid,
+            user: self
+                .users
+                .entry(uid)
+                .or_insert_with(|| user_name(uid))
+                .clone(),
+            group: self
+                .groups
+                .entry(gid)
+                .or_insert_with(|| group_name(gid))
+                .clone(),
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn owner(&mut self, _metadata: &std::fs::Metadata) -> Option<FileOwner> {
+        None
+    }
+}
+
+#[cfg(unix)]
+fn user_name(uid: u32) -> Option<String> {
+    let mut buf = vec![0 as libc::c_char; 1024];
+    loop {
+        // SAFETY: `pwd` and `buf` outlive the call; `pw_name` points into `buf` on success.
+        let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut out = std::ptr::null_mut();
+        let rc = unsafe { libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), buf.len(), &mut out) };
+        if rc == libc::ERANGE && buf.len() < 1 << 16 {
+            buf.resize(buf.len() * 2, 0);
+            continue;
+        }
+        if rc != 0 || out.is_null() {
+            return None;
+        }
+        return c_string(pwd.pw_name);
+    }
+}
+
+#[cfg(unix)]
+fn group_name(gid: u32) -> Option<String> {
+    let mut buf = vec![0 as libc::c_char; 1024];
+    loop {
+        // SAFETY: as in `user_name`.
+        let mut grp: libc::group = unsafe { std::mem::zeroed() };
+        let mut out = std::ptr::null_mut();
+        let rc = unsafe { libc::getgrgid_r(gid, &mut grp, buf.as_mut_ptr(), buf.len(), &mut out) };
+        if rc == libc::ERANGE && buf.len() < 1 << 16 {
+            buf.resize(buf.len() * 2, 0);
+            continue;
+        }
+        if rc != 0 || out.is_null() {
+            return None;
+        }
+        return c_string(grp.gr_name);
+    }
+}
+
+#[cfg(unix)]
+fn c_string(ptr: *const libc::c_char) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+    // SAFETY: non-null, NUL-terminated by getpwuid_r/getgrgid_r.
+    let s = unsafe { std::ffi::CStr::from_ptr(ptr) };
+    s.to_str().ok().map(str::to_string)
+}
+
+/// Recorded uid -> uid on the restoring machine, parsed from `olduid:newuid,...`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OwnerMapping(BTreeMap<u32, u32>);
+
+impl OwnerMapping {
+    pub fn parse(s: &str) -> Result<Self> {
+        let invalid = |message: String| Error::InvalidConfig { message };
+        let mut map = BTreeMap::new();
+        for pair in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (from, to) = pair.split_once(':').ok_or_else(|| {
+                invalid(format!(
+                    "invalid owner mapping {pair:?}: expected olduid:newuid"
+                ))
+            })?;
+            let parse_uid = |v: &str| {
+                v.trim().parse::<u32>().map_err(|_| {
+                    invalid(format!(
+                        "invalid owner mapping {pair:?}: {v:?} is not a numeric uid"
+                    ))
+                })
+            };
+            let (from, to) = (parse_uid(from)?, parse_uid(to)?);
+            if map.insert(from, to).is_some_and(|prev| prev != to) {
+                return Err(invalid(format!(
+                    "invalid owner mapping: uid {from} is mapped twice"
+                )));
+            }
+        }
+        if map.is_empty() {
+            return Err(invalid("owner mapping is empty".to_string()));
+        }
+        Ok(Self(map))
+    }
+
+    pub fn get(&self, uid: u32) -> Option<u32> {
+        self.0.get(&uid).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// How restored entries get their owners. The default leaves them with the restoring user.
+#[derive(Debug, Clone, Default)]
+pub struct OwnershipOptions {
+    /// Give every entry its recorded uid/gid (uids in `uid_map` mapped first).
+    pub preserve_owners: bool,
+    pub uid_map: OwnerMapping,
+}
+
+impl OwnershipOptions {
+    pub fn is_default(&self) -> bool {
+        !self.preserve_owners && self.uid_map.is_empty()
+    }
+}
+
+/// The `(uid, gid)` to `chown` a restored entry recorded with `recorded` (uid, gid) to; `None`
+/// parts are left alone, and `None` overall means the entry keeps the restoring user's owner.
+pub fn owner_change(
+    recorded: Option<(u32, u32)>,
+    options: &OwnershipOptions,
+) -> Option<(Option<u32>, Option<u32>)> {
+    let (uid, gid) = recorded?;
+    let mapped = options.uid_map.get(uid);
+    if options.preserve_owners {
+        Some((Some(mapped.unwrap_or(uid)), Some(gid)))
+    } else {
+        mapped.map(|to| (Some(to), None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mapping_parses_uid_pairs() {
+        let m = OwnerMapping::parse("501:502, 0:0,,1000:501").unwrap();
+        assert_eq!(m.get(501), Some(502));
+        assert_eq!(m.get(0), Some(0));
+        assert_eq!(m.get(1000), Some(501));
+        assert_eq!(m.get(502), None);
+        // The same pair twice is harmless.
+        assert_eq!(
+            OwnerMapping::parse("501:502,501:502").unwrap().get(501),
+            Some(502)
+        );
+
+        for bad in [
+            "",
+            " , ",
+            "501",
+            "501:",
+            ":502",
+            "alice:502",
+            "501:-1",
+            "501:502:503",
+            "501:502,501:503",
+        ] {
+            let err = OwnerMapping::parse(bad).unwrap_err();
+            assert!(
+                matches!(err, Error::InvalidConfig { .. }),
+                "{bad:?}: {err:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn owner_change_decision_matrix() {
+        let map = OwnerMapping::parse("501:502").unwrap();
+        let current = OwnershipOptions::default();
+        let mapped = OwnershipOptions {
+            preserve_owners: false,
+            uid_map: map.clone(),
+        };
+        let preserve = OwnershipOptions {
+            preserve_owners: true,
+            uid_map: OwnerMapping::default(),
+        };
+        let preserve_mapped = OwnershipOptions {
+            preserve_owners: true,
+            uid_map: map,
+        };
+
+        // Nothing recorded (old snapshots, symlinks): never touched.
+        for opts in [&current, &mapped, &preserve, &preserve_mapped] {
+            assert_eq!(owner_change(None, opts), None);
+        }
+        // Default: restoring user keeps everything.
+        assert!(current.is_default());
+        assert_eq!(owner_change(Some((501, 20)), &current), None);
+        // Mapping: only mapped uids change, and only the uid.
+        assert_eq!(
+            owner_change(Some((501, 20)), &mapped),
+            Some((Some(502), None))
+        );
+        assert_eq!(owner_change(Some((600, 20)), &mapped), None);
+        // Preserve: recorded uid/gid as they are, mapped uids first.
+        assert_eq!(
+            owner_change(Some((501, 20)), &preserve),
+            Some((Some(501), Some(20)))
+        );
+        assert_eq!(
+            owner_change(Some((501, 20)), &preserve_mapped),
+            Some((Some(502), Some(20)))
+        );
+        assert_eq!(
+            owner_change(Some((600, 80)), &preserve_mapped),
+            Some((Some(600), Some(80)))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn owner_names_resolve_the_current_user() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut names = OwnerNames::default();
+        let owner = names
+            .owner(&std::fs::metadata(dir.path()).unwrap())
+            .unwrap();
+        let again = names
+            .owner(&std::fs::metadata(dir.path()).unwrap())
+            .unwrap();
+        assert_eq!(owner, again);
+        assert_eq!(user_name(u32::MAX - 1), None);
+    }
+}
@@ -0,0 +1,358 @@
+//! Checking what a backup sends for file names (`televybackup privacy audit`).
+//!
+//! [`audit_backup_privacy`] backs a source up into [`PrivacyAuditStorage`], which keeps no bytes
+//! but searches every object name, would-be [`ObjectCaption`] and payload for the snapshot's paths
+//! and their components. Payloads are ciphertext, so any hit means a name left the machine
+//! unencrypted. The only sanctioned place for a path is `targets[].source_path` inside the
+//! encrypted bootstrap catalog.
+
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::io::Read;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Mutex;
+
+use aho_corasick::AhoCorasick;
+
+use crate::bootstrap::{self, PinnedStorage};
+use crate::config::TelegramRateLimit;
+use crate::storage::{ObjectCaption, ObjectKind, Storage, StorageProgress, UploadBody};
+use crate::{
+    BackupConfig, BackupOptions, ChunkingConfig, Error, KeyDerivation, RemoteDedupeMode, Result,
+    UploadMetadata, run_backup_with,
+};
+
+/// Names shorter than this are left out: ciphertext matches a short string by chance.
+pub const MIN_NEEDLE_BYTES: usize = 8;
+
+pub const SANCTIONED_LOCATIONS: &[&str] = &["bootstrap catalog (encrypted): targets[].source_path"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrivacyLocation {
+    /// The filename passed to `upload_document` (Telegram shows it on the document).
+    ObjectName,
+    /// The caption the MTProto provider writes next to the object.
+    Caption,
+    /// The uploaded bytes themselves.
+    Payload,
+}
+
+impl PrivacyLocation {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::ObjectName => "object_name",
+            Self::Caption => "caption",
+            Self::Payload => "payload",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrivacyFinding {
+    pub location: PrivacyLocation,
+    pub object_name: String,
+    pub kind: Option<ObjectKind>,
+    /// The path or path component that was found.
+    pub needle: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrivacyReport {
+    pub objects_checked: u64,
+    pub bytes_checked: u64,
+    /// Distinct paths and path components searched for.
+    pub needles: u64,
+    pub findings: Vec<PrivacyFinding>,
+}
+
+/// The paths and path components of `paths` (and `source_path`) worth searching for.
+pub fn privacy_needles<'a>(
+    source_path: &str,
+    paths: impl IntoIterator<Item = &'a str>,
+) -> Vec<String> {
+    let mut needles = BTreeSet::new();
+    let mut add = |path: &str| {
+        needles.insert(path.to_string());
+        for component in path.split(['/', '\\']) {
+            needles.insert(component.to_string());
+        }
+    };
+    add(source_path);
+    for path in paths {
+        add(path);
+    }
+    needles
+        .into_iter()
+        .filter(|n| n.len() >= MIN_NEEDLE_BYTES)
+        .collect()
+}
+
+/// A [`PinnedStorage`] that records where `needles` show up in what is uploaded to it. Uploads get
+/// fresh `audit:` object ids; nothing can be downloaded.
+pub struct PrivacyAuditStorage {
+    needles: Vec<String>,
+    matcher: Option<AhoCorasick>,
+    state: Mutex<AuditState>,
+}
+
+#[derive(Default)]
+struct AuditState {
+    report: PrivacyReport,
+    pinned: Option<String>,
+    last_manifest: Option<String>,
+}
+
+impl PrivacyAuditStorage {
+    pub fn new(needles: Vec<String>) -> Result<Self> {
+        let matcher = if needles.is_empty() {
+            None
+        } else {
+            Some(
+                AhoCorasick::new(&needles).map_err(|e| Error::InvalidConfig {
+                    message: format!("privacy audit: {e}"),
+                })?,
+            )
+        };
+        let report = PrivacyReport {
+            needles: needles.len() as u64,
+            ..PrivacyReport::default()
+        };
+        Ok(Self {
+            needles,
+            matcher,
+            state: Mutex::new(AuditState {
+                report,
+                ..AuditState::default()
+            }),
+        })
+    }
+
+    pub fn report(&self) -> PrivacyReport {
+        self.lock().report.clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, AuditState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn find(&self, haystack: &[u8]) -> BTreeSet<usize> {
+        match &self.matcher {
+            Some(m) => m
+                .find_overlapping_iter(haystack)
+                .map(|hit| hit.pattern().as_usize())
+                .collect(),
+            None => BTreeSet::new(),
+        }
+    }
+
+    fn record(&self, filename: &str, bytes: &[u8], kind: Option<ObjectKind>) -> String {
+        let mut hits = Vec::new();
+        for (location, haystack) in [
+            (PrivacyLocation::ObjectName, filename.as_bytes().to_vec()),
+            (
+                PrivacyLocation::Caption,
+                kind.map(|k| ObjectCaption::for_payload(k, bytes).encode().into_bytes())
+                    .unwrap_or_default(),
+            ),
+            (PrivacyLocation::Payload, bytes.to_vec()),
+        ] {
+            for i in self.find(&haystack) {
+                hits.push(PrivacyFinding {
+                    location,
+                    object_name: filename.to_string(),
+                    kind,
+                    needle: self.needles[i].clone(),
+                });
+            }
+        }
+
+        let object_id = format!("audit:{}", uuid::Uuid::new_v4());
+        let mut state = self.lock();
+        state.report.objects_checked += 1;
+        state.report.bytes_checked += bytes.len() as u64;
+        state.report.findings.extend(hits);
+        if kind == Some(ObjectKind::IndexManifest) {
+            state.last_manifest = Some(object_id.clone());
+        }
+        object_id
+    }
+}
+
+impl Storage for PrivacyAuditStorage {
+    fn provider(&self) -> &str {
+        "audit"
+    }
+
+    fn upload_document<'a>(
+        &'a self,
+        filename: &'a str,
+        bytes: Vec<u8>,
+    ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>> {
+        Box::pin(async move { Ok(self.record(filename, &bytes, None)) })
+    }
+
+    fn upload_document_stream_with_metadata<'a>(
+        &'a self,
+        filename: &'a str,
+        body: UploadBody<'a>,
+        len: u64,
+        mut progress: Option<Box<dyn FnMut(StorageProgress) + Send + 'a>>,
+        metadata: Option<UploadMetadata>,
+    ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            body.take(len).read_to_end(&mut bytes)?;
+            if bytes.len() as u64 != len {
+                return Err(Error::Integrity {
+                    message: format!(
+                        "upload body ended early: expected={len} got={}",
+                        bytes.len()
+                    ),
+                });
+            }
+            if let Some(cb) = progress.as_mut() {
+                cb(StorageProgress {
+                    bytes: len,
+                    net_bytes: None,
+                });
+            }
+            Ok(self.record(filename, &bytes, metadata.map(|m| m.kind)))
+        })
+    }
+
+    fn download_document<'a>(
+        &'a self,
+        object_id: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>>> + Send + 'a>> {
+        let message = format!("privacy audit storage keeps no objects: {object_id}");
+        Box::pin(async move { Err(Error::InvalidConfig { message }) })
+    }
+}
+
+impl PinnedStorage for PrivacyAuditStorage {
+    fn get_pinned_object_id(&self) -> Result<Option<String>> {
+        Ok(self.lock().pinned.clone())
+    }
+
+    fn set_pinned_object_id(&self, object_id: &str) -> Result<()> {
+        self.lock().pinned = Some(object_id.to_string());
+        Ok(())
+    }
+
+    fn find_recent_object_id(&self, _kind: ObjectKind) -> Result<Option<String>> {
+        Ok(None)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PrivacyAuditConfig {
+    pub source_path: PathBuf,
+    pub target_id: String,
+    pub label: String,
+    pub chunking: ChunkingConfig,
+    /// Searched for in everything uploaded; see [`privacy_needles`].
+    pub needles: Vec<String>,
+}
+
+/// Backs `source_path` up with a throwaway key into local temp state and a
+/// [`PrivacyAuditStorage`], then writes the bootstrap catalog entry the run would publish. Nothing
+/// is sent anywhere and the endpoint's index DB is not touched.
+pub async fn audit_backup_privacy(config: PrivacyAuditConfig) -> Result<PrivacyReport> {
+    let storage = PrivacyAuditStorage::new(config.needles)?;
+    let state = tempfile::TempDir::new()?;
+    let mut master_key = [0u8; 32];
+    getrandom::getrandom(&mut master_key).map_err(|e| Error::InvalidConfig {
+        message: format!("getrandom failed: {e}"),
+    })?;
+
+    let res = run_backup_with(
+        &storage,
+        BackupConfig {
+            endpoint_db_path: state.path().join("index.sqlite"),
+            filemap_dir: state.path().join("filemaps"),
+            dedupe_db_path: state.path().join("dedupe.sqlite"),
+            dedupe_pending_db_path: state.path().join("dedupe.pending.sqlite"),
+            source_path: config.source_path.clone(),
+            label: config.label.clone(),
+            chunking: config.chunking,
+            rate_limit: TelegramRateLimit::default(),
+            master_key,
+            data_key: None,
+            snapshot_id: None,
+            keep_last_snapshots: 1,
+            remote_dedupe: RemoteDedupeMode::Enable {
+                endpoint_dedupe_id: "privacy-audit".to_string(),
+            },
+            hint_changed_paths: None,
+            device: None,
+            index_full_every: 0,
+            created_at: None,
+        },
+        BackupOptions::default(),
+    )
+    .await?;
+
+    let manifest_object_id = storage.lock().last_manifest.clone().unwrap_or_default();
+    bootstrap::update_remote_latest(
+        &storage,
+        &master_key,
+        None,
+        None,
+        &config.target_id,
+        &config.source_path.to_string_lossy(),
+        &config.label,
+        &res.snapshot_id,
+        &manifest_object_id,
+        None,
+        KeyDerivation::Master,
+        None,
+    )
+    .await?;
+
+    Ok(storage.report())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn needles_cover_components_and_skip_short_names() {
+        let needles = privacy_needles(
+            "/home/alice/Documents",
+            ["tax-returns/2024/zebra-quartz.pdf", "a.txt"],
+        );
+        assert_eq!(
+            needles,
+            vec![
+                "/home/alice/Documents",
+                "Documents",
+                "tax-returns",
+                "tax-returns/2024/zebra-quartz.pdf",
+                "zebra-quartz.pdf",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn leaky_object_names_are_reported() {
+        let storage = PrivacyAuditStorage::new(vec!["zebra-quartz.pdf".to_string()]).unwrap();
+        storage
+            .upload_document("file_0123456789ab.dat", b"opaque".to_vec())
+            .await
+            .unwrap();
+        storage
+            .upload_document("zebra-quartz.pdf", b"zebra-quartz.pdf".to_vec())
+            .await
+            .unwrap();
+
+        let report = storage.report();
+        assert_eq!(report.objects_checked, 2);
+        let locations: Vec<_> = report.findings.iter().map(|f| f.location).collect();
+        assert_eq!(
+            locations,
+            vec![PrivacyLocation::ObjectName, PrivacyLocation::Payload]
+        );
+    }
+}
@@ -1,8 +1,122 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
 use serde::{Deserialize, Serialize};
 
+/// Stage of a run reported in [`TaskProgress::phase`].
+///
+/// Serialized as the lowercase strings the GUI and status snapshots have always carried
+/// (`"scan"`, `"index_sync"`, ...). A string this build does not know deserializes to
+/// [`Phase::Other`] and serializes back unchanged, so a newer peer's phases pass through.
+///
+/// Orderings within a run:
+/// - backup: `prepare`, `index_sync` (CLI/daemon), then `scan` → `scan_upload` → `upload` →
+///   `index`; `scan_upload` and `upload` are skipped when nothing needs uploading.
+/// - restore: `index` (remote index download), then `download` and `restore` alternating per file.
+/// - verify: `index`, then `chunks`.
+/// - `settings import-bundle --apply`: `decode`, `endpoint_connect` (per endpoint), `preflight`,
+///   `index_rebuild` (per endpoint), `settings_write`, `secrets_write`.
+///
+/// `running` is the daemon's placeholder before the first report; `preflight` is emitted by the
+/// CLI while it sizes the source; `waiting_chat` is the heartbeat of `telegram wait-chat --events`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum Phase {
+    #[default]
+    Running,
+    Preflight,
+    Prepare,
+    IndexSync,
+    Scan,
+    ScanUpload,
+    Upload,
+    Index,
+    Download,
+    Restore,
+    Chunks,
+    WaitingChat,
+    Decode,
+    EndpointConnect,
+    IndexRebuild,
+    SettingsWrite,
+    SecretsWrite,
+    Other(String),
+}
+
+impl Phase {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Phase::Running => "running",
+            Phase::Preflight => "preflight",
+            Phase::Prepare => "prepare",
+            Phase::IndexSync => "index_sync",
+            Phase::Scan => "scan",
+            Phase::ScanUpload => "scan_upload",
+            Phase::Upload => "upload",
+            Phase::Index => "index",
+            Phase::Download => "download",
+            Phase::Restore => "restore",
+            Phase::Chunks => "chunks",
+            Phase::WaitingChat => "waiting_chat",
+            Phase::Decode => "decode",
+            Phase::EndpointConnect => "endpoint_connect",
+            Phase::IndexRebuild => "index_rebuild",
+            Phase::SettingsWrite => "settings_write",
+            Phase::SecretsWrite => "secrets_write",
+            Phase::Other(s) => s,
+        }
+    }
+}
+
+impl From<&str> for Phase {
+    fn from(s: &str) -> Self {
+        match s {
+            "running" => Phase::Running,
+            "preflight" => Phase::Preflight,
+            "prepare" => Phase::Prepare,
+            "index_sync" => Phase::IndexSync,
+            "scan" => Phase::Scan,
+            "scan_upload" => Phase::ScanUpload,
+            "upload" => Phase::Upload,
+            "index" => Phase::Index,
+            "download" => Phase::Download,
+            "restore" => Phase::Restore,
+            "chunks" => Phase::Chunks,
+            "waiting_chat" => Phase::WaitingChat,
+            "decode" => Phase::Decode,
+            "endpoint_connect" => Phase::EndpointConnect,
+            "index_rebuild" => Phase::IndexRebuild,
+            "settings_write" => Phase::SettingsWrite,
+            "secrets_write" => Phase::SecretsWrite,
+            other => Phase::Other(other.to_string()),
+        }
+    }
+}
+
+impl From<String> for Phase {
+    fn from(s: String) -> Self {
+        Phase::from(s.as_str())
+    }
+}
+
+impl From<Phase> for String {
+    fn from(p: Phase) -> Self {
+        match p {
+            Phase::Other(s) => s,
+            known => known.as_str().to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct TaskProgress {
-    pub phase: String,
+    pub phase: Phase,
     pub files_total: Option<u64>,
     pub files_done: Option<u64>,
     pub source_files_total: Option<u64>,
@@ -30,9 +144,104 @@ pub struct TaskProgress {
     /// This is not protocol payload accounting; it may exceed `bytes_downloaded` due to overhead,
     /// retries, and buffering. Intended for realtime rate indicators.
     pub net_bytes_downloaded: Option<u64>,
+    /// Index DB bytes an index download has written to disk so far (decompressed).
+    pub bytes_written: Option<u64>,
     pub bytes_deduped: Option<u64>,
+    /// Source size estimated by the preflight walk, available before the scan starts.
+    pub bytes_total_estimated: Option<u64>,
+    /// Payload bytes a restore writes (file sizes) or a verify checks (chunk sizes), known from
+    /// the index before the first chunk downloads and constant for the run.
+    pub bytes_total: Option<u64>,
+}
+
+/// Wall time spent in each phase of a run, in milliseconds, keyed by the [`Phase`] wire
+/// strings. Phases that never ran are absent.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PhaseTimings(BTreeMap<String, u64>);
+
+impl PhaseTimings {
+    /// Adds `elapsed` to `phase`.
+    pub fn record(&mut self, phase: Phase, elapsed: Duration) {
+        let ms = u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX);
+        let entry = self.0.entry(phase.into()).or_default();
+        *entry = entry.saturating_add(ms);
+    }
+
+    pub fn get(&self, phase: &Phase) -> Option<Duration> {
+        self.0
+            .get(phase.as_str())
+            .map(|ms| Duration::from_millis(*ms))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl std::fmt::Display for PhaseTimings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let json = serde_json::to_string(&self.0).map_err(|_| std::fmt::Error)?;
+        f.write_str(&json)
+    }
 }
 
 pub trait ProgressSink: Send + Sync {
     fn on_progress(&self, progress: TaskProgress);
 }
+
+/// What a run got done before it stopped, from its last progress report. Reported for
+/// cancelled runs: chunks uploaded so far stay in storage and the next backup dedupes against
+/// them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PartialRunResult {
+    pub phase: Option<Phase>,
+    pub files_done: Option<u64>,
+    pub chunks_done: Option<u64>,
+    pub bytes_uploaded: Option<u64>,
+    pub bytes_downloaded: Option<u64>,
+    pub bytes_deduped: Option<u64>,
+}
+
+/// Forwards progress to `inner` (if any) and keeps the last report for
+/// [`ProgressRecorder::partial_result`].
+pub struct ProgressRecorder<'a> {
+    inner: Option<&'a dyn ProgressSink>,
+    last: std::sync::Mutex<Option<TaskProgress>>,
+}
+
+impl<'a> ProgressRecorder<'a> {
+    pub fn new(inner: Option<&'a dyn ProgressSink>) -> Self {
+        Self {
+            inner,
+            last: std::sync::Mutex::new(None),
+        }
+    }
+
+    pub fn partial_result(&self) -> PartialRunResult {
+        let last = self.last.lock().map(|p| p.clone()).unwrap_or_default();
+        let Some(p) = last else {
+            return PartialRunResult::default();
+        };
+        PartialRunResult {
+            phase: Some(p.phase),
+            files_done: p.files_done,
+            chunks_done: p.chunks_done,
+            bytes_uploaded: p.bytes_uploaded,
+            bytes_downloaded: p.bytes_downloaded,
+            bytes_deduped: p.bytes_deduped,
+        }
+    }
+}
+
+impl ProgressSink for ProgressRecorder<'_> {
+    fn on_progress(&self, progress: TaskProgress) {
+        if let Ok(mut last) = self.last.lock() {
+            *last = Some(progress.clone());
+        }
+        if let Some(inner) = self.inner {
+            inner.on_progress(progress);
+        }
+    }
+}
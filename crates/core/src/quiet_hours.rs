@@ -0,0 +1,256 @@
+//! `quiet_hours`: weekly time ranges in which the daemon starts no scheduled backups or verifies
+//! and throttles the uploads of runs still going to `quiet_hours.max_upload_bytes_per_sec`.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday};
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::{QuietHours, QuietHoursRange, load_settings_v2};
+use crate::schedule_tz::ScheduleTz;
+
+/// How often [`follow_settings`] re-reads `config.toml`.
+pub const SETTINGS_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Longest single sleep in [`UploadThrottle::acquire`], so a changed limit is picked up.
+const THROTTLE_RECHECK: Duration = Duration::from_secs(1);
+
+/// `mon`..`sun` (any case; full names work too).
+pub fn parse_day(s: &str) -> Option<Weekday> {
+    s.trim().parse::<Weekday>().ok()
+}
+
+fn parse_time(s: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(s.trim(), "%H:%M").ok()
+}
+
+/// End of the quiet hours `now` falls in, or `None` outside quiet hours. Ranges that meet or
+/// overlap count as one, so the end is when the daemon may start scheduled runs again.
+pub fn quiet_until(
+    quiet: &QuietHours,
+    tz: &ScheduleTz,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    let today = now.with_timezone(tz).date_naive();
+    // A range belongs to the day it starts on, so one that began yesterday may still run; a
+    // week ahead covers chains of back-to-back ranges.
+    let periods = (-1..=7)
+        .filter_map(|offset| today.checked_add_signed(chrono::Duration::days(offset)))
+        .flat_map(|day| quiet.ranges.iter().filter_map(move |r| period(r, tz, day)))
+        .collect::<Vec<_>>();
+
+    let mut until = periods
+        .iter()
+        .filter(|(start, end)| *start <= now && now < *end)
+        .map(|(_, end)| *end)
+        .max()?;
+    while let Some(end) = periods
+        .iter()
+        .filter(|(start, end)| *start <= until && until < *end)
+        .map(|(_, end)| *end)
+        .max()
+    {
+        until = end;
+    }
+    Some(until)
+}
+
+/// The span `range` covers when it starts on `day`; `None` when it doesn't apply to that
+/// weekday (or doesn't parse). `end` at or before `start` ends the next day, so `00:00`–`00:00`
+/// is the whole day.
+fn period(
+    range: &QuietHoursRange,
+    tz: &ScheduleTz,
+    day: NaiveDate,
+) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    if !range.days.is_empty()
+        && !range
+            .days
+            .iter()
+            .any(|d| parse_day(d) == Some(day.weekday()))
+    {
+        return None;
+    }
+    let start = parse_time(&range.start)?;
+    let end = parse_time(&range.end)?;
+    let end_day = if end <= start { day.succ_opt()? } else { day };
+    let start = local_to_utc(tz, day.and_time(start))?;
+    let end = local_to_utc(tz, end_day.and_time(end))?;
+    Some((start, end))
+}
+
+/// A wall-clock time inside a DST gap moves to the first minute after it.
+fn local_to_utc(tz: &ScheduleTz, wall: NaiveDateTime) -> Option<DateTime<Utc>> {
+    (0..=24 * 60).find_map(|m| {
+        tz.from_local_datetime(&(wall + chrono::Duration::minutes(m)))
+            .earliest()
+            .map(|t| t.with_timezone(&Utc))
+    })
+}
+
+/// Upload limit for runs at `now`: `max_upload_bytes_per_sec` inside quiet hours, 0
+/// (unthrottled) outside them.
+pub fn upload_limit(quiet: &QuietHours, tz: &ScheduleTz, now: DateTime<Utc>) -> u64 {
+    if quiet_until(quiet, tz, now).is_some() {
+        quiet.max_upload_bytes_per_sec
+    } else {
+        0
+    }
+}
+
+/// Bytes-per-second cap on uploads, shared by a run's upload workers and changeable while they
+/// run; 0 means unlimited.
+#[derive(Debug, Default)]
+pub struct UploadThrottle {
+    bytes_per_sec: AtomicU64,
+    /// When the last upload was let through and its size; the next one waits until that many
+    /// bytes have had time to go out at the current limit.
+    last: Mutex<Option<(Instant, u64)>>,
+}
+
+impl UploadThrottle {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec: AtomicU64::new(bytes_per_sec),
+            last: Mutex::new(None),
+        }
+    }
+
+    pub fn limit(&self) -> u64 {
+        self.bytes_per_sec.load(Ordering::Relaxed)
+    }
+
+    pub fn set_limit(&self, bytes_per_sec: u64) {
+        self.bytes_per_sec.store(bytes_per_sec, Ordering::Relaxed);
+    }
+
+    /// Waits until an upload of `bytes` fits the limit. A limit raised, lowered or lifted while
+    /// waiting applies within a second.
+    pub async fn acquire(&self, bytes: u64) {
+        loop {
+            let limit = self.limit();
+            let now = Instant::now();
+            let wait = {
+                let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+                let ready_at = match (*last, limit) {
+                    (_, 0) | (None, _) => now,
+                    (Some((at, sent)), limit) => {
+                        at + Duration::from_secs_f64(sent as f64 / limit as f64)
+                    }
+                };
+                if ready_at <= now {
+                    *last = Some((now, bytes));
+                    return;
+                }
+                ready_at - now
+            };
+            tokio::time::sleep(wait.min(THROTTLE_RECHECK)).await;
+        }
+    }
+}
+
+/// Keeps `throttle` at the quiet hours limit of `config_dir`'s `config.toml`, re-reading the
+/// file every [`SETTINGS_POLL_INTERVAL`] so edits apply to runs already going. Runs until
+/// `cancel`; an unreadable file keeps the last limit.
+pub async fn follow_settings(
+    config_dir: PathBuf,
+    throttle: Arc<UploadThrottle>,
+    cancel: CancellationToken,
+) {
+    loop {
+        if let Ok(settings) = load_settings_v2(&config_dir) {
+            let tz = ScheduleTz::parse(&settings.schedule.timezone).unwrap_or(ScheduleTz::Local);
+            throttle.set_limit(upload_limit(&settings.quiet_hours, &tz, Utc::now()));
+        }
+        tokio::select! {
+            _ = cancel.cancelled() => return,
+            _ = tokio::time::sleep(SETTINGS_POLL_INTERVAL) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(days: &[&str], start: &str, end: &str) -> QuietHoursRange {
+        QuietHoursRange {
+            days: days.iter().map(|d| d.to_string()).collect(),
+            start: start.to_string(),
+            end: end.to_string(),
+        }
+    }
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn quiet_until_handles_weekdays_midnight_and_chained_ranges() {
+        let tz = ScheduleTz::parse("UTC").unwrap();
+        // 2024-06-03 is a Monday.
+        let quiet = QuietHours {
+            max_upload_bytes_per_sec: 1000,
+            ranges: vec![
+                range(&["mon", "tue"], "09:00", "17:00"),
+                range(&["fri"], "22:00", "00:00"),
+                range(&["sat"], "00:00", "00:00"),
+            ],
+        };
+
+        assert_eq!(
+            quiet_until(&quiet, &tz, utc("2024-06-03T10:00:00Z")),
+            Some(utc("2024-06-03T17:00:00Z"))
+        );
+        assert_eq!(quiet_until(&quiet, &tz, utc("2024-06-03T17:00:00Z")), None);
+        assert_eq!(quiet_until(&quiet, &tz, utc("2024-06-05T10:00:00Z")), None);
+        // Friday night runs into the all-day Saturday range.
+        assert_eq!(
+            quiet_until(&quiet, &tz, utc("2024-06-07T23:00:00Z")),
+            Some(utc("2024-06-09T00:00:00Z"))
+        );
+        assert_eq!(
+            quiet_until(&quiet, &tz, utc("2024-06-08T12:00:00Z")),
+            Some(utc("2024-06-09T00:00:00Z"))
+        );
+
+        let nightly = QuietHours {
+            max_upload_bytes_per_sec: 0,
+            ranges: vec![range(&[], "23:00", "06:00")],
+        };
+        assert_eq!(
+            quiet_until(&nightly, &tz, utc("2024-06-04T02:00:00Z")),
+            Some(utc("2024-06-04T06:00:00Z"))
+        );
+        assert_eq!(upload_limit(&quiet, &tz, utc("2024-06-03T10:00:00Z")), 1000);
+        assert_eq!(upload_limit(&quiet, &tz, utc("2024-06-03T18:00:00Z")), 0);
+    }
+
+    #[tokio::test]
+    async fn throttle_paces_uploads_and_follows_limit_changes() {
+        let throttle = Arc::new(UploadThrottle::new(1000));
+        let started = std::time::Instant::now();
+        throttle.acquire(100).await;
+        throttle.acquire(100).await;
+        assert!(started.elapsed() >= Duration::from_millis(100));
+
+        // A 100 s backlog at the current limit clears once the limit is lifted.
+        throttle.acquire(100_000).await;
+        let waiter = tokio::spawn({
+            let throttle = throttle.clone();
+            async move { throttle.acquire(100).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiter.is_finished());
+        throttle.set_limit(0);
+        tokio::time::timeout(Duration::from_secs(3), waiter)
+            .await
+            .expect("waiter picks up the lifted limit")
+            .unwrap();
+    }
+}
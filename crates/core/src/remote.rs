@@ -0,0 +1,548 @@
+//! Read-only remote monitoring over TCP (`[remote]` in settings).
+//!
+//! One request per connection, like the control socket: the client writes one JSON line (a
+//! [`ControlRequest`] plus the bearer `token`) and reads one [`ControlResponse`] line back. With
+//! `remote.tls` the connection is TLS 1.3 with a self-signed certificate generated on first start;
+//! clients pin its SHA-256 fingerprint instead of trusting a CA.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use ring::rand::SystemRandom;
+use ring::signature::{ECDSA_P256_SHA256_ASN1_SIGNING, EcdsaKeyPair, KeyPair};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::control::{ControlRequest, ControlResponse};
+
+/// Methods the remote listener serves; none of them change anything.
+pub const REMOTE_METHODS: &[&str] = &["status.get", "snapshots.list", "stats.get", "runs.list"];
+
+pub const MAX_REMOTE_LINE_BYTES: usize = 64 * 1024;
+
+/// Server name clients send in the TLS handshake (the pinned certificate names no host).
+const TLS_SERVER_NAME: &str = "televybackupd";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteRequest {
+    #[serde(default)]
+    pub token: String,
+    #[serde(flatten)]
+    pub request: ControlRequest,
+}
+
+/// Default and maximum `limit` of `snapshots.list` and `runs.list`.
+pub const REMOTE_DEFAULT_LIMIT: u32 = 20;
+pub const REMOTE_MAX_LIMIT: u32 = 1000;
+
+/// `snapshots.list` params; `since`/`until` take a date or RFC3339 time like the CLI flags.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteSnapshotsListParams {
+    #[serde(default)]
+    pub limit: Option<u32>,
+    #[serde(default)]
+    pub target_id: Option<String>,
+    #[serde(default)]
+    pub source_path: Option<String>,
+    #[serde(default)]
+    pub since: Option<String>,
+    #[serde(default)]
+    pub until: Option<String>,
+    #[serde(default)]
+    pub asc: bool,
+    /// Answer with one page and its `nextCursor` (implied by `cursor`).
+    #[serde(default)]
+    pub paginate: bool,
+    /// `nextCursor` of the previous page.
+    #[serde(default)]
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteRunsListParams {
+    #[serde(default)]
+    pub limit: Option<u32>,
+    #[serde(default)]
+    pub target_id: Option<String>,
+    #[serde(default)]
+    pub kind: Option<String>,
+}
+
+/// Compares bearer tokens in constant time (over their digests, so lengths don't leak either).
+pub fn token_matches(expected: &str, presented: &str) -> bool {
+    let a = Sha256::digest(expected.as_bytes());
+    let b = Sha256::digest(presented.as_bytes());
+    a.iter()
+        .zip(b.iter())
+        .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+        == 0
+}
+
+/// Lowercase hex SHA-256 of a DER certificate, as printed by the daemon and passed to
+/// `--remote-fingerprint`. Colons and case are ignored when comparing.
+pub fn cert_fingerprint(cert_der: &[u8]) -> String {
+    hex::encode(Sha256::digest(cert_der))
+}
+
+fn parse_fingerprint(s: &str) -> Option<[u8; 32]> {
+    let hex_str = s.trim().replace(':', "").to_ascii_lowercase();
+    hex::decode(hex_str).ok()?.try_into().ok()
+}
+
+/// The listener's certificate and PKCS#8 key.
+#[derive(Clone)]
+pub struct RemoteTlsIdentity {
+    pub cert_der: Vec<u8>,
+    key_pkcs8_der: Vec<u8>,
+}
+
+impl std::fmt::Debug for RemoteTlsIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RemoteTlsIdentity")
+            .field("fingerprint", &self.fingerprint())
+            .finish_non_exhaustive()
+    }
+}
+
+impl RemoteTlsIdentity {
+    pub fn fingerprint(&self) -> String {
+        cert_fingerprint(&self.cert_der)
+    }
+}
+
+pub fn remote_tls_cert_path(config_dir: &Path) -> PathBuf {
+    config_dir.join("remote").join("tls-cert.der")
+}
+
+pub fn remote_tls_key_path(config_dir: &Path) -> PathBuf {
+    config_dir.join("remote").join("tls-key.pk8")
+}
+
+/// Loads the listener's certificate, generating and saving a self-signed one (mode 0600) the
+/// first time. Delete both files to rotate it.
+pub fn load_or_create_tls_identity(config_dir: &Path) -> std::io::Result<RemoteTlsIdentity> {
+    let cert_path = remote_tls_cert_path(config_dir);
+    let key_path = remote_tls_key_path(config_dir);
+    if cert_path.exists() && key_path.exists() {
+        return Ok(RemoteTlsIdentity {
+            cert_der: std::fs::read(&cert_path)?,
+            key_pkcs8_der: std::fs::read(&key_path)?,
+        });
+    }
+
+    let identity = generate_self_signed_identity().map_err(std::io::Error::other)?;
+    crate::secrets::write_atomic_private(&key_path, &identity.key_pkcs8_der)?;
+    crate::secrets::write_atomic_private(&cert_path, &identity.cert_der)?;
+    Ok(identity)
+}
+
+/// An ECDSA P-256 certificate signed by its own key, valid from 2024 to 9999.
+fn generate_self_signed_identity() -> Result<RemoteTlsIdentity, String> {
+    const ECDSA_WITH_SHA256: &[u8] = &[0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+    const EC_PUBLIC_KEY: &[u8] = &[0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+    const PRIME256V1: &[u8] = &[0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+    const COMMON_NAME: &[u8] = &[0x06, 0x03, 0x55, 0x04, 0x03];
+
+    let rng = SystemRandom::new();
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng)
+        .map_err(|_| "generate tls key failed".to_string())?;
+    let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng)
+        .map_err(|e| format!("load tls key failed: {e}"))?;
+
+    let mut serial = [0u8; 16];
+    getrandom::getrandom(&mut serial).map_err(|e| format!("getrandom failed: {e}"))?;
+    serial[0] = (serial[0] & 0x7f) | 0x40;
+
+    let signature_algorithm = der(0x30, ECDSA_WITH_SHA256);
+    let name = der(
+        0x30,
+        &der(
+            0x31,
+            &der(
+                0x30,
+                &[COMMON_NAME, &der(0x0c, TLS_SERVER_NAME.as_bytes())].concat(),
+            ),
+        ),
+    );
+    let validity = der(
+        0x30,
+        &[der(0x17, b"240101000000Z"), der(0x18, b"99991231235959Z")].concat(),
+    );
+    let spki = der(
+        0x30,
+        &[
+            der(0x30, &[EC_PUBLIC_KEY, PRIME256V1].concat()),
+            bit_string(key_pair.public_key().as_ref()),
+        ]
+        .concat(),
+    );
+    let tbs = der(
+        0x30,
+        &[
+            der(0xa0, &der(0x02, &[2])),
+            der(0x02, &serial),
+            signature_algorithm.clone(),
+            name.clone(),
+            validity,
+            name,
+            spki,
+        ]
+        .concat(),
+    );
+    let signature = key_pair
+        .sign(&rng, &tbs)
+        .map_err(|_| "sign tls certificate failed".to_string())?;
+    let cert_der = der(
+        0x30,
+        &[tbs, signature_algorithm, bit_string(signature.as_ref())].concat(),
+    );
+
+    Ok(RemoteTlsIdentity {
+        cert_der,
+        key_pkcs8_der: pkcs8.as_ref().to_vec(),
+    })
+}
+
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let len = content.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let skip = bytes.iter().take_while(|b| **b == 0).count();
+        out.push(0x80 | (bytes.len() - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+fn bit_string(bytes: &[u8]) -> Vec<u8> {
+    der(0x03, &[&[0u8][..], bytes].concat())
+}
+
+pub(crate) fn crypto_provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+/// Server-side TLS settings for [`RemoteServerStream`], built once per listener.
+#[derive(Clone)]
+pub struct RemoteTlsServerConfig(Arc<rustls::ServerConfig>);
+
+pub fn server_tls_config(identity: &RemoteTlsIdentity) -> std::io::Result<RemoteTlsServerConfig> {
+    let config = rustls::ServerConfig::builder_with_provider(crypto_provider())
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .and_then(|b| {
+            b.with_no_client_auth().with_single_cert(
+                vec![CertificateDer::from(identity.cert_der.clone())],
+                PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(identity.key_pkcs8_der.clone())),
+            )
+        })
+        .map_err(std::io::Error::other)?;
+    Ok(RemoteTlsServerConfig(Arc::new(config)))
+}
+
+/// Accepts exactly the certificate whose SHA-256 is pinned; names and validity are not checked.
+#[derive(Debug)]
+struct PinnedCertVerifier {
+    sha256: [u8; 32],
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if Sha256::digest(end_entity.as_ref()).as_slice() == self.sha256 {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::InvalidCertificate(
+                rustls::CertificateError::ApplicationVerificationFailure,
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+fn client_tls_config(fingerprint: &str) -> std::io::Result<Arc<rustls::ClientConfig>> {
+    let sha256 = parse_fingerprint(fingerprint).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "remote fingerprint must be 64 hex digits (SHA-256)",
+        )
+    })?;
+    let provider = crypto_provider();
+    let config = rustls::ClientConfig::builder_with_provider(provider.clone())
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .map_err(std::io::Error::other)?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(PinnedCertVerifier { sha256, provider }))
+        .with_no_client_auth();
+    Ok(Arc::new(config))
+}
+
+/// Reads one `\n`-terminated line of at most [`MAX_REMOTE_LINE_BYTES`].
+pub fn read_line<S: Read>(stream: &mut S) -> std::io::Result<String> {
+    let mut line = String::new();
+    BufReader::new(stream.take(MAX_REMOTE_LINE_BYTES as u64 + 1)).read_line(&mut line)?;
+    if line.len() > MAX_REMOTE_LINE_BYTES {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "line too large",
+        ));
+    }
+    Ok(line)
+}
+
+pub fn write_line<S: Write>(stream: &mut S, value: &impl Serialize) -> std::io::Result<()> {
+    let mut bytes = serde_json::to_vec(value).map_err(std::io::Error::other)?;
+    bytes.push(b'\n');
+    stream.write_all(&bytes)?;
+    stream.flush()
+}
+
+/// A connection accepted by the remote listener, TLS already set up when enabled.
+pub enum RemoteServerStream {
+    Plain(TcpStream),
+    Tls(Box<rustls::StreamOwned<rustls::ServerConnection, TcpStream>>),
+}
+
+impl RemoteServerStream {
+    pub fn new(tcp: TcpStream, tls: Option<&RemoteTlsServerConfig>) -> std::io::Result<Self> {
+        match tls {
+            None => Ok(Self::Plain(tcp)),
+            Some(config) => {
+                let conn = rustls::ServerConnection::new(config.0.clone())
+                    .map_err(std::io::Error::other)?;
+                Ok(Self::Tls(Box::new(rustls::StreamOwned::new(conn, tcp))))
+            }
+        }
+    }
+
+    /// Sends TLS close_notify; a no-op on plain connections.
+    pub fn close(&mut self) {
+        if let Self::Tls(s) = self {
+            s.conn.send_close_notify();
+            let _ = s.flush();
+        }
+    }
+}
+
+impl Read for RemoteServerStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Plain(s) => s.read(buf),
+            Self::Tls(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for RemoteServerStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Plain(s) => s.write(buf),
+            Self::Tls(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Plain(s) => s.flush(),
+            Self::Tls(s) => s.flush(),
+        }
+    }
+}
+
+/// Connects to the first address `addr` resolves to that accepts, with `timeout` applied to the
+/// connect and to every read and write.
+pub(crate) fn connect_tcp(
+    addr: impl ToSocketAddrs,
+    timeout: Duration,
+) -> std::io::Result<TcpStream> {
+    let mut last_err = None;
+    let mut tcp = None;
+    for sock_addr in addr.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&sock_addr, timeout) {
+            Ok(s) => {
+                tcp = Some(s);
+                break;
+            }
+            Err(e) => last_err = Some(e),
+        }
+    }
+    let tcp = tcp.ok_or_else(|| {
+        last_err.unwrap_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "address resolved to nothing")
+        })
+    })?;
+    tcp.set_read_timeout(Some(timeout))?;
+    tcp.set_write_timeout(Some(timeout))?;
+    Ok(tcp)
+}
+
+/// Sends one request to a remote listener at `addr` (`host:port`) and returns its response.
+/// `fingerprint` switches to TLS and pins the listener's certificate.
+pub fn remote_call(
+    addr: &str,
+    token: &str,
+    fingerprint: Option<&str>,
+    request: ControlRequest,
+    timeout: Duration,
+) -> std::io::Result<ControlResponse> {
+    let tls = fingerprint.map(client_tls_config).transpose()?;
+    let tcp = connect_tcp(addr, timeout)?;
+
+    let req = RemoteRequest {
+        token: token.to_string(),
+        request,
+    };
+    let line = match tls {
+        None => {
+            let mut tcp = tcp;
+            write_line(&mut tcp, &req)?;
+            read_line(&mut tcp)?
+        }
+        Some(config) => {
+            let name = ServerName::try_from(TLS_SERVER_NAME).map_err(std::io::Error::other)?;
+            let conn =
+                rustls::ClientConnection::new(config, name).map_err(std::io::Error::other)?;
+            let mut stream = rustls::StreamOwned::new(conn, tcp);
+            write_line(&mut stream, &req)?;
+            read_line(&mut stream)?
+        }
+    };
+    if line.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "remote closed the connection without a response",
+        ));
+    }
+    serde_json::from_str(&line).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    fn serve_once(
+        tls: Option<RemoteTlsServerConfig>,
+    ) -> (String, std::thread::JoinHandle<RemoteRequest>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let handle = std::thread::spawn(move || {
+            let (tcp, _) = listener.accept().unwrap();
+            let mut stream = RemoteServerStream::new(tcp, tls.as_ref()).unwrap();
+            let req: RemoteRequest =
+                serde_json::from_str(&read_line(&mut stream).unwrap()).unwrap();
+            let resp = ControlResponse::ok(&req.request.id, serde_json::json!({ "pong": true }));
+            write_line(&mut stream, &resp).unwrap();
+            stream.close();
+            req
+        });
+        (addr, handle)
+    }
+
+    fn ping() -> ControlRequest {
+        ControlRequest::new("1", "stats.get", serde_json::json!({}))
+    }
+
+    #[test]
+    fn tokens_compare_by_value() {
+        assert!(token_matches("s3cret", "s3cret"));
+        assert!(!token_matches("s3cret", "s3cret "));
+        assert!(!token_matches("s3cret", ""));
+    }
+
+    #[test]
+    fn tls_identity_is_generated_once_and_round_trips_with_a_pinned_client() {
+        let dir = tempfile::tempdir().unwrap();
+        let identity = load_or_create_tls_identity(dir.path()).unwrap();
+        let again = load_or_create_tls_identity(dir.path()).unwrap();
+        assert_eq!(identity.fingerprint(), again.fingerprint());
+
+        let config = server_tls_config(&identity).unwrap();
+        let (addr, server) = serve_once(Some(config.clone()));
+        let resp = remote_call(
+            &addr,
+            "tok",
+            Some(&identity.fingerprint().to_ascii_uppercase()),
+            ping(),
+            Duration::from_secs(5),
+        )
+        .unwrap();
+        assert!(resp.ok);
+        let req = server.join().unwrap();
+        assert_eq!(req.token, "tok");
+        assert_eq!(req.request.method, "stats.get");
+
+        // A client pinning a different certificate refuses the handshake.
+        let (addr, _server) = serve_once(Some(config));
+        let err = remote_call(
+            &addr,
+            "tok",
+            Some(&"00".repeat(32)),
+            ping(),
+            Duration::from_secs(5),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("certificate"), "{err}");
+    }
+
+    #[test]
+    fn plain_round_trip() {
+        let (addr, server) = serve_once(None);
+        let resp = remote_call(&addr, "tok", None, ping(), Duration::from_secs(5)).unwrap();
+        assert_eq!(resp.result.unwrap()["pong"], true);
+        assert_eq!(server.join().unwrap().request.id, "1");
+    }
+}
@@ -1,18 +1,26 @@
 use std::fs;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
 
+use sha2::{Digest, Sha256};
 use tokio_util::sync::CancellationToken;
-use tracing::error;
-
-use crate::crypto::decrypt_framed;
-use crate::index_manifest::{IndexManifest, index_part_aad};
-use crate::progress::{ProgressSink, TaskProgress};
+use tracing::{error, warn};
+
+use crate::crypto::{decrypt_framed, decrypt_framed_vec};
+use crate::index_delta::apply_filemap_delta_db;
+use crate::index_manifest::{
+    INDEX_MANIFEST_VERSION_DELTA, INDEX_MANIFEST_VERSION_FULL, IndexManifest, index_part_aad,
+};
+use crate::progress::{Phase, ProgressSink, TaskProgress};
 use crate::storage::Storage;
 use crate::{Error, Result};
 
+/// Deltas followed before giving up on finding a full index (a cycle or a corrupt chain); far
+/// above any sensible `index.full_every`.
+const MAX_INDEX_CHAIN_LEN: usize = 1000;
+
 #[derive(Debug, Clone, Copy)]
 pub struct DownloadedIndexDbStats {
     pub bytes_downloaded: u64,
@@ -20,26 +28,258 @@ pub struct DownloadedIndexDbStats {
     pub bytes_written: u64,
 }
 
+/// SHA-256 (hex) of an encrypted index manifest, as recorded in `remote_indexes` and the
+/// bootstrap catalog.
+pub fn manifest_sha256(manifest_enc: &[u8]) -> String {
+    hex::encode(Sha256::digest(manifest_enc))
+}
+
+/// Logs that a manifest is about to be trusted without a recorded hash (pointers written by older
+/// versions only carry the object id).
+pub fn warn_manifest_unverified(snapshot_id: &str, manifest_object_id: &str) {
+    warn!(
+        event = "integrity.manifest_unverified",
+        snapshot_id,
+        manifest_object_id,
+        "no manifest hash recorded for this snapshot; manifest content is not verified"
+    );
+}
+
+fn check_manifest_sha256(
+    snapshot_id: &str,
+    manifest_object_id: &str,
+    expected: Option<&str>,
+    manifest_enc: &[u8],
+) -> Result<()> {
+    let Some(expected) = expected else {
+        return Ok(());
+    };
+    let actual = manifest_sha256(manifest_enc);
+    if !actual.eq_ignore_ascii_case(expected) {
+        error!(
+            event = "integrity.manifest_mismatch",
+            snapshot_id,
+            object_id = manifest_object_id,
+            expected_sha256 = expected,
+            actual_sha256 = %actual,
+            "integrity.manifest_mismatch"
+        );
+        return Err(Error::ManifestMismatch {
+            snapshot_id: snapshot_id.to_string(),
+            object_id: manifest_object_id.to_string(),
+            expected_sha256: expected.to_string(),
+            actual_sha256: actual,
+        });
+    }
+    Ok(())
+}
+
+/// Downloads a snapshot's encrypted index manifest object as stored, checked against
+/// `expected_manifest_sha256` when given (`televybackup index export --raw-encrypted`).
+pub async fn download_encrypted_manifest<S: Storage>(
+    storage: &S,
+    snapshot_id: &str,
+    manifest_object_id: &str,
+    expected_manifest_sha256: Option<&str>,
+) -> Result<Vec<u8>> {
+    let manifest_enc = storage.download_document(manifest_object_id).await?;
+    check_manifest_sha256(
+        snapshot_id,
+        manifest_object_id,
+        expected_manifest_sha256,
+        &manifest_enc,
+    )?;
+    Ok(manifest_enc)
+}
+
+/// Downloads an index DB via its manifest and writes it atomically to `index_db_path`.
+///
+/// With `expected_manifest_sha256`, the downloaded (still encrypted) manifest must hash to it,
+/// otherwise [`Error::ManifestMismatch`] is returned before anything else is read.
+///
+/// A delta filemap index is rebuilt by following its parents back to the last full index; if one
+/// of them is gone, [`Error::IndexChainBroken`] names it.
+///
+/// Parts are streamed to temp files next to `index_db_path` one at a time, so memory use stays at
+/// a few MiB (about one part plus the zstd window) whatever the size of the index.
 #[allow(clippy::too_many_arguments)]
 pub async fn download_and_write_index_db_atomic<S: Storage>(
     storage: &S,
     snapshot_id: &str,
     manifest_object_id: &str,
+    expected_manifest_sha256: Option<&str>,
     master_key: &[u8; 32],
     index_db_path: &Path,
     cancel: Option<&CancellationToken>,
     normalize_provider: Option<&str>,
     progress: Option<&dyn ProgressSink>,
 ) -> Result<DownloadedIndexDbStats> {
+    if let Some(parent) = index_db_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut totals = DownloadTotals::default();
+    let mut temp_paths = Vec::new();
+    let res = async {
+        let tmp = sibling_temp_path(index_db_path, "tmp");
+        temp_paths.push(tmp.clone());
+        let manifest = download_index_db_file(
+            storage,
+            snapshot_id,
+            manifest_object_id,
+            expected_manifest_sha256,
+            master_key,
+            cancel,
+            progress,
+            &tmp,
+            &mut totals,
+        )
+        .await?;
+
+        let db_path = match manifest.parent {
+            None => tmp,
+            Some(parent) => {
+                // Walk back to the last full index, then replay the deltas from oldest to newest.
+                let mut deltas = vec![(snapshot_id.to_string(), parent.clone(), tmp)];
+                let mut next = parent;
+                let full_path = loop {
+                    if deltas.len() > MAX_INDEX_CHAIN_LEN {
+                        return Err(Error::IndexChainBroken {
+                            snapshot_id: snapshot_id.to_string(),
+                            missing_snapshot_id: next.snapshot_id.clone(),
+                            message: format!(
+                                "no full index within {MAX_INDEX_CHAIN_LEN} delta indexes"
+                            ),
+                        });
+                    }
+                    let path = sibling_temp_path(index_db_path, "delta");
+                    temp_paths.push(path.clone());
+                    let manifest = download_index_db_file(
+                        storage,
+                        &next.snapshot_id,
+                        &next.manifest_object_id,
+                        Some(&next.manifest_sha256),
+                        master_key,
+                        cancel,
+                        progress,
+                        &path,
+                        &mut totals,
+                    )
+                    .await
+                    .map_err(|e| chain_broken_or(e, snapshot_id, &next.snapshot_id))?;
+                    match manifest.parent {
+                        Some(parent) => {
+                            deltas.push((next.snapshot_id.clone(), parent.clone(), path));
+                            next = parent;
+                        }
+                        None => break path,
+                    }
+                };
+                for (snapshot_id, parent, delta_path) in deltas.into_iter().rev() {
+                    apply_filemap_delta_db(
+                        &full_path,
+                        &delta_path,
+                        &parent.snapshot_id,
+                        &snapshot_id,
+                    )
+                    .await?;
+                    fs::remove_file(&delta_path)?;
+                }
+                full_path
+            }
+        };
+
+        if let Some(provider) = normalize_provider {
+            normalize_provider_in_index_db(&db_path, provider).await?;
+        }
+        let bytes_written = fs::metadata(&db_path)?.len();
+        replace_atomic(&db_path, index_db_path)?;
+        Ok(bytes_written)
+    }
+    .await;
+    let bytes_written = match res {
+        Ok(bytes_written) => bytes_written,
+        Err(e) => {
+            for path in &temp_paths {
+                let _ = fs::remove_file(path);
+            }
+            return Err(e);
+        }
+    };
+
+    let DownloadTotals {
+        bytes_downloaded,
+        net_bytes_downloaded,
+        have_net_bytes,
+        bytes_written: _,
+    } = totals;
+    if let Some(sink) = progress {
+        sink.on_progress(TaskProgress {
+            phase: Phase::Index,
+            bytes_downloaded: Some(bytes_downloaded),
+            net_bytes_downloaded: have_net_bytes.then_some(net_bytes_downloaded),
+            bytes_written: Some(bytes_written),
+            ..TaskProgress::default()
+        });
+    }
+
+    Ok(DownloadedIndexDbStats {
+        bytes_downloaded,
+        net_bytes_downloaded: have_net_bytes.then_some(net_bytes_downloaded),
+        bytes_written,
+    })
+}
+
+/// Bytes downloaded and written so far across the manifests and parts of one index chain.
+#[derive(Debug, Default)]
+struct DownloadTotals {
+    bytes_downloaded: u64,
+    net_bytes_downloaded: u64,
+    have_net_bytes: bool,
+    bytes_written: u64,
+}
+
+/// Counts the bytes written through it.
+struct CountingWriter<W> {
+    inner: W,
+    written: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Downloads one manifest and its parts into the new file `out_path`: each part is checked
+/// against its size and hash, decrypted and fed through the zstd decoder before the next one is
+/// fetched. The file holds the SQLite DB (a delta DB when the manifest has a parent).
+#[allow(clippy::too_many_arguments)]
+async fn download_index_db_file<S: Storage>(
+    storage: &S,
+    snapshot_id: &str,
+    manifest_object_id: &str,
+    expected_manifest_sha256: Option<&str>,
+    master_key: &[u8; 32],
+    cancel: Option<&CancellationToken>,
+    progress: Option<&dyn ProgressSink>,
+    out_path: &Path,
+    totals: &mut DownloadTotals,
+) -> Result<IndexManifest> {
     if let Some(cancel) = cancel
         && cancel.is_cancelled()
     {
         return Err(Error::Cancelled);
     }
 
-    let mut bytes_downloaded = 0u64;
-    let mut net_bytes_downloaded = 0u64;
-    let mut have_net_bytes = false;
+    let mut bytes_downloaded = totals.bytes_downloaded;
+    let mut net_bytes_downloaded = totals.net_bytes_downloaded;
+    let mut have_net_bytes = totals.have_net_bytes;
 
     // Use streaming progress when the storage supports it so UI bandwidth indicators don't
     // "fall to zero" during long downloads (e.g. large remote index parts).
@@ -62,7 +302,7 @@ pub async fn download_and_write_index_db_atomic<S: Storage>(
                 }
                 if let Some(sink) = progress {
                     sink.on_progress(TaskProgress {
-                        phase: "index".to_string(),
+                        phase: Phase::Index,
                         bytes_downloaded: Some(base_total.saturating_add(n)),
                         net_bytes_downloaded: p
                             .net_bytes
@@ -97,13 +337,20 @@ pub async fn download_and_write_index_db_atomic<S: Storage>(
     }
     if let Some(sink) = progress {
         sink.on_progress(TaskProgress {
-            phase: "index".to_string(),
+            phase: Phase::Index,
             bytes_downloaded: Some(bytes_downloaded),
             net_bytes_downloaded: (streamed_net != u64::MAX).then_some(net_bytes_downloaded),
             ..TaskProgress::default()
         });
     }
 
+    check_manifest_sha256(
+        snapshot_id,
+        manifest_object_id,
+        expected_manifest_sha256,
+        &manifest_enc,
+    )?;
+
     let manifest_json = decrypt_framed(master_key, snapshot_id.as_bytes(), &manifest_enc).map_err(
         |e| Error::Crypto {
             message: format!(
@@ -117,7 +364,11 @@ pub async fn download_and_write_index_db_atomic<S: Storage>(
             message: format!("invalid index manifest json: {e}"),
         })?;
 
-    if manifest.version != 1 {
+    let version_ok = match manifest.parent {
+        None => manifest.version == INDEX_MANIFEST_VERSION_FULL,
+        Some(_) => manifest.version == INDEX_MANIFEST_VERSION_DELTA,
+    };
+    if !version_ok {
         return Err(Error::InvalidConfig {
             message: format!("unsupported manifest version: {}", manifest.version),
         });
@@ -141,7 +392,11 @@ pub async fn download_and_write_index_db_atomic<S: Storage>(
     let mut parts = manifest.parts.clone();
     parts.sort_by_key(|p| p.no);
 
-    let mut compressed = Vec::new();
+    let base_written = totals.bytes_written;
+    let mut decoder = zstd::stream::write::Decoder::new(CountingWriter {
+        inner: std::io::BufWriter::new(create_private_file(out_path)?),
+        written: 0,
+    })?;
     for part in parts {
         if let Some(cancel) = cancel
             && cancel.is_cancelled()
@@ -168,7 +423,7 @@ pub async fn download_and_write_index_db_atomic<S: Storage>(
                     }
                     if let Some(sink) = progress {
                         sink.on_progress(TaskProgress {
-                            phase: "index".to_string(),
+                            phase: Phase::Index,
                             bytes_downloaded: Some(base_total.saturating_add(n)),
                             net_bytes_downloaded: p
                                 .net_bytes
@@ -189,7 +444,7 @@ pub async fn download_and_write_index_db_atomic<S: Storage>(
                     "io.telegram.download_failed"
                 );
                 match e {
-                    Error::Telegram { message } => {
+                    Error::Telegram { message, .. } => {
                         // Treat "not found" style errors as permanent missing data, but keep
                         // timeouts/transient failures as retryable telegram errors.
                         if message.contains("message not found")
@@ -200,7 +455,7 @@ pub async fn download_and_write_index_db_atomic<S: Storage>(
                                 part_no: part.no,
                             }
                         } else {
-                            Error::Telegram { message }
+                            Error::telegram(message)
                         }
                     }
                     other => other,
@@ -218,15 +473,6 @@ pub async fn download_and_write_index_db_atomic<S: Storage>(
             net_bytes_downloaded = base_net_total.saturating_add(streamed_net);
             have_net_bytes = true;
         }
-        if let Some(sink) = progress {
-            sink.on_progress(TaskProgress {
-                phase: "index".to_string(),
-                bytes_downloaded: Some(bytes_downloaded),
-                net_bytes_downloaded: (streamed_net != u64::MAX).then_some(net_bytes_downloaded),
-                ..TaskProgress::default()
-            });
-        }
-
         if part_enc.len() != part.size {
             return Err(Error::Integrity {
                 message: format!(
@@ -249,7 +495,7 @@ pub async fn download_and_write_index_db_atomic<S: Storage>(
         }
 
         let aad = index_part_aad(snapshot_id, part.no);
-        let part_plain = decrypt_framed(master_key, aad.as_bytes(), &part_enc).map_err(|e| {
+        let part_plain = decrypt_framed_vec(master_key, aad.as_bytes(), part_enc).map_err(|e| {
             Error::Crypto {
                 message: format!(
                     "index part decrypt failed: snapshot_id={snapshot_id} part_no={} object_id={}; {e}",
@@ -257,65 +503,83 @@ pub async fn download_and_write_index_db_atomic<S: Storage>(
                 ),
             }
         })?;
-        compressed.extend_from_slice(&part_plain);
-    }
-
-    let sqlite_bytes = zstd::stream::decode_all(compressed.as_slice())?;
-    let bytes_written = sqlite_bytes.len() as u64;
+        decoder.write_all(&part_plain)?;
+        drop(part_plain);
 
-    write_index_db_atomic(index_db_path, &sqlite_bytes, normalize_provider).await?;
-    if let Some(sink) = progress {
-        sink.on_progress(TaskProgress {
-            phase: "index".to_string(),
-            bytes_downloaded: Some(bytes_downloaded),
-            net_bytes_downloaded: have_net_bytes.then_some(net_bytes_downloaded),
-            ..TaskProgress::default()
-        });
+        if let Some(sink) = progress {
+            sink.on_progress(TaskProgress {
+                phase: Phase::Index,
+                bytes_downloaded: Some(bytes_downloaded),
+                net_bytes_downloaded: (streamed_net != u64::MAX).then_some(net_bytes_downloaded),
+                bytes_written: Some(base_written + decoder.get_ref().written),
+                ..TaskProgress::default()
+            });
+        }
     }
 
-    Ok(DownloadedIndexDbStats {
+    decoder.flush()?;
+    let CountingWriter { inner, written } = decoder.into_inner();
+    inner.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    *totals = DownloadTotals {
         bytes_downloaded,
-        net_bytes_downloaded: have_net_bytes.then_some(net_bytes_downloaded),
-        bytes_written,
-    })
+        net_bytes_downloaded,
+        have_net_bytes,
+        bytes_written: base_written + written,
+    };
+    Ok(manifest)
 }
 
-async fn write_index_db_atomic(
-    path: &Path,
-    bytes: &[u8],
-    normalize_provider: Option<&str>,
-) -> Result<()> {
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent)?;
+/// Failures that mean an index the chain builds on is gone (as opposed to e.g. a timeout).
+fn chain_broken_or(e: Error, snapshot_id: &str, missing_snapshot_id: &str) -> Error {
+    let gone = match &e {
+        Error::MissingIndexPart { .. } | Error::ManifestMismatch { .. } => true,
+        Error::Telegram { message, .. } => {
+            message.contains("message not found") || message.contains("document mismatch")
+        }
+        _ => false,
+    };
+    if !gone {
+        return e;
     }
+    error!(
+        event = "index.chain_broken",
+        snapshot_id,
+        missing_snapshot_id,
+        error = %e,
+        "index.chain_broken"
+    );
+    Error::IndexChainBroken {
+        snapshot_id: snapshot_id.to_string(),
+        missing_snapshot_id: missing_snapshot_id.to_string(),
+        message: e.to_string(),
+    }
+}
 
+/// A fresh path next to `path` for a file that is renamed over it or removed.
+fn sibling_temp_path(path: &Path, kind: &str) -> PathBuf {
     let mut tmp = path.to_path_buf();
-    tmp.set_extension(format!("tmp-{}", uuid::Uuid::new_v4()));
+    tmp.set_extension(format!("{kind}-{}", uuid::Uuid::new_v4()));
+    tmp
+}
 
+fn create_private_file(path: &Path) -> std::io::Result<fs::File> {
     #[cfg(unix)]
     {
         use std::os::unix::fs::OpenOptionsExt;
-        let mut f = fs::OpenOptions::new()
+        fs::OpenOptions::new()
             .create_new(true)
             .write(true)
-            .truncate(true)
             .mode(0o600)
-            .open(&tmp)?;
-        f.write_all(bytes)?;
-        f.sync_all()?;
+            .open(path)
     }
 
     #[cfg(not(unix))]
     {
-        fs::write(&tmp, bytes)?;
-    }
-
-    if let Some(provider) = normalize_provider {
-        normalize_provider_in_index_db(&tmp, provider).await?;
+        fs::OpenOptions::new()
+            .create_new(true)
+            .write(true)
+            .open(path)
     }
-
-    replace_atomic(&tmp, path)?;
-    Ok(())
 }
 
 async fn normalize_provider_in_index_db(path: &Path, provider: &str) -> Result<()> {
@@ -415,9 +679,7 @@ mod tests {
         ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>>> + Send + 'a>> {
             Box::pin(async move {
                 if object_id == self.fail_object_id {
-                    return Err(Error::Telegram {
-                        message: self.fail_message.clone(),
-                    });
+                    return Err(Error::telegram(self.fail_message.clone()));
                 }
                 self.inner.download_document(object_id).await
             })
@@ -462,6 +724,9 @@ mod tests {
             hash_alg: "blake3".to_string(),
             enc_alg: "xchacha20poly1305".to_string(),
             compression: "zstd".to_string(),
+            device_id: None,
+            device_name: None,
+            parent: None,
             parts: vec![crate::index_manifest::IndexManifestPart {
                 no: part_no,
                 size: storage
@@ -486,6 +751,7 @@ mod tests {
             &storage,
             snapshot_id,
             &manifest_object_id,
+            None,
             &master_key,
             &out_db,
             None,
@@ -564,6 +830,9 @@ mod tests {
             hash_alg: "blake3".to_string(),
             enc_alg: "xchacha20poly1305".to_string(),
             compression: "zstd".to_string(),
+            device_id: None,
+            device_name: None,
+            parent: None,
             parts: vec![crate::index_manifest::IndexManifestPart {
                 no: part_no,
                 size: storage
@@ -588,6 +857,7 @@ mod tests {
             &storage,
             snapshot_id,
             &manifest_object_id,
+            None,
             &master_key,
             &out_db,
             None,
@@ -650,6 +920,9 @@ mod tests {
             hash_alg: "blake3".to_string(),
             enc_alg: "xchacha20poly1305".to_string(),
             compression: "zstd".to_string(),
+            device_id: None,
+            device_name: None,
+            parent: None,
             parts: vec![crate::index_manifest::IndexManifestPart {
                 no: part_no,
                 size: inner
@@ -680,6 +953,7 @@ mod tests {
             &storage,
             snapshot_id,
             &manifest_object_id,
+            None,
             &master_key,
             &out_db,
             None,
@@ -718,6 +992,9 @@ mod tests {
             hash_alg: "blake3".to_string(),
             enc_alg: "xchacha20poly1305".to_string(),
             compression: "zstd".to_string(),
+            device_id: None,
+            device_name: None,
+            parent: None,
             parts: vec![crate::index_manifest::IndexManifestPart {
                 no: part_no,
                 size: inner
@@ -748,6 +1025,7 @@ mod tests {
             &storage,
             snapshot_id,
             &manifest_object_id,
+            None,
             &master_key,
             &out_db,
             None,
@@ -759,4 +1037,133 @@ mod tests {
 
         assert_eq!(err.code(), "index.part_missing");
     }
+
+    #[derive(Default)]
+    struct WrittenProgress(std::sync::Mutex<Vec<u64>>);
+
+    impl ProgressSink for WrittenProgress {
+        fn on_progress(&self, progress: TaskProgress) {
+            if let Some(bytes) = progress.bytes_written {
+                self.0.lock().unwrap().push(bytes);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn large_index_downloads_with_bounded_memory() {
+        const PAD_ROWS: usize = 24;
+        const PAD_ROW_BYTES: usize = 16 * 1024 * 1024;
+        const PART_BYTES: usize = 64 * 1024;
+
+        let dir = tempfile::tempdir().unwrap();
+        let source_db = dir.path().join("source.sqlite");
+        let out_db = dir.path().join("out.sqlite");
+
+        let pool = crate::index_db::open_index_db(&source_db).await.unwrap();
+        sqlx::query(
+            "INSERT INTO snapshots (snapshot_id, created_at, source_path, label, base_snapshot_id) VALUES ('snp_big', '2026-01-01T00:00:00Z', '/', 'manual', NULL)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query("CREATE TABLE pad (b BLOB NOT NULL)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        for _ in 0..PAD_ROWS {
+            sqlx::query("INSERT INTO pad (b) VALUES (zeroblob(?))")
+                .bind(PAD_ROW_BYTES as i64)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        pool.close().await;
+        let sqlite_len = fs::metadata(&source_db).unwrap().len();
+        assert!(sqlite_len > (PAD_ROWS * PAD_ROW_BYTES) as u64);
+
+        let mut compressed = Vec::new();
+        {
+            let mut encoder = zstd::stream::Encoder::new(&mut compressed, 0).unwrap();
+            std::io::copy(&mut fs::File::open(&source_db).unwrap(), &mut encoder).unwrap();
+            encoder.finish().unwrap();
+        }
+
+        let snapshot_id = "snp_big";
+        let master_key = [9u8; 32];
+        let storage = crate::InMemoryStorage::new();
+        let mut parts = Vec::new();
+        for (no, plain) in compressed.chunks(PART_BYTES).enumerate() {
+            let no = no as u32;
+            let aad = index_part_aad(snapshot_id, no);
+            let part_enc =
+                crate::crypto::encrypt_framed(&master_key, aad.as_bytes(), plain).unwrap();
+            parts.push(crate::index_manifest::IndexManifestPart {
+                no,
+                size: part_enc.len(),
+                hash: blake3::hash(&part_enc).to_hex().to_string(),
+                object_id: storage.upload_document("part.dat", part_enc).await.unwrap(),
+            });
+        }
+        assert!(parts.len() > 1);
+        let manifest = IndexManifest {
+            version: INDEX_MANIFEST_VERSION_FULL,
+            snapshot_id: snapshot_id.to_string(),
+            hash_alg: "blake3".to_string(),
+            enc_alg: "xchacha20poly1305".to_string(),
+            compression: "zstd".to_string(),
+            device_id: None,
+            device_name: None,
+            parent: None,
+            parts,
+        };
+        let manifest_enc = crate::crypto::encrypt_framed(
+            &master_key,
+            snapshot_id.as_bytes(),
+            &serde_json::to_vec(&manifest).unwrap(),
+        )
+        .unwrap();
+        let manifest_object_id = storage
+            .upload_document("manifest.dat", manifest_enc)
+            .await
+            .unwrap();
+
+        let progress = WrittenProgress::default();
+        crate::alloc_tracking::start();
+        let stats = download_and_write_index_db_atomic(
+            &storage,
+            snapshot_id,
+            &manifest_object_id,
+            None,
+            &master_key,
+            &out_db,
+            None,
+            None,
+            Some(&progress),
+        )
+        .await;
+        let peak = crate::alloc_tracking::stop();
+        let stats = stats.unwrap();
+
+        assert_eq!(stats.bytes_written, sqlite_len);
+        assert!(
+            peak < 4 * 1024 * 1024,
+            "peak allocation {peak} bytes for a {sqlite_len}-byte index"
+        );
+        let written = progress.0.into_inner().unwrap();
+        assert!(written.len() > 2, "{written:?}");
+        assert!(written.windows(2).all(|w| w[0] <= w[1]), "{written:?}");
+        assert_eq!(written.last().copied(), Some(sqlite_len));
+
+        let pool = crate::index_db::open_existing_index_db(&out_db)
+            .await
+            .unwrap();
+        let (rows, bytes): (i64, i64) = sqlx::query_as("SELECT COUNT(*), SUM(length(b)) FROM pad")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            (rows as usize, bytes as usize),
+            (PAD_ROWS, PAD_ROWS * PAD_ROW_BYTES)
+        );
+    }
 }
@@ -0,0 +1,537 @@
+//! Offline copies of an endpoint's repository ("cold copy", `televybackup repo export`).
+//!
+//! An export is a plain directory:
+//!
+//! - `repo.json`: a [`RepoManifest`] naming the format version, the endpoint and the provider the
+//!   objects came from;
+//! - `objects/<xx>/<sha256 of the object id>`: every object the endpoint's index references, byte
+//!   for byte as stored (still encrypted), including the bootstrap catalog, the endpoint DB and
+//!   dedupe catalog objects, and each snapshot's file map manifest and parts;
+//! - `index/index.<endpoint_id>.sqlite`: a copy of the exporting machine's endpoint index DB.
+//!
+//! [`crate::LocalDirStorage`] reads it back, so restore and verify run against the export exactly
+//! as they run against the chat. Re-running an export only downloads objects that are missing or
+//! whose size differs from the provider's.
+
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+use sqlx::Row;
+use tokio_util::sync::CancellationToken;
+
+use crate::bootstrap::{BootstrapCatalogV1, decrypt_catalog};
+use crate::config::Retry;
+use crate::fs_sync::sync_dir;
+use crate::index_db::open_existing_index_db;
+use crate::index_sync::{
+    ENDPOINT_STATE_ENDPOINT_INDEX_ID_KEY, ENDPOINT_STATE_ENDPOINT_MANIFEST_OBJECT_ID_KEY,
+    endpoint_state_get,
+};
+use crate::progress::{Phase, ProgressSink, TaskProgress};
+use crate::retry::{RetryBudget, RetryStats};
+use crate::storage::{ChunkObjectRef, Storage, parse_chunk_object_ref};
+use crate::{Error, Result};
+
+/// `format` of every `repo.json`.
+pub const REPO_EXPORT_FORMAT: &str = "televybackup.repo";
+/// Newest `repo.json` version this build writes and reads.
+pub const REPO_EXPORT_VERSION: u32 = 1;
+pub const REPO_MANIFEST_FILE: &str = "repo.json";
+const OBJECTS_DIR: &str = "objects";
+const INDEX_DIR: &str = "index";
+
+/// `repo.json` at the root of an export.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepoManifest {
+    pub format: String,
+    pub version: u32,
+    /// Version of the televybackup build that wrote the export.
+    pub created_by: String,
+    /// RFC3339; when the last export run finished.
+    pub exported_at: String,
+    pub endpoint_id: String,
+    /// Provider of the exported objects (`telegram.mtproto/<endpoint_id>`); index rows of other
+    /// providers are not exported.
+    pub provider: String,
+    /// Object id scope of the provider (the chat id), from which endpoint index and dedupe ids
+    /// are derived when the index does not record them.
+    #[serde(default)]
+    pub object_id_scope: Option<String>,
+    /// The endpoint's bootstrap catalog object at export time.
+    #[serde(default)]
+    pub bootstrap_catalog_object_id: Option<String>,
+    #[serde(default)]
+    pub objects: u64,
+    #[serde(default)]
+    pub bytes: u64,
+}
+
+impl RepoManifest {
+    fn check_supported(&self, root: &Path) -> Result<()> {
+        if self.format != REPO_EXPORT_FORMAT {
+            return Err(Error::InvalidConfig {
+                message: format!(
+                    "not a televybackup repo export: {} (format={})",
+                    root.display(),
+                    self.format
+                ),
+            });
+        }
+        if self.version > REPO_EXPORT_VERSION {
+            return Err(Error::InvalidConfig {
+                message: format!(
+                    "repo export version {} is newer than this build reads ({REPO_EXPORT_VERSION}); upgrade televybackup: {}",
+                    self.version,
+                    root.display()
+                ),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Reads and checks `<root>/repo.json`.
+pub fn read_repo_manifest(root: &Path) -> Result<RepoManifest> {
+    let path = root.join(REPO_MANIFEST_FILE);
+    let text = std::fs::read_to_string(&path).map_err(|e| Error::InvalidConfig {
+        message: format!("repo export manifest unreadable: {}: {e}", path.display()),
+    })?;
+    let manifest: RepoManifest = serde_json::from_str(&text).map_err(|e| Error::InvalidConfig {
+        message: format!("repo export manifest invalid: {}: {e}", path.display()),
+    })?;
+    manifest.check_supported(root)?;
+    Ok(manifest)
+}
+
+fn write_repo_manifest(root: &Path, manifest: &RepoManifest) -> Result<()> {
+    let json = serde_json::to_vec_pretty(manifest).map_err(|e| Error::InvalidConfig {
+        message: format!("repo export manifest encode failed: {e}"),
+    })?;
+    write_file_atomic(&root.join(REPO_MANIFEST_FILE), &json)
+}
+
+/// Where an export keeps `object_id`.
+pub(crate) fn object_path(root: &Path, object_id: &str) -> PathBuf {
+    let name = hex::encode(sha2::Sha256::digest(object_id.as_bytes()));
+    root.join(OBJECTS_DIR).join(&name[..2]).join(name)
+}
+
+/// The endpoint index DB copy inside an export.
+pub fn repo_index_db_path(root: &Path, endpoint_id: &str) -> PathBuf {
+    root.join(INDEX_DIR)
+        .join(format!("index.{endpoint_id}.sqlite"))
+}
+
+/// Temp file, fsync, rename, then fsync of the directory: resume trusts any object of the right
+/// size, so a crash or an unplugged disk must not leave one whose contents never reached it.
+fn write_file_atomic(path: &Path, bytes: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let tmp = path.with_extension("tmp");
+    let mut f = std::fs::File::create(&tmp)?;
+    f.write_all(bytes)?;
+    f.sync_all()?;
+    drop(f);
+    std::fs::rename(&tmp, path)?;
+    if let Some(parent) = path.parent() {
+        sync_dir(parent);
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct RepoExportConfig {
+    pub endpoint_id: String,
+    /// This machine's endpoint index DB; it lists the objects to export and is copied along.
+    pub endpoint_db_path: PathBuf,
+    /// Bootstrap catalog object of the endpoint, if it keeps one. Its endpoint DB and dedupe
+    /// catalog pointers bring in snapshots other machines made.
+    pub bootstrap_catalog_object_id: Option<String>,
+    pub master_key: [u8; 32],
+    pub output_dir: PathBuf,
+}
+
+#[derive(Clone, Default)]
+pub struct RepoExportOptions<'a> {
+    pub cancel: Option<&'a CancellationToken>,
+    pub progress: Option<&'a dyn ProgressSink>,
+    /// Retries of transient download failures (`retry.*`).
+    pub retry: Retry,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepoExportResult {
+    pub objects_total: u64,
+    pub objects_downloaded: u64,
+    /// Objects already in the export with the provider's size, left alone.
+    pub objects_skipped: u64,
+    pub bytes_downloaded: u64,
+    /// Size of all exported objects.
+    pub bytes_total: u64,
+    pub retry: RetryStats,
+}
+
+/// Copies every object the endpoint's index references from `storage` into
+/// `config.output_dir` (see the module docs). An existing export of the same endpoint is
+/// resumed; one of another endpoint or provider is refused.
+pub async fn export_repo<S: Storage + Sync>(
+    storage: &S,
+    config: &RepoExportConfig,
+    options: RepoExportOptions<'_>,
+) -> Result<RepoExportResult> {
+    let root = config.output_dir.as_path();
+    let provider = storage.provider().to_string();
+    match read_repo_manifest(root) {
+        Ok(existing)
+            if existing.endpoint_id != config.endpoint_id || existing.provider != provider =>
+        {
+            return Err(Error::InvalidConfig {
+                message: format!(
+                    "{} holds an export of endpoint {} ({}), not {} ({provider})",
+                    root.display(),
+                    existing.endpoint_id,
+                    existing.provider,
+                    config.endpoint_id
+                ),
+            });
+        }
+        Ok(_) => {}
+        Err(_) if !root.join(REPO_MANIFEST_FILE).exists() => {}
+        Err(e) => return Err(e),
+    }
+    std::fs::create_dir_all(root.join(OBJECTS_DIR))?;
+
+    let retry = RetryBudget::new(&options.retry, options.cancel);
+    let exporter = ExportingStorage {
+        inner: storage,
+        root,
+        retry: &retry,
+        cancel: options.cancel,
+        totals: Default::default(),
+    };
+    let cancelled = || options.cancel.is_some_and(|c| c.is_cancelled());
+    report(&options, Phase::Index, 0, 0, 0);
+
+    let catalog: Option<BootstrapCatalogV1> = match &config.bootstrap_catalog_object_id {
+        Some(object_id) => {
+            let bytes = exporter.download_document(object_id).await?;
+            Some(decrypt_catalog(&config.master_key, &bytes)?)
+        }
+        None => None,
+    };
+
+    // The endpoint DB and dedupe catalog are fetched through the exporter: that stores their
+    // objects, and the scratch copies list chunks from other machines.
+    let scratch = tempfile::Builder::new()
+        .prefix(".export-")
+        .tempdir_in(root)?;
+    let mut index_dbs = Vec::new();
+    if config.endpoint_db_path.exists() {
+        index_dbs.push(config.endpoint_db_path.clone());
+    }
+    let (endpoint_manifest_object_id, endpoint_index_id) =
+        match catalog.as_ref().and_then(|c| c.endpoint_latest.as_ref()) {
+            Some(latest) => (
+                Some(latest.manifest_object_id.clone()),
+                Some(latest.endpoint_index_id.clone()),
+            ),
+            None => (
+                endpoint_state_get(
+                    &config.endpoint_db_path,
+                    ENDPOINT_STATE_ENDPOINT_MANIFEST_OBJECT_ID_KEY,
+                )
+                .await?,
+                endpoint_state_get(
+                    &config.endpoint_db_path,
+                    ENDPOINT_STATE_ENDPOINT_INDEX_ID_KEY,
+                )
+                .await?,
+            ),
+        };
+    if let Some(manifest_object_id) = endpoint_manifest_object_id {
+        let endpoint_index_id = match endpoint_index_id {
+            Some(v) => v,
+            None => crate::bootstrap::endpoint_index_id_for_storage(storage)?,
+        };
+        let path = scratch.path().join("endpoint.sqlite");
+        crate::remote_index_db::download_and_write_index_db_atomic(
+            &exporter,
+            &endpoint_index_id,
+            &manifest_object_id,
+            None,
+            &config.master_key,
+            &path,
+            options.cancel,
+            Some(&provider),
+            None,
+        )
+        .await?;
+        index_dbs.push(path);
+    }
+    if let Some(dedupe) = catalog
+        .as_ref()
+        .and_then(|c| c.endpoint_dedupe_latest.as_ref())
+    {
+        let path = scratch.path().join("dedupe.sqlite");
+        crate::dedupe_sync::materialize_remote_dedupe_db(
+            &exporter,
+            &config.master_key,
+            &dedupe.endpoint_dedupe_id,
+            &dedupe.catalog_object_id,
+            &path,
+            Some(&provider),
+            None,
+        )
+        .await?;
+        index_dbs.push(path);
+    }
+    if index_dbs.is_empty() {
+        return Err(Error::InvalidConfig {
+            message: format!(
+                "nothing to export: no local index db ({}) and no remote endpoint index",
+                config.endpoint_db_path.display()
+            ),
+        });
+    }
+
+    let mut object_ids = BTreeSet::new();
+    for db_path in &index_dbs {
+        collect_object_ids(db_path, &provider, &mut object_ids).await?;
+    }
+    let objects_total = object_ids.len() as u64;
+    let mut done = 0;
+    for object_id in &object_ids {
+        if cancelled() {
+            return Err(Error::Cancelled);
+        }
+        exporter.ensure(object_id).await?;
+        done += 1;
+        let totals = exporter.totals();
+        report(
+            &options,
+            Phase::Download,
+            objects_total,
+            done,
+            totals.bytes_downloaded,
+        );
+    }
+
+    if config.endpoint_db_path.exists() {
+        copy_index_db(
+            &config.endpoint_db_path,
+            &repo_index_db_path(root, &config.endpoint_id),
+        )
+        .await?;
+    }
+
+    let totals = exporter.totals();
+    let result = RepoExportResult {
+        objects_total: totals.objects_downloaded + totals.objects_skipped,
+        objects_downloaded: totals.objects_downloaded,
+        objects_skipped: totals.objects_skipped,
+        bytes_downloaded: totals.bytes_downloaded,
+        bytes_total: totals.bytes_total,
+        retry: retry.stats(),
+    };
+    write_repo_manifest(
+        root,
+        &RepoManifest {
+            format: REPO_EXPORT_FORMAT.to_string(),
+            version: REPO_EXPORT_VERSION,
+            created_by: format!("televybackup {}", crate::version::VERSION),
+            exported_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            endpoint_id: config.endpoint_id.clone(),
+            provider,
+            object_id_scope: storage.object_id_scope().map(str::to_string),
+            bootstrap_catalog_object_id: config.bootstrap_catalog_object_id.clone(),
+            objects: result.objects_total,
+            bytes: result.bytes_total,
+        },
+    )?;
+    Ok(result)
+}
+
+fn report(
+    options: &RepoExportOptions<'_>,
+    phase: Phase,
+    objects_total: u64,
+    objects_done: u64,
+    bytes_downloaded: u64,
+) {
+    if let Some(sink) = options.progress {
+        sink.on_progress(TaskProgress {
+            phase,
+            chunks_total: Some(objects_total),
+            chunks_done: Some(objects_done),
+            bytes_downloaded: Some(bytes_downloaded),
+            ..TaskProgress::default()
+        });
+    }
+}
+
+/// Storage objects referenced by `chunk_objects`, `remote_indexes` and `remote_index_parts` rows
+/// of `provider` in the index DB at `db_path` (tables it lacks are skipped).
+async fn collect_object_ids(
+    db_path: &Path,
+    provider: &str,
+    out: &mut BTreeSet<String>,
+) -> Result<()> {
+    let pool = open_existing_index_db(db_path).await?;
+    let tables: Vec<String> = sqlx::query_scalar(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name IN ('chunk_objects', 'remote_indexes', 'remote_index_parts')",
+    )
+    .fetch_all(&pool)
+    .await?;
+    let has = |name: &str| tables.iter().any(|t| t == name);
+    if has("chunk_objects") {
+        let rows = sqlx::query("SELECT object_id FROM chunk_objects WHERE provider = ?")
+            .bind(provider)
+            .fetch_all(&pool)
+            .await?;
+        for row in rows {
+            let object_id: String = row.get("object_id");
+            match parse_chunk_object_ref(&object_id)? {
+                ChunkObjectRef::Direct { object_id } => out.insert(object_id),
+                ChunkObjectRef::PackSlice { pack_object_id, .. } => out.insert(pack_object_id),
+            };
+        }
+    }
+    if has("remote_indexes") {
+        let ids: Vec<String> =
+            sqlx::query_scalar("SELECT manifest_object_id FROM remote_indexes WHERE provider = ?")
+                .bind(provider)
+                .fetch_all(&pool)
+                .await?;
+        out.extend(ids);
+    }
+    if has("remote_index_parts") {
+        let ids: Vec<String> =
+            sqlx::query_scalar("SELECT object_id FROM remote_index_parts WHERE provider = ?")
+                .bind(provider)
+                .fetch_all(&pool)
+                .await?;
+        out.extend(ids);
+    }
+    pool.close().await;
+    Ok(())
+}
+
+/// Consistent copy of a live index DB (`VACUUM INTO`), replacing `dest` atomically.
+async fn copy_index_db(src: &Path, dest: &Path) -> Result<()> {
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let tmp = dest.with_extension("sqlite.tmp");
+    match std::fs::remove_file(&tmp) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    let pool = open_existing_index_db(src).await?;
+    sqlx::query("VACUUM INTO ?")
+        .bind(tmp.to_string_lossy().as_ref())
+        .execute(&pool)
+        .await?;
+    pool.close().await;
+    std::fs::rename(&tmp, dest)?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct ExportTotals {
+    objects_downloaded: u64,
+    objects_skipped: u64,
+    bytes_downloaded: u64,
+    bytes_total: u64,
+}
+
+/// Reads through to `inner` and keeps every object it reads in the export; objects the export
+/// already holds (with the provider's size, when it reports one) are served from disk.
+struct ExportingStorage<'a, S> {
+    inner: &'a S,
+    root: &'a Path,
+    retry: &'a RetryBudget,
+    cancel: Option<&'a CancellationToken>,
+    totals: std::sync::Mutex<ExportTotals>,
+}
+
+impl<S: Storage + Sync> ExportingStorage<'_, S> {
+    fn totals(&self) -> ExportTotals {
+        *self.totals.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Makes sure the export holds `object_id`; returns its path.
+    async fn ensure(&self, object_id: &str) -> Result<PathBuf> {
+        let path = object_path(self.root, object_id);
+        if let Ok(meta) = std::fs::metadata(&path) {
+            let expected = self
+                .retry
+                .run("repo_export_size", || self.inner.object_size(object_id))
+                .await?;
+            if expected.is_none_or(|len| len == meta.len()) {
+                let mut totals = self.totals.lock().unwrap_or_else(|e| e.into_inner());
+                totals.objects_skipped += 1;
+                totals.bytes_total += meta.len();
+                return Ok(path);
+            }
+        }
+        if self.cancel.is_some_and(|c| c.is_cancelled()) {
+            return Err(Error::Cancelled);
+        }
+        let bytes = self
+            .retry
+            .run("repo_export_download", || {
+                self.inner.download_document(object_id)
+            })
+            .await?;
+        write_file_atomic(&path, &bytes)?;
+        let mut totals = self.totals.lock().unwrap_or_else(|e| e.into_inner());
+        totals.objects_downloaded += 1;
+        totals.bytes_downloaded += bytes.len() as u64;
+        totals.bytes_total += bytes.len() as u64;
+        Ok(path)
+    }
+}
+
+impl<S: Storage + Sync> Storage for ExportingStorage<'_, S> {
+    fn provider(&self) -> &str {
+        self.inner.provider()
+    }
+
+    fn object_id_scope(&self) -> Option<&str> {
+        self.inner.object_id_scope()
+    }
+
+    fn legacy_object_id_scopes(&self) -> &[String] {
+        self.inner.legacy_object_id_scopes()
+    }
+
+    fn upload_document<'a>(
+        &'a self,
+        _filename: &'a str,
+        _bytes: Vec<u8>,
+    ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>> {
+        Box::pin(async {
+            Err(Error::InvalidConfig {
+                message: "repo export does not upload".to_string(),
+            })
+        })
+    }
+
+    fn download_document<'a>(
+        &'a self,
+        object_id: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>>> + Send + 'a>> {
+        Box::pin(async move {
+            let path = self.ensure(object_id).await?;
+            Ok(std::fs::read(path)?)
+        })
+    }
+}
@@ -1,30 +1,52 @@
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::io::{Seek, SeekFrom, Write};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
+use futures::{StreamExt, TryStreamExt};
 use serde::{Deserialize, Serialize};
 use sqlx::{Row, SqlitePool};
-use tracing::{debug, error};
+use tracing::{debug, error, warn};
 
-use crate::crypto::decrypt_framed;
+use crate::case_fold::{CaseRenames, case_collisions, dir_is_case_insensitive};
+use crate::config::Retry;
+use crate::crypto::{DataKey, FRAMING_OVERHEAD_BYTES, decrypt_framed};
 use crate::dedupe_catalog::endpoint_dedupe_id_for_storage;
 use crate::dedupe_sync::materialize_remote_dedupe_db;
-use crate::index_db::open_existing_index_db;
+use crate::index_db::{
+    files_have_btime_column, files_have_owner_columns, open_existing_index_db,
+    snapshot_chunk_hash_alg,
+};
+use crate::long_paths::{PathLimits, RestorePaths};
+use crate::ownership::{OwnershipOptions, owner_change};
 use crate::pack::extract_pack_blob;
-use crate::progress::{ProgressSink, TaskProgress};
+use crate::progress::{Phase, PhaseTimings, ProgressSink, TaskProgress};
 use crate::remote_index_db::download_and_write_index_db_atomic;
-use crate::storage::{ChunkObjectRef, Storage, parse_chunk_object_ref};
+use crate::retry::{RetryBudget, RetryStats};
+use crate::storage::{
+    BATCH_MAX_OBJECTS, BATCH_MIN_OBJECTS, BATCH_SMALL_OBJECT_MAX_BYTES, ChunkObjectRef, Storage,
+    parse_chunk_object_ref,
+};
 use crate::{Error, Result};
 use tokio_util::sync::CancellationToken;
 
+/// Extra download attempts for a chunk that fails decryption or hash verification (bit flips,
+/// truncated documents) before the restore gives up on it.
+const RESTORE_CHUNK_VERIFY_RETRIES: u32 = 3;
+const RESTORE_CHUNK_RETRY_BASE_DELAY: Duration = Duration::from_millis(200);
+
 #[derive(Debug, Clone)]
 pub struct RestoreConfig {
     pub snapshot_id: String,
     /// Snapshot filemap manifest object id (the "per snapshot" index DB).
     pub filemap_manifest_object_id: String,
+    /// Expected hash of the filemap manifest (see `remote_index_db::manifest_sha256`). `None`
+    /// skips the check, e.g. for catalogs written by older versions.
+    pub filemap_manifest_sha256: Option<String>,
     /// Endpoint DB manifest object id (two-level mode). When set, restore/verify will download the
     /// endpoint DB and use it for `chunk_objects` lookups.
     pub endpoint_manifest_object_id: Option<String>,
@@ -37,7 +59,11 @@ pub struct RestoreConfig {
     /// Optional endpoint index id (AAD for endpoint DB manifest/parts). When omitted, it is
     /// derived from `storage.object_id_scope()` (or falls back to `storage.provider()` for tests).
     pub endpoint_index_id: Option<String>,
+    /// Decrypts the endpoint DB and the dedupe catalog; unused when neither is set.
     pub master_key: [u8; 32],
+    /// Key the snapshot's file map and chunks were written with (its recorded key derivation);
+    /// `None` uses `master_key`, as for every snapshot written before per-target keys.
+    pub data_key: Option<DataKey>,
     pub filemap_db_path: PathBuf,
     pub endpoint_db_path: Option<PathBuf>,
     pub dedupe_db_path: Option<PathBuf>,
@@ -47,28 +73,123 @@ pub struct RestoreConfig {
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct RestoreResult {
     pub files_restored: u64,
+    /// Directories recorded in the snapshot (empty ones included) that were created.
+    #[serde(default)]
+    pub dirs_restored: u64,
+    /// Chunks written to restored files; a chunk used in several places counts for each.
     pub chunks_downloaded: u64,
+    /// Storage objects (chunk objects and packs) downloaded. Each is fetched once however many
+    /// of its chunks the snapshot uses; re-downloads of a corrupt object count again.
+    #[serde(default)]
+    pub objects_downloaded: u64,
     pub bytes_written: u64,
+    /// Files left out of the restore (only non-zero with `RestoreOptions::keep_going`).
+    #[serde(default)]
+    pub files_failed: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failures: Vec<RestoreFailure>,
+    #[serde(default)]
+    pub phase_timings: PhaseTimings,
+    #[serde(default)]
+    pub retry: RetryStats,
+    /// Files and symlinks removed from the target by `RestoreOptions::delete_extraneous` (to be
+    /// removed, with `dry_run`), because the snapshot lacks them or has another kind there.
+    #[serde(default)]
+    pub files_deleted: u64,
+    /// Target-relative paths of those entries and of the directories removed along (`/` suffix).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deleted_paths: Vec<String>,
+    /// `(snapshot path, target-relative path)` of entries restored under another name by
+    /// `RestoreOptions::rename_collisions`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub renamed_paths: Vec<(String, String)>,
+    /// `(snapshot path, target-relative path)` of entries too long for the target, restored
+    /// under `.televybackup-long-paths/` by `RestoreOptions::shorten_long_paths`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shortened_paths: Vec<(String, String)>,
+    /// Files and directories whose owner `RestoreOptions::ownership` asked to change but could
+    /// not be changed (typically: not running as root). They keep the restoring user's owner.
+    #[serde(default)]
+    pub ownership_warnings: u64,
+    /// Time spent downloading and verifying chunks, not counting waits on busy writers.
+    #[serde(default)]
+    pub download_ms: u64,
+    /// Time the download side waited on busy writers, plus writing (and `at_end` fsync) left
+    /// after the last download.
+    #[serde(default)]
+    pub write_ms: u64,
+}
+
+/// A chunk (or whole file, when `chunk_hash` is `None`) that could not be restored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestoreFailure {
+    pub path: String,
+    pub chunk_hash: Option<String>,
+    pub object_id: Option<String>,
+    pub error: String,
 }
 
 #[derive(Debug, Clone)]
 pub struct VerifyConfig {
     pub snapshot_id: String,
     pub filemap_manifest_object_id: String,
+    pub filemap_manifest_sha256: Option<String>,
     pub endpoint_manifest_object_id: Option<String>,
     pub dedupe_catalog_object_id: Option<String>,
     pub endpoint_dedupe_id: Option<String>,
     pub endpoint_index_id: Option<String>,
     pub master_key: [u8; 32],
+    /// See [`RestoreConfig::data_key`].
+    pub data_key: Option<DataKey>,
     pub filemap_db_path: PathBuf,
     pub endpoint_db_path: Option<PathBuf>,
     pub dedupe_db_path: Option<PathBuf>,
+    /// Check only a subset of the snapshot's chunks (`None` = all of them).
+    pub sample: Option<VerifySample>,
+}
+
+/// Selects a deterministic, rotating subset of a snapshot's chunks for a cheap periodic check.
+///
+/// Every chunk gets a stable position on a ring (keyed by snapshot id and chunk hash); a run
+/// checks the `percent` wide window that starts at `rotation * percent`. Consecutive rotations
+/// check adjacent windows, so `ceil(100 / percent)` runs cover the whole snapshot.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VerifySample {
+    /// Share of the snapshot's chunks to check, in `(0, 100]`.
+    pub percent: f64,
+    /// Upper bound on the plaintext bytes checked; the window is cut short once it is reached.
+    pub max_bytes: Option<u64>,
+    pub rotation: u64,
+}
+
+impl VerifySample {
+    /// Sample that rotates once per week.
+    pub fn weekly(percent: f64, max_bytes: Option<u64>) -> Self {
+        const WEEK_SECS: u64 = 7 * 24 * 60 * 60;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Self {
+            percent,
+            max_bytes,
+            rotation: now / WEEK_SECS,
+        }
+    }
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct VerifyResult {
     pub chunks_checked: u64,
     pub bytes_checked: u64,
+    /// Chunks left out by sampling.
+    #[serde(default)]
+    pub chunks_skipped: u64,
+    /// `chunks_checked` as a share of the snapshot's chunks.
+    #[serde(default)]
+    pub coverage_percent: f64,
+    #[serde(default)]
+    pub phase_timings: PhaseTimings,
 }
 
 pub async fn restore_snapshot<S: Storage>(
@@ -82,27 +203,133 @@ pub async fn restore_snapshot<S: Storage>(
 pub struct RestoreOptions<'a> {
     pub cancel: Option<&'a CancellationToken>,
     pub progress: Option<&'a dyn ProgressSink>,
+    /// Record files with bad/missing chunks in `RestoreResult::failures` and keep restoring the
+    /// rest instead of failing on the first one. Failed files are not left in the target.
+    pub keep_going: bool,
+    /// Retries of transient download failures (`retry.*`).
+    pub retry: Retry,
+    /// Restore over a non-empty target, then remove everything under it that is not in the
+    /// snapshot, so the target ends up matching the snapshot.
+    pub delete_extraneous: bool,
+    /// With `delete_extraneous`: only list what would be removed. Nothing but the snapshot's
+    /// file map is downloaded and the target is left alone.
+    pub dry_run: bool,
+    /// Write the only file of a single-file snapshot exactly at `target_path` (which must not
+    /// exist yet) instead of at `target_path/<basename>`.
+    pub as_file: bool,
+    /// Give each restored file back its recorded mtime and, where the platform can set it
+    /// (macOS), its creation time. Elsewhere recorded creation times are skipped with one
+    /// `restore.btime_unsupported` warning per run.
+    pub preserve_times: bool,
+    /// When snapshot paths differ only in letter case and the target can't tell them apart,
+    /// restore the later ones as `name (case N).ext` instead of failing with
+    /// [`Error::CaseCollision`].
+    pub rename_collisions: bool,
+    /// Restore entries whose path the target can't hold (`NAME_MAX`/`PATH_MAX`) under
+    /// `.televybackup-long-paths/<hash>/`, listed in its `index.json`, instead of failing with
+    /// [`Error::PathTooLong`] before anything is downloaded.
+    pub shorten_long_paths: bool,
+    /// Limits to plan against instead of those the target's file system reports (`pathconf`),
+    /// e.g. for a target that is copied elsewhere afterwards.
+    pub path_limits: Option<PathLimits>,
+    /// Owners to give restored files and directories; by default they stay the restoring user's.
+    pub ownership: OwnershipOptions,
+    /// Files written at the same time; 0 means [`DEFAULT_RESTORE_WRITE_CONCURRENCY`].
+    pub write_concurrency: usize,
+    /// When restored files are flushed to disk (`restore.fsync`).
+    pub fsync: RestoreFsync,
+}
+
+/// Flushes a restored file to disk. Not a stable API: tests swap in a slow one through
+/// [`restore_snapshot_with_file_sync`] to model a disk whose fsyncs take a while.
+#[doc(hidden)]
+pub trait RestoreFileSync: Send + Sync {
+    fn sync(&self, file: &fs::File) -> std::io::Result<()>;
+}
+
+/// `File::sync_all`.
+struct SyncAll;
+
+impl RestoreFileSync for SyncAll {
+    fn sync(&self, file: &fs::File) -> std::io::Result<()> {
+        file.sync_all()
+    }
 }
 
 pub async fn restore_snapshot_with<S: Storage>(
     storage: &S,
     config: RestoreConfig,
     options: RestoreOptions<'_>,
+) -> Result<RestoreResult> {
+    restore_snapshot_with_file_sync(storage, config, options, Arc::new(SyncAll)).await
+}
+
+/// [`restore_snapshot_with`] flushing restored files through `file_sync`.
+#[doc(hidden)]
+pub async fn restore_snapshot_with_file_sync<S: Storage>(
+    storage: &S,
+    config: RestoreConfig,
+    options: RestoreOptions<'_>,
+    file_sync: Arc<dyn RestoreFileSync>,
 ) -> Result<RestoreResult> {
     let restore_started = Instant::now();
     debug!(event = "phase.start", phase = "restore", "phase.start");
+    let retry = RetryBudget::new(&options.retry, options.cancel);
+    if options.dry_run && !options.delete_extraneous {
+        return Err(Error::InvalidConfig {
+            message: "dry_run is only supported with delete_extraneous".to_string(),
+        });
+    }
+    if options.as_file {
+        if options.delete_extraneous {
+            return Err(Error::InvalidConfig {
+                message: "as_file cannot be combined with delete_extraneous".to_string(),
+            });
+        }
+        if config.target_path.symlink_metadata().is_ok() {
+            return Err(Error::InvalidConfig {
+                message: "target_path must not exist when restoring as a file".to_string(),
+            });
+        }
+    }
+    if options.delete_extraneous {
+        check_delete_extraneous_target(&config.target_path)?;
+    }
 
-    let stats = download_and_write_index_db_atomic(
-        storage,
-        &config.snapshot_id,
-        &config.filemap_manifest_object_id,
-        &config.master_key,
-        &config.filemap_db_path,
-        options.cancel,
-        Some(storage.provider()),
-        options.progress,
-    )
-    .await?;
+    let data_key = config
+        .data_key
+        .clone()
+        .unwrap_or_else(|| DataKey::master(&config.master_key));
+    let stats = retry
+        .run("index_download", || {
+            download_and_write_index_db_atomic(
+                storage,
+                &config.snapshot_id,
+                &config.filemap_manifest_object_id,
+                config.filemap_manifest_sha256.as_deref(),
+                data_key.key(),
+                &config.filemap_db_path,
+                options.cancel,
+                Some(storage.provider()),
+                options.progress,
+            )
+        })
+        .await?;
+
+    if options.dry_run {
+        let pool = open_existing_index_db(&config.filemap_db_path).await?;
+        ensure_snapshot_present(&pool, &config.snapshot_id).await?;
+        let entries = snapshot_entry_kinds(&pool, &config.snapshot_id).await?;
+        let mut result = RestoreResult::default();
+        if config.target_path.exists() {
+            delete_extraneous(&config.target_path, &entries, true, &mut result)?;
+        }
+        result
+            .phase_timings
+            .record(Phase::Index, restore_started.elapsed());
+        result.retry = retry.stats();
+        return Ok(result);
+    }
 
     let mut bytes_downloaded = stats.bytes_downloaded;
     let mut net_bytes_downloaded = stats.net_bytes_downloaded.unwrap_or(0);
@@ -149,16 +376,19 @@ pub async fn restore_snapshot_with<S: Storage>(
         });
         let progress_ref = progress.as_ref().map(|v| v as &dyn ProgressSink);
 
-        let dd_stats = materialize_remote_dedupe_db(
-            storage,
-            &config.master_key,
-            &endpoint_dedupe_id,
-            catalog_object_id,
-            dedupe_db_path,
-            Some(storage.provider()),
-            progress_ref,
-        )
-        .await?;
+        let dd_stats = retry
+            .run("dedupe_download", || {
+                materialize_remote_dedupe_db(
+                    storage,
+                    &config.master_key,
+                    &endpoint_dedupe_id,
+                    catalog_object_id,
+                    dedupe_db_path,
+                    Some(storage.provider()),
+                    progress_ref,
+                )
+            })
+            .await?;
 
         bytes_downloaded = bytes_downloaded
             .saturating_add(dd_stats.base_bytes_downloaded)
@@ -181,17 +411,21 @@ pub async fn restore_snapshot_with<S: Storage>(
             None => crate::bootstrap::endpoint_index_id_for_storage(storage)?,
         };
 
-        let ep_stats = download_and_write_index_db_atomic(
-            storage,
-            &endpoint_index_id,
-            endpoint_manifest_object_id,
-            &config.master_key,
-            endpoint_db_path,
-            options.cancel,
-            Some(storage.provider()),
-            options.progress,
-        )
-        .await?;
+        let ep_stats = retry
+            .run("index_download", || {
+                download_and_write_index_db_atomic(
+                    storage,
+                    &endpoint_index_id,
+                    endpoint_manifest_object_id,
+                    None,
+                    &config.master_key,
+                    endpoint_db_path,
+                    options.cancel,
+                    Some(storage.provider()),
+                    options.progress,
+                )
+            })
+            .await?;
 
         bytes_downloaded = bytes_downloaded.saturating_add(ep_stats.bytes_downloaded);
         if let Some(net) = ep_stats.net_bytes_downloaded {
@@ -200,7 +434,12 @@ pub async fn restore_snapshot_with<S: Storage>(
         }
     }
 
-    ensure_empty_dir(&config.target_path)?;
+    let index_elapsed = restore_started.elapsed();
+    if options.delete_extraneous {
+        fs::create_dir_all(&config.target_path)?;
+    } else if !options.as_file {
+        ensure_empty_dir(&config.target_path)?;
+    }
 
     let pool = open_existing_index_db(&config.filemap_db_path).await?;
     if use_dedupe_db {
@@ -211,37 +450,267 @@ pub async fn restore_snapshot_with<S: Storage>(
         attach_db(&pool, "ep", endpoint_db_path).await?;
     }
     ensure_snapshot_present(&pool, &config.snapshot_id).await?;
+    let data_key = with_recorded_chunk_hash(&pool, &config.snapshot_id, data_key).await?;
+    let renames = if options.as_file {
+        ensure_single_file_snapshot(&pool, &config.snapshot_id).await?;
+        RestorePaths::default()
+    } else {
+        let case = plan_case_renames(
+            &pool,
+            &config.snapshot_id,
+            &config.target_path,
+            options.rename_collisions,
+        )
+        .await?;
+        plan_long_paths(
+            &pool,
+            &config.snapshot_id,
+            &config.target_path,
+            case,
+            options.path_limits,
+            options.shorten_long_paths,
+        )
+        .await?
+    };
+    let snapshot_entries = if options.delete_extraneous {
+        let entries = snapshot_entry_kinds(&pool, &config.snapshot_id).await?;
+        Some(
+            entries
+                .into_iter()
+                .map(|(path, kind)| (renames.apply(&path), kind))
+                .chain(renames.shortened_entry_kinds())
+                .collect(),
+        )
+    } else {
+        None
+    };
+    // Before anything is written: `create_dir_all` and `File::create` would follow a symlink
+    // left in the target, and fail on an entry of the wrong kind.
+    let mut extraneous = RestoreResult::default();
+    if let Some(entries) = snapshot_entries.as_ref() {
+        delete_extraneous(&config.target_path, entries, false, &mut extraneous)?;
+    }
+    renames.write_index(&config.target_path)?;
 
-    restore_dirs(&pool, &config.snapshot_id, &config.target_path).await?;
-    let result = restore_files(
+    let dirs = restore_dirs(&pool, &config.snapshot_id, &config.target_path, &renames).await?;
+    let mut result = restore_files(
         storage,
         &pool,
         &config.snapshot_id,
         &config.target_path,
+        &renames,
+        options.as_file,
         use_endpoint_db,
         use_dedupe_db,
-        &config.master_key,
+        &data_key,
         options.cancel,
         &mut bytes_downloaded,
         &mut net_bytes_downloaded,
         Arc::clone(&have_net_bytes_downloaded),
         options.progress,
+        options.keep_going,
+        RestoreWriteOptions {
+            concurrency: match options.write_concurrency {
+                0 => DEFAULT_RESTORE_WRITE_CONCURRENCY,
+                n => n,
+            },
+            fsync: options.fsync,
+            file_sync,
+        },
+        &retry,
     )
     .await?;
+    result.files_deleted = extraneous.files_deleted;
+    result.deleted_paths = extraneous.deleted_paths;
+    if options.preserve_times {
+        apply_file_times(
+            &pool,
+            &config.snapshot_id,
+            &config.target_path,
+            &renames,
+            options.as_file,
+        )
+        .await?;
+    }
+    if !options.ownership.is_default() {
+        result.ownership_warnings = apply_ownership(
+            &pool,
+            &config.snapshot_id,
+            &config.target_path,
+            &renames,
+            options.as_file,
+            &options.ownership,
+        )
+        .await?;
+    }
+    apply_dir_metadata(&dirs)?;
+    result.dirs_restored = dirs.len() as u64;
+    result.renamed_paths = renames.case.renamed();
+    result.shortened_paths = renames.shortened();
 
     debug!(
         event = "phase.finish",
         phase = "restore",
         duration_ms = restore_started.elapsed().as_millis() as u64,
         files_restored = result.files_restored,
+        files_failed = result.files_failed,
         chunks_downloaded = result.chunks_downloaded,
+        objects_downloaded = result.objects_downloaded,
         bytes_written = result.bytes_written,
+        files_deleted = result.files_deleted,
         "phase.finish"
     );
+    result.phase_timings.record(Phase::Index, index_elapsed);
+    result
+        .phase_timings
+        .record(Phase::Restore, restore_started.elapsed() - index_elapsed);
+    result.retry = retry.stats();
 
     Ok(result)
 }
 
+/// What restoring a snapshot (or the part of it under a path) writes and downloads, from the
+/// index alone.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreEstimate {
+    pub files: u64,
+    pub dirs: u64,
+    /// Plaintext bytes written to the target.
+    pub bytes_to_write: u64,
+    /// Distinct chunks; a chunk shared by several files is downloaded once.
+    pub chunks: u64,
+    /// Distinct storage objects holding those chunks (direct chunk objects and packs).
+    pub objects: u64,
+    /// Encrypted bytes of those objects. Packs count in full (a restore fetches whole packs),
+    /// sized by their furthest known slice, so their small encrypted header is left out.
+    pub bytes_to_download: u64,
+    /// Chunks without an object in the index; a restore fails on them (or skips their files with
+    /// `keep_going`).
+    pub chunks_missing: u64,
+}
+
+/// Estimates a restore of `snapshot_id` from its file map (`filemap_db_path`) and an index DB
+/// holding the endpoint's `chunk_objects` for `provider` (the local endpoint index DB). No chunk
+/// data is read. With `path`, only entries at or under that snapshot-relative path count.
+pub async fn estimate_restore(
+    filemap_db_path: &Path,
+    chunk_objects_db_path: &Path,
+    snapshot_id: &str,
+    provider: &str,
+    path: Option<&str>,
+) -> Result<RestoreEstimate> {
+    let prefix = match path.map(|p| p.trim_matches('/')) {
+        Some(p) if p.split('/').any(|c| c == ".." || c == ".") => {
+            return Err(Error::InvalidConfig {
+                message: format!("path must be snapshot-relative without `.`/`..`: {p}"),
+            });
+        }
+        Some("") | None => None,
+        Some(p) => Some(p.to_string()),
+    };
+    // `path = ? OR path starts with ? || '/'`, without LIKE so `%`/`_` in names stay literal.
+    let path_filter =
+        "(?1 IS NULL OR f.path = ?1 OR substr(f.path, 1, length(?1) + 1) = ?1 || '/')";
+
+    let pool = open_existing_index_db(filemap_db_path).await?;
+    attach_db(&pool, "ep", chunk_objects_db_path).await?;
+    ensure_snapshot_present(&pool, snapshot_id).await?;
+
+    let mut estimate = RestoreEstimate::default();
+    let kinds = sqlx::query(&format!(
+        "SELECT f.kind, COUNT(1) AS n, COALESCE(SUM(f.size), 0) AS bytes FROM files f WHERE f.snapshot_id = ?2 AND {path_filter} GROUP BY f.kind"
+    ))
+    .bind(prefix.as_deref())
+    .bind(snapshot_id)
+    .fetch_all(&pool)
+    .await?;
+    for row in kinds {
+        let n = row.get::<i64, _>("n").max(0) as u64;
+        match row.get::<String, _>("kind").as_str() {
+            "file" => {
+                estimate.files = n;
+                estimate.bytes_to_write = row.get::<i64, _>("bytes").max(0) as u64;
+            }
+            "dir" => estimate.dirs = n,
+            _ => {}
+        }
+    }
+
+    let chunks = sqlx::query(&format!(
+        r#"
+        SELECT DISTINCT fc.chunk_hash, fc.len,
+               COALESCE(ep_co.object_id, co.object_id) AS object_id
+        FROM file_chunks fc
+        JOIN files f ON f.file_id = fc.file_id
+        LEFT JOIN ep.chunk_objects ep_co
+          ON ep_co.chunk_hash = fc.chunk_hash
+         AND ep_co.provider = ?3
+        LEFT JOIN chunk_objects co
+          ON co.chunk_hash = fc.chunk_hash
+         AND co.provider = ?3
+        WHERE f.snapshot_id = ?2 AND f.kind = 'file' AND {path_filter}
+        "#
+    ))
+    .bind(prefix.as_deref())
+    .bind(snapshot_id)
+    .bind(provider)
+    .fetch_all(&pool)
+    .await?;
+
+    let mut seen_chunks = HashSet::new();
+    let mut direct_objects = HashSet::new();
+    let mut packs = HashMap::<String, u64>::new();
+    for row in chunks {
+        let chunk_hash: String = row.get("chunk_hash");
+        if !seen_chunks.insert(chunk_hash) {
+            continue;
+        }
+        let Some(object_id) = row.get::<Option<String>, _>("object_id") else {
+            estimate.chunks_missing += 1;
+            continue;
+        };
+        match parse_chunk_object_ref(&object_id)? {
+            ChunkObjectRef::Direct { object_id } => {
+                if direct_objects.insert(object_id) {
+                    let len = row.get::<i64, _>("len").max(0) as u64;
+                    estimate.bytes_to_download += len + FRAMING_OVERHEAD_BYTES as u64;
+                }
+            }
+            ChunkObjectRef::PackSlice { pack_object_id, .. } => {
+                packs.entry(pack_object_id).or_default();
+            }
+        }
+    }
+    estimate.chunks = seen_chunks.len() as u64;
+    estimate.objects = (direct_objects.len() + packs.len()) as u64;
+
+    if !packs.is_empty() {
+        // A pack's size is the end of its last slice, including slices this restore doesn't use.
+        let mut rows = sqlx::query(
+            "SELECT object_id FROM ep.chunk_objects WHERE provider = ?1 UNION SELECT object_id FROM chunk_objects WHERE provider = ?1",
+        )
+        .bind(provider)
+        .fetch(&pool);
+        while let Some(row) = rows.next().await {
+            let object_id: String = row?.get("object_id");
+            if let Ok(ChunkObjectRef::PackSlice {
+                pack_object_id,
+                offset,
+                len,
+            }) = parse_chunk_object_ref(&object_id)
+                && let Some(end) = packs.get_mut(&pack_object_id)
+            {
+                *end = (*end).max(offset.saturating_add(len));
+            }
+        }
+        estimate.bytes_to_download += packs.values().sum::<u64>();
+    }
+
+    pool.close().await;
+    Ok(estimate)
+}
+
 pub async fn verify_snapshot<S: Storage>(
     storage: &S,
     config: VerifyConfig,
@@ -253,6 +722,9 @@ pub async fn verify_snapshot<S: Storage>(
 pub struct VerifyOptions<'a> {
     pub cancel: Option<&'a CancellationToken>,
     pub progress: Option<&'a dyn ProgressSink>,
+    /// Chunk objects downloaded and checked at the same time; 0 and 1 both check one at a time.
+    /// Each in-flight pack is held in memory while its slices are checked.
+    pub concurrency: usize,
 }
 
 pub async fn verify_snapshot_with<S: Storage>(
@@ -263,11 +735,16 @@ pub async fn verify_snapshot_with<S: Storage>(
     let verify_started = Instant::now();
     debug!(event = "phase.start", phase = "verify", "phase.start");
 
+    let data_key = config
+        .data_key
+        .clone()
+        .unwrap_or_else(|| DataKey::master(&config.master_key));
     let stats = download_and_write_index_db_atomic(
         storage,
         &config.snapshot_id,
         &config.filemap_manifest_object_id,
-        &config.master_key,
+        config.filemap_manifest_sha256.as_deref(),
+        data_key.key(),
         &config.filemap_db_path,
         options.cancel,
         Some(storage.provider()),
@@ -356,6 +833,7 @@ pub async fn verify_snapshot_with<S: Storage>(
             storage,
             &endpoint_index_id,
             endpoint_manifest_object_id,
+            None,
             &config.master_key,
             endpoint_db_path,
             options.cancel,
@@ -371,6 +849,7 @@ pub async fn verify_snapshot_with<S: Storage>(
         }
     }
 
+    let index_elapsed = verify_started.elapsed();
     let pool = open_existing_index_db(&config.filemap_db_path).await?;
     if use_dedupe_db {
         let dedupe_db_path = config.dedupe_db_path.as_deref().expect("checked above");
@@ -380,19 +859,22 @@ pub async fn verify_snapshot_with<S: Storage>(
         attach_db(&pool, "ep", endpoint_db_path).await?;
     }
     ensure_snapshot_present(&pool, &config.snapshot_id).await?;
+    let data_key = with_recorded_chunk_hash(&pool, &config.snapshot_id, data_key).await?;
 
-    let result = verify_chunks(
+    let mut result = verify_chunks(
         storage,
         &pool,
         &config.snapshot_id,
         use_endpoint_db,
         use_dedupe_db,
-        &config.master_key,
+        config.sample,
+        &data_key,
         options.cancel,
         &mut bytes_downloaded,
         &mut net_bytes_downloaded,
         Arc::clone(&have_net_bytes_downloaded),
         options.progress,
+        options.concurrency,
     )
     .await?;
 
@@ -402,12 +884,31 @@ pub async fn verify_snapshot_with<S: Storage>(
         duration_ms = verify_started.elapsed().as_millis() as u64,
         chunks_checked = result.chunks_checked,
         bytes_checked = result.bytes_checked,
+        chunks_skipped = result.chunks_skipped,
         "phase.finish"
     );
+    result.phase_timings.record(Phase::Index, index_elapsed);
+    result
+        .phase_timings
+        .record(Phase::Chunks, verify_started.elapsed() - index_elapsed);
 
     Ok(result)
 }
 
+/// `data_key` checking chunk ids with the algorithm the snapshot's file map records, whichever
+/// `chunking.hash` is configured now.
+async fn with_recorded_chunk_hash(
+    pool: &SqlitePool,
+    snapshot_id: &str,
+    data_key: DataKey,
+) -> Result<DataKey> {
+    let mut conn = pool.acquire().await?;
+    let alg = snapshot_chunk_hash_alg(&mut conn, snapshot_id)
+        .await?
+        .unwrap_or_default();
+    Ok(data_key.with_chunk_hash(alg))
+}
+
 async fn ensure_snapshot_present(pool: &SqlitePool, snapshot_id: &str) -> Result<()> {
     let row = sqlx::query("SELECT 1 as present FROM snapshots WHERE snapshot_id = ? LIMIT 1")
         .bind(snapshot_id)
@@ -421,6 +922,20 @@ async fn ensure_snapshot_present(pool: &SqlitePool, snapshot_id: &str) -> Result
     Ok(())
 }
 
+/// `as_file` needs a snapshot of exactly one file and nothing else (a single-file source).
+async fn ensure_single_file_snapshot(pool: &SqlitePool, snapshot_id: &str) -> Result<()> {
+    let rows = sqlx::query("SELECT kind FROM files WHERE snapshot_id = ? LIMIT 2")
+        .bind(snapshot_id)
+        .fetch_all(pool)
+        .await?;
+    if rows.len() != 1 || rows[0].get::<String, _>("kind") != "file" {
+        return Err(Error::InvalidConfig {
+            message: format!("as_file needs a single-file snapshot: {snapshot_id}"),
+        });
+    }
+    Ok(())
+}
+
 async fn attach_db(pool: &SqlitePool, alias: &str, path: &Path) -> Result<()> {
     // ATTACH needs a literal string; escape single quotes defensively.
     let path_sql = path.to_string_lossy().replace('\'', "''");
@@ -429,414 +944,1566 @@ async fn attach_db(pool: &SqlitePool, alias: &str, path: &Path) -> Result<()> {
     Ok(())
 }
 
-async fn restore_dirs(pool: &SqlitePool, snapshot_id: &str, target: &Path) -> Result<()> {
-    let rows =
-        sqlx::query("SELECT path FROM files WHERE snapshot_id = ? AND kind = 'dir' ORDER BY path")
+/// Fails with [`Error::CaseCollision`] when snapshot paths differ only in letter case and
+/// `target` is case-insensitive (probed), unless `rename` asks for [`CaseRenames`] instead.
+async fn plan_case_renames(
+    pool: &SqlitePool,
+    snapshot_id: &str,
+    target: &Path,
+    rename: bool,
+) -> Result<CaseRenames> {
+    let paths: Vec<String> = sqlx::query_scalar("SELECT path FROM files WHERE snapshot_id = ?")
+        .bind(snapshot_id)
+        .fetch_all(pool)
+        .await?;
+    let collisions = case_collisions(paths.iter().map(String::as_str));
+    if collisions.is_empty() || !dir_is_case_insensitive(target)? {
+        return Ok(CaseRenames::default());
+    }
+    if !rename {
+        return Err(Error::CaseCollision {
+            paths: collisions.into_iter().flatten().collect(),
+        });
+    }
+
+    let renames = CaseRenames::plan(&paths);
+    for (path, restored) in renames.renamed() {
+        warn!(
+            event = "restore.case_renamed",
+            path = %path,
+            restored_as = %restored,
+            "restore.case_renamed"
+        );
+    }
+    Ok(renames)
+}
+
+/// Fails with [`Error::PathTooLong`] when restored paths exceed the target's `NAME_MAX` or
+/// `PATH_MAX` (`limits`, or probed), unless `shorten` moves them under `.televybackup-long-paths`.
+/// Runs before anything is written or downloaded, so a restore never stops halfway on
+/// `ENAMETOOLONG`.
+async fn plan_long_paths(
+    pool: &SqlitePool,
+    snapshot_id: &str,
+    target: &Path,
+    case: CaseRenames,
+    limits: Option<PathLimits>,
+    shorten: bool,
+) -> Result<RestorePaths> {
+    let limits = match limits {
+        Some(limits) => limits,
+        None => PathLimits::probe(target)?,
+    };
+    let paths: Vec<String> =
+        sqlx::query_scalar("SELECT path FROM files WHERE snapshot_id = ? ORDER BY path")
             .bind(snapshot_id)
             .fetch_all(pool)
             .await?;
+    let mut renames = RestorePaths::new(case);
+    let too_long = renames.plan_long_paths(&paths, target, limits, shorten);
+    if !too_long.is_empty() {
+        return Err(Error::PathTooLong {
+            paths: too_long,
+            name_max: limits.name_max as u64,
+            path_max: limits.path_max as u64,
+        });
+    }
+    for (path, restored) in renames.shortened() {
+        warn!(
+            event = "restore.path_shortened",
+            path = %path,
+            restored_as = %restored,
+            "restore.path_shortened"
+        );
+    }
+    Ok(renames)
+}
+
+/// A snapshot directory and the metadata it gets once everything inside it is written.
+struct RestoredDir {
+    path: PathBuf,
+    mtime_ms: i64,
+    mode: i64,
+}
+
+/// Creates the snapshot's directories, empty ones included, in path order (parents first).
+async fn restore_dirs(
+    pool: &SqlitePool,
+    snapshot_id: &str,
+    target: &Path,
+    renames: &RestorePaths,
+) -> Result<Vec<RestoredDir>> {
+    let rows = sqlx::query(
+        "SELECT path, mtime_ms, mode FROM files WHERE snapshot_id = ? AND kind = 'dir' ORDER BY path",
+    )
+    .bind(snapshot_id)
+    .fetch_all(pool)
+    .await?;
 
+    let mut dirs = Vec::with_capacity(rows.len());
     for row in rows {
         let rel: String = row.get("path");
-        let path = target.join(rel);
-        fs::create_dir_all(path)?;
+        let path = target.join(renames.apply(&rel));
+        fs::create_dir_all(&path)?;
+        dirs.push(RestoredDir {
+            path,
+            mtime_ms: row.get("mtime_ms"),
+            mode: row.get("mode"),
+        });
     }
 
+    Ok(dirs)
+}
+
+/// Sets directory mtimes and modes deepest-first, so writing into a child (or restricting its
+/// mode) doesn't disturb a parent that is already done. Rows from snapshots taken before
+/// directory metadata was recorded carry zeros and are left alone.
+fn apply_dir_metadata(dirs: &[RestoredDir]) -> Result<()> {
+    for dir in dirs.iter().rev() {
+        if dir.mtime_ms > 0 {
+            let mtime = std::time::UNIX_EPOCH + Duration::from_millis(dir.mtime_ms as u64);
+            fs::File::open(&dir.path)?.set_modified(mtime)?;
+        }
+        #[cfg(unix)]
+        if dir.mode != 0 {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = (dir.mode as u32) & 0o7777;
+            fs::set_permissions(&dir.path, fs::Permissions::from_mode(mode))?;
+        }
+    }
     Ok(())
 }
 
-#[allow(clippy::too_many_arguments)]
-async fn restore_files<S: Storage>(
-    storage: &S,
+/// Sets restored files' mtimes and creation times (see `RestoreOptions::preserve_times`). Files
+/// left out by `keep_going` are skipped, as are zero mtimes and file maps written before
+/// `files.btime_ms` existed.
+async fn apply_file_times(
     pool: &SqlitePool,
     snapshot_id: &str,
     target: &Path,
-    use_endpoint_db: bool,
-    use_dedupe_db: bool,
-    master_key: &[u8; 32],
-    cancel: Option<&CancellationToken>,
-    bytes_downloaded: &mut u64,
-    net_bytes_downloaded: &mut u64,
-    have_net_bytes_downloaded: Arc<AtomicBool>,
-    progress: Option<&dyn ProgressSink>,
-) -> Result<RestoreResult> {
-    let mut result = RestoreResult::default();
-    let mut pack_cache: Option<(String, Vec<u8>)> = None;
+    renames: &RestorePaths,
+    as_file: bool,
+) -> Result<()> {
+    let sql = if files_have_btime_column(pool, "main").await? {
+        "SELECT path, mtime_ms, btime_ms FROM files WHERE snapshot_id = ? AND kind = 'file'"
+    } else {
+        "SELECT path, mtime_ms, NULL AS btime_ms FROM files WHERE snapshot_id = ? AND kind = 'file'"
+    };
+    let rows = sqlx::query(sql).bind(snapshot_id).fetch_all(pool).await?;
+
+    let mut btime_unsupported = 0u64;
+    for row in rows {
+        let rel: String = row.get("path");
+        let path = if as_file {
+            target.to_path_buf()
+        } else {
+            target.join(renames.apply(&rel))
+        };
+        let file = match fs::File::options().write(true).open(&path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut times = fs::FileTimes::new();
+        let mtime_ms: i64 = row.get("mtime_ms");
+        if mtime_ms > 0 {
+            times =
+                times.set_modified(std::time::UNIX_EPOCH + Duration::from_millis(mtime_ms as u64));
+        }
+        if let Some(btime_ms) = row.get::<Option<i64>, _>("btime_ms").filter(|ms| *ms > 0) {
+            let btime = std::time::UNIX_EPOCH + Duration::from_millis(btime_ms as u64);
+            match with_created(times, btime) {
+                Some(t) => times = t,
+                None => btime_unsupported += 1,
+            }
+        }
+        file.set_times(times)?;
+    }
 
+    if btime_unsupported > 0 {
+        warn!(
+            event = "restore.btime_unsupported",
+            files = btime_unsupported,
+            "restore.btime_unsupported"
+        );
+    }
+    Ok(())
+}
+
+/// Changes restored entries' owners as `ownership` asks (see [`owner_change`]), before directory
+/// modes are applied since `chown` may clear setuid/setgid bits. Entries already owned as asked,
+/// missing ones (left out by `keep_going`) and rows without a recorded owner are skipped. Returns
+/// how many `chown`s failed; each is logged and the restore goes on.
+async fn apply_ownership(
+    pool: &SqlitePool,
+    snapshot_id: &str,
+    target: &Path,
+    renames: &RestorePaths,
+    as_file: bool,
+    ownership: &OwnershipOptions,
+) -> Result<u64> {
+    if !files_have_owner_columns(pool, "main").await? {
+        return Ok(0);
+    }
     let rows = sqlx::query(
-        "SELECT file_id, path, size, kind FROM files WHERE snapshot_id = ? ORDER BY path",
+        "SELECT path, kind, uid, gid FROM files WHERE snapshot_id = ? AND kind IN ('file', 'dir') AND uid IS NOT NULL AND gid IS NOT NULL",
     )
     .bind(snapshot_id)
     .fetch_all(pool)
     .await?;
 
+    let mut failed = 0u64;
+    let mut first_error = None;
     for row in rows {
-        if let Some(cancel) = cancel
-            && cancel.is_cancelled()
-        {
-            return Err(Error::Cancelled);
-        }
-
         let kind: String = row.get("kind");
-        if kind != "file" {
+        if as_file && kind != "file" {
             continue;
         }
-        let file_id: String = row.get("file_id");
+        let (uid, gid) = (row.get::<i64, _>("uid"), row.get::<i64, _>("gid"));
+        let (Ok(uid), Ok(gid)) = (u32::try_from(uid), u32::try_from(gid)) else {
+            continue;
+        };
+        let Some((new_uid, new_gid)) = owner_change(Some((uid, gid)), ownership) else {
+            continue;
+        };
         let rel: String = row.get("path");
-        let expected_size: i64 = row.get("size");
-
-        let out_path = target.join(&rel);
-        if let Some(parent) = out_path.parent() {
-            fs::create_dir_all(parent)?;
-        }
-
-        let mut out = fs::File::create(&out_path)?;
-
-        let chunks = if use_dedupe_db {
-            sqlx::query(
-                r#"
-                SELECT fc.seq, fc.chunk_hash, fc.offset, fc.len,
-                       COALESCE(dd_co.object_id, co.object_id) as object_id
-                FROM file_chunks fc
-                LEFT JOIN dd.chunk_objects dd_co
-                  ON dd_co.chunk_hash = fc.chunk_hash
-                 AND dd_co.provider = ?
-                LEFT JOIN chunk_objects co
-                  ON co.chunk_hash = fc.chunk_hash
-                 AND co.provider = ?
-                WHERE fc.file_id = ?
-                ORDER BY fc.seq
-                "#,
-            )
-            .bind(storage.provider())
-            .bind(storage.provider())
-            .bind(&file_id)
-            .fetch_all(pool)
-            .await?
-        } else if use_endpoint_db {
-            sqlx::query(
-                r#"
-                SELECT fc.seq, fc.chunk_hash, fc.offset, fc.len,
-                       COALESCE(ep_co.object_id, co.object_id) as object_id
-                FROM file_chunks fc
-                LEFT JOIN ep.chunk_objects ep_co
-                  ON ep_co.chunk_hash = fc.chunk_hash
-                 AND ep_co.provider = ?
-                LEFT JOIN chunk_objects co
-                  ON co.chunk_hash = fc.chunk_hash
-                 AND co.provider = ?
-                WHERE fc.file_id = ?
-                ORDER BY fc.seq
-                "#,
-            )
-            .bind(storage.provider())
-            .bind(storage.provider())
-            .bind(&file_id)
-            .fetch_all(pool)
-            .await?
+        let path = if as_file {
+            target.to_path_buf()
         } else {
-            sqlx::query(
-                r#"
-                SELECT fc.seq, fc.chunk_hash, fc.offset, fc.len, co.object_id as object_id
-                FROM file_chunks fc
-                LEFT JOIN chunk_objects co
-                  ON co.chunk_hash = fc.chunk_hash
-                 AND co.provider = ?
-                WHERE fc.file_id = ?
-                ORDER BY fc.seq
-                "#,
-            )
-            .bind(storage.provider())
-            .bind(&file_id)
-            .fetch_all(pool)
-            .await?
+            target.join(renames.apply(&rel))
         };
-
-        for chunk_row in chunks {
-            if let Some(cancel) = cancel
-                && cancel.is_cancelled()
-            {
-                return Err(Error::Cancelled);
+        if let Err(e) = chown_if_needed(&path, new_uid, new_gid) {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                continue;
             }
+            debug!(
+                event = "restore.ownership_failed",
+                path = %path.display(),
+                error = %e,
+                "restore.ownership_failed"
+            );
+            failed += 1;
+            first_error.get_or_insert_with(|| e.to_string());
+        }
+    }
 
-            let chunk_hash: String = chunk_row.get("chunk_hash");
-            let offset: i64 = chunk_row.get("offset");
-            let len: i64 = chunk_row.get("len");
-            let encoded_object_id: Option<String> = chunk_row.get("object_id");
-            let encoded_object_id = encoded_object_id.ok_or_else(|| Error::MissingChunkObject {
-                chunk_hash: chunk_hash.clone(),
-            })?;
-            let object_ref = parse_chunk_object_ref(&encoded_object_id)?;
-
-            let plain = match object_ref {
-                ChunkObjectRef::Direct { object_id } => {
-                    let base_total = *bytes_downloaded;
-                    let base_net_total = *net_bytes_downloaded;
-                    // Use a sentinel so "0 bytes downloaded" (e.g. fully satisfied from cache) is
-                    // distinguishable from "no progress callbacks were ever emitted".
-                    let latest = Arc::new(AtomicU64::new(u64::MAX));
-                    let latest_for_cb = latest.clone();
-                    let latest_net = Arc::new(AtomicU64::new(u64::MAX));
-                    let latest_net_for_cb = latest_net.clone();
-                    let have_net_for_cb = Arc::clone(&have_net_bytes_downloaded);
-                    let framed = storage
-                        .download_document_with_progress(
-                            &object_id,
-                            Some(Box::new(move |p| {
-                                let n = p.bytes;
-                                latest_for_cb.store(n, Ordering::Relaxed);
-                                if let Some(net) = p.net_bytes {
-                                    latest_net_for_cb.store(net, Ordering::Relaxed);
-                                    have_net_for_cb.store(true, Ordering::Relaxed);
-                                }
-                                if let Some(sink) = progress {
-                                    sink.on_progress(TaskProgress {
-                                        phase: "download".to_string(),
-                                        bytes_downloaded: Some(base_total.saturating_add(n)),
-                                        net_bytes_downloaded: p
-                                            .net_bytes
-                                            .map(|net| base_net_total.saturating_add(net)),
-                                        ..TaskProgress::default()
-                                    });
-                                }
-                            })),
-                        )
-                        .await
-                        .map_err(|e| {
-                            error!(
-                                    event = "io.telegram.download_failed",
-                                    snapshot_id,
-                                    object_id = %object_id,
-                                    chunk_hash,
-                                error = %e,
-                                "io.telegram.download_failed"
-                            );
-                            match e {
-                                Error::Telegram { message } => {
-                                    // Treat "not found" style errors as permanent missing data, but keep
-                                    // timeouts/transient failures as retryable telegram errors.
-                                    if message.contains("message not found")
-                                        || message.contains("document mismatch")
-                                    {
-                                        Error::MissingChunkObject {
-                                            chunk_hash: chunk_hash.clone(),
-                                        }
-                                    } else {
-                                        Error::Telegram { message }
-                                    }
-                                }
-                                _other => Error::MissingChunkObject {
-                                    chunk_hash: chunk_hash.clone(),
-                                },
-                            }
-                        })?;
-                    let streamed = latest.load(Ordering::Relaxed);
-                    let actual = if streamed != u64::MAX {
-                        streamed
-                    } else {
-                        framed.len() as u64
-                    };
-                    *bytes_downloaded = base_total.saturating_add(actual);
-                    let streamed_net = latest_net.load(Ordering::Relaxed);
-                    if streamed_net != u64::MAX {
-                        *net_bytes_downloaded = base_net_total.saturating_add(streamed_net);
-                    }
-                    decrypt_framed(master_key, chunk_hash.as_bytes(), &framed).map_err(|e| {
-                        Error::Crypto {
-                            message: format!(
-                                "chunk decrypt failed: snapshot_id={snapshot_id} chunk_hash={chunk_hash} object_id={object_id}; {e}"
-                            ),
-                        }
-                    })?
-                }
-                ChunkObjectRef::PackSlice {
-                    pack_object_id,
-                    offset: pack_off,
-                    len: pack_len,
-                } => {
-                    let pack_bytes = match &pack_cache {
-                        Some((cached_id, cached_bytes)) if cached_id == &pack_object_id => {
-                            cached_bytes.as_slice()
-                        }
-                        _ => {
-                            let base_total = *bytes_downloaded;
-                            let base_net_total = *net_bytes_downloaded;
-                            // Use a sentinel so "0 bytes downloaded" (e.g. fully satisfied from
-                            // cache) is distinguishable from "no progress callbacks were ever
-                            // emitted".
-                            let latest = Arc::new(AtomicU64::new(u64::MAX));
-                            let latest_for_cb = latest.clone();
-                            let latest_net = Arc::new(AtomicU64::new(u64::MAX));
-                            let latest_net_for_cb = latest_net.clone();
-                            let have_net_for_cb = Arc::clone(&have_net_bytes_downloaded);
-                            let bytes = storage
-                                .download_document_with_progress(
-                                    &pack_object_id,
-                                    Some(Box::new(move |p| {
-                                        let n = p.bytes;
-                                        latest_for_cb.store(n, Ordering::Relaxed);
-                                        if let Some(net) = p.net_bytes {
-                                            latest_net_for_cb.store(net, Ordering::Relaxed);
-                                            have_net_for_cb.store(true, Ordering::Relaxed);
-                                        }
-                                        if let Some(sink) = progress {
-                                            sink.on_progress(TaskProgress {
-                                                phase: "download".to_string(),
-                                                bytes_downloaded: Some(
-                                                    base_total.saturating_add(n),
-                                                ),
-                                                net_bytes_downloaded: p
-                                                    .net_bytes
-                                                    .map(|net| base_net_total.saturating_add(net)),
-                                                ..TaskProgress::default()
-                                            });
-                                        }
-                                    })),
-                                )
-                                .await
-                                .map_err(|e| {
-                                    error!(
-                                        event = "io.telegram.download_failed",
-                                        snapshot_id,
-                                        object_id = %pack_object_id,
-                                        chunk_hash,
-                                        error = %e,
-                                        "io.telegram.download_failed"
-                                    );
-                                    match e {
-                                        Error::Telegram { message } => {
-                                            if message.contains("message not found")
-                                                || message.contains("document mismatch")
-                                            {
-                                                Error::MissingChunkObject {
-                                                    chunk_hash: chunk_hash.clone(),
-                                                }
-                                            } else {
-                                                Error::Telegram { message }
-                                            }
-                                        }
-                                        _other => Error::MissingChunkObject {
-                                            chunk_hash: chunk_hash.clone(),
-                                        },
-                                    }
-                                })?;
-                            let streamed = latest.load(Ordering::Relaxed);
-                            let actual = if streamed != u64::MAX {
-                                streamed
-                            } else {
-                                bytes.len() as u64
-                            };
-                            *bytes_downloaded = base_total.saturating_add(actual);
-                            let streamed_net = latest_net.load(Ordering::Relaxed);
-                            if streamed_net != u64::MAX {
-                                *net_bytes_downloaded = base_net_total.saturating_add(streamed_net);
-                            }
-                            pack_cache = Some((pack_object_id.clone(), bytes));
-                            pack_cache.as_ref().expect("just set").1.as_slice()
-                        }
-                    };
+    if failed > 0 {
+        warn!(
+            event = "restore.ownership_failed",
+            entries = failed,
+            first_error = first_error.as_deref().unwrap_or_default(),
+            "restore.ownership_failed"
+        );
+    }
+    Ok(failed)
+}
 
-                    if pack_len > usize::MAX as u64 {
-                        return Err(Error::Integrity {
-                            message: "pack slice too large".to_string(),
-                        });
-                    }
-                    let framed = extract_pack_blob(pack_bytes, pack_off, pack_len)?;
-                    decrypt_framed(master_key, chunk_hash.as_bytes(), framed).map_err(|e| {
-                        Error::Crypto {
+#[cfg(unix)]
+fn chown_if_needed(path: &Path, uid: Option<u32>, gid: Option<u32>) -> std::io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    let meta = fs::symlink_metadata(path)?;
+    let uid = uid.filter(|u| *u != meta.uid());
+    let gid = gid.filter(|g| *g != meta.gid());
+    if uid.is_none() && gid.is_none() {
+        return Ok(());
+    }
+    std::os::unix::fs::lchown(path, uid, gid)
+}
+
+#[cfg(not(unix))]
+fn chown_if_needed(_path: &Path, _uid: Option<u32>, _gid: Option<u32>) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "file owners are not supported on this platform",
+    ))
+}
+
+/// `times` with the creation time set, or `None` where the platform can't set one. On macOS std
+/// applies it with `setattrlist(ATTR_CMN_CRTIME)`.
+#[cfg(target_os = "macos")]
+fn with_created(times: fs::FileTimes, btime: std::time::SystemTime) -> Option<fs::FileTimes> {
+    use std::os::macos::fs::FileTimesExt;
+    Some(times.set_created(btime))
+}
+
+#[cfg(not(target_os = "macos"))]
+fn with_created(_times: fs::FileTimes, _btime: std::time::SystemTime) -> Option<fs::FileTimes> {
+    None
+}
+
+/// Stamps the run totals onto every progress event, so they stay constant once known.
+struct TotalsProgress<'a> {
+    inner: &'a dyn ProgressSink,
+    files_total: Option<u64>,
+    chunks_total: u64,
+    bytes_total: u64,
+}
+
+impl ProgressSink for TotalsProgress<'_> {
+    fn on_progress(&self, mut progress: TaskProgress) {
+        progress.files_total = self.files_total;
+        progress.chunks_total = Some(self.chunks_total);
+        progress.bytes_total = Some(self.bytes_total);
+        self.inner.on_progress(progress);
+    }
+}
+
+/// Fetches (a storage object, or a batch of small ones) downloaded ahead of the one being
+/// written. Each buffered fetch is held in memory in full, so this bounds a restore's download
+/// memory to a few objects (packs included).
+const RESTORE_READ_AHEAD_OBJECTS: usize = 4;
+
+/// A file of the restore and how many of its chunk writes are still outstanding.
+struct PlannedFile {
+    rel: String,
+    out_path: PathBuf,
+    expected_size: i64,
+    /// Chunks the file is assembled from.
+    chunks: usize,
+}
+
+/// A chunk to slice out of a downloaded object, and every `(file, offset)` it is written to.
+struct PlannedChunk {
+    chunk_hash: String,
+    len: i64,
+    encoded_object_id: String,
+    /// `(offset, len)` within a pack; `None` for a direct chunk object.
+    pack_slice: Option<(u64, u64)>,
+    dests: Vec<(usize, u64)>,
+}
+
+/// One download of a restore: a direct chunk object or a pack, with the chunks taken from it.
+struct PlannedObject {
+    object_id: String,
+    chunks: Vec<PlannedChunk>,
+}
+
+impl PlannedObject {
+    /// A direct chunk object small enough to go in a batched download.
+    fn is_small(&self) -> bool {
+        matches!(
+            self.chunks.as_slice(),
+            [chunk] if chunk.pack_slice.is_none()
+                && chunk.len.max(0) as u64 <= BATCH_SMALL_OBJECT_MAX_BYTES
+        )
+    }
+}
+
+/// Everything a restore downloads and writes, planned from the index before any download.
+struct RestorePlan {
+    files: Vec<PlannedFile>,
+    /// In order of first use when walking the files by path.
+    objects: Vec<PlannedObject>,
+    /// Chunks without an object in the index, with the file each is part of.
+    missing: Vec<(String, usize)>,
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn plan_restore(
+    pool: &SqlitePool,
+    provider: &str,
+    snapshot_id: &str,
+    target: &Path,
+    renames: &RestorePaths,
+    as_file: bool,
+    use_endpoint_db: bool,
+    use_dedupe_db: bool,
+) -> Result<RestorePlan> {
+    let rows = sqlx::query(
+        "SELECT file_id, path, size FROM files WHERE snapshot_id = ? AND kind = 'file' ORDER BY path",
+    )
+    .bind(snapshot_id)
+    .fetch_all(pool)
+    .await?;
+    let mut file_index = HashMap::with_capacity(rows.len());
+    let mut files = Vec::with_capacity(rows.len());
+    for row in rows {
+        let rel: String = row.get("path");
+        file_index.insert(row.get::<String, _>("file_id"), files.len());
+        files.push(PlannedFile {
+            out_path: if as_file {
+                target.to_path_buf()
+            } else {
+                target.join(renames.apply(&rel))
+            },
+            rel,
+            expected_size: row.get("size"),
+            chunks: 0,
+        });
+    }
+
+    let query = if use_dedupe_db {
+        sqlx::query(
+            r#"
+            SELECT fc.file_id, fc.chunk_hash, fc.offset, fc.len,
+                   COALESCE(dd_co.object_id, co.object_id) as object_id
+            FROM files f
+            JOIN file_chunks fc ON fc.file_id = f.file_id
+            LEFT JOIN dd.chunk_objects dd_co
+              ON dd_co.chunk_hash = fc.chunk_hash
+             AND dd_co.provider = ?
+            LEFT JOIN chunk_objects co
+              ON co.chunk_hash = fc.chunk_hash
+             AND co.provider = ?
+            WHERE f.snapshot_id = ? AND f.kind = 'file'
+            ORDER BY f.path, fc.seq
+            "#,
+        )
+        .bind(provider)
+        .bind(provider)
+    } else if use_endpoint_db {
+        sqlx::query(
+            r#"
+            SELECT fc.file_id, fc.chunk_hash, fc.offset, fc.len,
+                   COALESCE(ep_co.object_id, co.object_id) as object_id
+            FROM files f
+            JOIN file_chunks fc ON fc.file_id = f.file_id
+            LEFT JOIN ep.chunk_objects ep_co
+              ON ep_co.chunk_hash = fc.chunk_hash
+             AND ep_co.provider = ?
+            LEFT JOIN chunk_objects co
+              ON co.chunk_hash = fc.chunk_hash
+             AND co.provider = ?
+            WHERE f.snapshot_id = ? AND f.kind = 'file'
+            ORDER BY f.path, fc.seq
+            "#,
+        )
+        .bind(provider)
+        .bind(provider)
+    } else {
+        sqlx::query(
+            r#"
+            SELECT fc.file_id, fc.chunk_hash, fc.offset, fc.len, co.object_id as object_id
+            FROM files f
+            JOIN file_chunks fc ON fc.file_id = f.file_id
+            LEFT JOIN chunk_objects co
+              ON co.chunk_hash = fc.chunk_hash
+             AND co.provider = ?
+            WHERE f.snapshot_id = ? AND f.kind = 'file'
+            ORDER BY f.path, fc.seq
+            "#,
+        )
+        .bind(provider)
+    };
+
+    let mut objects: Vec<PlannedObject> = Vec::new();
+    let mut object_index: HashMap<String, usize> = HashMap::new();
+    // A chunk shared by several files (or used twice in one) is decrypted once and written to
+    // each place.
+    let mut chunk_index: HashMap<String, (usize, usize)> = HashMap::new();
+    let mut missing = Vec::new();
+    let mut chunk_rows = query.bind(snapshot_id).fetch(pool);
+    while let Some(row) = chunk_rows.try_next().await? {
+        let file_id: String = row.get("file_id");
+        let Some(&file) = file_index.get(&file_id) else {
+            continue;
+        };
+        files[file].chunks += 1;
+        let chunk_hash: String = row.get("chunk_hash");
+        let offset: i64 = row.get("offset");
+        let dest = (file, offset.max(0) as u64);
+        if let Some(&(o, c)) = chunk_index.get(&chunk_hash) {
+            objects[o].chunks[c].dests.push(dest);
+            continue;
+        }
+        let encoded_object_id: Option<String> = row.get("object_id");
+        let Some(encoded_object_id) = encoded_object_id else {
+            missing.push((chunk_hash, file));
+            continue;
+        };
+        let (object_id, pack_slice) = match parse_chunk_object_ref(&encoded_object_id)? {
+            ChunkObjectRef::Direct { object_id } => (object_id, None),
+            ChunkObjectRef::PackSlice {
+                pack_object_id,
+                offset,
+                len,
+            } => (pack_object_id, Some((offset, len))),
+        };
+        let o = *object_index.entry(object_id.clone()).or_insert_with(|| {
+            objects.push(PlannedObject {
+                object_id,
+                chunks: Vec::new(),
+            });
+            objects.len() - 1
+        });
+        chunk_index.insert(chunk_hash.clone(), (o, objects[o].chunks.len()));
+        objects[o].chunks.push(PlannedChunk {
+            chunk_hash,
+            len: row.get("len"),
+            encoded_object_id,
+            pack_slice,
+            dests: vec![dest],
+        });
+    }
+
+    Ok(RestorePlan {
+        files,
+        objects,
+        missing,
+    })
+}
+
+/// Restores the snapshot's files object by object: every needed storage object is downloaded
+/// once, with up to [`RESTORE_READ_AHEAD_OBJECTS`] fetched ahead, and its verified chunks are
+/// handed to the writers. `write_concurrency` writers each own a share of the files, so a file's
+/// chunks are written in the order they were planned while other files are written in parallel.
+#[allow(clippy::too_many_arguments)]
+async fn restore_files<S: Storage>(
+    storage: &S,
+    pool: &SqlitePool,
+    snapshot_id: &str,
+    target: &Path,
+    renames: &RestorePaths,
+    as_file: bool,
+    use_endpoint_db: bool,
+    use_dedupe_db: bool,
+    data_key: &DataKey,
+    cancel: Option<&CancellationToken>,
+    bytes_downloaded: &mut u64,
+    net_bytes_downloaded: &mut u64,
+    have_net_bytes_downloaded: Arc<AtomicBool>,
+    progress: Option<&dyn ProgressSink>,
+    keep_going: bool,
+    write: RestoreWriteOptions,
+    retry: &RetryBudget,
+) -> Result<RestoreResult> {
+    let started = Instant::now();
+    let RestorePlan {
+        files,
+        objects,
+        missing,
+    } = plan_restore(
+        pool,
+        storage.provider(),
+        snapshot_id,
+        target,
+        renames,
+        as_file,
+        use_endpoint_db,
+        use_dedupe_db,
+    )
+    .await?;
+
+    let bytes_total = files.iter().fold(0u64, |bytes, f| {
+        bytes.saturating_add(f.expected_size.max(0) as u64)
+    });
+    let chunks_total = files.iter().map(|f| f.chunks as u64).sum();
+    let totals = progress.map(|sink| TotalsProgress {
+        inner: sink,
+        files_total: Some(files.len() as u64),
+        chunks_total,
+        bytes_total,
+    });
+    let progress = totals.as_ref().map(|v| v as &dyn ProgressSink);
+    let state = DownloadProgressState::new(
+        *bytes_downloaded,
+        *net_bytes_downloaded,
+        have_net_bytes_downloaded,
+        progress,
+        Phase::Download,
+    );
+    if let Some(sink) = progress {
+        sink.on_progress(TaskProgress {
+            phase: Phase::Download,
+            files_done: Some(0),
+            chunks_done: Some(0),
+            bytes_read: Some(0),
+            bytes_downloaded: Some(*bytes_downloaded),
+            ..TaskProgress::default()
+        });
+    }
+
+    let writer_count = write.concurrency.max(1);
+    let files_restored = AtomicU64::new(0);
+    let (queues, writers): (Vec<_>, Vec<_>) = (0..writer_count)
+        .map(|_| {
+            let (tx, rx) = tokio::sync::mpsc::channel(RESTORE_WRITE_QUEUE_OPS);
+            let writer = run_restore_writer(
+                &files,
+                rx,
+                write.fsync,
+                Arc::clone(&write.file_sync),
+                keep_going,
+                &state,
+                &files_restored,
+            );
+            (tx, writer)
+        })
+        .unzip();
+
+    // The download side owns the queues, so the writers see them close however it ends.
+    let mut queues = WriteQueues {
+        queues,
+        wait: Duration::ZERO,
+    };
+    let download = {
+        let (files, objects, state) = (&files, &objects, &state);
+        async move {
+            let mut result = RestoreResult::default();
+            let mut files_left = files.iter().map(|f| f.chunks).collect::<Vec<_>>();
+            let mut files_failed = vec![false; files.len()];
+
+            for (file, left) in files_left.iter().enumerate() {
+                if *left == 0
+                    && !queues
+                        .send(WriteOp::Finish {
+                            file,
+                            failed: false,
+                        })
+                        .await
+                {
+                    return Ok((result, queues.wait, Instant::now()));
+                }
+            }
+
+            for (chunk_hash, file) in missing {
+                let e = Error::MissingChunkObject {
+                    chunk_hash: chunk_hash.clone(),
+                };
+                if !keep_going {
+                    return Err(e);
+                }
+                fail_chunk(
+                    &files[file],
+                    &mut files_left[file],
+                    &mut files_failed[file],
+                    snapshot_id,
+                    &chunk_hash,
+                    None,
+                    &e,
+                    &mut result,
+                );
+                if files_left[file] == 0
+                    && !queues.send(WriteOp::Finish { file, failed: true }).await
+                {
+                    return Ok((result, queues.wait, Instant::now()));
+                }
+            }
+
+            let fetches = plan_fetches(objects.iter().map(PlannedObject::is_small));
+            let mut downloads = futures::stream::iter(fetches)
+                .map(|range| {
+                    let objects = &objects[range];
+                    async move {
+                        if cancel.is_some_and(CancellationToken::is_cancelled) {
+                            return vec![(&objects[0], Err(Error::Cancelled))];
+                        }
+                        let batched = if let [_] = objects {
+                            vec![None]
+                        } else {
+                            let items = objects
+                                .iter()
+                                .map(|o| (o.object_id.as_str(), o.chunks[0].chunk_hash.as_str()))
+                                .collect::<Vec<_>>();
+                            download_small_objects(storage, snapshot_id, &items, state).await
+                        };
+                        let mut out = Vec::with_capacity(objects.len());
+                        for (object, batched) in objects.iter().zip(batched) {
+                            let res = match batched {
+                                Some(res) => res,
+                                None => {
+                                    download_restore_object(
+                                        storage,
+                                        snapshot_id,
+                                        &object.object_id,
+                                        &object.chunks[0].chunk_hash,
+                                        state,
+                                        retry,
+                                    )
+                                    .await
+                                }
+                            };
+                            out.push((object, res));
+                        }
+                        out
+                    }
+                })
+                .buffered(RESTORE_READ_AHEAD_OBJECTS)
+                .flat_map(futures::stream::iter);
+            loop {
+                let next = match cancel {
+                    Some(cancel) => tokio::select! {
+                        _ = cancel.cancelled() => return Err(Error::Cancelled),
+                        next = downloads.next() => next,
+                    },
+                    None => downloads.next().await,
+                };
+                let Some((object, downloaded)) = next else {
+                    break;
+                };
+                let mut object_bytes = match downloaded {
+                    Ok(bytes) => {
+                        result.objects_downloaded += 1;
+                        Some(bytes)
+                    }
+                    Err(Error::MissingChunkObject { .. }) => None,
+                    Err(e) => return Err(e),
+                };
+
+                for chunk in &object.chunks {
+                    let chunk_hash = chunk.chunk_hash.as_str();
+                    let encoded_object_id = chunk.encoded_object_id.as_str();
+                    let rel = files[chunk.dests[0].0].rel.as_str();
+                    // Chunks are verified (decrypt + hash + length) before they touch a target file;
+                    // a corrupt download is re-fetched a few times before giving up.
+                    let mut attempt = 0u32;
+                    let fetched = loop {
+                        let Some(bytes) = object_bytes.as_deref() else {
+                            break Err(Error::MissingChunkObject {
+                                chunk_hash: chunk_hash.to_string(),
+                            });
+                        };
+                        match open_restored_chunk(
+                            snapshot_id,
+                            data_key,
+                            &object.object_id,
+                            bytes,
+                            chunk,
+                        ) {
+                            Err(e @ (Error::Integrity { .. } | Error::Crypto { .. }))
+                                if attempt < RESTORE_CHUNK_VERIFY_RETRIES =>
+                            {
+                                attempt += 1;
+                                warn!(
+                                    event = "restore.chunk_retry",
+                                    snapshot_id,
+                                    chunk_hash,
+                                    object_id = encoded_object_id,
+                                    path = %rel,
+                                    attempt,
+                                    error = %e,
+                                    "restore.chunk_retry"
+                                );
+                                // The downloaded object may be the corrupt part; fetch it again.
+                                object_bytes = None;
+                                tokio::time::sleep(
+                                    RESTORE_CHUNK_RETRY_BASE_DELAY * (1 << (attempt - 1)),
+                                )
+                                .await;
+                                match download_restore_object(
+                                    storage,
+                                    snapshot_id,
+                                    &object.object_id,
+                                    chunk_hash,
+                                    state,
+                                    retry,
+                                )
+                                .await
+                                {
+                                    Ok(bytes) => {
+                                        result.objects_downloaded += 1;
+                                        object_bytes = Some(bytes);
+                                    }
+                                    Err(Error::MissingChunkObject { .. }) => {}
+                                    Err(e) => return Err(e),
+                                }
+                            }
+                            Err(e @ (Error::Integrity { .. } | Error::Crypto { .. })) => {
+                                break Err(Error::Integrity {
+                                    message: format!(
+                                        "chunk verification failed after {} attempts: snapshot_id={snapshot_id} chunk_hash={chunk_hash} object_id={encoded_object_id} path={rel}; {e}",
+                                        attempt + 1
+                                    ),
+                                });
+                            }
+                            other => break other,
+                        }
+                    };
+
+                    match fetched {
+                        Ok(plain) => {
+                            let data = Arc::new(plain);
+                            for &(file, offset) in &chunk.dests {
+                                // Keep checking the remaining chunks of a failed file so the summary
+                                // lists every bad one, but stop writing it.
+                                if !files_failed[file]
+                                    && !queues
+                                        .send(WriteOp::Chunk {
+                                            file,
+                                            offset,
+                                            data: Arc::clone(&data),
+                                        })
+                                        .await
+                                {
+                                    return Ok((result, queues.wait, Instant::now()));
+                                }
+                                files_left[file] -= 1;
+                                if files_left[file] == 0
+                                    && !queues
+                                        .send(WriteOp::Finish {
+                                            file,
+                                            failed: files_failed[file],
+                                        })
+                                        .await
+                                {
+                                    return Ok((result, queues.wait, Instant::now()));
+                                }
+                            }
+                        }
+                        Err(
+                            e @ (Error::Integrity { .. }
+                            | Error::Crypto { .. }
+                            | Error::MissingChunkObject { .. }),
+                        ) if keep_going => {
+                            for &(file, _) in &chunk.dests {
+                                fail_chunk(
+                                    &files[file],
+                                    &mut files_left[file],
+                                    &mut files_failed[file],
+                                    snapshot_id,
+                                    chunk_hash,
+                                    Some(encoded_object_id),
+                                    &e,
+                                    &mut result,
+                                );
+                                if files_left[file] == 0
+                                    && !queues.send(WriteOp::Finish { file, failed: true }).await
+                                {
+                                    return Ok((result, queues.wait, Instant::now()));
+                                }
+                            }
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+            Ok((result, queues.wait, Instant::now()))
+        }
+    };
+
+    let ((mut result, write_wait, downloads_done), tallies) =
+        tokio::try_join!(download, futures::future::try_join_all(writers))?;
+    let writes_done = Instant::now();
+    for tally in tallies {
+        result.files_restored += tally.files_restored;
+        result.files_failed += tally.files_failed;
+        result.failures.extend(tally.failures);
+    }
+
+    let counters = state
+        .counters
+        .into_inner()
+        .unwrap_or_else(|e| e.into_inner());
+    *bytes_downloaded = counters.bytes_downloaded;
+    *net_bytes_downloaded = counters.net_bytes_downloaded;
+    result.chunks_downloaded = counters.chunks_done;
+    result.bytes_written = counters.bytes_done;
+    let write_elapsed = write_wait + writes_done.saturating_duration_since(downloads_done);
+    result.write_ms = u64::try_from(write_elapsed.as_millis()).unwrap_or(u64::MAX);
+    result.download_ms = u64::try_from(
+        downloads_done
+            .saturating_duration_since(started)
+            .saturating_sub(write_wait)
+            .as_millis(),
+    )
+    .unwrap_or(u64::MAX);
+    Ok(result)
+}
+
+/// Files written at the same time when [`RestoreOptions::write_concurrency`] is 0.
+pub const DEFAULT_RESTORE_WRITE_CONCURRENCY: usize = 4;
+/// Writes queued per writer before the download side waits for it.
+const RESTORE_WRITE_QUEUE_OPS: usize = 64;
+/// Files a writer keeps open between their chunks; more are closed and reopened as needed.
+const RESTORE_WRITER_OPEN_FILES: usize = 64;
+
+/// When a restore flushes the files it wrote to disk (`restore.fsync`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RestoreFsync {
+    /// Each file once it is complete; slowest with many small files.
+    PerFile,
+    /// Every file once the last one is written: a finished restore is on disk, an interrupted
+    /// one may not be.
+    #[default]
+    AtEnd,
+    /// Never; the OS writes the data back on its own schedule.
+    Never,
+}
+
+impl RestoreFsync {
+    /// The `restore.fsync` values, in variant order.
+    pub const NAMES: &'static [&'static str] = &["per_file", "at_end", "none"];
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "per_file" => Some(Self::PerFile),
+            "at_end" => Some(Self::AtEnd),
+            "none" => Some(Self::Never),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct RestoreWriteOptions {
+    concurrency: usize,
+    fsync: RestoreFsync,
+    file_sync: Arc<dyn RestoreFileSync>,
+}
+
+/// Work for the writer owning `file`; the ops of one file arrive in the order they were sent.
+enum WriteOp {
+    /// Write `data` at `offset` of the file.
+    Chunk {
+        file: usize,
+        offset: u64,
+        data: Arc<Vec<u8>>,
+    },
+    /// Every chunk of the file was handled. A `failed` file is removed; any other is checked
+    /// against its recorded size.
+    Finish { file: usize, failed: bool },
+}
+
+/// The writers' queues, and how long the download side waited on full ones.
+struct WriteQueues {
+    queues: Vec<tokio::sync::mpsc::Sender<WriteOp>>,
+    wait: Duration,
+}
+
+impl WriteQueues {
+    /// Queues `op` for the writer owning its file; `false` once that writer stopped on an error,
+    /// which the writers' side reports.
+    async fn send(&mut self, op: WriteOp) -> bool {
+        let queue = &self.queues[op.file() % self.queues.len()];
+        let waited = Instant::now();
+        let sent = queue.send(op).await.is_ok();
+        self.wait += waited.elapsed();
+        sent
+    }
+}
+
+impl WriteOp {
+    fn file(&self) -> usize {
+        match self {
+            Self::Chunk { file, .. } | Self::Finish { file, .. } => *file,
+        }
+    }
+}
+
+/// What one writer finished.
+#[derive(Default)]
+struct WriterTally {
+    files_restored: u64,
+    files_failed: u64,
+    failures: Vec<RestoreFailure>,
+}
+
+/// Writes the files sent to it one op at a time, off the async runtime. The restored files are
+/// flushed as `fsync` says; `at_end` flushes them once the queue is closed.
+async fn run_restore_writer(
+    files: &[PlannedFile],
+    mut ops: tokio::sync::mpsc::Receiver<WriteOp>,
+    fsync: RestoreFsync,
+    file_sync: Arc<dyn RestoreFileSync>,
+    keep_going: bool,
+    state: &DownloadProgressState<'_>,
+    files_restored: &AtomicU64,
+) -> Result<WriterTally> {
+    let mut tally = WriterTally::default();
+    let mut open: HashMap<usize, fs::File> = HashMap::new();
+    let mut created: HashSet<usize> = HashSet::new();
+    let mut to_sync: Vec<PathBuf> = Vec::new();
+    while let Some(op) = ops.recv().await {
+        match op {
+            WriteOp::Chunk { file, offset, data } => {
+                if !open.contains_key(&file)
+                    && open.len() >= RESTORE_WRITER_OPEN_FILES
+                    && let Some(&evict) = open.keys().next()
+                {
+                    open.remove(&evict);
+                }
+                let handle = open.remove(&file);
+                let out_path = files[file].out_path.clone();
+                let create = created.insert(file);
+                let len = data.len() as u64;
+                let handle = spawn_write(move || {
+                    let mut out = match handle {
+                        Some(out) => out,
+                        None if create => create_restored_file(&out_path)?,
+                        None => fs::OpenOptions::new().write(true).open(&out_path)?,
+                    };
+                    out.seek(SeekFrom::Start(offset))?;
+                    out.write_all(&data)?;
+                    Ok(out)
+                })
+                .await?;
+                open.insert(file, handle);
+                state.add_done(len, Some(files_restored.load(Ordering::Relaxed)));
+            }
+            WriteOp::Finish { file, failed } => {
+                let handle = open.remove(&file);
+                let planned = &files[file];
+                let out_path = planned.out_path.clone();
+                let create = created.insert(file);
+                let file_sync = Arc::clone(&file_sync);
+                let written_size = spawn_write(move || {
+                    if failed {
+                        drop(handle);
+                        return match fs::remove_file(&out_path) {
+                            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+                            _ => Ok(None),
+                        };
+                    }
+                    let out = match handle {
+                        Some(out) => out,
+                        None if create => create_restored_file(&out_path)?,
+                        None => fs::OpenOptions::new().write(true).open(&out_path)?,
+                    };
+                    let size = out.metadata()?.len();
+                    if fsync == RestoreFsync::PerFile {
+                        file_sync.sync(&out)?;
+                    }
+                    Ok(Some(size))
+                })
+                .await?;
+                match written_size {
+                    None => tally.files_failed += 1,
+                    Some(size) if size as i64 != planned.expected_size => {
+                        let e = Error::Integrity {
                             message: format!(
-                                "chunk decrypt failed (pack slice): snapshot_id={snapshot_id} chunk_hash={chunk_hash} pack_object_id={pack_object_id} offset={pack_off} len={pack_len}; {e}"
+                                "file size mismatch: path={} expected={} got={size}",
+                                planned.rel, planned.expected_size
                             ),
+                        };
+                        if !keep_going {
+                            return Err(e);
+                        }
+                        tally.failures.push(RestoreFailure {
+                            path: planned.rel.clone(),
+                            chunk_hash: None,
+                            object_id: None,
+                            error: e.to_string(),
+                        });
+                        fs::remove_file(&planned.out_path)?;
+                        tally.files_failed += 1;
+                    }
+                    Some(_) => {
+                        tally.files_restored += 1;
+                        let done = files_restored.fetch_add(1, Ordering::Relaxed) + 1;
+                        state.report(&state.counters(), Phase::Restore, Some(done));
+                        if fsync == RestoreFsync::AtEnd {
+                            to_sync.push(planned.out_path.clone());
                         }
-                    })?
+                    }
                 }
-            };
+            }
+        }
+    }
+    drop(open);
+    if !to_sync.is_empty() {
+        spawn_write(move || {
+            for path in to_sync {
+                file_sync.sync(&fs::File::open(&path)?)?;
+            }
+            Ok(())
+        })
+        .await?;
+    }
+    Ok(tally)
+}
 
-            let got_hash = blake3::hash(&plain).to_hex().to_string();
-            if got_hash != chunk_hash {
+/// Runs a blocking file operation of a restore writer on the blocking thread pool.
+async fn spawn_write<T: Send + 'static>(
+    f: impl FnOnce() -> std::io::Result<T> + Send + 'static,
+) -> Result<T> {
+    Ok(tokio::task::spawn_blocking(f)
+        .await
+        .map_err(std::io::Error::other)??)
+}
+
+/// Creates (or truncates) a restored file. Its directory is created too; writers creating the
+/// same directory at once is fine. A symlink at `path` is not followed: the open fails instead.
+fn create_restored_file(path: &Path) -> std::io::Result<fs::File> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.custom_flags(libc::O_NOFOLLOW);
+    }
+    options.open(path)
+}
+
+/// Records a chunk `file` could not get and marks the file failed.
+#[allow(clippy::too_many_arguments)]
+fn fail_chunk(
+    file: &PlannedFile,
+    left: &mut usize,
+    failed: &mut bool,
+    snapshot_id: &str,
+    chunk_hash: &str,
+    object_id: Option<&str>,
+    e: &Error,
+    result: &mut RestoreResult,
+) {
+    warn!(
+        event = "restore.chunk_failed",
+        snapshot_id,
+        chunk_hash,
+        path = %file.rel,
+        error = %e,
+        "restore.chunk_failed"
+    );
+    result.failures.push(RestoreFailure {
+        path: file.rel.clone(),
+        chunk_hash: Some(chunk_hash.to_string()),
+        object_id: object_id.map(str::to_string),
+        error: e.to_string(),
+    });
+    *failed = true;
+    *left -= 1;
+}
+
+/// Downloads a restore object, retrying transient storage failures within the run's budget.
+async fn download_restore_object<S: Storage>(
+    storage: &S,
+    snapshot_id: &str,
+    object_id: &str,
+    chunk_hash: &str,
+    state: &DownloadProgressState<'_>,
+    retry: &RetryBudget,
+) -> Result<Vec<u8>> {
+    let mut attempt = 0u32;
+    loop {
+        match download_object(storage, snapshot_id, object_id, chunk_hash, state).await {
+            Err(e @ Error::Telegram { .. }) => {
+                attempt += 1;
+                let Some(backoff) = retry.next_backoff(attempt, &e) else {
+                    return Err(e);
+                };
+                warn!(
+                    event = "io.telegram.download_retry",
+                    snapshot_id,
+                    chunk_hash,
+                    object_id,
+                    attempt,
+                    max_attempts = retry.max_attempts(),
+                    backoff_ms = backoff.as_millis() as u64,
+                    error = %e,
+                    "io.telegram.download_retry"
+                );
+                retry.wait(backoff).await?;
+            }
+            other => return other,
+        }
+    }
+}
+
+/// Takes one chunk out of its downloaded object and returns the plaintext once decryption, hash,
+/// and length all check out.
+fn open_restored_chunk(
+    snapshot_id: &str,
+    data_key: &DataKey,
+    object_id: &str,
+    object_bytes: &[u8],
+    chunk: &PlannedChunk,
+) -> Result<Vec<u8>> {
+    let chunk_hash = chunk.chunk_hash.as_str();
+    let plain = match chunk.pack_slice {
+        None => decrypt_framed(data_key.key(), chunk_hash.as_bytes(), object_bytes).map_err(|e| {
+            Error::Crypto {
+                message: format!(
+                    "chunk decrypt failed: snapshot_id={snapshot_id} chunk_hash={chunk_hash} object_id={object_id}; {e}"
+                ),
+            }
+        })?,
+        Some((pack_off, pack_len)) => {
+            if pack_len > usize::MAX as u64 {
                 return Err(Error::Integrity {
-                    message: format!("chunk hash mismatch: {chunk_hash}"),
+                    message: "pack slice too large".to_string(),
                 });
             }
-            if plain.len() as i64 != len {
-                return Err(Error::Integrity {
+            let framed = extract_pack_blob(object_bytes, pack_off, pack_len)?;
+            decrypt_framed(data_key.key(), chunk_hash.as_bytes(), framed).map_err(|e| {
+                Error::Crypto {
                     message: format!(
-                        "chunk length mismatch: chunk_hash={chunk_hash} expected_len={len} got_len={}",
-                        plain.len()
+                        "chunk decrypt failed (pack slice): snapshot_id={snapshot_id} chunk_hash={chunk_hash} pack_object_id={object_id} offset={pack_off} len={pack_len}; {e}"
                     ),
-                });
-            }
+                }
+            })?
+        }
+    };
 
-            out.seek(SeekFrom::Start(offset as u64))?;
-            out.write_all(&plain)?;
-
-            result.chunks_downloaded += 1;
-            result.bytes_written += plain.len() as u64;
-
-            if let Some(sink) = progress {
-                sink.on_progress(TaskProgress {
-                    phase: "download".to_string(),
-                    source_files_total: None,
-                    source_bytes_total: None,
-                    source_bytes_need_upload_total: None,
-                    files_total: None,
-                    files_done: Some(result.files_restored),
-                    chunks_total: None,
-                    chunks_done: Some(result.chunks_downloaded),
-                    bytes_read: Some(result.bytes_written),
-                    upload_bytes_total: None,
-                    bytes_uploaded_confirmed: None,
-                    bytes_uploaded_source: None,
-                    bytes_uploaded: None,
-                    net_bytes_uploaded: None,
-                    bytes_downloaded: Some(*bytes_downloaded),
-                    net_bytes_downloaded: have_net_bytes_downloaded
-                        .load(Ordering::Relaxed)
-                        .then_some(*net_bytes_downloaded),
-                    bytes_deduped: None,
-                });
-            }
+    let got_hash = data_key.chunk_hash(&plain);
+    if got_hash != chunk_hash {
+        return Err(Error::Integrity {
+            message: format!("chunk hash mismatch: {chunk_hash}"),
+        });
+    }
+    if plain.len() as i64 != chunk.len {
+        return Err(Error::Integrity {
+            message: format!(
+                "chunk length mismatch: chunk_hash={chunk_hash} expected_len={} got_len={}",
+                chunk.len,
+                plain.len()
+            ),
+        });
+    }
+
+    Ok(plain)
+}
+
+/// One download of a verify run: a direct chunk object, a batch of small ones (as
+/// `(chunk_hash, object_id)`), or a pack and the slices checked from it.
+enum VerifyUnit {
+    Direct {
+        chunk_hash: String,
+        object_id: String,
+        len: u64,
+    },
+    Batch {
+        objects: Vec<(String, String)>,
+    },
+    Pack {
+        pack_object_id: String,
+        slices: Vec<(String, u64, u64)>,
+    },
+}
+
+struct DownloadCounters {
+    bytes_downloaded: u64,
+    net_bytes_downloaded: u64,
+    /// Chunks checked (verify) or written to their files (restore).
+    chunks_done: u64,
+    bytes_done: u64,
+}
+
+/// Totals shared by concurrent restore/verify downloads. Updates and progress events happen
+/// under one lock, so reported counters never go backwards even when downloads finish out of
+/// order.
+struct DownloadProgressState<'a> {
+    counters: std::sync::Mutex<DownloadCounters>,
+    have_net_bytes_downloaded: Arc<AtomicBool>,
+    progress: Option<&'a dyn ProgressSink>,
+    /// Phase of the chunk and byte progress events.
+    phase: Phase,
+}
+
+impl DownloadProgressState<'_> {
+    fn new(
+        bytes_downloaded: u64,
+        net_bytes_downloaded: u64,
+        have_net_bytes_downloaded: Arc<AtomicBool>,
+        progress: Option<&dyn ProgressSink>,
+        phase: Phase,
+    ) -> DownloadProgressState<'_> {
+        DownloadProgressState {
+            counters: std::sync::Mutex::new(DownloadCounters {
+                bytes_downloaded,
+                net_bytes_downloaded,
+                chunks_done: 0,
+                bytes_done: 0,
+            }),
+            have_net_bytes_downloaded,
+            progress,
+            phase,
         }
+    }
 
-        out.flush()?;
+    fn counters(&self) -> std::sync::MutexGuard<'_, DownloadCounters> {
+        self.counters.lock().unwrap_or_else(|e| e.into_inner())
+    }
 
-        let written_size = fs::metadata(&out_path)?.len() as i64;
-        if written_size != expected_size {
-            return Err(Error::Integrity {
-                message: format!(
-                    "file size mismatch: path={rel} expected={expected_size} got={written_size}"
-                ),
+    fn add_downloaded(&self, bytes: u64, net_bytes: Option<u64>) {
+        let mut c = self.counters();
+        c.bytes_downloaded = c.bytes_downloaded.saturating_add(bytes);
+        if let Some(net) = net_bytes {
+            self.have_net_bytes_downloaded
+                .store(true, Ordering::Relaxed);
+            c.net_bytes_downloaded = c.net_bytes_downloaded.saturating_add(net);
+        }
+        if let Some(sink) = self.progress {
+            sink.on_progress(TaskProgress {
+                phase: self.phase.clone(),
+                bytes_downloaded: Some(c.bytes_downloaded),
+                net_bytes_downloaded: net_bytes.map(|_| c.net_bytes_downloaded),
+                ..TaskProgress::default()
             });
         }
+    }
 
-        result.files_restored += 1;
+    fn add_done(&self, plain_len: u64, files_done: Option<u64>) {
+        let mut c = self.counters();
+        c.chunks_done += 1;
+        c.bytes_done += plain_len;
+        self.report(&c, self.phase.clone(), files_done);
+    }
 
-        if let Some(sink) = progress {
+    fn report(&self, c: &DownloadCounters, phase: Phase, files_done: Option<u64>) {
+        if let Some(sink) = self.progress {
             sink.on_progress(TaskProgress {
-                phase: "restore".to_string(),
+                phase,
                 source_files_total: None,
                 source_bytes_total: None,
                 source_bytes_need_upload_total: None,
                 files_total: None,
-                files_done: Some(result.files_restored),
+                files_done,
                 chunks_total: None,
-                chunks_done: Some(result.chunks_downloaded),
-                bytes_read: Some(result.bytes_written),
+                chunks_done: Some(c.chunks_done),
+                bytes_read: Some(c.bytes_done),
                 upload_bytes_total: None,
                 bytes_uploaded_confirmed: None,
                 bytes_uploaded_source: None,
                 bytes_uploaded: None,
                 net_bytes_uploaded: None,
-                bytes_downloaded: Some(*bytes_downloaded),
-                net_bytes_downloaded: have_net_bytes_downloaded
+                bytes_downloaded: Some(c.bytes_downloaded),
+                net_bytes_downloaded: self
+                    .have_net_bytes_downloaded
                     .load(Ordering::Relaxed)
-                    .then_some(*net_bytes_downloaded),
+                    .then_some(c.net_bytes_downloaded),
+                bytes_written: None,
                 bytes_deduped: None,
+                bytes_total_estimated: None,
+                bytes_total: None,
             });
         }
     }
+}
 
-    Ok(result)
+async fn verify_unit<S: Storage>(
+    storage: &S,
+    snapshot_id: &str,
+    data_key: &DataKey,
+    unit: VerifyUnit,
+    state: &DownloadProgressState<'_>,
+) -> Result<()> {
+    match unit {
+        VerifyUnit::Direct {
+            chunk_hash,
+            object_id,
+            ..
+        } => {
+            let framed =
+                download_object(storage, snapshot_id, &object_id, &chunk_hash, state).await?;
+            check_direct_chunk(
+                snapshot_id,
+                data_key,
+                &chunk_hash,
+                &object_id,
+                &framed,
+                state,
+            )?;
+        }
+        VerifyUnit::Batch { objects } => {
+            let items = objects
+                .iter()
+                .map(|(chunk_hash, object_id)| (object_id.as_str(), chunk_hash.as_str()))
+                .collect::<Vec<_>>();
+            let batched = download_small_objects(storage, snapshot_id, &items, state).await;
+            for ((chunk_hash, object_id), batched) in objects.iter().zip(batched) {
+                let framed = match batched {
+                    Some(res) => res?,
+                    None => {
+                        download_object(storage, snapshot_id, object_id, chunk_hash, state).await?
+                    }
+                };
+                check_direct_chunk(snapshot_id, data_key, chunk_hash, object_id, &framed, state)?;
+            }
+        }
+        VerifyUnit::Pack {
+            pack_object_id,
+            slices,
+        } => {
+            let first_chunk_hash = &slices[0].0;
+            let pack_bytes = download_object(
+                storage,
+                snapshot_id,
+                &pack_object_id,
+                first_chunk_hash,
+                state,
+            )
+            .await?;
+            for (chunk_hash, pack_off, pack_len) in slices {
+                if pack_len > usize::MAX as u64 {
+                    return Err(Error::Integrity {
+                        message: "pack slice too large".to_string(),
+                    });
+                }
+                let framed = extract_pack_blob(&pack_bytes, pack_off, pack_len)?;
+                let plain = decrypt_framed(data_key.key(), chunk_hash.as_bytes(), framed).map_err(|e| {
+                    Error::Crypto {
+                        message: format!(
+                            "chunk decrypt failed (pack slice): snapshot_id={snapshot_id} chunk_hash={chunk_hash} pack_object_id={pack_object_id} offset={pack_off} len={pack_len}; {e}"
+                        ),
+                    }
+                })?;
+                check_verified_chunk(data_key, &chunk_hash, &plain)?;
+                state.add_done(plain.len() as u64, None);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_direct_chunk(
+    snapshot_id: &str,
+    data_key: &DataKey,
+    chunk_hash: &str,
+    object_id: &str,
+    framed: &[u8],
+    state: &DownloadProgressState<'_>,
+) -> Result<()> {
+    let plain = decrypt_framed(data_key.key(), chunk_hash.as_bytes(), framed).map_err(|e| {
+        Error::Crypto {
+            message: format!(
+                "chunk decrypt failed: snapshot_id={snapshot_id} chunk_hash={chunk_hash} object_id={object_id}; {e}"
+            ),
+        }
+    })?;
+    check_verified_chunk(data_key, chunk_hash, &plain)?;
+    state.add_done(plain.len() as u64, None);
+    Ok(())
+}
+
+fn check_verified_chunk(data_key: &DataKey, chunk_hash: &str, plain: &[u8]) -> Result<()> {
+    let got_hash = data_key.chunk_hash(plain);
+    if got_hash != chunk_hash {
+        return Err(Error::Integrity {
+            message: format!("chunk hash mismatch: {chunk_hash}"),
+        });
+    }
+    Ok(())
+}
+
+/// Downloads one storage object, feeding byte progress into the shared totals.
+async fn download_object<S: Storage>(
+    storage: &S,
+    snapshot_id: &str,
+    object_id: &str,
+    chunk_hash: &str,
+    state: &DownloadProgressState<'_>,
+) -> Result<Vec<u8>> {
+    // Progress callbacks report cumulative bytes for this download; only the deltas are added
+    // to the shared totals. `reported` stays `None` when no callback fired (e.g. a cache hit).
+    let reported = std::sync::Mutex::new((None::<u64>, 0u64));
+    let reported_for_cb = &reported;
+    let bytes = storage
+        .download_document_with_progress(
+            object_id,
+            Some(Box::new(move |p| {
+                let mut r = reported_for_cb.lock().unwrap_or_else(|e| e.into_inner());
+                let prev = r.0.unwrap_or(0);
+                let net_delta = p.net_bytes.map(|net| {
+                    let d = net.saturating_sub(r.1);
+                    r.1 = r.1.max(net);
+                    d
+                });
+                r.0 = Some(prev.max(p.bytes));
+                state.add_downloaded(p.bytes.saturating_sub(prev), net_delta);
+            })),
+        )
+        .await
+        .map_err(|e| download_error(snapshot_id, object_id, chunk_hash, e))?;
+    let streamed = reported.lock().unwrap_or_else(|e| e.into_inner()).0;
+    if streamed.is_none() {
+        state.add_downloaded(bytes.len() as u64, None);
+    }
+    Ok(bytes)
+}
+
+/// Logs a failed download and tells a gone object (`MissingChunkObject`) apart from a transient
+/// provider error.
+fn download_error(snapshot_id: &str, object_id: &str, chunk_hash: &str, e: Error) -> Error {
+    error!(
+        event = "io.telegram.download_failed",
+        snapshot_id,
+        object_id = %object_id,
+        chunk_hash,
+        error = %e,
+        "io.telegram.download_failed"
+    );
+    match e {
+        Error::Telegram { message, .. } => {
+            if message.contains("message not found") || message.contains("document mismatch") {
+                Error::MissingChunkObject {
+                    chunk_hash: chunk_hash.to_string(),
+                }
+            } else {
+                Error::telegram(message)
+            }
+        }
+        _other => Error::MissingChunkObject {
+            chunk_hash: chunk_hash.to_string(),
+        },
+    }
+}
+
+/// Splits queued objects (`small` per object, in order) into fetches: more than
+/// [`BATCH_MIN_OBJECTS`] small objects in a row go out together, [`BATCH_MAX_OBJECTS`] at a time;
+/// everything else is fetched alone.
+fn plan_fetches(small: impl IntoIterator<Item = bool>) -> Vec<Range<usize>> {
+    let small = small.into_iter().collect::<Vec<_>>();
+    let mut fetches = Vec::new();
+    let mut i = 0;
+    while i < small.len() {
+        let run = small[i..].iter().take_while(|s| **s).count();
+        if run > BATCH_MIN_OBJECTS {
+            for start in (i..i + run).step_by(BATCH_MAX_OBJECTS) {
+                fetches.push(start..(start + BATCH_MAX_OBJECTS).min(i + run));
+            }
+            i += run;
+        } else {
+            fetches.push(i..i + 1);
+            i += 1;
+        }
+    }
+    fetches
+}
+
+/// Downloads `(object_id, chunk_hash)` items with one [`Storage::download_documents`] call. An
+/// entry is `None` when the item should be fetched on its own instead: the whole batch failed, or
+/// its object hit a transient error the single-object path retries.
+async fn download_small_objects<S: Storage>(
+    storage: &S,
+    snapshot_id: &str,
+    items: &[(&str, &str)],
+    state: &DownloadProgressState<'_>,
+) -> Vec<Option<Result<Vec<u8>>>> {
+    let object_ids = items
+        .iter()
+        .map(|(object_id, _)| object_id.to_string())
+        .collect::<Vec<_>>();
+    let batch = match storage.download_documents(&object_ids).await {
+        Ok(batch) if batch.len() == items.len() => batch,
+        Ok(batch) => {
+            warn!(
+                event = "io.telegram.download_batch_failed",
+                snapshot_id,
+                objects = items.len(),
+                error = %format!("expected {} results, got {}", items.len(), batch.len()),
+                "io.telegram.download_batch_failed"
+            );
+            return items.iter().map(|_| None).collect();
+        }
+        Err(e) => {
+            warn!(
+                event = "io.telegram.download_batch_failed",
+                snapshot_id,
+                objects = items.len(),
+                error = %e,
+                "io.telegram.download_batch_failed"
+            );
+            return items.iter().map(|_| None).collect();
+        }
+    };
+    items
+        .iter()
+        .zip(batch)
+        .map(|(&(object_id, chunk_hash), res)| match res {
+            Ok(bytes) => {
+                state.add_downloaded(bytes.len() as u64, None);
+                Some(Ok(bytes))
+            }
+            Err(e) => match download_error(snapshot_id, object_id, chunk_hash, e) {
+                Error::Telegram { .. } => None,
+                e => Some(Err(e)),
+            },
+        })
+        .collect()
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -846,26 +2513,29 @@ async fn verify_chunks<S: Storage>(
     snapshot_id: &str,
     use_endpoint_db: bool,
     use_dedupe_db: bool,
-    master_key: &[u8; 32],
+    sample: Option<VerifySample>,
+    data_key: &DataKey,
     cancel: Option<&CancellationToken>,
     bytes_downloaded: &mut u64,
     net_bytes_downloaded: &mut u64,
     have_net_bytes_downloaded: Arc<AtomicBool>,
     progress: Option<&dyn ProgressSink>,
+    concurrency: usize,
 ) -> Result<VerifyResult> {
     let mut result = VerifyResult::default();
-    let mut pack_cache: Option<(String, Vec<u8>)> = None;
 
     let rows = if use_dedupe_db {
         sqlx::query(
             r#"
             SELECT used.chunk_hash as chunk_hash,
+                   used.len as len,
                    COALESCE(dd_co.object_id, co.object_id) as object_id
             FROM (
-              SELECT DISTINCT fc.chunk_hash as chunk_hash
+              SELECT fc.chunk_hash as chunk_hash, MAX(fc.len) as len
               FROM file_chunks fc
               JOIN files f ON f.file_id = fc.file_id
               WHERE f.snapshot_id = ?
+              GROUP BY fc.chunk_hash
             ) used
             LEFT JOIN dd.chunk_objects dd_co
               ON dd_co.chunk_hash = used.chunk_hash
@@ -886,12 +2556,14 @@ async fn verify_chunks<S: Storage>(
         sqlx::query(
             r#"
             SELECT used.chunk_hash as chunk_hash,
+                   used.len as len,
                    COALESCE(ep_co.object_id, co.object_id) as object_id
             FROM (
-              SELECT DISTINCT fc.chunk_hash as chunk_hash
+              SELECT fc.chunk_hash as chunk_hash, MAX(fc.len) as len
               FROM file_chunks fc
               JOIN files f ON f.file_id = fc.file_id
               WHERE f.snapshot_id = ?
+              GROUP BY fc.chunk_hash
             ) used
             LEFT JOIN ep.chunk_objects ep_co
               ON ep_co.chunk_hash = used.chunk_hash
@@ -911,13 +2583,14 @@ async fn verify_chunks<S: Storage>(
     } else {
         sqlx::query(
             r#"
-            SELECT co.chunk_hash as chunk_hash, co.object_id as object_id
+            SELECT co.chunk_hash as chunk_hash, used.len as len, co.object_id as object_id
             FROM chunk_objects co
             JOIN (
-              SELECT DISTINCT fc.chunk_hash as chunk_hash
+              SELECT fc.chunk_hash as chunk_hash, MAX(fc.len) as len
               FROM file_chunks fc
               JOIN files f ON f.file_id = fc.file_id
               WHERE f.snapshot_id = ?
+              GROUP BY fc.chunk_hash
             ) used ON used.chunk_hash = co.chunk_hash
             WHERE co.provider = ?
             ORDER BY co.object_id, co.chunk_hash
@@ -929,232 +2602,320 @@ async fn verify_chunks<S: Storage>(
         .await?
     };
 
-    for row in rows {
-        if let Some(cancel) = cancel
-            && cancel.is_cancelled()
-        {
-            return Err(Error::Cancelled);
+    let chunks_total = rows.len() as u64;
+    let rows = match sample {
+        Some(sample) => {
+            let lens = rows
+                .iter()
+                .map(|row| {
+                    let chunk_hash: String = row.get("chunk_hash");
+                    let len: i64 = row.get("len");
+                    (chunk_hash, len.max(0) as u64)
+                })
+                .collect::<Vec<_>>();
+            let keep = select_verify_sample(snapshot_id, &lens, sample)?;
+            rows.into_iter()
+                .zip(keep)
+                .filter_map(|(row, keep)| keep.then_some(row))
+                .collect()
         }
+        None => rows,
+    };
 
+    let bytes_total = rows
+        .iter()
+        .map(|row| row.get::<i64, _>("len").max(0) as u64)
+        .fold(0u64, u64::saturating_add);
+    let totals = progress.map(|sink| TotalsProgress {
+        inner: sink,
+        files_total: None,
+        chunks_total: rows.len() as u64,
+        bytes_total,
+    });
+    let progress = totals.as_ref().map(|v| v as &dyn ProgressSink);
+    if let Some(sink) = progress {
+        sink.on_progress(TaskProgress {
+            phase: Phase::Chunks,
+            chunks_done: Some(0),
+            bytes_read: Some(0),
+            bytes_downloaded: Some(*bytes_downloaded),
+            ..TaskProgress::default()
+        });
+    }
+
+    // Slices of one pack are adjacent (rows are ordered by object id), so each pack is
+    // downloaded once no matter how the units are scheduled.
+    let mut units: Vec<VerifyUnit> = Vec::new();
+    for row in rows {
         let chunk_hash: String = row.get("chunk_hash");
         let encoded_object_id: String = row.get("object_id");
-        let object_ref = parse_chunk_object_ref(&encoded_object_id)?;
-
-        let plain = match object_ref {
-            ChunkObjectRef::Direct { object_id } => {
-                let base_total = *bytes_downloaded;
-                let base_net_total = *net_bytes_downloaded;
-                // Use a sentinel so "0 bytes downloaded" (e.g. fully satisfied from cache) is
-                // distinguishable from "no progress callbacks were ever emitted".
-                let latest = Arc::new(AtomicU64::new(u64::MAX));
-                let latest_for_cb = latest.clone();
-                let latest_net = Arc::new(AtomicU64::new(u64::MAX));
-                let latest_net_for_cb = latest_net.clone();
-                let have_net_for_cb = Arc::clone(&have_net_bytes_downloaded);
-                let framed = storage
-                    .download_document_with_progress(
-                        &object_id,
-                        Some(Box::new(move |p| {
-                            let n = p.bytes;
-                            latest_for_cb.store(n, Ordering::Relaxed);
-                            if let Some(net) = p.net_bytes {
-                                latest_net_for_cb.store(net, Ordering::Relaxed);
-                                have_net_for_cb.store(true, Ordering::Relaxed);
-                            }
-                            if let Some(sink) = progress {
-                                sink.on_progress(TaskProgress {
-                                    phase: "chunks".to_string(),
-                                    bytes_downloaded: Some(base_total.saturating_add(n)),
-                                    net_bytes_downloaded: p
-                                        .net_bytes
-                                        .map(|net| base_net_total.saturating_add(net)),
-                                    ..TaskProgress::default()
-                                });
-                            }
-                        })),
-                    )
-                    .await
-                    .map_err(|e| {
-                        error!(
-                            event = "io.telegram.download_failed",
-                            snapshot_id,
-                            object_id = %object_id,
-                            chunk_hash,
-                            error = %e,
-                            "io.telegram.download_failed"
-                        );
-                        match e {
-                            Error::Telegram { message } => {
-                                if message.contains("message not found")
-                                    || message.contains("document mismatch")
-                                {
-                                    Error::MissingChunkObject {
-                                        chunk_hash: chunk_hash.clone(),
-                                    }
-                                } else {
-                                    Error::Telegram { message }
-                                }
-                            }
-                            _other => Error::MissingChunkObject {
-                                chunk_hash: chunk_hash.clone(),
-                            },
-                        }
-                    })?;
-                let streamed = latest.load(Ordering::Relaxed);
-                let actual = if streamed != u64::MAX {
-                    streamed
-                } else {
-                    framed.len() as u64
-                };
-                *bytes_downloaded = base_total.saturating_add(actual);
-                let streamed_net = latest_net.load(Ordering::Relaxed);
-                if streamed_net != u64::MAX {
-                    *net_bytes_downloaded = base_net_total.saturating_add(streamed_net);
-                }
-                decrypt_framed(master_key, chunk_hash.as_bytes(), &framed).map_err(|e| {
-                    Error::Crypto {
-                        message: format!(
-                            "chunk decrypt failed: snapshot_id={snapshot_id} chunk_hash={chunk_hash} object_id={object_id}; {e}"
-                        ),
-                    }
-                })?
-            }
+        let len = row.get::<i64, _>("len").max(0) as u64;
+        match parse_chunk_object_ref(&encoded_object_id)? {
+            ChunkObjectRef::Direct { object_id } => units.push(VerifyUnit::Direct {
+                chunk_hash,
+                object_id,
+                len,
+            }),
             ChunkObjectRef::PackSlice {
                 pack_object_id,
-                offset: pack_off,
-                len: pack_len,
-            } => {
-                let pack_bytes = match &pack_cache {
-                    Some((cached_id, cached_bytes)) if cached_id == &pack_object_id => {
-                        cached_bytes.as_slice()
-                    }
-                    _ => {
-                        let base_total = *bytes_downloaded;
-                        let base_net_total = *net_bytes_downloaded;
-                        // Use a sentinel so "0 bytes downloaded" (e.g. fully satisfied from cache)
-                        // is distinguishable from "no progress callbacks were ever emitted".
-                        let latest = Arc::new(AtomicU64::new(u64::MAX));
-                        let latest_for_cb = latest.clone();
-                        let latest_net = Arc::new(AtomicU64::new(u64::MAX));
-                        let latest_net_for_cb = latest_net.clone();
-                        let have_net_for_cb = Arc::clone(&have_net_bytes_downloaded);
-                        let bytes = storage
-                            .download_document_with_progress(
-                                &pack_object_id,
-                                Some(Box::new(move |p| {
-                                    let n = p.bytes;
-                                    latest_for_cb.store(n, Ordering::Relaxed);
-                                    if let Some(net) = p.net_bytes {
-                                        latest_net_for_cb.store(net, Ordering::Relaxed);
-                                        have_net_for_cb.store(true, Ordering::Relaxed);
-                                    }
-                                    if let Some(sink) = progress {
-                                        sink.on_progress(TaskProgress {
-                                            phase: "chunks".to_string(),
-                                            bytes_downloaded: Some(base_total.saturating_add(n)),
-                                            net_bytes_downloaded: p
-                                                .net_bytes
-                                                .map(|net| base_net_total.saturating_add(net)),
-                                            ..TaskProgress::default()
-                                        });
-                                    }
-                                })),
-                            )
-                            .await
-                            .map_err(|e| {
-                                error!(
-                                event = "io.telegram.download_failed",
-                                snapshot_id,
-                                object_id = %pack_object_id,
-                                chunk_hash,
-                                    error = %e,
-                                    "io.telegram.download_failed"
-                                );
-                                match e {
-                                    Error::Telegram { message } => {
-                                        if message.contains("message not found")
-                                            || message.contains("document mismatch")
-                                        {
-                                            Error::MissingChunkObject {
-                                                chunk_hash: chunk_hash.clone(),
-                                            }
-                                        } else {
-                                            Error::Telegram { message }
-                                        }
-                                    }
-                                    _other => Error::MissingChunkObject {
-                                        chunk_hash: chunk_hash.clone(),
-                                    },
-                                }
-                            })?;
-                        let streamed = latest.load(Ordering::Relaxed);
-                        let actual = if streamed != u64::MAX {
-                            streamed
-                        } else {
-                            bytes.len() as u64
-                        };
-                        *bytes_downloaded = base_total.saturating_add(actual);
-                        let streamed_net = latest_net.load(Ordering::Relaxed);
-                        if streamed_net != u64::MAX {
-                            *net_bytes_downloaded = base_net_total.saturating_add(streamed_net);
-                        }
-                        pack_cache = Some((pack_object_id.clone(), bytes));
-                        pack_cache.as_ref().expect("just set").1.as_slice()
-                    }
-                };
+                offset,
+                len,
+            } => match units.last_mut() {
+                Some(VerifyUnit::Pack {
+                    pack_object_id: last,
+                    slices,
+                }) if *last == pack_object_id => slices.push((chunk_hash, offset, len)),
+                _ => units.push(VerifyUnit::Pack {
+                    pack_object_id,
+                    slices: vec![(chunk_hash, offset, len)],
+                }),
+            },
+        }
+    }
 
-                if pack_len > usize::MAX as u64 {
-                    return Err(Error::Integrity {
-                        message: "pack slice too large".to_string(),
-                    });
+    let fetches = plan_fetches(units.iter().map(|unit| {
+        matches!(unit, VerifyUnit::Direct { len, .. } if *len <= BATCH_SMALL_OBJECT_MAX_BYTES)
+    }));
+    let mut queued = units.into_iter();
+    let units = fetches
+        .into_iter()
+        .filter_map(|range| {
+            if range.len() == 1 {
+                return queued.next();
+            }
+            let objects = queued
+                .by_ref()
+                .take(range.len())
+                .filter_map(|unit| match unit {
+                    VerifyUnit::Direct {
+                        chunk_hash,
+                        object_id,
+                        ..
+                    } => Some((chunk_hash, object_id)),
+                    _ => None,
+                })
+                .collect();
+            Some(VerifyUnit::Batch { objects })
+        })
+        .collect::<Vec<_>>();
+
+    let state = DownloadProgressState::new(
+        *bytes_downloaded,
+        *net_bytes_downloaded,
+        have_net_bytes_downloaded,
+        progress,
+        Phase::Chunks,
+    );
+
+    let mut checks = futures::stream::iter(units)
+        .map(|unit| {
+            let state = &state;
+            async move {
+                if cancel.is_some_and(CancellationToken::is_cancelled) {
+                    return Err(Error::Cancelled);
                 }
-                let framed = extract_pack_blob(pack_bytes, pack_off, pack_len)?;
-                decrypt_framed(master_key, chunk_hash.as_bytes(), framed).map_err(|e| {
-                    Error::Crypto {
-                        message: format!(
-                            "chunk decrypt failed (pack slice): snapshot_id={snapshot_id} chunk_hash={chunk_hash} pack_object_id={pack_object_id} offset={pack_off} len={pack_len}; {e}"
-                        ),
-                    }
-                })?
+                verify_unit(storage, snapshot_id, data_key, unit, state).await
             }
+        })
+        .buffer_unordered(concurrency.max(1));
+    loop {
+        let next = match cancel {
+            Some(cancel) => tokio::select! {
+                _ = cancel.cancelled() => return Err(Error::Cancelled),
+                next = checks.next() => next,
+            },
+            None => checks.next().await,
         };
-
-        let got_hash = blake3::hash(&plain).to_hex().to_string();
-        if got_hash != chunk_hash {
-            return Err(Error::Integrity {
-                message: format!("chunk hash mismatch: {chunk_hash}"),
-            });
+        match next {
+            Some(res) => res?,
+            None => break,
         }
+    }
+    drop(checks);
+
+    let counters = state
+        .counters
+        .into_inner()
+        .unwrap_or_else(|e| e.into_inner());
+    *bytes_downloaded = counters.bytes_downloaded;
+    *net_bytes_downloaded = counters.net_bytes_downloaded;
+    result.chunks_checked = counters.chunks_done;
+    result.bytes_checked = counters.bytes_done;
+
+    result.chunks_skipped = chunks_total.saturating_sub(result.chunks_checked);
+    result.coverage_percent = if chunks_total == 0 {
+        100.0
+    } else {
+        result.chunks_checked as f64 * 100.0 / chunks_total as f64
+    };
+    Ok(result)
+}
 
-        result.chunks_checked += 1;
-        result.bytes_checked += plain.len() as u64;
+/// Marks which of `chunks` (`(chunk_hash, len)`, in any order) fall into the sample window.
+fn select_verify_sample(
+    snapshot_id: &str,
+    chunks: &[(String, u64)],
+    sample: VerifySample,
+) -> Result<Vec<bool>> {
+    if !(sample.percent > 0.0 && sample.percent <= 100.0) {
+        return Err(Error::InvalidConfig {
+            message: format!("sample percent must be in (0, 100]: got {}", sample.percent),
+        });
+    }
 
-        if let Some(sink) = progress {
-            sink.on_progress(TaskProgress {
-                phase: "chunks".to_string(),
-                source_files_total: None,
-                source_bytes_total: None,
-                source_bytes_need_upload_total: None,
-                files_total: None,
-                files_done: None,
-                chunks_total: None,
-                chunks_done: Some(result.chunks_checked),
-                bytes_read: Some(result.bytes_checked),
-                upload_bytes_total: None,
-                bytes_uploaded_confirmed: None,
-                bytes_uploaded_source: None,
-                bytes_uploaded: None,
-                net_bytes_uploaded: None,
-                bytes_downloaded: Some(*bytes_downloaded),
-                net_bytes_downloaded: have_net_bytes_downloaded
-                    .load(Ordering::Relaxed)
-                    .then_some(*net_bytes_downloaded),
-                bytes_deduped: None,
-            });
+    // Window width on the u64 ring; `as` saturates, so 100% maps to u64::MAX.
+    let span = (sample.percent / 100.0 * 2f64.powi(64)) as u64;
+    let window_start = sample.rotation.wrapping_mul(span);
+    let mut in_window = chunks
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, (chunk_hash, len))| {
+            let mut hasher = blake3::Hasher::new();
+            hasher.update(snapshot_id.as_bytes());
+            hasher.update(&[0]);
+            hasher.update(chunk_hash.as_bytes());
+            let pos = u64::from_le_bytes(
+                hasher.finalize().as_bytes()[..8]
+                    .try_into()
+                    .expect("8 bytes"),
+            );
+            let offset = pos.wrapping_sub(window_start);
+            (sample.percent >= 100.0 || offset < span).then_some((offset, idx, *len))
+        })
+        .collect::<Vec<_>>();
+    in_window.sort_unstable();
+
+    let mut keep = vec![false; chunks.len()];
+    let mut bytes = 0u64;
+    for (_, idx, len) in in_window {
+        if let Some(max_bytes) = sample.max_bytes {
+            bytes = bytes.saturating_add(len);
+            if bytes > max_bytes {
+                break;
+            }
         }
+        keep[idx] = true;
     }
+    Ok(keep)
+}
 
-    Ok(result)
+/// Refuses `delete_extraneous` on `/` and the home directory, where restoring the wrong snapshot
+/// would wipe far more than it restores.
+fn check_delete_extraneous_target(target: &Path) -> Result<()> {
+    let canonical = |p: &Path| fs::canonicalize(p).unwrap_or_else(|_| p.to_path_buf());
+    let target = canonical(target);
+    let home = std::env::var_os("HOME").map(|h| canonical(Path::new(&h)));
+    if target.parent().is_none() || home.as_deref() == Some(target.as_path()) {
+        return Err(Error::InvalidConfig {
+            message: format!(
+                "refusing to delete extraneous files under {}: pick a dedicated target directory",
+                target.display()
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// `path -> kind` of every entry in the snapshot; refuses a snapshot without files, which would
+/// make `delete_extraneous` empty the target.
+async fn snapshot_entry_kinds(
+    pool: &SqlitePool,
+    snapshot_id: &str,
+) -> Result<HashMap<String, String>> {
+    let rows = sqlx::query("SELECT path, kind FROM files WHERE snapshot_id = ?")
+        .bind(snapshot_id)
+        .fetch_all(pool)
+        .await?;
+    let entries = rows
+        .into_iter()
+        .map(|row| (row.get::<String, _>("path"), row.get::<String, _>("kind")))
+        .collect::<HashMap<_, _>>();
+    if !entries.values().any(|kind| kind == "file") {
+        return Err(Error::InvalidConfig {
+            message: format!(
+                "refusing to delete extraneous files: snapshot {snapshot_id} has no files"
+            ),
+        });
+    }
+    Ok(entries)
+}
+
+/// Removes the entries under `target` that the snapshot does not have or has as another kind
+/// (a file where it has a directory, a symlink where it has a file, ...), directories bottom-up.
+/// Symlinks are removed as links, never followed. `dry_run` only records them.
+fn delete_extraneous(
+    target: &Path,
+    snapshot: &HashMap<String, String>,
+    dry_run: bool,
+    result: &mut RestoreResult,
+) -> Result<()> {
+    delete_extraneous_in(target, "", snapshot, dry_run, result)
+}
+
+fn delete_extraneous_in(
+    dir: &Path,
+    rel_dir: &str,
+    snapshot: &HashMap<String, String>,
+    dry_run: bool,
+    result: &mut RestoreResult,
+) -> Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let rel = if rel_dir.is_empty() {
+            name.into_owned()
+        } else {
+            format!("{rel_dir}/{name}")
+        };
+        let kind = snapshot.get(&rel).map(String::as_str);
+        let path = entry.path();
+
+        // `file_type` does not follow symlinks, so a symlinked directory is removed as a link.
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            delete_extraneous_in(&path, &rel, snapshot, dry_run, result)?;
+            if kind == Some("dir") {
+                continue;
+            }
+            if !dry_run {
+                fs::remove_dir(&path)?;
+            }
+            warn!(
+                event = "restore.extraneous_deleted",
+                path = %rel,
+                kind = "dir",
+                dry_run,
+                "restore.extraneous_deleted"
+            );
+            result.deleted_paths.push(format!("{rel}/"));
+            continue;
+        }
+        let disk_kind = if file_type.is_symlink() {
+            "symlink"
+        } else if file_type.is_file() {
+            "file"
+        } else {
+            "other"
+        };
+        if kind == Some(disk_kind) {
+            continue;
+        }
+        if !dry_run {
+            fs::remove_file(&path)?;
+        }
+        warn!(
+            event = "restore.extraneous_deleted",
+            path = %rel,
+            kind = disk_kind,
+            dry_run,
+            "restore.extraneous_deleted"
+        );
+        result.files_deleted += 1;
+        result.deleted_paths.push(rel);
+    }
+    Ok(())
 }
 
 fn ensure_empty_dir(path: &Path) -> Result<()> {
@@ -0,0 +1,247 @@
+//! Retries of transient storage failures within a single run (`retry.max_attempts`,
+//! `retry.max_total_secs`).
+//!
+//! Every retried operation of a run draws on one [`RetryBudget`]: an operation gets at most
+//! `max_attempts` attempts, and all backoff waits of the run together stay within
+//! `max_total_secs`. Permanent errors (auth, missing chat, integrity) fail at once.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+
+use crate::config::Retry;
+use crate::{Error, Result};
+
+const RETRY_BACKOFF_BASE_MS: u64 = 1_000;
+const RETRY_BACKOFF_MAX_MS: u64 = 15_000;
+
+/// Retries spent by a run, reported in `BackupResult`/`RestoreResult` and `run.finish`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetryStats {
+    pub retries: u64,
+    /// Total backoff waited before those retries.
+    pub retry_wait_ms: u64,
+}
+
+#[derive(Debug)]
+pub struct RetryBudget {
+    max_attempts: u32,
+    max_total_ms: u64,
+    cancel: CancellationToken,
+    retries: AtomicU64,
+    wait_ms: AtomicU64,
+}
+
+impl RetryBudget {
+    pub fn new(settings: &Retry, cancel: Option<&CancellationToken>) -> Self {
+        Self::resume(settings, cancel, RetryStats::default())
+    }
+
+    /// Continues a run's budget after `spent` (e.g. the catalog update after the backup itself).
+    pub fn resume(settings: &Retry, cancel: Option<&CancellationToken>, spent: RetryStats) -> Self {
+        Self {
+            max_attempts: settings.max_attempts.max(1),
+            max_total_ms: settings.max_total_secs.saturating_mul(1000),
+            cancel: cancel.cloned().unwrap_or_default(),
+            retries: AtomicU64::new(spent.retries),
+            wait_ms: AtomicU64::new(spent.retry_wait_ms),
+        }
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    pub fn stats(&self) -> RetryStats {
+        RetryStats {
+            retries: self.retries.load(Ordering::Relaxed),
+            retry_wait_ms: self.wait_ms.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Books the backoff before retrying an operation whose attempt `attempt` (1-based) failed
+    /// with `error`; `None` when the error is permanent or the attempts or the run's wait budget
+    /// are used up.
+    pub fn next_backoff(&self, attempt: u32, error: &Error) -> Option<Duration> {
+        if attempt >= self.max_attempts || !is_transient(error) {
+            return None;
+        }
+        let backoff_ms = backoff_ms(attempt);
+        self.wait_ms
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |spent| {
+                let next = spent.saturating_add(backoff_ms);
+                (next <= self.max_total_ms).then_some(next)
+            })
+            .ok()?;
+        self.retries.fetch_add(1, Ordering::Relaxed);
+        Some(Duration::from_millis(backoff_ms))
+    }
+
+    /// Sleeps for `backoff`; cancelling the run ends the sleep with [`Error::Cancelled`].
+    pub async fn wait(&self, backoff: Duration) -> Result<()> {
+        tokio::select! {
+            _ = self.cancel.cancelled() => Err(Error::Cancelled),
+            _ = tokio::time::sleep(backoff) => Ok(()),
+        }
+    }
+
+    /// Runs `op` until it succeeds, fails permanently or the budget is used up.
+    pub async fn run<T, F, Fut>(&self, op_name: &str, mut op: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempt = 1;
+        loop {
+            let err = match op().await {
+                Ok(v) => return Ok(v),
+                Err(e) => e,
+            };
+            let Some(backoff) = self.next_backoff(attempt, &err) else {
+                return Err(err);
+            };
+            warn!(
+                event = "io.retry",
+                op = op_name,
+                attempt,
+                max_attempts = self.max_attempts,
+                backoff_ms = backoff.as_millis() as u64,
+                error = %err,
+                "io.retry"
+            );
+            self.wait(backoff).await?;
+            attempt += 1;
+        }
+    }
+}
+
+/// Errors worth another attempt: timeouts, dropped connections and flood waits.
+pub fn is_transient(error: &Error) -> bool {
+    match error {
+        Error::Telegram { message, .. } => crate::error::is_transient_telegram_message(message),
+        Error::Io(e) => matches!(
+            e.kind(),
+            std::io::ErrorKind::TimedOut
+                | std::io::ErrorKind::ConnectionReset
+                | std::io::ErrorKind::ConnectionAborted
+                | std::io::ErrorKind::BrokenPipe
+        ),
+        _ => false,
+    }
+}
+
+fn backoff_ms(attempt: u32) -> u64 {
+    let shift = attempt.saturating_sub(1).min(16);
+    RETRY_BACKOFF_BASE_MS
+        .saturating_mul(1u64 << shift)
+        .min(RETRY_BACKOFF_MAX_MS)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicU32;
+
+    use super::*;
+
+    fn settings(max_attempts: u32, max_total_secs: u64) -> Retry {
+        Retry {
+            max_attempts,
+            max_total_secs,
+        }
+    }
+
+    fn transient() -> Error {
+        Error::telegram("save_file_part timed out after 60s".to_string())
+    }
+
+    #[tokio::test]
+    async fn transient_errors_are_retried_until_attempts_run_out() {
+        let budget = RetryBudget::new(&settings(2, 300), None);
+        let calls = AtomicU32::new(0);
+        let err = budget
+            .run("test", || async {
+                calls.fetch_add(1, Ordering::Relaxed);
+                Err::<(), _>(transient())
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), "telegram.unavailable");
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+        assert_eq!(
+            budget.stats(),
+            RetryStats {
+                retries: 1,
+                retry_wait_ms: 1_000,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn permanent_errors_fail_fast() {
+        let budget = RetryBudget::new(&settings(5, 300), None);
+        let calls = AtomicU32::new(0);
+        let err = budget
+            .run("test", || async {
+                calls.fetch_add(1, Ordering::Relaxed);
+                Err::<(), _>(Error::telegram("rpc error: CHAT_NOT_FOUND".to_string()))
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), "telegram.unavailable");
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        assert_eq!(budget.stats(), RetryStats::default());
+    }
+
+    #[test]
+    fn wait_budget_is_shared_by_the_whole_run() {
+        let budget = RetryBudget::resume(
+            &settings(10, 5),
+            None,
+            RetryStats {
+                retries: 1,
+                retry_wait_ms: 1_000,
+            },
+        );
+        assert_eq!(
+            budget.next_backoff(1, &transient()),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(
+            budget.next_backoff(2, &transient()),
+            Some(Duration::from_secs(2))
+        );
+        // 4s more would exceed the 5s budget.
+        assert_eq!(budget.next_backoff(3, &transient()), None);
+        assert_eq!(
+            budget.next_backoff(1, &transient()),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(budget.next_backoff(1, &transient()), None);
+        assert_eq!(
+            budget.stats(),
+            RetryStats {
+                retries: 4,
+                retry_wait_ms: 5_000,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn cancel_interrupts_the_backoff_sleep() {
+        let cancel = CancellationToken::new();
+        let budget = RetryBudget::new(&settings(3, 3_600), Some(&cancel));
+        let canceller = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            canceller.cancel();
+        });
+        let started = std::time::Instant::now();
+        let err = budget.wait(Duration::from_secs(600)).await.unwrap_err();
+        assert!(matches!(err, Error::Cancelled));
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+}
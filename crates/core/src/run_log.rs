@@ -1,15 +1,20 @@
-use std::fs::OpenOptions;
-use std::io::{LineWriter, Write};
+use std::collections::{HashSet, VecDeque};
+use std::fs::{OpenOptions, TryLockError};
+use std::io::{BufRead, BufReader, LineWriter, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Mutex, MutexGuard, OnceLock};
 
-use chrono::Utc;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::Serialize;
+use tokio::sync::broadcast;
 use tracing::Dispatch;
-use tracing_subscriber::{EnvFilter, layer::SubscriberExt};
+use tracing_subscriber::{EnvFilter, Layer, layer::SubscriberExt};
 
 static RUN_LOGGER: OnceLock<RunLogger> = OnceLock::new();
 static RUN_LOG_DISPATCH: OnceLock<Dispatch> = OnceLock::new();
 static TRACING_INIT: OnceLock<()> = OnceLock::new();
+static LIVE_RUN_LOG: OnceLock<LiveRunLog> = OnceLock::new();
 
 #[derive(Debug)]
 struct RunState {
@@ -40,6 +45,8 @@ impl RunLogger {
         }
 
         let file = OpenOptions::new().create_new(true).write(true).open(path)?;
+        // Held until the writer is dropped; marks the log as active for pruning and `--follow`.
+        file.try_lock().map_err(std::io::Error::from)?;
         guard.writer = Some(LineWriter::new(file));
         Ok(())
     }
@@ -106,6 +113,144 @@ impl<'a> tracing_subscriber::fmt::MakeWriter<'a> for &RunLogger {
     }
 }
 
+/// Lines a `logs.stream` subscriber gets from before it subscribed.
+pub const LIVE_RUN_LOG_BACKLOG_LINES: usize = 100;
+/// Lines a subscriber may fall behind before it skips ahead.
+const LIVE_RUN_LOG_CHANNEL_LINES: usize = 1024;
+
+#[derive(Debug)]
+struct LiveRun {
+    run_id: String,
+    /// From the run's `run.start` event.
+    target_id: Option<String>,
+    backlog: VecDeque<String>,
+    tx: broadcast::Sender<String>,
+}
+
+/// The active run's log lines, kept in memory for `logs.stream` next to the run log file.
+#[derive(Debug, Default)]
+struct LiveRunLog {
+    run: Mutex<Option<LiveRun>>,
+    /// Lets the layer skip formatting events while no run is active.
+    active: AtomicBool,
+}
+
+impl LiveRunLog {
+    fn start(&self, run_id: &str) {
+        let (tx, _) = broadcast::channel(LIVE_RUN_LOG_CHANNEL_LINES);
+        *self.run.lock().expect("live run log mutex poisoned") = Some(LiveRun {
+            run_id: run_id.to_string(),
+            target_id: None,
+            backlog: VecDeque::with_capacity(LIVE_RUN_LOG_BACKLOG_LINES),
+            tx,
+        });
+        self.active.store(true, Ordering::Relaxed);
+    }
+
+    /// Drops the sender, which ends every subscriber's stream once it has read the last line.
+    fn finish(&self) {
+        self.active.store(false, Ordering::Relaxed);
+        self.run.lock().expect("live run log mutex poisoned").take();
+    }
+
+    fn push(&self, line: &str) {
+        let mut guard = self.run.lock().expect("live run log mutex poisoned");
+        let Some(run) = guard.as_mut() else {
+            return;
+        };
+        if run.target_id.is_none() && line.contains("run.start") {
+            run.target_id = serde_json::from_str::<serde_json::Value>(line)
+                .ok()
+                .filter(|v| v["fields"]["event"].as_str() == Some("run.start"))
+                .and_then(|v| v["fields"]["target_id"].as_str().map(|s| s.to_string()));
+        }
+        if run.backlog.len() == LIVE_RUN_LOG_BACKLOG_LINES {
+            run.backlog.pop_front();
+        }
+        run.backlog.push_back(line.to_string());
+        // No receivers is fine: nobody is watching.
+        let _ = run.tx.send(line.to_string());
+    }
+
+    fn subscribe(
+        &self,
+        run_id: Option<&str>,
+        target_id: Option<&str>,
+    ) -> Option<LiveRunLogSubscription> {
+        let guard = self.run.lock().expect("live run log mutex poisoned");
+        let run = guard.as_ref()?;
+        if run_id.is_some_and(|id| id != run.run_id)
+            || target_id.is_some_and(|id| run.target_id.as_deref() != Some(id))
+        {
+            return None;
+        }
+        // Under the same lock as `push`, so no line is both in the backlog and received.
+        Some(LiveRunLogSubscription {
+            run_id: run.run_id.clone(),
+            target_id: run.target_id.clone(),
+            backlog: run.backlog.iter().cloned().collect(),
+            lines: run.tx.subscribe(),
+        })
+    }
+}
+
+/// One formatted event; the fmt layer writes each event through a fresh writer.
+struct LiveRunLogWriter<'a> {
+    log: &'a LiveRunLog,
+    buf: Vec<u8>,
+}
+
+impl Write for LiveRunLogWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for LiveRunLogWriter<'_> {
+    fn drop(&mut self) {
+        let text = String::from_utf8_lossy(&self.buf);
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            self.log.push(line);
+        }
+    }
+}
+
+impl<'a> tracing_subscriber::fmt::MakeWriter<'a> for &LiveRunLog {
+    type Writer = LiveRunLogWriter<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        LiveRunLogWriter {
+            log: self,
+            buf: Vec::new(),
+        }
+    }
+}
+
+/// A subscription to the running task's log, as served by the daemon's `logs.stream`.
+#[derive(Debug)]
+pub struct LiveRunLogSubscription {
+    pub run_id: String,
+    pub target_id: Option<String>,
+    /// Up to [`LIVE_RUN_LOG_BACKLOG_LINES`] lines logged before subscribing, oldest first.
+    pub backlog: Vec<String>,
+    /// Lines logged after subscribing; closed when the run finishes.
+    pub lines: broadcast::Receiver<String>,
+}
+
+/// Subscribes to the active run's log when it matches `run_id` and/or `target_id` (a target is
+/// only known once the run logged `run.start`). The lines are the run log file's NDJSON lines.
+pub fn subscribe_live_run_log(
+    run_id: Option<&str>,
+    target_id: Option<&str>,
+) -> Option<LiveRunLogSubscription> {
+    LIVE_RUN_LOG.get()?.subscribe(run_id, target_id)
+}
+
 fn build_env_filter_from(televybackup_log: Option<&str>, rust_log: Option<&str>) -> EnvFilter {
     let default = || EnvFilter::new("debug");
 
@@ -128,14 +273,25 @@ fn build_env_filter() -> EnvFilter {
 pub fn init_run_logging() {
     TRACING_INIT.get_or_init(|| {
         let logger = RUN_LOGGER.get_or_init(RunLogger::new);
+        let live = LIVE_RUN_LOG.get_or_init(LiveRunLog::default);
         let env_filter = build_env_filter();
 
         let layer = tracing_subscriber::fmt::layer()
             .json()
             .with_timer(tracing_subscriber::fmt::time::UtcTime::rfc_3339())
             .with_writer(logger);
-
-        let subscriber = tracing_subscriber::registry().with(env_filter).with(layer);
+        let live_layer = tracing_subscriber::fmt::layer()
+            .json()
+            .with_timer(tracing_subscriber::fmt::time::UtcTime::rfc_3339())
+            .with_writer(live)
+            .with_filter(tracing_subscriber::filter::filter_fn(|_| {
+                live.active.load(Ordering::Relaxed)
+            }));
+
+        let subscriber = tracing_subscriber::registry()
+            .with(env_filter)
+            .with(layer)
+            .with(live_layer);
         let dispatch = Dispatch::new(subscriber);
 
         // Keep a handle so `start_run_log` can always install a thread-local dispatcher,
@@ -165,6 +321,9 @@ impl Drop for RunLogGuard {
         if let Some(logger) = RUN_LOGGER.get() {
             let _ = logger.finish();
         }
+        if let Some(live) = LIVE_RUN_LOG.get() {
+            live.finish();
+        }
     }
 }
 
@@ -188,13 +347,16 @@ pub fn start_run_log(kind: &str, run_id: &str, data_dir: &Path) -> std::io::Resu
     let file_name = format!(
         "sync-{}-{}-{}.ndjson",
         sanitize_filename_component(kind),
-        started_at_utc.format("%Y%m%dT%H%M%SZ"),
+        started_at_utc.format(RUN_LOG_FILE_TIMESTAMP_FORMAT),
         sanitize_filename_component(run_id)
     );
     let path = log_dir.join(file_name);
 
     let logger = RUN_LOGGER.get_or_init(RunLogger::new);
     logger.start(&path)?;
+    if let Some(live) = LIVE_RUN_LOG.get() {
+        live.start(run_id);
+    }
 
     let dispatch = RUN_LOG_DISPATCH
         .get()
@@ -209,6 +371,327 @@ pub fn start_run_log(kind: &str, run_id: &str, data_dir: &Path) -> std::io::Resu
     })
 }
 
+/// Upper bound on the number of lines kept in a run log excerpt (status snapshots, CLI output).
+pub const RUN_LOG_EXCERPT_MAX_LINES: usize = 20;
+/// Upper bound on the total size of a run log excerpt so `status.json` never balloons.
+pub const RUN_LOG_EXCERPT_MAX_BYTES: usize = 8 * 1024;
+const RUN_LOG_EXCERPT_MAX_LINE_BYTES: usize = 1024;
+const RUN_LOG_EXCERPT_TAIL_READ_BYTES: u64 = 64 * 1024;
+const RUN_LOG_SCAN_MAX_FILES: usize = 200;
+const RUN_LOG_SCAN_MAX_HEAD_LINES: usize = 16;
+
+pub fn run_log_dir(data_dir: &Path) -> PathBuf {
+    resolve_log_dir(data_dir)
+}
+
+pub fn redact_secret(s: impl Into<String>, secret: &str) -> String {
+    let s = s.into();
+    if secret.is_empty() {
+        s
+    } else {
+        s.replace(secret, "[redacted]")
+    }
+}
+
+/// Finds the newest run log of `kind` whose `run.start` event references `target_id`.
+///
+/// Run log file names embed the start timestamp, so a reverse lexical sort is newest-first.
+pub fn find_latest_run_log_for_target(
+    data_dir: &Path,
+    kind: &str,
+    target_id: &str,
+) -> std::io::Result<Option<PathBuf>> {
+    let log_dir = resolve_log_dir(data_dir);
+    let prefix = format!("sync-{}-", sanitize_filename_component(kind));
+
+    let entries = match std::fs::read_dir(&log_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let mut names = entries
+        .flatten()
+        .filter_map(|e| e.file_name().to_str().map(|s| s.to_string()))
+        .filter(|name| name.starts_with(&prefix) && name.ends_with(".ndjson"))
+        .collect::<Vec<_>>();
+    names.sort_unstable_by(|a, b| b.cmp(a));
+
+    for name in names.into_iter().take(RUN_LOG_SCAN_MAX_FILES) {
+        let path = log_dir.join(name);
+        if run_log_start_target_id(&path)?.as_deref() == Some(target_id) {
+            return Ok(Some(path));
+        }
+    }
+    Ok(None)
+}
+
+fn run_log_start_target_id(path: &Path) -> std::io::Result<Option<String>> {
+    let file = match std::fs::File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    for line in BufReader::new(file)
+        .lines()
+        .take(RUN_LOG_SCAN_MAX_HEAD_LINES)
+    {
+        let Ok(line) = line else {
+            break;
+        };
+        let Ok(v) = serde_json::from_str::<serde_json::Value>(&line) else {
+            continue;
+        };
+        let fields = &v["fields"];
+        if fields["event"].as_str() != Some("run.start") {
+            continue;
+        }
+        return Ok(fields["target_id"].as_str().map(|s| s.to_string()));
+    }
+    Ok(None)
+}
+
+/// Reads the tail of a run log as a bounded, redacted excerpt.
+///
+/// Secrets are redacted before any truncation so a cut can never leave a partial secret behind.
+pub fn read_run_log_excerpt(path: &Path, secrets: &[&str]) -> std::io::Result<Vec<String>> {
+    let mut file = std::fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    let start = len.saturating_sub(RUN_LOG_EXCERPT_TAIL_READ_BYTES);
+    file.seek(SeekFrom::Start(start))?;
+
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+    let text = String::from_utf8_lossy(&buf);
+
+    let mut lines = text.lines().collect::<Vec<_>>();
+    if start > 0 && !lines.is_empty() {
+        // The first line is most likely cut in the middle.
+        lines.remove(0);
+    }
+
+    let skip = lines.len().saturating_sub(RUN_LOG_EXCERPT_MAX_LINES);
+    let mut out = lines
+        .into_iter()
+        .skip(skip)
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| {
+            let redacted = secrets
+                .iter()
+                .fold(l.to_string(), |acc, secret| redact_secret(acc, secret));
+            truncate_utf8(redacted, RUN_LOG_EXCERPT_MAX_LINE_BYTES)
+        })
+        .collect::<Vec<_>>();
+
+    let mut total: usize = out.iter().map(|l| l.len()).sum();
+    while total > RUN_LOG_EXCERPT_MAX_BYTES && !out.is_empty() {
+        total -= out.remove(0).len();
+    }
+    Ok(out)
+}
+
+/// A run log file, identified from its name (`sync-<kind>-<started_at>-<run_id>.ndjson`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunLogFile {
+    pub path: PathBuf,
+    pub kind: String,
+    pub run_id: String,
+    pub started_at: DateTime<Utc>,
+}
+
+const RUN_LOG_FILE_TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+fn parse_run_log_file_name(dir: &Path, name: &str) -> Option<RunLogFile> {
+    let rest = name.strip_prefix("sync-")?.strip_suffix(".ndjson")?;
+    let (kind, rest) = rest.split_once('-')?;
+    let (ts, run_id) = rest.split_once('-')?;
+    let started_at = NaiveDateTime::parse_from_str(ts, RUN_LOG_FILE_TIMESTAMP_FORMAT)
+        .ok()?
+        .and_utc();
+    Some(RunLogFile {
+        path: dir.join(name),
+        kind: kind.to_string(),
+        run_id: run_id.to_string(),
+        started_at,
+    })
+}
+
+/// Lists run logs newest first.
+pub fn list_run_logs(data_dir: &Path) -> std::io::Result<Vec<RunLogFile>> {
+    let log_dir = resolve_log_dir(data_dir);
+    let entries = match std::fs::read_dir(&log_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut logs = entries
+        .flatten()
+        .filter_map(|e| {
+            let name = e.file_name();
+            parse_run_log_file_name(&log_dir, name.to_str()?)
+        })
+        .collect::<Vec<_>>();
+    logs.sort_unstable_by(|a, b| {
+        b.started_at
+            .cmp(&a.started_at)
+            .then_with(|| b.path.cmp(&a.path))
+    });
+    Ok(logs)
+}
+
+/// A run log as listed by `logs list` and the remote `runs.list`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunLogSummary {
+    pub run_id: String,
+    pub kind: String,
+    pub started_at: String,
+    pub target_id: Option<String>,
+    pub path: String,
+    pub bytes: Option<u64>,
+    pub active: bool,
+}
+
+/// The newest `limit` run logs, optionally only of one kind or target.
+pub fn summarize_run_logs(
+    data_dir: &Path,
+    target_id: Option<&str>,
+    kind: Option<&str>,
+    limit: usize,
+) -> std::io::Result<Vec<RunLogSummary>> {
+    let mut out = Vec::new();
+    for log in list_run_logs(data_dir)? {
+        if out.len() >= limit {
+            break;
+        }
+        if kind.is_some_and(|k| k != log.kind) {
+            continue;
+        }
+        let log_target_id = run_log_target_id(&log.path)?;
+        if target_id.is_some() && log_target_id.as_deref() != target_id {
+            continue;
+        }
+        out.push(RunLogSummary {
+            bytes: std::fs::metadata(&log.path).map(|m| m.len()).ok(),
+            active: run_log_is_active(&log.path).unwrap_or(false),
+            started_at: log
+                .started_at
+                .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            path: log.path.display().to_string(),
+            target_id: log_target_id,
+            run_id: log.run_id,
+            kind: log.kind,
+        });
+    }
+    Ok(out)
+}
+
+/// Target id recorded by the log's `run.start` event, if any.
+pub fn run_log_target_id(path: &Path) -> std::io::Result<Option<String>> {
+    run_log_start_target_id(path)
+}
+
+/// Whether a run is still writing to this log (the writer holds a file lock until it finishes).
+pub fn run_log_is_active(path: &Path) -> std::io::Result<bool> {
+    let file = std::fs::File::open(path)?;
+    match file.try_lock() {
+        Ok(()) => Ok(false),
+        Err(TryLockError::WouldBlock) => Ok(true),
+        Err(TryLockError::Error(e)) => Err(e),
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RunLogPruneStats {
+    pub deleted: usize,
+    pub kept: usize,
+    /// Over the limits but still being written by a running task.
+    pub skipped_active: usize,
+}
+
+/// Deletes the oldest run logs beyond `logs.keep_days` / `logs.keep_max_files` (0 disables a
+/// limit). The newest log of each kind per target is always kept, and logs that a running task
+/// still holds locked are skipped.
+pub fn prune_run_logs(
+    data_dir: &Path,
+    limits: &crate::config::Logs,
+) -> std::io::Result<RunLogPruneStats> {
+    prune_run_logs_at(data_dir, limits, Utc::now())
+}
+
+fn prune_run_logs_at(
+    data_dir: &Path,
+    limits: &crate::config::Logs,
+    now: DateTime<Utc>,
+) -> std::io::Result<RunLogPruneStats> {
+    let logs = list_run_logs(data_dir)?;
+    let cutoff =
+        (limits.keep_days > 0).then(|| now - chrono::Duration::days(i64::from(limits.keep_days)));
+    let over_limits = |idx: usize, log: &RunLogFile| {
+        (limits.keep_max_files > 0 && idx >= limits.keep_max_files as usize)
+            || cutoff.is_some_and(|c| log.started_at < c)
+    };
+
+    let mut stats = RunLogPruneStats::default();
+    if !logs.iter().enumerate().any(|(i, l)| over_limits(i, l)) {
+        stats.kept = logs.len();
+        return Ok(stats);
+    }
+
+    let mut latest_per_target = HashSet::<(String, String)>::new();
+    for (idx, log) in logs.iter().enumerate() {
+        // Newest first, so the first log seen for a target is its most recent run.
+        let is_latest_for_target = run_log_start_target_id(&log.path)?
+            .is_some_and(|t| latest_per_target.insert((log.kind.clone(), t)));
+        if is_latest_for_target || !over_limits(idx, log) {
+            stats.kept += 1;
+            continue;
+        }
+        match delete_run_log_if_inactive(&log.path)? {
+            true => stats.deleted += 1,
+            false => {
+                stats.kept += 1;
+                stats.skipped_active += 1;
+            }
+        }
+    }
+    Ok(stats)
+}
+
+fn delete_run_log_if_inactive(path: &Path) -> std::io::Result<bool> {
+    let file = match std::fs::File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(true),
+        Err(e) => return Err(e),
+    };
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => return Ok(false),
+        Err(TryLockError::Error(e)) => return Err(e),
+    }
+    // Remove while holding the lock so a writer can't pick the file up in between.
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(true),
+        Err(e) => Err(e),
+    }
+}
+
+fn truncate_utf8(mut s: String, max_bytes: usize) -> String {
+    if s.len() <= max_bytes {
+        return s;
+    }
+    let mut cut = max_bytes;
+    while !s.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    s.truncate(cut);
+    s.push('…');
+    s
+}
+
 fn resolve_log_dir(data_dir: &Path) -> PathBuf {
     if let Ok(v) = std::env::var("TELEVYBACKUP_LOG_DIR") {
         return PathBuf::from(v);
@@ -240,6 +723,150 @@ mod tests {
         assert_eq!(f3.to_string(), "debug");
     }
 
+    #[test]
+    fn live_run_log_keeps_a_bounded_backlog_and_fans_out_to_subscribers() {
+        let live = LiveRunLog::default();
+        live.push("before any run");
+        assert!(live.subscribe(None, None).is_none());
+
+        live.start("tsk_live");
+        live.push(r#"{"fields":{"event":"run.start","target_id":"t1"}}"#);
+        for i in 0..LIVE_RUN_LOG_BACKLOG_LINES {
+            live.push(&format!("line {i}"));
+        }
+        assert!(live.subscribe(Some("tsk_other"), None).is_none());
+        assert!(live.subscribe(None, Some("t2")).is_none());
+
+        let mut a = live.subscribe(None, Some("t1")).unwrap();
+        let mut b = live.subscribe(Some("tsk_live"), None).unwrap();
+        assert_eq!(a.run_id, "tsk_live");
+        assert_eq!(a.target_id.as_deref(), Some("t1"));
+        assert_eq!(a.backlog.len(), LIVE_RUN_LOG_BACKLOG_LINES);
+        assert_eq!(a.backlog.first().map(String::as_str), Some("line 0"));
+        assert_eq!(b.backlog, a.backlog);
+
+        live.push("after");
+        assert_eq!(a.lines.try_recv().unwrap(), "after");
+        assert_eq!(b.lines.try_recv().unwrap(), "after");
+
+        drop(b);
+        live.finish();
+        assert!(matches!(
+            a.lines.try_recv(),
+            Err(broadcast::error::TryRecvError::Closed)
+        ));
+        assert!(live.subscribe(None, None).is_none());
+    }
+
+    #[test]
+    fn run_log_excerpt_is_bounded_and_redacted() {
+        let temp = tempfile::tempdir().expect("create tempdir");
+        let path = temp
+            .path()
+            .join("sync-backup-20240101T000000Z-tsk_x.ndjson");
+
+        let mut text = String::new();
+        for i in 0..100 {
+            text.push_str(&format!(
+                "{{\"fields\":{{\"message\":\"line {i} token=123:SECRET\"}}}}\n"
+            ));
+        }
+        text.push_str(&format!("{}\n", "x".repeat(10_000)));
+        std::fs::write(&path, text).unwrap();
+
+        let excerpt = read_run_log_excerpt(&path, &["123:SECRET"]).unwrap();
+        assert!(excerpt.len() <= RUN_LOG_EXCERPT_MAX_LINES);
+        assert!(excerpt.iter().map(|l| l.len()).sum::<usize>() <= RUN_LOG_EXCERPT_MAX_BYTES);
+        assert!(excerpt.iter().all(|l| !l.contains("SECRET")));
+        assert!(excerpt[excerpt.len() - 2].contains("line 99 token=[redacted]"));
+        assert!(excerpt.last().unwrap().ends_with('…'));
+    }
+
+    #[test]
+    fn finds_latest_run_log_for_target() {
+        let temp = tempfile::tempdir().expect("create tempdir");
+        let log_dir = temp.path().join("logs");
+        std::fs::create_dir_all(&log_dir).unwrap();
+
+        let start = |target: &str| {
+            format!("{{\"fields\":{{\"event\":\"run.start\",\"target_id\":\"{target}\"}}}}\n")
+        };
+        let write = |name: &str, body: String| std::fs::write(log_dir.join(name), body).unwrap();
+        write("sync-backup-20240101T000000Z-a.ndjson", start("t1"));
+        write("sync-backup-20240102T000000Z-b.ndjson", start("t1"));
+        write("sync-backup-20240103T000000Z-c.ndjson", start("t2"));
+        write("sync-restore-20240104T000000Z-d.ndjson", start("t1"));
+
+        let got = find_latest_run_log_for_target(temp.path(), "backup", "t1")
+            .unwrap()
+            .unwrap();
+        assert!(got.ends_with("sync-backup-20240102T000000Z-b.ndjson"));
+        assert!(
+            find_latest_run_log_for_target(temp.path(), "backup", "t3")
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn prune_keeps_latest_per_target_and_skips_active_logs() {
+        let temp = tempfile::tempdir().expect("create tempdir");
+        let log_dir = temp.path().join("logs");
+        std::fs::create_dir_all(&log_dir).unwrap();
+
+        let start = |target: &str| {
+            format!("{{\"fields\":{{\"event\":\"run.start\",\"target_id\":\"{target}\"}}}}\n")
+        };
+        let write = |name: &str, body: String| std::fs::write(log_dir.join(name), body).unwrap();
+        write("sync-backup-20240101T000000Z-old_t1.ndjson", start("t1"));
+        write("sync-backup-20240102T000000Z-only_t2.ndjson", start("t2"));
+        write("sync-backup-20240103T000000Z-active.ndjson", start("t1"));
+        write("sync-backup-20240110T000000Z-new_t1.ndjson", start("t1"));
+        write("sync-backup-20240111T000000Z-recent_t1.ndjson", start("t1"));
+        write("sync-restore-20240112T000000Z-r.ndjson", String::new());
+        write("unrelated.txt", String::new());
+
+        // Simulate a task that is still writing its log.
+        let active =
+            std::fs::File::open(log_dir.join("sync-backup-20240103T000000Z-active.ndjson"))
+                .unwrap();
+        active.lock().unwrap();
+        assert!(
+            run_log_is_active(&log_dir.join("sync-backup-20240103T000000Z-active.ndjson")).unwrap()
+        );
+
+        let limits = crate::config::Logs {
+            keep_days: 5,
+            keep_max_files: 3,
+            usage_stats: false,
+        };
+        let now = DateTime::parse_from_rfc3339("2024-01-12T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let stats = prune_run_logs_at(temp.path(), &limits, now).unwrap();
+        assert_eq!(
+            stats,
+            RunLogPruneStats {
+                deleted: 1,
+                kept: 5,
+                skipped_active: 1,
+            }
+        );
+
+        let mut left = list_run_logs(temp.path())
+            .unwrap()
+            .into_iter()
+            .map(|l| l.run_id)
+            .collect::<Vec<_>>();
+        left.sort();
+        assert_eq!(left, vec!["active", "new_t1", "only_t2", "r", "recent_t1"]);
+        assert!(log_dir.join("unrelated.txt").exists());
+
+        drop(active);
+        let stats = prune_run_logs_at(temp.path(), &limits, now).unwrap();
+        assert_eq!(stats.deleted, 1);
+    }
+
     #[test]
     fn run_log_is_ndjson_and_flushed_on_drop() {
         let temp = tempfile::tempdir().expect("create tempdir");
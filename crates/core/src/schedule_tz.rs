@@ -0,0 +1,114 @@
+//! `schedule.timezone`: `local` (the machine's time zone) or an IANA tz database name such as
+//! `Europe/Berlin` or `UTC`.
+
+use std::fmt;
+
+use chrono::{FixedOffset, LocalResult, NaiveDate, NaiveDateTime, Offset, TimeZone};
+
+pub const LOCAL_TIMEZONE: &str = "local";
+
+/// The time zone schedule slots are wall-clock times in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduleTz {
+    Local,
+    Named(chrono_tz::Tz),
+}
+
+impl ScheduleTz {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        let name = name.trim();
+        if name.eq_ignore_ascii_case(LOCAL_TIMEZONE) {
+            return Ok(Self::Local);
+        }
+        name.parse::<chrono_tz::Tz>().map(Self::Named).map_err(|_| {
+            format!("unknown time zone {name:?} (use \"local\" or a tz database name like \"Europe/Berlin\")")
+        })
+    }
+
+    /// `local` resolves to the machine's zone name when the OS reports one.
+    pub fn resolved_name(&self) -> String {
+        match self {
+            Self::Local => {
+                iana_time_zone::get_timezone().unwrap_or_else(|_| LOCAL_TIMEZONE.to_string())
+            }
+            Self::Named(tz) => tz.name().to_string(),
+        }
+    }
+
+    fn fixed(&self, offset: FixedOffset) -> ScheduleOffset {
+        ScheduleOffset { tz: *self, offset }
+    }
+}
+
+/// An offset that remembers its zone, so `DateTime<ScheduleTz>` arithmetic stays in that zone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScheduleOffset {
+    tz: ScheduleTz,
+    offset: FixedOffset,
+}
+
+impl Offset for ScheduleOffset {
+    fn fix(&self) -> FixedOffset {
+        self.offset
+    }
+}
+
+impl fmt::Display for ScheduleOffset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.offset, f)
+    }
+}
+
+impl TimeZone for ScheduleTz {
+    type Offset = ScheduleOffset;
+
+    fn from_offset(offset: &ScheduleOffset) -> Self {
+        offset.tz
+    }
+
+    fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<ScheduleOffset> {
+        self.offset_from_local_datetime(&local.and_hms_opt(12, 0, 0).expect("valid time"))
+    }
+
+    fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<ScheduleOffset> {
+        let fixed = match self {
+            Self::Local => chrono::Local.offset_from_local_datetime(local),
+            Self::Named(tz) => tz.offset_from_local_datetime(local).map(|o| o.fix()),
+        };
+        fixed.map(|o| self.fixed(o))
+    }
+
+    fn offset_from_utc_date(&self, utc: &NaiveDate) -> ScheduleOffset {
+        self.offset_from_utc_datetime(&utc.and_hms_opt(0, 0, 0).expect("valid time"))
+    }
+
+    fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> ScheduleOffset {
+        let fixed = match self {
+            Self::Local => chrono::Local.offset_from_utc_datetime(utc),
+            Self::Named(tz) => tz.offset_from_utc_datetime(utc).fix(),
+        };
+        self.fixed(fixed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_parse_and_keep_their_zone_across_dst() {
+        assert_eq!(ScheduleTz::parse(" Local "), Ok(ScheduleTz::Local));
+        assert!(ScheduleTz::parse("Mars/Olympus_Mons").is_err());
+        assert!(ScheduleTz::parse("").is_err());
+
+        let berlin = ScheduleTz::parse("Europe/Berlin").unwrap();
+        assert_eq!(berlin.resolved_name(), "Europe/Berlin");
+        let winter = chrono::Utc
+            .with_ymd_and_hms(2024, 3, 30, 12, 0, 0)
+            .unwrap()
+            .with_timezone(&berlin);
+        let summer = winter + chrono::Duration::days(1);
+        assert_eq!(winter.to_rfc3339(), "2024-03-30T13:00:00+01:00");
+        assert_eq!(summer.to_rfc3339(), "2024-03-31T14:00:00+02:00");
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 
@@ -6,10 +6,21 @@ use base64::Engine;
 use chacha20poly1305::aead::{Aead, KeyInit, Payload};
 use chacha20poly1305::{XChaCha20Poly1305, XNonce};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::SettingsV2;
+use crate::error_code::ErrorCode;
 
 pub const SECRETS_FILE_NAME: &str = "secrets.enc";
 pub const VAULT_KEY_KEY: &str = "televybackup.vault_key";
+pub const MASTER_KEY_KEY: &str = "televybackup.master_key";
+/// Namespace of TelevyBackup's own keys (master key, vault key). Settings never name them, so they
+/// are never orphaned; every other key belongs to whatever setting references it.
+pub const RESERVED_KEY_PREFIX: &str = "televybackup.";
 pub const VAULT_KEY_FILE_NAME: &str = "vault.key";
+/// Per-key plaintext secret files (mode 0600) under the config dir, for headless setups.
+pub const SECRETS_DIR_NAME: &str = "secrets.d";
+pub const SECRET_ENV_PREFIX: &str = "TELEVYBACKUP_SECRET_";
 
 const SECRETS_FILE_VERSION: u8 = 1;
 const SECRETS_PAYLOAD_VERSION: u32 = 1;
@@ -34,6 +45,26 @@ pub enum SecretsStoreError {
 
     #[error("base64 error: {0}")]
     Base64(#[from] base64::DecodeError),
+
+    #[error("secret {key} is provided by {provider}, which is read-only; change or unset it there")]
+    ReadOnlyProvider { key: String, provider: String },
+
+    #[error("secret file {} must not be accessible by group or others (chmod 600)", path.display())]
+    InsecureFile { path: PathBuf },
+}
+
+impl SecretsStoreError {
+    pub fn error_code(&self) -> ErrorCode {
+        match self {
+            Self::ReadOnlyProvider { .. } => ErrorCode::SecretsReadOnlyProvider,
+            Self::InsecureFile { .. } => ErrorCode::SecretsInsecureFile,
+            _ => ErrorCode::SecretsStoreFailed,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.error_code().as_str()
+    }
 }
 
 impl From<getrandom::Error> for SecretsStoreError {
@@ -71,6 +102,328 @@ impl SecretsStore {
     }
 }
 
+/// Where the effective value of a secret comes from, highest precedence first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecretSource {
+    /// `TELEVYBACKUP_SECRET_<KEY>`; never written.
+    Env,
+    /// `<config_dir>/secrets.d/<key>`.
+    File,
+    /// `secrets.enc`, encrypted with the vault key.
+    Store,
+}
+
+impl SecretSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Env => "env",
+            Self::File => "file",
+            Self::Store => "store",
+        }
+    }
+}
+
+/// The secrets of one config dir as the CLI and daemon see them: `TELEVYBACKUP_SECRET_<KEY>`
+/// env vars, then `secrets.d/<key>` files, then `secrets.enc`.
+///
+/// Writes go to `secrets.d/` when that directory exists and to `secrets.enc` otherwise; a key set
+/// by an env var cannot be written, since the env var would keep shadowing the new value.
+#[derive(Debug, Clone)]
+pub struct SecretsProvider {
+    dir: PathBuf,
+    env: BTreeMap<String, String>,
+}
+
+impl SecretsProvider {
+    /// Reads `TELEVYBACKUP_SECRET_*` from the process environment once.
+    pub fn new(config_dir: &Path) -> Self {
+        Self::with_env(
+            config_dir,
+            std::env::vars().filter(|(k, _)| k.starts_with(SECRET_ENV_PREFIX)),
+        )
+    }
+
+    pub fn with_env(config_dir: &Path, env: impl IntoIterator<Item = (String, String)>) -> Self {
+        Self {
+            dir: secrets_dir_path(config_dir),
+            env: env.into_iter().collect(),
+        }
+    }
+
+    /// The value of `key` from an env var or `secrets.d/`, which take precedence over the store.
+    pub fn injected(&self, key: &str) -> Result<Option<(String, SecretSource)>, SecretsStoreError> {
+        if let Some(v) = self.env.get(&secret_env_var_name(key)).map(|v| v.trim())
+            && !v.is_empty()
+        {
+            return Ok(Some((v.to_string(), SecretSource::Env)));
+        }
+        Ok(self.read_file(key)?.map(|v| (v, SecretSource::File)))
+    }
+
+    /// Whether an env var provides `key`.
+    pub fn env_provides(&self, key: &str) -> bool {
+        self.env
+            .get(&secret_env_var_name(key))
+            .is_some_and(|v| !v.trim().is_empty())
+    }
+
+    /// Keys with a file in `secrets.d/`, sorted; temp files of interrupted writes are skipped.
+    pub fn file_keys(&self) -> Result<Vec<String>, SecretsStoreError> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut keys = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            if let Ok(name) = entry.file_name().into_string()
+                && !name.starts_with('.')
+            {
+                keys.push(name);
+            }
+        }
+        keys.sort();
+        Ok(keys)
+    }
+
+    fn read_file(&self, key: &str) -> Result<Option<String>, SecretsStoreError> {
+        let path = self.file_path(key)?;
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            if std::fs::metadata(&path)?.permissions().mode() & 0o077 != 0 {
+                return Err(SecretsStoreError::InsecureFile { path });
+            }
+        }
+        let v = text.trim();
+        Ok((!v.is_empty()).then(|| v.to_string()))
+    }
+
+    /// The effective value of `key`; `store` is the decrypted `secrets.enc`, if loaded.
+    pub fn get(
+        &self,
+        key: &str,
+        store: Option<&SecretsStore>,
+    ) -> Result<Option<(String, SecretSource)>, SecretsStoreError> {
+        if let Some(found) = self.injected(key)? {
+            return Ok(Some(found));
+        }
+        Ok(store
+            .and_then(|s| s.get(key))
+            .map(|v| (v.to_string(), SecretSource::Store)))
+    }
+
+    /// Where a write of `key` must go, or [`SecretsStoreError::ReadOnlyProvider`] when an env var
+    /// provides it.
+    pub fn write_target(&self, key: &str) -> Result<SecretSource, SecretsStoreError> {
+        if self.env_provides(key) {
+            return Err(SecretsStoreError::ReadOnlyProvider {
+                key: key.to_string(),
+                provider: format!("env var {}", secret_env_var_name(key)),
+            });
+        }
+        Ok(if self.dir.is_dir() {
+            SecretSource::File
+        } else {
+            SecretSource::Store
+        })
+    }
+
+    /// Writes `key` where [`Self::write_target`] says: `secrets.d/<key>`, or `store`, which is then
+    /// saved to `secrets_path`. Returns whether the value changed.
+    pub fn write(
+        &self,
+        key: &str,
+        value: &str,
+        store: &mut SecretsStore,
+        secrets_path: &Path,
+        vault_key: &[u8; 32],
+    ) -> Result<bool, SecretsStoreError> {
+        if let Some(changed) = self.write_if_file_target(key, value)? {
+            return Ok(changed);
+        }
+        let changed = store.get(key) != Some(value);
+        store.set(key, value);
+        save_secrets_store(secrets_path, vault_key, store)?;
+        Ok(changed)
+    }
+
+    /// The `secrets.d/` half of [`Self::write`], for callers that only open the store when the
+    /// write goes there: `None` when it does, otherwise whether the value changed.
+    pub fn write_if_file_target(
+        &self,
+        key: &str,
+        value: &str,
+    ) -> Result<Option<bool>, SecretsStoreError> {
+        if self.write_target(key)? != SecretSource::File {
+            return Ok(None);
+        }
+        let changed = self.read_file(key).ok().flatten().as_deref() != Some(value);
+        self.write_file(key, value)?;
+        Ok(Some(changed))
+    }
+
+    /// Writes `secrets.d/<key>` (mode 0600).
+    pub fn write_file(&self, key: &str, value: &str) -> Result<(), SecretsStoreError> {
+        let path = self.file_path(key)?;
+        let tmp = self.dir.join(format!(".{key}.tmp"));
+        write_private_via(&tmp, &path, format!("{value}\n").as_bytes()).map_err(|e| {
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                SecretsStoreError::ReadOnlyProvider {
+                    key: key.to_string(),
+                    provider: format!("{} (not writable)", self.dir.display()),
+                }
+            } else {
+                e.into()
+            }
+        })
+    }
+
+    /// Removes `secrets.d/<key>`; true when it existed.
+    pub fn remove_file(&self, key: &str) -> Result<bool, SecretsStoreError> {
+        match std::fs::remove_file(self.file_path(key)?) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn file_path(&self, key: &str) -> Result<PathBuf, SecretsStoreError> {
+        if key.is_empty() || key.starts_with('.') || key.contains(['/', '\\']) {
+            return Err(SecretsStoreError::InvalidFormat {
+                message: format!("secret key {key:?} cannot name a file in {SECRETS_DIR_NAME}"),
+            });
+        }
+        Ok(self.dir.join(key))
+    }
+}
+
+/// `TELEVYBACKUP_SECRET_` plus `key` upper-cased, with every character other than ASCII letters
+/// and digits (dots included) replaced by `_`: `telegram.bot_token.ep1` becomes
+/// `TELEVYBACKUP_SECRET_TELEGRAM_BOT_TOKEN_EP1`.
+pub fn secret_env_var_name(key: &str) -> String {
+    let mangled: String = key
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{SECRET_ENV_PREFIX}{mangled}")
+}
+
+/// Keys something reads under `settings`: the master key, the global and per-endpoint MTProto
+/// `api_hash` keys, and each endpoint's bot token and session keys.
+pub fn referenced_secret_keys(settings: &SettingsV2) -> BTreeSet<String> {
+    let mut keys = BTreeSet::from([
+        MASTER_KEY_KEY.to_string(),
+        settings.telegram.mtproto.api_hash_key.clone(),
+    ]);
+    for ep in &settings.telegram_endpoints {
+        keys.insert(ep.bot_token_key.clone());
+        keys.insert(ep.mtproto.session_key.clone());
+        keys.extend(ep.mtproto.api_hash_key.clone());
+    }
+    keys.retain(|k| !k.is_empty());
+    keys
+}
+
+/// A key nothing reads any more: outside [`RESERVED_KEY_PREFIX`] and not referenced by the
+/// settings (e.g. the bot token of a removed or renamed endpoint).
+pub fn is_orphaned_secret_key(key: &str, referenced: &BTreeSet<String>) -> bool {
+    !key.starts_with(RESERVED_KEY_PREFIX) && !referenced.contains(key)
+}
+
+/// One stored secret as `secrets list` shows it; never the value.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SecretKeyInfo {
+    pub key: String,
+    /// `env`, `file` (`secrets.d/`) or `store` (`secrets.enc`); a key kept in several is listed
+    /// once per source.
+    pub source: String,
+    pub value_len: usize,
+    /// First 12 hex digits of the value's SHA-256, to tell values apart without showing them.
+    pub fingerprint: String,
+    pub orphaned: bool,
+}
+
+fn secret_fingerprint(value: &str) -> String {
+    let digest = Sha256::digest(value.as_bytes());
+    digest[..6].iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Every key in `secrets.d/` and `store`, plus the referenced keys an env var provides (other
+/// `TELEVYBACKUP_SECRET_*` vars can't be mapped back to a key name). Sorted by key.
+pub fn list_secret_keys(
+    provider: &SecretsProvider,
+    store: &SecretsStore,
+    settings: &SettingsV2,
+) -> Result<Vec<SecretKeyInfo>, SecretsStoreError> {
+    let referenced = referenced_secret_keys(settings);
+    let info = |key: &str, source: SecretSource, value: &str| SecretKeyInfo {
+        key: key.to_string(),
+        source: source.as_str().to_string(),
+        value_len: value.len(),
+        fingerprint: secret_fingerprint(value),
+        orphaned: is_orphaned_secret_key(key, &referenced),
+    };
+
+    let mut out = Vec::new();
+    for key in referenced.iter().filter(|k| provider.env_provides(k)) {
+        if let Some((value, SecretSource::Env)) = provider.injected(key)? {
+            out.push(info(key, SecretSource::Env, &value));
+        }
+    }
+    for key in provider.file_keys()? {
+        if let Some(value) = provider.read_file(&key)? {
+            out.push(info(&key, SecretSource::File, &value));
+        }
+    }
+    for key in store.keys() {
+        out.push(info(
+            key,
+            SecretSource::Store,
+            store.get(key).unwrap_or_default(),
+        ));
+    }
+    out.sort_by(|a, b| a.key.cmp(&b.key));
+    Ok(out)
+}
+
+/// Removes `keys` from `secrets.d/` and from `store`, which the caller saves when anything was
+/// removed; returns the keys that were found. Nothing is removed when an env var provides one of
+/// the keys ([`SecretsStoreError::ReadOnlyProvider`]), since it would stay in effect.
+pub fn delete_secret_keys(
+    provider: &SecretsProvider,
+    store: &mut SecretsStore,
+    keys: &[String],
+) -> Result<Vec<String>, SecretsStoreError> {
+    for key in keys {
+        provider.write_target(key)?;
+    }
+    let mut deleted = Vec::new();
+    for key in keys {
+        let removed_file = provider.remove_file(key)?;
+        if store.remove(key) || removed_file {
+            deleted.push(key.clone());
+        }
+    }
+    Ok(deleted)
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct SecretsPayloadV1 {
     version: u32,
@@ -94,6 +447,10 @@ pub fn secrets_path(config_dir: &Path) -> PathBuf {
     config_dir.join(SECRETS_FILE_NAME)
 }
 
+pub fn secrets_dir_path(config_dir: &Path) -> PathBuf {
+    config_dir.join(SECRETS_DIR_NAME)
+}
+
 pub fn vault_key_file_path(config_dir: &Path) -> PathBuf {
     config_dir.join(VAULT_KEY_FILE_NAME)
 }
@@ -216,13 +573,15 @@ fn encrypt_secrets_store_bytes(
     Ok(out)
 }
 
-fn write_atomic_private(path: &Path, bytes: &[u8]) -> Result<(), std::io::Error> {
+pub(crate) fn write_atomic_private(path: &Path, bytes: &[u8]) -> Result<(), std::io::Error> {
     if let Some(parent) = path.parent() {
         std::fs::create_dir_all(parent)?;
     }
 
-    let tmp = path.with_extension("tmp");
+    write_private_via(&path.with_extension("tmp"), path, bytes)
+}
 
+fn write_private_via(tmp: &Path, path: &Path, bytes: &[u8]) -> Result<(), std::io::Error> {
     #[cfg(unix)]
     {
         use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
@@ -231,18 +590,18 @@ fn write_atomic_private(path: &Path, bytes: &[u8]) -> Result<(), std::io::Error>
             .write(true)
             .truncate(true)
             .mode(0o600)
-            .open(&tmp)?;
+            .open(tmp)?;
         f.write_all(bytes)?;
         f.sync_all()?;
-        std::fs::rename(&tmp, path)?;
+        std::fs::rename(tmp, path)?;
         std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
         Ok(())
     }
 
     #[cfg(not(unix))]
     {
-        std::fs::write(&tmp, bytes)?;
-        std::fs::rename(&tmp, path)?;
+        std::fs::write(tmp, bytes)?;
+        std::fs::rename(tmp, path)?;
         Ok(())
     }
 }
@@ -398,4 +757,210 @@ mod tests {
         let err = write_vault_key_file_private(&path, &key).unwrap_err();
         assert!(matches!(err, SecretsStoreError::Io(_)));
     }
+
+    #[test]
+    fn secret_env_var_name_mangles_dots_and_case() {
+        assert_eq!(
+            secret_env_var_name("telegram.bot_token.ep-1"),
+            "TELEVYBACKUP_SECRET_TELEGRAM_BOT_TOKEN_EP_1"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn secrets_provider_prefers_env_then_file_then_store() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let key = "telegram.bot_token.ep1";
+        let mut store = SecretsStore::default();
+        store.set(key, "from-store");
+
+        let provider = SecretsProvider::with_env(dir.path(), []);
+        assert_eq!(
+            provider.get(key, Some(&store)).unwrap(),
+            Some(("from-store".to_string(), SecretSource::Store))
+        );
+        assert_eq!(provider.write_target(key).unwrap(), SecretSource::Store);
+
+        std::fs::create_dir(secrets_dir_path(dir.path())).unwrap();
+        assert_eq!(provider.write_target(key).unwrap(), SecretSource::File);
+        provider.write_file(key, "from-file").unwrap();
+        let path = secrets_dir_path(dir.path()).join(key);
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        assert_eq!(
+            provider.get(key, Some(&store)).unwrap(),
+            Some(("from-file".to_string(), SecretSource::File))
+        );
+
+        let provider = SecretsProvider::with_env(
+            dir.path(),
+            [(secret_env_var_name(key), "from-env\n".to_string())],
+        );
+        assert_eq!(
+            provider.get(key, Some(&store)).unwrap(),
+            Some(("from-env".to_string(), SecretSource::Env))
+        );
+        let err = provider.write_target(key).unwrap_err();
+        assert_eq!(err.code(), "secrets.read_only_provider");
+
+        let provider = SecretsProvider::with_env(dir.path(), []);
+        assert!(provider.remove_file(key).unwrap());
+        assert_eq!(
+            provider.get(key, Some(&store)).unwrap(),
+            Some(("from-store".to_string(), SecretSource::Store))
+        );
+    }
+
+    #[test]
+    fn write_goes_to_the_write_target_and_reports_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = "telegram.bot_token.ep1";
+        let vault_key = [3u8; 32];
+        let path = secrets_path(dir.path());
+        let mut store = SecretsStore::default();
+        let provider = SecretsProvider::with_env(dir.path(), []);
+
+        assert!(
+            provider
+                .write(key, "a", &mut store, &path, &vault_key)
+                .unwrap()
+        );
+        assert!(
+            !provider
+                .write(key, "a", &mut store, &path, &vault_key)
+                .unwrap()
+        );
+        let saved = load_secrets_store(&path, &vault_key).unwrap();
+        assert_eq!(saved.get(key), Some("a"));
+
+        std::fs::create_dir(secrets_dir_path(dir.path())).unwrap();
+        assert!(
+            provider
+                .write(key, "b", &mut store, &path, &vault_key)
+                .unwrap()
+        );
+        assert!(
+            !provider
+                .write(key, "b", &mut store, &path, &vault_key)
+                .unwrap()
+        );
+        assert_eq!(
+            provider.get(key, Some(&store)).unwrap(),
+            Some(("b".to_string(), SecretSource::File))
+        );
+        // The store was left alone.
+        assert_eq!(store.get(key), Some("a"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn list_flags_orphaned_keys_and_delete_removes_every_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = crate::config::parse_settings_v2(
+            r#"
+version = 2
+
+[[telegram_endpoints]]
+id = "e1"
+mode = "mtproto"
+chat_id = "-100123"
+bot_token_key = "telegram.bot_token.e1"
+
+[telegram_endpoints.mtproto]
+session_key = "telegram.mtproto.session.e1"
+"#,
+        )
+        .unwrap();
+        let mut store = SecretsStore::default();
+        store.set(MASTER_KEY_KEY, "master");
+        store.set("telegram.bot_token.e1", "123:abc");
+        store.set("telegram.bot_token.old", "456:def");
+
+        std::fs::create_dir(secrets_dir_path(dir.path())).unwrap();
+        let provider = SecretsProvider::with_env(
+            dir.path(),
+            [(
+                secret_env_var_name("telegram.mtproto.api_hash"),
+                "hash".to_string(),
+            )],
+        );
+        provider
+            .write_file("telegram.mtproto.session.old", "sess")
+            .unwrap();
+        provider
+            .write_file("telegram.bot_token.old", "789")
+            .unwrap();
+
+        let listed = list_secret_keys(&provider, &store, &settings).unwrap();
+        let rows = listed
+            .iter()
+            .map(|i| (i.key.as_str(), i.source.as_str(), i.value_len, i.orphaned))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rows,
+            vec![
+                ("telegram.bot_token.e1", "store", 7, false),
+                ("telegram.bot_token.old", "file", 3, true),
+                ("telegram.bot_token.old", "store", 7, true),
+                ("telegram.mtproto.api_hash", "env", 4, false),
+                ("telegram.mtproto.session.old", "file", 4, true),
+                (MASTER_KEY_KEY, "store", 6, false),
+            ]
+        );
+        assert_eq!(listed[0].fingerprint.len(), 12);
+        assert_ne!(listed[1].fingerprint, listed[2].fingerprint);
+        assert!(
+            listed
+                .iter()
+                .all(|i| !i.fingerprint.contains("123") && i.fingerprint != "master")
+        );
+
+        let keys = |ks: &[&str]| ks.iter().map(|k| k.to_string()).collect::<Vec<_>>();
+        let err = delete_secret_keys(
+            &provider,
+            &mut store,
+            &keys(&["telegram.bot_token.old", "telegram.mtproto.api_hash"]),
+        )
+        .unwrap_err();
+        assert_eq!(err.code(), "secrets.read_only_provider");
+        assert!(store.contains_key("telegram.bot_token.old"));
+
+        let old = keys(&["telegram.bot_token.old", "telegram.bot_token.gone"]);
+        assert_eq!(
+            delete_secret_keys(&provider, &mut store, &old).unwrap(),
+            keys(&["telegram.bot_token.old"])
+        );
+        assert!(
+            delete_secret_keys(&provider, &mut store, &old)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(!store.contains_key("telegram.bot_token.old"));
+        assert_eq!(
+            provider.file_keys().unwrap(),
+            keys(&["telegram.mtproto.session.old"])
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn secrets_provider_rejects_group_readable_files() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let secrets_dir = secrets_dir_path(dir.path());
+        std::fs::create_dir(&secrets_dir).unwrap();
+        let path = secrets_dir.join("telegram.mtproto.api_hash");
+        std::fs::write(&path, "hash\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+
+        let provider = SecretsProvider::with_env(dir.path(), []);
+        let err = provider.injected("telegram.mtproto.api_hash").unwrap_err();
+        assert_eq!(err.code(), "secrets.insecure_file");
+        assert!(provider.injected("../vault.key").is_err());
+    }
 }
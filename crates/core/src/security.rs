@@ -0,0 +1,134 @@
+use std::time::{Duration, Instant};
+
+use argon2::Argon2;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+
+use crate::{Error, Result};
+
+/// Failed attempts allowed before [`PassphraseAttempts`] starts delaying retries.
+pub const PASSPHRASE_FREE_FAILURES: u32 = 3;
+const PASSPHRASE_BASE_DELAY: Duration = Duration::from_secs(1);
+const PASSPHRASE_MAX_DELAY: Duration = Duration::from_secs(5 * 60);
+
+/// Hashes a restore passphrase into an argon2id PHC string (`security.restore_passphrase_hash`).
+pub fn hash_restore_passphrase(passphrase: &str) -> Result<String> {
+    if passphrase.is_empty() {
+        return Err(Error::InvalidConfig {
+            message: "restore passphrase is empty".to_string(),
+        });
+    }
+
+    let mut salt = [0u8; 16];
+    getrandom::getrandom(&mut salt).map_err(|e| Error::Crypto {
+        message: format!("getrandom failed: {e}"),
+    })?;
+    let salt = SaltString::encode_b64(&salt).map_err(|e| Error::Crypto {
+        message: format!("argon2 salt encode failed: {e}"),
+    })?;
+    let hash = Argon2::default()
+        .hash_password(passphrase.as_bytes(), &salt)
+        .map_err(|e| Error::Crypto {
+            message: format!("argon2 hash failed: {e}"),
+        })?;
+    Ok(hash.to_string())
+}
+
+/// Checks `passphrase` against a hash from [`hash_restore_passphrase`].
+///
+/// Returns `Ok(false)` for a wrong passphrase and an error only when `hash` itself is unusable.
+pub fn verify_restore_passphrase(hash: &str, passphrase: &str) -> Result<bool> {
+    let parsed = PasswordHash::new(hash).map_err(|e| Error::InvalidConfig {
+        message: format!("invalid security.restore_passphrase_hash: {e}"),
+    })?;
+    match Argon2::default().verify_password(passphrase.as_bytes(), &parsed) {
+        Ok(()) => Ok(true),
+        Err(argon2::password_hash::Error::Password) => Ok(false),
+        Err(e) => Err(Error::Crypto {
+            message: format!("argon2 verify failed: {e}"),
+        }),
+    }
+}
+
+/// Failed passphrase attempts and the resulting lockout.
+///
+/// The first [`PASSPHRASE_FREE_FAILURES`] failures are free; each further failure doubles the
+/// delay before the next attempt is considered (1s, 2s, 4s, ... capped at 5 minutes).
+#[derive(Debug, Default)]
+pub struct PassphraseAttempts {
+    failures: u32,
+    locked_until: Option<Instant>,
+}
+
+impl PassphraseAttempts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Remaining lockout at `now`, if any.
+    pub fn retry_after(&self, now: Instant) -> Option<Duration> {
+        self.locked_until
+            .and_then(|until| until.checked_duration_since(now))
+            .filter(|d| !d.is_zero())
+    }
+
+    /// Records a wrong passphrase; returns the lockout it started, if any.
+    pub fn record_failure(&mut self, now: Instant) -> Option<Duration> {
+        self.failures = self.failures.saturating_add(1);
+        if self.failures < PASSPHRASE_FREE_FAILURES {
+            return None;
+        }
+        let exp = (self.failures - PASSPHRASE_FREE_FAILURES).min(16);
+        let delay = PASSPHRASE_BASE_DELAY
+            .saturating_mul(1u32 << exp)
+            .min(PASSPHRASE_MAX_DELAY);
+        self.locked_until = Some(now + delay);
+        Some(delay)
+    }
+
+    pub fn record_success(&mut self) {
+        self.failures = 0;
+        self.locked_until = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restore_passphrase_hash_round_trip() {
+        let hash = hash_restore_passphrase("correct horse").unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_restore_passphrase(&hash, "correct horse").unwrap());
+        assert!(!verify_restore_passphrase(&hash, "battery staple").unwrap());
+        assert!(verify_restore_passphrase("not-a-hash", "x").is_err());
+    }
+
+    #[test]
+    fn passphrase_attempts_back_off_exponentially_after_free_failures() {
+        let now = Instant::now();
+        let mut attempts = PassphraseAttempts::new();
+        assert_eq!(attempts.record_failure(now), None);
+        assert_eq!(attempts.record_failure(now), None);
+        assert_eq!(attempts.retry_after(now), None);
+
+        assert_eq!(attempts.record_failure(now), Some(Duration::from_secs(1)));
+        assert_eq!(attempts.retry_after(now), Some(Duration::from_secs(1)));
+        assert_eq!(attempts.record_failure(now), Some(Duration::from_secs(2)));
+        assert_eq!(attempts.record_failure(now), Some(Duration::from_secs(4)));
+        assert_eq!(attempts.retry_after(now + Duration::from_secs(5)), None);
+
+        for _ in 0..20 {
+            attempts.record_failure(now);
+        }
+        assert_eq!(attempts.retry_after(now), Some(PASSPHRASE_MAX_DELAY));
+
+        attempts.record_success();
+        assert_eq!(attempts.failures(), 0);
+        assert_eq!(attempts.retry_after(now), None);
+    }
+}
@@ -0,0 +1,694 @@
+//! Decoded file/chunk listing of one snapshot (`televybackup index export` / `index import`).
+//!
+//! The listing is the plaintext content of a snapshot's file map: every path with its metadata
+//! and chunk layout, plus the chunks it references. It is a stable JSON document for external
+//! tooling; [`SNAPSHOT_LISTING_FIELDS`] describes it and `index schema` prints that description.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+
+use crate::config::SettingsField;
+use crate::config::SettingsFieldType::{Integer, String as Str};
+use crate::index_db::{
+    files_optional_columns, open_existing_index_db, open_index_db, snapshots_have_device_columns,
+    snapshots_have_hash_alg_column,
+};
+use crate::{ChunkHashAlg, Error, Result};
+
+/// `version` written by this build; `index import` rejects other versions.
+pub const SNAPSHOT_LISTING_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotListing {
+    pub version: u32,
+    pub snapshot_id: String,
+    pub created_at: String,
+    pub source_path: String,
+    pub label: String,
+    pub base_snapshot_id: Option<String>,
+    pub device_id: Option<String>,
+    pub device_name: Option<String>,
+    /// How chunk ids were computed; absent from listings of older versions (plain blake3).
+    #[serde(default = "default_listing_hash_alg")]
+    pub hash_alg: String,
+    pub files: Vec<ListedFile>,
+    pub chunks: Vec<ListedChunk>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListedFile {
+    pub path: String,
+    pub kind: String,
+    pub size: i64,
+    pub mtime_ms: i64,
+    pub mode: i64,
+    pub btime_ms: Option<i64>,
+    pub uid: Option<i64>,
+    pub gid: Option<i64>,
+    pub owner_name: Option<String>,
+    pub group_name: Option<String>,
+    /// `"sqlite_backup"` when the chunks came from a consistent copy of a SQLite database
+    /// (see [`crate::sqlite_consistent`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_source: Option<String>,
+    pub chunks: Vec<ListedFileChunk>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListedFileChunk {
+    pub hash: String,
+    pub offset: i64,
+    pub len: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListedChunk {
+    pub hash: String,
+    pub size: i64,
+    pub hash_alg: String,
+    pub enc_alg: String,
+    pub objects: Vec<ListedChunkObject>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListedChunkObject {
+    pub provider: String,
+    pub object_id: String,
+}
+
+fn default_listing_hash_alg() -> String {
+    ChunkHashAlg::Blake3.as_str().to_string()
+}
+
+const fn listed(
+    path: &'static str,
+    ty: crate::config::SettingsFieldType,
+    required: bool,
+    description: &'static str,
+) -> SettingsField {
+    SettingsField {
+        path,
+        ty,
+        required,
+        description,
+        constraints: None,
+    }
+}
+
+/// Every field of [`SnapshotListing`]; `required: false` marks fields that may be `null`.
+pub const SNAPSHOT_LISTING_FIELDS: &[SettingsField] = &[
+    listed("version", Integer, true, "Listing format version (1)."),
+    listed("snapshotId", Str, true, "Snapshot ID."),
+    listed(
+        "createdAt",
+        Str,
+        true,
+        "Snapshot creation time (RFC3339, UTC).",
+    ),
+    listed("sourcePath", Str, true, "Folder the snapshot was taken of."),
+    listed(
+        "label",
+        Str,
+        true,
+        "Snapshot label (e.g. `manual`, `scheduled`).",
+    ),
+    listed(
+        "baseSnapshotId",
+        Str,
+        false,
+        "Previous snapshot of the same target.",
+    ),
+    listed("deviceId", Str, false, "Machine that took the snapshot."),
+    listed("deviceName", Str, false, "Name of that machine."),
+    listed(
+        "hashAlg",
+        Str,
+        true,
+        "How chunk ids were computed (`blake3` or `blake3-keyed`).",
+    ),
+    listed(
+        "files[].path",
+        Str,
+        true,
+        "Path relative to `sourcePath`, `/`-separated.",
+    ),
+    listed("files[].kind", Str, true, "`file`, `dir` or `symlink`."),
+    listed("files[].size", Integer, true, "Size in bytes."),
+    listed(
+        "files[].mtimeMs",
+        Integer,
+        true,
+        "Modification time, Unix milliseconds.",
+    ),
+    listed("files[].mode", Integer, true, "Unix permission bits."),
+    listed(
+        "files[].btimeMs",
+        Integer,
+        false,
+        "Creation time, Unix milliseconds.",
+    ),
+    listed("files[].uid", Integer, false, "Owner user ID."),
+    listed("files[].gid", Integer, false, "Owner group ID."),
+    listed(
+        "files[].ownerName",
+        Str,
+        false,
+        "Owner user name on the backed-up machine.",
+    ),
+    listed(
+        "files[].groupName",
+        Str,
+        false,
+        "Owner group name on the backed-up machine.",
+    ),
+    listed(
+        "files[].chunks[].hash",
+        Str,
+        true,
+        "Chunk hash; one of `chunks[].hash`.",
+    ),
+    listed(
+        "files[].chunks[].offset",
+        Integer,
+        true,
+        "Byte offset of the chunk in the file.",
+    ),
+    listed(
+        "files[].chunks[].len",
+        Integer,
+        true,
+        "Chunk length in bytes.",
+    ),
+    listed("chunks[].hash", Str, true, "Chunk hash (hex)."),
+    listed(
+        "chunks[].size",
+        Integer,
+        true,
+        "Plaintext chunk size in bytes.",
+    ),
+    listed(
+        "chunks[].hashAlg",
+        Str,
+        true,
+        "Hash algorithm (`blake3` or `blake3-keyed`).",
+    ),
+    listed(
+        "chunks[].encAlg",
+        Str,
+        true,
+        "Encryption algorithm (`xchacha20poly1305`).",
+    ),
+    listed(
+        "chunks[].objects[].provider",
+        Str,
+        true,
+        "Storage provider holding the chunk.",
+    ),
+    listed(
+        "chunks[].objects[].objectId",
+        Str,
+        true,
+        "Object ID of the chunk at that provider.",
+    ),
+];
+
+/// `index schema --json` output.
+pub fn snapshot_listing_schema_json() -> serde_json::Value {
+    serde_json::json!({ "version": SNAPSHOT_LISTING_VERSION, "fields": SNAPSHOT_LISTING_FIELDS })
+}
+
+/// Reads `snapshot_id` from a file map DB. Chunk objects come from `endpoint_db_path` when given;
+/// chunks it does not know have no `objects`.
+pub async fn read_snapshot_listing(
+    filemap_db_path: &Path,
+    endpoint_db_path: Option<&Path>,
+    snapshot_id: &str,
+) -> Result<SnapshotListing> {
+    let pool = open_existing_index_db(filemap_db_path).await?;
+    let res = read_listing(&pool, snapshot_id).await;
+    pool.close().await;
+    let mut listing = res?;
+
+    if let Some(endpoint_db_path) = endpoint_db_path.filter(|p| p.exists()) {
+        let pool = open_existing_index_db(endpoint_db_path).await?;
+        let res = chunk_objects(&pool, &listing.chunks).await;
+        pool.close().await;
+        let mut objects = res?;
+        for chunk in &mut listing.chunks {
+            chunk.objects = objects.remove(&chunk.hash).unwrap_or_default();
+        }
+    }
+    Ok(listing)
+}
+
+async fn read_listing(pool: &SqlitePool, snapshot_id: &str) -> Result<SnapshotListing> {
+    let device_cols = if snapshots_have_device_columns(pool).await? {
+        "device_id, device_name"
+    } else {
+        "NULL AS device_id, NULL AS device_name"
+    };
+    let hash_alg_col = if snapshots_have_hash_alg_column(pool, "main").await? {
+        "hash_alg"
+    } else {
+        "'blake3' AS hash_alg"
+    };
+    let snapshot = sqlx::query(&format!(
+        "SELECT created_at, source_path, label, base_snapshot_id, {device_cols}, {hash_alg_col} FROM snapshots WHERE snapshot_id = ?"
+    ))
+    .bind(snapshot_id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| Error::InvalidConfig {
+        message: format!("snapshot not found: {snapshot_id}"),
+    })?;
+
+    let mut layout: HashMap<String, Vec<ListedFileChunk>> = HashMap::new();
+    let mut rows = sqlx::query(
+        r#"
+        SELECT fc.file_id, fc.chunk_hash, fc.offset, fc.len
+        FROM file_chunks fc
+        JOIN files f ON f.file_id = fc.file_id
+        WHERE f.snapshot_id = ?
+        ORDER BY fc.file_id, fc.seq
+        "#,
+    )
+    .bind(snapshot_id)
+    .fetch(pool);
+    while let Some(row) = rows.try_next().await? {
+        layout
+            .entry(row.get("file_id"))
+            .or_default()
+            .push(ListedFileChunk {
+                hash: row.get("chunk_hash"),
+                offset: row.get("offset"),
+                len: row.get("len"),
+            });
+    }
+    drop(rows);
+
+    let optional_cols = files_optional_columns(pool, "main").await?;
+    let files = sqlx::query(&format!(
+        "SELECT file_id, path, kind, size, mtime_ms, mode, {optional_cols} FROM files WHERE snapshot_id = ? ORDER BY path"
+    ))
+    .bind(snapshot_id)
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| ListedFile {
+        chunks: layout
+            .remove(&row.get::<String, _>("file_id"))
+            .unwrap_or_default(),
+        path: row.get("path"),
+        kind: row.get("kind"),
+        size: row.get("size"),
+        mtime_ms: row.get("mtime_ms"),
+        mode: row.get("mode"),
+        btime_ms: row.get("btime_ms"),
+        uid: row.get("uid"),
+        gid: row.get("gid"),
+        owner_name: row.get("owner_name"),
+        group_name: row.get("group_name"),
+        content_source: row.get("content_source"),
+    })
+    .collect();
+
+    let chunks = sqlx::query(
+        r#"
+        SELECT c.chunk_hash, c.size, c.hash_alg, c.enc_alg
+        FROM chunks c
+        WHERE c.chunk_hash IN (
+          SELECT fc.chunk_hash
+          FROM file_chunks fc
+          JOIN files f ON f.file_id = fc.file_id
+          WHERE f.snapshot_id = ?
+        )
+        ORDER BY c.chunk_hash
+        "#,
+    )
+    .bind(snapshot_id)
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| ListedChunk {
+        hash: row.get("chunk_hash"),
+        size: row.get("size"),
+        hash_alg: row.get("hash_alg"),
+        enc_alg: row.get("enc_alg"),
+        objects: Vec::new(),
+    })
+    .collect();
+
+    Ok(SnapshotListing {
+        version: SNAPSHOT_LISTING_VERSION,
+        snapshot_id: snapshot_id.to_string(),
+        created_at: snapshot.get("created_at"),
+        source_path: snapshot.get("source_path"),
+        label: snapshot.get("label"),
+        base_snapshot_id: snapshot.get("base_snapshot_id"),
+        device_id: snapshot.get("device_id"),
+        device_name: snapshot.get("device_name"),
+        hash_alg: snapshot.get("hash_alg"),
+        files,
+        chunks,
+    })
+}
+
+/// `chunk_objects` has no index on `chunk_hash` alone, so scan it once instead of per chunk.
+async fn chunk_objects(
+    pool: &SqlitePool,
+    chunks: &[ListedChunk],
+) -> Result<HashMap<String, Vec<ListedChunkObject>>> {
+    let wanted = chunks
+        .iter()
+        .map(|c| c.hash.as_str())
+        .collect::<BTreeSet<_>>();
+    let mut out: HashMap<String, Vec<ListedChunkObject>> = HashMap::new();
+    let mut rows = sqlx::query(
+        "SELECT chunk_hash, provider, object_id FROM chunk_objects ORDER BY chunk_hash, provider",
+    )
+    .fetch(pool);
+    while let Some(row) = rows.try_next().await? {
+        let hash: String = row.get("chunk_hash");
+        if wanted.contains(hash.as_str()) {
+            out.entry(hash).or_default().push(ListedChunkObject {
+                provider: row.get("provider"),
+                object_id: row.get("object_id"),
+            });
+        }
+    }
+    Ok(out)
+}
+
+/// Writes a listing as a new file map DB at `filemap_db_path` and adds its snapshot, chunks and
+/// chunk objects to the endpoint DB. Fails when either already knows the snapshot.
+pub async fn import_snapshot_listing(
+    endpoint_db_path: &Path,
+    filemap_db_path: &Path,
+    listing: &SnapshotListing,
+) -> Result<()> {
+    check_listing(listing)?;
+    let snapshot_id = listing.snapshot_id.as_str();
+    if filemap_db_path.exists() {
+        return Err(Error::InvalidConfig {
+            message: format!(
+                "snapshot {snapshot_id} already has a local file map: {}",
+                filemap_db_path.display()
+            ),
+        });
+    }
+
+    let endpoint_pool = open_index_db(endpoint_db_path).await?;
+    let known = sqlx::query("SELECT 1 FROM snapshots WHERE snapshot_id = ?")
+        .bind(snapshot_id)
+        .fetch_optional(&endpoint_pool)
+        .await;
+    if !matches!(known, Ok(None)) {
+        endpoint_pool.close().await;
+        known?;
+        return Err(Error::InvalidConfig {
+            message: format!("snapshot {snapshot_id} is already in the local index"),
+        });
+    }
+
+    let parent = filemap_db_path.parent().unwrap_or(Path::new("."));
+    std::fs::create_dir_all(parent)?;
+    let staged = tempfile::Builder::new()
+        .prefix("televy-index-import-")
+        .suffix(".sqlite")
+        .tempfile_in(parent)?;
+    let filemap_pool = open_index_db(staged.path()).await?;
+    let res = write_filemap(&filemap_pool, listing).await;
+    filemap_pool.close().await;
+    if let Err(e) = res {
+        endpoint_pool.close().await;
+        return Err(e);
+    }
+
+    let res = write_endpoint_rows(&endpoint_pool, listing).await;
+    endpoint_pool.close().await;
+    res?;
+    staged
+        .persist(filemap_db_path)
+        .map_err(|e| Error::Io(e.error))?;
+    Ok(())
+}
+
+fn check_listing(listing: &SnapshotListing) -> Result<()> {
+    if listing.version != SNAPSHOT_LISTING_VERSION {
+        return Err(Error::InvalidConfig {
+            message: format!(
+                "unsupported snapshot listing version: {} (expected {SNAPSHOT_LISTING_VERSION})",
+                listing.version
+            ),
+        });
+    }
+    if ChunkHashAlg::parse(&listing.hash_alg).is_err() {
+        return Err(Error::InvalidConfig {
+            message: format!("unsupported snapshot listing hashAlg: {}", listing.hash_alg),
+        });
+    }
+    let known = listing
+        .chunks
+        .iter()
+        .map(|c| c.hash.as_str())
+        .collect::<BTreeSet<_>>();
+    for file in &listing.files {
+        if let Some(c) = file
+            .chunks
+            .iter()
+            .find(|c| !known.contains(c.hash.as_str()))
+        {
+            return Err(Error::InvalidConfig {
+                message: format!(
+                    "snapshot listing file {} references chunk {} missing from chunks[]",
+                    file.path, c.hash
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+async fn insert_snapshot(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    listing: &SnapshotListing,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO snapshots (snapshot_id, created_at, source_path, label, base_snapshot_id, device_id, device_name, hash_alg)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&listing.snapshot_id)
+    .bind(&listing.created_at)
+    .bind(&listing.source_path)
+    .bind(&listing.label)
+    .bind(&listing.base_snapshot_id)
+    .bind(&listing.device_id)
+    .bind(&listing.device_name)
+    .bind(&listing.hash_alg)
+    .execute(&mut **tx)
+    .await?;
+    for chunk in &listing.chunks {
+        sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO chunks (chunk_hash, size, hash_alg, enc_alg, created_at)
+            VALUES (?, ?, ?, ?, strftime('%Y-%m-%dT%H:%M:%fZ','now'))
+            "#,
+        )
+        .bind(&chunk.hash)
+        .bind(chunk.size)
+        .bind(&chunk.hash_alg)
+        .bind(&chunk.enc_alg)
+        .execute(&mut **tx)
+        .await?;
+    }
+    Ok(())
+}
+
+async fn write_filemap(pool: &SqlitePool, listing: &SnapshotListing) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    insert_snapshot(&mut tx, listing).await?;
+    for file in &listing.files {
+        let file_id = format!("f_{}", uuid::Uuid::new_v4());
+        sqlx::query(
+            r#"
+            INSERT INTO files (file_id, snapshot_id, path, size, mtime_ms, mode, kind, btime_ms, uid, gid, owner_name, group_name, content_source)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&file_id)
+        .bind(&listing.snapshot_id)
+        .bind(&file.path)
+        .bind(file.size)
+        .bind(file.mtime_ms)
+        .bind(file.mode)
+        .bind(&file.kind)
+        .bind(file.btime_ms)
+        .bind(file.uid)
+        .bind(file.gid)
+        .bind(&file.owner_name)
+        .bind(&file.group_name)
+        .bind(&file.content_source)
+        .execute(&mut *tx)
+        .await?;
+        for (seq, chunk) in file.chunks.iter().enumerate() {
+            sqlx::query(
+                "INSERT INTO file_chunks (file_id, seq, chunk_hash, offset, len) VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(&file_id)
+            .bind(seq as i64)
+            .bind(&chunk.hash)
+            .bind(chunk.offset)
+            .bind(chunk.len)
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+async fn write_endpoint_rows(pool: &SqlitePool, listing: &SnapshotListing) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    insert_snapshot(&mut tx, listing).await?;
+    for chunk in &listing.chunks {
+        for object in &chunk.objects {
+            sqlx::query(
+                r#"
+                INSERT OR IGNORE INTO chunk_objects (chunk_hash, provider, object_id, created_at)
+                VALUES (?, ?, ?, strftime('%Y-%m-%dT%H:%M:%fZ','now'))
+                "#,
+            )
+            .bind(&chunk.hash)
+            .bind(&object.provider)
+            .bind(&object.object_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listing() -> SnapshotListing {
+        SnapshotListing {
+            version: SNAPSHOT_LISTING_VERSION,
+            snapshot_id: "snp_1".to_string(),
+            created_at: "2026-01-01T00:00:00Z".to_string(),
+            source_path: "/src".to_string(),
+            label: "manual".to_string(),
+            base_snapshot_id: None,
+            device_id: Some("dev_1".to_string()),
+            device_name: Some("laptop".to_string()),
+            hash_alg: "blake3".to_string(),
+            files: vec![
+                ListedFile {
+                    path: "a.txt".to_string(),
+                    kind: "file".to_string(),
+                    size: 10,
+                    mtime_ms: 1,
+                    mode: 0o644,
+                    btime_ms: Some(0),
+                    uid: Some(501),
+                    gid: Some(20),
+                    owner_name: Some("alice".to_string()),
+                    group_name: None,
+                    content_source: None,
+                    chunks: vec![
+                        ListedFileChunk {
+                            hash: "h1".to_string(),
+                            offset: 0,
+                            len: 4,
+                        },
+                        ListedFileChunk {
+                            hash: "h2".to_string(),
+                            offset: 4,
+                            len: 6,
+                        },
+                    ],
+                },
+                ListedFile {
+                    path: "dir".to_string(),
+                    kind: "dir".to_string(),
+                    size: 0,
+                    mtime_ms: 2,
+                    mode: 0o755,
+                    btime_ms: None,
+                    uid: None,
+                    gid: None,
+                    owner_name: None,
+                    group_name: None,
+                    content_source: None,
+                    chunks: Vec::new(),
+                },
+            ],
+            chunks: vec![
+                ListedChunk {
+                    hash: "h1".to_string(),
+                    size: 4,
+                    hash_alg: "blake3".to_string(),
+                    enc_alg: "xchacha20poly1305".to_string(),
+                    objects: vec![ListedChunkObject {
+                        provider: "telegram.mtproto/ep1".to_string(),
+                        object_id: "obj1".to_string(),
+                    }],
+                },
+                ListedChunk {
+                    hash: "h2".to_string(),
+                    size: 6,
+                    hash_alg: "blake3".to_string(),
+                    enc_alg: "xchacha20poly1305".to_string(),
+                    objects: Vec::new(),
+                },
+            ],
+        }
+    }
+
+    #[tokio::test]
+    async fn import_then_read_round_trips_and_refuses_a_second_import() {
+        let dir = tempfile::tempdir().unwrap();
+        let endpoint_db = dir.path().join("index.sqlite");
+        let filemap_db = dir.path().join("filemap").join("snp_1.sqlite");
+        let listing = listing();
+
+        import_snapshot_listing(&endpoint_db, &filemap_db, &listing)
+            .await
+            .unwrap();
+        let read = read_snapshot_listing(&filemap_db, Some(&endpoint_db), "snp_1")
+            .await
+            .unwrap();
+        assert_eq!(read, listing);
+
+        std::fs::remove_file(&filemap_db).unwrap();
+        let err = import_snapshot_listing(&endpoint_db, &filemap_db, &listing)
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("already in the local index"),
+            "{err}"
+        );
+        assert!(!filemap_db.exists());
+    }
+
+    #[test]
+    fn listing_must_list_every_referenced_chunk() {
+        let mut listing = listing();
+        listing.chunks.pop();
+        let err = check_listing(&listing).unwrap_err();
+        assert!(err.to_string().contains("chunk h2"), "{err}");
+    }
+}
@@ -0,0 +1,479 @@
+//! `source_guard`: catches a backup of the wrong source before anything is uploaded, typically
+//! the empty mount point directory of an external drive that failed to mount (retention would
+//! then age out the good snapshots).
+//!
+//! After each successful backup the source's identity is recorded per target in
+//! `<data_dir>/source-guard.json`: the mount it lives on (mount point and, where the platform
+//! reports one, volume UUID), whether a [`ANCHOR_FILE_NAME`] file sits in its root, and its file
+//! count. The next run fails with `backup.source_suspicious` when the mount changed, the anchor
+//! disappeared, or the file count dropped by more than `scan.max_file_drop_percent` (or is below
+//! `targets[].min_expected_files`), unless the change is accepted. Device ids are not compared:
+//! macOS hands out new ones when a drive is reattached.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::mounts::{self, MountEntry};
+use crate::{Error, Result};
+
+pub const STATE_FILE_VERSION: u32 = 1;
+
+/// A file whose presence in the source root marks the real source; once a backup saw it, a
+/// source without it is suspicious.
+pub const ANCHOR_FILE_NAME: &str = ".televybackup-anchor";
+
+/// What made a source suspicious.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceCheck {
+    /// The source is on another mount (or volume) than at the last successful backup.
+    VolumeChanged,
+    /// The last successful backup saw [`ANCHOR_FILE_NAME`]; it's gone.
+    AnchorMissing,
+    /// The file count dropped by more than `scan.max_file_drop_percent`.
+    FileCountDropped,
+    /// Fewer files than `targets[].min_expected_files`.
+    TooFewFiles,
+}
+
+impl SourceCheck {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::VolumeChanged => "volume_changed",
+            Self::AnchorMissing => "anchor_missing",
+            Self::FileCountDropped => "file_count_dropped",
+            Self::TooFewFiles => "too_few_files",
+        }
+    }
+
+    /// The `TargetRunSummary::warnings` entry of a run that failed this check.
+    pub fn warning(self) -> &'static str {
+        match self {
+            Self::VolumeChanged => "source_volume_changed",
+            Self::AnchorMissing => "source_anchor_missing",
+            Self::FileCountDropped => "source_file_count_dropped",
+            Self::TooFewFiles => "source_too_few_files",
+        }
+    }
+}
+
+/// What a source looked like at one backup.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceIdentity {
+    /// Mount point of the file system holding the source.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mount_point: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fs_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volume_uuid: Option<String>,
+    /// [`ANCHOR_FILE_NAME`] exists in the source root.
+    #[serde(default)]
+    pub anchor: bool,
+    /// Files in the source before filters (the run's quick stats), when they were counted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub files_total: Option<u64>,
+}
+
+/// Per-target state in `source-guard.json`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TargetSourceState {
+    /// The source at the last successful backup.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub baseline: Option<SourceIdentity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recorded_at: Option<String>,
+    /// Skip the checks once, e.g. after `backup.runNow` with `acceptSourceChange`; cleared when
+    /// the next successful backup records a new baseline.
+    #[serde(default)]
+    pub accept_change: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SourceGuardFile {
+    version: u32,
+    targets: BTreeMap<String, TargetSourceState>,
+}
+
+/// Thresholds from settings.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SourceGuardLimits {
+    /// `targets[].min_expected_files`; 0 disables the check.
+    pub min_expected_files: u64,
+    /// `scan.max_file_drop_percent`; 0 disables the check.
+    pub max_file_drop_percent: u32,
+}
+
+pub fn state_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("source-guard.json")
+}
+
+fn load_state(data_dir: &Path) -> Result<SourceGuardFile> {
+    let path = state_path(data_dir);
+    let bytes = match std::fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(SourceGuardFile {
+                version: STATE_FILE_VERSION,
+                ..SourceGuardFile::default()
+            });
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let file: SourceGuardFile =
+        serde_json::from_slice(&bytes).map_err(|e| Error::InvalidConfig {
+            message: format!(
+                "source guard file decode failed: path={}; {e}",
+                path.display()
+            ),
+        })?;
+    if file.version != STATE_FILE_VERSION {
+        return Err(Error::InvalidConfig {
+            message: format!(
+                "unsupported source guard file version {}: path={}",
+                file.version,
+                path.display()
+            ),
+        });
+    }
+    Ok(file)
+}
+
+fn update_state(
+    data_dir: &Path,
+    target_id: &str,
+    f: impl FnOnce(&mut TargetSourceState),
+) -> Result<()> {
+    let mut file = load_state(data_dir)?;
+    f(file.targets.entry(target_id.to_string()).or_default());
+    let path = state_path(data_dir);
+    let bytes = serde_json::to_vec_pretty(&file).map_err(std::io::Error::other)?;
+    std::fs::create_dir_all(data_dir)?;
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, bytes)?;
+    std::fs::rename(&tmp, &path)?;
+    Ok(())
+}
+
+pub fn load_target_state(data_dir: &Path, target_id: &str) -> Result<TargetSourceState> {
+    Ok(load_state(data_dir)?
+        .targets
+        .remove(target_id)
+        .unwrap_or_default())
+}
+
+/// Records `identity` as the target's baseline after a successful backup and clears an
+/// accepted change.
+pub fn record_baseline(data_dir: &Path, target_id: &str, identity: &SourceIdentity) -> Result<()> {
+    update_state(data_dir, target_id, |state| {
+        state.baseline = Some(identity.clone());
+        state.recorded_at =
+            Some(chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true));
+        state.accept_change = false;
+    })
+}
+
+/// Lets the target's next backup run whatever its source looks like.
+pub fn accept_next_change(data_dir: &Path, target_id: &str) -> Result<()> {
+    update_state(data_dir, target_id, |state| state.accept_change = true)
+}
+
+/// Looks at `source_path` now; `files_total` comes from the run's quick stats.
+pub fn probe_source(source_path: &Path, files_total: Option<u64>) -> SourceIdentity {
+    let canonical =
+        std::fs::canonicalize(source_path).unwrap_or_else(|_| source_path.to_path_buf());
+    let root = if canonical.is_file() {
+        canonical.parent().unwrap_or(&canonical).to_path_buf()
+    } else {
+        canonical.clone()
+    };
+    let mount = mounts::containing_mount(&canonical);
+    SourceIdentity {
+        volume_uuid: mount.as_ref().and_then(volume_uuid),
+        mount_point: mount.as_ref().map(|m| m.path.clone()),
+        fs_type: mount.map(|m| m.fs_type),
+        anchor: root.join(ANCHOR_FILE_NAME).exists(),
+        files_total,
+    }
+}
+
+/// Compares `current` with the last successful backup's `baseline`; `None` (first backup) only
+/// runs the `min_expected_files` check.
+pub fn check_source(
+    source_path: &Path,
+    baseline: Option<&SourceIdentity>,
+    current: &SourceIdentity,
+    limits: SourceGuardLimits,
+) -> Result<()> {
+    let fail = |reason: SourceCheck, detail: String| {
+        Err(Error::SourceSuspicious {
+            source_path: source_path.to_path_buf(),
+            reason,
+            message: format!(
+                "{} doesn't look like the source backed up before: {detail}; check that its drive is mounted, or re-run with --accept-source-change",
+                source_path.display()
+            ),
+        })
+    };
+
+    if let Some(files) = current.files_total
+        && limits.min_expected_files > 0
+        && files < limits.min_expected_files
+    {
+        return fail(
+            SourceCheck::TooFewFiles,
+            format!(
+                "{files} files, fewer than targets[].min_expected_files = {}",
+                limits.min_expected_files
+            ),
+        );
+    }
+
+    let Some(baseline) = baseline else {
+        return Ok(());
+    };
+
+    if let (Some(was), Some(now)) = (&baseline.mount_point, &current.mount_point)
+        && was != now
+    {
+        return fail(
+            SourceCheck::VolumeChanged,
+            format!("it was on the file system mounted at {was}, now it's on the one at {now}"),
+        );
+    }
+    if let (Some(was), Some(now)) = (&baseline.volume_uuid, &current.volume_uuid)
+        && was != now
+    {
+        return fail(
+            SourceCheck::VolumeChanged,
+            format!("its volume changed from {was} to {now}"),
+        );
+    }
+
+    if baseline.anchor && !current.anchor {
+        return fail(
+            SourceCheck::AnchorMissing,
+            format!("{ANCHOR_FILE_NAME} is missing from its root"),
+        );
+    }
+
+    if let (Some(was), Some(now)) = (baseline.files_total, current.files_total)
+        && limits.max_file_drop_percent > 0
+        && was > 0
+        && now < was
+    {
+        let drop = (was - now).saturating_mul(100) / was;
+        if drop > u64::from(limits.max_file_drop_percent) {
+            return fail(
+                SourceCheck::FileCountDropped,
+                format!(
+                    "{now} files, {drop}% fewer than the {was} of the last backup (scan.max_file_drop_percent = {})",
+                    limits.max_file_drop_percent
+                ),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Checks the target's source before a backup and returns what to record once it succeeds.
+/// `accept` (`--accept-source-change`) or an accepted change in the state file skips the checks.
+pub fn guard_source(
+    data_dir: &Path,
+    target_id: &str,
+    source_path: &Path,
+    files_total: Option<u64>,
+    limits: SourceGuardLimits,
+    accept: bool,
+) -> Result<SourceIdentity> {
+    let state = load_target_state(data_dir, target_id)?;
+    let current = probe_source(source_path, files_total);
+    if accept || state.accept_change {
+        tracing::warn!(
+            event = "backup.source_change_accepted",
+            target_id,
+            source_path = %source_path.display(),
+            "backup.source_change_accepted"
+        );
+        return Ok(current);
+    }
+    if let Err(e) = check_source(source_path, state.baseline.as_ref(), &current, limits) {
+        tracing::error!(
+            event = "backup.source_suspicious",
+            target_id,
+            source_path = %source_path.display(),
+            error = %e,
+            "backup.source_suspicious"
+        );
+        return Err(e);
+    }
+    Ok(current)
+}
+
+#[cfg(target_os = "linux")]
+fn volume_uuid(mount: &MountEntry) -> Option<String> {
+    let device = std::fs::canonicalize(&mount.device).ok()?;
+    std::fs::read_dir("/dev/disk/by-uuid")
+        .ok()?
+        .flatten()
+        .find(|e| std::fs::canonicalize(e.path()).is_ok_and(|p| p == device))
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+}
+
+#[cfg(target_os = "macos")]
+fn volume_uuid(mount: &MountEntry) -> Option<String> {
+    if !mount.device.starts_with("/dev/") {
+        return None;
+    }
+    let out = std::process::Command::new("/usr/sbin/diskutil")
+        .args(["info", "-plist", &mount.path])
+        .output()
+        .ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    plist_string(&String::from_utf8_lossy(&out.stdout), "VolumeUUID")
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn volume_uuid(_mount: &MountEntry) -> Option<String> {
+    None
+}
+
+/// The `<string>` after `<key>{key}</key>` in an XML plist.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn plist_string(plist: &str, key: &str) -> Option<String> {
+    let (_, rest) = plist.split_once(&format!("<key>{key}</key>"))?;
+    let (_, rest) = rest.split_once("<string>")?;
+    let (value, _) = rest.split_once("</string>")?;
+    Some(value.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity(mount_point: &str, anchor: bool, files: u64) -> SourceIdentity {
+        SourceIdentity {
+            mount_point: Some(mount_point.to_string()),
+            fs_type: Some("apfs".to_string()),
+            volume_uuid: None,
+            anchor,
+            files_total: Some(files),
+        }
+    }
+
+    fn reason(r: Result<()>) -> Option<SourceCheck> {
+        match r {
+            Ok(()) => None,
+            Err(Error::SourceSuspicious { reason, .. }) => Some(reason),
+            Err(e) => panic!("unexpected error: {e}"),
+        }
+    }
+
+    #[test]
+    fn empty_mount_points_and_vanished_anchors_are_suspicious() {
+        let src = Path::new("/Volumes/Photos");
+        let limits = SourceGuardLimits {
+            min_expected_files: 0,
+            max_file_drop_percent: 90,
+        };
+        let baseline = identity("/Volumes/Photos", true, 10_000);
+        let check = |current: &SourceIdentity, limits| {
+            reason(check_source(src, Some(&baseline), current, limits))
+        };
+
+        assert_eq!(
+            check(&identity("/Volumes/Photos", true, 9_500), limits),
+            None
+        );
+        // Unmounted drive: the directory left behind is on the boot volume.
+        assert_eq!(
+            check(&identity("/", false, 0), limits),
+            Some(SourceCheck::VolumeChanged)
+        );
+        assert_eq!(
+            check(&identity("/Volumes/Photos", false, 10_000), limits),
+            Some(SourceCheck::AnchorMissing)
+        );
+        assert_eq!(
+            check(&identity("/Volumes/Photos", true, 500), limits),
+            Some(SourceCheck::FileCountDropped)
+        );
+        let no_drop_check = SourceGuardLimits {
+            max_file_drop_percent: 0,
+            ..limits
+        };
+        assert_eq!(
+            check(&identity("/Volumes/Photos", true, 500), no_drop_check),
+            None
+        );
+
+        let first_run = SourceGuardLimits {
+            min_expected_files: 100,
+            max_file_drop_percent: 90,
+        };
+        assert_eq!(
+            reason(check_source(src, None, &identity("/", false, 3), first_run)),
+            Some(SourceCheck::TooFewFiles)
+        );
+        assert_eq!(
+            reason(check_source(
+                src,
+                None,
+                &identity("/", false, 300),
+                first_run
+            )),
+            None
+        );
+
+        let err = check_source(src, Some(&baseline), &identity("/", false, 0), limits).unwrap_err();
+        assert_eq!(err.code(), "backup.source_suspicious");
+        assert_eq!(err.details()["reason"], "volume_changed");
+        assert_eq!(err.run_warnings(), vec!["source_volume_changed"]);
+    }
+
+    #[test]
+    fn accepted_changes_skip_the_checks_until_the_next_baseline() {
+        let data = tempfile::tempdir().unwrap();
+        let src = tempfile::tempdir().unwrap();
+        let limits = SourceGuardLimits {
+            min_expected_files: 0,
+            max_file_drop_percent: 50,
+        };
+
+        std::fs::write(src.path().join(ANCHOR_FILE_NAME), b"").unwrap();
+        let identity =
+            guard_source(data.path(), "t1", src.path(), Some(10), limits, false).unwrap();
+        assert!(identity.anchor);
+        record_baseline(data.path(), "t1", &identity).unwrap();
+
+        let err = guard_source(data.path(), "t1", src.path(), Some(1), limits, false).unwrap_err();
+        assert_eq!(err.code(), "backup.source_suspicious");
+        assert!(guard_source(data.path(), "t1", src.path(), Some(1), limits, true).is_ok());
+
+        accept_next_change(data.path(), "t1").unwrap();
+        let identity = guard_source(data.path(), "t1", src.path(), Some(1), limits, false).unwrap();
+        record_baseline(data.path(), "t1", &identity).unwrap();
+        let state = load_target_state(data.path(), "t1").unwrap();
+        assert!(!state.accept_change);
+        assert_eq!(state.baseline.unwrap().files_total, Some(1));
+        // Other targets start without a baseline.
+        assert!(guard_source(data.path(), "t2", src.path(), Some(0), limits, false).is_ok());
+    }
+
+    #[test]
+    fn plist_values_are_found_by_key() {
+        let plist = "<dict>\n<key>VolumeName</key>\n<string>Photos</string>\n<key>VolumeUUID</key>\n<string>0A1B-2C3D</string>\n</dict>";
+        assert_eq!(
+            plist_string(plist, "VolumeUUID").as_deref(),
+            Some("0A1B-2C3D")
+        );
+        assert_eq!(plist_string(plist, "DiskUUID"), None);
+    }
+}
@@ -0,0 +1,359 @@
+//! `targets[].sqlite_consistent`: SQLite databases backed up from a consistent copy.
+//!
+//! A live database, above all one in WAL mode, is rarely consistent on disk: reading it while
+//! the application writes mixes pages of different transactions, and committed data may still
+//! sit in its `-wal` file. A file that matches one of the target's patterns and starts with the
+//! SQLite header is copied with `VACUUM INTO` over a read-only connection, and the scan chunks
+//! the copy instead of the live file. Its file row records [`CONTENT_SOURCE`], and the database's
+//! `-wal`, `-shm` and `-journal` files are left out. A database larger than
+//! `scan.sqlite_consistent_max_bytes`, a copy running past `scan.sqlite_consistent_timeout_secs`
+//! or any SQLite error falls back to reading the live file and adds [`WARNING`] to the result.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use sqlx::ConnectOptions;
+use sqlx::sqlite::SqliteConnectOptions;
+use tempfile::TempPath;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::{Scan, Target};
+use crate::{Error, Result};
+
+/// `files.content_source` of a file chunked from a consistent copy.
+pub const CONTENT_SOURCE: &str = "sqlite_backup";
+
+/// `BackupResult::warnings` / `lastRun.warnings` entry when a matching database was read live.
+pub const WARNING: &str = "sqlite_consistent_fallback";
+
+/// Files SQLite keeps next to a database, named by appending these to its path.
+pub const SIDECAR_SUFFIXES: &[&str] = &["-wal", "-shm", "-journal"];
+
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+/// Virtual machine steps between checks of the deadline and the cancellation token.
+const PROGRESS_CHECK_OPS: i32 = 10_000;
+
+pub const DEFAULT_MAX_BYTES: u64 = 4 * 1024 * 1024 * 1024;
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// `targets[].sqlite_consistent` with the `scan.sqlite_consistent_*` limits.
+#[derive(Debug, Clone, Copy)]
+pub struct SqliteConsistentOptions<'a> {
+    /// Gitignore-style patterns matched against paths relative to the source.
+    pub patterns: &'a [String],
+    /// Databases larger than this (with their `-wal` file) are read live; 0 disables the limit.
+    pub max_bytes: u64,
+    pub timeout: Duration,
+}
+
+impl Default for SqliteConsistentOptions<'_> {
+    fn default() -> Self {
+        Self {
+            patterns: &[],
+            max_bytes: DEFAULT_MAX_BYTES,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+impl<'a> SqliteConsistentOptions<'a> {
+    pub fn for_target(target: &'a Target, scan: &Scan) -> Self {
+        Self {
+            patterns: &target.sqlite_consistent,
+            max_bytes: scan.sqlite_consistent_max_bytes,
+            timeout: Duration::from_secs(scan.sqlite_consistent_timeout_secs),
+        }
+    }
+}
+
+/// `Err` carries a message naming the offending pattern.
+pub fn validate_patterns(patterns: &[String]) -> std::result::Result<(), String> {
+    build_matcher(patterns).map(|_| ())
+}
+
+fn build_matcher(patterns: &[String]) -> std::result::Result<Gitignore, String> {
+    let mut builder = GitignoreBuilder::new("");
+    for pattern in patterns {
+        if pattern.trim().is_empty() || pattern.starts_with('!') {
+            return Err(format!(
+                "patterns must be non-empty and not negated (got {pattern:?})"
+            ));
+        }
+        builder
+            .add_line(None, pattern)
+            .map_err(|e| format!("invalid pattern {pattern:?}: {e}"))?;
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+/// Whether the file at `path` starts with the SQLite database header.
+pub fn is_sqlite_database(path: &Path) -> std::io::Result<bool> {
+    let mut head = [0u8; SQLITE_HEADER.len()];
+    let mut file = File::open(path)?;
+    let mut filled = 0;
+    while filled < head.len() {
+        match file.read(&mut head[filled..])? {
+            0 => return Ok(false),
+            n => filled += n,
+        }
+    }
+    Ok(&head == SQLITE_HEADER)
+}
+
+/// The database `path` belongs to when it is one of its [`SIDECAR_SUFFIXES`] files.
+pub fn sidecar_database(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    SIDECAR_SUFFIXES.iter().find_map(|suffix| {
+        name.strip_suffix(suffix)
+            .filter(|db| !db.is_empty())
+            .map(|db| path.with_file_name(db))
+    })
+}
+
+/// A consistent copy of a database; the file is removed when this is dropped.
+#[derive(Debug)]
+pub(crate) struct ConsistentCopy {
+    pub(crate) path: TempPath,
+    pub(crate) size: u64,
+}
+
+/// Why a matching database is read live instead.
+#[derive(Debug)]
+pub(crate) enum CopyFallback {
+    TooLarge { bytes: u64 },
+    TimedOut,
+    Failed(String),
+}
+
+impl CopyFallback {
+    pub(crate) fn reason(&self) -> &'static str {
+        match self {
+            Self::TooLarge { .. } => "too_large",
+            Self::TimedOut => "timeout",
+            Self::Failed(_) => "sqlite_error",
+        }
+    }
+}
+
+impl std::fmt::Display for CopyFallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooLarge { bytes } => write!(f, "database is {bytes} bytes"),
+            Self::TimedOut => f.write_str("consistent copy timed out"),
+            Self::Failed(message) => f.write_str(message),
+        }
+    }
+}
+
+pub(crate) struct SqliteConsistent {
+    matcher: Option<Gitignore>,
+    max_bytes: u64,
+    timeout: Duration,
+}
+
+impl SqliteConsistent {
+    pub(crate) fn new(options: SqliteConsistentOptions<'_>) -> Result<Self> {
+        let matcher = if options.patterns.is_empty() {
+            None
+        } else {
+            Some(
+                build_matcher(options.patterns).map_err(|message| Error::InvalidConfig {
+                    message: format!("targets[].sqlite_consistent: {message}"),
+                })?,
+            )
+        };
+        Ok(Self {
+            matcher,
+            max_bytes: options.max_bytes,
+            timeout: options.timeout,
+        })
+    }
+
+    /// Whether the file at `path` (`rel_path` within the source) should be copied first.
+    pub(crate) fn applies_to(&self, rel_path: &Path, path: &Path) -> bool {
+        let Some(matcher) = &self.matcher else {
+            return false;
+        };
+        matcher.matched(rel_path, false).is_ignore() && is_sqlite_database(path).unwrap_or(false)
+    }
+
+    /// Copies the database at `path` into a temp file in `dir`. `Err` only on cancellation;
+    /// everything else that goes wrong is a fallback.
+    pub(crate) async fn copy(
+        &self,
+        path: &Path,
+        dir: &Path,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<std::result::Result<ConsistentCopy, CopyFallback>> {
+        let bytes = database_bytes(path);
+        if self.max_bytes > 0 && bytes > self.max_bytes {
+            return Ok(Err(CopyFallback::TooLarge { bytes }));
+        }
+
+        let deadline = Instant::now() + self.timeout;
+        let copied = vacuum_into(path, dir, self.timeout, deadline, cancel).await;
+        if cancel.is_some_and(CancellationToken::is_cancelled) {
+            return Err(Error::Cancelled);
+        }
+        Ok(copied.map_err(|message| {
+            if Instant::now() >= deadline {
+                CopyFallback::TimedOut
+            } else {
+                CopyFallback::Failed(message)
+            }
+        }))
+    }
+}
+
+/// The database file and its `-wal` file, which `VACUUM INTO` reads as well.
+fn database_bytes(path: &Path) -> u64 {
+    let wal = path.with_file_name(format!(
+        "{}-wal",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    [path, wal.as_path()]
+        .iter()
+        .filter_map(|p| std::fs::metadata(p).ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+async fn vacuum_into(
+    path: &Path,
+    dir: &Path,
+    timeout: Duration,
+    deadline: Instant,
+    cancel: Option<&CancellationToken>,
+) -> std::result::Result<ConsistentCopy, String> {
+    let temp = tempfile::Builder::new()
+        .prefix(".sqlite-consistent-")
+        .tempfile_in(dir)
+        .map_err(|e| format!("create temp file in {}: {e}", dir.display()))?
+        .into_temp_path();
+    let dest = temp
+        .to_str()
+        .ok_or_else(|| "temp file path is not UTF-8".to_string())?
+        .to_string();
+
+    let mut conn = SqliteConnectOptions::new()
+        .filename(path)
+        .read_only(true)
+        .busy_timeout(timeout)
+        .disable_statement_logging()
+        .connect()
+        .await
+        .map_err(|e| e.to_string())?;
+    {
+        let cancel = cancel.cloned();
+        let mut handle = conn.lock_handle().await.map_err(|e| e.to_string())?;
+        handle.set_progress_handler(PROGRESS_CHECK_OPS, move || {
+            Instant::now() < deadline
+                && !cancel.as_ref().is_some_and(CancellationToken::is_cancelled)
+        });
+    }
+    let vacuumed = sqlx::query("VACUUM INTO ?")
+        .bind(&dest)
+        .execute(&mut conn)
+        .await;
+    let _ = sqlx::Connection::close(conn).await;
+    vacuumed.map_err(|e| e.to_string())?;
+
+    let size = std::fs::metadata(&temp).map_err(|e| e.to_string())?.len();
+    Ok(ConsistentCopy { path: temp, size })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sidecars_map_to_their_database() {
+        assert_eq!(
+            sidecar_database(Path::new("/a/app.db-wal")),
+            Some(PathBuf::from("/a/app.db"))
+        );
+        assert_eq!(
+            sidecar_database(Path::new("/a/x.sqlite-journal")),
+            Some(PathBuf::from("/a/x.sqlite"))
+        );
+        assert_eq!(sidecar_database(Path::new("/a/app.db")), None);
+        assert_eq!(sidecar_database(Path::new("/a/-wal")), None);
+    }
+
+    #[test]
+    fn patterns_match_like_ignore_rules_and_need_the_header() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let db = temp.path().join("app.db");
+        let fake = temp.path().join("fake.db");
+        std::fs::write(&db, b"SQLite format 3\0rest of the page").unwrap();
+        std::fs::write(&fake, b"not a database").unwrap();
+
+        let patterns = vec!["*.db".to_string(), "data/*.sqlite".to_string()];
+        let s = SqliteConsistent::new(SqliteConsistentOptions {
+            patterns: &patterns,
+            ..SqliteConsistentOptions::default()
+        })
+        .unwrap();
+        assert!(s.applies_to(Path::new("nested/app.db"), &db));
+        assert!(!s.applies_to(Path::new("fake.db"), &fake));
+        assert!(!s.applies_to(Path::new("app.sqlite"), &db));
+
+        assert!(validate_patterns(&["!*.db".to_string()]).is_err());
+        assert!(validate_patterns(&[" ".to_string()]).is_err());
+    }
+
+    #[tokio::test]
+    async fn copies_a_closed_wal_database_and_falls_back_on_limits() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let db = temp.path().join("app.db");
+        let mut conn = SqliteConnectOptions::new()
+            .filename(&db)
+            .create_if_missing(true)
+            .journal_mode(sqlx::sqlite::SqliteJournalMode::Wal)
+            .connect()
+            .await
+            .unwrap();
+        sqlx::query("CREATE TABLE t (v TEXT); INSERT INTO t VALUES ('x')")
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        sqlx::Connection::close(conn).await.unwrap();
+
+        let patterns = vec!["*.db".to_string()];
+        let s = SqliteConsistent::new(SqliteConsistentOptions {
+            patterns: &patterns,
+            ..SqliteConsistentOptions::default()
+        })
+        .unwrap();
+        let copy = s.copy(&db, temp.path(), None).await.unwrap().unwrap();
+        assert!(is_sqlite_database(&copy.path).unwrap());
+        assert_eq!(copy.size, std::fs::metadata(&copy.path).unwrap().len());
+        let copy_path = copy.path.to_path_buf();
+        drop(copy);
+        assert!(!copy_path.exists());
+
+        let small = SqliteConsistent::new(SqliteConsistentOptions {
+            patterns: &patterns,
+            max_bytes: 1,
+            ..SqliteConsistentOptions::default()
+        })
+        .unwrap();
+        let fallback = small
+            .copy(&db, temp.path(), None)
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(fallback.reason(), "too_large");
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        assert!(matches!(
+            s.copy(&db, temp.path(), Some(&cancel)).await,
+            Err(Error::Cancelled)
+        ));
+    }
+}
@@ -7,6 +7,9 @@ use std::time::{SystemTime, UNIX_EPOCH};
 
 use serde::{Deserialize, Serialize};
 
+use crate::fs_sync::sync_dir;
+use crate::progress::Phase;
+
 pub fn now_unix_ms() -> u64 {
     static LAST_UNIX_MS: AtomicU64 = AtomicU64::new(0);
 
@@ -48,7 +51,7 @@ pub struct Counter {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Progress {
-    pub phase: String,
+    pub phase: Phase,
     pub files_total: Option<u64>,
     pub files_done: Option<u64>,
     pub source_files_total: Option<u64>,
@@ -62,7 +65,47 @@ pub struct Progress {
     pub bytes_uploaded_source: Option<u64>,
     pub bytes_uploaded: Option<u64>,
     pub bytes_downloaded: Option<u64>,
+    /// Index DB bytes written by an index download (see `TaskProgress::bytes_written`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes_written: Option<u64>,
     pub bytes_deduped: Option<u64>,
+    pub bytes_total: Option<u64>,
+}
+
+/// How a backup, restore or verify run ended. `TargetRunSummary.status`, the CLI's `task.state`
+/// events, `run.finish` log lines and usage rows all carry these strings; a cancelled run is not
+/// a failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl RunStatus {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Succeeded => "succeeded",
+            Self::Failed => "failed",
+            Self::Cancelled => "cancelled",
+        }
+    }
+
+    /// `Cancelled` for `task.cancelled`, `Failed` for any other error code.
+    pub fn for_error_code(code: &str) -> Self {
+        if code == crate::ErrorCode::TaskCancelled.as_str() {
+            Self::Cancelled
+        } else {
+            Self::Failed
+        }
+    }
+}
+
+impl std::fmt::Display for RunStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -70,11 +113,26 @@ pub struct Progress {
 pub struct TargetRunSummary {
     pub finished_at: Option<String>,
     pub duration_seconds: Option<f64>,
+    /// A [`RunStatus`] string.
     pub status: Option<String>,
     pub error_code: Option<String>,
+    #[serde(default)]
+    pub error_message: Option<String>,
     pub files_indexed: Option<u64>,
     pub bytes_uploaded: Option<u64>,
     pub bytes_deduped: Option<u64>,
+    /// Part of `duration_seconds` spent uploading (successful backups only).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upload_duration_seconds: Option<f64>,
+
+    /// Tail of the run log for unsuccessful runs (bounded and redacted; see `run_log`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub log_excerpt: Vec<String>,
+
+    /// Problems with the run as a whole, e.g. `full_disk_access_missing` (see
+    /// [`crate::full_disk_access`]).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -82,6 +140,10 @@ pub struct TargetRunSummary {
 pub struct StatusSource {
     pub kind: String, // "daemon" | "cli" | "file"
     pub detail: Option<String>,
+    /// Read from the previous generation because `status.json` was missing or corrupt; consumers
+    /// show it as "last known status" (`generated_at` still tells its age).
+    #[serde(default)]
+    pub degraded: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -103,7 +165,7 @@ pub struct TargetState {
     pub endpoint_id: String,
     pub enabled: bool,
 
-    pub state: String, // "idle" | "running" | "failed" | "stale"
+    pub state: String, // "idle" | "queued" | "waiting_quiet_hours" | "running" | "failed" | "stale"
 
     pub running_since: Option<u64>,
 
@@ -132,7 +194,110 @@ pub struct StatusSnapshot {
     pub extra: BTreeMap<String, serde_json::Value>,
 }
 
+/// Key of the daemon scheduler's [`ScheduleStatus`] in `StatusSnapshot.extra`.
+pub const SCHEDULE_STATUS_KEY: &str = "schedule";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleStatus {
+    /// `schedule.timezone` with `local` resolved to the machine's zone name.
+    pub timezone: String,
+    pub catch_up: bool,
+    /// How often the scheduler ticks while no backup is running.
+    pub tick_interval_seconds: u64,
+    /// Unix ms of the last tick.
+    pub last_tick_at: Option<u64>,
+    /// Slots that passed while the daemon was not ticking and were run once it ticked again.
+    pub missed_slots_caught_up: u64,
+}
+
+impl StatusSnapshot {
+    pub fn schedule_status(&self) -> Option<ScheduleStatus> {
+        self.extra
+            .get(SCHEDULE_STATUS_KEY)
+            .and_then(|v| serde_json::from_value(v.clone()).ok())
+    }
+}
+
+/// Key of the bytes queued for deletion (`gc run`) but not flushed yet, summed over the
+/// endpoints, in `StatusSnapshot.extra`.
+pub const PENDING_DELETION_BYTES_KEY: &str = "pendingDeletionBytes";
+
+impl StatusSnapshot {
+    pub fn pending_deletion_bytes(&self) -> Option<u64> {
+        self.extra
+            .get(PENDING_DELETION_BYTES_KEY)
+            .and_then(|v| v.as_u64())
+    }
+}
+
+/// Key of a target's [`VerifySummary`] in `TargetState.extra`.
+pub const LAST_VERIFY_KEY: &str = "lastVerify";
+
+/// The daemon's last scheduled verify of a target (`verify_schedule`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifySummary {
+    /// RFC3339.
+    pub finished_at: String,
+    pub status: String, // "succeeded" | "failed"
+    pub snapshot_id: Option<String>,
+    pub chunks_checked: Option<u64>,
+    pub bytes_checked: Option<u64>,
+    /// Below 100 for sampled verifies.
+    pub coverage_percent: Option<f64>,
+    pub error_code: Option<String>,
+    pub error_message: Option<String>,
+    /// Set by a failed verify; cleared by the next successful one or `verify.acknowledge`.
+    pub warning: bool,
+}
+
+impl TargetState {
+    pub fn last_verify(&self) -> Option<VerifySummary> {
+        self.extra
+            .get(LAST_VERIFY_KEY)
+            .and_then(|v| serde_json::from_value(v.clone()).ok())
+    }
+}
+
+/// Key of a target's health in `TargetState.extra`: `false` once its last successful backup is
+/// older than its schedule period allows (see [`crate::health::is_stale`]).
+pub const HEALTHY_KEY: &str = "healthy";
+
+/// Key of a target's last successful backup (RFC3339) in `TargetState.extra`.
+pub const LAST_SUCCESS_AT_KEY: &str = "lastSuccessAt";
+
+impl TargetState {
+    /// `None` from daemons that do not report health yet.
+    pub fn healthy(&self) -> Option<bool> {
+        self.extra.get(HEALTHY_KEY).and_then(|v| v.as_bool())
+    }
+}
+
+/// The generation `write_status_snapshot_json_atomic` keeps next to `path` (`status.json.prev`).
+pub fn status_json_prev_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".prev");
+    path.with_file_name(name)
+}
+
+/// Reads `path`, falling back to the previous generation (marked `source.degraded`) when the
+/// primary is missing or corrupt, e.g. truncated by a crash mid-write. Errors are the primary's.
 pub fn read_status_snapshot_json(path: &Path) -> std::io::Result<StatusSnapshot> {
+    let err = match read_snapshot_file(path) {
+        Ok(snap) => return Ok(snap),
+        Err(e) => e,
+    };
+    match read_snapshot_file(&status_json_prev_path(path)) {
+        Ok(mut snap) => {
+            snap.source.degraded = true;
+            Ok(snap)
+        }
+        Err(_) => Err(err),
+    }
+}
+
+fn read_snapshot_file(path: &Path) -> std::io::Result<StatusSnapshot> {
     let mut f = File::open(path)?;
     let mut buf = Vec::new();
     f.read_to_end(&mut buf)?;
@@ -168,54 +333,97 @@ pub fn write_status_snapshot_json_atomic_with_options(
     snapshot: &StatusSnapshot,
     options: StatusWriteOptions,
 ) -> std::io::Result<()> {
-    if let Some(parent) = path.parent() {
-        std::fs::create_dir_all(parent)?;
+    StatusFileWriter::new(path).write(snapshot, options)
+}
+
+/// Rewrites one `status.json` again and again. Whether the file it last wrote is a good
+/// generation to keep as `.prev` is remembered instead of read back before every write; only
+/// the file found at creation is read.
+#[derive(Debug)]
+pub struct StatusFileWriter {
+    path: PathBuf,
+    primary_ok: bool,
+}
+
+impl StatusFileWriter {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            primary_ok: read_snapshot_file(path).is_ok(),
+        }
     }
 
-    let tmp = path.with_extension(format!("json.tmp.{}", std::process::id()));
-    let data = serde_json::to_vec(snapshot)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
-
-    let mut f = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open(&tmp)?;
-    f.write_all(&data)?;
-    if options.fsync_file {
-        f.sync_all()?;
+    pub fn path(&self) -> &Path {
+        &self.path
     }
-    drop(f);
 
-    std::fs::rename(&tmp, path)?;
+    pub fn write(
+        &mut self,
+        snapshot: &StatusSnapshot,
+        options: StatusWriteOptions,
+    ) -> std::io::Result<()> {
+        let path = self.path.as_path();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
 
-    // Best-effort directory sync (ignored on platforms where it isn't supported).
-    if options.fsync_dir
-        && let Some(parent) = path.parent()
-        && let Ok(dir) = File::open(parent)
-    {
-        let _ = dir.sync_all();
+        let tmp = path.with_extension(format!("json.tmp.{}", std::process::id()));
+        let data = serde_json::to_vec(snapshot)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+
+        let mut f = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&tmp)?;
+        f.write_all(&data)?;
+        if options.fsync_file {
+            f.sync_all()?;
+        }
+        drop(f);
+
+        // Keep the current generation as `.prev` for readers to fall back on, unless it is the
+        // corrupt leftover of a crash: a good `.prev` beats a bad one.
+        if self.primary_ok {
+            keep_previous_generation(path)?;
+        }
+        std::fs::rename(&tmp, path)?;
+        self.primary_ok = true;
+
+        if options.fsync_dir
+            && let Some(parent) = path.parent()
+        {
+            sync_dir(parent);
+        }
+
+        Ok(())
     }
+}
 
-    Ok(())
+/// Replaces `.prev` with the current `path`. The copy is staged next to it and renamed over the
+/// old one, so neither `path` nor `.prev` is ever missing.
+fn keep_previous_generation(path: &Path) -> std::io::Result<()> {
+    let prev = status_json_prev_path(path);
+    let staged = prev.with_extension(format!("prev.tmp.{}", std::process::id()));
+    // Left behind by a crash between the two steps.
+    let _ = std::fs::remove_file(&staged);
+    std::fs::hard_link(path, &staged).or_else(|_| std::fs::copy(path, &staged).map(|_| ()))?;
+    std::fs::rename(&staged, &prev)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[test]
-    fn roundtrip_status_snapshot_json_atomic() {
-        let dir = tempfile::tempdir().unwrap();
-        let path = status_json_path(dir.path());
-
-        let snapshot = StatusSnapshot {
+    fn sample_snapshot() -> StatusSnapshot {
+        StatusSnapshot {
             type_: "status.snapshot".to_string(),
             schema_version: 1,
             generated_at: 123,
             source: StatusSource {
                 kind: "daemon".to_string(),
                 detail: Some("test".to_string()),
+                degraded: false,
             },
             global: GlobalStatus {
                 up: Rate {
@@ -245,7 +453,30 @@ mod tests {
                 extra: Default::default(),
             }],
             extra: Default::default(),
-        };
+        }
+    }
+
+    #[test]
+    fn run_status_treats_only_task_cancelled_as_cancelled() {
+        assert_eq!(
+            RunStatus::for_error_code("task.cancelled"),
+            RunStatus::Cancelled
+        );
+        assert_eq!(
+            RunStatus::for_error_code("telegram.unavailable"),
+            RunStatus::Failed
+        );
+        assert_eq!(
+            serde_json::to_string(&RunStatus::Cancelled).unwrap(),
+            "\"cancelled\""
+        );
+    }
+
+    #[test]
+    fn roundtrip_status_snapshot_json_atomic() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = status_json_path(dir.path());
+        let snapshot = sample_snapshot();
 
         write_status_snapshot_json_atomic(&path, &snapshot).unwrap();
         let got = read_status_snapshot_json(&path).unwrap();
@@ -256,6 +487,97 @@ mod tests {
         assert_eq!(got.targets[0].target_id, "t1");
     }
 
+    fn write_generations(path: &Path) {
+        let mut snapshot = sample_snapshot();
+        write_status_snapshot_json_atomic(path, &snapshot).unwrap();
+        snapshot.generated_at = 456;
+        write_status_snapshot_json_atomic(path, &snapshot).unwrap();
+    }
+
+    #[test]
+    fn keeps_the_previous_generation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = status_json_path(dir.path());
+        write_generations(&path);
+
+        let got = read_status_snapshot_json(&path).unwrap();
+        assert_eq!(got.generated_at, 456);
+        assert!(!got.source.degraded);
+        let prev = read_snapshot_file(&status_json_prev_path(&path)).unwrap();
+        assert_eq!(prev.generated_at, 123);
+    }
+
+    #[test]
+    fn a_writer_replaces_the_previous_generation_over_a_crash_leftover() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = status_json_path(dir.path());
+        let mut writer = StatusFileWriter::new(&path);
+        let mut snapshot = sample_snapshot();
+        for generated_at in [1, 2, 3] {
+            snapshot.generated_at = generated_at;
+            writer
+                .write(&snapshot, StatusWriteOptions::default())
+                .unwrap();
+            if generated_at == 1 {
+                let staged = status_json_prev_path(&path)
+                    .with_extension(format!("prev.tmp.{}", std::process::id()));
+                std::fs::write(staged, b"{").unwrap();
+            }
+        }
+
+        assert_eq!(read_snapshot_file(&path).unwrap().generated_at, 3);
+        let prev = read_snapshot_file(&status_json_prev_path(&path)).unwrap();
+        assert_eq!(prev.generated_at, 2);
+    }
+
+    #[test]
+    fn truncated_or_missing_primary_falls_back_to_the_previous_generation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = status_json_path(dir.path());
+        write_generations(&path);
+
+        let full = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &full[..full.len() / 2]).unwrap();
+        let got = read_status_snapshot_json(&path).unwrap();
+        assert_eq!(got.generated_at, 123);
+        assert!(got.source.degraded);
+
+        std::fs::write(&path, b"").unwrap();
+        assert!(read_status_snapshot_json(&path).unwrap().source.degraded);
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(read_status_snapshot_json(&path).unwrap().source.degraded);
+    }
+
+    #[test]
+    fn a_corrupt_primary_does_not_replace_a_good_previous_generation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = status_json_path(dir.path());
+        write_generations(&path);
+        std::fs::write(&path, b"{\"type\":").unwrap();
+
+        let mut snapshot = sample_snapshot();
+        snapshot.generated_at = 789;
+        write_status_snapshot_json_atomic(&path, &snapshot).unwrap();
+
+        assert_eq!(read_status_snapshot_json(&path).unwrap().generated_at, 789);
+        // The corrupt 456 generation was dropped; 123 is still the fallback.
+        let prev = read_snapshot_file(&status_json_prev_path(&path)).unwrap();
+        assert_eq!(prev.generated_at, 123);
+    }
+
+    #[test]
+    fn without_a_readable_generation_the_primary_error_is_returned() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = status_json_path(dir.path());
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, b"not json").unwrap();
+        std::fs::write(status_json_prev_path(&path), b"").unwrap();
+
+        let err = read_status_snapshot_json(&path).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
     #[test]
     fn deserializes_with_missing_optional_fields() {
         let json = r#"
@@ -1,18 +1,30 @@
-use std::collections::HashMap;
 use std::future::Future;
+use std::io::Read;
 use std::pin::Pin;
-use std::sync::atomic::{AtomicUsize, Ordering};
-
-use tokio::sync::Mutex;
 
 use crate::{Error, Result};
 
 pub(crate) const MTPROTO_ENGINEERED_UPLOAD_MAX_BYTES: usize = 128 * 1024 * 1024;
 
+/// Objects per [`Storage::download_documents`] / [`Storage::upload_documents`] call.
+pub(crate) const BATCH_MAX_OBJECTS: usize = 16;
+/// Restore and verify only batch runs of more than this many small objects.
+pub(crate) const BATCH_MIN_OBJECTS: usize = 4;
+/// Objects up to this size count as small; bigger ones are fetched one by one with progress.
+pub(crate) const BATCH_SMALL_OBJECT_MAX_BYTES: u64 = 512 * 1024;
+
+mod in_memory;
+pub use in_memory::{InMemoryStorage, InMemoryStorageBuilder, StorageCallCounts};
+
+mod local_dir;
+pub use local_dir::LocalDirStorage;
+
 mod telegram_mtproto;
 pub use telegram_mtproto::{
-    TelegramDialogInfo, TelegramMtProtoStorage, TelegramMtProtoStorageConfig, TgMtProtoObjectIdV1,
-    encode_tgmtproto_object_id_v1, parse_tgmtproto_object_id_v1,
+    MtProtoHelperVersion, TelegramDialogInfo, TelegramDocumentBatch, TelegramDocumentInfo,
+    TelegramMtProtoStorage, TelegramMtProtoStorageConfig, TgMtProtoObjectIdV1,
+    encode_tgmtproto_object_id_v1, parse_tgmtproto_object_id_v1, probe_mtproto_helper,
+    split_object_prefix,
 };
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -81,6 +93,111 @@ pub fn parse_chunk_object_ref(encoded: &str) -> Result<ChunkObjectRef> {
     })
 }
 
+/// What an uploaded object holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ObjectKind {
+    Chunk,
+    Pack,
+    IndexPart,
+    IndexManifest,
+    /// An unpinned bootstrap catalog (`bootstrap.pin_mode = "tagged_message"`).
+    BootstrapCatalog,
+}
+
+impl ObjectKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Chunk => "chunk",
+            Self::Pack => "pack",
+            Self::IndexPart => "index-part",
+            Self::IndexManifest => "index-manifest",
+            Self::BootstrapCatalog => "bootstrap-catalog",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "chunk" => Some(Self::Chunk),
+            "pack" => Some(Self::Pack),
+            "index-part" => Some(Self::IndexPart),
+            "index-manifest" => Some(Self::IndexManifest),
+            "bootstrap-catalog" => Some(Self::BootstrapCatalog),
+            _ => None,
+        }
+    }
+}
+
+/// Optional metadata for [`Storage::upload_document_stream_with_metadata`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UploadMetadata {
+    pub kind: ObjectKind,
+}
+
+const OBJECT_CAPTION_PREFIX_V1: &str = "televybackup:v1";
+
+/// Audit label a provider stores next to an object: kind, length and SHA-256 of the uploaded
+/// (encrypted) payload. Nothing derived from plaintext goes in, so captions can't fingerprint
+/// file contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectCaption {
+    pub kind: ObjectKind,
+    pub len: u64,
+    /// Lowercase hex.
+    pub sha256: String,
+}
+
+impl ObjectCaption {
+    pub fn for_payload(kind: ObjectKind, payload: &[u8]) -> Self {
+        use sha2::Digest;
+        Self {
+            kind,
+            len: payload.len() as u64,
+            sha256: hex::encode(sha2::Sha256::digest(payload)),
+        }
+    }
+
+    /// `televybackup:v1 kind=chunk len=1234 sha256=<hex>`
+    pub fn encode(&self) -> String {
+        format!(
+            "{OBJECT_CAPTION_PREFIX_V1} kind={} len={} sha256={}",
+            self.kind.as_str(),
+            self.len,
+            self.sha256
+        )
+    }
+
+    /// `None` for anything that isn't a caption written by [`ObjectCaption::encode`].
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut fields = text.split_whitespace();
+        if fields.next()? != OBJECT_CAPTION_PREFIX_V1 {
+            return None;
+        }
+        let (mut kind, mut len, mut sha256) = (None, None, None);
+        for field in fields {
+            match field.split_once('=')? {
+                ("kind", v) => kind = ObjectKind::parse(v),
+                ("len", v) => len = v.parse().ok(),
+                ("sha256", v)
+                    if v.len() == 64
+                        && v.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) =>
+                {
+                    sha256 = Some(v.to_string())
+                }
+                _ => {}
+            }
+        }
+        Some(Self {
+            kind: kind?,
+            len: len?,
+            sha256: sha256?,
+        })
+    }
+
+    pub fn matches(&self, payload: &[u8]) -> bool {
+        *self == Self::for_payload(self.kind, payload)
+    }
+}
+
 /// Best-effort progress update emitted by storage providers.
 ///
 /// - `bytes`: cumulative *payload* bytes transferred for this invocation (monotonic; starts at 0).
@@ -93,6 +210,33 @@ pub struct StorageProgress {
     pub net_bytes: Option<u64>,
 }
 
+/// Upload body for [`Storage::upload_document_stream`]; read incrementally by the provider.
+pub type UploadBody<'a> = Box<dyn Read + Send + 'a>;
+
+/// Per-object results of [`Storage::download_documents`], in request order.
+pub type DownloadBatch = Vec<Result<Vec<u8>>>;
+
+/// Read granularity used when draining an [`UploadBody`].
+pub(crate) const UPLOAD_BODY_READ_BYTES: usize = 256 * 1024;
+
+/// Buffers a whole upload body (exactly `len` bytes) for providers without streaming support.
+fn read_upload_body(mut body: UploadBody<'_>, len: u64) -> Result<Vec<u8>> {
+    let cap = usize::try_from(len).map_err(|_| Error::InvalidConfig {
+        message: format!("upload body too large: {len}"),
+    })?;
+    let mut bytes = Vec::with_capacity(cap);
+    body.by_ref().take(len).read_to_end(&mut bytes)?;
+    if bytes.len() != cap {
+        return Err(Error::Integrity {
+            message: format!(
+                "upload body ended early: expected={len} got={}",
+                bytes.len()
+            ),
+        });
+    }
+    Ok(bytes)
+}
+
 pub trait Storage {
     fn provider(&self) -> &str;
 
@@ -104,6 +248,19 @@ pub trait Storage {
         None
     }
 
+    /// Earlier scopes whose object IDs are still readable through this storage (e.g. the chat_id
+    /// of a Telegram group before it was upgraded to a supergroup).
+    fn legacy_object_id_scopes(&self) -> &[String] {
+        &[]
+    }
+
+    /// Start of the names given to uploaded documents (`telegram_endpoints[].object_prefix`).
+    /// Recorded next to object ids to tell which machine uploaded an object; never used to find
+    /// one.
+    fn object_prefix(&self) -> Option<&str> {
+        None
+    }
+
     fn upload_document<'a>(
         &'a self,
         filename: &'a str,
@@ -127,6 +284,39 @@ pub trait Storage {
         self.upload_document(filename, bytes)
     }
 
+    /// Upload a document whose `len` bytes are produced incrementally by `body`.
+    ///
+    /// Providers that can forward bytes as they are read override this so large objects never
+    /// have to be materialized in memory. The default buffers the body and delegates to
+    /// [`Storage::upload_document_with_progress`]. Progress semantics are the same.
+    fn upload_document_stream<'a>(
+        &'a self,
+        filename: &'a str,
+        body: UploadBody<'a>,
+        len: u64,
+        progress: Option<Box<dyn FnMut(StorageProgress) + Send + 'a>>,
+    ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>> {
+        match read_upload_body(body, len) {
+            Ok(bytes) => self.upload_document_with_progress(filename, bytes, progress),
+            Err(e) => Box::pin(async move { Err(e) }),
+        }
+    }
+
+    /// [`Storage::upload_document_stream`] with metadata the provider may keep next to the object
+    /// (the MTProto provider writes an [`ObjectCaption`] into the document caption). The default
+    /// ignores `metadata`.
+    fn upload_document_stream_with_metadata<'a>(
+        &'a self,
+        filename: &'a str,
+        body: UploadBody<'a>,
+        len: u64,
+        progress: Option<Box<dyn FnMut(StorageProgress) + Send + 'a>>,
+        metadata: Option<UploadMetadata>,
+    ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>> {
+        let _ = metadata;
+        self.upload_document_stream(filename, body, len, progress)
+    }
+
     fn download_document<'a>(
         &'a self,
         object_id: &'a str,
@@ -147,63 +337,68 @@ pub trait Storage {
         let _ = progress;
         self.download_document(object_id)
     }
-}
-
-#[derive(Debug, Default)]
-pub struct InMemoryStorage {
-    pub uploaded: AtomicUsize,
-    inner: Mutex<HashMap<String, Vec<u8>>>,
-}
 
-impl InMemoryStorage {
-    pub fn new() -> Self {
-        Self::default()
-    }
-
-    pub async fn get(&self, object_id: &str) -> Option<Vec<u8>> {
-        self.inner.lock().await.get(object_id).cloned()
-    }
-
-    pub async fn remove(&self, object_id: &str) -> Option<Vec<u8>> {
-        self.inner.lock().await.remove(object_id)
-    }
-
-    pub async fn object_count(&self) -> usize {
-        self.inner.lock().await.len()
+    /// Size of a stored object as the provider reports it, without downloading it. Backups compare
+    /// it with the uploaded length before indexing the object. `None` means the provider has no
+    /// cheap way to tell (the default); an object that is not there is an [`Error::Integrity`].
+    fn object_size<'a>(
+        &'a self,
+        object_id: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<u64>>> + Send + 'a>> {
+        let _ = object_id;
+        Box::pin(async { Ok(None) })
     }
-}
 
-impl Storage for InMemoryStorage {
-    fn provider(&self) -> &str {
-        "test.mem"
+    /// Downloads several documents at once; the result holds one entry per id, in order, so a
+    /// missing object fails only its own entry. The outer error means the whole batch failed.
+    ///
+    /// Providers that can pipeline requests override this; the default downloads one at a time.
+    fn download_documents<'a>(
+        &'a self,
+        object_ids: &'a [String],
+    ) -> Pin<Box<dyn Future<Output = Result<DownloadBatch>> + Send + 'a>> {
+        let downloads = object_ids
+            .iter()
+            .map(|object_id| self.download_document(object_id))
+            .collect::<Vec<_>>();
+        Box::pin(async move {
+            let mut out = Vec::with_capacity(downloads.len());
+            for download in downloads {
+                out.push(download.await);
+            }
+            Ok(out)
+        })
     }
 
-    fn upload_document<'a>(
+    /// Uploads several `(filename, bytes)` documents at once, returning their object ids in order.
+    /// The default uploads one at a time.
+    fn upload_documents<'a>(
         &'a self,
-        _filename: &'a str,
-        bytes: Vec<u8>,
-    ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>> {
+        items: Vec<(&'a str, Vec<u8>)>,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<String>>> + Send + 'a>> {
+        let uploads = items
+            .into_iter()
+            .map(|(filename, bytes)| self.upload_document(filename, bytes))
+            .collect::<Vec<_>>();
         Box::pin(async move {
-            let object_id = format!("mem:{}", uuid::Uuid::new_v4());
-            self.inner.lock().await.insert(object_id.clone(), bytes);
-            self.uploaded.fetch_add(1, Ordering::Relaxed);
-            Ok(object_id)
+            let mut out = Vec::with_capacity(uploads.len());
+            for upload in uploads {
+                out.push(upload.await?);
+            }
+            Ok(out)
         })
     }
 
-    fn download_document<'a>(
+    /// Deletes a stored object (`televybackup gc run`). Deleting an object that is already gone
+    /// succeeds. The default refuses, for providers that cannot delete.
+    fn delete_document<'a>(
         &'a self,
         object_id: &'a str,
-    ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>>> + Send + 'a>> {
-        Box::pin(async move {
-            self.inner
-                .lock()
-                .await
-                .get(object_id)
-                .cloned()
-                .ok_or_else(|| Error::InvalidConfig {
-                    message: format!("object not found: {object_id}"),
-                })
-        })
+    ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+        let message = format!(
+            "storage provider {} cannot delete objects: {object_id}",
+            self.provider()
+        );
+        Box::pin(async move { Err(Error::InvalidConfig { message }) })
     }
 }
@@ -0,0 +1,423 @@
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::io::Read;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use tokio::sync::Mutex;
+
+use super::{Storage, StorageProgress, UPLOAD_BODY_READ_BYTES, UploadBody};
+use crate::{Error, Result};
+
+/// Storage kept in memory, for tests. Always succeeds instantly unless faults are injected,
+/// either up front through [`InMemoryStorage::builder`] or later through the methods of the same
+/// names.
+#[derive(Debug, Default)]
+pub struct InMemoryStorage {
+    pub uploaded: AtomicUsize,
+    inner: Mutex<HashMap<String, Vec<u8>>>,
+    faults: std::sync::Mutex<FaultPlan>,
+    calls: CallCounters,
+    object_prefix: Option<String>,
+}
+
+/// Calls an [`InMemoryStorage`] received, failed ones included. Batch calls count once per
+/// object.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StorageCallCounts {
+    pub uploads: usize,
+    pub downloads: usize,
+    pub deletes: usize,
+    /// [`Storage::object_size`] calls.
+    pub size_checks: usize,
+}
+
+#[derive(Debug, Default)]
+struct CallCounters {
+    uploads: AtomicUsize,
+    downloads: AtomicUsize,
+    deletes: AtomicUsize,
+    size_checks: AtomicUsize,
+}
+
+#[derive(Debug, Default)]
+struct FaultPlan {
+    latency: Duration,
+    upload_failures: usize,
+    upload_error: Option<Error>,
+    /// Uploads left to store without their last byte, like a transfer cut short.
+    truncated_uploads: usize,
+    /// Download as missing, like a deleted message.
+    dropped: HashSet<String>,
+    /// Download with the last byte flipped.
+    corrupted: HashSet<String>,
+}
+
+/// Builds an [`InMemoryStorage`] with a fault plan:
+///
+/// ```
+/// # use std::time::Duration;
+/// # use televy_backup_core::{Error, InMemoryStorage};
+/// let storage = InMemoryStorage::builder()
+///     .fail_uploads(2, Error::telegram("FLOOD_WAIT_1"))
+///     .latency(Duration::from_millis(20))
+///     .build();
+/// ```
+#[derive(Debug, Default)]
+pub struct InMemoryStorageBuilder {
+    faults: FaultPlan,
+    object_prefix: Option<String>,
+}
+
+impl InMemoryStorageBuilder {
+    /// Delay before every upload, download and delete.
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.faults.latency = latency;
+        self
+    }
+
+    /// Fails the next `n` uploads with copies of `error` (see [`InMemoryStorage::fail_uploads`]).
+    pub fn fail_uploads(mut self, n: usize, error: Error) -> Self {
+        self.faults.upload_failures = n;
+        self.faults.upload_error = Some(error);
+        self
+    }
+
+    /// Stores the next `n` uploads without their last byte (see
+    /// [`InMemoryStorage::truncate_uploads`]).
+    pub fn truncate_uploads(mut self, n: usize) -> Self {
+        self.faults.truncated_uploads = n;
+        self
+    }
+
+    /// Serves `object_id` as missing, as if its message was deleted.
+    pub fn drop_object(mut self, object_id: impl Into<String>) -> Self {
+        self.faults.dropped.insert(object_id.into());
+        self
+    }
+
+    /// Serves `object_id` with its last byte flipped.
+    pub fn corrupt_object(mut self, object_id: impl Into<String>) -> Self {
+        self.faults.corrupted.insert(object_id.into());
+        self
+    }
+
+    /// Reports `prefix` as [`Storage::object_prefix`].
+    pub fn object_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.object_prefix = Some(prefix.into());
+        self
+    }
+
+    pub fn build(self) -> InMemoryStorage {
+        InMemoryStorage {
+            faults: std::sync::Mutex::new(self.faults),
+            object_prefix: self.object_prefix,
+            ..InMemoryStorage::default()
+        }
+    }
+}
+
+impl InMemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn builder() -> InMemoryStorageBuilder {
+        InMemoryStorageBuilder::default()
+    }
+
+    pub async fn get(&self, object_id: &str) -> Option<Vec<u8>> {
+        self.inner.lock().await.get(object_id).cloned()
+    }
+
+    pub async fn remove(&self, object_id: &str) -> Option<Vec<u8>> {
+        self.inner.lock().await.remove(object_id)
+    }
+
+    /// Overwrites an object in place (tests use this to simulate tampering).
+    pub async fn replace(&self, object_id: &str, bytes: Vec<u8>) -> Option<Vec<u8>> {
+        self.inner.lock().await.insert(object_id.to_string(), bytes)
+    }
+
+    pub async fn object_count(&self) -> usize {
+        self.inner.lock().await.len()
+    }
+
+    pub fn calls(&self) -> StorageCallCounts {
+        StorageCallCounts {
+            uploads: self.calls.uploads.load(Ordering::Relaxed),
+            downloads: self.calls.downloads.load(Ordering::Relaxed),
+            deletes: self.calls.deletes.load(Ordering::Relaxed),
+            size_checks: self.calls.size_checks.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Fails the next `n` uploads with copies of `error`. Telegram, integrity, config, I/O and
+    /// cancellation errors are copied exactly; other variants come back as
+    /// [`Error::InvalidConfig`] with the same message.
+    pub fn fail_uploads(&self, n: usize, error: Error) {
+        let mut faults = self.faults();
+        faults.upload_failures = n;
+        faults.upload_error = Some(error);
+    }
+
+    /// Stores the next `n` uploads without their last byte. The upload itself succeeds; only
+    /// reading the object back (or its size) shows the damage.
+    pub fn truncate_uploads(&self, n: usize) {
+        self.faults().truncated_uploads = n;
+    }
+
+    pub fn drop_object(&self, object_id: &str) {
+        self.faults().dropped.insert(object_id.to_string());
+    }
+
+    pub fn corrupt_object(&self, object_id: &str) {
+        self.faults().corrupted.insert(object_id.to_string());
+    }
+
+    fn faults(&self) -> std::sync::MutexGuard<'_, FaultPlan> {
+        self.faults.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Counts the call and waits out the injected latency.
+    async fn begin_call(&self, counter: &AtomicUsize) {
+        counter.fetch_add(1, Ordering::Relaxed);
+        let latency = self.faults().latency;
+        if !latency.is_zero() {
+            tokio::time::sleep(latency).await;
+        }
+    }
+
+    fn take_upload_failure(&self) -> Option<Error> {
+        let mut faults = self.faults();
+        if faults.upload_failures == 0 {
+            return None;
+        }
+        faults.upload_failures -= 1;
+        faults.upload_error.as_ref().map(copy_error)
+    }
+
+    fn take_truncated_upload(&self) -> bool {
+        let mut faults = self.faults();
+        if faults.truncated_uploads == 0 {
+            return false;
+        }
+        faults.truncated_uploads -= 1;
+        true
+    }
+}
+
+fn copy_error(e: &Error) -> Error {
+    match e {
+        Error::Telegram {
+            message,
+            kind,
+            wait_seconds,
+        } => Error::Telegram {
+            message: message.clone(),
+            kind: *kind,
+            wait_seconds: *wait_seconds,
+        },
+        Error::Integrity { message } => Error::Integrity {
+            message: message.clone(),
+        },
+        Error::InvalidConfig { message } => Error::InvalidConfig {
+            message: message.clone(),
+        },
+        Error::Io(e) => Error::Io(std::io::Error::new(e.kind(), e.to_string())),
+        Error::Cancelled => Error::Cancelled,
+        other => Error::InvalidConfig {
+            message: other.to_string(),
+        },
+    }
+}
+
+impl Storage for InMemoryStorage {
+    fn provider(&self) -> &str {
+        "test.mem"
+    }
+
+    fn object_prefix(&self) -> Option<&str> {
+        self.object_prefix.as_deref()
+    }
+
+    fn upload_document<'a>(
+        &'a self,
+        _filename: &'a str,
+        mut bytes: Vec<u8>,
+    ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>> {
+        Box::pin(async move {
+            self.begin_call(&self.calls.uploads).await;
+            if let Some(e) = self.take_upload_failure() {
+                return Err(e);
+            }
+            if self.take_truncated_upload() {
+                bytes.pop();
+            }
+            let object_id = format!("mem:{}", uuid::Uuid::new_v4());
+            self.inner.lock().await.insert(object_id.clone(), bytes);
+            self.uploaded.fetch_add(1, Ordering::Relaxed);
+            Ok(object_id)
+        })
+    }
+
+    fn upload_document_stream<'a>(
+        &'a self,
+        filename: &'a str,
+        mut body: UploadBody<'a>,
+        len: u64,
+        mut progress: Option<Box<dyn FnMut(StorageProgress) + Send + 'a>>,
+    ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            let mut buf = vec![0u8; UPLOAD_BODY_READ_BYTES];
+            while (bytes.len() as u64) < len {
+                let want = (len - bytes.len() as u64).min(buf.len() as u64) as usize;
+                let n = body.read(&mut buf[..want])?;
+                if n == 0 {
+                    return Err(Error::Integrity {
+                        message: format!(
+                            "upload body ended early: expected={len} got={}",
+                            bytes.len()
+                        ),
+                    });
+                }
+                bytes.extend_from_slice(&buf[..n]);
+                if let Some(cb) = progress.as_mut() {
+                    cb(StorageProgress {
+                        bytes: bytes.len() as u64,
+                        net_bytes: None,
+                    });
+                }
+            }
+            self.upload_document(filename, bytes).await
+        })
+    }
+
+    fn download_document<'a>(
+        &'a self,
+        object_id: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>>> + Send + 'a>> {
+        Box::pin(async move {
+            self.begin_call(&self.calls.downloads).await;
+            let (dropped, corrupted) = {
+                let faults = self.faults();
+                (
+                    faults.dropped.contains(object_id),
+                    faults.corrupted.contains(object_id),
+                )
+            };
+            let bytes = if dropped {
+                None
+            } else {
+                self.inner.lock().await.get(object_id).cloned()
+            };
+            let mut bytes = bytes.ok_or_else(|| Error::InvalidConfig {
+                message: format!("object not found: {object_id}"),
+            })?;
+            if corrupted && let Some(last) = bytes.last_mut() {
+                *last ^= 0xFF;
+            }
+            Ok(bytes)
+        })
+    }
+
+    fn object_size<'a>(
+        &'a self,
+        object_id: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<u64>>> + Send + 'a>> {
+        Box::pin(async move {
+            self.begin_call(&self.calls.size_checks).await;
+            let dropped = self.faults().dropped.contains(object_id);
+            let size = if dropped {
+                None
+            } else {
+                self.inner
+                    .lock()
+                    .await
+                    .get(object_id)
+                    .map(|b| b.len() as u64)
+            };
+            size.map(Some).ok_or_else(|| Error::Integrity {
+                message: format!("object not found: {object_id}"),
+            })
+        })
+    }
+
+    fn delete_document<'a>(
+        &'a self,
+        object_id: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+        Box::pin(async move {
+            self.begin_call(&self.calls.deletes).await;
+            self.inner.lock().await.remove(object_id);
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn injected_faults_and_call_counts() {
+        let storage = InMemoryStorage::builder()
+            .fail_uploads(2, Error::telegram("FLOOD_WAIT_3"))
+            .build();
+
+        for _ in 0..2 {
+            let err = storage.upload_document("a", b"abc".to_vec()).await;
+            assert!(matches!(
+                err,
+                Err(Error::Telegram {
+                    wait_seconds: Some(3),
+                    ..
+                })
+            ));
+        }
+        let id = storage.upload_document("a", b"abc".to_vec()).await.unwrap();
+        assert_eq!(storage.uploaded.load(Ordering::Relaxed), 1);
+
+        storage.corrupt_object(&id);
+        assert_eq!(storage.download_document(&id).await.unwrap(), b"ab\x9c");
+        // The stored object itself is intact.
+        assert_eq!(storage.get(&id).await.unwrap(), b"abc");
+
+        assert_eq!(storage.object_size(&id).await.unwrap(), Some(3));
+
+        storage.drop_object(&id);
+        assert!(storage.download_document(&id).await.is_err());
+        assert!(matches!(
+            storage.object_size(&id).await,
+            Err(Error::Integrity { .. })
+        ));
+
+        storage.truncate_uploads(1);
+        let short = storage.upload_document("a", b"abc".to_vec()).await.unwrap();
+        assert_eq!(storage.get(&short).await.unwrap(), b"ab");
+
+        storage.delete_document(&id).await.unwrap();
+        assert_eq!(
+            storage.calls(),
+            StorageCallCounts {
+                uploads: 4,
+                downloads: 2,
+                deletes: 1,
+                size_checks: 2,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn latency_delays_every_call() {
+        let storage = InMemoryStorage::builder()
+            .latency(Duration::from_millis(20))
+            .build();
+        let started = std::time::Instant::now();
+        let id = storage.upload_document("a", b"abc".to_vec()).await.unwrap();
+        storage.download_document(&id).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(40));
+    }
+}
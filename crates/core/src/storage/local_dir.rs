@@ -0,0 +1,144 @@
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+
+use sha2::Digest;
+
+use super::{ObjectKind, Storage};
+use crate::bootstrap::{BootstrapPinMode, PinnedStorage};
+use crate::repo_export::{RepoManifest, object_path, read_repo_manifest, repo_index_db_path};
+use crate::{Error, Result};
+
+/// Storage over a repo export directory (see [`crate::repo_export`]), for restoring and
+/// verifying without Telegram. Objects keep the ids they had in the chat, and the provider,
+/// object id scope and bootstrap catalog come from the export's `repo.json`.
+#[derive(Debug)]
+pub struct LocalDirStorage {
+    root: PathBuf,
+    manifest: RepoManifest,
+}
+
+impl LocalDirStorage {
+    /// Opens the export at `root`; fails unless its `repo.json` is one this build reads.
+    pub fn open(root: impl Into<PathBuf>) -> Result<Self> {
+        let root = root.into();
+        let manifest = read_repo_manifest(&root)?;
+        Ok(Self { root, manifest })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn manifest(&self) -> &RepoManifest {
+        &self.manifest
+    }
+
+    /// The endpoint index DB copied into the export.
+    pub fn index_db_path(&self) -> PathBuf {
+        repo_index_db_path(&self.root, &self.manifest.endpoint_id)
+    }
+
+    fn read_object(&self, object_id: &str) -> Result<Vec<u8>> {
+        match std::fs::read(object_path(&self.root, object_id)) {
+            Ok(bytes) => Ok(bytes),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(Error::Integrity {
+                message: format!("object not found in repo export: {object_id}"),
+            }),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl Storage for LocalDirStorage {
+    fn provider(&self) -> &str {
+        &self.manifest.provider
+    }
+
+    fn object_id_scope(&self) -> Option<&str> {
+        self.manifest.object_id_scope.as_deref()
+    }
+
+    /// Stores `bytes` under `localdir:<sha256 of bytes>`.
+    fn upload_document<'a>(
+        &'a self,
+        _filename: &'a str,
+        bytes: Vec<u8>,
+    ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>> {
+        Box::pin(async move {
+            let object_id = format!("localdir:{}", hex::encode(sha2::Sha256::digest(&bytes)));
+            let path = object_path(&self.root, &object_id);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let tmp = path.with_extension("tmp");
+            std::fs::write(&tmp, &bytes)?;
+            std::fs::rename(&tmp, &path)?;
+            Ok(object_id)
+        })
+    }
+
+    fn download_document<'a>(
+        &'a self,
+        object_id: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>>> + Send + 'a>> {
+        Box::pin(async move { self.read_object(object_id) })
+    }
+
+    fn object_size<'a>(
+        &'a self,
+        object_id: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<u64>>> + Send + 'a>> {
+        Box::pin(async move {
+            match std::fs::metadata(object_path(&self.root, object_id)) {
+                Ok(meta) => Ok(Some(meta.len())),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(Error::Integrity {
+                    message: format!("object not found in repo export: {object_id}"),
+                }),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    fn delete_document<'a>(
+        &'a self,
+        object_id: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+        Box::pin(async move {
+            match std::fs::remove_file(object_path(&self.root, object_id)) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+                _ => Ok(()),
+            }
+        })
+    }
+}
+
+/// The catalog "pin" is the one recorded in `repo.json`; an export is a copy, so it never moves.
+impl PinnedStorage for LocalDirStorage {
+    fn get_pinned_object_id(&self) -> Result<Option<String>> {
+        Ok(self.manifest.bootstrap_catalog_object_id.clone())
+    }
+
+    fn set_pinned_object_id(&self, _object_id: &str) -> Result<()> {
+        Err(Error::InvalidConfig {
+            message: format!(
+                "the bootstrap catalog of a repo export cannot be changed: {}",
+                self.root.display()
+            ),
+        })
+    }
+
+    fn find_recent_object_id(&self, kind: ObjectKind) -> Result<Option<String>> {
+        match kind {
+            ObjectKind::BootstrapCatalog => self.get_pinned_object_id(),
+            _ => Ok(None),
+        }
+    }
+
+    fn bootstrap_pin_mode(&self) -> BootstrapPinMode {
+        match self.manifest.bootstrap_catalog_object_id {
+            Some(_) => BootstrapPinMode::Pin,
+            None => BootstrapPinMode::Disabled,
+        }
+    }
+}
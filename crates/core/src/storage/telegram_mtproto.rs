@@ -3,13 +3,20 @@ use std::io::{BufRead, BufReader, Read, Write};
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Condvar, Mutex, mpsc};
 use std::time::{Duration, Instant};
 
 use base64::Engine;
 use serde::{Deserialize, Serialize};
-
-use super::{Storage, StorageProgress};
+use sha2::Digest;
+
+use super::{
+    DownloadBatch, ObjectCaption, ObjectKind, Storage, StorageProgress, UPLOAD_BODY_READ_BYTES,
+    UploadBody, UploadMetadata,
+};
+use crate::bootstrap::BootstrapPinMode;
+use crate::version::{self, Component, ComponentVersion};
 use crate::{Error, Result};
 
 const TG_MTPROTO_OBJECT_ID_PREFIX_V1: &str = "tgmtproto:v1:";
@@ -19,6 +26,15 @@ const MTPROTO_HELPER_READ_TIMEOUT_SECS: u64 = 600;
 // a stalled helper and fail fast so the caller can retry/respawn instead of freezing.
 const MTPROTO_HELPER_UPLOAD_EVENT_TIMEOUT_SECS: u64 = 45;
 const MTPROTO_HELPER_SHUTDOWN_TIMEOUT_SECS: u64 = 2;
+// Error prefix the helper uses when the configured group was upgraded to a supergroup.
+const CHAT_MIGRATED_ERROR_PREFIX: &str = "chat migrated: ";
+// Helpers reporting at least this `protocolVersion` on init take `download_batch` /
+// `upload_batch`; older ones get the singular commands.
+const MTPROTO_HELPER_BATCH_PROTOCOL_VERSION: u64 = 2;
+// Message ids per `list_documents` call (the helper's cap).
+const LIST_DOCUMENTS_WINDOW: i32 = 100;
+// How many windows back from the end of the chat `find_recent_document` looks.
+const RECENT_DOCUMENT_WINDOWS: i32 = 30;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TgMtProtoObjectIdV1 {
@@ -124,16 +140,26 @@ pub struct TelegramMtProtoStorageConfig {
     pub api_hash: String,
     pub bot_token: String,
     pub chat_id: String,
+    /// Chat ids used before the group was upgraded to a supergroup; objects uploaded there stay
+    /// downloadable.
+    pub migrated_from_chat_ids: Vec<String>,
     pub session: Option<Vec<u8>>,
     pub cache_dir: PathBuf,
     pub min_delay_ms: Option<u64>,
     pub max_concurrent_uploads: Option<usize>,
     pub helper_path: Option<PathBuf>,
+    /// How the bootstrap catalog is kept in the chat (`bootstrap.pin_mode`).
+    pub bootstrap_pin_mode: BootstrapPinMode,
+    /// Put in front of every uploaded document's name (`object_prefix`).
+    pub object_prefix: Option<String>,
 }
 
 pub struct TelegramMtProtoStorage {
     provider: String,
     chat_id: String,
+    migrated_from_chat_ids: Vec<String>,
+    /// Set when `connect` found `config.chat_id` upgraded to a supergroup and switched to it.
+    migrated_from: Option<String>,
     api_id: i32,
     api_hash: String,
     bot_token: String,
@@ -141,15 +167,15 @@ pub struct TelegramMtProtoStorage {
     min_delay_ms: Option<u64>,
     max_concurrent_uploads: Option<usize>,
     helper_path: PathBuf,
+    bootstrap_pin_mode: BootstrapPinMode,
+    object_prefix: Option<String>,
     session: Mutex<Option<Vec<u8>>>,
     helper_pool: MtProtoHelperPool,
 }
 
 impl TelegramMtProtoStorage {
     pub async fn connect(config: TelegramMtProtoStorageConfig) -> Result<Self> {
-        let helper_path = config.helper_path.unwrap_or_else(|| {
-            default_helper_path().unwrap_or_else(|| PathBuf::from("televybackup-mtproto-helper"))
-        });
+        let helper_path = resolve_helper_path(config.helper_path);
 
         let session_b64 = config
             .session
@@ -159,21 +185,25 @@ impl TelegramMtProtoStorage {
         let api_hash = config.api_hash;
         let bot_token = config.bot_token;
         let cache_dir = config.cache_dir;
-        let chat_id = config.chat_id;
+        let mut chat_id = config.chat_id;
+        let mut migrated_from_chat_ids = config.migrated_from_chat_ids;
+        let mut migrated_from = None::<String>;
         let min_delay_ms = config.min_delay_ms;
         let max_concurrent_uploads = config.max_concurrent_uploads;
 
         let pool_size = max_concurrent_uploads.unwrap_or(1).clamp(1, 8);
         let mut helpers = Vec::with_capacity(pool_size);
         let mut primary_session_bytes = None::<Vec<u8>>;
-        for i in 0..pool_size {
+        let mut i = 0;
+        while i < pool_size {
             let is_primary = i == 0;
             let mut helper = MtProtoHelper::spawn(&helper_path)?;
-            helper.init(InitRequest {
+            let init = helper.init(InitRequest {
                 api_id,
                 api_hash: api_hash.clone(),
                 bot_token: bot_token.clone(),
                 chat_id: chat_id.clone(),
+                migrated_from_chat_ids: migrated_from_chat_ids.clone(),
                 // MTProto sessions are not safe to use concurrently across multiple processes.
                 // Only the primary helper (the one whose session we persist) should reuse the
                 // stored session; additional helpers start with a fresh session and authenticate
@@ -186,16 +216,43 @@ impl TelegramMtProtoStorage {
                 cache_dir: cache_dir.clone(),
                 min_delay_ms,
                 max_concurrent_uploads,
-            })?;
+            });
+            match init {
+                Ok(()) => {}
+                // The group was upgraded to a supergroup: follow it once, keeping the old chat
+                // readable for objects uploaded before the upgrade.
+                Err(Error::ChatMigrated {
+                    old_chat_id,
+                    new_chat_id,
+                }) if is_primary && migrated_from.is_none() => {
+                    tracing::warn!(
+                        event = "telegram.chat_migrated",
+                        provider = %config.provider,
+                        old = %old_chat_id,
+                        new = %new_chat_id,
+                        "telegram.chat_migrated"
+                    );
+                    if !migrated_from_chat_ids.contains(&old_chat_id) {
+                        migrated_from_chat_ids.push(old_chat_id.clone());
+                    }
+                    migrated_from = Some(old_chat_id);
+                    chat_id = new_chat_id;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
             if is_primary {
                 primary_session_bytes = helper.session_bytes();
             }
             helpers.push(PooledHelper { helper, is_primary });
+            i += 1;
         }
 
         Ok(Self {
             provider: config.provider,
             chat_id,
+            migrated_from_chat_ids,
+            migrated_from,
             api_id,
             api_hash,
             bot_token,
@@ -203,18 +260,56 @@ impl TelegramMtProtoStorage {
             min_delay_ms,
             max_concurrent_uploads,
             helper_path,
+            bootstrap_pin_mode: config.bootstrap_pin_mode,
+            object_prefix: config.object_prefix,
             session: Mutex::new(primary_session_bytes),
             helper_pool: MtProtoHelperPool::new(helpers),
         })
     }
 
+    /// The chat this storage uploads to (the supergroup after a migration).
+    pub fn chat_id(&self) -> &str {
+        &self.chat_id
+    }
+
+    /// The configured chat id when `connect` followed a group → supergroup migration; callers
+    /// should persist [`Self::chat_id`] for the endpoint.
+    pub fn migrated_from_chat_id(&self) -> Option<&str> {
+        self.migrated_from.as_deref()
+    }
+
+    /// Chat ids before a migration (configured ones plus one detected by `connect`).
+    pub fn migrated_from_chat_ids(&self) -> &[String] {
+        &self.migrated_from_chat_ids
+    }
+
+    fn ensure_object_peer(&self, peer: &str) -> Result<()> {
+        if object_peer_in_scope(peer, &self.chat_id, &self.migrated_from_chat_ids) {
+            return Ok(());
+        }
+        Err(Error::InvalidConfig {
+            message: format!(
+                "tgmtproto peer mismatch: expected={} got={}",
+                self.chat_id, peer
+            ),
+        })
+    }
+
     pub fn session_bytes(&self) -> Option<Vec<u8>> {
         self.session.lock().ok().and_then(|guard| guard.clone())
     }
 
+    /// Name of the Telegram document holding `filename`: `{object_prefix}-{filename}`.
+    fn document_name(&self, filename: &str) -> String {
+        match self.object_prefix.as_deref() {
+            Some(prefix) => format!("{prefix}-{filename}"),
+            None => filename.to_string(),
+        }
+    }
+
     fn should_respawn_helper_after(err: &Error) -> bool {
         match err {
-            Error::Telegram { message } => {
+            Error::Telegram { message, .. } => {
                 message.contains("mtproto helper")
                     || message.to_ascii_lowercase().contains("timed out")
                     || message.contains("save_file_part failed")
@@ -240,6 +335,7 @@ impl TelegramMtProtoStorage {
             api_hash: self.api_hash.clone(),
             bot_token: self.bot_token.clone(),
             chat_id: self.chat_id.clone(),
+            migrated_from_chat_ids: self.migrated_from_chat_ids.clone(),
             session_b64,
             cache_dir: self.cache_dir.clone(),
             min_delay_ms: self.min_delay_ms,
@@ -248,8 +344,8 @@ impl TelegramMtProtoStorage {
 
         *helper = new_helper;
         if is_primary {
-            *self.session.lock().map_err(|_| Error::Telegram {
-                message: "mtproto helper session lock poisoned".to_string(),
+            *self.session.lock().map_err(|_| {
+                Error::telegram("mtproto helper session lock poisoned".to_string())
             })? = helper.session_bytes();
         }
         Ok(())
@@ -288,8 +384,8 @@ impl TelegramMtProtoStorage {
             // run with independent sessions to avoid MTProto seqno/message_id divergence across
             // processes.
             if pooled.is_primary {
-                *self.session.lock().map_err(|_| Error::Telegram {
-                    message: "mtproto helper session lock poisoned".to_string(),
+                *self.session.lock().map_err(|_| {
+                    Error::telegram("mtproto helper session lock poisoned".to_string())
                 })? = helper.session_bytes();
             }
 
@@ -304,7 +400,7 @@ impl TelegramMtProtoStorage {
             self.helper_pool.checkin(pooled);
 
             match res {
-                Ok(v) => v,
+                Ok(v) => v.map_err(chat_migrated_or),
                 Err(panic) => std::panic::resume_unwind(panic),
             }
         })
@@ -334,6 +430,37 @@ impl TelegramMtProtoStorage {
     ) -> Result<TelegramDialogInfo> {
         self.with_helper(|helper| helper.wait_for_chat(timeout_secs, include_users))
     }
+
+    /// One poll of [`Self::wait_for_chat`]: waits up to `wait` and returns `None` if no message
+    /// arrived. `first` drops updates buffered before the call; later polls keep them, so a
+    /// message sent between two polls is not missed. Helpers predating polls wait whole seconds
+    /// and always drop buffered updates.
+    pub fn check_for_new_chat(
+        &self,
+        wait: Duration,
+        include_users: bool,
+        first: bool,
+    ) -> Result<Option<TelegramDialogInfo>> {
+        let req = WaitForChatRequest {
+            timeout_secs: wait.as_secs().max(1),
+            timeout_ms: Some(u64::try_from(wait.as_millis()).unwrap_or(u64::MAX)),
+            include_users,
+            poll: true,
+            drain: Some(first),
+        };
+        match self.with_helper(|helper| helper.wait_for_chat_request(&req)) {
+            Err(Error::Telegram { message, .. }) if message.contains("wait_for_chat timed out") => {
+                Ok(None)
+            }
+            res => res,
+        }
+    }
+
+    /// Document messages with ids in `from_msg_id..from_msg_id + count` (at most 100). Bots can't
+    /// read chat history, so callers walk the chat window by window.
+    pub fn list_documents(&self, from_msg_id: i32, count: usize) -> Result<TelegramDocumentBatch> {
+        self.with_helper(|helper| helper.list_documents(from_msg_id, count))
+    }
 }
 
 struct PooledHelper {
@@ -355,16 +482,18 @@ impl MtProtoHelperPool {
     }
 
     fn checkout(&self) -> Result<PooledHelper> {
-        let mut guard = self.inner.lock().map_err(|_| Error::Telegram {
-            message: "mtproto helper pool lock poisoned".to_string(),
-        })?;
+        let mut guard = self
+            .inner
+            .lock()
+            .map_err(|_| Error::telegram("mtproto helper pool lock poisoned".to_string()))?;
         loop {
             if let Some(h) = guard.pop() {
                 return Ok(h);
             }
-            guard = self.available.wait(guard).map_err(|_| Error::Telegram {
-                message: "mtproto helper pool lock poisoned".to_string(),
-            })?;
+            guard = self
+                .available
+                .wait(guard)
+                .map_err(|_| Error::telegram("mtproto helper pool lock poisoned".to_string()))?;
         }
     }
 
@@ -393,6 +522,83 @@ impl crate::bootstrap::PinnedStorage for TelegramMtProtoStorage {
         }
         self.pin_message_id(parsed.msg_id)
     }
+
+    fn find_recent_object_id(&self, kind: ObjectKind) -> Result<Option<String>> {
+        let doc = find_recent_document(
+            |from_msg_id, count| self.list_documents(from_msg_id, count),
+            |d| {
+                d.caption
+                    .as_deref()
+                    .and_then(ObjectCaption::parse)
+                    .is_some_and(|c| c.kind == kind && c.len == d.size)
+            },
+        )?;
+        doc.map(|d| encode_tgmtproto_object_id_v1(&self.chat_id, d.msg_id, d.doc_id, d.access_hash))
+            .transpose()
+    }
+
+    fn bootstrap_pin_mode(&self) -> BootstrapPinMode {
+        self.bootstrap_pin_mode
+    }
+}
+
+/// Newest document matching `is_match` among the last [`RECENT_DOCUMENT_WINDOWS`] windows of
+/// message ids; `list` is [`TelegramMtProtoStorage::list_documents`].
+///
+/// Bots can neither read nor search chat history, so the end of the chat is found by probing
+/// windows at doubling distances and bisecting. Best effort: a window-sized run of deleted
+/// messages where a probe lands looks like the end of the chat.
+fn find_recent_document(
+    mut list: impl FnMut(i32, usize) -> Result<TelegramDocumentBatch>,
+    is_match: impl Fn(&TelegramDocumentInfo) -> bool,
+) -> Result<Option<TelegramDocumentInfo>> {
+    const W: i32 = LIST_DOCUMENTS_WINDOW;
+
+    // `lo` starts a window with messages, `hi` one without (past the end of the chat).
+    let mut lo = None::<i32>;
+    let mut probe = 1i32;
+    let mut step = W;
+    let mut hi = loop {
+        if list(probe, W as usize)?.messages > 0 {
+            lo = Some(probe);
+        } else if lo.is_some() {
+            break probe;
+        }
+        match probe.checked_add(step) {
+            Some(next) if next <= i32::MAX - W => probe = next,
+            _ if lo.is_some() => break i32::MAX - W,
+            _ => return Ok(None),
+        }
+        step = step.saturating_mul(2);
+    };
+    let mut lo = lo.expect("set before breaking");
+    while hi - lo > W {
+        let mid = lo + (hi - lo) / 2;
+        if list(mid, W as usize)?.messages > 0 {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+
+    let mut end = lo + W;
+    for _ in 0..RECENT_DOCUMENT_WINDOWS {
+        if end <= 1 {
+            break;
+        }
+        let start = (end - W).max(1);
+        let batch = list(start, (end - start) as usize)?;
+        if let Some(doc) = batch
+            .documents
+            .into_iter()
+            .filter(|d| is_match(d))
+            .max_by_key(|d| d.msg_id)
+        {
+            return Ok(Some(doc));
+        }
+        end = start;
+    }
+    Ok(None)
 }
 
 impl Storage for TelegramMtProtoStorage {
@@ -404,37 +610,72 @@ impl Storage for TelegramMtProtoStorage {
         Some(&self.chat_id)
     }
 
+    fn legacy_object_id_scopes(&self) -> &[String] {
+        &self.migrated_from_chat_ids
+    }
+
+    fn object_prefix(&self) -> Option<&str> {
+        self.object_prefix.as_deref()
+    }
+
     fn upload_document<'a>(
         &'a self,
         filename: &'a str,
         bytes: Vec<u8>,
     ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>> {
-        Box::pin(async move {
-            let resp = self.with_helper(|helper| {
-                helper.upload(UploadRequest {
-                    filename: filename.to_string(),
-                    bytes,
-                })
-            })?;
-            Ok(resp)
-        })
+        self.upload_document_with_progress(filename, bytes, None)
     }
 
     fn upload_document_with_progress<'a>(
         &'a self,
         filename: &'a str,
         bytes: Vec<u8>,
+        progress: Option<Box<dyn FnMut(StorageProgress) + Send + 'a>>,
+    ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>> {
+        let len = bytes.len() as u64;
+        self.upload_document_stream(
+            filename,
+            Box::new(std::io::Cursor::new(bytes)),
+            len,
+            progress,
+        )
+    }
+
+    fn upload_document_stream<'a>(
+        &'a self,
+        filename: &'a str,
+        body: UploadBody<'a>,
+        len: u64,
+        progress: Option<Box<dyn FnMut(StorageProgress) + Send + 'a>>,
+    ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>> {
+        self.upload_document_stream_with_metadata(filename, body, len, progress, None)
+    }
+
+    fn upload_document_stream_with_metadata<'a>(
+        &'a self,
+        filename: &'a str,
+        body: UploadBody<'a>,
+        len: u64,
         mut progress: Option<Box<dyn FnMut(StorageProgress) + Send + 'a>>,
+        metadata: Option<UploadMetadata>,
     ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>> {
         Box::pin(async move {
+            // A retried helper needs the body from the start, which a reader cannot provide; the
+            // caller's retry loop builds a fresh body instead.
+            let mut body = Some(body);
             let resp = self.with_helper(|helper| {
+                let body = body.take().ok_or_else(|| {
+                    Error::telegram("mtproto upload body already consumed".to_string())
+                })?;
                 let progress = progress
                     .as_deref_mut()
                     .map(|cb| cb as &mut dyn FnMut(StorageProgress));
                 helper.upload_with_progress(
                     UploadRequest {
-                        filename: filename.to_string(),
-                        bytes,
+                        filename: self.document_name(filename),
+                        body,
+                        len,
+                        caption_kind: metadata.map(|m| m.kind),
                     },
                     progress,
                 )
@@ -449,14 +690,7 @@ impl Storage for TelegramMtProtoStorage {
     ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>>> + Send + 'a>> {
         Box::pin(async move {
             let parsed = parse_tgmtproto_object_id_v1(object_id)?;
-            if parsed.peer != self.chat_id {
-                return Err(Error::InvalidConfig {
-                    message: format!(
-                        "tgmtproto peer mismatch: expected={} got={}",
-                        self.chat_id, parsed.peer
-                    ),
-                });
-            }
+            self.ensure_object_peer(&parsed.peer)?;
 
             let resp = self.with_helper(|helper| {
                 helper.download(DownloadRequest {
@@ -474,14 +708,7 @@ impl Storage for TelegramMtProtoStorage {
     ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>>> + Send + 'a>> {
         Box::pin(async move {
             let parsed = parse_tgmtproto_object_id_v1(object_id)?;
-            if parsed.peer != self.chat_id {
-                return Err(Error::InvalidConfig {
-                    message: format!(
-                        "tgmtproto peer mismatch: expected={} got={}",
-                        self.chat_id, parsed.peer
-                    ),
-                });
-            }
+            self.ensure_object_peer(&parsed.peer)?;
 
             let resp = self.with_helper(|helper| {
                 let progress = progress
@@ -497,6 +724,115 @@ impl Storage for TelegramMtProtoStorage {
             Ok(resp)
         })
     }
+
+    fn object_size<'a>(
+        &'a self,
+        object_id: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<u64>>> + Send + 'a>> {
+        Box::pin(async move {
+            let parsed = parse_tgmtproto_object_id_v1(object_id)?;
+            self.ensure_object_peer(&parsed.peer)?;
+
+            // The message listing carries the document size, so one window of a single id is
+            // enough; nothing is downloaded.
+            let batch = self.list_documents(parsed.msg_id, 1)?;
+            let doc = batch
+                .documents
+                .into_iter()
+                .find(|d| d.msg_id == parsed.msg_id && d.doc_id == parsed.doc_id)
+                .ok_or_else(|| Error::Integrity {
+                    message: format!(
+                        "uploaded document not found: msg_id={} doc_id={}",
+                        parsed.msg_id, parsed.doc_id
+                    ),
+                })?;
+            Ok(Some(doc.size))
+        })
+    }
+
+    fn download_documents<'a>(
+        &'a self,
+        object_ids: &'a [String],
+    ) -> Pin<Box<dyn Future<Output = Result<DownloadBatch>> + Send + 'a>> {
+        Box::pin(async move {
+            for object_id in object_ids {
+                let parsed = parse_tgmtproto_object_id_v1(object_id)?;
+                self.ensure_object_peer(&parsed.peer)?;
+            }
+
+            self.with_helper(|helper| {
+                if helper.supports_batches() {
+                    return helper.download_batch(object_ids);
+                }
+                let mut out = Vec::with_capacity(object_ids.len());
+                for object_id in object_ids {
+                    match helper.download(DownloadRequest {
+                        object_id: object_id.clone(),
+                    }) {
+                        // The helper itself is unhealthy; fail the batch so it gets respawned.
+                        Err(e) if Self::should_respawn_helper_after(&e) => return Err(e),
+                        res => out.push(res),
+                    }
+                }
+                Ok(out)
+            })
+        })
+    }
+
+    fn upload_documents<'a>(
+        &'a self,
+        items: Vec<(&'a str, Vec<u8>)>,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<String>>> + Send + 'a>> {
+        Box::pin(async move {
+            let names = items
+                .iter()
+                .map(|(filename, _)| self.document_name(filename))
+                .collect::<Vec<_>>();
+            let items = names
+                .iter()
+                .map(String::as_str)
+                .zip(items.into_iter().map(|(_, bytes)| bytes))
+                .collect::<Vec<_>>();
+            self.with_helper(|helper| {
+                if helper.supports_batches() {
+                    return helper.upload_batch(items);
+                }
+                let mut out = Vec::with_capacity(items.len());
+                for (filename, bytes) in items {
+                    let len = bytes.len() as u64;
+                    out.push(helper.upload_with_progress(
+                        UploadRequest {
+                            filename: filename.to_string(),
+                            body: Box::new(std::io::Cursor::new(bytes)),
+                            len,
+                            caption_kind: None,
+                        },
+                        None,
+                    )?);
+                }
+                Ok(out)
+            })
+        })
+    }
+
+    fn delete_document<'a>(
+        &'a self,
+        object_id: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+        Box::pin(async move {
+            let parsed = parse_tgmtproto_object_id_v1(object_id)?;
+            // Messages of a chat before its migration are read-only from the new chat.
+            if parsed.peer != self.chat_id {
+                return Err(Error::InvalidConfig {
+                    message: format!(
+                        "object belongs to another chat (peer={}): {object_id}",
+                        parsed.peer
+                    ),
+                });
+            }
+            self.with_helper(|helper| helper.delete(parsed.msg_id))
+        })
+    }
 }
 
 impl Drop for MtProtoHelper {
@@ -505,6 +841,44 @@ impl Drop for MtProtoHelper {
     }
 }
 
+/// Whether an object uploaded to `peer` can be read through a storage bound to `chat_id`.
+fn object_peer_in_scope(peer: &str, chat_id: &str, migrated_from_chat_ids: &[String]) -> bool {
+    peer == chat_id || migrated_from_chat_ids.iter().any(|id| id == peer)
+}
+
+/// Parses the helper's `chat migrated: old=<id> new=<id>` error into `(old, new)`.
+fn parse_chat_migrated_message(message: &str) -> Option<(String, String)> {
+    let rest =
+        &message[message.find(CHAT_MIGRATED_ERROR_PREFIX)? + CHAT_MIGRATED_ERROR_PREFIX.len()..];
+    let mut old_chat_id = None;
+    let mut new_chat_id = None;
+    for field in rest.split_whitespace() {
+        if let Some(v) = field.strip_prefix("old=") {
+            old_chat_id = Some(v.to_string());
+        } else if let Some(v) = field.strip_prefix("new=") {
+            new_chat_id = Some(v.to_string());
+        }
+    }
+    match (old_chat_id, new_chat_id) {
+        (Some(old), Some(new)) if !old.is_empty() && !new.is_empty() => Some((old, new)),
+        _ => None,
+    }
+}
+
+fn chat_migrated_or(err: Error) -> Error {
+    let message = match &err {
+        Error::Telegram { message, .. } | Error::InvalidConfig { message } => message,
+        _ => return err,
+    };
+    match parse_chat_migrated_message(message) {
+        Some((old_chat_id, new_chat_id)) => Error::ChatMigrated {
+            old_chat_id,
+            new_chat_id,
+        },
+        None => err,
+    }
+}
+
 fn maybe_block_in_place<T>(f: impl FnOnce() -> T) -> T {
     match tokio::runtime::Handle::try_current() {
         Ok(handle)
@@ -519,6 +893,116 @@ fn maybe_block_in_place<T>(f: impl FnOnce() -> T) -> T {
     }
 }
 
+/// What a helper binary reports about itself (see [`probe_mtproto_helper`]).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MtProtoHelperVersion {
+    pub path: PathBuf,
+    /// `None` for helpers predating the version handshake.
+    pub version: Option<String>,
+    pub protocol_version: u64,
+}
+
+impl MtProtoHelperVersion {
+    pub fn component_version(&self) -> ComponentVersion {
+        ComponentVersion {
+            component: Component::Helper,
+            version: self.version.clone(),
+            schema: Some(self.protocol_version),
+        }
+    }
+
+    fn from_envelope(path: &Path, env: &ResponseEnvelope) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            version: env
+                .data
+                .get("helperVersion")
+                .and_then(|v| v.as_str())
+                .map(str::to_string),
+            protocol_version: env
+                .data
+                .get("protocolVersion")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(1),
+        }
+    }
+}
+
+/// Asks the helper `connect` would start (or `helper_path`) for its version, without logging in.
+/// Names uploaded without a prefix that contain a `-` themselves.
+const DASHED_DOCUMENT_NAMES: &[&str] = &[
+    "televybackup-bootstrap.catalog",
+    "televybackup-dedupe.catalog",
+];
+
+/// Splits a document name written by [`TelegramMtProtoStorage`] into its `object_prefix` (`None`
+/// for documents uploaded without one) and the name the caller uploaded.
+pub fn split_object_prefix(file_name: &str) -> (Option<&str>, &str) {
+    let base_start = DASHED_DOCUMENT_NAMES
+        .iter()
+        .find_map(|base| file_name.strip_suffix(base).map(str::len))
+        .or_else(|| file_name.rfind('-').map(|i| i + 1))
+        .unwrap_or(0);
+    let (head, base) = file_name.split_at(base_start);
+    (head.strip_suffix('-').filter(|p| !p.is_empty()), base)
+}
+
+pub fn probe_mtproto_helper(helper_path: Option<PathBuf>) -> Result<MtProtoHelperVersion> {
+    let path = resolve_helper_path(helper_path);
+    let mut helper = MtProtoHelper::spawn(&path)?;
+    helper.send_json(&Request::Version)?;
+    let env = helper.read_json_line_with_timeout(MTPROTO_HELPER_SHUTDOWN_TIMEOUT_SECS * 5)?;
+    // Helpers predating the handshake reject the command; they also predate protocol 2.
+    Ok(MtProtoHelperVersion::from_envelope(&path, &env))
+}
+
+/// The binary this process runs as, for naming it in skew messages.
+fn host_component() -> Component {
+    let is_daemon = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.file_stem().map(|s| s.to_string_lossy().into_owned()))
+        .is_some_and(|name| name == "televybackupd");
+    if is_daemon {
+        Component::Daemon
+    } else {
+        Component::Cli
+    }
+}
+
+/// Refuses a helper from an incompatible release; warns (once per process) about other skew.
+fn check_helper_skew(helper: &MtProtoHelperVersion) -> Result<()> {
+    static WARNED: AtomicBool = AtomicBool::new(false);
+
+    let local = ComponentVersion::local(
+        host_component(),
+        version::VERSION,
+        version::MTPROTO_HELPER_PROTOCOL_VERSION,
+    );
+    let Some(skew) = version::check_skew(&local, &helper.component_version()) else {
+        return Ok(());
+    };
+    if skew.breaking {
+        return Err(skew.into());
+    }
+    if !WARNED.swap(true, Ordering::Relaxed) {
+        tracing::warn!(
+            event = "version.skew",
+            component = "helper",
+            path = %helper.path.display(),
+            "{}",
+            skew.message()
+        );
+    }
+    Ok(())
+}
+
+fn resolve_helper_path(helper_path: Option<PathBuf>) -> PathBuf {
+    helper_path.unwrap_or_else(|| {
+        default_helper_path().unwrap_or_else(|| PathBuf::from("televybackup-mtproto-helper"))
+    })
+}
+
 fn default_helper_path() -> Option<PathBuf> {
     let exe = std::env::current_exe().ok()?;
     let sibling = exe.with_file_name("televybackup-mtproto-helper");
@@ -576,6 +1060,8 @@ mod tests {
     enum FakeHelperMode {
         Graceful,
         HangAfterShutdownAck,
+        /// Reports protocol version 2 and answers `download_batch` / `upload_batch`.
+        Batching,
     }
 
     #[cfg(unix)]
@@ -615,6 +1101,157 @@ mod tests {
         assert!(parse_tgmtproto_object_id_v1(&bad_at).is_err());
     }
 
+    #[test]
+    fn split_object_prefix_separates_the_uploaded_name() {
+        assert_eq!(
+            split_object_prefix("mac-mini-file_0123456789ab.dat"),
+            (Some("mac-mini"), "file_0123456789ab.dat")
+        );
+        assert_eq!(
+            split_object_prefix("nas-televybackup-bootstrap.catalog"),
+            (Some("nas"), "televybackup-bootstrap.catalog")
+        );
+        assert_eq!(
+            split_object_prefix("televybackup-dedupe.catalog"),
+            (None, "televybackup-dedupe.catalog")
+        );
+        assert_eq!(
+            split_object_prefix("file_0123456789ab.dat"),
+            (None, "file_0123456789ab.dat")
+        );
+    }
+
+    #[test]
+    fn chat_migrated_helper_error_maps_to_dedicated_error() {
+        let err = chat_migrated_or(Error::InvalidConfig {
+            message: "chat migrated: old=-123 new=-1001234567890".to_string(),
+        });
+        match err {
+            Error::ChatMigrated {
+                old_chat_id,
+                new_chat_id,
+            } => {
+                assert_eq!(old_chat_id, "-123");
+                assert_eq!(new_chat_id, "-1001234567890");
+            }
+            other => panic!("unexpected error: {other:?}"),
+        }
+        assert_eq!(
+            chat_migrated_or(Error::telegram(
+                "send_message failed: chat migrated: old=-1 new=-1001".to_string()
+            ))
+            .code(),
+            "telegram.chat_migrated"
+        );
+
+        assert!(matches!(
+            chat_migrated_or(Error::telegram(
+                "resolve chat failed: chat not found: -123".to_string()
+            )),
+            Error::Telegram { .. }
+        ));
+        assert!(parse_chat_migrated_message("chat migrated: old=-123").is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn captioned_upload_body_is_followed_by_its_caption_line() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let out_path = tempdir.path().join("stdin.bin");
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(format!("cat > '{}'", out_path.display()))
+            .stdin(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdin = child.stdin.take().unwrap();
+        let payload = vec![7u8; UPLOAD_BODY_READ_BYTES + 3];
+        write_upload_body(
+            &mut stdin,
+            Box::new(payload.as_slice()),
+            payload.len() as u64,
+            Some(ObjectKind::Chunk),
+        )
+        .unwrap();
+        write_upload_body(&mut stdin, Box::new(&b"abc"[..]), 3, None).unwrap();
+        drop(stdin);
+        assert!(child.wait().unwrap().success());
+
+        let written = fs::read(&out_path).unwrap();
+        let (body, rest) = written.split_at(payload.len());
+        assert_eq!(body, payload.as_slice());
+        let caption = ObjectCaption::for_payload(ObjectKind::Chunk, &payload);
+        assert_eq!(rest, format!("{}\nabc", caption.encode()).as_bytes());
+    }
+
+    #[test]
+    fn object_ids_from_the_pre_migration_chat_stay_in_scope() {
+        let object_id = encode_tgmtproto_object_id_v1("-123", 7, 11, 13).unwrap();
+        let slice = crate::storage::encode_tgpack_object_id(&object_id, 0, 42);
+        let crate::storage::ChunkObjectRef::PackSlice { pack_object_id, .. } =
+            crate::storage::parse_chunk_object_ref(&slice).unwrap()
+        else {
+            panic!("expected a pack slice");
+        };
+        let parsed = parse_tgmtproto_object_id_v1(&pack_object_id).unwrap();
+        assert_eq!(parsed.peer, "-123");
+        assert_eq!(parsed.msg_id, 7);
+
+        let legacy = vec!["-123".to_string()];
+        assert!(object_peer_in_scope(&parsed.peer, "-100123", &legacy));
+        assert!(object_peer_in_scope("-100123", "-100123", &legacy));
+        assert!(!object_peer_in_scope(&parsed.peer, "-100123", &[]));
+        assert!(!object_peer_in_scope("-456", "-100123", &legacy));
+    }
+
+    /// `find_recent_document` over a chat holding messages `present`; documents at `tagged` match.
+    fn find_in_fake_chat(
+        present: std::ops::RangeInclusive<i32>,
+        tagged: &[i32],
+    ) -> (Option<i32>, usize) {
+        let calls = std::cell::Cell::new(0usize);
+        let found = find_recent_document(
+            |from, count| {
+                calls.set(calls.get() + 1);
+                let ids = (from..from + count as i32).filter(|id| present.contains(id));
+                Ok(TelegramDocumentBatch {
+                    messages: ids.clone().count(),
+                    documents: ids
+                        .map(|id| TelegramDocumentInfo {
+                            msg_id: id,
+                            doc_id: i64::from(id),
+                            access_hash: 0,
+                            size: 1,
+                            caption: tagged.contains(&id).then(|| "tag".to_string()),
+                            forwarded_from: None,
+                            file_name: None,
+                        })
+                        .collect(),
+                })
+            },
+            |d| d.caption.is_some(),
+        )
+        .unwrap();
+        (found.map(|d| d.msg_id), calls.get())
+    }
+
+    #[test]
+    fn find_recent_document_searches_back_from_the_end_of_the_chat() {
+        // The chat's first messages were deleted; the newest tagged one wins.
+        let (found, calls) = find_in_fake_chat(151..=1234, &[500, 1200]);
+        assert_eq!(found, Some(1200));
+        assert!(calls < 20, "{calls}");
+
+        let (found, calls) = find_in_fake_chat(1..=2_000_000, &[1_999_000]);
+        assert_eq!(found, Some(1_999_000));
+        assert!(calls < 60, "{calls}");
+
+        // Too far back to count as recent.
+        assert_eq!(find_in_fake_chat(1..=10_000, &[5]).0, None);
+        // No messages at all.
+        assert_eq!(find_in_fake_chat(-5..=0, &[]).0, None);
+    }
+
     #[cfg(unix)]
     fn write_fake_helper(mode: FakeHelperMode) -> FakeHelperEnv {
         let tempdir = tempfile::tempdir().unwrap();
@@ -627,6 +1264,7 @@ mod tests {
         let mode = match mode {
             FakeHelperMode::Graceful => "graceful",
             FakeHelperMode::HangAfterShutdownAck => "hang_after_ack",
+            FakeHelperMode::Batching => "batching",
         };
         let script = format!(
             r#"#!/bin/sh
@@ -641,8 +1279,41 @@ touch "$REQUESTS" "$EVENTS"
 while IFS= read -r line; do
   printf '%s\n' "$line" >> "$REQUESTS"
   case "$line" in
+    *'"cmd":"init"'*'"chatId":"-123"'*)
+      printf '%s\n' '{{"ok":false,"error":"chat migrated: old=-123 new=-100123"}}'
+      ;;
     *'"cmd":"init"'*)
-      printf '%s\n' '{{"ok":true,"session":"{FAKE_HELPER_SESSION_B64}"}}'
+      if [ "$MODE" = "batching" ]; then
+        printf '%s\n' '{{"ok":true,"session":"{FAKE_HELPER_SESSION_B64}","protocolVersion":2}}'
+      else
+        printf '%s\n' '{{"ok":true,"session":"{FAKE_HELPER_SESSION_B64}"}}'
+      fi
+      ;;
+    *'"cmd":"download"'*'"objectId":"'*)
+      printf '%s\n' '{{"ok":true,"size":3}}'
+      printf 'abc'
+      ;;
+    *'"cmd":"download_batch"'*)
+      printf '%s\n' '{{"ok":true,"event":"download_progress","bytesDownloaded":3}}'
+      printf '%s\n' '{{"ok":true,"event":"batch_item","index":0,"size":3}}'
+      printf 'abc'
+      printf '%s\n' '{{"ok":true,"event":"batch_item","index":1,"itemError":"message not found"}}'
+      ;;
+    *'"cmd":"upload_batch"'*)
+      dd bs=1 count=3 of=/dev/null 2>/dev/null
+      printf '%s\n' '{{"ok":true,"event":"upload_progress","bytesUploaded":2}}'
+      printf '%s\n' '{{"ok":true,"event":"batch_item","index":0,"objectId":"first"}}'
+      printf '%s\n' '{{"ok":true,"event":"batch_item","index":1,"objectId":"second"}}'
+      ;;
+    *'"cmd":"wait_for_chat"'*'"drain":false'*)
+      printf '%s\n' '{{"ok":true,"chat":{{"kind":"group","title":"Backups","peerId":-42,"configChatId":"-42","bootstrapHint":true}}}}'
+      ;;
+    *'"cmd":"wait_for_chat"'*)
+      if [ "$MODE" = "batching" ]; then
+        printf '%s\n' '{{"ok":true}}'
+      else
+        printf '%s\n' '{{"ok":false,"error":"wait_for_chat timed out after 1s"}}'
+      fi
       ;;
     *'"cmd":"shutdown"'*)
       printf 'shutdown\n' >> "$EVENTS"
@@ -682,6 +1353,7 @@ printf 'eof\n' >> "$EVENTS"
             api_hash: "hash".to_string(),
             bot_token: "bot".to_string(),
             chat_id: String::new(),
+            migrated_from_chat_ids: Vec::new(),
             session_b64,
             cache_dir: cache_dir.to_path_buf(),
             min_delay_ms: None,
@@ -702,11 +1374,14 @@ printf 'eof\n' >> "$EVENTS"
             api_hash: "hash".to_string(),
             bot_token: "bot".to_string(),
             chat_id: String::new(),
+            migrated_from_chat_ids: Vec::new(),
             session,
             cache_dir: cache_dir.to_path_buf(),
             min_delay_ms: None,
             max_concurrent_uploads,
             helper_path: Some(script_path.to_path_buf()),
+            bootstrap_pin_mode: BootstrapPinMode::Pin,
+            object_prefix: None,
         })
         .await
         .unwrap()
@@ -774,6 +1449,90 @@ printf 'eof\n' >> "$EVENTS"
         assert_eq!(events.lines().collect::<Vec<_>>(), vec!["shutdown"]);
     }
 
+    #[cfg(unix)]
+    #[tokio::test(flavor = "current_thread")]
+    async fn batched_downloads_fall_back_to_single_requests_on_old_helpers() {
+        let connect = |script_path: PathBuf| {
+            TelegramMtProtoStorage::connect(TelegramMtProtoStorageConfig {
+                provider: "telegram_mtproto".to_string(),
+                api_id: 1,
+                api_hash: "hash".to_string(),
+                bot_token: "bot".to_string(),
+                chat_id: "-100123".to_string(),
+                migrated_from_chat_ids: Vec::new(),
+                session: None,
+                cache_dir: script_path.parent().unwrap().join("cache"),
+                min_delay_ms: None,
+                max_concurrent_uploads: Some(1),
+                helper_path: Some(script_path),
+                bootstrap_pin_mode: BootstrapPinMode::Pin,
+                object_prefix: Some("mac-mini".to_string()),
+            })
+        };
+        let ids = vec![
+            encode_tgmtproto_object_id_v1("-100123", 1, 11, 111).unwrap(),
+            encode_tgmtproto_object_id_v1("-100123", 2, 22, 222).unwrap(),
+        ];
+
+        // An old helper doesn't report a protocol version: one `download` per object.
+        let old = write_fake_helper(FakeHelperMode::Graceful);
+        let storage = connect(old.script_path.clone()).await.unwrap();
+        let got = storage.download_documents(&ids).await.unwrap();
+        assert_eq!(
+            got.into_iter().map(|r| r.unwrap()).collect::<Vec<_>>(),
+            vec![b"abc".to_vec(), b"abc".to_vec()]
+        );
+        drop(storage);
+        let requests = fs::read_to_string(&old.requests_path).unwrap();
+        assert_eq!(requests.matches(r#""cmd":"download""#).count(), 2);
+        assert!(!requests.contains("download_batch"));
+
+        // A current helper takes the whole batch in one request; a failed item fails alone.
+        let new = write_fake_helper(FakeHelperMode::Batching);
+        let storage = connect(new.script_path.clone()).await.unwrap();
+        let mut got = storage.download_documents(&ids).await.unwrap().into_iter();
+        assert_eq!(got.next().unwrap().unwrap(), b"abc");
+        let err = got.next().unwrap().unwrap_err();
+        assert!(err.to_string().contains("message not found"), "{err}");
+
+        let uploaded = storage
+            .upload_documents(vec![("a", b"xy".to_vec()), ("b", b"z".to_vec())])
+            .await
+            .unwrap();
+        assert_eq!(uploaded, vec!["first", "second"]);
+        drop(storage);
+        let requests = fs::read_to_string(&new.requests_path).unwrap();
+        assert_eq!(requests.matches(r#""cmd":"download_batch""#).count(), 1);
+        assert!(!requests.contains(r#""cmd":"download""#));
+        assert!(requests.contains(
+            r#"{"cmd":"upload_batch","items":[{"filename":"mac-mini-a","size":2},{"filename":"mac-mini-b","size":1}]}"#
+        ));
+        let events = fs::read_to_string(&new.events_path).unwrap();
+        assert!(!events.contains("unexpected"), "{events}");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "current_thread")]
+    async fn chat_polls_return_none_until_a_message_arrives() {
+        for mode in [FakeHelperMode::Graceful, FakeHelperMode::Batching] {
+            let fake = write_fake_helper(mode);
+            let cache_dir = fake.script_path.parent().unwrap().join("cache-poll");
+            let storage = connect_fake_storage(&fake.script_path, &cache_dir, None, Some(1)).await;
+
+            let wait = Duration::from_millis(250);
+            let none = storage.check_for_new_chat(wait, false, true).unwrap();
+            assert!(none.is_none());
+            let chat = storage.check_for_new_chat(wait, false, false).unwrap();
+            assert_eq!(chat.map(|c| c.config_chat_id).as_deref(), Some("-42"));
+            drop(storage);
+
+            let requests = fs::read_to_string(&fake.requests_path).unwrap();
+            assert!(requests.contains(
+                r#"{"cmd":"wait_for_chat","timeoutSecs":1,"timeoutMs":250,"includeUsers":false,"poll":true,"drain":true}"#
+            ));
+        }
+    }
+
     #[cfg(unix)]
     #[tokio::test(flavor = "current_thread")]
     async fn connect_reuses_session_only_for_primary_helper() {
@@ -813,6 +1572,49 @@ printf 'eof\n' >> "$EVENTS"
         drop(storage);
     }
 
+    #[cfg(unix)]
+    #[tokio::test(flavor = "current_thread")]
+    async fn connect_follows_chat_migration_to_supergroup() {
+        let fake = write_fake_helper(FakeHelperMode::Graceful);
+        let cache_dir = fake.script_path.parent().unwrap().join("cache-migrated");
+        let storage = TelegramMtProtoStorage::connect(TelegramMtProtoStorageConfig {
+            provider: "telegram_mtproto".to_string(),
+            api_id: 1,
+            api_hash: "hash".to_string(),
+            bot_token: "bot".to_string(),
+            chat_id: "-123".to_string(),
+            migrated_from_chat_ids: Vec::new(),
+            session: None,
+            cache_dir: cache_dir.clone(),
+            min_delay_ms: None,
+            max_concurrent_uploads: Some(2),
+            helper_path: Some(fake.script_path.clone()),
+            bootstrap_pin_mode: BootstrapPinMode::Pin,
+            object_prefix: None,
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(storage.chat_id(), "-100123");
+        assert_eq!(storage.object_id_scope(), Some("-100123"));
+        assert_eq!(storage.migrated_from_chat_id(), Some("-123"));
+        assert_eq!(storage.legacy_object_id_scopes(), ["-123".to_string()]);
+
+        let requests = wait_for_request_count(&fake.requests_path, 3);
+        let retried = requests
+            .lines()
+            .filter(|line| line.contains(r#""chatId":"-100123""#))
+            .collect::<Vec<_>>();
+        assert_eq!(retried.len(), 2);
+        assert!(
+            retried
+                .iter()
+                .all(|line| line.contains(r#""migratedFromChatIds":["-123"]"#))
+        );
+
+        drop(storage);
+    }
+
     #[cfg(unix)]
     #[test]
     fn mtproto_helper_drop_kills_when_shutdown_hangs() {
@@ -839,13 +1641,18 @@ printf 'eof\n' >> "$EVENTS"
 #[serde(tag = "cmd", rename_all = "snake_case")]
 enum Request {
     Init(InitRequest),
+    Version,
     Shutdown,
     Upload(UploadRequestMeta),
     Download(DownloadRequest),
+    DownloadBatch(DownloadBatchRequest),
+    UploadBatch(UploadBatchRequest),
     GetPinned,
     Pin(PinRequest),
+    Delete(DeleteRequest),
     ListDialogs(ListDialogsRequest),
     WaitForChat(WaitForChatRequest),
+    ListDocuments(ListDocumentsRequest),
 }
 
 #[derive(Debug, Serialize)]
@@ -858,6 +1665,8 @@ struct InitRequest {
     bot_token: String,
     #[serde(rename = "chatId")]
     chat_id: String,
+    #[serde(skip_serializing_if = "Vec::is_empty", rename = "migratedFromChatIds")]
+    migrated_from_chat_ids: Vec<String>,
     #[serde(rename = "session")]
     session_b64: Option<String>,
     #[serde(rename = "cacheDir")]
@@ -871,16 +1680,21 @@ struct InitRequest {
     max_concurrent_uploads: Option<usize>,
 }
 
-#[derive(Debug)]
-struct UploadRequest {
+struct UploadRequest<'a> {
     filename: String,
-    bytes: Vec<u8>,
+    body: UploadBody<'a>,
+    len: u64,
+    /// Caption the document with an [`ObjectCaption`] of this kind.
+    caption_kind: Option<ObjectKind>,
 }
 
 #[derive(Debug, Serialize)]
 struct UploadRequestMeta {
     filename: String,
     size: usize,
+    /// The body is followed by one caption line (its hash is only known once the body is written).
+    #[serde(skip_serializing_if = "std::ops::Not::not", rename = "captionTrailer")]
+    caption_trailer: bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -889,12 +1703,30 @@ struct DownloadRequest {
     object_id: String,
 }
 
+#[derive(Debug, Serialize)]
+struct DownloadBatchRequest {
+    #[serde(rename = "objectIds")]
+    object_ids: Vec<String>,
+}
+
+/// The bodies follow the request line back to back, in `items` order.
+#[derive(Debug, Serialize)]
+struct UploadBatchRequest {
+    items: Vec<UploadRequestMeta>,
+}
+
 #[derive(Debug, Serialize)]
 struct PinRequest {
     #[serde(rename = "msgId")]
     msg_id: i32,
 }
 
+#[derive(Debug, Serialize)]
+struct DeleteRequest {
+    #[serde(rename = "msgId")]
+    msg_id: i32,
+}
+
 #[derive(Debug, Serialize)]
 struct ListDialogsRequest {
     limit: usize,
@@ -902,12 +1734,25 @@ struct ListDialogsRequest {
     include_users: bool,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 struct WaitForChatRequest {
     #[serde(rename = "timeoutSecs")]
     timeout_secs: u64,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "timeoutMs")]
+    timeout_ms: Option<u64>,
     #[serde(rename = "includeUsers")]
     include_users: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    poll: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    drain: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+struct ListDocumentsRequest {
+    #[serde(rename = "fromMsgId")]
+    from_msg_id: i32,
+    count: usize,
 }
 
 #[derive(Debug, Deserialize)]
@@ -922,10 +1767,36 @@ struct ResponseEnvelope {
 }
 
 struct MtProtoHelper {
+    path: PathBuf,
     child: Child,
     stdin: ChildStdin,
     stdout: BufReader<ChildStdout>,
     session_b64: Option<String>,
+    /// From the `init` response; helpers predating batches don't report one (1).
+    protocol_version: u64,
+}
+
+/// A document message in the endpoint's chat.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TelegramDocumentInfo {
+    pub msg_id: i32,
+    pub doc_id: i64,
+    pub access_hash: i64,
+    pub size: u64,
+    /// Message text, e.g. an [`ObjectCaption`].
+    pub caption: Option<String>,
+    /// `(chat_id, msg_id)` of the channel post this message was forwarded from.
+    pub forwarded_from: Option<(String, i32)>,
+    /// The document's file name (see [`split_object_prefix`]).
+    pub file_name: Option<String>,
+}
+
+/// One window of message ids (see [`TelegramMtProtoStorage::list_documents`]).
+#[derive(Debug, Clone, Default)]
+pub struct TelegramDocumentBatch {
+    /// Messages that exist in the window, documents or not; `0` past the end of the chat.
+    pub messages: usize,
+    pub documents: Vec<TelegramDocumentInfo>,
 }
 
 #[derive(Debug, Clone)]
@@ -961,10 +1832,12 @@ impl MtProtoHelper {
         })?;
 
         Ok(Self {
+            path: path.to_path_buf(),
             child,
             stdin,
             stdout: BufReader::new(stdout),
             session_b64: None,
+            protocol_version: 1,
         })
     }
 
@@ -1033,74 +1906,112 @@ impl MtProtoHelper {
         let env = self.read_json_line()?;
         self.apply_session(&env)?;
         if !env.ok {
-            return Err(Error::InvalidConfig {
+            return Err(chat_migrated_or(Error::InvalidConfig {
                 message: env
                     .error
                     .unwrap_or_else(|| "mtproto init failed".to_string()),
-            });
+            }));
         }
-        Ok(())
+        let reported = MtProtoHelperVersion::from_envelope(&self.path, &env);
+        self.protocol_version = reported.protocol_version;
+        check_helper_skew(&reported)
     }
 
-    fn upload(&mut self, req: UploadRequest) -> Result<String> {
-        self.upload_with_progress(req, None)
+    fn supports_batches(&self) -> bool {
+        self.protocol_version >= MTPROTO_HELPER_BATCH_PROTOCOL_VERSION
     }
 
+    /// Sends an upload request followed by the raw body bytes.
+    ///
+    /// The body is written from a separate thread while responses are read here: the helper
+    /// consumes large uploads part by part and reports progress as it goes, so waiting for the
+    /// whole body to be written first could deadlock on a full stdout pipe.
     fn upload_with_progress(
         &mut self,
-        req: UploadRequest,
+        req: UploadRequest<'_>,
         mut on_progress: Option<&mut dyn FnMut(StorageProgress)>,
     ) -> Result<String> {
+        let size = usize::try_from(req.len).map_err(|_| Error::InvalidConfig {
+            message: format!("mtproto upload too large: {}", req.len),
+        })?;
         let meta = UploadRequestMeta {
             filename: req.filename,
-            size: req.bytes.len(),
+            size,
+            caption_trailer: req.caption_kind.is_some(),
         };
         self.send_json(&Request::Upload(meta))?;
-        self.stdin
-            .write_all(&req.bytes)
-            .map_err(|e| Error::Telegram {
-                message: format!("mtproto helper upload write failed: {e}"),
-            })?;
-        self.stdin.flush().ok();
 
-        loop {
-            let env = self.read_json_line_with_timeout(MTPROTO_HELPER_UPLOAD_EVENT_TIMEOUT_SECS)?;
-            self.apply_session(&env)?;
-            if !env.ok {
-                return Err(Error::Telegram {
-                    message: env
-                        .error
-                        .unwrap_or_else(|| "mtproto upload failed".to_string()),
-                });
-            }
+        let Self {
+            child,
+            stdin,
+            stdout,
+            session_b64,
+            ..
+        } = self;
+        let UploadRequest {
+            body,
+            len,
+            caption_kind,
+            ..
+        } = req;
+        std::thread::scope(|s| {
+            let writer = s.spawn(move || write_upload_body(stdin, body, len, caption_kind));
+
+            let res = (|| loop {
+                let env =
+                    read_response_line(child, stdout, MTPROTO_HELPER_UPLOAD_EVENT_TIMEOUT_SECS)?;
+                apply_session_b64(session_b64, &env);
+                if !env.ok {
+                    return Err(Error::telegram(
+                        env.error
+                            .unwrap_or_else(|| "mtproto upload failed".to_string()),
+                    ));
+                }
 
-            let event = env
-                .data
-                .get("event")
-                .and_then(|v| v.as_str())
-                .unwrap_or_default();
-            if event == "upload_progress" {
-                if let (Some(bytes), Some(cb)) = (
-                    env.data.get("bytesUploaded").and_then(|v| v.as_u64()),
-                    on_progress.as_mut(),
-                ) {
-                    let net_bytes = env.data.get("netBytesOut").and_then(|v| v.as_u64());
-                    (**cb)(StorageProgress { bytes, net_bytes });
+                let event = env
+                    .data
+                    .get("event")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default();
+                if event == "upload_progress" {
+                    if let (Some(bytes), Some(cb)) = (
+                        env.data.get("bytesUploaded").and_then(|v| v.as_u64()),
+                        on_progress.as_mut(),
+                    ) {
+                        let net_bytes = env.data.get("netBytesOut").and_then(|v| v.as_u64());
+                        (**cb)(StorageProgress { bytes, net_bytes });
+                    }
+                    continue;
                 }
-                continue;
-            }
 
-            let object_id = env
-                .data
-                .get("objectId")
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| Error::Telegram {
-                    message: "mtproto upload missing objectId".to_string(),
-                })?
-                .to_string();
+                let object_id = env
+                    .data
+                    .get("objectId")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| Error::telegram("mtproto upload missing objectId".to_string()))?
+                    .to_string();
 
-            return Ok(object_id);
-        }
+                return Ok(object_id);
+            })();
+
+            if res.is_err() && !writer.is_finished() {
+                // The helper will not drain the rest of the body; unblock the writer.
+                let _ = child.kill();
+            }
+            let written = writer.join().unwrap_or_else(|_| {
+                Err(Error::telegram(
+                    "mtproto helper upload writer panicked".to_string(),
+                ))
+            });
+            match (res, written) {
+                (Ok(object_id), Ok(())) => Ok(object_id),
+                // A body that failed mid-stream leaves the helper waiting for bytes; surface the
+                // body error rather than the resulting helper timeout.
+                (_, Err(e @ (Error::Io(_) | Error::Integrity { .. }))) => Err(e),
+                (Err(e), _) => Err(e),
+                (Ok(_), Err(e)) => Err(e),
+            }
+        })
     }
 
     fn download(&mut self, req: DownloadRequest) -> Result<Vec<u8>> {
@@ -1122,11 +2033,10 @@ impl MtProtoHelper {
             let env = self.read_json_line()?;
             self.apply_session(&env)?;
             if !env.ok {
-                return Err(Error::Telegram {
-                    message: env
-                        .error
+                return Err(Error::telegram(
+                    env.error
                         .unwrap_or_else(|| "mtproto download failed".to_string()),
-                });
+                ));
             }
 
             let event = env
@@ -1153,13 +2063,30 @@ impl MtProtoHelper {
             break env;
         };
 
+        // For older helpers, we only learn about download progress by reading the payload bytes.
+        // Newer helpers emit `download_progress` events while they download into a local cache, so
+        // reporting progress here would create unrealistic spikes and even "rewind" the counter.
+        self.read_payload(&env, |read| {
+            if !saw_progress_event && let Some(cb) = on_progress.as_mut() {
+                (**cb)(StorageProgress {
+                    bytes: read,
+                    net_bytes: None,
+                });
+            }
+        })
+    }
+
+    /// Reads the raw bytes following a response that announced their `size`.
+    fn read_payload(
+        &mut self,
+        env: &ResponseEnvelope,
+        mut on_read: impl FnMut(u64),
+    ) -> Result<Vec<u8>> {
         let size = env
             .data
             .get("size")
             .and_then(|v| v.as_u64())
-            .ok_or_else(|| Error::Telegram {
-                message: "mtproto download missing size".to_string(),
-            })?;
+            .ok_or_else(|| Error::telegram("mtproto download missing size".to_string()))?;
         if size > (usize::MAX as u64) {
             return Err(Error::InvalidConfig {
                 message: "mtproto download too large".to_string(),
@@ -1169,52 +2096,167 @@ impl MtProtoHelper {
         let size_usize = size as usize;
         let mut bytes = vec![0u8; size_usize];
 
-        // For older helpers, we only learn about download progress by reading the payload bytes.
-        // Newer helpers emit `download_progress` events while they download into a local cache, so
-        // reporting progress here would create unrealistic spikes and even "rewind" the counter.
         const READ_CHUNK: usize = 256 * 1024;
         let mut read = 0usize;
         while read < size_usize {
             let end = (read + READ_CHUNK).min(size_usize);
             self.stdout
                 .read_exact(&mut bytes[read..end])
-                .map_err(|e| Error::Telegram {
-                    message: format!("mtproto download read failed: {e}"),
-                })?;
+                .map_err(|e| Error::telegram(format!("mtproto download read failed: {e}")))?;
             read = end;
-            if !saw_progress_event && let Some(cb) = on_progress.as_mut() {
-                (**cb)(StorageProgress {
-                    bytes: read as u64,
-                    net_bytes: None,
-                });
-            }
+            on_read(read as u64);
         }
 
         Ok(bytes)
     }
 
+    /// Downloads `object_ids` with one `download_batch` request. Per-object failures (e.g. a
+    /// deleted message) come back in their entry; the helper keeps going with the rest.
+    fn download_batch(&mut self, object_ids: &[String]) -> Result<DownloadBatch> {
+        self.send_json(&Request::DownloadBatch(DownloadBatchRequest {
+            object_ids: object_ids.to_vec(),
+        }))?;
+        let mut out = Vec::with_capacity(object_ids.len());
+        while out.len() < object_ids.len() {
+            let env = self.read_json_line()?;
+            self.apply_session(&env)?;
+            if !env.ok {
+                return Err(Error::telegram(
+                    env.error
+                        .unwrap_or_else(|| "mtproto download batch failed".to_string()),
+                ));
+            }
+            match env.data.get("event").and_then(|v| v.as_str()) {
+                Some("download_progress") => continue,
+                Some("batch_item") => {}
+                other => {
+                    return Err(Error::telegram(format!(
+                        "mtproto helper unexpected batch event: {other:?}"
+                    )));
+                }
+            }
+            check_batch_index(&env, out.len())?;
+            match env.data.get("itemError").and_then(|v| v.as_str()) {
+                Some(error) => out.push(Err(Error::telegram(error.to_string()))),
+                None => out.push(Ok(self.read_payload(&env, |_| {})?)),
+            }
+        }
+        Ok(out)
+    }
+
+    /// Uploads `items` with one `upload_batch` request, writing the bodies from a separate thread
+    /// as [`Self::upload_with_progress`] does. Fails if any item failed, after reading them all.
+    fn upload_batch(&mut self, items: Vec<(&str, Vec<u8>)>) -> Result<Vec<String>> {
+        let metas = items
+            .iter()
+            .map(|(filename, bytes)| UploadRequestMeta {
+                filename: filename.to_string(),
+                size: bytes.len(),
+                caption_trailer: false,
+            })
+            .collect();
+        self.send_json(&Request::UploadBatch(UploadBatchRequest { items: metas }))?;
+
+        let Self {
+            child,
+            stdin,
+            stdout,
+            session_b64,
+            ..
+        } = self;
+        let items = &items;
+        std::thread::scope(|s| {
+            let writer = s.spawn(move || {
+                for (_, bytes) in items {
+                    write_upload_body(stdin, Box::new(bytes.as_slice()), bytes.len() as u64, None)?;
+                }
+                Ok(())
+            });
+
+            let res = (|| {
+                let mut object_ids = Vec::with_capacity(items.len());
+                let mut first_error = None;
+                let mut done = 0;
+                while done < items.len() {
+                    let env = read_response_line(
+                        child,
+                        stdout,
+                        MTPROTO_HELPER_UPLOAD_EVENT_TIMEOUT_SECS,
+                    )?;
+                    apply_session_b64(session_b64, &env);
+                    if !env.ok {
+                        return Err(Error::telegram(
+                            env.error
+                                .unwrap_or_else(|| "mtproto upload batch failed".to_string()),
+                        ));
+                    }
+                    match env.data.get("event").and_then(|v| v.as_str()) {
+                        Some("upload_progress") => continue,
+                        Some("batch_item") => {}
+                        other => {
+                            return Err(Error::telegram(format!(
+                                "mtproto helper unexpected batch event: {other:?}"
+                            )));
+                        }
+                    }
+                    check_batch_index(&env, done)?;
+                    done += 1;
+                    if let Some(error) = env.data.get("itemError").and_then(|v| v.as_str()) {
+                        first_error.get_or_insert_with(|| Error::telegram(error.to_string()));
+                        continue;
+                    }
+                    let object_id = env
+                        .data
+                        .get("objectId")
+                        .and_then(|v| v.as_str())
+                        .ok_or_else(|| {
+                            Error::telegram("mtproto upload missing objectId".to_string())
+                        })?;
+                    object_ids.push(object_id.to_string());
+                }
+                match first_error {
+                    Some(e) => Err(e),
+                    None => Ok(object_ids),
+                }
+            })();
+
+            if res.is_err() && !writer.is_finished() {
+                let _ = child.kill();
+            }
+            let written = writer.join().unwrap_or_else(|_| {
+                Err(Error::telegram(
+                    "mtproto helper upload writer panicked".to_string(),
+                ))
+            });
+            match (res, written) {
+                (Ok(object_ids), Ok(())) => Ok(object_ids),
+                (Err(e), _) | (Ok(_), Err(e)) => Err(e),
+            }
+        })
+    }
+
     fn get_pinned(&mut self) -> Result<Option<String>> {
         self.send_json(&Request::GetPinned)?;
 
         let env = self.read_json_line()?;
         self.apply_session(&env)?;
         if !env.ok {
-            return Err(Error::Telegram {
-                message: env
-                    .error
+            return Err(Error::telegram(
+                env.error
                     .unwrap_or_else(|| "mtproto get_pinned failed".to_string()),
-            });
+            ));
         }
 
-        let v = env.data.get("objectId").ok_or_else(|| Error::Telegram {
-            message: "mtproto get_pinned missing objectId".to_string(),
-        })?;
+        let v = env
+            .data
+            .get("objectId")
+            .ok_or_else(|| Error::telegram("mtproto get_pinned missing objectId".to_string()))?;
         if v.is_null() {
             return Ok(None);
         }
-        let object_id = v.as_str().ok_or_else(|| Error::Telegram {
-            message: "mtproto get_pinned invalid objectId".to_string(),
-        })?;
+        let object_id = v
+            .as_str()
+            .ok_or_else(|| Error::telegram("mtproto get_pinned invalid objectId".to_string()))?;
         Ok(Some(object_id.to_string()))
     }
 
@@ -1224,11 +2266,24 @@ impl MtProtoHelper {
         let env = self.read_json_line()?;
         self.apply_session(&env)?;
         if !env.ok {
-            return Err(Error::Telegram {
-                message: env
-                    .error
+            return Err(Error::telegram(
+                env.error
                     .unwrap_or_else(|| "mtproto pin failed".to_string()),
-            });
+            ));
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self, msg_id: i32) -> Result<()> {
+        self.send_json(&Request::Delete(DeleteRequest { msg_id }))?;
+
+        let env = self.read_json_line()?;
+        self.apply_session(&env)?;
+        if !env.ok {
+            return Err(Error::telegram(
+                env.error
+                    .unwrap_or_else(|| "mtproto delete failed".to_string()),
+            ));
         }
         Ok(())
     }
@@ -1246,52 +2301,42 @@ impl MtProtoHelper {
         let env = self.read_json_line()?;
         self.apply_session(&env)?;
         if !env.ok {
-            return Err(Error::Telegram {
-                message: env
-                    .error
+            return Err(Error::telegram(
+                env.error
                     .unwrap_or_else(|| "mtproto list_dialogs failed".to_string()),
-            });
+            ));
         }
 
         let dialogs = env
             .data
             .get("dialogs")
             .and_then(|v| v.as_array())
-            .ok_or_else(|| Error::Telegram {
-                message: "mtproto list_dialogs missing dialogs".to_string(),
-            })?;
+            .ok_or_else(|| Error::telegram("mtproto list_dialogs missing dialogs".to_string()))?;
 
         let mut out = Vec::with_capacity(dialogs.len());
         for d in dialogs {
             let kind = d
                 .get("kind")
                 .and_then(|v| v.as_str())
-                .ok_or_else(|| Error::Telegram {
-                    message: "mtproto list_dialogs invalid kind".to_string(),
-                })?
+                .ok_or_else(|| Error::telegram("mtproto list_dialogs invalid kind".to_string()))?
                 .to_string();
             let title = d
                 .get("title")
                 .and_then(|v| v.as_str())
-                .ok_or_else(|| Error::Telegram {
-                    message: "mtproto list_dialogs invalid title".to_string(),
-                })?
+                .ok_or_else(|| Error::telegram("mtproto list_dialogs invalid title".to_string()))?
                 .to_string();
             let username = d
                 .get("username")
                 .and_then(|v| v.as_str())
                 .map(|s| s.to_string());
-            let peer_id =
-                d.get("peerId")
-                    .and_then(|v| v.as_i64())
-                    .ok_or_else(|| Error::Telegram {
-                        message: "mtproto list_dialogs invalid peerId".to_string(),
-                    })?;
+            let peer_id = d.get("peerId").and_then(|v| v.as_i64()).ok_or_else(|| {
+                Error::telegram("mtproto list_dialogs invalid peerId".to_string())
+            })?;
             let config_chat_id = d
                 .get("configChatId")
                 .and_then(|v| v.as_str())
-                .ok_or_else(|| Error::Telegram {
-                    message: "mtproto list_dialogs invalid configChatId".to_string(),
+                .ok_or_else(|| {
+                    Error::telegram("mtproto list_dialogs invalid configChatId".to_string())
                 })?
                 .to_string();
             let bootstrap_hint = d
@@ -1317,38 +2362,45 @@ impl MtProtoHelper {
         timeout_secs: u64,
         include_users: bool,
     ) -> Result<TelegramDialogInfo> {
-        self.send_json(&Request::WaitForChat(WaitForChatRequest {
+        self.wait_for_chat_request(&WaitForChatRequest {
             timeout_secs,
+            timeout_ms: None,
             include_users,
-        }))?;
+            poll: false,
+            drain: None,
+        })?
+        .ok_or_else(|| Error::telegram("mtproto wait_for_chat missing chat".to_string()))
+    }
+
+    /// `None` when a `poll` request timed out.
+    fn wait_for_chat_request(
+        &mut self,
+        req: &WaitForChatRequest,
+    ) -> Result<Option<TelegramDialogInfo>> {
+        self.send_json(&Request::WaitForChat(req.clone()))?;
 
         let env = self.read_json_line()?;
         self.apply_session(&env)?;
         if !env.ok {
-            return Err(Error::Telegram {
-                message: env
-                    .error
+            return Err(Error::telegram(
+                env.error
                     .unwrap_or_else(|| "mtproto wait_for_chat failed".to_string()),
-            });
+            ));
         }
 
-        let d = env.data.get("chat").ok_or_else(|| Error::Telegram {
-            message: "mtproto wait_for_chat missing chat".to_string(),
-        })?;
+        let Some(d) = env.data.get("chat") else {
+            return Ok(None);
+        };
 
         let kind = d
             .get("kind")
             .and_then(|v| v.as_str())
-            .ok_or_else(|| Error::Telegram {
-                message: "mtproto wait_for_chat invalid kind".to_string(),
-            })?
+            .ok_or_else(|| Error::telegram("mtproto wait_for_chat invalid kind".to_string()))?
             .to_string();
         let title = d
             .get("title")
             .and_then(|v| v.as_str())
-            .ok_or_else(|| Error::Telegram {
-                message: "mtproto wait_for_chat invalid title".to_string(),
-            })?
+            .ok_or_else(|| Error::telegram("mtproto wait_for_chat invalid title".to_string()))?
             .to_string();
         let username = d
             .get("username")
@@ -1357,14 +2409,12 @@ impl MtProtoHelper {
         let peer_id = d
             .get("peerId")
             .and_then(|v| v.as_i64())
-            .ok_or_else(|| Error::Telegram {
-                message: "mtproto wait_for_chat invalid peerId".to_string(),
-            })?;
+            .ok_or_else(|| Error::telegram("mtproto wait_for_chat invalid peerId".to_string()))?;
         let config_chat_id = d
             .get("configChatId")
             .and_then(|v| v.as_str())
-            .ok_or_else(|| Error::Telegram {
-                message: "mtproto wait_for_chat invalid configChatId".to_string(),
+            .ok_or_else(|| {
+                Error::telegram("mtproto wait_for_chat invalid configChatId".to_string())
             })?
             .to_string();
         let bootstrap_hint = d
@@ -1372,22 +2422,97 @@ impl MtProtoHelper {
             .and_then(|v| v.as_bool())
             .unwrap_or(false);
 
-        Ok(TelegramDialogInfo {
+        Ok(Some(TelegramDialogInfo {
             kind,
             title,
             username,
             peer_id,
             config_chat_id,
             bootstrap_hint,
+        }))
+    }
+
+    fn list_documents(&mut self, from_msg_id: i32, count: usize) -> Result<TelegramDocumentBatch> {
+        self.send_json(&Request::ListDocuments(ListDocumentsRequest {
+            from_msg_id,
+            count,
+        }))?;
+
+        let env = self.read_json_line()?;
+        self.apply_session(&env)?;
+        if !env.ok {
+            return Err(Error::telegram(
+                env.error
+                    .unwrap_or_else(|| "mtproto list_documents failed".to_string()),
+            ));
+        }
+
+        let messages = env
+            .data
+            .get("messages")
+            .and_then(|v| v.as_u64())
+            .ok_or_else(|| {
+                Error::telegram("mtproto list_documents missing messages".to_string())
+            })?;
+        let documents = env
+            .data
+            .get("documents")
+            .and_then(|v| v.as_array())
+            .ok_or_else(|| {
+                Error::telegram("mtproto list_documents missing documents".to_string())
+            })?;
+
+        let mut out = Vec::with_capacity(documents.len());
+        for d in documents {
+            let msg_id = d
+                .get("msgId")
+                .and_then(|v| v.as_i64())
+                .and_then(|v| i32::try_from(v).ok());
+            let doc_id = d.get("docId").and_then(|v| v.as_i64());
+            let access_hash = d.get("accessHash").and_then(|v| v.as_i64());
+            let size = d.get("size").and_then(|v| v.as_u64());
+            let (Some(msg_id), Some(doc_id), Some(access_hash), Some(size)) =
+                (msg_id, doc_id, access_hash, size)
+            else {
+                return Err(Error::telegram(
+                    "mtproto list_documents invalid document".to_string(),
+                ));
+            };
+            let fwd_chat_id = d
+                .get("fwdChatId")
+                .and_then(|v| v.as_str())
+                .map(str::to_string);
+            let fwd_msg_id = d
+                .get("fwdMsgId")
+                .and_then(|v| v.as_i64())
+                .and_then(|v| i32::try_from(v).ok());
+            let caption = d
+                .get("caption")
+                .and_then(|v| v.as_str())
+                .map(str::to_string);
+            let file_name = d
+                .get("fileName")
+                .and_then(|v| v.as_str())
+                .map(str::to_string);
+            out.push(TelegramDocumentInfo {
+                msg_id,
+                doc_id,
+                access_hash,
+                size,
+                caption,
+                forwarded_from: fwd_chat_id.zip(fwd_msg_id),
+                file_name,
+            });
+        }
+
+        Ok(TelegramDocumentBatch {
+            messages: usize::try_from(messages).unwrap_or(usize::MAX),
+            documents: out,
         })
     }
 
     fn apply_session(&mut self, env: &ResponseEnvelope) -> Result<()> {
-        if let Some(b64) = &env.session_b64
-            && !b64.is_empty()
-        {
-            self.session_b64 = Some(b64.to_string());
-        }
+        apply_session_b64(&mut self.session_b64, env);
         Ok(())
     }
 
@@ -1397,12 +2522,10 @@ impl MtProtoHelper {
         })?;
         self.stdin
             .write_all(line.as_bytes())
-            .map_err(|e| Error::Telegram {
-                message: format!("mtproto helper write failed: {e}"),
-            })?;
-        self.stdin.write_all(b"\n").map_err(|e| Error::Telegram {
-            message: format!("mtproto helper write failed: {e}"),
-        })?;
+            .map_err(|e| Error::telegram(format!("mtproto helper write failed: {e}")))?;
+        self.stdin
+            .write_all(b"\n")
+            .map_err(|e| Error::telegram(format!("mtproto helper write failed: {e}")))?;
         self.stdin.flush().ok();
         Ok(())
     }
@@ -1412,59 +2535,121 @@ impl MtProtoHelper {
     }
 
     fn read_json_line_with_timeout(&mut self, timeout_secs: u64) -> Result<ResponseEnvelope> {
-        let (child, stdout) = (&mut self.child, &mut self.stdout);
-        let (tx, rx) = mpsc::channel::<std::io::Result<String>>();
+        read_response_line(&mut self.child, &mut self.stdout, timeout_secs)
+    }
+}
 
-        std::thread::scope(|s| {
-            s.spawn(|| {
-                let mut line = String::new();
-                let res = stdout.read_line(&mut line).and_then(|n| {
-                    if n == 0 {
-                        Err(std::io::Error::new(
-                            std::io::ErrorKind::UnexpectedEof,
-                            "mtproto helper closed stdout",
-                        ))
-                    } else {
-                        Ok(line)
-                    }
-                });
-                let _ = tx.send(res);
-            });
+/// Batch items come back in request order; anything else means the stream is out of step.
+fn check_batch_index(env: &ResponseEnvelope, expected: usize) -> Result<()> {
+    let index = env.data.get("index").and_then(|v| v.as_u64());
+    if index != Some(expected as u64) {
+        return Err(Error::telegram(format!(
+            "mtproto helper batch item out of order: expected={expected} got={index:?}"
+        )));
+    }
+    Ok(())
+}
 
-            let line = match rx.recv_timeout(Duration::from_secs(timeout_secs)) {
-                Ok(Ok(line)) => line,
-                Ok(Err(e)) => {
-                    return Err(Error::Telegram {
-                        message: format!("mtproto helper read failed: {e}"),
-                    });
+fn apply_session_b64(session_b64: &mut Option<String>, env: &ResponseEnvelope) {
+    if let Some(b64) = &env.session_b64
+        && !b64.is_empty()
+    {
+        *session_b64 = Some(b64.to_string());
+    }
+}
+
+fn read_response_line(
+    child: &mut Child,
+    stdout: &mut BufReader<ChildStdout>,
+    timeout_secs: u64,
+) -> Result<ResponseEnvelope> {
+    let (tx, rx) = mpsc::channel::<std::io::Result<String>>();
+
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            let mut line = String::new();
+            let res = stdout.read_line(&mut line).and_then(|n| {
+                if n == 0 {
+                    Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "mtproto helper closed stdout",
+                    ))
+                } else {
+                    Ok(line)
                 }
-                Err(mpsc::RecvTimeoutError::Timeout) => {
-                    // The helper became unresponsive. Kill it so the blocked read unblocks,
-                    // then let the caller decide whether to retry after respawn.
-                    let _ = child.kill();
-                    for _ in 0..50 {
-                        match child.try_wait() {
-                            Ok(Some(_)) => break,
-                            Ok(None) => std::thread::sleep(Duration::from_millis(100)),
-                            Err(_) => break,
-                        }
+            });
+            let _ = tx.send(res);
+        });
+
+        let line = match rx.recv_timeout(Duration::from_secs(timeout_secs)) {
+            Ok(Ok(line)) => line,
+            Ok(Err(e)) => {
+                return Err(Error::telegram(format!("mtproto helper read failed: {e}")));
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                // The helper became unresponsive. Kill it so the blocked read unblocks,
+                // then let the caller decide whether to retry after respawn.
+                let _ = child.kill();
+                for _ in 0..50 {
+                    match child.try_wait() {
+                        Ok(Some(_)) => break,
+                        Ok(None) => std::thread::sleep(Duration::from_millis(100)),
+                        Err(_) => break,
                     }
-                    return Err(Error::Telegram {
-                        message: format!(
-                            "mtproto helper timed out waiting for response after {timeout_secs}s"
-                        ),
-                    });
                 }
-                Err(mpsc::RecvTimeoutError::Disconnected) => {
-                    return Err(Error::Telegram {
-                        message: "mtproto helper response channel disconnected".to_string(),
-                    });
-                }
-            };
+                return Err(Error::telegram(format!(
+                    "mtproto helper timed out waiting for response after {timeout_secs}s"
+                )));
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                return Err(Error::telegram(
+                    "mtproto helper response channel disconnected".to_string(),
+                ));
+            }
+        };
 
-            serde_json::from_str::<ResponseEnvelope>(line.trim_end()).map_err(|e| Error::Telegram {
-                message: format!("mtproto helper invalid response: {e}"),
-            })
-        })
+        serde_json::from_str::<ResponseEnvelope>(line.trim_end())
+            .map_err(|e| Error::telegram(format!("mtproto helper invalid response: {e}")))
+    })
+}
+
+fn write_upload_body(
+    stdin: &mut ChildStdin,
+    mut body: UploadBody<'_>,
+    len: u64,
+    caption_kind: Option<ObjectKind>,
+) -> Result<()> {
+    let mut buf = vec![0u8; UPLOAD_BODY_READ_BYTES];
+    let mut hasher = caption_kind.map(|_| sha2::Sha256::new());
+    let mut remaining = len;
+    while remaining > 0 {
+        let want = remaining.min(buf.len() as u64) as usize;
+        let n = body.read(&mut buf[..want])?;
+        if n == 0 {
+            return Err(Error::Integrity {
+                message: format!(
+                    "upload body ended early: expected={len} got={}",
+                    len - remaining
+                ),
+            });
+        }
+        stdin
+            .write_all(&buf[..n])
+            .map_err(|e| Error::telegram(format!("mtproto helper upload write failed: {e}")))?;
+        if let Some(hasher) = hasher.as_mut() {
+            hasher.update(&buf[..n]);
+        }
+        remaining -= n as u64;
+    }
+    if let (Some(kind), Some(hasher)) = (caption_kind, hasher) {
+        let caption = ObjectCaption {
+            kind,
+            len,
+            sha256: hex::encode(hasher.finalize()),
+        };
+        writeln!(stdin, "{}", caption.encode())
+            .map_err(|e| Error::telegram(format!("mtproto helper upload write failed: {e}")))?;
     }
+    stdin.flush().ok();
+    Ok(())
 }
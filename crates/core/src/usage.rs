@@ -0,0 +1,341 @@
+//! Local usage statistics: one row per finished run in `<data_dir>/usage.sqlite`.
+//!
+//! Kept indefinitely (until `stats prune`) and independent of run logs. Nothing here leaves the
+//! machine.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Serialize;
+use sqlx::Row;
+use sqlx::SqlitePool;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
+use tracing::warn;
+
+use crate::Result;
+use crate::status::RunStatus;
+
+pub const USAGE_DB_FILE_NAME: &str = "usage.sqlite";
+
+/// How long a run waits for a locked usage DB before giving up on recording itself.
+const USAGE_DB_BUSY_TIMEOUT: Duration = Duration::from_millis(250);
+
+pub fn usage_db_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(USAGE_DB_FILE_NAME)
+}
+
+/// One finished backup, restore or verify run.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageRun {
+    /// RFC3339 (UTC).
+    pub finished_at: String,
+    /// `backup`, `restore` or `verify`.
+    pub kind: String,
+    pub target_id: Option<String>,
+    /// Bytes uploaded (backup), written (restore) or checked (verify).
+    pub bytes: u64,
+    pub duration_seconds: f64,
+    /// A [`RunStatus`] string.
+    pub status: String,
+}
+
+impl UsageRun {
+    pub fn finished_now(
+        kind: &str,
+        target_id: Option<&str>,
+        bytes: u64,
+        duration_seconds: f64,
+        status: RunStatus,
+    ) -> Self {
+        Self {
+            finished_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            kind: kind.to_string(),
+            target_id: target_id.map(str::to_string),
+            bytes,
+            duration_seconds,
+            status: status.as_str().to_string(),
+        }
+    }
+}
+
+/// Runs of one kind in one calendar month (UTC).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageMonth {
+    /// `YYYY-MM`.
+    pub month: String,
+    pub kind: String,
+    pub runs: u64,
+    pub runs_failed: u64,
+    pub runs_cancelled: u64,
+    pub bytes: u64,
+    pub duration_seconds_avg: f64,
+}
+
+async fn open_usage_db(path: &Path) -> Result<SqlitePool> {
+    let options = SqliteConnectOptions::new()
+        .filename(path)
+        .create_if_missing(true)
+        .journal_mode(SqliteJournalMode::Delete)
+        .busy_timeout(USAGE_DB_BUSY_TIMEOUT);
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .acquire_timeout(USAGE_DB_BUSY_TIMEOUT * 4)
+        .connect_with(options)
+        .await?;
+    sqlx::raw_sql(
+        r#"
+        CREATE TABLE IF NOT EXISTS usage_runs (
+          finished_at TEXT NOT NULL,
+          kind TEXT NOT NULL,
+          target_id TEXT NULL,
+          bytes INTEGER NOT NULL,
+          duration_seconds REAL NOT NULL,
+          status TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS usage_runs_finished_at ON usage_runs(finished_at);
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+    Ok(pool)
+}
+
+pub async fn record_usage_run(data_dir: &Path, run: &UsageRun) -> Result<()> {
+    std::fs::create_dir_all(data_dir)?;
+    let pool = open_usage_db(&usage_db_path(data_dir)).await?;
+    sqlx::query(
+        "INSERT INTO usage_runs (finished_at, kind, target_id, bytes, duration_seconds, status) VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&run.finished_at)
+    .bind(&run.kind)
+    .bind(&run.target_id)
+    .bind(i64::try_from(run.bytes).unwrap_or(i64::MAX))
+    .bind(run.duration_seconds)
+    .bind(&run.status)
+    .execute(&pool)
+    .await?;
+    pool.close().await;
+    Ok(())
+}
+
+/// Records `run`, logging instead of failing: a locked or broken usage DB never fails a run.
+pub async fn record_usage_run_best_effort(data_dir: &Path, run: &UsageRun) {
+    if let Err(e) = record_usage_run(data_dir, run).await {
+        warn!(
+            event = "usage.record_failed",
+            kind = %run.kind,
+            error = %e,
+            "usage.record_failed"
+        );
+    }
+}
+
+/// Per-month, per-kind aggregates, oldest month first. Empty when nothing was recorded yet.
+pub async fn usage_monthly(data_dir: &Path, target_id: Option<&str>) -> Result<Vec<UsageMonth>> {
+    let path = usage_db_path(data_dir);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let pool = open_usage_db(&path).await?;
+    let rows = sqlx::query(
+        r#"
+        SELECT substr(finished_at, 1, 7) as month,
+               kind,
+               COUNT(1) as runs,
+               SUM(CASE WHEN status = 'failed' THEN 1 ELSE 0 END) as runs_failed,
+               SUM(CASE WHEN status = 'cancelled' THEN 1 ELSE 0 END) as runs_cancelled,
+               COALESCE(SUM(bytes), 0) as bytes,
+               COALESCE(AVG(duration_seconds), 0.0) as duration_seconds_avg
+        FROM usage_runs
+        WHERE ? IS NULL OR target_id = ?
+        GROUP BY month, kind
+        ORDER BY month, kind
+        "#,
+    )
+    .bind(target_id)
+    .bind(target_id)
+    .fetch_all(&pool)
+    .await?;
+    pool.close().await;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| UsageMonth {
+            month: row.get("month"),
+            kind: row.get("kind"),
+            runs: row.get::<i64, _>("runs").max(0) as u64,
+            runs_failed: row.get::<i64, _>("runs_failed").max(0) as u64,
+            runs_cancelled: row.get::<i64, _>("runs_cancelled").max(0) as u64,
+            bytes: row.get::<i64, _>("bytes").max(0) as u64,
+            duration_seconds_avg: row.get("duration_seconds_avg"),
+        })
+        .collect())
+}
+
+/// Runs of `kind` that finished at or after `since` (RFC3339, UTC), oldest first.
+pub async fn usage_runs_since(data_dir: &Path, kind: &str, since: &str) -> Result<Vec<UsageRun>> {
+    let path = usage_db_path(data_dir);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let pool = open_usage_db(&path).await?;
+    let rows = sqlx::query(
+        r#"
+        SELECT finished_at, kind, target_id, bytes, duration_seconds, status
+        FROM usage_runs
+        WHERE kind = ? AND finished_at >= ?
+        ORDER BY finished_at
+        "#,
+    )
+    .bind(kind)
+    .bind(since)
+    .fetch_all(&pool)
+    .await?;
+    pool.close().await;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| UsageRun {
+            finished_at: row.get("finished_at"),
+            kind: row.get("kind"),
+            target_id: row.get("target_id"),
+            bytes: row.get::<i64, _>("bytes").max(0) as u64,
+            duration_seconds: row.get("duration_seconds"),
+            status: row.get("status"),
+        })
+        .collect())
+}
+
+/// Deletes runs that finished before `before` (RFC3339, UTC); returns how many were removed.
+pub async fn prune_usage_runs(data_dir: &Path, before: &str) -> Result<u64> {
+    let path = usage_db_path(data_dir);
+    if !path.exists() {
+        return Ok(0);
+    }
+    let pool = open_usage_db(&path).await?;
+    let res = sqlx::query("DELETE FROM usage_runs WHERE finished_at < ?")
+        .bind(before)
+        .execute(&pool)
+        .await?;
+    pool.close().await;
+    Ok(res.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(
+        finished_at: &str,
+        kind: &str,
+        target_id: &str,
+        bytes: u64,
+        status: RunStatus,
+    ) -> UsageRun {
+        UsageRun {
+            finished_at: finished_at.to_string(),
+            kind: kind.to_string(),
+            target_id: Some(target_id.to_string()),
+            bytes,
+            duration_seconds: 10.0,
+            status: status.as_str().to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn monthly_aggregates_and_prune() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(usage_monthly(dir.path(), None).await.unwrap().is_empty());
+
+        for r in [
+            run(
+                "2024-01-05T10:00:00Z",
+                "backup",
+                "t1",
+                100,
+                RunStatus::Succeeded,
+            ),
+            run(
+                "2024-01-20T10:00:00Z",
+                "backup",
+                "t1",
+                50,
+                RunStatus::Failed,
+            ),
+            run(
+                "2024-01-21T10:00:00Z",
+                "backup",
+                "t2",
+                7,
+                RunStatus::Succeeded,
+            ),
+            run(
+                "2024-01-22T10:00:00Z",
+                "backup",
+                "t2",
+                3,
+                RunStatus::Cancelled,
+            ),
+            run(
+                "2024-02-01T00:00:00Z",
+                "verify",
+                "t1",
+                30,
+                RunStatus::Succeeded,
+            ),
+        ] {
+            record_usage_run(dir.path(), &r).await.unwrap();
+        }
+
+        let since = usage_runs_since(dir.path(), "backup", "2024-01-21T00:00:00Z")
+            .await
+            .unwrap();
+        assert_eq!(
+            since
+                .iter()
+                .map(|r| (r.target_id.as_deref(), r.bytes, r.status.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(Some("t2"), 7, "succeeded"), (Some("t2"), 3, "cancelled")]
+        );
+
+        let t1 = usage_monthly(dir.path(), Some("t1")).await.unwrap();
+        assert_eq!(
+            t1.iter()
+                .map(|m| (
+                    m.month.as_str(),
+                    m.kind.as_str(),
+                    m.runs,
+                    m.runs_failed,
+                    m.bytes
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("2024-01", "backup", 2, 1, 150),
+                ("2024-02", "verify", 1, 0, 30),
+            ]
+        );
+        let all = usage_monthly(dir.path(), None).await.unwrap();
+        assert_eq!(all[0].runs, 4);
+        assert_eq!(all[0].runs_failed, 1);
+        assert_eq!(all[0].runs_cancelled, 1);
+        assert_eq!(all[0].bytes, 160);
+
+        assert_eq!(
+            prune_usage_runs(dir.path(), "2024-02-01T00:00:00Z")
+                .await
+                .unwrap(),
+            4
+        );
+        let all = usage_monthly(dir.path(), None).await.unwrap();
+        assert_eq!(all.len(), 1);
+        assert_eq!(all[0].month, "2024-02");
+        assert!(
+            usage_runs_since(dir.path(), "backup", "2024-01-01T00:00:00Z")
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,317 @@
+//! Version handshake between the CLI, the daemon and the MTProto helper.
+//!
+//! After an upgrade the launchd daemon (and the helper it spawns) can keep running old binaries
+//! while a new CLI talks to them. The daemon stamps every control and vault IPC response with a
+//! [`DaemonVersionStamp`]; the helper reports its version in its `init` and `version` responses.
+//! Each side compares with [`check_skew`]: a breaking difference refuses with `version.skew`,
+//! anything else only warns.
+
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+use crate::config::SETTINGS_SCHEMA_VERSION;
+
+/// Version of this build; the CLI, daemon and helper are released together.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The newest MTProto helper protocol this build speaks.
+pub const MTPROTO_HELPER_PROTOCOL_VERSION: u64 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Component {
+    Cli,
+    Daemon,
+    Helper,
+}
+
+impl Component {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Cli => "cli",
+            Self::Daemon => "daemon",
+            Self::Helper => "helper",
+        }
+    }
+
+    fn display_name(self) -> &'static str {
+        match self {
+            Self::Cli => "the CLI (televybackup)",
+            Self::Daemon => "the daemon (televybackupd)",
+            Self::Helper => "the MTProto helper (televybackup-mtproto-helper)",
+        }
+    }
+
+    /// How to get rid of a stale binary of this component.
+    fn restart_hint(self) -> &'static str {
+        match self {
+            Self::Cli => "run the televybackup shipped with the running app, or upgrade it",
+            Self::Daemon => {
+                "restart it with `brew services restart televybackupd` (or `launchctl kickstart -k gui/$(id -u)/homebrew.mxcl.televybackupd`)"
+            }
+            Self::Helper => {
+                "reinstall the app so the helper next to televybackupd matches, then restart the daemon"
+            }
+        }
+    }
+
+    /// Helper protocol differences are bridged by fallbacks; a settings schema is binding.
+    fn schema_label(self) -> &'static str {
+        match self {
+            Self::Helper => "protocol",
+            Self::Cli | Self::Daemon => "settings schema",
+        }
+    }
+}
+
+/// Fields the daemon adds to every control and vault IPC response; both are absent from daemons
+/// predating the handshake.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DaemonVersionStamp {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daemon_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settings_version: Option<u32>,
+}
+
+impl DaemonVersionStamp {
+    pub fn new(daemon_version: impl Into<String>) -> Self {
+        Self {
+            daemon_version: Some(daemon_version.into()),
+            settings_version: Some(SETTINGS_SCHEMA_VERSION),
+        }
+    }
+
+    pub fn component_version(&self) -> ComponentVersion {
+        ComponentVersion {
+            component: Component::Daemon,
+            version: self.daemon_version.clone(),
+            schema: self.settings_version.map(u64::from),
+        }
+    }
+}
+
+/// What one side of a handshake knows about a component.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ComponentVersion {
+    pub component: Component,
+    /// `None` when the component predates the version handshake.
+    pub version: Option<String>,
+    /// Settings schema (CLI, daemon) or protocol version (helper).
+    pub schema: Option<u64>,
+}
+
+impl ComponentVersion {
+    /// This process, speaking `schema` (the settings schema, or the helper protocol).
+    pub fn local(component: Component, version: &str, schema: u64) -> Self {
+        Self {
+            component,
+            version: Some(version.to_string()),
+            schema: Some(schema),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionSkew {
+    pub local: ComponentVersion,
+    pub remote: ComponentVersion,
+    /// Refuse to continue rather than warn.
+    pub breaking: bool,
+}
+
+impl VersionSkew {
+    /// The older side; a component predating the handshake is older by definition.
+    pub fn stale(&self) -> &ComponentVersion {
+        let Some(remote) = self.remote.version.as_deref() else {
+            return &self.remote;
+        };
+        let local = self.local.version.as_deref().unwrap_or_default();
+        let key = |v: &ComponentVersion, s: &str| (parse_semver(s), v.schema);
+        if key(&self.local, local) < key(&self.remote, remote) {
+            &self.local
+        } else {
+            &self.remote
+        }
+    }
+
+    pub fn message(&self) -> String {
+        let stale = self.stale();
+        let current = if stale == &self.local {
+            &self.remote
+        } else {
+            &self.local
+        };
+        let describe = |v: &ComponentVersion| {
+            let version = v.version.as_deref().unwrap_or("unknown");
+            match v.schema {
+                Some(schema) => format!(
+                    "{} {version} ({} {schema})",
+                    v.component.display_name(),
+                    v.component.schema_label()
+                ),
+                None => format!("{} {version}", v.component.display_name()),
+            }
+        };
+        let what = if stale.version.is_none() {
+            format!(
+                "{} predates the version handshake; {} is {}",
+                stale.component.display_name(),
+                current.component.display_name(),
+                current.version.as_deref().unwrap_or("unknown")
+            )
+        } else {
+            format!("{} is older than {}", describe(stale), describe(current))
+        };
+        format!("version skew: {what}; {}", stale.component.restart_hint())
+    }
+}
+
+impl From<VersionSkew> for Error {
+    fn from(skew: VersionSkew) -> Self {
+        Error::VersionSkew {
+            component: skew.stale().component.as_str().to_string(),
+            local_version: skew.local.version.clone().unwrap_or_default(),
+            remote_version: skew.remote.version.clone(),
+            message: skew.message(),
+        }
+    }
+}
+
+/// `None` when both sides agree. Breaking: a different major version (minor while at 0.x), or a
+/// different settings schema; helper protocol differences and patch releases only warn.
+pub fn check_skew(local: &ComponentVersion, remote: &ComponentVersion) -> Option<VersionSkew> {
+    let schema_binding =
+        local.component != Component::Helper && remote.component != Component::Helper;
+    let schema_differs =
+        local.schema.is_some() && remote.schema.is_some() && local.schema != remote.schema;
+    let (breaking, differs) = match (local.version.as_deref(), remote.version.as_deref()) {
+        (Some(l), Some(r)) => {
+            let breaking = match (parse_semver(l), parse_semver(r)) {
+                (Some(l), Some(r)) => compatibility_key(l) != compatibility_key(r),
+                _ => false,
+            };
+            (breaking, l != r)
+        }
+        _ => (false, true),
+    };
+    let breaking = breaking || (schema_binding && schema_differs);
+    (breaking || differs || schema_differs).then(|| VersionSkew {
+        local: local.clone(),
+        remote: remote.clone(),
+        breaking,
+    })
+}
+
+/// Releases sharing this key are compatible: the major version, or `0.minor` before 1.0.
+fn compatibility_key((major, minor, _): (u64, u64, u64)) -> (u64, u64) {
+    if major == 0 { (0, minor) } else { (major, 0) }
+}
+
+/// `1.2.3`, `v1.2.3` or `1.2.3-beta.1+build`; pre-release and build suffixes are ignored.
+fn parse_semver(v: &str) -> Option<(u64, u64, u64)> {
+    let core = v.trim().trim_start_matches('v');
+    let core = core.split(['-', '+']).next()?;
+    let mut parts = core.split('.').map(|p| p.parse::<u64>().ok());
+    let major = parts.next()??;
+    let minor = parts.next().unwrap_or(Some(0))?;
+    let patch = parts.next().unwrap_or(Some(0))?;
+    Some((major, minor, patch))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cli(version: &str) -> ComponentVersion {
+        ComponentVersion::local(Component::Cli, version, u64::from(SETTINGS_SCHEMA_VERSION))
+    }
+
+    fn daemon(version: Option<&str>, schema: Option<u64>) -> ComponentVersion {
+        ComponentVersion {
+            component: Component::Daemon,
+            version: version.map(str::to_string),
+            schema,
+        }
+    }
+
+    #[test]
+    fn parses_semver_with_suffixes() {
+        assert_eq!(parse_semver("1.2.3"), Some((1, 2, 3)));
+        assert_eq!(parse_semver("v0.4.0-beta.1+abc"), Some((0, 4, 0)));
+        assert_eq!(parse_semver("2"), Some((2, 0, 0)));
+        assert_eq!(parse_semver("dev"), None);
+    }
+
+    #[test]
+    fn matching_versions_report_nothing() {
+        let schema = Some(u64::from(SETTINGS_SCHEMA_VERSION));
+        assert_eq!(
+            check_skew(&cli("0.3.1"), &daemon(Some("0.3.1"), schema)),
+            None
+        );
+    }
+
+    #[test]
+    fn patch_differences_warn_and_name_the_older_side() {
+        let schema = Some(u64::from(SETTINGS_SCHEMA_VERSION));
+        let skew = check_skew(&cli("0.3.2"), &daemon(Some("0.3.1"), schema)).unwrap();
+        assert!(!skew.breaking);
+        assert_eq!(skew.stale().component, Component::Daemon);
+        assert!(
+            skew.message()
+                .contains("brew services restart televybackupd")
+        );
+
+        let skew = check_skew(&cli("1.0.0"), &daemon(Some("1.4.0"), schema)).unwrap();
+        assert!(!skew.breaking);
+        assert_eq!(skew.stale().component, Component::Cli);
+    }
+
+    #[test]
+    fn major_and_schema_differences_are_breaking() {
+        let schema = Some(u64::from(SETTINGS_SCHEMA_VERSION));
+        assert!(
+            check_skew(&cli("2.0.0"), &daemon(Some("1.9.0"), schema))
+                .unwrap()
+                .breaking
+        );
+        assert!(
+            check_skew(&cli("0.4.0"), &daemon(Some("0.3.9"), schema))
+                .unwrap()
+                .breaking
+        );
+
+        let old_schema = Some(u64::from(SETTINGS_SCHEMA_VERSION) - 1);
+        let skew = check_skew(&cli("0.3.1"), &daemon(Some("0.3.1"), old_schema)).unwrap();
+        assert!(skew.breaking);
+        assert_eq!(skew.stale().component, Component::Daemon);
+    }
+
+    #[test]
+    fn a_peer_without_a_version_predates_the_handshake() {
+        let skew = check_skew(&cli("0.3.1"), &daemon(None, None)).unwrap();
+        assert!(!skew.breaking);
+        assert_eq!(skew.stale().component, Component::Daemon);
+        assert!(skew.message().contains("predates the version handshake"));
+
+        let err = Error::from(skew);
+        assert_eq!(err.code(), "version.skew");
+        assert_eq!(err.details()["component"], "daemon");
+    }
+
+    #[test]
+    fn helper_protocol_differences_only_warn() {
+        let core = ComponentVersion::local(Component::Daemon, "0.3.1", 2);
+        let helper = ComponentVersion {
+            component: Component::Helper,
+            version: Some("0.3.1".to_string()),
+            schema: Some(1),
+        };
+        let skew = check_skew(&core, &helper).unwrap();
+        assert!(!skew.breaking);
+        assert_eq!(skew.stale().component, Component::Helper);
+    }
+}
@@ -1,13 +1,24 @@
+use std::future::Future;
 use std::path::{Path, PathBuf};
+use std::pin::Pin;
 use std::sync::Mutex;
 use std::sync::atomic::{AtomicBool, Ordering};
 
 use sqlx::Row;
+use televy_backup_core::config::{TargetFilter, TelegramRateLimit};
+use televy_backup_core::privacy_audit::{
+    PrivacyAuditConfig, audit_backup_privacy, privacy_needles,
+};
+use televy_backup_core::quiet_hours::UploadThrottle;
 use televy_backup_core::{
-    BackupConfig, BackupOptions, ChunkingConfig, InMemoryStorage, ProgressSink, RemoteDedupeMode,
-    SourceQuickStats, TaskProgress, run_backup, run_backup_with,
+    BackupConfig, BackupOptions, ChunkingConfig, Error, GcConfig, GcOptions, InMemoryStorage,
+    ObjectCaption, Phase, ProgressSink, RemoteDedupeMode, SkipReason, SourceQuickStats, Storage,
+    StorageProgress, TaskProgress, UploadBody, UploadMetadata, collect_garbage,
+    compute_source_quick_stats, delete_snapshot, preview_source, rebase_snapshot, run_backup,
+    run_backup_with, snapshot_chain,
 };
 use tempfile::TempDir;
+use tokio_util::sync::CancellationToken;
 
 fn write_file(path: PathBuf, bytes: &[u8]) {
     std::fs::create_dir_all(path.parent().unwrap()).unwrap();
@@ -38,7 +49,7 @@ impl ProgressSink for MutateOnUpload {
             .lock()
             .expect("progress sink mutex poisoned")
             .push(progress.clone());
-        if (progress.phase == "upload" || progress.phase == "scan_upload")
+        if (progress.phase == Phase::Upload || progress.phase == Phase::ScanUpload)
             && !self.fired.swap(true, Ordering::SeqCst)
         {
             std::fs::write(&self.file_path, &self.bytes).unwrap();
@@ -80,9 +91,14 @@ async fn backup_pipeline_dedupes_chunks_across_runs() {
         chunking: chunking.clone(),
         rate_limit: Default::default(),
         master_key: [7u8; 32],
+        data_key: None,
         snapshot_id: None,
         keep_last_snapshots: 10,
         remote_dedupe: RemoteDedupeMode::Disabled,
+        hint_changed_paths: None,
+        device: None,
+        index_full_every: 1,
+        created_at: None,
     };
 
     let r1 = run_backup(&storage, cfg1).await.unwrap();
@@ -106,9 +122,14 @@ async fn backup_pipeline_dedupes_chunks_across_runs() {
         chunking,
         rate_limit: Default::default(),
         master_key: [7u8; 32],
+        data_key: None,
         snapshot_id: None,
         keep_last_snapshots: 10,
         remote_dedupe: RemoteDedupeMode::Disabled,
+        hint_changed_paths: None,
+        device: None,
+        index_full_every: 1,
+        created_at: None,
     };
 
     let r2 = run_backup(&storage, cfg2).await.unwrap();
@@ -183,9 +204,14 @@ async fn backup_uploads_while_scanning_when_source_changes_mid_run() {
         },
         rate_limit: Default::default(),
         master_key: [9u8; 32],
+        data_key: None,
         snapshot_id: None,
         keep_last_snapshots: 10,
         remote_dedupe: RemoteDedupeMode::Disabled,
+        hint_changed_paths: None,
+        device: None,
+        index_full_every: 1,
+        created_at: None,
     };
 
     let sink = MutateOnUpload::new(&file_path, changed);
@@ -199,6 +225,18 @@ async fn backup_uploads_while_scanning_when_source_changes_mid_run() {
                 files_total: 1,
                 bytes_total: initial.len() as u64,
             }),
+            strict: false,
+            scan_root: None,
+            retry: Default::default(),
+            worker_threads: 0,
+            one_file_system: true,
+            default_excludes: false,
+            verify_after_upload: false,
+            filters: &[],
+            warn_path_bytes: 0,
+            max_unreadable_percent: 0,
+            upload_throttle: None,
+            sqlite_consistent: Default::default(),
         },
     )
     .await
@@ -223,7 +261,7 @@ async fn backup_uploads_while_scanning_when_source_changes_mid_run() {
     let overlapped = seen.iter().any(|p| {
         p.bytes_uploaded.unwrap_or(0) > 0
             && p.bytes_read.unwrap_or(u64::MAX) < initial.len() as u64
-            && (p.phase == "scan_upload" || p.phase == "upload")
+            && (p.phase == Phase::ScanUpload || p.phase == Phase::Upload)
     });
     assert!(
         overlapped,
@@ -260,9 +298,14 @@ async fn backup_compacts_local_index_db_after_success() {
         },
         rate_limit: Default::default(),
         master_key: [3u8; 32],
+        data_key: None,
         snapshot_id: None,
         keep_last_snapshots: 10,
         remote_dedupe: RemoteDedupeMode::Disabled,
+        hint_changed_paths: None,
+        device: None,
+        index_full_every: 1,
+        created_at: None,
     };
 
     let r1 = run_backup(&storage, cfg.clone()).await.unwrap();
@@ -301,3 +344,1108 @@ async fn backup_compacts_local_index_db_after_success() {
         .get("n");
     assert_eq!(file_chunks, 0);
 }
+
+fn isolated_config(root: &Path, source: &Path) -> BackupConfig {
+    BackupConfig {
+        endpoint_db_path: root.join("index.sqlite"),
+        filemap_dir: root.join("filemaps"),
+        dedupe_db_path: root.join("dedupe.sqlite"),
+        dedupe_pending_db_path: root.join("dedupe.pending.sqlite"),
+        source_path: source.to_path_buf(),
+        label: "hint".to_string(),
+        chunking: ChunkingConfig {
+            min_bytes: 64,
+            avg_bytes: 256,
+            max_bytes: 1024,
+        },
+        rate_limit: Default::default(),
+        master_key: [5u8; 32],
+        data_key: None,
+        snapshot_id: None,
+        keep_last_snapshots: 10,
+        remote_dedupe: RemoteDedupeMode::Disabled,
+        hint_changed_paths: None,
+        device: None,
+        index_full_every: 1,
+        created_at: None,
+    }
+}
+
+type SnapshotEntry = (String, String, i64, i64, i64, Option<String>);
+
+async fn snapshot_contents(filemap_dir: &Path, snapshot_id: &str) -> Vec<SnapshotEntry> {
+    let db_path = filemap_dir.join(format!("{snapshot_id}.sqlite"));
+    let pool = sqlx::SqlitePool::connect(&format!("sqlite:{}", db_path.display()))
+        .await
+        .unwrap();
+    let rows = sqlx::query(
+        r#"
+        SELECT f.path, f.kind, f.size, f.mtime_ms, f.mode,
+               (SELECT group_concat(chunk_hash, ',')
+                FROM (SELECT chunk_hash FROM file_chunks fc WHERE fc.file_id = f.file_id ORDER BY seq))
+               AS chunks
+        FROM files f
+        WHERE f.snapshot_id = ?
+        ORDER BY f.path
+        "#,
+    )
+    .bind(snapshot_id)
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    rows.into_iter()
+        .map(|r| {
+            (
+                r.get("path"),
+                r.get("kind"),
+                r.get("size"),
+                r.get("mtime_ms"),
+                r.get("mode"),
+                r.get("chunks"),
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn backup_with_changed_path_hint_matches_full_scan() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("src");
+    std::fs::create_dir_all(&source).unwrap();
+
+    write_file(source.join("stable/a.txt"), &[1u8; 3000]);
+    write_file(source.join("stable/deep/b.bin"), &[2u8; 5000]);
+    write_file(source.join("changed.txt"), b"before");
+    write_file(source.join("gone.txt"), b"delete me");
+    write_file(source.join("old_dir/c.txt"), b"moved later");
+    write_file(source.join("skip.log"), b"ignored after rules change");
+
+    let full_root = temp.path().join("full");
+    let hinted_root = temp.path().join("hinted");
+    let storage = InMemoryStorage::new();
+    run_backup(&storage, isolated_config(&full_root, &source))
+        .await
+        .unwrap();
+    run_backup(&storage, isolated_config(&hinted_root, &source))
+        .await
+        .unwrap();
+
+    write_file(source.join("changed.txt"), b"after, and longer than before");
+    std::fs::remove_file(source.join("gone.txt")).unwrap();
+    std::fs::rename(source.join("old_dir"), source.join("moved_dir")).unwrap();
+    write_file(source.join("new/nested/d.bin"), &[4u8; 2048]);
+    write_file(source.join(".televyignore"), b"skip.log\n");
+
+    let full = run_backup(&storage, isolated_config(&full_root, &source))
+        .await
+        .unwrap();
+    let mut hinted_cfg = isolated_config(&hinted_root, &source);
+    hinted_cfg.hint_changed_paths = Some(vec![
+        source.join("changed.txt"),
+        source.join("gone.txt"),
+        source.join("old_dir"),
+        source.join("moved_dir"),
+        // Relative entries resolve against `source_path`.
+        PathBuf::from("new"),
+        PathBuf::from(".televyignore"),
+    ]);
+    let hinted = run_backup(&storage, hinted_cfg).await.unwrap();
+
+    let full_contents = snapshot_contents(&full_root.join("filemaps"), &full.snapshot_id).await;
+    let hinted_contents =
+        snapshot_contents(&hinted_root.join("filemaps"), &hinted.snapshot_id).await;
+    assert_eq!(hinted_contents, full_contents);
+
+    let paths = full_contents
+        .iter()
+        .map(|(p, ..)| p.as_str())
+        .collect::<Vec<_>>();
+    assert!(paths.contains(&"moved_dir/c.txt"));
+    assert!(paths.contains(&"new/nested/d.bin"));
+    assert!(!paths.contains(&"gone.txt"));
+    assert!(!paths.contains(&"old_dir/c.txt"));
+    assert!(!paths.contains(&"skip.log"));
+    assert_eq!(hinted.files_indexed, full.files_indexed);
+    assert_eq!(hinted.chunks_total, full.chunks_total);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn unreadable_file_is_skipped_unless_strict() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("src");
+    write_file(source.join("ok.txt"), b"readable\n");
+    let locked = source.join("locked.txt");
+    write_file(locked.clone(), b"secret\n");
+    std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o000)).unwrap();
+    if std::fs::read(&locked).is_ok() {
+        // Running as root: permissions are not enforced.
+        eprintln!("skipping: file permissions are not enforced for this user");
+        return;
+    }
+
+    let storage = InMemoryStorage::new();
+    let lenient_root = temp.path().join("lenient");
+    let res = run_backup(&storage, isolated_config(&lenient_root, &source))
+        .await
+        .unwrap();
+    assert_eq!(res.files_skipped_errors, 1);
+    assert_eq!(res.skipped_files.len(), 1);
+    assert_eq!(res.skipped_files[0].path, "locked.txt");
+    assert_eq!(res.skipped_files[0].reason, SkipReason::PermissionDenied);
+    let paths = snapshot_contents(&lenient_root.join("filemaps"), &res.snapshot_id)
+        .await
+        .into_iter()
+        .map(|(p, ..)| p)
+        .collect::<Vec<_>>();
+    assert_eq!(paths, vec!["ok.txt".to_string()]);
+
+    let strict_root = temp.path().join("strict");
+    let err = run_backup_with(
+        &storage,
+        isolated_config(&strict_root, &source),
+        BackupOptions {
+            strict: true,
+            ..BackupOptions::default()
+        },
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, Error::Io(_)), "{err:?}");
+
+    let mostly_unreadable_root = temp.path().join("mostly_unreadable");
+    let err = run_backup_with(
+        &storage,
+        isolated_config(&mostly_unreadable_root, &source),
+        BackupOptions {
+            max_unreadable_percent: 10,
+            ..BackupOptions::default()
+        },
+    )
+    .await
+    .unwrap_err();
+    assert!(
+        matches!(
+            err,
+            Error::SourceUnreadable {
+                entries_unreadable: 1,
+                ..
+            }
+        ),
+        "{err:?}"
+    );
+
+    std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o644)).unwrap();
+}
+
+#[tokio::test]
+async fn file_filters_exclude_files_and_dry_run_preview_matches() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("src");
+    write_file(source.join("small.txt"), b"keep me");
+    write_file(source.join("big.bin"), &[1u8; 4096]);
+    write_file(source.join("photo.dat"), b"\x89PNG\r\n\x1a\nnot really");
+
+    let filter = |kind: &str| TargetFilter {
+        kind: kind.to_string(),
+        max_bytes: None,
+        mime_types: Vec::new(),
+        command: Vec::new(),
+        timeout_ms: 5_000,
+        budget_secs: 600,
+    };
+    let filters = vec![
+        TargetFilter {
+            max_bytes: Some(1024),
+            ..filter("max_file_bytes")
+        },
+        TargetFilter {
+            mime_types: vec!["image/*".to_string()],
+            ..filter("mime_exclude")
+        },
+    ];
+
+    let preview = preview_source(&source, &filters, None, true, false)
+        .await
+        .unwrap();
+    assert_eq!(preview.files_total, 1);
+    assert_eq!(preview.bytes_total, 7);
+    assert_eq!(preview.files_excluded, 2);
+
+    let root = temp.path().join("state");
+    let storage = InMemoryStorage::new();
+    let res = run_backup_with(
+        &storage,
+        isolated_config(&root, &source),
+        BackupOptions {
+            filters: &filters,
+            ..BackupOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(res.file_filters, preview.file_filters);
+    assert_eq!(
+        res.file_filters
+            .iter()
+            .map(|f| (f.kind.as_str(), f.files_excluded))
+            .collect::<Vec<_>>(),
+        vec![("max_file_bytes", 1), ("mime_exclude", 1)]
+    );
+
+    let contents = snapshot_contents(&root.join("filemaps"), &res.snapshot_id).await;
+    let files = contents
+        .iter()
+        .filter(|(_, kind, ..)| kind == "file")
+        .map(|(p, ..)| p.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(files, vec!["small.txt"]);
+}
+
+#[tokio::test]
+async fn backup_from_scan_root_records_logical_source_paths() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("src");
+    // Stands in for the source directory inside a mounted APFS snapshot.
+    let frozen = temp.path().join("mount/src");
+    write_file(source.join("a.txt"), b"live, written after the snapshot");
+    for dir in [&source, &frozen] {
+        write_file(dir.join(".televyignore"), b"skip.log\n");
+        write_file(dir.join("skip.log"), b"ignored");
+        write_file(dir.join("nested/b.bin"), &[7u8; 2048]);
+    }
+    write_file(frozen.join("a.txt"), b"frozen");
+
+    let root = temp.path().join("state");
+    let storage = InMemoryStorage::new();
+    let options = || BackupOptions {
+        scan_root: Some(&frozen),
+        ..BackupOptions::default()
+    };
+    let first = run_backup_with(&storage, isolated_config(&root, &source), options())
+        .await
+        .unwrap();
+
+    let contents = snapshot_contents(&root.join("filemaps"), &first.snapshot_id).await;
+    let files = contents
+        .iter()
+        .filter(|(_, kind, ..)| kind == "file")
+        .map(|(p, _, size, ..)| (p.as_str(), *size))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        files,
+        vec![(".televyignore", 9), ("a.txt", 6), ("nested/b.bin", 2048)]
+    );
+
+    let pool =
+        sqlx::SqlitePool::connect(&format!("sqlite:{}", root.join("index.sqlite").display()))
+            .await
+            .unwrap();
+    let source_path: String = sqlx::query_scalar("SELECT source_path FROM snapshots")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(source_path, source.to_str().unwrap());
+    pool.close().await;
+
+    // Changed-path hints name logical paths; they still apply to the walked mount.
+    write_file(frozen.join("a.txt"), b"frozen again, now longer");
+    let mut cfg = isolated_config(&root, &source);
+    cfg.hint_changed_paths = Some(vec![source.join("a.txt")]);
+    let second = run_backup_with(&storage, cfg, options()).await.unwrap();
+    let contents = snapshot_contents(&root.join("filemaps"), &second.snapshot_id).await;
+    assert!(
+        contents
+            .iter()
+            .any(|(p, _, size, ..)| p == "a.txt" && *size == 24)
+    );
+}
+
+#[tokio::test]
+async fn imported_history_keeps_created_at_and_dedupes_across_snapshots() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("src");
+    std::fs::create_dir_all(&source).unwrap();
+    let dump = temp.path().join("dump");
+    let shared = generated_bytes(11, 64 * 1024);
+    for (i, dir) in ["2021", "2022"].into_iter().enumerate() {
+        write_file(dump.join(dir).join("shared.bin"), &shared);
+        write_file(dump.join(dir).join("own.txt"), format!("v{i}").as_bytes());
+    }
+
+    let root = temp.path().join("state");
+    let storage = InMemoryStorage::new();
+    let mut results = Vec::new();
+    for (dir, created_at) in [
+        ("2021", "2021-03-04T05:06:07.000Z"),
+        ("2022", "2022-01-02T01:04:05.000Z"),
+    ] {
+        let scan_root = dump.join(dir);
+        let mut cfg = isolated_config(&root, &source);
+        cfg.label = format!("imported:{dir}");
+        cfg.created_at = Some(created_at.to_string());
+        let options = BackupOptions {
+            scan_root: Some(&scan_root),
+            ..BackupOptions::default()
+        };
+        results.push(run_backup_with(&storage, cfg, options).await.unwrap());
+    }
+    assert!(results[1].bytes_deduped >= shared.len() as u64);
+
+    let pool =
+        sqlx::SqlitePool::connect(&format!("sqlite:{}", root.join("index.sqlite").display()))
+            .await
+            .unwrap();
+    let rows =
+        sqlx::query("SELECT snapshot_id, created_at, label FROM snapshots ORDER BY created_at")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    let rows = rows
+        .iter()
+        .map(|r| {
+            (
+                r.get::<String, _>("snapshot_id"),
+                r.get::<String, _>("created_at"),
+                r.get::<String, _>("label"),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rows,
+        vec![
+            (
+                results[0].snapshot_id.clone(),
+                "2021-03-04T05:06:07.000Z".to_string(),
+                "imported:2021".to_string()
+            ),
+            (
+                results[1].snapshot_id.clone(),
+                "2022-01-02T01:04:05.000Z".to_string(),
+                "imported:2022".to_string()
+            ),
+        ]
+    );
+    pool.close().await;
+}
+
+#[tokio::test]
+async fn backup_counts_paths_that_collide_ignoring_case() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("src");
+    write_file(source.join("README.md"), b"upper");
+    write_file(source.join("readme.md"), b"lower");
+    write_file(source.join("other.txt"), b"other");
+
+    let storage = InMemoryStorage::new();
+    let res = run_backup(
+        &storage,
+        isolated_config(&temp.path().join("state"), &source),
+    )
+    .await
+    .unwrap();
+    assert_eq!(res.case_collisions, 2);
+}
+
+/// Deterministic, incompressible-looking bytes so chunk boundaries and hashes vary per file.
+fn generated_bytes(seed: u64, len: usize) -> Vec<u8> {
+    let mut state = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+fn generate_tree(root: &Path, files: u64, file_len: usize) {
+    for i in 0..files {
+        write_file(
+            root.join(format!("d{}/f{i:04}.bin", i % 8)),
+            &generated_bytes(i, file_len),
+        );
+    }
+}
+
+fn worker_config(root: &Path, source: &Path) -> BackupConfig {
+    BackupConfig {
+        chunking: ChunkingConfig {
+            min_bytes: 4 * 1024,
+            avg_bytes: 16 * 1024,
+            max_bytes: 64 * 1024,
+        },
+        // Keep the (in-memory) uploads from pacing the scan.
+        rate_limit: TelegramRateLimit {
+            max_concurrent_uploads: 8,
+            min_delay_ms: 0,
+        },
+        ..isolated_config(root, source)
+    }
+}
+
+#[tokio::test]
+async fn scan_workers_keep_snapshots_identical() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("src");
+    generate_tree(&source, 48, 128 * 1024);
+    let source_bytes = 48 * 128 * 1024;
+
+    let mut snapshots = Vec::new();
+    for worker_threads in [1, 4] {
+        let root = temp.path().join(format!("w{worker_threads}"));
+        let storage = InMemoryStorage::new();
+        let res = run_backup_with(
+            &storage,
+            worker_config(&root, &source),
+            BackupOptions {
+                worker_threads,
+                ..BackupOptions::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.bytes_read, source_bytes as u64);
+
+        let filemap = root
+            .join("filemaps")
+            .join(format!("{}.sqlite", res.snapshot_id));
+        let pool = sqlx::SqlitePool::connect(&format!("sqlite:{}", filemap.display()))
+            .await
+            .unwrap();
+        let insert_order: Vec<String> = sqlx::query_scalar("SELECT path FROM files ORDER BY rowid")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        pool.close().await;
+        let mut sorted = insert_order.clone();
+        sorted.sort();
+        assert_eq!(insert_order, sorted, "file rows follow path order");
+
+        snapshots.push(snapshot_contents(&root.join("filemaps"), &res.snapshot_id).await);
+    }
+    assert_eq!(snapshots[0].len(), 48 + 8);
+    assert_eq!(snapshots[0], snapshots[1]);
+}
+
+/// Benchmark: scan and chunking of a generated 2 GiB tree with one worker against one per core
+/// (up to 4). Ignored by default: it writes 2 GiB to disk, keeps the uploads in memory and needs
+/// several cores. Run it with
+/// `cargo test --release -p televy_backup_core --test backup_pipeline -- --ignored scan_throughput`.
+#[tokio::test(flavor = "multi_thread")]
+#[ignore]
+async fn scan_throughput_scales_with_worker_threads() {
+    let workers = std::thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(4);
+    assert!(workers >= 2, "needs at least two cores");
+
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("src");
+    let (files, file_len) = (2048, 1024 * 1024);
+    generate_tree(&source, files, file_len);
+
+    let mut scan_times = Vec::new();
+    for worker_threads in [1, workers] {
+        let storage = InMemoryStorage::new();
+        let res = run_backup_with(
+            &storage,
+            worker_config(&temp.path().join(format!("w{worker_threads}")), &source),
+            BackupOptions {
+                worker_threads,
+                ..BackupOptions::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.bytes_read, files * file_len as u64);
+        scan_times.push(res.phase_timings.get(&Phase::Scan).unwrap());
+    }
+
+    let mib_per_s = |elapsed: std::time::Duration| {
+        (files * file_len as u64) as f64 / (1024.0 * 1024.0) / elapsed.as_secs_f64()
+    };
+    // Well below the ideal `workers`x, so a busy machine doesn't fail it.
+    assert!(
+        scan_times[1].as_secs_f64() * 1.3 < scan_times[0].as_secs_f64(),
+        "1 worker: {:.1} MiB/s, {workers} workers: {:.1} MiB/s",
+        mib_per_s(scan_times[0]),
+        mib_per_s(scan_times[1]),
+    );
+}
+
+struct CancelOnUpload<'a> {
+    cancel: &'a CancellationToken,
+}
+
+impl ProgressSink for CancelOnUpload<'_> {
+    fn on_progress(&self, progress: TaskProgress) {
+        if progress.phase == Phase::ScanUpload || progress.phase == Phase::Upload {
+            self.cancel.cancel();
+        }
+    }
+}
+
+#[tokio::test]
+async fn cancelling_mid_scan_drains_the_workers() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("src");
+    generate_tree(&source, 400, 64 * 1024);
+
+    let cancel = CancellationToken::new();
+    let sink = CancelOnUpload { cancel: &cancel };
+    let storage = InMemoryStorage::new();
+    let res = tokio::time::timeout(
+        std::time::Duration::from_secs(60),
+        run_backup_with(
+            &storage,
+            worker_config(&temp.path().join("state"), &source),
+            BackupOptions {
+                cancel: Some(&cancel),
+                progress: Some(&sink),
+                worker_threads: 4,
+                ..BackupOptions::default()
+            },
+        ),
+    )
+    .await
+    .expect("cancelled backup must not hang");
+    assert!(matches!(res, Err(Error::Cancelled)), "{res:?}");
+}
+
+struct CancelAfterUploadedBytes<'a> {
+    cancel: &'a CancellationToken,
+    after_bytes: u64,
+}
+
+impl ProgressSink for CancelAfterUploadedBytes<'_> {
+    fn on_progress(&self, progress: TaskProgress) {
+        if progress.bytes_uploaded_source.unwrap_or(0) >= self.after_bytes {
+            self.cancel.cancel();
+        }
+    }
+}
+
+async fn chunk_object_count(db_path: &Path) -> i64 {
+    let pool = sqlx::SqlitePool::connect(&format!("sqlite:{}", db_path.display()))
+        .await
+        .unwrap();
+    let n: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM chunk_objects")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    pool.close().await;
+    n
+}
+
+#[tokio::test]
+async fn cancelled_large_file_upload_resumes_from_checkpointed_chunks() {
+    let temp = TempDir::new().unwrap();
+    let image = temp.path().join("vm/disk.img");
+    let len = 8 * 1024 * 1024;
+    write_file(image.clone(), &generated_bytes(11, len));
+
+    let full = run_backup(
+        &InMemoryStorage::new(),
+        worker_config(&temp.path().join("full"), &image),
+    )
+    .await
+    .unwrap();
+
+    let root = temp.path().join("state");
+    let storage = InMemoryStorage::new();
+    let cancel = CancellationToken::new();
+    let sink = CancelAfterUploadedBytes {
+        cancel: &cancel,
+        after_bytes: len as u64 / 4,
+    };
+    let res = run_backup_with(
+        &storage,
+        BackupConfig {
+            // Pace the uploads so the cancel lands while the file is still being sent.
+            rate_limit: TelegramRateLimit {
+                max_concurrent_uploads: 1,
+                min_delay_ms: 20,
+            },
+            ..worker_config(&root, &image)
+        },
+        BackupOptions {
+            cancel: Some(&cancel),
+            progress: Some(&sink),
+            ..BackupOptions::default()
+        },
+    )
+    .await;
+    assert!(matches!(res, Err(Error::Cancelled)), "{res:?}");
+
+    let checkpointed = chunk_object_count(&root.join("index.sqlite")).await;
+    assert!(checkpointed > 0, "cancelled run kept no chunks");
+    assert!(
+        (checkpointed as u64) < full.chunks_uploaded,
+        "cancelled run uploaded the whole file"
+    );
+
+    let resumed = run_backup(&storage, worker_config(&root, &image))
+        .await
+        .unwrap();
+    assert_eq!(
+        resumed.chunks_uploaded,
+        full.chunks_uploaded - checkpointed as u64
+    );
+    assert!(resumed.bytes_deduped > 0);
+}
+
+#[tokio::test]
+async fn single_file_source_is_recorded_by_basename_and_dedups_unchanged_regions() {
+    let temp = TempDir::new().unwrap();
+    let image = temp.path().join("vm/disk.img");
+    let len = 4 * 1024 * 1024;
+    let mut bytes = generated_bytes(7, len);
+    write_file(image.clone(), &bytes);
+
+    let stats = compute_source_quick_stats(&image, None, true, false).unwrap();
+    assert_eq!((stats.files_total, stats.bytes_total), (1, len as u64));
+
+    let root = temp.path().join("state");
+    let storage = InMemoryStorage::new();
+    let r1 = run_backup(&storage, worker_config(&root, &image))
+        .await
+        .unwrap();
+    assert_eq!(r1.files_indexed, 1);
+    assert_eq!(r1.bytes_read, len as u64);
+
+    // Rewrite 8 KiB in the middle; CDC keeps the chunks around it.
+    bytes[len / 2..len / 2 + 8 * 1024].copy_from_slice(&generated_bytes(8, 8 * 1024));
+    write_file(image.clone(), &bytes);
+    let r2 = run_backup(&storage, worker_config(&root, &image))
+        .await
+        .unwrap();
+    assert_eq!(r2.files_indexed, 1);
+    assert!(r2.chunks_uploaded >= 1);
+    assert!(
+        r2.bytes_deduped >= (len - 256 * 1024) as u64,
+        "deduped {} of {len}",
+        r2.bytes_deduped
+    );
+
+    let entries = snapshot_contents(&root.join("filemaps"), &r2.snapshot_id).await;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].0, "disk.img");
+    assert_eq!(entries[0].1, "file");
+    assert_eq!(entries[0].2, len as i64);
+}
+
+#[tokio::test]
+async fn overlapping_targets_on_one_endpoint_share_chunks_and_gc_keeps_shared_ones() {
+    let temp = TempDir::new().unwrap();
+    let documents = temp.path().join("Documents");
+    let project = documents.join("project");
+    generate_tree(&project, 16, 64 * 1024);
+    let project_bytes = 16 * 64 * 1024;
+    write_file(
+        documents.join("notes.bin"),
+        &generated_bytes(99, 256 * 1024),
+    );
+
+    let root = temp.path().join("state");
+    let storage = InMemoryStorage::new();
+    let docs = run_backup(&storage, worker_config(&root, &documents))
+        .await
+        .unwrap();
+    assert_eq!(docs.bytes_deduped, 0);
+
+    // A second target inside the first: every chunk is already on the endpoint.
+    let proj = run_backup(&storage, worker_config(&root, &project))
+        .await
+        .unwrap();
+    assert_eq!(proj.bytes_read, project_bytes as u64);
+    assert_eq!(proj.chunks_uploaded, 0);
+    assert_eq!(proj.bytes_deduped, project_bytes as u64);
+
+    // Dropping the outer target's snapshot only orphans the chunks the inner one lacks.
+    assert!(
+        delete_snapshot(
+            &root.join("index.sqlite"),
+            &root.join("filemaps"),
+            &docs.snapshot_id,
+            false
+        )
+        .await
+        .unwrap()
+    );
+    let gc = collect_garbage(
+        &storage,
+        &GcConfig {
+            endpoint_db_path: root.join("index.sqlite"),
+            filemap_dir: root.join("filemaps"),
+            dedupe_db_path: None,
+            dedupe_pending_db_path: None,
+            master_key: [5u8; 32],
+            min_age_days: 0,
+            grace_days: 0,
+        },
+        GcOptions::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(gc.snapshots_live, 1);
+    assert!(gc.objects_deleted > 0);
+
+    let again = run_backup(&storage, worker_config(&root, &documents))
+        .await
+        .unwrap();
+    // Notes chunks packed next to shared ones survive with their pack object.
+    assert!(again.bytes_deduped >= project_bytes as u64);
+    assert!(again.chunks_uploaded > 0);
+}
+
+/// Keeps the name and metadata of every upload next to the stored object.
+#[derive(Default)]
+struct RecordingStorage {
+    inner: InMemoryStorage,
+    uploads: Mutex<Vec<(String, Option<UploadMetadata>, String)>>,
+}
+
+impl Storage for RecordingStorage {
+    fn provider(&self) -> &str {
+        self.inner.provider()
+    }
+
+    fn upload_document<'a>(
+        &'a self,
+        filename: &'a str,
+        bytes: Vec<u8>,
+    ) -> Pin<Box<dyn Future<Output = televy_backup_core::Result<String>> + Send + 'a>> {
+        self.upload_document_stream_with_metadata(
+            filename,
+            Box::new(std::io::Cursor::new(bytes.clone())),
+            bytes.len() as u64,
+            None,
+            None,
+        )
+    }
+
+    fn upload_document_stream_with_metadata<'a>(
+        &'a self,
+        filename: &'a str,
+        body: UploadBody<'a>,
+        len: u64,
+        progress: Option<Box<dyn FnMut(StorageProgress) + Send + 'a>>,
+        metadata: Option<UploadMetadata>,
+    ) -> Pin<Box<dyn Future<Output = televy_backup_core::Result<String>> + Send + 'a>> {
+        Box::pin(async move {
+            let object_id = self
+                .inner
+                .upload_document_stream(filename, body, len, progress)
+                .await?;
+            self.uploads
+                .lock()
+                .unwrap()
+                .push((filename.to_string(), metadata, object_id.clone()));
+            Ok(object_id)
+        })
+    }
+
+    fn download_document<'a>(
+        &'a self,
+        object_id: &'a str,
+    ) -> Pin<Box<dyn Future<Output = televy_backup_core::Result<Vec<u8>>> + Send + 'a>> {
+        self.inner.download_document(object_id)
+    }
+}
+
+#[tokio::test]
+async fn stored_objects_never_reveal_file_names() {
+    let name = "zebra-quartz-distinctive";
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join(format!("{name}-dir"));
+    write_file(
+        source.join(format!("{name}.txt")),
+        name.repeat(50).as_bytes(),
+    );
+    for i in 0..40 {
+        write_file(
+            source.join(format!("{name}-{i}/{name}.bin")),
+            &generated_bytes(i, 2048),
+        );
+    }
+
+    let root = temp.path().join("state");
+    let storage = RecordingStorage::default();
+    run_backup(&storage, worker_config(&root, &source))
+        .await
+        .unwrap();
+
+    let uploads = storage.uploads.lock().unwrap().clone();
+    assert!(uploads.len() > 1);
+    for (filename, metadata, object_id) in uploads {
+        let bytes = storage.inner.get(&object_id).await.unwrap();
+        assert!(!filename.contains(name), "object name {filename}");
+        if let Some(m) = metadata {
+            let caption = ObjectCaption::for_payload(m.kind, &bytes).encode();
+            assert!(!caption.contains(name), "caption {caption}");
+        }
+        assert!(
+            !bytes.windows(name.len()).any(|w| w == name.as_bytes()),
+            "payload of {filename}"
+        );
+    }
+
+    let report = audit_backup_privacy(PrivacyAuditConfig {
+        source_path: source.clone(),
+        target_id: "t1".to_string(),
+        label: "manual".to_string(),
+        chunking: ChunkingConfig {
+            min_bytes: 64,
+            avg_bytes: 256,
+            max_bytes: 1024,
+        },
+        needles: privacy_needles(&source.to_string_lossy(), [format!("{name}.txt").as_str()]),
+    })
+    .await
+    .unwrap();
+    assert!(report.objects_checked > 1);
+    assert!(report.needles >= 2);
+    assert_eq!(report.findings, Vec::new());
+}
+
+#[tokio::test]
+async fn short_upload_is_uploaded_again_once_then_fails_the_run() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("src");
+    // One data object, so nothing else uploads next to the damaged one.
+    write_file(source.join("a.txt"), b"one chunk\n");
+
+    let storage = InMemoryStorage::builder().truncate_uploads(1).build();
+    let res = run_backup(
+        &storage,
+        isolated_config(&temp.path().join("once"), &source),
+    )
+    .await
+    .unwrap();
+    assert_eq!(res.upload_reuploads, 1);
+    let calls = storage.calls();
+    // Every upload, the damaged one included, had its size checked.
+    assert_eq!(res.upload_checks, calls.uploads as u64);
+    assert_eq!(calls.size_checks, calls.uploads);
+    assert_eq!(calls.downloads, 0);
+
+    let storage = InMemoryStorage::builder().truncate_uploads(2).build();
+    let root = temp.path().join("twice");
+    let err = run_backup(&storage, isolated_config(&root, &source))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::UploadCorrupted { .. }), "{err:?}");
+    assert_eq!(err.code(), "telegram.upload_corrupted");
+    assert_eq!(storage.calls().uploads, 2);
+    assert_eq!(chunk_object_count(&root.join("index.sqlite")).await, 0);
+}
+
+#[tokio::test]
+async fn verify_after_upload_reads_every_object_back() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("src");
+    write_file(source.join("a.bin"), &generated_bytes(12, 4096));
+    write_file(source.join("b.txt"), b"small\n");
+
+    let storage = InMemoryStorage::builder().truncate_uploads(1).build();
+    let res = run_backup_with(
+        &storage,
+        isolated_config(temp.path(), &source),
+        BackupOptions {
+            verify_after_upload: true,
+            ..BackupOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(res.upload_reuploads, 1);
+    let calls = storage.calls();
+    assert_eq!(res.upload_checks, calls.uploads as u64);
+    assert_eq!(calls.downloads, calls.uploads);
+    assert_eq!(calls.size_checks, 0);
+}
+
+#[tokio::test]
+async fn object_prefix_is_recorded_next_to_object_ids() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("src");
+    write_file(source.join("a.txt"), b"prefixed\n");
+
+    let storage = InMemoryStorage::builder().object_prefix("mac-mini").build();
+    let db_path = temp.path().join("index.sqlite");
+    run_backup(&storage, isolated_config(temp.path(), &source))
+        .await
+        .unwrap();
+
+    let pool = sqlx::SqlitePool::connect(&format!("sqlite:{}", db_path.display()))
+        .await
+        .unwrap();
+    for table in ["chunk_objects", "remote_indexes"] {
+        let prefixes: Vec<Option<String>> =
+            sqlx::query_scalar(&format!("SELECT DISTINCT object_prefix FROM {table}"))
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(prefixes, vec![Some("mac-mini".to_string())], "{table}");
+    }
+}
+
+fn noise(seed: u64, len: usize) -> Vec<u8> {
+    let mut x = seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+    (0..len)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x as u8
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn deleting_a_middle_snapshot_then_rebasing_keeps_chain_and_retention_working() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("src");
+    let root = temp.path().join("state");
+    let db_path = root.join("index.sqlite");
+    let filemap_dir = root.join("filemaps");
+    let storage = InMemoryStorage::new();
+
+    let mut ids = Vec::new();
+    for (name, seed) in [("a.bin", 1), ("b.bin", 2), ("c.bin", 3)] {
+        write_file(source.join(name), &noise(seed, 3000));
+        let res = run_backup(&storage, isolated_config(&root, &source))
+            .await
+            .unwrap();
+        ids.push(res.snapshot_id);
+    }
+    let (a, b, c) = (&ids[0], &ids[1], &ids[2]);
+
+    let chain = snapshot_chain(&db_path, &filemap_dir, c)
+        .await
+        .unwrap()
+        .unwrap();
+    let links = chain
+        .links
+        .iter()
+        .map(|l| (l.snapshot_id.as_str(), l.bytes_new))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        links,
+        vec![
+            (c.as_str(), Some(3000)),
+            (b.as_str(), Some(3000)),
+            (a.as_str(), Some(3000))
+        ]
+    );
+    assert_eq!(chain.missing_base, None);
+
+    assert!(
+        delete_snapshot(&db_path, &filemap_dir, b, false)
+            .await
+            .unwrap()
+    );
+    let chain = snapshot_chain(&db_path, &filemap_dir, c)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(chain.links.len(), 1);
+    assert_eq!(chain.links[0].base_snapshot_id, None);
+    assert_eq!(chain.links[0].bytes_new, Some(9000));
+
+    let err = rebase_snapshot(&db_path, &filemap_dir, c, Some(b))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::InvalidConfig { .. }), "{err:?}");
+    let err = rebase_snapshot(&db_path, &filemap_dir, a, Some(c))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::InvalidConfig { .. }), "{err:?}");
+
+    assert!(
+        rebase_snapshot(&db_path, &filemap_dir, c, Some(a))
+            .await
+            .unwrap()
+    );
+    let chain = snapshot_chain(&db_path, &filemap_dir, c)
+        .await
+        .unwrap()
+        .unwrap();
+    let links = chain
+        .links
+        .iter()
+        .map(|l| (l.snapshot_id.as_str(), l.bytes_new))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        links,
+        vec![(c.as_str(), Some(6000)), (a.as_str(), Some(3000))]
+    );
+
+    // Retention drops the new base like any other snapshot and unlinks it from the chain.
+    let mut cfg = isolated_config(&root, &source);
+    cfg.keep_last_snapshots = 2;
+    let d = run_backup(&storage, cfg).await.unwrap().snapshot_id;
+    let chain = snapshot_chain(&db_path, &filemap_dir, &d)
+        .await
+        .unwrap()
+        .unwrap();
+    let links = chain
+        .links
+        .iter()
+        .map(|l| (l.snapshot_id.as_str(), l.bytes_new))
+        .collect::<Vec<_>>();
+    assert_eq!(links, vec![(d.as_str(), Some(0)), (c.as_str(), Some(9000))]);
+
+    assert!(
+        rebase_snapshot(&db_path, &filemap_dir, &d, None)
+            .await
+            .unwrap()
+    );
+    let chain = snapshot_chain(&db_path, &filemap_dir, &d)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(chain.links.len(), 1);
+    assert_eq!(chain.links[0].bytes_new, Some(9000));
+    assert!(
+        !rebase_snapshot(&db_path, &filemap_dir, "snp_missing", None)
+            .await
+            .unwrap()
+    );
+}
+
+#[tokio::test]
+async fn lifting_the_upload_throttle_mid_run_lets_the_backup_finish() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("src");
+    write_file(source.join("a.bin"), &[3u8; 8192]);
+    let root = temp.path().join("state");
+    let storage = InMemoryStorage::new();
+
+    // 1 byte/s with a gigabyte already "sent": every upload waits until the limit changes.
+    let throttle = UploadThrottle::new(1);
+    throttle.acquire(1 << 30).await;
+
+    let run = run_backup_with(
+        &storage,
+        isolated_config(&root, &source),
+        BackupOptions {
+            upload_throttle: Some(&throttle),
+            ..BackupOptions::default()
+        },
+    );
+    tokio::pin!(run);
+    let early = tokio::time::timeout(std::time::Duration::from_millis(300), &mut run).await;
+    assert!(early.is_err(), "uploads went out despite the throttle");
+
+    throttle.set_limit(0);
+    let res = tokio::time::timeout(std::time::Duration::from_secs(10), run)
+        .await
+        .expect("backup finishes once the throttle is lifted")
+        .unwrap();
+    assert!(res.bytes_uploaded > 0);
+}
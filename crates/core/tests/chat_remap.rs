@@ -0,0 +1,331 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Mutex;
+
+use sqlx::Row;
+use televy_backup_core::chat_remap::{
+    ChatRemap, RemappedStorage, index_db_documents_in_chat, load_chat_remaps, mapping_csv,
+    match_forwarded_documents, remap_index_db_chat,
+};
+use televy_backup_core::{
+    BackupConfig, ChunkingConfig, RemoteDedupeMode, RestoreConfig, RestoreOptions, Storage,
+    TelegramDocumentInfo, encode_tgmtproto_object_id_v1, parse_tgmtproto_object_id_v1,
+    restore_snapshot_with, run_backup,
+};
+use tempfile::TempDir;
+
+const OLD_CHAT: &str = "-100111";
+const NEW_CHAT: &str = "-100222";
+const ACCESS_HASH: i64 = 77;
+
+/// Stand-in for Telegram: every chat numbers its own messages, and a forwarded message keeps its
+/// document (id and access hash) under a new message id.
+#[derive(Default)]
+struct FakeTelegram {
+    objects: Mutex<HashMap<String, Vec<u8>>>,
+    next_msg_id: Mutex<HashMap<String, i32>>,
+    next_doc_id: Mutex<i64>,
+}
+
+impl FakeTelegram {
+    fn post(&self, chat_id: &str, doc_id: i64, bytes: Vec<u8>) -> String {
+        let msg_id = {
+            let mut next = self.next_msg_id.lock().unwrap();
+            let id = next.entry(chat_id.to_string()).or_insert(1);
+            let msg_id = *id;
+            *id += 1;
+            msg_id
+        };
+        let object_id =
+            encode_tgmtproto_object_id_v1(chat_id, msg_id, doc_id, ACCESS_HASH).unwrap();
+        self.objects
+            .lock()
+            .unwrap()
+            .insert(object_id.clone(), bytes);
+        object_id
+    }
+
+    /// Forwards every message of `from` into `to` and deletes `from`; returns the new chat's
+    /// documents as `list_documents` would report them.
+    fn move_chat(&self, from: &str, to: &str) -> Vec<TelegramDocumentInfo> {
+        let mut old: Vec<(i32, i64, Vec<u8>)> = {
+            let mut objects = self.objects.lock().unwrap();
+            let ids: Vec<String> = objects.keys().cloned().collect();
+            ids.into_iter()
+                .filter_map(|id| {
+                    let parsed = parse_tgmtproto_object_id_v1(&id).unwrap();
+                    if parsed.peer != from {
+                        return None;
+                    }
+                    let bytes = objects.remove(&id).unwrap();
+                    Some((parsed.msg_id, parsed.doc_id, bytes))
+                })
+                .collect()
+        };
+        // Unrelated chatter in the new chat shifts the message ids.
+        self.post(to, -1, Vec::new());
+        old.sort_by_key(|(msg_id, _, _)| *msg_id);
+        old.into_iter()
+            .map(|(old_msg_id, doc_id, bytes)| {
+                let bytes_len = bytes.len() as u64;
+                let object_id = self.post(to, doc_id, bytes);
+                let parsed = parse_tgmtproto_object_id_v1(&object_id).unwrap();
+                TelegramDocumentInfo {
+                    msg_id: parsed.msg_id,
+                    doc_id,
+                    access_hash: ACCESS_HASH,
+                    size: bytes_len,
+                    caption: None,
+                    forwarded_from: Some((from.to_string(), old_msg_id)),
+                    file_name: None,
+                }
+            })
+            .collect()
+    }
+}
+
+struct FakeChatStorage<'a> {
+    telegram: &'a FakeTelegram,
+    chat_id: String,
+}
+
+impl Storage for FakeChatStorage<'_> {
+    fn provider(&self) -> &str {
+        "telegram.mtproto/ep1"
+    }
+
+    fn object_id_scope(&self) -> Option<&str> {
+        Some(&self.chat_id)
+    }
+
+    fn upload_document<'a>(
+        &'a self,
+        _filename: &'a str,
+        bytes: Vec<u8>,
+    ) -> Pin<Box<dyn Future<Output = televy_backup_core::Result<String>> + Send + 'a>> {
+        Box::pin(async move {
+            let doc_id = {
+                let mut next = self.telegram.next_doc_id.lock().unwrap();
+                *next += 1;
+                *next
+            };
+            Ok(self.telegram.post(&self.chat_id, doc_id, bytes))
+        })
+    }
+
+    fn download_document<'a>(
+        &'a self,
+        object_id: &'a str,
+    ) -> Pin<Box<dyn Future<Output = televy_backup_core::Result<Vec<u8>>> + Send + 'a>> {
+        Box::pin(async move {
+            let parsed = parse_tgmtproto_object_id_v1(object_id)?;
+            if parsed.peer != self.chat_id {
+                return Err(televy_backup_core::Error::telegram(format!(
+                    "chat not found: {}",
+                    parsed.peer
+                )));
+            }
+            self.telegram
+                .objects
+                .lock()
+                .unwrap()
+                .get(object_id)
+                .cloned()
+                .ok_or_else(|| {
+                    televy_backup_core::Error::telegram(format!(
+                        "message not found: {}",
+                        parsed.msg_id
+                    ))
+                })
+        })
+    }
+}
+
+fn write_file(path: PathBuf, bytes: &[u8]) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, bytes).unwrap();
+}
+
+async fn object_ids(db_path: &Path) -> Vec<String> {
+    let pool = sqlx::SqlitePool::connect(&format!("sqlite:{}", db_path.display()))
+        .await
+        .unwrap();
+    let mut out = Vec::new();
+    for sql in [
+        "SELECT object_id AS id FROM chunk_objects",
+        "SELECT manifest_object_id AS id FROM remote_indexes",
+        "SELECT object_id AS id FROM remote_index_parts",
+    ] {
+        for row in sqlx::query(sql).fetch_all(&pool).await.unwrap() {
+            out.push(row.get::<String, _>("id"));
+        }
+    }
+    pool.close().await;
+    out.sort();
+    out
+}
+
+#[tokio::test]
+async fn restore_reads_a_backup_forwarded_to_a_new_chat() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("src");
+    write_file(source.join("a.txt"), b"hello world\nhello world\n");
+    write_file(source.join("nested/b.bin"), &[42u8; 10_000]);
+
+    let db_path = temp.path().join("index.sqlite");
+    let telegram = FakeTelegram::default();
+    let old_storage = FakeChatStorage {
+        telegram: &telegram,
+        chat_id: OLD_CHAT.to_string(),
+    };
+    let master_key = [7u8; 32];
+
+    let backup = run_backup(
+        &old_storage,
+        BackupConfig {
+            endpoint_db_path: db_path.clone(),
+            filemap_dir: temp.path().join("filemaps"),
+            dedupe_db_path: temp.path().join("dedupe.sqlite"),
+            dedupe_pending_db_path: temp.path().join("dedupe.pending.sqlite"),
+            source_path: source.clone(),
+            label: "t1".to_string(),
+            chunking: ChunkingConfig {
+                min_bytes: 64,
+                avg_bytes: 256,
+                max_bytes: 1024,
+            },
+            rate_limit: Default::default(),
+            master_key,
+            data_key: None,
+            snapshot_id: None,
+            keep_last_snapshots: 10,
+            remote_dedupe: RemoteDedupeMode::Disabled,
+            hint_changed_paths: None,
+            device: None,
+            index_full_every: 1,
+            created_at: None,
+        },
+    )
+    .await
+    .unwrap();
+
+    let old_documents = index_db_documents_in_chat(&db_path, OLD_CHAT)
+        .await
+        .unwrap();
+    assert!(!old_documents.is_empty());
+
+    let forwarded = telegram.move_chat(OLD_CHAT, NEW_CHAT);
+    let pairs = match_forwarded_documents(OLD_CHAT, &old_documents, &forwarded);
+    // Index parts that only the uploaded endpoint manifest references are matched by header.
+    assert_eq!(pairs.len(), forwarded.len());
+    assert!(pairs.len() > old_documents.len());
+    assert!(pairs.iter().all(|(old, new)| old != new));
+    let remap = ChatRemap::parse_mapping_csv(OLD_CHAT, NEW_CHAT, &mapping_csv(&pairs)).unwrap();
+
+    let before = object_ids(&db_path).await;
+    let dry = remap_index_db_chat(&db_path, &remap, true).await.unwrap();
+    assert!(dry.dry_run);
+    assert!(dry.chunk_objects_remapped > 0);
+    assert_eq!(dry.remote_indexes_remapped, 1);
+    assert_eq!(dry.endpoint_state_remapped, 1);
+    assert_eq!(dry.unmapped, 0);
+    assert_eq!(object_ids(&db_path).await, before);
+    assert!(load_chat_remaps(&db_path).await.unwrap().is_empty());
+
+    let report = remap_index_db_chat(&db_path, &remap, false).await.unwrap();
+    assert_eq!(report.remapped(), dry.remapped());
+    let after = object_ids(&db_path).await;
+    assert_eq!(after.len(), before.len());
+    for encoded in &after {
+        let object_id = encoded
+            .strip_prefix("tgpack:")
+            .and_then(|s| s.split_once('@'))
+            .map(|(id, _)| id)
+            .or_else(|| encoded.strip_prefix("tgfile:"))
+            .unwrap_or(encoded);
+        assert_eq!(
+            parse_tgmtproto_object_id_v1(object_id).unwrap().peer,
+            NEW_CHAT
+        );
+    }
+
+    let pool = sqlx::SqlitePool::connect(&format!("sqlite:{}", db_path.display()))
+        .await
+        .unwrap();
+    let manifest_object_id: String =
+        sqlx::query("SELECT manifest_object_id FROM remote_indexes WHERE snapshot_id = ?")
+            .bind(&backup.snapshot_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap()
+            .get("manifest_object_id");
+    let endpoint_manifest_object_id: String =
+        sqlx::query("SELECT value FROM endpoint_state WHERE key = ?")
+            .bind(televy_backup_core::index_sync::ENDPOINT_STATE_ENDPOINT_MANIFEST_OBJECT_ID_KEY)
+            .fetch_one(&pool)
+            .await
+            .unwrap()
+            .get("value");
+    // The endpoint index AAD stays the one the backup used (restore reads it from endpoint_state).
+    let endpoint_index_id: String = sqlx::query("SELECT value FROM endpoint_state WHERE key = ?")
+        .bind(televy_backup_core::index_sync::ENDPOINT_STATE_ENDPOINT_INDEX_ID_KEY)
+        .fetch_one(&pool)
+        .await
+        .unwrap()
+        .get("value");
+    pool.close().await;
+
+    let new_storage = FakeChatStorage {
+        telegram: &telegram,
+        chat_id: NEW_CHAT.to_string(),
+    };
+    let restore_config = |name: &str| RestoreConfig {
+        snapshot_id: backup.snapshot_id.clone(),
+        filemap_manifest_object_id: manifest_object_id.clone(),
+        filemap_manifest_sha256: None,
+        endpoint_manifest_object_id: Some(endpoint_manifest_object_id.clone()),
+        dedupe_catalog_object_id: None,
+        endpoint_dedupe_id: None,
+        endpoint_index_id: Some(endpoint_index_id.clone()),
+        master_key,
+        data_key: None,
+        filemap_db_path: temp.path().join(format!("{name}-filemap.sqlite")),
+        endpoint_db_path: Some(temp.path().join(format!("{name}-endpoint.sqlite"))),
+        dedupe_db_path: None,
+        target_path: temp.path().join(name),
+    };
+
+    // The uploaded index copies still name the old chat, so a plain restore fails...
+    assert!(
+        restore_snapshot_with(
+            &new_storage,
+            restore_config("plain"),
+            RestoreOptions::default()
+        )
+        .await
+        .is_err()
+    );
+
+    // ...and reading through the recorded remap succeeds.
+    let remaps = load_chat_remaps(&db_path).await.unwrap();
+    assert_eq!(remaps.len(), 1);
+    let remapped = RemappedStorage::new(&new_storage, remaps);
+    restore_snapshot_with(
+        &remapped,
+        restore_config("restored"),
+        RestoreOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    let restored = temp.path().join("restored");
+    for rel in ["a.txt", "nested/b.bin"] {
+        assert_eq!(
+            std::fs::read(source.join(rel)).unwrap(),
+            std::fs::read(restored.join(rel)).unwrap(),
+            "{rel}"
+        );
+    }
+}
@@ -0,0 +1,60 @@
+-- Index DB as written by the Bot API era builds: no migrations table beyond version 1, nullable
+-- provider columns, bare Bot API file_ids in chunk_objects.
+CREATE TABLE schema_migrations (
+  version INTEGER PRIMARY KEY,
+  applied_at TEXT NOT NULL
+);
+INSERT INTO schema_migrations(version, applied_at) VALUES (1, '2025-03-01T00:00:00Z');
+
+CREATE TABLE snapshots (
+  snapshot_id TEXT PRIMARY KEY,
+  created_at TEXT NOT NULL,
+  source_path TEXT NOT NULL,
+  label TEXT NOT NULL,
+  base_snapshot_id TEXT NULL
+);
+
+CREATE TABLE chunks (
+  chunk_hash TEXT PRIMARY KEY,
+  size INTEGER NOT NULL,
+  hash_alg TEXT NOT NULL,
+  enc_alg TEXT NOT NULL,
+  created_at TEXT NOT NULL
+);
+
+CREATE TABLE chunk_objects (
+  chunk_hash TEXT NOT NULL REFERENCES chunks(chunk_hash),
+  provider TEXT NULL,
+  object_id TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  PRIMARY KEY (provider, object_id),
+  UNIQUE (provider, chunk_hash)
+);
+
+CREATE TABLE remote_indexes (
+  snapshot_id TEXT PRIMARY KEY REFERENCES snapshots(snapshot_id),
+  provider TEXT NULL,
+  manifest_object_id TEXT NOT NULL,
+  created_at TEXT NOT NULL
+);
+
+CREATE TABLE remote_index_parts (
+  snapshot_id TEXT NOT NULL REFERENCES snapshots(snapshot_id),
+  part_no INTEGER NOT NULL,
+  provider TEXT NULL,
+  object_id TEXT NOT NULL,
+  size INTEGER NOT NULL,
+  hash TEXT NOT NULL,
+  PRIMARY KEY (snapshot_id, part_no)
+);
+
+INSERT INTO snapshots VALUES ('snp_old', '2025-03-01T00:00:00Z', '/Users/me/Documents', 'manual', NULL);
+INSERT INTO remote_indexes VALUES ('snp_old', 'telegram.botapi', 'BQACAgUAAxkBAAIManifest', '2025-03-01T00:00:00Z');
+INSERT INTO remote_index_parts VALUES ('snp_old', 0, NULL, 'BQACAgUAAxkBAAIPart0', 1024, 'h0');
+
+INSERT INTO chunks VALUES ('chk_a', 10, 'blake3', 'xchacha20poly1305', '2025-03-01T00:00:00Z');
+INSERT INTO chunks VALUES ('chk_b', 10, 'blake3', 'xchacha20poly1305', '2025-03-01T00:00:00Z');
+INSERT INTO chunks VALUES ('chk_c', 10, 'blake3', 'xchacha20poly1305', '2025-03-01T00:00:00Z');
+INSERT INTO chunk_objects VALUES ('chk_a', 'telegram.botapi', 'BQACAgUAAxkBAAIChunkA', '2025-03-01T00:00:00Z');
+INSERT INTO chunk_objects VALUES ('chk_b', NULL, 'tgpack:BQACAgUAAxkBAAIPack@0+10', '2025-03-01T00:00:00Z');
+INSERT INTO chunk_objects VALUES ('chk_c', 'telegram', 'tgfile:BQACAgUAAxkBAAIChunkC', '2025-03-01T00:00:00Z');
@@ -0,0 +1,67 @@
+-- Global `index.sqlite` from the first MTProto builds: provider `telegram.mtproto` without an
+-- endpoint, mixed with rows written after endpoints were introduced.
+CREATE TABLE schema_migrations (
+  version INTEGER PRIMARY KEY,
+  applied_at TEXT NOT NULL
+);
+INSERT INTO schema_migrations(version, applied_at) VALUES (1, '2025-09-01T00:00:00Z');
+
+CREATE TABLE snapshots (
+  snapshot_id TEXT PRIMARY KEY,
+  created_at TEXT NOT NULL,
+  source_path TEXT NOT NULL,
+  label TEXT NOT NULL,
+  base_snapshot_id TEXT NULL
+);
+
+CREATE TABLE chunks (
+  chunk_hash TEXT PRIMARY KEY,
+  size INTEGER NOT NULL,
+  hash_alg TEXT NOT NULL,
+  enc_alg TEXT NOT NULL,
+  created_at TEXT NOT NULL
+);
+
+CREATE TABLE chunk_objects (
+  chunk_hash TEXT NOT NULL REFERENCES chunks(chunk_hash),
+  provider TEXT NOT NULL,
+  object_id TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  PRIMARY KEY (provider, object_id),
+  UNIQUE (provider, chunk_hash)
+);
+
+CREATE TABLE remote_indexes (
+  snapshot_id TEXT PRIMARY KEY REFERENCES snapshots(snapshot_id),
+  provider TEXT NOT NULL,
+  manifest_object_id TEXT NOT NULL,
+  created_at TEXT NOT NULL
+);
+
+CREATE TABLE remote_index_parts (
+  snapshot_id TEXT NOT NULL REFERENCES snapshots(snapshot_id),
+  part_no INTEGER NOT NULL,
+  provider TEXT NOT NULL,
+  object_id TEXT NOT NULL,
+  size INTEGER NOT NULL,
+  hash TEXT NOT NULL,
+  PRIMARY KEY (snapshot_id, part_no)
+);
+
+CREATE TABLE endpoint_state (
+  key TEXT PRIMARY KEY,
+  value TEXT NOT NULL
+);
+
+INSERT INTO snapshots VALUES ('snp_1', '2025-09-01T00:00:00Z', '/Users/me/Photos', 'manual', NULL);
+INSERT INTO snapshots VALUES ('snp_2', '2025-10-01T00:00:00Z', '/Users/me/Photos', 'manual', 'snp_1');
+INSERT INTO remote_indexes VALUES ('snp_1', 'telegram.mtproto', 'tgmtproto:v1:manifest1', '2025-09-01T00:00:00Z');
+INSERT INTO remote_indexes VALUES ('snp_2', 'telegram.mtproto/main', 'tgmtproto:v1:manifest2', '2025-10-01T00:00:00Z');
+INSERT INTO remote_index_parts VALUES ('snp_1', 0, 'telegram.mtproto', 'tgmtproto:v1:part1', 2048, 'h1');
+
+INSERT INTO chunks VALUES ('chk_1', 10, 'blake3', 'xchacha20poly1305', '2025-09-01T00:00:00Z');
+INSERT INTO chunks VALUES ('chk_2', 10, 'blake3', 'xchacha20poly1305', '2025-09-01T00:00:00Z');
+INSERT INTO chunk_objects VALUES ('chk_1', 'telegram.mtproto', 'tgpack:tgmtproto:v1:pack1@00+010', '2025-09-01T00:00:00Z');
+-- Re-uploaded after endpoints existed: the legacy duplicate must give way to this row.
+INSERT INTO chunk_objects VALUES ('chk_2', 'telegram.mtproto', 'tgfile:tgmtproto:v1:old2', '2025-09-01T00:00:00Z');
+INSERT INTO chunk_objects VALUES ('chk_2', 'telegram.mtproto/main', 'tgfile:tgmtproto:v1:new2', '2025-10-01T00:00:00Z');
@@ -0,0 +1,555 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use sqlx::Row;
+use televy_backup_core::dedup_stats::{TopFiles, top_files};
+use televy_backup_core::index_db::index_stats;
+use televy_backup_core::{
+    BackupConfig, ChunkObjectRef, ChunkRefCheck, ChunkingConfig, Error, GcConfig, GcOptions,
+    InMemoryStorage, RemoteDedupeMode, RestoreConfig, check_chunk_ref_counts, collect_garbage,
+    delete_snapshot, flush_pending_deletions, parse_chunk_object_ref, pending_deletions,
+    restore_snapshot, run_backup, undo_snapshot_delete,
+};
+use tempfile::TempDir;
+
+fn pseudo_random_bytes(seed: u64, len: usize) -> Vec<u8> {
+    let mut x = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+    (0..len)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x as u8
+        })
+        .collect()
+}
+
+fn backup_config(temp: &Path, source: &Path) -> BackupConfig {
+    BackupConfig {
+        endpoint_db_path: temp.join("index.sqlite"),
+        filemap_dir: temp.join("filemaps"),
+        dedupe_db_path: temp.join("dedupe.sqlite"),
+        dedupe_pending_db_path: temp.join("dedupe.pending.sqlite"),
+        source_path: source.to_path_buf(),
+        label: "t".to_string(),
+        chunking: ChunkingConfig {
+            min_bytes: 256,
+            avg_bytes: 1024,
+            max_bytes: 4096,
+        },
+        rate_limit: Default::default(),
+        master_key: [7u8; 32],
+        data_key: None,
+        snapshot_id: None,
+        keep_last_snapshots: 10,
+        remote_dedupe: RemoteDedupeMode::Disabled,
+        hint_changed_paths: None,
+        device: None,
+        index_full_every: 1,
+        created_at: None,
+    }
+}
+
+fn gc_config(temp: &Path, min_age_days: u32) -> GcConfig {
+    GcConfig {
+        endpoint_db_path: temp.join("index.sqlite"),
+        filemap_dir: temp.join("filemaps"),
+        dedupe_db_path: None,
+        dedupe_pending_db_path: None,
+        master_key: [7u8; 32],
+        min_age_days,
+        grace_days: 0,
+    }
+}
+
+/// Sizes of the direct chunk objects the index maps chunks to.
+async fn chunk_object_sizes(storage: &InMemoryStorage, db_path: &Path) -> HashMap<String, u64> {
+    let pool = sqlx::SqlitePool::connect(&format!("sqlite:{}", db_path.display()))
+        .await
+        .unwrap();
+    let ids: Vec<String> = sqlx::query_scalar("SELECT object_id FROM chunk_objects")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    pool.close().await;
+    let mut sizes = HashMap::new();
+    for id in ids {
+        let ChunkObjectRef::Direct { object_id } = parse_chunk_object_ref(&id).unwrap() else {
+            panic!("expected direct chunk objects: {id}");
+        };
+        let len = storage.get(&object_id).await.unwrap().len() as u64;
+        sizes.insert(object_id, len);
+    }
+    sizes
+}
+
+#[tokio::test]
+async fn gc_deletes_exactly_the_objects_dry_run_reports_and_keeps_live_snapshots_restorable() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("src");
+    std::fs::create_dir_all(&source).unwrap();
+    let shared = pseudo_random_bytes(1, 3_000);
+    let replaced = pseudo_random_bytes(2, 3_000);
+    let current = pseudo_random_bytes(3, 3_000);
+    let write = |name: &str, bytes: &[u8]| std::fs::write(source.join(name), bytes).unwrap();
+
+    write("shared.bin", &shared);
+    write("changing.bin", &replaced);
+    let storage = InMemoryStorage::new();
+    let old = run_backup(&storage, backup_config(temp.path(), &source))
+        .await
+        .unwrap();
+    write("changing.bin", &current);
+    let live = run_backup(&storage, backup_config(temp.path(), &source))
+        .await
+        .unwrap();
+
+    let db_path = temp.path().join("index.sqlite");
+    let before = chunk_object_sizes(&storage, &db_path).await;
+    assert!(
+        delete_snapshot(
+            &db_path,
+            &temp.path().join("filemaps"),
+            &old.snapshot_id,
+            false
+        )
+        .await
+        .unwrap()
+    );
+
+    // Everything unreferenced is younger than a day.
+    let kept = collect_garbage(
+        &storage,
+        &gc_config(temp.path(), 1),
+        GcOptions {
+            dry_run: true,
+            ..GcOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(kept.objects_deleted, 0);
+    assert!(kept.objects_too_recent > 0);
+
+    let dry = collect_garbage(
+        &storage,
+        &gc_config(temp.path(), 0),
+        GcOptions {
+            dry_run: true,
+            ..GcOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(dry.snapshots_live, 1);
+    assert_eq!(dry.objects_deleted, kept.objects_too_recent);
+    assert_eq!(chunk_object_sizes(&storage, &db_path).await, before);
+
+    let res = collect_garbage(&storage, &gc_config(temp.path(), 0), GcOptions::default())
+        .await
+        .unwrap();
+    assert!(!res.dry_run);
+    assert_eq!(res.objects_failed, 0);
+    assert_eq!(
+        (res.objects_deleted, res.chunks_deleted, res.bytes_reclaimed),
+        (dry.objects_deleted, dry.chunks_deleted, dry.bytes_reclaimed)
+    );
+
+    let mut deleted_bytes = 0;
+    let mut deleted = 0;
+    for (object_id, len) in &before {
+        if storage.get(object_id).await.is_none() {
+            deleted += 1;
+            deleted_bytes += len;
+        }
+    }
+    assert_eq!(deleted, res.objects_deleted);
+    assert_eq!(deleted_bytes, res.bytes_reclaimed);
+    let after = chunk_object_sizes(&storage, &db_path).await;
+    assert_eq!(
+        after.len() as u64,
+        before.len() as u64 - res.objects_deleted
+    );
+
+    let pool = sqlx::SqlitePool::connect(&format!("sqlite:{}", db_path.display()))
+        .await
+        .unwrap();
+    let row = sqlx::query(
+        "SELECT manifest_object_id, manifest_sha256 FROM remote_indexes WHERE snapshot_id = ?",
+    )
+    .bind(&live.snapshot_id)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    let endpoint_manifest_object_id: String =
+        sqlx::query_scalar("SELECT value FROM endpoint_state WHERE key = ?")
+            .bind(televy_backup_core::index_sync::ENDPOINT_STATE_ENDPOINT_MANIFEST_OBJECT_ID_KEY)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    pool.close().await;
+    let target: PathBuf = temp.path().join("restored");
+    restore_snapshot(
+        &storage,
+        RestoreConfig {
+            snapshot_id: live.snapshot_id.clone(),
+            filemap_manifest_object_id: row.get("manifest_object_id"),
+            filemap_manifest_sha256: row.get("manifest_sha256"),
+            endpoint_manifest_object_id: Some(endpoint_manifest_object_id),
+            dedupe_catalog_object_id: None,
+            endpoint_dedupe_id: None,
+            endpoint_index_id: None,
+            master_key: [7u8; 32],
+            data_key: None,
+            filemap_db_path: temp.path().join("restore-filemap.sqlite"),
+            endpoint_db_path: Some(temp.path().join("restore-endpoint.sqlite")),
+            dedupe_db_path: None,
+            target_path: target.clone(),
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(std::fs::read(target.join("shared.bin")).unwrap(), shared);
+    assert_eq!(std::fs::read(target.join("changing.bin")).unwrap(), current);
+
+    // Nothing left to collect.
+    let again = collect_garbage(&storage, &gc_config(temp.path(), 0), GcOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(again.objects_deleted, 0);
+    assert_eq!(again.objects_too_recent, 0);
+}
+
+async fn check_refs(storage: &InMemoryStorage, temp: &Path, repair: bool) -> ChunkRefCheck {
+    check_chunk_ref_counts(
+        storage,
+        &temp.join("index.sqlite"),
+        &temp.join("filemaps"),
+        &[7u8; 32],
+        repair,
+        None,
+    )
+    .await
+    .unwrap()
+}
+
+async fn exec(db_path: &Path, sql: &str) {
+    let pool = sqlx::SqlitePool::connect(&format!("sqlite:{}", db_path.display()))
+        .await
+        .unwrap();
+    sqlx::raw_sql(sql).execute(&pool).await.unwrap();
+    pool.close().await;
+}
+
+async fn unreferenced_chunks(db_path: &Path) -> i64 {
+    let pool = sqlx::SqlitePool::connect(&format!("sqlite:{}", db_path.display()))
+        .await
+        .unwrap();
+    let n = sqlx::query_scalar("SELECT COUNT(*) FROM chunks WHERE ref_count = 0")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    pool.close().await;
+    n
+}
+
+/// Two snapshots of a source whose second file changed in between.
+async fn two_snapshots(temp: &Path) -> (InMemoryStorage, String) {
+    let source = temp.join("src");
+    std::fs::create_dir_all(&source).unwrap();
+    std::fs::write(source.join("shared.bin"), pseudo_random_bytes(1, 3_000)).unwrap();
+    std::fs::write(source.join("changing.bin"), pseudo_random_bytes(2, 3_000)).unwrap();
+    let storage = InMemoryStorage::new();
+    let old = run_backup(&storage, backup_config(temp, &source))
+        .await
+        .unwrap();
+    std::fs::write(source.join("changing.bin"), pseudo_random_bytes(3, 3_000)).unwrap();
+    run_backup(&storage, backup_config(temp, &source))
+        .await
+        .unwrap();
+    (storage, old.snapshot_id)
+}
+
+#[tokio::test]
+async fn chunk_ref_counts_follow_backups_and_snapshot_deletes() {
+    let temp = TempDir::new().unwrap();
+    let db_path = temp.path().join("index.sqlite");
+    let (storage, old) = two_snapshots(temp.path()).await;
+
+    let check = check_refs(&storage, temp.path(), false).await;
+    assert!(check.is_consistent(), "{check:?}");
+    assert_eq!((check.snapshots, check.snapshots_uncounted), (2, 0));
+    assert_eq!(unreferenced_chunks(&db_path).await, 0);
+
+    delete_snapshot(&db_path, &temp.path().join("filemaps"), &old, false)
+        .await
+        .unwrap();
+    let check = check_refs(&storage, temp.path(), false).await;
+    assert!(check.is_consistent(), "{check:?}");
+    let orphans = unreferenced_chunks(&db_path).await;
+    assert!(orphans > 0);
+    let stats = index_stats(std::slice::from_ref(&db_path)).await.unwrap();
+    assert_eq!(stats.chunks_unreferenced, Some(orphans));
+
+    let res = collect_garbage(&storage, &gc_config(temp.path(), 0), GcOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(res.chunks_deleted, orphans as u64);
+    let stats = index_stats(std::slice::from_ref(&db_path)).await.unwrap();
+    assert_eq!(stats.chunks_unreferenced, Some(0));
+}
+
+#[tokio::test]
+async fn gc_counts_uncounted_snapshots_and_check_repairs_drift() {
+    let temp = TempDir::new().unwrap();
+    let db_path = temp.path().join("index.sqlite");
+    let (storage, _) = two_snapshots(temp.path()).await;
+    // As left by a version that did not count references.
+    exec(
+        &db_path,
+        "UPDATE snapshots SET chunk_refs_counted = 0; UPDATE chunks SET ref_count = 0;",
+    )
+    .await;
+    let stats = index_stats(std::slice::from_ref(&db_path)).await.unwrap();
+    assert_eq!(stats.chunks_unreferenced, None);
+    let check = check_refs(&storage, temp.path(), false).await;
+    assert!(check.is_consistent(), "{check:?}");
+    assert_eq!(check.snapshots_uncounted, 2);
+
+    let res = collect_garbage(&storage, &gc_config(temp.path(), 0), GcOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(res.objects_deleted, 0);
+    let check = check_refs(&storage, temp.path(), false).await;
+    assert!(check.is_consistent(), "{check:?}");
+    assert_eq!(check.snapshots_uncounted, 0);
+
+    exec(
+        &db_path,
+        "UPDATE chunks SET ref_count = ref_count + 5 WHERE chunk_hash = (SELECT MIN(chunk_hash) FROM chunks)",
+    )
+    .await;
+    let check = check_refs(&storage, temp.path(), false).await;
+    assert_eq!(check.chunks_drifted, 1);
+    let drift = &check.drift_examples[0];
+    assert_eq!(drift.stored, drift.actual + 5);
+    assert!(!check.repaired);
+
+    assert!(check_refs(&storage, temp.path(), true).await.repaired);
+    assert!(
+        check_refs(&storage, temp.path(), false)
+            .await
+            .is_consistent()
+    );
+}
+
+#[tokio::test]
+async fn deleting_a_snapshot_without_its_file_map_marks_counts_stale() {
+    let temp = TempDir::new().unwrap();
+    let db_path = temp.path().join("index.sqlite");
+    let filemap_dir = temp.path().join("filemaps");
+    let (storage, old) = two_snapshots(temp.path()).await;
+
+    std::fs::remove_file(filemap_dir.join(format!("{old}.sqlite"))).unwrap();
+    delete_snapshot(&db_path, &filemap_dir, &old, false)
+        .await
+        .unwrap();
+    let check = check_refs(&storage, temp.path(), false).await;
+    assert!(check.stale_reason.is_some());
+    assert!(!check.is_consistent());
+    let stats = index_stats(std::slice::from_ref(&db_path)).await.unwrap();
+    assert_eq!(stats.chunks_unreferenced, None);
+
+    // Stale counts are not trusted: GC scans the file maps and collects only the old chunks.
+    let dry = collect_garbage(
+        &storage,
+        &gc_config(temp.path(), 0),
+        GcOptions {
+            dry_run: true,
+            ..GcOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    assert!(check_refs(&storage, temp.path(), true).await.repaired);
+    let check = check_refs(&storage, temp.path(), false).await;
+    assert!(check.is_consistent(), "{check:?}");
+    assert_eq!(
+        unreferenced_chunks(&db_path).await as u64,
+        dry.chunks_deleted
+    );
+}
+
+async fn snapshot_count(db_path: &Path, snapshot_id: &str) -> i64 {
+    let pool = sqlx::SqlitePool::connect(&format!("sqlite:{}", db_path.display()))
+        .await
+        .unwrap();
+    let n = sqlx::query_scalar("SELECT COUNT(*) FROM snapshots WHERE snapshot_id = ?")
+        .bind(snapshot_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    pool.close().await;
+    n
+}
+
+#[tokio::test]
+async fn gc_queues_objects_until_flush_and_undo_brings_the_snapshot_back() {
+    let temp = TempDir::new().unwrap();
+    let db_path = temp.path().join("index.sqlite");
+    let filemap_dir = temp.path().join("filemaps");
+    let (storage, old) = two_snapshots(temp.path()).await;
+    let config = GcConfig {
+        grace_days: 7,
+        ..gc_config(temp.path(), 0)
+    };
+    let before = chunk_object_sizes(&storage, &db_path).await;
+
+    delete_snapshot(&db_path, &filemap_dir, &old, false)
+        .await
+        .unwrap();
+    let queued = collect_garbage(&storage, &config, GcOptions::default())
+        .await
+        .unwrap();
+    assert!(queued.objects_queued > 0);
+    assert_eq!(queued.objects_deleted, 0);
+    assert_eq!(
+        (queued.pending_objects, queued.pending_bytes),
+        (queued.objects_queued, queued.bytes_queued)
+    );
+    let queue = pending_deletions(&db_path).await.unwrap();
+    assert_eq!((queue.bytes, queue.due_objects), (queued.bytes_queued, 0));
+
+    // Nothing is due yet.
+    let flushed = flush_pending_deletions(&storage, &config, GcOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(flushed.objects_deleted, 0);
+    assert_eq!(flushed.objects_not_due, queued.objects_queued);
+    assert_eq!(chunk_object_sizes(&storage, &db_path).await, before);
+
+    let undone = undo_snapshot_delete(&storage, &config, &old)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(undone.objects_unqueued, queued.objects_queued);
+    assert_eq!(undone.bytes_unqueued, queued.bytes_queued);
+    assert_eq!(snapshot_count(&db_path, &old).await, 1);
+    assert_eq!(pending_deletions(&db_path).await.unwrap().objects, 0);
+    // The next run counts the snapshot's references again and finds nothing to queue.
+    let again = collect_garbage(&storage, &config, GcOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(again.objects_queued, 0);
+    assert!(
+        check_refs(&storage, temp.path(), false)
+            .await
+            .is_consistent()
+    );
+
+    // Once the grace period is over, a flush deletes the objects and undo is refused.
+    delete_snapshot(&db_path, &filemap_dir, &old, false)
+        .await
+        .unwrap();
+    let queued = collect_garbage(&storage, &config, GcOptions::default())
+        .await
+        .unwrap();
+    exec(
+        &db_path,
+        "UPDATE pending_deletions SET delete_after = '2000-01-01T00:00:00.000Z'",
+    )
+    .await;
+    let flushed = flush_pending_deletions(&storage, &config, GcOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(flushed.objects_deleted, queued.objects_queued);
+    assert_eq!(flushed.bytes_reclaimed, queued.bytes_queued);
+    assert_eq!(flushed.pending_objects, 0);
+    assert_eq!(
+        chunk_object_sizes(&storage, &db_path).await.len() as u64,
+        before.len() as u64 - flushed.objects_deleted
+    );
+    let err = undo_snapshot_delete(&storage, &config, &old)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::MissingChunkObject { .. }), "{err:?}");
+    assert_eq!(snapshot_count(&db_path, &old).await, 0);
+
+    exec(
+        &db_path,
+        "UPDATE snapshot_tombstones SET deleted_at = '2000-01-01T00:00:00.000Z'",
+    )
+    .await;
+    let flushed = flush_pending_deletions(&storage, &config, GcOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(flushed.tombstones_purged, 1);
+    assert!(
+        undo_snapshot_delete(&storage, &config, &old)
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[tokio::test]
+async fn top_files_splits_unique_and_shared_bytes_with_or_without_ref_counts() {
+    let temp = TempDir::new().unwrap();
+    let db_path = temp.path().join("index.sqlite");
+    let filemap_dir = temp.path().join("filemaps");
+    let (_storage, old) = two_snapshots(temp.path()).await;
+    let pool = sqlx::SqlitePool::connect(&format!("sqlite:{}", db_path.display()))
+        .await
+        .unwrap();
+    let latest: String =
+        sqlx::query_scalar("SELECT snapshot_id FROM snapshots WHERE snapshot_id != ?")
+            .bind(&old)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    pool.close().await;
+
+    let sizes = |top: &TopFiles| {
+        top.files
+            .iter()
+            .map(|f| (f.path.clone(), f.unique_bytes, f.shared_bytes))
+            .collect::<Vec<_>>()
+    };
+    let expected = vec![
+        ("changing.bin".to_string(), 3_000, 0),
+        ("shared.bin".to_string(), 0, 3_000),
+    ];
+    let top = top_files(&db_path, &filemap_dir, &latest, 50)
+        .await
+        .unwrap();
+    assert_eq!(sizes(&top), expected);
+    assert_eq!(top.snapshots_unscanned, 0);
+    assert_eq!(
+        top_files(&db_path, &filemap_dir, &latest, 1)
+            .await
+            .unwrap()
+            .files
+            .len(),
+        1
+    );
+
+    // Stale counts: the other snapshots' file maps are read instead.
+    exec(
+        &db_path,
+        "INSERT INTO endpoint_state (key, value) VALUES ('chunk_ref_counts_stale', 'test')",
+    )
+    .await;
+    let top = top_files(&db_path, &filemap_dir, &latest, 50)
+        .await
+        .unwrap();
+    assert_eq!(sizes(&top), expected);
+
+    std::fs::remove_file(filemap_dir.join(format!("{old}.sqlite"))).unwrap();
+    let top = top_files(&db_path, &filemap_dir, &latest, 50)
+        .await
+        .unwrap();
+    assert_eq!(top.snapshots_unscanned, 1);
+    assert!(top.files.iter().all(|f| f.shared_bytes == 0));
+    assert!(top_files(&db_path, &filemap_dir, &old, 50).await.is_err());
+}
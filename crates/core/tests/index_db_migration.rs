@@ -0,0 +1,263 @@
+use std::path::{Path, PathBuf};
+
+use sqlx::Row;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use televy_backup_core::index_db::{
+    PROVIDER_MIGRATION_SCHEMA_VERSION, migrate_index_db_providers, open_existing_index_db,
+    open_index_db,
+};
+
+fn fixture(name: &str) -> String {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/index_db")
+        .join(name);
+    std::fs::read_to_string(path).unwrap()
+}
+
+async fn write_fixture_db(path: &Path, name: &str) {
+    let options = SqliteConnectOptions::new()
+        .filename(path)
+        .create_if_missing(true);
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(options)
+        .await
+        .unwrap();
+    sqlx::raw_sql(&fixture(name)).execute(&pool).await.unwrap();
+    pool.close().await;
+}
+
+async fn chunk_objects(pool: &sqlx::SqlitePool) -> Vec<(String, Option<String>, String)> {
+    sqlx::query(
+        "SELECT chunk_hash, provider, object_id FROM chunk_objects ORDER BY chunk_hash, provider",
+    )
+    .fetch_all(pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| (r.get("chunk_hash"), r.get("provider"), r.get("object_id")))
+    .collect()
+}
+
+async fn migration_recorded(pool: &sqlx::SqlitePool) -> bool {
+    sqlx::query_scalar::<_, i64>("SELECT COUNT(1) FROM schema_migrations WHERE version = ?")
+        .bind(PROVIDER_MIGRATION_SCHEMA_VERSION)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+        == 1
+}
+
+#[tokio::test]
+async fn botapi_era_endpoint_db_is_migrated_on_open() {
+    let dir = tempfile::tempdir().unwrap();
+    let db_path = dir.path().join("index.ep1.sqlite");
+    write_fixture_db(&db_path, "botapi_era.sql").await;
+
+    let pool = open_existing_index_db(&db_path).await.unwrap();
+    assert!(migration_recorded(&pool).await);
+
+    let provider: String =
+        sqlx::query_scalar("SELECT provider FROM remote_indexes WHERE snapshot_id = 'snp_old'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(provider, "telegram.mtproto/ep1");
+    let part_provider: String =
+        sqlx::query_scalar("SELECT provider FROM remote_index_parts WHERE snapshot_id = 'snp_old'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(part_provider, "telegram.mtproto/ep1");
+
+    let ep = Some("telegram.mtproto/ep1".to_string());
+    assert_eq!(
+        chunk_objects(&pool).await,
+        vec![
+            (
+                "chk_a".to_string(),
+                ep.clone(),
+                "tgfile:BQACAgUAAxkBAAIChunkA".to_string()
+            ),
+            (
+                "chk_b".to_string(),
+                ep.clone(),
+                "tgpack:BQACAgUAAxkBAAIPack@0+10".to_string()
+            ),
+            (
+                "chk_c".to_string(),
+                ep,
+                "tgfile:BQACAgUAAxkBAAIChunkC".to_string()
+            ),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn pre_endpoint_global_db_uses_the_only_endpoint_and_drops_legacy_duplicates() {
+    let dir = tempfile::tempdir().unwrap();
+    let db_path = dir.path().join("index.sqlite");
+    write_fixture_db(&db_path, "mtproto_pre_endpoint.sql").await;
+
+    // The read-write open path (with sqlx migrations) migrates providers as well.
+    let pool = open_index_db(&db_path).await.unwrap();
+    assert!(migration_recorded(&pool).await);
+
+    let providers: Vec<String> =
+        sqlx::query_scalar("SELECT DISTINCT provider FROM remote_indexes ORDER BY provider")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(providers, vec!["telegram.mtproto/main".to_string()]);
+
+    let main = Some("telegram.mtproto/main".to_string());
+    assert_eq!(
+        chunk_objects(&pool).await,
+        vec![
+            (
+                "chk_1".to_string(),
+                main.clone(),
+                "tgpack:tgmtproto:v1:pack1@0+10".to_string()
+            ),
+            (
+                "chk_2".to_string(),
+                main,
+                "tgfile:tgmtproto:v1:new2".to_string()
+            ),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn migration_runs_once_on_open_and_again_on_request() {
+    let dir = tempfile::tempdir().unwrap();
+    let db_path = dir.path().join("index.ep1.sqlite");
+    write_fixture_db(&db_path, "botapi_era.sql").await;
+
+    let pool = open_existing_index_db(&db_path).await.unwrap();
+    sqlx::query("INSERT INTO chunks VALUES ('chk_d', 10, 'blake3', 'x', '2025-03-01T00:00:00Z')")
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query(
+        "INSERT INTO chunk_objects VALUES ('chk_d', 'telegram.botapi', 'BQACAgUAAxkBAAIChunkD', '2025-03-01T00:00:00Z')",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    pool.close().await;
+
+    let pool = open_existing_index_db(&db_path).await.unwrap();
+    let legacy: i64 =
+        sqlx::query_scalar("SELECT COUNT(1) FROM chunk_objects WHERE provider = 'telegram.botapi'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(legacy, 1);
+    pool.close().await;
+
+    let report = migrate_index_db_providers(&db_path).await.unwrap();
+    assert!(report.ran);
+    assert_eq!(report.endpoint_id.as_deref(), Some("ep1"));
+    assert_eq!(report.providers_updated, 1);
+    assert_eq!(report.object_ids_updated, 1);
+}
+
+#[tokio::test]
+async fn ambiguous_legacy_rows_are_left_alone() {
+    let dir = tempfile::tempdir().unwrap();
+    let db_path = dir.path().join("index.sqlite");
+    write_fixture_db(&db_path, "mtproto_pre_endpoint.sql").await;
+    {
+        let options = SqliteConnectOptions::new().filename(&db_path);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE remote_indexes SET provider = 'telegram.mtproto/other' WHERE snapshot_id = 'snp_2'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        pool.close().await;
+    }
+
+    let report = migrate_index_db_providers(&db_path).await.unwrap();
+    assert!(report.ran);
+    assert_eq!(report.endpoint_id, None);
+
+    let pool = open_existing_index_db(&db_path).await.unwrap();
+    let provider: String =
+        sqlx::query_scalar("SELECT provider FROM remote_indexes WHERE snapshot_id = 'snp_1'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(provider, "telegram.mtproto");
+}
+
+#[tokio::test]
+async fn chunk_ref_counts_are_backfilled_from_file_maps_in_the_db() {
+    let dir = tempfile::tempdir().unwrap();
+    let db_path = dir.path().join("index.ep1.sqlite");
+    write_fixture_db(&db_path, "botapi_era.sql").await;
+    {
+        let options = SqliteConnectOptions::new().filename(&db_path);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await
+            .unwrap();
+        sqlx::raw_sql(
+            r#"
+            CREATE TABLE files (
+              file_id TEXT PRIMARY KEY,
+              snapshot_id TEXT NOT NULL REFERENCES snapshots(snapshot_id),
+              path TEXT NOT NULL,
+              size INTEGER NOT NULL,
+              mtime_ms INTEGER NOT NULL,
+              mode INTEGER NOT NULL,
+              kind TEXT NOT NULL,
+              UNIQUE (snapshot_id, path)
+            );
+            CREATE TABLE file_chunks (
+              file_id TEXT NOT NULL REFERENCES files(file_id),
+              seq INTEGER NOT NULL,
+              chunk_hash TEXT NOT NULL REFERENCES chunks(chunk_hash),
+              offset INTEGER NOT NULL,
+              len INTEGER NOT NULL,
+              PRIMARY KEY (file_id, seq)
+            );
+            INSERT INTO files VALUES ('f1', 'snp_old', 'a.txt', 30, 0, 420, 'file');
+            INSERT INTO file_chunks VALUES ('f1', 0, 'chk_a', 0, 10);
+            INSERT INTO file_chunks VALUES ('f1', 1, 'chk_a', 10, 10);
+            INSERT INTO file_chunks VALUES ('f1', 2, 'chk_b', 20, 10);
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        pool.close().await;
+    }
+
+    let pool = open_index_db(&db_path).await.unwrap();
+    let counts: Vec<(String, i64)> =
+        sqlx::query_as("SELECT chunk_hash, ref_count FROM chunks ORDER BY chunk_hash")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(
+        counts,
+        vec![
+            ("chk_a".to_string(), 2),
+            ("chk_b".to_string(), 1),
+            ("chk_c".to_string(), 0)
+        ]
+    );
+    let counted: bool = sqlx::query_scalar(
+        "SELECT chunk_refs_counted FROM snapshots WHERE snapshot_id = 'snp_old'",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert!(counted);
+}
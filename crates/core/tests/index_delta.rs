@@ -0,0 +1,280 @@
+use std::path::{Path, PathBuf};
+
+use sqlx::Row;
+use televy_backup_core::{
+    BackupConfig, ChunkingConfig, Error, InMemoryStorage, RemoteDedupeMode, RestoreConfig,
+    republish_snapshot_index, restore_snapshot, run_backup,
+};
+use tempfile::TempDir;
+
+const MASTER_KEY: [u8; 32] = [7u8; 32];
+
+fn write_file(path: PathBuf, bytes: &[u8]) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, bytes).unwrap();
+}
+
+fn config(root: &Path, source: &Path) -> BackupConfig {
+    BackupConfig {
+        endpoint_db_path: root.join("index.sqlite"),
+        filemap_dir: root.join("filemaps"),
+        dedupe_db_path: root.join("dedupe.sqlite"),
+        dedupe_pending_db_path: root.join("dedupe.pending.sqlite"),
+        source_path: source.to_path_buf(),
+        label: "t1".to_string(),
+        chunking: ChunkingConfig {
+            min_bytes: 64,
+            avg_bytes: 256,
+            max_bytes: 1024,
+        },
+        rate_limit: Default::default(),
+        master_key: MASTER_KEY,
+        data_key: None,
+        snapshot_id: None,
+        keep_last_snapshots: 10,
+        remote_dedupe: RemoteDedupeMode::Disabled,
+        hint_changed_paths: None,
+        device: None,
+        index_full_every: 3,
+        created_at: None,
+    }
+}
+
+struct RemoteIndexRow {
+    manifest_object_id: String,
+    manifest_sha256: String,
+    manifest_kind: String,
+    parent_manifest_object_id: Option<String>,
+}
+
+async fn remote_index(db_path: &Path, snapshot_id: &str) -> RemoteIndexRow {
+    let pool = sqlx::SqlitePool::connect(&format!("sqlite:{}", db_path.display()))
+        .await
+        .unwrap();
+    let row = sqlx::query(
+        "SELECT manifest_object_id, manifest_sha256, manifest_kind, parent_manifest_object_id FROM remote_indexes WHERE snapshot_id = ?",
+    )
+    .bind(snapshot_id)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    pool.close().await;
+    RemoteIndexRow {
+        manifest_object_id: row.get("manifest_object_id"),
+        manifest_sha256: row.get("manifest_sha256"),
+        manifest_kind: row.get("manifest_kind"),
+        parent_manifest_object_id: row.get("parent_manifest_object_id"),
+    }
+}
+
+async fn endpoint_manifest_object_id(db_path: &Path) -> String {
+    let pool = sqlx::SqlitePool::connect(&format!("sqlite:{}", db_path.display()))
+        .await
+        .unwrap();
+    let value = sqlx::query("SELECT value FROM endpoint_state WHERE key = ?")
+        .bind(televy_backup_core::index_sync::ENDPOINT_STATE_ENDPOINT_MANIFEST_OBJECT_ID_KEY)
+        .fetch_one(&pool)
+        .await
+        .unwrap()
+        .get("value");
+    pool.close().await;
+    value
+}
+
+/// `path|size|mtime_ms|mode|kind|chunk list` of every file of the snapshot, sorted by path.
+async fn file_map(filemap_db_path: &Path, snapshot_id: &str) -> Vec<String> {
+    let pool = sqlx::SqlitePool::connect(&format!("sqlite:{}", filemap_db_path.display()))
+        .await
+        .unwrap();
+    let rows = sqlx::query(
+        r#"
+        SELECT f.path || '|' || f.size || '|' || f.mtime_ms || '|' || f.mode || '|' || f.kind || '|' ||
+          COALESCE((
+            SELECT group_concat(fc.chunk_hash || ':' || fc.offset || ':' || fc.len, ',' ORDER BY fc.seq)
+            FROM file_chunks fc WHERE fc.file_id = f.file_id
+          ), '') AS entry
+        FROM files f
+        WHERE f.snapshot_id = ?
+        ORDER BY f.path
+        "#,
+    )
+    .bind(snapshot_id)
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    pool.close().await;
+    rows.into_iter().map(|r| r.get("entry")).collect()
+}
+
+async fn restore(
+    storage: &InMemoryStorage,
+    root: &Path,
+    snapshot_id: &str,
+    index: &RemoteIndexRow,
+    name: &str,
+) -> televy_backup_core::Result<PathBuf> {
+    let target = root.join(name);
+    restore_snapshot(
+        storage,
+        RestoreConfig {
+            snapshot_id: snapshot_id.to_string(),
+            filemap_manifest_object_id: index.manifest_object_id.clone(),
+            filemap_manifest_sha256: Some(index.manifest_sha256.clone()),
+            endpoint_manifest_object_id: Some(
+                endpoint_manifest_object_id(&root.join("index.sqlite")).await,
+            ),
+            dedupe_catalog_object_id: None,
+            endpoint_dedupe_id: None,
+            endpoint_index_id: None,
+            master_key: MASTER_KEY,
+            data_key: None,
+            filemap_db_path: root.join(format!("{name}-filemap.sqlite")),
+            endpoint_db_path: Some(root.join(format!("{name}-endpoint.sqlite"))),
+            dedupe_db_path: None,
+            target_path: target.clone(),
+        },
+    )
+    .await?;
+    Ok(target)
+}
+
+#[tokio::test]
+async fn delta_indexes_chain_back_to_a_full_index() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    let source = root.join("src");
+    write_file(source.join("a.txt"), b"first version of a\n");
+    write_file(source.join("b.txt"), b"b goes away\n");
+    write_file(source.join("nested/c.bin"), &[42u8; 5_000]);
+    let storage = InMemoryStorage::new();
+    let db_path = root.join("index.sqlite");
+
+    let r1 = run_backup(&storage, config(root, &source)).await.unwrap();
+
+    write_file(source.join("a.txt"), b"second, longer version of a\n");
+    std::fs::remove_file(source.join("b.txt")).unwrap();
+    write_file(source.join("d.txt"), b"new file d\n");
+    let r2 = run_backup(&storage, config(root, &source)).await.unwrap();
+
+    write_file(source.join("nested/c.bin"), &[43u8; 6_000]);
+    let r3 = run_backup(&storage, config(root, &source)).await.unwrap();
+
+    // Two deltas since the last full index: the next one is full again.
+    let r4 = run_backup(&storage, config(root, &source)).await.unwrap();
+
+    let i1 = remote_index(&db_path, &r1.snapshot_id).await;
+    let i2 = remote_index(&db_path, &r2.snapshot_id).await;
+    let i3 = remote_index(&db_path, &r3.snapshot_id).await;
+    let i4 = remote_index(&db_path, &r4.snapshot_id).await;
+    assert_eq!(
+        [
+            i1.manifest_kind.as_str(),
+            i2.manifest_kind.as_str(),
+            i3.manifest_kind.as_str(),
+            i4.manifest_kind.as_str(),
+        ],
+        ["full", "delta", "delta", "full"]
+    );
+    assert_eq!(i1.parent_manifest_object_id, None);
+    assert_eq!(
+        i2.parent_manifest_object_id.as_deref(),
+        Some(i1.manifest_object_id.as_str())
+    );
+    assert_eq!(
+        i3.parent_manifest_object_id.as_deref(),
+        Some(i2.manifest_object_id.as_str())
+    );
+    assert_eq!(i4.parent_manifest_object_id, None);
+
+    // The rebuilt file map matches the one the backup wrote.
+    let restored = restore(&storage, root, &r3.snapshot_id, &i3, "restored-3")
+        .await
+        .unwrap();
+    let local_filemap = root
+        .join("filemaps")
+        .join(format!("{}.sqlite", r3.snapshot_id));
+    assert_eq!(
+        file_map(&root.join("restored-3-filemap.sqlite"), &r3.snapshot_id).await,
+        file_map(&local_filemap, &r3.snapshot_id).await
+    );
+    for rel in ["a.txt", "d.txt", "nested/c.bin"] {
+        assert_eq!(
+            std::fs::read(source.join(rel)).unwrap(),
+            std::fs::read(restored.join(rel)).unwrap(),
+            "{rel}"
+        );
+    }
+    assert!(!restored.join("b.txt").exists());
+
+    // A missing link in the chain is reported as such.
+    storage
+        .replace(&i2.manifest_object_id, b"gone".to_vec())
+        .await
+        .unwrap();
+    let err = restore(&storage, root, &r3.snapshot_id, &i3, "broken")
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), "index.chain_broken");
+    match err {
+        Error::IndexChainBroken {
+            snapshot_id,
+            missing_snapshot_id,
+            ..
+        } => {
+            assert_eq!(snapshot_id, r3.snapshot_id);
+            assert_eq!(missing_snapshot_id, r2.snapshot_id);
+        }
+        other => panic!("unexpected error: {other}"),
+    }
+
+    // Republishing from the local file map makes the snapshot restorable again.
+    let republished = republish_snapshot_index(
+        &storage,
+        &db_path,
+        &local_filemap,
+        &r3.snapshot_id,
+        &MASTER_KEY,
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        republished.replaced_manifest_object_id.as_deref(),
+        Some(i3.manifest_object_id.as_str())
+    );
+    let i3 = remote_index(&db_path, &r3.snapshot_id).await;
+    assert_eq!(i3.manifest_kind, "full");
+    assert_eq!(i3.manifest_object_id, republished.manifest_object_id);
+    let restored = restore(&storage, root, &r3.snapshot_id, &i3, "republished")
+        .await
+        .unwrap();
+    assert_eq!(
+        std::fs::read(source.join("nested/c.bin")).unwrap(),
+        std::fs::read(restored.join("nested/c.bin")).unwrap()
+    );
+}
+
+#[tokio::test]
+async fn full_every_one_always_uploads_full_indexes() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    let source = root.join("src");
+    write_file(source.join("a.txt"), b"a\n");
+    let storage = InMemoryStorage::new();
+
+    let mut ids = Vec::new();
+    for n in 0..2 {
+        write_file(source.join("a.txt"), format!("a{n}\n").as_bytes());
+        let cfg = BackupConfig {
+            index_full_every: 1,
+            created_at: None,
+            ..config(root, &source)
+        };
+        ids.push(run_backup(&storage, cfg).await.unwrap().snapshot_id);
+    }
+    for id in ids {
+        let index = remote_index(&root.join("index.sqlite"), &id).await;
+        assert_eq!(index.manifest_kind, "full");
+        assert_eq!(index.parent_manifest_object_id, None);
+    }
+}
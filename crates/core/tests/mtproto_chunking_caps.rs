@@ -78,9 +78,14 @@ async fn mtproto_provider_chunking_max_bytes_over_limit_fails_fast() {
             },
             rate_limit: Default::default(),
             master_key: [7u8; 32],
+            data_key: None,
             snapshot_id: None,
             keep_last_snapshots: 10,
             remote_dedupe: RemoteDedupeMode::Disabled,
+            hint_changed_paths: None,
+            device: None,
+            index_full_every: 1,
+            created_at: None,
         },
     )
     .await
@@ -127,9 +132,14 @@ async fn mtproto_provider_chunking_max_bytes_allows_exact_limit() {
             },
             rate_limit: Default::default(),
             master_key: [7u8; 32],
+            data_key: None,
             snapshot_id: None,
             keep_last_snapshots: 10,
             remote_dedupe: RemoteDedupeMode::Disabled,
+            hint_changed_paths: None,
+            device: None,
+            index_full_every: 1,
+            created_at: None,
         },
     )
     .await
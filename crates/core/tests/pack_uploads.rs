@@ -78,9 +78,7 @@ impl<'a, S: Storage + Sync> Storage for FailOnUpload<'a, S> {
         Box::pin(async move {
             let call_no = self.calls.fetch_add(1, Ordering::Relaxed) + 1;
             if call_no == self.fail_on_call {
-                return Err(Error::Telegram {
-                    message: "injected upload failure".to_string(),
-                });
+                return Err(Error::telegram("injected upload failure".to_string()));
             }
             self.inner.upload_document(filename, bytes).await
         })
@@ -94,23 +92,19 @@ impl<'a, S: Storage + Sync> Storage for FailOnUpload<'a, S> {
     }
 }
 
+/// Fails only the `fail_on_call`-th upload; `InMemoryStorage::builder().fail_uploads` covers
+/// the first ones.
 struct FailOnRetryableUpload<'a, S: Storage + Sync> {
     inner: &'a S,
     fail_on_call: usize,
-    fail_message: &'static str,
     calls: AtomicUsize,
 }
 
 impl<'a, S: Storage + Sync> FailOnRetryableUpload<'a, S> {
     fn new(inner: &'a S, fail_on_call: usize) -> Self {
-        Self::with_message(inner, fail_on_call, "timed out injected upload failure")
-    }
-
-    fn with_message(inner: &'a S, fail_on_call: usize, fail_message: &'static str) -> Self {
         Self {
             inner,
             fail_on_call,
-            fail_message,
             calls: AtomicUsize::new(0),
         }
     }
@@ -129,9 +123,7 @@ impl<'a, S: Storage + Sync> Storage for FailOnRetryableUpload<'a, S> {
         Box::pin(async move {
             let call_no = self.calls.fetch_add(1, Ordering::Relaxed) + 1;
             if call_no == self.fail_on_call {
-                return Err(Error::Telegram {
-                    message: self.fail_message.to_string(),
-                });
+                return Err(Error::telegram("timed out injected upload failure"));
             }
             self.inner.upload_document(filename, bytes).await
         })
@@ -174,9 +166,14 @@ async fn pack_enabled_by_count_reduces_upload_calls() {
             },
             rate_limit: Default::default(),
             master_key: [7u8; 32],
+            data_key: None,
             snapshot_id: None,
             keep_last_snapshots: 10,
             remote_dedupe: RemoteDedupeMode::Disabled,
+            hint_changed_paths: None,
+            device: None,
+            index_full_every: 1,
+            created_at: None,
         },
     )
     .await
@@ -219,9 +216,14 @@ async fn small_batch_does_not_enable_pack() {
             },
             rate_limit: Default::default(),
             master_key: [7u8; 32],
+            data_key: None,
             snapshot_id: None,
             keep_last_snapshots: 10,
             remote_dedupe: RemoteDedupeMode::Disabled,
+            hint_changed_paths: None,
+            device: None,
+            index_full_every: 1,
+            created_at: None,
         },
     )
     .await
@@ -262,9 +264,14 @@ async fn packed_upload_source_bytes_match_source_need_upload_total() {
             },
             rate_limit: Default::default(),
             master_key: [7u8; 32],
+            data_key: None,
             snapshot_id: None,
             keep_last_snapshots: 10,
             remote_dedupe: RemoteDedupeMode::Disabled,
+            hint_changed_paths: None,
+            device: None,
+            index_full_every: 1,
+            created_at: None,
         },
         BackupOptions {
             cancel: None,
@@ -273,6 +280,18 @@ async fn packed_upload_source_bytes_match_source_need_upload_total() {
                 files_total: 11,
                 bytes_total: 11 * 4096,
             }),
+            strict: false,
+            scan_root: None,
+            retry: Default::default(),
+            worker_threads: 0,
+            one_file_system: true,
+            default_excludes: false,
+            verify_after_upload: false,
+            filters: &[],
+            warn_path_bytes: 0,
+            max_unreadable_percent: 0,
+            upload_throttle: None,
+            sqlite_consistent: Default::default(),
         },
     )
     .await
@@ -365,9 +384,14 @@ async fn large_index_db_uploads_multiple_index_parts() {
             },
             rate_limit: Default::default(),
             master_key: [7u8; 32],
+            data_key: None,
             snapshot_id: None,
             keep_last_snapshots: 10,
             remote_dedupe: RemoteDedupeMode::Disabled,
+            hint_changed_paths: None,
+            device: None,
+            index_full_every: 1,
+            created_at: None,
         },
     )
     .await
@@ -412,9 +436,14 @@ async fn restart_after_index_upload_failure_does_not_reupload_chunks() {
             },
             rate_limit: Default::default(),
             master_key: [7u8; 32],
+            data_key: None,
             snapshot_id: None,
             keep_last_snapshots: 10,
             remote_dedupe: RemoteDedupeMode::Disabled,
+            hint_changed_paths: None,
+            device: None,
+            index_full_every: 1,
+            created_at: None,
         },
     )
     .await
@@ -449,9 +478,14 @@ async fn restart_after_index_upload_failure_does_not_reupload_chunks() {
             },
             rate_limit: Default::default(),
             master_key: [7u8; 32],
+            data_key: None,
             snapshot_id: None,
             keep_last_snapshots: 10,
             remote_dedupe: RemoteDedupeMode::Disabled,
+            hint_changed_paths: None,
+            device: None,
+            index_full_every: 1,
+            created_at: None,
         },
     )
     .await
@@ -470,15 +504,15 @@ async fn upload_retries_after_network_unreachable_failure() {
 
     let db_path = temp.path().join("index.sqlite");
     let filemap_dir = temp.path().join("filemaps");
-    let storage = InMemoryStorage::new();
-    let failing = FailOnRetryableUpload::with_message(
-        &storage,
-        1,
-        "network is unreachable injected upload failure",
-    );
+    let storage = InMemoryStorage::builder()
+        .fail_uploads(
+            1,
+            Error::telegram("network is unreachable injected upload failure"),
+        )
+        .build();
 
     let res = run_backup(
-        &failing,
+        &storage,
         BackupConfig {
             endpoint_db_path: db_path,
             filemap_dir: filemap_dir.clone(),
@@ -493,15 +527,22 @@ async fn upload_retries_after_network_unreachable_failure() {
             },
             rate_limit: Default::default(),
             master_key: [7u8; 32],
+            data_key: None,
             snapshot_id: None,
             keep_last_snapshots: 10,
             remote_dedupe: RemoteDedupeMode::Disabled,
+            hint_changed_paths: None,
+            device: None,
+            index_full_every: 1,
+            created_at: None,
         },
     )
     .await
     .expect("expected network unreachable upload to retry and succeed");
 
     assert!(res.index_parts >= 1);
+    let calls = storage.calls();
+    assert_eq!(calls.uploads, storage.uploaded.load(Ordering::Relaxed) + 1);
 }
 
 #[tokio::test]
@@ -512,11 +553,12 @@ async fn index_part_upload_retries_after_transient_failure() {
 
     let db_path = temp.path().join("index.sqlite");
     let filemap_dir = temp.path().join("filemaps");
-    let storage = InMemoryStorage::new();
-    let failing = FailOnRetryableUpload::new(&storage, 1);
+    let storage = InMemoryStorage::builder()
+        .fail_uploads(1, Error::telegram("timed out injected upload failure"))
+        .build();
 
     let res = run_backup(
-        &failing,
+        &storage,
         BackupConfig {
             endpoint_db_path: db_path,
             filemap_dir: filemap_dir.clone(),
@@ -531,9 +573,14 @@ async fn index_part_upload_retries_after_transient_failure() {
             },
             rate_limit: Default::default(),
             master_key: [7u8; 32],
+            data_key: None,
             snapshot_id: None,
             keep_last_snapshots: 10,
             remote_dedupe: RemoteDedupeMode::Disabled,
+            hint_changed_paths: None,
+            device: None,
+            index_full_every: 1,
+            created_at: None,
         },
     )
     .await
@@ -569,9 +616,14 @@ async fn index_manifest_upload_retries_after_transient_failure() {
             },
             rate_limit: Default::default(),
             master_key: [7u8; 32],
+            data_key: None,
             snapshot_id: None,
             keep_last_snapshots: 10,
             remote_dedupe: RemoteDedupeMode::Disabled,
+            hint_changed_paths: None,
+            device: None,
+            index_full_every: 1,
+            created_at: None,
         },
     )
     .await
@@ -604,9 +656,14 @@ async fn retention_preflight_bounds_snapshot_growth_on_repeated_failures() {
         },
         rate_limit: Default::default(),
         master_key: [9u8; 32],
+        data_key: None,
         snapshot_id: None,
         keep_last_snapshots: 2,
         remote_dedupe: RemoteDedupeMode::Disabled,
+        hint_changed_paths: None,
+        device: None,
+        index_full_every: 1,
+        created_at: None,
     };
 
     for _ in 0..6 {
@@ -658,9 +715,14 @@ async fn retention_preflight_does_not_prune_other_sources_before_backup() {
                 chunking: base_chunking.clone(),
                 rate_limit: Default::default(),
                 master_key: [3u8; 32],
+                data_key: None,
                 snapshot_id: None,
                 keep_last_snapshots: 10,
                 remote_dedupe: RemoteDedupeMode::Disabled,
+                hint_changed_paths: None,
+                device: None,
+                index_full_every: 1,
+                created_at: None,
             },
         )
         .await
@@ -680,9 +742,14 @@ async fn retention_preflight_does_not_prune_other_sources_before_backup() {
                 chunking: base_chunking.clone(),
                 rate_limit: Default::default(),
                 master_key: [3u8; 32],
+                data_key: None,
                 snapshot_id: None,
                 keep_last_snapshots: 10,
                 remote_dedupe: RemoteDedupeMode::Disabled,
+                hint_changed_paths: None,
+                device: None,
+                index_full_every: 1,
+                created_at: None,
             },
         )
         .await
@@ -707,9 +774,14 @@ async fn retention_preflight_does_not_prune_other_sources_before_backup() {
             chunking: base_chunking,
             rate_limit: Default::default(),
             master_key: [3u8; 32],
+            data_key: None,
             snapshot_id: None,
             keep_last_snapshots: 2,
             remote_dedupe: RemoteDedupeMode::Disabled,
+            hint_changed_paths: None,
+            device: None,
+            index_full_every: 1,
+            created_at: None,
         },
     )
     .await
@@ -756,9 +828,14 @@ async fn retention_preflight_handles_large_backlog_with_batched_prune() {
                 chunking: chunking.clone(),
                 rate_limit: Default::default(),
                 master_key: [5u8; 32],
+                data_key: None,
                 snapshot_id: None,
                 keep_last_snapshots: 64,
                 remote_dedupe: RemoteDedupeMode::Disabled,
+                hint_changed_paths: None,
+                device: None,
+                index_full_every: 1,
+                created_at: None,
             },
         )
         .await
@@ -782,9 +859,14 @@ async fn retention_preflight_handles_large_backlog_with_batched_prune() {
             chunking,
             rate_limit: Default::default(),
             master_key: [5u8; 32],
+            data_key: None,
             snapshot_id: None,
             keep_last_snapshots: 2,
             remote_dedupe: RemoteDedupeMode::Disabled,
+            hint_changed_paths: None,
+            device: None,
+            index_full_every: 1,
+            created_at: None,
         },
     )
     .await
@@ -796,3 +878,155 @@ async fn retention_preflight_handles_large_backlog_with_batched_prune() {
         "expected batched retention pruning to keep only the configured snapshots"
     );
 }
+
+#[tokio::test]
+async fn pinned_snapshots_survive_retention_and_need_force_to_delete() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("sync");
+    std::fs::create_dir_all(&source).unwrap();
+    write_file(source.join("payload.bin"), &[6u8; 4096]);
+
+    let db_path = temp.path().join("index.sqlite");
+    let filemap_dir = temp.path().join("filemaps");
+    let storage = InMemoryStorage::new();
+    let cfg = BackupConfig {
+        endpoint_db_path: db_path.clone(),
+        filemap_dir: filemap_dir.clone(),
+        dedupe_db_path: temp.path().join("dedupe.sqlite"),
+        dedupe_pending_db_path: temp.path().join("dedupe.pending.sqlite"),
+        source_path: source.clone(),
+        label: "pin".to_string(),
+        chunking: ChunkingConfig {
+            min_bytes: 4096,
+            avg_bytes: 4096,
+            max_bytes: 4096,
+        },
+        rate_limit: Default::default(),
+        master_key: [6u8; 32],
+        data_key: None,
+        snapshot_id: None,
+        keep_last_snapshots: 2,
+        remote_dedupe: RemoteDedupeMode::Disabled,
+        hint_changed_paths: None,
+        device: None,
+        index_full_every: 1,
+        created_at: None,
+    };
+
+    let first = run_backup(&storage, cfg.clone()).await.unwrap();
+    assert!(
+        televy_backup_core::set_snapshot_pinned(&db_path, &first.snapshot_id, true)
+            .await
+            .unwrap()
+    );
+    for _ in 0..3 {
+        run_backup(&storage, cfg.clone()).await.unwrap();
+    }
+
+    let pool = sqlx::SqlitePool::connect(&format!("sqlite:{}", db_path.display()))
+        .await
+        .unwrap();
+    assert_eq!(
+        snapshot_count_for_source(&pool, &source).await,
+        3,
+        "expected the pinned snapshot to be kept on top of keep_last_snapshots"
+    );
+
+    let err =
+        televy_backup_core::delete_snapshot(&db_path, &filemap_dir, &first.snapshot_id, false)
+            .await
+            .unwrap_err();
+    assert_eq!(err.code(), "snapshot.pinned");
+    assert!(
+        televy_backup_core::delete_snapshot(&db_path, &filemap_dir, &first.snapshot_id, true)
+            .await
+            .unwrap()
+    );
+    assert_eq!(snapshot_count_for_source(&pool, &source).await, 2);
+    assert!(
+        !televy_backup_core::delete_snapshot(&db_path, &filemap_dir, &first.snapshot_id, true)
+            .await
+            .unwrap()
+    );
+}
+
+#[tokio::test]
+async fn verified_at_is_tracked_per_source_across_snapshots() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("sync");
+    std::fs::create_dir_all(&source).unwrap();
+    write_file(source.join("payload.bin"), &[7u8; 4096]);
+
+    let db_path = temp.path().join("index.sqlite");
+    let storage = InMemoryStorage::new();
+    let cfg = BackupConfig {
+        endpoint_db_path: db_path.clone(),
+        filemap_dir: temp.path().join("filemaps"),
+        dedupe_db_path: temp.path().join("dedupe.sqlite"),
+        dedupe_pending_db_path: temp.path().join("dedupe.pending.sqlite"),
+        source_path: source.clone(),
+        label: "verify".to_string(),
+        chunking: ChunkingConfig {
+            min_bytes: 4096,
+            avg_bytes: 4096,
+            max_bytes: 4096,
+        },
+        rate_limit: Default::default(),
+        master_key: [7u8; 32],
+        data_key: None,
+        snapshot_id: None,
+        keep_last_snapshots: 5,
+        remote_dedupe: RemoteDedupeMode::Disabled,
+        hint_changed_paths: None,
+        device: None,
+        index_full_every: 1,
+        created_at: Some("2024-05-01T00:00:00.000Z".to_string()),
+    };
+    let source_path = source.to_string_lossy().to_string();
+
+    assert_eq!(
+        televy_backup_core::snapshot_verify_state(&db_path, &source_path)
+            .await
+            .unwrap(),
+        None
+    );
+
+    let first = run_backup(&storage, cfg.clone()).await.unwrap();
+    assert!(
+        televy_backup_core::record_snapshot_verified(
+            &db_path,
+            &first.snapshot_id,
+            "2024-06-01T00:00:00Z"
+        )
+        .await
+        .unwrap()
+    );
+    assert!(
+        !televy_backup_core::record_snapshot_verified(
+            &db_path,
+            "snp_missing",
+            "2024-06-02T00:00:00Z"
+        )
+        .await
+        .unwrap()
+    );
+    let second = run_backup(
+        &storage,
+        BackupConfig {
+            created_at: Some("2024-05-02T00:00:00.000Z".to_string()),
+            ..cfg
+        },
+    )
+    .await
+    .unwrap();
+
+    let state = televy_backup_core::snapshot_verify_state(&db_path, &source_path)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(state.latest_snapshot_id, second.snapshot_id);
+    assert_eq!(
+        state.last_verified_at.as_deref(),
+        Some("2024-06-01T00:00:00Z")
+    );
+}
@@ -0,0 +1,196 @@
+use std::path::Path;
+
+use sqlx::Row;
+use televy_backup_core::repo_export::{
+    REPO_EXPORT_FORMAT, REPO_EXPORT_VERSION, RepoExportConfig, RepoExportOptions, export_repo,
+    read_repo_manifest,
+};
+use televy_backup_core::{
+    BackupConfig, ChunkingConfig, InMemoryStorage, LocalDirStorage, RemoteDedupeMode,
+    RestoreConfig, VerifyConfig, restore_snapshot, run_backup, verify_snapshot,
+};
+use tempfile::TempDir;
+
+fn pseudo_random_bytes(seed: u64, len: usize) -> Vec<u8> {
+    let mut x = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+    (0..len)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x as u8
+        })
+        .collect()
+}
+
+fn backup_config(temp: &Path, source: &Path) -> BackupConfig {
+    BackupConfig {
+        endpoint_db_path: temp.join("index.sqlite"),
+        filemap_dir: temp.join("filemaps"),
+        dedupe_db_path: temp.join("dedupe.sqlite"),
+        dedupe_pending_db_path: temp.join("dedupe.pending.sqlite"),
+        source_path: source.to_path_buf(),
+        label: "t".to_string(),
+        chunking: ChunkingConfig {
+            min_bytes: 256,
+            avg_bytes: 1024,
+            max_bytes: 4096,
+        },
+        rate_limit: Default::default(),
+        master_key: [7u8; 32],
+        data_key: None,
+        snapshot_id: None,
+        keep_last_snapshots: 10,
+        remote_dedupe: RemoteDedupeMode::Disabled,
+        hint_changed_paths: None,
+        device: None,
+        index_full_every: 1,
+        created_at: None,
+    }
+}
+
+#[tokio::test]
+async fn export_resumes_and_restores_and_verifies_without_the_original_storage() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("src");
+    std::fs::create_dir_all(&source).unwrap();
+    let shared = pseudo_random_bytes(1, 5_000);
+    let first = pseudo_random_bytes(2, 5_000);
+    let second = pseudo_random_bytes(3, 5_000);
+    std::fs::write(source.join("shared.bin"), &shared).unwrap();
+    std::fs::write(source.join("changing.bin"), &first).unwrap();
+
+    let storage = InMemoryStorage::new();
+    let old = run_backup(&storage, backup_config(temp.path(), &source))
+        .await
+        .unwrap();
+    std::fs::write(source.join("changing.bin"), &second).unwrap();
+    let new = run_backup(&storage, backup_config(temp.path(), &source))
+        .await
+        .unwrap();
+
+    let repo = temp.path().join("cold");
+    let config = RepoExportConfig {
+        endpoint_id: "ep1".to_string(),
+        endpoint_db_path: temp.path().join("index.sqlite"),
+        bootstrap_catalog_object_id: None,
+        master_key: [7u8; 32],
+        output_dir: repo.clone(),
+    };
+    let res = export_repo(&storage, &config, RepoExportOptions::default())
+        .await
+        .unwrap();
+    assert!(res.objects_downloaded > 0);
+    assert_eq!(res.objects_skipped, 0);
+    assert_eq!(res.objects_total, res.objects_downloaded);
+
+    // A re-run only checks sizes.
+    let downloads = storage.calls().downloads;
+    let again = export_repo(&storage, &config, RepoExportOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(again.objects_downloaded, 0);
+    assert_eq!(again.objects_skipped, res.objects_total);
+    assert_eq!(again.bytes_total, res.bytes_total);
+    assert_eq!(storage.calls().downloads, downloads);
+
+    let manifest = read_repo_manifest(&repo).unwrap();
+    assert_eq!(manifest.format, REPO_EXPORT_FORMAT);
+    assert_eq!(manifest.version, REPO_EXPORT_VERSION);
+    assert_eq!(manifest.endpoint_id, "ep1");
+    assert_eq!(manifest.provider, "test.mem");
+    assert_eq!(manifest.objects, res.objects_total);
+
+    let local = LocalDirStorage::open(&repo).unwrap();
+    let pool = sqlx::SqlitePool::connect(&format!("sqlite:{}", local.index_db_path().display()))
+        .await
+        .unwrap();
+    let endpoint_manifest_object_id: String =
+        sqlx::query_scalar("SELECT value FROM endpoint_state WHERE key = ?")
+            .bind(televy_backup_core::index_sync::ENDPOINT_STATE_ENDPOINT_MANIFEST_OBJECT_ID_KEY)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    let mut manifests = Vec::new();
+    for snapshot_id in [&old.snapshot_id, &new.snapshot_id] {
+        let row = sqlx::query(
+            "SELECT manifest_object_id, manifest_sha256 FROM remote_indexes WHERE snapshot_id = ?",
+        )
+        .bind(snapshot_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        manifests.push((
+            row.get::<String, _>("manifest_object_id"),
+            row.get::<Option<String>, _>("manifest_sha256"),
+        ));
+    }
+    pool.close().await;
+
+    let restore_config =
+        |name: &str, snapshot_id: &str, manifest: &(String, Option<String>)| RestoreConfig {
+            snapshot_id: snapshot_id.to_string(),
+            filemap_manifest_object_id: manifest.0.clone(),
+            filemap_manifest_sha256: manifest.1.clone(),
+            endpoint_manifest_object_id: Some(endpoint_manifest_object_id.clone()),
+            dedupe_catalog_object_id: None,
+            endpoint_dedupe_id: None,
+            endpoint_index_id: None,
+            master_key: [7u8; 32],
+            data_key: None,
+            filemap_db_path: temp.path().join(format!("{name}-filemap.sqlite")),
+            endpoint_db_path: Some(temp.path().join(format!("{name}-endpoint.sqlite"))),
+            dedupe_db_path: None,
+            target_path: temp.path().join(name),
+        };
+    restore_snapshot(
+        &local,
+        restore_config("old", &old.snapshot_id, &manifests[0]),
+    )
+    .await
+    .unwrap();
+    restore_snapshot(
+        &local,
+        restore_config("new", &new.snapshot_id, &manifests[1]),
+    )
+    .await
+    .unwrap();
+    let read = |name: &str, file: &str| std::fs::read(temp.path().join(name).join(file)).unwrap();
+    assert_eq!(read("old", "shared.bin"), shared);
+    assert_eq!(read("old", "changing.bin"), first);
+    assert_eq!(read("new", "shared.bin"), shared);
+    assert_eq!(read("new", "changing.bin"), second);
+
+    let verified = verify_snapshot(
+        &local,
+        VerifyConfig {
+            snapshot_id: new.snapshot_id.clone(),
+            filemap_manifest_object_id: manifests[1].0.clone(),
+            filemap_manifest_sha256: manifests[1].1.clone(),
+            endpoint_manifest_object_id: Some(endpoint_manifest_object_id.clone()),
+            dedupe_catalog_object_id: None,
+            endpoint_dedupe_id: None,
+            endpoint_index_id: None,
+            master_key: [7u8; 32],
+            data_key: None,
+            filemap_db_path: temp.path().join("verify-filemap.sqlite"),
+            endpoint_db_path: Some(temp.path().join("verify-endpoint.sqlite")),
+            dedupe_db_path: None,
+            sample: None,
+        },
+    )
+    .await
+    .unwrap();
+    assert!(verified.chunks_checked > 0);
+
+    // An export of another endpoint is not mixed into this one.
+    let other = RepoExportConfig {
+        endpoint_id: "ep2".to_string(),
+        ..config
+    };
+    assert!(
+        export_repo(&storage, &other, RepoExportOptions::default())
+            .await
+            .is_err()
+    );
+}
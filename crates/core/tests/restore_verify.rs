@@ -1,9 +1,19 @@
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
 use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use sqlx::Row;
 use televy_backup_core::{
-    BackupConfig, ChunkObjectRef, ChunkingConfig, InMemoryStorage, RemoteDedupeMode, RestoreConfig,
-    VerifyConfig, parse_chunk_object_ref, restore_snapshot, run_backup, verify_snapshot,
+    BackupConfig, BackupOptions, ChunkHashAlg, ChunkObjectRef, ChunkingConfig, DataKey,
+    DownloadBatch, Error, InMemoryStorage, KeyDerivation, LONG_PATHS_DIR, LONG_PATHS_INDEX_FILE,
+    PathLimits, Phase, PhaseTimings, ProgressSink, RemoteDedupeMode, RestoreConfig,
+    RestoreFileSync, RestoreFsync, RestoreOptions, Storage, TaskProgress, VerifyConfig,
+    VerifyOptions, VerifySample, estimate_restore, parse_chunk_object_ref, restore_snapshot,
+    restore_snapshot_with, restore_snapshot_with_file_sync, run_backup, run_backup_with,
+    verify_snapshot, verify_snapshot_with,
 };
 use tempfile::TempDir;
 
@@ -47,9 +57,14 @@ async fn restore_and_verify_snapshot_from_remote_index() {
             },
             rate_limit: Default::default(),
             master_key,
+            data_key: None,
             snapshot_id: None,
             keep_last_snapshots: 10,
             remote_dedupe: RemoteDedupeMode::Disabled,
+            hint_changed_paths: None,
+            device: None,
+            index_full_every: 1,
+            created_at: None,
         },
     )
     .await
@@ -79,16 +94,25 @@ async fn restore_and_verify_snapshot_from_remote_index() {
     let restore_endpoint_db_path = temp.path().join("restored-endpoint.sqlite");
     let restore_target = temp.path().join("restored");
 
-    restore_snapshot(
+    for phase in [Phase::Scan, Phase::Upload, Phase::Index] {
+        assert!(
+            r1.phase_timings.get(&phase).is_some(),
+            "backup phase {phase}"
+        );
+    }
+
+    let rr = restore_snapshot(
         &storage,
         RestoreConfig {
             snapshot_id: r1.snapshot_id.clone(),
             filemap_manifest_object_id: manifest_object_id.clone(),
+            filemap_manifest_sha256: None,
             endpoint_manifest_object_id: Some(endpoint_manifest_object_id.clone()),
             dedupe_catalog_object_id: None,
             endpoint_dedupe_id: None,
             endpoint_index_id: None,
             master_key,
+            data_key: None,
             filemap_db_path: restore_filemap_db_path.clone(),
             endpoint_db_path: Some(restore_endpoint_db_path.clone()),
             dedupe_db_path: None,
@@ -97,6 +121,9 @@ async fn restore_and_verify_snapshot_from_remote_index() {
     )
     .await
     .unwrap();
+    assert!(rr.phase_timings.get(&Phase::Index).is_some());
+    assert!(rr.phase_timings.get(&Phase::Restore).is_some());
+    assert_eq!(rr.phase_timings.get(&Phase::Scan), None);
 
     assert_eq!(
         std::fs::read(source.join("a.txt")).unwrap(),
@@ -113,14 +140,17 @@ async fn restore_and_verify_snapshot_from_remote_index() {
         VerifyConfig {
             snapshot_id: r1.snapshot_id.clone(),
             filemap_manifest_object_id: manifest_object_id,
+            filemap_manifest_sha256: None,
             endpoint_manifest_object_id: Some(endpoint_manifest_object_id),
             dedupe_catalog_object_id: None,
             endpoint_dedupe_id: None,
             endpoint_index_id: None,
             master_key,
+            data_key: None,
             filemap_db_path: verify_index_db_path,
             endpoint_db_path: Some(temp.path().join("verify-endpoint.sqlite")),
             dedupe_db_path: None,
+            sample: None,
         },
     )
     .await
@@ -128,6 +158,9 @@ async fn restore_and_verify_snapshot_from_remote_index() {
 
     assert!(vr.chunks_checked > 0);
     assert!(vr.bytes_checked > 0);
+    assert!(vr.phase_timings.get(&Phase::Index).is_some());
+    assert!(vr.phase_timings.get(&Phase::Chunks).is_some());
+    assert_eq!(vr.phase_timings.get(&Phase::Restore), None);
 }
 
 #[tokio::test]
@@ -164,9 +197,14 @@ async fn verify_fails_when_any_chunk_missing() {
             },
             rate_limit: Default::default(),
             master_key,
+            data_key: None,
             snapshot_id: None,
             keep_last_snapshots: 10,
             remote_dedupe: RemoteDedupeMode::Disabled,
+            hint_changed_paths: None,
+            device: None,
+            index_full_every: 1,
+            created_at: None,
         },
     )
     .await
@@ -205,21 +243,24 @@ async fn verify_fails_when_any_chunk_missing() {
         ChunkObjectRef::Direct { object_id } => object_id,
         ChunkObjectRef::PackSlice { pack_object_id, .. } => pack_object_id,
     };
-    storage.remove(&underlying_object_id).await;
+    storage.drop_object(&underlying_object_id);
 
     let err = verify_snapshot(
         &storage,
         VerifyConfig {
             snapshot_id: r1.snapshot_id.clone(),
             filemap_manifest_object_id: manifest_object_id,
+            filemap_manifest_sha256: None,
             endpoint_manifest_object_id: Some(endpoint_manifest_object_id),
             dedupe_catalog_object_id: None,
             endpoint_dedupe_id: None,
             endpoint_index_id: None,
             master_key,
+            data_key: None,
             filemap_db_path: temp.path().join("verify-index.sqlite"),
             endpoint_db_path: Some(temp.path().join("verify-endpoint.sqlite")),
             dedupe_db_path: None,
+            sample: None,
         },
     )
     .await
@@ -228,3 +269,2004 @@ async fn verify_fails_when_any_chunk_missing() {
     let msg = err.to_string();
     assert!(msg.contains(&chunk_hash));
 }
+
+/// Serves flipped bytes for the next `n` downloads of selected objects.
+struct CorruptingStorage<'a> {
+    inner: &'a InMemoryStorage,
+    corrupt_remaining: Mutex<HashMap<String, usize>>,
+}
+
+impl<'a> CorruptingStorage<'a> {
+    fn new(inner: &'a InMemoryStorage, object_id: &str, times: usize) -> Self {
+        Self {
+            inner,
+            corrupt_remaining: Mutex::new(HashMap::from([(object_id.to_string(), times)])),
+        }
+    }
+}
+
+impl Storage for CorruptingStorage<'_> {
+    fn provider(&self) -> &str {
+        self.inner.provider()
+    }
+
+    fn upload_document<'a>(
+        &'a self,
+        filename: &'a str,
+        bytes: Vec<u8>,
+    ) -> Pin<Box<dyn Future<Output = televy_backup_core::Result<String>> + Send + 'a>> {
+        self.inner.upload_document(filename, bytes)
+    }
+
+    fn download_document<'a>(
+        &'a self,
+        object_id: &'a str,
+    ) -> Pin<Box<dyn Future<Output = televy_backup_core::Result<Vec<u8>>> + Send + 'a>> {
+        Box::pin(async move {
+            let mut bytes = self.inner.download_document(object_id).await?;
+            let corrupt = {
+                let mut remaining = self.corrupt_remaining.lock().unwrap();
+                match remaining.get_mut(object_id) {
+                    Some(n) if *n > 0 => {
+                        *n -= 1;
+                        true
+                    }
+                    _ => false,
+                }
+            };
+            if corrupt {
+                let last = bytes.len() - 1;
+                bytes[last] ^= 0xFF;
+            }
+            Ok(bytes)
+        })
+    }
+}
+
+/// Delays every download of the selected objects, like a slow remote.
+struct DelayingStorage<'a> {
+    inner: &'a InMemoryStorage,
+    delayed: HashSet<String>,
+    delay: Duration,
+}
+
+impl Storage for DelayingStorage<'_> {
+    fn provider(&self) -> &str {
+        self.inner.provider()
+    }
+
+    fn upload_document<'a>(
+        &'a self,
+        filename: &'a str,
+        bytes: Vec<u8>,
+    ) -> Pin<Box<dyn Future<Output = televy_backup_core::Result<String>> + Send + 'a>> {
+        self.inner.upload_document(filename, bytes)
+    }
+
+    fn download_document<'a>(
+        &'a self,
+        object_id: &'a str,
+    ) -> Pin<Box<dyn Future<Output = televy_backup_core::Result<Vec<u8>>> + Send + 'a>> {
+        Box::pin(async move {
+            if self.delayed.contains(object_id) {
+                tokio::time::sleep(self.delay).await;
+            }
+            self.inner.download_document(object_id).await
+        })
+    }
+}
+
+/// Counts downloads per object.
+struct CountingStorage<'a> {
+    inner: &'a InMemoryStorage,
+    downloads: Mutex<HashMap<String, usize>>,
+}
+
+impl Storage for CountingStorage<'_> {
+    fn provider(&self) -> &str {
+        self.inner.provider()
+    }
+
+    fn upload_document<'a>(
+        &'a self,
+        filename: &'a str,
+        bytes: Vec<u8>,
+    ) -> Pin<Box<dyn Future<Output = televy_backup_core::Result<String>> + Send + 'a>> {
+        self.inner.upload_document(filename, bytes)
+    }
+
+    fn download_document<'a>(
+        &'a self,
+        object_id: &'a str,
+    ) -> Pin<Box<dyn Future<Output = televy_backup_core::Result<Vec<u8>>> + Send + 'a>> {
+        *self
+            .downloads
+            .lock()
+            .unwrap()
+            .entry(object_id.to_string())
+            .or_default() += 1;
+        self.inner.download_document(object_id)
+    }
+}
+
+/// Records the size of every batched download.
+struct BatchRecordingStorage<'a> {
+    inner: &'a InMemoryStorage,
+    batches: Mutex<Vec<usize>>,
+}
+
+impl<'a> BatchRecordingStorage<'a> {
+    fn new(inner: &'a InMemoryStorage) -> Self {
+        Self {
+            inner,
+            batches: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl Storage for BatchRecordingStorage<'_> {
+    fn provider(&self) -> &str {
+        self.inner.provider()
+    }
+
+    fn upload_document<'a>(
+        &'a self,
+        filename: &'a str,
+        bytes: Vec<u8>,
+    ) -> Pin<Box<dyn Future<Output = televy_backup_core::Result<String>> + Send + 'a>> {
+        self.inner.upload_document(filename, bytes)
+    }
+
+    fn download_document<'a>(
+        &'a self,
+        object_id: &'a str,
+    ) -> Pin<Box<dyn Future<Output = televy_backup_core::Result<Vec<u8>>> + Send + 'a>> {
+        self.inner.download_document(object_id)
+    }
+
+    fn download_documents<'a>(
+        &'a self,
+        object_ids: &'a [String],
+    ) -> Pin<Box<dyn Future<Output = televy_backup_core::Result<DownloadBatch>> + Send + 'a>> {
+        self.batches.lock().unwrap().push(object_ids.len());
+        self.inner.download_documents(object_ids)
+    }
+}
+
+#[derive(Default)]
+struct ChunksDoneSink {
+    chunks_done: Mutex<Vec<u64>>,
+}
+
+impl ProgressSink for ChunksDoneSink {
+    fn on_progress(&self, progress: TaskProgress) {
+        if let Some(done) = progress.chunks_done {
+            self.chunks_done.lock().unwrap().push(done);
+        }
+    }
+}
+
+struct RestoreFixture {
+    temp: TempDir,
+    source: PathBuf,
+    storage: InMemoryStorage,
+    snapshot_id: String,
+    manifest_object_id: String,
+    manifest_sha256: String,
+    endpoint_manifest_object_id: String,
+    /// Underlying storage object holding the only chunk of `a.txt`.
+    a_txt_object_id: String,
+    a_txt_chunk_hash: String,
+    dirs_indexed: u64,
+    /// Phases the fixture's backup reported, in order.
+    backup_phases: Vec<Phase>,
+}
+
+/// mtime given to the fixture's directories, so a restore can't match it by accident.
+const FIXTURE_DIR_MTIME: Duration = Duration::from_secs(1_600_000_000);
+/// mtime given to the fixture's `a.txt` (see [`FIXTURE_DIR_MTIME`]).
+const FIXTURE_FILE_MTIME: Duration = Duration::from_secs(1_500_000_000);
+
+impl RestoreFixture {
+    async fn new() -> Self {
+        Self::build(None, 0).await
+    }
+
+    /// Backs up only `src/<file>` (a single-file source) when `file` is set.
+    async fn backing_up(file: Option<&str>) -> Self {
+        Self::build(file, 0).await
+    }
+
+    /// Adds `small/<i>.txt` for `i < n`, one small chunk object each.
+    async fn with_small_files(n: usize) -> Self {
+        Self::build(None, n).await
+    }
+
+    async fn build(file: Option<&str>, small_files: usize) -> Self {
+        let temp = TempDir::new().unwrap();
+        let source = temp.path().join("src");
+        let a_txt = b"hello world\nhello world\nhello world\n";
+        write_file(source.join("a.txt"), a_txt);
+        for i in 0..small_files {
+            write_file(
+                source.join(format!("small/{i}.txt")),
+                format!("small file {i}\n").as_bytes(),
+            );
+        }
+        write_file(source.join("nested/b.bin"), &[42u8; 10_000]);
+        std::fs::create_dir_all(source.join("empty/inner")).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(source.join("a.txt"))
+            .unwrap()
+            .set_modified(std::time::UNIX_EPOCH + FIXTURE_FILE_MTIME)
+            .unwrap();
+        for dir in ["nested", "empty/inner", "empty"] {
+            std::fs::File::open(source.join(dir))
+                .unwrap()
+                .set_modified(std::time::UNIX_EPOCH + FIXTURE_DIR_MTIME)
+                .unwrap();
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(
+                source.join("empty/inner"),
+                std::fs::Permissions::from_mode(0o750),
+            )
+            .unwrap();
+        }
+
+        let db_path = temp.path().join("index.sqlite");
+        let storage = InMemoryStorage::new();
+        let sink = RecordingSink::default();
+        let r1 = run_backup_with(
+            &storage,
+            BackupConfig {
+                endpoint_db_path: db_path.clone(),
+                filemap_dir: temp.path().join("filemaps"),
+                dedupe_db_path: temp.path().join("dedupe.sqlite"),
+                dedupe_pending_db_path: temp.path().join("dedupe.pending.sqlite"),
+                source_path: file.map_or_else(|| source.clone(), |f| source.join(f)),
+                label: "t1".to_string(),
+                chunking: ChunkingConfig {
+                    min_bytes: 64,
+                    avg_bytes: 256,
+                    max_bytes: 1024,
+                },
+                rate_limit: Default::default(),
+                master_key: [7u8; 32],
+                data_key: None,
+                snapshot_id: None,
+                keep_last_snapshots: 10,
+                remote_dedupe: RemoteDedupeMode::Disabled,
+                hint_changed_paths: None,
+                device: None,
+                index_full_every: 1,
+                created_at: None,
+            },
+            BackupOptions {
+                progress: Some(&sink),
+                ..BackupOptions::default()
+            },
+        )
+        .await
+        .unwrap();
+        let backup_phases = sink.phases();
+
+        let pool = sqlx::SqlitePool::connect(&format!("sqlite:{}", db_path.display()))
+            .await
+            .unwrap();
+        let row = sqlx::query(
+            "SELECT manifest_object_id, manifest_sha256 FROM remote_indexes WHERE snapshot_id = ? LIMIT 1",
+        )
+        .bind(&r1.snapshot_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        let manifest_object_id: String = row.get("manifest_object_id");
+        let manifest_sha256: String = row.get("manifest_sha256");
+        let endpoint_manifest_object_id: String =
+            sqlx::query("SELECT value FROM endpoint_state WHERE key = ? LIMIT 1")
+                .bind(
+                    televy_backup_core::index_sync::ENDPOINT_STATE_ENDPOINT_MANIFEST_OBJECT_ID_KEY,
+                )
+                .fetch_one(&pool)
+                .await
+                .unwrap()
+                .get("value");
+
+        let a_txt_chunk_hash = blake3::hash(a_txt).to_hex().to_string();
+        let object_id: String = sqlx::query(
+            "SELECT object_id FROM chunk_objects WHERE provider = 'test.mem' AND chunk_hash = ?",
+        )
+        .bind(&a_txt_chunk_hash)
+        .fetch_one(&pool)
+        .await
+        .unwrap()
+        .get("object_id");
+        let a_txt_object_id = match parse_chunk_object_ref(&object_id).unwrap() {
+            ChunkObjectRef::Direct { object_id } => object_id,
+            ChunkObjectRef::PackSlice { pack_object_id, .. } => pack_object_id,
+        };
+
+        Self {
+            temp,
+            source,
+            storage,
+            snapshot_id: r1.snapshot_id,
+            manifest_object_id,
+            manifest_sha256,
+            endpoint_manifest_object_id,
+            a_txt_object_id,
+            a_txt_chunk_hash,
+            dirs_indexed: r1.dirs_indexed,
+            backup_phases,
+        }
+    }
+
+    fn restore_config(&self, name: &str) -> RestoreConfig {
+        RestoreConfig {
+            snapshot_id: self.snapshot_id.clone(),
+            filemap_manifest_object_id: self.manifest_object_id.clone(),
+            filemap_manifest_sha256: Some(self.manifest_sha256.clone()),
+            endpoint_manifest_object_id: Some(self.endpoint_manifest_object_id.clone()),
+            dedupe_catalog_object_id: None,
+            endpoint_dedupe_id: None,
+            endpoint_index_id: None,
+            master_key: [7u8; 32],
+            data_key: None,
+            filemap_db_path: self.temp.path().join(format!("{name}-filemap.sqlite")),
+            endpoint_db_path: Some(self.temp.path().join(format!("{name}-endpoint.sqlite"))),
+            dedupe_db_path: None,
+            target_path: self.temp.path().join(name),
+        }
+    }
+
+    /// Storage objects holding chunks (direct objects or packs).
+    async fn chunk_storage_object_ids(&self) -> HashSet<String> {
+        let db_path = self.temp.path().join("index.sqlite");
+        let pool = sqlx::SqlitePool::connect(&format!("sqlite:{}", db_path.display()))
+            .await
+            .unwrap();
+        sqlx::query_scalar::<_, String>("SELECT object_id FROM chunk_objects")
+            .fetch_all(&pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|id| match parse_chunk_object_ref(&id).unwrap() {
+                ChunkObjectRef::Direct { object_id } => object_id,
+                ChunkObjectRef::PackSlice { pack_object_id, .. } => pack_object_id,
+            })
+            .collect()
+    }
+
+    fn verify_config(&self, name: &str, sample: Option<VerifySample>) -> VerifyConfig {
+        VerifyConfig {
+            snapshot_id: self.snapshot_id.clone(),
+            filemap_manifest_object_id: self.manifest_object_id.clone(),
+            filemap_manifest_sha256: Some(self.manifest_sha256.clone()),
+            endpoint_manifest_object_id: Some(self.endpoint_manifest_object_id.clone()),
+            dedupe_catalog_object_id: None,
+            endpoint_dedupe_id: None,
+            endpoint_index_id: None,
+            master_key: [7u8; 32],
+            data_key: None,
+            filemap_db_path: self.temp.path().join(format!("{name}-filemap.sqlite")),
+            endpoint_db_path: Some(self.temp.path().join(format!("{name}-endpoint.sqlite"))),
+            dedupe_db_path: None,
+            sample,
+        }
+    }
+}
+
+#[tokio::test]
+async fn restore_retries_corrupt_chunk_download() {
+    let fx = RestoreFixture::new().await;
+    let storage = CorruptingStorage::new(&fx.storage, &fx.a_txt_object_id, 2);
+
+    let cfg = fx.restore_config("restored");
+    let target = cfg.target_path.clone();
+    let res = restore_snapshot(&storage, cfg).await.unwrap();
+
+    assert_eq!(res.files_restored, 2);
+    assert_eq!(res.files_failed, 0);
+    assert_eq!(
+        std::fs::read(fx.source.join("a.txt")).unwrap(),
+        std::fs::read(target.join("a.txt")).unwrap()
+    );
+}
+
+#[tokio::test]
+async fn restore_fails_after_retries_or_skips_file_with_keep_going() {
+    let fx = RestoreFixture::new().await;
+    let storage = CorruptingStorage::new(&fx.storage, &fx.a_txt_object_id, usize::MAX);
+
+    let err = restore_snapshot(&storage, fx.restore_config("strict"))
+        .await
+        .unwrap_err();
+    let msg = err.to_string();
+    assert!(matches!(err, televy_backup_core::Error::Integrity { .. }));
+    assert!(msg.contains(&fx.a_txt_chunk_hash));
+    assert!(msg.contains(&fx.a_txt_object_id));
+    assert!(msg.contains("path=a.txt"));
+    // Corrupt bytes never reach the target: a file is only created by its first verified chunk.
+    assert!(!fx.temp.path().join("strict/a.txt").exists());
+
+    let cfg = fx.restore_config("lenient");
+    let target = cfg.target_path.clone();
+    let res = restore_snapshot_with(
+        &storage,
+        cfg,
+        RestoreOptions {
+            keep_going: true,
+            ..RestoreOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(res.files_restored, 1);
+    assert_eq!(res.files_failed, 1);
+    assert_eq!(res.failures.len(), 1);
+    assert_eq!(res.failures[0].path, "a.txt");
+    assert_eq!(
+        res.failures[0].chunk_hash.as_deref(),
+        Some(fx.a_txt_chunk_hash.as_str())
+    );
+    assert!(!target.join("a.txt").exists());
+    assert_eq!(
+        std::fs::read(fx.source.join("nested/b.bin")).unwrap(),
+        std::fs::read(target.join("nested/b.bin")).unwrap()
+    );
+}
+
+#[tokio::test]
+async fn verify_sample_rotates_through_all_chunks_and_still_fails_on_missing_ones() {
+    let fx = RestoreFixture::new().await;
+    let half = |rotation| VerifySample {
+        percent: 50.0,
+        max_bytes: None,
+        rotation,
+    };
+
+    let full = verify_snapshot(&fx.storage, fx.verify_config("full", None))
+        .await
+        .unwrap();
+    assert_eq!(full.chunks_skipped, 0);
+    assert_eq!(full.coverage_percent, 100.0);
+
+    // Adjacent windows are disjoint and together cover the snapshot.
+    let mut checked = 0;
+    for rotation in [0, 1] {
+        let name = format!("half-{rotation}");
+        let res = verify_snapshot(&fx.storage, fx.verify_config(&name, Some(half(rotation))))
+            .await
+            .unwrap();
+        assert_eq!(res.chunks_checked + res.chunks_skipped, full.chunks_checked);
+        checked += res.chunks_checked;
+    }
+    assert_eq!(checked, full.chunks_checked);
+
+    let capped = VerifySample {
+        percent: 100.0,
+        max_bytes: Some(0),
+        rotation: 0,
+    };
+    let res = verify_snapshot(&fx.storage, fx.verify_config("capped", Some(capped)))
+        .await
+        .unwrap();
+    assert_eq!(res.chunks_checked, 0);
+    assert_eq!(res.chunks_skipped, full.chunks_checked);
+    assert_eq!(res.coverage_percent, 0.0);
+
+    // Whichever window holds the missing chunk fails just like a full verify.
+    fx.storage.remove(&fx.a_txt_object_id).await;
+    let mut missing_reported = false;
+    for rotation in [0, 1] {
+        let name = format!("missing-{rotation}");
+        if let Err(e) =
+            verify_snapshot(&fx.storage, fx.verify_config(&name, Some(half(rotation)))).await
+        {
+            missing_reported |= e.to_string().contains(&fx.a_txt_chunk_hash);
+        }
+    }
+    assert!(missing_reported);
+}
+
+#[tokio::test]
+async fn verify_detects_a_corrupt_chunk_object() {
+    let fx = RestoreFixture::new().await;
+    fx.storage.corrupt_object(&fx.a_txt_object_id);
+    let downloads_before = fx.storage.calls().downloads;
+
+    let err = verify_snapshot(&fx.storage, fx.verify_config("corrupt", None))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains(&fx.a_txt_chunk_hash), "{err}");
+    assert!(fx.storage.calls().downloads > downloads_before);
+}
+
+#[tokio::test]
+async fn restore_and_verify_reject_a_manifest_that_does_not_match_its_recorded_hash() {
+    let fx = RestoreFixture::new().await;
+    let original = fx.storage.get(&fx.manifest_object_id).await.unwrap();
+    assert_eq!(
+        televy_backup_core::remote_index_db::manifest_sha256(&original),
+        fx.manifest_sha256
+    );
+
+    let mut tampered = original.clone();
+    let last = tampered.len() - 1;
+    tampered[last] ^= 0x01;
+    fx.storage.replace(&fx.manifest_object_id, tampered).await;
+
+    let err = restore_snapshot(&fx.storage, fx.restore_config("tampered"))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::ManifestMismatch { .. }), "{err:?}");
+    assert_eq!(err.code(), "integrity.manifest_mismatch");
+    assert!(!fx.temp.path().join("tampered").exists());
+
+    let err = verify_snapshot(&fx.storage, fx.verify_config("tampered-verify", None))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::ManifestMismatch { .. }), "{err:?}");
+
+    // Pointers from older catalogs carry no hash and keep working with the original manifest.
+    fx.storage.replace(&fx.manifest_object_id, original).await;
+    let mut legacy = fx.restore_config("legacy");
+    legacy.filemap_manifest_sha256 = None;
+    restore_snapshot(&fx.storage, legacy).await.unwrap();
+}
+
+#[tokio::test]
+async fn concurrent_verify_matches_sequential_results_in_less_time() {
+    let fx = RestoreFixture::new().await;
+    let delayed = fx.chunk_storage_object_ids().await;
+    assert!(delayed.len() >= 2, "fixture needs several chunk objects");
+    let storage = DelayingStorage {
+        inner: &fx.storage,
+        delayed,
+        delay: Duration::from_millis(150),
+    };
+
+    let run = |name: &'static str, concurrency: usize| {
+        let cfg = fx.verify_config(name, None);
+        let storage = &storage;
+        async move {
+            let sink = ChunksDoneSink::default();
+            let started = Instant::now();
+            let res = verify_snapshot_with(
+                storage,
+                cfg,
+                VerifyOptions {
+                    progress: Some(&sink),
+                    concurrency,
+                    ..VerifyOptions::default()
+                },
+            )
+            .await
+            .unwrap();
+            (
+                res,
+                started.elapsed(),
+                sink.chunks_done.into_inner().unwrap(),
+            )
+        }
+    };
+
+    let (sequential, sequential_elapsed, _) = run("sequential", 1).await;
+    let (parallel, parallel_elapsed, chunks_done) = run("parallel", 8).await;
+
+    assert_eq!(parallel.chunks_checked, sequential.chunks_checked);
+    assert_eq!(parallel.bytes_checked, sequential.bytes_checked);
+    assert_eq!(parallel.chunks_skipped, 0);
+    assert!(
+        parallel_elapsed * 3 < sequential_elapsed * 2,
+        "parallel={parallel_elapsed:?} sequential={sequential_elapsed:?}"
+    );
+
+    assert!(
+        chunks_done.windows(2).all(|w| w[0] < w[1]),
+        "{chunks_done:?}"
+    );
+    assert_eq!(chunks_done.last().copied(), Some(parallel.chunks_checked));
+}
+
+#[derive(Default)]
+struct RecordingSink {
+    events: Mutex<Vec<TaskProgress>>,
+}
+
+impl ProgressSink for RecordingSink {
+    fn on_progress(&self, progress: TaskProgress) {
+        self.events.lock().unwrap().push(progress);
+    }
+}
+
+impl RecordingSink {
+    fn phases(&self) -> Vec<Phase> {
+        let events = self.events.lock().unwrap();
+        events.iter().map(|p| p.phase.clone()).collect()
+    }
+}
+
+/// Asserts that `phases` starts with `first` and only moves along `edges` (staying in a phase is
+/// always fine).
+fn assert_phase_machine(kind: &str, phases: &[Phase], first: Phase, edges: &[(Phase, Phase)]) {
+    let mut runs = phases.to_vec();
+    runs.dedup();
+    assert_eq!(runs.first(), Some(&first), "{kind} phases {runs:?}");
+    for pair in runs.windows(2) {
+        assert!(
+            edges.contains(&(pair[0].clone(), pair[1].clone())),
+            "{kind}: illegal phase change {} -> {} in {runs:?}",
+            pair[0],
+            pair[1]
+        );
+    }
+}
+
+#[tokio::test]
+async fn progress_phases_follow_the_legal_orderings() {
+    let fx = RestoreFixture::new().await;
+    assert_phase_machine(
+        "backup",
+        &fx.backup_phases,
+        Phase::Scan,
+        &[
+            (Phase::Scan, Phase::ScanUpload),
+            (Phase::Scan, Phase::Upload),
+            (Phase::ScanUpload, Phase::Upload),
+            (Phase::Upload, Phase::Index),
+        ],
+    );
+    assert_eq!(fx.backup_phases.last(), Some(&Phase::Index));
+
+    let sink = RecordingSink::default();
+    restore_snapshot_with(
+        &fx.storage,
+        fx.restore_config("restored"),
+        RestoreOptions {
+            progress: Some(&sink),
+            ..RestoreOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_phase_machine(
+        "restore",
+        &sink.phases(),
+        Phase::Index,
+        &[
+            (Phase::Index, Phase::Download),
+            (Phase::Download, Phase::Restore),
+            (Phase::Restore, Phase::Download),
+        ],
+    );
+
+    let sink = RecordingSink::default();
+    verify_snapshot_with(
+        &fx.storage,
+        fx.verify_config("verified", None),
+        VerifyOptions {
+            progress: Some(&sink),
+            ..VerifyOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_phase_machine(
+        "verify",
+        &sink.phases(),
+        Phase::Index,
+        &[(Phase::Index, Phase::Chunks)],
+    );
+}
+
+#[test]
+fn phases_keep_their_wire_strings() {
+    for (phase, wire) in [
+        (Phase::Running, "running"),
+        (Phase::Preflight, "preflight"),
+        (Phase::Prepare, "prepare"),
+        (Phase::IndexSync, "index_sync"),
+        (Phase::Scan, "scan"),
+        (Phase::ScanUpload, "scan_upload"),
+        (Phase::Upload, "upload"),
+        (Phase::Index, "index"),
+        (Phase::Download, "download"),
+        (Phase::Restore, "restore"),
+        (Phase::Chunks, "chunks"),
+        (Phase::WaitingChat, "waiting_chat"),
+        (Phase::IndexRebuild, "index_rebuild"),
+        (Phase::SecretsWrite, "secrets_write"),
+        (Phase::Other("defrag".to_string()), "defrag"),
+    ] {
+        let json = serde_json::to_value(&phase).unwrap();
+        assert_eq!(json, serde_json::json!(wire));
+        assert_eq!(serde_json::from_value::<Phase>(json).unwrap(), phase);
+    }
+
+    let mut timings = PhaseTimings::default();
+    timings.record(Phase::ScanUpload, Duration::from_millis(5));
+    assert_eq!(timings.to_string(), r#"{"scan_upload":5}"#);
+}
+
+#[tokio::test]
+async fn restore_and_verify_report_totals_from_the_index() {
+    let fx = RestoreFixture::new().await;
+    let bytes_total = 36 + 10_000;
+
+    let sink = RecordingSink::default();
+    let res = restore_snapshot_with(
+        &fx.storage,
+        fx.restore_config("restored"),
+        RestoreOptions {
+            progress: Some(&sink),
+            ..RestoreOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+    let events = sink.events.into_inner().unwrap();
+    let run = events
+        .iter()
+        .filter(|p| p.phase == Phase::Download || p.phase == Phase::Restore)
+        .collect::<Vec<_>>();
+    assert_eq!(run[0].chunks_done, Some(0));
+    assert_eq!(run[0].bytes_read, Some(0));
+    for p in &run {
+        assert_eq!(p.files_total, Some(2));
+        assert_eq!(p.chunks_total, Some(res.chunks_downloaded));
+        assert_eq!(p.bytes_total, Some(bytes_total));
+    }
+    assert_eq!(run.last().unwrap().bytes_read, Some(bytes_total));
+
+    let sink = RecordingSink::default();
+    let res = verify_snapshot_with(
+        &fx.storage,
+        fx.verify_config("verified", None),
+        VerifyOptions {
+            progress: Some(&sink),
+            ..VerifyOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+    let events = sink.events.into_inner().unwrap();
+    let run = events
+        .iter()
+        .filter(|p| p.phase == Phase::Chunks)
+        .collect::<Vec<_>>();
+    assert_eq!(run[0].chunks_done, Some(0));
+    for p in &run {
+        assert_eq!(p.chunks_total, Some(res.chunks_checked));
+        assert_eq!(p.bytes_total, Some(res.bytes_checked));
+    }
+}
+
+#[tokio::test]
+async fn restore_with_delete_extraneous_makes_target_match_snapshot() {
+    let fx = RestoreFixture::with_small_files(2).await;
+    let cfg = fx.restore_config("synced");
+    let target = cfg.target_path.clone();
+    write_file(target.join("stale.txt"), b"gone from the source");
+    write_file(target.join("nested/old.bin"), &[1u8; 16]);
+    write_file(target.join("junk/deep/x.txt"), b"x");
+    // A directory where the snapshot has a file, and a file where it has a directory.
+    write_file(target.join("nested/b.bin/keep.txt"), b"in the way");
+    write_file(target.join("small"), b"in the way");
+    // Symlinks out of the target where the snapshot has a file and a directory.
+    let outside = target.with_file_name("outside");
+    write_file(outside.join("secret.txt"), b"not the restore's");
+    std::fs::create_dir_all(outside.join("dir")).unwrap();
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(outside.join("secret.txt"), target.join("a.txt")).unwrap();
+        std::os::unix::fs::symlink(outside.join("dir"), target.join("empty")).unwrap();
+    }
+    #[cfg(not(unix))]
+    {
+        write_file(target.join("a.txt/inner.txt"), b"in the way");
+        write_file(target.join("empty"), b"in the way");
+    }
+    let extraneous = [
+        #[cfg(unix)]
+        "a.txt",
+        #[cfg(not(unix))]
+        "a.txt/inner.txt",
+        #[cfg(not(unix))]
+        "a.txt/",
+        "empty",
+        "junk/deep/x.txt",
+        "junk/deep/",
+        "junk/",
+        "nested/b.bin/keep.txt",
+        "nested/b.bin/",
+        "nested/old.bin",
+        "small",
+        "stale.txt",
+    ];
+
+    let dry = restore_snapshot_with(
+        &fx.storage,
+        cfg.clone(),
+        RestoreOptions {
+            delete_extraneous: true,
+            dry_run: true,
+            ..RestoreOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(dry.files_deleted, 7);
+    assert_eq!(dry.deleted_paths, extraneous);
+    assert_eq!(dry.files_restored, 0);
+    assert!(target.join("a.txt").symlink_metadata().is_ok());
+    assert!(target.join("junk/deep/x.txt").exists());
+    assert!(target.join("small").is_file());
+
+    let res = restore_snapshot_with(
+        &fx.storage,
+        cfg,
+        RestoreOptions {
+            delete_extraneous: true,
+            ..RestoreOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(res.files_restored, 4);
+    assert_eq!(res.files_deleted, 7);
+    assert_eq!(res.deleted_paths, extraneous);
+    for rel in ["a.txt", "nested/b.bin", "small/0.txt", "small/1.txt"] {
+        let meta = target.join(rel).symlink_metadata().unwrap();
+        assert!(meta.is_file(), "{rel}");
+        assert_eq!(
+            std::fs::read(fx.source.join(rel)).unwrap(),
+            std::fs::read(target.join(rel)).unwrap(),
+            "{rel}"
+        );
+    }
+    assert!(target.join("empty").symlink_metadata().unwrap().is_dir());
+    assert!(target.join("empty/inner").is_dir());
+    assert!(!target.join("stale.txt").exists());
+    assert!(!target.join("nested/old.bin").exists());
+    assert!(!target.join("junk").exists());
+
+    // Nothing was written through the links.
+    assert_eq!(
+        std::fs::read(outside.join("secret.txt")).unwrap(),
+        b"not the restore's"
+    );
+    assert_eq!(std::fs::read_dir(outside.join("dir")).unwrap().count(), 0);
+}
+
+#[tokio::test]
+async fn restore_recreates_empty_dirs_with_their_mtime_and_mode() {
+    let fx = RestoreFixture::new().await;
+    assert_eq!(fx.dirs_indexed, 3);
+
+    let cfg = fx.restore_config("restored");
+    let target = cfg.target_path.clone();
+    let res = restore_snapshot(&fx.storage, cfg).await.unwrap();
+    assert_eq!(res.files_restored, 2);
+    assert_eq!(res.dirs_restored, 3);
+
+    assert!(target.join("empty/inner").is_dir());
+    for dir in ["nested", "empty", "empty/inner"] {
+        let mtime = std::fs::metadata(target.join(dir))
+            .unwrap()
+            .modified()
+            .unwrap();
+        assert_eq!(mtime, std::time::UNIX_EPOCH + FIXTURE_DIR_MTIME, "{dir}");
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(target.join("empty/inner"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o750);
+    }
+}
+
+#[tokio::test]
+async fn restore_with_preserve_times_sets_recorded_file_mtimes() {
+    let fx = RestoreFixture::new().await;
+    let filemap = fx
+        .temp
+        .path()
+        .join("filemaps")
+        .join(format!("{}.sqlite", fx.snapshot_id));
+    let pool = sqlx::SqlitePool::connect(&format!("sqlite:{}", filemap.display()))
+        .await
+        .unwrap();
+    let btime_ms: Option<i64> =
+        sqlx::query_scalar("SELECT btime_ms FROM files WHERE path = 'a.txt'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    let source_btime = std::fs::metadata(fx.source.join("a.txt"))
+        .unwrap()
+        .created();
+    assert_eq!(btime_ms.is_some(), source_btime.is_ok());
+    let dir_btime: Option<i64> =
+        sqlx::query_scalar("SELECT btime_ms FROM files WHERE path = 'nested'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(dir_btime, None);
+
+    let a_txt_mtime = |target: &std::path::Path| {
+        std::fs::metadata(target.join("a.txt"))
+            .unwrap()
+            .modified()
+            .unwrap()
+    };
+    let cfg = fx.restore_config("plain");
+    let plain = cfg.target_path.clone();
+    restore_snapshot(&fx.storage, cfg).await.unwrap();
+    assert_ne!(
+        a_txt_mtime(&plain),
+        std::time::UNIX_EPOCH + FIXTURE_FILE_MTIME
+    );
+
+    let cfg = fx.restore_config("timed");
+    let timed = cfg.target_path.clone();
+    restore_snapshot_with(
+        &fx.storage,
+        cfg,
+        RestoreOptions {
+            preserve_times: true,
+            ..RestoreOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        a_txt_mtime(&timed),
+        std::time::UNIX_EPOCH + FIXTURE_FILE_MTIME
+    );
+    assert_eq!(
+        std::fs::read(timed.join("nested/b.bin")).unwrap(),
+        [42u8; 10_000]
+    );
+}
+
+#[cfg(target_os = "macos")]
+#[tokio::test]
+async fn restore_with_preserve_times_round_trips_creation_times() {
+    let fx = RestoreFixture::new().await;
+    let cfg = fx.restore_config("timed");
+    let target = cfg.target_path.clone();
+    restore_snapshot_with(
+        &fx.storage,
+        cfg,
+        RestoreOptions {
+            preserve_times: true,
+            ..RestoreOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+    for file in ["a.txt", "nested/b.bin"] {
+        let created = |root: &std::path::Path| {
+            std::fs::metadata(root.join(file))
+                .unwrap()
+                .created()
+                .unwrap()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_millis()
+        };
+        // The index keeps milliseconds.
+        assert_eq!(created(&target), created(&fx.source), "{file}");
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn restore_owners_follow_the_mapping_and_failures_only_warn() {
+    use std::os::unix::fs::MetadataExt;
+    use televy_backup_core::ownership::{OwnerMapping, OwnershipOptions};
+
+    let fx = RestoreFixture::new().await;
+    // The fixture's files were created by this process, so `uid == 0` means we run as root.
+    let source = std::fs::metadata(fx.source.join("a.txt")).unwrap();
+    let (uid, gid) = (source.uid(), source.gid());
+    let filemap = fx
+        .temp
+        .path()
+        .join("filemaps")
+        .join(format!("{}.sqlite", fx.snapshot_id));
+    let pool = sqlx::SqlitePool::connect(&format!("sqlite:{}", filemap.display()))
+        .await
+        .unwrap();
+    let owners: Vec<(String, Option<i64>, Option<i64>)> =
+        sqlx::query_as("SELECT path, uid, gid FROM files ORDER BY path")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(owners.len(), 5);
+    for (path, row_uid, row_gid) in &owners {
+        assert_eq!(*row_uid, Some(i64::from(uid)), "{path}");
+        assert_eq!(*row_gid, Some(i64::from(gid)), "{path}");
+    }
+
+    let restore = |name: &'static str, ownership: OwnershipOptions| {
+        let cfg = fx.restore_config(name);
+        let target = cfg.target_path.clone();
+        let storage = &fx.storage;
+        async move {
+            let res = restore_snapshot_with(
+                storage,
+                cfg,
+                RestoreOptions {
+                    ownership,
+                    ..RestoreOptions::default()
+                },
+            )
+            .await
+            .unwrap();
+            (target, res)
+        }
+    };
+
+    // Our own files back as they were: nothing to change.
+    let (target, res) = restore(
+        "preserved",
+        OwnershipOptions {
+            preserve_owners: true,
+            uid_map: OwnerMapping::default(),
+        },
+    )
+    .await;
+    assert_eq!(res.ownership_warnings, 0);
+    assert_eq!(std::fs::metadata(target.join("a.txt")).unwrap().uid(), uid);
+
+    // Another user's uid: root hands everything over, anyone else keeps the files and warns.
+    let other = if uid == 4242 { 4243 } else { 4242 };
+    let (target, res) = restore(
+        "mapped",
+        OwnershipOptions {
+            preserve_owners: false,
+            uid_map: OwnerMapping::parse(&format!("{uid}:{other}")).unwrap(),
+        },
+    )
+    .await;
+    assert_eq!(res.files_restored, 2);
+    let restored = std::fs::metadata(target.join("nested/b.bin")).unwrap();
+    if uid == 0 {
+        assert_eq!(res.ownership_warnings, 0);
+        assert_eq!(restored.uid(), other);
+        assert_eq!(
+            std::fs::metadata(target.join("empty")).unwrap().uid(),
+            other
+        );
+    } else {
+        assert_eq!(
+            res.ownership_warnings,
+            res.files_restored + res.dirs_restored
+        );
+        assert_eq!(restored.uid(), uid);
+    }
+    assert_eq!(
+        std::fs::read(target.join("nested/b.bin")).unwrap(),
+        [42u8; 10_000]
+    );
+}
+
+#[tokio::test]
+async fn restore_estimate_counts_shared_objects_once_and_honors_the_path() {
+    let fx = RestoreFixture::new().await;
+    let filemap = fx
+        .temp
+        .path()
+        .join("filemaps")
+        .join(format!("{}.sqlite", fx.snapshot_id));
+    let index = fx.temp.path().join("index.sqlite");
+
+    let est = estimate_restore(&filemap, &index, &fx.snapshot_id, "test.mem", None)
+        .await
+        .unwrap();
+    assert_eq!((est.files, est.dirs), (2, 3));
+    assert_eq!(est.bytes_to_write, 36 + 10_000);
+    assert_eq!(est.chunks_missing, 0);
+    let objects = fx.chunk_storage_object_ids().await;
+    assert_eq!(est.objects, objects.len() as u64);
+    let mut stored = 0u64;
+    for id in &objects {
+        stored += fx.storage.download_document(id).await.unwrap().len() as u64;
+    }
+    // Only the encrypted pack headers are left out.
+    assert!(est.bytes_to_download <= stored);
+    assert!(est.bytes_to_download + 4096 * objects.len() as u64 >= stored);
+
+    let nested = estimate_restore(
+        &filemap,
+        &index,
+        &fx.snapshot_id,
+        "test.mem",
+        Some("nested/"),
+    )
+    .await
+    .unwrap();
+    assert_eq!((nested.files, nested.dirs), (1, 1));
+    assert_eq!(nested.bytes_to_write, 10_000);
+    assert!(nested.chunks < est.chunks);
+
+    let none = estimate_restore(&filemap, &index, &fx.snapshot_id, "test.mem", Some("nest"))
+        .await
+        .unwrap();
+    assert_eq!(none, Default::default());
+    assert!(
+        estimate_restore(&filemap, &index, &fx.snapshot_id, "test.mem", Some("../x"))
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn restore_downloads_each_chunk_object_once() {
+    let fx = RestoreFixture::new().await;
+    let objects = fx.chunk_storage_object_ids().await;
+    let storage = CountingStorage {
+        inner: &fx.storage,
+        downloads: Mutex::new(HashMap::new()),
+    };
+
+    let cfg = fx.restore_config("restored");
+    let target = cfg.target_path.clone();
+    let res = restore_snapshot(&storage, cfg).await.unwrap();
+
+    assert_eq!(res.files_restored, 2);
+    assert_eq!(res.objects_downloaded, objects.len() as u64);
+    // `nested/b.bin` repeats one chunk many times, all taken from a single download.
+    assert!(res.chunks_downloaded > res.objects_downloaded, "{res:?}");
+    let downloads = storage.downloads.into_inner().unwrap();
+    for id in &objects {
+        assert_eq!(downloads.get(id), Some(&1), "{id}");
+    }
+    for rel in ["a.txt", "nested/b.bin"] {
+        assert_eq!(
+            std::fs::read(fx.source.join(rel)).unwrap(),
+            std::fs::read(target.join(rel)).unwrap()
+        );
+    }
+}
+
+#[tokio::test]
+async fn delete_extraneous_refuses_the_filesystem_root() {
+    let fx = RestoreFixture::new().await;
+    let mut cfg = fx.restore_config("root");
+    cfg.target_path = PathBuf::from("/");
+    let err = restore_snapshot_with(
+        &fx.storage,
+        cfg,
+        RestoreOptions {
+            delete_extraneous: true,
+            dry_run: true,
+            ..RestoreOptions::default()
+        },
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, Error::InvalidConfig { .. }));
+    assert!(err.to_string().contains("refusing"));
+
+    let err = restore_snapshot_with(
+        &fx.storage,
+        fx.restore_config("plain"),
+        RestoreOptions {
+            dry_run: true,
+            ..RestoreOptions::default()
+        },
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, Error::InvalidConfig { .. }));
+}
+
+#[tokio::test]
+async fn single_file_snapshot_restores_by_basename_or_as_the_target_itself() {
+    let fx = RestoreFixture::backing_up(Some("a.txt")).await;
+    let a_txt = std::fs::read(fx.source.join("a.txt")).unwrap();
+
+    let cfg = fx.restore_config("into-dir");
+    let target = cfg.target_path.clone();
+    let res = restore_snapshot(&fx.storage, cfg).await.unwrap();
+    assert_eq!(res.files_restored, 1);
+    assert_eq!(std::fs::read(target.join("a.txt")).unwrap(), a_txt);
+    assert_eq!(std::fs::read_dir(&target).unwrap().count(), 1);
+
+    let mut cfg = fx.restore_config("as-file");
+    cfg.target_path = fx.temp.path().join("restored/copy.txt");
+    let target = cfg.target_path.clone();
+    let res = restore_snapshot_with(
+        &fx.storage,
+        cfg.clone(),
+        RestoreOptions {
+            as_file: true,
+            ..RestoreOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(res.files_restored, 1);
+    assert_eq!(std::fs::read(&target).unwrap(), a_txt);
+
+    // Never overwrites an existing path.
+    let err = restore_snapshot_with(
+        &fx.storage,
+        cfg,
+        RestoreOptions {
+            as_file: true,
+            ..RestoreOptions::default()
+        },
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("must not exist"), "{err}");
+
+    // A directory snapshot has no single file to write.
+    let dir_fx = RestoreFixture::new().await;
+    let mut cfg = dir_fx.restore_config("dir-as-file");
+    cfg.target_path = dir_fx.temp.path().join("copy.bin");
+    let err = restore_snapshot_with(
+        &dir_fx.storage,
+        cfg,
+        RestoreOptions {
+            as_file: true,
+            ..RestoreOptions::default()
+        },
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("single-file snapshot"), "{err}");
+    assert!(!dir_fx.temp.path().join("copy.bin").exists());
+}
+
+#[tokio::test]
+async fn restore_and_verify_batch_runs_of_small_objects() {
+    let fx = RestoreFixture::with_small_files(6).await;
+    let objects = fx.chunk_storage_object_ids().await;
+    assert!(objects.len() > 6, "{objects:?}");
+
+    let storage = BatchRecordingStorage::new(&fx.storage);
+    let cfg = fx.restore_config("restored");
+    let target = cfg.target_path.clone();
+    let res = restore_snapshot(&storage, cfg).await.unwrap();
+    assert_eq!(res.files_restored, 8);
+    assert_eq!(res.objects_downloaded, objects.len() as u64);
+    for i in 0..6 {
+        let rel = format!("small/{i}.txt");
+        assert_eq!(
+            std::fs::read(fx.source.join(&rel)).unwrap(),
+            std::fs::read(target.join(&rel)).unwrap()
+        );
+    }
+    let batched = storage.batches.lock().unwrap().iter().sum::<usize>();
+    assert!(batched >= 6, "{:?}", storage.batches.lock().unwrap());
+
+    let storage = BatchRecordingStorage::new(&fx.storage);
+    let res = verify_snapshot(&storage, fx.verify_config("verified", None))
+        .await
+        .unwrap();
+    assert!(res.chunks_checked >= 6, "{res:?}");
+    assert!(!storage.batches.lock().unwrap().is_empty());
+
+    // A deleted object fails its own entry of the batch as a missing chunk.
+    let small_hash = blake3::hash(b"small file 3\n").to_hex().to_string();
+    let db_path = fx.temp.path().join("index.sqlite");
+    let pool = sqlx::SqlitePool::connect(&format!("sqlite:{}", db_path.display()))
+        .await
+        .unwrap();
+    let object_id: String =
+        sqlx::query_scalar("SELECT object_id FROM chunk_objects WHERE chunk_hash = ?")
+            .bind(&small_hash)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    let ChunkObjectRef::Direct { object_id } = parse_chunk_object_ref(&object_id).unwrap() else {
+        panic!("expected a direct chunk object");
+    };
+    fx.storage.remove(&object_id).await.unwrap();
+    let err = verify_snapshot(&fx.storage, fx.verify_config("missing", None))
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(&err, Error::MissingChunkObject { chunk_hash } if *chunk_hash == small_hash),
+        "{err:?}"
+    );
+}
+
+/// A disk whose fsync takes a while, the cost parallel writers hide.
+struct SlowFsync(Duration);
+
+impl RestoreFileSync for SlowFsync {
+    fn sync(&self, _file: &std::fs::File) -> std::io::Result<()> {
+        std::thread::sleep(self.0);
+        Ok(())
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn parallel_restore_writes_the_same_files_as_a_sequential_one_in_less_write_time() {
+    let fx = RestoreFixture::with_small_files(10_000).await;
+
+    let run = |name: &'static str, write_concurrency: usize| {
+        let cfg = fx.restore_config(name);
+        let storage = &fx.storage;
+        async move {
+            let target = cfg.target_path.clone();
+            let res = restore_snapshot_with_file_sync(
+                storage,
+                cfg,
+                RestoreOptions {
+                    write_concurrency,
+                    fsync: RestoreFsync::PerFile,
+                    ..RestoreOptions::default()
+                },
+                Arc::new(SlowFsync(Duration::from_micros(300))),
+            )
+            .await
+            .unwrap();
+            (res, target)
+        }
+    };
+
+    let (sequential, sequential_target) = run("sequential", 1).await;
+    let (parallel, parallel_target) = run("parallel", 4).await;
+
+    assert_eq!(sequential.files_restored, 10_002);
+    assert_eq!(parallel.files_restored, sequential.files_restored);
+    assert_eq!(parallel.chunks_downloaded, sequential.chunks_downloaded);
+    assert_eq!(parallel.bytes_written, sequential.bytes_written);
+    assert_eq!(parallel.files_failed, 0);
+    for i in 0..10_000 {
+        let rel = format!("small/{i}.txt");
+        let expected = std::fs::read(fx.source.join(&rel)).unwrap();
+        assert_eq!(
+            std::fs::read(sequential_target.join(&rel)).unwrap(),
+            expected
+        );
+        assert_eq!(std::fs::read(parallel_target.join(&rel)).unwrap(), expected);
+    }
+    for rel in ["a.txt", "nested/b.bin"] {
+        assert_eq!(
+            std::fs::read(parallel_target.join(rel)).unwrap(),
+            std::fs::read(fx.source.join(rel)).unwrap()
+        );
+    }
+
+    // Four writers sleep through their fsyncs side by side.
+    assert!(
+        parallel.write_ms * 2 < sequential.write_ms,
+        "parallel={} sequential={}",
+        parallel.write_ms,
+        sequential.write_ms
+    );
+}
+
+#[tokio::test]
+async fn target_keyed_snapshot_restores_with_only_its_target_key() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("src");
+    write_file(source.join("a.txt"), b"shared chat, separate keys\n");
+    write_file(source.join("nested/b.bin"), &[9u8; 5_000]);
+
+    let db_path = temp.path().join("index.sqlite");
+    let storage = InMemoryStorage::new();
+    let master_key = [7u8; 32];
+    let target_key =
+        televy_backup_core::gold_key::TargetKey::derive(&master_key, "t1", storage.provider());
+
+    let r1 = run_backup(
+        &storage,
+        BackupConfig {
+            endpoint_db_path: db_path.clone(),
+            filemap_dir: temp.path().join("filemaps"),
+            dedupe_db_path: temp.path().join("dedupe.sqlite"),
+            dedupe_pending_db_path: temp.path().join("dedupe.pending.sqlite"),
+            source_path: source.clone(),
+            label: "t1".to_string(),
+            chunking: ChunkingConfig {
+                min_bytes: 64,
+                avg_bytes: 256,
+                max_bytes: 1024,
+            },
+            rate_limit: Default::default(),
+            master_key,
+            data_key: Some(DataKey::for_target(&master_key, "t1")),
+            snapshot_id: None,
+            keep_last_snapshots: 10,
+            remote_dedupe: RemoteDedupeMode::Disabled,
+            hint_changed_paths: None,
+            device: None,
+            index_full_every: 1,
+            created_at: None,
+        },
+    )
+    .await
+    .unwrap();
+
+    let pool = sqlx::SqlitePool::connect(&format!("sqlite:{}", db_path.display()))
+        .await
+        .unwrap();
+    let row = sqlx::query(
+        "SELECT s.key_derivation, s.key_target_id, ri.manifest_object_id FROM snapshots s JOIN remote_indexes ri ON ri.snapshot_id = s.snapshot_id WHERE s.snapshot_id = ?",
+    )
+    .bind(&r1.snapshot_id)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        row.get::<i64, _>("key_derivation"),
+        KeyDerivation::TargetV1.version()
+    );
+    assert_eq!(
+        row.get::<Option<String>, _>("key_target_id").as_deref(),
+        Some("t1")
+    );
+    let manifest_object_id: String = row.get("manifest_object_id");
+
+    let restore_with =
+        |name: &str, master_key: [u8; 32], data_key: Option<DataKey>| RestoreConfig {
+            snapshot_id: r1.snapshot_id.clone(),
+            filemap_manifest_object_id: manifest_object_id.clone(),
+            filemap_manifest_sha256: None,
+            endpoint_manifest_object_id: None,
+            dedupe_catalog_object_id: None,
+            endpoint_dedupe_id: None,
+            endpoint_index_id: None,
+            master_key,
+            data_key,
+            filemap_db_path: temp.path().join(format!("{name}-filemap.sqlite")),
+            endpoint_db_path: None,
+            dedupe_db_path: None,
+            target_path: temp.path().join(name),
+        };
+
+    // The master key alone does not open a target-keyed snapshot.
+    assert!(
+        restore_snapshot(&storage, restore_with("by-master", master_key, None))
+            .await
+            .is_err()
+    );
+
+    let restore_target = temp.path().join("by-target-key");
+    restore_snapshot(
+        &storage,
+        restore_with("by-target-key", [0u8; 32], Some(target_key.data_key())),
+    )
+    .await
+    .unwrap();
+    for rel in ["a.txt", "nested/b.bin"] {
+        assert_eq!(
+            std::fs::read(source.join(rel)).unwrap(),
+            std::fs::read(restore_target.join(rel)).unwrap()
+        );
+    }
+
+    // Another target's key is useless.
+    let other = DataKey::for_target(&master_key, "t2");
+    assert!(
+        restore_snapshot(&storage, restore_with("by-other", [0u8; 32], Some(other)))
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn plain_and_keyed_chunk_hash_snapshots_share_an_endpoint_and_both_restore() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("src");
+    write_file(source.join("a.txt"), b"known file, known chunk ids?\n");
+    write_file(source.join("nested/b.bin"), &[5u8; 6_000]);
+
+    let db_path = temp.path().join("index.sqlite");
+    let storage = InMemoryStorage::new();
+    let master_key = [7u8; 32];
+    let keyed = DataKey::master(&master_key).with_chunk_hash(ChunkHashAlg::Blake3Keyed);
+    let backup = |data_key: Option<DataKey>| BackupConfig {
+        endpoint_db_path: db_path.clone(),
+        filemap_dir: temp.path().join("filemaps"),
+        dedupe_db_path: temp.path().join("dedupe.sqlite"),
+        dedupe_pending_db_path: temp.path().join("dedupe.pending.sqlite"),
+        source_path: source.clone(),
+        label: "t1".to_string(),
+        chunking: ChunkingConfig {
+            min_bytes: 64,
+            avg_bytes: 256,
+            max_bytes: 1024,
+        },
+        rate_limit: Default::default(),
+        master_key,
+        data_key,
+        snapshot_id: None,
+        keep_last_snapshots: 10,
+        remote_dedupe: RemoteDedupeMode::Disabled,
+        hint_changed_paths: None,
+        device: None,
+        index_full_every: 1,
+        created_at: None,
+    };
+
+    let plain = run_backup(&storage, backup(None)).await.unwrap();
+    // Same bytes, other ids: only repeats within the snapshot dedupe, not the plain chunks.
+    let keyed1 = run_backup(&storage, backup(Some(keyed.clone())))
+        .await
+        .unwrap();
+    assert_eq!(keyed1.bytes_deduped, plain.bytes_deduped);
+    assert_eq!(keyed1.chunks_uploaded, plain.chunks_uploaded);
+    let keyed2 = run_backup(&storage, backup(Some(keyed.clone())))
+        .await
+        .unwrap();
+    assert_eq!(keyed2.chunks_uploaded, 0);
+
+    let pool = sqlx::SqlitePool::connect(&format!("sqlite:{}", db_path.display()))
+        .await
+        .unwrap();
+    let row = |snapshot_id: &str| {
+        sqlx::query(
+            "SELECT s.hash_alg, s.base_snapshot_id, ri.manifest_object_id FROM snapshots s JOIN remote_indexes ri ON ri.snapshot_id = s.snapshot_id WHERE s.snapshot_id = ?",
+        )
+        .bind(snapshot_id.to_string())
+        .fetch_one(&pool)
+    };
+    let plain_row = row(&plain.snapshot_id).await.unwrap();
+    let keyed1_row = row(&keyed1.snapshot_id).await.unwrap();
+    let keyed2_row = row(&keyed2.snapshot_id).await.unwrap();
+    assert_eq!(plain_row.get::<String, _>("hash_alg"), "blake3");
+    assert_eq!(keyed1_row.get::<String, _>("hash_alg"), "blake3-keyed");
+    assert_eq!(
+        keyed1_row.get::<Option<String>, _>("base_snapshot_id"),
+        None
+    );
+    assert_eq!(
+        keyed2_row
+            .get::<Option<String>, _>("base_snapshot_id")
+            .as_deref(),
+        Some(keyed1.snapshot_id.as_str())
+    );
+    let algs: Vec<(String, i64)> =
+        sqlx::query_as("SELECT hash_alg, COUNT(1) FROM chunks GROUP BY hash_alg ORDER BY hash_alg")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(
+        algs,
+        vec![
+            ("blake3".to_string(), plain.chunks_uploaded as i64),
+            ("blake3-keyed".to_string(), keyed1.chunks_uploaded as i64),
+        ]
+    );
+    let endpoint_manifest_object_id: String =
+        sqlx::query_scalar("SELECT value FROM endpoint_state WHERE key = ? LIMIT 1")
+            .bind(televy_backup_core::index_sync::ENDPOINT_STATE_ENDPOINT_MANIFEST_OBJECT_ID_KEY)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+
+    // Restore and verify take the algorithm from each snapshot's file map, not from the caller.
+    for (name, snapshot_id, manifest_row) in [
+        ("plain", &plain.snapshot_id, &plain_row),
+        ("keyed", &keyed2.snapshot_id, &keyed2_row),
+    ] {
+        let manifest_object_id: String = manifest_row.get("manifest_object_id");
+        let restore_target = temp.path().join(format!("{name}-restored"));
+        restore_snapshot(
+            &storage,
+            RestoreConfig {
+                snapshot_id: snapshot_id.clone(),
+                filemap_manifest_object_id: manifest_object_id.clone(),
+                filemap_manifest_sha256: None,
+                endpoint_manifest_object_id: Some(endpoint_manifest_object_id.clone()),
+                dedupe_catalog_object_id: None,
+                endpoint_dedupe_id: None,
+                endpoint_index_id: None,
+                master_key,
+                data_key: None,
+                filemap_db_path: temp.path().join(format!("{name}-filemap.sqlite")),
+                endpoint_db_path: Some(temp.path().join(format!("{name}-endpoint.sqlite"))),
+                dedupe_db_path: None,
+                target_path: restore_target.clone(),
+            },
+        )
+        .await
+        .unwrap();
+        for rel in ["a.txt", "nested/b.bin"] {
+            assert_eq!(
+                std::fs::read(source.join(rel)).unwrap(),
+                std::fs::read(restore_target.join(rel)).unwrap()
+            );
+        }
+
+        let vr = verify_snapshot(
+            &storage,
+            VerifyConfig {
+                snapshot_id: snapshot_id.clone(),
+                filemap_manifest_object_id: manifest_object_id,
+                filemap_manifest_sha256: None,
+                endpoint_manifest_object_id: Some(endpoint_manifest_object_id.clone()),
+                dedupe_catalog_object_id: None,
+                endpoint_dedupe_id: None,
+                endpoint_index_id: None,
+                master_key,
+                data_key: Some(DataKey::master(&master_key)),
+                filemap_db_path: temp.path().join(format!("{name}-verify-filemap.sqlite")),
+                endpoint_db_path: Some(temp.path().join(format!("{name}-verify-endpoint.sqlite"))),
+                dedupe_db_path: None,
+                sample: None,
+            },
+        )
+        .await
+        .unwrap();
+        assert!(vr.chunks_checked > 0);
+    }
+}
+
+#[tokio::test]
+async fn long_paths_are_counted_at_backup_and_refused_or_shortened_before_restoring() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("src");
+    let deep = format!("deep/{}/{}/file.txt", "x".repeat(80), "y".repeat(80));
+    write_file(source.join("a.txt"), b"short path\n");
+    write_file(source.join(&deep), b"deep file\n");
+
+    let storage = InMemoryStorage::new();
+    let db_path = temp.path().join("index.sqlite");
+    let r1 = run_backup_with(
+        &storage,
+        BackupConfig {
+            endpoint_db_path: db_path.clone(),
+            filemap_dir: temp.path().join("filemaps"),
+            dedupe_db_path: temp.path().join("dedupe.sqlite"),
+            dedupe_pending_db_path: temp.path().join("dedupe.pending.sqlite"),
+            source_path: source.clone(),
+            label: "t1".to_string(),
+            chunking: ChunkingConfig {
+                min_bytes: 64,
+                avg_bytes: 256,
+                max_bytes: 1024,
+            },
+            rate_limit: Default::default(),
+            master_key: [7u8; 32],
+            data_key: None,
+            snapshot_id: None,
+            keep_last_snapshots: 10,
+            remote_dedupe: RemoteDedupeMode::Disabled,
+            hint_changed_paths: None,
+            device: None,
+            index_full_every: 1,
+            created_at: None,
+        },
+        BackupOptions {
+            warn_path_bytes: 100,
+            ..BackupOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+    // The file and the `yyy` directory holding it.
+    assert_eq!(r1.long_paths, 2);
+
+    let pool = sqlx::SqlitePool::connect(&format!("sqlite:{}", db_path.display()))
+        .await
+        .unwrap();
+    let manifest_object_id: String =
+        sqlx::query_scalar("SELECT manifest_object_id FROM remote_indexes WHERE snapshot_id = ?")
+            .bind(&r1.snapshot_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    let endpoint_manifest_object_id: String =
+        sqlx::query_scalar("SELECT value FROM endpoint_state WHERE key = ?")
+            .bind(televy_backup_core::index_sync::ENDPOINT_STATE_ENDPOINT_MANIFEST_OBJECT_ID_KEY)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    let restore_config = |name: &str| RestoreConfig {
+        snapshot_id: r1.snapshot_id.clone(),
+        filemap_manifest_object_id: manifest_object_id.clone(),
+        filemap_manifest_sha256: None,
+        endpoint_manifest_object_id: Some(endpoint_manifest_object_id.clone()),
+        dedupe_catalog_object_id: None,
+        endpoint_dedupe_id: None,
+        endpoint_index_id: None,
+        master_key: [7u8; 32],
+        data_key: None,
+        filemap_db_path: temp.path().join(format!("{name}-filemap.sqlite")),
+        endpoint_db_path: Some(temp.path().join(format!("{name}-endpoint.sqlite"))),
+        dedupe_db_path: None,
+        target_path: temp.path().join(name),
+    };
+    // Room for `deep/xxx…` and the shortened paths, but not for `deep/xxx…/yyy…`.
+    let limits_for = |target: &std::path::Path| PathLimits {
+        name_max: 255,
+        path_max: target.as_os_str().len() + 150,
+    };
+
+    let refused = restore_config("refused");
+    let downloads_before = storage.calls().downloads;
+    let err = restore_snapshot_with(
+        &storage,
+        refused.clone(),
+        RestoreOptions {
+            path_limits: Some(limits_for(&refused.target_path)),
+            ..RestoreOptions::default()
+        },
+    )
+    .await
+    .unwrap_err();
+    match &err {
+        Error::PathTooLong { paths, .. } => {
+            assert_eq!(paths.len(), 2, "{paths:?}");
+            assert!(paths.contains(&deep));
+        }
+        other => panic!("unexpected error: {other:?}"),
+    }
+    let refused_downloads = storage.calls().downloads - downloads_before;
+    assert_eq!(std::fs::read_dir(&refused.target_path).unwrap().count(), 0);
+
+    let shortened = restore_config("shortened");
+    let downloads_before = storage.calls().downloads;
+    let res = restore_snapshot_with(
+        &storage,
+        shortened.clone(),
+        RestoreOptions {
+            path_limits: Some(limits_for(&shortened.target_path)),
+            shorten_long_paths: true,
+            ..RestoreOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(res.files_restored, 2);
+    // The refused restore fetched the file map only, no chunk data.
+    assert_eq!(
+        refused_downloads as u64 + res.objects_downloaded,
+        (storage.calls().downloads - downloads_before) as u64
+    );
+    assert_eq!(res.shortened_paths.len(), 2);
+    let (_, restored_as) = res
+        .shortened_paths
+        .iter()
+        .find(|(path, _)| *path == deep)
+        .unwrap();
+    assert!(restored_as.starts_with(LONG_PATHS_DIR) && restored_as.ends_with("/file.txt"));
+    let target = &shortened.target_path;
+    assert_eq!(
+        std::fs::read(target.join(restored_as)).unwrap(),
+        b"deep file\n"
+    );
+    assert_eq!(
+        std::fs::read(target.join("a.txt")).unwrap(),
+        b"short path\n"
+    );
+    assert!(target.join("deep").join("x".repeat(80)).is_dir());
+
+    let index: serde_json::Value = serde_json::from_slice(
+        &std::fs::read(target.join(LONG_PATHS_DIR).join(LONG_PATHS_INDEX_FILE)).unwrap(),
+    )
+    .unwrap();
+    assert!(
+        index
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|e| e["path"] == deep.as_str() && e["restoredPath"] == restored_as.as_str())
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn sqlite_database_written_during_backup_restores_intact_from_a_consistent_copy() {
+    use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode};
+    use sqlx::{ConnectOptions, Connection};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+    use televy_backup_core::sqlite_consistent::{self, SqliteConsistentOptions};
+
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("src");
+    std::fs::create_dir_all(&source).unwrap();
+    write_file(source.join("notes.txt"), b"not a database\n");
+    let db_path = source.join("app.db");
+
+    let mut writer = SqliteConnectOptions::new()
+        .filename(&db_path)
+        .create_if_missing(true)
+        .journal_mode(SqliteJournalMode::Wal)
+        .connect()
+        .await
+        .unwrap();
+    sqlx::query("CREATE TABLE events (id INTEGER PRIMARY KEY, payload BLOB NOT NULL)")
+        .execute(&mut writer)
+        .await
+        .unwrap();
+    sqlx::query("CREATE INDEX events_payload ON events(payload)")
+        .execute(&mut writer)
+        .await
+        .unwrap();
+
+    // Keeps committing transactions (and checkpointing the WAL into the main file) for as long
+    // as the backup runs.
+    let stop = Arc::new(AtomicBool::new(false));
+    let committed = Arc::new(AtomicU64::new(0));
+    let writer_task = {
+        let stop = stop.clone();
+        let committed = committed.clone();
+        tokio::spawn(async move {
+            let mut n = 0u64;
+            while !stop.load(Ordering::Relaxed) || n < 200 {
+                let mut tx = writer.begin().await.unwrap();
+                for _ in 0..20 {
+                    n += 1;
+                    let payload: Vec<u8> =
+                        (0..512).map(|i| (n.wrapping_mul(31) + i) as u8).collect();
+                    sqlx::query("INSERT INTO events (payload) VALUES (?)")
+                        .bind(payload)
+                        .execute(&mut *tx)
+                        .await
+                        .unwrap();
+                }
+                tx.commit().await.unwrap();
+                committed.store(n, Ordering::Relaxed);
+                if n.is_multiple_of(400) {
+                    sqlx::query("PRAGMA wal_checkpoint(PASSIVE)")
+                        .execute(&mut writer)
+                        .await
+                        .unwrap();
+                }
+                tokio::task::yield_now().await;
+            }
+            writer
+        })
+    };
+    while committed.load(Ordering::Relaxed) < 200 {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+
+    let storage = InMemoryStorage::new();
+    let index_db_path = temp.path().join("index.sqlite");
+    let filemap_dir = temp.path().join("filemaps");
+    let patterns = vec!["*.db".to_string()];
+    let res = run_backup_with(
+        &storage,
+        BackupConfig {
+            endpoint_db_path: index_db_path.clone(),
+            filemap_dir: filemap_dir.clone(),
+            dedupe_db_path: temp.path().join("dedupe.sqlite"),
+            dedupe_pending_db_path: temp.path().join("dedupe.pending.sqlite"),
+            source_path: source.clone(),
+            label: "t1".to_string(),
+            chunking: ChunkingConfig {
+                min_bytes: 1024,
+                avg_bytes: 4096,
+                max_bytes: 16384,
+            },
+            rate_limit: Default::default(),
+            master_key: [7u8; 32],
+            data_key: None,
+            snapshot_id: None,
+            keep_last_snapshots: 10,
+            remote_dedupe: RemoteDedupeMode::Disabled,
+            hint_changed_paths: None,
+            device: None,
+            index_full_every: 1,
+            created_at: None,
+        },
+        BackupOptions {
+            sqlite_consistent: SqliteConsistentOptions {
+                patterns: &patterns,
+                ..SqliteConsistentOptions::default()
+            },
+            ..BackupOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+    stop.store(true, Ordering::Relaxed);
+    writer_task.await.unwrap().close().await.unwrap();
+
+    assert_eq!(res.sqlite_consistent_files, 1);
+    assert_eq!(res.sqlite_consistent_fallbacks, 0);
+    assert!(res.warnings.is_empty(), "{:?}", res.warnings);
+    // No copies left behind next to the file maps.
+    assert!(std::fs::read_dir(&filemap_dir).unwrap().all(|e| {
+        !e.unwrap()
+            .file_name()
+            .to_string_lossy()
+            .starts_with(".sqlite-consistent-")
+    }));
+
+    let filemap = sqlx::SqlitePool::connect(&format!(
+        "sqlite:{}",
+        filemap_dir
+            .join(format!("{}.sqlite", res.snapshot_id))
+            .display()
+    ))
+    .await
+    .unwrap();
+    let rows: Vec<(String, Option<String>)> =
+        sqlx::query_as("SELECT path, content_source FROM files WHERE kind = 'file' ORDER BY path")
+            .fetch_all(&filemap)
+            .await
+            .unwrap();
+    assert_eq!(
+        rows,
+        vec![
+            (
+                "app.db".to_string(),
+                Some(sqlite_consistent::CONTENT_SOURCE.to_string())
+            ),
+            ("notes.txt".to_string(), None),
+        ]
+    );
+
+    let pool = sqlx::SqlitePool::connect(&format!("sqlite:{}", index_db_path.display()))
+        .await
+        .unwrap();
+    let manifest_object_id: String =
+        sqlx::query_scalar("SELECT manifest_object_id FROM remote_indexes WHERE snapshot_id = ?")
+            .bind(&res.snapshot_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    let endpoint_manifest_object_id: String =
+        sqlx::query_scalar("SELECT value FROM endpoint_state WHERE key = ?")
+            .bind(televy_backup_core::index_sync::ENDPOINT_STATE_ENDPOINT_MANIFEST_OBJECT_ID_KEY)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    let target = temp.path().join("restored");
+    restore_snapshot(
+        &storage,
+        RestoreConfig {
+            snapshot_id: res.snapshot_id.clone(),
+            filemap_manifest_object_id: manifest_object_id,
+            filemap_manifest_sha256: None,
+            endpoint_manifest_object_id: Some(endpoint_manifest_object_id),
+            dedupe_catalog_object_id: None,
+            endpoint_dedupe_id: None,
+            endpoint_index_id: None,
+            master_key: [7u8; 32],
+            data_key: None,
+            filemap_db_path: temp.path().join("restore-filemap.sqlite"),
+            endpoint_db_path: Some(temp.path().join("restore-endpoint.sqlite")),
+            dedupe_db_path: None,
+            target_path: target.clone(),
+        },
+    )
+    .await
+    .unwrap();
+
+    for sidecar in ["app.db-wal", "app.db-shm"] {
+        assert!(!target.join(sidecar).exists(), "{sidecar} was restored");
+    }
+    let mut restored = SqliteConnectOptions::new()
+        .filename(target.join("app.db"))
+        .read_only(true)
+        .connect()
+        .await
+        .unwrap();
+    let check: Vec<String> = sqlx::query_scalar("PRAGMA integrity_check")
+        .fetch_all(&mut restored)
+        .await
+        .unwrap();
+    assert_eq!(check, vec!["ok".to_string()]);
+    let (rows, max_id): (i64, i64) = sqlx::query_as("SELECT COUNT(*), MAX(id) FROM events")
+        .fetch_one(&mut restored)
+        .await
+        .unwrap();
+    // Whole transactions only: every restored row up to the last one is there.
+    assert!(
+        rows >= 200 && (rows as u64).is_multiple_of(20),
+        "{rows} rows"
+    );
+    assert_eq!(rows, max_id);
+}
@@ -2,8 +2,8 @@ use std::path::{Path, PathBuf};
 
 use sqlx::Row;
 use televy_backup_core::{
-    BackupConfig, ChunkingConfig, InMemoryStorage, RemoteDedupeMode, compute_source_quick_stats,
-    run_backup,
+    BackupConfig, BackupOptions, ChunkingConfig, InMemoryStorage, RemoteDedupeMode,
+    compute_source_quick_stats, run_backup, run_backup_with,
 };
 use tempfile::TempDir;
 
@@ -27,9 +27,14 @@ fn base_backup_config(temp: &TempDir, source: &Path) -> BackupConfig {
         },
         rate_limit: Default::default(),
         master_key: [7u8; 32],
+        data_key: None,
         snapshot_id: None,
         keep_last_snapshots: 10,
         remote_dedupe: RemoteDedupeMode::Disabled,
+        hint_changed_paths: None,
+        device: None,
+        index_full_every: 1,
+        created_at: None,
     }
 }
 
@@ -133,7 +138,7 @@ async fn quick_stats_respects_televyignore() {
     write_file(source.join("included.bin"), b"12345");
     write_file(source.join("ignored.bin"), b"this-should-not-count");
 
-    let quick = compute_source_quick_stats(&source, None).unwrap();
+    let quick = compute_source_quick_stats(&source, None, true, false).unwrap();
 
     let storage = InMemoryStorage::new();
     let cfg = base_backup_config(&temp, &source);
@@ -194,7 +199,7 @@ async fn hidden_files_not_implicitly_excluded() {
 fn quick_stats_missing_source_root_fails() {
     let temp = TempDir::new().unwrap();
     let missing_source = temp.path().join("missing");
-    let err = compute_source_quick_stats(&missing_source, None).unwrap_err();
+    let err = compute_source_quick_stats(&missing_source, None, true, false).unwrap_err();
     assert_eq!(err.code(), "walkdir");
 }
 
@@ -219,3 +224,65 @@ async fn ignore_rule_file_count_includes_self_ignored_rule_file() {
     assert!(!file_paths.contains(&"drop.tmp"));
     assert_eq!(result.ignore_rule_files, 1);
 }
+
+#[tokio::test]
+async fn default_excludes_leave_out_noise_alongside_televyignore() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("src");
+    std::fs::create_dir_all(&source).unwrap();
+
+    write_file(source.join(".televyignore"), b"*.tmp\n");
+    write_file(source.join("notes.txt"), b"keep");
+    write_file(source.join("draft.tmp"), b"drop");
+    write_file(source.join(".DS_Store"), b"noise");
+    write_file(source.join("Library/Caches/app/blob"), b"noise");
+    write_file(source.join("Library/Preferences/app.plist"), b"keep");
+    write_file(source.join(".Trash/old.txt"), b"noise");
+    write_file(source.join("web/node_modules/.cache/babel"), b"noise");
+    write_file(source.join("web/node_modules/pkg/index.js"), b"keep");
+    write_file(source.join("render.nobackup/frame.exr"), b"noise");
+
+    let quick = compute_source_quick_stats(&source, None, true, true).unwrap();
+
+    let storage = InMemoryStorage::new();
+    let cfg = base_backup_config(&temp, &source);
+    let filemap_dir = cfg.filemap_dir.clone();
+    let result = run_backup_with(
+        &storage,
+        cfg,
+        BackupOptions {
+            default_excludes: true,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let files = snapshot_files(&filemap_dir, &result.snapshot_id).await;
+    let file_paths = files.iter().map(|(p, _)| p.as_str()).collect::<Vec<_>>();
+
+    assert_eq!(
+        file_paths,
+        [
+            ".televyignore",
+            "Library/Preferences/app.plist",
+            "notes.txt",
+            "web/node_modules/pkg/index.js",
+        ]
+    );
+    // `.DS_Store`, `Caches/`, `.Trash/`, `node_modules/.cache/` and `render.nobackup/`; the
+    // directories count once.
+    assert_eq!(result.files_skipped_default_excludes, 5);
+    assert_eq!(quick.files_total, files.len() as u64);
+
+    let temp = TempDir::new().unwrap();
+    let cfg = base_backup_config(&temp, &source);
+    let filemap_dir = cfg.filemap_dir.clone();
+    let result = run_backup(&InMemoryStorage::new(), cfg).await.unwrap();
+    assert_eq!(result.files_skipped_default_excludes, 0);
+    assert_eq!(
+        snapshot_files(&filemap_dir, &result.snapshot_id)
+            .await
+            .len(),
+        9
+    );
+}
@@ -128,9 +128,14 @@ async fn upload_concurrency_respects_limit() {
                 min_delay_ms: 0,
             },
             master_key: [7u8; 32],
+            data_key: None,
             snapshot_id: None,
             keep_last_snapshots: 10,
             remote_dedupe: RemoteDedupeMode::Disabled,
+            hint_changed_paths: None,
+            device: None,
+            index_full_every: 1,
+            created_at: None,
         },
     )
     .await
@@ -176,9 +181,14 @@ async fn upload_min_delay_is_global() {
                 min_delay_ms: 50,
             },
             master_key: [7u8; 32],
+            data_key: None,
             snapshot_id: None,
             keep_last_snapshots: 10,
             remote_dedupe: RemoteDedupeMode::Disabled,
+            hint_changed_paths: None,
+            device: None,
+            index_full_every: 1,
+            created_at: None,
         },
     )
     .await
@@ -3,17 +3,36 @@ use std::sync::{Arc, Mutex};
 
 use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter};
 use tokio::net::{UnixListener, UnixStream};
-use tokio::sync::{RwLock, broadcast, oneshot};
+use tokio::sync::{RwLock, broadcast, mpsc, oneshot};
 
-use televy_backup_core::TaskProgress;
+use televy_backup_core::audit::{self, AuditActor};
 use televy_backup_core::control::{
-    ControlError, ControlRequest, ControlResponse, SecretsClearTelegramMtprotoSessionParams,
-    SecretsPresenceParams, SecretsSetTelegramApiHashParams, SecretsSetTelegramBotTokenParams,
-    StatusTaskFinishParams, StatusTaskProgressParams, StatusTaskStartParams, VaultStatusResult,
+    BackupRunNowParams, BackupRunNowResult, ControlError, ControlRequest, ControlResponse,
+    DaemonVersionResult, IndexSyncParams, LogsStreamLagged, LogsStreamParams, LogsStreamResult,
+    QueueListResult, QueueRemoveParams, RestoreEstimateParams,
+    SecretsClearTelegramMtprotoSessionParams, SecretsDeleteParams, SecretsDeleteResult,
+    SecretsListResult, SecretsPresenceParams, SecretsSetTelegramApiHashParams,
+    SecretsSetTelegramBotTokenParams, SecurityAuthorizeRestoreParams,
+    SecurityAuthorizeRestoreResult, StatsTopFilesParams, StatusTaskFinishParams,
+    StatusTaskProgressParams, StatusTaskStartParams, TargetsSetEnabledParams,
+    TargetsSetEnabledResult, VaultStatusResult, VerifyAcknowledgeParams, VerifyAcknowledgeResult,
 };
+use televy_backup_core::index_sync::IndexSyncReport;
+use televy_backup_core::secrets::{SecretsProvider, SecretsStoreError};
+use televy_backup_core::security::{self, PassphraseAttempts};
+use televy_backup_core::version::DaemonVersionStamp;
+use televy_backup_core::{ErrorCode, TaskProgress};
+
+use crate::run_queue::RunTrigger;
 
 type Settings = televy_backup_core::config::SettingsV2;
 
+/// An `index.sync` call waiting for the main loop, which owns the endpoint connections.
+pub struct IndexSyncRequest {
+    pub params: IndexSyncParams,
+    pub reply: oneshot::Sender<Result<IndexSyncReport, ControlError>>,
+}
+
 pub struct ControlIpcServerHandle {
     socket_path: PathBuf,
     shutdown_tx: Option<oneshot::Sender<()>>,
@@ -49,8 +68,10 @@ impl Drop for ControlIpcServerHandle {
 pub fn spawn_control_ipc_server(
     socket_path: PathBuf,
     config_root: PathBuf,
+    data_root: PathBuf,
     settings: Arc<RwLock<Settings>>,
     status_state: Arc<Mutex<crate::StatusRuntimeState>>,
+    index_sync_tx: mpsc::UnboundedSender<IndexSyncRequest>,
 ) -> std::io::Result<ControlIpcServerHandle> {
     if let Some(parent) = socket_path.parent() {
         std::fs::create_dir_all(parent)?;
@@ -96,6 +117,7 @@ pub fn spawn_control_ipc_server(
     }
 
     let handle_socket_path = socket_path.clone();
+    let passphrase_attempts = Arc::new(Mutex::new(PassphraseAttempts::new()));
     let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<()>();
     let (shutdown_broadcast, _) = broadcast::channel::<()>(8);
 
@@ -122,10 +144,13 @@ pub fn spawn_control_ipc_server(
 
                     let mut shutdown = shutdown_broadcast.subscribe();
                     let config_root = config_root.clone();
+                    let data_root = data_root.clone();
                     let settings = settings.clone();
                     let status_state = status_state.clone();
+                    let passphrase_attempts = passphrase_attempts.clone();
+                    let index_sync_tx = index_sync_tx.clone();
                     tokio::spawn(async move {
-                        let _ = handle_control_ipc_client(stream, &config_root, settings, status_state, &mut shutdown).await;
+                        let _ = handle_control_ipc_client(stream, &config_root, &data_root, settings, status_state, passphrase_attempts, index_sync_tx, &mut shutdown).await;
                     });
                 }
             }
@@ -139,11 +164,15 @@ pub fn spawn_control_ipc_server(
     })
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_control_ipc_client(
     stream: UnixStream,
     config_root: &std::path::Path,
+    data_root: &std::path::Path,
     settings: Arc<RwLock<Settings>>,
     status_state: Arc<Mutex<crate::StatusRuntimeState>>,
+    passphrase_attempts: Arc<Mutex<PassphraseAttempts>>,
+    index_sync_tx: mpsc::UnboundedSender<IndexSyncRequest>,
     shutdown: &mut broadcast::Receiver<()>,
 ) -> std::io::Result<()> {
     let (r, w) = stream.into_split();
@@ -183,7 +212,7 @@ async fn handle_control_ipc_client(
     if buf.len() > MAX_REQUEST_LINE_BYTES {
         write_json_line(
             &mut w,
-            &ControlResponse::err(
+            ControlResponse::err(
                 "unknown",
                 ControlError::invalid_request("request too large", serde_json::json!({})),
             ),
@@ -197,7 +226,7 @@ async fn handle_control_ipc_client(
         Err(_) => {
             write_json_line(
                 &mut w,
-                &ControlResponse::err(
+                ControlResponse::err(
                     "unknown",
                     ControlError::invalid_request("invalid utf-8", serde_json::json!({})),
                 ),
@@ -212,7 +241,7 @@ async fn handle_control_ipc_client(
         Err(e) => {
             write_json_line(
                 &mut w,
-                &ControlResponse::err(
+                ControlResponse::err(
                     "unknown",
                     ControlError::invalid_request(
                         "invalid json",
@@ -225,19 +254,47 @@ async fn handle_control_ipc_client(
         }
     };
 
-    let resp = {
+    if req.method == "logs.stream" {
+        // Keeps the connection for the run's log lines instead of answering once.
+        return logs_stream(&req, &mut r, &mut w, shutdown).await;
+    }
+
+    let resp = if req.method == "restore.estimate" {
+        // Reads SQLite, so it is served here rather than by the synchronous `handle_request`.
+        let settings = settings.read().await.clone();
+        restore_estimate(&req, data_root, &settings).await
+    } else if req.method == "stats.topFiles" {
+        let settings = settings.read().await.clone();
+        stats_top_files(&req, data_root, &settings).await
+    } else if req.method == "index.sync" {
+        // Downloads over the endpoint's connection, which only the main loop holds.
+        let settings = settings.read().await.clone();
+        index_sync(&req, &settings, &index_sync_tx).await
+    } else if req.method == "daemon.version" {
+        // Spawns the helper, so it stays off the synchronous path too.
+        daemon_version(&req).await
+    } else {
         let settings = settings.read().await;
-        handle_request(&req, config_root, &settings, &status_state)
+        handle_request(
+            &req,
+            config_root,
+            data_root,
+            &settings,
+            &status_state,
+            &passphrase_attempts,
+        )
     };
-    write_json_line(&mut w, &resp).await?;
+    write_json_line(&mut w, resp).await?;
     Ok(())
 }
 
 fn handle_request(
     req: &ControlRequest,
     config_root: &std::path::Path,
+    data_root: &std::path::Path,
     settings: &Settings,
     status_state: &Arc<Mutex<crate::StatusRuntimeState>>,
+    passphrase_attempts: &Mutex<PassphraseAttempts>,
 ) -> ControlResponse {
     if req.type_ != "control.request" || req.id.trim().is_empty() || req.method.trim().is_empty() {
         return ControlResponse::err(
@@ -349,6 +406,60 @@ fn handle_request(
                 Err(e) => ControlResponse::err(req.id.clone(), e),
             }
         }
+        "secrets.list" => match secrets_list(config_root, settings) {
+            Ok(result) => ControlResponse::ok(
+                req.id.clone(),
+                serde_json::to_value(result).unwrap_or(serde_json::json!({})),
+            ),
+            Err(e) => ControlResponse::err(req.id.clone(), e),
+        },
+        "secrets.delete" => {
+            let params: SecretsDeleteParams = match serde_json::from_value(req.params.clone()) {
+                Ok(p) => p,
+                Err(e) => {
+                    return ControlResponse::err(
+                        req.id.clone(),
+                        ControlError::invalid_request(
+                            "invalid params",
+                            serde_json::json!({ "error": e.to_string() }),
+                        ),
+                    );
+                }
+            };
+            match secrets_delete(config_root, &params) {
+                Ok(result) => ControlResponse::ok(
+                    req.id.clone(),
+                    serde_json::to_value(result).unwrap_or(serde_json::json!({})),
+                ),
+                Err(e) => ControlResponse::err(req.id.clone(), e),
+            }
+        }
+        "security.authorizeRestore" => {
+            let params: SecurityAuthorizeRestoreParams =
+                match serde_json::from_value(req.params.clone()) {
+                    Ok(p) => p,
+                    Err(e) => {
+                        return ControlResponse::err(
+                            req.id.clone(),
+                            ControlError::invalid_request(
+                                "invalid params",
+                                serde_json::json!({ "error": e.to_string() }),
+                            ),
+                        );
+                    }
+                };
+            match security_authorize_restore(
+                settings,
+                passphrase_attempts,
+                params.passphrase.as_deref(),
+            ) {
+                Ok(r) => ControlResponse::ok(
+                    req.id.clone(),
+                    serde_json::to_value(r).unwrap_or(serde_json::json!({})),
+                ),
+                Err(e) => ControlResponse::err(req.id.clone(), e),
+            }
+        }
         "status.taskStart" => {
             let params: StatusTaskStartParams = match serde_json::from_value(req.params.clone()) {
                 Ok(p) => p,
@@ -401,7 +512,10 @@ fn handle_request(
                     net_bytes_uploaded: None,
                     bytes_downloaded: params.progress.bytes_downloaded,
                     net_bytes_downloaded: None,
+                    bytes_written: None,
                     bytes_deduped: params.progress.bytes_deduped,
+                    bytes_total_estimated: None,
+                    bytes_total: params.progress.bytes_total,
                 };
                 st.on_external_progress(&params.target_id, &params.task_id, p);
             }
@@ -426,6 +540,122 @@ fn handle_request(
             }
             ControlResponse::ok(req.id.clone(), serde_json::json!({ "ok": true }))
         }
+        "backup.runNow" => {
+            let params: BackupRunNowParams = match serde_json::from_value(req.params.clone()) {
+                Ok(p) => p,
+                Err(e) => {
+                    return ControlResponse::err(
+                        req.id.clone(),
+                        ControlError::invalid_request(
+                            "invalid params",
+                            serde_json::json!({ "error": e.to_string() }),
+                        ),
+                    );
+                }
+            };
+            match backup_run_now(settings, status_state, data_root, &params) {
+                Ok(r) => ControlResponse::ok(
+                    req.id.clone(),
+                    serde_json::to_value(r).unwrap_or(serde_json::json!({})),
+                ),
+                Err(e) => ControlResponse::err(req.id.clone(), e),
+            }
+        }
+        "queue.list" => {
+            let entries = status_state
+                .lock()
+                .map(|st| st.run_queue.list())
+                .unwrap_or_default();
+            let result = QueueListResult {
+                max_concurrent_runs: settings.schedule.max_concurrent_runs,
+                entries,
+            };
+            ControlResponse::ok(
+                req.id.clone(),
+                serde_json::to_value(result).unwrap_or(serde_json::json!({})),
+            )
+        }
+        "queue.remove" => {
+            let params: QueueRemoveParams = match serde_json::from_value(req.params.clone()) {
+                Ok(p) => p,
+                Err(e) => {
+                    return ControlResponse::err(
+                        req.id.clone(),
+                        ControlError::invalid_request(
+                            "invalid params",
+                            serde_json::json!({ "error": e.to_string() }),
+                        ),
+                    );
+                }
+            };
+            let removed = status_state
+                .lock()
+                .ok()
+                .and_then(|mut st| st.remove_queued_run(&params.task_id));
+            match removed {
+                Some(run) => {
+                    tracing::info!(
+                        event = "queue.removed",
+                        task_id = %run.task_id,
+                        target_id = %run.target_id,
+                        "queue.removed"
+                    );
+                    ControlResponse::ok(
+                        req.id.clone(),
+                        serde_json::json!({ "taskId": run.task_id, "targetId": run.target_id }),
+                    )
+                }
+                None => ControlResponse::err(
+                    req.id.clone(),
+                    ControlError::not_found(
+                        "task is not queued",
+                        serde_json::json!({ "taskId": params.task_id }),
+                    ),
+                ),
+            }
+        }
+        "targets.setEnabled" => {
+            let params: TargetsSetEnabledParams = match serde_json::from_value(req.params.clone()) {
+                Ok(p) => p,
+                Err(e) => {
+                    return ControlResponse::err(
+                        req.id.clone(),
+                        ControlError::invalid_request(
+                            "invalid params",
+                            serde_json::json!({ "error": e.to_string() }),
+                        ),
+                    );
+                }
+            };
+            match targets_set_enabled(config_root, &params) {
+                Ok(r) => ControlResponse::ok(
+                    req.id.clone(),
+                    serde_json::to_value(r).unwrap_or(serde_json::json!({})),
+                ),
+                Err(e) => ControlResponse::err(req.id.clone(), e),
+            }
+        }
+        "verify.acknowledge" => {
+            let params: VerifyAcknowledgeParams = match serde_json::from_value(req.params.clone()) {
+                Ok(p) => p,
+                Err(e) => {
+                    return ControlResponse::err(
+                        req.id.clone(),
+                        ControlError::invalid_request(
+                            "invalid params",
+                            serde_json::json!({ "error": e.to_string() }),
+                        ),
+                    );
+                }
+            };
+            match verify_acknowledge(settings, status_state, &params.target_id) {
+                Ok(r) => ControlResponse::ok(
+                    req.id.clone(),
+                    serde_json::to_value(r).unwrap_or(serde_json::json!({})),
+                ),
+                Err(e) => ControlResponse::err(req.id.clone(), e),
+            }
+        }
         _ => ControlResponse::err(
             req.id.clone(),
             ControlError::method_not_found(
@@ -436,6 +666,322 @@ fn handle_request(
     }
 }
 
+async fn daemon_version(req: &ControlRequest) -> ControlResponse {
+    let probe =
+        tokio::task::spawn_blocking(|| televy_backup_core::probe_mtproto_helper(None)).await;
+    let (helper, helper_error) = match probe {
+        Ok(Ok(helper)) => (Some(helper), None),
+        Ok(Err(e)) => (None, Some(e.to_string())),
+        Err(e) => (None, Some(e.to_string())),
+    };
+    let result = DaemonVersionResult {
+        daemon_version: env!("CARGO_PKG_VERSION").to_string(),
+        settings_version: televy_backup_core::config::SETTINGS_SCHEMA_VERSION,
+        helper,
+        helper_error,
+    };
+    ControlResponse::ok(
+        req.id.clone(),
+        serde_json::to_value(result).unwrap_or(serde_json::json!({})),
+    )
+}
+
+/// Queues the sync for the main loop, which runs it between backups, and waits for its report.
+async fn index_sync(
+    req: &ControlRequest,
+    settings: &Settings,
+    index_sync_tx: &mpsc::UnboundedSender<IndexSyncRequest>,
+) -> ControlResponse {
+    let params: IndexSyncParams = match serde_json::from_value(req.params.clone()) {
+        Ok(p) => p,
+        Err(e) => {
+            return ControlResponse::err(
+                req.id.clone(),
+                ControlError::invalid_request(
+                    "invalid params",
+                    serde_json::json!({ "error": e.to_string() }),
+                ),
+            );
+        }
+    };
+    if !settings.targets.iter().any(|t| t.id == params.target_id) {
+        return ControlResponse::err(
+            req.id.clone(),
+            ControlError::invalid_request(
+                "unknown target",
+                serde_json::json!({ "targetId": params.target_id }),
+            ),
+        );
+    }
+    let (reply, report) = oneshot::channel();
+    if index_sync_tx
+        .send(IndexSyncRequest { params, reply })
+        .is_err()
+    {
+        return ControlResponse::err(
+            req.id.clone(),
+            ControlError::unavailable("daemon is shutting down", serde_json::json!({})),
+        );
+    }
+    match report.await {
+        Ok(Ok(r)) => ControlResponse::ok(
+            req.id.clone(),
+            serde_json::to_value(r).unwrap_or(serde_json::json!({})),
+        ),
+        Ok(Err(e)) => ControlResponse::err(req.id.clone(), e),
+        Err(_) => ControlResponse::err(
+            req.id.clone(),
+            ControlError::unavailable("daemon is shutting down", serde_json::json!({})),
+        ),
+    }
+}
+
+async fn restore_estimate(
+    req: &ControlRequest,
+    data_root: &std::path::Path,
+    settings: &Settings,
+) -> ControlResponse {
+    let params: RestoreEstimateParams = match serde_json::from_value(req.params.clone()) {
+        Ok(p) => p,
+        Err(e) => {
+            return ControlResponse::err(
+                req.id.clone(),
+                ControlError::invalid_request(
+                    "invalid params",
+                    serde_json::json!({ "error": e.to_string() }),
+                ),
+            );
+        }
+    };
+    match estimate_restore_from_local_index(data_root, settings, &params).await {
+        Ok(r) => ControlResponse::ok(
+            req.id.clone(),
+            serde_json::to_value(r).unwrap_or(serde_json::json!({})),
+        ),
+        Err(e) => ControlResponse::err(req.id.clone(), e),
+    }
+}
+
+/// Estimates from the first endpoint holding a local file map of the snapshot.
+async fn estimate_restore_from_local_index(
+    data_root: &std::path::Path,
+    settings: &Settings,
+    params: &RestoreEstimateParams,
+) -> Result<televy_backup_core::RestoreEstimate, ControlError> {
+    let index_dir = data_root.join("index");
+    for ep in &settings.telegram_endpoints {
+        if params.endpoint_id.as_deref().is_some_and(|id| id != ep.id) {
+            continue;
+        }
+        let filemap_db_path = index_dir
+            .join("filemaps")
+            .join(&ep.id)
+            .join(format!("{}.sqlite", params.snapshot_id));
+        if !filemap_db_path.exists() {
+            continue;
+        }
+        let db_path = index_dir.join(format!("index.{}.sqlite", ep.id));
+        return televy_backup_core::estimate_restore(
+            &filemap_db_path,
+            &db_path,
+            &params.snapshot_id,
+            &televy_backup_core::config::endpoint_provider(&ep.id),
+            params.path.as_deref(),
+        )
+        .await
+        .map_err(|e| ControlError::new(e.error_code(), e.to_string(), false, e.details()));
+    }
+    Err(ControlError::new(
+        ErrorCode::SnapshotNotFound,
+        format!(
+            "no local file map for snapshot {} (`televybackup restore estimate` downloads it)",
+            params.snapshot_id
+        ),
+        false,
+        serde_json::json!({ "snapshotId": params.snapshot_id }),
+    ))
+}
+
+async fn stats_top_files(
+    req: &ControlRequest,
+    data_root: &std::path::Path,
+    settings: &Settings,
+) -> ControlResponse {
+    let params: StatsTopFilesParams = match serde_json::from_value(req.params.clone()) {
+        Ok(p) => p,
+        Err(e) => {
+            return ControlResponse::err(
+                req.id.clone(),
+                ControlError::invalid_request(
+                    "invalid params",
+                    serde_json::json!({ "error": e.to_string() }),
+                ),
+            );
+        }
+    };
+    let index_dir = data_root.join("index");
+    for ep in &settings.telegram_endpoints {
+        if params.endpoint_id.as_deref().is_some_and(|id| id != ep.id) {
+            continue;
+        }
+        let filemap_dir = index_dir.join("filemaps").join(&ep.id);
+        if !filemap_dir
+            .join(format!("{}.sqlite", params.snapshot_id))
+            .exists()
+        {
+            continue;
+        }
+        let db_path = index_dir.join(format!("index.{}.sqlite", ep.id));
+        return match televy_backup_core::dedup_stats::top_files(
+            &db_path,
+            &filemap_dir,
+            &params.snapshot_id,
+            params.limit.unwrap_or(50),
+        )
+        .await
+        {
+            Ok(r) => ControlResponse::ok(
+                req.id.clone(),
+                serde_json::to_value(r).unwrap_or(serde_json::json!({})),
+            ),
+            Err(e) => ControlResponse::err(
+                req.id.clone(),
+                ControlError::new(e.error_code(), e.to_string(), false, e.details()),
+            ),
+        };
+    }
+    ControlResponse::err(
+        req.id.clone(),
+        ControlError::new(
+            ErrorCode::SnapshotNotFound,
+            format!(
+                "no local file map for snapshot {} (`televybackup index sync` downloads it)",
+                params.snapshot_id
+            ),
+            false,
+            serde_json::json!({ "snapshotId": params.snapshot_id }),
+        ),
+    )
+}
+
+/// Queues a backup of `target_id`; a target already waiting keeps its place.
+fn backup_run_now(
+    settings: &Settings,
+    status_state: &Mutex<crate::StatusRuntimeState>,
+    data_root: &std::path::Path,
+    params: &BackupRunNowParams,
+) -> Result<BackupRunNowResult, ControlError> {
+    let target_id = params.target_id.as_str();
+    if !settings.targets.iter().any(|t| t.id == target_id) {
+        return Err(ControlError::invalid_request(
+            "unknown target",
+            serde_json::json!({ "targetId": target_id }),
+        ));
+    }
+    if params.accept_source_change {
+        televy_backup_core::source_guard::accept_next_change(data_root, target_id)
+            .map_err(|e| ControlError::new(e.error_code(), e.to_string(), false, e.details()))?;
+    }
+    let mut st = status_state.lock().map_err(|_| {
+        ControlError::unavailable("status state unavailable", serde_json::json!({}))
+    })?;
+    let (queued, position, added) = st.run_queue.enqueue(target_id, RunTrigger::Ipc, None);
+    if added {
+        tracing::info!(
+            event = "queue.enqueued",
+            task_id = %queued.task_id,
+            target_id,
+            trigger = RunTrigger::Ipc.as_str(),
+            position,
+            "queue.enqueued"
+        );
+    }
+    Ok(BackupRunNowResult {
+        task_id: queued.task_id.clone(),
+        position: u32::try_from(position).unwrap_or(u32::MAX),
+        already_queued: !added,
+    })
+}
+
+/// Clears the warning a failed scheduled verify left on `target_id`.
+fn verify_acknowledge(
+    settings: &Settings,
+    status_state: &Mutex<crate::StatusRuntimeState>,
+    target_id: &str,
+) -> Result<VerifyAcknowledgeResult, ControlError> {
+    if !settings.targets.iter().any(|t| t.id == target_id) {
+        return Err(ControlError::invalid_request(
+            "unknown target",
+            serde_json::json!({ "targetId": target_id }),
+        ));
+    }
+    let mut st = status_state.lock().map_err(|_| {
+        ControlError::unavailable("status state unavailable", serde_json::json!({}))
+    })?;
+    let cleared = st.verify.acknowledge(target_id);
+    if cleared {
+        tracing::info!(
+            event = "verify.acknowledged",
+            target_id,
+            "verify.acknowledged"
+        );
+    }
+    Ok(VerifyAcknowledgeResult {
+        target_id: target_id.to_string(),
+        cleared,
+    })
+}
+
+/// Saves `targets[].enabled`/`disabled_until` to the settings file; the daemon loop picks the
+/// change up on its next config reload.
+fn targets_set_enabled(
+    config_root: &std::path::Path,
+    params: &TargetsSetEnabledParams,
+) -> Result<TargetsSetEnabledResult, ControlError> {
+    let config_error = |e: televy_backup_core::Error| {
+        ControlError::new(
+            ErrorCode::ConfigInvalid,
+            e.to_string(),
+            false,
+            serde_json::json!({ "targetId": params.target_id }),
+        )
+    };
+    if params.enabled && params.disabled_until.is_some() {
+        return Err(ControlError::invalid_request(
+            "disabledUntil requires enabled=false",
+            serde_json::json!({ "targetId": params.target_id }),
+        ));
+    }
+    let mut settings =
+        televy_backup_core::config::load_settings_v2(config_root).map_err(config_error)?;
+    let target = settings
+        .targets
+        .iter_mut()
+        .find(|t| t.id == params.target_id)
+        .ok_or_else(|| {
+            ControlError::invalid_request(
+                "unknown target",
+                serde_json::json!({ "targetId": params.target_id }),
+            )
+        })?;
+    target.enabled = params.enabled;
+    target.disabled_until = params.disabled_until.clone();
+    let result = TargetsSetEnabledResult {
+        target_id: target.id.clone(),
+        enabled: target.enabled,
+        disabled_until: target.disabled_until.clone(),
+    };
+    televy_backup_core::config::save_settings_v2(config_root, &settings).map_err(config_error)?;
+    tracing::info!(
+        event = "target.enabled_changed",
+        target_id = %result.target_id,
+        enabled = result.enabled,
+        disabled_until = result.disabled_until.as_deref().unwrap_or("-"),
+        "target.enabled_changed"
+    );
+    Ok(result)
+}
+
 fn vault_status(config_root: &std::path::Path) -> Result<VaultStatusResult, ControlError> {
     let keychain_disabled = crate::keychain_disabled();
     let key_file_path = std::env::var("TELEVYBACKUP_VAULT_KEY_FILE")
@@ -466,12 +1012,12 @@ fn vault_status(config_root: &std::path::Path) -> Result<VaultStatusResult, Cont
             Err(televy_backup_core::secrets::SecretsStoreError::Io(e))
                 if e.kind() == std::io::ErrorKind::NotFound => {}
             Err(e) => {
-                return Err(ControlError {
-                    code: "secrets.vault_key_file_io_failed".to_string(),
-                    message: e.to_string(),
-                    retryable: false,
-                    details: serde_json::json!({ "path": key_file_path.display().to_string() }),
-                });
+                return Err(ControlError::new(
+                    ErrorCode::SecretsVaultKeyFileIoFailed,
+                    e.to_string(),
+                    false,
+                    serde_json::json!({ "path": key_file_path.display().to_string() }),
+                ));
             }
         }
 
@@ -511,12 +1057,12 @@ fn vault_ensure(config_root: &std::path::Path) -> Result<VaultStatusResult, Cont
                 crate::set_cached_vault_key(key);
             }
             Err(e) => {
-                return Err(ControlError {
-                    code: "secrets.vault_unavailable".to_string(),
-                    message: e.to_string(),
-                    retryable: false,
-                    details: serde_json::json!({}),
-                });
+                return Err(ControlError::new(
+                    ErrorCode::SecretsVaultUnavailable,
+                    e.to_string(),
+                    false,
+                    serde_json::json!({}),
+                ));
             }
         }
     }
@@ -537,41 +1083,41 @@ fn secrets_presence(
         ));
     }
 
-    let vault_key = crate::load_or_create_vault_key().map_err(|e| ControlError {
-        code: "secrets.vault_unavailable".to_string(),
-        message: e.to_string(),
-        retryable: false,
-        details: serde_json::json!({}),
-    })?;
-
-    let secrets_path = televy_backup_core::secrets::secrets_path(config_root);
-    let store = televy_backup_core::secrets::load_secrets_store(&secrets_path, &vault_key)
-        .map_err(|e| ControlError {
-            code: "secrets.store_failed".to_string(),
-            message: e.to_string(),
-            retryable: false,
-            details: serde_json::json!({ "path": secrets_path.display().to_string() }),
-        })?;
+    let (store, secrets_path, _) = load_secrets_store(config_root)?;
+    let provider = SecretsProvider::new(config_root);
+    let present = |key: &str| -> Result<bool, ControlError> {
+        provider
+            .get(key, Some(&store))
+            .map(|v| v.is_some())
+            .map_err(|e| secrets_error(e, &secrets_path))
+    };
 
-    let master_present = store.contains_key(crate::MASTER_KEY_KEY);
+    let master_present = present(crate::MASTER_KEY_KEY)?;
 
-    let api_hash_present = store.contains_key(&settings.telegram.mtproto.api_hash_key);
+    let api_hash_present = present(&settings.telegram.mtproto.api_hash_key)?;
 
     let mut bot_present_by_endpoint = serde_json::Map::<String, serde_json::Value>::new();
     let mut mtproto_session_present_by_endpoint =
         serde_json::Map::<String, serde_json::Value>::new();
+    // Only endpoints overriding `telegram.mtproto.api_hash_key`.
+    let mut api_hash_present_by_endpoint = serde_json::Map::<String, serde_json::Value>::new();
 
     for ep in &settings.telegram_endpoints {
         if endpoint_id.is_some_and(|id| id != ep.id) {
             continue;
         }
 
-        let bot_present = store.contains_key(&ep.bot_token_key);
+        let bot_present = present(&ep.bot_token_key)?;
         bot_present_by_endpoint.insert(ep.id.clone(), serde_json::Value::Bool(bot_present));
 
-        let sess_present = store.contains_key(&ep.mtproto.session_key);
+        let sess_present = present(&ep.mtproto.session_key)?;
         mtproto_session_present_by_endpoint
             .insert(ep.id.clone(), serde_json::Value::Bool(sess_present));
+
+        if let Some(key) = ep.mtproto.api_hash_key.as_deref() {
+            api_hash_present_by_endpoint
+                .insert(ep.id.clone(), serde_json::Value::Bool(present(key)?));
+        }
     }
 
     Ok(serde_json::json!({
@@ -579,9 +1125,46 @@ fn secrets_presence(
         "telegramMtprotoApiHashPresent": api_hash_present,
         "telegramBotTokenPresentByEndpoint": bot_present_by_endpoint,
         "telegramMtprotoSessionPresentByEndpoint": mtproto_session_present_by_endpoint,
+        "telegramMtprotoApiHashPresentByEndpoint": api_hash_present_by_endpoint,
     }))
 }
 
+fn secrets_error(e: SecretsStoreError, path: &std::path::Path) -> ControlError {
+    ControlError::new(
+        e.error_code(),
+        e.to_string(),
+        false,
+        serde_json::json!({ "path": path.display().to_string() }),
+    )
+}
+
+/// Decrypts `secrets.enc`; also returns its path and the vault key for saving it back.
+fn load_secrets_store(
+    config_root: &std::path::Path,
+) -> Result<(televy_backup_core::secrets::SecretsStore, PathBuf, [u8; 32]), ControlError> {
+    let vault_key = crate::load_or_create_vault_key().map_err(|e| {
+        ControlError::new(
+            ErrorCode::SecretsVaultUnavailable,
+            e.to_string(),
+            false,
+            serde_json::json!({}),
+        )
+    })?;
+    let secrets_path = televy_backup_core::secrets::secrets_path(config_root);
+    let store = televy_backup_core::secrets::load_secrets_store(&secrets_path, &vault_key)
+        .map_err(|e| secrets_error(e, &secrets_path))?;
+    Ok((store, secrets_path, vault_key))
+}
+
+fn write_secret(config_root: &std::path::Path, key: &str, value: &str) -> Result<(), ControlError> {
+    let provider = SecretsProvider::new(config_root);
+    let (mut store, secrets_path, vault_key) = load_secrets_store(config_root)?;
+    provider
+        .write(key, value, &mut store, &secrets_path, &vault_key)
+        .map_err(|e| secrets_error(e, &secrets_path))?;
+    Ok(())
+}
+
 fn secrets_set_telegram_bot_token(
     config_root: &std::path::Path,
     settings: &Settings,
@@ -606,30 +1189,12 @@ fn secrets_set_telegram_bot_token(
             )
         })?;
 
-    let vault_key = crate::load_or_create_vault_key().map_err(|e| ControlError {
-        code: "secrets.vault_unavailable".to_string(),
-        message: e.to_string(),
-        retryable: false,
-        details: serde_json::json!({}),
-    })?;
-    let secrets_path = televy_backup_core::secrets::secrets_path(config_root);
-    let mut store = televy_backup_core::secrets::load_secrets_store(&secrets_path, &vault_key)
-        .map_err(|e| ControlError {
-            code: "secrets.store_failed".to_string(),
-            message: e.to_string(),
-            retryable: false,
-            details: serde_json::json!({ "path": secrets_path.display().to_string() }),
-        })?;
-
-    store.set(&ep.bot_token_key, token.trim());
-    televy_backup_core::secrets::save_secrets_store(&secrets_path, &vault_key, &store).map_err(
-        |e| ControlError {
-            code: "secrets.store_failed".to_string(),
-            message: e.to_string(),
-            retryable: false,
-            details: serde_json::json!({ "path": secrets_path.display().to_string() }),
-        },
-    )?;
+    write_secret(config_root, &ep.bot_token_key, token.trim())?;
+    crate::record_audit(
+        AuditActor::Gui,
+        audit::AUDIT_OP_SECRET_SET,
+        serde_json::json!({ "key": ep.bot_token_key }),
+    );
     Ok(())
 }
 
@@ -645,30 +1210,16 @@ fn secrets_set_telegram_api_hash(
         ));
     }
 
-    let vault_key = crate::load_or_create_vault_key().map_err(|e| ControlError {
-        code: "secrets.vault_unavailable".to_string(),
-        message: e.to_string(),
-        retryable: false,
-        details: serde_json::json!({}),
-    })?;
-    let secrets_path = televy_backup_core::secrets::secrets_path(config_root);
-    let mut store = televy_backup_core::secrets::load_secrets_store(&secrets_path, &vault_key)
-        .map_err(|e| ControlError {
-            code: "secrets.store_failed".to_string(),
-            message: e.to_string(),
-            retryable: false,
-            details: serde_json::json!({ "path": secrets_path.display().to_string() }),
-        })?;
-
-    store.set(&settings.telegram.mtproto.api_hash_key, api_hash.trim());
-    televy_backup_core::secrets::save_secrets_store(&secrets_path, &vault_key, &store).map_err(
-        |e| ControlError {
-            code: "secrets.store_failed".to_string(),
-            message: e.to_string(),
-            retryable: false,
-            details: serde_json::json!({ "path": secrets_path.display().to_string() }),
-        },
+    write_secret(
+        config_root,
+        &settings.telegram.mtproto.api_hash_key,
+        api_hash.trim(),
     )?;
+    crate::record_audit(
+        AuditActor::Gui,
+        audit::AUDIT_OP_SECRET_SET,
+        serde_json::json!({ "key": settings.telegram.mtproto.api_hash_key }),
+    );
     Ok(())
 }
 
@@ -688,39 +1239,239 @@ fn secrets_clear_telegram_mtproto_session(
             )
         })?;
 
-    let vault_key = crate::load_or_create_vault_key().map_err(|e| ControlError {
-        code: "secrets.vault_unavailable".to_string(),
-        message: e.to_string(),
-        retryable: false,
-        details: serde_json::json!({}),
-    })?;
-    let secrets_path = televy_backup_core::secrets::secrets_path(config_root);
-    let mut store = televy_backup_core::secrets::load_secrets_store(&secrets_path, &vault_key)
-        .map_err(|e| ControlError {
-            code: "secrets.store_failed".to_string(),
-            message: e.to_string(),
-            retryable: false,
-            details: serde_json::json!({ "path": secrets_path.display().to_string() }),
-        })?;
-
-    let removed = store.remove(&ep.mtproto.session_key);
-    if removed {
+    let key = ep.mtproto.session_key.as_str();
+    let provider = SecretsProvider::new(config_root);
+    let secrets_dir = televy_backup_core::secrets::secrets_dir_path(config_root);
+    // An env-provided session would come straight back; refuse rather than pretend to clear it.
+    provider
+        .write_target(key)
+        .map_err(|e| secrets_error(e, &secrets_dir))?;
+    let mut removed = provider
+        .remove_file(key)
+        .map_err(|e| secrets_error(e, &secrets_dir))?;
+
+    let (mut store, secrets_path, vault_key) = load_secrets_store(config_root)?;
+    if store.remove(key) {
         televy_backup_core::secrets::save_secrets_store(&secrets_path, &vault_key, &store)
-            .map_err(|e| ControlError {
-                code: "secrets.store_failed".to_string(),
-                message: e.to_string(),
-                retryable: false,
-                details: serde_json::json!({ "path": secrets_path.display().to_string() }),
-            })?;
+            .map_err(|e| secrets_error(e, &secrets_path))?;
+        removed = true;
+    }
+    if removed {
+        crate::record_audit(
+            AuditActor::Gui,
+            audit::AUDIT_OP_SECRET_DELETE,
+            serde_json::json!({ "key": key }),
+        );
     }
     Ok(())
 }
 
+fn secrets_list(
+    config_root: &std::path::Path,
+    settings: &Settings,
+) -> Result<SecretsListResult, ControlError> {
+    let (store, secrets_path, _) = load_secrets_store(config_root)?;
+    let provider = SecretsProvider::new(config_root);
+    let keys = televy_backup_core::secrets::list_secret_keys(&provider, &store, settings)
+        .map_err(|e| secrets_error(e, &secrets_path))?;
+    Ok(SecretsListResult { keys })
+}
+
+fn secrets_delete(
+    config_root: &std::path::Path,
+    params: &SecretsDeleteParams,
+) -> Result<SecretsDeleteResult, ControlError> {
+    if !params.i_understand_data_loss && params.keys.iter().any(|k| k == crate::MASTER_KEY_KEY) {
+        return Err(ControlError::invalid_request(
+            "deleting the master key makes every backup unreadable; pass iUnderstandDataLoss",
+            serde_json::json!({ "key": crate::MASTER_KEY_KEY }),
+        ));
+    }
+
+    let (mut store, secrets_path, vault_key) = load_secrets_store(config_root)?;
+    let provider = SecretsProvider::new(config_root);
+    let deleted =
+        televy_backup_core::secrets::delete_secret_keys(&provider, &mut store, &params.keys)
+            .map_err(|e| secrets_error(e, &secrets_path))?;
+    if !deleted.is_empty() {
+        televy_backup_core::secrets::save_secrets_store(&secrets_path, &vault_key, &store)
+            .map_err(|e| secrets_error(e, &secrets_path))?;
+    }
+    for key in &deleted {
+        crate::record_audit(
+            AuditActor::Gui,
+            audit::AUDIT_OP_SECRET_DELETE,
+            serde_json::json!({ "key": key }),
+        );
+    }
+    Ok(SecretsDeleteResult { deleted })
+}
+
+fn security_authorize_restore(
+    settings: &Settings,
+    attempts: &Mutex<PassphraseAttempts>,
+    passphrase: Option<&str>,
+) -> Result<SecurityAuthorizeRestoreResult, ControlError> {
+    if !settings.security.restore_requires_passphrase {
+        return Ok(SecurityAuthorizeRestoreResult {
+            passphrase_required: false,
+        });
+    }
+
+    let Some(hash) = settings.security.restore_passphrase_hash.as_deref() else {
+        return Err(ControlError::new(
+            ErrorCode::SecurityPassphraseNotSet,
+            "restore passphrase is required but not set (televybackup security set-restore-passphrase)".to_string(),
+            false,
+            serde_json::json!({}),
+        ));
+    };
+    let Some(passphrase) = passphrase.filter(|p| !p.is_empty()) else {
+        return Err(ControlError::new(
+            ErrorCode::SecurityPassphraseRequired,
+            "restore requires a passphrase".to_string(),
+            false,
+            serde_json::json!({}),
+        ));
+    };
+
+    let mut attempts = attempts
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let now = std::time::Instant::now();
+    if let Some(wait) = attempts.retry_after(now) {
+        return Err(passphrase_invalid_error(
+            "too many failed passphrase attempts",
+            attempts.failures(),
+            Some(wait),
+        ));
+    }
+
+    match security::verify_restore_passphrase(hash, passphrase) {
+        Ok(true) => {
+            attempts.record_success();
+            Ok(SecurityAuthorizeRestoreResult {
+                passphrase_required: true,
+            })
+        }
+        Ok(false) => {
+            let wait = attempts.record_failure(now);
+            tracing::warn!(
+                event = "security.passphrase_invalid",
+                failures = attempts.failures(),
+                retry_after_ms = wait.map(|d| d.as_millis() as u64),
+                "security.passphrase_invalid"
+            );
+            Err(passphrase_invalid_error(
+                "invalid restore passphrase",
+                attempts.failures(),
+                wait,
+            ))
+        }
+        Err(e) => Err(ControlError::from(&e)),
+    }
+}
+
+fn passphrase_invalid_error(
+    message: &str,
+    failures: u32,
+    retry_after: Option<std::time::Duration>,
+) -> ControlError {
+    ControlError::new(
+        ErrorCode::SecurityPassphraseInvalid,
+        message.to_string(),
+        retry_after.is_some(),
+        serde_json::json!({
+            "failures": failures,
+            "retryAfterMs": retry_after.map(|d| d.as_millis() as u64),
+        }),
+    )
+}
+
+/// Writes `v` stamped with this daemon's version, so clients can detect a stale daemon.
+/// Serves `logs.stream`. The lines come from the run's in-memory buffer, so a slow or vanished
+/// client never holds up the run.
+async fn logs_stream(
+    req: &ControlRequest,
+    r: &mut BufReader<tokio::net::unix::OwnedReadHalf>,
+    w: &mut BufWriter<tokio::net::unix::OwnedWriteHalf>,
+    shutdown: &mut broadcast::Receiver<()>,
+) -> std::io::Result<()> {
+    let params: LogsStreamParams = match serde_json::from_value(req.params.clone()) {
+        Ok(p) => p,
+        Err(e) => {
+            let err = ControlError::invalid_request(
+                "invalid params",
+                serde_json::json!({ "error": e.to_string() }),
+            );
+            return write_json_line(w, ControlResponse::err(req.id.clone(), err)).await;
+        }
+    };
+    if params.task_id.is_none() && params.target_id.is_none() {
+        let err =
+            ControlError::invalid_request("taskId or targetId is required", serde_json::json!({}));
+        return write_json_line(w, ControlResponse::err(req.id.clone(), err)).await;
+    }
+
+    let Some(mut sub) = televy_backup_core::run_log::subscribe_live_run_log(
+        params.task_id.as_deref(),
+        params.target_id.as_deref(),
+    ) else {
+        let err = ControlError::not_found(
+            "no running task matches",
+            serde_json::json!({ "taskId": params.task_id, "targetId": params.target_id }),
+        );
+        return write_json_line(w, ControlResponse::err(req.id.clone(), err)).await;
+    };
+
+    let result = LogsStreamResult {
+        task_id: sub.run_id.clone(),
+        target_id: sub.target_id.clone(),
+    };
+    write_json_line(
+        w,
+        ControlResponse::ok(
+            req.id.clone(),
+            serde_json::to_value(result).unwrap_or(serde_json::json!({})),
+        ),
+    )
+    .await?;
+    for line in &sub.backlog {
+        w.write_all(line.as_bytes()).await?;
+        w.write_all(b"\n").await?;
+    }
+    w.flush().await?;
+
+    let mut chunk = [0u8; 256];
+    loop {
+        let line = tokio::select! {
+            recv = sub.lines.recv() => match recv {
+                Ok(line) => line,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    serde_json::to_string(&LogsStreamLagged::new(skipped))
+                        .map_err(|e| std::io::Error::other(e.to_string()))?
+                }
+                // The run finished.
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            },
+            res = r.read(&mut chunk) => match res {
+                Ok(0) | Err(_) => return Ok(()),
+                Ok(_) => continue,
+            },
+            _ = shutdown.recv() => return Ok(()),
+        };
+        w.write_all(line.as_bytes()).await?;
+        w.write_all(b"\n").await?;
+        w.flush().await?;
+    }
+}
+
 async fn write_json_line(
     w: &mut BufWriter<tokio::net::unix::OwnedWriteHalf>,
-    v: &ControlResponse,
+    mut v: ControlResponse,
 ) -> std::io::Result<()> {
-    let line = serde_json::to_string(v).map_err(|e| std::io::Error::other(e.to_string()))?;
+    v.versions = DaemonVersionStamp::new(env!("CARGO_PKG_VERSION"));
+    let line = serde_json::to_string(&v).map_err(|e| std::io::Error::other(e.to_string()))?;
     w.write_all(line.as_bytes()).await?;
     w.write_all(b"\n").await?;
     w.flush().await?;
@@ -729,6 +1480,7 @@ async fn write_json_line(
 
 #[cfg(test)]
 mod tests {
+    use televy_backup_core::index_sync::IndexSyncOutcome;
     use tokio::io::AsyncBufReadExt;
 
     use super::*;
@@ -740,9 +1492,12 @@ mod tests {
                 id: "ep1".to_string(),
                 mode: "mtproto".to_string(),
                 chat_id: "-100".to_string(),
+                migrated_from_chat_ids: Vec::new(),
                 bot_token_key: "telegram.bot_token.ep1".to_string(),
                 mtproto: televy_backup_core::config::TelegramEndpointMtproto::default(),
                 rate_limit: televy_backup_core::config::TelegramRateLimit::default(),
+                bootstrap: televy_backup_core::config::TelegramEndpointBootstrap::default(),
+                object_prefix: None,
             });
         s
     }
@@ -760,8 +1515,10 @@ mod tests {
         let _server = spawn_control_ipc_server(
             socket_path.clone(),
             cfg_root.clone(),
+            dir.path().join("data"),
             Arc::new(RwLock::new(settings())),
             status_state,
+            mpsc::unbounded_channel().0,
         )
         .unwrap();
 
@@ -781,5 +1538,420 @@ mod tests {
             resp.error.as_ref().unwrap().code,
             "control.method_not_found"
         );
+        assert_eq!(
+            resp.versions.daemon_version.as_deref(),
+            Some(env!("CARGO_PKG_VERSION"))
+        );
+        assert_eq!(
+            resp.versions.settings_version,
+            Some(televy_backup_core::config::SETTINGS_SCHEMA_VERSION)
+        );
+    }
+
+    #[test]
+    fn run_now_queues_targets_and_queue_methods_list_and_remove_them() {
+        let data = tempfile::tempdir().unwrap();
+        let mut s = settings();
+        for id in ["t1", "t2"] {
+            s.targets.push(televy_backup_core::config::Target {
+                id: id.to_string(),
+                source_path: format!("/tmp/{id}"),
+                label: String::new(),
+                label_template: None,
+                endpoint_id: "ep1".to_string(),
+                enabled: true,
+                disabled_until: None,
+                priority: 0,
+                schedule: None,
+                scan: None,
+                target_key: false,
+                allow_overlap: false,
+                min_expected_files: 0,
+                filters: Vec::new(),
+                sqlite_consistent: Vec::new(),
+            });
+        }
+        let status_state = Arc::new(Mutex::new(crate::StatusRuntimeState::from_settings(&s)));
+        let attempts = Mutex::new(PassphraseAttempts::new());
+        let call = |method: &str, params: serde_json::Value| {
+            handle_request(
+                &ControlRequest::new("1", method, params),
+                std::path::Path::new("/nonexistent"),
+                data.path(),
+                &s,
+                &status_state,
+                &attempts,
+            )
+        };
+
+        let first = call("backup.runNow", serde_json::json!({ "targetId": "t1" }));
+        let first_task = first.result.as_ref().unwrap()["taskId"].clone();
+        assert_eq!(first.result.as_ref().unwrap()["position"], 1);
+        let second = call("backup.runNow", serde_json::json!({ "targetId": "t2" }));
+        assert_eq!(second.result.as_ref().unwrap()["position"], 2);
+        let dup = call("backup.runNow", serde_json::json!({ "targetId": "t1" }));
+        assert_eq!(dup.result.as_ref().unwrap()["taskId"], first_task);
+        assert_eq!(dup.result.as_ref().unwrap()["alreadyQueued"], true);
+        let unknown = call("backup.runNow", serde_json::json!({ "targetId": "nope" }));
+        assert_eq!(unknown.error.unwrap().code, "control.invalid_request");
+        let accepted = call(
+            "backup.runNow",
+            serde_json::json!({ "targetId": "t1", "acceptSourceChange": true }),
+        );
+        assert_eq!(accepted.result.unwrap()["taskId"], first_task);
+        assert!(
+            televy_backup_core::source_guard::load_target_state(data.path(), "t1")
+                .unwrap()
+                .accept_change
+        );
+
+        let list = call("queue.list", serde_json::json!({})).result.unwrap();
+        assert_eq!(list["maxConcurrentRuns"], 1);
+        assert_eq!(list["entries"].as_array().unwrap().len(), 2);
+        assert_eq!(list["entries"][1]["targetId"], "t2");
+        assert_eq!(list["entries"][1]["trigger"], "ipc");
+
+        let snap = status_state.lock().unwrap().build_snapshot(0);
+        assert_eq!(snap.targets[1].state, "queued");
+        assert_eq!(snap.targets[1].extra["queuePosition"], 2);
+
+        let removed = call("queue.remove", serde_json::json!({ "taskId": first_task }));
+        assert!(removed.ok);
+        let again = call("queue.remove", serde_json::json!({ "taskId": first_task }));
+        assert_eq!(again.error.unwrap().code, "control.not_found");
+
+        let snap = status_state.lock().unwrap().build_snapshot(0);
+        assert_eq!(snap.targets[0].state, "idle");
+        assert!(!snap.targets[0].extra.contains_key("queuePosition"));
+        assert_eq!(snap.targets[1].extra["queuePosition"], 1);
+    }
+
+    #[test]
+    fn verify_acknowledge_clears_a_failed_verify_warning() {
+        let mut s = settings();
+        s.targets.push(televy_backup_core::config::Target {
+            id: "t1".to_string(),
+            source_path: "/tmp/t1".to_string(),
+            label: String::new(),
+            label_template: None,
+            endpoint_id: "ep1".to_string(),
+            enabled: true,
+            disabled_until: None,
+            priority: 0,
+            schedule: None,
+            scan: None,
+            target_key: false,
+            allow_overlap: false,
+            min_expected_files: 0,
+            filters: Vec::new(),
+            sqlite_consistent: Vec::new(),
+        });
+        let status_state = Arc::new(Mutex::new(crate::StatusRuntimeState::from_settings(&s)));
+        status_state.lock().unwrap().verify.record(
+            "t1",
+            televy_backup_core::status::VerifySummary {
+                finished_at: "2024-06-01T00:00:00.000Z".to_string(),
+                status: "failed".to_string(),
+                snapshot_id: None,
+                chunks_checked: None,
+                bytes_checked: None,
+                coverage_percent: None,
+                error_code: Some("integrity".to_string()),
+                error_message: Some("chunk hash mismatch".to_string()),
+                warning: true,
+            },
+        );
+        let attempts = Mutex::new(PassphraseAttempts::new());
+        let call = |params: serde_json::Value| {
+            handle_request(
+                &ControlRequest::new("1", "verify.acknowledge", params),
+                std::path::Path::new("/nonexistent"),
+                std::path::Path::new("/nonexistent"),
+                &s,
+                &status_state,
+                &attempts,
+            )
+        };
+
+        let snap = status_state.lock().unwrap().build_snapshot(0);
+        let last = snap.targets[0].last_verify().unwrap();
+        assert!(last.warning);
+        assert_eq!(last.status, "failed");
+
+        let first = call(serde_json::json!({ "targetId": "t1" }));
+        assert_eq!(first.result.unwrap()["cleared"], true);
+        let again = call(serde_json::json!({ "targetId": "t1" }));
+        assert_eq!(again.result.unwrap()["cleared"], false);
+        let unknown = call(serde_json::json!({ "targetId": "nope" }));
+        assert_eq!(unknown.error.unwrap().code, "control.invalid_request");
+
+        let snap = status_state.lock().unwrap().build_snapshot(0);
+        let last = snap.targets[0].last_verify().unwrap();
+        assert!(!last.warning);
+        assert_eq!(last.error_code.as_deref(), Some("integrity"));
+    }
+
+    #[tokio::test]
+    async fn index_sync_is_answered_by_the_main_loop() {
+        let mut s = settings();
+        s.targets.push(televy_backup_core::config::Target {
+            id: "t1".to_string(),
+            source_path: "/tmp/src".to_string(),
+            label: String::new(),
+            label_template: None,
+            endpoint_id: "ep1".to_string(),
+            enabled: true,
+            disabled_until: None,
+            priority: 0,
+            schedule: None,
+            scan: None,
+            target_key: false,
+            allow_overlap: false,
+            min_expected_files: 0,
+            filters: Vec::new(),
+            sqlite_consistent: Vec::new(),
+        });
+        let (tx, mut rx) = mpsc::unbounded_channel::<IndexSyncRequest>();
+        tokio::spawn(async move {
+            while let Some(req) = rx.recv().await {
+                let _ = req.reply.send(Ok(IndexSyncReport {
+                    target_id: req.params.target_id,
+                    snapshot_id: req.params.snapshot_id,
+                    endpoint_db: IndexSyncOutcome::Kept,
+                    filemap: if req.params.force {
+                        IndexSyncOutcome::Replaced
+                    } else {
+                        IndexSyncOutcome::Kept
+                    },
+                    bytes_downloaded: 0,
+                }));
+            }
+        });
+
+        let req = ControlRequest::new(
+            "1",
+            "index.sync",
+            serde_json::json!({ "targetId": "t1", "snapshotId": "snp_1", "force": true }),
+        );
+        let resp = index_sync(&req, &s, &tx).await;
+        assert!(resp.ok, "{resp:?}");
+        let result = resp.result.unwrap();
+        assert_eq!(result["snapshotId"], "snp_1");
+        assert_eq!(result["endpointDb"], "kept");
+        assert_eq!(result["filemap"], "replaced");
+
+        let req = ControlRequest::new("2", "index.sync", serde_json::json!({ "targetId": "t9" }));
+        let resp = index_sync(&req, &s, &tx).await;
+        assert_eq!(resp.error.unwrap().code, "control.invalid_request");
+    }
+
+    #[tokio::test]
+    async fn logs_stream_follows_the_running_task_for_every_subscriber() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("ipc").join("control.sock");
+        let status_state = Arc::new(Mutex::new(crate::StatusRuntimeState::from_settings(
+            &settings(),
+        )));
+        let _server = spawn_control_ipc_server(
+            socket_path.clone(),
+            dir.path().join("cfg"),
+            dir.path().join("data"),
+            Arc::new(RwLock::new(settings())),
+            status_state,
+            mpsc::unbounded_channel().0,
+        )
+        .unwrap();
+
+        let subscribe = |target_id: &str| {
+            let socket_path = socket_path.clone();
+            let req = ControlRequest::new(
+                "1",
+                "logs.stream",
+                serde_json::json!({ "targetId": target_id }),
+            );
+            async move {
+                let stream = UnixStream::connect(&socket_path).await.unwrap();
+                let (r, mut w) = stream.into_split();
+                let line = serde_json::to_string(&req).unwrap() + "\n";
+                w.write_all(line.as_bytes()).await.unwrap();
+                w.flush().await.unwrap();
+                let mut lines = tokio::io::BufReader::new(r).lines();
+                let resp: ControlResponse =
+                    serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+                (resp, lines, w)
+            }
+        };
+        async fn next_with(
+            lines: &mut tokio::io::Lines<tokio::io::BufReader<tokio::net::unix::OwnedReadHalf>>,
+            needle: &str,
+        ) -> String {
+            loop {
+                let line =
+                    tokio::time::timeout(std::time::Duration::from_secs(5), lines.next_line())
+                        .await
+                        .unwrap()
+                        .unwrap()
+                        .unwrap();
+                if line.contains(needle) {
+                    return line;
+                }
+            }
+        }
+
+        let (resp, _, _) = subscribe("t1").await;
+        assert_eq!(resp.error.unwrap().code, "control.not_found");
+
+        let guard =
+            televy_backup_core::run_log::start_run_log("backup", "tsk_live", dir.path()).unwrap();
+        tracing::warn!(
+            event = "run.start",
+            task_id = "tsk_live",
+            target_id = "t1",
+            "run.start"
+        );
+
+        let (resp, mut a, _wa) = subscribe("t1").await;
+        assert!(resp.ok, "{resp:?}");
+        assert_eq!(resp.result.unwrap()["taskId"], "tsk_live");
+        let (resp, mut b, wb) = subscribe("t1").await;
+        assert!(resp.ok, "{resp:?}");
+        next_with(&mut a, "run.start").await;
+        next_with(&mut b, "run.start").await;
+
+        tracing::warn!(event = "phase.start", phase = "scan", "phase.start");
+        next_with(&mut a, "phase.start").await;
+        next_with(&mut b, "phase.start").await;
+
+        // One client going away leaves the run and the other client alone.
+        drop(b);
+        drop(wb);
+        tracing::warn!(event = "phase.finish", phase = "scan", "phase.finish");
+        next_with(&mut a, "phase.finish").await;
+
+        drop(guard);
+        let end = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while let Some(line) = a.next_line().await.unwrap() {
+                assert!(!line.is_empty());
+            }
+        })
+        .await;
+        assert!(end.is_ok(), "stream did not end with the run");
+    }
+
+    #[tokio::test]
+    async fn restore_estimate_without_a_local_file_map_is_snapshot_not_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let params = RestoreEstimateParams {
+            snapshot_id: "snp_missing".to_string(),
+            endpoint_id: None,
+            path: None,
+        };
+        let err = estimate_restore_from_local_index(dir.path(), &settings(), &params)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code, "snapshot.not_found");
+        assert_eq!(err.details["snapshotId"], "snp_missing");
+    }
+
+    #[test]
+    fn set_enabled_pauses_and_resumes_targets_in_the_settings_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut s = settings();
+        s.telegram_endpoints[0].mtproto.session_key = "telegram.mtproto.session.ep1".to_string();
+        s.targets.push(televy_backup_core::config::Target {
+            id: "t1".to_string(),
+            source_path: "/tmp/t1".to_string(),
+            label: String::new(),
+            label_template: None,
+            endpoint_id: "ep1".to_string(),
+            enabled: true,
+            disabled_until: None,
+            priority: 0,
+            schedule: None,
+            scan: None,
+            target_key: false,
+            allow_overlap: false,
+            min_expected_files: 0,
+            filters: Vec::new(),
+            sqlite_consistent: Vec::new(),
+        });
+        televy_backup_core::config::save_settings_v2(dir.path(), &s).unwrap();
+        let status_state = Arc::new(Mutex::new(crate::StatusRuntimeState::from_settings(&s)));
+        let attempts = Mutex::new(PassphraseAttempts::new());
+        let call = |params: serde_json::Value| {
+            handle_request(
+                &ControlRequest::new("1", "targets.setEnabled", params),
+                dir.path(),
+                dir.path(),
+                &s,
+                &status_state,
+                &attempts,
+            )
+        };
+        let saved = || televy_backup_core::config::load_settings_v2(dir.path()).unwrap();
+
+        let paused = call(serde_json::json!({
+            "targetId": "t1",
+            "enabled": false,
+            "disabledUntil": "2024-07-01T00:00:00Z",
+        }));
+        assert_eq!(
+            paused.result.unwrap()["disabledUntil"],
+            "2024-07-01T00:00:00Z"
+        );
+        assert!(!saved().targets[0].enabled);
+        assert_eq!(
+            saved().targets[0].disabled_until.as_deref(),
+            Some("2024-07-01T00:00:00Z")
+        );
+
+        let bad = call(serde_json::json!({
+            "targetId": "t1",
+            "enabled": false,
+            "disabledUntil": "tomorrow",
+        }));
+        assert_eq!(bad.error.unwrap().code, "config.invalid");
+        let unknown = call(serde_json::json!({ "targetId": "nope", "enabled": true }));
+        assert_eq!(unknown.error.unwrap().code, "control.invalid_request");
+
+        let resumed = call(serde_json::json!({ "targetId": "t1", "enabled": true }));
+        assert!(resumed.ok);
+        assert!(saved().targets[0].enabled);
+        assert_eq!(saved().targets[0].disabled_until, None);
+    }
+
+    #[test]
+    fn authorize_restore_checks_passphrase_and_backs_off() {
+        let attempts = Mutex::new(PassphraseAttempts::new());
+        let mut s = settings();
+        let r = security_authorize_restore(&s, &attempts, None).unwrap();
+        assert!(!r.passphrase_required);
+
+        s.security.restore_requires_passphrase = true;
+        let e = security_authorize_restore(&s, &attempts, Some("pw")).unwrap_err();
+        assert_eq!(e.code, "security.passphrase_not_set");
+
+        s.security.restore_passphrase_hash = Some(security::hash_restore_passphrase("pw").unwrap());
+        let e = security_authorize_restore(&s, &attempts, None).unwrap_err();
+        assert_eq!(e.code, "security.passphrase_required");
+
+        for _ in 0..2 {
+            let e = security_authorize_restore(&s, &attempts, Some("nope")).unwrap_err();
+            assert_eq!(e.code, "security.passphrase_invalid");
+            assert!(e.details["retryAfterMs"].is_null());
+        }
+        let e = security_authorize_restore(&s, &attempts, Some("nope")).unwrap_err();
+        assert_eq!(e.code, "security.passphrase_invalid");
+        assert_eq!(e.details["retryAfterMs"], 1000);
+
+        // Locked out: even the right passphrase is refused until the delay passes.
+        let e = security_authorize_restore(&s, &attempts, Some("pw")).unwrap_err();
+        assert_eq!(e.code, "security.passphrase_invalid");
+        assert!(e.retryable);
+
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+        let r = security_authorize_restore(&s, &attempts, Some("pw")).unwrap();
+        assert!(r.passphrase_required);
+        assert_eq!(attempts.lock().unwrap().failures(), 0);
     }
 }
@@ -0,0 +1,132 @@
+//! Deletion queue housekeeping: the daemon reports the bytes `gc run` queued for deletion as
+//! `pendingDeletionBytes`, and flushes an endpoint's queue (`gc flush`) once objects in it are past
+//! `retention.deletion_grace_days`, between backups and never while a backup to the endpoint runs
+//! or waits in the queue.
+
+use std::path::Path;
+
+use televy_backup_core::config as settings_config;
+use televy_backup_core::{
+    GcConfig, GcOptions, GcResult, PendingDeletions, TelegramMtProtoStorage, bootstrap,
+};
+
+/// How often the main loop reads the endpoints' deletion queues.
+pub const DELETION_QUEUE_CHECK_INTERVAL: std::time::Duration =
+    std::time::Duration::from_secs(10 * 60);
+
+/// The deletion queue of every endpoint that has a local index DB.
+pub async fn pending_deletions<'a>(
+    settings: &'a settings_config::SettingsV2,
+    index_dir: &Path,
+) -> Vec<(&'a settings_config::TelegramEndpoint, PendingDeletions)> {
+    let mut out = Vec::new();
+    for ep in &settings.telegram_endpoints {
+        let db_path = index_dir.join(format!("index.{}.sqlite", ep.id));
+        if !db_path.exists() {
+            continue;
+        }
+        match televy_backup_core::pending_deletions(&db_path).await {
+            Ok(queue) => out.push((ep, queue)),
+            Err(e) => tracing::warn!(
+                event = "gc.queue_read_failed",
+                endpoint_id = %ep.id,
+                error_code = e.code(),
+                error_message = %e,
+                "gc.queue_read_failed"
+            ),
+        }
+    }
+    out
+}
+
+/// Flushes the deletion queue of `ep` as `televybackup gc flush` does. `None` while the local
+/// endpoint or dedupe DB lags the bootstrap catalog: a stale index would miss snapshots taken on
+/// other machines, and the next backup to the endpoint syncs it.
+pub async fn flush_endpoint(
+    storage: &TelegramMtProtoStorage,
+    master_key: &[u8; 32],
+    settings: &settings_config::SettingsV2,
+    ep: &settings_config::TelegramEndpoint,
+    data_dir: &Path,
+) -> televy_backup_core::Result<Option<GcResult>> {
+    let index_dir = data_dir.join("index");
+    let endpoint_db_path = index_dir.join(format!("index.{}.sqlite", ep.id));
+    let dedupe_db_path = index_dir
+        .join("dedupe")
+        .join(format!("dedupe.{}.sqlite", ep.id));
+    let dedupe_pending_db_path = index_dir
+        .join("dedupe")
+        .join(format!("pending.{}.sqlite", ep.id));
+
+    let catalog = if ep.bootstrap.pin_mode == bootstrap::BootstrapPinMode::Disabled {
+        None
+    } else {
+        bootstrap::load_remote_catalog(storage, master_key).await?
+    };
+    if let Some(latest) = catalog.as_ref().and_then(|c| c.endpoint_latest.as_ref())
+        && !televy_backup_core::index_sync::local_endpoint_db_matches_remote_latest(
+            &endpoint_db_path,
+            &latest.manifest_object_id,
+        )
+        .await?
+    {
+        return Ok(None);
+    }
+    let dedupe_latest = catalog.and_then(|c| c.endpoint_dedupe_latest);
+    if let Some(latest) = &dedupe_latest
+        && !televy_backup_core::dedupe_sync::local_dedupe_db_matches_remote_latest(
+            &dedupe_db_path,
+            &latest.catalog_object_id,
+        )
+        .await?
+    {
+        return Ok(None);
+    }
+
+    let config = GcConfig {
+        endpoint_db_path,
+        filemap_dir: index_dir.join("filemaps").join(&ep.id),
+        dedupe_db_path: Some(dedupe_db_path.clone()),
+        dedupe_pending_db_path: Some(dedupe_pending_db_path.clone()),
+        master_key: *master_key,
+        // Queued objects passed the age check when `gc run` queued them.
+        min_age_days: 0,
+        grace_days: settings.retention.deletion_grace_days,
+    };
+    let res = televy_backup_core::flush_pending_deletions(
+        storage,
+        &config,
+        GcOptions {
+            cancel: None,
+            dry_run: false,
+            retry: settings.retry.clone(),
+        },
+    )
+    .await?;
+
+    // No machine may dedupe against a deleted object.
+    if let Some(latest) = dedupe_latest
+        && res.objects_deleted + res.objects_failed > 0
+    {
+        let device = televy_backup_core::device::load_or_create_device_identity(data_dir)?;
+        let catalog_object_id = televy_backup_core::republish_dedupe_base(
+            storage,
+            &dedupe_db_path,
+            &dedupe_pending_db_path,
+            &latest.endpoint_dedupe_id,
+            master_key,
+            Some(&device),
+        )
+        .await?;
+        let mut cat = bootstrap::load_remote_catalog(storage, master_key)
+            .await?
+            .unwrap_or_default();
+        cat.touch();
+        cat.endpoint_dedupe_latest = Some(bootstrap::BootstrapEndpointDedupeLatest {
+            endpoint_dedupe_id: latest.endpoint_dedupe_id,
+            catalog_object_id,
+        });
+        bootstrap::save_remote_catalog(storage, master_key, &cat).await?;
+    }
+    Ok(Some(res))
+}
@@ -0,0 +1,224 @@
+//! Health and daily digest housekeeping: the main loop reads every target's newest snapshot from
+//! its endpoint's index DB for the status `healthy` flag, and once a day after
+//! `notifications.digest_at` sends the digest of the last 24 hours (see
+//! `televy_backup_core::notifications`), built from `usage.sqlite`.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use televy_backup_core::config as settings_config;
+use televy_backup_core::notifications;
+use televy_backup_core::schedule_tz::ScheduleTz;
+use televy_backup_core::usage;
+
+use crate::schedule::{local_or_after_gap, parse_hhmm};
+
+/// How often the main loop refreshes health and looks for a due digest.
+pub const HEALTH_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+/// Each target's newest snapshot; targets without one (or whose index DB can't be read) are left
+/// out.
+pub async fn last_successful_backups(
+    settings: &settings_config::SettingsV2,
+    data_root: &Path,
+) -> HashMap<String, DateTime<Utc>> {
+    let mut out = HashMap::new();
+    for target in &settings.targets {
+        match crate::latest_snapshot_created_at(data_root, target).await {
+            Ok(Some(created_at)) => {
+                if let Ok(at) = DateTime::parse_from_rfc3339(&created_at) {
+                    out.insert(target.id.clone(), at.to_utc());
+                }
+            }
+            Ok(None) => {}
+            Err(e) => tracing::warn!(
+                event = "health.check_failed",
+                target_id = %target.id,
+                error = %e,
+                "health.check_failed"
+            ),
+        }
+    }
+    out
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PersistedDigestState {
+    version: u32,
+    last_sent_at: Option<String>,
+}
+
+/// When the last digest went out, kept in `digest-state.json` so a restart neither repeats nor
+/// skips one.
+#[derive(Debug)]
+pub struct DigestLedger {
+    path: PathBuf,
+    last_sent_at: Option<DateTime<Utc>>,
+}
+
+impl DigestLedger {
+    pub fn load(data_dir: &Path) -> Self {
+        let path = data_dir.join("digest-state.json");
+        let last_sent_at = std::fs::read(&path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<PersistedDigestState>(&bytes).ok())
+            .and_then(|state| state.last_sent_at)
+            .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
+            .map(|at| at.to_utc());
+        Self { path, last_sent_at }
+    }
+
+    /// Whether the latest `digest_at` slot at or before `now` has no digest yet. The first check
+    /// only starts the ledger, so turning the digest on never sends one for a slot already past.
+    pub fn due<Tz: TimeZone>(&mut self, tz: &Tz, digest_at: &str, now: DateTime<Utc>) -> bool {
+        let Some(last_sent_at) = self.last_sent_at else {
+            self.record(now);
+            return false;
+        };
+        latest_digest_slot(tz, digest_at, now).is_some_and(|slot| slot > last_sent_at)
+    }
+
+    /// Marks the current slot as handled, whether or not the send got through: a broken URL is
+    /// logged once a day instead of every check.
+    pub fn record(&mut self, at: DateTime<Utc>) {
+        self.last_sent_at = Some(at);
+        let doc = PersistedDigestState {
+            version: 1,
+            last_sent_at: Some(at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)),
+        };
+        let res = serde_json::to_vec(&doc)
+            .map_err(std::io::Error::other)
+            .and_then(|bytes| {
+                let tmp = self.path.with_extension("json.tmp");
+                std::fs::write(&tmp, bytes)?;
+                std::fs::rename(&tmp, &self.path)
+            });
+        if let Err(e) = res {
+            tracing::warn!(
+                event = "notifications.state_persist_failed",
+                path = %self.path.display(),
+                error = %e,
+                "notifications.state_persist_failed"
+            );
+        }
+    }
+}
+
+/// The latest `digest_at` (`HH:MM` in `tz`) at or before `now`.
+pub fn latest_digest_slot<Tz: TimeZone>(
+    tz: &Tz,
+    digest_at: &str,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    let (hh, mm) = parse_hhmm(digest_at.trim()).ok()?;
+    let today = now.with_timezone(tz).date_naive();
+    [Some(today), today.pred_opt()]
+        .into_iter()
+        .flatten()
+        .find_map(|day| {
+            let slot = local_or_after_gap(tz, day.and_hms_opt(hh.into(), mm.into(), 0)?)?;
+            let slot = slot.with_timezone(&Utc);
+            (slot <= now).then_some(slot)
+        })
+}
+
+/// Sends the digest when `[notifications]` has it on and its slot has come.
+pub async fn send_if_due(
+    settings: &settings_config::SettingsV2,
+    data_root: &Path,
+    ledger: &mut DigestLedger,
+    last_success: &HashMap<String, DateTime<Utc>>,
+) {
+    let notifications_settings = &settings.notifications;
+    if !notifications_settings.digest_enabled {
+        return;
+    }
+    let Ok(tz) = ScheduleTz::parse(&settings.schedule.timezone) else {
+        return;
+    };
+    let now = Utc::now();
+    if !ledger.due(&tz, &notifications_settings.digest_at, now) {
+        return;
+    }
+    ledger.record(now);
+
+    let since =
+        (now - notifications::digest_window()).to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    let runs = match usage::usage_runs_since(data_root, "backup", &since).await {
+        Ok(runs) => runs,
+        Err(e) => {
+            tracing::warn!(
+                event = "notifications.digest_failed",
+                error = %e,
+                "notifications.digest_failed"
+            );
+            return;
+        }
+    };
+    let digest = notifications::build_digest(settings, &runs, last_success, now);
+    let stale_targets = digest.stale_targets().count();
+    let config = notifications_settings.clone();
+    let sent =
+        tokio::task::spawn_blocking(move || notifications::send_digest(&config, &digest)).await;
+    match sent {
+        Ok(Ok(())) => tracing::info!(
+            event = "notifications.digest_sent",
+            stale_targets,
+            "notifications.digest_sent"
+        ),
+        Ok(Err(e)) => tracing::warn!(
+            event = "notifications.digest_failed",
+            error = %e,
+            "notifications.digest_failed"
+        ),
+        Err(e) => tracing::warn!(
+            event = "notifications.digest_failed",
+            error = %e,
+            "notifications.digest_failed"
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::FixedOffset;
+
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().to_utc()
+    }
+
+    #[test]
+    fn digest_slot_is_the_latest_digest_at_in_the_schedule_timezone() {
+        let tz = FixedOffset::east_opt(2 * 3600).unwrap();
+        assert_eq!(
+            latest_digest_slot(&tz, "08:00", at("2024-06-10T07:00:00Z")),
+            Some(at("2024-06-10T06:00:00Z"))
+        );
+        assert_eq!(
+            latest_digest_slot(&tz, "08:00", at("2024-06-10T05:59:00Z")),
+            Some(at("2024-06-09T06:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn digest_is_due_once_per_slot_and_not_on_the_first_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let tz = Utc;
+        let mut ledger = DigestLedger::load(dir.path());
+        assert!(!ledger.due(&tz, "08:00", at("2024-06-10T09:00:00Z")));
+        assert!(!ledger.due(&tz, "08:00", at("2024-06-10T12:00:00Z")));
+
+        // Missed while the daemon was not running: sent once it checks again.
+        let mut ledger = DigestLedger::load(dir.path());
+        let late = at("2024-06-11T15:00:00Z");
+        assert!(ledger.due(&tz, "08:00", late));
+        ledger.record(late);
+        assert!(!DigestLedger::load(dir.path()).due(&tz, "08:00", at("2024-06-12T07:59:00Z")));
+        assert!(DigestLedger::load(dir.path()).due(&tz, "08:00", at("2024-06-12T08:00:00Z")));
+    }
+}
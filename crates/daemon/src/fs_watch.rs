@@ -0,0 +1,375 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use notify::event::{AccessKind, AccessMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use televy_backup_core::config as settings_config;
+use tokio::time::Duration;
+
+/// Past this many distinct paths a full scan is cheaper than tracking; treat as overflow.
+const DIRTY_SET_MAX_PATHS: usize = 100_000;
+const PERSIST_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Changed paths for one target since its last successful backup.
+///
+/// The set is only trusted as a scan hint once the watcher has observed a full run end to end
+/// (`armed`): a freshly created watcher (e.g. after a daemon restart) knows nothing about changes
+/// made before it started.
+#[derive(Debug, Default)]
+struct DirtySet {
+    paths: BTreeSet<PathBuf>,
+    overflowed: bool,
+    armed: bool,
+    in_flight: Option<InFlightRun>,
+    revision: u64,
+}
+
+#[derive(Debug)]
+struct InFlightRun {
+    paths: BTreeSet<PathBuf>,
+    overflowed: bool,
+    hinted: bool,
+}
+
+impl DirtySet {
+    fn record(&mut self, path: PathBuf) {
+        if self.overflowed {
+            return;
+        }
+        if self.paths.insert(path) {
+            self.revision += 1;
+            if self.paths.len() > DIRTY_SET_MAX_PATHS {
+                self.mark_overflow();
+            }
+        }
+    }
+
+    fn mark_overflow(&mut self) {
+        if !self.overflowed {
+            self.overflowed = true;
+            self.paths.clear();
+            self.revision += 1;
+        }
+    }
+
+    /// Returns the scan hint (if trustworthy) and starts a new accumulation window.
+    fn begin_run(&mut self) -> Option<Vec<PathBuf>> {
+        let hinted = self.armed && !self.overflowed;
+        let hint = hinted.then(|| self.paths.iter().cloned().collect());
+        self.in_flight = Some(InFlightRun {
+            paths: std::mem::take(&mut self.paths),
+            overflowed: self.overflowed,
+            hinted,
+        });
+        self.overflowed = false;
+        self.revision += 1;
+        hint
+    }
+
+    fn finish_run(&mut self, succeeded: bool) {
+        let Some(run) = self.in_flight.take() else {
+            return;
+        };
+        if succeeded {
+            // Changes seen during the run stay in `paths` for the next one.
+            self.armed = true;
+            return;
+        }
+        // The base snapshot did not move, so the window must keep covering everything since it.
+        if run.overflowed {
+            self.mark_overflow();
+        } else {
+            for path in run.paths {
+                self.record(path);
+            }
+        }
+        if !run.hinted {
+            self.armed = false;
+        }
+        self.revision += 1;
+    }
+}
+
+struct TargetWatch {
+    source_path: PathBuf,
+    state: Arc<Mutex<DirtySet>>,
+    _watcher: RecommendedWatcher,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PersistedTarget {
+    source_path: String,
+    armed: bool,
+    overflowed: bool,
+    paths: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PersistedDirtySets {
+    version: u32,
+    targets: HashMap<String, PersistedTarget>,
+}
+
+/// File system watchers for enabled targets (`scan.watch = true`).
+///
+/// The dirty sets are persisted periodically for inspection only; after a restart every target
+/// falls back to a full scan because changes made while the daemon was down are unknown.
+pub struct FsWatchers {
+    persist_path: PathBuf,
+    targets: HashMap<String, TargetWatch>,
+    persisted_revision: Option<u64>,
+    last_persist: Option<Instant>,
+}
+
+impl FsWatchers {
+    pub fn new(data_dir: &Path) -> Self {
+        Self {
+            persist_path: data_dir.join("fs-watch.json"),
+            targets: HashMap::new(),
+            persisted_revision: None,
+            last_persist: None,
+        }
+    }
+
+    /// Starts/stops watchers so they match the enabled targets in `settings`.
+    pub fn sync(&mut self, settings: &settings_config::SettingsV2) {
+        let now = chrono::Local::now();
+        let wanted = if settings.scan.watch {
+            settings
+                .targets
+                .iter()
+                .filter(|t| t.enabled_at(&now))
+                .map(|t| (t.id.as_str(), PathBuf::from(&t.source_path)))
+                .collect::<HashMap<_, _>>()
+        } else {
+            HashMap::new()
+        };
+
+        self.targets.retain(|id, w| {
+            let keep = wanted.get(id.as_str()).is_some_and(|p| *p == w.source_path);
+            if !keep {
+                tracing::info!(event = "fs_watch.stop", target_id = %id, "fs_watch.stop");
+            }
+            keep
+        });
+
+        for (id, source_path) in wanted {
+            if self.targets.contains_key(id) {
+                continue;
+            }
+            match start_target_watch(&source_path) {
+                Ok(watch) => {
+                    tracing::info!(
+                        event = "fs_watch.start",
+                        target_id = %id,
+                        source_path = %source_path.display(),
+                        "fs_watch.start"
+                    );
+                    self.targets.insert(id.to_string(), watch);
+                }
+                Err(e) => {
+                    // Leave it unwatched; runs for this target keep doing full scans. The next
+                    // sync retries (e.g. once an external volume is mounted).
+                    tracing::debug!(
+                        event = "fs_watch.start_failed",
+                        target_id = %id,
+                        source_path = %source_path.display(),
+                        error = %e,
+                        "fs_watch.start_failed"
+                    );
+                }
+            }
+        }
+    }
+
+    /// Takes the changed-path hint for a run that is about to start (`None` = full scan).
+    pub fn begin_run(&self, target_id: &str) -> Option<Vec<PathBuf>> {
+        let watch = self.targets.get(target_id)?;
+        let mut state = watch.state.lock().ok()?;
+        state.begin_run()
+    }
+
+    pub fn finish_run(&self, target_id: &str, succeeded: bool) {
+        if let Some(watch) = self.targets.get(target_id)
+            && let Ok(mut state) = watch.state.lock()
+        {
+            state.finish_run(succeeded);
+        }
+    }
+
+    /// Best-effort snapshot of the dirty sets to `fs-watch.json`, at most every 30s.
+    pub fn persist_if_due(&mut self) {
+        if self
+            .last_persist
+            .is_some_and(|t| t.elapsed() < PERSIST_INTERVAL)
+        {
+            return;
+        }
+
+        let mut revision = 0u64;
+        let mut targets = HashMap::new();
+        for (id, watch) in &self.targets {
+            let Ok(state) = watch.state.lock() else {
+                continue;
+            };
+            revision = revision.wrapping_add(state.revision);
+            targets.insert(
+                id.clone(),
+                PersistedTarget {
+                    source_path: watch.source_path.display().to_string(),
+                    armed: state.armed,
+                    overflowed: state.overflowed,
+                    paths: state
+                        .paths
+                        .iter()
+                        .map(|p| p.display().to_string())
+                        .collect(),
+                },
+            );
+        }
+        revision = revision.wrapping_add(targets.len() as u64);
+        self.last_persist = Some(Instant::now());
+        if self.persisted_revision == Some(revision) {
+            return;
+        }
+
+        let doc = PersistedDirtySets {
+            version: 1,
+            targets,
+        };
+        let res = serde_json::to_vec(&doc)
+            .map_err(std::io::Error::other)
+            .and_then(|bytes| {
+                let tmp = self.persist_path.with_extension("json.tmp");
+                std::fs::write(&tmp, bytes)?;
+                std::fs::rename(&tmp, &self.persist_path)
+            });
+        match res {
+            Ok(()) => self.persisted_revision = Some(revision),
+            Err(e) => tracing::debug!(
+                event = "fs_watch.persist_failed",
+                path = %self.persist_path.display(),
+                error = %e,
+                "fs_watch.persist_failed"
+            ),
+        }
+    }
+}
+
+fn start_target_watch(source_path: &Path) -> notify::Result<TargetWatch> {
+    // Backends report canonical paths (e.g. `/private/var/...` on macOS); map them back onto the
+    // configured source path so they line up with the scan's walk paths.
+    let canonical_root = std::fs::canonicalize(source_path)?;
+    let source_root = source_path.to_path_buf();
+    let state = Arc::new(Mutex::new(DirtySet::default()));
+
+    let handler_state = Arc::clone(&state);
+    let handler_canonical_root = canonical_root.clone();
+    let handler_source_root = source_root.clone();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+        let Ok(mut state) = handler_state.lock() else {
+            return;
+        };
+        let event = match res {
+            Ok(event) => event,
+            Err(_) => {
+                state.mark_overflow();
+                return;
+            }
+        };
+        if event.need_rescan() {
+            state.mark_overflow();
+            return;
+        }
+        if let EventKind::Access(kind) = event.kind
+            && kind != AccessKind::Close(AccessMode::Write)
+        {
+            return;
+        }
+        for path in event.paths {
+            if let Some(path) =
+                rebase_event_path(&path, &handler_canonical_root, &handler_source_root)
+            {
+                state.record(path);
+            }
+        }
+    })?;
+    watcher.watch(&canonical_root, RecursiveMode::Recursive)?;
+
+    Ok(TargetWatch {
+        source_path: source_root,
+        state,
+        _watcher: watcher,
+    })
+}
+
+fn rebase_event_path(path: &Path, canonical_root: &Path, source_root: &Path) -> Option<PathBuf> {
+    if let Ok(rel) = path.strip_prefix(canonical_root) {
+        return Some(source_root.join(rel));
+    }
+    path.starts_with(source_root).then(|| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dirty_set_hints_only_after_an_observed_run_and_survives_failures() {
+        let mut set = DirtySet::default();
+        set.record(PathBuf::from("/src/a"));
+
+        // Fresh watcher: changes before it started are unknown.
+        assert_eq!(set.begin_run(), None);
+        set.record(PathBuf::from("/src/during"));
+        set.finish_run(true);
+
+        set.record(PathBuf::from("/src/b"));
+        assert_eq!(
+            set.begin_run(),
+            Some(vec![PathBuf::from("/src/b"), PathBuf::from("/src/during")])
+        );
+        set.record(PathBuf::from("/src/c"));
+        set.finish_run(false);
+
+        // The failed run's paths are folded back in since the base snapshot did not change.
+        assert_eq!(
+            set.begin_run(),
+            Some(vec![
+                PathBuf::from("/src/b"),
+                PathBuf::from("/src/c"),
+                PathBuf::from("/src/during"),
+            ])
+        );
+        set.finish_run(true);
+
+        set.mark_overflow();
+        set.record(PathBuf::from("/src/d"));
+        assert_eq!(set.begin_run(), None);
+        set.finish_run(true);
+        assert_eq!(set.begin_run(), Some(Vec::new()));
+    }
+
+    #[test]
+    fn event_paths_are_rebased_onto_the_configured_source_path() {
+        let canonical = Path::new("/private/var/src");
+        let source = Path::new("/var/src");
+        assert_eq!(
+            rebase_event_path(Path::new("/private/var/src/a/b"), canonical, source),
+            Some(PathBuf::from("/var/src/a/b"))
+        );
+        assert_eq!(
+            rebase_event_path(Path::new("/var/src/x"), canonical, source),
+            Some(PathBuf::from("/var/src/x"))
+        );
+        assert_eq!(
+            rebase_event_path(Path::new("/elsewhere"), canonical, source),
+            None
+        );
+    }
+}
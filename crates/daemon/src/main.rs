@@ -9,40 +9,50 @@ use std::sync::{Arc, Mutex};
 use std::time::{Instant, SystemTime};
 
 use base64::Engine;
-use chrono::{Datelike, Timelike};
 use sqlx::Row;
+use televy_backup_core::audit::{self, AuditActor};
+use televy_backup_core::control::{ControlError, IndexSyncParams};
+use televy_backup_core::index_sync::{IndexSyncOptions, IndexSyncReport};
+use televy_backup_core::schedule_tz::ScheduleTz;
+use televy_backup_core::sqlite_consistent::SqliteConsistentOptions;
 use televy_backup_core::status::{
-    Counter, GlobalStatus, Progress, Rate, StatusSnapshot, StatusSource, StatusWriteOptions,
-    TargetRunSummary, TargetState, now_unix_ms, status_ipc_socket_path, status_json_path,
-    write_status_snapshot_json_atomic_with_options,
+    Counter, GlobalStatus, HEALTHY_KEY, LAST_SUCCESS_AT_KEY, LAST_VERIFY_KEY,
+    PENDING_DELETION_BYTES_KEY, Progress, Rate, SCHEDULE_STATUS_KEY, ScheduleStatus,
+    StatusFileWriter, StatusSnapshot, StatusSource, StatusWriteOptions, TargetRunSummary,
+    TargetState, VerifySummary, now_unix_ms, status_ipc_socket_path, status_json_path,
 };
+use televy_backup_core::usage::{self, UsageRun};
 use televy_backup_core::{
     BackupConfig, BackupOptions, ChunkingConfig, SourceQuickStats, TelegramMtProtoStorage,
     TelegramMtProtoStorageConfig,
 };
-use televy_backup_core::{ProgressSink, Storage, TaskProgress};
-use televy_backup_core::{bootstrap, config as settings_config};
+use televy_backup_core::{
+    ErrorCode, PartialRunResult, Phase, ProgressRecorder, ProgressSink, RunStatus, Storage,
+    TaskProgress,
+};
+use televy_backup_core::{bootstrap, config as settings_config, health, quiet_hours};
 use tokio::sync::RwLock;
 use tokio::time::{Duration, sleep};
 use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
+use run_queue::{QueuedRun, RunQueue, RunTrigger};
+use schedule::{ScheduleOutcome, ScheduleSlot, TargetScheduleState};
+use status_writer::{StatusMarker, StatusWrite, StatusWriteScheduler};
+use verify_schedule::VerifyLedger;
+
 mod control_ipc;
+mod deletion_queue;
+mod digest;
+mod fs_watch;
+mod mtproto_pool;
+mod remote_rpc;
+mod run_queue;
+mod schedule;
 mod status_ipc;
+mod status_writer;
 mod vault_ipc;
-
-#[derive(Default, Clone)]
-struct TargetScheduleState {
-    last_hourly: Option<(i32, u32, u32, u32)>, // year, month, day, hour
-    last_daily: Option<(i32, u32, u32)>,       // year, month, day
-}
-
-#[derive(Debug, Clone, Copy)]
-enum ScheduleSlot {
-    Hourly((i32, u32, u32, u32)),
-    Daily((i32, u32, u32)),
-    Manual,
-}
+mod verify_schedule;
 
 #[derive(Debug, Clone)]
 struct TargetRuntime {
@@ -51,9 +61,18 @@ struct TargetRuntime {
     source_path: String,
     endpoint_id: String,
     enabled: bool,
-
-    state: String, // "idle" | "running" | "failed"
+    disabled_until: Option<chrono::DateTime<chrono::FixedOffset>>,
+    /// Targets whose source path is equal to or nested in this one's (see
+    /// `settings_config::target_overlaps`).
+    overlaps_with: Vec<String>,
+    /// Time between the target's scheduled runs; `None` when unscheduled.
+    schedule_period: Option<chrono::TimeDelta>,
+    /// Newest snapshot in the index DB, or the end of the latest successful daemon run.
+    last_success_at: Option<chrono::DateTime<chrono::Utc>>,
+
+    state: String, // "idle" | "queued" | "running" | "failed" (see also `waiting_quiet_hours`)
     running_since: Option<u64>,
+    group_id: Option<String>,
     progress: Option<Progress>,
     last_run: Option<TargetRunSummary>,
 
@@ -70,16 +89,177 @@ struct TargetRuntime {
     down_rate: ByteRateWindow,
 }
 
+impl TargetRuntime {
+    /// A target that was waiting but will not run now falls back to its last known state.
+    fn settle_queued_state(&mut self) {
+        if self.state == "queued" {
+            let last_failed = self
+                .last_run
+                .as_ref()
+                .is_some_and(|r| r.status.as_deref() == Some("failed"));
+            self.state = if last_failed { "failed" } else { "idle" }.to_string();
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BackupGroupTargetResult {
+    target_id: String,
+    status: String, // "succeeded" | "failed" | "cancelled"
+    snapshot_id: Option<String>,
+    error_code: Option<String>,
+}
+
+/// Targets on the same endpoint that came due in the same schedule slot; they share one
+/// MTProto connection and run sequentially by `targets[].priority`.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BackupGroupStatus {
+    group_id: String,
+    endpoint_id: String,
+    state: String, // "running" | "finished"
+    target_ids: Vec<String>,
+    results: Vec<BackupGroupTargetResult>,
+}
+
+struct BackupGroup<'a> {
+    endpoint_id: String,
+    runs: Vec<(&'a settings_config::Target, ScheduleSlot)>,
+}
+
+/// Orders due targets into per-endpoint groups.
+///
+/// Higher `priority` runs first (ties keep settings order); a group is placed where its first
+/// member lands in that order.
+fn plan_backup_groups(
+    mut due: Vec<(&settings_config::Target, ScheduleSlot)>,
+) -> Vec<BackupGroup<'_>> {
+    due.sort_by_key(|(t, _)| std::cmp::Reverse(t.priority));
+
+    let mut groups = Vec::<BackupGroup<'_>>::new();
+    for (target, slot) in due {
+        match groups
+            .iter_mut()
+            .find(|g| g.endpoint_id == target.endpoint_id)
+        {
+            Some(g) => g.runs.push((target, slot)),
+            None => groups.push(BackupGroup {
+                endpoint_id: target.endpoint_id.clone(),
+                runs: vec![(target, slot)],
+            }),
+        }
+    }
+    groups
+}
+
+/// The backup group whose queued runs are being started.
+#[derive(Debug)]
+struct ActiveBackupGroup {
+    group_id: String,
+    endpoint_id: String,
+    started: Instant,
+}
+
+/// Keeps backup group status in step with the queue before `next` starts: the active group ends
+/// once a run outside it starts or none of its runs is left queued, and `next`'s group starts.
+fn advance_backup_group(
+    st: &mut StatusRuntimeState,
+    active: &mut Option<ActiveBackupGroup>,
+    next: Option<&QueuedRun>,
+) {
+    let next_group_id = next.and_then(|q| q.group_id.as_deref());
+    if let Some(group) = active.as_ref()
+        && next_group_id != Some(group.group_id.as_str())
+        && (next.is_some() || st.run_queue.group_target_ids(&group.group_id).is_empty())
+    {
+        let group = active.take().expect("active group");
+        if let Some(summary) = st.mark_group_finish(&group.group_id) {
+            let succeeded = summary
+                .results
+                .iter()
+                .filter(|r| r.status == "succeeded")
+                .count();
+            tracing::warn!(
+                event = "group.finish",
+                kind = "backup",
+                group_id = %group.group_id,
+                endpoint_id = %group.endpoint_id,
+                duration_seconds = group.started.elapsed().as_secs_f64(),
+                targets_total = summary.results.len(),
+                targets_succeeded = succeeded,
+                results = %serde_json::to_string(&summary.results).unwrap_or_default(),
+                "group.finish"
+            );
+        }
+    }
+
+    if let (Some(next), Some(group_id)) = (next, next_group_id)
+        && active.is_none()
+    {
+        let endpoint_id = st
+            .targets
+            .get(&next.target_id)
+            .map(|t| t.endpoint_id.clone())
+            .unwrap_or_default();
+        let mut target_ids = vec![next.target_id.clone()];
+        target_ids.extend(st.run_queue.group_target_ids(group_id));
+        st.mark_group_start(group_id, &endpoint_id, &target_ids);
+        *active = Some(ActiveBackupGroup {
+            group_id: group_id.to_string(),
+            endpoint_id,
+            started: Instant::now(),
+        });
+    }
+}
+
 #[derive(Debug)]
 struct StatusRuntimeState {
     target_order: Vec<String>,
     targets: HashMap<String, TargetRuntime>,
+    backup_group: Option<BackupGroupStatus>,
+    schedule: Option<ScheduleStatus>,
+    run_queue: RunQueue,
+    verify: VerifyLedger,
+    /// Bytes queued for deletion across the endpoints; `None` until the queues were read.
+    pending_deletion_bytes: Option<u64>,
+    /// Set once `last_success_at` was read from the index DBs; `healthy` is reported from then on.
+    health_checked: bool,
+    /// End of the current quiet hours; scheduled runs stay queued until then.
+    quiet_until: Option<chrono::DateTime<chrono::Utc>>,
+    /// Progress reports received; lets the status writer coalesce them (see `status_writer`).
+    progress_revision: u64,
+    /// `performance.status_write_interval_ms`.
+    status_write_interval: Duration,
+}
+
+fn log_settings_warnings(warnings: &[settings_config::SettingsWarning]) {
+    for w in warnings {
+        tracing::warn!(
+            event = "config.warning",
+            code = w.code,
+            target_ids = %w.target_ids.join(","),
+            message = %w.message,
+            "config.warning"
+        );
+    }
+}
+
+/// For each target with an overlapping source path, the ids of the targets it overlaps.
+fn target_overlaps_by_id(settings: &settings_config::SettingsV2) -> HashMap<String, Vec<String>> {
+    let mut out = HashMap::<String, Vec<String>>::new();
+    for (a, b) in settings_config::target_overlaps(settings) {
+        out.entry(a.id.clone()).or_default().push(b.id.clone());
+        out.entry(b.id.clone()).or_default().push(a.id.clone());
+    }
+    out
 }
 
 impl StatusRuntimeState {
     fn from_settings(settings: &settings_config::SettingsV2) -> Self {
         let mut target_order = Vec::new();
         let mut targets = HashMap::new();
+        let overlaps = target_overlaps_by_id(settings);
         for t in &settings.targets {
             target_order.push(t.id.clone());
             targets.insert(
@@ -94,8 +274,13 @@ impl StatusRuntimeState {
                     source_path: t.source_path.clone(),
                     endpoint_id: t.endpoint_id.clone(),
                     enabled: t.enabled,
+                    disabled_until: t.disabled_until_at(),
+                    overlaps_with: overlaps.get(&t.id).cloned().unwrap_or_default(),
+                    schedule_period: health::target_schedule_period(settings, t),
+                    last_success_at: None,
                     state: "idle".to_string(),
                     running_since: None,
+                    group_id: None,
                     progress: None,
                     last_run: None,
                     external_task_id: None,
@@ -111,12 +296,22 @@ impl StatusRuntimeState {
         Self {
             target_order,
             targets,
+            backup_group: None,
+            schedule: None,
+            run_queue: RunQueue::default(),
+            verify: VerifyLedger::default(),
+            pending_deletion_bytes: None,
+            health_checked: false,
+            quiet_until: None,
+            progress_revision: 0,
+            status_write_interval: status_write_interval(settings),
         }
     }
 
     fn apply_settings(&mut self, settings: &settings_config::SettingsV2) {
         let mut target_order = Vec::new();
         let mut targets = HashMap::new();
+        let mut overlaps = target_overlaps_by_id(settings);
 
         for t in &settings.targets {
             target_order.push(t.id.clone());
@@ -127,8 +322,13 @@ impl StatusRuntimeState {
                 source_path: t.source_path.clone(),
                 endpoint_id: t.endpoint_id.clone(),
                 enabled: t.enabled,
+                disabled_until: t.disabled_until_at(),
+                overlaps_with: Vec::new(),
+                schedule_period: None,
+                last_success_at: None,
                 state: "idle".to_string(),
                 running_since: None,
+                group_id: None,
                 progress: None,
                 last_run: None,
                 external_task_id: None,
@@ -148,12 +348,31 @@ impl StatusRuntimeState {
             rt.source_path = t.source_path.clone();
             rt.endpoint_id = t.endpoint_id.clone();
             rt.enabled = t.enabled;
+            rt.disabled_until = t.disabled_until_at();
+            rt.overlaps_with = overlaps.remove(&t.id).unwrap_or_default();
+            rt.schedule_period = health::target_schedule_period(settings, t);
 
             targets.insert(t.id.clone(), rt);
         }
 
+        self.run_queue.retain_targets(|id| targets.contains_key(id));
+        self.verify.retain_targets(|id| targets.contains_key(id));
         self.target_order = target_order;
         self.targets = targets;
+        self.status_write_interval = status_write_interval(settings);
+    }
+
+    /// Takes each target's newest snapshot from the index DBs, unless a daemon run finished since.
+    fn set_last_successes(
+        &mut self,
+        last_success: &HashMap<String, chrono::DateTime<chrono::Utc>>,
+    ) {
+        for t in self.targets.values_mut() {
+            t.last_success_at = t
+                .last_success_at
+                .max(last_success.get(&t.target_id).copied());
+        }
+        self.health_checked = true;
     }
 
     fn mark_run_start(&mut self, target_id: &str) {
@@ -165,7 +384,7 @@ impl StatusRuntimeState {
         let now = now_unix_ms();
         t.running_since = Some(now);
         t.progress = Some(Progress {
-            phase: "running".to_string(),
+            phase: Phase::Running,
             files_total: None,
             files_done: None,
             source_files_total: None,
@@ -179,7 +398,9 @@ impl StatusRuntimeState {
             bytes_uploaded_source: Some(0),
             bytes_uploaded: Some(0),
             bytes_downloaded: Some(0),
+            bytes_written: None,
             bytes_deduped: Some(0),
+            bytes_total: None,
         });
         t.up_total_bytes = Some(0);
         t.up_bps = Some(0);
@@ -200,7 +421,7 @@ impl StatusRuntimeState {
         t.state = "running".to_string();
         t.running_since = Some(now);
         t.progress = Some(Progress {
-            phase: "running".to_string(),
+            phase: Phase::Running,
             files_total: None,
             files_done: None,
             source_files_total: None,
@@ -214,7 +435,9 @@ impl StatusRuntimeState {
             bytes_uploaded_source: Some(0),
             bytes_uploaded: Some(0),
             bytes_downloaded: Some(0),
+            bytes_written: None,
             bytes_deduped: Some(0),
+            bytes_total: None,
         });
 
         // Reset upload baselines so status sampling can compute rates cleanly for CLI runs.
@@ -231,6 +454,7 @@ impl StatusRuntimeState {
         let Some(t) = self.targets.get_mut(target_id) else {
             return;
         };
+        self.progress_revision += 1;
 
         // Ignore stale updates from an earlier task.
         match t.external_task_id.as_deref() {
@@ -261,7 +485,9 @@ impl StatusRuntimeState {
             bytes_uploaded_source: p.bytes_uploaded_source,
             bytes_uploaded: p.bytes_uploaded,
             bytes_downloaded: p.bytes_downloaded,
+            bytes_written: p.bytes_written,
             bytes_deduped: p.bytes_deduped,
+            bytes_total: p.bytes_total,
         });
 
         // Prefer payload bytes for "last 1s" transfer rates.
@@ -309,6 +535,7 @@ impl StatusRuntimeState {
         let Some(t) = self.targets.get_mut(target_id) else {
             return;
         };
+        self.progress_revision += 1;
         if t.state != "running" {
             t.state = "running".to_string();
         }
@@ -330,7 +557,9 @@ impl StatusRuntimeState {
             bytes_uploaded_source: p.bytes_uploaded_source,
             bytes_uploaded: p.bytes_uploaded,
             bytes_downloaded: p.bytes_downloaded,
+            bytes_written: p.bytes_written,
             bytes_deduped: p.bytes_deduped,
+            bytes_total: p.bytes_total,
         });
 
         if let Some(bytes) = p.bytes_uploaded.or(p.net_bytes_uploaded) {
@@ -346,13 +575,17 @@ impl StatusRuntimeState {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn mark_run_finish_success(
         &mut self,
         target_id: &str,
+        snapshot_id: &str,
         duration_seconds: f64,
         files_indexed: u64,
         bytes_uploaded: u64,
         bytes_deduped: u64,
+        upload_duration_seconds: Option<f64>,
+        warnings: Vec<String>,
     ) {
         let Some(t) = self.targets.get_mut(target_id) else {
             return;
@@ -366,17 +599,22 @@ impl StatusRuntimeState {
         t.down_bps = None;
         t.down_total_bytes = None;
         t.down_rate = ByteRateWindow::default();
+        let finished_at = chrono::Utc::now();
+        t.last_success_at = Some(finished_at);
         t.last_run = Some(TargetRunSummary {
-            finished_at: Some(
-                chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
-            ),
+            finished_at: Some(finished_at.to_rfc3339_opts(chrono::SecondsFormat::Millis, true)),
             duration_seconds: Some(duration_seconds),
             status: Some("succeeded".to_string()),
             error_code: None,
+            error_message: None,
             files_indexed: Some(files_indexed),
             bytes_uploaded: Some(bytes_uploaded),
             bytes_deduped: Some(bytes_deduped),
+            upload_duration_seconds,
+            log_excerpt: Vec::new(),
+            warnings,
         });
+        self.record_group_result(target_id, "succeeded", Some(snapshot_id), None);
     }
 
     fn mark_run_finish_failure(
@@ -384,10 +622,14 @@ impl StatusRuntimeState {
         target_id: &str,
         duration_seconds: f64,
         error_code: String,
+        error_message: String,
+        log_excerpt: Vec<String>,
+        warnings: Vec<String>,
     ) {
         let Some(t) = self.targets.get_mut(target_id) else {
             return;
         };
+        let group_error_code = error_code.clone();
         t.state = "failed".to_string();
         t.running_since = None;
         t.progress = None;
@@ -404,16 +646,196 @@ impl StatusRuntimeState {
             duration_seconds: Some(duration_seconds),
             status: Some("failed".to_string()),
             error_code: Some(error_code),
+            error_message: Some(error_message),
             files_indexed: None,
             bytes_uploaded: None,
             bytes_deduped: None,
+            upload_duration_seconds: None,
+            log_excerpt,
+            warnings,
+        });
+        self.record_group_result(target_id, "failed", None, Some(&group_error_code));
+    }
+
+    /// A cancelled run is not a failure: the target goes back to `idle` and keeps what the run
+    /// got done before it stopped.
+    fn mark_run_finish_cancelled(
+        &mut self,
+        target_id: &str,
+        duration_seconds: f64,
+        partial: PartialRunResult,
+    ) {
+        let Some(t) = self.targets.get_mut(target_id) else {
+            return;
+        };
+        t.state = "idle".to_string();
+        t.running_since = None;
+        t.progress = None;
+        t.up_bps = None;
+        t.up_total_bytes = None;
+        t.up_rate = ByteRateWindow::default();
+        t.down_bps = None;
+        t.down_total_bytes = None;
+        t.down_rate = ByteRateWindow::default();
+        t.last_run = Some(TargetRunSummary {
+            finished_at: Some(
+                chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            ),
+            duration_seconds: Some(duration_seconds),
+            status: Some(RunStatus::Cancelled.as_str().to_string()),
+            error_code: Some(ErrorCode::TaskCancelled.as_str().to_string()),
+            error_message: None,
+            files_indexed: partial.files_done,
+            bytes_uploaded: partial.bytes_uploaded,
+            bytes_deduped: partial.bytes_deduped,
+            upload_duration_seconds: None,
+            log_excerpt: Vec::new(),
+            warnings: Vec::new(),
+        });
+        self.record_group_result(
+            target_id,
+            RunStatus::Cancelled.as_str(),
+            None,
+            Some(ErrorCode::TaskCancelled.as_str()),
+        );
+    }
+
+    fn mark_group_start(&mut self, group_id: &str, endpoint_id: &str, target_ids: &[String]) {
+        for id in target_ids {
+            let Some(t) = self.targets.get_mut(id) else {
+                continue;
+            };
+            t.group_id = Some(group_id.to_string());
+            if t.state != "running" {
+                t.state = "queued".to_string();
+            }
+        }
+        self.backup_group = Some(BackupGroupStatus {
+            group_id: group_id.to_string(),
+            endpoint_id: endpoint_id.to_string(),
+            state: "running".to_string(),
+            target_ids: target_ids.to_vec(),
+            results: Vec::new(),
         });
     }
 
+    fn record_group_result(
+        &mut self,
+        target_id: &str,
+        status: &str,
+        snapshot_id: Option<&str>,
+        error_code: Option<&str>,
+    ) {
+        let Some(group_id) = self.targets.get(target_id).and_then(|t| t.group_id.clone()) else {
+            return;
+        };
+        let Some(group) = self
+            .backup_group
+            .as_mut()
+            .filter(|g| g.group_id == group_id)
+        else {
+            return;
+        };
+        group.results.push(BackupGroupTargetResult {
+            target_id: target_id.to_string(),
+            status: status.to_string(),
+            snapshot_id: snapshot_id.map(|s| s.to_string()),
+            error_code: error_code.map(|s| s.to_string()),
+        });
+    }
+
+    /// Ends a backup group: targets that never started fall back to their last known state.
+    fn mark_group_finish(&mut self, group_id: &str) -> Option<BackupGroupStatus> {
+        for t in self.targets.values_mut() {
+            if t.group_id.as_deref() != Some(group_id) {
+                continue;
+            }
+            t.group_id = None;
+            t.settle_queued_state();
+        }
+
+        let group = self
+            .backup_group
+            .as_mut()
+            .filter(|g| g.group_id == group_id)?;
+        group.state = "finished".to_string();
+        Some(group.clone())
+    }
+
+    /// Whether a run on `endpoint_id` is active (daemon or CLI) or a backup of it is queued.
+    fn endpoint_busy(&self, endpoint_id: &str) -> bool {
+        self.targets
+            .values()
+            .filter(|t| t.endpoint_id == endpoint_id)
+            .any(|t| t.state == "running" || self.run_queue.position(&t.target_id).is_some())
+    }
+
+    /// What the status writer compares to decide whether `status.json` needs a write: progress
+    /// reports are coalesced, while a change of any target's run state, the backup group or quiet
+    /// hours is written right away.
+    fn status_marker(&self) -> StatusMarker {
+        use std::hash::{Hash, Hasher};
+
+        let mut h = std::collections::hash_map::DefaultHasher::new();
+        for id in &self.target_order {
+            id.hash(&mut h);
+            if let Some(t) = self.targets.get(id) {
+                t.state.hash(&mut h);
+                t.enabled.hash(&mut h);
+                t.running_since.hash(&mut h);
+                t.group_id.hash(&mut h);
+                t.external_task_id.hash(&mut h);
+                t.last_success_at.hash(&mut h);
+                if let Some(r) = &t.last_run {
+                    r.finished_at.hash(&mut h);
+                    r.status.hash(&mut h);
+                }
+            }
+        }
+        if let Some(g) = &self.backup_group {
+            g.group_id.hash(&mut h);
+            g.state.hash(&mut h);
+            g.results.len().hash(&mut h);
+        }
+        self.quiet_until.hash(&mut h);
+        StatusMarker {
+            revision: self.progress_revision,
+            transition: h.finish(),
+        }
+    }
+
     fn has_running(&self) -> bool {
         self.targets.values().any(|t| t.state == "running")
     }
 
+    /// Daemon and CLI runs alike (CLI runs report in via `status.taskStart`).
+    fn running_count(&self) -> usize {
+        self.targets
+            .values()
+            .filter(|t| t.state == "running")
+            .count()
+    }
+
+    /// Takes the next queued run unless `max_concurrent_runs` runs are already active. During
+    /// quiet hours scheduled runs wait and later manual runs go first.
+    fn next_queued_run(&mut self, max_concurrent_runs: u32) -> Option<QueuedRun> {
+        if self.running_count() >= max_concurrent_runs.max(1) as usize {
+            return None;
+        }
+        if self.quiet_until.is_some() {
+            return self.run_queue.pop_first_unscheduled();
+        }
+        self.run_queue.pop_front()
+    }
+
+    fn remove_queued_run(&mut self, task_id: &str) -> Option<QueuedRun> {
+        let removed = self.run_queue.remove(task_id)?;
+        if let Some(t) = self.targets.get_mut(&removed.target_id) {
+            t.settle_queued_state();
+        }
+        Some(removed)
+    }
+
     fn tick_rates_at(&mut self, now: Instant) {
         for t in self.targets.values_mut() {
             if t.state != "running" {
@@ -470,13 +892,84 @@ impl StatusRuntimeState {
                 global_down_total = global_down_total.saturating_add(bytes);
                 have_global_down = true;
             }
+            let queue_position = self
+                .run_queue
+                .position(&t.target_id)
+                .filter(|_| t.state != "running");
+            let mut extra: std::collections::BTreeMap<String, serde_json::Value> = t
+                .group_id
+                .as_ref()
+                .map(|g| [("groupId".to_string(), serde_json::json!(g))].into())
+                .unwrap_or_default();
+            if let Some(position) = queue_position {
+                extra.insert("queuePosition".to_string(), serde_json::json!(position));
+            }
+            let quiet_until = self.quiet_until.filter(|_| {
+                queue_position.is_some()
+                    && self
+                        .run_queue
+                        .get(&t.target_id)
+                        .is_some_and(|q| q.trigger == RunTrigger::Schedule)
+            });
+            if let Some(until) = quiet_until {
+                extra.insert(
+                    "quietHoursUntil".to_string(),
+                    serde_json::json!(until.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)),
+                );
+            }
+            let resumed = t
+                .disabled_until
+                .is_some_and(|until| until.timestamp_millis() <= now_ms as i64);
+            if let Some(until) = t.disabled_until.filter(|_| !t.enabled && !resumed) {
+                extra.insert(
+                    "disabledUntil".to_string(),
+                    serde_json::json!(until.to_rfc3339()),
+                );
+            }
+            if let Some(v) = self
+                .verify
+                .get(&t.target_id)
+                .and_then(|v| serde_json::to_value(v).ok())
+            {
+                extra.insert(LAST_VERIFY_KEY.to_string(), v);
+            }
+            if self.verify.is_running(&t.target_id) {
+                extra.insert("verifyRunning".to_string(), serde_json::json!(true));
+            }
+            if !t.overlaps_with.is_empty() {
+                extra.insert(
+                    "overlapsWith".to_string(),
+                    serde_json::json!(t.overlaps_with),
+                );
+            }
+            if self.health_checked {
+                let period = t.schedule_period.filter(|_| t.enabled || resumed);
+                let now =
+                    chrono::DateTime::from_timestamp_millis(now_ms as i64).unwrap_or_default();
+                extra.insert(
+                    HEALTHY_KEY.to_string(),
+                    serde_json::json!(!health::is_stale(period, t.last_success_at, now)),
+                );
+            }
+            if let Some(at) = t.last_success_at {
+                extra.insert(
+                    LAST_SUCCESS_AT_KEY.to_string(),
+                    serde_json::json!(at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)),
+                );
+            }
             out_targets.push(TargetState {
                 target_id: t.target_id.clone(),
                 label: t.label.clone(),
                 source_path: t.source_path.clone(),
                 endpoint_id: t.endpoint_id.clone(),
-                enabled: t.enabled,
-                state: t.state.clone(),
+                enabled: t.enabled || resumed,
+                state: if quiet_until.is_some() {
+                    "waiting_quiet_hours".to_string()
+                } else if queue_position.is_some() {
+                    "queued".to_string()
+                } else {
+                    t.state.clone()
+                },
                 running_since: t.running_since,
                 up: Rate {
                     bytes_per_second: t.up_bps,
@@ -486,10 +979,28 @@ impl StatusRuntimeState {
                 },
                 progress: t.progress.clone(),
                 last_run: t.last_run.clone(),
-                extra: Default::default(),
+                extra,
             });
         }
 
+        let mut extra = std::collections::BTreeMap::new();
+        if let Some(group) = &self.backup_group
+            && let Ok(v) = serde_json::to_value(group)
+        {
+            extra.insert("backupGroup".to_string(), v);
+        }
+        if let Some(schedule) = &self.schedule
+            && let Ok(v) = serde_json::to_value(schedule)
+        {
+            extra.insert(SCHEDULE_STATUS_KEY.to_string(), v);
+        }
+        if let Some(bytes) = self.pending_deletion_bytes {
+            extra.insert(
+                PENDING_DELETION_BYTES_KEY.to_string(),
+                serde_json::json!(bytes),
+            );
+        }
+
         StatusSnapshot {
             type_: "status.snapshot".to_string(),
             schema_version: 1,
@@ -497,6 +1008,7 @@ impl StatusRuntimeState {
             source: StatusSource {
                 kind: "daemon".to_string(),
                 detail: Some("televybackupd (status.json)".to_string()),
+                degraded: false,
             },
             global: GlobalStatus {
                 up: Rate {
@@ -514,7 +1026,7 @@ impl StatusRuntimeState {
                 ui_uptime_seconds: None,
             },
             targets: out_targets,
-            extra: Default::default(),
+            extra,
         }
     }
 }
@@ -660,6 +1172,28 @@ impl ProgressSink for StatusProgressSink {
 mod tests {
     use super::*;
 
+    #[test]
+    fn daemon_args_accept_once_flags_only_with_once() {
+        let parse = |args: &[&str]| parse_daemon_args(args.iter().map(|a| a.to_string()));
+        assert_eq!(parse(&[]).unwrap(), None);
+        let once = parse(&[
+            "--once",
+            "--simulate-time",
+            "2024-06-01T02:00:00+08:00",
+            "--execute",
+        ])
+        .unwrap()
+        .unwrap();
+        assert!(once.execute);
+        assert_eq!(
+            once.simulate_time.unwrap().to_rfc3339(),
+            "2024-06-01T02:00:00+08:00"
+        );
+        assert!(parse(&["--execute"]).is_err());
+        assert!(parse(&["--once", "--simulate-time", "tomorrow"]).is_err());
+        assert!(parse(&["--verbose"]).is_err());
+    }
+
     #[cfg(unix)]
     #[test]
     fn daemon_instance_lock_is_exclusive() {
@@ -713,6 +1247,15 @@ mod tests {
         let mut st = StatusRuntimeState {
             target_order: vec!["t1".to_string()],
             targets: HashMap::new(),
+            backup_group: None,
+            schedule: None,
+            run_queue: RunQueue::default(),
+            verify: VerifyLedger::default(),
+            pending_deletion_bytes: None,
+            health_checked: false,
+            quiet_until: None,
+            progress_revision: 0,
+            status_write_interval: Duration::from_millis(250),
         };
         st.targets.insert(
             "t1".to_string(),
@@ -722,8 +1265,13 @@ mod tests {
                 source_path: "/tmp".to_string(),
                 endpoint_id: "ep".to_string(),
                 enabled: true,
+                disabled_until: None,
+                overlaps_with: Vec::new(),
+                schedule_period: None,
+                last_success_at: None,
                 state: "idle".to_string(),
                 running_since: None,
+                group_id: None,
                 progress: None,
                 last_run: None,
                 external_task_id: None,
@@ -740,7 +1288,7 @@ mod tests {
 
     fn progress(bytes_uploaded: u64) -> TaskProgress {
         TaskProgress {
-            phase: "upload".to_string(),
+            phase: Phase::Upload,
             files_total: None,
             files_done: None,
             source_files_total: None,
@@ -756,10 +1304,208 @@ mod tests {
             net_bytes_uploaded: None,
             bytes_downloaded: None,
             net_bytes_downloaded: None,
+            bytes_written: None,
             bytes_deduped: None,
+            bytes_total_estimated: None,
+            bytes_total: None,
+        }
+    }
+
+    fn target(id: &str, endpoint_id: &str, priority: i32) -> settings_config::Target {
+        settings_config::Target {
+            id: id.to_string(),
+            source_path: format!("/tmp/{id}"),
+            label: String::new(),
+            label_template: None,
+            endpoint_id: endpoint_id.to_string(),
+            enabled: true,
+            disabled_until: None,
+            priority,
+            schedule: None,
+            scan: None,
+            target_key: false,
+            allow_overlap: false,
+            min_expected_files: 0,
+            filters: Vec::new(),
+            sqlite_consistent: Vec::new(),
         }
     }
 
+    #[test]
+    fn backup_groups_are_per_endpoint_and_ordered_by_priority() {
+        let targets = [
+            target("a", "ep1", 0),
+            target("b", "ep2", 0),
+            target("c", "ep1", 5),
+            target("d", "ep1", 0),
+        ];
+        let due = targets
+            .iter()
+            .map(|t| (t, ScheduleSlot::Manual))
+            .collect::<Vec<_>>();
+
+        let groups = plan_backup_groups(due);
+        let ids = groups
+            .iter()
+            .map(|g| {
+                (
+                    g.endpoint_id.as_str(),
+                    g.runs
+                        .iter()
+                        .map(|(t, _)| t.id.as_str())
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![("ep1", vec!["c", "a", "d"]), ("ep2", vec!["b"])]);
+    }
+
+    #[test]
+    fn quiet_hours_hold_scheduled_runs_but_not_manual_ones() {
+        let mut st = state_one_target();
+        let mut t2 = st.targets.get("t1").unwrap().clone();
+        t2.target_id = "t2".to_string();
+        st.targets.insert("t2".to_string(), t2);
+        st.target_order.push("t2".to_string());
+        st.run_queue
+            .enqueue("t1", RunTrigger::Schedule, Some("grp_1"));
+        st.run_queue.enqueue("t2", RunTrigger::Ipc, None);
+
+        let until = chrono::Utc::now() + chrono::Duration::hours(2);
+        st.quiet_until = Some(until);
+        let snap = st.build_snapshot(now_unix_ms());
+        assert_eq!(snap.targets[0].state, "waiting_quiet_hours");
+        assert_eq!(
+            snap.targets[0].extra["quietHoursUntil"],
+            until.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+        );
+        assert_eq!(snap.targets[1].state, "queued");
+
+        let next = st.next_queued_run(2).unwrap();
+        assert_eq!(next.target_id, "t2");
+        assert!(st.next_queued_run(2).is_none());
+
+        st.quiet_until = None;
+        let snap = st.build_snapshot(now_unix_ms());
+        assert_eq!(snap.targets[0].state, "queued");
+        assert!(!snap.targets[0].extra.contains_key("quietHoursUntil"));
+        assert_eq!(st.next_queued_run(2).unwrap().target_id, "t1");
+    }
+
+    #[test]
+    fn backup_group_marks_queued_and_summarizes_results() {
+        let mut st = state_one_target();
+        let mut t2 = st.targets.get("t1").unwrap().clone();
+        t2.target_id = "t2".to_string();
+        st.targets.insert("t2".to_string(), t2);
+        st.target_order.push("t2".to_string());
+
+        let ids = vec!["t1".to_string(), "t2".to_string()];
+        st.mark_group_start("grp_1", "ep", &ids);
+        assert_eq!(st.targets.get("t1").unwrap().state, "queued");
+        assert_eq!(st.targets.get("t2").unwrap().state, "queued");
+
+        st.mark_run_start("t1");
+        st.mark_run_finish_success(
+            "t1",
+            "snp_1",
+            1.0,
+            1,
+            2,
+            3,
+            Some(0.5),
+            vec![televy_backup_core::full_disk_access::WARNING.to_string()],
+        );
+        let snap = st.build_snapshot(now_unix_ms());
+        assert_eq!(snap.targets[0].state, "idle");
+        let last_run = snap.targets[0].last_run.as_ref().unwrap();
+        assert_eq!(last_run.upload_duration_seconds, Some(0.5));
+        assert_eq!(last_run.warnings, vec!["full_disk_access_missing"]);
+        assert_eq!(snap.targets[1].state, "queued");
+        assert_eq!(snap.targets[1].extra["groupId"], "grp_1");
+        assert_eq!(snap.extra["backupGroup"]["state"], "running");
+
+        // t2 never starts (e.g. config error); it must not stay queued forever.
+        let summary = st.mark_group_finish("grp_1").unwrap();
+        assert_eq!(summary.results.len(), 1);
+        assert_eq!(summary.results[0].snapshot_id.as_deref(), Some("snp_1"));
+        assert_eq!(st.targets.get("t2").unwrap().state, "idle");
+        assert!(st.targets.get("t2").unwrap().group_id.is_none());
+    }
+
+    #[test]
+    fn status_marks_targets_with_overlapping_sources() {
+        let mut settings = settings_config::SettingsV2 {
+            targets: vec![
+                target("docs", "ep1", 0),
+                target("old", "ep2", 0),
+                target("other", "ep1", 0),
+            ],
+            ..Default::default()
+        };
+        settings.targets[1].source_path = "/tmp/docs/old".to_string();
+        let mut st = StatusRuntimeState::from_settings(&settings);
+        let snap = st.build_snapshot(now_unix_ms());
+        assert_eq!(
+            snap.targets[0].extra["overlapsWith"],
+            serde_json::json!(["old"])
+        );
+        assert_eq!(
+            snap.targets[1].extra["overlapsWith"],
+            serde_json::json!(["docs"])
+        );
+        assert!(!snap.targets[2].extra.contains_key("overlapsWith"));
+
+        settings.targets[1].allow_overlap = true;
+        st.apply_settings(&settings);
+        let snap = st.build_snapshot(now_unix_ms());
+        assert!(
+            snap.targets
+                .iter()
+                .all(|t| !t.extra.contains_key("overlapsWith"))
+        );
+    }
+
+    #[test]
+    fn status_reports_scheduled_targets_behind_schedule_as_unhealthy() {
+        let mut settings = settings_config::SettingsV2 {
+            targets: vec![
+                target("fresh", "ep1", 0),
+                target("old", "ep1", 0),
+                target("manual", "ep1", 0),
+            ],
+            ..Default::default()
+        };
+        settings.schedule.enabled = true;
+        settings.schedule.kind = "daily".to_string();
+        settings.targets[2].schedule = Some(settings_config::TargetScheduleOverride {
+            enabled: Some(false),
+            ..Default::default()
+        });
+        let mut st = StatusRuntimeState::from_settings(&settings);
+        let now = chrono::Utc::now();
+        // Unknown until the index DBs were read.
+        let snap = st.build_snapshot(now.timestamp_millis() as u64);
+        assert!(snap.targets.iter().all(|t| t.healthy().is_none()));
+
+        st.set_last_successes(&HashMap::from([
+            ("fresh".to_string(), now - chrono::TimeDelta::hours(3)),
+            ("old".to_string(), now - chrono::TimeDelta::days(3)),
+        ]));
+        let snap = st.build_snapshot(now.timestamp_millis() as u64);
+        assert_eq!(
+            snap.targets.iter().map(|t| t.healthy()).collect::<Vec<_>>(),
+            vec![Some(true), Some(false), Some(true)]
+        );
+        assert!(snap.targets[1].extra.contains_key(LAST_SUCCESS_AT_KEY));
+        assert!(!snap.targets[2].extra.contains_key(LAST_SUCCESS_AT_KEY));
+
+        st.mark_run_start("old");
+        st.mark_run_finish_success("old", "s1", 1.0, 1, 1, 0, None, Vec::new());
+        let snap = st.build_snapshot(chrono::Utc::now().timestamp_millis() as u64);
+        assert_eq!(snap.targets[1].healthy(), Some(true));
+    }
+
     #[test]
     fn up_total_tracks_progress_bytes_uploaded() {
         let mut st = state_one_target();
@@ -842,82 +1588,177 @@ mod tests {
     }
 }
 
+fn spawn_remote_rpc(
+    listen: &str,
+    remote: &settings_config::Remote,
+    config_root: &Path,
+    data_root: &Path,
+    settings: Arc<RwLock<settings_config::SettingsV2>>,
+    status_snapshot_fn: Arc<dyn Fn() -> (StatusSnapshot, bool) + Send + Sync>,
+) -> std::io::Result<remote_rpc::RemoteRpcServerHandle> {
+    let addr = listen
+        .parse()
+        .map_err(|e| std::io::Error::new(ErrorKind::InvalidInput, format!("{e}")))?;
+    let token = remote.token.clone().unwrap_or_default();
+    let identity = if remote.tls {
+        Some(televy_backup_core::remote::load_or_create_tls_identity(
+            config_root,
+        )?)
+    } else {
+        None
+    };
+    let handle = remote_rpc::spawn_remote_rpc_server(
+        addr,
+        token,
+        identity.as_ref(),
+        data_root.to_path_buf(),
+        settings,
+        status_snapshot_fn,
+    )?;
+    let fingerprint = identity.as_ref().map(|i| i.fingerprint());
+    eprintln!(
+        "remote.listening: addr={} tls={} fingerprint={}",
+        handle.local_addr(),
+        remote.tls,
+        fingerprint.as_deref().unwrap_or("-")
+    );
+    tracing::info!(
+        event = "remote.listening",
+        addr = %handle.local_addr(),
+        tls = remote.tls,
+        fingerprint = fingerprint.as_deref(),
+        "remote.listening"
+    );
+    Ok(handle)
+}
+
+fn run_label(
+    settings: &settings_config::SettingsV2,
+    target: &settings_config::Target,
+    trigger: RunTrigger,
+    device: Option<&televy_backup_core::device::DeviceIdentity>,
+) -> String {
+    let (schedule, fallback) = match trigger {
+        RunTrigger::Manual | RunTrigger::Ipc => ("manual".to_string(), "manual".to_string()),
+        RunTrigger::Schedule => {
+            let kind =
+                settings_config::effective_schedule(&settings.schedule, target.schedule.as_ref())
+                    .kind;
+            let fallback = if target.label.trim().is_empty() {
+                "scheduled".to_string()
+            } else {
+                target.label.clone()
+            };
+            (kind, fallback)
+        }
+    };
+    let Some(template) = target.label_template.as_deref() else {
+        return fallback;
+    };
+    let hostname = televy_backup_core::device::host_name().unwrap_or_default();
+    let vars = televy_backup_core::label_template::LabelVars {
+        schedule: &schedule,
+        local_time: chrono::Local::now().naive_local(),
+        hostname: &hostname,
+        target_id: &target.id,
+        device_name: device.map(|d| d.device_name.as_str()).unwrap_or_default(),
+    };
+    match televy_backup_core::label_template::expand_label_template(template, &vars) {
+        Ok(label) => label,
+        Err(e) => {
+            tracing::warn!(
+                event = "backup.label_template_failed",
+                target_id = %target.id,
+                error = %e,
+                "backup.label_template_failed"
+            );
+            fallback
+        }
+    }
+}
+
+fn status_write_interval(settings: &settings_config::SettingsV2) -> Duration {
+    Duration::from_millis(settings.performance.status_write_interval_ms.into())
+}
+
+/// How often the status writer logs its counters (`status.write_stats`) while progress comes in.
+const STATUS_WRITE_STATS_INTERVAL: Duration = Duration::from_secs(60);
+
 async fn status_writer_loop(state: Arc<Mutex<StatusRuntimeState>>, status_path: PathBuf) {
-    let mut last_write = Instant::now()
-        .checked_sub(Duration::from_secs(3600))
-        .unwrap_or_else(Instant::now);
+    let mut scheduler = StatusWriteScheduler::new(Duration::from_millis(250));
+    let mut writer = Some(StatusFileWriter::new(&status_path));
+    let mut stats_logged_at = Instant::now();
+    let mut stats_logged = scheduler.stats();
 
     loop {
-        let has_running = state
-            .lock()
-            .ok()
-            .map(|st| st.has_running())
-            .unwrap_or(false);
-
         let now = Instant::now();
-        let min_interval = if has_running {
-            Duration::from_millis(200)
-        } else {
-            Duration::from_secs(1)
+        let polled = state.lock().ok().map(|mut st| {
+            scheduler.set_interval(st.status_write_interval);
+            let has_running = st.has_running();
+            let snapshot = scheduler.should_write(now, st.status_marker()).map(|due| {
+                st.tick_rates_at(now);
+                (due, st.build_snapshot(now_unix_ms()))
+            });
+            (has_running, snapshot)
+        });
+        let Some((has_running, snapshot_opt)) = polled else {
+            sleep(Duration::from_millis(100)).await;
+            continue;
         };
-        let should_write = now.duration_since(last_write) >= min_interval;
-        if should_write {
-            let snapshot_opt = {
-                match state.lock() {
-                    Ok(mut st) => {
-                        st.tick_rates_at(now);
-                        Some(st.build_snapshot(now_unix_ms()))
-                    }
-                    Err(_) => None,
-                }
-            };
-            let snapshot = match snapshot_opt {
-                Some(s) => s,
-                None => {
-                    sleep(Duration::from_millis(100)).await;
-                    continue;
-                }
-            };
 
+        if let Some((due, snapshot)) = snapshot_opt {
             // Writing status snapshots is sync I/O + fsync-heavy; keep it off Tokio worker threads.
-            // Status snapshots are "best-effort" and do not need durability guarantees; atomic rename is sufficient.
+            // The file is always synced before it is renamed into place, so a crash can't leave a
+            // truncated status.json; the rename itself is made durable for transitions, while a
+            // lost progress or heartbeat rename only leaves the previous generation in place.
             let options = StatusWriteOptions {
-                fsync_file: false,
-                fsync_dir: false,
+                fsync_file: true,
+                fsync_dir: due == StatusWrite::Transition,
             };
-            let status_path_for_write = status_path.clone();
-            let status_path_for_log = status_path.clone();
+            let mut file_writer = writer
+                .take()
+                .unwrap_or_else(|| StatusFileWriter::new(&status_path));
             let res = tokio::task::spawn_blocking(move || {
-                write_status_snapshot_json_atomic_with_options(
-                    &status_path_for_write,
-                    &snapshot,
-                    options,
-                )
+                let res = file_writer.write(&snapshot, options);
+                (file_writer, res)
             })
             .await;
-            match res {
-                Ok(Ok(())) => {}
-                Ok(Err(e)) => {
-                    tracing::warn!(
-                        event = "status.write_failed",
-                        error = %e,
-                        path = %status_path_for_log.display(),
-                        "status.write_failed"
-                    );
-                }
-                Err(e) => {
-                    tracing::warn!(
-                        event = "status.write_failed",
-                        error = %e,
-                        path = %status_path_for_log.display(),
-                        "status.write_failed"
-                    );
+            let error = match res {
+                Ok((file_writer, res)) => {
+                    writer = Some(file_writer);
+                    res.err().map(|e| e.to_string())
                 }
+                Err(e) => Some(e.to_string()),
+            };
+            if let Some(error) = error {
+                tracing::warn!(
+                    event = "status.write_failed",
+                    error = %error,
+                    path = %status_path.display(),
+                    "status.write_failed"
+                );
             }
-            last_write = Instant::now();
         }
 
-        let tick = if has_running {
+        // Heartbeat-only periods are not logged, so an idle daemon stays quiet.
+        let stats = scheduler.stats();
+        if stats.suppressed != stats_logged.suppressed
+            && stats_logged_at.elapsed() >= STATUS_WRITE_STATS_INTERVAL
+        {
+            tracing::info!(
+                event = "status.write_stats",
+                writes = stats.writes - stats_logged.writes,
+                heartbeats = stats.heartbeats - stats_logged.heartbeats,
+                suppressed = stats.suppressed - stats_logged.suppressed,
+                writes_total = stats.writes,
+                suppressed_total = stats.suppressed,
+                "status.write_stats"
+            );
+            stats_logged = stats;
+            stats_logged_at = Instant::now();
+        }
+
+        let tick = if has_running {
             Duration::from_millis(50)
         } else {
             Duration::from_millis(200)
@@ -1000,33 +1841,63 @@ fn acquire_daemon_instance_lock(data_root: &Path) -> std::io::Result<File> {
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let once = match parse_daemon_args(std::env::args().skip(1)) {
+        Ok(once) => once,
+        Err(e) => {
+            eprintln!("error: {e}\n{DAEMON_USAGE}");
+            std::process::exit(2);
+        }
+    };
+
     let config_dir = std::env::var("TELEVYBACKUP_CONFIG_DIR")
         .ok()
         .map(PathBuf::from);
-    let data_dir = std::env::var("TELEVYBACKUP_DATA_DIR")
-        .ok()
-        .map(PathBuf::from);
 
     let config_root = config_dir.unwrap_or_else(default_config_dir);
-    let data_root = data_dir.unwrap_or_else(default_data_dir);
+    let (data_root, _) =
+        televy_backup_core::data_dir::resolve_data_dir(None, &config_root, default_data_dir)?;
     let index_dir = data_root.join("index");
+
+    // A dry `--once` only reads settings and index DBs, so it may run next to the daemon.
+    if let Some(once) = once.as_ref().filter(|o| !o.execute) {
+        let settings = settings_config::load_settings_v2(&config_root)?;
+        settings_config::validate_settings_schema_v2(&settings)?;
+        schedule_once(&settings, &data_root, once).await?;
+        return Ok(());
+    }
+
     #[cfg(unix)]
     let _daemon_instance_lock = acquire_daemon_instance_lock(&data_root)?;
 
     let config_path = settings_config::config_path(&config_root);
     let mut settings = settings_config::load_settings_v2(&config_root)?;
     let _ = CONFIG_ROOT_CACHE.set(config_root.clone());
-    settings_config::validate_settings_schema_v2(&settings)?;
+    let _ = DATA_ROOT_CACHE.set(data_root.clone());
+    log_settings_warnings(&settings_config::validate_settings_schema_v2(&settings)?);
     let mut last_config_mtime = file_mtime(&config_path);
 
+    // `--once --execute`: queue the targets due at the simulated time, run them, then exit.
+    let mut once_due = match once.as_ref() {
+        Some(once) => {
+            let due = schedule_once(&settings, &data_root, once).await?;
+            if due.is_empty() {
+                return Ok(());
+            }
+            Some(due)
+        }
+        None => None,
+    };
+
     let status_state = Arc::new(Mutex::new(StatusRuntimeState::from_settings(&settings)));
+    if let Ok(mut st) = status_state.lock() {
+        st.verify = VerifyLedger::load(&data_root);
+    }
     let status_path = status_json_path(&data_root);
     tokio::spawn(status_writer_loop(status_state.clone(), status_path));
 
     let ipc_socket_path = status_ipc_socket_path(&data_root);
     let ipc_state = status_state.clone();
-    let _status_ipc_server = match status_ipc::spawn_status_ipc_server(
-        ipc_socket_path.clone(),
+    let status_snapshot_fn: Arc<dyn Fn() -> (StatusSnapshot, bool) + Send + Sync> =
         Arc::new(move || {
             let now_ms = now_unix_ms();
             match ipc_state.lock() {
@@ -1047,6 +1918,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         source: StatusSource {
                             kind: "daemon".to_string(),
                             detail: Some("televybackupd (ipc)".to_string()),
+                            degraded: false,
                         },
                         global: GlobalStatus {
                             up: Rate {
@@ -1065,7 +1937,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     (snap, false)
                 }
             }
-        }),
+        });
+    let _status_ipc_server = match status_ipc::spawn_status_ipc_server(
+        ipc_socket_path.clone(),
+        status_snapshot_fn.clone(),
     ) {
         Ok(h) => Some(h),
         Err(e) => {
@@ -1106,11 +1981,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let control_ipc_settings = Arc::new(RwLock::new(settings.clone()));
 
     let control_socket_path = televy_backup_core::control::control_ipc_socket_path(&data_root);
+    let (index_sync_tx, mut index_sync_rx) = tokio::sync::mpsc::unbounded_channel();
     let _control_ipc_server = match control_ipc::spawn_control_ipc_server(
         control_socket_path.clone(),
         config_root.clone(),
+        data_root.clone(),
         control_ipc_settings.clone(),
         status_state.clone(),
+        index_sync_tx,
     ) {
         Ok(h) => Some(h),
         Err(e) => {
@@ -1129,7 +2007,32 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     };
 
-    let mut has_enabled_targets = settings.targets.iter().any(|t| t.enabled);
+    // Read once at start: changing `[remote]` takes a daemon restart.
+    let _remote_rpc_server = match settings.remote.listen.as_deref() {
+        Some(listen) => match spawn_remote_rpc(
+            listen,
+            &settings.remote,
+            &config_root,
+            &data_root,
+            control_ipc_settings.clone(),
+            status_snapshot_fn.clone(),
+        ) {
+            Ok(h) => Some(h),
+            Err(e) => {
+                eprintln!("WARN: remote.bind_failed: listen={listen} error={e}");
+                tracing::warn!(
+                    event = "remote.bind_failed",
+                    error = %e,
+                    listen,
+                    "remote.bind_failed"
+                );
+                None
+            }
+        },
+        None => None,
+    };
+
+    let mut has_enabled_targets = has_schedulable_targets(&settings);
     if has_enabled_targets {
         if settings.telegram.mtproto.api_id <= 0 {
             return Err("telegram.mtproto.api_id must be > 0".into());
@@ -1155,6 +2058,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut vault_key_last_error: Option<VaultKeyLoadError> = None;
 
     let mut secrets_store: Option<televy_backup_core::secrets::SecretsStore> = None;
+    // Env-var and `secrets.d/` secrets shadow `secrets.enc`; see `SecretsProvider`.
+    let secrets_provider = televy_backup_core::secrets::SecretsProvider::new(&config_root);
     let mut master_key: Option<[u8; 32]> = None;
     let mut api_hash: Option<String> = None;
 
@@ -1174,9 +2079,32 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     let mut schedule_state_by_target = HashMap::<String, TargetScheduleState>::new();
-    let mut storage_by_endpoint = HashMap::<String, TelegramMtProtoStorage>::new();
+    let mut last_schedule_check: Option<chrono::DateTime<chrono::Local>> = None;
+    let mut missed_slots_caught_up = 0u64;
+    let mut active_backup_group: Option<ActiveBackupGroup> = None;
+    let mut storage_pool = mtproto_pool::MtProtoStoragePool::default();
+    let mut fs_watchers = fs_watch::FsWatchers::new(&data_root);
+    let mut last_run_log_prune: Option<Instant> = None;
+    let mut last_verify_check: Option<Instant> = None;
+    let mut last_deletion_queue_check: Option<Instant> = None;
+    let mut last_health_check: Option<Instant> = None;
+    let mut digest_ledger = digest::DigestLedger::load(&data_root);
+    let stop = stop_signal_token();
+    // Shared by every backup; `follow_settings` keeps its limit in step with quiet hours and
+    // re-reads config.toml itself, so edits reach runs already going.
+    let upload_throttle = Arc::new(quiet_hours::UploadThrottle::default());
+    tokio::spawn(quiet_hours::follow_settings(
+        config_root.clone(),
+        Arc::clone(&upload_throttle),
+        stop.clone(),
+    ));
 
     loop {
+        if stop.is_cancelled() {
+            tracing::warn!(event = "daemon.stopping", "daemon.stopping");
+            storage_pool.clear("stop_signal").await;
+            return Ok(());
+        }
         let now = chrono::Local::now();
 
         // Hot-reload settings + secrets when files change. This avoids confusing situations where the
@@ -1192,35 +2120,34 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 if config_changed {
                     match settings_config::load_settings_v2(&config_root) {
                         Ok(new_settings) => {
-                            if let Err(e) =
-                                settings_config::validate_settings_schema_v2(&new_settings)
-                            {
-                                tracing::warn!(
+                            match settings_config::validate_settings_schema_v2(&new_settings) {
+                                Err(e) => tracing::warn!(
                                     event = "config.reload_failed",
                                     error = %e,
                                     path = %config_path.display(),
                                     "config.reload_failed"
-                                );
-                            } else {
-                                settings = new_settings;
-                                has_enabled_targets = settings.targets.iter().any(|t| t.enabled);
-                                *control_ipc_settings.write().await = settings.clone();
-                                last_config_mtime = config_mtime;
-                                clear_mtproto_storage_cache(
-                                    &mut storage_by_endpoint,
-                                    "config_reloaded",
-                                )
-                                .await;
-                                schedule_state_by_target
-                                    .retain(|k, _| settings.targets.iter().any(|t| t.id == *k));
-                                if let Ok(mut st) = status_state.lock() {
-                                    st.apply_settings(&settings);
+                                ),
+                                Ok(warnings) => {
+                                    log_settings_warnings(&warnings);
+                                    settings = new_settings;
+                                    has_enabled_targets = has_schedulable_targets(&settings);
+                                    *control_ipc_settings.write().await = settings.clone();
+                                    last_config_mtime = config_mtime;
+                                    // Changed endpoints are reconnected on next use (see the pool's
+                                    // connection fingerprint).
+                                    storage_pool.retain_endpoints(&settings).await;
+                                    schedule_state_by_target
+                                        .retain(|k, _| settings.targets.iter().any(|t| t.id == *k));
+                                    if let Ok(mut st) = status_state.lock() {
+                                        st.apply_settings(&settings);
+                                    }
+                                    last_health_check = None;
+                                    tracing::info!(
+                                        event = "config.reloaded",
+                                        path = %config_path.display(),
+                                        "config.reloaded"
+                                    );
                                 }
-                                tracing::info!(
-                                    event = "config.reloaded",
-                                    path = %config_path.display(),
-                                    "config.reloaded"
-                                );
                             }
                         }
                         Err(e) => {
@@ -1243,7 +2170,6 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     master_key = None;
                     api_hash = None;
                     last_secrets_crypto_error_mtime = None;
-                    clear_mtproto_storage_cache(&mut storage_by_endpoint, "secrets_changed").await;
 
                     tracing::info!(
                         event = "secrets.changed",
@@ -1254,6 +2180,48 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         }
 
+        fs_watchers.sync(&settings);
+        fs_watchers.persist_if_due();
+
+        if last_run_log_prune.is_none_or(|t| t.elapsed() >= RUN_LOG_PRUNE_INTERVAL) {
+            last_run_log_prune = Some(Instant::now());
+            prune_run_logs_best_effort(&data_root, &settings);
+        }
+
+        let quiet_tz = ScheduleTz::parse(&settings.schedule.timezone).unwrap_or(ScheduleTz::Local);
+        let quiet_until = quiet_hours::quiet_until(
+            &settings.quiet_hours,
+            &quiet_tz,
+            now.with_timezone(&chrono::Utc),
+        );
+        if let Ok(mut st) = status_state.lock()
+            && st.quiet_until != quiet_until
+        {
+            match quiet_until {
+                Some(until) if st.quiet_until.is_none() => tracing::info!(
+                    event = "quiet_hours.start",
+                    until = %until.to_rfc3339(),
+                    "quiet_hours.start"
+                ),
+                None => tracing::info!(event = "quiet_hours.end", "quiet_hours.end"),
+                Some(_) => {}
+            }
+            st.quiet_until = quiet_until;
+        }
+
+        // Health and the digest only read local state, so they don't wait for the vault key or
+        // for running backups.
+        if last_health_check.is_none_or(|t| t.elapsed() >= digest::HEALTH_CHECK_INTERVAL) {
+            last_health_check = Some(Instant::now());
+            let last_success = digest::last_successful_backups(&settings, &data_root).await;
+            if let Ok(mut st) = status_state.lock() {
+                st.set_last_successes(&last_success);
+            }
+            if once.is_none() {
+                digest::send_if_due(&settings, &data_root, &mut digest_ledger, &last_success).await;
+            }
+        }
+
         // Manual backups are triggered by the UI via a control file under the configured data dir.
         //
         // We'll also use the trigger file's mtime as a coarse "user intent" signal: if Keychain
@@ -1372,7 +2340,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             // Master key is required for all backup/restore operations. In dev mode (keychain disabled),
             // auto-generate a master key on first run if no secrets file exists yet.
             if master_key.is_none() {
-                let v = get_secret_from_store(store, MASTER_KEY_KEY);
+                let v = get_secret_from_store(&secrets_provider, store, MASTER_KEY_KEY);
                 match v {
                     Some(b64) => {
                         if let Ok(k) = decode_base64_32(&b64) {
@@ -1386,12 +2354,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 std::io::Error::other(format!("getrandom failed: {e}"))
                             })?;
                             let b64 = televy_backup_core::secrets::vault_key_to_base64(&bytes);
-                            store.set(MASTER_KEY_KEY, b64.clone());
-                            televy_backup_core::secrets::save_secrets_store(
+                            secrets_provider.write(
+                                MASTER_KEY_KEY,
+                                &b64,
+                                store,
                                 &secrets_path,
                                 &vault_key,
-                                store,
                             )?;
+                            record_audit(
+                                AuditActor::Daemon,
+                                audit::AUDIT_OP_SECRET_SET,
+                                serde_json::json!({ "key": MASTER_KEY_KEY }),
+                            );
                             secrets_file_exists = true;
                             master_key = Some(bytes);
                         }
@@ -1401,7 +2375,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
 
             if api_hash.is_none() && has_enabled_targets {
-                api_hash = get_secret_from_store(store, &settings.telegram.mtproto.api_hash_key);
+                api_hash = get_secret_from_store(
+                    &secrets_provider,
+                    store,
+                    &settings.telegram.mtproto.api_hash_key,
+                );
             }
         }
 
@@ -1416,7 +2394,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         let manual_triggered = match maybe_consume_manual_trigger_file(
             &manual_trigger_path,
-            manual_trigger_present && can_attempt_run,
+            manual_trigger_present && can_attempt_run && once.is_none(),
         ) {
             Ok(true) => {
                 tracing::info!(
@@ -1444,8 +2422,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             || settings.telegram.mtproto.api_hash_key.trim().is_empty()
         {
             // Keep the daemon alive so the UI can show status, but skip running backups until config is fixed.
-            clear_mtproto_storage_cache(&mut storage_by_endpoint, "invalid_mtproto_api_config")
-                .await;
+            storage_pool.clear("invalid_mtproto_api_config").await;
+            reject_index_sync_requests(
+                &mut index_sync_rx,
+                "telegram.mtproto api config is invalid",
+            );
             sleep(Duration::from_secs(1)).await;
             continue;
         }
@@ -1469,7 +2450,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     "run.skip"
                 );
             }
-            clear_mtproto_storage_cache(&mut storage_by_endpoint, "secrets_unavailable").await;
+            storage_pool.clear("secrets_unavailable").await;
+            reject_index_sync_requests(&mut index_sync_rx, "secrets are not available yet");
             sleep(Duration::from_secs(1)).await;
             continue;
         }
@@ -1482,59 +2464,140 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             .clone()
             .expect("api_hash must be available when starting runs");
 
-        for target in &settings.targets {
-            if !target.enabled {
-                continue;
+        // Slots are consumed when queued, so a queue entry removed over IPC does not come back
+        // within the same slot.
+        let due = if let Some(once_due) = once_due.take() {
+            once_due
+                .iter()
+                .filter_map(|(id, slot)| {
+                    settings
+                        .targets
+                        .iter()
+                        .find(|t| t.id == *id)
+                        .map(|t| (t, *slot))
+                })
+                .collect::<Vec<_>>()
+        } else if once.is_some() {
+            Vec::new()
+        } else {
+            // Validated with the settings; `local` keeps an unreadable zone from stopping backups.
+            let tz = ScheduleTz::parse(&settings.schedule.timezone).unwrap_or(ScheduleTz::Local);
+            let schedule_now = now.with_timezone(&tz);
+            if let Some(last) = last_schedule_check {
+                let gap = now.signed_duration_since(last);
+                if gap < chrono::Duration::zero() {
+                    tracing::warn!(
+                        event = "schedule.clock_went_back",
+                        by_seconds = -gap.num_seconds(),
+                        "schedule.clock_went_back"
+                    );
+                } else if gap.num_seconds() > schedule::MISSED_SLOT_AFTER_SECS {
+                    tracing::info!(
+                        event = "schedule.tick_gap",
+                        gap_seconds = gap.num_seconds(),
+                        "schedule.tick_gap"
+                    );
+                }
             }
+            let schedule_since = last_schedule_check
+                .map(|t| t.with_timezone(&tz))
+                .unwrap_or(schedule_now - chrono::Duration::minutes(1));
+            last_schedule_check = Some(now);
+            let checks = schedule::evaluate_schedule(
+                &settings,
+                &mut schedule_state_by_target,
+                &schedule_since,
+                &schedule_now,
+                manual_triggered,
+            )?;
+            for check in &checks {
+                match check.outcome {
+                    ScheduleOutcome::SkippedDisabled(slot) => {
+                        record_skipped_disabled_run(&data_root, check.target, slot);
+                    }
+                    ScheduleOutcome::Missed(slot) => {
+                        tracing::warn!(
+                            event = "schedule.slot_missed",
+                            target_id = %check.target.id,
+                            slot = %slot,
+                            "schedule.slot_missed"
+                        );
+                    }
+                    ScheduleOutcome::Due(slot) if check.caught_up => {
+                        missed_slots_caught_up += 1;
+                        tracing::info!(
+                            event = "schedule.slot_caught_up",
+                            target_id = %check.target.id,
+                            slot = %slot,
+                            "schedule.slot_caught_up"
+                        );
+                    }
+                    _ => {}
+                }
+            }
+            if let Ok(mut st) = status_state.lock() {
+                st.schedule = Some(ScheduleStatus {
+                    timezone: tz.resolved_name(),
+                    catch_up: settings.schedule.catch_up,
+                    tick_interval_seconds: SCHEDULER_TICK_INTERVAL.as_secs(),
+                    last_tick_at: Some(now_unix_ms()),
+                    missed_slots_caught_up,
+                });
+            }
+            checks
+                .into_iter()
+                .filter_map(|check| match check.outcome {
+                    ScheduleOutcome::Due(slot) => Some((check.target, slot)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
 
-            let state = schedule_state_by_target
-                .entry(target.id.clone())
-                .or_default();
-
-            let scheduled_slot = if manual_triggered {
-                Some(ScheduleSlot::Manual)
-            } else {
-                let eff = settings_config::effective_schedule(
-                    &settings.schedule,
-                    target.schedule.as_ref(),
-                );
-                if !eff.enabled {
-                    None
-                } else {
-                    match eff.kind.as_str() {
-                        "hourly" => {
-                            if now.minute() != eff.hourly_minute as u32 {
-                                None
-                            } else {
-                                let key = (now.year(), now.month(), now.day(), now.hour());
-                                if state.last_hourly == Some(key) {
-                                    None
-                                } else {
-                                    Some(ScheduleSlot::Hourly(key))
-                                }
-                            }
-                        }
-                        "daily" => {
-                            let (hh, mm) = parse_hhmm(&eff.daily_at)?;
-                            if now.hour() != hh as u32 || now.minute() != mm as u32 {
-                                None
-                            } else {
-                                let key = (now.year(), now.month(), now.day());
-                                if state.last_daily == Some(key) {
-                                    None
-                                } else {
-                                    Some(ScheduleSlot::Daily(key))
-                                }
-                            }
-                        }
-                        other => {
-                            return Err(format!("unsupported schedule.kind: {other}").into());
-                        }
+        if !due.is_empty()
+            && let Ok(mut st) = status_state.lock()
+        {
+            for group in plan_backup_groups(due) {
+                let group_id = format!("grp_{}", Uuid::new_v4());
+                for (target, slot) in group.runs {
+                    let trigger = match slot {
+                        ScheduleSlot::Manual => RunTrigger::Manual,
+                        _ => RunTrigger::Schedule,
+                    };
+                    let (queued, position, added) =
+                        st.run_queue.enqueue(&target.id, trigger, Some(&group_id));
+                    if added {
+                        tracing::info!(
+                            event = "queue.enqueued",
+                            task_id = %queued.task_id,
+                            target_id = %target.id,
+                            trigger = trigger.as_str(),
+                            position,
+                            "queue.enqueued"
+                        );
                     }
                 }
-            };
+            }
+        }
 
-            let Some(scheduled_slot) = scheduled_slot else {
+        // Start queued runs in order while fewer than `schedule.max_concurrent_runs` are active.
+        // The daemon runs its own backups one at a time; the limit also counts CLI runs.
+        loop {
+            let next = status_state.lock().ok().and_then(|mut st| {
+                let next = st.next_queued_run(settings.schedule.max_concurrent_runs);
+                advance_backup_group(&mut st, &mut active_backup_group, next.as_ref());
+                next
+            });
+            let Some(queued) = next else {
+                break;
+            };
+            let Some(target) = settings.targets.iter().find(|t| t.id == queued.target_id) else {
+                tracing::warn!(
+                    event = "queue.dropped",
+                    task_id = %queued.task_id,
+                    target_id = %queued.target_id,
+                    reason = "unknown_target",
+                    "queue.dropped"
+                );
                 continue;
             };
 
@@ -1547,12 +2610,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     event = "run.finish",
                     kind = "backup",
                     status = "failed",
-                    error_code = "config.invalid",
+                    error_code = ErrorCode::ConfigInvalid.as_str(),
                     error_message = "target references unknown endpoint_id",
                     target_id = %target.id,
                     endpoint_id = %target.endpoint_id,
                     "run.finish"
                 );
+                if let Ok(mut st) = status_state.lock() {
+                    st.record_group_result(
+                        &target.id,
+                        "failed",
+                        None,
+                        Some(ErrorCode::ConfigInvalid.as_str()),
+                    );
+                }
                 continue;
             };
 
@@ -1561,107 +2632,134 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     event = "run.finish",
                     kind = "backup",
                     status = "failed",
-                    error_code = "config.invalid",
+                    error_code = ErrorCode::ConfigInvalid.as_str(),
                     error_message = "endpoint chat_id is empty",
                     target_id = %target.id,
                     endpoint_id = %ep.id,
                     "run.finish"
                 );
+                if let Ok(mut st) = status_state.lock() {
+                    st.record_group_result(
+                        &target.id,
+                        "failed",
+                        None,
+                        Some(ErrorCode::ConfigInvalid.as_str()),
+                    );
+                }
                 continue;
             }
 
             let bot_token = secrets_store
                 .as_ref()
-                .and_then(|s| get_secret_from_store(s, &ep.bot_token_key));
+                .and_then(|s| get_secret_from_store(&secrets_provider, s, &ep.bot_token_key));
             let Some(bot_token) = bot_token else {
                 tracing::error!(
                     event = "run.finish",
                     kind = "backup",
                     status = "failed",
-                    error_code = "telegram.unauthorized",
+                    error_code = ErrorCode::TelegramUnauthorized.as_str(),
                     error_message = "bot token missing",
                     target_id = %target.id,
                     endpoint_id = %ep.id,
                     "run.finish"
                 );
+                if let Ok(mut st) = status_state.lock() {
+                    st.record_group_result(
+                        &target.id,
+                        "failed",
+                        None,
+                        Some(ErrorCode::TelegramUnauthorized.as_str()),
+                    );
+                }
                 continue;
             };
 
-            if !storage_by_endpoint.contains_key(&ep.id) {
-                let session = match secrets_store
-                    .as_ref()
-                    .and_then(|s| get_secret_from_store(s, &ep.mtproto.session_key))
-                {
-                    Some(b64) if !b64.trim().is_empty() => {
-                        Some(base64::engine::general_purpose::STANDARD.decode(b64.as_bytes())?)
-                    }
-                    _ => None,
-                };
+            let Some(api_hash) =
+                endpoint_api_hash(&secrets_provider, secrets_store.as_ref(), ep, &api_hash)
+            else {
+                tracing::error!(
+                    event = "run.finish",
+                    kind = "backup",
+                    status = "failed",
+                    error_code = ErrorCode::TelegramMtprotoMissingApiHash.as_str(),
+                    error_message = "mtproto api_hash missing",
+                    target_id = %target.id,
+                    endpoint_id = %ep.id,
+                    "run.finish"
+                );
+                if let Ok(mut st) = status_state.lock() {
+                    st.record_group_result(
+                        &target.id,
+                        "failed",
+                        None,
+                        Some(ErrorCode::TelegramMtprotoMissingApiHash.as_str()),
+                    );
+                }
+                continue;
+            };
 
-                let cache_dir = data_root.join("cache").join("mtproto").join(&ep.id);
-                std::fs::create_dir_all(&cache_dir)?;
-                let provider = settings_config::endpoint_provider(&ep.id);
+            let session = match secrets_store
+                .as_ref()
+                .and_then(|s| get_secret_from_store(&secrets_provider, s, &ep.mtproto.session_key))
+            {
+                Some(b64) if !b64.trim().is_empty() => {
+                    Some(base64::engine::general_purpose::STANDARD.decode(b64.as_bytes())?)
+                }
+                _ => None,
+            };
 
-                let storage = TelegramMtProtoStorage::connect(TelegramMtProtoStorageConfig {
-                    provider,
-                    api_id: settings.telegram.mtproto.api_id,
-                    api_hash: api_hash.clone(),
-                    bot_token: bot_token.clone(),
-                    chat_id: ep.chat_id.clone(),
-                    session,
-                    cache_dir,
-                    min_delay_ms: Some(ep.rate_limit.min_delay_ms as u64),
-                    max_concurrent_uploads: Some(ep.rate_limit.max_concurrent_uploads as usize),
-                    helper_path: None,
-                })
+            storage_pool
+                .ensure_connected(
+                    &ep.id,
+                    endpoint_storage_config(
+                        &settings, ep, &data_root, &api_hash, &bot_token, session,
+                    )?,
+                )
                 .await?;
 
-                storage_by_endpoint.insert(ep.id.clone(), storage);
-            }
-
-            let storage = match storage_by_endpoint.get(&ep.id) {
+            let storage = match storage_pool.get(&ep.id) {
                 Some(s) => s,
                 None => continue,
             };
 
-            // Only consume the schedule slot once all required config/secrets are available
-            // and the endpoint storage is ready.
-            match scheduled_slot {
-                ScheduleSlot::Hourly(key) => state.last_hourly = Some(key),
-                ScheduleSlot::Daily(key) => state.last_daily = Some(key),
-                ScheduleSlot::Manual => {
-                    // If a manual trigger happens to coincide with a scheduled slot, consume that slot too
-                    // to avoid an immediate second run within the same minute.
-                    let eff = settings_config::effective_schedule(
-                        &settings.schedule,
-                        target.schedule.as_ref(),
+            // The group was upgraded to a supergroup: point the endpoint at it (the config
+            // reload picks the change up once no run is active).
+            if let Some(old_chat_id) = storage.migrated_from_chat_id()
+                && ep.chat_id.trim() == old_chat_id
+                && let Err(e) = settings_config::record_chat_migration(
+                    &config_root,
+                    &ep.id,
+                    old_chat_id,
+                    storage.chat_id(),
+                )
+            {
+                tracing::error!(
+                    event = "run.finish",
+                    kind = "backup",
+                    status = "failed",
+                    error_code = ErrorCode::TelegramChatMigrated.as_str(),
+                    error_message = %format!("chat id could not be saved: {e}"),
+                    old_chat_id,
+                    new_chat_id = storage.chat_id(),
+                    target_id = %target.id,
+                    endpoint_id = %ep.id,
+                    "run.finish"
+                );
+                if let Ok(mut st) = status_state.lock() {
+                    st.record_group_result(
+                        &target.id,
+                        "failed",
+                        None,
+                        Some(ErrorCode::TelegramChatMigrated.as_str()),
                     );
-                    if eff.enabled {
-                        match eff.kind.as_str() {
-                            "hourly" => {
-                                if now.minute() == eff.hourly_minute as u32 {
-                                    let key = (now.year(), now.month(), now.day(), now.hour());
-                                    state.last_hourly = Some(key);
-                                }
-                            }
-                            "daily" => {
-                                if let Ok((hh, mm)) = parse_hhmm(&eff.daily_at)
-                                    && now.hour() == hh as u32
-                                    && now.minute() == mm as u32
-                                {
-                                    let key = (now.year(), now.month(), now.day());
-                                    state.last_daily = Some(key);
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
                 }
+                continue;
             }
 
-            let task_id = format!("tsk_{}", Uuid::new_v4());
+            let task_id = queued.task_id.clone();
             let run_log =
                 televy_backup_core::run_log::start_run_log("backup", &task_id, &data_root)?;
+            prune_run_logs_best_effort(&data_root, &settings);
 
             // Run summaries must appear even when the daemon is started with `RUST_LOG=warn`,
             // otherwise successful runs create empty NDJSON files and the UI shows no history.
@@ -1678,16 +2776,6 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             );
 
             let started = Instant::now();
-            let label = match scheduled_slot {
-                ScheduleSlot::Manual => "manual".to_string(),
-                _ => {
-                    if target.label.trim().is_empty() {
-                        "scheduled".to_string()
-                    } else {
-                        target.label.clone()
-                    }
-                }
-            };
 
             let db_path = index_dir.join(format!("index.{}.sqlite", ep.id));
             let filemap_dir = index_dir.join("filemaps").join(&ep.id);
@@ -1698,6 +2786,22 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .join("dedupe")
                 .join(format!("pending.{}.sqlite", ep.id));
 
+            // Re-read per run so `televybackup device rename` applies without a restart.
+            let device =
+                match televy_backup_core::device::load_or_create_device_identity(&data_root) {
+                    Ok(device) => Some(device),
+                    Err(e) => {
+                        tracing::warn!(
+                            event = "device.load_failed",
+                            error = %e,
+                            "device.load_failed"
+                        );
+                        None
+                    }
+                };
+
+            let label = run_label(&settings, target, queued.trigger, device.as_ref());
+
             if let Ok(mut st) = status_state.lock() {
                 st.mark_run_start(&target.id);
             }
@@ -1707,6 +2811,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 state: status_state.clone(),
             };
             let progress_sink = Some(&sink as &dyn ProgressSink);
+            let recorder = ProgressRecorder::new(progress_sink);
             let quick_stats_cancel = CancellationToken::new();
             let quick_stats_cancel_for_task = quick_stats_cancel.clone();
             let prepare_res = tokio::try_join!(
@@ -1718,12 +2823,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     &db_path,
                     &filemap_dir,
                     &dedupe_db_path,
-                    is_likely_private_chat_id(&ep.chat_id),
+                    settings_config::is_likely_private_chat_id(&ep.chat_id),
                     progress_sink,
                 ),
                 async {
                     match preflight_local_quick_stats_daemon(
                         Path::new(&target.source_path),
+                        settings.scan.one_file_system,
+                        settings.scan.default_excludes,
                         progress_sink,
                         Some(quick_stats_cancel_for_task),
                     )
@@ -1745,8 +2852,30 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
             );
 
+            let mut source_identity = None;
             let result = match prepare_res {
-                Ok((remote_dedupe, quick_stats)) => {
+                Ok((remote_dedupe, quick_stats)) => 'run: {
+                    let hint_changed_paths = fs_watchers.begin_run(&target.id);
+                    tracing::debug!(
+                        event = "scan.hint",
+                        target_id = %target.id,
+                        changed_paths = hint_changed_paths.as_ref().map(|p| p.len() as u64),
+                        "scan.hint"
+                    );
+                    match televy_backup_core::source_guard::guard_source(
+                        &data_root,
+                        &target.id,
+                        Path::new(&target.source_path),
+                        quick_stats.map(|s| s.files_total),
+                        televy_backup_core::source_guard::SourceGuardLimits {
+                            min_expected_files: target.min_expected_files,
+                            max_file_drop_percent: settings.scan.max_file_drop_percent,
+                        },
+                        false,
+                    ) {
+                        Ok(identity) => source_identity = Some(identity),
+                        Err(e) => break 'run Err(e),
+                    }
                     let cfg = BackupConfig {
                         endpoint_db_path: db_path.clone(),
                         filemap_dir: filemap_dir.clone(),
@@ -1761,14 +2890,48 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         },
                         rate_limit: ep.rate_limit.clone(),
                         master_key,
+                        data_key: target.data_key(&master_key, settings.chunking.hash_alg()),
                         snapshot_id: None,
                         keep_last_snapshots: settings.retention.keep_last_snapshots,
                         remote_dedupe,
+                        hint_changed_paths,
+                        device: device.clone(),
+                        index_full_every: settings.index.full_every,
+                        created_at: None,
+                    };
+                    // Dropping the guard unmounts and deletes the snapshot, also when the
+                    // run fails or is cancelled.
+                    let apfs_snapshot = if target.use_apfs_snapshot(&settings.scan) {
+                        televy_backup_core::apfs_snapshot::prepare_apfs_snapshot(
+                            &target.id,
+                            Path::new(&target.source_path),
+                        )
+                        .await
+                    } else {
+                        None
                     };
                     let opts = BackupOptions {
-                        cancel: None,
-                        progress: progress_sink,
+                        cancel: Some(&stop),
+                        progress: Some(&recorder),
                         source_quick_stats: quick_stats,
+                        strict: settings.scan.strict,
+                        one_file_system: settings.scan.one_file_system,
+                        default_excludes: settings.scan.default_excludes,
+                        scan_root: apfs_snapshot.as_ref().map(|s| s.scan_root()),
+                        retry: settings.retry.clone(),
+                        worker_threads: settings.performance.worker_threads as usize,
+                        verify_after_upload: settings.upload.verify_after_upload,
+                        filters: &target.filters,
+                        warn_path_bytes: settings.scan.warn_path_bytes,
+                        max_unreadable_percent: settings.scan.max_unreadable_percent,
+                        upload_throttle: Some(&upload_throttle),
+                        // An APFS snapshot is crash-consistent already, and its read-only volume
+                        // leaves SQLite no place for a WAL database's shared-memory file.
+                        sqlite_consistent: if apfs_snapshot.is_none() {
+                            SqliteConsistentOptions::for_target(target, &settings.scan)
+                        } else {
+                            SqliteConsistentOptions::default()
+                        },
                     };
                     televy_backup_core::run_backup_with(storage, cfg, opts).await
                 }
@@ -1778,29 +2941,40 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
             };
             let duration_seconds = started.elapsed().as_secs_f64();
+            // The local snapshot (the next run's base) exists once `run_backup_with` succeeds,
+            // even if the remote bootstrap update below fails.
+            fs_watchers.finish_run(&target.id, result.is_ok());
 
             match result {
-                Ok(res) => {
+                Ok(mut res) => {
+                    let retry = televy_backup_core::retry::RetryBudget::resume(
+                        &settings.retry,
+                        None,
+                        res.retry,
+                    );
                     // Strict remote gating: if bootstrap update fails, the overall run is failed.
-                    let bootstrap_update = if is_likely_private_chat_id(&ep.chat_id) {
+                    let bootstrap_update = if settings_config::is_likely_private_chat_id(
+                        &ep.chat_id,
+                    ) {
                         tracing::warn!(
                             event = "bootstrap.skipped",
                             reason = "unsupported_private_chat",
                             chat_id = %ep.chat_id,
                             "bootstrap catalog requires pinning; use a group/channel (e.g. -100...) or @username chat id"
                         );
-                        Ok(())
+                        Ok(None)
                     } else {
                         let pool = televy_backup_core::index_db::open_index_db(&db_path).await?;
 
                         let row = sqlx::query(
-                            "SELECT manifest_object_id FROM remote_indexes WHERE snapshot_id = ? AND provider = ? LIMIT 1",
-                        )
-                        .bind(&res.snapshot_id)
-                        .bind(storage.provider())
-                        .fetch_one(&pool)
-                        .await?;
+                                "SELECT manifest_object_id, manifest_sha256 FROM remote_indexes WHERE snapshot_id = ? AND provider = ? LIMIT 1",
+                            )
+                            .bind(&res.snapshot_id)
+                            .bind(storage.provider())
+                            .fetch_one(&pool)
+                            .await?;
                         let filemap_manifest_object_id: String = row.get("manifest_object_id");
+                        let filemap_manifest_sha256: Option<String> = row.get("manifest_sha256");
 
                         let endpoint_index_id = match sqlx::query(
                             "SELECT value FROM endpoint_state WHERE key = ? LIMIT 1",
@@ -1816,54 +2990,75 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         };
 
                         let endpoint_manifest_object_id = sqlx::query(
-                            "SELECT value FROM endpoint_state WHERE key = ? LIMIT 1",
-                        )
-                        .bind(
-                            televy_backup_core::index_sync::ENDPOINT_STATE_ENDPOINT_MANIFEST_OBJECT_ID_KEY,
-                        )
-                        .fetch_optional(&pool)
-                        .await?
-                        .map(|r| r.get::<String, _>("value"))
-                        .ok_or_else(|| televy_backup_core::Error::Integrity {
-                            message: "missing endpoint_state.endpoint_manifest_object_id after backup".to_string(),
-                        })?;
+                                "SELECT value FROM endpoint_state WHERE key = ? LIMIT 1",
+                            )
+                            .bind(
+                                televy_backup_core::index_sync::ENDPOINT_STATE_ENDPOINT_MANIFEST_OBJECT_ID_KEY,
+                            )
+                            .fetch_optional(&pool)
+                            .await?
+                            .map(|r| r.get::<String, _>("value"))
+                            .ok_or_else(|| televy_backup_core::Error::Integrity {
+                                message: "missing endpoint_state.endpoint_manifest_object_id after backup".to_string(),
+                            })?;
 
                         let endpoint_dedupe_id =
                             televy_backup_core::dedupe_catalog::endpoint_dedupe_id_for_storage(
                                 storage,
                             )?;
                         let dedupe_catalog_object_id =
-                            televy_backup_core::index_sync::endpoint_state_get(
-                                &dedupe_db_path,
-                                televy_backup_core::index_sync::ENDPOINT_STATE_DEDUPE_CATALOG_OBJECT_ID_KEY,
-                            )
-                            .await?
-                            .ok_or_else(|| televy_backup_core::Error::Integrity {
-                                message: "missing endpoint_state.dedupe_catalog_object_id after backup".to_string(),
-                            })?;
-
-                        bootstrap::update_remote_latest(
-                            storage,
-                            &master_key,
-                            Some(bootstrap::BootstrapEndpointLatest {
-                                endpoint_index_id,
-                                manifest_object_id: endpoint_manifest_object_id,
-                            }),
-                            Some(bootstrap::BootstrapEndpointDedupeLatest {
-                                endpoint_dedupe_id,
-                                catalog_object_id: dedupe_catalog_object_id,
-                            }),
-                            &target.id,
-                            &target.source_path,
-                            &label,
-                            &res.snapshot_id,
-                            &filemap_manifest_object_id,
-                        )
-                        .await
+                                televy_backup_core::index_sync::endpoint_state_get(
+                                    &dedupe_db_path,
+                                    televy_backup_core::index_sync::ENDPOINT_STATE_DEDUPE_CATALOG_OBJECT_ID_KEY,
+                                )
+                                .await?
+                                .ok_or_else(|| televy_backup_core::Error::Integrity {
+                                    message: "missing endpoint_state.dedupe_catalog_object_id after backup".to_string(),
+                                })?;
+
+                        let endpoint_latest = bootstrap::BootstrapEndpointLatest {
+                            endpoint_index_id,
+                            manifest_object_id: endpoint_manifest_object_id,
+                        };
+                        let dedupe_latest = bootstrap::BootstrapEndpointDedupeLatest {
+                            endpoint_dedupe_id,
+                            catalog_object_id: dedupe_catalog_object_id,
+                        };
+                        retry
+                            .run("bootstrap_catalog", || {
+                                bootstrap::update_remote_latest(
+                                    storage,
+                                    &master_key,
+                                    Some(endpoint_latest.clone()),
+                                    Some(dedupe_latest.clone()),
+                                    &target.id,
+                                    &target.source_path,
+                                    &label,
+                                    &res.snapshot_id,
+                                    &filemap_manifest_object_id,
+                                    filemap_manifest_sha256.as_deref(),
+                                    target.key_derivation(),
+                                    device.as_ref(),
+                                )
+                            })
+                            .await
                     };
+                    res.retry = retry.stats();
 
                     match bootstrap_update {
-                        Ok(()) => {
+                        Ok(replaced) => {
+                            if let Some(previous) = replaced {
+                                record_audit(
+                                    AuditActor::Daemon,
+                                    audit::AUDIT_OP_BOOTSTRAP_OVERWRITE,
+                                    audit::bootstrap_overwrite_details(
+                                        &target.id,
+                                        &previous,
+                                        &res.snapshot_id,
+                                        device.as_ref(),
+                                    ),
+                                );
+                            }
                             tracing::warn!(
                                 event = "run.finish",
                                 kind = "backup",
@@ -1873,22 +3068,51 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 duration_seconds,
                                 snapshot_id = %res.snapshot_id,
                                 files_indexed = res.files_indexed,
+                                dirs_indexed = res.dirs_indexed,
                                 chunks_uploaded = res.chunks_uploaded,
                                 data_objects_uploaded = res.data_objects_uploaded,
                                 data_objects_estimated_without_pack = res.data_objects_estimated_without_pack,
                                 bytes_uploaded = res.bytes_uploaded,
                                 bytes_deduped = res.bytes_deduped,
                                 index_parts = res.index_parts,
+                                files_skipped_errors = res.files_skipped_errors,
+                                retries = res.retry.retries,
+                                retry_wait_ms = res.retry.retry_wait_ms,
+                                upload_checks = res.upload_checks,
+                                upload_reuploads = res.upload_reuploads,
+                                phase_timings_ms = %res.phase_timings,
                                 "run.finish"
                             );
 
+                            record_usage(
+                                settings.records_usage(),
+                                &target.id,
+                                res.bytes_uploaded,
+                                duration_seconds,
+                                RunStatus::Succeeded,
+                            );
                             if let Ok(mut st) = status_state.lock() {
                                 st.mark_run_finish_success(
                                     &target.id,
+                                    &res.snapshot_id,
                                     duration_seconds,
                                     res.files_indexed,
                                     res.bytes_uploaded,
                                     res.bytes_deduped,
+                                    res.upload_duration().map(|d| d.as_secs_f64()),
+                                    res.warnings.clone(),
+                                );
+                            }
+                            if let Some(identity) = &source_identity
+                                && let Err(e) = televy_backup_core::source_guard::record_baseline(
+                                    &data_root, &target.id, identity,
+                                )
+                            {
+                                tracing::warn!(
+                                    event = "backup.source_baseline_failed",
+                                    target_id = %target.id,
+                                    error = %e,
+                                    "backup.source_baseline_failed"
                                 );
                             }
                         }
@@ -1912,16 +3136,55 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 error_message = %e,
                                 "run.finish"
                             );
+                            record_usage(
+                                settings.records_usage(),
+                                &target.id,
+                                res.bytes_uploaded,
+                                duration_seconds,
+                                RunStatus::Failed,
+                            );
+                            let (error_message, log_excerpt) = run_failure_details(
+                                &e,
+                                run_log.path(),
+                                &[bot_token.as_str(), api_hash.as_str()],
+                            );
                             if let Ok(mut st) = status_state.lock() {
                                 st.mark_run_finish_failure(
                                     &target.id,
                                     duration_seconds,
                                     e.code().to_string(),
+                                    error_message,
+                                    log_excerpt,
+                                    res.warnings.clone(),
                                 );
                             }
                         }
                     }
                 }
+                Err(televy_backup_core::Error::Cancelled) => {
+                    let partial = recorder.partial_result();
+                    tracing::warn!(
+                        event = "run.finish",
+                        kind = "backup",
+                        run_id = %task_id,
+                        task_id = %task_id,
+                        status = RunStatus::Cancelled.as_str(),
+                        duration_seconds,
+                        error_code = ErrorCode::TaskCancelled.as_str(),
+                        partial = %serde_json::to_string(&partial).unwrap_or_default(),
+                        "run.finish"
+                    );
+                    record_usage(
+                        settings.records_usage(),
+                        &target.id,
+                        partial.bytes_uploaded.unwrap_or(0),
+                        duration_seconds,
+                        RunStatus::Cancelled,
+                    );
+                    if let Ok(mut st) = status_state.lock() {
+                        st.mark_run_finish_cancelled(&target.id, duration_seconds, partial);
+                    }
+                }
                 Err(e) => {
                     tracing::error!(
                         event = "run.finish",
@@ -1935,11 +3198,27 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         "run.finish"
                     );
 
+                    record_usage(
+                        settings.records_usage(),
+                        &target.id,
+                        0,
+                        duration_seconds,
+                        RunStatus::Failed,
+                    );
+
+                    let (error_message, log_excerpt) = run_failure_details(
+                        &e,
+                        run_log.path(),
+                        &[bot_token.as_str(), api_hash.as_str()],
+                    );
                     if let Ok(mut st) = status_state.lock() {
                         st.mark_run_finish_failure(
                             &target.id,
                             duration_seconds,
                             e.code().to_string(),
+                            error_message,
+                            log_excerpt,
+                            e.run_warnings(),
                         );
                     }
                 }
@@ -1948,50 +3227,867 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             if let Some(bytes) = storage.session_bytes() {
                 let b64 = base64::engine::general_purpose::STANDARD.encode(bytes);
                 if let Some(store) = secrets_store.as_mut() {
-                    let should_write = store
-                        .get(&ep.mtproto.session_key)
-                        .is_none_or(|v| v != b64.as_str());
+                    let should_write =
+                        get_secret_from_store(&secrets_provider, store, &ep.mtproto.session_key)
+                            .is_none_or(|v| v != b64);
                     if should_write {
-                        store.set(&ep.mtproto.session_key, b64);
-                        if let Err(e) = televy_backup_core::secrets::save_secrets_store(
+                        match secrets_provider.write(
+                            &ep.mtproto.session_key,
+                            &b64,
+                            store,
                             &secrets_path,
                             &vault_key,
-                            store,
                         ) {
-                            tracing::warn!(
+                            Ok(_) => record_audit(
+                                AuditActor::Daemon,
+                                audit::AUDIT_OP_SECRET_SET,
+                                serde_json::json!({ "key": ep.mtproto.session_key }),
+                            ),
+                            Err(e) => tracing::warn!(
                                 event = "secrets.session_persist_failed",
                                 error = %e,
                                 "failed to persist mtproto session"
-                            );
+                            ),
                         }
                     }
                 }
             }
+            storage_pool.touch(&ep.id);
         }
 
-        clear_mtproto_storage_cache(&mut storage_by_endpoint, "idle_loop_end").await;
-        sleep(Duration::from_secs(1)).await;
-    }
-}
-
-async fn clear_mtproto_storage_cache(
-    storage_by_endpoint: &mut HashMap<String, TelegramMtProtoStorage>,
-    reason: &str,
-) {
-    if storage_by_endpoint.is_empty() {
-        return;
-    }
-
-    tracing::info!(
-        event = "mtproto.storage_cache.clear",
-        reason,
-        endpoint_count = storage_by_endpoint.len(),
-        "mtproto.storage_cache.clear"
-    );
-
-    let drained = std::mem::take(storage_by_endpoint);
-    let _ = tokio::task::spawn_blocking(move || drop(drained)).await;
-}
+        // Scheduled verifies run here, between backups, so the daemon never verifies and backs up
+        // at once; `next_due_verify` also waits for CLI runs on the endpoint to finish. A verify
+        // that comes due in quiet hours waits for them to end.
+        if once.is_none()
+            && settings.verify_schedule.enabled
+            && quiet_until.is_none()
+            && last_verify_check
+                .is_none_or(|t| t.elapsed() >= verify_schedule::VERIFY_CHECK_INTERVAL)
+        {
+            last_verify_check = Some(Instant::now());
+            if let Some((target, ep)) = next_due_verify(&settings, &status_state, &index_dir).await
+                && let Some(bot_token) = secrets_store
+                    .as_ref()
+                    .and_then(|s| get_secret_from_store(&secrets_provider, s, &ep.bot_token_key))
+                && let Some(api_hash) =
+                    endpoint_api_hash(&secrets_provider, secrets_store.as_ref(), ep, &api_hash)
+            {
+                let session = secrets_store
+                    .as_ref()
+                    .and_then(|s| {
+                        get_secret_from_store(&secrets_provider, s, &ep.mtproto.session_key)
+                    })
+                    .filter(|b64| !b64.trim().is_empty())
+                    .and_then(|b64| {
+                        base64::engine::general_purpose::STANDARD
+                            .decode(b64.as_bytes())
+                            .ok()
+                    });
+                run_scheduled_verify(
+                    &settings,
+                    target,
+                    ep,
+                    &mut storage_pool,
+                    &status_state,
+                    &data_root,
+                    &master_key,
+                    &api_hash,
+                    &bot_token,
+                    session,
+                )
+                .await;
+            }
+        }
+
+        // The deletion queue is flushed between backups too, one endpoint per check.
+        if once.is_none()
+            && last_deletion_queue_check
+                .is_none_or(|t| t.elapsed() >= deletion_queue::DELETION_QUEUE_CHECK_INTERVAL)
+        {
+            last_deletion_queue_check = Some(Instant::now());
+            let queues = deletion_queue::pending_deletions(&settings, &index_dir).await;
+            if let Ok(mut st) = status_state.lock() {
+                st.pending_deletion_bytes = Some(queues.iter().map(|(_, q)| q.bytes).sum());
+            }
+            let due = queues
+                .iter()
+                .filter(|(_, q)| q.due_objects > 0)
+                .map(|(ep, _)| *ep)
+                .find(|ep| {
+                    status_state
+                        .lock()
+                        .is_ok_and(|st| !st.endpoint_busy(&ep.id))
+                });
+            if let Some(ep) = due
+                && let Some(bot_token) = secrets_store
+                    .as_ref()
+                    .and_then(|s| get_secret_from_store(&secrets_provider, s, &ep.bot_token_key))
+                && let Some(api_hash) =
+                    endpoint_api_hash(&secrets_provider, secrets_store.as_ref(), ep, &api_hash)
+            {
+                let session = secrets_store
+                    .as_ref()
+                    .and_then(|s| {
+                        get_secret_from_store(&secrets_provider, s, &ep.mtproto.session_key)
+                    })
+                    .filter(|b64| !b64.trim().is_empty())
+                    .and_then(|b64| {
+                        base64::engine::general_purpose::STANDARD
+                            .decode(b64.as_bytes())
+                            .ok()
+                    });
+                run_deletion_queue_flush(
+                    &settings,
+                    ep,
+                    &mut storage_pool,
+                    &status_state,
+                    &data_root,
+                    &master_key,
+                    &api_hash,
+                    &bot_token,
+                    session,
+                )
+                .await;
+            }
+        }
+
+        // `index.sync` calls from the control IPC, also served between backups.
+        while let Ok(req) = index_sync_rx.try_recv() {
+            let res = run_index_sync(
+                &settings,
+                &req.params,
+                &mut storage_pool,
+                &status_state,
+                &secrets_provider,
+                secrets_store.as_ref(),
+                &data_root,
+                &master_key,
+                &api_hash,
+            )
+            .await;
+            let _ = req.reply.send(res);
+        }
+
+        if once.is_some()
+            && once_due.is_none()
+            && status_state
+                .lock()
+                .ok()
+                .is_some_and(|st| st.run_queue.list().is_empty() && !st.has_running())
+        {
+            storage_pool.clear("once_finished").await;
+            return Ok(());
+        }
+
+        storage_pool
+            .evict_idle(mtproto_pool::MTPROTO_STORAGE_IDLE_TIMEOUT)
+            .await;
+        tokio::select! {
+            _ = sleep(SCHEDULER_TICK_INTERVAL) => {}
+            _ = stop.cancelled() => {}
+        }
+    }
+}
+
+/// Cancelled on SIGTERM (launchd stopping the agent) or Ctrl-C: a running backup stops at its
+/// next checkpoint and is recorded as `cancelled`, then the scheduler loop exits. A second
+/// signal exits right away.
+fn stop_signal_token() -> CancellationToken {
+    let stop = CancellationToken::new();
+    let for_task = stop.clone();
+    tokio::spawn(async move {
+        wait_for_stop_signal().await;
+        for_task.cancel();
+        wait_for_stop_signal().await;
+        std::process::exit(130);
+    });
+    stop
+}
+
+#[cfg(unix)]
+async fn wait_for_stop_signal() {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    match signal(SignalKind::terminate()) {
+        Ok(mut term) => {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = term.recv() => {}
+            }
+        }
+        Err(_) => {
+            let _ = tokio::signal::ctrl_c().await;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_stop_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Pause between main loop iterations, each of which is one scheduler tick. A running backup
+/// holds up the next tick until it finishes.
+const SCHEDULER_TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+const DAEMON_USAGE: &str = "usage: televybackupd [--once [--simulate-time <RFC3339>] [--execute]]";
+
+/// `--once`: evaluate the schedule a single time and exit (see [`schedule_once`]).
+#[derive(Debug, Default, PartialEq)]
+struct OnceArgs {
+    simulate_time: Option<chrono::DateTime<chrono::FixedOffset>>,
+    execute: bool,
+}
+
+fn parse_daemon_args(args: impl IntoIterator<Item = String>) -> Result<Option<OnceArgs>, String> {
+    let mut once = false;
+    let mut out = OnceArgs::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--once" => once = true,
+            "--execute" => out.execute = true,
+            "--simulate-time" => {
+                let raw = args.next().ok_or("--simulate-time needs a value")?;
+                let at = chrono::DateTime::parse_from_rfc3339(raw.trim())
+                    .map_err(|e| format!("--simulate-time must be RFC3339 (got {raw:?}): {e}"))?;
+                out.simulate_time = Some(at);
+            }
+            other => return Err(format!("unknown argument: {other}")),
+        }
+    }
+    if !once {
+        if out.execute || out.simulate_time.is_some() {
+            return Err("--simulate-time and --execute need --once".to_string());
+        }
+        return Ok(None);
+    }
+    Ok(Some(out))
+}
+
+/// Evaluates the schedule once, as a freshly started daemon would at `--simulate-time` (default:
+/// now), and prints one line per target with the decision. Returns the targets to run.
+///
+/// Like on startup the scheduler looks back one minute, so a simulated time should fall within
+/// the minute after the slot. A due slot is reported as `already_ran` when the target has a
+/// snapshot created since the slot started.
+async fn schedule_once(
+    settings: &settings_config::SettingsV2,
+    data_root: &Path,
+    once: &OnceArgs,
+) -> Result<Vec<(String, ScheduleSlot)>, Box<dyn std::error::Error>> {
+    let tz = ScheduleTz::parse(&settings.schedule.timezone).unwrap_or(ScheduleTz::Local);
+    let now = once
+        .simulate_time
+        .map(|t| t.with_timezone(&tz))
+        .unwrap_or_else(|| chrono::Utc::now().with_timezone(&tz));
+    let since = now - chrono::Duration::minutes(1);
+    println!(
+        "now={} window=({}, {}]",
+        now.to_rfc3339(),
+        since.to_rfc3339(),
+        now.to_rfc3339()
+    );
+
+    let checks = schedule::evaluate_schedule(settings, &mut HashMap::new(), &since, &now, false)?;
+    let mut due = Vec::new();
+    for check in checks {
+        let mut outcome = check.outcome;
+        let mut last_snapshot_at = None;
+        if let (ScheduleOutcome::Due(slot), Some(slot_at)) = (outcome, check.slot_at.as_ref()) {
+            last_snapshot_at = latest_snapshot_created_at(data_root, check.target).await?;
+            let slot_at_utc = slot_at
+                .with_timezone(&chrono::Utc)
+                .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+                .to_string();
+            if last_snapshot_at
+                .as_deref()
+                .is_some_and(|t| *t >= *slot_at_utc)
+            {
+                outcome = ScheduleOutcome::AlreadyRan(slot);
+            }
+        }
+
+        let slot = match outcome {
+            ScheduleOutcome::Due(slot)
+            | ScheduleOutcome::AlreadyRan(slot)
+            | ScheduleOutcome::SkippedDisabled(slot) => slot.to_string(),
+            _ => "-".to_string(),
+        };
+        println!(
+            "target={} decision={} slot={} slotAt={} lastSnapshotAt={}",
+            check.target.id,
+            outcome.as_str(),
+            slot,
+            check
+                .slot_at
+                .map(|t| t.to_rfc3339())
+                .unwrap_or_else(|| "-".to_string()),
+            last_snapshot_at.as_deref().unwrap_or("-"),
+        );
+        if let ScheduleOutcome::Due(slot) = outcome {
+            due.push((check.target.id.clone(), slot));
+        }
+    }
+    Ok(due)
+}
+
+/// Targets that run now or resume once their `disabled_until` passes.
+fn has_schedulable_targets(settings: &settings_config::SettingsV2) -> bool {
+    settings
+        .targets
+        .iter()
+        .any(|t| t.enabled || t.disabled_until.is_some())
+}
+
+/// Writes the run log of a schedule slot that fired while `target` was disabled, so the history
+/// shows a skipped run instead of a gap.
+fn record_skipped_disabled_run(
+    data_root: &Path,
+    target: &settings_config::Target,
+    slot: ScheduleSlot,
+) {
+    let task_id = format!("tsk_{}", Uuid::new_v4());
+    let run_log = match televy_backup_core::run_log::start_run_log("backup", &task_id, data_root) {
+        Ok(guard) => guard,
+        Err(e) => {
+            tracing::warn!(
+                event = "run_log.create_failed",
+                target_id = %target.id,
+                error = %e,
+                "run_log.create_failed"
+            );
+            return;
+        }
+    };
+    tracing::warn!(
+        event = "run.start",
+        kind = "backup",
+        run_id = %task_id,
+        task_id = %task_id,
+        target_id = %target.id,
+        endpoint_id = %target.endpoint_id,
+        source_path = %target.source_path,
+        log_path = %run_log.path().display(),
+        "run.start"
+    );
+    tracing::warn!(
+        event = "run.finish",
+        kind = "backup",
+        run_id = %task_id,
+        task_id = %task_id,
+        status = "skipped",
+        reason = "disabled",
+        error_code = ErrorCode::TargetDisabled.as_str(),
+        slot = %slot,
+        disabled_until = target.disabled_until.as_deref().unwrap_or("-"),
+        duration_seconds = 0.0,
+        "run.finish"
+    );
+}
+
+async fn latest_snapshot_created_at(
+    data_root: &Path,
+    target: &settings_config::Target,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let db_path = data_root
+        .join("index")
+        .join(format!("index.{}.sqlite", target.endpoint_id));
+    if !db_path.exists() {
+        return Ok(None);
+    }
+    let pool = televy_backup_core::index_db::open_existing_index_db(&db_path).await?;
+    let created_at: Option<String> =
+        sqlx::query_scalar("SELECT MAX(created_at) FROM snapshots WHERE source_path = ?")
+            .bind(&target.source_path)
+            .fetch_one(&pool)
+            .await?;
+    pool.close().await;
+    Ok(created_at)
+}
+
+/// The first enabled target whose scheduled verify is due while no backup on its endpoint runs
+/// or waits.
+async fn next_due_verify<'a>(
+    settings: &'a settings_config::SettingsV2,
+    status_state: &Mutex<StatusRuntimeState>,
+    index_dir: &Path,
+) -> Option<(
+    &'a settings_config::Target,
+    &'a settings_config::TelegramEndpoint,
+)> {
+    let now = chrono::Local::now();
+    for target in settings.targets.iter().filter(|t| t.enabled_at(&now)) {
+        let Some(ep) = settings
+            .telegram_endpoints
+            .iter()
+            .find(|e| e.id == target.endpoint_id)
+        else {
+            continue;
+        };
+        if ep.chat_id.trim().is_empty() || !verify_schedule::endpoint_supports_verify(ep) {
+            continue;
+        }
+        let (busy, last_attempt) = {
+            let st = status_state.lock().ok()?;
+            (st.endpoint_busy(&ep.id), st.verify.get(&target.id).cloned())
+        };
+        if busy {
+            continue;
+        }
+        // Targets without a snapshot yet have nothing to verify.
+        let db_path = index_dir.join(format!("index.{}.sqlite", ep.id));
+        if !db_path.exists() {
+            continue;
+        }
+        let state =
+            match televy_backup_core::snapshot_verify_state(&db_path, &target.source_path).await {
+                Ok(Some(state)) => state,
+                Ok(None) => continue,
+                Err(e) => {
+                    tracing::warn!(
+                        event = "verify.schedule_check_failed",
+                        target_id = %target.id,
+                        error_code = e.code(),
+                        error_message = %e,
+                        "verify.schedule_check_failed"
+                    );
+                    continue;
+                }
+            };
+        if verify_schedule::verify_due(
+            settings.verify_schedule.every_days,
+            state.last_verified_at.as_deref(),
+            last_attempt.as_ref(),
+            chrono::Utc::now(),
+        ) {
+            return Some((target, ep));
+        }
+    }
+    None
+}
+
+/// One scheduled `verify latest` of `target`, logged as a `verify` run; the outcome becomes the
+/// target's `lastVerify` status.
+#[allow(clippy::too_many_arguments)]
+async fn run_scheduled_verify(
+    settings: &settings_config::SettingsV2,
+    target: &settings_config::Target,
+    ep: &settings_config::TelegramEndpoint,
+    storage_pool: &mut mtproto_pool::MtProtoStoragePool,
+    status_state: &Mutex<StatusRuntimeState>,
+    data_root: &Path,
+    master_key: &[u8; 32],
+    api_hash: &str,
+    bot_token: &str,
+    session: Option<Vec<u8>>,
+) {
+    let task_id = format!("tsk_{}", Uuid::new_v4());
+    let run_log = match televy_backup_core::run_log::start_run_log("verify", &task_id, data_root) {
+        Ok(guard) => guard,
+        Err(e) => {
+            tracing::warn!(
+                event = "run_log.create_failed",
+                target_id = %target.id,
+                error = %e,
+                "run_log.create_failed"
+            );
+            return;
+        }
+    };
+    let sample = verify_schedule::verify_sample(&settings.verify_schedule);
+    tracing::warn!(
+        event = "run.start",
+        kind = "verify",
+        run_id = %task_id,
+        task_id = %task_id,
+        trigger = "schedule",
+        target_id = %target.id,
+        endpoint_id = %ep.id,
+        source_path = %target.source_path,
+        snapshot_id = "latest",
+        sample_percent = settings.verify_schedule.sample_percent,
+        log_path = %run_log.path().display(),
+        "run.start"
+    );
+    if let Ok(mut st) = status_state.lock() {
+        st.verify.mark_start(&target.id);
+    }
+
+    let started = Instant::now();
+    let index_dir = data_root.join("index");
+    let result = async {
+        storage_pool
+            .ensure_connected(
+                &ep.id,
+                endpoint_storage_config(settings, ep, data_root, api_hash, bot_token, session)?,
+            )
+            .await?;
+        let storage =
+            storage_pool
+                .get(&ep.id)
+                .ok_or_else(|| televy_backup_core::Error::InvalidConfig {
+                    message: format!("endpoint not connected: {}", ep.id),
+                })?;
+        verify_schedule::verify_latest_snapshot(storage, master_key, target, ep, &index_dir, sample)
+            .await
+    }
+    .await;
+    storage_pool.touch(&ep.id);
+
+    let duration_seconds = started.elapsed().as_secs_f64();
+    let finished_at = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+    let summary = match result {
+        Ok((snapshot_id, res)) => {
+            tracing::warn!(
+                event = "run.finish",
+                kind = "verify",
+                run_id = %task_id,
+                task_id = %task_id,
+                target_id = %target.id,
+                endpoint_id = %ep.id,
+                snapshot_id = %snapshot_id,
+                status = "succeeded",
+                duration_seconds,
+                chunks_checked = res.chunks_checked,
+                bytes_checked = res.bytes_checked,
+                chunks_skipped = res.chunks_skipped,
+                coverage_percent = res.coverage_percent,
+                phase_timings_ms = %res.phase_timings,
+                "run.finish"
+            );
+            let db_path = index_dir.join(format!("index.{}.sqlite", ep.id));
+            if let Err(e) =
+                televy_backup_core::record_snapshot_verified(&db_path, &snapshot_id, &finished_at)
+                    .await
+            {
+                tracing::warn!(
+                    event = "verify.record_failed",
+                    target_id = %target.id,
+                    snapshot_id = %snapshot_id,
+                    error_code = e.code(),
+                    error_message = %e,
+                    "verify.record_failed"
+                );
+            }
+            VerifySummary {
+                finished_at,
+                status: "succeeded".to_string(),
+                snapshot_id: Some(snapshot_id),
+                chunks_checked: Some(res.chunks_checked),
+                bytes_checked: Some(res.bytes_checked),
+                coverage_percent: Some(res.coverage_percent),
+                error_code: None,
+                error_message: None,
+                warning: false,
+            }
+        }
+        Err(e) => {
+            tracing::error!(
+                event = "run.finish",
+                kind = "verify",
+                run_id = %task_id,
+                task_id = %task_id,
+                target_id = %target.id,
+                endpoint_id = %ep.id,
+                status = "failed",
+                duration_seconds,
+                error_code = e.code(),
+                error_message = %e,
+                "run.finish"
+            );
+            let (error_message, _) =
+                run_failure_details(&e, run_log.path(), &[bot_token, api_hash]);
+            VerifySummary {
+                finished_at,
+                status: "failed".to_string(),
+                snapshot_id: None,
+                chunks_checked: None,
+                bytes_checked: None,
+                coverage_percent: None,
+                error_code: Some(e.code().to_string()),
+                error_message: Some(error_message),
+                warning: true,
+            }
+        }
+    };
+    if let Ok(mut st) = status_state.lock() {
+        st.verify.record(&target.id, summary);
+    }
+}
+
+/// One `gc flush` of the deletion queue of `ep`; refreshes `pendingDeletionBytes` afterwards.
+#[allow(clippy::too_many_arguments)]
+async fn run_deletion_queue_flush(
+    settings: &settings_config::SettingsV2,
+    ep: &settings_config::TelegramEndpoint,
+    storage_pool: &mut mtproto_pool::MtProtoStoragePool,
+    status_state: &Mutex<StatusRuntimeState>,
+    data_root: &Path,
+    master_key: &[u8; 32],
+    api_hash: &str,
+    bot_token: &str,
+    session: Option<Vec<u8>>,
+) {
+    let started = Instant::now();
+    let result = async {
+        storage_pool
+            .ensure_connected(
+                &ep.id,
+                endpoint_storage_config(settings, ep, data_root, api_hash, bot_token, session)?,
+            )
+            .await?;
+        let storage =
+            storage_pool
+                .get(&ep.id)
+                .ok_or_else(|| televy_backup_core::Error::InvalidConfig {
+                    message: format!("endpoint not connected: {}", ep.id),
+                })?;
+        deletion_queue::flush_endpoint(storage, master_key, settings, ep, data_root).await
+    }
+    .await;
+    storage_pool.touch(&ep.id);
+
+    let duration_seconds = started.elapsed().as_secs_f64();
+    match result {
+        Ok(Some(res)) => tracing::info!(
+            event = "gc.flush",
+            endpoint_id = %ep.id,
+            duration_seconds,
+            objects_deleted = res.objects_deleted,
+            bytes_reclaimed = res.bytes_reclaimed,
+            objects_failed = res.objects_failed,
+            objects_unqueued = res.objects_unqueued,
+            pending_bytes = res.pending_bytes,
+            tombstones_purged = res.tombstones_purged,
+            "gc.flush"
+        ),
+        Ok(None) => tracing::info!(
+            event = "gc.flush_skipped",
+            endpoint_id = %ep.id,
+            reason = "index_stale",
+            "gc.flush_skipped"
+        ),
+        Err(e) => tracing::warn!(
+            event = "gc.flush_failed",
+            endpoint_id = %ep.id,
+            duration_seconds,
+            error_code = e.code(),
+            error_message = %e,
+            "gc.flush_failed"
+        ),
+    }
+
+    let queues = deletion_queue::pending_deletions(settings, &data_root.join("index")).await;
+    if let Ok(mut st) = status_state.lock() {
+        st.pending_deletion_bytes = Some(queues.iter().map(|(_, q)| q.bytes).sum());
+    }
+}
+
+/// Answers the queued `index.sync` calls while runs cannot start.
+fn reject_index_sync_requests(
+    requests: &mut tokio::sync::mpsc::UnboundedReceiver<control_ipc::IndexSyncRequest>,
+    reason: &str,
+) {
+    while let Ok(req) = requests.try_recv() {
+        let _ = req.reply.send(Err(ControlError::unavailable(
+            reason,
+            serde_json::json!({ "targetId": req.params.target_id }),
+        )));
+    }
+}
+
+/// One `index.sync` over the endpoint's pooled connection; see
+/// [`televy_backup_core::index_sync::sync_index_from_remote`]. Refused while a run uses the
+/// endpoint, since the sync may replace its index DB.
+#[allow(clippy::too_many_arguments)]
+async fn run_index_sync(
+    settings: &settings_config::SettingsV2,
+    params: &IndexSyncParams,
+    storage_pool: &mut mtproto_pool::MtProtoStoragePool,
+    status_state: &Mutex<StatusRuntimeState>,
+    secrets_provider: &televy_backup_core::secrets::SecretsProvider,
+    secrets_store: Option<&televy_backup_core::secrets::SecretsStore>,
+    data_root: &Path,
+    master_key: &[u8; 32],
+    api_hash: &str,
+) -> Result<IndexSyncReport, ControlError> {
+    let details = serde_json::json!({ "targetId": params.target_id });
+    let target = settings
+        .targets
+        .iter()
+        .find(|t| t.id == params.target_id)
+        .ok_or_else(|| ControlError::invalid_request("unknown target", details.clone()))?;
+    let ep = settings
+        .telegram_endpoints
+        .iter()
+        .find(|e| e.id == target.endpoint_id)
+        .ok_or_else(|| ControlError::invalid_request("unknown endpoint", details.clone()))?;
+    if settings_config::is_likely_private_chat_id(&ep.chat_id) {
+        return Err(ControlError::new(
+            ErrorCode::BootstrapUnsupportedChat,
+            "index sync needs the bootstrap catalog, which private chats cannot pin",
+            false,
+            details,
+        ));
+    }
+    if ep.bootstrap.pin_mode == bootstrap::BootstrapPinMode::Disabled {
+        return Err(ControlError::new(
+            ErrorCode::BootstrapDisabled,
+            "index sync needs the bootstrap catalog (bootstrap.pin_mode = \"disabled\")",
+            false,
+            details,
+        ));
+    }
+    if status_state
+        .lock()
+        .ok()
+        .is_some_and(|st| st.endpoint_busy(&ep.id))
+    {
+        return Err(ControlError::unavailable(
+            "a run is using the endpoint; retry when it finishes",
+            details,
+        ));
+    }
+    let bot_token = secrets_store
+        .and_then(|s| get_secret_from_store(secrets_provider, s, &ep.bot_token_key))
+        .ok_or_else(|| {
+            ControlError::new(
+                ErrorCode::TelegramUnauthorized,
+                "bot token missing",
+                false,
+                details.clone(),
+            )
+        })?;
+    let api_hash =
+        endpoint_api_hash(secrets_provider, secrets_store, ep, api_hash).ok_or_else(|| {
+            ControlError::new(
+                ErrorCode::TelegramUnauthorized,
+                "api hash missing",
+                false,
+                details.clone(),
+            )
+        })?;
+    let session = secrets_store
+        .and_then(|s| get_secret_from_store(secrets_provider, s, &ep.mtproto.session_key))
+        .filter(|b64| !b64.trim().is_empty())
+        .and_then(|b64| {
+            base64::engine::general_purpose::STANDARD
+                .decode(b64.as_bytes())
+                .ok()
+        });
+
+    let index_dir = data_root.join("index");
+    let result = async {
+        storage_pool
+            .ensure_connected(
+                &ep.id,
+                endpoint_storage_config(settings, ep, data_root, &api_hash, &bot_token, session)?,
+            )
+            .await?;
+        let storage =
+            storage_pool
+                .get(&ep.id)
+                .ok_or_else(|| televy_backup_core::Error::InvalidConfig {
+                    message: format!("endpoint not connected: {}", ep.id),
+                })?;
+        televy_backup_core::index_sync::sync_index_from_remote(
+            storage,
+            master_key,
+            &index_dir.join(format!("index.{}.sqlite", ep.id)),
+            &index_dir.join("filemaps").join(&ep.id),
+            &IndexSyncOptions {
+                target_id: params.target_id.clone(),
+                snapshot_id: params.snapshot_id.clone(),
+                force: params.force,
+            },
+        )
+        .await
+    }
+    .await;
+    storage_pool.touch(&ep.id);
+
+    match result {
+        Ok(report) => {
+            tracing::info!(
+                event = "index_sync.finished",
+                target_id = %report.target_id,
+                snapshot_id = report.snapshot_id.as_deref().unwrap_or(""),
+                endpoint_db = report.endpoint_db.as_str(),
+                filemap = report.filemap.as_str(),
+                bytes_downloaded = report.bytes_downloaded,
+                "index_sync.finished"
+            );
+            Ok(report)
+        }
+        Err(e) => {
+            tracing::warn!(
+                event = "index_sync.failed",
+                target_id = %params.target_id,
+                error_code = e.code(),
+                error_message = %e,
+                "index_sync.failed"
+            );
+            Err(ControlError::from(&e))
+        }
+    }
+}
+
+/// Settings for `ep`'s pooled MTProto client; creates its cache dir.
+fn endpoint_storage_config(
+    settings: &settings_config::SettingsV2,
+    ep: &settings_config::TelegramEndpoint,
+    data_root: &Path,
+    api_hash: &str,
+    bot_token: &str,
+    session: Option<Vec<u8>>,
+) -> std::io::Result<TelegramMtProtoStorageConfig> {
+    let cache_dir = data_root.join("cache").join("mtproto").join(&ep.id);
+    std::fs::create_dir_all(&cache_dir)?;
+    Ok(TelegramMtProtoStorageConfig {
+        provider: settings_config::endpoint_provider(&ep.id),
+        api_id: ep.mtproto_api_id(&settings.telegram.mtproto),
+        api_hash: api_hash.to_string(),
+        bot_token: bot_token.to_string(),
+        chat_id: ep.chat_id.clone(),
+        migrated_from_chat_ids: ep.migrated_from_chat_ids.clone(),
+        session,
+        cache_dir,
+        min_delay_ms: Some(ep.rate_limit.min_delay_ms as u64),
+        max_concurrent_uploads: Some(ep.rate_limit.max_concurrent_uploads as usize),
+        helper_path: None,
+        bootstrap_pin_mode: ep.bootstrap.pin_mode,
+        object_prefix: ep.object_prefix.clone().or_else(|| {
+            televy_backup_core::device::load_or_create_device_identity(data_root)
+                .ok()
+                .map(|d| ep.object_prefix_for(&d.device_name))
+        }),
+    })
+}
+
+fn run_failure_details(
+    e: &televy_backup_core::Error,
+    run_log_path: &Path,
+    secrets: &[&str],
+) -> (String, Vec<String>) {
+    let error_message = secrets.iter().fold(e.to_string(), |acc, secret| {
+        televy_backup_core::run_log::redact_secret(acc, secret)
+    });
+    let log_excerpt = match televy_backup_core::run_log::read_run_log_excerpt(run_log_path, secrets)
+    {
+        Ok(lines) => lines,
+        Err(err) => {
+            tracing::warn!(
+                event = "run_log.excerpt_failed",
+                error = %err,
+                path = %run_log_path.display(),
+                "run_log.excerpt_failed"
+            );
+            Vec::new()
+        }
+    };
+    (error_message, log_excerpt)
+}
 
 fn try_consume_manual_trigger_file(path: &Path) -> std::io::Result<bool> {
     match std::fs::remove_file(path) {
@@ -2050,7 +4146,7 @@ async fn preflight_remote_first_index_sync_daemon(
     tracing::debug!(event = "phase.start", phase = "index_sync", "phase.start");
     if let Some(sink) = sink {
         sink.on_progress(TaskProgress {
-            phase: "index_sync".to_string(),
+            phase: Phase::IndexSync,
             ..Default::default()
         });
     }
@@ -2072,6 +4168,26 @@ async fn preflight_remote_first_index_sync_daemon(
         return Ok(televy_backup_core::RemoteDedupeMode::Disabled);
     }
 
+    let pin_mode = bootstrap::PinnedStorage::bootstrap_pin_mode(storage);
+    if pin_mode == bootstrap::BootstrapPinMode::Disabled {
+        // Remote index and dedupe sync both hang off the catalog.
+        tracing::debug!(
+            event = "index_sync.skipped",
+            reason = "bootstrap_disabled",
+            target_id,
+            "bootstrap.pin_mode is disabled; continue with the local index only"
+        );
+        tracing::debug!(
+            event = "phase.finish",
+            phase = "index_sync",
+            duration_ms = started.elapsed().as_millis() as u64,
+            index_source = "skipped",
+            reason = "bootstrap_disabled",
+            "phase.finish"
+        );
+        return Ok(televy_backup_core::RemoteDedupeMode::Disabled);
+    }
+
     std::fs::create_dir_all(filemap_dir)?;
 
     // Strict remote gating: Telegram errors in bootstrap/index fetch are fatal; only "bootstrap is
@@ -2093,6 +4209,7 @@ async fn preflight_remote_first_index_sync_daemon(
                 storage,
                 &endpoint_latest.endpoint_index_id,
                 &endpoint_latest.manifest_object_id,
+                None,
                 master_key,
                 local_endpoint_db,
                 None,
@@ -2128,7 +4245,8 @@ async fn preflight_remote_first_index_sync_daemon(
             event = "index_sync.skipped",
             reason = "bootstrap_missing",
             target_id,
-            "no pinned bootstrap catalog (first init); continue without remote endpoint sync"
+            pin_mode = pin_mode.as_str(),
+            "no bootstrap catalog (first init); continue without remote endpoint sync"
         );
     }
 
@@ -2180,22 +4298,19 @@ async fn preflight_remote_first_index_sync_daemon(
         let pool = televy_backup_core::index_db::open_index_db(local_endpoint_db).await?;
 
         let provider = storage.provider();
-        let kind = provider.split(['/', ':']).next().unwrap_or(provider).trim();
-        let like = format!("{kind}%");
         let base_row = sqlx::query(
             r#"
             SELECT s.snapshot_id as snapshot_id
             FROM snapshots s
             JOIN remote_indexes ri ON ri.snapshot_id = s.snapshot_id
             WHERE s.source_path = ?
-              AND (ri.provider = ? OR ri.provider LIKE ?)
+              AND ri.provider = ?
             ORDER BY s.created_at DESC
             LIMIT 1
             "#,
         )
         .bind(source_path)
         .bind(provider)
-        .bind(&like)
         .fetch_optional(&pool)
         .await?;
 
@@ -2204,7 +4319,7 @@ async fn preflight_remote_first_index_sync_daemon(
             let cached_path = filemap_dir.join(format!("{base_snapshot_id}.sqlite"));
             if !cached_path.exists() {
                 let row = sqlx::query(
-                    "SELECT provider, manifest_object_id FROM remote_indexes WHERE snapshot_id = ? LIMIT 1",
+                    "SELECT provider, manifest_object_id, manifest_sha256 FROM remote_indexes WHERE snapshot_id = ? LIMIT 1",
                 )
                 .bind(&base_snapshot_id)
                 .fetch_optional(&pool)
@@ -2218,17 +4333,40 @@ async fn preflight_remote_first_index_sync_daemon(
 
                 let row_provider: String = row.get("provider");
                 let manifest_object_id: String = row.get("manifest_object_id");
-                let row_kind = row_provider
-                    .split(['/', ':'])
-                    .next()
-                    .unwrap_or(&row_provider)
-                    .trim();
-                if row_provider == provider || row_kind == kind {
+                // Prefer the hash pinned in the catalog for this target; the synced endpoint DB
+                // records it too for snapshots uploaded by current versions.
+                let manifest_sha256 = catalog
+                    .as_ref()
+                    .and_then(|c| c.targets.iter().find(|t| t.target_id == target_id))
+                    .and_then(|t| t.latest.as_ref())
+                    .filter(|l| {
+                        l.snapshot_id == base_snapshot_id
+                            && l.manifest_object_id == manifest_object_id
+                    })
+                    .and_then(|l| l.manifest_sha256.clone())
+                    .or_else(|| row.get::<Option<String>, _>("manifest_sha256"));
+                if row_provider == provider {
+                    if manifest_sha256.is_none() {
+                        televy_backup_core::remote_index_db::warn_manifest_unverified(
+                            &base_snapshot_id,
+                            &manifest_object_id,
+                        );
+                    }
+                    let mut conn = pool.acquire().await?;
+                    let data_key = televy_backup_core::index_db::snapshot_data_key(
+                        &mut conn,
+                        master_key,
+                        &base_snapshot_id,
+                    )
+                    .await?
+                    .unwrap_or_else(|| televy_backup_core::DataKey::master(master_key));
+                    drop(conn);
                     televy_backup_core::remote_index_db::download_and_write_index_db_atomic(
                         storage,
                         &base_snapshot_id,
                         &manifest_object_id,
-                        master_key,
+                        manifest_sha256.as_deref(),
+                        data_key.key(),
                         &cached_path,
                         None,
                         Some(provider),
@@ -2253,12 +4391,14 @@ async fn preflight_remote_first_index_sync_daemon(
 
 async fn preflight_local_quick_stats_daemon(
     source_path: &Path,
+    one_file_system: bool,
+    default_excludes: bool,
     sink: Option<&dyn ProgressSink>,
     cancel: Option<CancellationToken>,
 ) -> televy_backup_core::Result<SourceQuickStats> {
     if let Some(sink) = sink {
         sink.on_progress(TaskProgress {
-            phase: "prepare".to_string(),
+            phase: Phase::Prepare,
             ..Default::default()
         });
     }
@@ -2266,7 +4406,12 @@ async fn preflight_local_quick_stats_daemon(
     let source_path = source_path.to_path_buf();
     let cancel_for_task = cancel;
     let stats = tokio::task::spawn_blocking(move || {
-        televy_backup_core::compute_source_quick_stats(&source_path, cancel_for_task.as_ref())
+        televy_backup_core::compute_source_quick_stats(
+            &source_path,
+            cancel_for_task.as_ref(),
+            one_file_system,
+            default_excludes,
+        )
     })
     .await
     .map_err(|e| televy_backup_core::Error::InvalidConfig {
@@ -2275,7 +4420,7 @@ async fn preflight_local_quick_stats_daemon(
 
     if let Some(sink) = sink {
         sink.on_progress(TaskProgress {
-            phase: "prepare".to_string(),
+            phase: Phase::Prepare,
             source_files_total: Some(stats.files_total),
             source_bytes_total: Some(stats.bytes_total),
             ..Default::default()
@@ -2285,21 +4430,6 @@ async fn preflight_local_quick_stats_daemon(
     Ok(stats)
 }
 
-fn is_likely_private_chat_id(chat_id: &str) -> bool {
-    let s = chat_id.trim();
-    let Ok(id) = s.parse::<i64>() else {
-        return false;
-    };
-    !s.starts_with("-100") && id > 0
-}
-
-fn parse_hhmm(s: &str) -> Result<(u8, u8), Box<dyn std::error::Error>> {
-    let (hh, mm) = s.split_once(':').ok_or("daily_at must be HH:MM")?;
-    let hh: u8 = hh.parse()?;
-    let mm: u8 = mm.parse()?;
-    Ok((hh, mm))
-}
-
 fn default_config_dir() -> PathBuf {
     let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
     PathBuf::from(home)
@@ -2308,12 +4438,64 @@ fn default_config_dir() -> PathBuf {
         .join("TelevyBackup")
 }
 
+fn prune_run_logs_best_effort(data_root: &Path, settings: &settings_config::SettingsV2) {
+    match televy_backup_core::run_log::prune_run_logs(data_root, &settings.logs) {
+        Ok(stats) if stats.deleted > 0 || stats.skipped_active > 0 => tracing::info!(
+            event = "run_log.pruned",
+            deleted = stats.deleted as u64,
+            kept = stats.kept as u64,
+            skipped_active = stats.skipped_active as u64,
+            "run_log.pruned"
+        ),
+        Ok(_) => {}
+        Err(e) => tracing::warn!(
+            event = "run_log.prune_failed",
+            error = %e,
+            "run_log.prune_failed"
+        ),
+    }
+}
+
+/// Appends to `audit.ndjson` in the data dir; failures are logged and never fail the caller.
+pub(crate) fn record_audit(actor: AuditActor, op: &str, details: serde_json::Value) {
+    let Some(data_root) = DATA_ROOT_CACHE.get() else {
+        return;
+    };
+    if let Err(e) = audit::append_audit_entry(data_root, actor, op, details) {
+        tracing::warn!(event = "audit.append_failed", op, error = %e, "audit.append_failed");
+    }
+}
+
+/// Records a scheduled run in `usage.sqlite` in the background, so a locked DB never delays or
+/// fails the run.
+fn record_usage(
+    enabled: bool,
+    target_id: &str,
+    bytes: u64,
+    duration_seconds: f64,
+    status: RunStatus,
+) {
+    let Some(data_root) = DATA_ROOT_CACHE.get() else {
+        return;
+    };
+    if !enabled {
+        return;
+    }
+    let run = UsageRun::finished_now("backup", Some(target_id), bytes, duration_seconds, status);
+    tokio::spawn(async move {
+        usage::record_usage_run_best_effort(data_root, &run).await;
+    });
+}
+
 fn default_data_dir() -> PathBuf {
     default_config_dir()
 }
 
 const MASTER_KEY_KEY: &str = "televybackup.master_key";
+/// Run logs are also pruned at the start of every run; this covers idle periods.
+const RUN_LOG_PRUNE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
 static CONFIG_ROOT_CACHE: OnceLock<PathBuf> = OnceLock::new();
+static DATA_ROOT_CACHE: OnceLock<PathBuf> = OnceLock::new();
 static VAULT_KEY_CACHE: OnceLock<Mutex<Option<[u8; 32]>>> = OnceLock::new();
 static VAULT_KEY_LOAD_LOCK: OnceLock<Mutex<()>> = OnceLock::new();
 
@@ -2336,11 +4518,36 @@ pub(crate) fn clear_cached_vault_key() {
     }
 }
 
+/// `ep`'s `mtproto.api_hash_key` override, else the already loaded global API hash.
+fn endpoint_api_hash(
+    provider: &televy_backup_core::secrets::SecretsProvider,
+    store: Option<&televy_backup_core::secrets::SecretsStore>,
+    ep: &settings_config::TelegramEndpoint,
+    global_api_hash: &str,
+) -> Option<String> {
+    match ep.mtproto.api_hash_key.as_deref() {
+        Some(key) => store.and_then(|s| get_secret_from_store(provider, s, key)),
+        None => Some(global_api_hash.to_string()),
+    }
+}
+
 fn get_secret_from_store(
+    provider: &televy_backup_core::secrets::SecretsProvider,
     store: &televy_backup_core::secrets::SecretsStore,
     key: &str,
 ) -> Option<String> {
-    store.get(key).map(|s| s.to_string())
+    match provider.get(key, Some(store)) {
+        Ok(v) => v.map(|(value, _)| value),
+        Err(e) => {
+            tracing::warn!(
+                event = "secrets.provider_failed",
+                key,
+                error = %e,
+                "secrets.provider_failed"
+            );
+            store.get(key).map(|s| s.to_string())
+        }
+    }
 }
 
 fn decode_base64_32(b64: &str) -> Result<[u8; 32], Box<dyn std::error::Error>> {
@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use televy_backup_core::{TelegramMtProtoStorage, TelegramMtProtoStorageConfig};
+use tokio::time::Duration;
+
+/// Long enough for an hourly schedule to reuse the previous run's connection.
+pub const MTPROTO_STORAGE_IDLE_TIMEOUT: Duration = Duration::from_secs(75 * 60);
+
+struct PoolEntry {
+    storage: TelegramMtProtoStorage,
+    fingerprint: String,
+    last_used: Mutex<Instant>,
+}
+
+/// Connected MTProto storages keyed by endpoint id, kept across runs.
+///
+/// An entry is replaced when the endpoint's connection settings or credentials change (see
+/// [`connection_fingerprint`]), when it fails a health check before reuse, or once it has been
+/// idle for [`MTPROTO_STORAGE_IDLE_TIMEOUT`].
+#[derive(Default)]
+pub struct MtProtoStoragePool {
+    entries: HashMap<String, PoolEntry>,
+}
+
+impl MtProtoStoragePool {
+    /// Makes sure a healthy storage for `endpoint_id` is connected with `config`.
+    ///
+    /// `config.session` is only used when a new connection is made; a reused connection keeps its
+    /// own (newer) session.
+    pub async fn ensure_connected(
+        &mut self,
+        endpoint_id: &str,
+        config: TelegramMtProtoStorageConfig,
+    ) -> televy_backup_core::Result<()> {
+        let fingerprint = connection_fingerprint(&config);
+        if let Some(entry) = self.entries.get(endpoint_id) {
+            let reason = if entry.fingerprint != fingerprint {
+                Some("config_changed".to_string())
+            } else if config.session.is_none() {
+                // The stored session was cleared (e.g. from the UI): start over.
+                Some("session_cleared".to_string())
+            } else {
+                entry
+                    .storage
+                    .pinned_object_id()
+                    .err()
+                    .map(|e| format!("health_check_failed: {e}"))
+            };
+            match reason {
+                None => {
+                    entry.touch();
+                    tracing::debug!(
+                        event = "mtproto.storage_pool.reuse",
+                        endpoint_id,
+                        "mtproto.storage_pool.reuse"
+                    );
+                    return Ok(());
+                }
+                Some(reason) => self.remove(endpoint_id, &reason).await,
+            }
+        }
+
+        let mut fingerprint_config = config.clone();
+        let storage = TelegramMtProtoStorage::connect(config).await?;
+        let fingerprint = if storage.migrated_from_chat_id().is_some() {
+            // Settings are about to point at the supergroup; keep this connection when they do.
+            fingerprint_config.chat_id = storage.chat_id().to_string();
+            fingerprint_config.migrated_from_chat_ids = storage.migrated_from_chat_ids().to_vec();
+            connection_fingerprint(&fingerprint_config)
+        } else {
+            fingerprint
+        };
+        tracing::info!(
+            event = "mtproto.storage_pool.connect",
+            endpoint_id,
+            "mtproto.storage_pool.connect"
+        );
+        self.entries.insert(
+            endpoint_id.to_string(),
+            PoolEntry {
+                storage,
+                fingerprint,
+                last_used: Mutex::new(Instant::now()),
+            },
+        );
+        Ok(())
+    }
+
+    pub fn get(&self, endpoint_id: &str) -> Option<&TelegramMtProtoStorage> {
+        self.entries.get(endpoint_id).map(|e| &e.storage)
+    }
+
+    /// Restarts the idle clock (call when a run using the endpoint finishes).
+    pub fn touch(&self, endpoint_id: &str) {
+        if let Some(entry) = self.entries.get(endpoint_id) {
+            entry.touch();
+        }
+    }
+
+    /// Disconnects storages that have not been used for `idle_timeout`.
+    pub async fn evict_idle(&mut self, idle_timeout: Duration) {
+        let idle = self
+            .entries
+            .iter()
+            .filter(|(_, e)| e.idle_for() >= idle_timeout)
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<_>>();
+        for endpoint_id in idle {
+            self.remove(&endpoint_id, "idle_timeout").await;
+        }
+    }
+
+    /// Disconnects storages of endpoints that are no longer configured.
+    pub async fn retain_endpoints(&mut self, settings: &televy_backup_core::config::SettingsV2) {
+        let removed = self
+            .entries
+            .keys()
+            .filter(|id| !settings.telegram_endpoints.iter().any(|ep| &ep.id == *id))
+            .cloned()
+            .collect::<Vec<_>>();
+        for endpoint_id in removed {
+            self.remove(&endpoint_id, "endpoint_removed").await;
+        }
+    }
+
+    pub async fn clear(&mut self, reason: &str) {
+        if self.entries.is_empty() {
+            return;
+        }
+
+        tracing::info!(
+            event = "mtproto.storage_pool.clear",
+            reason,
+            endpoint_count = self.entries.len(),
+            "mtproto.storage_pool.clear"
+        );
+
+        let drained = std::mem::take(&mut self.entries);
+        let _ = tokio::task::spawn_blocking(move || drop(drained)).await;
+    }
+
+    async fn remove(&mut self, endpoint_id: &str, reason: &str) {
+        let Some(entry) = self.entries.remove(endpoint_id) else {
+            return;
+        };
+        tracing::info!(
+            event = "mtproto.storage_pool.disconnect",
+            endpoint_id,
+            reason,
+            "mtproto.storage_pool.disconnect"
+        );
+        // Dropping shuts down the helper processes, which blocks.
+        let _ = tokio::task::spawn_blocking(move || drop(entry)).await;
+    }
+}
+
+impl PoolEntry {
+    fn touch(&self) {
+        if let Ok(mut last_used) = self.last_used.lock() {
+            *last_used = Instant::now();
+        }
+    }
+
+    fn idle_for(&self) -> Duration {
+        self.last_used
+            .lock()
+            .map(|t| t.elapsed())
+            .unwrap_or(Duration::MAX)
+    }
+}
+
+/// Everything a live connection depends on except the session, which the connection refreshes
+/// itself.
+fn connection_fingerprint(config: &TelegramMtProtoStorageConfig) -> String {
+    let mut hasher = blake3::Hasher::new();
+    for part in [
+        config.provider.as_str(),
+        &config.api_id.to_string(),
+        &config.api_hash,
+        &config.bot_token,
+        &config.chat_id,
+        &config.migrated_from_chat_ids.join(","),
+        &config.cache_dir.display().to_string(),
+        &format!("{:?}", config.min_delay_ms),
+        &format!("{:?}", config.max_concurrent_uploads),
+        &format!("{:?}", config.helper_path),
+        config.bootstrap_pin_mode.as_str(),
+        &format!("{:?}", config.object_prefix),
+    ] {
+        hasher.update(part.as_bytes());
+        hasher.update(&[0]);
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use televy_backup_core::bootstrap::BootstrapPinMode;
+
+    use super::*;
+
+    #[test]
+    fn fingerprint_tracks_credentials_and_endpoint_settings_but_not_session() {
+        let base = TelegramMtProtoStorageConfig {
+            provider: "telegram.mtproto/ep1".to_string(),
+            api_id: 1,
+            api_hash: "hash".to_string(),
+            bot_token: "token".to_string(),
+            chat_id: "-100".to_string(),
+            migrated_from_chat_ids: Vec::new(),
+            session: None,
+            cache_dir: "/tmp/cache".into(),
+            min_delay_ms: Some(250),
+            max_concurrent_uploads: Some(2),
+            helper_path: None,
+            bootstrap_pin_mode: BootstrapPinMode::Pin,
+            object_prefix: None,
+        };
+        let fp = connection_fingerprint(&base);
+
+        let with_session = TelegramMtProtoStorageConfig {
+            session: Some(vec![1, 2, 3]),
+            ..base.clone()
+        };
+        assert_eq!(connection_fingerprint(&with_session), fp);
+
+        let new_token = TelegramMtProtoStorageConfig {
+            bot_token: "token2".to_string(),
+            ..base.clone()
+        };
+        assert_ne!(connection_fingerprint(&new_token), fp);
+
+        let new_chat = TelegramMtProtoStorageConfig {
+            chat_id: "-200".to_string(),
+            ..base.clone()
+        };
+        assert_ne!(connection_fingerprint(&new_chat), fp);
+
+        let new_rate = TelegramMtProtoStorageConfig {
+            min_delay_ms: Some(500),
+            ..base.clone()
+        };
+        assert_ne!(connection_fingerprint(&new_rate), fp);
+
+        let new_pin_mode = TelegramMtProtoStorageConfig {
+            bootstrap_pin_mode: BootstrapPinMode::TaggedMessage,
+            ..base.clone()
+        };
+        assert_ne!(connection_fingerprint(&new_pin_mode), fp);
+
+        let new_prefix = TelegramMtProtoStorageConfig {
+            object_prefix: Some("mac-mini".to_string()),
+            ..base
+        };
+        assert_ne!(connection_fingerprint(&new_prefix), fp);
+    }
+}
@@ -0,0 +1,537 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::{RwLock, Semaphore, oneshot};
+
+use televy_backup_core::ErrorCode;
+use televy_backup_core::control::{ControlError, ControlRequest, ControlResponse};
+use televy_backup_core::index_db::{self, SnapshotQuery};
+use televy_backup_core::remote::{
+    self, REMOTE_DEFAULT_LIMIT, REMOTE_MAX_LIMIT, REMOTE_METHODS, RemoteRequest,
+    RemoteRunsListParams, RemoteServerStream, RemoteSnapshotsListParams,
+};
+use televy_backup_core::run_log;
+use televy_backup_core::security::PassphraseAttempts;
+use televy_backup_core::status::StatusSnapshot;
+
+type Settings = televy_backup_core::config::SettingsV2;
+type StatusSnapshotFn = Arc<dyn Fn() -> (StatusSnapshot, bool) + Send + Sync>;
+
+/// A client has this long to send its request line.
+const REMOTE_IO_TIMEOUT: Duration = Duration::from_secs(10);
+/// Clients served at once. Each holds a blocking thread until it is answered or times out, so
+/// connections beyond this are closed right away rather than queued on tokio's blocking pool.
+const REMOTE_MAX_CLIENTS: usize = 16;
+/// Addresses with failed token attempts tracked at once; idle ones are dropped beyond this.
+const MAX_TRACKED_ADDRS: usize = 1024;
+
+pub struct RemoteRpcServerHandle {
+    local_addr: SocketAddr,
+    shutdown_tx: Option<oneshot::Sender<()>>,
+    task: Option<tokio::task::JoinHandle<()>>,
+}
+
+impl RemoteRpcServerHandle {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for RemoteRpcServerHandle {
+    fn drop(&mut self) {
+        if let Some(tx) = self.shutdown_tx.take() {
+            let _ = tx.send(());
+        }
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+}
+
+struct RemoteRpcContext {
+    token: String,
+    tls: Option<remote::RemoteTlsServerConfig>,
+    data_root: PathBuf,
+    settings: Arc<RwLock<Settings>>,
+    status_fn: StatusSnapshotFn,
+    attempts: Mutex<HashMap<IpAddr, PassphraseAttempts>>,
+}
+
+/// Serves the read-only monitoring methods ([`REMOTE_METHODS`]) on `listen`; every request must
+/// carry `token`. Addresses sending wrong tokens are backed off like restore passphrase attempts.
+pub fn spawn_remote_rpc_server(
+    listen: SocketAddr,
+    token: String,
+    tls: Option<&remote::RemoteTlsIdentity>,
+    data_root: PathBuf,
+    settings: Arc<RwLock<Settings>>,
+    status_fn: StatusSnapshotFn,
+) -> std::io::Result<RemoteRpcServerHandle> {
+    let tls = tls.map(remote::server_tls_config).transpose()?;
+    let listener = std::net::TcpListener::bind(listen)?;
+    listener.set_nonblocking(true)?;
+    let local_addr = listener.local_addr()?;
+    let listener = tokio::net::TcpListener::from_std(listener)?;
+
+    let ctx = Arc::new(RemoteRpcContext {
+        token,
+        tls,
+        data_root,
+        settings,
+        status_fn,
+        attempts: Mutex::new(HashMap::new()),
+    });
+    let clients = Arc::new(Semaphore::new(REMOTE_MAX_CLIENTS));
+    let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<()>();
+    let task = tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = &mut shutdown_rx => break,
+                accept = listener.accept() => {
+                    let (stream, peer) = match accept {
+                        Ok(x) => x,
+                        Err(e) => {
+                            tracing::warn!(
+                                event = "remote.accept_failed",
+                                error = %e,
+                                "remote.accept_failed"
+                            );
+                            continue;
+                        }
+                    };
+                    let Ok(permit) = clients.clone().try_acquire_owned() else {
+                        tracing::warn!(
+                            event = "remote.client_rejected",
+                            peer = %peer,
+                            max_clients = REMOTE_MAX_CLIENTS,
+                            "remote.client_rejected"
+                        );
+                        continue;
+                    };
+                    let ctx = ctx.clone();
+                    let rt = tokio::runtime::Handle::current();
+                    tokio::task::spawn_blocking(move || {
+                        let _permit = permit;
+                        if let Err(e) = handle_remote_client(stream, peer, &ctx, &rt) {
+                            tracing::debug!(
+                                event = "remote.client_failed",
+                                peer = %peer,
+                                error = %e,
+                                "remote.client_failed"
+                            );
+                        }
+                    });
+                }
+            }
+        }
+    });
+
+    Ok(RemoteRpcServerHandle {
+        local_addr,
+        shutdown_tx: Some(shutdown_tx),
+        task: Some(task),
+    })
+}
+
+fn handle_remote_client(
+    stream: tokio::net::TcpStream,
+    peer: SocketAddr,
+    ctx: &RemoteRpcContext,
+    rt: &tokio::runtime::Handle,
+) -> std::io::Result<()> {
+    let stream = stream.into_std()?;
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(REMOTE_IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(REMOTE_IO_TIMEOUT))?;
+    let mut stream = RemoteServerStream::new(stream, ctx.tls.as_ref())?;
+
+    let resp = match remote::read_line(&mut stream) {
+        Ok(line) if line.trim().is_empty() => return Ok(()),
+        Ok(line) => match serde_json::from_str::<RemoteRequest>(line.trim_end()) {
+            Ok(req) => {
+                let id = req.request.id.clone();
+                match authorize(ctx, peer.ip(), &req.token) {
+                    Ok(()) => match rt.block_on(dispatch(ctx, &req.request)) {
+                        Ok(result) => ControlResponse::ok(id, result),
+                        Err(e) => ControlResponse::err(id, e),
+                    },
+                    Err(e) => ControlResponse::err(id, e),
+                }
+            }
+            Err(e) => ControlResponse::err(
+                "unknown",
+                ControlError::invalid_request(
+                    format!("invalid request json: {e}"),
+                    serde_json::json!({}),
+                ),
+            ),
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::InvalidData => ControlResponse::err(
+            "unknown",
+            ControlError::invalid_request("request too large", serde_json::json!({})),
+        ),
+        Err(e) => return Err(e),
+    };
+
+    remote::write_line(&mut stream, &resp)?;
+    stream.close();
+    Ok(())
+}
+
+fn authorize(ctx: &RemoteRpcContext, ip: IpAddr, token: &str) -> Result<(), ControlError> {
+    let mut attempts = ctx
+        .attempts
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let now = Instant::now();
+    if let Some(wait) = attempts.get(&ip).and_then(|a| a.retry_after(now)) {
+        return Err(rate_limited_error(wait));
+    }
+
+    if remote::token_matches(&ctx.token, token) {
+        attempts.remove(&ip);
+        return Ok(());
+    }
+
+    if attempts.len() >= MAX_TRACKED_ADDRS && !attempts.contains_key(&ip) {
+        attempts.retain(|_, a| a.retry_after(now).is_some());
+    }
+    let entry = attempts.entry(ip).or_default();
+    let wait = entry.record_failure(now);
+    tracing::warn!(
+        event = "remote.unauthorized",
+        peer = %ip,
+        failures = entry.failures(),
+        retry_after_ms = wait.map(|d| d.as_millis() as u64),
+        "remote.unauthorized"
+    );
+    Err(ControlError::new(
+        ErrorCode::RemoteUnauthorized,
+        "invalid remote token",
+        false,
+        serde_json::json!({}),
+    ))
+}
+
+fn rate_limited_error(wait: Duration) -> ControlError {
+    ControlError::new(
+        ErrorCode::RemoteRateLimited,
+        "too many failed remote token attempts",
+        true,
+        serde_json::json!({ "retryAfterMs": wait.as_millis() as u64 }),
+    )
+}
+
+fn params<T: serde::de::DeserializeOwned + Default>(
+    req: &ControlRequest,
+) -> Result<T, ControlError> {
+    if req.params.is_null() {
+        return Ok(T::default());
+    }
+    serde_json::from_value(req.params.clone()).map_err(|e| {
+        ControlError::invalid_request(format!("invalid params: {e}"), serde_json::json!({}))
+    })
+}
+
+fn limit(limit: Option<u32>) -> u32 {
+    limit.unwrap_or(REMOTE_DEFAULT_LIMIT).min(REMOTE_MAX_LIMIT)
+}
+
+async fn dispatch(
+    ctx: &RemoteRpcContext,
+    req: &ControlRequest,
+) -> Result<serde_json::Value, ControlError> {
+    let db_failed = |e: std::io::Error| {
+        ControlError::new(
+            ErrorCode::DbFailed,
+            e.to_string(),
+            false,
+            serde_json::json!({}),
+        )
+    };
+    match req.method.as_str() {
+        "status.get" => {
+            let (snap, _) = (ctx.status_fn)();
+            serde_json::to_value(snap).map_err(|e| {
+                ControlError::new(
+                    ErrorCode::StatusInvalid,
+                    e.to_string(),
+                    false,
+                    serde_json::json!({}),
+                )
+            })
+        }
+        "snapshots.list" => {
+            let p = params::<RemoteSnapshotsListParams>(req)?;
+            let paged = p.paginate || p.cursor.is_some();
+            let bound = |name: &str, raw: Option<&str>| {
+                raw.map(|raw| {
+                    index_db::snapshot_created_at_bound(raw).ok_or_else(|| {
+                        ControlError::invalid_request(
+                            format!("{name} must be YYYY-MM-DD or RFC3339 (got {raw:?})"),
+                            serde_json::json!({}),
+                        )
+                    })
+                })
+                .transpose()
+            };
+            let mut query = SnapshotQuery {
+                source_path: p.source_path.clone(),
+                since: bound("since", p.since.as_deref())?,
+                until: bound("until", p.until.as_deref())?,
+                asc: p.asc,
+                limit: limit(p.limit),
+            };
+
+            // Snapshots don't record a target id; a target is its endpoint DB + source path.
+            let db_paths = match p.target_id.as_deref() {
+                Some(target_id) => {
+                    let settings = ctx.settings.read().await;
+                    let target = settings
+                        .targets
+                        .iter()
+                        .find(|t| t.id == target_id)
+                        .ok_or_else(|| {
+                            ControlError::not_found(
+                                format!("unknown target_id: {target_id}"),
+                                serde_json::json!({ "targetId": target_id }),
+                            )
+                        })?;
+                    if query
+                        .source_path
+                        .as_deref()
+                        .is_some_and(|p| p != target.source_path)
+                    {
+                        return Ok(if paged {
+                            serde_json::json!({ "snapshots": [], "nextCursor": null })
+                        } else {
+                            serde_json::json!({ "snapshots": [] })
+                        });
+                    }
+                    query.source_path = Some(target.source_path.clone());
+                    let db = ctx
+                        .data_root
+                        .join("index")
+                        .join(format!("index.{}.sqlite", target.endpoint_id));
+                    if db.exists() { vec![db] } else { Vec::new() }
+                }
+                None => index_db::list_index_db_paths(&ctx.data_root).map_err(db_failed)?,
+            };
+            if paged {
+                let cursor = p
+                    .cursor
+                    .as_deref()
+                    .map(index_db::SnapshotCursor::decode)
+                    .transpose()
+                    .map_err(|e| {
+                        ControlError::invalid_request(e.to_string(), serde_json::json!({}))
+                    })?;
+                let page = index_db::list_snapshot_page(&db_paths, &query, cursor.as_ref())
+                    .await
+                    .map_err(|e| ControlError::from(&e))?;
+                return Ok(serde_json::json!(page));
+            }
+            let snapshots = index_db::list_snapshots(&db_paths, &query)
+                .await
+                .map_err(|e| ControlError::from(&e))?;
+            Ok(serde_json::json!({ "snapshots": snapshots }))
+        }
+        "stats.get" => {
+            let db_paths = index_db::list_index_db_paths(&ctx.data_root).map_err(db_failed)?;
+            let stats = index_db::index_stats(&db_paths)
+                .await
+                .map_err(|e| ControlError::from(&e))?;
+            Ok(serde_json::json!(stats))
+        }
+        "runs.list" => {
+            let p = params::<RemoteRunsListParams>(req)?;
+            let runs = run_log::summarize_run_logs(
+                &ctx.data_root,
+                p.target_id.as_deref(),
+                p.kind.as_deref(),
+                limit(p.limit) as usize,
+            )
+            .map_err(|e| {
+                ControlError::new(
+                    ErrorCode::LogReadFailed,
+                    e.to_string(),
+                    false,
+                    serde_json::json!({}),
+                )
+            })?;
+            Ok(serde_json::json!({ "runs": runs }))
+        }
+        method => {
+            debug_assert!(!REMOTE_METHODS.contains(&method));
+            Err(ControlError::method_not_found(
+                format!("remote monitoring is read-only; {method} is not served"),
+                serde_json::json!({ "method": method }),
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status_fn() -> StatusSnapshotFn {
+        let state = Arc::new(Mutex::new(crate::StatusRuntimeState::from_settings(
+            &Settings::default(),
+        )));
+        Arc::new(move || (state.lock().unwrap().build_snapshot(0), false))
+    }
+
+    async fn call(
+        server: &RemoteRpcServerHandle,
+        token: &str,
+        fingerprint: Option<String>,
+        method: &str,
+    ) -> ControlResponse {
+        let addr = server.local_addr().to_string();
+        let token = token.to_string();
+        let req = ControlRequest::new("r1", method, serde_json::json!({}));
+        tokio::task::spawn_blocking(move || {
+            remote::remote_call(
+                &addr,
+                &token,
+                fingerprint.as_deref(),
+                req,
+                Duration::from_secs(5),
+            )
+        })
+        .await
+        .unwrap()
+        .unwrap()
+    }
+
+    fn code(resp: &ControlResponse) -> &str {
+        &resp.error.as_ref().expect("error response").code
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn serves_only_read_methods_and_backs_off_wrong_tokens() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = spawn_remote_rpc_server(
+            "127.0.0.1:0".parse().unwrap(),
+            "s3cret".to_string(),
+            None,
+            dir.path().to_path_buf(),
+            Arc::new(RwLock::new(Settings::default())),
+            status_fn(),
+        )
+        .unwrap();
+
+        let resp = call(&server, "s3cret", None, "stats.get").await;
+        assert!(resp.ok, "{resp:?}");
+        assert_eq!(resp.result.unwrap()["snapshotsTotal"], 0);
+        let resp = call(&server, "s3cret", None, "status.get").await;
+        assert_eq!(resp.result.unwrap()["type"], "status.snapshot");
+        let resp = call(&server, "s3cret", None, "runs.list").await;
+        assert_eq!(resp.result.unwrap()["runs"], serde_json::json!([]));
+        let resp = call(&server, "s3cret", None, "backup.runNow").await;
+        assert_eq!(code(&resp), "control.method_not_found");
+
+        for _ in 0..televy_backup_core::security::PASSPHRASE_FREE_FAILURES {
+            let resp = call(&server, "wrong", None, "stats.get").await;
+            assert_eq!(code(&resp), "remote.unauthorized");
+        }
+        // Locked out now, even with the right token.
+        let resp = call(&server, "s3cret", None, "stats.get").await;
+        assert_eq!(code(&resp), "remote.rate_limited");
+        assert!(
+            resp.error.unwrap().details["retryAfterMs"]
+                .as_u64()
+                .unwrap()
+                > 0
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn connections_beyond_the_client_limit_are_closed_at_once() {
+        use std::io::Read;
+
+        let dir = tempfile::tempdir().unwrap();
+        let server = spawn_remote_rpc_server(
+            "127.0.0.1:0".parse().unwrap(),
+            "s3cret".to_string(),
+            None,
+            dir.path().to_path_buf(),
+            Arc::new(RwLock::new(Settings::default())),
+            status_fn(),
+        )
+        .unwrap();
+        let addr = server.local_addr();
+
+        // Clients that connect but never send their request line.
+        let idle = (0..REMOTE_MAX_CLIENTS)
+            .map(|_| std::net::TcpStream::connect(addr).unwrap())
+            .collect::<Vec<_>>();
+        let closed = tokio::task::spawn_blocking(move || {
+            let started = Instant::now();
+            loop {
+                let mut extra = std::net::TcpStream::connect(addr).unwrap();
+                extra
+                    .set_read_timeout(Some(Duration::from_millis(500)))
+                    .unwrap();
+                // EOF (or a reset) rather than a read timeout: the server dropped it.
+                match extra.read(&mut [0u8; 1]) {
+                    Ok(0) => return true,
+                    Err(e) if e.kind() == std::io::ErrorKind::ConnectionReset => return true,
+                    _ if started.elapsed() > Duration::from_secs(5) => return false,
+                    _ => {}
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert!(closed);
+
+        // Their slots free up once they hang up.
+        drop(idle);
+        let served = tokio::task::spawn_blocking(move || {
+            let started = Instant::now();
+            loop {
+                let req = ControlRequest::new("r1", "stats.get", serde_json::json!({}));
+                let res = remote::remote_call(
+                    &addr.to_string(),
+                    "s3cret",
+                    None,
+                    req,
+                    Duration::from_secs(5),
+                );
+                if res.as_ref().is_ok_and(|resp| resp.ok)
+                    || started.elapsed() > Duration::from_secs(5)
+                {
+                    return res;
+                }
+                std::thread::sleep(Duration::from_millis(20));
+            }
+        })
+        .await
+        .unwrap()
+        .unwrap();
+        assert!(served.ok, "{served:?}");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn tls_listener_answers_clients_pinning_its_certificate() {
+        let dir = tempfile::tempdir().unwrap();
+        let identity = remote::load_or_create_tls_identity(dir.path()).unwrap();
+        let server = spawn_remote_rpc_server(
+            "127.0.0.1:0".parse().unwrap(),
+            "s3cret".to_string(),
+            Some(&identity),
+            dir.path().to_path_buf(),
+            Arc::new(RwLock::new(Settings::default())),
+            status_fn(),
+        )
+        .unwrap();
+
+        let resp = call(&server, "s3cret", Some(identity.fingerprint()), "stats.get").await;
+        assert!(resp.ok, "{resp:?}");
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::VecDeque;
+
+use televy_backup_core::control::QueueEntry;
+use uuid::Uuid;
+
+/// What asked for a queued run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunTrigger {
+    Schedule,
+    /// The `control/backup-now` file (all enabled targets).
+    Manual,
+    /// `backup.runNow` over control IPC.
+    Ipc,
+}
+
+impl RunTrigger {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Schedule => "schedule",
+            Self::Manual => "manual",
+            Self::Ipc => "ipc",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct QueuedRun {
+    /// Also the run's `task_id` once it starts.
+    pub task_id: String,
+    pub target_id: String,
+    pub trigger: RunTrigger,
+    /// Targets enqueued together for one endpoint share a backup group (see `plan_backup_groups`).
+    pub group_id: Option<String>,
+    pub enqueued_at: String,
+}
+
+/// FIFO of backups waiting for a free run slot; at most one entry per target.
+///
+/// In memory only: a restarted daemon starts with an empty queue.
+#[derive(Debug, Default)]
+pub struct RunQueue {
+    entries: VecDeque<QueuedRun>,
+}
+
+impl RunQueue {
+    /// Queues a run for `target_id`, or returns the entry already queued for it. The position is
+    /// 1-based; the flag is false when an existing entry was returned.
+    pub fn enqueue(
+        &mut self,
+        target_id: &str,
+        trigger: RunTrigger,
+        group_id: Option<&str>,
+    ) -> (&QueuedRun, usize, bool) {
+        if let Some(idx) = self.entries.iter().position(|e| e.target_id == target_id) {
+            return (&self.entries[idx], idx + 1, false);
+        }
+        self.entries.push_back(QueuedRun {
+            task_id: format!("tsk_{}", Uuid::new_v4()),
+            target_id: target_id.to_string(),
+            trigger,
+            group_id: group_id.map(str::to_string),
+            enqueued_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        });
+        let len = self.entries.len();
+        (&self.entries[len - 1], len, true)
+    }
+
+    pub fn pop_front(&mut self) -> Option<QueuedRun> {
+        self.entries.pop_front()
+    }
+
+    /// Takes the first run that isn't a scheduled one; scheduled runs keep their place (quiet
+    /// hours).
+    pub fn pop_first_unscheduled(&mut self) -> Option<QueuedRun> {
+        let idx = self
+            .entries
+            .iter()
+            .position(|e| e.trigger != RunTrigger::Schedule)?;
+        self.entries.remove(idx)
+    }
+
+    pub fn get(&self, target_id: &str) -> Option<&QueuedRun> {
+        self.entries.iter().find(|e| e.target_id == target_id)
+    }
+
+    pub fn remove(&mut self, task_id: &str) -> Option<QueuedRun> {
+        let idx = self.entries.iter().position(|e| e.task_id == task_id)?;
+        self.entries.remove(idx)
+    }
+
+    /// Drops entries whose target no longer exists (settings reload).
+    pub fn retain_targets(&mut self, keep: impl Fn(&str) -> bool) {
+        self.entries.retain(|e| keep(&e.target_id));
+    }
+
+    /// 1-based position of the run queued for `target_id`.
+    pub fn position(&self, target_id: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|e| e.target_id == target_id)
+            .map(|idx| idx + 1)
+    }
+
+    pub fn group_target_ids(&self, group_id: &str) -> Vec<String> {
+        self.entries
+            .iter()
+            .filter(|e| e.group_id.as_deref() == Some(group_id))
+            .map(|e| e.target_id.clone())
+            .collect()
+    }
+
+    pub fn list(&self) -> Vec<QueueEntry> {
+        self.entries
+            .iter()
+            .enumerate()
+            .map(|(idx, e)| QueueEntry {
+                task_id: e.task_id.clone(),
+                target_id: e.target_id.clone(),
+                trigger: e.trigger.as_str().to_string(),
+                position: u32::try_from(idx + 1).unwrap_or(u32::MAX),
+                enqueued_at: e.enqueued_at.clone(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queue_is_fifo_and_collapses_duplicate_targets() {
+        let mut q = RunQueue::default();
+        let (a, pos, added) = q.enqueue("a", RunTrigger::Schedule, Some("grp_1"));
+        let a_task = a.task_id.clone();
+        assert_eq!((pos, added), (1, true));
+        q.enqueue("b", RunTrigger::Schedule, Some("grp_1"));
+        q.enqueue("c", RunTrigger::Ipc, None);
+
+        let (dup, pos, added) = q.enqueue("a", RunTrigger::Ipc, None);
+        assert_eq!(
+            (dup.task_id.as_str(), pos, added),
+            (a_task.as_str(), 1, false)
+        );
+        assert_eq!(dup.trigger, RunTrigger::Schedule);
+
+        assert_eq!(q.group_target_ids("grp_1"), vec!["a", "b"]);
+        let b_task = q.list()[1].task_id.clone();
+        assert_eq!(
+            q.remove(&b_task).map(|e| e.target_id),
+            Some("b".to_string())
+        );
+        assert!(q.remove(&b_task).is_none());
+        assert_eq!(q.position("c"), Some(2));
+
+        let listed = q.list();
+        assert_eq!(
+            listed
+                .iter()
+                .map(|e| (e.target_id.as_str(), e.position, e.trigger.as_str()))
+                .collect::<Vec<_>>(),
+            vec![("a", 1, "schedule"), ("c", 2, "ipc")]
+        );
+
+        assert_eq!(q.pop_front().map(|e| e.task_id), Some(a_task));
+        q.retain_targets(|id| id != "c");
+        assert!(q.pop_front().is_none());
+    }
+
+    #[test]
+    fn unscheduled_runs_can_pass_scheduled_ones() {
+        let mut q = RunQueue::default();
+        q.enqueue("a", RunTrigger::Schedule, Some("grp_1"));
+        q.enqueue("b", RunTrigger::Ipc, None);
+        q.enqueue("c", RunTrigger::Manual, None);
+
+        assert_eq!(
+            q.pop_first_unscheduled().map(|e| e.target_id),
+            Some("b".to_string())
+        );
+        assert_eq!(
+            q.pop_first_unscheduled().map(|e| e.target_id),
+            Some("c".to_string())
+        );
+        assert!(q.pop_first_unscheduled().is_none());
+        assert_eq!(q.get("a").map(|e| e.trigger), Some(RunTrigger::Schedule));
+        assert_eq!(q.position("a"), Some(1));
+    }
+}
@@ -0,0 +1,616 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Datelike, NaiveDateTime, TimeZone, Timelike};
+use televy_backup_core::config as settings_config;
+
+/// A slot that started longer ago than this when a tick first sees it came due while the daemon
+/// was not ticking (asleep, busy with a run); see `schedule.catch_up`.
+pub const MISSED_SLOT_AFTER_SECS: i64 = 60;
+
+/// Schedule slots already queued for a target (in memory only, like the run queue).
+#[derive(Debug, Default, Clone)]
+pub struct TargetScheduleState {
+    pub last_hourly: Option<(i32, u32, u32, u32)>, // year, month, day, hour
+    pub last_daily: Option<(i32, u32, u32)>,       // year, month, day
+}
+
+impl TargetScheduleState {
+    fn consumed(&self, slot: ScheduleSlot) -> bool {
+        match slot {
+            ScheduleSlot::Hourly(key) => self.last_hourly == Some(key),
+            ScheduleSlot::Daily(key) => self.last_daily == Some(key),
+            ScheduleSlot::Manual => false,
+        }
+    }
+
+    fn consume(&mut self, slot: ScheduleSlot) {
+        match slot {
+            ScheduleSlot::Hourly(key) => self.last_hourly = Some(key),
+            ScheduleSlot::Daily(key) => self.last_daily = Some(key),
+            ScheduleSlot::Manual => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduleSlot {
+    Hourly((i32, u32, u32, u32)),
+    Daily((i32, u32, u32)),
+    Manual,
+}
+
+impl std::fmt::Display for ScheduleSlot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Hourly((y, m, d, h)) => write!(f, "hourly:{y:04}-{m:02}-{d:02}T{h:02}"),
+            Self::Daily((y, m, d)) => write!(f, "daily:{y:04}-{m:02}-{d:02}"),
+            Self::Manual => f.write_str("manual"),
+        }
+    }
+}
+
+/// What one scheduler tick decided for a target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduleOutcome {
+    Due(ScheduleSlot),
+    TargetDisabled,
+    /// A slot started while the target was disabled; recorded once as a skipped run.
+    SkippedDisabled(ScheduleSlot),
+    ScheduleDisabled,
+    /// The slot that started in the window was already queued.
+    AlreadyRan(ScheduleSlot),
+    /// The slot passed while the daemon was not ticking and `schedule.catch_up` is off.
+    Missed(ScheduleSlot),
+    /// No slot started in the window.
+    NotDue,
+}
+
+impl ScheduleOutcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Due(_) => "due",
+            Self::TargetDisabled => "target_disabled",
+            Self::SkippedDisabled(_) => "skipped_disabled",
+            Self::ScheduleDisabled => "schedule_disabled",
+            Self::AlreadyRan(_) => "already_ran",
+            Self::Missed(_) => "missed",
+            Self::NotDue => "not_due",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TargetScheduleCheck<'a, Tz: TimeZone> {
+    pub target: &'a settings_config::Target,
+    pub outcome: ScheduleOutcome,
+    /// Start of the target's slot in the window, if one started there.
+    pub slot_at: Option<DateTime<Tz>>,
+    /// The slot came due while the daemon was not ticking and runs now to catch up.
+    pub caught_up: bool,
+}
+
+/// One scheduler tick: decides for every target whether a slot started in `(since, now]` and
+/// consumes the due slots in `states`.
+///
+/// With `manual` (the `control/backup-now` file) every enabled target is due; its scheduled slot
+/// is still consumed so it does not run twice. A target counts as enabled once its
+/// `disabled_until` has passed. A slot that started more than [`MISSED_SLOT_AFTER_SECS`] before
+/// `now` is run late when `schedule.catch_up` is on and reported as missed otherwise.
+pub fn evaluate_schedule<'a, Tz: TimeZone>(
+    settings: &'a settings_config::SettingsV2,
+    states: &mut HashMap<String, TargetScheduleState>,
+    since: &DateTime<Tz>,
+    now: &DateTime<Tz>,
+    manual: bool,
+) -> Result<Vec<TargetScheduleCheck<'a, Tz>>, Box<dyn std::error::Error>> {
+    let mut checks = Vec::with_capacity(settings.targets.len());
+    for target in &settings.targets {
+        let enabled = target.enabled_at(now);
+        let state = states.entry(target.id.clone()).or_default();
+        let eff = settings_config::effective_schedule(&settings.schedule, target.schedule.as_ref());
+        let in_window = if eff.enabled {
+            schedule_slot_in_window(&eff, since, now)?
+        } else {
+            None
+        };
+        let slot_at = in_window.as_ref().map(|(_, at)| at.clone());
+        let late = slot_at.as_ref().is_some_and(|at| {
+            now.clone().signed_duration_since(at.clone())
+                > chrono::Duration::seconds(MISSED_SLOT_AFTER_SECS)
+        });
+        let scheduled = match in_window {
+            Some((slot, _)) if state.consumed(slot) => Err(slot),
+            Some((slot, _)) => {
+                state.consume(slot);
+                Ok(Some(slot))
+            }
+            None => Ok(None),
+        };
+
+        if !enabled {
+            let outcome = match scheduled {
+                Ok(Some(slot)) => ScheduleOutcome::SkippedDisabled(slot),
+                _ => ScheduleOutcome::TargetDisabled,
+            };
+            checks.push(TargetScheduleCheck {
+                target,
+                outcome,
+                slot_at,
+                caught_up: false,
+            });
+            continue;
+        }
+
+        let outcome = match scheduled {
+            _ if manual => ScheduleOutcome::Due(ScheduleSlot::Manual),
+            Ok(Some(slot)) if late && !eff.catch_up => ScheduleOutcome::Missed(slot),
+            Ok(Some(slot)) => ScheduleOutcome::Due(slot),
+            Err(slot) => ScheduleOutcome::AlreadyRan(slot),
+            Ok(None) if !eff.enabled => ScheduleOutcome::ScheduleDisabled,
+            Ok(None) => ScheduleOutcome::NotDue,
+        };
+        checks.push(TargetScheduleCheck {
+            target,
+            outcome,
+            slot_at,
+            caught_up: late
+                && matches!(
+                    outcome,
+                    ScheduleOutcome::Due(ScheduleSlot::Hourly(_) | ScheduleSlot::Daily(_))
+                ),
+        });
+    }
+    Ok(checks)
+}
+
+/// A schedule slot and the instant it started.
+type SlotStart<Tz> = (ScheduleSlot, DateTime<Tz>);
+
+/// The latest slot of `eff` at or before `now` with the instant it started, if that is after
+/// `since`.
+///
+/// Checking a window rather than the current minute keeps a slot that passed while a run kept
+/// the main loop busy; it is queued as soon as the loop gets back to the scheduler.
+///
+/// Slots are wall-clock times in `now`'s time zone. When a DST change repeats an hour, its slot
+/// starts at the first occurrence. When a DST change skips an hour, an hourly slot in it does not
+/// exist and a daily slot starts when the gap ends.
+fn schedule_slot_in_window<Tz: TimeZone>(
+    eff: &settings_config::Schedule,
+    since: &DateTime<Tz>,
+    now: &DateTime<Tz>,
+) -> Result<Option<SlotStart<Tz>>, Box<dyn std::error::Error>> {
+    let tz = now.timezone();
+    let local = now.naive_local();
+    let (slot, at) = match eff.kind.as_str() {
+        "hourly" => {
+            let Some(mut wall) =
+                local
+                    .date()
+                    .and_hms_opt(local.hour(), eff.hourly_minute as u32, 0)
+            else {
+                return Ok(None);
+            };
+            if wall > local {
+                wall -= chrono::Duration::hours(1);
+            }
+            let slot = ScheduleSlot::Hourly((wall.year(), wall.month(), wall.day(), wall.hour()));
+            (slot, tz.from_local_datetime(&wall).earliest())
+        }
+        "daily" => {
+            let (hh, mm) = parse_hhmm(&eff.daily_at)?;
+            let Some(mut wall) = local.date().and_hms_opt(hh as u32, mm as u32, 0) else {
+                return Ok(None);
+            };
+            if wall > local {
+                wall -= chrono::Duration::days(1);
+            }
+            let slot = ScheduleSlot::Daily((wall.year(), wall.month(), wall.day()));
+            (slot, local_or_after_gap(&tz, wall))
+        }
+        other => return Err(format!("unsupported schedule.kind: {other}").into()),
+    };
+    Ok(at.filter(|at| at > since && at <= now).map(|at| (slot, at)))
+}
+
+/// `wall` in `tz`, moved to the end of the gap when a DST change skips it.
+pub fn local_or_after_gap<Tz: TimeZone>(tz: &Tz, wall: NaiveDateTime) -> Option<DateTime<Tz>> {
+    (0..=24 * 60).find_map(|m| {
+        tz.from_local_datetime(&(wall + chrono::Duration::minutes(m)))
+            .earliest()
+    })
+}
+
+pub fn parse_hhmm(s: &str) -> Result<(u8, u8), Box<dyn std::error::Error>> {
+    let (hh, mm) = s.split_once(':').ok_or("daily_at must be HH:MM")?;
+    let hh: u8 = hh.parse()?;
+    let mm: u8 = mm.parse()?;
+    Ok((hh, mm))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{FixedOffset, LocalResult, NaiveDate, Offset};
+
+    use super::*;
+
+    /// Central European time: UTC+1, UTC+2 from 2024-03-31 01:00 UTC until 2024-10-27 01:00 UTC.
+    #[derive(Debug, Clone, Copy)]
+    struct Cet;
+
+    impl Cet {
+        fn dst_at_utc(utc: &NaiveDateTime) -> bool {
+            let start = NaiveDate::from_ymd_opt(2024, 3, 31)
+                .unwrap()
+                .and_hms_opt(1, 0, 0)
+                .unwrap();
+            let end = NaiveDate::from_ymd_opt(2024, 10, 27)
+                .unwrap()
+                .and_hms_opt(1, 0, 0)
+                .unwrap();
+            *utc >= start && *utc < end
+        }
+
+        fn offset(dst: bool) -> FixedOffset {
+            FixedOffset::east_opt(if dst { 7200 } else { 3600 }).unwrap()
+        }
+    }
+
+    impl TimeZone for Cet {
+        type Offset = FixedOffset;
+
+        fn from_offset(_: &FixedOffset) -> Self {
+            Cet
+        }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_hms_opt(12, 0, 0).unwrap())
+        }
+
+        fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+            let candidates = [false, true]
+                .into_iter()
+                .map(Self::offset)
+                .filter(|off| {
+                    let utc = *local - chrono::Duration::seconds(off.local_minus_utc() as i64);
+                    Self::offset(Self::dst_at_utc(&utc)) == *off
+                })
+                .collect::<Vec<_>>();
+            match candidates.as_slice() {
+                [] => LocalResult::None,
+                [one] => LocalResult::Single(*one),
+                // Listed standard time first; the earlier instant is the summer-time one.
+                [std, dst] => LocalResult::Ambiguous(*dst, *std),
+                _ => unreachable!(),
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            self.offset_from_utc_datetime(&utc.and_hms_opt(0, 0, 0).unwrap())
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            Self::offset(Self::dst_at_utc(utc)).fix()
+        }
+    }
+
+    fn utc(d: u32, h: u32, m: u32) -> DateTime<Cet> {
+        chrono::Utc
+            .with_ymd_and_hms(2024, 3, d, h, m, 30)
+            .unwrap()
+            .with_timezone(&Cet)
+    }
+
+    fn local_at(h: u32, m: u32) -> DateTime<chrono::Local> {
+        chrono::Local
+            .with_ymd_and_hms(2024, 1, 5, h, m, 30)
+            .unwrap()
+    }
+
+    fn hourly(minute: u8) -> settings_config::Schedule {
+        settings_config::Schedule {
+            enabled: true,
+            hourly_minute: minute,
+            ..Default::default()
+        }
+    }
+
+    fn daily(at: &str) -> settings_config::Schedule {
+        settings_config::Schedule {
+            enabled: true,
+            kind: "daily".to_string(),
+            daily_at: at.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Slots that come due when the scheduler ticks every minute over `[from, to)` (UTC).
+    fn tick_every_minute(
+        eff: &settings_config::Schedule,
+        from: DateTime<Cet>,
+        to: DateTime<Cet>,
+    ) -> Vec<(ScheduleSlot, String)> {
+        let mut state = TargetScheduleState::default();
+        let mut due = Vec::new();
+        let mut since = from - chrono::Duration::minutes(1);
+        let mut now = from;
+        while now < to {
+            if let Some((slot, at)) = schedule_slot_in_window(eff, &since, &now).unwrap()
+                && !state.consumed(slot)
+            {
+                state.consume(slot);
+                due.push((slot, at.format("%H:%M%:z").to_string()));
+            }
+            since = now;
+            now += chrono::Duration::minutes(1);
+        }
+        due
+    }
+
+    #[test]
+    fn schedule_slot_that_passed_during_a_run_is_still_due() {
+        let due = |eff, state: &TargetScheduleState, since, now| {
+            schedule_slot_in_window(eff, &since, &now)
+                .unwrap()
+                .map(|(slot, _)| slot)
+                .filter(|slot| !state.consumed(*slot))
+        };
+        let hourly = hourly(0);
+        let mut state = TargetScheduleState::default();
+
+        // The 10:00 slot fired while a run kept the loop busy from 09:58 to 10:30.
+        let slot = due(&hourly, &state, local_at(9, 58), local_at(10, 30));
+        assert_eq!(slot, Some(ScheduleSlot::Hourly((2024, 1, 5, 10))));
+        state.last_hourly = Some((2024, 1, 5, 10));
+        assert!(due(&hourly, &state, local_at(9, 58), local_at(10, 30)).is_none());
+        assert!(
+            due(
+                &hourly,
+                &TargetScheduleState::default(),
+                local_at(10, 1),
+                local_at(10, 30)
+            )
+            .is_none()
+        );
+
+        let daily = daily("02:00");
+        let slot = due(&daily, &state, local_at(1, 30), local_at(3, 0));
+        assert_eq!(slot, Some(ScheduleSlot::Daily((2024, 1, 5))));
+        assert!(due(&daily, &state, local_at(2, 1), local_at(3, 0)).is_none());
+    }
+
+    #[test]
+    fn hourly_slots_across_spring_forward_skip_the_missing_hour() {
+        // 2024-03-31 00:00 to 03:00 UTC = 01:00 CET to 05:00 CEST; 02:xx local does not exist.
+        let due = tick_every_minute(&hourly(30), utc(31, 0, 0), utc(31, 3, 0));
+        assert_eq!(
+            due,
+            vec![
+                (
+                    ScheduleSlot::Hourly((2024, 3, 31, 1)),
+                    "01:30+01:00".to_string()
+                ),
+                (
+                    ScheduleSlot::Hourly((2024, 3, 31, 3)),
+                    "03:30+02:00".to_string()
+                ),
+                (
+                    ScheduleSlot::Hourly((2024, 3, 31, 4)),
+                    "04:30+02:00".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn daily_slot_in_the_spring_forward_gap_runs_when_the_gap_ends() {
+        let due = tick_every_minute(&daily("02:30"), utc(30, 0, 0), utc(31, 6, 0));
+        assert_eq!(
+            due,
+            vec![
+                (
+                    ScheduleSlot::Daily((2024, 3, 30)),
+                    "02:30+01:00".to_string()
+                ),
+                (
+                    ScheduleSlot::Daily((2024, 3, 31)),
+                    "03:00+02:00".to_string()
+                ),
+            ]
+        );
+
+        // Slots next to the gap are not affected.
+        let due = tick_every_minute(&daily("03:00"), utc(31, 0, 0), utc(31, 6, 0));
+        assert_eq!(
+            due,
+            vec![(
+                ScheduleSlot::Daily((2024, 3, 31)),
+                "03:00+02:00".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn evaluate_schedule_reports_why_targets_are_not_due() {
+        let target = |id: &str, enabled: bool| settings_config::Target {
+            id: id.to_string(),
+            source_path: format!("/src/{id}"),
+            label: String::new(),
+            label_template: None,
+            endpoint_id: "ep".to_string(),
+            enabled,
+            disabled_until: None,
+            priority: 0,
+            schedule: None,
+            scan: None,
+            target_key: false,
+            allow_overlap: false,
+            min_expected_files: 0,
+            filters: Vec::new(),
+            sqlite_consistent: Vec::new(),
+        };
+        let mut paused = target("paused", true);
+        paused.schedule = Some(settings_config::TargetScheduleOverride {
+            enabled: Some(false),
+            ..Default::default()
+        });
+        let mut until = target("until", false);
+        until.disabled_until = Some("2024-03-05T10:30:00Z".to_string());
+        let settings = settings_config::SettingsV2 {
+            schedule: hourly(0),
+            targets: vec![target("on", true), target("off", false), paused, until],
+            ..Default::default()
+        };
+
+        let mut states = HashMap::new();
+        let outcomes = |checks: Vec<TargetScheduleCheck<'_, Cet>>| {
+            checks
+                .into_iter()
+                .map(|c| (c.target.id.clone(), c.outcome))
+                .collect::<Vec<_>>()
+        };
+        let key = (2024, 3, 5, 10);
+        let first = evaluate_schedule(&settings, &mut states, &utc(5, 8, 59), &utc(5, 9, 0), false)
+            .unwrap();
+        assert_eq!(
+            outcomes(first),
+            vec![
+                (
+                    "on".to_string(),
+                    ScheduleOutcome::Due(ScheduleSlot::Hourly(key))
+                ),
+                (
+                    "off".to_string(),
+                    ScheduleOutcome::SkippedDisabled(ScheduleSlot::Hourly(key))
+                ),
+                ("paused".to_string(), ScheduleOutcome::ScheduleDisabled),
+                (
+                    "until".to_string(),
+                    ScheduleOutcome::SkippedDisabled(ScheduleSlot::Hourly(key))
+                ),
+            ]
+        );
+
+        let again = evaluate_schedule(&settings, &mut states, &utc(5, 8, 59), &utc(5, 9, 0), false)
+            .unwrap();
+        assert_eq!(
+            again[0].outcome,
+            ScheduleOutcome::AlreadyRan(ScheduleSlot::Hourly(key))
+        );
+
+        let later =
+            evaluate_schedule(&settings, &mut states, &utc(5, 9, 0), &utc(5, 9, 10), true).unwrap();
+        assert_eq!(
+            outcomes(later),
+            vec![
+                ("on".to_string(), ScheduleOutcome::Due(ScheduleSlot::Manual)),
+                ("off".to_string(), ScheduleOutcome::TargetDisabled),
+                (
+                    "paused".to_string(),
+                    ScheduleOutcome::Due(ScheduleSlot::Manual)
+                ),
+                ("until".to_string(), ScheduleOutcome::TargetDisabled),
+            ]
+        );
+
+        // `disabled_until` passed at 10:30Z: the 11:00Z slot runs again.
+        let resumed = evaluate_schedule(
+            &settings,
+            &mut states,
+            &utc(5, 10, 59),
+            &utc(5, 11, 0),
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            resumed[3].outcome,
+            ScheduleOutcome::Due(ScheduleSlot::Hourly((2024, 3, 5, 12)))
+        );
+        assert_eq!(
+            resumed[1].outcome,
+            ScheduleOutcome::SkippedDisabled(ScheduleSlot::Hourly((2024, 3, 5, 12)))
+        );
+    }
+
+    #[test]
+    fn slot_missed_while_asleep_runs_once_unless_catch_up_is_off() {
+        let target = settings_config::Target {
+            id: "t".to_string(),
+            source_path: "/src/t".to_string(),
+            label: String::new(),
+            label_template: None,
+            endpoint_id: "ep".to_string(),
+            enabled: true,
+            disabled_until: None,
+            priority: 0,
+            schedule: None,
+            scan: None,
+            target_key: false,
+            allow_overlap: false,
+            min_expected_files: 0,
+            filters: Vec::new(),
+            sqlite_consistent: Vec::new(),
+        };
+        let mut settings = settings_config::SettingsV2 {
+            schedule: hourly(0),
+            targets: vec![target],
+            ..Default::default()
+        };
+        let slot = ScheduleSlot::Hourly((2024, 3, 5, 11));
+
+        // Asleep from 08:00 to 11:20 local: only the latest slot (11:00) runs, once.
+        let mut states = HashMap::new();
+        let woke = evaluate_schedule(
+            &settings,
+            &mut states,
+            &utc(5, 7, 0),
+            &utc(5, 10, 20),
+            false,
+        )
+        .unwrap();
+        assert_eq!(woke[0].outcome, ScheduleOutcome::Due(slot));
+        assert!(woke[0].caught_up);
+        let next = evaluate_schedule(
+            &settings,
+            &mut states,
+            &utc(5, 10, 20),
+            &utc(5, 10, 21),
+            false,
+        )
+        .unwrap();
+        assert_eq!(next[0].outcome, ScheduleOutcome::NotDue);
+
+        // On time is not a catch-up.
+        let on_time = evaluate_schedule(
+            &settings,
+            &mut HashMap::new(),
+            &utc(5, 9, 59),
+            &utc(5, 10, 0),
+            false,
+        )
+        .unwrap();
+        assert_eq!(on_time[0].outcome, ScheduleOutcome::Due(slot));
+        assert!(!on_time[0].caught_up);
+
+        settings.schedule.catch_up = false;
+        let mut states = HashMap::new();
+        let woke = evaluate_schedule(
+            &settings,
+            &mut states,
+            &utc(5, 7, 0),
+            &utc(5, 10, 20),
+            false,
+        )
+        .unwrap();
+        assert_eq!(woke[0].outcome, ScheduleOutcome::Missed(slot));
+        let again = evaluate_schedule(
+            &settings,
+            &mut states,
+            &utc(5, 7, 0),
+            &utc(5, 10, 20),
+            false,
+        )
+        .unwrap();
+        assert_eq!(again[0].outcome, ScheduleOutcome::AlreadyRan(slot));
+    }
+}
@@ -152,6 +152,7 @@ mod tests {
             source: StatusSource {
                 kind: "daemon".to_string(),
                 detail: Some("test".to_string()),
+                degraded: false,
             },
             global: GlobalStatus {
                 up: Rate {
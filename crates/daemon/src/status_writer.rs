@@ -0,0 +1,179 @@
+//! When the daemon rewrites `status.json`.
+//!
+//! Progress reports change the in-memory status many times a second during a fast scan, but the
+//! file only needs the latest state a few times a second. Changes are coalesced into at most one
+//! write per `performance.status_write_interval_ms`; state transitions (a run starting, finishing
+//! or getting queued) are written right away, and an unchanged status is rewritten every
+//! [`HEARTBEAT_INTERVAL`] so readers can tell from `generatedAt` that the daemon is alive. The
+//! status IPC stream builds its snapshots from the in-memory state and is not affected.
+
+use std::time::{Duration, Instant};
+
+/// Rewrite interval of an unchanged status.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Where the in-memory status stands, as seen by the scheduler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StatusMarker {
+    /// Bumped on every progress report.
+    pub revision: u64,
+    /// Hash of the parts whose change is a state transition.
+    pub transition: u64,
+}
+
+/// Counters of the status writer, logged as `status.write_stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StatusWriteStats {
+    /// Files written, heartbeats included.
+    pub writes: u64,
+    /// Writes of an unchanged status.
+    pub heartbeats: u64,
+    /// Progress reports that were folded into a later write instead of getting their own.
+    pub suppressed: u64,
+}
+
+/// Why a write is due. Transitions are written durably (see `status_writer_loop`); losing the
+/// latest progress or heartbeat write to a crash only leaves an older, intact generation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusWrite {
+    /// The first write, or a state transition.
+    Transition,
+    Progress,
+    Heartbeat,
+}
+
+#[derive(Debug)]
+pub struct StatusWriteScheduler {
+    interval: Duration,
+    last_write: Option<(Instant, StatusMarker)>,
+    stats: StatusWriteStats,
+}
+
+impl StatusWriteScheduler {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_write: None,
+            stats: StatusWriteStats::default(),
+        }
+    }
+
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    pub fn stats(&self) -> StatusWriteStats {
+        self.stats
+    }
+
+    /// Whether to write the status at `now`, counting the write when it is due: the first time,
+    /// on a transition, once `interval` passed since the last write with progress pending, or
+    /// after [`HEARTBEAT_INTERVAL`] without changes.
+    pub fn should_write(&mut self, now: Instant, marker: StatusMarker) -> Option<StatusWrite> {
+        let due = match self.last_write {
+            None => Some(StatusWrite::Transition),
+            Some((at, written)) => {
+                let since = now.saturating_duration_since(at);
+                if marker.transition != written.transition {
+                    Some(StatusWrite::Transition)
+                } else if marker.revision != written.revision {
+                    (since >= self.interval).then_some(StatusWrite::Progress)
+                } else {
+                    (since >= HEARTBEAT_INTERVAL).then_some(StatusWrite::Heartbeat)
+                }
+            }
+        };
+        due?;
+
+        let reports = self.last_write.map_or(0, |(_, written)| {
+            marker.revision.wrapping_sub(written.revision)
+        });
+        self.stats.writes += 1;
+        if reports == 0 && self.last_write.is_some() {
+            self.stats.heartbeats += 1;
+        }
+        self.stats.suppressed += reports.saturating_sub(1);
+        self.last_write = Some((now, marker));
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marker(revision: u64, transition: u64) -> StatusMarker {
+        StatusMarker {
+            revision,
+            transition,
+        }
+    }
+
+    #[test]
+    fn progress_is_coalesced_to_one_write_per_interval() {
+        let t0 = Instant::now();
+        let at = |ms: u64| t0 + Duration::from_millis(ms);
+        let mut s = StatusWriteScheduler::new(Duration::from_millis(250));
+
+        assert!(s.should_write(at(0), marker(0, 1)).is_some());
+        // 100 progress reports over 240ms: nothing written until the interval passed.
+        for i in 1..=100 {
+            assert!(s.should_write(at(i * 240 / 100), marker(i, 1)).is_none());
+        }
+        assert!(s.should_write(at(250), marker(100, 1)).is_some());
+        assert!(s.should_write(at(300), marker(101, 1)).is_none());
+
+        assert_eq!(
+            s.stats(),
+            StatusWriteStats {
+                writes: 2,
+                heartbeats: 0,
+                suppressed: 99,
+            }
+        );
+    }
+
+    #[test]
+    fn transitions_are_written_immediately() {
+        let t0 = Instant::now();
+        let at = |ms: u64| t0 + Duration::from_millis(ms);
+        let mut s = StatusWriteScheduler::new(Duration::from_millis(250));
+
+        assert!(s.should_write(at(0), marker(0, 1)).is_some());
+        assert!(s.should_write(at(10), marker(5, 1)).is_none());
+        // The run finished 20ms after the last write.
+        assert_eq!(
+            s.should_write(at(20), marker(6, 2)),
+            Some(StatusWrite::Transition)
+        );
+        assert!(s.should_write(at(30), marker(6, 2)).is_none());
+        assert_eq!(s.stats().suppressed, 5);
+    }
+
+    #[test]
+    fn unchanged_status_gets_a_heartbeat_write() {
+        let t0 = Instant::now();
+        let at = |ms: u64| t0 + Duration::from_millis(ms);
+        let mut s = StatusWriteScheduler::new(Duration::from_millis(250));
+
+        assert!(s.should_write(at(0), marker(3, 1)).is_some());
+        assert!(s.should_write(at(500), marker(3, 1)).is_none());
+        assert!(s.should_write(at(999), marker(3, 1)).is_none());
+        assert_eq!(
+            s.should_write(at(1000), marker(3, 1)),
+            Some(StatusWrite::Heartbeat)
+        );
+        assert_eq!(
+            s.stats(),
+            StatusWriteStats {
+                writes: 2,
+                heartbeats: 1,
+                suppressed: 0,
+            }
+        );
+
+        s.set_interval(Duration::from_millis(500));
+        assert!(s.should_write(at(1400), marker(4, 1)).is_none());
+        assert!(s.should_write(at(1500), marker(4, 1)).is_some());
+    }
+}
@@ -4,6 +4,8 @@ use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter};
 use tokio::net::{UnixListener, UnixStream};
 use tokio::sync::{broadcast, oneshot};
 
+use televy_backup_core::version::DaemonVersionStamp;
+
 #[derive(Debug, serde::Deserialize)]
 #[serde(tag = "type")]
 enum VaultIpcRequest {
@@ -325,10 +327,24 @@ async fn handle_vault_ipc_client(
     Ok(())
 }
 
+/// Like the control socket, every response carries the daemon's version (see
+/// `televy_backup_core::version`).
+#[derive(Debug, serde::Serialize)]
+struct StampedResponse {
+    #[serde(flatten)]
+    resp: VaultIpcResponse,
+    #[serde(flatten)]
+    versions: DaemonVersionStamp,
+}
+
 async fn write_json_line<W: tokio::io::AsyncWrite + Unpin>(
     w: &mut BufWriter<W>,
     v: VaultIpcResponse,
 ) -> std::io::Result<()> {
+    let v = StampedResponse {
+        resp: v,
+        versions: DaemonVersionStamp::new(env!("CARGO_PKG_VERSION")),
+    };
     let line = serde_json::to_string(&v)
         .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
     w.write_all(line.as_bytes()).await?;
@@ -0,0 +1,306 @@
+//! `verify_schedule`: the daemon verifies the latest snapshot of each enabled target every
+//! `every_days`, one target at a time, between backups and never while a backup to the same
+//! endpoint runs or waits in the queue.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use televy_backup_core::chat_remap::{RemappedStorage, load_chat_remaps};
+use televy_backup_core::config as settings_config;
+use televy_backup_core::status::VerifySummary;
+use televy_backup_core::{
+    Error, TelegramMtProtoStorage, VerifyConfig, VerifyOptions, VerifyResult, VerifySample,
+    bootstrap, verify_snapshot_with,
+};
+
+/// How often the main loop looks for a due verify (each look reads every target's index DB).
+pub const VERIFY_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PersistedVerifyState {
+    version: u32,
+    targets: HashMap<String, VerifySummary>,
+}
+
+/// The last scheduled verify of each target, kept in `verify-state.json` so a failure's warning
+/// survives daemon restarts.
+#[derive(Debug, Default)]
+pub struct VerifyLedger {
+    path: Option<PathBuf>,
+    summaries: HashMap<String, VerifySummary>,
+    running: Option<String>,
+}
+
+impl VerifyLedger {
+    pub fn load(data_dir: &Path) -> Self {
+        let path = data_dir.join("verify-state.json");
+        let summaries = match std::fs::read(&path) {
+            Ok(bytes) => match serde_json::from_slice::<PersistedVerifyState>(&bytes) {
+                Ok(state) => state.targets,
+                Err(e) => {
+                    tracing::warn!(
+                        event = "verify.state_load_failed",
+                        path = %path.display(),
+                        error = %e,
+                        "verify.state_load_failed"
+                    );
+                    HashMap::new()
+                }
+            },
+            Err(_) => HashMap::new(),
+        };
+        Self {
+            path: Some(path),
+            summaries,
+            running: None,
+        }
+    }
+
+    pub fn get(&self, target_id: &str) -> Option<&VerifySummary> {
+        self.summaries.get(target_id)
+    }
+
+    pub fn is_running(&self, target_id: &str) -> bool {
+        self.running.as_deref() == Some(target_id)
+    }
+
+    pub fn mark_start(&mut self, target_id: &str) {
+        self.running = Some(target_id.to_string());
+    }
+
+    pub fn record(&mut self, target_id: &str, summary: VerifySummary) {
+        self.running = None;
+        self.summaries.insert(target_id.to_string(), summary);
+        self.persist();
+    }
+
+    /// Clears a failed verify's warning; `false` when the target had none.
+    pub fn acknowledge(&mut self, target_id: &str) -> bool {
+        let Some(summary) = self.summaries.get_mut(target_id).filter(|s| s.warning) else {
+            return false;
+        };
+        summary.warning = false;
+        self.persist();
+        true
+    }
+
+    pub fn retain_targets(&mut self, keep: impl Fn(&str) -> bool) {
+        let before = self.summaries.len();
+        self.summaries.retain(|id, _| keep(id));
+        if self.summaries.len() != before {
+            self.persist();
+        }
+    }
+
+    fn persist(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let doc = PersistedVerifyState {
+            version: 1,
+            targets: self.summaries.clone(),
+        };
+        let res = serde_json::to_vec(&doc)
+            .map_err(std::io::Error::other)
+            .and_then(|bytes| {
+                let tmp = path.with_extension("json.tmp");
+                std::fs::write(&tmp, bytes)?;
+                std::fs::rename(&tmp, path)
+            });
+        if let Err(e) = res {
+            tracing::warn!(
+                event = "verify.state_persist_failed",
+                path = %path.display(),
+                error = %e,
+                "verify.state_persist_failed"
+            );
+        }
+    }
+}
+
+/// Whether a target is due: `every_days` after its last successful verify (`verified_at` in the
+/// index DB) or its last scheduled attempt, whichever is later. Never verified = due.
+pub fn verify_due(
+    every_days: u32,
+    last_verified_at: Option<&str>,
+    last_attempt: Option<&VerifySummary>,
+    now: DateTime<Utc>,
+) -> bool {
+    let parse = |s: &str| {
+        DateTime::parse_from_rfc3339(s)
+            .ok()
+            .map(|t| t.with_timezone(&Utc))
+    };
+    let since = [
+        last_verified_at.and_then(parse),
+        last_attempt.and_then(|s| parse(&s.finished_at)),
+    ]
+    .into_iter()
+    .flatten()
+    .max();
+    since.is_none_or(|t| now.signed_duration_since(t) >= chrono::Duration::days(every_days.into()))
+}
+
+/// `None` (check every chunk) at 100%.
+pub fn verify_sample(schedule: &settings_config::VerifySchedule) -> Option<VerifySample> {
+    (schedule.sample_percent < 100)
+        .then(|| VerifySample::weekly(f64::from(schedule.sample_percent), None))
+}
+
+/// Verifying latest snapshots needs the pinned bootstrap catalog.
+pub fn endpoint_supports_verify(ep: &settings_config::TelegramEndpoint) -> bool {
+    ep.bootstrap.pin_mode != bootstrap::BootstrapPinMode::Disabled
+        && !settings_config::is_likely_private_chat_id(&ep.chat_id)
+}
+
+/// Verifies the latest snapshot of `target` as `televybackup verify latest` does, returning its
+/// id. Index DBs are read from (and downloaded to) `index_dir`.
+pub async fn verify_latest_snapshot(
+    storage: &TelegramMtProtoStorage,
+    master_key: &[u8; 32],
+    target: &settings_config::Target,
+    ep: &settings_config::TelegramEndpoint,
+    index_dir: &Path,
+    sample: Option<VerifySample>,
+) -> televy_backup_core::Result<(String, VerifyResult)> {
+    let cat = bootstrap::load_remote_catalog(storage, master_key)
+        .await?
+        .ok_or_else(|| Error::BootstrapMissing {
+            message: format!(
+                "bootstrap missing ({})",
+                ep.bootstrap.pin_mode.missing_message()
+            ),
+        })?;
+    let latest = cat
+        .targets
+        .iter()
+        .find(|it| it.target_id == target.id)
+        .and_then(|it| it.latest.clone())
+        .ok_or_else(|| Error::BootstrapMissing {
+            message: format!("bootstrap missing latest for target_id: {}", target.id),
+        })?;
+    if latest.manifest_sha256.is_none() {
+        televy_backup_core::remote_index_db::warn_manifest_unverified(
+            &latest.snapshot_id,
+            &latest.manifest_object_id,
+        );
+    }
+
+    let filemap_dir = index_dir.join("filemaps").join(&ep.id);
+    std::fs::create_dir_all(&filemap_dir)?;
+    let endpoint_db_path = index_dir.join(format!("index.{}.sqlite", ep.id));
+    let dedupe_db_path = index_dir
+        .join("dedupe")
+        .join(format!("dedupe.{}.sqlite", ep.id));
+    let endpoint_manifest_object_id = cat
+        .endpoint_latest
+        .as_ref()
+        .map(|v| v.manifest_object_id.clone());
+    let dedupe_catalog_object_id = cat
+        .endpoint_dedupe_latest
+        .as_ref()
+        .map(|v| v.catalog_object_id.clone());
+    let data_key = latest.data_key(master_key, &target.id)?;
+    let cfg = VerifyConfig {
+        snapshot_id: latest.snapshot_id.clone(),
+        filemap_manifest_object_id: latest.manifest_object_id,
+        filemap_manifest_sha256: latest.manifest_sha256,
+        endpoint_manifest_object_id: endpoint_manifest_object_id.clone(),
+        dedupe_catalog_object_id: dedupe_catalog_object_id.clone(),
+        endpoint_dedupe_id: cat
+            .endpoint_dedupe_latest
+            .as_ref()
+            .map(|v| v.endpoint_dedupe_id.clone()),
+        endpoint_index_id: cat
+            .endpoint_latest
+            .as_ref()
+            .map(|v| v.endpoint_index_id.clone()),
+        master_key: *master_key,
+        data_key: Some(data_key),
+        filemap_db_path: filemap_dir.join(format!("{}.sqlite", latest.snapshot_id)),
+        endpoint_db_path: (dedupe_catalog_object_id.is_none()
+            && endpoint_manifest_object_id.is_some())
+        .then(|| endpoint_db_path.clone()),
+        dedupe_db_path: dedupe_catalog_object_id.is_some().then_some(dedupe_db_path),
+        sample,
+    };
+
+    let remapped = RemappedStorage::new(storage, load_chat_remaps(&endpoint_db_path).await?);
+    let res = verify_snapshot_with(
+        &remapped,
+        cfg,
+        VerifyOptions {
+            cancel: None,
+            progress: None,
+            concurrency: (ep.rate_limit.max_concurrent_uploads as usize).max(1),
+        },
+    )
+    .await?;
+    Ok((latest.snapshot_id, res))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(finished_at: &str, status: &str) -> VerifySummary {
+        VerifySummary {
+            finished_at: finished_at.to_string(),
+            status: status.to_string(),
+            snapshot_id: Some("snp_1".to_string()),
+            chunks_checked: None,
+            bytes_checked: None,
+            coverage_percent: None,
+            error_code: (status == "failed").then(|| "telegram.unavailable".to_string()),
+            error_message: None,
+            warning: status == "failed",
+        }
+    }
+
+    #[test]
+    fn due_counts_from_the_later_of_last_success_and_last_attempt() {
+        let now = DateTime::parse_from_rfc3339("2024-06-10T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert!(verify_due(7, None, None, now));
+        assert!(!verify_due(7, Some("2024-06-05T00:00:00Z"), None, now));
+        assert!(verify_due(7, Some("2024-06-03T12:00:00Z"), None, now));
+        // A failed attempt waits a full period too instead of retrying every tick.
+        let failed = summary("2024-06-09T00:00:00Z", "failed");
+        assert!(!verify_due(
+            7,
+            Some("2024-05-01T00:00:00Z"),
+            Some(&failed),
+            now
+        ));
+        assert!(!verify_due(2, None, Some(&failed), now));
+        assert!(verify_due(1, None, Some(&failed), now));
+        assert!(verify_due(1, Some("garbage"), None, now));
+    }
+
+    #[test]
+    fn warnings_persist_until_acknowledged_or_a_passing_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut ledger = VerifyLedger::load(dir.path());
+        assert!(!ledger.acknowledge("t1"));
+        ledger.mark_start("t1");
+        assert!(ledger.is_running("t1"));
+        ledger.record("t1", summary("2024-06-09T00:00:00Z", "failed"));
+        assert!(!ledger.is_running("t1"));
+
+        let reloaded = VerifyLedger::load(dir.path());
+        assert!(reloaded.get("t1").unwrap().warning);
+
+        let mut ledger = reloaded;
+        assert!(ledger.acknowledge("t1"));
+        assert!(!ledger.acknowledge("t1"));
+        assert!(!VerifyLedger::load(dir.path()).get("t1").unwrap().warning);
+
+        ledger.record("t1", summary("2024-06-10T00:00:00Z", "failed"));
+        ledger.record("t1", summary("2024-06-11T00:00:00Z", "succeeded"));
+        assert!(!VerifyLedger::load(dir.path()).get("t1").unwrap().warning);
+    }
+}
@@ -20,15 +20,21 @@ use tokio::task::{JoinHandle, JoinSet};
 use tokio::time::{Duration, Instant, timeout};
 
 const TG_MTPROTO_OBJECT_ID_PREFIX_V1: &str = "tgmtproto:v1:";
+// Reported in the `init` and `version` responses. Version 2 adds `download_batch` /
+// `upload_batch`; cores seeing an older (or missing) version stick to the singular commands.
+const PROTOCOL_VERSION: u64 = 2;
 const INIT_IS_AUTHORIZED_TIMEOUT_SECS: u64 = 120;
 const INIT_BOT_SIGN_IN_TIMEOUT_SECS: u64 = 120;
 const INIT_RESOLVE_CHAT_TIMEOUT_SECS: u64 = 60;
 const UPLOAD_SEND_MESSAGE_TIMEOUT_SECS: u64 = 60;
 const DOWNLOAD_GET_MESSAGE_TIMEOUT_SECS: u64 = 60;
+// `messages.getMessages` / `channels.getMessages` accept at most 100 ids per call.
+const LIST_DOCUMENTS_MAX_COUNT: usize = 100;
 const DOWNLOAD_CHUNK_TIMEOUT_SECS: u64 = 120;
 const LIST_DIALOGS_TIMEOUT_SECS: u64 = 30;
 const WAIT_FOR_CHAT_TIMEOUT_SECS_DEFAULT: u64 = 60;
 const WAIT_FOR_CHAT_TIMEOUT_SECS_MAX: u64 = 10 * 60;
+const WAIT_FOR_CHAT_TIMEOUT_MS_MIN: u64 = 100;
 const UPLOAD_SAVE_FILE_PART_TIMEOUT_SECS: u64 = 120;
 const UPLOAD_SAVE_BIG_FILE_PART_TIMEOUT_SECS: u64 = 120;
 const UPLOAD_BIG_FILE_SIZE_BYTES: usize = 10 * 1024 * 1024;
@@ -405,13 +411,18 @@ mod tests {
 #[serde(tag = "cmd", rename_all = "snake_case")]
 enum Request {
     Init(InitRequest),
+    Version,
     Shutdown,
     Upload(UploadRequest),
     Download(DownloadRequest),
+    DownloadBatch(DownloadBatchRequest),
+    UploadBatch(UploadBatchRequest),
     GetPinned,
     Pin(PinRequest),
+    Delete(DeleteRequest),
     ListDialogs(ListDialogsRequest),
     WaitForChat(WaitForChatRequest),
+    ListDocuments(ListDocumentsRequest),
 }
 
 #[derive(Debug, Deserialize)]
@@ -424,6 +435,8 @@ struct InitRequest {
     bot_token: String,
     #[serde(rename = "chatId")]
     chat_id: String,
+    #[serde(default, rename = "migratedFromChatIds")]
+    migrated_from_chat_ids: Vec<String>,
     #[serde(rename = "session")]
     session_b64: Option<String>,
     #[serde(rename = "cacheDir")]
@@ -438,6 +451,9 @@ struct InitRequest {
 struct UploadRequest {
     filename: String,
     size: usize,
+    /// The body is followed by one line of caption text.
+    #[serde(default, rename = "captionTrailer")]
+    caption_trailer: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -446,12 +462,33 @@ struct DownloadRequest {
     object_id: String,
 }
 
+/// Responds with one `batch_item` event per object, in order; a downloaded item is followed by its
+/// `size` raw bytes, a failed one carries `itemError` instead.
+#[derive(Debug, Deserialize)]
+struct DownloadBatchRequest {
+    #[serde(rename = "objectIds")]
+    object_ids: Vec<String>,
+}
+
+/// The bodies (each with its caption line, if any) follow back to back. Responds with one
+/// `batch_item` event per item, in order, carrying `objectId` or `itemError`.
+#[derive(Debug, Deserialize)]
+struct UploadBatchRequest {
+    items: Vec<UploadRequest>,
+}
+
 #[derive(Debug, Deserialize)]
 struct PinRequest {
     #[serde(rename = "msgId")]
     msg_id: i32,
 }
 
+#[derive(Debug, Deserialize)]
+struct DeleteRequest {
+    #[serde(rename = "msgId")]
+    msg_id: i32,
+}
+
 #[derive(Debug, Deserialize)]
 struct ListDialogsRequest {
     #[serde(default)]
@@ -464,8 +501,24 @@ struct ListDialogsRequest {
 struct WaitForChatRequest {
     #[serde(default, rename = "timeoutSecs")]
     timeout_secs: Option<u64>,
+    /// Takes precedence over `timeoutSecs`, for short polls.
+    #[serde(default, rename = "timeoutMs")]
+    timeout_ms: Option<u64>,
     #[serde(default, rename = "includeUsers")]
     include_users: bool,
+    /// Answer a timeout with no `chat` instead of an error.
+    #[serde(default)]
+    poll: bool,
+    /// Drop updates buffered before the request (default); polls after the first keep them.
+    #[serde(default)]
+    drain: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListDocumentsRequest {
+    #[serde(rename = "fromMsgId")]
+    from_msg_id: i32,
+    count: usize,
 }
 
 #[derive(Debug, Serialize)]
@@ -479,6 +532,26 @@ struct Response {
     data: BTreeMap<String, serde_json::Value>,
 }
 
+#[derive(Debug, Serialize)]
+struct DocumentInfo {
+    #[serde(rename = "msgId")]
+    msg_id: i32,
+    #[serde(rename = "docId")]
+    doc_id: i64,
+    #[serde(rename = "accessHash")]
+    access_hash: i64,
+    size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    caption: Option<String>,
+    /// Original chat (Bot API id) and message id of a post forwarded from a channel.
+    #[serde(rename = "fwdChatId", skip_serializing_if = "Option::is_none")]
+    fwd_chat_id: Option<String>,
+    #[serde(rename = "fwdMsgId", skip_serializing_if = "Option::is_none")]
+    fwd_msg_id: Option<i32>,
+    #[serde(rename = "fileName", skip_serializing_if = "Option::is_none")]
+    file_name: Option<String>,
+}
+
 #[derive(Debug, Serialize)]
 struct DialogInfo {
     kind: String,
@@ -495,6 +568,7 @@ struct DialogInfo {
 
 struct State {
     chat_id: String,
+    migrated_from_chat_ids: Vec<String>,
     cache_dir: PathBuf,
     session: Arc<TlSession>,
     client: Client,
@@ -605,7 +679,7 @@ async fn main() {
                                 ok: true,
                                 error: None,
                                 session_b64: Some(session_b64),
-                                data: BTreeMap::new(),
+                                data: version_data(),
                             },
                         );
                     }
@@ -622,6 +696,18 @@ async fn main() {
                     }
                 }
             }
+            Request::Version => {
+                // Answered before `init` too, so `televybackup version --all` needs no login.
+                let _ = write_response(
+                    &mut output,
+                    Response {
+                        ok: true,
+                        error: None,
+                        session_b64: None,
+                        data: version_data(),
+                    },
+                );
+            }
             Request::Shutdown => {
                 let session_b64 = state.as_ref().map(|s| session_b64(&s.session));
                 let _ = write_response(
@@ -652,21 +738,18 @@ async fn main() {
                     continue;
                 };
 
-                let mut bytes = vec![0u8; req.size];
-                if let Err(e) = input.read_exact(&mut bytes) {
-                    let _ = write_response(
-                        &mut output,
-                        Response {
-                            ok: false,
-                            error: Some(format!("upload bytes read failed: {e}")),
-                            session_b64: Some(session_b64(&s.session)),
-                            data: BTreeMap::new(),
-                        },
-                    );
-                    continue;
-                }
-
-                let res = upload_with_progress(s, req.filename, bytes, &mut output).await;
+                let mut body = UploadBody {
+                    input: &mut input,
+                    remaining: req.size,
+                    caption_pending: req.caption_trailer,
+                };
+                let res = upload_with_progress(s, req.filename, &mut body, &mut output).await;
+                // Whatever the upload did not consume (failure, timeout) must be skipped so the
+                // next request line stays aligned.
+                let res = match body.drain() {
+                    Ok(()) => res,
+                    Err(e) => res.and(Err(format!("upload bytes read failed: {e}"))),
+                };
                 match res {
                     Ok(object_id) => {
                         let mut data = BTreeMap::new();
@@ -779,6 +862,125 @@ async fn main() {
                     }
                 }
             }
+            Request::DownloadBatch(req) => {
+                let Some(s) = state.as_mut() else {
+                    let _ = write_response(
+                        &mut output,
+                        Response {
+                            ok: false,
+                            error: Some("not initialized".to_string()),
+                            session_b64: None,
+                            data: BTreeMap::new(),
+                        },
+                    );
+                    continue;
+                };
+
+                for (index, object_id) in req.object_ids.iter().enumerate() {
+                    let res = match download_to_cache(s, object_id, &mut output).await {
+                        Ok(bytes_path) => {
+                            open_cached_download(&bytes_path).map(|(f, size)| (bytes_path, f, size))
+                        }
+                        Err(err) => Err(err),
+                    };
+                    match res {
+                        Ok((bytes_path, mut f, size)) => {
+                            let mut data = batch_item_data(index);
+                            data.insert("size".to_string(), serde_json::json!(size));
+                            let _ = write_response(
+                                &mut output,
+                                Response {
+                                    ok: true,
+                                    error: None,
+                                    session_b64: Some(session_b64(&s.session)),
+                                    data,
+                                },
+                            );
+                            if let Err(e) = std::io::copy(&mut f, &mut output) {
+                                let _ = output.flush();
+                                eprintln!("stdout copy failed: {e}");
+                                // As for `download`: the core is mid-payload, so hard-exit.
+                                std::process::exit(1);
+                            }
+                            let _ = output.flush();
+                            let _ = std::fs::remove_file(&bytes_path);
+                        }
+                        Err(err) => {
+                            let mut data = batch_item_data(index);
+                            data.insert("itemError".to_string(), serde_json::json!(err));
+                            let _ = write_response(
+                                &mut output,
+                                Response {
+                                    ok: true,
+                                    error: None,
+                                    session_b64: Some(session_b64(&s.session)),
+                                    data,
+                                },
+                            );
+                        }
+                    }
+                }
+            }
+            Request::UploadBatch(req) => {
+                let Some(s) = state.as_mut() else {
+                    // The bodies are on their way regardless; skip them so the stream stays
+                    // aligned.
+                    let mut res = Ok(());
+                    for item in &req.items {
+                        let mut body = UploadBody {
+                            input: &mut input,
+                            remaining: item.size,
+                            caption_pending: item.caption_trailer,
+                        };
+                        res = res.and(body.drain());
+                    }
+                    let error = match res {
+                        Ok(()) => "not initialized".to_string(),
+                        Err(e) => format!("not initialized; upload bytes read failed: {e}"),
+                    };
+                    let _ = write_response(
+                        &mut output,
+                        Response {
+                            ok: false,
+                            error: Some(error),
+                            session_b64: None,
+                            data: BTreeMap::new(),
+                        },
+                    );
+                    continue;
+                };
+
+                for (index, item) in req.items.into_iter().enumerate() {
+                    let mut body = UploadBody {
+                        input: &mut input,
+                        remaining: item.size,
+                        caption_pending: item.caption_trailer,
+                    };
+                    let res = upload_with_progress(s, item.filename, &mut body, &mut output).await;
+                    let res = match body.drain() {
+                        Ok(()) => res,
+                        Err(e) => res.and(Err(format!("upload bytes read failed: {e}"))),
+                    };
+                    let mut data = batch_item_data(index);
+                    match res {
+                        Ok(object_id) => {
+                            data.insert("objectId".to_string(), serde_json::json!(object_id));
+                        }
+                        Err(err) => {
+                            data.insert("itemError".to_string(), serde_json::json!(err));
+                        }
+                    }
+                    let _ = write_response(
+                        &mut output,
+                        Response {
+                            ok: true,
+                            error: None,
+                            session_b64: Some(session_b64(&s.session)),
+                            data,
+                        },
+                    );
+                }
+            }
             Request::GetPinned => {
                 let Some(s) = state.as_mut() else {
                     let _ = write_response(
@@ -868,6 +1070,46 @@ async fn main() {
                     }
                 }
             }
+            Request::Delete(req) => {
+                let Some(s) = state.as_mut() else {
+                    let _ = write_response(
+                        &mut output,
+                        Response {
+                            ok: false,
+                            error: Some("not initialized".to_string()),
+                            session_b64: None,
+                            data: BTreeMap::new(),
+                        },
+                    );
+                    continue;
+                };
+
+                let res = delete_message(s, req.msg_id).await;
+                match res {
+                    Ok(()) => {
+                        let _ = write_response(
+                            &mut output,
+                            Response {
+                                ok: true,
+                                error: None,
+                                session_b64: Some(session_b64(&s.session)),
+                                data: BTreeMap::new(),
+                            },
+                        );
+                    }
+                    Err(err) => {
+                        let _ = write_response(
+                            &mut output,
+                            Response {
+                                ok: false,
+                                error: Some(err),
+                                session_b64: Some(session_b64(&s.session)),
+                                data: BTreeMap::new(),
+                            },
+                        );
+                    }
+                }
+            }
             Request::ListDialogs(req) => {
                 let Some(s) = state.as_mut() else {
                     let _ = write_response(
@@ -925,14 +1167,70 @@ async fn main() {
                     continue;
                 };
 
-                let timeout_secs = req
-                    .timeout_secs
-                    .unwrap_or(WAIT_FOR_CHAT_TIMEOUT_SECS_DEFAULT);
-                let res = wait_for_chat(s, timeout_secs, req.include_users).await;
+                let wait = match req.timeout_ms {
+                    Some(ms) => Duration::from_millis(ms),
+                    None => Duration::from_secs(
+                        req.timeout_secs
+                            .unwrap_or(WAIT_FOR_CHAT_TIMEOUT_SECS_DEFAULT),
+                    ),
+                };
+                let res =
+                    wait_for_chat(s, wait, req.include_users, req.drain.unwrap_or(true)).await;
+                let res = match res {
+                    Ok(chat) => Ok(Some(chat)),
+                    Err(WaitForChatError::TimedOut(_)) if req.poll => Ok(None),
+                    Err(e) => Err(e.into_message()),
+                };
                 match res {
                     Ok(chat) => {
                         let mut data = BTreeMap::new();
-                        data.insert("chat".to_string(), serde_json::json!(chat));
+                        if let Some(chat) = chat {
+                            data.insert("chat".to_string(), serde_json::json!(chat));
+                        }
+                        let _ = write_response(
+                            &mut output,
+                            Response {
+                                ok: true,
+                                error: None,
+                                session_b64: Some(session_b64(&s.session)),
+                                data,
+                            },
+                        );
+                    }
+                    Err(err) => {
+                        let _ = write_response(
+                            &mut output,
+                            Response {
+                                ok: false,
+                                error: Some(err),
+                                session_b64: Some(session_b64(&s.session)),
+                                data: BTreeMap::new(),
+                            },
+                        );
+                    }
+                }
+            }
+            Request::ListDocuments(req) => {
+                let Some(s) = state.as_mut() else {
+                    let _ = write_response(
+                        &mut output,
+                        Response {
+                            ok: false,
+                            error: Some("not initialized".to_string()),
+                            session_b64: None,
+                            data: BTreeMap::new(),
+                        },
+                    );
+                    continue;
+                };
+
+                let count = req.count.clamp(1, LIST_DOCUMENTS_MAX_COUNT);
+                let res = list_documents(s, req.from_msg_id, count).await;
+                match res {
+                    Ok((messages, documents)) => {
+                        let mut data = BTreeMap::new();
+                        data.insert("messages".to_string(), serde_json::json!(messages));
+                        data.insert("documents".to_string(), serde_json::json!(documents));
                         let _ = write_response(
                             &mut output,
                             Response {
@@ -969,6 +1267,36 @@ fn write_response(out: &mut dyn Write, res: Response) -> std::io::Result<()> {
     Ok(())
 }
 
+/// `helperVersion` and `protocolVersion`, reported by `init` and `version`.
+fn version_data() -> BTreeMap<String, serde_json::Value> {
+    let mut data = BTreeMap::new();
+    data.insert(
+        "helperVersion".to_string(),
+        serde_json::json!(env!("CARGO_PKG_VERSION")),
+    );
+    data.insert(
+        "protocolVersion".to_string(),
+        serde_json::json!(PROTOCOL_VERSION),
+    );
+    data
+}
+
+fn batch_item_data(index: usize) -> BTreeMap<String, serde_json::Value> {
+    let mut data = BTreeMap::new();
+    data.insert("event".to_string(), serde_json::json!("batch_item"));
+    data.insert("index".to_string(), serde_json::json!(index));
+    data
+}
+
+fn open_cached_download(path: &std::path::Path) -> Result<(std::fs::File, u64), String> {
+    let f = std::fs::File::open(path).map_err(|e| format!("cache file open failed: {e}"))?;
+    let size = f
+        .metadata()
+        .map_err(|e| format!("cache file stat failed: {e}"))?
+        .len();
+    Ok((f, size))
+}
+
 fn session_b64(session: &TlSession) -> String {
     base64::engine::general_purpose::STANDARD.encode(session.save())
 }
@@ -1038,6 +1366,9 @@ async fn init(req: InitRequest) -> Result<State, String> {
             .map_err(|e| format!("resolve chat failed: {e}"))?,
         )
     };
+    if let Some(new_chat_id) = chat.as_ref().and_then(migrated_to_chat_id) {
+        return Err(chat_migrated_error(&req.chat_id, new_chat_id));
+    }
 
     std::fs::create_dir_all(&req.cache_dir).map_err(|e| format!("cache dir create failed: {e}"))?;
 
@@ -1066,6 +1397,7 @@ async fn init(req: InitRequest) -> Result<State, String> {
 
     Ok(State {
         chat_id: req.chat_id,
+        migrated_from_chat_ids: req.migrated_from_chat_ids,
         cache_dir: req.cache_dir,
         session,
         client,
@@ -1140,6 +1472,46 @@ async fn resolve_chat(client: &Client, chat_id: &str) -> Result<Peer, String> {
         .ok_or_else(|| format!("chat not found: {chat_id}"))
 }
 
+/// Bot API id of the supergroup a basic group was upgraded to, if it was.
+fn migrated_to_chat_id(peer: &Peer) -> Option<i64> {
+    let Peer::Group(group) = peer else {
+        return None;
+    };
+    let tl::enums::Chat::Chat(chat) = &group.raw else {
+        return None;
+    };
+    let channel_id = match chat.migrated_to.as_ref()? {
+        tl::enums::InputChannel::Channel(c) => c.channel_id,
+        tl::enums::InputChannel::FromMessage(c) => c.channel_id,
+        tl::enums::InputChannel::Empty => return None,
+    };
+    Some(-1000000000000 - channel_id)
+}
+
+// Parsed by the core side into `Error::ChatMigrated`; keep the format in sync.
+fn chat_migrated_error(old_chat_id: &str, new_chat_id: i64) -> String {
+    format!(
+        "chat migrated: old={} new={new_chat_id}",
+        old_chat_id.trim()
+    )
+}
+
+/// After a failed write to a basic group, checks whether it has been upgraded in the meantime.
+async fn detect_chat_migration(state: &State) -> Option<String> {
+    if !matches!(state.chat, Some(Peer::Group(_))) {
+        return None;
+    }
+    let peer = timeout(
+        Duration::from_secs(INIT_RESOLVE_CHAT_TIMEOUT_SECS),
+        resolve_chat(&state.client, &state.chat_id),
+    )
+    .await
+    .ok()?
+    .ok()?;
+    let new_chat_id = migrated_to_chat_id(&peer)?;
+    Some(chat_migrated_error(&state.chat_id, new_chat_id))
+}
+
 async fn get_pinned_object_id(state: &mut State) -> Result<Option<String>, String> {
     let chat = require_chat(state)?;
     let pinned = match timeout(
@@ -1187,16 +1559,93 @@ async fn get_pinned_object_id(state: &mut State) -> Result<Option<String>, Strin
 
 async fn pin_message(state: &mut State, msg_id: i32) -> Result<(), String> {
     let chat = require_chat(state)?;
-    timeout(
+    let res = timeout(
         Duration::from_secs(UPLOAD_SEND_MESSAGE_TIMEOUT_SECS),
         state.client.pin_message(chat, msg_id),
     )
     .await
-    .map_err(|_| format!("pin_message timed out after {UPLOAD_SEND_MESSAGE_TIMEOUT_SECS}s"))?
-    .map_err(|e| format!("pin_message failed: {e}"))?;
+    .map_err(|_| format!("pin_message timed out after {UPLOAD_SEND_MESSAGE_TIMEOUT_SECS}s"))?;
+    if let Err(e) = res {
+        return Err(detect_chat_migration(state)
+            .await
+            .unwrap_or_else(|| format!("pin_message failed: {e}")));
+    }
+    Ok(())
+}
+
+/// Deleting a message that is already gone succeeds (Telegram reports 0 affected).
+async fn delete_message(state: &mut State, msg_id: i32) -> Result<(), String> {
+    let chat = require_chat(state)?;
+    let res = timeout(
+        Duration::from_secs(UPLOAD_SEND_MESSAGE_TIMEOUT_SECS),
+        state.client.delete_messages(chat, &[msg_id]),
+    )
+    .await
+    .map_err(|_| format!("delete_messages timed out after {UPLOAD_SEND_MESSAGE_TIMEOUT_SECS}s"))?;
+    if let Err(e) = res {
+        return Err(detect_chat_migration(state)
+            .await
+            .unwrap_or_else(|| format!("delete_messages failed: {e}")));
+    }
     Ok(())
 }
 
+/// Messages that exist in `from_msg_id..from_msg_id + count` and the documents among them. Bots
+/// can't call `messages.getHistory`, so this fetches the window by id.
+async fn list_documents(
+    state: &mut State,
+    from_msg_id: i32,
+    count: usize,
+) -> Result<(usize, Vec<DocumentInfo>), String> {
+    let chat = require_chat(state)?;
+    let ids: Vec<i32> = (0..count)
+        .map_while(|i| from_msg_id.checked_add(i32::try_from(i).ok()?))
+        .filter(|id| *id > 0)
+        .collect();
+    let msgs = timeout(
+        Duration::from_secs(DOWNLOAD_GET_MESSAGE_TIMEOUT_SECS),
+        state.client.get_messages_by_id(chat, &ids),
+    )
+    .await
+    .map_err(|_| {
+        format!("get_messages_by_id timed out after {DOWNLOAD_GET_MESSAGE_TIMEOUT_SECS}s")
+    })?
+    .map_err(|e| format!("get_messages_by_id failed: {e}"))?;
+
+    let mut messages = 0;
+    let mut documents = Vec::new();
+    for msg in msgs.into_iter().flatten() {
+        messages += 1;
+        if let Some(media) = msg.media()
+            && let Ok((doc_id, access_hash, size)) = extract_document_id_and_size(&media)
+        {
+            let (fwd_chat_id, fwd_msg_id) = forward_source(&msg).unzip();
+            let caption = Some(msg.text().to_string()).filter(|t| !t.is_empty());
+            documents.push(DocumentInfo {
+                msg_id: msg.id(),
+                doc_id,
+                access_hash,
+                size,
+                caption,
+                fwd_chat_id,
+                fwd_msg_id,
+                file_name: document_file_name(&media),
+            });
+        }
+    }
+    Ok((messages, documents))
+}
+
+/// Channel post a message was forwarded from; other forwards don't carry the original message id.
+fn forward_source(msg: &grammers_client::types::Message) -> Option<(String, i32)> {
+    let tl::enums::MessageFwdHeader::Header(header) = msg.forward_header()?;
+    let post = header.channel_post?;
+    match header.from_id? {
+        tl::enums::Peer::Channel(c) => Some((format!("-100{}", c.channel_id), post)),
+        _ => None,
+    }
+}
+
 async fn list_dialogs(
     state: &mut State,
     limit: usize,
@@ -1274,23 +1723,44 @@ async fn list_dialogs(
         .map_err(|_| format!("list_dialogs timed out after {LIST_DIALOGS_TIMEOUT_SECS}s"))?
 }
 
+enum WaitForChatError {
+    TimedOut(String),
+    Failed(String),
+}
+
+impl WaitForChatError {
+    fn into_message(self) -> String {
+        match self {
+            Self::TimedOut(message) | Self::Failed(message) => message,
+        }
+    }
+}
+
 async fn wait_for_chat(
     state: &mut State,
-    timeout_secs: u64,
+    wait: Duration,
     include_users: bool,
-) -> Result<DialogInfo, String> {
-    let timeout_secs = timeout_secs.clamp(1, WAIT_FOR_CHAT_TIMEOUT_SECS_MAX);
+    drain: bool,
+) -> Result<DialogInfo, WaitForChatError> {
+    let wait = wait.clamp(
+        Duration::from_millis(WAIT_FOR_CHAT_TIMEOUT_MS_MIN),
+        Duration::from_secs(WAIT_FOR_CHAT_TIMEOUT_SECS_MAX),
+    );
 
     // Drain any already-buffered updates so we only react to messages that arrive *after* the
     // caller started listening. This avoids returning stale dialogs in long-running helper
-    // sessions.
-    {
+    // sessions. Polls after the first skip this, or messages sent between polls would be lost.
+    if drain {
         let deadline = tokio::time::Instant::now() + Duration::from_millis(200);
         let mut drained: u32 = 0;
         while tokio::time::Instant::now() < deadline && drained < 1_000 {
             match timeout(Duration::from_millis(0), state.updates.next()).await {
                 Ok(Ok(_)) => drained += 1,
-                Ok(Err(e)) => return Err(format!("updates next failed: {e}")),
+                Ok(Err(e)) => {
+                    return Err(WaitForChatError::Failed(format!(
+                        "updates next failed: {e}"
+                    )));
+                }
                 Err(_) => break,
             }
         }
@@ -1366,9 +1836,15 @@ async fn wait_for_chat(
         }
     };
 
-    timeout(Duration::from_secs(timeout_secs), fut)
+    timeout(wait, fut)
         .await
-        .map_err(|_| format!("wait_for_chat timed out after {timeout_secs}s"))?
+        .map_err(|_| {
+            WaitForChatError::TimedOut(format!(
+                "wait_for_chat timed out after {}s",
+                wait.as_secs_f64()
+            ))
+        })?
+        .map_err(WaitForChatError::Failed)
 }
 
 fn generate_upload_file_id() -> i64 {
@@ -1444,13 +1920,65 @@ fn write_download_progress(
     Ok(())
 }
 
+/// Upload payload that follows an `upload` request line on stdin (`remaining` bytes).
+struct UploadBody<'a> {
+    input: &'a mut dyn Read,
+    remaining: usize,
+    /// A caption line follows the body and hasn't been read yet.
+    caption_pending: bool,
+}
+
+impl UploadBody<'_> {
+    fn read_part(&mut self, len: usize) -> Result<Vec<u8>, String> {
+        let len = len.min(self.remaining);
+        let mut buf = vec![0u8; len];
+        self.input
+            .read_exact(&mut buf)
+            .map_err(|e| format!("upload bytes read failed: {e}"))?;
+        self.remaining -= len;
+        Ok(buf)
+    }
+
+    /// The caption line sent after the body (empty without one). Call once the body is read.
+    fn read_caption(&mut self) -> std::io::Result<String> {
+        if !self.caption_pending {
+            return Ok(String::new());
+        }
+        let mut line = Vec::new();
+        let mut byte = [0u8; 1];
+        loop {
+            self.input.read_exact(&mut byte)?;
+            if byte[0] == b'\n' {
+                break;
+            }
+            line.push(byte[0]);
+        }
+        self.caption_pending = false;
+        Ok(String::from_utf8_lossy(&line).trim().to_string())
+    }
+
+    fn drain(&mut self) -> std::io::Result<()> {
+        let want = self.remaining as u64;
+        let skipped = std::io::copy(&mut (&mut *self.input).take(want), &mut std::io::sink())?;
+        self.remaining -= skipped as usize;
+        if self.remaining > 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "stdin closed mid-upload",
+            ));
+        }
+        self.read_caption()?;
+        Ok(())
+    }
+}
+
 async fn upload_bytes_with_progress(
     state: &mut State,
     filename: String,
-    bytes: Vec<u8>,
+    body: &mut UploadBody<'_>,
     out: &mut impl Write,
 ) -> Result<Uploaded, String> {
-    let size = bytes.len();
+    let size = body.remaining;
     let bytes_total = size as u64;
     if size == 0 {
         return Err("invalid upload: empty stream".to_string());
@@ -1473,32 +2001,29 @@ async fn upload_bytes_with_progress(
     write_upload_progress(out, session, 0, bytes_total, 0)?;
 
     if size > UPLOAD_BIG_FILE_SIZE_BYTES {
-        let bytes = Arc::new(bytes);
-        let next_part = Arc::new(AtomicI32::new(0));
+        // Big files need no checksum, so parts are read from stdin as workers free up instead of
+        // buffering the whole object; at most ~2 parts per worker are held at a time.
+        let (part_tx, part_rx) =
+            tokio::sync::mpsc::channel::<(i32, Vec<u8>)>(state.max_concurrent_uploads);
+        let part_rx = Arc::new(Mutex::new(part_rx));
+        let mut part_tx = Some(part_tx);
+        let mut next_read = 0i32;
         let payload_done = Arc::new(AtomicU64::new(0));
         let client = state.client.clone();
         let limiter = Arc::clone(&state.part_rate_limiter);
 
         let mut join_set = JoinSet::new();
         for _ in 0..state.max_concurrent_uploads {
-            let bytes = Arc::clone(&bytes);
-            let next_part = Arc::clone(&next_part);
+            let part_rx = Arc::clone(&part_rx);
             let payload_done = Arc::clone(&payload_done);
             let client = client.clone();
             let limiter = Arc::clone(&limiter);
             join_set.spawn(async move {
                 loop {
-                    let part = next_part.fetch_add(1, Ordering::Relaxed);
-                    if part >= total_parts {
+                    let next = part_rx.lock().await.recv().await;
+                    let Some((part, chunk)) = next else {
                         break;
-                    }
-                    let start = (part as usize).saturating_mul(chunk_size);
-                    let end = (start + chunk_size).min(bytes.len());
-                    let len = end.saturating_sub(start);
-                    if len == 0 {
-                        continue;
-                    }
-                    let chunk = bytes[start..end].to_vec();
+                    };
                     save_big_file_part_with_retry(
                         limiter.as_ref(),
                         &client,
@@ -1508,7 +2033,7 @@ async fn upload_bytes_with_progress(
                         &chunk,
                     )
                     .await?;
-                    payload_done.fetch_add(len as u64, Ordering::Relaxed);
+                    payload_done.fetch_add(chunk.len() as u64, Ordering::Relaxed);
                 }
                 Ok::<(), String>(())
             });
@@ -1522,6 +2047,25 @@ async fn upload_bytes_with_progress(
 
         loop {
             tokio::select! {
+                permit = async { part_tx.as_ref()?.reserve().await.ok() }, if part_tx.is_some() => {
+                    let Some(permit) = permit else {
+                        // All workers are gone; their error surfaces via `join_next`.
+                        part_tx = None;
+                        continue;
+                    };
+                    let chunk = match body.read_part(chunk_size) {
+                        Ok(chunk) => chunk,
+                        Err(e) => {
+                            join_set.abort_all();
+                            return Err(e);
+                        }
+                    };
+                    permit.send((next_read, chunk));
+                    next_read += 1;
+                    if next_read >= total_parts {
+                        part_tx = None;
+                    }
+                }
                 _ = interval.tick() => {
                     let payload = payload_done.load(Ordering::Relaxed).min(bytes_total);
                     let (_in_total, out_total) = state.net_stats.snapshot();
@@ -1570,6 +2114,7 @@ async fn upload_bytes_with_progress(
     // For small (<=10MiB) files Telegram requires an MD5 checksum, but file parts can still be
     // uploaded in parallel. This is important for TelevyBackup because most blobs are ~4MiB and
     // would otherwise never use `max_concurrent_uploads`.
+    let bytes = body.read_part(size)?;
     let md5_checksum = format!("{:x}", md5::compute(&bytes));
 
     let bytes = Arc::new(bytes);
@@ -1663,19 +2208,22 @@ async fn upload_bytes_with_progress(
 async fn upload_with_progress(
     state: &mut State,
     filename: String,
-    bytes: Vec<u8>,
+    body: &mut UploadBody<'_>,
     out: &mut impl Write,
 ) -> Result<String, String> {
     let chat = require_chat(state)?.clone();
-    let size = bytes.len();
+    let size = body.remaining;
     let bytes_total = size as u64;
     let timeout_secs = upload_stream_timeout_secs(size);
     let uploaded = timeout(
         Duration::from_secs(timeout_secs),
-        upload_bytes_with_progress(state, filename, bytes, out),
+        upload_bytes_with_progress(state, filename, body, out),
     )
     .await
     .map_err(|_| format!("upload_stream timed out after {timeout_secs}s"))??;
+    let caption = body
+        .read_caption()
+        .map_err(|e| format!("upload caption read failed: {e}"))?;
 
     let (_base_in, send_base_out) = state.net_stats.snapshot();
     let mut last_net_reported: Option<u64> = None;
@@ -1697,13 +2245,18 @@ async fn upload_with_progress(
         }
         Ok(())
     };
-    let msg = send_media_message_with_retry(
+    let msg = match send_media_message_with_retry(
         &state.client,
         &chat,
         &uploaded,
+        &caption,
         &mut emit_send_message_progress,
     )
-    .await?;
+    .await
+    {
+        Ok(msg) => msg,
+        Err(e) => return Err(detect_chat_migration(state).await.unwrap_or(e)),
+    };
 
     let msg_id = msg.id();
     let media = msg
@@ -1719,6 +2272,7 @@ async fn send_media_message_with_retry(
     client: &Client,
     chat: &Peer,
     uploaded: &Uploaded,
+    caption: &str,
     emit_progress: &mut dyn FnMut() -> Result<(), String>,
 ) -> Result<grammers_client::types::Message, String> {
     for attempt in 1..=SEND_MESSAGE_MAX_ATTEMPTS {
@@ -1735,9 +2289,10 @@ async fn send_media_message_with_retry(
         let client = client.clone();
         let chat = chat.clone();
         let uploaded = uploaded.clone();
+        let caption = caption.to_string();
         let send_task = tokio::spawn(async move {
             let res = client
-                .send_message(&chat, InputMessage::new().text("").file(uploaded))
+                .send_message(&chat, InputMessage::new().text(caption).file(uploaded))
                 .await;
             let _ = tx.send(res);
         });
@@ -1816,14 +2371,26 @@ async fn download_to_cache(
     object_id: &str,
     out: &mut impl Write,
 ) -> Result<PathBuf, String> {
-    let chat = require_chat(state)?;
     let parsed = parse_tgmtproto_object_id_v1(object_id)?;
-    if parsed.peer != state.chat_id {
+    // Objects uploaded before the group was upgraded to a supergroup stay in the old chat.
+    let legacy_chat;
+    let chat = if parsed.peer == state.chat_id {
+        require_chat(state)?
+    } else if state.migrated_from_chat_ids.contains(&parsed.peer) {
+        legacy_chat = timeout(
+            Duration::from_secs(INIT_RESOLVE_CHAT_TIMEOUT_SECS),
+            resolve_chat(&state.client, &parsed.peer),
+        )
+        .await
+        .map_err(|_| format!("resolve_chat timed out after {INIT_RESOLVE_CHAT_TIMEOUT_SECS}s"))?
+        .map_err(|e| format!("resolve chat failed: {e}"))?;
+        &legacy_chat
+    } else {
         return Err(format!(
             "peer mismatch: expected {} got {}",
             state.chat_id, parsed.peer
         ));
-    }
+    };
 
     let cache_key = blake3::hash(object_id.as_bytes()).to_hex().to_string();
     let cache_path = state.cache_dir.join(format!("{cache_key}.part"));
@@ -2071,6 +2638,19 @@ fn extract_document_id(media: &Media) -> Result<(i64, i64), String> {
     Ok((doc_id, access_hash))
 }
 
+fn document_file_name(media: &Media) -> Option<String> {
+    let Media::Document(d) = media else {
+        return None;
+    };
+    let tl::enums::Document::Document(doc) = d.raw.document.as_ref()? else {
+        return None;
+    };
+    doc.attributes.iter().find_map(|a| match a {
+        tl::enums::DocumentAttribute::Filename(f) => Some(f.file_name.clone()),
+        _ => None,
+    })
+}
+
 fn extract_document_id_and_size(media: &Media) -> Result<(i64, i64, u64), String> {
     let doc_media = match media {
         Media::Document(d) => d,
@@ -385,6 +385,8 @@ private struct TargetListRow: View {
                 if let stage { parts.append(stage) }
                 if let fp = filesProgressText(effectiveProgress) { parts.append(fp) }
                 return parts.isEmpty ? "Working…" : parts.joined(separator: " · ")
+            case .queued:
+                return "Waiting for other targets in this group…"
             case .idle:
                 return lastRunCompactText(now: now) ?? "No recent runs."
             case .failed:
@@ -1254,6 +1256,7 @@ private struct RunLogRow: View {
                             switch run.status {
                             case "succeeded": return Color.green
                             case "failed": return Color.red
+                            case "cancelled": return Color.orange
                             case "running": return Color.blue
                             default: return Color.secondary
                             }
@@ -177,6 +177,7 @@ struct TelegramEndpointV2: Codable, Identifiable {
     var id: String
     var mode: String
     var chat_id: String
+    var migrated_from_chat_ids: [String]? = nil
     var bot_token_key: String
     var mtproto: TelegramEndpointMtprotoV2
     var rate_limit: TelegramRateLimitV2
@@ -184,6 +185,9 @@ struct TelegramEndpointV2: Codable, Identifiable {
 
 struct TelegramEndpointMtprotoV2: Codable {
     var session_key: String
+    /// Per-endpoint overrides of `telegram.mtproto.api_id` / `api_hash_key`.
+    var api_id: Int? = nil
+    var api_hash_key: String? = nil
 }
 
 struct TelegramRateLimitV2: Codable {
@@ -1373,11 +1377,20 @@ struct SettingsWindowRootView: View {
             out.append("id = \(tomlString(ep.id))")
             out.append("mode = \(tomlString(ep.mode))")
             out.append("chat_id = \(tomlString(ep.chat_id))")
+            if let ids = ep.migrated_from_chat_ids, !ids.isEmpty {
+                out.append("migrated_from_chat_ids = [\(ids.map { tomlString($0) }.joined(separator: ", "))]")
+            }
             out.append("bot_token_key = \(tomlString(ep.bot_token_key))")
             out.append("")
 
             out.append("[telegram_endpoints.mtproto]")
             out.append("session_key = \(tomlString(ep.mtproto.session_key))")
+            if let apiId = ep.mtproto.api_id {
+                out.append("api_id = \(apiId)")
+            }
+            if let key = ep.mtproto.api_hash_key {
+                out.append("api_hash_key = \(tomlString(key))")
+            }
             out.append("")
 
             out.append("[telegram_endpoints.rate_limit]")
@@ -24,6 +24,7 @@ struct StatusProgress: Codable {
     var bytesUploaded: Int64?
     var bytesDownloaded: Int64?
     var bytesDeduped: Int64?
+    var bytesTotal: Int64? = nil
 }
 
 struct StatusTargetRunSummary: Codable {
@@ -34,11 +35,14 @@ struct StatusTargetRunSummary: Codable {
     var filesIndexed: Int64?
     var bytesUploaded: Int64?
     var bytesDeduped: Int64?
+    var uploadDurationSeconds: Double? = nil
 }
 
 struct StatusSource: Codable {
     var kind: String
     var detail: String?
+    /// Read from `status.json.prev` because `status.json` was missing or corrupt.
+    var degraded: Bool? = nil
 }
 
 struct StatusGlobal: Codable {
@@ -130,10 +130,13 @@ struct TargetDiagnosticsView: View {
 
     private func sourceText(_ snap: StatusSnapshot?) -> String {
         guard let snap else { return "—" }
+        let kind = snap.source.degraded == true
+            ? "\(snap.source.kind), last known status"
+            : snap.source.kind
         if let detail = snap.source.detail, !detail.isEmpty {
-            return "\(snap.source.kind) (\(detail))"
+            return "\(kind) (\(detail))"
         }
-        return snap.source.kind
+        return kind
     }
 
     private func freshnessText(snap: StatusSnapshot?, nowMs: Int64) -> String {
@@ -21,6 +21,7 @@ struct TargetRateEstimate: Equatable {
 
 enum TargetUserStatus: String {
     case running
+    case queued
     case idle
     case failed
     case offline
@@ -28,6 +29,7 @@ enum TargetUserStatus: String {
     var title: String {
         switch self {
         case .running: return "Running"
+        case .queued: return "Queued"
         case .idle: return "Idle"
         case .failed: return "Failed"
         case .offline: return "Offline"
@@ -37,6 +39,7 @@ enum TargetUserStatus: String {
     var tint: Color {
         switch self {
         case .running: return .blue
+        case .queued: return .teal
         case .idle: return .gray
         case .failed: return .red
         case .offline: return .orange
@@ -115,6 +118,7 @@ enum TargetPresentation {
         if snapshotOffline { return .offline }
 
         if target.state == "running" { return .running }
+        if target.state == "queued" { return .queued }
 
         if target.state == "failed" || target.lastRun?.status == "failed" { return .failed }
         if target.state == "stale" { return .offline }
@@ -193,6 +197,7 @@ enum TargetPresentation {
             bytesUploaded: r.bytesUploaded,
             bytesDeduped: r.bytesDeduped,
             durationSeconds: r.durationSeconds,
+            uploadDurationSeconds: r.uploadDurationSeconds,
             now: now
         )
     }
@@ -255,6 +260,7 @@ enum TargetPresentation {
         bytesUploaded: Int64?,
         bytesDeduped: Int64?,
         durationSeconds: Double?,
+        uploadDurationSeconds: Double? = nil,
         now: Date
     ) -> String {
         let status = normalizedStatus(status)
@@ -289,7 +295,13 @@ enum TargetPresentation {
         }
 
         if let durationSeconds, durationSeconds > 0 {
-            parts.append(formatDuration(durationSeconds))
+            if status != "failed", let uploadDurationSeconds, uploadDurationSeconds > 0 {
+                parts.append(
+                    "Uploading took \(formatDuration(uploadDurationSeconds)) of \(formatDuration(durationSeconds))"
+                )
+            } else {
+                parts.append(formatDuration(durationSeconds))
+            }
         }
 
         return parts.joined(separator: " · ")
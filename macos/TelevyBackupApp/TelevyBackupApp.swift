@@ -117,6 +117,8 @@ final class AppModel: ObservableObject {
     private var mainWindow: NSWindow? = nil
     private var lastTaskKind: String? = nil
     private var lastTaskState: String? = nil
+    // stdin of the running `--events` CLI task; `task.prompt` answers are written here.
+    private var taskPromptInput: FileHandle? = nil
     private let launchOverrides: LaunchOverrides = .parse(CommandLine.arguments)
     private let uiDemoSandboxRunID: String = UUID().uuidString
     let appearanceOverride: AppAppearanceOverride = .fromEnvironment()
@@ -477,9 +479,18 @@ final class AppModel: ObservableObject {
         if ProcessInfo.processInfo.environment["TELEVYBACKUP_UI_DEMO"] == "1" {
             return uiDemoSandboxDataDirURL()
         }
+        if let p = dataDirPointer() { return URL(fileURLWithPath: p) }
         return defaultDataDir()
     }
 
+    /// `<config_dir>/data-dir.txt`, written by `televybackup data-dir migrate`.
+    private func dataDirPointer() -> String? {
+        let url = effectiveConfigDirURL().appendingPathComponent("data-dir.txt")
+        guard let text = try? String(contentsOf: url, encoding: .utf8) else { return nil }
+        let p = text.trimmingCharacters(in: .whitespacesAndNewlines)
+        return p.hasPrefix("/") ? p : nil
+    }
+
     private func bundledMacOSDirURL() -> URL {
         Bundle.main.bundleURL
             .appendingPathComponent("Contents")
@@ -490,6 +501,8 @@ final class AppModel: ObservableObject {
         var env = ProcessInfo.processInfo.environment
         env["TELEVYBACKUP_CONFIG_DIR"] = effectiveConfigDirURL().path
         env["TELEVYBACKUP_DATA_DIR"] = effectiveDataDirURL().path
+        // CLI audit log entries written on behalf of the app are attributed to the GUI.
+        env["TELEVYBACKUP_AUDIT_ACTOR"] = "gui"
         let bundledBinDir = bundledMacOSDirURL().path
         if let currentPath = env["PATH"], !currentPath.isEmpty {
             if !currentPath.split(separator: ":").contains(Substring(bundledBinDir)) {
@@ -705,9 +718,7 @@ final class AppModel: ObservableObject {
         t.schedule(deadline: .now() + 0.10, repeating: 1.0)
         t.setEventHandler { [weak self] in
             guard let self else { return }
-            let url = self.statusJsonURL()
-            guard let data = try? Data(contentsOf: url) else { return }
-            guard let snap = try? JSONDecoder().decode(StatusSnapshot.self, from: data) else { return }
+            guard let snap = self.readStatusJson() else { return }
             DispatchQueue.main.async {
                 if self.statusSnapshot?.generatedAt == snap.generatedAt { return }
                 self.applyStatusSnapshot(snap)
@@ -717,6 +728,23 @@ final class AppModel: ObservableObject {
         t.activate()
     }
 
+    /// Like the CLI: a missing or corrupt `status.json` falls back to `status.json.prev`, marked
+    /// degraded so the UI can show it as the last known status.
+    private func readStatusJson() -> StatusSnapshot? {
+        let url = statusJsonURL()
+        if let data = try? Data(contentsOf: url),
+           let snap = try? JSONDecoder().decode(StatusSnapshot.self, from: data)
+        {
+            return snap
+        }
+        let prev = url.deletingLastPathComponent().appendingPathComponent("status.json.prev")
+        guard let data = try? Data(contentsOf: prev),
+              var snap = try? JSONDecoder().decode(StatusSnapshot.self, from: data)
+        else { return nil }
+        snap.source.degraded = true
+        return snap
+    }
+
     func ensureDaemonRunning() {
         let now = Date()
         if let last = lastDaemonStartAttemptAt, now.timeIntervalSince(last) < 3 {
@@ -2334,6 +2362,17 @@ final class AppModel: ObservableObject {
         return (stdout, stderr, status, reason)
     }
 
+    private func answerTaskPrompt(message: String) {
+        let alert = NSAlert()
+        alert.alertStyle = .warning
+        alert.messageText = "Large first backup"
+        alert.informativeText = message
+        alert.addButton(withTitle: "Continue")
+        alert.addButton(withTitle: "Cancel")
+        let answer = alert.runModal() == .alertFirstButtonReturn ? "continue" : "cancel"
+        taskPromptInput?.write(Data("\(answer)\n".utf8))
+    }
+
     private func handleOutputLine(_ line: String) {
         guard let data = line.data(using: .utf8),
               let obj = try? JSONSerialization.jsonObject(with: data) as? [String: Any]
@@ -2360,6 +2399,12 @@ final class AppModel: ObservableObject {
 
         guard let type = obj["type"] as? String else { return }
 
+        if type == "task.prompt" {
+            let message = obj["message"] as? String ?? "Continue?"
+            DispatchQueue.main.async { self.answerTaskPrompt(message: message) }
+            return
+        }
+
         if type == "task.progress" {
             let taskId = obj["taskId"] as? String ?? ""
             let phase = obj["phase"] as? String ?? "running"
@@ -2380,6 +2425,7 @@ final class AppModel: ObservableObject {
                 let bytesUploaded = (obj["bytesUploaded"] as? NSNumber)?.int64Value
                 let bytesDownloaded = (obj["bytesDownloaded"] as? NSNumber)?.int64Value
                 let bytesDeduped = (obj["bytesDeduped"] as? NSNumber)?.int64Value
+                let bytesTotal = (obj["bytesTotal"] as? NSNumber)?.int64Value
 
                 self.phase = phase
                 self.currentBytesUploaded = bytesUploaded ?? bytesUploadedSource ?? 0
@@ -2420,7 +2466,8 @@ final class AppModel: ObservableObject {
                     bytesUploadedSource: bytesUploadedSource,
                     bytesUploaded: bytesUploaded,
                     bytesDownloaded: bytesDownloaded,
-                    bytesDeduped: bytesDeduped
+                    bytesDeduped: bytesDeduped,
+                    bytesTotal: bytesTotal
                 )
                 self.activeTask = task
 
@@ -2590,11 +2637,17 @@ final class AppModel: ObservableObject {
         task.standardOutput = out
         task.standardError = err
 
+        var promptInput: FileHandle? = nil
         if let stdin {
             let input = Pipe()
             task.standardInput = input
             input.fileHandleForWriting.write(stdin.data(using: .utf8) ?? Data())
             try? input.fileHandleForWriting.close()
+        } else if args.contains("--events") {
+            let input = Pipe()
+            task.standardInput = input
+            promptInput = input.fileHandleForWriting
+            taskPromptInput = promptInput
         }
 
         let bufLock = NSLock()
@@ -2667,6 +2720,12 @@ final class AppModel: ObservableObject {
             } catch {
                 self.handleOutputLine("ERROR: failed to run process: \(error)")
             }
+            if let promptInput {
+                try? promptInput.close()
+                DispatchQueue.main.async {
+                    if self.taskPromptInput === promptInput { self.taskPromptInput = nil }
+                }
+            }
             // Stop streaming, read remaining output, and drain buffered lines BEFORE deciding whether
             // to show a generic failure toast (prevents duplicate failure toasts).
             out.fileHandleForReading.readabilityHandler = nil